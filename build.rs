@@ -2,6 +2,11 @@ extern crate cmake;
 use cmake::Config;
 
 fn main() {
+    // The mock backend implements the C bindings in rust, so there is nothing to build or link
+    if std::env::var_os("CARGO_FEATURE_MOCK_BACKEND").is_some() {
+        return;
+    }
+
     let dst = Config::new("vendor/libdecklink_c").build();
 
     println!("cargo:rustc-link-search=native={}", dst.display());
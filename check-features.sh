@@ -0,0 +1,35 @@
+#!/bin/bash
+# Check that the crate builds with no features, with each feature on its own, with every
+# combination of the features that do not need extra toolchains, and with all of them.
+#
+# Usage: ./check-features.sh [--with-cuda]
+
+set -euo pipefail
+
+# Features that build with only a Rust toolchain. Every combination of these is checked.
+FEATURES=(leak-check image-interop thumbnail-jpeg container mock-backend cli serde realtime raw-api)
+if [ "${1:-}" == "--with-cuda" ]; then
+    FEATURES+=(cuda)
+fi
+
+check() {
+    echo "=== cargo check $*"
+    cargo check --lib --bins --examples "$@"
+}
+
+check --no-default-features
+
+count=${#FEATURES[@]}
+for ((mask = 1; mask < (1 << count); mask++)); do
+    selected=()
+    for ((i = 0; i < count; i++)); do
+        if ((mask & (1 << i))); then
+            selected+=("${FEATURES[$i]}")
+        fi
+    done
+    check --no-default-features --features "$(IFS=,; echo "${selected[*]}")"
+done
+
+if [ "${1:-}" == "--with-cuda" ]; then
+    check --all-features
+fi
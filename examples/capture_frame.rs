@@ -2,14 +2,17 @@ extern crate decklink;
 #[macro_use]
 extern crate text_io;
 
+use decklink::conformance::{ConformanceChecker, EnabledConfig};
+use decklink::deinterlace::{DeinterlacePolicy, FrameLayout};
 use decklink::device::input::{
-    DeckLinkInputCallback, DecklinkDetectedVideoInputFormatFlags, DecklinkVideoInputFlags,
-    DecklinkVideoInputFormatChangedEvents,
+    CallbackResult, DecklinkVideoInputFlags, FrameArrival, InputFormatChange, InputHandler,
+    PixelFormatPreference,
 };
 use decklink::device::DecklinkDeviceDisplayModes;
 use decklink::device::{get_devices, DecklinkDevice};
-use decklink::display_mode::{DecklinkDisplayMode, DecklinkDisplayModeId};
-use decklink::frame::{DecklinkFrameBase, DecklinkPixelFormat, DecklinkVideoFrame};
+use decklink::display_mode::DecklinkDisplayMode;
+use decklink::frame::{DecklinkFrameBase, DecklinkPixelFormat};
+use decklink::monitor::{EchoSlot, MonitorEcho};
 
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Condvar, Mutex};
@@ -21,6 +24,8 @@ struct FrameCapture {
     frame_ready: Condvar,
     captured: AtomicBool,
     frames_seen: std::sync::atomic::AtomicU32,
+    echo_slot: Option<Arc<EchoSlot>>,
+    conformance: Mutex<ConformanceChecker>,
 }
 
 struct FrameInfo {
@@ -30,26 +35,36 @@ struct FrameInfo {
     pixel_format: DecklinkPixelFormat,
 }
 
-impl DeckLinkInputCallback for FrameCapture {
-    fn video_input_format_changed(
-        &self,
-        events: DecklinkVideoInputFormatChangedEvents,
-        new_display_mode: DecklinkDisplayModeId,
-        detected_signal_flags: DecklinkDetectedVideoInputFormatFlags,
-    ) {
+impl InputHandler for FrameCapture {
+    fn format_changed(&self, change: &InputFormatChange) {
         println!(
             "Input format changed: events={:?}, mode={:?}, flags={:?}",
-            events, new_display_mode, detected_signal_flags
+            change.events, change.display_mode, change.detected_signal_flags
         );
+
+        let warnings = self
+            .conformance
+            .lock()
+            .unwrap()
+            .check_format(change.display_mode, change.detected_signal_flags);
+        for warning in warnings {
+            println!("Warning: capture settings do not match the signal: {:?}", warning);
+        }
     }
 
-    fn video_input_frame_arrived(&self, video_frame: Option<DecklinkVideoFrame>) -> bool {
+    fn frame_arrived(&self, arrival: &FrameArrival<'_>) -> CallbackResult {
         // Only capture once
         if self.captured.load(Ordering::Relaxed) {
-            return true;
+            return CallbackResult::Ok;
         }
 
-        if let Some(frame) = video_frame {
+        if let Some(frame) = arrival.video_frame {
+            if let Some(slot) = &self.echo_slot {
+                if let Err(e) = slot.offer(frame) {
+                    eprintln!("Failed to retain frame for monitor echo: {:?}", e);
+                }
+            }
+
             let count = self.frames_seen.fetch_add(1, Ordering::Relaxed);
             let width = frame.width();
             let height = frame.height();
@@ -67,7 +82,7 @@ impl DeckLinkInputCallback for FrameCapture {
 
             // Skip first 30 frames to let the signal stabilize
             if count < 60 {
-                return true;
+                return CallbackResult::Ok;
             }
 
             match frame.bytes_to_vec() {
@@ -91,7 +106,7 @@ impl DeckLinkInputCallback for FrameCapture {
             println!("Frame arrived with no video data (no input signal?)");
         }
 
-        true
+        CallbackResult::Ok
     }
 }
 
@@ -108,11 +123,11 @@ fn select_input_and_format() -> Option<(
         }
 
         println!("Found {} device(s):", devices.len());
-        for i in 0..devices.len() {
+        for (i, device) in devices.iter().enumerate() {
             println!(
                 "  {}: {}",
                 i,
-                devices[i]
+                device
                     .display_name()
                     .unwrap_or_else(|| "Unknown".to_string())
             );
@@ -149,8 +164,7 @@ fn select_input_and_format() -> Option<(
             .expect("Failed to list input display modes");
 
         println!("Available input modes:");
-        for i in 0..supported_modes.len() {
-            let m = &supported_modes[i];
+        for (i, m) in supported_modes.iter().enumerate() {
             let framerate = m
                 .framerate()
                 .map(|(d, s)| {
@@ -184,6 +198,14 @@ fn select_input_and_format() -> Option<(
     Some((device, input, mode))
 }
 
+/// What `write_ppm` wrote.
+enum PpmContents {
+    /// The picture, converted into RGB.
+    Rgb,
+    /// The raw bytes of a pixel format it cannot convert.
+    RawBytes,
+}
+
 /// Write raw frame data as a simple PPM image file (P6 format).
 /// This works for 8-bit BGRA by converting to RGB.
 fn write_ppm(
@@ -193,7 +215,7 @@ fn write_ppm(
     row_bytes: usize,
     pixel_format: DecklinkPixelFormat,
     data: &[u8],
-) -> std::io::Result<()> {
+) -> std::io::Result<PpmContents> {
     use std::io::Write;
 
     let mut file = std::fs::File::create(path)?;
@@ -275,10 +297,6 @@ fn write_ppm(
         }
         _ => {
             // For other formats, just write raw data as grayscale-ish (best effort)
-            eprintln!(
-                "Warning: pixel format {:?} not fully supported for PPM conversion, writing raw bytes",
-                pixel_format
-            );
             for y in 0..height {
                 let row_start = y * row_bytes;
                 for x in 0..width {
@@ -290,9 +308,33 @@ fn write_ppm(
                     }
                 }
             }
+            return Ok(PpmContents::RawBytes);
         }
     }
 
+    Ok(PpmContents::Rgb)
+}
+
+/// Write the frame as a PNG, converting it through the `image` crate.
+#[cfg(feature = "image-interop")]
+fn write_png(
+    path: &str,
+    info: &FrameInfo,
+    data: &[u8],
+) -> Result<(), Box<dyn std::error::Error>> {
+    use decklink::frame::{DecklinkFrameFlags, DecklinkVideoMutableFrame};
+
+    let mut frame = DecklinkVideoMutableFrame::create(
+        info.width,
+        info.height,
+        info.row_bytes,
+        info.pixel_format,
+        DecklinkFrameFlags::empty(),
+    );
+    frame.copy_bytes(data).map_err(|e| format!("{:?}", e))?;
+
+    let image = image::RgbaImage::try_from(&frame)?;
+    image.save(path)?;
     Ok(())
 }
 
@@ -304,25 +346,65 @@ fn main() {
         return;
     }
 
-    let (_device, mut input, mode) = match select_input_and_format() {
+    let (device, mut input, mode) = match select_input_and_format() {
         Some(v) => v,
         None => return,
     };
 
-    let pixel_format = DecklinkPixelFormat::Format8BitYUV;
+    // Optionally echo captured frames to the output of the same device
+    let output = if std::env::args().any(|a| a == "--monitor-echo") {
+        device.output()
+    } else {
+        None
+    };
+    let echo = output
+        .as_ref()
+        .and_then(|output| match MonitorEcho::new(output, mode.mode(), 5.0) {
+            Ok(echo) => {
+                println!("Monitor echo enabled on the device output");
+                Some(echo)
+            }
+            Err(e) => {
+                eprintln!("Failed to enable monitor echo: {:?}", e);
+                None
+            }
+        });
+
+    let preference = PixelFormatPreference::PreferredWithFallback(vec![
+        DecklinkPixelFormat::Format8BitBGRA,
+        DecklinkPixelFormat::Format8BitYUV,
+    ]);
+
+    // Enable video input
+    let chosen = input
+        .enable_video_input_with_preference(
+            mode.mode(),
+            &preference,
+            DecklinkVideoInputFlags::empty(),
+        )
+        .expect("Failed to enable video input");
 
     println!(
-        "\nConfiguring capture: {} ({}x{}) with {:?}",
+        "\nConfiguring capture: {} ({}x{}) with {:?}{}",
         mode.name().unwrap_or_else(|| "Unknown".to_string()),
         mode.width(),
         mode.height(),
-        pixel_format,
+        chosen.pixel_format,
+        if chosen.is_fallback() {
+            " (fallback)"
+        } else {
+            ""
+        },
     );
 
-    // Enable video input
-    input
-        .enable_video_input(mode.mode(), pixel_format, DecklinkVideoInputFlags::empty())
-        .expect("Failed to enable video input");
+    let conformance = ConformanceChecker::new(
+        EnabledConfig {
+            display_mode: mode.mode(),
+            pixel_format: chosen.pixel_format,
+        },
+        &input.display_modes().unwrap_or_default(),
+        48000 * 5,
+    );
 
     // Create capture callback
     let capture = Arc::new(FrameCapture {
@@ -331,11 +413,13 @@ fn main() {
         frame_ready: Condvar::new(),
         captured: AtomicBool::new(false),
         frames_seen: std::sync::atomic::AtomicU32::new(0),
+        echo_slot: echo.as_ref().map(|e| e.slot()),
+        conformance: Mutex::new(conformance),
     });
 
     // Set callback
     input
-        .set_callback(Some(capture.clone()))
+        .set_handler(Some(capture.clone()))
         .expect("Failed to set input callback");
 
     // Start capture
@@ -346,12 +430,23 @@ fn main() {
     // Wait for a frame (with 10 second timeout)
     {
         let mut data = capture.frame_data.lock().unwrap();
-        let timeout = std::time::Duration::from_secs(10);
-        let result = capture
-            .frame_ready
-            .wait_timeout_while(data, timeout, |d| d.is_none())
-            .unwrap();
-        data = result.0;
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(10);
+        while data.is_none() {
+            let now = std::time::Instant::now();
+            if now >= deadline {
+                break;
+            }
+
+            // Wake up regularly so the monitor echo can be refreshed
+            let step = std::time::Duration::from_millis(100).min(deadline - now);
+            data = capture.frame_ready.wait_timeout(data, step).unwrap().0;
+
+            if let Some(echo) = &echo {
+                if let Err(e) = echo.poll() {
+                    eprintln!("Failed to display monitor echo: {:?}", e);
+                }
+            }
+        }
 
         if data.is_none() {
             println!("Timeout: No frame received within 10 seconds.");
@@ -368,7 +463,7 @@ fn main() {
     let frame_data = capture.frame_data.lock().unwrap().take();
     let frame_info = capture.frame_info.lock().unwrap().take();
 
-    if let (Some(data), Some(info)) = (frame_data, frame_info) {
+    if let (Some(mut data), Some(info)) = (frame_data, frame_info) {
         let raw_path = "captured_frame.raw";
         let ppm_path = "captured_frame.ppm";
 
@@ -383,6 +478,32 @@ fn main() {
             info.pixel_format
         );
 
+        // Line double interlaced sources, so the still has no combing
+        let layout = FrameLayout {
+            width: info.width,
+            height: info.height,
+            row_bytes: info.row_bytes,
+            pixel_format: info.pixel_format,
+        };
+        if let Err(e) = DeinterlacePolicy::Auto.apply_in_place(
+            &mut data,
+            layout,
+            mode.field_dominance(),
+            &mut Vec::new(),
+        ) {
+            eprintln!("Failed to deinterlace frame: {:?}", e);
+        }
+
+        #[cfg(feature = "image-interop")]
+        if std::env::args().any(|a| a == "--png") {
+            let png_path = "captured_frame.png";
+            match write_png(png_path, &info, &data) {
+                Ok(_) => println!("PNG image saved to {}", png_path),
+                Err(e) => eprintln!("Failed to write PNG: {}", e),
+            }
+            return;
+        }
+
         // Write PPM image
         match write_ppm(
             ppm_path,
@@ -392,7 +513,11 @@ fn main() {
             info.pixel_format,
             &data,
         ) {
-            Ok(_) => println!("PPM image saved to {}", ppm_path),
+            Ok(PpmContents::Rgb) => println!("PPM image saved to {}", ppm_path),
+            Ok(PpmContents::RawBytes) => println!(
+                "Pixel format {:?} is not fully supported for PPM conversion, raw bytes saved to {}",
+                info.pixel_format, ppm_path
+            ),
             Err(e) => eprintln!("Failed to write PPM: {}", e),
         }
     } else {
@@ -0,0 +1,159 @@
+extern crate decklink;
+
+use decklink::device::input::{
+    CallbackResult, DecklinkAudioInputPacket, DecklinkDetectedVideoInputFormatFlags,
+    DecklinkVideoInputFlags, DecklinkVideoInputFormatChangedEvents,
+};
+use decklink::device::selector::DeviceSelector;
+use decklink::device::DecklinkDeviceDisplayModes;
+use decklink::display_mode::DecklinkDisplayModeId;
+use decklink::experimental::compat::{ClassicInputAdapter, ClassicInputCallback};
+use decklink::frame::{
+    DecklinkFrameBase, DecklinkFrameFlags, DecklinkPixelFormat, DecklinkVideoFrame,
+};
+use decklink::time::DecklinkFrameTiming;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// The input callback of the SDK's CapturePreview sample, ported through the compat shim.
+///
+/// The C++ callback restarts the input itself on a format change. Here the input is owned by
+/// the main thread, so the new format is sent there instead.
+struct CapturePreviewCallback {
+    restart: Mutex<Sender<(DecklinkDisplayModeId, DecklinkPixelFormat)>>,
+    frame_count: AtomicU64,
+    has_signal: AtomicBool,
+}
+
+impl ClassicInputCallback for CapturePreviewCallback {
+    fn video_input_format_changed(
+        &self,
+        notification_events: DecklinkVideoInputFormatChangedEvents,
+        new_display_mode: DecklinkDisplayModeId,
+        detected_signal_flags: DecklinkDetectedVideoInputFormatFlags,
+    ) -> CallbackResult {
+        if !notification_events.intersects(
+            DecklinkVideoInputFormatChangedEvents::DISPLAY_MODE_CHANGED
+                | DecklinkVideoInputFormatChangedEvents::COLORSPACE_CHANGED,
+        ) {
+            return CallbackResult::Ok;
+        }
+
+        let pixel_format =
+            if detected_signal_flags.contains(DecklinkDetectedVideoInputFormatFlags::RGB_444) {
+                DecklinkPixelFormat::Format10BitRGB
+            } else {
+                DecklinkPixelFormat::Format10BitYUV
+            };
+        match self
+            .restart
+            .lock()
+            .unwrap()
+            .send((new_display_mode, pixel_format))
+        {
+            Ok(()) => CallbackResult::Ok,
+            Err(_) => CallbackResult::Fail,
+        }
+    }
+
+    fn video_input_frame_arrived(
+        &self,
+        video_frame: Option<DecklinkVideoFrame>,
+        _audio_packet: Option<DecklinkAudioInputPacket>,
+        _timing: Option<DecklinkFrameTiming>,
+    ) -> CallbackResult {
+        let Some(frame) = video_frame else {
+            return CallbackResult::Ok;
+        };
+        let has_signal = !frame
+            .flags()
+            .contains(DecklinkFrameFlags::HAS_NO_INPUT_SOURCE);
+        self.has_signal.store(has_signal, Ordering::Relaxed);
+        self.frame_count.fetch_add(1, Ordering::Relaxed);
+        CallbackResult::Ok
+    }
+}
+
+/// Capture with format detection for a while, reporting the signal state and the formats
+/// the input was restarted in, as the SDK's CapturePreview sample does.
+///
+/// Usage: capture_preview [device] [seconds]
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let selector: DeviceSelector = args
+        .next()
+        .unwrap_or_else(|| "first".to_string())
+        .parse()
+        .expect("Invalid device selector");
+    let seconds: u64 = args
+        .next()
+        .map(|s| s.parse().expect("Invalid duration"))
+        .unwrap_or(30);
+
+    let device = selector.resolve().expect("Failed to find the device");
+    let mut input = device.input().expect("The device has no input");
+    let modes = input.display_modes().expect("Failed to list display modes");
+    let mode = modes.first().expect("The input has no display modes");
+
+    let (sender, receiver) = channel();
+    let callback = Arc::new(CapturePreviewCallback {
+        restart: Mutex::new(sender),
+        frame_count: AtomicU64::new(0),
+        has_signal: AtomicBool::new(false),
+    });
+    input
+        .set_handler(Some(Arc::new(ClassicInputAdapter::new(callback.clone()))))
+        .expect("Failed to set input callback");
+    input
+        .enable_video_input(
+            mode.mode(),
+            DecklinkPixelFormat::Format10BitYUV,
+            DecklinkVideoInputFlags::ENABLE_FORMAT_DETECTION,
+        )
+        .expect("Failed to enable video input");
+    input.start_streams().expect("Failed to start streams");
+
+    let deadline = Instant::now() + Duration::from_secs(seconds);
+    let mut next_report = Instant::now() + Duration::from_secs(1);
+    while Instant::now() < deadline {
+        match receiver.recv_timeout(next_report.saturating_duration_since(Instant::now())) {
+            Ok((display_mode, pixel_format)) => {
+                // Restart the input in the new format, as the C++ callback does
+                input.pause_streams().expect("Failed to pause streams");
+                input
+                    .disable_video_input()
+                    .expect("Failed to disable video input");
+                input
+                    .enable_video_input(
+                        display_mode,
+                        pixel_format,
+                        DecklinkVideoInputFlags::ENABLE_FORMAT_DETECTION,
+                    )
+                    .expect("Failed to enable video input");
+                input.flush_streams().expect("Failed to flush streams");
+                input.start_streams().expect("Failed to start streams");
+                println!(
+                    "Input format changed to {:?} in {:?}",
+                    display_mode, pixel_format
+                );
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                println!(
+                    "{} frames, {}",
+                    callback.frame_count.load(Ordering::Relaxed),
+                    if callback.has_signal.load(Ordering::Relaxed) {
+                        "signal present"
+                    } else {
+                        "no input signal"
+                    }
+                );
+                next_report += Duration::from_secs(1);
+            }
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+    }
+
+    input.stop_streams().ok();
+}
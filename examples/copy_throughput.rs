@@ -0,0 +1,75 @@
+extern crate decklink;
+
+use decklink::memcopy::{copy_frame_using, copy_paths, CopyLayout, CopyPath};
+use std::time::{Duration, Instant};
+
+/// The working set of the simulated consumer, which a cache-friendly copy leaves in place.
+const CONSUMER_BYTES: usize = 4 * 1024 * 1024;
+
+/// Read every cache line of the consumer's working set, as code that runs after a copy would.
+fn consume(working_set: &[u8]) -> u64 {
+    working_set.iter().step_by(64).map(|b| *b as u64).sum()
+}
+
+struct Measurement {
+    copy: Duration,
+    consumer: Duration,
+}
+
+fn measure(path: CopyPath, bytes: usize, frames: u32, consumer: Option<&[u8]>) -> Measurement {
+    let src = vec![0x5a; bytes];
+    // Two destinations, as a retained copy would not be written to the same buffer each time
+    let mut dst = [vec![0; bytes], vec![0; bytes]];
+    let layout = CopyLayout::contiguous(bytes);
+
+    let mut copy = Duration::ZERO;
+    let mut consumed = Duration::ZERO;
+    for n in 0..frames as usize {
+        let started = Instant::now();
+        copy_frame_using(path, &mut dst[n % 2], &src, layout).expect("The copy failed");
+        copy += started.elapsed();
+        std::hint::black_box(&dst[n % 2]);
+
+        if let Some(working_set) = consumer {
+            let started = Instant::now();
+            std::hint::black_box(consume(working_set));
+            consumed += started.elapsed();
+        }
+    }
+    Measurement {
+        copy: copy / frames,
+        consumer: consumed / frames,
+    }
+}
+
+/// Copy 1080p and 2160p 8-bit YUV frames with each path this processor has, and report the
+/// throughput of each, on its own and followed by a consumer reading a 4 MiB working set.
+///
+/// Usage: copy_throughput [frames]
+///
+/// No device is needed. The consumer is faster after a streaming copy when the standard
+/// copy evicts its working set from the caches.
+fn main() {
+    let frames: u32 = std::env::args()
+        .nth(1)
+        .map_or(200, |s| s.parse().expect("Invalid frame count"));
+    let working_set = vec![1u8; CONSUMER_BYTES];
+    std::hint::black_box(consume(&working_set));
+
+    for (name, bytes) in [("1080p", 3840 * 1080), ("2160p", 7680 * 2160)] {
+        println!("{} ({} bytes a frame)", name, bytes);
+        for path in copy_paths() {
+            let alone = measure(path, bytes, frames, None);
+            let with_consumer = measure(path, bytes, frames, Some(&working_set));
+            let throughput = bytes as f64 / alone.copy.as_secs_f64() / 1e9;
+            println!(
+                "  {:<14} {:>6.2} GB/s  copy {:>9.3?}  with consumer: copy {:>9.3?}  consumer {:>9.3?}",
+                format!("{:?}", path),
+                throughput,
+                alone.copy,
+                with_consumer.copy,
+                with_consumer.consumer
+            );
+        }
+    }
+}
@@ -4,27 +4,32 @@
 //!
 //! This demonstrates using the CUDA allocator provider to receive DeckLink
 //! input frames directly into CUDA pinned (page-locked) host memory, which
-//! can then be efficiently transferred to GPU device memory.
+//! can then be efficiently transferred to GPU device memory. Each frame is
+//! copied to device memory, and the achieved copy bandwidth is reported.
+//!
+//! With `--simulate-reset`, the CUDA context is then destroyed and recreated as after a
+//! GPU reset, following the recovery steps of `CudaAllocatorProvider`, and capture resumes
+//! with a provider for the new context.
 
 extern crate cudarc;
 extern crate decklink;
 #[macro_use]
 extern crate text_io;
 
-use decklink::allocator::VideoBufferAllocatorProvider;
-use decklink::cuda::CudaAllocatorProvider;
+use decklink::cuda::{copy_frame_to_device, CudaAllocatorProvider, CudaCopyLayout};
 use decklink::device::input::{
-    DeckLinkInputCallback, DecklinkDetectedVideoInputFormatFlags, DecklinkVideoInputFlags,
-    DecklinkVideoInputFormatChangedEvents,
+    CallbackResult, DecklinkInputDevice, DecklinkVideoInputFlags, FrameArrival, InputFormatChange,
+    InputHandler,
 };
 use decklink::device::DecklinkDeviceDisplayModes;
 use decklink::device::{get_devices, DecklinkDevice};
 use decklink::display_mode::{DecklinkDisplayMode, DecklinkDisplayModeId};
 use decklink::frame::{DecklinkFrameBase, DecklinkPixelFormat, DecklinkVideoFrame};
 
-use cudarc::driver::CudaContext;
-use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use cudarc::driver::{sys, CudaContext, CudaStream};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
 use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
 
 /// Callback handler that captures frames arriving in CUDA pinned memory.
 struct CudaFrameCapture {
@@ -33,39 +38,85 @@ struct CudaFrameCapture {
     done: AtomicBool,
     notify: Condvar,
     lock: Mutex<()>,
+
+    stream: Arc<CudaStream>,
+    /// Device memory the frames are copied into, allocated for the first frame.
+    device_buffer: Mutex<Option<(sys::CUdeviceptr, usize)>>,
+    bytes_copied: AtomicU64,
+    copy_time: Mutex<Duration>,
 }
 
 impl CudaFrameCapture {
-    fn new(max_frames: u32) -> Self {
+    fn new(max_frames: u32, stream: Arc<CudaStream>) -> Self {
         Self {
             frame_count: AtomicU32::new(0),
             max_frames,
             done: AtomicBool::new(false),
             notify: Condvar::new(),
             lock: Mutex::new(()),
+            stream,
+            device_buffer: Mutex::new(None),
+            bytes_copied: AtomicU64::new(0),
+            copy_time: Mutex::new(Duration::ZERO),
+        }
+    }
+
+    fn copy_to_device(&self, frame: DecklinkVideoFrame) {
+        let size = frame.row_bytes() * frame.height();
+        let mut buffer = self.device_buffer.lock().unwrap();
+        if buffer.is_none_or(|(_, len)| len < size) {
+            if let Some((ptr, _)) = buffer.take() {
+                let _ = unsafe { cudarc::driver::result::free_sync(ptr) };
+            }
+            match unsafe { cudarc::driver::result::malloc_sync(size) } {
+                Ok(ptr) => *buffer = Some((ptr, size)),
+                Err(e) => {
+                    eprintln!("Failed to allocate device memory: {:?}", e);
+                    return;
+                }
+            }
+        }
+        let (dst, dst_len) = buffer.unwrap();
+
+        let start = Instant::now();
+        let ticket = unsafe {
+            copy_frame_to_device(frame, dst, dst_len, &self.stream, CudaCopyLayout::Packed)
+        };
+        match ticket {
+            Ok(ticket) => {
+                let byte_count = ticket.byte_count();
+                if ticket.synchronize().is_ok() {
+                    *self.copy_time.lock().unwrap() += start.elapsed();
+                    self.bytes_copied.fetch_add(byte_count as u64, Ordering::Relaxed);
+                }
+            }
+            Err(e) => eprintln!("Failed to copy frame to device memory: {:?}", e),
+        }
+    }
+}
+
+impl Drop for CudaFrameCapture {
+    fn drop(&mut self) {
+        if let Some((ptr, _)) = self.device_buffer.lock().unwrap().take() {
+            let _ = unsafe { cudarc::driver::result::free_sync(ptr) };
         }
     }
 }
 
-impl DeckLinkInputCallback for CudaFrameCapture {
-    fn video_input_format_changed(
-        &self,
-        events: DecklinkVideoInputFormatChangedEvents,
-        new_display_mode: DecklinkDisplayModeId,
-        detected_signal_flags: DecklinkDetectedVideoInputFormatFlags,
-    ) {
+impl InputHandler for CudaFrameCapture {
+    fn format_changed(&self, change: &InputFormatChange) {
         println!(
             "Input format changed: events={:?}, mode={:?}, flags={:?}",
-            events, new_display_mode, detected_signal_flags
+            change.events, change.display_mode, change.detected_signal_flags
         );
     }
 
-    fn video_input_frame_arrived(&self, video_frame: Option<DecklinkVideoFrame>) -> bool {
+    fn frame_arrived(&self, arrival: &FrameArrival<'_>) -> CallbackResult {
         if self.done.load(Ordering::Relaxed) {
-            return true;
+            return CallbackResult::Ok;
         }
 
-        if let Some(frame) = video_frame {
+        if let Some(frame) = arrival.retain_video_frame() {
             let count = self.frame_count.fetch_add(1, Ordering::Relaxed) + 1;
             println!(
                 "Frame #{}: {}x{}, row_bytes={}, format={:?} (in CUDA pinned memory)",
@@ -76,10 +127,9 @@ impl DeckLinkInputCallback for CudaFrameCapture {
                 frame.pixel_format(),
             );
 
-            // The frame data is already in CUDA pinned memory!
-            // You can now efficiently copy it to GPU device memory using:
-            //   cuMemcpyHtoDAsync(device_ptr, host_ptr, size, stream)
-            // Or access it directly via zero-copy if supported.
+            // The frame data is already in CUDA pinned memory, so the copy to
+            // device memory runs without staging.
+            self.copy_to_device(frame);
 
             if count >= self.max_frames {
                 self.done.store(true, Ordering::Relaxed);
@@ -88,7 +138,7 @@ impl DeckLinkInputCallback for CudaFrameCapture {
             }
         }
 
-        true
+        CallbackResult::Ok
     }
 }
 
@@ -121,56 +171,38 @@ fn select_display_mode(modes: &[DecklinkDisplayMode]) -> usize {
     idx
 }
 
-fn main() {
-    // Initialize CUDA
-    let ctx = CudaContext::new(0).expect("Failed to initialize CUDA context 0");
-    println!("CUDA context initialized");
-
-    // Create the CUDA allocator provider
-    let cuda_provider: Arc<dyn VideoBufferAllocatorProvider> =
-        Arc::new(CudaAllocatorProvider::new(ctx));
-
-    // Get DeckLink devices
-    let devices = get_devices().expect("Failed to enumerate DeckLink devices");
-    if devices.is_empty() {
-        eprintln!("No DeckLink devices found.");
-        return;
-    }
-
-    let dev_idx = select_device(&devices);
-    let device = &devices[dev_idx];
-
-    // Get input device
-    let mut input = device.input().expect("Failed to get input device");
-
-    // List display modes
-    let modes = input.display_modes().expect("Failed to get display modes");
-    let mode_idx = select_display_mode(&modes);
-    let selected_mode = modes[mode_idx].mode();
-
-    let pixel_format = DecklinkPixelFormat::Format8BitYUV;
+/// Capture `frames` frames into pinned memory of `ctx`, copying each to device memory, then
+/// stop and disable the input. Returns the provider, which the driver no longer uses.
+fn capture(
+    input: &mut DecklinkInputDevice,
+    mode: DecklinkDisplayModeId,
+    ctx: Arc<CudaContext>,
+    frames: u32,
+) -> Arc<CudaAllocatorProvider> {
+    let stream = ctx.default_stream();
+    let provider = Arc::new(CudaAllocatorProvider::new(ctx));
 
     // Enable video input with CUDA allocator provider
     input
         .enable_video_input_with_allocator(
-            selected_mode,
-            pixel_format,
+            mode,
+            DecklinkPixelFormat::Format8BitYUV,
             DecklinkVideoInputFlags::empty(),
-            cuda_provider,
+            provider.clone(),
         )
         .expect("Failed to enable video input with CUDA allocator");
 
     println!("\nVideo input enabled with CUDA pinned memory allocator");
 
     // Set up callback
-    let capture = Arc::new(CudaFrameCapture::new(30)); // Capture 30 frames
+    let capture = Arc::new(CudaFrameCapture::new(frames, stream));
     input
         .set_callback(Some(capture.clone()))
         .expect("Failed to set callback");
 
     // Start streaming
     input.start_streams().expect("Failed to start streams");
-    println!("Capturing 30 frames into CUDA pinned memory...\n");
+    println!("Capturing {} frames into CUDA pinned memory...\n", frames);
 
     // Wait for frames
     let lock = capture.lock.lock().unwrap();
@@ -179,12 +211,72 @@ fn main() {
         .wait_while(lock, |_| !capture.done.load(Ordering::Relaxed))
         .unwrap();
 
-    // Stop
+    // Stop, which releases the provider's allocators, and the handler with its stream
     input.stop_streams().expect("Failed to stop streams");
     input
         .disable_video_input()
         .expect("Failed to disable video input");
+    input.set_callback(None).expect("Failed to clear callback");
 
     let total = capture.frame_count.load(Ordering::Relaxed);
     println!("\nDone! Captured {} frames into CUDA pinned memory.", total);
+
+    let bytes = capture.bytes_copied.load(Ordering::Relaxed);
+    let secs = capture.copy_time.lock().unwrap().as_secs_f64();
+    if secs > 0.0 {
+        println!(
+            "Copied {} MB to device memory at {:.1} MB/s",
+            bytes / 1_000_000,
+            bytes as f64 / secs / 1_000_000.0
+        );
+    }
+    provider
+}
+
+fn main() {
+    let simulate_reset = std::env::args().any(|arg| arg == "--simulate-reset");
+
+    // Initialize CUDA
+    let ctx = CudaContext::new(0).expect("Failed to initialize CUDA context 0");
+    println!("CUDA context initialized");
+
+    // Get DeckLink devices
+    let devices = get_devices().expect("Failed to enumerate DeckLink devices");
+    if devices.is_empty() {
+        eprintln!("No DeckLink devices found.");
+        return;
+    }
+
+    let dev_idx = select_device(&devices);
+    let device = &devices[dev_idx];
+
+    // Get input device
+    let mut input = device.input().expect("Failed to get input device");
+
+    // List display modes
+    let modes = input.display_modes().expect("Failed to get display modes");
+    let mode_idx = select_display_mode(&modes);
+    let selected_mode = modes[mode_idx].mode();
+
+    let provider = capture(&mut input, selected_mode, ctx, 30);
+    if !simulate_reset {
+        return;
+    }
+
+    // The input is disabled, so the provider can be invalidated before its context goes.
+    // Buffers of frames still held would be leaked rather than freed.
+    println!("\nSimulating a GPU reset");
+    provider
+        .invalidate()
+        .expect("Failed to invalidate the provider");
+    println!(
+        "Provider invalidated, {} buffers still held",
+        provider.live_buffer_count()
+    );
+    // The context is destroyed once the provider, and any buffers, are dropped
+    drop(provider);
+
+    let ctx = CudaContext::new(0).expect("Failed to recreate CUDA context 0");
+    println!("CUDA context recreated");
+    capture(&mut input, selected_mode, ctx, 30);
 }
@@ -0,0 +1,99 @@
+extern crate decklink;
+
+use decklink::dashboard::{
+    DeviceDashboard, DeviceMonitor, DeviceMonitorConfig, TemperatureAlert, TemperatureThreshold,
+};
+use decklink::device::get_devices;
+use std::time::Duration;
+
+fn show<T: std::fmt::Debug>(value: &Option<T>) -> String {
+    match value {
+        Some(v) => format!("{:?}", v),
+        None => "Unknown".to_string(),
+    }
+}
+
+fn render(dashboard: &DeviceDashboard) {
+    // Clear the terminal and move to the top left
+    print!("\x1B[2J\x1B[H");
+    println!("{0: <20} {1}", "Present", dashboard.present);
+    println!("{0: <20} {1}", "Label", show(&dashboard.label));
+    println!(
+        "{0: <20} {1}",
+        "Signal locked",
+        show(&dashboard.signal_locked)
+    );
+    println!(
+        "{0: <20} {1}",
+        "Detected mode",
+        show(&dashboard.detected_mode)
+    );
+    println!(
+        "{0: <20} {1}",
+        "Reference locked",
+        show(&dashboard.reference_locked)
+    );
+    println!("{0: <20} {1}", "Busy", show(&dashboard.busy));
+    println!(
+        "{0: <20} {1}",
+        "Temperature",
+        dashboard
+            .temperature
+            .map(|t| format!("{} C", t))
+            .unwrap_or_else(|| "Unknown".to_string())
+    );
+    println!("{0: <20} {1}", "Profile", show(&dashboard.profile));
+    for reading in &dashboard.custom {
+        println!("{}", reading);
+    }
+}
+
+fn main() {
+    let devices = get_devices()
+        .expect("Unable to list Decklink devices. The Decklink drivers may not be insalled.");
+    let device = devices
+        .first()
+        .expect("Could not find any Decklink devices");
+
+    let monitor = DeviceMonitor::new(
+        device,
+        DeviceMonitorConfig {
+            interval: Duration::from_millis(250),
+            debounce: Duration::from_millis(500),
+            temperature_threshold: Some(TemperatureThreshold {
+                limit_c: 70,
+                hysteresis_c: 5,
+            }),
+        },
+        |delta| {
+            render(&delta.current);
+            match delta.temperature_alert {
+                Some(TemperatureAlert::OverLimit { temperature_c }) => {
+                    println!(
+                        "\nWarning: the device is running hot, at {} C",
+                        temperature_c
+                    )
+                }
+                Some(TemperatureAlert::Recovered { temperature_c }) => {
+                    println!("\nThe device has cooled down to {} C", temperature_c)
+                }
+                None => {}
+            }
+        },
+    )
+    .expect("Failed to start device monitor");
+
+    render(&monitor.current());
+
+    // Run until killed, reporting the polling cost now and then
+    loop {
+        std::thread::sleep(Duration::from_secs(10));
+        let cost = monitor.poll_cost();
+        println!(
+            "\n{} polls, average {:?}, max {:?}",
+            cost.polls,
+            cost.average(),
+            cost.max
+        );
+    }
+}
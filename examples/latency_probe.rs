@@ -0,0 +1,171 @@
+extern crate decklink;
+
+use decklink::batch::{BatchConfig, BatchDispatcher, BatchedFrame, DeckLinkInputBatchCallback};
+use decklink::device::input::{
+    CallbackResult, DecklinkVideoInputFlags, FrameArrival, InputHandler,
+};
+use decklink::device::selector::DeviceSelector;
+use decklink::device::DecklinkDeviceDisplayModes;
+use decklink::frame::{DecklinkFrameBase, DecklinkPixelFormat};
+use decklink::latency::{
+    FrameLatency, LatencyBound, LatencyMeter, LatencyProfile, LatencyStats, StageStats,
+};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Stands in for real work on a frame, by reading its first row.
+fn touch<F: DecklinkFrameBase>(frame: &F) -> u64 {
+    frame
+        .bytes()
+        .map(|bytes| {
+            bytes
+                .0
+                .iter()
+                .take(frame.row_bytes())
+                .map(|b| *b as u64)
+                .sum()
+        })
+        .unwrap_or(0)
+}
+
+/// Handles frames in the driver callback, for the low latency profile.
+struct DirectHandler;
+
+impl InputHandler for DirectHandler {
+    fn frame_arrived(&self, arrival: &FrameArrival<'_>) -> CallbackResult {
+        if let Some(frame) = arrival.video_frame {
+            std::hint::black_box(touch(frame));
+        }
+        CallbackResult::Ok
+    }
+}
+
+/// Handles batches of frames, for the default profile, recording how long each waited.
+struct BatchHandler {
+    meter: LatencyMeter,
+}
+
+impl DeckLinkInputBatchCallback for BatchHandler {
+    fn frames_arrived(&mut self, frames: &[BatchedFrame]) {
+        for batched in frames {
+            let queue_wait = batched.arrived.elapsed();
+            let started = Instant::now();
+            std::hint::black_box(touch(&batched.frame));
+            self.meter.record(FrameLatency {
+                queue_wait,
+                handler: started.elapsed(),
+                ..FrameLatency::default()
+            });
+        }
+    }
+}
+
+fn print_stage(name: &str, stage: &StageStats) {
+    println!(
+        "  {:<11} p50 {:>9.3?}  p99 {:>9.3?}  max {:>9.3?}",
+        name, stage.p50, stage.p99, stage.max
+    );
+}
+
+fn print_stats(stats: &LatencyStats) {
+    println!(
+        "{} frames, percentiles of the last {}:",
+        stats.frames, stats.window
+    );
+    print_stage("queue wait", &stats.queue_wait);
+    print_stage("handler", &stats.handler);
+    print_stage("total", &stats.total);
+}
+
+/// Capture for a while and report the latency from the driver callback to the handler.
+///
+/// Usage: latency_probe [--low-latency] [--batch] [device] [mode name] [seconds]
+///
+/// The default profile batches frames on a dispatch thread. `--low-latency` handles them in
+/// the driver callback instead, and `--batch` with it shows the conflict being refused.
+fn main() {
+    let (flags, args): (Vec<String>, Vec<String>) =
+        std::env::args().skip(1).partition(|a| a.starts_with("--"));
+    let profile = if flags.iter().any(|f| f == "--low-latency") {
+        LatencyProfile::LowLatency
+    } else {
+        LatencyProfile::Default
+    };
+    let batch = profile == LatencyProfile::Default || flags.iter().any(|f| f == "--batch");
+
+    let mut args = args.into_iter();
+    let selector: DeviceSelector = args
+        .next()
+        .unwrap_or_else(|| "first".to_string())
+        .parse()
+        .expect("Invalid device selector");
+    let mode_name = args.next();
+    let seconds: u64 = args
+        .next()
+        .map_or(10, |s| s.parse().expect("Invalid seconds"));
+
+    let batch_config = BatchConfig::default();
+    if batch {
+        if let Err(conflict) = profile.check_batch(&batch_config) {
+            eprintln!("Refused: {}", conflict);
+            std::process::exit(1);
+        }
+    }
+
+    let device = selector.resolve().expect("Failed to find the device");
+    let mut input = device.input().expect("The device has no input");
+    let modes = input.display_modes().expect("Failed to list display modes");
+    let mode = match &mode_name {
+        Some(name) => modes.iter().find(|m| m.name_str() == Some(name.as_str())),
+        None => modes.first(),
+    }
+    .expect("No such display mode");
+    input
+        .enable_video_input(
+            mode.mode(),
+            DecklinkPixelFormat::Format8BitYUV,
+            DecklinkVideoInputFlags::empty(),
+        )
+        .expect("Failed to enable video input");
+
+    let meter = LatencyMeter::new(1000);
+    meter.set_bound(Some(LatencyBound {
+        p99: Duration::from_millis(5),
+        sustained: Duration::from_secs(1),
+    }));
+
+    // The dispatcher is kept until the end, as dropping it stops its thread
+    let dispatcher = batch.then(|| {
+        BatchDispatcher::new(
+            batch_config,
+            BatchHandler {
+                meter: meter.clone(),
+            },
+        )
+    });
+    let callback = match &dispatcher {
+        Some(dispatcher) => dispatcher.callback(),
+        None => meter.measured(Arc::new(DirectHandler)),
+    };
+    input
+        .set_handler(Some(callback))
+        .expect("Failed to set input callback");
+
+    println!(
+        "Capturing {} for {}s with the {:?} profile",
+        mode.name_str().unwrap_or("unknown mode"),
+        seconds,
+        profile
+    );
+    input.start_streams().expect("Failed to start streams");
+    for _ in 0..seconds {
+        std::thread::sleep(Duration::from_secs(1));
+        for event in meter.take_events() {
+            println!("{:?}", event);
+        }
+    }
+    input.stop_streams().ok();
+    drop(dispatcher);
+
+    print_stats(&meter.stats());
+}
@@ -22,11 +22,11 @@ fn main() {
             "A DeckLink iterator could not be created.  The DeckLink drivers may not be installed."
         ),
         Ok(devices) => {
-            if devices.len() == 0 {
+            if devices.is_empty() {
                 println!("No Blackmagic Design devices were found.\n");
             } else {
                 for device in devices {
-                    if let Some(name) = device.model_name() {
+                    if let Some(name) = device.model_name_str() {
                         println!("=============== {} ===============\n", name);
                     }
 
@@ -179,7 +179,17 @@ fn print_output_modes(device: &DecklinkDevice) {
     }
 }
 
-fn print_input_modes(_device: &DecklinkDevice) {
+fn print_input_modes(device: &DecklinkDevice) {
+    if let Some(input) = device.input() {
+        match input.supported_pixel_formats() {
+            Ok(formats) => println!("Supported video input pixel formats: {:?}", formats),
+            Err(e) => println!(
+                "Could not obtain supported input pixel formats - result = {:?}",
+                e
+            ),
+        }
+    }
+
     //    if let Some(input) = device.input() {
     //        if let Ok(modes) = output.display_modes() {
     //            println!("Supported video input display modes and pixel formats:");
@@ -238,4 +248,20 @@ fn print_capabilities(device: &DecklinkDevice) {
             println!();
         }
     }
+
+    match device.concurrent_capability() {
+        Err(e) => println!("Could not obtain the concurrent capability - result = {:?}", e),
+        Ok(capability) => {
+            println!(
+                "Simultaneous capture and playback: {}",
+                capability.can_capture_and_playback_simultaneously
+            );
+            if let Some(profile) = capability.limited_by_profile {
+                println!("Limited by profile: {:?}", profile);
+            }
+            if let Some(id) = capability.paired_device {
+                println!("Paired device: {:x}", id);
+            }
+        }
+    }
 }
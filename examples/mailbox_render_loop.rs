@@ -0,0 +1,87 @@
+extern crate decklink;
+
+use decklink::device::get_devices;
+use decklink::device::input::DecklinkVideoInputFlags;
+use decklink::device::DecklinkDeviceDisplayModes;
+use decklink::frame::{DecklinkFrameBase, DecklinkPixelFormat};
+use decklink::mailbox::{LatestFrameMailbox, MailboxConfig, MailboxPoll};
+use decklink::retention::{RetentionBudget, RetentionLimit, RetentionMode};
+use std::time::{Duration, Instant};
+
+/// The rate of the render loop, which need not match the input's.
+const RENDER_RATE: u32 = 30;
+
+/// Capture from the first input and draw the newest frame in a render loop of its own rate,
+/// as an application built around polling would, for ten seconds or the number of seconds
+/// given.
+///
+/// Usage: mailbox_render_loop [seconds]
+fn main() {
+    let seconds: u64 = std::env::args()
+        .nth(1)
+        .map(|s| s.parse().expect("Invalid number of seconds"))
+        .unwrap_or(10);
+
+    let devices = get_devices().expect("Failed to list devices");
+    let mut input = devices
+        .iter()
+        .find_map(|d| d.input())
+        .expect("No device has an input");
+    let modes = input.display_modes().expect("Failed to list display modes");
+    let mode = modes.first().expect("The input has no display modes");
+    input
+        .enable_video_input(
+            mode.mode(),
+            DecklinkPixelFormat::Format8BitYUV,
+            DecklinkVideoInputFlags::empty(),
+        )
+        .expect("Failed to enable video input");
+
+    // Room for the frame being drawn, and the next one arriving meanwhile
+    let budget = RetentionBudget::new(RetentionLimit::Frames(2), RetentionMode::Strict);
+    let mailbox = LatestFrameMailbox::new(MailboxConfig::default(), Some(budget));
+    input
+        .set_handler(Some(mailbox.callback()))
+        .expect("Failed to set input callback");
+    input.start_streams().expect("Failed to start streams");
+
+    let tick = Duration::from_secs(1) / RENDER_RATE;
+    let end = Instant::now() + Duration::from_secs(seconds);
+    let mut last = 0;
+    let mut repeated = 0;
+    while Instant::now() < end {
+        let started = Instant::now();
+        match mailbox.poll_if_newer(last) {
+            MailboxPoll::Frame(frame) => {
+                // Drawing is left to the application, this only reports the frame
+                println!(
+                    "Frame {}: {}x{}, {:.1} ms old",
+                    frame.sequence(),
+                    frame.frame().width(),
+                    frame.frame().height(),
+                    frame.age().as_secs_f64() * 1000.0
+                );
+                last = frame.sequence();
+            }
+            // Draw the last frame again
+            MailboxPoll::Unchanged | MailboxPoll::Empty => repeated += 1,
+            MailboxPoll::FormatChanged => {
+                println!("The input changed format, waiting for a frame in the new one");
+            }
+            MailboxPoll::Closed => break,
+        }
+        if let Some(rest) = tick.checked_sub(started.elapsed()) {
+            std::thread::sleep(rest);
+        }
+    }
+
+    input.stop_streams().expect("Failed to stop streams");
+    input
+        .set_handler(None)
+        .expect("Failed to clear input callback");
+    let stats = mailbox.stats();
+    println!(
+        "{} frames arrived, {} drawn, {} overwritten unread, {} ticks repeated a frame",
+        stats.published, stats.polled, stats.overwritten, repeated
+    );
+}
@@ -0,0 +1,136 @@
+extern crate decklink;
+
+use decklink::device::input::{
+    CallbackResult, DecklinkAudioInputPacket, DecklinkAudioSampleRate, DecklinkAudioSampleType,
+    DecklinkVideoInputFlags, FrameArrival, InputFormatChange, InputHandler,
+};
+use decklink::device::selector::DeviceSelector;
+use decklink::device::DecklinkDeviceDisplayModes;
+use decklink::experimental::mov::{MovAudioConfig, MovConfig, MovWriter};
+use decklink::frame::{DecklinkPixelFormat, DecklinkVideoFrame};
+use decklink::time::{DecklinkFrameTiming, DecklinkTime};
+use std::sync::mpsc::{channel, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const SAMPLE_RATE: u32 = 48000;
+const CHANNELS: u32 = 2;
+
+enum Captured {
+    Frame(DecklinkVideoFrame, Option<DecklinkFrameTiming>),
+    /// The samples and time of an audio packet, copied as packets cannot be sent to another
+    /// thread.
+    Audio(Vec<u8>, DecklinkTime),
+}
+
+/// Sends what is captured to the writing thread.
+struct Capture {
+    sender: Mutex<Sender<Captured>>,
+}
+
+impl InputHandler for Capture {
+    fn format_changed(&self, change: &InputFormatChange) {
+        eprintln!("The input changed to {:?}", change.display_mode);
+    }
+
+    fn frame_arrived(&self, arrival: &FrameArrival<'_>) -> CallbackResult {
+        let sender = self.sender.lock().unwrap();
+        if let Some(packet) = arrival.audio_packet {
+            if let (Ok(bytes), Ok(time)) = (packet.bytes(), packet.packet_time(SAMPLE_RATE as i64))
+            {
+                let time = DecklinkTime::new(time, SAMPLE_RATE as i64);
+                let _ = sender.send(Captured::Audio(bytes.to_vec(), time));
+            }
+        }
+        if let Some(frame) = arrival.retain_video_frame() {
+            let _ = sender.send(Captured::Frame(frame, arrival.timing()));
+        }
+        CallbackResult::Ok
+    }
+}
+
+/// Record ten seconds of 8-bit YUV video and stereo audio into a QuickTime movie.
+///
+/// Usage: mov_record [device] [mode name] [out.mov]
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let selector: DeviceSelector = args
+        .next()
+        .unwrap_or_else(|| "first".to_string())
+        .parse()
+        .expect("Invalid device selector");
+    let mode_name = args.next();
+    let path = args.next().unwrap_or_else(|| "capture.mov".to_string());
+
+    let device = selector.resolve().expect("Failed to find the device");
+    let mut input = device.input().expect("The device has no input");
+    let modes = input.display_modes().expect("Failed to list display modes");
+    let mode = match &mode_name {
+        Some(name) => modes.iter().find(|m| m.name_str() == Some(name.as_str())),
+        None => modes.first(),
+    }
+    .expect("No such display mode");
+
+    let config = MovConfig::for_mode(mode)
+        .expect("The display mode has no frame duration")
+        .with_audio(MovAudioConfig {
+            sample_rate: SAMPLE_RATE,
+            channels: CHANNELS,
+            sample_type: DecklinkAudioSampleType::Int16,
+        });
+    let mut writer = MovWriter::create(&path, config).expect("Failed to create the file");
+
+    let (sender, receiver) = channel();
+    input
+        .set_handler(Some(Arc::new(Capture {
+            sender: Mutex::new(sender),
+        })))
+        .expect("Failed to set input callback");
+    input
+        .enable_video_input(
+            mode.mode(),
+            DecklinkPixelFormat::Format8BitYUV,
+            DecklinkVideoInputFlags::empty(),
+        )
+        .expect("Failed to enable video input");
+    input
+        .enable_audio_input(
+            DecklinkAudioSampleRate::Rate48kHz,
+            DecklinkAudioSampleType::Int16,
+            CHANNELS,
+        )
+        .expect("Failed to enable audio input");
+
+    println!(
+        "Recording {} to {}",
+        mode.name_str().unwrap_or("unknown mode"),
+        path
+    );
+    input.start_streams().expect("Failed to start streams");
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while let Ok(captured) =
+        receiver.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+    {
+        match captured {
+            Captured::Frame(frame, timing) => writer
+                .write_frame(&frame, timing.as_ref())
+                .expect("Failed to write a frame"),
+            Captured::Audio(bytes, time) => {
+                let packet = DecklinkAudioInputPacket::from_samples(
+                    DecklinkAudioSampleType::Int16,
+                    CHANNELS,
+                    bytes,
+                    time,
+                );
+                writer.write_audio(&packet).expect("Failed to write audio");
+            }
+        }
+    }
+    input.stop_streams().ok();
+
+    let info = writer.finish().expect("Failed to finish the file");
+    println!(
+        "Wrote {} frames with {} gaps and {} audio sample frames, {} bytes",
+        info.video_frames, info.video_gaps, info.audio_frames, info.byte_count
+    );
+}
@@ -0,0 +1,94 @@
+extern crate decklink;
+
+use decklink::device::get_devices;
+use decklink::device::input::{
+    CallbackResult, DecklinkVideoInputFlags, FrameArrival, InputHandler,
+};
+use decklink::device::DecklinkDeviceDisplayModes;
+use decklink::frame::DecklinkPixelFormat;
+use decklink::multiview::{MultiviewConfig, Multiviewer, RawOutput};
+use decklink::tap::{TapSpec, TapSplitter};
+use std::fs::File;
+use std::io::BufWriter;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// The primary callback, which would normally be the ingest itself.
+struct Discard;
+
+impl InputHandler for Discard {
+    fn frame_arrived(&self, _arrival: &FrameArrival<'_>) -> CallbackResult {
+        CallbackResult::Ok
+    }
+}
+
+/// Composite the first four inputs into a 1920x1080 quad split at 25 frames per second, and
+/// write it as raw BGRA, which can be played with
+/// `ffplay -f rawvideo -pixel_format bgra -video_size 1920x1080 -framerate 25 <file>`.
+///
+/// Usage: multiview [file, or - for standard output]
+fn main() {
+    let path = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "multiview.bgra".to_string());
+
+    let config = MultiviewConfig::default();
+    let mut viewer = Multiviewer::new(config).expect("Invalid multiview config");
+
+    let devices = get_devices().expect("Failed to list devices");
+    let mut captures = Vec::new();
+    for (tile, device) in devices.iter().take(viewer.tiles().len()).enumerate() {
+        let Some(mut input) = device.input() else {
+            continue;
+        };
+        let modes = input.display_modes().expect("Failed to list display modes");
+        let mode = modes.first().expect("The input has no display modes");
+        input
+            .enable_video_input(
+                mode.mode(),
+                DecklinkPixelFormat::Format8BitYUV,
+                DecklinkVideoInputFlags::empty(),
+            )
+            .expect("Failed to enable video input");
+
+        let splitter = TapSplitter::new(Arc::new(Discard), None);
+        input
+            .set_handler(Some(splitter.callback()))
+            .expect("Failed to set input callback");
+        let label = device.display_name().unwrap_or_default();
+        let tap = splitter.tap(viewer.feed(tile, &label), TapSpec::default());
+        input.start_streams().expect("Failed to start streams");
+        captures.push((input, splitter, tap));
+    }
+
+    if path == "-" {
+        viewer.start(RawOutput::new(std::io::stdout()));
+    } else {
+        let file = File::create(&path).expect("Failed to create the output file");
+        viewer.start(RawOutput::new(BufWriter::new(file)));
+    }
+    eprintln!(
+        "Compositing {} inputs into {}, press enter to stop",
+        captures.len(),
+        path
+    );
+    let _ = std::io::stdin().read_line(&mut String::new());
+
+    for (input, splitter, tap) in captures {
+        input.stop_streams().ok();
+        splitter.finish(Duration::from_secs(1));
+        tap.join();
+    }
+    viewer.stop();
+    let stats = viewer.stats();
+    eprintln!(
+        "{} frames composited, {} missed, {} output errors",
+        stats.composited, stats.missed, stats.output_errors
+    );
+    for tile in &stats.tiles {
+        eprintln!(
+            "{}: {:?}, {} frames, {} scaled, {} skipped",
+            tile.label, tile.signal, tile.frames, tile.scaled, tile.skipped
+        );
+    }
+}
@@ -0,0 +1,114 @@
+extern crate decklink;
+#[macro_use]
+extern crate text_io;
+
+use decklink::device::output::{DecklinkOutputDevice, DecklinkVideoOutputFlags};
+use decklink::device::DecklinkDeviceDisplayModes;
+use decklink::device::{get_devices, DecklinkDevice};
+use decklink::display_mode::DecklinkDisplayMode;
+use decklink::frame::{DecklinkFrameFlags, DecklinkPixelFormat};
+
+/// The 75% colour bars, as BGRA: white, yellow, cyan, green, magenta, red and blue.
+const BARS: [[u8; 4]; 7] = [
+    [191, 191, 191, 255],
+    [0, 191, 191, 255],
+    [191, 191, 0, 255],
+    [0, 191, 0, 255],
+    [191, 0, 191, 255],
+    [0, 0, 191, 255],
+    [191, 0, 0, 255],
+];
+
+fn select_output_and_format() -> Option<(DecklinkDevice, DecklinkOutputDevice, DecklinkDisplayMode)>
+{
+    let device = {
+        let mut devices = get_devices().expect("list devices failed");
+        println!("Found {} devices", devices.len());
+        for (i, device) in devices.iter().enumerate() {
+            println!(
+                "{}: {}",
+                i,
+                device
+                    .display_name()
+                    .unwrap_or_else(|| "Unknown".to_string())
+            );
+        }
+
+        let index: usize = read!();
+        if index >= devices.len() {
+            println!("Invalid device index");
+            return None;
+        }
+
+        devices.swap_remove(index)
+    };
+
+    let output = match device.output() {
+        None => {
+            println!("Failed to create device output");
+            return None;
+        }
+        Some(o) => o,
+    };
+
+    let mode = {
+        let mut supported_modes = output
+            .display_modes()
+            .expect("Failed to list display modes");
+        for (i, mode) in supported_modes.iter().enumerate() {
+            println!(
+                "{}: {}",
+                i,
+                mode.name().unwrap_or_else(|| "Unknown".to_string())
+            );
+        }
+
+        let index: usize = read!();
+        if index >= supported_modes.len() {
+            println!("Invalid mode index");
+            return None;
+        }
+
+        supported_modes.swap_remove(index)
+    };
+
+    Some((device, output, mode))
+}
+
+fn main() {
+    if let Some((_device, output, mode)) = select_output_and_format() {
+        let (width, height) = (mode.width(), mode.height());
+        output
+            .enable_video_output(mode.mode(), DecklinkVideoOutputFlags::empty())
+            .expect("Failed to enable video output");
+
+        // Draw the bars straight into a frame of the driver's
+        let row_bytes = output
+            .row_bytes_for_pixel_format(DecklinkPixelFormat::Format8BitBGRA, width)
+            .expect("Failed to get the row bytes");
+        let mut frame = output
+            .create_video_frame(
+                width,
+                height,
+                row_bytes,
+                DecklinkPixelFormat::Format8BitBGRA,
+                DecklinkFrameFlags::empty(),
+            )
+            .expect("Failed to create frame");
+        let bytes = frame.bytes_mut().expect("Failed to get frame bytes");
+        for row in bytes.chunks_exact_mut(row_bytes) {
+            for (x, pixel) in row[..width * 4].chunks_exact_mut(4).enumerate() {
+                pixel.copy_from_slice(&BARS[x * BARS.len() / width]);
+            }
+        }
+
+        output
+            .display_video_frame(&frame)
+            .expect("Failed to display frame");
+
+        println!("Press enter to continue");
+        let _s: String = read!();
+
+        // Video output is disabled when the output is dropped
+    }
+}
@@ -13,11 +13,11 @@ fn select_output_and_format() -> Option<(DecklinkDevice, DecklinkOutputDevice, D
     let device = {
         let mut devices = get_devices().expect("list devices failed");
         println!("Found {} devices", devices.len());
-        for i in 0..devices.len() {
+        for (i, device) in devices.iter().enumerate() {
             println!(
                 "{}: {}",
                 i,
-                devices[i]
+                device
                     .display_name()
                     .unwrap_or_else(|| "Unknown".to_string())
             );
@@ -51,11 +51,11 @@ fn select_output_and_format() -> Option<(DecklinkDevice, DecklinkOutputDevice, D
         let mut supported_modes = output
             .display_modes()
             .expect("Failed to list display modes");
-        for i in 0..supported_modes.len() {
+        for (i, mode) in supported_modes.iter().enumerate() {
             println!(
                 "{}: {}",
                 i,
-                supported_modes[i]
+                mode
                     .name()
                     .unwrap_or_else(|| "Unknown".to_string())
             );
@@ -83,7 +83,7 @@ fn main() {
             DecklinkFrameFlags::empty(),
         ));
 
-        let bytes = vec![120u8; (mode.width() * mode.height() * 4) as usize];
+        let bytes = vec![120u8; mode.width() * mode.height() * 4];
         if frame.copy_bytes(&bytes).is_err() {
             println!("Failed to set frame bytes");
             return;
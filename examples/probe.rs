@@ -0,0 +1,54 @@
+extern crate decklink;
+
+use decklink::capabilities;
+use decklink::device::get_devices;
+use decklink::probe::run_all;
+use std::time::Instant;
+
+fn main() {
+    let json = std::env::args().any(|a| a == "--json");
+
+    // Built with the mock backend, probe a virtual 8K device, to show that a full scan of
+    // its hundreds of mode and pixel format combinations completes within the probe timeout
+    #[cfg(feature = "mock-backend")]
+    let _backend = decklink::mock::MockBackend::install(vec![decklink::mock::MockDevice::eight_k(
+        "DeckLink 8K Pro",
+    )]);
+
+    let devices = get_devices()
+        .expect("Unable to list Decklink devices. The Decklink drivers may not be insalled.");
+    if devices.is_empty() {
+        eprintln!("Could not find any Decklink devices");
+        std::process::exit(1);
+    }
+
+    let start = Instant::now();
+    let reports: Vec<_> = devices.iter().map(run_all).collect();
+    eprintln!(
+        "Probed {} device(s) in {:.1}s",
+        devices.len(),
+        start.elapsed().as_secs_f64()
+    );
+    if !json {
+        let capabilities = capabilities();
+        println!("Optional driver capabilities:");
+        println!("  allocator provider: {}", capabilities.allocator_provider);
+        println!("  profiles:           {}", capabilities.profiles);
+        println!();
+    }
+    if json {
+        // One report per device, as a JSON array
+        let reports: Vec<String> = reports.iter().map(|r| r.to_json()).collect();
+        println!("[\n{}]", reports.join(",\n"));
+    } else {
+        for report in &reports {
+            println!("{}", report);
+        }
+    }
+
+    let failed = reports.iter().any(|r| r.failures().next().is_some());
+
+    if failed {
+        std::process::exit(2);
+    }
+}
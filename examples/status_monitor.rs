@@ -114,6 +114,11 @@ fn print_status(status: &DecklinkDeviceStatus, id: DecklinkStatusId) {
                 print_line(id, format!("{:?}", value))
             }
         }
+        DecklinkStatusId::DeviceTemperature => {
+            if let Ok(value) = status.device_temperature() {
+                print_line(id, format!("{} C", value))
+            }
+        }
     }
 }
 
@@ -137,6 +142,7 @@ fn main() {
     print_status(&status, DecklinkStatusId::Busy);
     print_status(&status, DecklinkStatusId::PCIExpressLinkWidth);
     print_status(&status, DecklinkStatusId::PCIExpressLinkSpeed);
+    print_status(&status, DecklinkStatusId::DeviceTemperature);
 
     // Print video input status values
     print_status(&status, DecklinkStatusId::VideoInputSignalLocked);
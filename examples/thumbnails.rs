@@ -0,0 +1,87 @@
+extern crate decklink;
+
+use decklink::device::input::{
+    CallbackResult, DecklinkVideoInputFlags, FrameArrival, InputHandler,
+};
+use decklink::device::selector::DeviceSelector;
+use decklink::device::DecklinkDeviceDisplayModes;
+use decklink::frame::DecklinkPixelFormat;
+use decklink::tap::{TapSpec, TapSplitter};
+use decklink::thumbnail::{ThumbnailInterval, ThumbnailPipeline, ThumbnailSink, ThumbnailSpec};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// The primary callback, which would normally be the ingest itself.
+struct Discard;
+
+impl InputHandler for Discard {
+    fn frame_arrived(&self, _arrival: &FrameArrival<'_>) -> CallbackResult {
+        CallbackResult::Ok
+    }
+}
+
+/// Write a thumbnail every five seconds of capture into a directory.
+///
+/// Usage: thumbnails [device] [mode name] [directory]
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let selector: DeviceSelector = args
+        .next()
+        .unwrap_or_else(|| "first".to_string())
+        .parse()
+        .expect("Invalid device selector");
+    let mode_name = args.next();
+    let directory = args.next().unwrap_or_else(|| "thumbnails".to_string());
+
+    let device = selector.resolve().expect("Failed to find the device");
+    let mut input = device.input().expect("The device has no input");
+
+    let modes = input.display_modes().expect("Failed to list display modes");
+    let mode = match &mode_name {
+        Some(name) => modes.iter().find(|m| m.name_str() == Some(name.as_str())),
+        None => modes.first(),
+    }
+    .expect("No such display mode");
+
+    input
+        .enable_video_input(
+            mode.mode(),
+            DecklinkPixelFormat::Format8BitYUV,
+            DecklinkVideoInputFlags::empty(),
+        )
+        .expect("Failed to enable video input");
+
+    std::fs::create_dir_all(&directory).expect("Failed to create the directory");
+    let spec =
+        ThumbnailSpec::new(ThumbnailInterval::Every(Duration::from_secs(5))).for_mode(mode, false);
+    let pipeline = ThumbnailPipeline::new(spec, ThumbnailSink::directory(&directory));
+    let stats = pipeline.stats();
+
+    let splitter = TapSplitter::new(Arc::new(Discard), None);
+    input
+        .set_handler(Some(splitter.callback()))
+        .expect("Failed to set input callback");
+    let tap = splitter.tap(pipeline, TapSpec::default());
+
+    input.start_streams().expect("Failed to start streams");
+    println!(
+        "Writing thumbnails of {} into {}, press enter to stop",
+        mode.name_str().unwrap_or("unknown mode"),
+        directory
+    );
+    let _ = std::io::stdin().read_line(&mut String::new());
+    input.stop_streams().ok();
+
+    // Let the thumbnail being encoded when the streams stopped be written
+    splitter.finish(Duration::from_secs(5));
+    tap.join();
+    println!(
+        "{} thumbnails written, {} skipped while busy, {} failed",
+        stats.written(),
+        stats.skipped(),
+        stats.failed()
+    );
+    if let Some(error) = stats.last_error() {
+        println!("Last error: {}", error);
+    }
+}
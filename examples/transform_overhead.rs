@@ -0,0 +1,113 @@
+extern crate decklink;
+
+use decklink::deinterlace::FrameLayout;
+use decklink::experimental::transform::{
+    Crop, FrameBufferMut, FrameTransform, FrameView, MaskRegion, NegotiationError, TransformChain,
+    TransformError,
+};
+use decklink::frame::Rect;
+use decklink::testing::{FillPattern, TestFrameBuilder};
+use std::time::Instant;
+
+/// Does nothing, in place, to measure what a stage costs on its own.
+struct Nothing;
+
+impl FrameTransform for Nothing {
+    fn name(&self) -> &str {
+        "nothing"
+    }
+    fn negotiate(&self, input: FrameLayout) -> Result<FrameLayout, NegotiationError> {
+        Ok(input)
+    }
+    fn in_place(&self) -> bool {
+        true
+    }
+    fn process(
+        &self,
+        input: FrameView<'_>,
+        output: &mut FrameBufferMut<'_>,
+    ) -> Result<(), TransformError> {
+        output.data_mut().copy_from_slice(input.data());
+        Ok(())
+    }
+    fn process_in_place(&self, _frame: &mut FrameBufferMut<'_>) -> Result<(), TransformError> {
+        Ok(())
+    }
+}
+
+fn measure(name: &str, mut chain: TransformChain, frames: u32) {
+    let frame = TestFrameBuilder::new(1920, 1080)
+        .fill(FillPattern::Counting)
+        .build()
+        .expect("Failed to build the test frame");
+
+    let started = Instant::now();
+    for _ in 0..frames {
+        let view = chain.run(&frame).expect("The chain failed");
+        std::hint::black_box(view.data());
+    }
+    let elapsed = started.elapsed();
+
+    println!("{}: {:?} a frame", name, elapsed / frames);
+    for stage in chain.stats() {
+        println!(
+            "  {:<8} in place {:<5} mean {:>9.3?}  max {:>9.3?}",
+            stage.name,
+            stage.in_place,
+            stage.mean_time(),
+            stage.max_time
+        );
+    }
+}
+
+/// Run 1080p 8-bit YUV test frames through a few chains, to show what each stage costs.
+///
+/// Usage: transform_overhead [frames]
+///
+/// No device is needed. The first in place stage of a chain copies the frame into the
+/// chain's buffer, so a chain of stages that do nothing costs one copy, and each stage
+/// after that only its dispatch.
+fn main() {
+    let frames: u32 = std::env::args()
+        .nth(1)
+        .map_or(500, |s| s.parse().expect("Invalid frame count"));
+    let mask = MaskRegion {
+        rect: Rect {
+            x: 0,
+            y: 0,
+            width: 320,
+            height: 180,
+        },
+    };
+    let crop = Crop {
+        rect: Rect {
+            x: 480,
+            y: 270,
+            width: 960,
+            height: 540,
+        },
+    };
+
+    measure("no stages", TransformChain::new(), frames);
+    measure(
+        "one empty stage",
+        TransformChain::new().with(Nothing),
+        frames,
+    );
+    measure(
+        "four empty stages",
+        TransformChain::new()
+            .with(Nothing)
+            .with(Nothing)
+            .with(Nothing)
+            .with(Nothing),
+        frames,
+    );
+    measure("mask", TransformChain::new().with(mask), frames);
+    measure("crop", TransformChain::new().with(crop), frames);
+    measure(
+        "crop then mask",
+        TransformChain::new().with(crop).with(mask),
+        frames,
+    );
+}
@@ -3,19 +3,41 @@
 //! This module provides traits and helpers for creating custom video buffer allocator
 //! providers that control where DeckLink writes incoming frame data. This is useful
 //! for receiving frames directly into GPU memory (e.g. CUDA pinned or device memory).
-
+//!
+//! One provider can be passed to `enable_video_input_with_allocator` on several devices at
+//! once, such as the sub-devices of one card. Each device keeps its own cache of
+//! allocators, and the driver may ask for allocators from any of its threads, so
+//! `get_allocator` can be called concurrently, for the same spec from different
+//! devices. Within a device, two first requests for the same spec share one call to
+//! `get_allocator`, and requests for other specs do not wait on it. Wrap a provider in
+//! `MeteredProvider` to see how long its calls take, and how many fail.
+//!
+//! A buffer that breaks its contract by giving a null pointer from `get_bytes` is not passed
+//! on to the driver, which is given `SdkError::POINTER` instead, and is counted in
+//! `null_buffer_count`.
+//!
+//! A mode does not always keep one buffer spec for a whole capture. Some drivers ask for a
+//! second spec for the same frames in the middle of a capture, with a different
+//! `buffer_size` or `row_bytes`, such as after ancillary data is toggled. The allocators of
+//! both specs are kept until the input is disabled, and frames captured into buffers of the
+//! old spec stay valid while they are held. Each such change is reported by `take_events` as
+//! an `AllocatorEvent::SpecChanged`.
+
+use crate::ptr::{VideoBufferAllocatorProviderPtr, VideoBufferAllocatorPtr, VideoBufferPtr};
+use crate::util::{track_created, track_dropped};
 use crate::{sdk, SdkError};
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::ffi::c_void;
-use std::ptr::null_mut;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 /// Trait for a custom video buffer that supplies its own memory.
 ///
 /// Implementors provide a pointer to memory where DeckLink will read/write pixel data,
 /// and receive notifications about access begin/end for synchronization.
 pub trait VideoBuffer: Send + Sync {
-    /// Return a raw pointer to the buffer memory.
+    /// Return a raw pointer to the buffer memory, which must not be null.
     /// For DMA-capable devices, this should be a host-visible pointer
     /// (e.g. CUDA pinned memory returned by `cuMemAllocHost`).
     fn get_bytes(&self) -> Result<*mut c_void, SdkError>;
@@ -44,6 +66,7 @@ pub trait VideoBufferAllocator: Send + Sync {
 
 /// Information about the buffer format requested by DeckLink.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct BufferSpec {
     /// Total buffer size in bytes.
     pub buffer_size: u32,
@@ -57,11 +80,25 @@ pub struct BufferSpec {
     pub pixel_format: u32,
 }
 
+impl BufferSpec {
+    /// Whether the buffers of both specs hold frames of the same size and pixel format.
+    pub fn same_frames(&self, other: &BufferSpec) -> bool {
+        (self.width, self.height, self.pixel_format)
+            == (other.width, other.height, other.pixel_format)
+    }
+}
+
 /// Trait for providing video buffer allocators to the DeckLink runtime.
 ///
 /// When DeckLink needs buffers with a new specification, it calls `get_allocator`
 /// with the buffer parameters. The returned allocator is used to create individual
 /// buffers of that specification.
+///
+/// A spec is not the same as a mode. `get_allocator` can be called several times for one
+/// mode, with specs that differ only in `buffer_size` or `row_bytes`, and after the second
+/// call buffers of both specs can be in use at once: frames captured before the change keep
+/// their buffers for as long as they are held. Do not reuse or free the buffers of an older
+/// spec when a newer one is asked for.
 pub trait VideoBufferAllocatorProvider: Send + Sync {
     /// Return an allocator for the given buffer specification.
     /// The allocator may be cached internally — DeckLink will call this once
@@ -69,22 +106,189 @@ pub trait VideoBufferAllocatorProvider: Send + Sync {
     fn get_allocator(&self, spec: BufferSpec) -> Result<Arc<dyn VideoBufferAllocator>, SdkError>;
 }
 
+/// How long the calls made through a `MeteredProvider` took, for one buffer spec.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AllocationTiming {
+    pub spec: BufferSpec,
+    /// The number of `get_allocator` calls, and their total and longest durations.
+    pub get_allocator_calls: u64,
+    pub get_allocator_time: Duration,
+    pub slowest_get_allocator: Duration,
+    /// The number of buffers allocated, and the total and longest allocation durations.
+    pub allocations: u64,
+    pub allocation_time: Duration,
+    pub slowest_allocation: Duration,
+    /// The number of the calls above that returned an error. Failed calls are counted in the
+    /// calls and their durations too.
+    pub failed_get_allocator_calls: u64,
+    pub failed_allocations: u64,
+}
+
+impl AllocationTiming {
+    fn new(spec: BufferSpec) -> AllocationTiming {
+        AllocationTiming {
+            spec,
+            get_allocator_calls: 0,
+            get_allocator_time: Duration::ZERO,
+            slowest_get_allocator: Duration::ZERO,
+            allocations: 0,
+            allocation_time: Duration::ZERO,
+            slowest_allocation: Duration::ZERO,
+            failed_get_allocator_calls: 0,
+            failed_allocations: 0,
+        }
+    }
+}
+
+type TimingTable = Arc<Mutex<HashMap<BufferSpec, AllocationTiming>>>;
+
+/// A provider that times the calls made to another, so slow allocations can be seen.
+///
+/// The timings are recorded after each call returns, so a slow call does not hold up calls
+/// for other specs.
+pub struct MeteredProvider {
+    provider: Arc<dyn VideoBufferAllocatorProvider>,
+    timings: TimingTable,
+}
+
+impl MeteredProvider {
+    pub fn new(provider: Arc<dyn VideoBufferAllocatorProvider>) -> MeteredProvider {
+        MeteredProvider {
+            provider,
+            timings: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// The timings of each buffer spec that has been requested.
+    pub fn timings(&self) -> Vec<AllocationTiming> {
+        self.timings.lock().unwrap().values().copied().collect()
+    }
+}
+
+impl VideoBufferAllocatorProvider for MeteredProvider {
+    fn get_allocator(&self, spec: BufferSpec) -> Result<Arc<dyn VideoBufferAllocator>, SdkError> {
+        let start = Instant::now();
+        let result = self.provider.get_allocator(spec);
+        let elapsed = start.elapsed();
+
+        {
+            let mut timings = self.timings.lock().unwrap();
+            let timing = timings
+                .entry(spec)
+                .or_insert_with(|| AllocationTiming::new(spec));
+            timing.get_allocator_calls += 1;
+            timing.get_allocator_time += elapsed;
+            timing.slowest_get_allocator = timing.slowest_get_allocator.max(elapsed);
+            if result.is_err() {
+                timing.failed_get_allocator_calls += 1;
+            }
+        }
+
+        result.map(|allocator| {
+            Arc::new(MeteredAllocator {
+                allocator,
+                spec,
+                timings: self.timings.clone(),
+            }) as Arc<dyn VideoBufferAllocator>
+        })
+    }
+}
+
+struct MeteredAllocator {
+    allocator: Arc<dyn VideoBufferAllocator>,
+    spec: BufferSpec,
+    timings: TimingTable,
+}
+
+impl VideoBufferAllocator for MeteredAllocator {
+    fn allocate(&self) -> Result<Box<dyn VideoBuffer>, SdkError> {
+        let start = Instant::now();
+        let result = self.allocator.allocate();
+        let elapsed = start.elapsed();
+
+        let mut timings = self.timings.lock().unwrap();
+        let timing = timings
+            .entry(self.spec)
+            .or_insert_with(|| AllocationTiming::new(self.spec));
+        timing.allocations += 1;
+        timing.allocation_time += elapsed;
+        timing.slowest_allocation = timing.slowest_allocation.max(elapsed);
+        if result.is_err() {
+            timing.failed_allocations += 1;
+        }
+        result
+    }
+}
+
+/// The most events kept for `take_events`. Older ones are discarded.
+const EVENT_CAPACITY: usize = 1024;
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AllocatorEvent {
+    /// The driver asked a provider for buffers of `current`, after asking it for `previous`
+    /// for frames of the same size and pixel format. Buffers of `previous` may still be in
+    /// use.
+    SpecChanged {
+        previous: BufferSpec,
+        current: BufferSpec,
+    },
+    /// A buffer of `bytes` was dropped after its provider was invalidated, and its memory
+    /// was leaked rather than freed, as with `crate::cuda::CudaAllocatorProvider::invalidate`.
+    BufferLeaked { bytes: u64 },
+}
+
+static EVENTS: Mutex<VecDeque<AllocatorEvent>> = Mutex::new(VecDeque::new());
+
+pub(crate) fn push_event(event: AllocatorEvent) {
+    let mut events = EVENTS.lock().unwrap_or_else(|e| e.into_inner());
+    if events.len() >= EVENT_CAPACITY {
+        events.pop_front();
+    }
+    events.push_back(event);
+}
+
+/// The events raised by the providers of every input since the last call, oldest first.
+/// Only the most recent 1024 are kept.
+pub fn take_events() -> Vec<AllocatorEvent> {
+    let mut events = EVENTS.lock().unwrap_or_else(|e| e.into_inner());
+    events.drain(..).collect()
+}
+
 // ============================================================================
 // C callback bridge — wires Rust traits to the C FFI function pointers
 // ============================================================================
 
 // ---- VideoBuffer C callback trampolines ----
 
+static NULL_BUFFERS: AtomicU64 = AtomicU64::new(0);
+
+/// The number of times a `VideoBuffer` gave a null pointer from `get_bytes`, which the
+/// driver was refused with `SdkError::POINTER`.
+pub fn null_buffer_count() -> u64 {
+    NULL_BUFFERS.load(Ordering::Relaxed)
+}
+
 struct VideoBufferContext {
     buffer: Box<dyn VideoBuffer>,
 }
 
+impl Drop for VideoBufferContext {
+    fn drop(&mut self) {
+        track_dropped("VideoBufferContext", self as *const Self);
+    }
+}
+
 unsafe extern "C" fn video_buffer_get_bytes(
     context: *mut c_void,
     buffer: *mut *mut c_void,
 ) -> sdk::HRESULT {
     let ctx = &*(context as *const VideoBufferContext);
     match ctx.buffer.get_bytes() {
+        Ok(ptr) if ptr.is_null() => {
+            NULL_BUFFERS.fetch_add(1, Ordering::Relaxed);
+            SdkError::POINTER.code()
+        }
         Ok(ptr) => {
             *buffer = ptr;
             0 // S_OK
@@ -120,29 +324,23 @@ unsafe extern "C" fn video_buffer_release(context: *mut c_void) {
 }
 
 /// Create a C `cdecklink_video_buffer_t` backed by a Rust `VideoBuffer`.
-fn create_c_video_buffer(
-    buffer: Box<dyn VideoBuffer>,
-) -> Result<*mut sdk::cdecklink_video_buffer_t, SdkError> {
+fn create_c_video_buffer(buffer: Box<dyn VideoBuffer>) -> Result<VideoBufferPtr, SdkError> {
     let ctx = Box::into_raw(Box::new(VideoBufferContext { buffer }));
-    let mut out: *mut sdk::cdecklink_video_buffer_t = null_mut();
+    track_created("VideoBufferContext", ctx);
 
     let result = unsafe {
-        sdk::cdecklink_custom_video_buffer_create(
+        VideoBufferPtr::create_custom(
             ctx as *mut c_void,
             Some(video_buffer_get_bytes),
             Some(video_buffer_start_access),
             Some(video_buffer_end_access),
             Some(video_buffer_release),
-            &mut out,
         )
     };
-
-    if SdkError::is_ok(result) {
-        Ok(out)
-    } else {
+    if result.is_err() {
         unsafe { drop(Box::from_raw(ctx)) };
-        Err(SdkError::from(result))
     }
+    result
 }
 
 // ---- VideoBufferAllocator C callback trampolines ----
@@ -151,6 +349,12 @@ struct AllocatorContext {
     allocator: Arc<dyn VideoBufferAllocator>,
 }
 
+impl Drop for AllocatorContext {
+    fn drop(&mut self) {
+        track_dropped("AllocatorContext", self as *const Self);
+    }
+}
+
 unsafe extern "C" fn allocator_allocate(
     context: *mut c_void,
     allocated_buffer: *mut *mut sdk::cdecklink_video_buffer_t,
@@ -159,7 +363,7 @@ unsafe extern "C" fn allocator_allocate(
     match ctx.allocator.allocate() {
         Ok(buffer) => match create_c_video_buffer(buffer) {
             Ok(c_buf) => {
-                *allocated_buffer = c_buf;
+                *allocated_buffer = c_buf.into_raw();
                 0 // S_OK
             }
             Err(e) => e.code(),
@@ -175,36 +379,71 @@ unsafe extern "C" fn allocator_release(context: *mut c_void) {
 /// Create a C `cdecklink_video_buffer_allocator_t` backed by a Rust allocator.
 fn create_c_allocator(
     allocator: Arc<dyn VideoBufferAllocator>,
-) -> Result<*mut sdk::cdecklink_video_buffer_allocator_t, SdkError> {
+) -> Result<VideoBufferAllocatorPtr, SdkError> {
     let ctx = Box::into_raw(Box::new(AllocatorContext { allocator }));
-    let mut out: *mut sdk::cdecklink_video_buffer_allocator_t = null_mut();
+    track_created("AllocatorContext", ctx);
 
     let result = unsafe {
-        sdk::cdecklink_custom_video_buffer_allocator_create(
+        VideoBufferAllocatorPtr::create_custom(
             ctx as *mut c_void,
             Some(allocator_allocate),
             Some(allocator_release),
-            &mut out,
         )
     };
-
-    if SdkError::is_ok(result) {
-        Ok(out)
-    } else {
+    if result.is_err() {
         unsafe { drop(Box::from_raw(ctx)) };
-        Err(SdkError::from(result))
     }
+    result
 }
 
 // ---- AllocatorProvider C callback trampolines ----
 
+/// The cached C allocator object for one buffer spec.
+///
+/// Its lock is held while the provider creates the allocator, so that concurrent first
+/// requests for the spec wait for one allocator rather than each creating their own.
+#[derive(Default)]
+struct CachedAllocator {
+    allocator: Mutex<Option<VideoBufferAllocatorPtr>>,
+}
+
 /// Internal context passed to C as the provider's opaque context pointer.
 /// Owned by the C side — freed when the C provider is released.
 struct ProviderContext {
     provider: Arc<dyn VideoBufferAllocatorProvider>,
     /// Cache of C allocator objects keyed by buffer spec, so we return the same
-    /// C allocator pointer for repeated calls with the same spec.
-    allocator_cache: Mutex<HashMap<BufferSpec, *mut sdk::cdecklink_video_buffer_allocator_t>>,
+    /// C allocator pointer for repeated calls with the same spec. The map lock is only held
+    /// to find the entry for a spec, never while an allocator is created. Entries are only
+    /// removed when the provider is released, as buffers of any spec the driver was given may
+    /// still be in use.
+    allocator_cache: Mutex<HashMap<BufferSpec, Arc<CachedAllocator>>>,
+    /// The spec most recently asked for, of each frame size and pixel format.
+    latest_specs: Mutex<Vec<BufferSpec>>,
+}
+
+impl ProviderContext {
+    /// Note a request for `spec`, raising `SpecChanged` if the last request for the same
+    /// frames was for another spec.
+    fn requested(&self, spec: BufferSpec) {
+        let mut latest = self.latest_specs.lock().unwrap();
+        match latest.iter_mut().find(|s| s.same_frames(&spec)) {
+            Some(previous) if *previous != spec => {
+                push_event(AllocatorEvent::SpecChanged {
+                    previous: *previous,
+                    current: spec,
+                });
+                *previous = spec;
+            }
+            Some(_) => {}
+            None => latest.push(spec),
+        }
+    }
+}
+
+impl Drop for ProviderContext {
+    fn drop(&mut self) {
+        track_dropped("ProviderContext", self as *const Self);
+    }
 }
 
 unsafe extern "C" fn provider_get_allocator(
@@ -224,26 +463,31 @@ unsafe extern "C" fn provider_get_allocator(
         row_bytes,
         pixel_format,
     };
+    pctx.requested(spec);
+
+    let entry = pctx
+        .allocator_cache
+        .lock()
+        .unwrap()
+        .entry(spec)
+        .or_default()
+        .clone();
+    let mut cached = entry.allocator.lock().unwrap();
 
     // Check cache first
-    {
-        let cache = pctx.allocator_cache.lock().unwrap();
-        if let Some(&c_alloc) = cache.get(&spec) {
-            // AddRef since DeckLink will take ownership of this reference
-            sdk::cdecklink_video_buffer_allocator_add_ref(c_alloc);
-            *allocator = c_alloc;
-            return 0;
-        }
+    if let Some(c_alloc) = &*cached {
+        // Another reference, since DeckLink will take ownership of it
+        *allocator = c_alloc.clone().into_raw();
+        return 0;
     }
 
     // Ask the Rust provider for a new allocator
     match pctx.provider.get_allocator(spec) {
         Ok(rust_allocator) => match create_c_allocator(rust_allocator) {
             Ok(c_alloc) => {
-                // AddRef for the cache
-                sdk::cdecklink_video_buffer_allocator_add_ref(c_alloc);
-                pctx.allocator_cache.lock().unwrap().insert(spec, c_alloc);
-                *allocator = c_alloc;
+                // The cache keeps the reference it was created with
+                *allocator = c_alloc.clone().into_raw();
+                *cached = Some(c_alloc);
                 0
             }
             Err(e) => e.code(),
@@ -253,44 +497,33 @@ unsafe extern "C" fn provider_get_allocator(
 }
 
 unsafe extern "C" fn provider_release(context: *mut c_void) {
-    let pctx = Box::from_raw(context as *mut ProviderContext);
-    // Release all cached C allocator objects
-    let cache = pctx.allocator_cache.lock().unwrap();
-    for (_, c_alloc) in cache.iter() {
-        if !c_alloc.is_null() {
-            sdk::cdecklink_video_buffer_allocator_release(*c_alloc);
-        }
-    }
+    // Releases the cached C allocator objects with it
+    drop(Box::from_raw(context as *mut ProviderContext));
 }
 
 /// Create a C allocator provider object from a Rust `VideoBufferAllocatorProvider`.
 ///
-/// Returns the raw C provider pointer. The caller is responsible for releasing it
-/// via `cdecklink_video_buffer_allocator_provider_release` when done.
-/// The internal bridge context is owned by the C object and freed when released.
+/// Returns the C provider, which is released when dropped. The internal bridge context is
+/// owned by the C object and freed when released.
 pub(crate) fn create_c_allocator_provider(
     provider: Arc<dyn VideoBufferAllocatorProvider>,
-) -> Result<*mut sdk::cdecklink_video_buffer_allocator_provider_t, SdkError> {
+) -> Result<VideoBufferAllocatorProviderPtr, SdkError> {
     let pctx = Box::into_raw(Box::new(ProviderContext {
         provider,
         allocator_cache: Mutex::new(HashMap::new()),
+        latest_specs: Mutex::new(Vec::new()),
     }));
-
-    let mut c_provider: *mut sdk::cdecklink_video_buffer_allocator_provider_t = null_mut();
+    track_created("ProviderContext", pctx);
 
     let result = unsafe {
-        sdk::cdecklink_custom_video_buffer_allocator_provider_create(
+        VideoBufferAllocatorProviderPtr::create_custom(
             pctx as *mut c_void,
             Some(provider_get_allocator),
             Some(provider_release),
-            &mut c_provider,
         )
     };
-
-    if SdkError::is_ok(result) {
-        Ok(c_provider)
-    } else {
+    if result.is_err() {
         unsafe { drop(Box::from_raw(pctx)) };
-        Err(SdkError::from(result))
     }
+    result
 }
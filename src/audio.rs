@@ -0,0 +1,176 @@
+//! Audio level metering.
+
+use crate::device::input::{DecklinkAudioInputPacket, DecklinkAudioSampleType};
+use crate::SdkError;
+use std::time::Duration;
+
+/// The level of one audio channel, relative to digital full scale.
+///
+/// Silence is reported as `f64::NEG_INFINITY`.
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub struct ChannelLevel {
+    /// Peak level in dBFS, with decay applied.
+    pub peak_dbfs: f64,
+    /// RMS level in dBFS over the last complete window. A full scale sine reads -3.01 dBFS.
+    pub rms_dbfs: f64,
+    /// Number of samples at full scale since the meter was created or reset.
+    pub clipped_samples: u64,
+}
+
+#[derive(Default, Clone)]
+struct ChannelState {
+    window_peak: f64,
+    window_sum_squares: f64,
+    held_peak_dbfs: Option<f64>,
+    rms_dbfs: Option<f64>,
+    clipped_samples: u64,
+}
+
+/// Computes per-channel peak and RMS levels from interleaved audio samples.
+///
+/// Levels are updated once per window of samples. The displayed peak falls at the configured
+/// rate (in dB per second of audio) unless a louder window arrives, so the meter can be polled
+/// at any rate.
+pub struct LevelMeter {
+    window_frames: usize,
+    fall_rate_db_per_sec: f64,
+    window_secs: f64,
+    frames_in_window: usize,
+    channels: Vec<ChannelState>,
+}
+
+fn to_dbfs(level: f64) -> f64 {
+    if level > 0.0 {
+        20.0 * level.log10()
+    } else {
+        f64::NEG_INFINITY
+    }
+}
+
+impl LevelMeter {
+    /// Create a meter for audio at `sample_rate`, measuring over `window` and with peaks
+    /// falling at `fall_rate_db_per_sec`.
+    pub fn new(sample_rate: u32, window: Duration, fall_rate_db_per_sec: f64) -> LevelMeter {
+        let window_frames = ((sample_rate as f64 * window.as_secs_f64()).round() as usize).max(1);
+        LevelMeter {
+            window_frames,
+            fall_rate_db_per_sec: fall_rate_db_per_sec.max(0.0),
+            window_secs: window_frames as f64 / sample_rate.max(1) as f64,
+            frames_in_window: 0,
+            channels: Vec::new(),
+        }
+    }
+
+    /// Clear all levels and clip counts.
+    pub fn reset(&mut self) {
+        self.frames_in_window = 0;
+        self.channels.clear();
+    }
+
+    /// Feed a captured audio packet.
+    pub fn feed_packet(&mut self, packet: &DecklinkAudioInputPacket) -> Result<(), SdkError> {
+        let channels = packet.channel_count() as usize;
+        let bytes = packet.bytes()?;
+        match packet.sample_type() {
+            DecklinkAudioSampleType::Int16 => self.feed(
+                channels,
+                bytes
+                    .chunks_exact(2)
+                    .map(|b| i16::from_ne_bytes([b[0], b[1]]) as i32),
+                i16::MAX as i32,
+                32768.0,
+            ),
+            DecklinkAudioSampleType::Int32 => self.feed(
+                channels,
+                bytes
+                    .chunks_exact(4)
+                    .map(|b| i32::from_ne_bytes([b[0], b[1], b[2], b[3]])),
+                i32::MAX,
+                2147483648.0,
+            ),
+        }
+        Ok(())
+    }
+
+    /// Feed interleaved 16-bit samples.
+    pub fn feed_i16(&mut self, samples: &[i16], channels: usize) {
+        self.feed(
+            channels,
+            samples.iter().map(|s| *s as i32),
+            i16::MAX as i32,
+            32768.0,
+        )
+    }
+
+    /// Feed interleaved 32-bit samples.
+    pub fn feed_i32(&mut self, samples: &[i32], channels: usize) {
+        self.feed(channels, samples.iter().copied(), i32::MAX, 2147483648.0)
+    }
+
+    fn feed<I: Iterator<Item = i32>>(
+        &mut self,
+        channels: usize,
+        samples: I,
+        max: i32,
+        full_scale: f64,
+    ) {
+        if channels == 0 {
+            return;
+        }
+        if channels != self.channels.len() {
+            self.reset();
+            self.channels = vec![ChannelState::default(); channels];
+        }
+
+        let mut channel = 0;
+        for sample in samples {
+            let state = &mut self.channels[channel];
+            if sample >= max || sample <= -max {
+                state.clipped_samples += 1;
+            }
+
+            let value = sample as f64 / full_scale;
+            state.window_peak = state.window_peak.max(value.abs());
+            state.window_sum_squares += value * value;
+
+            channel += 1;
+            if channel == channels {
+                channel = 0;
+                self.frames_in_window += 1;
+                if self.frames_in_window == self.window_frames {
+                    self.finish_window();
+                }
+            }
+        }
+    }
+
+    fn finish_window(&mut self) {
+        let fall = self.fall_rate_db_per_sec * self.window_secs;
+        for state in self.channels.iter_mut() {
+            let peak = to_dbfs(state.window_peak);
+            state.held_peak_dbfs = Some(match state.held_peak_dbfs {
+                Some(held) if held - fall > peak => held - fall,
+                _ => peak,
+            });
+            state.rms_dbfs = Some(to_dbfs(
+                (state.window_sum_squares / self.window_frames as f64).sqrt(),
+            ));
+
+            state.window_peak = 0.0;
+            state.window_sum_squares = 0.0;
+        }
+        self.frames_in_window = 0;
+    }
+
+    /// Get the current level of each channel.
+    pub fn levels(&self) -> Vec<ChannelLevel> {
+        self.channels
+            .iter()
+            .map(|state| ChannelLevel {
+                peak_dbfs: state.held_peak_dbfs.unwrap_or(f64::NEG_INFINITY),
+                rms_dbfs: state.rms_dbfs.unwrap_or(f64::NEG_INFINITY),
+                clipped_samples: state.clipped_samples,
+            })
+            .collect()
+    }
+}
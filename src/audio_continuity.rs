@@ -0,0 +1,457 @@
+//! Checking that captured audio is continuous, and filling the gaps in it with silence.
+//!
+//! Embedded audio can lose samples upstream of the card. Nothing in the packets says so,
+//! and a track that is a few samples short per hour only shows as sync that drifts. An
+//! `AudioReconciler` follows the packet time and sample count of each packet, and reports
+//! where the next packet starts later than the last one ended, a gap, or earlier, an
+//! overlap, as `AudioContinuityEvent`s and in `AudioContinuityStats`. All positions and
+//! sizes are in sample frames at `AUDIO_SAMPLE_RATE`, for 16-bit and 32-bit samples alike.
+//!
+//! The reconciler can also make the stream continuous again, as `AudioContinuityConfig`
+//! says: silence is passed on before a packet that follows a gap, and the overlapping
+//! start of a packet is cut off. Each insertion and cut is reported with the event that
+//! `AudioContinuityEvent::manifest_event` records in a capture manifest.
+//!
+//! Packets an application drops itself, such as when a queue in front of a recorder is
+//! full, are not gaps in the source. Report them with `AudioReconciler::dropped`, and they
+//! are counted apart from gaps, and filled too if `AudioContinuityConfig::fill_dropped` is
+//! set. A reconciler placed after such a queue sees the packets that survived it, and the
+//! dropped ones handed to `dropped` in their place.
+//!
+//! `AudioContinuity` is an input callback that reconciles the audio passed on to a primary
+//! callback. Install it in front of a `crate::av_offset::AvAligner`, so that the aligner
+//! and everything after it see the reconciled stream. Format changes reset it, as the
+//! packet times start again. Call `AudioContinuity::reset` after enabling audio input or
+//! flushing the streams too.
+
+use crate::av_offset::{sample_frame_bytes, AUDIO_SAMPLE_RATE};
+use crate::device::input::{
+    CallbackResult, DecklinkAudioInputPacket, DecklinkAudioSampleType, FrameArrival,
+    InputFormatChange, InputHandler,
+};
+use crate::manifest::ManifestEvent;
+use crate::time::DecklinkTime;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub struct AudioContinuityConfig {
+    /// Pass on silence in place of the samples missing in gaps.
+    pub fill_gaps: bool,
+    /// Pass on silence in place of the samples of packets reported as dropped.
+    pub fill_dropped: bool,
+    /// Cut off the start of packets that overlap the samples already passed on.
+    pub trim_overlaps: bool,
+    /// Differences of up to this many sample frames between where a packet starts and the
+    /// last one ended are ignored, for sources whose packet times are rounded.
+    pub tolerance: u32,
+    /// A jump in the packet times longer than this is taken to be a new start rather than
+    /// lost or repeated audio. It is reported as `AudioContinuityEvent::Discontinuity`,
+    /// and not filled or cut.
+    pub max_fill: Duration,
+}
+
+impl Default for AudioContinuityConfig {
+    fn default() -> Self {
+        AudioContinuityConfig {
+            fill_gaps: false,
+            fill_dropped: false,
+            trim_overlaps: false,
+            tolerance: 0,
+            max_fill: Duration::from_secs(10),
+        }
+    }
+}
+
+/// A change in the continuity of the audio. Sample times are packet times in ticks of
+/// `AUDIO_SAMPLE_RATE`.
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AudioContinuityEvent {
+    /// `samples` sample frames from `sample_time` never arrived.
+    Gap { sample_time: i64, samples: u64 },
+    /// The packet at `sample_time` repeats `samples` sample frames that had already arrived.
+    Overlap { sample_time: i64, samples: u64 },
+    /// The packet at `sample_time` was reported as dropped.
+    Dropped { sample_time: i64, samples: u64 },
+    /// The packet times jumped from `expected` to `sample_time`, further than
+    /// `AudioContinuityConfig::max_fill`, and counting started again from there.
+    Discontinuity { sample_time: i64, expected: i64 },
+    /// Silence was passed on for `samples` sample frames from `sample_time`.
+    Filled { sample_time: i64, samples: u64 },
+    /// The first `samples` sample frames of the packet at `sample_time` were cut off.
+    Trimmed { sample_time: i64, samples: u64 },
+}
+
+impl AudioContinuityEvent {
+    /// The event to record in a capture manifest, for changes made to the stream.
+    pub fn manifest_event(&self) -> Option<ManifestEvent> {
+        match *self {
+            AudioContinuityEvent::Filled {
+                sample_time,
+                samples,
+            } => Some(ManifestEvent::AudioFilled {
+                sample_time,
+                samples,
+            }),
+            AudioContinuityEvent::Trimmed {
+                sample_time,
+                samples,
+            } => Some(ManifestEvent::AudioTrimmed {
+                sample_time,
+                samples,
+            }),
+            _ => None,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Default)]
+pub struct AudioContinuityStats {
+    /// Packets passed to `reconcile`.
+    pub packets: u64,
+    /// Sample frames in the packets passed to `reconcile`, before any were cut off.
+    pub received_samples: u64,
+    /// Sample frames the packet times span, counting from each reset or discontinuity.
+    /// Short of `received_samples` plus `dropped_samples` by the net size of the gaps less
+    /// the overlaps.
+    pub expected_samples: u64,
+    pub gaps: u64,
+    pub gap_samples: u64,
+    pub overlaps: u64,
+    pub overlap_samples: u64,
+    pub dropped_packets: u64,
+    pub dropped_samples: u64,
+    pub discontinuities: u64,
+    /// Sample frames of silence passed on.
+    pub filled_samples: u64,
+    /// Sample frames cut off the start of packets.
+    pub trimmed_samples: u64,
+    /// Packets passed on unchecked, as their packet time could not be read.
+    pub untimed_packets: u64,
+    pub resets: u64,
+}
+
+impl AudioContinuityStats {
+    /// The sample frames missing from the source, less those it repeated: the drift of the
+    /// audio against its own packet times, were the stream not reconciled.
+    pub fn missing_samples(&self) -> i64 {
+        self.expected_samples as i64 - self.received_samples as i64 - self.dropped_samples as i64
+    }
+}
+
+/// Where the samples passed on so far end.
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+struct Position {
+    sample_type: DecklinkAudioSampleType,
+    channel_count: u32,
+    /// The packet time just after the last sample frame.
+    end: i64,
+}
+
+/// Follows the continuity of a stream of audio packets, and reconciles it.
+pub struct AudioReconciler {
+    config: AudioContinuityConfig,
+    position: Option<Position>,
+    /// Runs of silence owed before the next packet that is passed on, as sample time and
+    /// length.
+    pending_fill: Vec<(i64, u64)>,
+    stats: AudioContinuityStats,
+    events: Vec<AudioContinuityEvent>,
+}
+
+impl AudioReconciler {
+    pub fn new(config: AudioContinuityConfig) -> AudioReconciler {
+        AudioReconciler {
+            config,
+            position: None,
+            pending_fill: Vec::new(),
+            stats: AudioContinuityStats::default(),
+            events: Vec::new(),
+        }
+    }
+
+    pub fn config(&self) -> AudioContinuityConfig {
+        self.config
+    }
+
+    /// Change the config from the next packet.
+    pub fn set_config(&mut self, config: AudioContinuityConfig) {
+        self.config = config;
+    }
+
+    pub fn stats(&self) -> AudioContinuityStats {
+        self.stats
+    }
+
+    pub fn take_events(&mut self) -> Vec<AudioContinuityEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    /// Start again from the next packet, as after enabling audio input, a flush or a format
+    /// change. Silence still owed for dropped packets is not passed on.
+    pub fn reset(&mut self) {
+        self.stats.resets += 1;
+        self.position = None;
+        self.pending_fill.clear();
+    }
+
+    /// Check `packet` against the packets before it, and return what to pass on in its
+    /// place, in order: silence for any gaps or dropped packets before it if they are
+    /// filled, then the packet, unless it was cut off entirely.
+    pub fn reconcile(&mut self, packet: DecklinkAudioInputPacket) -> Vec<DecklinkAudioInputPacket> {
+        self.stats.packets += 1;
+        let count = packet.sample_frame_count() as u64;
+        self.stats.received_samples += count;
+        let time = match packet.packet_time(AUDIO_SAMPLE_RATE) {
+            Ok(time) => time,
+            Err(_) => {
+                self.stats.untimed_packets += 1;
+                return vec![packet];
+            }
+        };
+
+        let overlap = self.advance(&packet, time, count, false);
+        let sample_type = packet.sample_type();
+        let channel_count = packet.channel_count();
+        let frame_bytes = sample_frame_bytes(sample_type, channel_count);
+
+        let mut out: Vec<DecklinkAudioInputPacket> = std::mem::take(&mut self.pending_fill)
+            .into_iter()
+            .map(|(sample_time, samples)| {
+                self.stats.filled_samples += samples;
+                self.events.push(AudioContinuityEvent::Filled {
+                    sample_time,
+                    samples,
+                });
+                DecklinkAudioInputPacket::from_samples(
+                    sample_type,
+                    channel_count,
+                    vec![0; samples as usize * frame_bytes],
+                    DecklinkTime::new(sample_time, AUDIO_SAMPLE_RATE),
+                )
+            })
+            .collect();
+
+        let trim = if self.config.trim_overlaps {
+            overlap.min(count)
+        } else {
+            0
+        };
+        if trim == 0 {
+            out.push(packet);
+            return out;
+        }
+
+        self.stats.trimmed_samples += trim;
+        self.events.push(AudioContinuityEvent::Trimmed {
+            sample_time: time,
+            samples: trim,
+        });
+        if trim < count {
+            if let Ok(bytes) = packet.bytes() {
+                out.push(DecklinkAudioInputPacket::from_samples(
+                    sample_type,
+                    channel_count,
+                    bytes[trim as usize * frame_bytes..].to_vec(),
+                    DecklinkTime::new(time + trim as i64, AUDIO_SAMPLE_RATE),
+                ));
+            }
+        }
+        out
+    }
+
+    /// Account for a packet that was dropped rather than passed to `reconcile`, in the
+    /// order it arrived in.
+    pub fn dropped(&mut self, packet: &DecklinkAudioInputPacket) {
+        let count = packet.sample_frame_count() as u64;
+        self.stats.dropped_packets += 1;
+        self.stats.dropped_samples += count;
+        match packet.packet_time(AUDIO_SAMPLE_RATE) {
+            Ok(time) => {
+                self.advance(packet, time, count, true);
+            }
+            Err(_) => self.stats.untimed_packets += 1,
+        }
+    }
+
+    /// Move the position past a packet of `count` sample frames at `time`, recording any
+    /// gap or overlap before it. Returns the sample frames it overlaps.
+    fn advance(
+        &mut self,
+        packet: &DecklinkAudioInputPacket,
+        time: i64,
+        count: u64,
+        dropped: bool,
+    ) -> u64 {
+        let format = (packet.sample_type(), packet.channel_count());
+        let end = time + count as i64;
+        let position = match self.position {
+            Some(position) if (position.sample_type, position.channel_count) == format => position,
+            _ => {
+                // A new start, or a new format that the silence owed would not be in
+                self.pending_fill.clear();
+                self.start(format, end, count);
+                self.record_dropped(time, count, count, dropped);
+                return 0;
+            }
+        };
+
+        let difference = time - position.end;
+        let max_fill = (self.config.max_fill.as_secs_f64() * AUDIO_SAMPLE_RATE as f64) as i64;
+        if difference.abs() <= self.config.tolerance as i64 {
+            self.start(format, end, count);
+            self.record_dropped(time, count, count, dropped);
+            return 0;
+        }
+        if difference.abs() > max_fill {
+            self.stats.discontinuities += 1;
+            self.events.push(AudioContinuityEvent::Discontinuity {
+                sample_time: time,
+                expected: position.end,
+            });
+            self.pending_fill.clear();
+            self.start(format, end, count);
+            self.record_dropped(time, count, count, dropped);
+            return 0;
+        }
+
+        if difference > 0 {
+            let samples = difference as u64;
+            self.stats.gaps += 1;
+            self.stats.gap_samples += samples;
+            self.events.push(AudioContinuityEvent::Gap {
+                sample_time: position.end,
+                samples,
+            });
+            if self.config.fill_gaps {
+                self.owe_silence(position.end, samples);
+            }
+            self.start(format, end, samples + count);
+            self.record_dropped(time, count, count, dropped);
+            0
+        } else {
+            let overlap = difference.unsigned_abs();
+            self.stats.overlaps += 1;
+            self.stats.overlap_samples += overlap;
+            self.events.push(AudioContinuityEvent::Overlap {
+                sample_time: time,
+                samples: overlap,
+            });
+            let fresh = count.saturating_sub(overlap);
+            self.start(format, end.max(position.end), fresh);
+            self.record_dropped(time, count, fresh, dropped);
+            overlap
+        }
+    }
+
+    fn start(&mut self, format: (DecklinkAudioSampleType, u32), end: i64, expected: u64) {
+        self.position = Some(Position {
+            sample_type: format.0,
+            channel_count: format.1,
+            end,
+        });
+        self.stats.expected_samples += expected;
+    }
+
+    /// Record a dropped packet, whose last `fresh` sample frames had not arrived before.
+    fn record_dropped(&mut self, time: i64, count: u64, fresh: u64, dropped: bool) {
+        if dropped {
+            self.events.push(AudioContinuityEvent::Dropped {
+                sample_time: time,
+                samples: count,
+            });
+            if self.config.fill_dropped && fresh > 0 {
+                self.owe_silence(time + (count - fresh) as i64, fresh);
+            }
+        }
+    }
+
+    fn owe_silence(&mut self, sample_time: i64, samples: u64) {
+        match self.pending_fill.last_mut() {
+            Some((start, length)) if *start + *length as i64 == sample_time => *length += samples,
+            _ => self.pending_fill.push((sample_time, samples)),
+        }
+    }
+}
+
+struct ContinuityShared {
+    primary: Arc<dyn InputHandler>,
+    reconciler: Mutex<AudioReconciler>,
+}
+
+/// An input callback that reconciles the audio it passes on to a primary callback.
+///
+/// Install the callback returned by `callback` with `DecklinkInputDevice::set_handler`.
+/// Video and everything else is passed on unchanged.
+pub struct AudioContinuity {
+    shared: Arc<ContinuityShared>,
+}
+
+impl AudioContinuity {
+    pub fn new(primary: Arc<dyn InputHandler>, config: AudioContinuityConfig) -> AudioContinuity {
+        AudioContinuity {
+            shared: Arc::new(ContinuityShared {
+                primary,
+                reconciler: Mutex::new(AudioReconciler::new(config)),
+            }),
+        }
+    }
+
+    /// The input callback to install on the device.
+    pub fn callback(&self) -> Arc<dyn InputHandler> {
+        Arc::new(ContinuityInputCallback {
+            shared: self.shared.clone(),
+        })
+    }
+
+    pub fn set_config(&self, config: AudioContinuityConfig) {
+        self.shared.reconciler.lock().unwrap().set_config(config);
+    }
+
+    /// Start again from the next packet, after enabling audio input or flushing the streams.
+    pub fn reset(&self) {
+        self.shared.reconciler.lock().unwrap().reset();
+    }
+
+    pub fn stats(&self) -> AudioContinuityStats {
+        self.shared.reconciler.lock().unwrap().stats()
+    }
+
+    pub fn take_events(&self) -> Vec<AudioContinuityEvent> {
+        self.shared.reconciler.lock().unwrap().take_events()
+    }
+}
+
+struct ContinuityInputCallback {
+    shared: Arc<ContinuityShared>,
+}
+
+impl InputHandler for ContinuityInputCallback {
+    fn format_changed(&self, change: &InputFormatChange) {
+        self.shared.reconciler.lock().unwrap().reset();
+        self.shared.primary.format_changed(change);
+    }
+
+    fn frame_arrived(&self, arrival: &FrameArrival<'_>) -> CallbackResult {
+        let Some(packet) = arrival.retain_audio_packet() else {
+            return self.shared.primary.frame_arrived(arrival);
+        };
+        let packets = self.shared.reconciler.lock().unwrap().reconcile(packet);
+
+        // Silence filled in before the packet is passed on in callbacks of their own, ahead
+        // of the callback with the frame, which carries the last packet
+        let (last, filled) = match packets.split_last() {
+            Some((last, filled)) => (Some(last), filled),
+            None => (None, &[][..]),
+        };
+        for packet in filled {
+            self.shared
+                .primary
+                .frame_arrived(&FrameArrival::new(None).with_audio_packet(Some(packet)));
+        }
+        self.shared
+            .primary
+            .frame_arrived(&arrival.with_audio_packet(last))
+    }
+}
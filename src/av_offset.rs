@@ -0,0 +1,456 @@
+//! Shifting captured audio against video by a fixed offset, before it reaches consumers.
+//!
+//! Converters and frame synchronizers upstream of a card often delay video by a fixed
+//! amount relative to audio. An `AvAligner` is installed as the input callback in front of
+//! the primary callback, and undoes such a delay as the callbacks are passed on, so that
+//! everything downstream, such as a recorder, sees aligned audio and video.
+//!
+//! A positive offset delays audio: samples are held in a delay line and passed on that much
+//! later, in packets of the same size as they arrived in. A negative offset delays video:
+//! whole frames are retained and passed on that many callbacks later, and any remainder of
+//! the offset that is not a whole number of frames is made up by delaying audio, so the
+//! offset is applied to the nearest audio sample either way. Delayed audio and video are
+//! passed on with the times of the callback they are delivered in, so consumers see a
+//! continuous stream, as if the source had been aligned. A zero offset passes every
+//! callback on unchanged.
+//!
+//! On a flush or a format change the delay lines are emptied. What they held, the last part
+//! of the stream before the change, is discarded, and they fill again from the change: a
+//! delayed stream starts with silence, or with callbacks without a frame, for the length of
+//! the offset.
+
+use crate::device::input::{
+    ArrivalFlags, CallbackResult, DecklinkAudioInputPacket, DecklinkAudioSampleType, FrameArrival,
+    InputFormatChange, InputHandler,
+};
+use crate::frame::{DecklinkFrameBase, DecklinkVideoFrame};
+use crate::retention::{RetentionBudget, RetentionCharge};
+use crate::time::{DecklinkFrameTiming, DecklinkTime};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// The rate of captured audio, the only rate the SDK captures at.
+pub const AUDIO_SAMPLE_RATE: i64 = 48000;
+
+/// The largest offset that can be applied, either way.
+pub const MAX_AV_OFFSET: Duration = Duration::from_secs(1);
+
+/// How far audio is shifted against video. Positive offsets delay audio, and negative
+/// offsets delay video.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+pub enum AvOffset {
+    Time(DecklinkTime),
+    /// A number of frames of the display mode.
+    Frames(i64),
+    /// A number of fields of the display mode, each half a frame.
+    Fields(i64),
+}
+
+impl AvOffset {
+    pub const ZERO: AvOffset = AvOffset::Frames(0);
+
+    /// The offset in ticks of `frame_duration`, or in its own timescale for a time.
+    fn time(&self, frame_duration: DecklinkTime) -> Option<DecklinkTime> {
+        Some(match *self {
+            AvOffset::Time(time) => time,
+            AvOffset::Frames(frames) => DecklinkTime::new(
+                frame_duration.value.checked_mul(frames)?,
+                frame_duration.scale,
+            ),
+            AvOffset::Fields(fields) => DecklinkTime::new(
+                frame_duration.value.checked_mul(fields)?,
+                frame_duration.scale.checked_mul(2)?,
+            ),
+        })
+    }
+}
+
+impl Default for AvOffset {
+    fn default() -> Self {
+        AvOffset::ZERO
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+pub enum AvOffsetError {
+    /// The offset is further than `max` either way.
+    TooLarge { offset: AvOffset, max: Duration },
+    /// The frame duration is not positive, so an offset in frames cannot be resolved.
+    InvalidFrameDuration(DecklinkTime),
+}
+
+impl std::fmt::Display for AvOffsetError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AvOffsetError::TooLarge { offset, max } => {
+                write!(f, "the offset {:?} is larger than {:?}", offset, max)
+            }
+            AvOffsetError::InvalidFrameDuration(duration) => {
+                write!(f, "the frame duration {:?} is not valid", duration)
+            }
+        }
+    }
+}
+
+impl std::error::Error for AvOffsetError {}
+
+/// An offset resolved for a display mode into the delays that apply it.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+pub struct AvPlan {
+    pub offset: AvOffset,
+    /// The frame duration of the display mode the offset was resolved for.
+    pub frame_duration: DecklinkTime,
+    /// The offset in audio samples, positive when audio is delayed.
+    pub offset_samples: i64,
+    /// The number of frames video is delayed by.
+    pub video_delay_frames: usize,
+    /// The number of samples audio is delayed by, including the part of a negative offset
+    /// that is not a whole number of frames.
+    pub audio_delay_samples: usize,
+}
+
+impl AvPlan {
+    /// Resolve `offset` for a display mode with frames of `frame_duration`.
+    pub fn new(offset: AvOffset, frame_duration: DecklinkTime) -> Result<AvPlan, AvOffsetError> {
+        let invalid = AvOffsetError::InvalidFrameDuration(frame_duration);
+        if frame_duration.value <= 0 || frame_duration.scale <= 0 {
+            return Err(invalid);
+        }
+        let time = offset.time(frame_duration).ok_or(invalid)?;
+        let samples = time.rescale(AUDIO_SAMPLE_RATE).ok_or(invalid)?.value;
+        if samples.unsigned_abs() > MAX_AV_OFFSET.as_secs() * AUDIO_SAMPLE_RATE as u64 {
+            return Err(AvOffsetError::TooLarge {
+                offset,
+                max: MAX_AV_OFFSET,
+            });
+        }
+
+        // Frames do not hold a whole number of samples in every mode, so the delays are
+        // worked out from the exact offset, in ticks of both timescales, and only the audio
+        // delay is rounded to a sample
+        let scale = time.scale as i128 * frame_duration.scale as i128;
+        let offset_ticks = time.value as i128 * frame_duration.scale as i128;
+        let frame = frame_duration.value as i128 * time.scale as i128;
+        let (video_delay_frames, audio_delay) = if offset_ticks >= 0 {
+            (0, offset_ticks)
+        } else {
+            let frames = (-offset_ticks + frame - 1) / frame;
+            (frames, frames * frame + offset_ticks)
+        };
+        let audio_delay = audio_delay * AUDIO_SAMPLE_RATE as i128;
+
+        Ok(AvPlan {
+            offset,
+            frame_duration,
+            offset_samples: samples,
+            video_delay_frames: video_delay_frames as usize,
+            audio_delay_samples: ((audio_delay + scale / 2) / scale) as usize,
+        })
+    }
+
+    /// The offset as a time, as recorded in `crate::manifest::ManifestEvent::AvOffset`.
+    pub fn time(&self) -> DecklinkTime {
+        DecklinkTime::new(self.offset_samples, AUDIO_SAMPLE_RATE)
+    }
+
+    fn is_passthrough(&self) -> bool {
+        self.video_delay_frames == 0 && self.audio_delay_samples == 0
+    }
+}
+
+/// Counts of what an `AvAligner` has done.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash, Default)]
+pub struct AvAlignerStats {
+    /// Flushes and format changes that emptied the delay lines.
+    pub resets: u64,
+    /// Frames that could not be retained within the retention budget, and were passed on as
+    /// a callback without a frame instead.
+    pub frames_dropped: u64,
+    /// Frames and samples held in the delay lines when they were emptied.
+    pub frames_discarded: u64,
+    pub samples_discarded: u64,
+    /// The most recent error resolving the offset for a new display mode, after which the
+    /// offset was set to zero.
+    pub last_error: Option<AvOffsetError>,
+}
+
+struct DelayedFrame {
+    frame: DecklinkVideoFrame,
+    _charge: Option<RetentionCharge>,
+}
+
+#[derive(Default)]
+struct AlignerState {
+    offset: AvOffset,
+    /// `None` while every callback is passed on unchanged.
+    plan: Option<AvPlan>,
+    /// The frame duration of the display mode, from the latest frame timing.
+    frame_duration: Option<DecklinkTime>,
+    /// Delayed interleaved samples, and the format they are in. The line is filled with
+    /// silence when the first packet of a format arrives.
+    audio: VecDeque<u8>,
+    audio_format: Option<(DecklinkAudioSampleType, u32)>,
+    /// Delayed frames, or `None` for callbacks without a frame.
+    video: VecDeque<Option<DelayedFrame>>,
+    stats: AvAlignerStats,
+}
+
+impl AlignerState {
+    fn reset(&mut self) {
+        self.stats.resets += 1;
+        self.stats.frames_discarded += self.video.iter().filter(|f| f.is_some()).count() as u64;
+        if let Some((sample_type, channel_count)) = self.audio_format {
+            self.stats.samples_discarded +=
+                (self.audio.len() / sample_frame_bytes(sample_type, channel_count)) as u64;
+        }
+        self.audio.clear();
+        self.audio_format = None;
+        self.video.clear();
+    }
+
+    fn set_plan(&mut self, plan: AvPlan) {
+        self.reset();
+        self.offset = plan.offset;
+        self.plan = (!plan.is_passthrough()).then_some(plan);
+    }
+}
+
+pub(crate) fn sample_frame_bytes(
+    sample_type: DecklinkAudioSampleType,
+    channel_count: u32,
+) -> usize {
+    let sample_size = match sample_type {
+        DecklinkAudioSampleType::Int16 => 2,
+        DecklinkAudioSampleType::Int32 => 4,
+    };
+    (sample_size * channel_count as usize).max(1)
+}
+
+struct AlignerShared {
+    primary: Arc<dyn InputHandler>,
+    budget: Option<RetentionBudget>,
+    /// Whether there is a plan, checked before taking the state lock.
+    active: AtomicBool,
+    state: Mutex<AlignerState>,
+}
+
+impl AlignerShared {
+    fn update_active(&self, state: &AlignerState) {
+        self.active.store(state.plan.is_some(), Ordering::Release);
+    }
+}
+
+/// An input callback that applies an audio/video offset to the callbacks it passes on to a
+/// primary callback.
+///
+/// Install the callback returned by `callback` with `DecklinkInputDevice::set_handler`.
+/// Delaying video holds frames from the driver's pool, so give the aligner a retention
+/// budget, or make sure the pool has frames to spare, when delaying video.
+pub struct AvAligner {
+    shared: Arc<AlignerShared>,
+}
+
+impl AvAligner {
+    /// Create an aligner in front of `primary`, with a zero offset. Frames delayed for
+    /// negative offsets are counted against `budget`, if it is given.
+    pub fn new(primary: Arc<dyn InputHandler>, budget: Option<RetentionBudget>) -> AvAligner {
+        AvAligner {
+            shared: Arc::new(AlignerShared {
+                primary,
+                budget,
+                active: AtomicBool::new(false),
+                state: Mutex::new(AlignerState::default()),
+            }),
+        }
+    }
+
+    /// The input callback to install on the device.
+    pub fn callback(&self) -> Arc<dyn InputHandler> {
+        Arc::new(AlignerInputCallback {
+            shared: self.shared.clone(),
+        })
+    }
+
+    /// Apply `offset` from the next callback, for a display mode with frames of
+    /// `frame_duration`. The delay lines are flushed. Fails, leaving the current offset in
+    /// place, if the offset is larger than `MAX_AV_OFFSET`.
+    ///
+    /// The offset is resolved again if frames arrive with a different frame duration.
+    pub fn set_offset(
+        &self,
+        offset: AvOffset,
+        frame_duration: DecklinkTime,
+    ) -> Result<AvPlan, AvOffsetError> {
+        let plan = AvPlan::new(offset, frame_duration)?;
+        let mut state = self.shared.state.lock().unwrap();
+        state.set_plan(plan);
+        state.frame_duration = Some(frame_duration);
+        self.shared.update_active(&state);
+        Ok(plan)
+    }
+
+    /// The offset being applied.
+    pub fn offset(&self) -> AvOffset {
+        self.shared.state.lock().unwrap().offset
+    }
+
+    /// How the offset is being applied, or `None` if every callback is passed on unchanged.
+    pub fn plan(&self) -> Option<AvPlan> {
+        self.shared.state.lock().unwrap().plan
+    }
+
+    /// Empty the delay lines, discarding what they hold, as when a capture is restarted.
+    pub fn flush(&self) {
+        self.shared.state.lock().unwrap().reset();
+    }
+
+    pub fn stats(&self) -> AvAlignerStats {
+        self.shared.state.lock().unwrap().stats
+    }
+}
+
+struct AlignerInputCallback {
+    shared: Arc<AlignerShared>,
+}
+
+impl AlignerInputCallback {
+    /// Resolve the offset again if frames arrive with a different frame duration.
+    fn follow_frame_duration(&self, state: &mut AlignerState, timing: DecklinkFrameTiming) {
+        if state.frame_duration == Some(timing.mode_duration) {
+            return;
+        }
+        state.frame_duration = Some(timing.mode_duration);
+        let offset = state.offset;
+        match AvPlan::new(offset, timing.mode_duration) {
+            Ok(plan) => state.set_plan(plan),
+            Err(e) => {
+                state.set_plan(AvPlan::new(AvOffset::ZERO, timing.mode_duration).unwrap());
+                state.stats.last_error = Some(e);
+            }
+        }
+        self.shared.update_active(state);
+    }
+
+    /// Put the frame of a callback into the delay line, and take out the one to pass on in
+    /// its place.
+    fn delay_video(
+        &self,
+        state: &mut AlignerState,
+        frame: Option<DecklinkVideoFrame>,
+        delay: usize,
+    ) -> Option<DelayedFrame> {
+        let delayed = frame.and_then(|frame| {
+            let charge = match &self.shared.budget {
+                Some(budget) => match budget.charge(frame.row_bytes() * frame.height()) {
+                    Ok(charge) => Some(charge),
+                    Err(_) => {
+                        state.stats.frames_dropped += 1;
+                        return None;
+                    }
+                },
+                None => None,
+            };
+            Some(DelayedFrame {
+                frame,
+                _charge: charge,
+            })
+        });
+        state.video.push_back(delayed);
+
+        if state.video.len() > delay {
+            state.video.pop_front().flatten()
+        } else {
+            None
+        }
+    }
+
+    /// Put the samples of a packet into the delay line, and take out as many to pass on in
+    /// its place. Returns `None` if the packet cannot be read, to pass it on as it is.
+    fn delay_audio(
+        state: &mut AlignerState,
+        packet: &DecklinkAudioInputPacket,
+        delay: usize,
+    ) -> Option<DecklinkAudioInputPacket> {
+        let (Ok(bytes), Ok(time)) = (packet.bytes(), packet.packet_time(AUDIO_SAMPLE_RATE)) else {
+            return None;
+        };
+
+        let format = (packet.sample_type(), packet.channel_count());
+        if state.audio_format != Some(format) {
+            // Silence is zero in both sample types
+            let frame_bytes = sample_frame_bytes(format.0, format.1);
+            state.audio.clear();
+            state.audio.resize(delay * frame_bytes, 0);
+            state.audio_format = Some(format);
+        }
+
+        state.audio.extend(bytes);
+        let delayed: Vec<u8> = state.audio.drain(..bytes.len()).collect();
+        Some(DecklinkAudioInputPacket::from_samples(
+            format.0,
+            format.1,
+            delayed,
+            DecklinkTime::new(time, AUDIO_SAMPLE_RATE),
+        ))
+    }
+}
+
+impl InputHandler for AlignerInputCallback {
+    fn format_changed(&self, change: &InputFormatChange) {
+        if self.shared.active.load(Ordering::Acquire) {
+            self.shared.state.lock().unwrap().reset();
+        }
+        self.shared.primary.format_changed(change);
+    }
+
+    fn frame_arrived(&self, arrival: &FrameArrival<'_>) -> CallbackResult {
+        if !self.shared.active.load(Ordering::Acquire) {
+            return self.shared.primary.frame_arrived(arrival);
+        }
+
+        let mut state = self.shared.state.lock().unwrap();
+        if let Some(timing) = arrival.timing() {
+            self.follow_frame_duration(&mut state, timing);
+        }
+        let Some(plan) = state.plan else {
+            drop(state);
+            return self.shared.primary.frame_arrived(arrival);
+        };
+
+        let packet = match arrival.audio_packet {
+            Some(packet) if plan.audio_delay_samples > 0 => {
+                Self::delay_audio(&mut state, packet, plan.audio_delay_samples)
+            }
+            _ => None,
+        };
+        // A lost frame leaves the video delay line as it is, and is passed on as lost
+        let video = (plan.video_delay_frames > 0
+            && !arrival.flags.contains(ArrivalFlags::FRAME_LOST))
+        .then(|| {
+            self.delay_video(
+                &mut state,
+                arrival.retain_video_frame(),
+                plan.video_delay_frames,
+            )
+        });
+        drop(state);
+
+        let mut delayed = *arrival;
+        if let Some(packet) = &packet {
+            delayed = delayed.with_audio_packet(Some(packet));
+        }
+        if let Some(frame) = &video {
+            delayed = delayed.with_video_frame(frame.as_ref().map(|delayed| &delayed.frame));
+            // A dimension mismatch is of the frame that arrived, which is held back, so it
+            // must not make the frame passed on in its place look dropped
+            delayed.flags.remove(ArrivalFlags::DIMENSION_MISMATCH);
+            if frame.is_none() {
+                // The timing is passed on with the frame that is delivered in this callback
+                delayed.context.timing = None;
+            }
+        }
+        self.shared.primary.frame_arrived(&delayed)
+    }
+}
@@ -0,0 +1,380 @@
+//! Delivering captured frames in batches, to amortize per-frame overhead at high frame rates.
+//!
+//! A `BatchDispatcher` is installed as the input callback. The driver callback only queues
+//! each frame, and a dispatch thread collects them into batches of up to `max_frames`,
+//! invoking the `DeckLinkInputBatchCallback` once per batch. A batch is also delivered once
+//! `max_latency` has passed since its first frame arrived, so frames are never held back
+//! for long at low frame rates.
+//!
+//! Frames are delivered in the order they arrived, within and across batches. A format
+//! change, or a change between having and not having an input signal, delivers the pending
+//! batch before the event, so every frame in a batch was captured under the same conditions.
+
+use crate::config::ConfigError;
+use crate::device::input::{
+    CallbackResult, DecklinkDetectedVideoInputFormatFlags, DecklinkVideoInputFormatChangedEvents,
+    FrameArrival, InputFormatChange, InputHandler,
+};
+use crate::display_mode::DecklinkDisplayModeId;
+use crate::frame::{DecklinkFrameBase, DecklinkFrameFlags, DecklinkVideoFrame};
+use crate::queue::{FrameQueue, OverflowPolicy, PopError, PushResult};
+use crate::threads::{self, ThreadHandle};
+use crate::time::DecklinkFrameTiming;
+use crate::util::internal_thread_started;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub struct BatchConfig {
+    /// The most frames delivered in one batch.
+    pub max_frames: usize,
+    /// The longest a frame waits for its batch to fill before the batch is delivered.
+    pub max_latency: Duration,
+    /// The number of frames and events that can be queued for the dispatch thread. Once full,
+    /// further frames are dropped and counted in `BatchStats::dropped`.
+    pub queue_capacity: usize,
+}
+
+impl Default for BatchConfig {
+    fn default() -> Self {
+        BatchConfig {
+            max_frames: 4,
+            max_latency: Duration::from_millis(20),
+            queue_capacity: 32,
+        }
+    }
+}
+
+impl BatchConfig {
+    pub fn builder() -> BatchConfigBuilder {
+        BatchConfigBuilder {
+            config: BatchConfig::default(),
+        }
+    }
+
+    /// Check that a dispatcher with this config could deliver batches.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let error = |field, problem| Err(ConfigError::new("BatchConfig", field, problem));
+        if self.max_frames == 0 {
+            return error("max_frames", "must be at least 1");
+        }
+        if self.queue_capacity == 0 {
+            return error(
+                "queue_capacity",
+                "must be at least 1, or every frame would be dropped",
+            );
+        }
+        if self.max_latency.is_zero() && self.max_frames > 1 {
+            return error(
+                "max_latency",
+                "is zero, so batches are delivered before a second frame can join them; \
+                 set max_frames to 1 to deliver frames one at a time",
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Builds a `BatchConfig`, starting from the default.
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub struct BatchConfigBuilder {
+    config: BatchConfig,
+}
+
+impl BatchConfigBuilder {
+    pub fn max_frames(mut self, max_frames: usize) -> Self {
+        self.config.max_frames = max_frames;
+        self
+    }
+
+    pub fn max_latency(mut self, max_latency: Duration) -> Self {
+        self.config.max_latency = max_latency;
+        self
+    }
+
+    pub fn queue_capacity(mut self, queue_capacity: usize) -> Self {
+        self.config.queue_capacity = queue_capacity;
+        self
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        self.config.validate()
+    }
+
+    pub fn build(self) -> Result<BatchConfig, ConfigError> {
+        self.validate()?;
+        Ok(self.config)
+    }
+}
+
+/// A captured frame with the context it arrived with.
+pub struct BatchedFrame {
+    pub frame: DecklinkVideoFrame,
+    /// The timing of the frame, if the display mode timescale was known.
+    pub timing: Option<DecklinkFrameTiming>,
+    /// When the frame arrived in the driver callback.
+    pub arrived: Instant,
+}
+
+/// Receives batches of frames from a `BatchDispatcher`, on its dispatch thread.
+pub trait DeckLinkInputBatchCallback: Send {
+    /// Called with each batch of frames, oldest first. Batches are never empty.
+    fn frames_arrived(&mut self, frames: &[BatchedFrame]);
+
+    /// Called when the video input format changes, after any pending batch.
+    fn video_input_format_changed(
+        &mut self,
+        _events: DecklinkVideoInputFormatChangedEvents,
+        _new_display_mode: DecklinkDisplayModeId,
+        _detected_signal_flags: DecklinkDetectedVideoInputFormatFlags,
+    ) {
+    }
+
+    /// Called when frames start arriving without an input source, after any pending batch.
+    fn signal_lost(&mut self) {}
+
+    /// Called when frames have an input source again, after any pending batch.
+    fn signal_restored(&mut self) {}
+}
+
+/// Why a batch was delivered.
+#[derive(Copy, Clone)]
+enum FlushReason {
+    /// The batch reached `max_frames`.
+    Full,
+    /// The first frame of the batch had waited for `max_latency`.
+    Latency,
+    /// An event had to be delivered after the batch.
+    Event,
+    /// The dispatcher was stopped.
+    Teardown,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct BatchStats {
+    pub batches: u64,
+    pub frames: u64,
+    /// The number of batches of each size, indexed by the number of frames.
+    pub batch_sizes: Vec<u64>,
+    pub flushed_full: u64,
+    pub flushed_latency: u64,
+    pub flushed_event: u64,
+    pub flushed_teardown: u64,
+    /// Frames and events dropped because the dispatch queue was full.
+    pub dropped: u64,
+}
+
+impl BatchStats {
+    /// The average number of frames per batch.
+    pub fn average_batch_size(&self) -> f64 {
+        if self.batches == 0 {
+            0.0
+        } else {
+            self.frames as f64 / self.batches as f64
+        }
+    }
+}
+
+enum BatchEvent {
+    Frame(BatchedFrame),
+    FormatChanged(
+        DecklinkVideoInputFormatChangedEvents,
+        DecklinkDisplayModeId,
+        DecklinkDetectedVideoInputFormatFlags,
+    ),
+    SignalLost,
+    SignalRestored,
+}
+
+struct DispatcherShared {
+    queue: FrameQueue<BatchEvent>,
+    stats: Mutex<BatchStats>,
+    has_signal: AtomicBool,
+}
+
+impl DispatcherShared {
+    fn push(&self, event: BatchEvent) {
+        if let PushResult::Rejected(_) = self.queue.push(event) {
+            self.stats.lock().unwrap().dropped += 1;
+        }
+    }
+}
+
+struct Batcher<H: DeckLinkInputBatchCallback> {
+    handler: H,
+    batch: Vec<BatchedFrame>,
+    shared: Arc<DispatcherShared>,
+}
+
+impl<H: DeckLinkInputBatchCallback> Batcher<H> {
+    fn flush(&mut self, reason: FlushReason) {
+        if self.batch.is_empty() {
+            return;
+        }
+        self.handler.frames_arrived(&self.batch);
+
+        let size = self.batch.len();
+        let mut stats = self.shared.stats.lock().unwrap();
+        stats.batches += 1;
+        stats.frames += size as u64;
+        if stats.batch_sizes.len() <= size {
+            stats.batch_sizes.resize(size + 1, 0);
+        }
+        stats.batch_sizes[size] += 1;
+        match reason {
+            FlushReason::Full => stats.flushed_full += 1,
+            FlushReason::Latency => stats.flushed_latency += 1,
+            FlushReason::Event => stats.flushed_event += 1,
+            FlushReason::Teardown => stats.flushed_teardown += 1,
+        }
+        drop(stats);
+
+        // Release the frames back to the driver before waiting for more
+        self.batch.clear();
+    }
+
+    fn run(&mut self, config: BatchConfig) {
+        let max_frames = config.max_frames.max(1);
+        loop {
+            let event = match self.batch.first() {
+                None => self.shared.queue.pop().ok_or(PopError::Closed),
+                Some(first) => {
+                    let waited = first.arrived.elapsed();
+                    match config.max_latency.checked_sub(waited) {
+                        Some(remaining) if !remaining.is_zero() => {
+                            self.shared.queue.pop_timeout(remaining)
+                        }
+                        _ => Err(PopError::Timeout),
+                    }
+                }
+            };
+
+            match event {
+                Ok(BatchEvent::Frame(frame)) => {
+                    self.batch.push(frame);
+                    if self.batch.len() >= max_frames {
+                        self.flush(FlushReason::Full);
+                    }
+                }
+                Ok(BatchEvent::FormatChanged(events, mode, flags)) => {
+                    self.flush(FlushReason::Event);
+                    self.handler.video_input_format_changed(events, mode, flags);
+                }
+                Ok(BatchEvent::SignalLost) => {
+                    self.flush(FlushReason::Event);
+                    self.handler.signal_lost();
+                }
+                Ok(BatchEvent::SignalRestored) => {
+                    self.flush(FlushReason::Event);
+                    self.handler.signal_restored();
+                }
+                Err(PopError::Timeout) => self.flush(FlushReason::Latency),
+                Err(PopError::Closed) => {
+                    self.flush(FlushReason::Teardown);
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// An input callback that delivers frames in batches from its own thread.
+///
+/// Install the callback returned by `callback` with `DecklinkInputDevice::set_handler`.
+/// When the dispatcher is dropped, the frames still queued are delivered, including any
+/// partial batch, before its thread exits.
+pub struct BatchDispatcher {
+    shared: Arc<DispatcherShared>,
+    thread: Option<ThreadHandle>,
+}
+
+impl BatchDispatcher {
+    pub fn new<H>(config: BatchConfig, handler: H) -> BatchDispatcher
+    where
+        H: DeckLinkInputBatchCallback + 'static,
+    {
+        let shared = Arc::new(DispatcherShared {
+            queue: FrameQueue::new(config.queue_capacity, OverflowPolicy::RejectNewest),
+            stats: Mutex::new(BatchStats::default()),
+            has_signal: AtomicBool::new(true),
+        });
+
+        let thread = {
+            let shared = shared.clone();
+            threads::spawn("batch", move || {
+                internal_thread_started("batch");
+                let mut batcher = Batcher {
+                    handler,
+                    batch: Vec::with_capacity(config.max_frames.max(1)),
+                    shared,
+                };
+                batcher.run(config);
+            })
+        };
+
+        BatchDispatcher {
+            shared,
+            thread: Some(thread),
+        }
+    }
+
+    /// The input callback to install on the device.
+    pub fn callback(&self) -> Arc<dyn InputHandler> {
+        Arc::new(BatchInputCallback {
+            shared: self.shared.clone(),
+        })
+    }
+
+    pub fn stats(&self) -> BatchStats {
+        self.shared.stats.lock().unwrap().clone()
+    }
+}
+
+impl Drop for BatchDispatcher {
+    fn drop(&mut self) {
+        self.shared.queue.close();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+struct BatchInputCallback {
+    shared: Arc<DispatcherShared>,
+}
+
+impl InputHandler for BatchInputCallback {
+    fn format_changed(&self, change: &InputFormatChange) {
+        self.shared.push(BatchEvent::FormatChanged(
+            change.events,
+            change.display_mode,
+            change.detected_signal_flags,
+        ));
+    }
+
+    fn frame_arrived(&self, arrival: &FrameArrival<'_>) -> CallbackResult {
+        let frame = match arrival.retain_video_frame() {
+            Some(frame) => frame,
+            None => return CallbackResult::Ok,
+        };
+
+        let has_signal = !frame
+            .flags()
+            .contains(DecklinkFrameFlags::HAS_NO_INPUT_SOURCE);
+        if self.shared.has_signal.swap(has_signal, Ordering::Relaxed) != has_signal {
+            self.shared.push(if has_signal {
+                BatchEvent::SignalRestored
+            } else {
+                BatchEvent::SignalLost
+            });
+        }
+
+        self.shared.push(BatchEvent::Frame(BatchedFrame {
+            frame,
+            timing: arrival.timing(),
+            arrived: Instant::now(),
+        }));
+        CallbackResult::Ok
+    }
+}
@@ -0,0 +1,1406 @@
+//! A command line tool for scripted capture operations.
+//!
+//! Built with the `cli` feature, as the `decklink` binary. It only uses the public api of the
+//! crate, so each subcommand is also an example of how to do the same from a program.
+
+extern crate decklink;
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use decklink::colorimetry::TransferFunction;
+use decklink::device::input::{
+    CallbackResult, CancellationToken, DecklinkDetectedVideoInputFormatFlags, DecklinkInputDevice,
+    DecklinkVideoInputFlags, FirstFrameError, FirstFrameOptions, FrameArrival, InputFormatChange,
+    InputHandler,
+};
+use decklink::device::output::DecklinkVideoOutputFlags;
+use decklink::device::registry::DeviceRegistry;
+use decklink::device::selector::{DeviceSelector, ParseSelectorError, SelectError};
+use decklink::device::{DecklinkDevice, DecklinkDeviceDisplayModes};
+use decklink::display_mode::DecklinkDisplayModeId;
+use decklink::dump::{DumpError, DumpFrame, DumpHeader, DumpReader, SegmentedDumpWriter};
+use decklink::effective_config::DecklinkEffectiveConfigExt;
+use decklink::event::DeviceIdentity;
+use decklink::experimental::mov::{MovConfig, SegmentedMovWriter};
+use decklink::experimental::quirks::QuirkPolicy;
+use decklink::format_detect::{FormatDecision, FormatDetector};
+use decklink::frame::{
+    DecklinkFrameBase, DecklinkFrameFlags, DecklinkPixelFormat, DecklinkVideoFrame,
+};
+use decklink::image_interop::Colorimetry;
+use decklink::manifest::{CaptureManifest, CaptureSetup, ManifestEvent};
+use decklink::probe::run_all;
+use decklink::queue::{FrameQueue, OverflowPolicy, PopError, PushResult};
+use decklink::reference;
+use decklink::scaffold::{write_project, DecklinkDependency, ScaffoldOptions};
+use decklink::segment::{SegmentPolicy, SegmentSink, SegmentedWriter};
+use decklink::session::{CaptureSession, ExactCapture, Limit};
+use decklink::soak::{self, SoakConfig, SoakError, SoakProfile};
+use decklink::still::{save_png, ExportColorPolicy, SourceColor, ToneMapOperator};
+use decklink::time::DecklinkFrameTiming;
+use decklink::timecode::{frame_rate_of, TimecodeTracker, TimecodeTrackerConfig};
+use decklink::vpid::DecklinkVpidExt;
+use decklink::{api_version, capabilities, SdkError};
+use std::fmt::Write as _;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Exit codes, listed in the help text.
+pub const EXIT_FAILED: u8 = 1;
+pub const EXIT_PROBE_FAILED: u8 = 2;
+pub const EXIT_UNSUPPORTED: u8 = 3;
+pub const EXIT_NO_DRIVER: u8 = 4;
+pub const EXIT_NO_DEVICE: u8 = 5;
+
+#[derive(Parser)]
+#[command(
+    name = "decklink",
+    version,
+    about = "Scripted capture operations on Blackmagic Decklink devices",
+    after_help = "Devices are chosen by their index in `decklink list`, their persistent id, \
+                  or their display name.\n\n\
+                  Exit codes:\n  \
+                  0  success\n  \
+                  1  the operation failed\n  \
+                  2  a probe failed\n  \
+                  3  the operation is not supported\n  \
+                  4  the Decklink drivers are not installed\n  \
+                  5  the device was not found"
+)]
+pub struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// List the devices, with their persistent ids and connections
+    List(JsonArgs),
+    /// Run the diagnostic probes on a device
+    Probe {
+        device: String,
+        #[command(flatten)]
+        output: JsonArgs,
+    },
+    /// Capture a single frame to a PNG file
+    Still {
+        device: String,
+        /// The display mode name, such as 1080i50, or auto to follow the detected signal
+        #[arg(long, default_value = "auto")]
+        mode: String,
+        #[arg(long)]
+        out: PathBuf,
+        /// Seconds to wait for a frame
+        #[arg(long, default_value_t = 10)]
+        timeout: u64,
+        #[command(flatten)]
+        color: StillColorArgs,
+    },
+    /// Record raw frames, frame dumps or movie files into a directory, with a manifest of the
+    /// capture
+    Record {
+        device: String,
+        /// The display mode name, such as 1080i50, or auto to follow the detected signal
+        #[arg(long, default_value = "auto")]
+        mode: String,
+        #[arg(long, default_value_t = 10)]
+        seconds: u64,
+        /// Record exactly this many frames, rather than for a number of seconds
+        #[arg(long, conflicts_with = "seconds")]
+        frames: Option<u64>,
+        /// Start a new file after this many frames
+        #[arg(long, default_value_t = 1500)]
+        segment_frames: u64,
+        /// How the frames are stored
+        #[arg(long, value_enum, default_value = "raw")]
+        container: Container,
+        #[arg(long)]
+        out: PathBuf,
+    },
+    /// Play the frames of a frame dump out of a device, at the rate they were captured
+    Play { device: String, file: PathBuf },
+    /// Describe a frame dump, and check it for damage
+    Inspect {
+        file: PathBuf,
+        #[command(flatten)]
+        output: JsonArgs,
+    },
+    /// Back up, restore or compare the configuration of a device
+    Config {
+        #[arg(value_enum)]
+        action: ConfigAction,
+        device: String,
+        file: Option<PathBuf>,
+    },
+    /// Report the input connections of a device, the signal it detects and its SDI payload id
+    Scan {
+        device: String,
+        /// Seconds to wait for a signal to be detected
+        #[arg(long, default_value_t = 3)]
+        timeout: u64,
+        #[command(flatten)]
+        output: JsonArgs,
+    },
+    /// Capture for hours, checking the invariants of the crate, and print a summary as JSON
+    Soak {
+        /// The device to capture from, or none for a mock device, run faster than real time
+        device: Option<String>,
+        /// How long to run for, in stream time on a mock device
+        #[arg(long, default_value_t = 24.0)]
+        hours: f64,
+        /// How the capture holds its frames
+        #[arg(long, value_enum, default_value = "default")]
+        profile: Profile,
+        /// The display mode name, such as 1080p25
+        #[arg(long, default_value = "1080p25")]
+        mode: String,
+        /// Append the progress of the run to this file, as lines of JSON
+        #[arg(long)]
+        checkpoint: Option<PathBuf>,
+        /// Perturb a mock capture every this many seconds
+        #[arg(long)]
+        perturb_every: Option<u64>,
+    },
+    /// Print the hardware, health and capability details of every device as JSON
+    Report,
+    /// Print the reference tables of pixel formats, display modes and status ids as JSON
+    Reference,
+    /// Generate a cargo project that captures with this crate, as a starting point
+    Scaffold {
+        /// The directory of the project, created if needed
+        dir: PathBuf,
+        /// The name of the package
+        #[arg(long, default_value = "decklink-capture")]
+        name: String,
+        /// Capture audio along with the video
+        #[arg(long)]
+        audio: bool,
+        /// Capture into CUDA pinned memory
+        #[arg(long)]
+        cuda: bool,
+        /// Run the capture from a tokio main
+        #[arg(long)]
+        tokio: bool,
+        /// Depend on a checkout of this crate rather than the version on crates.io
+        #[arg(long)]
+        decklink_path: Option<PathBuf>,
+    },
+}
+
+#[derive(Args)]
+struct JsonArgs {
+    /// Print the result as JSON
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(Args)]
+struct StillColorArgs {
+    /// The transfer function of the source, which the signal does not say
+    #[arg(long, value_enum, default_value = "sdr")]
+    transfer: Transfer,
+    /// The colorimetry of the source, or auto for Rec. 2020 for HDR and the usual one for
+    /// the frame height otherwise
+    #[arg(long, value_enum, default_value = "auto")]
+    colorimetry: ColorimetryArg,
+    /// How HDR is mapped into an 8-bit SDR image, or hdr16 for a 16-bit image of the signal
+    /// tagged with its colour
+    #[arg(long, value_enum, default_value = "bt2408")]
+    color_policy: ColorPolicy,
+}
+
+#[derive(ValueEnum, Clone, Copy)]
+enum Container {
+    /// Frame buffers as they are, with no header
+    Raw,
+    /// QuickTime movie files of uncompressed video
+    Mov,
+    /// Frame dumps, with the metadata and timecode of every frame, as `decklink play` and
+    /// `decklink inspect` read
+    Dump,
+}
+
+#[derive(ValueEnum, Clone, Copy)]
+enum Transfer {
+    Sdr,
+    /// SMPTE ST 2084, BT.2100 PQ
+    Pq,
+    /// BT.2100 hybrid log-gamma
+    Hlg,
+}
+
+#[derive(ValueEnum, Clone, Copy)]
+enum ColorimetryArg {
+    Auto,
+    Rec601,
+    Rec709,
+    Rec2020,
+}
+
+#[derive(ValueEnum, Clone, Copy)]
+enum ColorPolicy {
+    /// Clip everything brighter than reference white
+    Clip,
+    /// Compress the light up to 1000 cd/m², darkening the picture
+    Reinhard,
+    /// The BT.2408 EETF, rolling off above reference white
+    Bt2408,
+    /// A 16-bit PNG of the signal with a cICP chunk
+    Hdr16,
+}
+
+#[derive(ValueEnum, Clone, Copy)]
+enum Profile {
+    /// Hold the last three frames
+    Default,
+    /// Hold only the last frame
+    #[value(name = "lowlatency")]
+    LowLatency,
+    /// Hold the last three frames, in buffers from a custom allocator
+    Allocator,
+}
+
+#[derive(ValueEnum, Clone, Copy)]
+enum ConfigAction {
+    Backup,
+    Restore,
+    Diff,
+}
+
+/// A failed command, with the exit code to report it with.
+pub struct Failure {
+    pub code: u8,
+    pub message: String,
+}
+
+impl Failure {
+    fn new(code: u8, message: impl Into<String>) -> Failure {
+        Failure {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl From<SdkError> for Failure {
+    fn from(e: SdkError) -> Self {
+        Failure::new(
+            EXIT_FAILED,
+            format!("the device reported an error: {:?}", e),
+        )
+    }
+}
+
+impl From<std::io::Error> for Failure {
+    fn from(e: std::io::Error) -> Self {
+        Failure::new(EXIT_FAILED, e.to_string())
+    }
+}
+
+impl From<DumpError> for Failure {
+    fn from(e: DumpError) -> Self {
+        Failure::new(EXIT_FAILED, e.to_string())
+    }
+}
+
+fn main() -> ExitCode {
+    match run(Cli::parse(), &mut std::io::stdout()) {
+        Ok(code) => ExitCode::from(code),
+        Err(failure) => {
+            eprintln!("{}", failure.message);
+            ExitCode::from(failure.code)
+        }
+    }
+}
+
+/// Run a parsed command line, writing its output to `out`, and return the exit code.
+///
+/// Public for `tests/cli.rs`, which includes this file as a module.
+pub fn run(cli: Cli, out: &mut dyn Write) -> Result<u8, Failure> {
+    // Generating a project, printing the reference tables, inspecting a dump and soaking a
+    // mock device do not need the drivers
+    let needs_driver = !matches!(
+        cli.command,
+        Command::Scaffold { .. }
+            | Command::Reference
+            | Command::Inspect { .. }
+            | Command::Soak { device: None, .. }
+    );
+    if needs_driver && api_version().is_err() {
+        return Err(Failure::new(
+            EXIT_NO_DRIVER,
+            "The Decklink drivers are not installed, or could not be loaded",
+        ));
+    }
+
+    match cli.command {
+        Command::List(output) => list(output.json, out),
+        Command::Probe { device, output } => probe(&device, output.json, out),
+        Command::Still {
+            device,
+            mode,
+            out: path,
+            timeout,
+            color,
+        } => still(
+            &device,
+            &mode,
+            path,
+            Duration::from_secs(timeout),
+            color,
+            out,
+        ),
+        Command::Record {
+            device,
+            mode,
+            seconds,
+            frames,
+            segment_frames,
+            container,
+            out: path,
+        } => record(
+            &device,
+            &mode,
+            match frames {
+                Some(frames) => Limit::Frames(frames),
+                None => Limit::Duration(Duration::from_secs(seconds)),
+            },
+            segment_frames,
+            container,
+            path,
+            out,
+        ),
+        Command::Play { device, file } => play(&device, &file, out),
+        Command::Inspect { file, output } => inspect(&file, output.json, out),
+        Command::Config { action, device, .. } => config(action, &device),
+        Command::Scan {
+            device,
+            timeout,
+            output,
+        } => scan(&device, Duration::from_secs(timeout), output.json, out),
+        Command::Soak {
+            device,
+            hours,
+            profile,
+            mode,
+            checkpoint,
+            perturb_every,
+        } => {
+            let mut config = SoakConfig::new(Duration::from_secs_f64(hours.max(0.0) * 3600.0));
+            config.profile = match profile {
+                Profile::Default => SoakProfile::Default,
+                Profile::LowLatency => SoakProfile::LowLatency,
+                Profile::Allocator => SoakProfile::Allocator,
+            };
+            config.mode = reference::all()
+                .display_modes
+                .iter()
+                .find(|m| m.name == mode || format!("{:?}", m.mode) == mode)
+                .map(|m| m.mode)
+                .ok_or_else(|| {
+                    Failure::new(EXIT_FAILED, format!("unknown display mode {:?}", mode))
+                })?;
+            config.checkpoint = checkpoint;
+            config.perturb_every = perturb_every.map(Duration::from_secs);
+            soak(device.as_deref(), &config, out)
+        }
+        Command::Report => report(out),
+        Command::Reference => {
+            write!(out, "{}", reference::all().to_json())?;
+            Ok(0)
+        }
+        Command::Scaffold {
+            dir,
+            name,
+            audio,
+            cuda,
+            tokio,
+            decklink_path,
+        } => {
+            let options = ScaffoldOptions {
+                name,
+                audio,
+                cuda,
+                tokio,
+                decklink: decklink_path
+                    .map_or_else(DecklinkDependency::default, DecklinkDependency::Path),
+            };
+            scaffold(&dir, &options, out)
+        }
+    }
+}
+
+fn json_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+fn json_opt_string(out: &mut String, s: Option<&str>) {
+    match s {
+        Some(s) => json_string(out, s),
+        None => out.push_str("null"),
+    }
+}
+
+fn json_opt<T: std::fmt::Display>(out: &mut String, value: Option<T>) {
+    match value {
+        Some(value) => {
+            let _ = write!(out, "{}", value);
+        }
+        None => out.push_str("null"),
+    }
+}
+
+/// Find a device by a selector such as `0`, `id:0x1234` or `name:DeckLink Duo (1)`.
+fn find_device(spec: &str) -> Result<DecklinkDevice, Failure> {
+    let selector: DeviceSelector = spec
+        .parse()
+        .map_err(|e: ParseSelectorError| Failure::new(EXIT_FAILED, e.to_string()))?;
+    selector.resolve().map_err(|e| match e {
+        SelectError::Sdk(e) => Failure::from(e),
+        e @ (SelectError::NoMatch { .. } | SelectError::Ambiguous { .. }) => {
+            Failure::new(EXIT_NO_DEVICE, e.to_string())
+        }
+        e => Failure::new(EXIT_UNSUPPORTED, e.to_string()),
+    })
+}
+
+fn input_of(device: &DecklinkDevice) -> Result<DecklinkInputDevice, Failure> {
+    device
+        .input()
+        .ok_or_else(|| Failure::new(EXIT_UNSUPPORTED, "the device has no input"))
+}
+
+/// The display mode to start capturing in, and whether to follow the detected format.
+fn choose_mode(
+    input: &DecklinkInputDevice,
+    device: &DecklinkDevice,
+    name: &str,
+) -> Result<(DecklinkDisplayModeId, bool), Failure> {
+    let modes = input.display_modes()?;
+    if name == "auto" {
+        let detection = device
+            .get_attributes()
+            .and_then(|a| a.supports_input_format_detection())
+            .unwrap_or(false);
+        if !detection {
+            return Err(Failure::new(
+                EXIT_UNSUPPORTED,
+                "the device cannot detect the input format, so a --mode must be given",
+            ));
+        }
+        let mode = modes
+            .first()
+            .map(|m| m.mode())
+            .ok_or_else(|| Failure::new(EXIT_UNSUPPORTED, "the input has no display modes"))?;
+        Ok((mode, true))
+    } else {
+        modes
+            .iter()
+            .find(|m| m.name().as_deref() == Some(name) || format!("{:?}", m.mode()) == name)
+            .map(|m| (m.mode(), false))
+            .ok_or_else(|| Failure::new(EXIT_FAILED, format!("unknown display mode {:?}", name)))
+    }
+}
+
+fn enable_input(
+    input: &mut DecklinkInputDevice,
+    mode: DecklinkDisplayModeId,
+    pixel_format: DecklinkPixelFormat,
+    detect: bool,
+) -> Result<(), SdkError> {
+    let flags = if detect {
+        DecklinkVideoInputFlags::ENABLE_FORMAT_DETECTION
+    } else {
+        DecklinkVideoInputFlags::empty()
+    };
+    input.enable_video_input(mode, pixel_format, flags)
+}
+
+fn list(json: bool, output: &mut dyn Write) -> Result<u8, Failure> {
+    let devices = DeviceRegistry::new()?.devices();
+    let mut out = String::new();
+    if json {
+        out.push('[');
+    }
+    for (i, device) in devices.iter().enumerate() {
+        let attributes = device.get_attributes().ok();
+        let persistent_id = attributes.as_ref().and_then(|a| a.persistent_id().ok());
+        let inputs = attributes
+            .as_ref()
+            .and_then(|a| a.video_input_connections().ok());
+        let outputs = attributes
+            .as_ref()
+            .and_then(|a| a.video_output_connections().ok());
+
+        if json {
+            if i > 0 {
+                out.push(',');
+            }
+            let _ = write!(out, "\n  {{\"index\": {}, \"display_name\": ", i);
+            json_opt_string(&mut out, device.display_name().as_deref());
+            out.push_str(", \"model_name\": ");
+            json_opt_string(&mut out, device.model_name().as_deref());
+            out.push_str(", \"persistent_id\": ");
+            json_opt(&mut out, persistent_id);
+            out.push_str(", \"video_inputs\": ");
+            json_opt_string(&mut out, inputs.map(|c| format!("{:?}", c)).as_deref());
+            out.push_str(", \"video_outputs\": ");
+            json_opt_string(&mut out, outputs.map(|c| format!("{:?}", c)).as_deref());
+            out.push('}');
+        } else {
+            let _ = writeln!(
+                out,
+                "{}: {} ({})",
+                i,
+                device
+                    .display_name()
+                    .unwrap_or_else(|| "Unknown".to_string()),
+                device.model_name().unwrap_or_else(|| "Unknown".to_string())
+            );
+            if let Some(id) = persistent_id {
+                let _ = writeln!(out, "   persistent id: {}", id);
+            }
+            if let Some(inputs) = inputs {
+                let _ = writeln!(out, "   video inputs:  {:?}", inputs);
+            }
+            if let Some(outputs) = outputs {
+                let _ = writeln!(out, "   video outputs: {:?}", outputs);
+            }
+        }
+    }
+    if json {
+        out.push_str("\n]");
+        writeln!(output, "{}", out)?;
+    } else if devices.is_empty() {
+        writeln!(output, "No Decklink devices were found")?;
+    } else {
+        write!(output, "{}", out)?;
+    }
+    Ok(0)
+}
+
+fn probe(device: &str, json: bool, out: &mut dyn Write) -> Result<u8, Failure> {
+    let device = find_device(device)?;
+    let report = run_all(&device);
+    if json {
+        writeln!(out, "{}", report.to_json())?;
+    } else {
+        writeln!(out, "{}", report)?;
+    }
+    if report.failures().next().is_some() {
+        Ok(EXIT_PROBE_FAILED)
+    } else {
+        Ok(0)
+    }
+}
+
+/// Follows format changes through a `FormatDetector`, and notes the mode of the last one it
+/// decided on, so capture can be restarted in it.
+struct FormatFollower {
+    detector: Mutex<FormatDetector>,
+    detected: Mutex<Option<DecklinkDisplayModeId>>,
+}
+
+impl FormatFollower {
+    fn new(mode: DecklinkDisplayModeId) -> FormatFollower {
+        let mut detector = FormatDetector::new(0, &QuirkPolicy::BuiltIn);
+        detector.enabled(mode, DecklinkDetectedVideoInputFormatFlags::empty());
+        FormatFollower {
+            detector: Mutex::new(detector),
+            detected: Mutex::new(None),
+        }
+    }
+
+    /// Note that capture was restarted in `mode`.
+    fn enabled(&self, mode: DecklinkDisplayModeId) {
+        let mut detector = self.detector.lock().unwrap();
+        detector.enabled(mode, DecklinkDetectedVideoInputFormatFlags::empty());
+    }
+
+    fn decided(&self, decision: Option<FormatDecision>) {
+        if let Some(decision) = decision {
+            *self.detected.lock().unwrap() = Some(decision.mode);
+        }
+    }
+
+    /// Tell the user about the quirk rules that changed how the signal was followed.
+    fn report_quirks(&self) {
+        for applied in self.detector.lock().unwrap().take_applied() {
+            eprintln!(
+                "Applied format detection quirk {} to {:?}",
+                applied.rule, applied.detected_mode
+            );
+        }
+    }
+}
+
+impl InputHandler for FormatFollower {
+    fn format_changed(&self, change: &InputFormatChange) {
+        let decision = self.detector.lock().unwrap().format_changed(
+            change.events,
+            change.display_mode,
+            change.detected_signal_flags,
+        );
+        self.decided(decision);
+    }
+
+    fn frame_arrived(&self, _arrival: &FrameArrival<'_>) -> CallbackResult {
+        let decision = self.detector.lock().unwrap().frame();
+        self.decided(decision);
+        CallbackResult::Ok
+    }
+}
+
+fn still(
+    device: &str,
+    mode: &str,
+    path: PathBuf,
+    timeout: Duration,
+    color: StillColorArgs,
+    out: &mut dyn Write,
+) -> Result<u8, Failure> {
+    let device = find_device(device)?;
+    let mut input = input_of(&device)?;
+    let (mut mode, detect) = choose_mode(&input, &device, mode)?;
+
+    let transfer = match color.transfer {
+        Transfer::Sdr => TransferFunction::Sdr,
+        Transfer::Pq => TransferFunction::Pq,
+        Transfer::Hlg => TransferFunction::Hlg,
+    };
+    let policy = match color.color_policy {
+        ColorPolicy::Clip => ExportColorPolicy::ToneMap(ToneMapOperator::Clip),
+        ColorPolicy::Reinhard => ExportColorPolicy::ToneMap(ToneMapOperator::Reinhard),
+        ColorPolicy::Bt2408 => ExportColorPolicy::ToneMap(ToneMapOperator::Bt2408),
+        ColorPolicy::Hdr16 => ExportColorPolicy::Hdr16,
+    };
+    // Capture HDR and 16-bit stills in 10 bits, so they are not truncated to 8 bits first
+    let pixel_format = if transfer != TransferFunction::Sdr || policy == ExportColorPolicy::Hdr16 {
+        DecklinkPixelFormat::Format10BitYUV
+    } else {
+        DecklinkPixelFormat::Format8BitYUV
+    };
+
+    let follower = Arc::new(FormatFollower::new(mode));
+    input.set_handler(Some(follower.clone()))?;
+
+    let deadline = Instant::now() + timeout;
+    let frame = loop {
+        enable_input(&mut input, mode, pixel_format, detect)?;
+        input.start_streams()?;
+
+        // Wait in short steps, so a detected format change can restart the capture
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let options = FirstFrameOptions {
+            timeout: remaining.min(Duration::from_millis(500)),
+            frames_to_skip_after_format_change: 2,
+            ..Default::default()
+        };
+        let result = input.wait_first_frame(options, None);
+        input.stop_streams()?;
+        input.disable_video_input()?;
+
+        match result {
+            Ok(first) => match follower.detected.lock().unwrap().take() {
+                // The signal changed to another mode, so capture again in it
+                Some(detected) if detect && detected != mode => mode = detected,
+                _ => break first.frame,
+            },
+            Err(FirstFrameError::Timeout { .. }) if Instant::now() < deadline => {
+                if let Some(detected) = follower.detected.lock().unwrap().take() {
+                    mode = detected;
+                    follower.enabled(mode);
+                }
+            }
+            Err(FirstFrameError::Timeout { .. }) => {
+                return Err(Failure::new(
+                    EXIT_FAILED,
+                    "no frame with a signal arrived in time",
+                ))
+            }
+            Err(FirstFrameError::DeviceError(e)) => return Err(e.into()),
+            Err(FirstFrameError::Cancelled(_)) => {
+                return Err(Failure::new(EXIT_FAILED, "the capture was cancelled"))
+            }
+        }
+    };
+    follower.report_quirks();
+
+    let colorimetry = match color.colorimetry {
+        ColorimetryArg::Auto if transfer != TransferFunction::Sdr => Colorimetry::Rec2020,
+        ColorimetryArg::Auto => Colorimetry::for_height(frame.height()),
+        ColorimetryArg::Rec601 => Colorimetry::Rec601,
+        ColorimetryArg::Rec709 => Colorimetry::Rec709,
+        ColorimetryArg::Rec2020 => Colorimetry::Rec2020,
+    };
+    let source = SourceColor {
+        colorimetry,
+        transfer,
+    };
+    let export = save_png(&frame, source, policy, &path)
+        .map_err(|e| Failure::new(EXIT_FAILED, e.to_string()))?;
+    writeln!(out, "Captured {:?} to {}", mode, path.display())?;
+    if let Some(operator) = export.tone_mapped {
+        writeln!(out, "Tone mapped with {:?}", operator)?;
+    }
+    Ok(0)
+}
+
+enum RecordEvent {
+    Frame(DecklinkVideoFrame, Option<DecklinkFrameTiming>),
+    FormatChanged(DecklinkDisplayModeId),
+}
+
+/// Queues frames for the recording thread.
+struct Recorder {
+    queue: FrameQueue<RecordEvent>,
+    dropped: AtomicU64,
+}
+
+impl InputHandler for Recorder {
+    fn format_changed(&self, change: &InputFormatChange) {
+        let _ = self
+            .queue
+            .push(RecordEvent::FormatChanged(change.display_mode));
+    }
+
+    fn frame_arrived(&self, arrival: &FrameArrival<'_>) -> CallbackResult {
+        if let Some(frame) = arrival.retain_video_frame() {
+            let event = RecordEvent::Frame(frame, arrival.timing());
+            if let PushResult::Rejected(_) = self.queue.push(event) {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        CallbackResult::Ok
+    }
+}
+
+fn record(
+    device: &str,
+    mode: &str,
+    limit: Limit,
+    segment_frames: u64,
+    container: Container,
+    dir: PathBuf,
+    out: &mut dyn Write,
+) -> Result<u8, Failure> {
+    let device = find_device(device)?;
+    let mut input = input_of(&device)?;
+    let (mode, detect) = choose_mode(&input, &device, mode)?;
+    let display_mode = input
+        .display_modes()?
+        .into_iter()
+        .find(|m| m.mode() == mode)
+        .ok_or_else(|| Failure::new(EXIT_FAILED, "the display mode is no longer available"))?;
+
+    std::fs::create_dir_all(&dir)?;
+    let policy = SegmentPolicy::EveryFrames(segment_frames);
+    // Without a frame duration, assume 30 fps, which only affects the continuity checks
+    let frame_rate = display_mode.frame_duration().map_or(30, frame_rate_of);
+
+    let recorder = Arc::new(Recorder {
+        queue: FrameQueue::new(16, OverflowPolicy::RejectNewest),
+        dropped: AtomicU64::new(0),
+    });
+    input.set_handler(Some(recorder.clone()))?;
+    enable_input(&mut input, mode, DecklinkPixelFormat::Format8BitYUV, detect)?;
+
+    let driver_version = api_version().ok();
+    let mut effective_config = input.effective_config();
+    effective_config.device = Some(DeviceIdentity::of(&device));
+    effective_config.driver_version = driver_version.clone();
+    let writer: Box<dyn SegmentSink> = match container {
+        Container::Raw => Box::new(SegmentedWriter::new(
+            dir.join("segment_{index}.raw").to_string_lossy(),
+            policy,
+        )),
+        Container::Mov => {
+            let config = MovConfig::for_mode(&display_mode).ok_or_else(|| {
+                Failure::new(EXIT_UNSUPPORTED, "the display mode has no frame duration")
+            })?;
+            Box::new(SegmentedMovWriter::new(
+                dir.join("segment_{index}.mov").to_string_lossy(),
+                policy,
+                config,
+            ))
+        }
+        Container::Dump => {
+            let mut header = DumpHeader::new(
+                mode,
+                DecklinkPixelFormat::Format8BitYUV,
+                display_mode.width(),
+                display_mode.height(),
+            )
+            .effective_config(&effective_config);
+            header.frame_duration = display_mode.frame_duration();
+            Box::new(SegmentedDumpWriter::new(
+                dir.join("segment_{index}.dump").to_string_lossy(),
+                policy,
+                header,
+            ))
+        }
+    };
+    let manifest = CaptureManifest::new(CaptureSetup {
+        device_name: device.display_name(),
+        driver_version,
+        display_mode: mode,
+        pixel_format: DecklinkPixelFormat::Format8BitYUV,
+        width: display_mode.width(),
+        height: display_mode.height(),
+        frame_duration: display_mode.frame_duration(),
+        effective_config: Some(effective_config),
+    });
+
+    let mut recording = Recording {
+        writer,
+        manifest,
+        timecode: TimecodeTracker::new(TimecodeTrackerConfig::new(frame_rate)),
+        mode,
+        detect,
+        frames: 0,
+        dropped: 0,
+        has_signal: true,
+    };
+    let (mut input, capture) = match limit {
+        Limit::Frames(frames) => {
+            let (input, capture) = record_frames(input, frames, &recorder, &mut recording)?;
+            (input, Some(capture))
+        }
+        Limit::Duration(length) => (record_for(input, length, &recorder, &mut recording)?, None),
+    };
+    input.disable_video_input()?;
+
+    let segments = recording.writer.finish()?;
+    if let Some(last) = segments.last() {
+        recording
+            .manifest
+            .record_event(ManifestEvent::Segment(last.clone()))?;
+    }
+    let sources = recording.timecode.stats();
+    recording.manifest.set_timecode_sources(sources);
+    recording.manifest.finish(&dir.join("manifest.json"))?;
+    writeln!(
+        out,
+        "Recorded {} frames in {} segments to {}, {} dropped",
+        recording.frames,
+        segments.len(),
+        dir.display(),
+        recording.dropped
+    )?;
+    if let Some(short) = capture.and_then(|c| c.under_delivered) {
+        match short.missing_frames {
+            Some(missing) => writeln!(out, "Stopped {} frames short: {:?}", missing, short.reason)?,
+            None => writeln!(out, "Stopped short: {:?}", short.reason)?,
+        }
+    }
+    Ok(0)
+}
+
+/// Record for `length` of wall clock time, then the frames the driver still has buffered.
+fn record_for(
+    input: DecklinkInputDevice,
+    length: Duration,
+    recorder: &Recorder,
+    recording: &mut Recording,
+) -> Result<DecklinkInputDevice, Failure> {
+    input.start_streams()?;
+    let deadline = Instant::now() + length;
+    let mut stopped = false;
+    while !stopped {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            break;
+        }
+        match recorder.queue.pop_timeout(remaining) {
+            Ok(event) => stopped = recording.handle(event, recorder)?,
+            Err(PopError::Timeout) | Err(PopError::Closed) => break,
+        }
+    }
+
+    // Drain the frames still buffered by the driver on another thread, recording them as
+    // they arrive, so the recording ends on the last frame captured
+    let drain = std::thread::spawn(move || {
+        let report = input.stop_streams_drained(Duration::from_secs(1), None);
+        (input, report)
+    });
+    while !drain.is_finished() {
+        if let Ok(event) = recorder.queue.pop_timeout(Duration::from_millis(10)) {
+            if !stopped {
+                stopped = recording.handle(event, recorder)?;
+            }
+        }
+    }
+    let (input, report) = drain.join().expect("the drain thread panicked");
+    report?;
+    while let Ok(event) = recorder.queue.pop_timeout(Duration::ZERO) {
+        if !stopped {
+            stopped = recording.handle(event, recorder)?;
+        }
+    }
+    Ok(input)
+}
+
+/// Record exactly `frames` frames, unless the signal stops first or the recording stops on a
+/// format change.
+fn record_frames(
+    mut input: DecklinkInputDevice,
+    frames: u64,
+    recorder: &Arc<Recorder>,
+    recording: &mut Recording,
+) -> Result<(DecklinkInputDevice, ExactCapture), Failure> {
+    // Frames without a signal are recorded, and noted in the manifest, as for a timed recording
+    let cancel = CancellationToken::new();
+    let session = {
+        let handler = recorder.clone();
+        let cancel = cancel.clone();
+        std::thread::spawn(move || {
+            let capture = CaptureSession::new(&mut input, handler)
+                .with_stop_on_signal_loss(false)
+                .with_cancel(cancel)
+                .capture_exact(Limit::Frames(frames));
+            (input, capture)
+        })
+    };
+    let mut stopped = false;
+    while !session.is_finished() {
+        if let Ok(event) = recorder.queue.pop_timeout(Duration::from_millis(10)) {
+            if !stopped {
+                stopped = recording.handle(event, recorder)?;
+                if stopped {
+                    cancel.cancel();
+                }
+            }
+        }
+    }
+    let (input, capture) = session.join().expect("the capture thread panicked");
+    let capture = capture?;
+    while let Ok(event) = recorder.queue.pop_timeout(Duration::ZERO) {
+        if !stopped {
+            stopped = recording.handle(event, recorder)?;
+        }
+    }
+    Ok((input, capture))
+}
+
+/// The state of a recording, updated from the events queued by `Recorder`.
+struct Recording {
+    writer: Box<dyn SegmentSink>,
+    manifest: CaptureManifest,
+    timecode: TimecodeTracker,
+    mode: DecklinkDisplayModeId,
+    detect: bool,
+    frames: u64,
+    dropped: u64,
+    has_signal: bool,
+}
+
+impl Recording {
+    /// Record an event, returning whether the recording must stop.
+    fn handle(&mut self, event: RecordEvent, recorder: &Recorder) -> std::io::Result<bool> {
+        let dropped = recorder.dropped.load(Ordering::Relaxed);
+        if dropped > self.dropped {
+            self.manifest.record_event(ManifestEvent::Dropped {
+                first_frame: self.frames,
+                count: dropped - self.dropped,
+            })?;
+            self.dropped = dropped;
+        }
+
+        match event {
+            RecordEvent::Frame(frame, timing) => {
+                let signal = !frame
+                    .flags()
+                    .contains(DecklinkFrameFlags::HAS_NO_INPUT_SOURCE);
+                if signal != self.has_signal {
+                    self.has_signal = signal;
+                    self.manifest.record_event(if signal {
+                        ManifestEvent::SignalRestored { frame: self.frames }
+                    } else {
+                        ManifestEvent::SignalLost { frame: self.frames }
+                    })?;
+                }
+                self.timecode.observe_frame(&frame);
+                let best = self.timecode.best();
+                self.writer.set_timecode(best);
+                if let Some(finished) = self.writer.write_frame(&frame, timing.as_ref())? {
+                    self.manifest
+                        .record_event(ManifestEvent::Segment(finished))?;
+                }
+                let timecode = best.map(|(_, tc)| tc.to_string());
+                self.manifest.record_frame(timecode)?;
+                self.frames += 1;
+                Ok(false)
+            }
+            RecordEvent::FormatChanged(new_mode) => {
+                // The capture keeps its format, so frames after a change are not recorded
+                // in the new one. Note it and stop, rather than writing mismatched frames.
+                self.manifest.record_event(ManifestEvent::FormatChanged {
+                    frame: self.frames,
+                    display_mode: new_mode,
+                })?;
+                self.writer.format_changed();
+                if self.detect && new_mode != self.mode {
+                    eprintln!(
+                        "The input changed to {:?}, stopping the recording",
+                        new_mode
+                    );
+                    return Ok(true);
+                }
+                Ok(false)
+            }
+        }
+    }
+}
+
+fn play(device: &str, file: &Path, out: &mut dyn Write) -> Result<u8, Failure> {
+    let mut reader = DumpReader::open(file)?;
+    let device = find_device(device)?;
+    let output = device
+        .output()
+        .ok_or_else(|| Failure::new(EXIT_UNSUPPORTED, "the device has no output"))?;
+    let header = reader.header().clone();
+    output.enable_video_output(header.display_mode, DecklinkVideoOutputFlags::empty())?;
+
+    // Frames are displayed as they are given, so they are paced here
+    let pace = header
+        .frame_duration
+        .filter(|d| d.scale > 0 && d.value > 0)
+        .map(|d| Duration::from_secs_f64(d.value as f64 / d.scale as f64));
+    let start = Instant::now();
+    let mut played = 0;
+    for frame in reader.by_ref() {
+        let frame = match frame {
+            Ok(frame) => frame,
+            Err(DumpError::Damaged(_)) => break,
+            Err(e) => return Err(e.into()),
+        };
+        let mut video = output.create_video_frame(
+            frame.width,
+            frame.height,
+            frame.row_bytes,
+            frame.pixel_format,
+            frame.flags,
+        )?;
+        let bytes = video.bytes_mut()?;
+        let length = bytes.len().min(frame.payload.len());
+        bytes[..length].copy_from_slice(&frame.payload[..length]);
+
+        if let Some(pace) = pace {
+            let due = start + pace * played;
+            std::thread::sleep(due.saturating_duration_since(Instant::now()));
+        }
+        output.display_video_frame(&video)?;
+        played += 1;
+    }
+    output.disable_video_output()?;
+
+    writeln!(out, "Played {} frames from {}", played, file.display())?;
+    if let Some(damage) = reader.damage() {
+        writeln!(out, "{}", DumpError::Damaged(damage))?;
+        return Ok(EXIT_FAILED);
+    }
+    Ok(0)
+}
+
+fn inspect(file: &Path, json: bool, output: &mut dyn Write) -> Result<u8, Failure> {
+    let mut reader = DumpReader::open(file)?;
+    // Read every frame, so damage the index does not show is found
+    let mut first: Option<DumpFrame> = None;
+    let mut last: Option<DumpFrame> = None;
+    let mut frames = 0;
+    for frame in reader.by_ref() {
+        let frame = match frame {
+            Ok(frame) => frame,
+            Err(DumpError::Damaged(_)) => break,
+            Err(e) => return Err(e.into()),
+        };
+        frames += 1;
+        if first.is_none() {
+            first = Some(frame);
+        } else {
+            last = Some(frame);
+        }
+    }
+    let last = last.as_ref().or(first.as_ref());
+    let header = reader.header();
+    let damage = reader.damage();
+
+    if json {
+        let mut out = String::new();
+        let _ = write!(
+            out,
+            "{{\"version\": {}, \"display_mode\": \"{:?}\", \"pixel_format\": \"{:?}\", \
+             \"width\": {}, \"height\": {}, \"row_bytes\": {}, \"frame_duration\": ",
+            reader.version(),
+            header.display_mode,
+            header.pixel_format,
+            header.width,
+            header.height,
+            header.row_bytes
+        );
+        match header.frame_duration {
+            Some(d) => {
+                let _ = write!(out, "{{\"value\": {}, \"scale\": {}}}", d.value, d.scale);
+            }
+            None => out.push_str("null"),
+        }
+        let _ = write!(
+            out,
+            ", \"checksums\": {}, \"frames\": {}, \"first_sequence\": {}, \
+             \"last_sequence\": {}, \"damage\": ",
+            header.checksums,
+            frames,
+            first
+                .as_ref()
+                .map_or("null".to_string(), |f| f.sequence.to_string()),
+            last.map_or("null".to_string(), |f| f.sequence.to_string()),
+        );
+        match damage {
+            Some(d) => {
+                let _ = write!(
+                    out,
+                    "{{\"intact_frames\": {}, \"offset\": {}, \"reason\": \"{:?}\"}}",
+                    d.intact_frames, d.offset, d.reason
+                );
+            }
+            None => out.push_str("null"),
+        }
+        out.push_str(", \"config\": ");
+        if header.config.is_empty() {
+            out.push_str("null");
+        } else {
+            out.push_str(&header.config);
+        }
+        out.push('}');
+        writeln!(output, "{}", out)?;
+    } else {
+        writeln!(output, "Frame dump version {}", reader.version())?;
+        writeln!(
+            output,
+            "{:?} in {:?}, {}x{}, {} bytes per row",
+            header.display_mode, header.pixel_format, header.width, header.height, header.row_bytes
+        )?;
+        if let Some(d) = header.frame_duration {
+            writeln!(output, "Frame duration: {}/{} s", d.value, d.scale)?;
+        }
+        match (first.as_ref(), last) {
+            (Some(first), Some(last)) => writeln!(
+                output,
+                "{} frames, sequence {} to {}",
+                frames, first.sequence, last.sequence
+            )?,
+            _ => writeln!(output, "No frames")?,
+        }
+        let times = first
+            .as_ref()
+            .and_then(|f| f.timing)
+            .zip(last.and_then(|f| f.timing));
+        if let Some((first, last)) = times {
+            writeln!(
+                output,
+                "Stream time {} to {}, in ticks of 1/{} s",
+                first.stream_time.value, last.stream_time.value, first.stream_time.scale
+            )?;
+        }
+        writeln!(
+            output,
+            "Checksums: {}",
+            if header.checksums { "yes" } else { "no" }
+        )?;
+        match damage {
+            Some(damage) => writeln!(output, "{}", DumpError::Damaged(damage))?,
+            None => writeln!(output, "No damage found")?,
+        }
+    }
+    Ok(if damage.is_some() { EXIT_FAILED } else { 0 })
+}
+
+fn config(action: ConfigAction, device: &str) -> Result<u8, Failure> {
+    // Find the device anyway, so a missing device is reported as such
+    find_device(device)?;
+    let action = match action {
+        ConfigAction::Backup => "backed up",
+        ConfigAction::Restore => "restored",
+        ConfigAction::Diff => "compared",
+    };
+    Err(Failure::new(
+        EXIT_UNSUPPORTED,
+        format!(
+            "device configuration cannot be {}, as it is not exposed by the Decklink C bindings",
+            action
+        ),
+    ))
+}
+
+fn scan(
+    device: &str,
+    timeout: Duration,
+    json: bool,
+    output: &mut dyn Write,
+) -> Result<u8, Failure> {
+    let device = find_device(device)?;
+    let mut input = input_of(&device)?;
+    let connections = device.get_attributes()?.video_input_connections()?;
+
+    // The active connection cannot be changed from here, so only the one in use is scanned
+    let (mode, detect) = choose_mode(&input, &device, "auto")?;
+    let follower = Arc::new(FormatFollower::new(mode));
+    input.set_handler(Some(follower.clone()))?;
+    enable_input(&mut input, mode, DecklinkPixelFormat::Format8BitYUV, detect)?;
+    input.start_streams()?;
+    let options = FirstFrameOptions {
+        timeout,
+        frames_to_skip_after_format_change: 2,
+        ..Default::default()
+    };
+    let signal = input.wait_first_frame(options, None).is_ok();
+    let detected = follower
+        .detected
+        .lock()
+        .unwrap()
+        .take()
+        .or(signal.then_some(mode));
+    input.stop_streams()?;
+    // Read before disabling, which forgets it
+    let vpid = input.vpid().ok().flatten().filter(|_| signal);
+    input.disable_video_input()?;
+    follower.report_quirks();
+
+    if json {
+        let mut out = String::new();
+        out.push_str("{\"connections\": ");
+        json_string(&mut out, &format!("{:?}", connections));
+        let _ = write!(out, ", \"signal\": {}, \"detected_mode\": ", signal);
+        json_opt_string(&mut out, detected.map(|m| format!("{:?}", m)).as_deref());
+        out.push_str(", \"vpid\": ");
+        match vpid {
+            Some(vpid) => {
+                let [byte1, byte2, byte3, byte4] = vpid.bytes;
+                let _ = write!(
+                    out,
+                    "{{\"bytes\": \"{:02X} {:02X} {:02X} {:02X}\", \"description\": ",
+                    byte1, byte2, byte3, byte4
+                );
+                json_string(&mut out, &vpid.describe());
+                out.push('}');
+            }
+            None => out.push_str("null"),
+        }
+        out.push('}');
+        writeln!(output, "{}", out)?;
+    } else {
+        writeln!(output, "Input connections: {:?}", connections)?;
+        match detected {
+            Some(mode) if signal => writeln!(output, "Signal detected: {:?}", mode)?,
+            _ => writeln!(output, "No signal detected")?,
+        }
+        match vpid {
+            Some(vpid) => writeln!(output, "VPID: {}", vpid)?,
+            None if signal => writeln!(output, "No VPID in the signal")?,
+            None => {}
+        }
+    }
+    Ok(0)
+}
+
+fn soak(device: Option<&str>, config: &SoakConfig, out: &mut dyn Write) -> Result<u8, Failure> {
+    let soak_failure = |e: SoakError| Failure::new(EXIT_FAILED, e.to_string());
+    let summary = match device {
+        Some(device) => soak::run_device(&find_device(device)?, config).map_err(soak_failure)?,
+        #[cfg(feature = "mock-backend")]
+        None => soak::run_mock(config).map_err(soak_failure)?,
+        #[cfg(not(feature = "mock-backend"))]
+        None => {
+            return Err(Failure::new(
+                EXIT_UNSUPPORTED,
+                "soaking without a device needs the mock-backend feature",
+            ))
+        }
+    };
+    writeln!(out, "{}", summary.to_json())?;
+    if let Some(failure) = &summary.failure {
+        return Err(Failure::new(
+            EXIT_FAILED,
+            format!("the soak run failed at {}", failure),
+        ));
+    }
+    Ok(0)
+}
+
+fn scaffold(dir: &Path, options: &ScaffoldOptions, out: &mut dyn Write) -> Result<u8, Failure> {
+    let written =
+        write_project(dir, options).map_err(|e| Failure::new(EXIT_FAILED, e.to_string()))?;
+    for path in written {
+        writeln!(out, "{}", path.display())?;
+    }
+    Ok(0)
+}
+
+fn report(output: &mut dyn Write) -> Result<u8, Failure> {
+    let devices = DeviceRegistry::new()?.devices();
+    let mut out = String::new();
+    let capabilities = capabilities();
+    out.push_str("{\"driver_version\": ");
+    json_opt_string(&mut out, api_version().ok().as_deref());
+    let _ = write!(
+        out,
+        ", \"capabilities\": {{\"allocator_provider\": {}, \"profiles\": {}}}",
+        capabilities.allocator_provider, capabilities.profiles
+    );
+    out.push_str(", \"devices\": [");
+    for (i, device) in devices.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        let info = device.hardware_info();
+        let health = device.health().unwrap_or_default();
+        let capability = device.concurrent_capability().ok();
+        let persistent_id = device.persistent_id();
+
+        out.push_str("\n  {\"display_name\": ");
+        json_opt_string(&mut out, device.display_name().as_deref());
+        out.push_str(", \"model_name\": ");
+        json_opt_string(&mut out, info.model_name.as_deref());
+        out.push_str(", \"persistent_id\": ");
+        json_opt(&mut out, persistent_id);
+        out.push_str(", \"hardware_revision\": ");
+        json_opt_string(&mut out, info.hardware_revision.as_deref());
+        out.push_str(", \"interface\": ");
+        json_opt_string(
+            &mut out,
+            info.device_interface.map(|i| format!("{:?}", i)).as_deref(),
+        );
+        out.push_str(", \"pcie_link\": ");
+        match info.pcie_link {
+            Some(link) => {
+                let _ = write!(out, "{{\"gen\": {}, \"width\": {}}}", link.gen, link.width);
+            }
+            None => out.push_str("null"),
+        }
+        out.push_str(", \"temperature_c\": ");
+        json_opt(&mut out, health.temperature_c);
+        out.push_str(", \"concurrent_capture_and_playback\": ");
+        json_opt(
+            &mut out,
+            capability.map(|c| c.can_capture_and_playback_simultaneously),
+        );
+        out.push('}');
+    }
+    out.push_str("\n]}");
+    writeln!(output, "{}", out)?;
+    Ok(0)
+}
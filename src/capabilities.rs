@@ -0,0 +1,102 @@
+//! Which optional parts of the SDK the installed drivers provide.
+//!
+//! The C wrapper loads the drivers when it is first used, so a part of the api that older
+//! drivers do not implement does not fail to link. Calls into it fail with
+//! `SdkError::NOINTERFACE` instead. `capabilities` tells an application up front which of the
+//! parts used by this crate are available, so it can choose its code paths before starting.
+
+use crate::device::input::CallbackReturnHandling;
+use crate::{api_version_number, ApiVersion};
+
+/// The driver release that introduced the video buffer allocator provider used by
+/// `crate::allocator`.
+pub(crate) const ALLOCATOR_API_VERSION: ApiVersion = ApiVersion::new(14, 3, 0);
+
+/// The driver release that introduced device profiles and the profile attributes used by
+/// `DecklinkDevice::get_attributes`.
+pub(crate) const PROFILES_API_VERSION: ApiVersion = ApiVersion::new(11, 0, 0);
+
+/// The driver releases whose handling of frame-arrived results is known, as the first release
+/// of each range, the first release after it, and the handling. See
+/// `LibraryCapabilities::callback_returns` for where each finding comes from.
+const CALLBACK_RETURN_FINDINGS: [(ApiVersion, ApiVersion, CallbackReturnHandling); 1] = [(
+    ApiVersion::new(10, 0, 0),
+    ApiVersion::new(15, 0, 0),
+    CallbackReturnHandling::Unknown,
+)];
+
+/// The handling of drivers with api version `version`, by the findings.
+fn callback_return_handling(version: ApiVersion) -> CallbackReturnHandling {
+    CALLBACK_RETURN_FINDINGS
+        .iter()
+        .find(|(first, after, _)| *first <= version && version < *after)
+        .map_or(CallbackReturnHandling::Unknown, |(_, _, handling)| {
+            *handling
+        })
+}
+
+/// The optional parts of the SDK that the installed drivers provide.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash, Default)]
+pub struct LibraryCapabilities {
+    /// The api version of the drivers, or `None` if none are installed.
+    pub driver_version: Option<ApiVersion>,
+    /// Custom video buffer allocators, for
+    /// `DecklinkInputDevice::enable_video_input_with_allocator`.
+    pub allocator_provider: bool,
+    /// Device profiles, and the profile attributes that all device attributes are read through.
+    pub profiles: bool,
+    /// What the drivers do with the result of a frame-arrived callback, which decides whether
+    /// `crate::device::input::CallbackReturnPolicy::PropagateConsumerPressure` passes consumer
+    /// pressure on.
+    ///
+    /// | Drivers | Handling | Found by |
+    /// |---|---|---|
+    /// | before 10.0 | `Unknown` | not yet checked |
+    /// | 10.0 to 14.x | `Unknown` | not yet observed: the SDK manual lists `S_OK` and `E_FAIL` as the results of `VideoInputFrameArrived` without giving either an effect on capture, which does not show that there is none |
+    /// | 15.0 and later | `Unknown` | not yet checked |
+    ///
+    /// The loopback tests under `tests/hardware` record what a card does, in the
+    /// `callback_returns` cases of their report: how many frames arrived, and how many went
+    /// missing, while every callback returned `S_OK`, `S_FALSE` and `E_FAIL`. A report from
+    /// other drivers is how a row is added or moved. Until a report shows drivers ignoring
+    /// results, no row is `Ignored`.
+    pub callback_returns: CallbackReturnHandling,
+}
+
+impl LibraryCapabilities {
+    /// The capabilities of drivers with api version `version`.
+    pub fn for_version(version: ApiVersion) -> LibraryCapabilities {
+        LibraryCapabilities {
+            driver_version: Some(version),
+            allocator_provider: version >= ALLOCATOR_API_VERSION,
+            profiles: version >= PROFILES_API_VERSION,
+            callback_returns: callback_return_handling(version),
+        }
+    }
+
+    pub fn drivers_installed(&self) -> bool {
+        self.driver_version.is_some()
+    }
+}
+
+/// Find which optional parts of the SDK the installed drivers provide. If no drivers are
+/// installed, nothing is available.
+pub fn capabilities() -> LibraryCapabilities {
+    match api_version_number() {
+        Ok(version) => LibraryCapabilities {
+            callback_returns: installed_callback_returns(version),
+            ..LibraryCapabilities::for_version(version)
+        },
+        Err(_) => LibraryCapabilities::default(),
+    }
+}
+
+/// The handling of frame-arrived results by the installed drivers of version `version`, or the
+/// handling the mock backend was set to report.
+fn installed_callback_returns(version: ApiVersion) -> CallbackReturnHandling {
+    #[cfg(feature = "mock-backend")]
+    if let Some(handling) = crate::mock::callback_returns() {
+        return handling;
+    }
+    callback_return_handling(version)
+}
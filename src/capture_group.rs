@@ -0,0 +1,685 @@
+//! Capturing from several inputs at once, with their frames grouped by time.
+//!
+//! A `CaptureGroup` runs one input per member, and a `GroupFrameAligner` groups the frames
+//! of the members into an `AlignedFrameSet` per tick, a frame period of the shared stream
+//! clock. Members can be added and removed while the others keep capturing. Each member
+//! keeps its slot in the frame vector of every set for the life of the group, so indices
+//! never shift: the slot of a departed member is `None` from the tick it left, and a new
+//! member's slot is `None` until it has been in the group for
+//! `CaptureGroupConfig::warm_up_ticks` ticks, as the first frames after an input starts
+//! may still be settling.
+//!
+//! Members joined with `InputSpec::synchronize` are enabled with
+//! `DecklinkVideoInputFlags::SYNCHRONIZE_TO_CAPTURE_GROUP`, so the driver aligns their stream
+//! times. The capture group id itself is a device configuration setting, which this crate
+//! does not wrap, so members join the group set for them in Desktop Video Setup. Without
+//! synchronization, frames are grouped by their own stream times, which only line up if the
+//! inputs were started together from a common reference.
+
+use crate::config::{ConfigError, Unset};
+use crate::device::input::{
+    CallbackResult, DecklinkInputDevice, DecklinkVideoInputFlags, FrameArrival, InputFormatChange,
+    InputHandler,
+};
+use crate::device::DecklinkDevice;
+use crate::display_mode::DecklinkDisplayModeId;
+use crate::frame::{DecklinkPixelFormat, DecklinkVideoFrame};
+use crate::manifest::ManifestEvent;
+use crate::queue::{FrameQueue, OverflowPolicy, PopError};
+use crate::time::{DecklinkFrameTiming, DecklinkTime};
+use crate::SdkError;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Marks a member callback whose member has not been added to the aligner yet.
+const NO_MEMBER: u32 = u32::MAX;
+
+/// A member of a capture group, and the index of its slot in `AlignedFrameSet::frames`.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MemberId(u32);
+
+impl MemberId {
+    pub fn index(&self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// The frames of every member at one tick.
+#[derive(Debug)]
+pub struct AlignedFrameSet<F = DecklinkVideoFrame> {
+    pub tick: i64,
+    /// One entry per member ever added, indexed by `MemberId::index`. `None` for a member
+    /// that is not in the group at this tick, is still warming up, or missed the tick.
+    pub frames: Vec<Option<F>>,
+    /// The members expected at this tick that had no frame for it.
+    pub missing: Vec<MemberId>,
+}
+
+struct Slot {
+    /// The first tick this member's frames are placed at.
+    first_tick: i64,
+    /// The tick the member left at, after which its frames are not expected.
+    left_at: Option<i64>,
+}
+
+impl Slot {
+    fn expected(&self, tick: i64) -> bool {
+        tick >= self.first_tick && self.left_at.is_none_or(|left| tick < left)
+    }
+}
+
+/// Groups the frames of several members by tick.
+///
+/// Sets are produced for consecutive ticks, each exactly once. A tick is given out once
+/// every member expected at it has a frame for it, or once `max_pending_ticks` later ticks
+/// have been seen, with the members that had no frame listed as missing. Frames for a tick
+/// that has been given out, or for a member not expected at it, are discarded.
+pub struct GroupFrameAligner<F = DecklinkVideoFrame> {
+    warm_up_ticks: i64,
+    max_pending_ticks: i64,
+    slots: Vec<Slot>,
+    pending: BTreeMap<i64, Vec<Option<F>>>,
+    /// The next tick to give out, from the first frame on.
+    next_tick: Option<i64>,
+    latest_tick: Option<i64>,
+    discarded: u64,
+}
+
+impl<F> GroupFrameAligner<F> {
+    pub fn new(warm_up_ticks: u32, max_pending_ticks: u32) -> GroupFrameAligner<F> {
+        GroupFrameAligner {
+            warm_up_ticks: warm_up_ticks as i64,
+            max_pending_ticks: max_pending_ticks as i64,
+            slots: Vec::new(),
+            pending: BTreeMap::new(),
+            next_tick: None,
+            latest_tick: None,
+            discarded: 0,
+        }
+    }
+
+    /// The tick after the latest one seen, where a membership change made now takes effect.
+    /// `None` before the first frame, when a change applies from the start.
+    pub fn change_tick(&self) -> Option<i64> {
+        self.latest_tick.map(|tick| tick + 1)
+    }
+
+    /// Add a member, whose frames are placed from `warm_up_ticks` after `at_tick`, or from
+    /// the start if `at_tick` is `None`. Returns the member and its first aligned tick.
+    pub fn add_member(&mut self, at_tick: Option<i64>) -> (MemberId, Option<i64>) {
+        let first_tick = at_tick.map(|tick| tick + self.warm_up_ticks);
+        let id = MemberId(self.slots.len() as u32);
+        self.slots.push(Slot {
+            first_tick: first_tick.unwrap_or(i64::MIN),
+            left_at: None,
+        });
+        for frames in self.pending.values_mut() {
+            frames.push(None);
+        }
+        (id, first_tick)
+    }
+
+    /// Remove a member from `at_tick` on, or from the start if `at_tick` is `None`. Sets
+    /// for the ticks before it still include its frames.
+    pub fn remove_member(&mut self, member: MemberId, at_tick: Option<i64>) {
+        if let Some(slot) = self.slots.get_mut(member.index()) {
+            let at_tick = at_tick.unwrap_or(i64::MIN);
+            slot.left_at = Some(slot.left_at.map_or(at_tick, |left| left.min(at_tick)));
+            for (_, frames) in self.pending.range_mut(at_tick..) {
+                frames[member.index()] = None;
+            }
+        }
+    }
+
+    /// The number of frames discarded, as late, duplicated or from a member not expected
+    /// at their tick.
+    pub fn discarded_count(&self) -> u64 {
+        self.discarded
+    }
+
+    fn complete(&self, tick: i64) -> bool {
+        let frames = self.pending.get(&tick);
+        self.slots.iter().enumerate().all(|(i, slot)| {
+            !slot.expected(tick) || frames.is_some_and(|frames| frames[i].is_some())
+        })
+    }
+
+    /// Place a member's frame at `tick`, returning the sets that are now complete.
+    pub fn push(&mut self, member: MemberId, tick: i64, frame: F) -> Vec<AlignedFrameSet<F>> {
+        let expected = self
+            .slots
+            .get(member.index())
+            .is_some_and(|slot| slot.expected(tick));
+        let late = self.next_tick.is_some_and(|next| tick < next);
+        if !expected || late {
+            self.discarded += 1;
+            return Vec::new();
+        }
+
+        let slot_count = self.slots.len();
+        let frames = self
+            .pending
+            .entry(tick)
+            .or_insert_with(|| (0..slot_count).map(|_| None).collect());
+        if frames[member.index()].is_some() {
+            self.discarded += 1;
+        }
+        frames[member.index()] = Some(frame);
+        self.next_tick.get_or_insert(tick);
+        self.latest_tick = Some(self.latest_tick.map_or(tick, |latest| latest.max(tick)));
+
+        let mut ready = Vec::new();
+        while let (Some(next), Some(latest)) = (self.next_tick, self.latest_tick) {
+            if next > latest || !(self.complete(next) || latest - next >= self.max_pending_ticks) {
+                break;
+            }
+            ready.push(self.take(next));
+            self.next_tick = Some(next + 1);
+        }
+        ready
+    }
+
+    fn take(&mut self, tick: i64) -> AlignedFrameSet<F> {
+        let slot_count = self.slots.len();
+        let frames = self
+            .pending
+            .remove(&tick)
+            .unwrap_or_else(|| (0..slot_count).map(|_| None).collect());
+        let missing = self
+            .slots
+            .iter()
+            .enumerate()
+            .filter(|(i, slot)| slot.expected(tick) && frames[*i].is_none())
+            .map(|(i, _)| MemberId(i as u32))
+            .collect();
+        AlignedFrameSet {
+            tick,
+            frames,
+            missing,
+        }
+    }
+}
+
+/// The tick of a frame: its stream time in whole frame periods of `frame_duration`,
+/// rounded to the nearest.
+pub fn tick_of(timing: &DecklinkFrameTiming, frame_duration: DecklinkTime) -> Option<i64> {
+    if frame_duration.value <= 0 {
+        return None;
+    }
+    let time = timing.stream_time.rescale(frame_duration.scale)?;
+    Some((time.value + frame_duration.value / 2).div_euclid(frame_duration.value))
+}
+
+/// An input to capture as a member of a group.
+pub struct InputSpec {
+    pub device: DecklinkDevice,
+    pub mode: DecklinkDisplayModeId,
+    pub pixel_format: DecklinkPixelFormat,
+    pub flags: DecklinkVideoInputFlags,
+    /// Enable the input with `DecklinkVideoInputFlags::SYNCHRONIZE_TO_CAPTURE_GROUP`.
+    pub synchronize: bool,
+}
+
+impl InputSpec {
+    /// Start building a spec. `build` can only be called once the device and the mode have
+    /// been set:
+    ///
+    /// ```compile_fail
+    /// # use decklink::capture_group::InputSpec;
+    /// # use decklink::display_mode::DecklinkDisplayModeId;
+    /// # use decklink::frame::DecklinkPixelFormat;
+    /// let spec = InputSpec::builder()
+    ///     .mode(DecklinkDisplayModeId::HD1080p25, DecklinkPixelFormat::Format10BitYUV)
+    ///     .build();
+    /// ```
+    pub fn builder() -> InputSpecBuilder<Unset, Unset> {
+        InputSpecBuilder {
+            device: Unset,
+            mode: Unset,
+            flags: DecklinkVideoInputFlags::empty(),
+            synchronize: false,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        validate_input_flags(self.flags, self.synchronize)
+    }
+}
+
+fn validate_input_flags(
+    flags: DecklinkVideoInputFlags,
+    synchronize: bool,
+) -> Result<(), ConfigError> {
+    if flags.contains(DecklinkVideoInputFlags::SYNCHRONIZE_TO_CAPTURE_GROUP) && !synchronize {
+        return Err(ConfigError::new(
+            "InputSpec",
+            "flags",
+            "include SYNCHRONIZE_TO_CAPTURE_GROUP, but synchronize is false; \
+             set synchronize instead of the flag",
+        ));
+    }
+    Ok(())
+}
+
+/// The display mode and pixel format set on an `InputSpecBuilder`.
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub struct InputMode {
+    pub mode: DecklinkDisplayModeId,
+    pub pixel_format: DecklinkPixelFormat,
+}
+
+/// Builds an `InputSpec`. `D` and `M` are `Unset` until the device and the mode are set.
+pub struct InputSpecBuilder<D, M> {
+    device: D,
+    mode: M,
+    flags: DecklinkVideoInputFlags,
+    synchronize: bool,
+}
+
+impl<D, M> InputSpecBuilder<D, M> {
+    pub fn device(self, device: DecklinkDevice) -> InputSpecBuilder<DecklinkDevice, M> {
+        InputSpecBuilder {
+            device,
+            mode: self.mode,
+            flags: self.flags,
+            synchronize: self.synchronize,
+        }
+    }
+
+    pub fn mode(
+        self,
+        mode: DecklinkDisplayModeId,
+        pixel_format: DecklinkPixelFormat,
+    ) -> InputSpecBuilder<D, InputMode> {
+        InputSpecBuilder {
+            device: self.device,
+            mode: InputMode { mode, pixel_format },
+            flags: self.flags,
+            synchronize: self.synchronize,
+        }
+    }
+
+    pub fn flags(mut self, flags: DecklinkVideoInputFlags) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    pub fn synchronize(mut self, synchronize: bool) -> Self {
+        self.synchronize = synchronize;
+        self
+    }
+}
+
+impl InputSpecBuilder<DecklinkDevice, InputMode> {
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        validate_input_flags(self.flags, self.synchronize)
+    }
+
+    pub fn build(self) -> Result<InputSpec, ConfigError> {
+        self.validate()?;
+        Ok(InputSpec {
+            device: self.device,
+            mode: self.mode.mode,
+            pixel_format: self.mode.pixel_format,
+            flags: self.flags,
+            synchronize: self.synchronize,
+        })
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub struct CaptureGroupConfig {
+    /// The frame duration of the display mode every member captures in, which sets the
+    /// length of a tick.
+    pub frame_duration: DecklinkTime,
+    /// The ticks a new member is in the group before its frames are placed.
+    pub warm_up_ticks: u32,
+    /// How many later ticks are waited for before a tick is given out without the frames
+    /// still missing.
+    pub max_pending_ticks: u32,
+    /// The number of sets held for the consumer. When full, the oldest is dropped.
+    pub queue_capacity: usize,
+}
+
+impl CaptureGroupConfig {
+    pub fn new(frame_duration: DecklinkTime) -> CaptureGroupConfig {
+        CaptureGroupConfig {
+            frame_duration,
+            warm_up_ticks: 3,
+            max_pending_ticks: 2,
+            queue_capacity: 8,
+        }
+    }
+
+    pub fn builder(frame_duration: DecklinkTime) -> CaptureGroupConfigBuilder {
+        CaptureGroupConfigBuilder {
+            config: CaptureGroupConfig::new(frame_duration),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let error = |field, problem| Err(ConfigError::new("CaptureGroupConfig", field, problem));
+        if self.frame_duration.value <= 0 || self.frame_duration.scale <= 0 {
+            return error(
+                "frame_duration",
+                "must be a positive duration in a positive timescale, such as \
+                 DecklinkDisplayMode::frame_duration gives",
+            );
+        }
+        if self.queue_capacity == 0 {
+            return error(
+                "queue_capacity",
+                "must be at least 1, or every set would be dropped",
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Builds a `CaptureGroupConfig`, starting from the defaults of `CaptureGroupConfig::new`.
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub struct CaptureGroupConfigBuilder {
+    config: CaptureGroupConfig,
+}
+
+impl CaptureGroupConfigBuilder {
+    pub fn warm_up_ticks(mut self, warm_up_ticks: u32) -> Self {
+        self.config.warm_up_ticks = warm_up_ticks;
+        self
+    }
+
+    pub fn max_pending_ticks(mut self, max_pending_ticks: u32) -> Self {
+        self.config.max_pending_ticks = max_pending_ticks;
+        self
+    }
+
+    pub fn queue_capacity(mut self, queue_capacity: usize) -> Self {
+        self.config.queue_capacity = queue_capacity;
+        self
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        self.config.validate()
+    }
+
+    pub fn build(self) -> Result<CaptureGroupConfig, ConfigError> {
+        self.validate()?;
+        Ok(self.config)
+    }
+}
+
+/// A notable change in a capture group.
+#[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum GroupEvent {
+    /// A member was added at `tick`, and its frames are placed from `first_tick` on. Both
+    /// are `None` for members added before the first frame.
+    MemberAdded {
+        member: MemberId,
+        tick: Option<i64>,
+        first_tick: Option<i64>,
+    },
+    /// A member was removed, and its frames are not placed from `tick` on.
+    MemberRemoved { member: MemberId, tick: Option<i64> },
+    /// A member could not be added. The members already in the group were not changed.
+    MemberAddFailed { error: SdkError },
+    /// A member's input reported a format change.
+    MemberFormatChanged {
+        member: MemberId,
+        display_mode: DecklinkDisplayModeId,
+    },
+    /// A member delivered a frame without timing, which cannot be placed.
+    UntimedFrame { member: MemberId },
+}
+
+impl GroupEvent {
+    /// The event to record in a capture manifest, for membership changes.
+    pub fn manifest_event(&self) -> Option<ManifestEvent> {
+        match self {
+            GroupEvent::MemberAdded { member, tick, .. } => Some(ManifestEvent::MemberAdded {
+                member: member.0,
+                tick: *tick,
+            }),
+            GroupEvent::MemberRemoved { member, tick } => Some(ManifestEvent::MemberRemoved {
+                member: member.0,
+                tick: *tick,
+            }),
+            _ => None,
+        }
+    }
+}
+
+struct GroupShared {
+    frame_duration: DecklinkTime,
+    aligner: Mutex<GroupFrameAligner>,
+    sets: FrameQueue<AlignedFrameSet>,
+    events: Mutex<Vec<GroupEvent>>,
+}
+
+/// The callback of one member, which places its frames in the aligner.
+struct MemberCallback {
+    shared: Arc<GroupShared>,
+    /// The member, or `NO_MEMBER` until it has been added to the aligner.
+    member: Arc<AtomicU32>,
+}
+
+impl MemberCallback {
+    fn member(&self) -> Option<MemberId> {
+        match self.member.load(Ordering::Acquire) {
+            NO_MEMBER => None,
+            id => Some(MemberId(id)),
+        }
+    }
+}
+
+impl InputHandler for MemberCallback {
+    fn format_changed(&self, change: &InputFormatChange) {
+        if let Some(member) = self.member() {
+            self.shared
+                .events
+                .lock()
+                .unwrap()
+                .push(GroupEvent::MemberFormatChanged {
+                    member,
+                    display_mode: change.display_mode,
+                });
+        }
+    }
+
+    fn frame_arrived(&self, arrival: &FrameArrival<'_>) -> CallbackResult {
+        // A lost frame's tick is given out without this member once later ticks arrive
+        let (Some(member), Some(frame)) = (self.member(), arrival.retain_video_frame()) else {
+            return CallbackResult::Ok;
+        };
+        let tick = match arrival
+            .timing()
+            .and_then(|t| tick_of(&t, self.shared.frame_duration))
+        {
+            Some(tick) => tick,
+            None => {
+                self.shared
+                    .events
+                    .lock()
+                    .unwrap()
+                    .push(GroupEvent::UntimedFrame { member });
+                return CallbackResult::Ok;
+            }
+        };
+        let ready = self
+            .shared
+            .aligner
+            .lock()
+            .unwrap()
+            .push(member, tick, frame);
+        for set in ready {
+            let _ = self.shared.sets.push(set);
+        }
+        CallbackResult::Ok
+    }
+}
+
+struct Member {
+    id: MemberId,
+    input: DecklinkInputDevice,
+    /// Kept for the life of the member, as the input belongs to it.
+    _device: DecklinkDevice,
+}
+
+/// Captures from a changing set of inputs, grouping their frames by tick.
+///
+/// Adding or removing a member starts or stops only that member's input. Members are
+/// stopped when the group is dropped.
+pub struct CaptureGroup {
+    shared: Arc<GroupShared>,
+    members: Vec<Member>,
+}
+
+impl CaptureGroup {
+    pub fn new(config: CaptureGroupConfig) -> CaptureGroup {
+        CaptureGroup {
+            shared: Arc::new(GroupShared {
+                frame_duration: config.frame_duration,
+                aligner: Mutex::new(GroupFrameAligner::new(
+                    config.warm_up_ticks,
+                    config.max_pending_ticks,
+                )),
+                sets: FrameQueue::new(config.queue_capacity, OverflowPolicy::DropOldest),
+                events: Mutex::new(Vec::new()),
+            }),
+            members: Vec::new(),
+        }
+    }
+
+    /// The members in the group.
+    pub fn members(&self) -> Vec<MemberId> {
+        self.members.iter().map(|m| m.id).collect()
+    }
+
+    fn start_member(
+        &self,
+        input: &mut DecklinkInputDevice,
+        spec: &InputSpec,
+        member: &Arc<AtomicU32>,
+    ) -> Result<(), SdkError> {
+        let mut flags = spec.flags;
+        if spec.synchronize {
+            flags |= DecklinkVideoInputFlags::SYNCHRONIZE_TO_CAPTURE_GROUP;
+        }
+        input.set_handler(Some(Arc::new(MemberCallback {
+            shared: self.shared.clone(),
+            member: member.clone(),
+        })))?;
+        input.enable_video_input(spec.mode, spec.pixel_format, flags)?;
+        input.start_streams()
+    }
+
+    /// Configure and start an input, and add it to the group while the other members keep
+    /// capturing. Its frames are placed from `CaptureGroupConfig::warm_up_ticks` after the
+    /// latest tick seen.
+    ///
+    /// If the input cannot be started it is disabled again, the members already in the
+    /// group are left as they were, and the error is also recorded as
+    /// `GroupEvent::MemberAddFailed`.
+    pub fn add_member(&mut self, spec: InputSpec) -> Result<MemberId, SdkError> {
+        let mut input = match spec.device.input() {
+            Some(input) => input,
+            None => return Err(self.add_failed(SdkError::NOINTERFACE)),
+        };
+        let member = Arc::new(AtomicU32::new(NO_MEMBER));
+        if let Err(error) = self.start_member(&mut input, &spec, &member) {
+            let _ = input.stop_streams();
+            let _ = input.disable_video_input();
+            let _ = input.set_handler(None);
+            return Err(self.add_failed(error));
+        }
+
+        // Added to the aligner only once started, so a failed member never has a slot
+        let (id, tick, first_tick) = {
+            let mut aligner = self.shared.aligner.lock().unwrap();
+            let tick = aligner.change_tick();
+            let (id, first_tick) = aligner.add_member(tick);
+            (id, tick, first_tick)
+        };
+        member.store(id.0, Ordering::Release);
+        self.members.push(Member {
+            id,
+            input,
+            _device: spec.device,
+        });
+        self.shared
+            .events
+            .lock()
+            .unwrap()
+            .push(GroupEvent::MemberAdded {
+                member: id,
+                tick,
+                first_tick,
+            });
+        Ok(id)
+    }
+
+    fn add_failed(&self, error: SdkError) -> SdkError {
+        self.shared
+            .events
+            .lock()
+            .unwrap()
+            .push(GroupEvent::MemberAddFailed {
+                error: SdkError::from(error.code()),
+            });
+        error
+    }
+
+    /// Stop a member's input and remove it from the group, while the other members keep
+    /// capturing. Its slot is `None` from the tick after the latest one seen.
+    pub fn remove_member(&mut self, member: MemberId) -> Result<(), SdkError> {
+        let position = self
+            .members
+            .iter()
+            .position(|m| m.id == member)
+            .ok_or(SdkError::INVALIDARG)?;
+        let mut removed = self.members.remove(position);
+
+        let tick = {
+            let mut aligner = self.shared.aligner.lock().unwrap();
+            let tick = aligner.change_tick();
+            aligner.remove_member(member, tick);
+            tick
+        };
+        self.shared
+            .events
+            .lock()
+            .unwrap()
+            .push(GroupEvent::MemberRemoved { member, tick });
+
+        let stopped = removed.input.stop_streams();
+        let disabled = removed.input.disable_video_input();
+        removed.input.set_handler(None)?;
+        stopped.and(disabled)
+    }
+
+    /// The next set of aligned frames, waiting up to `timeout`.
+    pub fn pop_set(&self, timeout: Duration) -> Result<AlignedFrameSet, PopError> {
+        self.shared.sets.pop_timeout(timeout)
+    }
+
+    /// The number of sets dropped as the consumer fell behind.
+    pub fn dropped_set_count(&self) -> u64 {
+        self.shared.sets.dropped_count()
+    }
+
+    /// The events since the last call.
+    pub fn take_events(&self) -> Vec<GroupEvent> {
+        std::mem::take(&mut *self.shared.events.lock().unwrap())
+    }
+}
+
+impl Drop for CaptureGroup {
+    fn drop(&mut self) {
+        for member in self.members.iter().map(|m| m.id).collect::<Vec<_>>() {
+            let _ = self.remove_member(member);
+        }
+    }
+}
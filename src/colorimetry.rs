@@ -0,0 +1,68 @@
+//! The colour matrices and transfer functions video is encoded with.
+//!
+//! These are part of the core of the crate, so that the optional integrations that convert
+//! frames, such as `crate::image_interop`, share one `Colorimetry` and `TransferFunction`
+//! type whichever of them are enabled.
+
+/// The colour matrix used to convert YUV into RGB.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+pub enum Colorimetry {
+    Rec601,
+    Rec709,
+    Rec2020,
+}
+
+impl Colorimetry {
+    /// The usual colorimetry for a frame height, Rec. 601 for standard definition and
+    /// Rec. 709 otherwise.
+    pub fn for_height(height: usize) -> Colorimetry {
+        if height <= 576 {
+            Colorimetry::Rec601
+        } else {
+            Colorimetry::Rec709
+        }
+    }
+
+    /// The red and blue luma coefficients, Kr and Kb.
+    pub fn coefficients(&self) -> (f32, f32) {
+        match self {
+            Colorimetry::Rec601 => (0.299, 0.114),
+            Colorimetry::Rec709 => (0.2126, 0.0722),
+            Colorimetry::Rec2020 => (0.2627, 0.0593),
+        }
+    }
+
+    /// The colour primaries and matrix coefficients, as numbered in ITU-T H.273, of a frame
+    /// `height` lines high. Rec. 601 has the primaries of 625 line systems at 576 lines.
+    pub fn h273(&self, height: usize) -> (u8, u8) {
+        match self {
+            Colorimetry::Rec601 if height == 576 => (5, 6),
+            Colorimetry::Rec601 => (6, 6),
+            Colorimetry::Rec709 => (1, 1),
+            Colorimetry::Rec2020 => (9, 9),
+        }
+    }
+}
+
+/// The transfer function that light is encoded into signal with.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash, Default)]
+pub enum TransferFunction {
+    /// Standard dynamic range, BT.709 encoded for a BT.1886 display.
+    #[default]
+    Sdr,
+    /// The perceptual quantizer of SMPTE ST 2084 and BT.2100.
+    Pq,
+    /// Hybrid log-gamma, as BT.2100.
+    Hlg,
+}
+
+impl TransferFunction {
+    /// The transfer characteristics, as numbered in ITU-T H.273.
+    pub fn h273(&self) -> u8 {
+        match self {
+            TransferFunction::Sdr => 1,
+            TransferFunction::Pq => 16,
+            TransferFunction::Hlg => 18,
+        }
+    }
+}
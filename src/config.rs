@@ -0,0 +1,51 @@
+//! Checking configurations before they are used.
+//!
+//! The configs with several fields have builders, such as `DispatchConfig::builder()`,
+//! whose `build` fails with a `ConfigError` for values that cannot work, such as a queue
+//! capacity of zero, or options that contradict each other. Fields a config cannot do
+//! without are taken by the builder's constructor, or for `InputSpec`, whose builder is
+//! generic over what has been set, checked by the compiler. Each config also has the
+//! `validate` its builder uses, for configs made as struct literals.
+
+use std::fmt;
+
+/// A field of a builder that has not been set yet.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Default)]
+pub struct Unset;
+
+/// A config that would not work.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct ConfigError {
+    /// The type of the config, such as `"DispatchConfig"`.
+    pub config: &'static str,
+    /// The field at fault, or the first of the fields that contradict each other.
+    pub field: &'static str,
+    /// What is wrong with the field, and what to do instead.
+    pub problem: String,
+}
+
+impl ConfigError {
+    pub(crate) fn new(
+        config: &'static str,
+        field: &'static str,
+        problem: impl Into<String>,
+    ) -> ConfigError {
+        ConfigError {
+            config,
+            field,
+            problem: problem.into(),
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid {}: {} {}",
+            self.config, self.field, self.problem
+        )
+    }
+}
+
+impl std::error::Error for ConfigError {}
@@ -0,0 +1,327 @@
+//! Checking that the enabled capture settings match the detected signal.
+//!
+//! Capture keeps working when the enabled display mode, bit depth, color mode or audio
+//! channel count do not match the source, but produces juddery, tinted or degraded video or
+//! silent channels.
+//! A `ConformanceChecker` compares the enabled settings against what the device detects
+//! and the audio it delivers, and reports each mismatch once.
+
+use crate::device::input::{
+    CaptureColorMode, DecklinkAudioInputPacket, DecklinkAudioSampleType,
+    DecklinkDetectedVideoInputFormatFlags,
+};
+use crate::display_mode::{DecklinkDisplayMode, DecklinkDisplayModeId, DecklinkFieldDominance};
+use crate::frame::DecklinkPixelFormat;
+use crate::SdkError;
+use std::collections::HashSet;
+
+/// The capture settings that were enabled.
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub struct EnabledConfig {
+    pub display_mode: DecklinkDisplayModeId,
+    pub pixel_format: DecklinkPixelFormat,
+}
+
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+pub enum ConformanceWarningKind {
+    ModeMismatch,
+    BitDepthDowngrade,
+    SilentAudioChannels,
+    InterlaceMismatch,
+    ColorModeMismatch,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ConformanceWarning {
+    /// The detected display mode differs from the enabled one.
+    ModeMismatch {
+        enabled: DecklinkDisplayModeId,
+        detected: DecklinkDisplayModeId,
+    },
+    /// The source has a higher bit depth than the capture pixel format.
+    BitDepthDowngrade {
+        capture_bits: u32,
+        detected_bits: u32,
+    },
+    /// These zero based channels have been digital silence for the configured period.
+    SilentAudioChannels { channels: Vec<u32> },
+    /// The source is interlaced and the enabled mode is progressive, or the reverse.
+    InterlaceMismatch {
+        enabled: DecklinkFieldDominance,
+        detected: DecklinkFieldDominance,
+    },
+    /// The source is RGB 4:4:4 and the capture pixel format YCbCr 4:2:2, or the reverse.
+    ColorModeMismatch {
+        capture: CaptureColorMode,
+        detected: CaptureColorMode,
+    },
+}
+
+impl ConformanceWarning {
+    pub fn kind(&self) -> ConformanceWarningKind {
+        match self {
+            ConformanceWarning::ModeMismatch { .. } => ConformanceWarningKind::ModeMismatch,
+            ConformanceWarning::BitDepthDowngrade { .. } => {
+                ConformanceWarningKind::BitDepthDowngrade
+            }
+            ConformanceWarning::SilentAudioChannels { .. } => {
+                ConformanceWarningKind::SilentAudioChannels
+            }
+            ConformanceWarning::InterlaceMismatch { .. } => {
+                ConformanceWarningKind::InterlaceMismatch
+            }
+            ConformanceWarning::ColorModeMismatch { .. } => {
+                ConformanceWarningKind::ColorModeMismatch
+            }
+        }
+    }
+}
+
+fn capture_bits(format: DecklinkPixelFormat) -> Option<u32> {
+    match format {
+        DecklinkPixelFormat::Format8BitYUV
+        | DecklinkPixelFormat::Format8BitARGB
+        | DecklinkPixelFormat::Format8BitBGRA => Some(8),
+        DecklinkPixelFormat::Format10BitYUV
+        | DecklinkPixelFormat::Format10BitRGB
+        | DecklinkPixelFormat::Format10BitRGBXLE
+        | DecklinkPixelFormat::Format10BitRGBX => Some(10),
+        DecklinkPixelFormat::Format12BitRGB | DecklinkPixelFormat::Format12BitRGBLE => Some(12),
+        DecklinkPixelFormat::FormatH265 | DecklinkPixelFormat::FormatDNxHR => None,
+    }
+}
+
+fn detected_bits(flags: DecklinkDetectedVideoInputFormatFlags) -> Option<u32> {
+    if flags.contains(DecklinkDetectedVideoInputFormatFlags::BIT_DEPTH_12) {
+        Some(12)
+    } else if flags.contains(DecklinkDetectedVideoInputFormatFlags::BIT_DEPTH_10) {
+        Some(10)
+    } else if flags.contains(DecklinkDetectedVideoInputFormatFlags::BIT_DEPTH_8) {
+        Some(8)
+    } else {
+        None
+    }
+}
+
+fn is_interlaced(dominance: DecklinkFieldDominance) -> Option<bool> {
+    match dominance {
+        DecklinkFieldDominance::LowerFieldFirst | DecklinkFieldDominance::UpperFieldFirst => {
+            Some(true)
+        }
+        DecklinkFieldDominance::ProgressiveFrame
+        | DecklinkFieldDominance::ProgressiveSegmentedFrame => Some(false),
+        DecklinkFieldDominance::Unknown => None,
+    }
+}
+
+/// Compares the enabled capture settings against the detected signal.
+///
+/// Feed it format changes from `InputHandler::format_changed` and the audio packets of
+/// `InputHandler::frame_arrived`. Each warning is returned once when its condition starts,
+/// and again only after the condition has cleared. Audio is only inspected for one packet out
+/// of every `audio_sample_interval`.
+pub struct ConformanceChecker {
+    enabled: EnabledConfig,
+    field_dominance: Vec<(DecklinkDisplayModeId, DecklinkFieldDominance)>,
+    suppressed: HashSet<ConformanceWarningKind>,
+    active: HashSet<ConformanceWarningKind>,
+
+    silence_threshold_frames: u64,
+    audio_sample_interval: u32,
+    packets_seen: u32,
+    silent_frames: Vec<u64>,
+}
+
+impl ConformanceChecker {
+    /// Create a checker for `enabled`. `modes` are the display modes of the device, used to
+    /// tell whether a detected mode is interlaced. Audio channels are reported silent after
+    /// `silence_threshold_frames` sample frames of digital silence.
+    pub fn new(
+        enabled: EnabledConfig,
+        modes: &[DecklinkDisplayMode],
+        silence_threshold_frames: u64,
+    ) -> ConformanceChecker {
+        ConformanceChecker {
+            enabled,
+            field_dominance: modes
+                .iter()
+                .map(|m| (m.mode(), m.field_dominance()))
+                .collect(),
+            suppressed: HashSet::new(),
+            active: HashSet::new(),
+            silence_threshold_frames: silence_threshold_frames.max(1),
+            audio_sample_interval: 4,
+            packets_seen: 0,
+            silent_frames: Vec::new(),
+        }
+    }
+
+    /// Set how many audio packets are skipped between inspected packets. Defaults to 4.
+    pub fn set_audio_sample_interval(&mut self, interval: u32) {
+        self.audio_sample_interval = interval.max(1);
+    }
+
+    /// Never report warnings of `kind`.
+    pub fn suppress(&mut self, kind: ConformanceWarningKind) {
+        self.suppressed.insert(kind);
+    }
+
+    fn field_dominance_of(&self, mode: DecklinkDisplayModeId) -> DecklinkFieldDominance {
+        self.field_dominance
+            .iter()
+            .find(|(m, _)| *m == mode)
+            .map(|(_, d)| *d)
+            .unwrap_or(DecklinkFieldDominance::Unknown)
+    }
+
+    /// Update the state of a condition, adding its warning to `out` if it has just started.
+    fn update(
+        &mut self,
+        kind: ConformanceWarningKind,
+        warning: Option<ConformanceWarning>,
+        out: &mut Vec<ConformanceWarning>,
+    ) {
+        match warning {
+            Some(warning) => {
+                if self.active.insert(kind) && !self.suppressed.contains(&kind) {
+                    out.push(warning);
+                }
+            }
+            None => {
+                self.active.remove(&kind);
+            }
+        }
+    }
+
+    /// Check a detected format. Returns any warnings that have just started.
+    pub fn check_format(
+        &mut self,
+        detected_mode: DecklinkDisplayModeId,
+        detected_flags: DecklinkDetectedVideoInputFormatFlags,
+    ) -> Vec<ConformanceWarning> {
+        let mut out = Vec::new();
+        let enabled = self.enabled;
+
+        let mode_mismatch = (detected_mode != enabled.display_mode
+            && detected_mode != DecklinkDisplayModeId::Unknown)
+            .then_some(ConformanceWarning::ModeMismatch {
+                enabled: enabled.display_mode,
+                detected: detected_mode,
+            });
+        self.update(
+            ConformanceWarningKind::ModeMismatch,
+            mode_mismatch,
+            &mut out,
+        );
+
+        let downgrade = match (
+            capture_bits(enabled.pixel_format),
+            detected_bits(detected_flags),
+        ) {
+            (Some(capture_bits), Some(detected_bits)) if detected_bits > capture_bits => {
+                Some(ConformanceWarning::BitDepthDowngrade {
+                    capture_bits,
+                    detected_bits,
+                })
+            }
+            _ => None,
+        };
+        self.update(
+            ConformanceWarningKind::BitDepthDowngrade,
+            downgrade,
+            &mut out,
+        );
+
+        let enabled_dominance = self.field_dominance_of(enabled.display_mode);
+        let detected_dominance = self.field_dominance_of(detected_mode);
+        let interlace = match (
+            is_interlaced(enabled_dominance),
+            is_interlaced(detected_dominance),
+        ) {
+            (Some(a), Some(b)) if a != b => Some(ConformanceWarning::InterlaceMismatch {
+                enabled: enabled_dominance,
+                detected: detected_dominance,
+            }),
+            _ => None,
+        };
+        self.update(
+            ConformanceWarningKind::InterlaceMismatch,
+            interlace,
+            &mut out,
+        );
+
+        let color_mode = match (
+            CaptureColorMode::of_pixel_format(enabled.pixel_format),
+            CaptureColorMode::detected(detected_flags),
+        ) {
+            (Some(capture), Some(detected)) if capture != detected => {
+                Some(ConformanceWarning::ColorModeMismatch { capture, detected })
+            }
+            _ => None,
+        };
+        self.update(
+            ConformanceWarningKind::ColorModeMismatch,
+            color_mode,
+            &mut out,
+        );
+
+        out
+    }
+
+    /// Check a captured audio packet. Returns any warnings that have just started.
+    pub fn check_audio_packet(
+        &mut self,
+        packet: &DecklinkAudioInputPacket,
+    ) -> Result<Vec<ConformanceWarning>, SdkError> {
+        let mut out = Vec::new();
+
+        self.packets_seen = self.packets_seen.wrapping_add(1);
+        if !self.packets_seen.is_multiple_of(self.audio_sample_interval) {
+            return Ok(out);
+        }
+
+        let channels = packet.channel_count() as usize;
+        if channels == 0 {
+            return Ok(out);
+        }
+        if self.silent_frames.len() != channels {
+            self.silent_frames = vec![0; channels];
+        }
+
+        let sample_size = match packet.sample_type() {
+            DecklinkAudioSampleType::Int16 => 2,
+            DecklinkAudioSampleType::Int32 => 4,
+        };
+        let bytes = packet.bytes()?;
+        let mut has_audio = vec![false; channels];
+        for (i, sample) in bytes.chunks_exact(sample_size).enumerate() {
+            if sample.iter().any(|b| *b != 0) {
+                has_audio[i % channels] = true;
+            }
+        }
+
+        // Skipped packets are assumed to match the inspected one
+        let frames = packet.sample_frame_count() as u64 * self.audio_sample_interval as u64;
+        for (silent, has_audio) in self.silent_frames.iter_mut().zip(has_audio) {
+            *silent = if has_audio { 0 } else { *silent + frames };
+        }
+
+        let silent: Vec<u32> = self
+            .silent_frames
+            .iter()
+            .enumerate()
+            .filter(|(_, frames)| **frames >= self.silence_threshold_frames)
+            .map(|(i, _)| i as u32)
+            .collect();
+        let warning = (!silent.is_empty())
+            .then_some(ConformanceWarning::SilentAudioChannels { channels: silent });
+        self.update(
+            ConformanceWarningKind::SilentAudioChannels,
+            warning,
+            &mut out,
+        );
+
+        Ok(out)
+    }
+}
@@ -0,0 +1,384 @@
+//! Unpacking and packing the 10-bit RGB pixel formats.
+//!
+//! The three 10-bit RGB formats each hold one pixel in a 32-bit word, and differ only in
+//! where the components sit in the word and the byte order of the word. Reading one as
+//! another gives a plausible picture with the wrong tint, so each has its own unpacker. Bit
+//! positions below count from the least significant bit of the word:
+//!
+//! | Format                        | FourCC | Word order    | Red   | Green | Blue  | Unused |
+//! |-------------------------------|--------|---------------|-------|-------|-------|--------|
+//! | `Format10BitRGB`              | `r210` | big endian    | 29-20 | 19-10 | 9-0   | 31-30  |
+//! | `Format10BitRGBX`             | `R10b` | big endian    | 31-22 | 21-12 | 11-2  | 1-0    |
+//! | `Format10BitRGBXLE`           | `R10l` | little endian | 31-22 | 21-12 | 11-2  | 1-0    |
+//!
+//! Components are in video range, 64 to 940. The unpackers give each pixel as three `u16`
+//! holding the 10-bit components unscaled, red first, so a round trip through a packer is
+//! lossless. Rows of these formats are padded to a multiple of 64 pixels, 256 bytes, as
+//! `rgb_10bit_row_bytes` gives, and the frame level functions skip the padding.
+//!
+//! `convert_frame` converts whole frames between pixel formats, for the pairs
+//! `can_convert` accepts: 10-bit YUV to 8-bit YUV, the 10-bit RGB formats to each other and
+//! to 8-bit RGB, and 8-bit ARGB to BGRA and back. YUV keeps its video range, taking the top
+//! 8 bits of each component rounded, while 10-bit RGB is scaled from video range to the full
+//! range of the 8-bit RGB formats.
+
+use crate::colorimetry::Colorimetry;
+use crate::frame::{
+    DecklinkAlignedVec, DecklinkFrameBase, DecklinkPixelFormat, DecklinkVideoFrame,
+    DecklinkVideoMutableFrame,
+};
+use crate::ptr::VideoConversionPtr;
+use crate::{sdk, SdkError};
+use aligned_vec::AVec;
+
+/// The largest 10-bit component value.
+const MAX_COMPONENT: u16 = 0x3ff;
+
+#[derive(Debug)]
+pub enum ConvertError {
+    UnsupportedPixelFormat(DecklinkPixelFormat),
+    /// `convert_frame` cannot convert frames from one format to the other.
+    UnsupportedConversion {
+        from: DecklinkPixelFormat,
+        to: DecklinkPixelFormat,
+    },
+    /// A buffer is smaller than the pixels it must hold.
+    BufferTooSmall,
+    Sdk(SdkError),
+}
+
+impl From<SdkError> for ConvertError {
+    fn from(e: SdkError) -> Self {
+        ConvertError::Sdk(e)
+    }
+}
+
+impl std::fmt::Display for ConvertError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConvertError::UnsupportedPixelFormat(format) => {
+                write!(f, "pixel format {:?} is not a 10-bit RGB format", format)
+            }
+            ConvertError::UnsupportedConversion { from, to } => {
+                write!(f, "cannot convert {:?} to {:?}", from, to)
+            }
+            ConvertError::BufferTooSmall => write!(f, "the buffer is too small"),
+            ConvertError::Sdk(e) => write!(f, "failed to read the frame: {:?}", e),
+        }
+    }
+}
+
+impl std::error::Error for ConvertError {}
+
+/// The byte count of a row of `width` pixels in a 10-bit RGB format, padded to 256 bytes.
+pub fn rgb_10bit_row_bytes(width: usize) -> usize {
+    width.div_ceil(64) * 256
+}
+
+/// The byte order of a packed word, and the shift of its red component. Green and blue
+/// follow ten and twenty bits below.
+#[derive(Copy, Clone)]
+struct Layout {
+    big_endian: bool,
+    red_shift: u32,
+}
+
+const R210: Layout = Layout {
+    big_endian: true,
+    red_shift: 20,
+};
+const R10B: Layout = Layout {
+    big_endian: true,
+    red_shift: 22,
+};
+const R10L: Layout = Layout {
+    big_endian: false,
+    red_shift: 22,
+};
+
+fn layout(format: DecklinkPixelFormat) -> Result<Layout, ConvertError> {
+    match format {
+        DecklinkPixelFormat::Format10BitRGB => Ok(R210),
+        DecklinkPixelFormat::Format10BitRGBX => Ok(R10B),
+        DecklinkPixelFormat::Format10BitRGBXLE => Ok(R10L),
+        _ => Err(ConvertError::UnsupportedPixelFormat(format)),
+    }
+}
+
+fn unpack(layout: Layout, src: &[u8], dst: &mut [u16]) -> Result<(), ConvertError> {
+    let pixels = dst.len() / 3;
+    if src.len() < pixels * 4 {
+        return Err(ConvertError::BufferTooSmall);
+    }
+
+    for (word, rgb) in src.chunks_exact(4).zip(dst.chunks_exact_mut(3)) {
+        let word = [word[0], word[1], word[2], word[3]];
+        let word = if layout.big_endian {
+            u32::from_be_bytes(word)
+        } else {
+            u32::from_le_bytes(word)
+        };
+        rgb[0] = ((word >> layout.red_shift) & 0x3ff) as u16;
+        rgb[1] = ((word >> (layout.red_shift - 10)) & 0x3ff) as u16;
+        rgb[2] = ((word >> (layout.red_shift - 20)) & 0x3ff) as u16;
+    }
+    Ok(())
+}
+
+fn pack(layout: Layout, src: &[u16], dst: &mut [u8]) -> Result<(), ConvertError> {
+    let pixels = src.len() / 3;
+    if dst.len() < pixels * 4 {
+        return Err(ConvertError::BufferTooSmall);
+    }
+
+    for (rgb, out) in src.chunks_exact(3).zip(dst.chunks_exact_mut(4)) {
+        let component = |c: u16| c.min(MAX_COMPONENT) as u32;
+        let word = component(rgb[0]) << layout.red_shift
+            | component(rgb[1]) << (layout.red_shift - 10)
+            | component(rgb[2]) << (layout.red_shift - 20);
+        let word = if layout.big_endian {
+            word.to_be_bytes()
+        } else {
+            word.to_le_bytes()
+        };
+        out.copy_from_slice(&word);
+    }
+    Ok(())
+}
+
+/// Unpack `r210` pixels, a big endian word of 2 unused bits, then 10 bits each of red,
+/// green and blue from the most significant end. Fills `dst` with as many pixels as it holds
+/// three components for, and fails if `src` has fewer.
+pub fn r210_to_rgb16(src: &[u8], dst: &mut [u16]) -> Result<(), ConvertError> {
+    unpack(R210, src, dst)
+}
+
+/// Unpack `R10b` pixels, a big endian word of 10 bits each of red, green and blue from the
+/// most significant end, then 2 unused bits. Otherwise as `r210_to_rgb16`.
+pub fn r10b_to_rgb16(src: &[u8], dst: &mut [u16]) -> Result<(), ConvertError> {
+    unpack(R10B, src, dst)
+}
+
+/// Unpack `R10l` pixels, the word of `R10b` stored little endian, so the first byte holds
+/// the low 6 bits of blue and the 2 unused bits. Otherwise as `r210_to_rgb16`.
+pub fn r10l_to_rgb16(src: &[u8], dst: &mut [u16]) -> Result<(), ConvertError> {
+    unpack(R10L, src, dst)
+}
+
+/// Pack 10-bit components into `r210` pixels, leaving the unused bits clear. Components
+/// above 1023 are clamped. Fails if `dst` cannot hold every pixel of `src`.
+pub fn rgb16_to_r210(src: &[u16], dst: &mut [u8]) -> Result<(), ConvertError> {
+    pack(R210, src, dst)
+}
+
+/// Pack 10-bit components into `R10b` pixels. Otherwise as `rgb16_to_r210`.
+pub fn rgb16_to_r10b(src: &[u16], dst: &mut [u8]) -> Result<(), ConvertError> {
+    pack(R10B, src, dst)
+}
+
+/// Pack 10-bit components into `R10l` pixels. Otherwise as `rgb16_to_r210`.
+pub fn rgb16_to_r10l(src: &[u16], dst: &mut [u8]) -> Result<(), ConvertError> {
+    pack(R10L, src, dst)
+}
+
+/// Unpack a row of pixels in any 10-bit RGB `format`.
+pub fn unpack_rgb_10bit(
+    format: DecklinkPixelFormat,
+    src: &[u8],
+    dst: &mut [u16],
+) -> Result<(), ConvertError> {
+    unpack(layout(format)?, src, dst)
+}
+
+/// Pack a row of pixels into any 10-bit RGB `format`.
+pub fn pack_rgb_10bit(
+    format: DecklinkPixelFormat,
+    src: &[u16],
+    dst: &mut [u8],
+) -> Result<(), ConvertError> {
+    pack(layout(format)?, src, dst)
+}
+
+/// Unpack a whole frame in a 10-bit RGB format into tightly packed components, three for
+/// each pixel, skipping the padding at the end of each row.
+pub fn frame_to_rgb16<F: DecklinkFrameBase + ?Sized>(frame: &F) -> Result<Vec<u16>, ConvertError> {
+    let layout = layout(frame.pixel_format())?;
+    let (width, height, row_bytes) = (frame.width(), frame.height(), frame.row_bytes());
+    let bytes = frame.bytes()?;
+    if row_bytes < width * 4 || bytes.0.len() < row_bytes * height {
+        return Err(ConvertError::BufferTooSmall);
+    }
+
+    let mut out = vec![0; width * height * 3];
+    if width == 0 {
+        return Ok(out);
+    }
+    for (row, dst) in bytes
+        .0
+        .chunks(row_bytes)
+        .zip(out.chunks_exact_mut(width * 3))
+    {
+        unpack(layout, row, dst)?;
+    }
+    Ok(out)
+}
+
+/// Whether `convert_frame` converts frames in `from` into `to`. A format converts to itself,
+/// as a copy, unless it is compressed.
+pub fn can_convert(from: DecklinkPixelFormat, to: DecklinkPixelFormat) -> bool {
+    use DecklinkPixelFormat::*;
+    if from == to {
+        return from.bytes_per_row(1).is_some();
+    }
+    match (from, to) {
+        (Format10BitYUV, Format8BitYUV) => true,
+        (Format8BitARGB, Format8BitBGRA) | (Format8BitBGRA, Format8BitARGB) => true,
+        _ if from.is_rgb_10bit() => {
+            to.is_rgb_10bit() || matches!(to, Format8BitARGB | Format8BitBGRA)
+        }
+        _ => false,
+    }
+}
+
+/// Round a 10-bit component to 8 bits, keeping its range.
+fn to_8bit(component: u32) -> u8 {
+    ((component + 2) >> 2).min(255) as u8
+}
+
+/// Scale a 10-bit video range component, 64 to 940, to a full range 8-bit one.
+fn to_full_range_8bit(component: u16) -> u8 {
+    let component = (component as i32 - 64).clamp(0, 876);
+    ((component * 255 + 438) / 876) as u8
+}
+
+/// v210 holds six pixels in four little endian words of three 10-bit components each, in
+/// the order `U Y V Y U Y`, the same order as the bytes of UYVY.
+fn v210_to_uyvy_row(src: &[u8], dst: &mut [u8]) {
+    let mut out = dst.iter_mut();
+    for word in src.chunks_exact(4) {
+        let word = u32::from_le_bytes([word[0], word[1], word[2], word[3]]);
+        for shift in [0, 10, 20] {
+            match out.next() {
+                Some(byte) => *byte = to_8bit((word >> shift) & 0x3ff),
+                None => return,
+            }
+        }
+    }
+}
+
+/// Convert a row of `width` pixels.
+fn convert_row(
+    from: DecklinkPixelFormat,
+    to: DecklinkPixelFormat,
+    width: usize,
+    src: &[u8],
+    dst: &mut [u8],
+    rgb: &mut [u16],
+) -> Result<(), ConvertError> {
+    use DecklinkPixelFormat::*;
+    match (from, to) {
+        _ if from == to => {
+            let len = dst.len().min(src.len());
+            dst[..len].copy_from_slice(&src[..len]);
+        }
+        (Format10BitYUV, Format8BitYUV) => v210_to_uyvy_row(src, dst),
+        (Format8BitARGB, Format8BitBGRA) | (Format8BitBGRA, Format8BitARGB) => {
+            for (src, dst) in src.chunks_exact(4).zip(dst.chunks_exact_mut(4)).take(width) {
+                dst.copy_from_slice(&[src[3], src[2], src[1], src[0]]);
+            }
+        }
+        _ => {
+            unpack(layout(from)?, src, rgb)?;
+            match to {
+                Format8BitBGRA | Format8BitARGB => {
+                    for (rgb, dst) in rgb.chunks_exact(3).zip(dst.chunks_exact_mut(4)) {
+                        let [r, g, b] = [rgb[0], rgb[1], rgb[2]].map(to_full_range_8bit);
+                        let pixel = if to == Format8BitBGRA {
+                            [b, g, r, 255]
+                        } else {
+                            [255, r, g, b]
+                        };
+                        dst.copy_from_slice(&pixel);
+                    }
+                }
+                _ => pack(layout(to)?, rgb, dst)?,
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Convert a frame into `to`, with the row bytes the driver uses for it. Fails with
+/// `ConvertError::UnsupportedConversion` when `can_convert` does not accept the pair.
+pub fn convert_frame<F: DecklinkFrameBase + ?Sized>(
+    frame: &F,
+    to: DecklinkPixelFormat,
+) -> Result<DecklinkVideoMutableFrame, ConvertError> {
+    let from = frame.pixel_format();
+    let unsupported = ConvertError::UnsupportedConversion { from, to };
+    if !can_convert(from, to) {
+        return Err(unsupported);
+    }
+    let (width, height, src_row_bytes) = (frame.width(), frame.height(), frame.row_bytes());
+    let (Some(src_row_len), Some(row_bytes)) = (from.bytes_per_row(width), to.bytes_per_row(width))
+    else {
+        return Err(unsupported);
+    };
+    let bytes = frame.bytes()?;
+    if src_row_bytes < src_row_len || bytes.0.len() < src_row_bytes * height {
+        return Err(ConvertError::BufferTooSmall);
+    }
+
+    let mut out: DecklinkAlignedVec = AVec::from_slice(64, &vec![0; row_bytes * height]);
+    let mut rgb = vec![0; if from.is_rgb_10bit() { width * 3 } else { 0 }];
+    if width > 0 {
+        for (src, dst) in bytes
+            .0
+            .chunks(src_row_bytes)
+            .zip(out.chunks_exact_mut(row_bytes))
+        {
+            convert_row(from, to, width, src, dst, &mut rgb)?;
+        }
+    }
+
+    let mut converted =
+        DecklinkVideoMutableFrame::create(width, height, row_bytes, to, frame.flags());
+    converted.set_bytes(out)?;
+    Ok(converted)
+}
+
+/// The driver's converter, `IDeckLinkVideoConversion`, which converts between more formats
+/// than `convert_frame` and may use the hardware.
+pub(crate) struct SdkConversion {
+    conversion: VideoConversionPtr,
+}
+
+// Safety: The converter's reference count is atomic, so it can be released from any thread.
+// It is not shared, so its calls are never concurrent.
+unsafe impl Send for SdkConversion {}
+
+impl SdkConversion {
+    /// The driver's converter, or `None` when the drivers have none, as the mock backend
+    /// does not.
+    pub(crate) fn new() -> Option<SdkConversion> {
+        VideoConversionPtr::create().map(|conversion| SdkConversion { conversion })
+    }
+
+    /// Convert a captured frame into a new frame in `to`, in the colorimetry usual for its
+    /// height.
+    pub(crate) fn convert(
+        &self,
+        frame: &DecklinkVideoFrame,
+        to: DecklinkPixelFormat,
+    ) -> Result<DecklinkVideoFrame, SdkError> {
+        let colorspace = match Colorimetry::for_height(frame.height()) {
+            Colorimetry::Rec601 => sdk::_DecklinkColorspace_decklinkColorspaceRec601,
+            Colorimetry::Rec709 => sdk::_DecklinkColorspace_decklinkColorspaceRec709,
+            Colorimetry::Rec2020 => sdk::_DecklinkColorspace_decklinkColorspaceRec2020,
+        };
+        let converted =
+            self.conversion
+                .convert_new_frame(frame.frame_ptr(), to as u32, colorspace)?;
+        Ok(DecklinkVideoFrame::from_ptr(converted))
+    }
+}
@@ -0,0 +1,594 @@
+//! Measuring the processor time each stage of capture takes, against the frame interval.
+//!
+//! A frame passes through up to four stages of the crate: the driver callback, a conversion
+//! or copy, a `crate::experimental::transform::TransformChain` and the consumers that are
+//! given it. A `CpuMeter` is given the time each stage takes, from two clock reads around
+//! it, and reports the time of each stage as a fraction of the frame interval: a stage with
+//! a utilization of 0.61 at 60 fps takes 61% of each 16.7ms frame. Once the stages together
+//! reach 1.0 on one thread, frames are dropped.
+//!
+//! The meter divides time into frame intervals, one for each call of
+//! `CpuMeter::frame_arrived`, and keeps the time of the last `CpuConfig::window` of them. The
+//! time of a stage run on several threads is added up, so its utilization is the processor
+//! time it takes for each frame and may be more than 1.0; `StageUtilization::workers` splits
+//! it by thread, giving each thread's share of its own time.
+//!
+//! The layers that take a meter record their own stages: `crate::dispatch::DispatchPool`
+//! records the callback and consumer stages of a source, `TransformChain` the transform stage
+//! and `crate::experimental::transform::TransformTap` the consumer stage.
+//! `CpuMeter::measured` records the callback stage of any input callback, and
+//! `CpuMeter::measure` any other stage. The stages are not nested, so measuring a callback
+//! that records its own stages counts their time twice.
+//!
+//! With `CpuBudgets` set, the meter raises `CpuEvent::BudgetExceeded` when a stage, or every
+//! stage together, stays over its budget for `CpuConfig::sustained`, and
+//! `CpuEvent::BudgetRecovered` once it is back under the budget by `CpuConfig::hysteresis`.
+
+use crate::config::ConfigError;
+use crate::device::input::{CallbackResult, FrameArrival, InputFormatChange, InputHandler};
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::thread::ThreadId;
+use std::time::{Duration, Instant};
+
+/// A stage of the crate a frame passes through.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CpuStage {
+    /// The driver callback, up to where it gives the frame to another stage or thread.
+    Callback,
+    /// Converting or copying the frame before it is transformed or handled.
+    Conversion,
+    /// The transforms of a `crate::experimental::transform::TransformChain`.
+    Transform,
+    /// The handlers and consumers the frame is given to.
+    Consumer,
+}
+
+impl CpuStage {
+    pub const ALL: [CpuStage; 4] = [
+        CpuStage::Callback,
+        CpuStage::Conversion,
+        CpuStage::Transform,
+        CpuStage::Consumer,
+    ];
+
+    fn index(&self) -> usize {
+        *self as usize
+    }
+}
+
+impl fmt::Display for CpuStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CpuStage::Callback => "callback",
+            CpuStage::Conversion => "conversion",
+            CpuStage::Transform => "transform",
+            CpuStage::Consumer => "consumer",
+        })
+    }
+}
+
+/// The highest acceptable utilization of each stage, and of every stage together, as
+/// fractions of the frame interval. Stages without a budget raise no events.
+#[derive(PartialEq, Debug, Copy, Clone, Default)]
+pub struct CpuBudgets {
+    pub callback: Option<f64>,
+    pub conversion: Option<f64>,
+    pub transform: Option<f64>,
+    pub consumer: Option<f64>,
+    pub total: Option<f64>,
+}
+
+impl CpuBudgets {
+    /// The budget of `stage`, or of every stage together for `None`.
+    pub fn get(&self, stage: Option<CpuStage>) -> Option<f64> {
+        match stage {
+            Some(CpuStage::Callback) => self.callback,
+            Some(CpuStage::Conversion) => self.conversion,
+            Some(CpuStage::Transform) => self.transform,
+            Some(CpuStage::Consumer) => self.consumer,
+            None => self.total,
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub struct CpuConfig {
+    /// The frame interval of the input, which utilization is a fraction of.
+    pub frame_interval: Duration,
+    /// The number of recent frame intervals utilization is taken over.
+    pub window: usize,
+    pub budgets: CpuBudgets,
+    /// How long a stage must stay over its budget before the event is raised, rounded up to
+    /// whole frame intervals.
+    pub sustained: Duration,
+    /// How far under its budget a stage must fall, as a fraction of the frame interval,
+    /// before it has recovered. Keeps a stage near its budget from raising an event on
+    /// every frame.
+    pub hysteresis: f64,
+}
+
+impl Default for CpuConfig {
+    fn default() -> Self {
+        CpuConfig {
+            frame_interval: Duration::from_nanos(1_001_000_000 / 60),
+            window: 120,
+            budgets: CpuBudgets::default(),
+            sustained: Duration::from_secs(1),
+            hysteresis: 0.05,
+        }
+    }
+}
+
+impl CpuConfig {
+    pub fn builder() -> CpuConfigBuilder {
+        CpuConfigBuilder {
+            config: CpuConfig::default(),
+        }
+    }
+
+    /// Check that a meter with this config could report utilization.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let error = |field, problem| Err(ConfigError::new("CpuConfig", field, problem));
+        if self.frame_interval.is_zero() {
+            return error("frame_interval", "must be longer than zero");
+        }
+        if self.window == 0 {
+            return error("window", "must be at least 1 frame");
+        }
+        let stages = CpuStage::ALL.into_iter().map(Some).chain([None]);
+        for budget in stages.filter_map(|stage| self.budgets.get(stage)) {
+            if !(budget.is_finite() && budget > 0.0) {
+                return error("budgets", "must be more than zero");
+            }
+        }
+        if !(0.0..1.0).contains(&self.hysteresis) {
+            return error("hysteresis", "must be at least 0 and less than 1");
+        }
+        Ok(())
+    }
+
+    /// The number of frame intervals a stage must stay over its budget for.
+    fn sustained_frames(&self) -> u64 {
+        let interval = self.frame_interval.as_nanos().max(1);
+        (self.sustained.as_nanos().div_ceil(interval) as u64).max(1)
+    }
+}
+
+/// Builds a `CpuConfig`, starting from the default.
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub struct CpuConfigBuilder {
+    config: CpuConfig,
+}
+
+impl CpuConfigBuilder {
+    pub fn frame_interval(mut self, frame_interval: Duration) -> Self {
+        self.config.frame_interval = frame_interval;
+        self
+    }
+
+    pub fn window(mut self, window: usize) -> Self {
+        self.config.window = window;
+        self
+    }
+
+    pub fn budgets(mut self, budgets: CpuBudgets) -> Self {
+        self.config.budgets = budgets;
+        self
+    }
+
+    pub fn sustained(mut self, sustained: Duration) -> Self {
+        self.config.sustained = sustained;
+        self
+    }
+
+    pub fn hysteresis(mut self, hysteresis: f64) -> Self {
+        self.config.hysteresis = hysteresis;
+        self
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        self.config.validate()
+    }
+
+    pub fn build(self) -> Result<CpuConfig, ConfigError> {
+        self.validate()?;
+        Ok(self.config)
+    }
+}
+
+/// The time one thread spent in a stage.
+#[derive(PartialEq, Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WorkerUtilization {
+    /// The thread, numbered in the order threads were first measured by the meter.
+    pub worker: usize,
+    /// The time the thread spent in the stage over the window.
+    pub time: Duration,
+    /// The fraction of the thread's time over the window it spent in the stage.
+    pub utilization: f64,
+}
+
+/// The time taken by a stage, or by every stage together, over a meter's window.
+#[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StageUtilization {
+    /// The stage, or `None` for every stage together.
+    pub stage: Option<CpuStage>,
+    /// The time the stage took for each frame, added up across threads.
+    pub per_frame: Duration,
+    /// `per_frame` as a fraction of the frame interval.
+    pub utilization: f64,
+    /// The time of each thread, in the order of their numbers.
+    pub workers: Vec<WorkerUtilization>,
+    pub budget: Option<f64>,
+    /// Whether the stage has been over its budget for long enough to raise an event, and
+    /// has not yet recovered.
+    pub over_budget: bool,
+    pub frame_interval: Duration,
+}
+
+impl StageUtilization {
+    /// The highest utilization of one thread, which is what drops frames when a stage runs
+    /// on a single thread.
+    pub fn busiest_worker(&self) -> Option<WorkerUtilization> {
+        self.workers
+            .iter()
+            .copied()
+            .max_by(|a, b| a.utilization.total_cmp(&b.utilization))
+    }
+}
+
+impl fmt::Display for StageUtilization {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.stage {
+            Some(stage) => write!(f, "{} is", stage)?,
+            None => write!(f, "all stages are")?,
+        }
+        write!(
+            f,
+            " using {:.0}% of the {:.1}ms frame interval",
+            self.utilization * 100.0,
+            self.frame_interval.as_secs_f64() * 1000.0
+        )?;
+        if let Some(budget) = self.budget {
+            write!(f, ", with a budget of {:.0}%", budget * 100.0)?;
+        }
+        Ok(())
+    }
+}
+
+/// The utilization of each stage, as reported by `CpuMeter::report`.
+#[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CpuReport {
+    /// The frames counted since the meter was created.
+    pub frames: u64,
+    /// The number of frame intervals utilization is taken over.
+    pub window: usize,
+    pub frame_interval: Duration,
+    /// The utilization of each stage, in the order of `CpuStage::ALL`.
+    pub stages: Vec<StageUtilization>,
+    pub total: StageUtilization,
+}
+
+impl CpuReport {
+    pub fn stage(&self, stage: CpuStage) -> &StageUtilization {
+        &self.stages[stage.index()]
+    }
+
+    /// The stage taking the most time.
+    pub fn busiest_stage(&self) -> CpuStage {
+        self.stages
+            .iter()
+            .max_by(|a, b| a.utilization.total_cmp(&b.utilization))
+            .and_then(|s| s.stage)
+            .unwrap_or(CpuStage::Consumer)
+    }
+}
+
+impl fmt::Display for CpuReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for stage in &self.stages {
+            writeln!(f, "{}", stage)?;
+        }
+        write!(f, "{}", self.total)
+    }
+}
+
+#[derive(PartialEq, Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CpuEvent {
+    /// `stage`, or every stage together for `None`, has been over its budget for the
+    /// sustained period. `busiest` is the stage taking the most time.
+    BudgetExceeded {
+        stage: Option<CpuStage>,
+        utilization: f64,
+        budget: f64,
+        busiest: CpuStage,
+    },
+    /// `stage` is under its budget by the hysteresis again.
+    BudgetRecovered {
+        stage: Option<CpuStage>,
+        utilization: f64,
+    },
+}
+
+/// Whether a budget has been exceeded, for the stages in `CpuStage::ALL` and then the total.
+#[derive(Copy, Clone, Default)]
+struct Alarm {
+    /// The frame intervals the budget has been exceeded for in a row.
+    over: u64,
+    reported: bool,
+}
+
+struct MeterState {
+    config: CpuConfig,
+    /// The time of each stage and thread in the frame interval in progress.
+    current: Vec<(CpuStage, usize, Duration)>,
+    /// The times of the last `window` frame intervals, oldest first.
+    intervals: VecDeque<Vec<(CpuStage, usize, Duration)>>,
+    /// The times of `intervals`, added up.
+    totals: BTreeMap<(CpuStage, usize), Duration>,
+    frames: u64,
+    /// The threads measured, each numbered by its position.
+    threads: Vec<ThreadId>,
+    alarms: [Alarm; 5],
+    events: Vec<CpuEvent>,
+}
+
+impl MeterState {
+    fn worker(&mut self, thread: ThreadId) -> usize {
+        match self.threads.iter().position(|t| *t == thread) {
+            Some(worker) => worker,
+            None => {
+                self.threads.push(thread);
+                self.threads.len() - 1
+            }
+        }
+    }
+
+    /// The time of every thread in `stage`, or in every stage for `None`, over the window.
+    fn worker_times(&self, stage: Option<CpuStage>) -> BTreeMap<usize, Duration> {
+        let mut times = BTreeMap::new();
+        for ((s, worker), time) in &self.totals {
+            if stage.is_none_or(|stage| stage == *s) {
+                *times.entry(*worker).or_insert(Duration::ZERO) += *time;
+            }
+        }
+        times
+    }
+
+    fn window_time(&self) -> Duration {
+        self.config.frame_interval * self.intervals.len().max(1) as u32
+    }
+
+    fn utilization(&self, stage: Option<CpuStage>) -> f64 {
+        let time: Duration = self.worker_times(stage).values().sum();
+        time.as_secs_f64() / self.window_time().as_secs_f64()
+    }
+
+    fn stage_utilization(&self, stage: Option<CpuStage>, alarm: usize) -> StageUtilization {
+        let times = self.worker_times(stage);
+        let window = self.window_time();
+        let time: Duration = times.values().sum();
+        StageUtilization {
+            stage,
+            per_frame: time / self.intervals.len().max(1) as u32,
+            utilization: time.as_secs_f64() / window.as_secs_f64(),
+            workers: times
+                .into_iter()
+                .map(|(worker, time)| WorkerUtilization {
+                    worker,
+                    time,
+                    utilization: time.as_secs_f64() / window.as_secs_f64(),
+                })
+                .collect(),
+            budget: self.config.budgets.get(stage),
+            over_budget: self.alarms[alarm].reported,
+            frame_interval: self.config.frame_interval,
+        }
+    }
+
+    fn busiest_stage(&self) -> CpuStage {
+        CpuStage::ALL
+            .into_iter()
+            .map(|stage| (stage, self.utilization(Some(stage))))
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .map_or(CpuStage::Consumer, |(stage, _)| stage)
+    }
+
+    fn check_budgets(&mut self) {
+        let sustained = self.config.sustained_frames();
+        let stages = CpuStage::ALL.into_iter().map(Some).chain([None]);
+        for (index, stage) in stages.enumerate() {
+            let Some(budget) = self.config.budgets.get(stage) else {
+                continue;
+            };
+            let utilization = self.utilization(stage);
+            let alarm = self.alarms[index];
+            if utilization > budget {
+                self.alarms[index].over = alarm.over + 1;
+                if !alarm.reported && alarm.over + 1 >= sustained {
+                    self.alarms[index].reported = true;
+                    let busiest = stage.unwrap_or_else(|| self.busiest_stage());
+                    self.events.push(CpuEvent::BudgetExceeded {
+                        stage,
+                        utilization,
+                        budget,
+                        busiest,
+                    });
+                }
+                continue;
+            }
+
+            self.alarms[index].over = 0;
+            if alarm.reported && utilization < budget - self.config.hysteresis {
+                self.alarms[index].reported = false;
+                self.events
+                    .push(CpuEvent::BudgetRecovered { stage, utilization });
+            }
+        }
+    }
+}
+
+/// Adds up the time of each stage over recent frame intervals.
+///
+/// A meter can be cloned and shared, so the stages run on different threads record to the
+/// same one. Give each meter the stages of one input, so its frames are counted once.
+#[derive(Clone)]
+pub struct CpuMeter {
+    state: Arc<Mutex<MeterState>>,
+}
+
+impl CpuMeter {
+    /// A meter with `config`. Values `CpuConfig::validate` rejects are raised to the least
+    /// that works.
+    pub fn new(mut config: CpuConfig) -> CpuMeter {
+        config.window = config.window.max(1);
+        if config.frame_interval.is_zero() {
+            config.frame_interval = CpuConfig::default().frame_interval;
+        }
+        config.hysteresis = config.hysteresis.clamp(0.0, 0.99);
+        CpuMeter {
+            state: Arc::new(Mutex::new(MeterState {
+                config,
+                current: Vec::new(),
+                intervals: VecDeque::with_capacity(config.window),
+                totals: BTreeMap::new(),
+                frames: 0,
+                threads: Vec::new(),
+                alarms: [Alarm::default(); 5],
+                events: Vec::new(),
+            })),
+        }
+    }
+
+    pub fn config(&self) -> CpuConfig {
+        self.state.lock().unwrap().config
+    }
+
+    /// Raise events against `budgets` from the next frame interval.
+    pub fn set_budgets(&self, budgets: CpuBudgets) {
+        let mut state = self.state.lock().unwrap();
+        state.config.budgets = budgets;
+        state.alarms = [Alarm::default(); 5];
+    }
+
+    /// Change the frame interval, such as after the input's display mode changes. The times
+    /// already recorded are kept.
+    pub fn set_frame_interval(&self, frame_interval: Duration) {
+        if !frame_interval.is_zero() {
+            self.state.lock().unwrap().config.frame_interval = frame_interval;
+        }
+    }
+
+    /// Record that the calling thread spent `time` in `stage`, in the current frame interval.
+    pub fn record(&self, stage: CpuStage, time: Duration) {
+        let thread = std::thread::current().id();
+        let mut state = self.state.lock().unwrap();
+        let worker = state.worker(thread);
+        match state
+            .current
+            .iter_mut()
+            .find(|(s, w, _)| *s == stage && *w == worker)
+        {
+            Some((_, _, total)) => *total += time,
+            None => state.current.push((stage, worker, time)),
+        }
+    }
+
+    /// Run `f`, and record the time it took in `stage`.
+    pub fn measure<T>(&self, stage: CpuStage, f: impl FnOnce() -> T) -> T {
+        let started = Instant::now();
+        let result = f();
+        self.record(stage, started.elapsed());
+        result
+    }
+
+    /// End the current frame interval, and check the budgets over the window up to it.
+    pub fn frame_arrived(&self) {
+        let mut state = self.state.lock().unwrap();
+        let current = std::mem::take(&mut state.current);
+        for (stage, worker, time) in &current {
+            *state
+                .totals
+                .entry((*stage, *worker))
+                .or_insert(Duration::ZERO) += *time;
+        }
+        state.intervals.push_back(current);
+        if state.intervals.len() > state.config.window {
+            for (stage, worker, time) in state.intervals.pop_front().unwrap_or_default() {
+                let key = (stage, worker);
+                if let Some(total) = state.totals.get_mut(&key) {
+                    *total = total.saturating_sub(time);
+                    // Threads that have not run the stage over the window are left out
+                    if total.is_zero() {
+                        state.totals.remove(&key);
+                    }
+                }
+            }
+        }
+        state.frames += 1;
+        state.check_budgets();
+    }
+
+    pub fn report(&self) -> CpuReport {
+        let state = self.state.lock().unwrap();
+        CpuReport {
+            frames: state.frames,
+            window: state.intervals.len(),
+            frame_interval: state.config.frame_interval,
+            stages: CpuStage::ALL
+                .into_iter()
+                .map(|stage| state.stage_utilization(Some(stage), stage.index()))
+                .collect(),
+            total: state.stage_utilization(None, CpuStage::ALL.len()),
+        }
+    }
+
+    /// The events raised since the last call, oldest first.
+    pub fn take_events(&self) -> Vec<CpuEvent> {
+        std::mem::take(&mut self.state.lock().unwrap().events)
+    }
+
+    /// An input callback that passes everything to `primary`, counts each frame, and records
+    /// the time `primary` takes to handle it as the callback stage.
+    pub fn measured(&self, primary: Arc<dyn InputHandler>) -> Arc<dyn InputHandler> {
+        Arc::new(MeasuredInputCallback {
+            primary,
+            meter: self.clone(),
+        })
+    }
+}
+
+struct MeasuredInputCallback {
+    primary: Arc<dyn InputHandler>,
+    meter: CpuMeter,
+}
+
+impl InputHandler for MeasuredInputCallback {
+    fn format_changed(&self, change: &InputFormatChange) {
+        self.meter
+            .measure(CpuStage::Callback, || self.primary.format_changed(change));
+    }
+
+    fn frame_arrived(&self, arrival: &FrameArrival<'_>) -> CallbackResult {
+        if let Some(timing) = arrival.timing() {
+            if let Ok(interval) = Duration::try_from_secs_f64(timing.mode_duration.as_secs_f64()) {
+                self.meter.set_frame_interval(interval);
+            }
+        }
+        if arrival.video_frame.is_some() {
+            self.meter.frame_arrived();
+        }
+        self.meter
+            .measure(CpuStage::Callback, || self.primary.frame_arrived(arrival))
+    }
+
+    fn type_name(&self) -> &'static str {
+        self.primary.type_name()
+    }
+}
@@ -18,16 +18,95 @@
 //! // Use with DecklinkInputDevice::enable_video_input_with_allocator
 //! ```
 //!
+//! An application that recreates its CUDA context, such as after a GPU reset, invalidates
+//! the provider first, see [`CudaAllocatorProvider`].
+//!
 //! Requires the `cuda` feature.
 
 use crate::allocator::{
-    BufferSpec, VideoBuffer, VideoBufferAllocator, VideoBufferAllocatorProvider,
+    push_event, AllocatorEvent, BufferSpec, VideoBuffer, VideoBufferAllocator,
+    VideoBufferAllocatorProvider,
 };
+use crate::frame::{pixel_group, DecklinkFrameBase};
 use crate::SdkError;
-use cudarc::driver::CudaContext;
+use cudarc::driver::{sys, CudaContext, CudaStream};
 use std::ffi::c_void;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
 
+/// The error the buffers and allocators of an invalidated [`CudaAllocatorProvider`] fail
+/// with, so that the driver fails the capture rather than writing into memory of a destroyed
+/// context.
+pub const GPU_LOST: SdkError = SdkError::ABORT;
+
+/// The CUDA driver calls that pinned buffers are allocated and freed with.
+///
+/// `CudaContext` implements it. Tests implement it over ordinary memory, to check
+/// invalidation and recovery without a GPU.
+pub trait PinnedMemory: Send + Sync {
+    /// Allocate `size` bytes of pinned host memory.
+    fn alloc_host(&self, size: usize) -> Result<*mut c_void, SdkError>;
+
+    /// Free memory that `alloc_host` gave.
+    ///
+    /// # Safety
+    /// `ptr` must have come from `alloc_host` of the same memory, and not be freed already.
+    unsafe fn free_host(&self, ptr: *mut c_void);
+}
+
+impl PinnedMemory for CudaContext {
+    /// Uses `CU_MEMHOSTALLOC_PORTABLE` so the memory is portable across CUDA
+    /// contexts and readable/writable from both host and device sides (unlike
+    /// `WRITECOMBINED` which penalises host reads).
+    fn alloc_host(&self, size: usize) -> Result<*mut c_void, SdkError> {
+        self.bind_to_thread().map_err(|_| SdkError::FAIL)?;
+        let ptr = unsafe {
+            cudarc::driver::result::malloc_host(size, cudarc::driver::sys::CU_MEMHOSTALLOC_PORTABLE)
+        }
+        .map_err(|_| SdkError::OUTOFMEMORY)?;
+        if ptr.is_null() {
+            return Err(SdkError::OUTOFMEMORY);
+        }
+        Ok(ptr)
+    }
+
+    unsafe fn free_host(&self, ptr: *mut c_void) {
+        let _ = self.bind_to_thread();
+        let _ = cudarc::driver::result::free_host(ptr);
+    }
+}
+
+/// What the allocators and buffers of one provider share.
+struct PinnedPool {
+    /// Keeps the CUDA context alive while buffers from it are.
+    memory: Arc<dyn PinnedMemory>,
+    invalidated: AtomicBool,
+    allocators: AtomicUsize,
+    buffers: AtomicUsize,
+    leaked_buffers: AtomicU64,
+}
+
+impl PinnedPool {
+    fn new(memory: Arc<dyn PinnedMemory>) -> Arc<PinnedPool> {
+        Arc::new(PinnedPool {
+            memory,
+            invalidated: AtomicBool::new(false),
+            allocators: AtomicUsize::new(0),
+            buffers: AtomicUsize::new(0),
+            leaked_buffers: AtomicU64::new(0),
+        })
+    }
+
+    fn check(&self) -> Result<(), SdkError> {
+        if self.invalidated.load(Ordering::SeqCst) {
+            Err(GPU_LOST)
+        } else {
+            Ok(())
+        }
+    }
+}
+
 /// A video buffer backed by CUDA pinned (page-locked) host memory.
 ///
 /// DeckLink writes frame data here via DMA. The memory is pinned, so it can
@@ -37,8 +116,7 @@ pub struct CudaPinnedBuffer {
     ptr: *mut c_void,
     /// Size of the allocation in bytes.
     size: usize,
-    /// Keep the CUDA context alive for the lifetime of the buffer.
-    _ctx: Arc<CudaContext>,
+    pool: Arc<PinnedPool>,
 }
 
 // Safety: The pinned memory pointer is valid from any thread.
@@ -47,24 +125,15 @@ unsafe impl Sync for CudaPinnedBuffer {}
 
 impl CudaPinnedBuffer {
     /// Allocate a new buffer of `size` bytes in CUDA pinned host memory.
-    ///
-    /// Uses `CU_MEMHOSTALLOC_PORTABLE` so the memory is portable across CUDA
-    /// contexts and readable/writable from both host and device sides (unlike
-    /// `WRITECOMBINED` which penalises host reads).
     pub fn new(ctx: Arc<CudaContext>, size: usize) -> Result<Self, SdkError> {
-        ctx.bind_to_thread().map_err(|_| SdkError::FAIL)?;
-        let ptr = unsafe {
-            cudarc::driver::result::malloc_host(size, cudarc::driver::sys::CU_MEMHOSTALLOC_PORTABLE)
-        }
-        .map_err(|_| SdkError::OUTOFMEMORY)?;
-        if ptr.is_null() {
-            return Err(SdkError::OUTOFMEMORY);
-        }
-        Ok(Self {
-            ptr,
-            size,
-            _ctx: ctx,
-        })
+        Self::in_pool(PinnedPool::new(ctx), size)
+    }
+
+    fn in_pool(pool: Arc<PinnedPool>, size: usize) -> Result<Self, SdkError> {
+        pool.check()?;
+        let ptr = pool.memory.alloc_host(size)?;
+        pool.buffers.fetch_add(1, Ordering::SeqCst);
+        Ok(Self { ptr, size, pool })
     }
 
     /// Get a raw pointer to the pinned memory.
@@ -81,43 +150,89 @@ impl CudaPinnedBuffer {
     pub fn is_empty(&self) -> bool {
         self.size == 0
     }
+
+    /// Whether the provider the buffer came from was invalidated, so its memory must not
+    /// be used.
+    pub fn is_orphaned(&self) -> bool {
+        self.pool.invalidated.load(Ordering::SeqCst)
+    }
 }
 
 impl Drop for CudaPinnedBuffer {
     fn drop(&mut self) {
-        if !self.ptr.is_null() {
-            let _ = self._ctx.bind_to_thread();
-            unsafe {
-                let _ = cudarc::driver::result::free_host(self.ptr);
-            }
-            self.ptr = std::ptr::null_mut();
+        self.pool.buffers.fetch_sub(1, Ordering::SeqCst);
+        if self.ptr.is_null() {
+            return;
+        }
+        if self.is_orphaned() {
+            // Freeing into a destroyed context is undefined, so the memory is leaked
+            self.pool.leaked_buffers.fetch_add(1, Ordering::Relaxed);
+            push_event(AllocatorEvent::BufferLeaked {
+                bytes: self.size as u64,
+            });
+        } else {
+            unsafe { self.pool.memory.free_host(self.ptr) };
         }
+        self.ptr = std::ptr::null_mut();
     }
 }
 
 impl VideoBuffer for CudaPinnedBuffer {
     fn get_bytes(&self) -> Result<*mut c_void, SdkError> {
+        self.pool.check()?;
         if self.ptr.is_null() {
             Err(SdkError::POINTER)
         } else {
             Ok(self.ptr)
         }
     }
+
+    fn start_access(&self, _flags: u32) -> Result<(), SdkError> {
+        self.pool.check()
+    }
 }
 
 /// A video buffer allocator that creates CUDA pinned host memory buffers.
 struct CudaPinnedAllocator {
-    ctx: Arc<CudaContext>,
+    pool: Arc<PinnedPool>,
     buffer_size: usize,
 }
 
 impl VideoBufferAllocator for CudaPinnedAllocator {
     fn allocate(&self) -> Result<Box<dyn VideoBuffer>, SdkError> {
-        let buf = CudaPinnedBuffer::new(self.ctx.clone(), self.buffer_size)?;
+        let buf = CudaPinnedBuffer::in_pool(self.pool.clone(), self.buffer_size)?;
         Ok(Box::new(buf))
     }
 }
 
+impl Drop for CudaPinnedAllocator {
+    fn drop(&mut self) {
+        self.pool.allocators.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Why [`CudaAllocatorProvider::invalidate`] refused.
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub enum InvalidateError {
+    /// The driver still holds `allocators` allocators of the provider, as an input enabled
+    /// with it has not been disabled.
+    InUse { allocators: usize },
+}
+
+impl fmt::Display for InvalidateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InvalidateError::InUse { allocators } => write!(
+                f,
+                "{} allocators of the provider are still in use, disable the inputs first",
+                allocators
+            ),
+        }
+    }
+}
+
+impl std::error::Error for InvalidateError {}
+
 /// Allocator provider that creates CUDA pinned (page-locked) host memory
 /// buffers for DeckLink video capture.
 ///
@@ -126,6 +241,25 @@ impl VideoBufferAllocator for CudaPinnedAllocator {
 /// memory can then be efficiently copied to GPU device memory using
 /// `cuMemcpyHtoDAsync` or accessed directly via zero-copy if the GPU supports it.
 ///
+/// Each spec gets its own allocator, and each buffer is freed when the driver releases it,
+/// so buffers of an older spec of the same mode stay valid while frames in them are held.
+///
+/// # Recovering from a GPU reset
+///
+/// An application that destroys and recreates its CUDA context, such as after a GPU reset,
+/// must not free pinned memory into the destroyed context, nor let the driver capture into
+/// it. To recover:
+///
+/// 1. Stop the streams of every input enabled with the provider.
+/// 2. Disable their video input, which releases the provider's allocators.
+/// 3. Call `invalidate`, which fails with `InvalidateError::InUse` until step 2 is done.
+/// 4. Destroy the context, and create a new one and a new provider for it.
+/// 5. Enable video input with the new provider, and start the streams again.
+///
+/// Frames still held from before the reset keep their buffers, which fail with
+/// [`GPU_LOST`] from then on, and are leaked rather than freed when dropped. Each leak is
+/// counted by `leaked_buffer_count` and reported as an `AllocatorEvent::BufferLeaked`.
+///
 /// # Example
 ///
 /// ```no_run
@@ -139,21 +273,241 @@ impl VideoBufferAllocator for CudaPinnedAllocator {
 /// // input_device.enable_video_input_with_allocator(mode, pixel_format, flags, provider)?;
 /// ```
 pub struct CudaAllocatorProvider {
-    ctx: Arc<CudaContext>,
+    pool: Arc<PinnedPool>,
 }
 
 impl CudaAllocatorProvider {
     /// Create a new CUDA allocator provider using the given CUDA context.
     pub fn new(ctx: Arc<CudaContext>) -> Self {
-        Self { ctx }
+        Self::with_memory(ctx)
+    }
+
+    /// Create a provider allocating from `memory`, such as a stand-in for a GPU in tests.
+    pub fn with_memory(memory: Arc<dyn PinnedMemory>) -> Self {
+        Self {
+            pool: PinnedPool::new(memory),
+        }
+    }
+
+    /// Orphan every buffer of the provider, before its CUDA context is destroyed.
+    ///
+    /// From then on, the provider and its buffers fail with [`GPU_LOST`], and buffers
+    /// are leaked rather than freed when dropped. Fails if an input still holds allocators
+    /// of the provider. See the recovery steps above.
+    pub fn invalidate(&self) -> Result<(), InvalidateError> {
+        let allocators = self.pool.allocators.load(Ordering::SeqCst);
+        if allocators > 0 {
+            return Err(InvalidateError::InUse { allocators });
+        }
+        self.pool.invalidated.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// Whether `invalidate` has been called.
+    pub fn is_invalidated(&self) -> bool {
+        self.pool.invalidated.load(Ordering::SeqCst)
+    }
+
+    /// The number of buffers of the provider that have not been dropped, which are leaked
+    /// when dropped after `invalidate`.
+    pub fn live_buffer_count(&self) -> usize {
+        self.pool.buffers.load(Ordering::SeqCst)
+    }
+
+    /// The number of buffers dropped after `invalidate`, whose memory was leaked.
+    pub fn leaked_buffer_count(&self) -> u64 {
+        self.pool.leaked_buffers.load(Ordering::Relaxed)
     }
 }
 
 impl VideoBufferAllocatorProvider for CudaAllocatorProvider {
     fn get_allocator(&self, spec: BufferSpec) -> Result<Arc<dyn VideoBufferAllocator>, SdkError> {
+        self.pool.check()?;
+        self.pool.allocators.fetch_add(1, Ordering::SeqCst);
         Ok(Arc::new(CudaPinnedAllocator {
-            ctx: self.ctx.clone(),
+            pool: self.pool.clone(),
             buffer_size: spec.buffer_size as usize,
         }))
     }
 }
+
+/// How [`copy_frame_to_device`] lays out a frame in device memory.
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub enum CudaCopyLayout {
+    /// Copy rows including any padding, so the device pitch is the frame's `row_bytes`.
+    Pitched,
+    /// Strip row padding, so the device pitch is the shortest the pixel format allows.
+    Packed,
+}
+
+/// Tells when the GPU has finished a copy, so that its source may be released.
+///
+/// [`CudaCopyEvent`] is the completion [`copy_frame_to_device`] uses. Another completion can
+/// hold a frame in a [`CudaCopyTicket`] the same way.
+pub trait CopyCompletion {
+    /// Returns true if the copy has completed.
+    fn is_complete(&self) -> Result<bool, SdkError>;
+    /// Block until the copy has completed.
+    fn synchronize(&self) -> Result<(), SdkError>;
+}
+
+/// A CUDA event recorded on a stream after a copy. It is destroyed on drop.
+pub struct CudaCopyEvent(sys::CUevent);
+
+impl CopyCompletion for CudaCopyEvent {
+    fn is_complete(&self) -> Result<bool, SdkError> {
+        match unsafe { cudarc::driver::result::event::query(self.0) } {
+            Ok(()) => Ok(true),
+            Err(cudarc::driver::DriverError(sys::CUresult::CUDA_ERROR_NOT_READY)) => Ok(false),
+            Err(_) => Err(SdkError::FAIL),
+        }
+    }
+
+    fn synchronize(&self) -> Result<(), SdkError> {
+        unsafe { cudarc::driver::result::event::synchronize(self.0) }.map_err(|_| SdkError::FAIL)
+    }
+}
+
+impl Drop for CudaCopyEvent {
+    fn drop(&mut self) {
+        let _ = unsafe { cudarc::driver::result::event::destroy(self.0) };
+    }
+}
+
+/// An in-flight copy of a frame into device memory, started by [`copy_frame_to_device`].
+///
+/// The ticket keeps the frame alive until the copy has completed. Dropping the ticket
+/// before then blocks until the copy finishes, so the frame is never released while the
+/// GPU is still reading from it.
+pub struct CudaCopyTicket<F: DecklinkFrameBase, C: CopyCompletion = CudaCopyEvent> {
+    frame: Option<F>,
+    completion: C,
+    pitch: usize,
+    byte_count: usize,
+}
+
+impl<F: DecklinkFrameBase, C: CopyCompletion> CudaCopyTicket<F, C> {
+    /// Hold `frame` until `completion` fires, for a copy of `byte_count` bytes in rows of
+    /// `pitch` bytes.
+    pub fn new(frame: F, completion: C, pitch: usize, byte_count: usize) -> Self {
+        CudaCopyTicket {
+            frame: Some(frame),
+            completion,
+            pitch,
+            byte_count,
+        }
+    }
+
+    /// The pitch of the copied rows in device memory.
+    pub fn pitch(&self) -> usize {
+        self.pitch
+    }
+
+    /// The number of bytes written to device memory.
+    pub fn byte_count(&self) -> usize {
+        self.byte_count
+    }
+
+    /// Returns true if the copy has completed.
+    pub fn is_complete(&self) -> Result<bool, SdkError> {
+        self.completion.is_complete()
+    }
+
+    /// Block until the copy has completed, and return the frame.
+    pub fn synchronize(mut self) -> Result<F, SdkError> {
+        self.completion.synchronize()?;
+        self.frame.take().ok_or(SdkError::HANDLE)
+    }
+}
+
+impl<F: DecklinkFrameBase, C: CopyCompletion> Drop for CudaCopyTicket<F, C> {
+    fn drop(&mut self) {
+        if self.frame.is_some() {
+            let _ = self.completion.synchronize();
+            self.frame = None;
+        }
+    }
+}
+
+/// Copy a frame into device memory at `dst`, which is `dst_len` bytes long, ordered on `stream`.
+///
+/// The copy is asynchronous: the frame is held by the returned ticket until the copy has
+/// completed. Frames captured into pinned memory (see [`CudaAllocatorProvider`]) are copied
+/// without staging through pageable memory.
+///
+/// # Safety
+/// `dst` must be a valid device allocation of at least `dst_len` bytes, which is not
+/// accessed by anything else until the copy has completed.
+pub unsafe fn copy_frame_to_device<F: DecklinkFrameBase>(
+    frame: F,
+    dst: sys::CUdeviceptr,
+    dst_len: usize,
+    stream: &CudaStream,
+    layout: CudaCopyLayout,
+) -> Result<CudaCopyTicket<F>, SdkError> {
+    let height = frame.height();
+    let row_bytes = frame.row_bytes();
+    let pitch = match layout {
+        CudaCopyLayout::Pitched => row_bytes,
+        CudaCopyLayout::Packed => {
+            let (group_pixels, group_bytes) =
+                pixel_group(frame.pixel_format()).ok_or(SdkError::NOTIMPL)?;
+            frame.width().div_ceil(group_pixels) * group_bytes
+        }
+    }
+    .min(row_bytes);
+
+    let byte_count = pitch * height;
+    if dst_len < byte_count {
+        return Err(SdkError::INVALIDARG);
+    }
+
+    stream
+        .context()
+        .bind_to_thread()
+        .map_err(|_| SdkError::FAIL)?;
+    let cu_stream = stream.cu_stream();
+
+    {
+        let bytes = frame.bytes()?;
+        if bytes.0.len() < row_bytes * height {
+            return Err(SdkError::INVALIDARG);
+        }
+
+        if pitch == row_bytes {
+            cudarc::driver::result::memcpy_htod_async(dst, &bytes.0[..byte_count], cu_stream)
+                .map_err(|_| SdkError::FAIL)?;
+        } else {
+            let params = sys::CUDA_MEMCPY2D {
+                srcMemoryType: sys::CUmemorytype::CU_MEMORYTYPE_HOST,
+                srcHost: bytes.0.as_ptr() as *const c_void,
+                srcPitch: row_bytes,
+                dstMemoryType: sys::CUmemorytype::CU_MEMORYTYPE_DEVICE,
+                dstDevice: dst,
+                dstPitch: pitch,
+                WidthInBytes: pitch,
+                Height: height,
+                ..std::mem::zeroed()
+            };
+            sys::cuMemcpy2DAsync_v2(&params, cu_stream)
+                .result()
+                .map_err(|_| SdkError::FAIL)?;
+        }
+    }
+
+    let event = cudarc::driver::result::event::create(sys::CUevent_flags::CU_EVENT_DISABLE_TIMING)
+        .map_err(|_| SdkError::FAIL)?;
+    if cudarc::driver::result::event::record(event, cu_stream).is_err() {
+        // The copy is already queued, so it must finish before the frame can be released
+        let _ = cudarc::driver::result::stream::synchronize(cu_stream);
+        let _ = cudarc::driver::result::event::destroy(event);
+        return Err(SdkError::FAIL);
+    }
+
+    Ok(CudaCopyTicket::new(
+        frame,
+        CudaCopyEvent(event),
+        pitch,
+        byte_count,
+    ))
+}
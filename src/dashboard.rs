@@ -0,0 +1,383 @@
+//! Keeping a summary of a device's status up to date for display.
+//!
+//! A `DeviceMonitor` polls a device on its own thread and keeps a `DeviceDashboard` current.
+//! The callback is only invoked when something changes, and a value has to hold for the
+//! debounce period before it is reported, so a flapping signal does not flood a UI.
+
+use crate::device::attributes::DecklinkProfileId;
+use crate::device::custom_id::{read_custom_ids, CustomReading};
+use crate::device::registry::DeviceRegistry;
+use crate::device::status::DecklinkDeviceBusyState;
+use crate::device::DecklinkDevice;
+use crate::display_mode::DecklinkDisplayModeId;
+use crate::threads::{self, ThreadHandle};
+use crate::SdkError;
+use std::sync::{Arc, Condvar, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+/// The status of a device. Fields are `None` when the device does not report them, or
+/// while the device is not present.
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct DeviceDashboard {
+    /// Whether the device is currently attached.
+    pub present: bool,
+    pub label: Option<String>,
+    pub signal_locked: Option<bool>,
+    pub detected_mode: Option<DecklinkDisplayModeId>,
+    pub reference_locked: Option<bool>,
+    pub busy: Option<DecklinkDeviceBusyState>,
+    /// The on-board temperature in degrees Celsius.
+    pub temperature: Option<i64>,
+    pub profile: Option<DecklinkProfileId>,
+    /// The ids registered with `crate::device::custom_id::register_custom_id`.
+    pub custom: Vec<CustomReading>,
+}
+
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+pub enum DashboardField {
+    Present,
+    Label,
+    SignalLocked,
+    DetectedMode,
+    ReferenceLocked,
+    Busy,
+    Temperature,
+    Profile,
+    Custom,
+}
+
+impl DeviceDashboard {
+    /// The fields that differ between `self` and `other`.
+    pub fn changed_fields(&self, other: &DeviceDashboard) -> Vec<DashboardField> {
+        let mut changed = Vec::new();
+        if self.present != other.present {
+            changed.push(DashboardField::Present);
+        }
+        if self.label != other.label {
+            changed.push(DashboardField::Label);
+        }
+        if self.signal_locked != other.signal_locked {
+            changed.push(DashboardField::SignalLocked);
+        }
+        if self.detected_mode != other.detected_mode {
+            changed.push(DashboardField::DetectedMode);
+        }
+        if self.reference_locked != other.reference_locked {
+            changed.push(DashboardField::ReferenceLocked);
+        }
+        if self.busy != other.busy {
+            changed.push(DashboardField::Busy);
+        }
+        if self.temperature != other.temperature {
+            changed.push(DashboardField::Temperature);
+        }
+        if self.profile != other.profile {
+            changed.push(DashboardField::Profile);
+        }
+        if self.custom != other.custom {
+            changed.push(DashboardField::Custom);
+        }
+        changed
+    }
+
+    /// Read the current status of `device`.
+    pub fn read(device: &DecklinkDevice) -> Result<DeviceDashboard, SdkError> {
+        let status = device.get_status()?;
+        let attributes = device.get_attributes().ok();
+        let custom = read_custom_ids(attributes.as_ref(), Some(&status));
+
+        Ok(DeviceDashboard {
+            present: true,
+            label: device.display_name(),
+            signal_locked: status.video_input_signal_locked().ok(),
+            detected_mode: status.detected_video_input_mode().ok(),
+            reference_locked: status.reference_signal_locked().ok(),
+            busy: status.busy_state().ok(),
+            temperature: status.device_temperature().ok(),
+            profile: attributes.and_then(|a| a.profile_id().ok()),
+            custom,
+        })
+    }
+}
+
+/// A change to the dashboard.
+#[derive(PartialEq, Debug, Clone)]
+pub struct DashboardDelta {
+    pub changed: Vec<DashboardField>,
+    pub previous: DeviceDashboard,
+    pub current: DeviceDashboard,
+    /// Set if this change took the temperature across the configured threshold.
+    pub temperature_alert: Option<TemperatureAlert>,
+}
+
+/// A temperature limit to alert on.
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub struct TemperatureThreshold {
+    /// Alert once the temperature rises above this, in degrees Celsius.
+    pub limit_c: i64,
+    /// How far the temperature must fall below the limit before the alert clears, so a
+    /// temperature hovering at the limit does not alert repeatedly.
+    pub hysteresis_c: i64,
+}
+
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+pub enum TemperatureAlert {
+    /// The temperature rose above the limit.
+    OverLimit { temperature_c: i64 },
+    /// The temperature fell below the limit, less the hysteresis.
+    Recovered { temperature_c: i64 },
+}
+
+/// Tracks whether the temperature is over a threshold.
+struct TemperatureWatch {
+    threshold: TemperatureThreshold,
+    over: bool,
+}
+
+impl TemperatureWatch {
+    /// Returns an alert if `temperature` crosses the threshold. Unknown temperatures leave
+    /// the state unchanged.
+    fn update(&mut self, temperature: Option<i64>) -> Option<TemperatureAlert> {
+        let temperature_c = temperature?;
+        if !self.over && temperature_c > self.threshold.limit_c {
+            self.over = true;
+            Some(TemperatureAlert::OverLimit { temperature_c })
+        } else if self.over
+            && temperature_c <= self.threshold.limit_c - self.threshold.hysteresis_c.max(0)
+        {
+            self.over = false;
+            Some(TemperatureAlert::Recovered { temperature_c })
+        } else {
+            None
+        }
+    }
+}
+
+/// How long polling the device takes.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Default)]
+pub struct PollCost {
+    pub polls: u64,
+    pub last: Duration,
+    pub max: Duration,
+    pub total: Duration,
+}
+
+impl PollCost {
+    pub fn average(&self) -> Duration {
+        if self.polls == 0 {
+            Duration::ZERO
+        } else {
+            self.total / self.polls as u32
+        }
+    }
+}
+
+/// Applies the debounce to polled dashboards.
+struct Debouncer {
+    debounce: Duration,
+    committed: DeviceDashboard,
+    pending: Option<(DeviceDashboard, Instant)>,
+}
+
+impl Debouncer {
+    /// Returns a delta once `sample` has held for the debounce period.
+    fn sample(&mut self, sample: DeviceDashboard, now: Instant) -> Option<DashboardDelta> {
+        if sample == self.committed {
+            self.pending = None;
+            return None;
+        }
+
+        let since = match &self.pending {
+            Some((pending, since)) if *pending == sample => *since,
+            _ => {
+                self.pending = Some((sample.clone(), now));
+                now
+            }
+        };
+        if now.duration_since(since) < self.debounce {
+            return None;
+        }
+
+        self.pending = None;
+        let previous = std::mem::replace(&mut self.committed, sample);
+        Some(DashboardDelta {
+            changed: previous.changed_fields(&self.committed),
+            previous,
+            current: self.committed.clone(),
+            temperature_alert: None,
+        })
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub struct DeviceMonitorConfig {
+    /// How often to poll the device.
+    pub interval: Duration,
+    /// How long a change must hold before it is reported.
+    pub debounce: Duration,
+    /// A temperature to alert on. Alerts are raised on the reported, debounced, temperature,
+    /// so a device that is already over the limit alerts with the first change reported.
+    pub temperature_threshold: Option<TemperatureThreshold>,
+}
+
+impl Default for DeviceMonitorConfig {
+    fn default() -> Self {
+        DeviceMonitorConfig {
+            interval: Duration::from_millis(500),
+            debounce: Duration::from_secs(1),
+            temperature_threshold: None,
+        }
+    }
+}
+
+struct MonitorShared {
+    stop: Mutex<bool>,
+    wake: Condvar,
+    cost: Mutex<PollCost>,
+}
+
+/// Polls a device on a background thread, keeping a `DeviceDashboard` current.
+///
+/// The device is found again by its handle each time it arrives, so the monitor keeps
+/// working if it is removed and reattached. While it is absent, `present` is false and every
+/// other field is `None`. The thread is stopped when the monitor is dropped.
+pub struct DeviceMonitor {
+    dashboard: Arc<RwLock<DeviceDashboard>>,
+    shared: Arc<MonitorShared>,
+    thread: Option<ThreadHandle>,
+}
+
+/// Find the device by its handle, creating the registry on the first call so that it is owned
+/// by the monitor thread.
+fn find_device(registry: &mut Option<DeviceRegistry>, handle: &str) -> Option<Arc<DecklinkDevice>> {
+    match registry {
+        Some(registry) => {
+            registry.refresh().ok()?;
+        }
+        None => *registry = Some(DeviceRegistry::new().ok()?),
+    }
+    registry.as_ref()?.find_by_handle(handle)
+}
+
+impl DeviceMonitor {
+    /// Start monitoring `device`, calling `on_change` from the monitor thread with each
+    /// change.
+    pub fn new<F>(
+        device: &DecklinkDevice,
+        config: DeviceMonitorConfig,
+        mut on_change: F,
+    ) -> Result<DeviceMonitor, SdkError>
+    where
+        F: FnMut(&DashboardDelta) + Send + 'static,
+    {
+        let handle = device.get_attributes()?.device_handle()?;
+        let initial = DeviceDashboard::read(device)?;
+
+        let dashboard = Arc::new(RwLock::new(initial.clone()));
+        let shared = Arc::new(MonitorShared {
+            stop: Mutex::new(false),
+            wake: Condvar::new(),
+            cost: Mutex::new(PollCost::default()),
+        });
+
+        let thread = {
+            let dashboard = dashboard.clone();
+            let shared = shared.clone();
+            threads::spawn("monitor", move || {
+                let mut debouncer = Debouncer {
+                    debounce: config.debounce,
+                    committed: initial,
+                    pending: None,
+                };
+                let mut temperature_watch =
+                    config
+                        .temperature_threshold
+                        .map(|threshold| TemperatureWatch {
+                            threshold,
+                            over: false,
+                        });
+                // The device is opened on this thread, as it cannot be sent between threads
+                let mut registry = None;
+                let mut device = None;
+
+                loop {
+                    let start = Instant::now();
+                    // With discovery notifications a refresh costs nothing until a device
+                    // arrives or is removed, so the removal is seen before the next read
+                    if device.is_none()
+                        || registry
+                            .as_ref()
+                            .is_some_and(DeviceRegistry::is_event_driven)
+                    {
+                        device = find_device(&mut registry, &handle);
+                    }
+                    let sample = match device.as_deref().map(DeviceDashboard::read) {
+                        Some(Ok(sample)) => sample,
+                        Some(Err(_)) => {
+                            // The device has gone away
+                            device = None;
+                            DeviceDashboard::default()
+                        }
+                        None => DeviceDashboard::default(),
+                    };
+                    let elapsed = start.elapsed();
+                    {
+                        let mut cost = shared.cost.lock().unwrap();
+                        cost.polls += 1;
+                        cost.last = elapsed;
+                        cost.max = cost.max.max(elapsed);
+                        cost.total += elapsed;
+                    }
+
+                    if let Some(mut delta) = debouncer.sample(sample, Instant::now()) {
+                        if let Some(watch) = temperature_watch.as_mut() {
+                            delta.temperature_alert = watch.update(delta.current.temperature);
+                        }
+                        *dashboard.write().unwrap() = delta.current.clone();
+                        on_change(&delta);
+                    }
+
+                    let stop = shared.stop.lock().unwrap();
+                    let (stop, _) = shared
+                        .wake
+                        .wait_timeout_while(stop, config.interval, |stop| !*stop)
+                        .unwrap();
+                    if *stop {
+                        break;
+                    }
+                }
+            })
+        };
+
+        Ok(DeviceMonitor {
+            dashboard,
+            shared,
+            thread: Some(thread),
+        })
+    }
+
+    /// The latest reported dashboard.
+    pub fn current(&self) -> DeviceDashboard {
+        self.dashboard.read().unwrap().clone()
+    }
+
+    /// The shared dashboard, for reading without copying.
+    pub fn dashboard(&self) -> Arc<RwLock<DeviceDashboard>> {
+        self.dashboard.clone()
+    }
+
+    /// How long polling has taken so far, to help choose the poll interval.
+    pub fn poll_cost(&self) -> PollCost {
+        *self.shared.cost.lock().unwrap()
+    }
+}
+
+impl Drop for DeviceMonitor {
+    fn drop(&mut self) {
+        *self.shared.stop.lock().unwrap() = true;
+        self.shared.wake.notify_all();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
@@ -0,0 +1,124 @@
+//! Live object accounting for the wrappers this crate creates around C objects.
+//!
+//! Enabled by the `leak-check` feature. Every wrapper around a C object (devices, input
+//! devices, callback wrappers, allocator providers/allocators/buffers, frames, display modes,
+//! and the timecodes and ancillary packets read from frames) registers itself when created and
+//! unregisters when dropped.
+//!
+//! Downstream users can call [`assert_no_leaks`] at the end of their own integration tests,
+//! once every device has been dropped, to catch misuse such as frames being retained after
+//! the device that produced them has gone away.
+//!
+//! ```no_run
+//! # fn run_capture() {}
+//! run_capture();
+//! if let Err(report) = decklink::debug::assert_no_leaks() {
+//!     panic!("{}", report);
+//! }
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Mutex;
+
+/// The name of a tracked wrapper type.
+pub type TypeName = &'static str;
+
+struct LiveObject {
+    #[cfg(debug_assertions)]
+    backtrace: std::backtrace::Backtrace,
+}
+
+type Registry = HashMap<(TypeName, usize), Vec<LiveObject>>;
+
+static REGISTRY: Mutex<Option<Registry>> = Mutex::new(None);
+
+fn with_registry<T, F: FnOnce(&mut Registry) -> T>(f: F) -> T {
+    // A panic while holding the lock must not stop the accounting
+    let mut locked = REGISTRY.lock().unwrap_or_else(|e| e.into_inner());
+    f(locked.get_or_insert_with(HashMap::new))
+}
+
+pub(crate) fn track_created<T>(type_name: TypeName, ptr: *const T) {
+    let object = LiveObject {
+        #[cfg(debug_assertions)]
+        backtrace: std::backtrace::Backtrace::force_capture(),
+    };
+    with_registry(|r| {
+        r.entry((type_name, ptr as usize)).or_default().push(object);
+    });
+}
+
+pub(crate) fn track_dropped<T>(type_name: TypeName, ptr: *const T) {
+    with_registry(|r| {
+        let key = (type_name, ptr as usize);
+        if let Some(objects) = r.get_mut(&key) {
+            objects.pop();
+            if objects.is_empty() {
+                r.remove(&key);
+            }
+        }
+    });
+}
+
+/// Get the number of live objects of each tracked type, sorted by type name.
+pub fn live_objects() -> Vec<(TypeName, usize)> {
+    let mut counts: HashMap<TypeName, usize> = HashMap::new();
+    with_registry(|r| {
+        for ((type_name, _), objects) in r.iter() {
+            *counts.entry(*type_name).or_default() += objects.len();
+        }
+    });
+
+    let mut res: Vec<_> = counts.into_iter().collect();
+    res.sort();
+    res
+}
+
+/// A report of the objects that were still alive when `assert_no_leaks` was called.
+#[derive(Debug, Clone)]
+pub struct LeakReport {
+    /// The number of live objects of each type.
+    pub objects: Vec<(TypeName, usize)>,
+    /// The creation backtrace of each live object. Only populated in debug builds.
+    pub backtraces: Vec<(TypeName, String)>,
+}
+
+impl fmt::Display for LeakReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "decklink objects are still alive:")?;
+        for (type_name, count) in &self.objects {
+            writeln!(f, "  {}: {}", type_name, count)?;
+        }
+        for (type_name, backtrace) in &self.backtraces {
+            writeln!(f, "\n{} created at:\n{}", type_name, backtrace)?;
+        }
+        Ok(())
+    }
+}
+
+/// Check that no wrapped C objects are still alive.
+///
+/// Returns a `LeakReport` describing the live objects if there are any.
+pub fn assert_no_leaks() -> Result<(), LeakReport> {
+    let objects = live_objects();
+    if objects.is_empty() {
+        return Ok(());
+    }
+
+    #[allow(unused_mut)]
+    let mut backtraces = Vec::new();
+    #[cfg(debug_assertions)]
+    with_registry(|r| {
+        for ((type_name, _), live) in r.iter() {
+            for object in live {
+                backtraces.push((*type_name, object.backtrace.to_string()));
+            }
+        }
+    });
+
+    Err(LeakReport {
+        objects,
+        backtraces,
+    })
+}
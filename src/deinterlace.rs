@@ -0,0 +1,314 @@
+//! Handling the two fields of interlaced video.
+//!
+//! Interlaced display modes deliver each frame with its two fields woven together, one on
+//! the even rows and one on the odd rows, captured half a frame apart. Showing such a frame
+//! as a still image gives combing on anything that moves. These helpers split a frame into
+//! its fields in temporal order, or line double one field into a progressive image.
+//!
+//! Only the 8-bit packed pixel formats are supported, as their rows can be blended byte by
+//! byte.
+
+use crate::display_mode::DecklinkFieldDominance;
+use crate::frame::{DecklinkFrameBase, DecklinkPixelFormat, DecklinkVideoMutableFrame};
+use crate::memcopy::{copy_frame, CopyHint, CopyLayout};
+use crate::SdkError;
+
+/// One of the two fields of an interlaced frame.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+pub enum Field {
+    /// The field on the even rows, counting from zero.
+    Upper,
+    /// The field on the odd rows.
+    Lower,
+}
+
+impl Field {
+    pub(crate) fn first_row(self) -> usize {
+        match self {
+            Field::Upper => 0,
+            Field::Lower => 1,
+        }
+    }
+}
+
+/// The layout of a frame buffer.
+#[derive(PartialEq, Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FrameLayout {
+    pub width: usize,
+    pub height: usize,
+    pub row_bytes: usize,
+    pub pixel_format: DecklinkPixelFormat,
+}
+
+impl FrameLayout {
+    /// The layout of `frame`.
+    pub fn of(frame: &dyn DecklinkFrameBase) -> FrameLayout {
+        FrameLayout {
+            width: frame.width(),
+            height: frame.height(),
+            row_bytes: frame.row_bytes(),
+            pixel_format: frame.pixel_format(),
+        }
+    }
+
+    fn byte_count(&self) -> usize {
+        self.row_bytes * self.height
+    }
+}
+
+#[derive(Debug)]
+pub enum DeinterlaceError {
+    /// Only 8-bit packed formats can be deinterlaced.
+    UnsupportedPixelFormat(DecklinkPixelFormat),
+    /// The field order is unknown, so fields cannot be put in temporal order.
+    UnknownFieldOrder,
+    /// A buffer is smaller than its layout requires.
+    BufferTooSmall,
+    Sdk(SdkError),
+}
+
+impl From<SdkError> for DeinterlaceError {
+    fn from(e: SdkError) -> Self {
+        DeinterlaceError::Sdk(e)
+    }
+}
+
+fn check_format(format: DecklinkPixelFormat) -> Result<(), DeinterlaceError> {
+    match format {
+        DecklinkPixelFormat::Format8BitYUV
+        | DecklinkPixelFormat::Format8BitARGB
+        | DecklinkPixelFormat::Format8BitBGRA => Ok(()),
+        _ => Err(DeinterlaceError::UnsupportedPixelFormat(format)),
+    }
+}
+
+fn check_buffer(bytes: &[u8], layout: &FrameLayout) -> Result<(), DeinterlaceError> {
+    check_format(layout.pixel_format)?;
+    if bytes.len() < layout.byte_count() {
+        Err(DeinterlaceError::BufferTooSmall)
+    } else {
+        Ok(())
+    }
+}
+
+/// A view of one field of a woven frame. Its rows are every other row of the frame.
+#[derive(Debug, Copy, Clone)]
+pub struct FieldView<'a> {
+    bytes: &'a [u8],
+    layout: FrameLayout,
+    field: Field,
+}
+
+impl<'a> FieldView<'a> {
+    pub fn field(&self) -> Field {
+        self.field
+    }
+    pub fn width(&self) -> usize {
+        self.layout.width
+    }
+    /// The number of rows in the field.
+    pub fn height(&self) -> usize {
+        (self.layout.height + 1 - self.field.first_row()) / 2
+    }
+    /// The byte count per row of the field.
+    pub fn row_bytes(&self) -> usize {
+        self.layout.row_bytes
+    }
+    /// The distance in bytes between the starts of consecutive field rows.
+    pub fn stride(&self) -> usize {
+        self.layout.row_bytes * 2
+    }
+    pub fn pixel_format(&self) -> DecklinkPixelFormat {
+        self.layout.pixel_format
+    }
+
+    /// Get row `index` of the field, or `None` if it is past the last row.
+    pub fn row(&self, index: usize) -> Option<&'a [u8]> {
+        if index >= self.height() {
+            return None;
+        }
+        let start = (index * 2 + self.field.first_row()) * self.layout.row_bytes;
+        self.bytes.get(start..start + self.layout.row_bytes)
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item = &'a [u8]> + '_ {
+        (0..self.height()).filter_map(move |i| self.row(i))
+    }
+}
+
+/// The field that is captured first for `dominance`. Progressive and segmented frames
+/// hold a single instant, so the upper field is treated as first.
+pub fn first_field(dominance: DecklinkFieldDominance) -> Option<Field> {
+    match dominance {
+        DecklinkFieldDominance::UpperFieldFirst
+        | DecklinkFieldDominance::ProgressiveFrame
+        | DecklinkFieldDominance::ProgressiveSegmentedFrame => Some(Field::Upper),
+        DecklinkFieldDominance::LowerFieldFirst => Some(Field::Lower),
+        DecklinkFieldDominance::Unknown => None,
+    }
+}
+
+/// Split a woven frame into its two fields, earliest first.
+pub fn split_fields<'a>(
+    frame_bytes: &'a [u8],
+    layout: FrameLayout,
+    dominance: DecklinkFieldDominance,
+) -> Result<(FieldView<'a>, FieldView<'a>), DeinterlaceError> {
+    check_buffer(frame_bytes, &layout)?;
+    let first = first_field(dominance).ok_or(DeinterlaceError::UnknownFieldOrder)?;
+    let second = match first {
+        Field::Upper => Field::Lower,
+        Field::Lower => Field::Upper,
+    };
+
+    let view = |field| FieldView {
+        bytes: frame_bytes,
+        layout,
+        field,
+    };
+    Ok((view(first), view(second)))
+}
+
+/// Line double one field of a woven frame into `out`, a full height progressive image with
+/// the same layout. Rows of the other field are replaced with the average of the field rows
+/// above and below them.
+pub fn bob(
+    frame_bytes: &[u8],
+    layout: FrameLayout,
+    field: Field,
+    out: &mut [u8],
+) -> Result<(), DeinterlaceError> {
+    check_buffer(frame_bytes, &layout)?;
+    if out.len() < layout.byte_count() {
+        return Err(DeinterlaceError::BufferTooSmall);
+    }
+
+    let row_bytes = layout.row_bytes;
+    let height = layout.height;
+    let parity = field.first_row();
+    let row = |y: usize| &frame_bytes[y * row_bytes..(y + 1) * row_bytes];
+
+    for y in 0..height {
+        let dst = &mut out[y * row_bytes..(y + 1) * row_bytes];
+        if y % 2 == parity {
+            dst.copy_from_slice(row(y));
+            continue;
+        }
+
+        let above = y.checked_sub(1);
+        let below = (y + 1 < height).then_some(y + 1);
+        match (above, below) {
+            (Some(above), Some(below)) => {
+                for ((d, a), b) in dst.iter_mut().zip(row(above)).zip(row(below)) {
+                    *d = (*a as u16 + *b as u16).div_ceil(2) as u8;
+                }
+            }
+            (Some(y), None) | (None, Some(y)) => dst.copy_from_slice(row(y)),
+            // A single row frame, from the other field
+            (None, None) => dst.copy_from_slice(row(y)),
+        }
+    }
+
+    Ok(())
+}
+
+/// Keep both fields woven together, copying the frame unchanged into `out`.
+pub fn weave(
+    frame_bytes: &[u8],
+    layout: FrameLayout,
+    out: &mut [u8],
+) -> Result<(), DeinterlaceError> {
+    check_buffer(frame_bytes, &layout)?;
+    let byte_count = layout.byte_count();
+    if out.len() < byte_count {
+        return Err(DeinterlaceError::BufferTooSmall);
+    }
+
+    copy_frame(
+        out,
+        frame_bytes,
+        CopyLayout::contiguous(byte_count),
+        CopyHint::WillReadSoon,
+    )
+    .map_err(|_| DeinterlaceError::BufferTooSmall)
+}
+
+/// How frames should be deinterlaced before being shown.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash, Default)]
+pub enum DeinterlacePolicy {
+    /// Leave frames woven.
+    #[default]
+    None,
+    /// Line double the upper field.
+    BobTopFirst,
+    /// Line double the lower field.
+    BobBottomFirst,
+    /// Line double the first field of interlaced modes, and leave progressive modes alone.
+    Auto,
+}
+
+impl DeinterlacePolicy {
+    /// The field to line double for a mode with `dominance`, or `None` to leave it woven.
+    pub fn field(&self, dominance: DecklinkFieldDominance) -> Option<Field> {
+        match self {
+            DeinterlacePolicy::None => None,
+            DeinterlacePolicy::BobTopFirst => Some(Field::Upper),
+            DeinterlacePolicy::BobBottomFirst => Some(Field::Lower),
+            DeinterlacePolicy::Auto => match dominance {
+                DecklinkFieldDominance::UpperFieldFirst => Some(Field::Upper),
+                DecklinkFieldDominance::LowerFieldFirst => Some(Field::Lower),
+                _ => None,
+            },
+        }
+    }
+
+    /// Apply the policy to `bytes` in place, using `scratch` as a working buffer.
+    pub fn apply_in_place(
+        &self,
+        bytes: &mut [u8],
+        layout: FrameLayout,
+        dominance: DecklinkFieldDominance,
+        scratch: &mut Vec<u8>,
+    ) -> Result<(), DeinterlaceError> {
+        if let Some(field) = self.field(dominance) {
+            scratch.resize(layout.byte_count(), 0);
+            bob(bytes, layout, field, scratch)?;
+            copy_frame(
+                bytes,
+                scratch,
+                CopyLayout::contiguous(scratch.len()),
+                CopyHint::WillReadSoon,
+            )
+            .map_err(|_| DeinterlaceError::BufferTooSmall)?;
+        }
+        Ok(())
+    }
+
+    /// Apply the policy to `frame`, giving a new frame. The copy is woven unchanged if the
+    /// policy does not deinterlace this mode.
+    pub fn apply(
+        &self,
+        frame: &dyn DecklinkFrameBase,
+        dominance: DecklinkFieldDominance,
+    ) -> Result<DecklinkVideoMutableFrame, DeinterlaceError> {
+        let layout = FrameLayout::of(frame);
+        let bytes = frame.bytes()?;
+
+        let mut out = vec![0; layout.byte_count()];
+        match self.field(dominance) {
+            Some(field) => bob(bytes.0, layout, field, &mut out)?,
+            None => weave(bytes.0, layout, &mut out)?,
+        }
+
+        let mut result = DecklinkVideoMutableFrame::create(
+            layout.width,
+            layout.height,
+            layout.row_bytes,
+            layout.pixel_format,
+            frame.flags(),
+        );
+        result.copy_bytes(&out)?;
+        Ok(result)
+    }
+}
@@ -1,61 +1,103 @@
 use crate::connectors::{
     DecklinkAudioConnection, DecklinkDeckControlConnection, DecklinkVideoConnection,
 };
+use crate::ptr::ProfileAttributesPtr;
 use crate::sdk::DecklinkAttributeID;
-use crate::util::{convert_and_release_c_string, convert_c_string};
 use crate::{sdk, SdkError};
-use std::ptr::{null, null_mut};
+use num_traits::FromPrimitive;
 
-pub struct DecklinkDeviceAttributes {
-    dev: *mut sdk::cdecklink_profile_attributes_t,
+#[derive(FromPrimitive, PartialEq, Eq, Debug, Copy, Clone, Hash)]
+pub enum DecklinkProfileId {
+    OneSubDeviceFullDuplex = sdk::_DecklinkProfileID_decklinkProfileOneSubDeviceFullDuplex as isize,
+    OneSubDeviceHalfDuplex = sdk::_DecklinkProfileID_decklinkProfileOneSubDeviceHalfDuplex as isize,
+    TwoSubDevicesFullDuplex =
+        sdk::_DecklinkProfileID_decklinkProfileTwoSubDevicesFullDuplex as isize,
+    TwoSubDevicesHalfDuplex =
+        sdk::_DecklinkProfileID_decklinkProfileTwoSubDevicesHalfDuplex as isize,
+    FourSubDevicesHalfDuplex =
+        sdk::_DecklinkProfileID_decklinkProfileFourSubDevicesHalfDuplex as isize,
+}
+
+#[derive(FromPrimitive, PartialEq, Eq, Debug, Copy, Clone, Hash)]
+pub enum DecklinkDeviceInterface {
+    PCI = sdk::_DecklinkDeviceInterface_decklinkDeviceInterfacePCI as isize,
+    USB = sdk::_DecklinkDeviceInterface_decklinkDeviceInterfaceUSB as isize,
+    Thunderbolt = sdk::_DecklinkDeviceInterface_decklinkDeviceInterfaceThunderbolt as isize,
 }
 
-impl Drop for DecklinkDeviceAttributes {
-    fn drop(&mut self) {
-        if !self.dev.is_null() {
-            unsafe { sdk::cdecklink_profile_attributes_release(self.dev) };
-            self.dev = null_mut();
-        }
+bitflags! {
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    pub struct DecklinkVideoIOSupport: u32 {
+        const CAPTURE = sdk::_DecklinkVideoIOSupport_decklinkDeviceSupportsCapture;
+        const PLAYBACK = sdk::_DecklinkVideoIOSupport_decklinkDeviceSupportsPlayback;
     }
 }
 
+#[derive(FromPrimitive, PartialEq, Eq, Debug, Copy, Clone, Hash)]
+pub enum DecklinkDuplexMode {
+    /// Capture and playback are possible at the same time.
+    Full = sdk::_DecklinkDuplexMode_decklinkDuplexFull as isize,
+    /// Capture or playback are possible, but not at the same time.
+    Half = sdk::_DecklinkDuplexMode_decklinkDuplexHalf as isize,
+    /// Only capture or only playback is possible.
+    Simplex = sdk::_DecklinkDuplexMode_decklinkDuplexSimplex as isize,
+    /// The sub-device is not active in the current profile.
+    Inactive = sdk::_DecklinkDuplexMode_decklinkDuplexInactive as isize,
+}
+
+pub struct DecklinkDeviceAttributes {
+    dev: ProfileAttributesPtr,
+}
+
+// Safety: The attributes interface only reads values, and the SDK allows its getters to be
+// called concurrently from any thread.
+unsafe impl Send for DecklinkDeviceAttributes {}
+unsafe impl Sync for DecklinkDeviceAttributes {}
+
 impl DecklinkDeviceAttributes {
-    pub(crate) fn from(ptr: *mut sdk::cdecklink_profile_attributes_t) -> DecklinkDeviceAttributes {
+    pub(crate) fn from(ptr: ProfileAttributesPtr) -> DecklinkDeviceAttributes {
         DecklinkDeviceAttributes { dev: ptr }
     }
 
     fn get_flag(&self, id: DecklinkAttributeID) -> Result<bool, SdkError> {
-        let mut val = false;
-        let result = unsafe { sdk::cdecklink_profile_attributes_get_flag(self.dev, id, &mut val) };
-        SdkError::result_or(result, val)
+        self.dev.flag(id)
     }
 
     fn get_int(&self, id: DecklinkAttributeID) -> Result<i64, SdkError> {
-        let mut val = 0;
-        let result = unsafe { sdk::cdecklink_profile_attributes_get_int(self.dev, id, &mut val) };
-        SdkError::result_or(result, val)
+        self.dev.int(id)
     }
 
     fn get_float(&self, id: DecklinkAttributeID) -> Result<f64, SdkError> {
-        let mut val = 0.0;
-        let result = unsafe { sdk::cdecklink_profile_attributes_get_float(self.dev, id, &mut val) };
-        SdkError::result_or(result, val)
+        self.dev.float(id)
     }
 
     fn get_string_pointer(&self, id: DecklinkAttributeID) -> Result<String, SdkError> {
-        unsafe {
-            let mut val = null();
-            let result = sdk::cdecklink_profile_attributes_get_string(self.dev, id, &mut val);
-            SdkError::result_or_else(result, || convert_and_release_c_string(val))
-        }
+        self.dev.string(id)
     }
 
     fn get_string_from_reference(&self, id: DecklinkAttributeID) -> Result<String, SdkError> {
-        unsafe {
-            let mut val = null();
-            let result = sdk::cdecklink_profile_attributes_get_string(self.dev, id, &mut val);
-            SdkError::result_or_else(result, || convert_c_string(val))
-        }
+        self.dev.borrowed_string(id)
+    }
+
+    /// Read a flag attribute by its id, for attributes without a typed getter.
+    /// See `crate::device::custom_id`.
+    pub fn get_flag_raw(&self, id: u32) -> Result<bool, SdkError> {
+        self.get_flag(id)
+    }
+    /// Read an integer attribute by its id. See `crate::device::custom_id`.
+    pub fn get_int_raw(&self, id: u32) -> Result<i64, SdkError> {
+        self.get_int(id)
+    }
+    /// Read a float attribute by its id. See `crate::device::custom_id`.
+    pub fn get_float_raw(&self, id: u32) -> Result<f64, SdkError> {
+        self.get_float(id)
+    }
+    /// Read a string attribute by its id. See `crate::device::custom_id`.
+    ///
+    /// The string is released after it is copied, as most string attributes require. Do not
+    /// use this for the vendor name, which is owned by the driver.
+    pub fn get_string_raw(&self, id: u32) -> Result<String, SdkError> {
+        self.get_string_pointer(id)
     }
 
     /// True if internal keying is supported on this device.
@@ -196,11 +238,21 @@ impl DecklinkDeviceAttributes {
         self.get_int(sdk::_DecklinkAttributeID_decklinkAudioInputConnections)
             .map(|v| DecklinkAudioConnection::from_bits_truncate(v as u32))
     }
+    /// The active profile of the device.
+    pub fn profile_id(&self) -> Result<DecklinkProfileId, SdkError> {
+        self.get_int(sdk::_DecklinkAttributeID_decklinkProfileID)
+            .and_then(|v| DecklinkProfileId::from_i64(v).ok_or(SdkError::FALSE))
+    }
+    /// The duplex mode of the device in the active profile.
+    pub fn duplex(&self) -> Result<DecklinkDuplexMode, SdkError> {
+        self.get_int(sdk::_DecklinkAttributeID_decklinkDuplex)
+            .and_then(|v| DecklinkDuplexMode::from_i64(v).ok_or(SdkError::FALSE))
+    }
     /// The capture and/or playback capability of the device.
     /// (See BMDVideoIOSupport for more information)
-    pub fn video_io_support(&self) -> Result<i64, SdkError> {
-        // TODO - return BMDVideoIOSupport
+    pub fn video_io_support(&self) -> Result<DecklinkVideoIOSupport, SdkError> {
         self.get_int(sdk::_DecklinkAttributeID_decklinkVideoIOSupport)
+            .map(|v| DecklinkVideoIOSupport::from_bits_truncate(v as u32))
     }
     /// The deck control connections supported by the hardware
     /// (see BMDDeckControlConnection for more information).
@@ -210,9 +262,9 @@ impl DecklinkDeviceAttributes {
     }
     /// The active device interface
     /// (see BMDDeviceInterface for more information)
-    pub fn device_interface(&self) -> Result<i64, SdkError> {
-        // TODO - return BMDDeviceInterface
+    pub fn device_interface(&self) -> Result<DecklinkDeviceInterface, SdkError> {
         self.get_int(sdk::_DecklinkAttributeID_decklinkDeviceInterface)
+            .and_then(|v| DecklinkDeviceInterface::from_i64(v).ok_or(SdkError::FALSE))
     }
     /// Number of input audio RCA channels supported by this device.
     pub fn audio_input_rca_channel_count(&self) -> Result<i64, SdkError> {
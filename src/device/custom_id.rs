@@ -0,0 +1,192 @@
+//! Reading attribute and status ids that this crate does not wrap yet.
+//!
+//! Each driver release adds ids before this crate has a typed accessor for them. The `_raw`
+//! getters of `DecklinkDeviceAttributes` and `DecklinkDeviceStatus` read any id, through the
+//! same calls and error mapping as the typed getters. Ids registered with
+//! `register_custom_id` are also read into `DeviceDashboard::custom`, under their names.
+//!
+//! Prefer the typed getters where they exist. An id is only meaningful for the drivers that
+//! define it, and reading it as the wrong kind of value fails with `SdkError::INVALIDARG`
+//! or returns garbage.
+
+use crate::device::attributes::DecklinkDeviceAttributes;
+use crate::device::status::DecklinkDeviceStatus;
+use crate::SdkError;
+use std::fmt;
+use std::sync::RwLock;
+
+/// Which interface an id is read through.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+pub enum CustomIdScope {
+    Attribute,
+    Status,
+}
+
+/// The kind of value an id holds.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+pub enum CustomIdKind {
+    Flag,
+    Int,
+    Float,
+    String,
+    /// Only status ids hold bytes.
+    Bytes,
+}
+
+/// An id registered with `register_custom_id`.
+#[derive(PartialEq, Eq, Debug, Clone, Hash)]
+pub struct CustomId {
+    pub scope: CustomIdScope,
+    /// The id, as in the SDK headers.
+    pub id: u32,
+    pub name: String,
+    pub kind: CustomIdKind,
+}
+
+/// A value read from a custom id.
+#[derive(PartialEq, Debug, Clone)]
+pub enum CustomValue {
+    Flag(bool),
+    Int(i64),
+    Float(f64),
+    String(String),
+    Bytes(Vec<u8>),
+}
+
+impl fmt::Display for CustomValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CustomValue::Flag(v) => write!(f, "{}", v),
+            CustomValue::Int(v) => write!(f, "{}", v),
+            CustomValue::Float(v) => write!(f, "{}", v),
+            CustomValue::String(v) => write!(f, "{:?}", v),
+            CustomValue::Bytes(v) => {
+                for byte in v {
+                    write!(f, "{:02x}", byte)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// A registered id and the value read from it. `value` is `None` if the device did not
+/// report it.
+#[derive(PartialEq, Debug, Clone)]
+pub struct CustomReading {
+    pub id: CustomId,
+    pub value: Option<CustomValue>,
+}
+
+impl fmt::Display for CustomReading {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.value {
+            Some(value) => write!(f, "{} (0x{:08x}): {}", self.id.name, self.id.id, value),
+            None => write!(f, "{} (0x{:08x}): unavailable", self.id.name, self.id.id),
+        }
+    }
+}
+
+static CUSTOM_IDS: RwLock<Vec<CustomId>> = RwLock::new(Vec::new());
+
+/// Register an id, so that it is read into `DeviceDashboard::custom` and shown with `name`.
+/// Registering an id of the same scope again replaces its name and kind.
+///
+/// Fails with `SdkError::INVALIDARG` for `CustomIdKind::Bytes` in `CustomIdScope::Attribute`,
+/// as attributes cannot hold bytes.
+pub fn register_custom_id(
+    scope: CustomIdScope,
+    id: u32,
+    name: impl Into<String>,
+    kind: CustomIdKind,
+) -> Result<(), SdkError> {
+    if scope == CustomIdScope::Attribute && kind == CustomIdKind::Bytes {
+        return Err(SdkError::INVALIDARG);
+    }
+
+    let custom = CustomId {
+        scope,
+        id,
+        name: name.into(),
+        kind,
+    };
+    let mut ids = CUSTOM_IDS.write().unwrap();
+    match ids.iter_mut().find(|c| c.scope == scope && c.id == id) {
+        Some(existing) => *existing = custom,
+        None => ids.push(custom),
+    }
+    Ok(())
+}
+
+/// The registered id, if `id` has been registered in `scope`.
+pub fn custom_id(scope: CustomIdScope, id: u32) -> Option<CustomId> {
+    CUSTOM_IDS
+        .read()
+        .unwrap()
+        .iter()
+        .find(|c| c.scope == scope && c.id == id)
+        .cloned()
+}
+
+/// The registered ids, in the order they were first registered.
+pub fn custom_ids() -> Vec<CustomId> {
+    CUSTOM_IDS.read().unwrap().clone()
+}
+
+/// The name an id is shown with: its registered name, or its number if it has none.
+pub fn custom_id_name(scope: CustomIdScope, id: u32) -> String {
+    match custom_id(scope, id) {
+        Some(custom) => custom.name,
+        None => format!("0x{:08x}", id),
+    }
+}
+
+impl CustomId {
+    /// Read the id from the interface of its scope.
+    pub fn read(
+        &self,
+        attributes: Option<&DecklinkDeviceAttributes>,
+        status: Option<&DecklinkDeviceStatus>,
+    ) -> Result<CustomValue, SdkError> {
+        match self.scope {
+            CustomIdScope::Attribute => {
+                let attributes = attributes.ok_or(SdkError::NOINTERFACE)?;
+                match self.kind {
+                    CustomIdKind::Flag => attributes.get_flag_raw(self.id).map(CustomValue::Flag),
+                    CustomIdKind::Int => attributes.get_int_raw(self.id).map(CustomValue::Int),
+                    CustomIdKind::Float => {
+                        attributes.get_float_raw(self.id).map(CustomValue::Float)
+                    }
+                    CustomIdKind::String => {
+                        attributes.get_string_raw(self.id).map(CustomValue::String)
+                    }
+                    CustomIdKind::Bytes => Err(SdkError::INVALIDARG),
+                }
+            }
+            CustomIdScope::Status => {
+                let status = status.ok_or(SdkError::NOINTERFACE)?;
+                match self.kind {
+                    CustomIdKind::Flag => status.get_flag_raw(self.id).map(CustomValue::Flag),
+                    CustomIdKind::Int => status.get_int_raw(self.id).map(CustomValue::Int),
+                    CustomIdKind::Float => status.get_float_raw(self.id).map(CustomValue::Float),
+                    CustomIdKind::String => status.get_string_raw(self.id).map(CustomValue::String),
+                    CustomIdKind::Bytes => status.get_bytes_raw(self.id).map(CustomValue::Bytes),
+                }
+            }
+        }
+    }
+}
+
+/// Read every registered id.
+pub fn read_custom_ids(
+    attributes: Option<&DecklinkDeviceAttributes>,
+    status: Option<&DecklinkDeviceStatus>,
+) -> Vec<CustomReading> {
+    custom_ids()
+        .into_iter()
+        .map(|id| {
+            let value = id.read(attributes, status).ok();
+            CustomReading { id, value }
+        })
+        .collect()
+}
@@ -0,0 +1,113 @@
+use crate::device::input::enums::DecklinkAudioSampleType;
+use crate::ptr::AudioInputPacketPtr;
+use crate::time::DecklinkTime;
+use crate::{sdk, SdkError};
+
+/// Where the samples of a packet are held.
+#[derive(Clone)]
+enum Samples {
+    /// In a packet of the driver.
+    Driver(AudioInputPacketPtr),
+    /// In memory, for a packet made by this crate rather than the driver, with the time of
+    /// its first sample.
+    Owned(Vec<u8>, DecklinkTime),
+}
+
+/// A packet of audio samples that has been received from a decklink device.
+pub struct DecklinkAudioInputPacket {
+    samples: Samples,
+    sample_type: DecklinkAudioSampleType,
+    channel_count: u32,
+}
+
+impl DecklinkAudioInputPacket {
+    /// Wrap a raw pointer
+    pub(crate) unsafe fn from(
+        ptr: *mut sdk::cdecklink_audio_input_packet_t,
+        sample_type: DecklinkAudioSampleType,
+        channel_count: u32,
+    ) -> Self {
+        Self {
+            samples: Samples::Driver(AudioInputPacketPtr::retain(ptr)),
+            sample_type,
+            channel_count,
+        }
+    }
+
+    /// Take another reference to the wrapped packet, or copy the samples of one made by
+    /// this crate
+    pub(crate) fn retain(&self) -> Self {
+        Self {
+            samples: self.samples.clone(),
+            sample_type: self.sample_type,
+            channel_count: self.channel_count,
+        }
+    }
+
+    /// Make a packet of interleaved samples held in memory, with the time of its first sample,
+    /// as when passing on audio that has been delayed or edited. Any partial sample frame
+    /// at the end of `bytes` is ignored.
+    pub fn from_samples(
+        sample_type: DecklinkAudioSampleType,
+        channel_count: u32,
+        bytes: Vec<u8>,
+        time: DecklinkTime,
+    ) -> Self {
+        Self {
+            samples: Samples::Owned(bytes, time),
+            sample_type,
+            channel_count,
+        }
+    }
+
+    fn sample_frame_bytes(&self) -> usize {
+        let sample_size = match self.sample_type {
+            DecklinkAudioSampleType::Int16 => 2,
+            DecklinkAudioSampleType::Int32 => 4,
+        };
+        self.channel_count as usize * sample_size
+    }
+
+    /// Get the sample type that audio input was enabled with
+    pub fn sample_type(&self) -> DecklinkAudioSampleType {
+        self.sample_type
+    }
+    /// Get the channel count that audio input was enabled with
+    pub fn channel_count(&self) -> u32 {
+        self.channel_count
+    }
+    /// Get the number of sample frames in the packet
+    pub fn sample_frame_count(&self) -> usize {
+        match &self.samples {
+            Samples::Driver(packet) => packet.sample_frame_count(),
+            Samples::Owned(bytes, _) => bytes
+                .len()
+                .checked_div(self.sample_frame_bytes())
+                .unwrap_or(0),
+        }
+    }
+
+    /// Get the interleaved sample data of the packet
+    pub fn bytes(&self) -> Result<&[u8], SdkError> {
+        let byte_count = self.sample_frame_count() * self.sample_frame_bytes();
+        match &self.samples {
+            Samples::Driver(packet) => {
+                let bytes = packet.bytes()?;
+                // Safety: The packet owns its samples for as long as it is live
+                Ok(unsafe { std::slice::from_raw_parts(bytes, byte_count) })
+            }
+            Samples::Owned(bytes, _) => Ok(&bytes[..byte_count]),
+        }
+    }
+
+    /// Get the time of the packet, in the given timescale
+    pub fn packet_time(&self, timescale: i64) -> Result<i64, SdkError> {
+        match &self.samples {
+            Samples::Driver(packet) => packet.packet_time(timescale),
+            Samples::Owned(_, time) => time
+                .rescale(timescale)
+                .map(|t| t.value)
+                .ok_or(SdkError::INVALIDARG),
+        }
+    }
+}
@@ -0,0 +1,54 @@
+use crate::device::input::enums::{DecklinkAudioSampleRate, DecklinkAudioSampleType};
+use crate::SdkError;
+
+/// The error `DecklinkInputDevice::enable_audio_input` fails with on drivers that only enable
+/// audio input once video input is enabled, when video input is not.
+pub(crate) const AUDIO_BEFORE_VIDEO_ERROR: SdkError = SdkError::FAIL;
+
+/// What `DecklinkInputDevice::request_audio_input` did with the request.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+pub enum AudioInputState {
+    /// Audio input is enabled.
+    Enabled,
+    /// The driver needs video input enabled first, so audio input is enabled right after it.
+    Deferred,
+}
+
+/// An enable of audio input that had to wait for video input.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AudioEnableEvent {
+    /// Audio input was requested before video input was enabled, on a driver that needs
+    /// video input first, and waits for it.
+    Deferred,
+    /// The deferred audio input was enabled, right after video input.
+    Enabled,
+    /// The deferred audio input failed to enable after video input too, and was dropped.
+    Failed,
+}
+
+/// Audio input of the sample rate, sample type and channel count.
+pub(crate) type AudioInputFormat = (DecklinkAudioSampleRate, DecklinkAudioSampleType, u32);
+
+/// How audio input is ordered with video input on one input.
+#[derive(Default)]
+pub(crate) struct AudioEnableOrder {
+    /// Whether the driver was seen to need video input enabled before audio input.
+    pub(crate) requires_video_first: Option<bool>,
+    /// Audio input waiting for video input to be enabled.
+    pub(crate) pending: Option<AudioInputFormat>,
+    /// Deferred audio inputs enabled after video input.
+    pub(crate) deferred: u64,
+    pub(crate) events: Vec<AudioEnableEvent>,
+}
+
+impl AudioEnableOrder {
+    /// Learn from the result of enabling audio input while video input was not enabled.
+    pub(crate) fn observe(&mut self, result: Result<(), SdkError>) {
+        match result {
+            Ok(()) => self.requires_video_first = Some(false),
+            Err(AUDIO_BEFORE_VIDEO_ERROR) => self.requires_video_first = Some(true),
+            Err(_) => {}
+        }
+    }
+}
@@ -0,0 +1,237 @@
+use crate::device::input::video_callback::CallbackResult;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+
+/// What drivers do with the HRESULT a frame-arrived callback returns, as
+/// `crate::LibraryCapabilities::callback_returns` lists it for each release.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash, Default)]
+pub enum CallbackReturnHandling {
+    /// The result is ignored: frames keep arriving at the rate of the signal, and none are
+    /// dropped or held back, whatever is returned.
+    Ignored,
+    /// The drivers have not been observed, or are not installed. They are assumed to act on
+    /// results other than `S_OK`, so the crate does not return one unless asked to outright.
+    #[default]
+    Unknown,
+}
+
+/// How far behind the layers consuming frames are, as each reports it through
+/// `PressureSource`.
+#[derive(PartialEq, Debug, Copy, Clone, Default)]
+pub struct ConsumerPressure {
+    /// How full the fullest queue is, from 0 when empty to 1 when full.
+    pub queue_saturation: f32,
+    /// Whether a retention budget is fully used, so the next frame retained is over it.
+    pub retention_exhausted: bool,
+    /// The most frames dropped in a row by a layer, up to its latest.
+    pub consecutive_drops: u64,
+}
+
+impl ConsumerPressure {
+    /// The worse of this and `other` in each part.
+    pub fn combine(self, other: ConsumerPressure) -> ConsumerPressure {
+        ConsumerPressure {
+            queue_saturation: self.queue_saturation.max(other.queue_saturation),
+            retention_exhausted: self.retention_exhausted || other.retention_exhausted,
+            consecutive_drops: self.consecutive_drops.max(other.consecutive_drops),
+        }
+    }
+}
+
+/// A layer consuming frames that reports how far behind it is, for
+/// `DecklinkInputDevice::add_pressure_source`.
+///
+/// `crate::queue::FrameQueue` and `crate::retention::RetentionBudget` report theirs. It is
+/// read in the input callback of every frame, so must not block.
+pub trait PressureSource: Send + Sync {
+    fn pressure(&self) -> ConsumerPressure;
+}
+
+/// When `CallbackReturnPolicy::PropagateConsumerPressure` passes consumer pressure on. Any
+/// one part reaching its threshold is enough.
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub struct PressureThresholds {
+    /// The queue saturation to pass on from, 1 (a full queue) by default.
+    pub queue_saturation: f32,
+    /// Whether an exhausted retention budget is passed on, as it is by default.
+    pub retention_exhausted: bool,
+    /// The consecutive drops to pass on from, 1 by default.
+    pub consecutive_drops: u64,
+}
+
+impl Default for PressureThresholds {
+    fn default() -> PressureThresholds {
+        PressureThresholds {
+            queue_saturation: 1.0,
+            retention_exhausted: true,
+            consecutive_drops: 1,
+        }
+    }
+}
+
+impl PressureThresholds {
+    /// Whether `pressure` reaches any of the thresholds.
+    pub fn reached_by(&self, pressure: &ConsumerPressure) -> bool {
+        pressure.queue_saturation >= self.queue_saturation
+            || (self.retention_exhausted && pressure.retention_exhausted)
+            || pressure.consecutive_drops >= self.consecutive_drops
+    }
+}
+
+/// What an input returns to the driver from each frame-arrived callback.
+///
+/// The handler's `CallbackResult` says how the handler fared with the frame, but the layers
+/// behind it, such as a queue to a slower thread or a retention budget, fall behind without
+/// the handler knowing. A policy combines the two: it is given the handler's result and the
+/// `ConsumerPressure` of the sources added with `DecklinkInputDevice::add_pressure_source`,
+/// and decides the result the driver sees. `DecklinkInputDevice::callback_return_stats`
+/// counts what was returned.
+///
+/// What drivers do with the result has not been observed for any release, see
+/// `crate::LibraryCapabilities::callback_returns`. `PropagateConsumerPressure` only passes
+/// pressure on to drivers known to ignore it, so until a release is, it is a no-op: pressure
+/// is counted as withheld, and the handler's result is returned. The other policies return
+/// what they decide to any driver.
+///
+/// Callbacks set through `DeckLinkInputCallback` keep their meaning under the default
+/// policy: `true` returns `S_OK` and `false` returns `S_FALSE`.
+#[derive(Debug, Copy, Clone, Default)]
+pub enum CallbackReturnPolicy {
+    /// Return the handler's result, or `S_OK` without a handler.
+    #[default]
+    HandlerResult,
+    /// Return `S_OK` whatever the handler returned.
+    AlwaysOk,
+    /// Return `S_FALSE` while the pressure reaches the thresholds and the handler returned
+    /// `S_OK`, and the handler's result otherwise. Unless the installed drivers are known to
+    /// ignore results, which no release yet is, the pressure is only counted, and this
+    /// returns what `HandlerResult` would.
+    PropagateConsumerPressure(PressureThresholds),
+    /// Return what the function makes of the handler's result and the pressure. It is called
+    /// in the input callback, so must not block, and its result is returned to any driver.
+    Custom(fn(CallbackResult, &ConsumerPressure) -> CallbackResult),
+}
+
+/// What an input has returned to the driver from frame-arrived callbacks.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Default)]
+pub struct CallbackReturnStats {
+    /// The callbacks returned `S_FALSE`, and `E_FAIL`.
+    pub returned_false: u64,
+    pub returned_fail: u64,
+    /// The callbacks the policy returned something other than the handler's result for.
+    pub overridden: u64,
+    /// The callbacks that arrived with the consumer pressure reaching the thresholds of
+    /// `CallbackReturnPolicy::PropagateConsumerPressure`.
+    pub pressured: u64,
+    /// Of those, the ones whose pressure was not passed on, as the installed drivers are not
+    /// known to ignore results.
+    pub withheld: u64,
+    /// What the installed drivers do with the results, as of when the policy was set. With
+    /// `CallbackReturnHandling::Ignored`, results other than `S_OK` did not throttle capture.
+    pub driver_handling: CallbackReturnHandling,
+}
+
+/// The return policy of an input and its pressure sources, shared by the input device and
+/// its callback wrapper.
+pub(crate) struct ReturnPolicy {
+    policy: RwLock<CallbackReturnPolicy>,
+    handling: RwLock<CallbackReturnHandling>,
+    sources: RwLock<Vec<Arc<dyn PressureSource>>>,
+    returned_false: AtomicU64,
+    returned_fail: AtomicU64,
+    overridden: AtomicU64,
+    pressured: AtomicU64,
+    withheld: AtomicU64,
+}
+
+impl ReturnPolicy {
+    pub(crate) fn new() -> ReturnPolicy {
+        ReturnPolicy {
+            policy: RwLock::new(CallbackReturnPolicy::default()),
+            handling: RwLock::new(CallbackReturnHandling::Unknown),
+            sources: RwLock::new(Vec::new()),
+            returned_false: AtomicU64::new(0),
+            returned_fail: AtomicU64::new(0),
+            overridden: AtomicU64::new(0),
+            pressured: AtomicU64::new(0),
+            withheld: AtomicU64::new(0),
+        }
+    }
+
+    pub(crate) fn set(&self, policy: CallbackReturnPolicy, handling: CallbackReturnHandling) {
+        *self.policy.write().unwrap() = policy;
+        *self.handling.write().unwrap() = handling;
+    }
+
+    pub(crate) fn policy(&self) -> CallbackReturnPolicy {
+        *self.policy.read().unwrap()
+    }
+
+    pub(crate) fn add_source(&self, source: Arc<dyn PressureSource>) {
+        self.sources.write().unwrap().push(source);
+    }
+
+    pub(crate) fn clear_sources(&self) {
+        self.sources.write().unwrap().clear();
+    }
+
+    fn pressure(&self) -> ConsumerPressure {
+        self.sources
+            .read()
+            .unwrap()
+            .iter()
+            .fold(ConsumerPressure::default(), |pressure, source| {
+                pressure.combine(source.pressure())
+            })
+    }
+
+    /// The result to return to the driver for a callback the handler returned `result` for,
+    /// counting it.
+    pub(crate) fn resolve(&self, result: CallbackResult) -> CallbackResult {
+        let returned = match self.policy() {
+            CallbackReturnPolicy::HandlerResult => result,
+            CallbackReturnPolicy::AlwaysOk => CallbackResult::Ok,
+            CallbackReturnPolicy::PropagateConsumerPressure(thresholds) => {
+                if !thresholds.reached_by(&self.pressure()) {
+                    result
+                } else {
+                    self.pressured.fetch_add(1, Ordering::Relaxed);
+                    if *self.handling.read().unwrap() != CallbackReturnHandling::Ignored {
+                        self.withheld.fetch_add(1, Ordering::Relaxed);
+                        result
+                    } else if result == CallbackResult::Ok {
+                        CallbackResult::False
+                    } else {
+                        result
+                    }
+                }
+            }
+            CallbackReturnPolicy::Custom(decide) => decide(result, &self.pressure()),
+        };
+
+        if returned != result {
+            self.overridden.fetch_add(1, Ordering::Relaxed);
+        }
+        match returned {
+            CallbackResult::Ok => {}
+            CallbackResult::False => {
+                self.returned_false.fetch_add(1, Ordering::Relaxed);
+            }
+            CallbackResult::Fail => {
+                self.returned_fail.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        returned
+    }
+
+    pub(crate) fn stats(&self) -> CallbackReturnStats {
+        CallbackReturnStats {
+            returned_false: self.returned_false.load(Ordering::Relaxed),
+            returned_fail: self.returned_fail.load(Ordering::Relaxed),
+            overridden: self.overridden.load(Ordering::Relaxed),
+            pressured: self.pressured.load(Ordering::Relaxed),
+            withheld: self.withheld.load(Ordering::Relaxed),
+            driver_handling: *self.handling.read().unwrap(),
+        }
+    }
+}
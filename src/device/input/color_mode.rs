@@ -0,0 +1,140 @@
+use crate::device::input::enums::DecklinkDetectedVideoInputFormatFlags;
+use crate::frame::DecklinkPixelFormat;
+use crate::SdkError;
+use std::fmt;
+
+/// Which chroma sampling to capture a signal with.
+///
+/// An RGB 4:4:4 signal captured into a YUV format loses half its chroma resolution, and a
+/// YCbCr signal captured into an RGB format of the wrong kind comes out tinted, so the pixel
+/// format must agree with the signal. Devices that take 4:4:4 over dual link also need their
+/// 444 SDI input setting turned on, which is not exposed by the C bindings, so it must be set
+/// with Desktop Video Setup.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CaptureColorMode {
+    /// Follow the detected signal, or YCbCr 4:2:2 if nothing has been detected.
+    #[default]
+    Auto,
+    YCbCr422,
+    RGB444,
+}
+
+/// Formats for each color mode, in order of preference.
+const YCBCR_422_FORMATS: [DecklinkPixelFormat; 2] = [
+    DecklinkPixelFormat::Format10BitYUV,
+    DecklinkPixelFormat::Format8BitYUV,
+];
+const RGB_444_FORMATS: [DecklinkPixelFormat; 7] = [
+    DecklinkPixelFormat::Format12BitRGB,
+    DecklinkPixelFormat::Format12BitRGBLE,
+    DecklinkPixelFormat::Format10BitRGB,
+    DecklinkPixelFormat::Format10BitRGBXLE,
+    DecklinkPixelFormat::Format10BitRGBX,
+    DecklinkPixelFormat::Format8BitBGRA,
+    DecklinkPixelFormat::Format8BitARGB,
+];
+
+impl CaptureColorMode {
+    /// The color mode of the signal described by the detected format flags, if they say.
+    pub fn detected(flags: DecklinkDetectedVideoInputFormatFlags) -> Option<CaptureColorMode> {
+        if flags.contains(DecklinkDetectedVideoInputFormatFlags::RGB_444) {
+            Some(CaptureColorMode::RGB444)
+        } else if flags.contains(DecklinkDetectedVideoInputFormatFlags::YCBCR_422) {
+            Some(CaptureColorMode::YCbCr422)
+        } else {
+            None
+        }
+    }
+
+    /// The color mode that a pixel format captures in, or `None` for compressed formats.
+    pub fn of_pixel_format(pixel_format: DecklinkPixelFormat) -> Option<CaptureColorMode> {
+        if YCBCR_422_FORMATS.contains(&pixel_format) {
+            Some(CaptureColorMode::YCbCr422)
+        } else if RGB_444_FORMATS.contains(&pixel_format) {
+            Some(CaptureColorMode::RGB444)
+        } else {
+            None
+        }
+    }
+
+    /// Resolve `Auto` from the detected format flags. With format detection enabled, resolve
+    /// again in `video_input_format_changed`, and re-enable video input if the mode changed.
+    pub fn resolve(&self, detected: DecklinkDetectedVideoInputFormatFlags) -> CaptureColorMode {
+        match self {
+            CaptureColorMode::Auto => {
+                CaptureColorMode::detected(detected).unwrap_or(CaptureColorMode::YCbCr422)
+            }
+            mode => *mode,
+        }
+    }
+
+    /// The pixel formats that capture in this color mode, in order of preference. `Auto`
+    /// allows the formats of both.
+    pub fn pixel_formats(&self) -> Vec<DecklinkPixelFormat> {
+        match self {
+            CaptureColorMode::Auto => [&YCBCR_422_FORMATS[..], &RGB_444_FORMATS[..]].concat(),
+            CaptureColorMode::YCbCr422 => YCBCR_422_FORMATS.to_vec(),
+            CaptureColorMode::RGB444 => RGB_444_FORMATS.to_vec(),
+        }
+    }
+
+    pub fn is_compatible(&self, pixel_format: DecklinkPixelFormat) -> bool {
+        match self {
+            CaptureColorMode::Auto => CaptureColorMode::of_pixel_format(pixel_format).is_some(),
+            mode => CaptureColorMode::of_pixel_format(pixel_format) == Some(*mode),
+        }
+    }
+}
+
+/// Returned by `DecklinkInputDevice::enable_video_input_with_color_mode`.
+#[derive(Debug)]
+pub enum ColorModeError {
+    /// The requested pixel format does not capture in the color mode.
+    Incompatible {
+        color_mode: CaptureColorMode,
+        pixel_format: DecklinkPixelFormat,
+    },
+    /// The device supports none of the pixel formats of the color mode in the display mode.
+    Unsupported { color_mode: CaptureColorMode },
+    /// The device reported an error.
+    Sdk(SdkError),
+}
+
+impl From<SdkError> for ColorModeError {
+    fn from(e: SdkError) -> Self {
+        ColorModeError::Sdk(e)
+    }
+}
+
+impl fmt::Display for ColorModeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ColorModeError::Incompatible {
+                color_mode,
+                pixel_format,
+            } => {
+                let formats: Vec<String> = color_mode
+                    .pixel_formats()
+                    .iter()
+                    .map(|f| format!("{:?}", f))
+                    .collect();
+                write!(
+                    f,
+                    "{:?} requires one of {}; {:?} requested",
+                    color_mode,
+                    formats.join(", "),
+                    pixel_format
+                )
+            }
+            ColorModeError::Unsupported { color_mode } => write!(
+                f,
+                "the device cannot capture {:?} in this display mode",
+                color_mode
+            ),
+            ColorModeError::Sdk(e) => write!(f, "the device reported an error: {:?}", e),
+        }
+    }
+}
+
+impl std::error::Error for ColorModeError {}
@@ -1,21 +1,214 @@
-use crate::sdk;
-use std::ptr::null_mut;
-use std::sync::atomic::AtomicBool;
-use std::sync::Arc;
-
-pub struct DecklinkInputDevicePtr {
-    pub(crate) dev: *mut crate::sdk::cdecklink_input_t,
-    pub video_active: Arc<AtomicBool>,
+use crate::device::input::back_pressure::ReturnPolicy;
+use crate::device::input::dimensions::DimensionCheck;
+use crate::device::input::enums::DecklinkAudioSampleType;
+use crate::ptr::InputPtr;
+use crate::time::DecklinkTime;
+use crate::vpid::VpidTracker;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+/// Decides whether input callbacks are forwarded to the handler.
+///
+/// Some drivers deliver a callback or two after `stop_streams` has returned. The gate is closed
+/// before streams are stopped, and any callback arriving while it is closed is counted and
+/// dropped instead.
+pub(crate) struct CallbackGate {
+    open: AtomicBool,
+    suppressed: AtomicU64,
+    /// Forwarded frame callbacks.
+    callbacks: AtomicU64,
+    /// Forwarded frame callbacks that carried a video frame, and an audio packet.
+    video_frames: AtomicU64,
+    audio_packets: AtomicU64,
+    /// Video frames that arrived but could not be converted for reading.
+    conversion_failures: AtomicU64,
+    /// Frame callbacks that have been entered and not yet returned.
+    in_flight: AtomicU64,
+    /// When the gate was made, which `last_callback` counts from.
+    created: Instant,
+    /// Nanoseconds from `created` to the last callback, plus one, or zero before any.
+    last_callback: AtomicU64,
 }
 
-unsafe impl Send for DecklinkInputDevicePtr {}
-unsafe impl Sync for DecklinkInputDevicePtr {}
+impl CallbackGate {
+    pub(crate) fn new() -> CallbackGate {
+        CallbackGate {
+            open: AtomicBool::new(false),
+            suppressed: AtomicU64::new(0),
+            callbacks: AtomicU64::new(0),
+            video_frames: AtomicU64::new(0),
+            audio_packets: AtomicU64::new(0),
+            conversion_failures: AtomicU64::new(0),
+            in_flight: AtomicU64::new(0),
+            created: Instant::now(),
+            last_callback: AtomicU64::new(0),
+        }
+    }
+
+    pub(crate) fn open(&self) {
+        self.open.store(true, Ordering::SeqCst);
+    }
+    pub(crate) fn close(&self) {
+        self.open.store(false, Ordering::SeqCst);
+    }
+
+    /// Record a callback, returning whether it should be forwarded.
+    pub(crate) fn admit(&self) -> bool {
+        let since_created = self.created.elapsed().as_nanos() as u64;
+        self.last_callback
+            .store(since_created + 1, Ordering::Relaxed);
+        if self.open.load(Ordering::SeqCst) {
+            true
+        } else {
+            self.suppressed.fetch_add(1, Ordering::Relaxed);
+            false
+        }
+    }
+
+    /// Count a frame callback as in flight until the returned guard is dropped.
+    pub(crate) fn enter(&self) -> InFlight<'_> {
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        InFlight(self)
+    }
+
+    /// Number of frame callbacks that have been entered and not yet returned.
+    pub(crate) fn in_flight(&self) -> u64 {
+        self.in_flight.load(Ordering::SeqCst)
+    }
+
+    /// Count a forwarded frame callback, returning the number forwarded before it.
+    pub(crate) fn delivered(&self, video: bool, audio: bool) -> u64 {
+        if video {
+            self.video_frames.fetch_add(1, Ordering::Relaxed);
+        }
+        if audio {
+            self.audio_packets.fetch_add(1, Ordering::Relaxed);
+        }
+        self.callbacks.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Number of forwarded frame callbacks that carried a video frame, and an audio packet.
+    pub(crate) fn delivered_counts(&self) -> (u64, u64) {
+        (
+            self.video_frames.load(Ordering::Relaxed),
+            self.audio_packets.load(Ordering::Relaxed),
+        )
+    }
+
+    pub(crate) fn conversion_failed(&self) {
+        self.conversion_failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn conversion_failure_count(&self) -> u64 {
+        self.conversion_failures.load(Ordering::Relaxed)
+    }
+
+    /// Number of callbacks that have been dropped because the gate was closed.
+    pub(crate) fn suppressed_count(&self) -> u64 {
+        self.suppressed.load(Ordering::Relaxed)
+    }
 
-impl Drop for DecklinkInputDevicePtr {
+    /// Block until no callback has arrived for `quiet_period`, giving up after ten periods.
+    /// Returns whether the callbacks went quiet.
+    pub(crate) fn wait_quiet(&self, quiet_period: Duration) -> bool {
+        let deadline = Instant::now() + quiet_period * 10;
+        loop {
+            let since = match self.last_callback.load(Ordering::Relaxed) {
+                0 => quiet_period,
+                last => self
+                    .created
+                    .elapsed()
+                    .saturating_sub(Duration::from_nanos(last - 1)),
+            };
+            if since >= quiet_period {
+                return true;
+            }
+            if Instant::now() >= deadline {
+                return false;
+            }
+            std::thread::sleep(quiet_period - since);
+        }
+    }
+}
+
+/// A frame callback in flight, counted by its `CallbackGate` until dropped.
+pub(crate) struct InFlight<'a>(&'a CallbackGate);
+
+impl Drop for InFlight<'_> {
     fn drop(&mut self) {
-        if !self.dev.is_null() {
-            unsafe { sdk::cdecklink_input_release(self.dev) };
-            self.dev = null_mut();
+        self.0.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Where video input is between being enabled and disabled.
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+#[repr(u8)]
+pub(crate) enum VideoInputState {
+    Disabled,
+    /// An enable is calling the driver, or rolling back after it failed.
+    Enabling,
+    Enabled,
+    /// A disable is calling the driver and releasing what the enable created.
+    Disabling,
+}
+
+/// The `VideoInputState` of an input, changed with compare-exchange so an enable cannot
+/// begin while another is in progress or enabled.
+pub(crate) struct VideoInputStateCell(AtomicU8);
+
+impl VideoInputStateCell {
+    pub(crate) fn new() -> VideoInputStateCell {
+        VideoInputStateCell(AtomicU8::new(VideoInputState::Disabled as u8))
+    }
+
+    pub(crate) fn get(&self) -> VideoInputState {
+        VideoInputState::from_u8(self.0.load(Ordering::Acquire))
+    }
+
+    /// Move from `from` to `to`, or return the state found if it was not `from`.
+    pub(crate) fn transition(
+        &self,
+        from: VideoInputState,
+        to: VideoInputState,
+    ) -> Result<(), VideoInputState> {
+        self.0
+            .compare_exchange(from as u8, to as u8, Ordering::AcqRel, Ordering::Acquire)
+            .map(|_| ())
+            .map_err(VideoInputState::from_u8)
+    }
+
+    pub(crate) fn set(&self, state: VideoInputState) {
+        self.0.store(state as u8, Ordering::Release);
+    }
+}
+
+impl VideoInputState {
+    fn from_u8(value: u8) -> VideoInputState {
+        match value {
+            0 => VideoInputState::Disabled,
+            1 => VideoInputState::Enabling,
+            2 => VideoInputState::Enabled,
+            _ => VideoInputState::Disabling,
         }
     }
 }
+
+pub(crate) struct DecklinkInputDevicePtr {
+    pub(crate) dev: InputPtr,
+    pub(crate) video_state: VideoInputStateCell,
+    /// Frame duration of the active display mode, in that mode's timescale.
+    pub(crate) frame_duration: Arc<RwLock<Option<DecklinkTime>>>,
+    /// Sample type and channel count that audio input is enabled with.
+    pub(crate) audio_format: Arc<RwLock<Option<(DecklinkAudioSampleType, u32)>>>,
+    pub(crate) gate: Arc<CallbackGate>,
+    /// The check of frame sizes against the active display mode.
+    pub(crate) dimensions: Arc<DimensionCheck>,
+    /// The payload identifier of the signal being captured, or the error reading it.
+    pub(crate) vpid: Arc<VpidTracker>,
+    /// What frame callbacks return to the driver.
+    pub(crate) returns: Arc<ReturnPolicy>,
+}
+
+unsafe impl Send for DecklinkInputDevicePtr {}
+unsafe impl Sync for DecklinkInputDevicePtr {}
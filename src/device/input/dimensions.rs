@@ -0,0 +1,165 @@
+use crate::display_mode::DecklinkDisplayModeId;
+use crate::frame::{DecklinkFrameBase, DecklinkPixelFormat};
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Mutex, RwLock};
+
+/// The most mismatches kept for `DecklinkInputDevice::take_dimension_mismatches`. Older ones
+/// are dropped first, and still counted.
+pub(crate) const MISMATCH_HISTORY: usize = 64;
+
+/// What to do with a frame whose size differs from that of the enabled display mode.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DimensionPolicy {
+    /// Deliver the frame flagged with `ArrivalFlags::DIMENSION_MISMATCH`, for handlers that
+    /// size their buffers from each frame.
+    #[default]
+    Permissive,
+    /// Drop the frame. The handler is still called with the audio of the callback, with
+    /// `video_frame` `None` and the flag set.
+    Strict,
+}
+
+/// The size of a frame.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FrameDimensions {
+    pub width: usize,
+    pub height: usize,
+    pub row_bytes: usize,
+}
+
+/// A frame that arrived with a size other than that of the enabled display mode, outside a
+/// format change.
+#[derive(PartialEq, Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DimensionMismatch {
+    /// The display mode the input expected frames of.
+    pub mode: DecklinkDisplayModeId,
+    pub pixel_format: DecklinkPixelFormat,
+    /// The size of the mode. `row_bytes` is the least the pixel format needs at its width, or
+    /// the reported row size for the compressed formats, which have no fixed row size.
+    pub expected: FrameDimensions,
+    /// The size the frame reported.
+    pub reported: FrameDimensions,
+    /// The `FrameContext::sequence` of the callback.
+    pub sequence: u64,
+    /// Whether the frame was dropped, under `DimensionPolicy::Strict`.
+    pub dropped: bool,
+}
+
+impl fmt::Display for DimensionMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "frame {} is {}x{} with {} bytes a row, but {:?} in {:?} is {}x{} with at least {}",
+            self.sequence,
+            self.reported.width,
+            self.reported.height,
+            self.reported.row_bytes,
+            self.mode,
+            self.pixel_format,
+            self.expected.width,
+            self.expected.height,
+            self.expected.row_bytes
+        )?;
+        if self.dropped {
+            write!(f, ", so it was dropped")?;
+        }
+        Ok(())
+    }
+}
+
+/// The check of frame sizes against the enabled display mode, shared by the input device and
+/// its callback wrapper.
+pub(crate) struct DimensionCheck {
+    /// The enabled display mode with its width and height, while video input is enabled.
+    mode: RwLock<Option<(DecklinkDisplayModeId, usize, usize)>>,
+    strict: AtomicBool,
+    count: AtomicU64,
+    mismatches: Mutex<VecDeque<DimensionMismatch>>,
+}
+
+impl DimensionCheck {
+    pub(crate) fn new() -> DimensionCheck {
+        DimensionCheck {
+            mode: RwLock::new(None),
+            strict: AtomicBool::new(false),
+            count: AtomicU64::new(0),
+            mismatches: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    pub(crate) fn set_mode(&self, mode: Option<(DecklinkDisplayModeId, usize, usize)>) {
+        *self.mode.write().unwrap() = mode;
+    }
+
+    pub(crate) fn set_policy(&self, policy: DimensionPolicy) {
+        self.strict
+            .store(policy == DimensionPolicy::Strict, Ordering::Relaxed);
+    }
+
+    pub(crate) fn policy(&self) -> DimensionPolicy {
+        if self.strict.load(Ordering::Relaxed) {
+            DimensionPolicy::Strict
+        } else {
+            DimensionPolicy::Permissive
+        }
+    }
+
+    /// Compare `frame` with the enabled display mode, recording a mismatch. Frames are not
+    /// checked while no mode is known. Rows padded more than the pixel format needs are left
+    /// to `crate::row_bytes`, as reading them stays within the frame.
+    pub(crate) fn check<F: DecklinkFrameBase + ?Sized>(
+        &self,
+        frame: &F,
+        sequence: u64,
+    ) -> Option<DimensionMismatch> {
+        let (mode, width, height) = (*self.mode.read().unwrap())?;
+        let pixel_format = frame.pixel_format();
+        let reported = FrameDimensions {
+            width: frame.width(),
+            height: frame.height(),
+            row_bytes: frame.row_bytes(),
+        };
+        let expected = FrameDimensions {
+            width,
+            height,
+            row_bytes: pixel_format
+                .bytes_per_row(width)
+                .unwrap_or(reported.row_bytes),
+        };
+        if reported.width == expected.width
+            && reported.height == expected.height
+            && reported.row_bytes >= expected.row_bytes
+        {
+            return None;
+        }
+
+        let mismatch = DimensionMismatch {
+            mode,
+            pixel_format,
+            expected,
+            reported,
+            sequence,
+            dropped: self.strict.load(Ordering::Relaxed),
+        };
+        self.count.fetch_add(1, Ordering::Relaxed);
+        let mut mismatches = self.mismatches.lock().unwrap();
+        if mismatches.len() == MISMATCH_HISTORY {
+            mismatches.pop_front();
+        }
+        mismatches.push_back(mismatch);
+        Some(mismatch)
+    }
+
+    pub(crate) fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn take(&self) -> Vec<DimensionMismatch> {
+        self.mismatches.lock().unwrap().drain(..).collect()
+    }
+}
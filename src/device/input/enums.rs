@@ -1,3 +1,4 @@
+use crate::frame::DecklinkPixelFormat;
 use crate::sdk;
 
 bitflags! {
@@ -20,6 +21,7 @@ bitflags! {
 
 bitflags! {
     #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
     pub struct DecklinkDetectedVideoInputFormatFlags: u32 {
         const YCBCR_422 = sdk::_DecklinkDetectedVideoInputFormatFlags_decklinkDetectedVideoInputYCbCr422;
         const RGB_444 = sdk::_DecklinkDetectedVideoInputFormatFlags_decklinkDetectedVideoInputRGB444;
@@ -30,13 +32,91 @@ bitflags! {
     }
 }
 
-#[derive(FromPrimitive, PartialEq, Debug, Copy, Clone)]
+#[derive(FromPrimitive, PartialEq, Eq, Hash, Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum DecklinkAudioSampleRate {
     Rate48kHz = sdk::_DecklinkAudioSampleRate_decklinkAudioSampleRate48kHz as isize,
 }
 
-#[derive(FromPrimitive, PartialEq, Debug, Copy, Clone)]
+#[derive(FromPrimitive, PartialEq, Eq, Hash, Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum DecklinkAudioSampleType {
     Int16 = sdk::_DecklinkAudioSampleType_decklinkAudioSampleType16bitInteger as isize,
     Int32 = sdk::_DecklinkAudioSampleType_decklinkAudioSampleType32bitInteger as isize,
 }
+
+/// Pixel format ordering used by `PixelFormatPreference::BestQuality`, highest quality first.
+const BEST_QUALITY_ORDER: [DecklinkPixelFormat; 9] = [
+    DecklinkPixelFormat::Format12BitRGB,
+    DecklinkPixelFormat::Format12BitRGBLE,
+    DecklinkPixelFormat::Format10BitRGB,
+    DecklinkPixelFormat::Format10BitRGBXLE,
+    DecklinkPixelFormat::Format10BitRGBX,
+    DecklinkPixelFormat::Format10BitYUV,
+    DecklinkPixelFormat::Format8BitBGRA,
+    DecklinkPixelFormat::Format8BitARGB,
+    DecklinkPixelFormat::Format8BitYUV,
+];
+
+/// How to pick a pixel format when enabling video input.
+#[derive(PartialEq, Debug, Clone)]
+pub enum PixelFormatPreference {
+    /// Only the given format is acceptable.
+    Exact(DecklinkPixelFormat),
+    /// Try each format in order and use the first one supported by the device.
+    PreferredWithFallback(Vec<DecklinkPixelFormat>),
+    /// Use the highest quality format supported by the device
+    /// (12-bit RGB > 10-bit RGB > 10-bit YUV > 8-bit formats).
+    BestQuality,
+}
+
+impl PixelFormatPreference {
+    /// The formats to probe, in order of preference.
+    pub fn candidates(&self) -> Vec<DecklinkPixelFormat> {
+        match self {
+            PixelFormatPreference::Exact(format) => vec![*format],
+            PixelFormatPreference::PreferredWithFallback(formats) => formats.clone(),
+            PixelFormatPreference::BestQuality => BEST_QUALITY_ORDER.to_vec(),
+        }
+    }
+
+    /// The format the caller asked for, or `None` for `BestQuality`, which asks for none in
+    /// particular.
+    pub fn requested(&self) -> Option<DecklinkPixelFormat> {
+        match self {
+            PixelFormatPreference::Exact(format) => Some(*format),
+            PixelFormatPreference::PreferredWithFallback(formats) => formats.first().copied(),
+            PixelFormatPreference::BestQuality => None,
+        }
+    }
+}
+
+/// The pixel format that was negotiated when enabling video input.
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub struct ChosenFormat {
+    /// The format that video input was enabled with.
+    pub pixel_format: DecklinkPixelFormat,
+    /// The format the caller asked for: see `PixelFormatPreference::requested`.
+    pub requested: Option<DecklinkPixelFormat>,
+}
+
+impl ChosenFormat {
+    /// Whether a format other than the one asked for had to be used. Never true for
+    /// `PixelFormatPreference::BestQuality`.
+    pub fn is_fallback(&self) -> bool {
+        self.requested.is_some_and(|r| r != self.pixel_format)
+    }
+}
+
+/// What `DecklinkInputDevice::stop_streams_drained` delivered before stopping.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Default)]
+pub struct DrainReport {
+    /// Frame callbacks with a video frame forwarded while draining.
+    pub video_frames: u64,
+    /// Frame callbacks with an audio packet forwarded while draining.
+    pub audio_packets: u64,
+    /// Whether frames were still buffered when the timeout expired.
+    pub timed_out: bool,
+    /// Whether the drain was cancelled through its token.
+    pub cancelled: bool,
+}
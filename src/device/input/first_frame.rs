@@ -0,0 +1,208 @@
+use crate::device::input::enums::{
+    DecklinkDetectedVideoInputFormatFlags, DecklinkVideoInputFormatChangedEvents,
+};
+use crate::display_mode::DecklinkDisplayModeId;
+use crate::experimental::quirks::QuirkPolicy;
+use crate::frame::{
+    DecklinkFrameBase, DecklinkFrameFlags, DecklinkVideoFrame, DecklinkVideoMutableFrame,
+};
+use crate::settle::{DetectionSettle, SettleConfig, SettleState};
+use crate::time::DecklinkFrameTiming;
+use crate::SdkError;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// Options for `DecklinkInputDevice::wait_first_frame`.
+#[derive(PartialEq, Debug, Clone)]
+pub struct FirstFrameOptions {
+    /// How long to wait before giving up.
+    pub timeout: Duration,
+    /// Whether frames flagged as having no input source are accepted.
+    pub accept_no_signal: bool,
+    /// Number of frames to skip after each format change, to let format detection settle.
+    /// Frames without a signal start the count over, unless they are accepted.
+    pub frames_to_skip_after_format_change: u32,
+}
+
+impl Default for FirstFrameOptions {
+    fn default() -> Self {
+        FirstFrameOptions {
+            timeout: Duration::from_secs(10),
+            accept_no_signal: false,
+            frames_to_skip_after_format_change: 0,
+        }
+    }
+}
+
+/// A token that can be used to cancel a `wait_first_frame` from another thread.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> CancellationToken {
+        Self::default()
+    }
+    /// Cancel any wait using this token.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+/// The first frame received by `DecklinkInputDevice::wait_first_frame`.
+pub struct FirstFrame {
+    /// A copy of the frame.
+    pub frame: DecklinkVideoMutableFrame,
+    /// The timing of the frame, if the display mode timescale was known.
+    pub timing: Option<DecklinkFrameTiming>,
+    /// How long the wait took.
+    pub waited: Duration,
+}
+
+#[derive(Debug)]
+pub enum FirstFrameError {
+    /// No acceptable frame arrived before the timeout.
+    Timeout {
+        /// Number of frames that were rejected for having no input source.
+        no_signal_frames_seen: u32,
+        /// Number of format changes that were notified during the wait.
+        format_changes_seen: u32,
+    },
+    /// The device reported an error.
+    DeviceError(SdkError),
+    /// The wait was cancelled through its token.
+    Cancelled(CancellationToken),
+}
+
+impl From<SdkError> for FirstFrameError {
+    fn from(e: SdkError) -> Self {
+        FirstFrameError::DeviceError(e)
+    }
+}
+
+struct FrameWaiterState {
+    frame: Option<Result<(DecklinkVideoMutableFrame, Option<DecklinkFrameTiming>), SdkError>>,
+    no_signal_frames: u32,
+    format_changes: u32,
+    /// Decides when a frame is accepted. It does not re-enable the input, and the wait has
+    /// one timeout rather than one per phase.
+    settle: DetectionSettle,
+}
+
+/// Observes frames from the input callback, alongside any user handler.
+pub(crate) struct FrameWaiter {
+    options: FirstFrameOptions,
+    state: Mutex<FrameWaiterState>,
+    cond: Condvar,
+}
+
+impl FrameWaiter {
+    pub(crate) fn new(options: FirstFrameOptions) -> FrameWaiter {
+        let config = SettleConfig {
+            stable_frames: 1,
+            stable_frames_after_change: options.frames_to_skip_after_format_change + 1,
+            debounce_frames: 0,
+            quirks: QuirkPolicy::Off,
+            reconfigure: false,
+            ..SettleConfig::default()
+        };
+        let settle = DetectionSettle::new(
+            config,
+            DecklinkDisplayModeId::Unknown,
+            DecklinkDetectedVideoInputFormatFlags::empty(),
+            Instant::now(),
+        );
+        FrameWaiter {
+            options,
+            state: Mutex::new(FrameWaiterState {
+                frame: None,
+                no_signal_frames: 0,
+                format_changes: 0,
+                settle,
+            }),
+            cond: Condvar::new(),
+        }
+    }
+
+    pub(crate) fn format_changed(
+        &self,
+        events: DecklinkVideoInputFormatChangedEvents,
+        mode: DecklinkDisplayModeId,
+        flags: DecklinkDetectedVideoInputFormatFlags,
+    ) {
+        let mut state = self.state.lock().unwrap();
+        state.format_changes += 1;
+        state
+            .settle
+            .format_changed(events, mode, flags, Instant::now());
+    }
+
+    pub(crate) fn frame_arrived(
+        &self,
+        frame: &DecklinkVideoFrame,
+        timing: Option<DecklinkFrameTiming>,
+    ) {
+        let mut state = self.state.lock().unwrap();
+        if state.frame.is_some() {
+            return;
+        }
+
+        let has_signal = self.options.accept_no_signal
+            || !frame
+                .flags()
+                .contains(DecklinkFrameFlags::HAS_NO_INPUT_SOURCE);
+        if !has_signal {
+            state.no_signal_frames += 1;
+        }
+        state.settle.frame(has_signal, Instant::now());
+        if !matches!(state.settle.state(), SettleState::Settled { .. }) {
+            return;
+        }
+
+        state.frame = Some(DecklinkVideoMutableFrame::copy_from(frame).map(|f| (f, timing)));
+        self.cond.notify_all();
+    }
+
+    /// Block until a frame is accepted, the timeout expires, or the token is cancelled.
+    pub(crate) fn wait(
+        &self,
+        cancel: Option<&CancellationToken>,
+    ) -> Result<FirstFrame, FirstFrameError> {
+        let start = Instant::now();
+        let deadline = start + self.options.timeout;
+
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if let Some(res) = state.frame.take() {
+                let (frame, timing) = res?;
+                return Ok(FirstFrame {
+                    frame,
+                    timing,
+                    waited: start.elapsed(),
+                });
+            }
+            if let Some(token) = cancel {
+                if token.is_cancelled() {
+                    return Err(FirstFrameError::Cancelled(token.clone()));
+                }
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(FirstFrameError::Timeout {
+                    no_signal_frames_seen: state.no_signal_frames,
+                    format_changes_seen: state.format_changes,
+                });
+            }
+
+            // Wake up regularly to check the cancellation token
+            let step = Duration::from_millis(50).min(deadline - now);
+            state = self.cond.wait_timeout(state, step).unwrap().0;
+        }
+    }
+}
@@ -0,0 +1,248 @@
+use crate::device::input::audio::DecklinkAudioInputPacket;
+use crate::device::input::enums::{
+    DecklinkDetectedVideoInputFormatFlags, DecklinkVideoInputFormatChangedEvents,
+};
+use crate::device::input::input_frame::DecklinkVideoInputFrame;
+use crate::device::input::video_callback::{
+    CallbackResult, DeckLinkInputCallback, FrameConversionFailure,
+};
+use crate::display_mode::DecklinkDisplayModeId;
+use crate::frame::DecklinkVideoFrame;
+use crate::time::DecklinkFrameTiming;
+use bitflags::bitflags;
+
+/// Receives the callbacks of an input.
+///
+/// Each callback passes a single struct, and the structs are `#[non_exhaustive]`, so what a
+/// callback carries can grow without changing the signature of the methods. Handlers written
+/// against this trait keep compiling as fields are added.
+///
+/// Every `DeckLinkInputCallback` is also an `InputHandler`, so handlers written against the
+/// older trait can be set on an input, or wrapped by the handlers of this crate, unchanged.
+/// See `DeckLinkInputCallback` for how to move one over.
+pub trait InputHandler: Send + Sync {
+    /// Called when the video input format changes (e.g. resolution, field dominance,
+    /// colorspace).
+    fn format_changed(&self, _change: &InputFormatChange) {}
+
+    /// Called for each frame-arrived callback from the driver, and returns the result passed
+    /// back to the driver.
+    ///
+    /// The frame and packet are borrowed for the duration of the call. Use
+    /// `FrameArrival::retain_video_frame` to keep the frame after returning.
+    fn frame_arrived(&self, arrival: &FrameArrival<'_>) -> CallbackResult;
+
+    /// The name of the handler's type, hashed into
+    /// `crate::effective_config::EffectiveConfig::handler` so that reports of the same
+    /// application can be matched. Handlers that only measure another return its name.
+    fn type_name(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
+}
+
+/// A format-changed callback.
+#[non_exhaustive]
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub struct InputFormatChange {
+    pub events: DecklinkVideoInputFormatChangedEvents,
+    /// The detected display mode, or `Unknown` if the driver did not pass one.
+    pub display_mode: DecklinkDisplayModeId,
+    pub detected_signal_flags: DecklinkDetectedVideoInputFormatFlags,
+}
+
+impl InputFormatChange {
+    pub fn new(
+        events: DecklinkVideoInputFormatChangedEvents,
+        display_mode: DecklinkDisplayModeId,
+        detected_signal_flags: DecklinkDetectedVideoInputFormatFlags,
+    ) -> InputFormatChange {
+        InputFormatChange {
+            events,
+            display_mode,
+            detected_signal_flags,
+        }
+    }
+}
+
+/// What is known about a frame-arrived callback besides its frame and audio.
+#[non_exhaustive]
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Default)]
+pub struct FrameContext {
+    /// The timing of the frame, expressed in the timescale of the active display mode, if
+    /// that was known.
+    pub timing: Option<DecklinkFrameTiming>,
+    /// The number of frame-arrived callbacks the input forwarded before this one.
+    pub sequence: u64,
+}
+
+impl FrameContext {
+    pub fn new(timing: Option<DecklinkFrameTiming>, sequence: u64) -> FrameContext {
+        FrameContext { timing, sequence }
+    }
+}
+
+bitflags! {
+    /// Conditions of a frame-arrived callback that only hold for some callbacks.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Default)]
+    pub struct ArrivalFlags: u32 {
+        /// A video frame arrived but could not be read, so `video_frame` is `None`. Such
+        /// frames are also counted by `DecklinkInputDevice::frame_conversion_failure_count`.
+        const FRAME_LOST = 1 << 0;
+        /// The first callback since a format change was notified.
+        const AFTER_FORMAT_CHANGE = 1 << 1;
+        /// The video frame's size differs from that of the enabled display mode, outside a
+        /// format change, so buffers sized from the mode do not fit it. Under
+        /// `DimensionPolicy::Strict` the frame is dropped, so `video_frame` is `None`. Such
+        /// frames are also counted by `DecklinkInputDevice::dimension_mismatch_count`.
+        const DIMENSION_MISMATCH = 1 << 2;
+    }
+}
+
+/// A frame-arrived callback.
+#[non_exhaustive]
+#[derive(Copy, Clone)]
+pub struct FrameArrival<'a> {
+    /// The video frame, or `None` if the callback carried no video, or its frame was lost.
+    pub video_frame: Option<&'a DecklinkVideoFrame>,
+    /// The audio packet, if audio input is enabled and the callback carried one.
+    pub audio_packet: Option<&'a DecklinkAudioInputPacket>,
+    /// The video frame as the input captured it, with its stream time and hardware reference
+    /// timestamp. It is `None` when the captured frame was lost or dropped, and stays the
+    /// captured frame when a handler passes on a processed `video_frame`.
+    pub input_frame: Option<&'a DecklinkVideoInputFrame>,
+    pub context: FrameContext,
+    pub flags: ArrivalFlags,
+}
+
+impl<'a> FrameArrival<'a> {
+    /// An arrival of `video_frame` without audio, context or flags, as for a handler that
+    /// passes frames it held back on to another.
+    pub fn new(video_frame: Option<&'a DecklinkVideoFrame>) -> FrameArrival<'a> {
+        FrameArrival {
+            video_frame,
+            audio_packet: None,
+            input_frame: None,
+            context: FrameContext::default(),
+            flags: ArrivalFlags::empty(),
+        }
+    }
+
+    pub fn with_audio_packet(
+        self,
+        audio_packet: Option<&'a DecklinkAudioInputPacket>,
+    ) -> FrameArrival<'a> {
+        FrameArrival {
+            audio_packet,
+            ..self
+        }
+    }
+
+    pub fn with_input_frame(
+        self,
+        input_frame: Option<&'a DecklinkVideoInputFrame>,
+    ) -> FrameArrival<'a> {
+        FrameArrival {
+            input_frame,
+            ..self
+        }
+    }
+
+    pub fn with_context(self, context: FrameContext) -> FrameArrival<'a> {
+        FrameArrival { context, ..self }
+    }
+
+    pub fn with_flags(self, flags: ArrivalFlags) -> FrameArrival<'a> {
+        FrameArrival { flags, ..self }
+    }
+
+    /// The same callback with its video frame replaced, as for a handler that passes on a
+    /// processed copy of the frame.
+    pub fn with_video_frame(self, video_frame: Option<&'a DecklinkVideoFrame>) -> FrameArrival<'a> {
+        FrameArrival {
+            video_frame,
+            ..self
+        }
+    }
+
+    /// The timing of the frame, from `context`.
+    pub fn timing(&self) -> Option<DecklinkFrameTiming> {
+        self.context.timing
+    }
+
+    /// Take another reference to the video frame, which can be kept after the callback
+    /// returns.
+    ///
+    /// The frame holds its buffer in the driver's capture pool until it is dropped, so keep
+    /// as few as the processing needs. See `crate::retention`.
+    pub fn retain_video_frame(&self) -> Option<DecklinkVideoFrame> {
+        self.video_frame.map(DecklinkVideoFrame::retain)
+    }
+
+    /// Take another reference to the captured frame, which can be kept after the callback
+    /// returns, as `retain_video_frame` does.
+    pub fn retain_input_frame(&self) -> Option<DecklinkVideoInputFrame> {
+        self.input_frame.map(DecklinkVideoInputFrame::retain)
+    }
+
+    /// Take another reference to the audio packet, which can be kept after the callback
+    /// returns.
+    pub fn retain_audio_packet(&self) -> Option<DecklinkAudioInputPacket> {
+        self.audio_packet.map(DecklinkAudioInputPacket::retain)
+    }
+}
+
+/// Calls a `DeckLinkInputCallback` as its documented methods describe.
+///
+/// Each frame and packet is retained for the callback, which owns it. The frame context is
+/// reduced to the timing, and the arrival flags other than `FRAME_LOST` are not passed on. A
+/// frame dropped under `DimensionPolicy::Strict` is not reported, but its audio packet is.
+impl<T: DeckLinkInputCallback + ?Sized> InputHandler for T {
+    fn format_changed(&self, change: &InputFormatChange) {
+        self.video_input_format_changed(
+            change.events,
+            change.display_mode,
+            change.detected_signal_flags,
+        );
+    }
+
+    fn frame_arrived(&self, arrival: &FrameArrival<'_>) -> CallbackResult {
+        if arrival.flags.contains(ArrivalFlags::FRAME_LOST) {
+            if let Some(packet) = arrival.retain_audio_packet() {
+                self.audio_input_packet_arrived(packet);
+            }
+            self.video_input_frame_conversion_failed(FrameConversionFailure {
+                timing: arrival.timing(),
+            });
+            return CallbackResult::Ok;
+        }
+        if arrival.flags.contains(ArrivalFlags::DIMENSION_MISMATCH) && arrival.video_frame.is_none()
+        {
+            if let Some(packet) = arrival.retain_audio_packet() {
+                self.audio_input_packet_arrived(packet);
+            }
+            return CallbackResult::Ok;
+        }
+        self.video_input_frame_and_audio_arrived(
+            arrival.retain_video_frame(),
+            arrival.retain_audio_packet(),
+            arrival.timing(),
+        )
+    }
+}
+
+/// Forwards to the shared handler, so a handler already held as an
+/// `Arc<dyn DeckLinkInputCallback>` can be passed on as an `Arc<dyn InputHandler>` by wrapping
+/// it in another `Arc`.
+impl<T: InputHandler + ?Sized> InputHandler for std::sync::Arc<T> {
+    fn format_changed(&self, change: &InputFormatChange) {
+        (**self).format_changed(change);
+    }
+
+    fn frame_arrived(&self, arrival: &FrameArrival<'_>) -> CallbackResult {
+        (**self).frame_arrived(arrival)
+    }
+
+    fn type_name(&self) -> &'static str {
+        (**self).type_name()
+    }
+}
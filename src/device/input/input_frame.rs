@@ -0,0 +1,83 @@
+use crate::frame::{
+    DecklinkAlignedBytes, DecklinkFrameBase, DecklinkFrameFlags, DecklinkPixelFormat,
+    DecklinkVideoFrame,
+};
+use crate::ptr::VideoInputFramePtr;
+use crate::SdkError;
+
+/// A frame as an input captured it, with the times the driver gives captured frames.
+///
+/// Its pixels are read as those of the video frame it holds, through `DecklinkFrameBase` or
+/// `video_frame`. Like that frame, it can be sent to another thread, but not shared.
+pub struct DecklinkVideoInputFrame {
+    frame: DecklinkVideoFrame,
+    input: VideoInputFramePtr,
+}
+
+// Safety: As for `DecklinkVideoFrame`, both references are counted atomically and can be
+// released from any thread, and the frame can be used on another thread as long as calls on
+// it are not concurrent.
+unsafe impl Send for DecklinkVideoInputFrame {}
+
+impl DecklinkVideoInputFrame {
+    pub(crate) fn new(frame: DecklinkVideoFrame, input: VideoInputFramePtr) -> Self {
+        DecklinkVideoInputFrame { frame, input }
+    }
+
+    /// The `IDeckLinkVideoInputFrame` this wraps, for calls the wrapper does not cover. The
+    /// wrapper still owns its reference, so it must not be released, and must not be used after
+    /// the wrapper is dropped.
+    #[cfg(feature = "raw-api")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "raw-api")))]
+    pub fn as_raw(&self) -> *mut crate::sdk::cdecklink_video_input_frame_t {
+        self.input.as_ptr()
+    }
+
+    /// The video frame, for the calls that take one.
+    pub fn video_frame(&self) -> &DecklinkVideoFrame {
+        &self.frame
+    }
+
+    /// The stream time of the frame and its duration, in ticks of `timescale` per second. The
+    /// stream time counts from the start of the streams, so it restarts with them.
+    pub fn stream_time(&self, timescale: i64) -> Result<(i64, i64), SdkError> {
+        self.input.stream_time(timescale)
+    }
+
+    /// The time the frame was captured and its duration, by the hardware reference clock of
+    /// the device, in ticks of `timescale` per second. Unlike the stream time, it is shared by
+    /// the inputs and outputs of a device, and keeps counting across restarts, so it is the
+    /// clock to measure latency and lip sync against.
+    pub fn hardware_reference_timestamp(&self, timescale: i64) -> Result<(i64, i64), SdkError> {
+        self.input.hardware_reference_timestamp(timescale)
+    }
+
+    /// Take another reference to the frame, which can be kept after the callback returns.
+    pub(crate) fn retain(&self) -> Self {
+        DecklinkVideoInputFrame {
+            frame: self.frame.retain(),
+            input: self.input.clone(),
+        }
+    }
+}
+
+impl DecklinkFrameBase for DecklinkVideoInputFrame {
+    fn width(&self) -> usize {
+        self.frame.width()
+    }
+    fn height(&self) -> usize {
+        self.frame.height()
+    }
+    fn row_bytes(&self) -> usize {
+        self.frame.row_bytes()
+    }
+    fn pixel_format(&self) -> DecklinkPixelFormat {
+        self.frame.pixel_format()
+    }
+    fn flags(&self) -> DecklinkFrameFlags {
+        self.frame.flags()
+    }
+    fn bytes(&self) -> Result<DecklinkAlignedBytes<'_>, SdkError> {
+        self.frame.bytes()
+    }
+}
@@ -1,30 +1,76 @@
+mod audio;
+mod audio_enable;
+mod back_pressure;
+mod color_mode;
 mod device;
+mod dimensions;
 pub mod enums;
+mod first_frame;
+mod handler;
+mod input_frame;
 mod video_callback;
 
 use crate::allocator::{create_c_allocator_provider, VideoBufferAllocatorProvider};
-use crate::device::input::device::DecklinkInputDevicePtr;
+use crate::device::input::audio_enable::{AudioEnableOrder, AUDIO_BEFORE_VIDEO_ERROR};
+use crate::device::input::back_pressure::ReturnPolicy;
+use crate::device::input::device::{
+    CallbackGate, DecklinkInputDevicePtr, VideoInputState, VideoInputStateCell,
+};
+use crate::device::input::dimensions::DimensionCheck;
+use crate::device::input::first_frame::FrameWaiter;
 use crate::device::input::video_callback::{register_input_callback, InputCallbackWrapper};
 use crate::display_mode::{
-    iterate_display_modes, DecklinkDisplayMode, DecklinkDisplayModeId,
+    collect_display_modes, DecklinkDisplayMode, DecklinkDisplayModeId,
+};
+use crate::effective_config::{
+    component_hash, AllocatorKind, AudioSettings, EffectiveConfig, Negotiated,
 };
 use crate::frame::DecklinkPixelFormat;
-use crate::{sdk, SdkError};
+use crate::ptr::{InputPtr, VideoBufferAllocatorProviderPtr};
+use crate::vpid::{Vpid, VpidTracker};
+use crate::{capabilities, SdkError};
 use num_traits::FromPrimitive;
 use std::ptr::null_mut;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use strum::IntoEnumIterator;
 
+pub use crate::device::input::audio::DecklinkAudioInputPacket;
+pub use crate::device::input::audio_enable::{AudioEnableEvent, AudioInputState};
+pub use crate::device::input::back_pressure::{
+    CallbackReturnHandling, CallbackReturnPolicy, CallbackReturnStats, ConsumerPressure,
+    PressureSource, PressureThresholds,
+};
+pub use crate::device::input::color_mode::{CaptureColorMode, ColorModeError};
+pub use crate::device::input::dimensions::{DimensionMismatch, DimensionPolicy, FrameDimensions};
 pub use crate::device::input::enums::*;
-pub use crate::device::input::video_callback::DeckLinkInputCallback;
+pub use crate::device::input::first_frame::{
+    CancellationToken, FirstFrame, FirstFrameError, FirstFrameOptions,
+};
+pub use crate::device::input::handler::{
+    ArrivalFlags, FrameArrival, FrameContext, InputFormatChange, InputHandler,
+};
+pub use crate::device::input::input_frame::DecklinkVideoInputFrame;
+pub use crate::device::input::video_callback::{
+    CallbackResult, DeckLinkInputCallback, FrameConversionFailure,
+};
 use crate::device::DecklinkDeviceDisplayModes;
 
+/// The capture interface of a device.
+///
+/// An input can be sent to another thread, but not shared, as enabling and starting streams
+/// must not race. Keep it on one control thread, and send frames from the callback to the
+/// threads that process them.
 pub struct DecklinkInputDevice {
     ptr: Arc<DecklinkInputDevicePtr>,
     callback_wrapper: *mut InputCallbackWrapper,
-    video_active: bool,
-    /// C allocator provider pointer, released on drop.
-    allocator_provider: *mut sdk::cdecklink_video_buffer_allocator_provider_t,
+    /// The allocator provider video input was enabled with, released on disable and drop.
+    allocator_provider: Option<VideoBufferAllocatorProviderPtr>,
+    /// Cached result of `supported_pixel_formats`.
+    supported_pixel_formats: Mutex<Option<Vec<DecklinkPixelFormat>>>,
+    audio_order: Mutex<AudioEnableOrder>,
+    /// The values negotiated so far, for `effective_config`.
+    effective: Mutex<EffectiveConfig>,
 }
 
 // Safety: The underlying C pointer is thread-safe for the operations we perform
@@ -37,52 +83,94 @@ impl DecklinkDeviceDisplayModes<enums::DecklinkVideoInputFlags> for DecklinkInpu
         pixel_format: DecklinkPixelFormat,
         flags: enums::DecklinkVideoInputFlags,
     ) -> Result<(bool, Option<DecklinkDisplayModeId>), SdkError> {
-        let mut supported = false;
-        let mut display_mode_id: u32 = 0;
-        let result = unsafe {
-            sdk::cdecklink_input_does_support_video_mode(
-                self.ptr.dev,
-                sdk::_DecklinkVideoConnection_decklinkVideoConnectionUnspecified,
-                mode as u32,
-                pixel_format as u32,
-                sdk::_DecklinkVideoInputConversionMode_decklinkNoVideoInputConversion,
-                flags.bits(),
-                &mut display_mode_id,
-                &mut supported,
-            )
-        };
-        SdkError::result_or_else(result, move || {
-            let possible_mode = DecklinkDisplayModeId::from_u32(display_mode_id);
-            (supported, possible_mode)
-        })
+        let (supported, display_mode_id) =
+            self.ptr
+                .dev
+                .does_support_video_mode(mode as u32, pixel_format as u32, flags.bits())?;
+        Ok((supported, DecklinkDisplayModeId::from_u32(display_mode_id)))
     }
 
     fn display_modes(&self) -> Result<Vec<DecklinkDisplayMode>, SdkError> {
-        unsafe {
-            let mut it = null_mut();
-            let ok = sdk::cdecklink_input_get_display_mode_iterator(self.ptr.dev, &mut it);
-            if SdkError::is_ok(ok) {
-                let v = iterate_display_modes(it);
-                sdk::cdecklink_display_mode_iterator_release(it);
-                v
-            } else {
-                Err(SdkError::from(ok))
-            }
-        }
+        collect_display_modes(self.ptr.dev.display_mode_iterator()?)
     }
 }
 
 impl DecklinkInputDevice {
-    pub(crate) fn from(ptr: *mut crate::sdk::cdecklink_input_t) -> DecklinkInputDevice {
+    /// The `IDeckLinkInput` this wraps, for calls the wrapper does not cover. The wrapper still
+    /// owns its reference, so it must not be released, and must not be used after the wrapper is
+    /// dropped.
+    #[cfg(feature = "raw-api")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "raw-api")))]
+    pub fn as_raw(&self) -> *mut crate::sdk::cdecklink_input_t {
+        self.ptr.dev.as_ptr()
+    }
+
+    pub(crate) fn from(dev: InputPtr) -> DecklinkInputDevice {
         DecklinkInputDevice {
             ptr: Arc::new(DecklinkInputDevicePtr {
-                dev: ptr,
-                video_active: Arc::new(AtomicBool::new(false)),
+                dev,
+                video_state: VideoInputStateCell::new(),
+                frame_duration: Arc::new(RwLock::new(None)),
+                audio_format: Arc::new(RwLock::new(None)),
+                gate: Arc::new(CallbackGate::new()),
+                dimensions: Arc::new(DimensionCheck::new()),
+                vpid: Arc::new(VpidTracker::new()),
+                returns: Arc::new(ReturnPolicy::new()),
             }),
             callback_wrapper: null_mut(),
-            video_active: false,
-            allocator_provider: null_mut(),
+            allocator_provider: None,
+            supported_pixel_formats: Mutex::new(None),
+            audio_order: Mutex::new(AudioEnableOrder::default()),
+            effective: Mutex::new(EffectiveConfig::new()),
+        }
+    }
+
+    /// Get the pixel formats that this input can capture in at least one display mode.
+    ///
+    /// This probes the device for every known format, so the result is cached. Use
+    /// `refresh_supported_pixel_formats` to probe again. To check a specific display mode,
+    /// use `does_support_video_mode`.
+    pub fn supported_pixel_formats(&self) -> Result<Vec<DecklinkPixelFormat>, SdkError> {
+        let mut cached = self.supported_pixel_formats.lock().unwrap();
+        if let Some(formats) = &*cached {
+            return Ok(formats.clone());
+        }
+
+        let formats = self.probe_pixel_formats()?;
+        *cached = Some(formats.clone());
+        Ok(formats)
+    }
+
+    /// Discard the cached `supported_pixel_formats` and probe the device again.
+    pub fn refresh_supported_pixel_formats(&self) -> Result<Vec<DecklinkPixelFormat>, SdkError> {
+        *self.supported_pixel_formats.lock().unwrap() = None;
+        self.supported_pixel_formats()
+    }
+
+    fn probe_pixel_formats(&self) -> Result<Vec<DecklinkPixelFormat>, SdkError> {
+        let mut modes = self.display_modes()?;
+
+        // Probe a HD mode first, as that is where most formats are supported
+        if let Some(index) = modes.iter().position(|m| m.width() >= 1280) {
+            let hd_mode = modes.remove(index);
+            modes.insert(0, hd_mode);
+        }
+
+        let mut formats = Vec::new();
+        for format in DecklinkPixelFormat::iter() {
+            for mode in &modes {
+                let (supported, _) = self.does_support_video_mode(
+                    mode.mode(),
+                    format,
+                    enums::DecklinkVideoInputFlags::empty(),
+                )?;
+                if supported {
+                    formats.push(format);
+                    break;
+                }
+            }
         }
+        Ok(formats)
     }
 
     /// Enable video input with the specified display mode, pixel format, and flags.
@@ -93,40 +181,145 @@ impl DecklinkInputDevice {
         pixel_format: DecklinkPixelFormat,
         flags: enums::DecklinkVideoInputFlags,
     ) -> Result<(), SdkError> {
-        if self.ptr.video_active.swap(true, Ordering::Relaxed) {
-            return Err(SdkError::ACCESSDENIED);
+        self.begin_enable()?;
+        let result =
+            self.ptr
+                .dev
+                .enable_video_input(mode as u32, pixel_format as u32, flags.bits());
+        if let Err(e) = result {
+            self.ptr.video_state.set(VideoInputState::Disabled);
+            return Err(e);
         }
-        let result = unsafe {
-            sdk::cdecklink_input_enable_video_input(
-                self.ptr.dev,
-                mode as u32,
-                pixel_format as u32,
-                flags.bits(),
+        self.cache_mode(mode);
+        self.effective.lock().unwrap().record_video(
+            mode,
+            pixel_format,
+            flags.bits(),
+            AllocatorKind::Driver,
+        );
+        self.ptr.video_state.set(VideoInputState::Enabled);
+        self.enable_deferred_audio_input()
+    }
+
+    /// Move video input from disabled to enabling, failing with `SdkError::ACCESSDENIED` if
+    /// it is enabled or being enabled or disabled.
+    fn begin_enable(&self) -> Result<(), SdkError> {
+        self.ptr
+            .video_state
+            .transition(VideoInputState::Disabled, VideoInputState::Enabling)
+            .map_err(|_| SdkError::ACCESSDENIED)
+    }
+
+    /// Whether video input is enabled.
+    fn video_enabled(&self) -> bool {
+        self.ptr.video_state.get() == VideoInputState::Enabled
+    }
+
+    /// Cache the frame duration of `mode`, used to express frame timings, and its size, used
+    /// to check the size of each frame.
+    fn cache_mode(&self, mode: DecklinkDisplayModeId) {
+        let (duration, size) = if let Some(display_mode) = self.ptr.dev.display_mode(mode as u32) {
+            let display_mode = DecklinkDisplayMode::from_ptr(display_mode);
+            (
+                display_mode.frame_duration(),
+                Some((mode, display_mode.width(), display_mode.height())),
             )
+        } else {
+            (None, None)
         };
-        if !SdkError::is_ok(result) {
-            self.ptr.video_active.store(false, Ordering::Relaxed);
-            return Err(SdkError::from(result));
+        *self.ptr.frame_duration.write().unwrap() = duration;
+        self.ptr.dimensions.set_mode(size);
+    }
+
+    /// Enable video input with the first pixel format allowed by `preference` that the
+    /// device supports for `mode`. Returns the format that was chosen.
+    ///
+    /// Fails with `SdkError::NOTIMPL` if none of the candidate formats are supported.
+    pub fn enable_video_input_with_preference(
+        &mut self,
+        mode: DecklinkDisplayModeId,
+        preference: &enums::PixelFormatPreference,
+        flags: enums::DecklinkVideoInputFlags,
+    ) -> Result<enums::ChosenFormat, SdkError> {
+        let requested = preference.requested();
+        for pixel_format in preference.candidates() {
+            if self.does_support_video_mode(mode, pixel_format, flags)?.0 {
+                self.enable_video_input(mode, pixel_format, flags)?;
+                self.effective.lock().unwrap().pixel_format.requested = requested;
+                return Ok(enums::ChosenFormat {
+                    pixel_format,
+                    requested,
+                });
+            }
         }
-        self.video_active = true;
-        Ok(())
+        Err(SdkError::NOTIMPL)
+    }
+
+    /// Enable video input in `color_mode`, with `pixel_format` or, if `None`, the first
+    /// format of the color mode that the device supports for `mode`. Returns the format that
+    /// was chosen.
+    ///
+    /// `Auto` is resolved from `detected`, the flags of the last detected format if known.
+    /// Fails with `ColorModeError::Incompatible` if `pixel_format` does not capture in the
+    /// color mode, as YUV formats cannot hold a 4:4:4 signal.
+    pub fn enable_video_input_with_color_mode(
+        &mut self,
+        mode: DecklinkDisplayModeId,
+        color_mode: CaptureColorMode,
+        pixel_format: Option<DecklinkPixelFormat>,
+        detected: Option<enums::DecklinkDetectedVideoInputFormatFlags>,
+        flags: enums::DecklinkVideoInputFlags,
+    ) -> Result<DecklinkPixelFormat, ColorModeError> {
+        let (requested_color_mode, requested_format) = (color_mode, pixel_format);
+        let color_mode = match detected {
+            Some(detected) => color_mode.resolve(detected),
+            None => color_mode,
+        };
+
+        let pixel_format = match pixel_format {
+            Some(pixel_format) if !color_mode.is_compatible(pixel_format) => {
+                return Err(ColorModeError::Incompatible {
+                    color_mode,
+                    pixel_format,
+                });
+            }
+            Some(pixel_format) => pixel_format,
+            None => {
+                let mut chosen = None;
+                for pixel_format in color_mode.pixel_formats() {
+                    if self.does_support_video_mode(mode, pixel_format, flags)?.0 {
+                        chosen = Some(pixel_format);
+                        break;
+                    }
+                }
+                chosen.ok_or(ColorModeError::Unsupported { color_mode })?
+            }
+        };
+
+        self.enable_video_input(mode, pixel_format, flags)?;
+        let mut effective = self.effective.lock().unwrap();
+        effective.color_mode = Negotiated {
+            requested: Some(requested_color_mode),
+            actual: Some(color_mode),
+        };
+        effective.pixel_format.requested = requested_format;
+        Ok(pixel_format)
     }
 
     /// Disable video input.
     pub fn disable_video_input(&mut self) -> Result<(), SdkError> {
-        let result = unsafe { sdk::cdecklink_input_disable_video_input(self.ptr.dev) };
-        self.video_active = false;
-        self.ptr.video_active.store(false, Ordering::Relaxed);
+        self.ptr.gate.close();
+        self.ptr.video_state.set(VideoInputState::Disabling);
+        let result = self.ptr.dev.disable_video_input();
+        *self.ptr.frame_duration.write().unwrap() = None;
+        self.ptr.dimensions.set_mode(None);
+        self.ptr.vpid.clear();
 
-        // Release the allocator provider if one was set
-        if !self.allocator_provider.is_null() {
-            unsafe {
-                sdk::cdecklink_video_buffer_allocator_provider_release(self.allocator_provider)
-            };
-            self.allocator_provider = null_mut();
-        }
+        // Release the allocator provider if one was set, before an enable can begin again
+        self.allocator_provider = None;
+        self.ptr.video_state.set(VideoInputState::Disabled);
 
-        SdkError::result(result)
+        result
     }
 
     /// Enable video input with a custom allocator provider.
@@ -138,7 +331,8 @@ impl DecklinkInputDevice {
     /// specifications, and those allocators will be used to allocate individual
     /// video buffers where DeckLink DMAs frame data.
     ///
-    /// A callback must be set before starting streams.
+    /// A callback must be set before starting streams. Fails with `SdkError::NOINTERFACE` if
+    /// the drivers are too old to support custom allocators, as reported by `capabilities`.
     pub fn enable_video_input_with_allocator(
         &mut self,
         mode: DecklinkDisplayModeId,
@@ -146,70 +340,195 @@ impl DecklinkInputDevice {
         flags: enums::DecklinkVideoInputFlags,
         provider: Arc<dyn VideoBufferAllocatorProvider>,
     ) -> Result<(), SdkError> {
-        if self.ptr.video_active.swap(true, Ordering::Relaxed) {
-            return Err(SdkError::ACCESSDENIED);
+        if !capabilities().allocator_provider {
+            return Err(SdkError::NOINTERFACE);
         }
+        self.begin_enable()?;
 
         // Create the C allocator provider from the Rust trait object
-        let c_provider = create_c_allocator_provider(provider)?;
-
-        let result = unsafe {
-            sdk::cdecklink_input_enable_video_input_with_allocator_provider(
-                self.ptr.dev,
-                mode as u32,
-                pixel_format as u32,
-                flags.bits(),
-                c_provider,
-            )
+        let c_provider = match create_c_allocator_provider(provider) {
+            Ok(c_provider) => c_provider,
+            Err(e) => {
+                self.ptr.video_state.set(VideoInputState::Disabled);
+                return Err(e);
+            }
         };
 
-        if !SdkError::is_ok(result) {
-            // Release the C provider on failure
-            unsafe { sdk::cdecklink_video_buffer_allocator_provider_release(c_provider) };
-            self.ptr.video_active.store(false, Ordering::Relaxed);
-            return Err(SdkError::from(result));
+        let result = self.ptr.dev.enable_video_input_with_allocator_provider(
+            mode as u32,
+            pixel_format as u32,
+            flags.bits(),
+            &c_provider,
+        );
+
+        if let Err(e) = result {
+            // Release the C provider on failure, before an enable can begin again and find
+            // the driver still holding it
+            drop(c_provider);
+            self.ptr.video_state.set(VideoInputState::Disabled);
+            return Err(e);
         }
 
         // Store the provider so we release it on drop/disable
-        self.allocator_provider = c_provider;
-        self.video_active = true;
-        Ok(())
+        self.allocator_provider = Some(c_provider);
+        self.cache_mode(mode);
+        self.effective.lock().unwrap().record_video(
+            mode,
+            pixel_format,
+            flags.bits(),
+            AllocatorKind::Provider,
+        );
+        self.ptr.video_state.set(VideoInputState::Enabled);
+        self.enable_deferred_audio_input()
     }
 
     /// Enable audio input with the specified sample rate, sample type, and channel count.
+    ///
+    /// # Ordering with video input
+    ///
+    /// Some drivers only enable audio input once video input is enabled, and fail with
+    /// `SdkError::FAIL` if it is not, while others enable them in either order. Enable video
+    /// input first to work with both, or use `request_audio_input`, which waits for video
+    /// input where the driver needs it. What this input's driver does is learnt from the
+    /// result of enabling audio input before video input, and answered by
+    /// `audio_requires_video_first`.
     pub fn enable_audio_input(
         &self,
         sample_rate: enums::DecklinkAudioSampleRate,
         sample_type: enums::DecklinkAudioSampleType,
         channel_count: u32,
     ) -> Result<(), SdkError> {
-        let result = unsafe {
-            sdk::cdecklink_input_enable_audio_input(
-                self.ptr.dev,
-                sample_rate as u32,
-                sample_type as u32,
+        let result =
+            self.ptr
+                .dev
+                .enable_audio_input(sample_rate as u32, sample_type as u32, channel_count);
+        if !self.video_enabled() {
+            self.audio_order.lock().unwrap().observe(result);
+        }
+        result?;
+
+        *self.ptr.audio_format.write().unwrap() = Some((sample_type, channel_count));
+        self.effective.lock().unwrap().audio = Negotiated::granted(AudioSettings {
+            sample_rate,
+            sample_type,
+            channel_count,
+        });
+        Ok(())
+    }
+
+    /// Enable audio input as `enable_audio_input` does, or, if video input is not enabled
+    /// and the driver needs it to be, as soon as video input is enabled.
+    ///
+    /// A deferred audio input is enabled by whichever `enable_video_input` method enables
+    /// video input next, and an error enabling it is returned from that method, with video
+    /// input left enabled. `take_audio_enable_events` reports each deferral.
+    pub fn request_audio_input(
+        &self,
+        sample_rate: enums::DecklinkAudioSampleRate,
+        sample_type: enums::DecklinkAudioSampleType,
+        channel_count: u32,
+    ) -> Result<AudioInputState, SdkError> {
+        let known = self.audio_requires_video_first();
+        let video_enabled = self.video_enabled();
+        if video_enabled || known != Some(true) {
+            match self.enable_audio_input(sample_rate, sample_type, channel_count) {
+                Ok(()) => return Ok(AudioInputState::Enabled),
+                Err(AUDIO_BEFORE_VIDEO_ERROR) if !video_enabled => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        let mut order = self.audio_order.lock().unwrap();
+        order.pending = Some((sample_rate, sample_type, channel_count));
+        order.events.push(AudioEnableEvent::Deferred);
+        let mut effective = self.effective.lock().unwrap();
+        effective.audio = Negotiated {
+            requested: Some(AudioSettings {
+                sample_rate,
+                sample_type,
                 channel_count,
-            )
+            }),
+            actual: None,
         };
-        SdkError::result(result)
+        effective.audio_deferred = true;
+        Ok(AudioInputState::Deferred)
     }
 
-    /// Disable audio input.
+    /// Enable the audio input `request_audio_input` deferred, now that video input is.
+    fn enable_deferred_audio_input(&self) -> Result<(), SdkError> {
+        let pending = self.audio_order.lock().unwrap().pending.take();
+        let Some((sample_rate, sample_type, channel_count)) = pending else {
+            return Ok(());
+        };
+        let result = self.enable_audio_input(sample_rate, sample_type, channel_count);
+
+        let mut order = self.audio_order.lock().unwrap();
+        if result.is_ok() {
+            order.deferred += 1;
+            order.events.push(AudioEnableEvent::Enabled);
+        } else {
+            order.events.push(AudioEnableEvent::Failed);
+        }
+        result
+    }
+
+    /// Whether the driver only enables audio input once video input is enabled, if that has
+    /// been seen, by enabling audio input before video input on this input.
+    pub fn audio_requires_video_first(&self) -> Option<bool> {
+        self.audio_order.lock().unwrap().requires_video_first
+    }
+
+    /// Whether audio input was deferred by `request_audio_input` and is waiting for video
+    /// input to be enabled.
+    pub fn audio_input_pending(&self) -> bool {
+        self.audio_order.lock().unwrap().pending.is_some()
+    }
+
+    /// Get the number of audio inputs deferred by `request_audio_input` that were enabled
+    /// after video input.
+    pub fn deferred_audio_input_count(&self) -> u64 {
+        self.audio_order.lock().unwrap().deferred
+    }
+
+    /// Take the deferrals of audio input since the last call, oldest first.
+    pub fn take_audio_enable_events(&self) -> Vec<AudioEnableEvent> {
+        std::mem::take(&mut self.audio_order.lock().unwrap().events)
+    }
+
+    /// Disable audio input, and drop any audio input waiting for video input.
     pub fn disable_audio_input(&self) -> Result<(), SdkError> {
-        let result = unsafe { sdk::cdecklink_input_disable_audio_input(self.ptr.dev) };
-        SdkError::result(result)
+        self.audio_order.lock().unwrap().pending = None;
+        let result = self.ptr.dev.disable_audio_input();
+        *self.ptr.audio_format.write().unwrap() = None;
+        let mut effective = self.effective.lock().unwrap();
+        effective.audio = Negotiated::default();
+        effective.audio_deferred = false;
+        result
     }
 
     /// Set the input callback handler. Must be called before `start_streams`.
+    ///
+    /// The callback is called through `InputHandler`, as `set_handler` would call it, and
+    /// replaces any handler set with it.
     pub fn set_callback(
         &mut self,
         handler: Option<Arc<dyn DeckLinkInputCallback>>,
     ) -> Result<(), SdkError> {
+        self.set_handler(handler.map(|h| Arc::new(h) as Arc<dyn InputHandler>))
+    }
+
+    /// Set the handler of the input's callbacks. Must be called before `start_streams`.
+    ///
+    /// Any `DeckLinkInputCallback` is also an `InputHandler`, so can be passed here as well
+    /// as to `set_callback`.
+    pub fn set_handler(&mut self, handler: Option<Arc<dyn InputHandler>>) -> Result<(), SdkError> {
         // Register the internal C callback wrapper if not already done
         if self.callback_wrapper.is_null() {
             self.callback_wrapper = register_input_callback(&self.ptr)?;
         }
 
+        self.effective.lock().unwrap().handler =
+            handler.as_ref().map(|h| component_hash(h.type_name()));
         unsafe {
             let wrapper = &(*self.callback_wrapper);
             *wrapper.handler.write().unwrap() = handler;
@@ -217,76 +536,248 @@ impl DecklinkInputDevice {
         Ok(())
     }
 
+    /// Block until the first acceptable frame arrives, or `options.timeout` expires.
+    ///
+    /// Streams must already be started, or be started from another thread. Any handler set
+    /// with `set_handler` continues to receive every frame during the wait.
+    pub fn wait_first_frame(
+        &mut self,
+        options: FirstFrameOptions,
+        cancel: Option<&CancellationToken>,
+    ) -> Result<FirstFrame, FirstFrameError> {
+        if self.callback_wrapper.is_null() {
+            self.callback_wrapper = register_input_callback(&self.ptr)?;
+        }
+
+        let waiter = Arc::new(FrameWaiter::new(options));
+        let wrapper = unsafe { &(*self.callback_wrapper) };
+        wrapper.waiters.lock().unwrap().push(waiter.clone());
+
+        let result = waiter.wait(cancel);
+
+        wrapper
+            .waiters
+            .lock()
+            .unwrap()
+            .retain(|w| !Arc::ptr_eq(w, &waiter));
+
+        result
+    }
+
     /// Start capturing streams (video and/or audio).
     pub fn start_streams(&self) -> Result<(), SdkError> {
-        let result = unsafe { sdk::cdecklink_input_start_streams(self.ptr.dev) };
-        SdkError::result(result)
+        self.ptr.gate.open();
+        let result = self.ptr.dev.start_streams();
+        if result.is_err() {
+            self.ptr.gate.close();
+        }
+        result
     }
 
     /// Stop capturing streams.
+    ///
+    /// Callbacks the driver starts after this has been called, including any it makes after
+    /// this returns, are not forwarded to the handler, and are counted in
+    /// `suppressed_callback_count`. A callback already being forwarded when this is called
+    /// is not interrupted, and may still reach the handler after this returns.
     pub fn stop_streams(&self) -> Result<(), SdkError> {
-        let result = unsafe { sdk::cdecklink_input_stop_streams(self.ptr.dev) };
-        SdkError::result(result)
+        self.ptr.gate.close();
+        self.ptr.dev.stop_streams()
+    }
+
+    /// Stop capturing streams, then block until the driver has made no callbacks for
+    /// `quiet_period`. Defaults to two frame durations of the active display mode.
+    ///
+    /// Fails with `SdkError::ABORT` if callbacks are still arriving after ten quiet periods.
+    pub fn stop_streams_quiesced(&self, quiet_period: Option<Duration>) -> Result<(), SdkError> {
+        self.stop_streams()?;
+
+        let quiet_period = quiet_period.unwrap_or_else(|| {
+            let frame = self.ptr.frame_duration.read().unwrap().map(|d| d.as_secs_f64());
+            Duration::from_secs_f64(frame.unwrap_or(1.0 / 25.0) * 2.0)
+        });
+        if self.ptr.gate.wait_quiet(quiet_period) {
+            Ok(())
+        } else {
+            Err(SdkError::ABORT)
+        }
+    }
+
+    /// Stop capturing streams after delivering the frames that are already buffered, so the
+    /// last frames before the stop are not lost.
+    ///
+    /// Capture is paused, so no new frames are buffered, and callbacks are still forwarded
+    /// to the handler until the frames buffered at the pause have been delivered, `timeout`
+    /// expires or `cancel` is cancelled. Streams are then stopped as by `stop_streams`, so callbacks
+    /// arriving after that are suppressed. If nothing is buffered, this stops straight away
+    /// and returns an empty report.
+    pub fn stop_streams_drained(
+        &self,
+        timeout: Duration,
+        cancel: Option<&CancellationToken>,
+    ) -> Result<DrainReport, SdkError> {
+        let (video_before, audio_before) = self.ptr.gate.delivered_counts();
+        let mut report = DrainReport::default();
+
+        // Pause without closing the gate, so the buffered frames still reach the handler
+        let drained = self.ptr.dev.pause_streams().and_then(|()| {
+            let deadline = Instant::now() + timeout;
+            // The driver takes a frame off its buffer before calling back with it, so the
+            // last frame may still be on its way once the count reaches zero. What was
+            // delivered is read before what is buffered, so a frame delivered in between is
+            // counted in neither rather than in both, and more frames are never waited for
+            // than will arrive. That frame is waited for as a callback in flight instead.
+            let delivered = self.ptr.gate.delivered_counts().0;
+            let expected = delivered + self.available_video_frame_count()? as u64;
+            while self.available_video_frame_count()? > 0
+                || self.ptr.gate.delivered_counts().0 < expected
+                || self.ptr.gate.in_flight() > 0
+            {
+                if cancel.is_some_and(|c| c.is_cancelled()) {
+                    report.cancelled = true;
+                    break;
+                }
+                if Instant::now() >= deadline {
+                    report.timed_out = true;
+                    break;
+                }
+                std::thread::sleep(Duration::from_millis(2));
+            }
+            Ok(())
+        });
+
+        // Stop even if draining failed, so the streams are never left paused
+        self.stop_streams()?;
+        drained?;
+
+        let (video_after, audio_after) = self.ptr.gate.delivered_counts();
+        report.video_frames = video_after - video_before;
+        report.audio_packets = audio_after - audio_before;
+        Ok(report)
+    }
+
+    /// Get the number of callbacks that were dropped because they arrived after streams
+    /// were stopped, paused or disabled.
+    pub fn suppressed_callback_count(&self) -> u64 {
+        self.ptr.gate.suppressed_count()
+    }
+
+    /// Get the number of video frames that arrived but could not be read, and were reported
+    /// to the handler with `ArrivalFlags::FRAME_LOST`.
+    pub fn frame_conversion_failure_count(&self) -> u64 {
+        self.ptr.gate.conversion_failure_count()
+    }
+
+    /// Set what is done with frames whose size differs from that of the enabled display mode,
+    /// outside a format change. Defaults to `DimensionPolicy::Permissive`.
+    pub fn set_dimension_policy(&self, policy: DimensionPolicy) {
+        self.ptr.dimensions.set_policy(policy);
+    }
+
+    pub fn dimension_policy(&self) -> DimensionPolicy {
+        self.ptr.dimensions.policy()
+    }
+
+    /// Get the number of video frames whose size differed from that of the enabled display
+    /// mode, and were reported to the handler with `ArrivalFlags::DIMENSION_MISMATCH`.
+    pub fn dimension_mismatch_count(&self) -> u64 {
+        self.ptr.dimensions.count()
+    }
+
+    /// Take the frames whose size differed from that of the enabled display mode since the
+    /// last call, oldest first. Only the most recent are kept.
+    pub fn take_dimension_mismatches(&self) -> Vec<DimensionMismatch> {
+        self.ptr.dimensions.take()
+    }
+
+    /// Set what frame callbacks return to the driver. Defaults to
+    /// `CallbackReturnPolicy::HandlerResult`. Takes effect from the next callback.
+    ///
+    /// The handling of the installed drivers is looked up here, in
+    /// `crate::LibraryCapabilities::callback_returns`, and reported in
+    /// `callback_return_stats`.
+    pub fn set_callback_return_policy(&self, policy: CallbackReturnPolicy) {
+        let handling = capabilities().callback_returns;
+        self.ptr.returns.set(policy, handling);
+    }
+
+    pub fn callback_return_policy(&self) -> CallbackReturnPolicy {
+        self.ptr.returns.policy()
+    }
+
+    /// Add a layer whose `ConsumerPressure` the callback return policy is given, combined
+    /// with that of the others added.
+    pub fn add_pressure_source(&self, source: Arc<dyn PressureSource>) {
+        self.ptr.returns.add_source(source);
+    }
+
+    /// Remove every pressure source added with `add_pressure_source`.
+    pub fn clear_pressure_sources(&self) {
+        self.ptr.returns.clear_sources();
+    }
+
+    /// Get what frame callbacks have returned to the driver, and what the installed drivers
+    /// do with it.
+    pub fn callback_return_stats(&self) -> CallbackReturnStats {
+        self.ptr.returns.stats()
+    }
+
+    /// The payload identifier kept for `crate::vpid::DecklinkVpidExt::vpid`.
+    pub(crate) fn signal_vpid(&self) -> Result<Option<Vpid>, SdkError> {
+        self.ptr.vpid.get()
+    }
+
+    /// The values negotiated so far, for
+    /// `crate::effective_config::DecklinkEffectiveConfigExt::effective_config`.
+    pub(crate) fn negotiated_config(&self) -> EffectiveConfig {
+        let mut config = self.effective.lock().unwrap().clone();
+        config.dimension_policy = self.dimension_policy();
+        config
     }
 
     /// Pause capturing streams.
     pub fn pause_streams(&self) -> Result<(), SdkError> {
-        let result = unsafe { sdk::cdecklink_input_pause_streams(self.ptr.dev) };
-        SdkError::result(result)
+        self.ptr.gate.close();
+        self.ptr.dev.pause_streams()
     }
 
     /// Flush all buffered frames.
     pub fn flush_streams(&self) -> Result<(), SdkError> {
-        let result = unsafe { sdk::cdecklink_input_flush_streams(self.ptr.dev) };
-        SdkError::result(result)
+        self.ptr.dev.flush_streams()
     }
 
     /// Get the number of available video frames in the buffer.
     pub fn available_video_frame_count(&self) -> Result<u32, SdkError> {
-        let mut count = 0u32;
-        let result = unsafe {
-            sdk::cdecklink_input_get_available_video_frame_count(self.ptr.dev, &mut count)
-        };
-        SdkError::result_or(result, count)
+        self.ptr.dev.available_video_frame_count()
     }
 
     /// Get the number of available audio sample frames in the buffer.
     pub fn available_audio_sample_frame_count(&self) -> Result<u32, SdkError> {
-        let mut count = 0u32;
-        let result = unsafe {
-            sdk::cdecklink_input_get_available_audio_sample_frame_count(self.ptr.dev, &mut count)
-        };
-        SdkError::result_or(result, count)
+        self.ptr.dev.available_audio_sample_frame_count()
     }
 }
 
 impl Drop for DecklinkInputDevice {
     fn drop(&mut self) {
-        unsafe {
-            if self.video_active {
-                let _ = sdk::cdecklink_input_stop_streams(self.ptr.dev);
-                let _ = sdk::cdecklink_input_disable_video_input(self.ptr.dev);
-                self.ptr.video_active.store(false, Ordering::Relaxed);
-            }
+        self.ptr.gate.close();
+        if self.video_enabled() {
+            self.ptr.video_state.set(VideoInputState::Disabling);
+            let _ = self.ptr.dev.stop_streams();
+            let _ = self.ptr.dev.disable_video_input();
+        }
 
-            // Clear the callback to release the C++ side reference
-            if !self.callback_wrapper.is_null() {
+        // Clear the callback to release the C++ side reference
+        if !self.callback_wrapper.is_null() {
+            unsafe {
                 // Set a null callback to ensure no more callbacks fire
-                sdk::cdecklink_input_set_callback(
-                    self.ptr.dev,
-                    null_mut(),
-                    None,
-                    None,
-                );
+                let _ = self.ptr.dev.set_callback(null_mut(), None, None);
                 drop(Box::from_raw(self.callback_wrapper));
-                self.callback_wrapper = null_mut();
-            }
-
-            // Release the allocator provider if one was set
-            if !self.allocator_provider.is_null() {
-                sdk::cdecklink_video_buffer_allocator_provider_release(self.allocator_provider);
-                self.allocator_provider = null_mut();
             }
+            self.callback_wrapper = null_mut();
         }
+
+        // Release the allocator provider if one was set
+        self.allocator_provider = None;
+        self.ptr.video_state.set(VideoInputState::Disabled);
     }
 }
@@ -1,12 +1,26 @@
-use crate::device::input::device::DecklinkInputDevicePtr;
+use crate::device::input::audio::DecklinkAudioInputPacket;
+use crate::device::input::back_pressure::ReturnPolicy;
+use crate::device::input::device::{CallbackGate, DecklinkInputDevicePtr};
+use crate::device::input::dimensions::DimensionCheck;
 use crate::device::input::enums::{
-    DecklinkDetectedVideoInputFormatFlags, DecklinkVideoInputFormatChangedEvents,
+    DecklinkAudioSampleType, DecklinkDetectedVideoInputFormatFlags,
+    DecklinkVideoInputFormatChangedEvents,
 };
+use crate::device::input::first_frame::FrameWaiter;
+use crate::device::input::handler::{
+    ArrivalFlags, FrameArrival, FrameContext, InputFormatChange, InputHandler,
+};
+use crate::device::input::input_frame::DecklinkVideoInputFrame;
 use crate::display_mode::DecklinkDisplayModeId;
 use crate::frame::DecklinkVideoFrame;
+use crate::ptr::{DisplayModePtr, VideoInputFramePtr};
+use crate::time::{DecklinkFrameTiming, DecklinkTime};
+use crate::util::{track_created, track_dropped};
+use crate::vpid::VpidTracker;
 use crate::{sdk, SdkError};
 use num_traits::FromPrimitive;
-use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
 
 pub(crate) fn free_callback_wrapper(wrapper: *mut InputCallbackWrapper) {
     unsafe {
@@ -14,32 +28,67 @@ pub(crate) fn free_callback_wrapper(wrapper: *mut InputCallbackWrapper) {
     }
 }
 
-pub fn register_input_callback(
+pub(crate) fn register_input_callback(
     ptr: &Arc<DecklinkInputDevicePtr>,
 ) -> Result<*mut InputCallbackWrapper, SdkError> {
     let callback_wrapper = Box::into_raw(Box::new(InputCallbackWrapper {
         handler: RwLock::new(None),
+        frame_duration: ptr.frame_duration.clone(),
+        audio_format: ptr.audio_format.clone(),
+        waiters: Mutex::new(Vec::new()),
+        gate: ptr.gate.clone(),
+        dimensions: ptr.dimensions.clone(),
+        vpid: ptr.vpid.clone(),
+        returns: ptr.returns.clone(),
+        format_changed: AtomicBool::new(false),
     }));
+    track_created("InputCallbackWrapper", callback_wrapper);
 
     let result = unsafe {
-        sdk::cdecklink_input_set_callback(
-            ptr.dev,
+        ptr.dev.set_callback(
             callback_wrapper as *mut std::ffi::c_void,
             Some(video_input_format_changed_callback),
             Some(video_input_frame_arrived_callback),
         )
     };
 
-    match SdkError::result_or(result, callback_wrapper) {
+    match result {
         Err(e) => {
             free_callback_wrapper(callback_wrapper);
             Err(e)
         }
-        Ok(v) => Ok(v),
+        Ok(()) => Ok(callback_wrapper),
     }
 }
 
-/// Trait for receiving input callbacks from the DeckLink device.
+/// Trait for receiving input callbacks from the DeckLink device, with a method per part of
+/// each callback.
+///
+/// This is the first version of the callback surface, kept so that existing callbacks
+/// compile unchanged. Every `DeckLinkInputCallback` is an `InputHandler`, which is what inputs
+/// and the wrapping handlers of this crate call, so a callback can still be set with
+/// `DecklinkInputDevice::set_callback` or wrapped as before. Through this trait a callback
+/// owns each frame and packet it is given, retained for it whether it keeps them or not, and
+/// sees none of `FrameContext` but the timing, nor the `ArrivalFlags`. New fields of
+/// `FrameArrival` will not reach it either.
+///
+/// # Migrating to `InputHandler`
+///
+/// | `DeckLinkInputCallback` | `InputHandler` |
+/// |---|---|
+/// | `video_input_format_changed(events, mode, flags)` | `format_changed(change)`, with `change.events`, `change.display_mode` and `change.detected_signal_flags` |
+/// | `video_input_frame_arrived(frame) -> bool` | `frame_arrived(arrival) -> CallbackResult`, with the frame borrowed as `arrival.video_frame`. Return `CallbackResult::from(ok)` |
+/// | keeping the `DecklinkVideoFrame` after returning | `arrival.retain_video_frame()` |
+/// | `video_input_frame_timing(timing)`, stashed until the frame arrives | `arrival.context.timing`, or `arrival.timing()` |
+/// | `audio_input_packet_arrived(packet)` | `arrival.audio_packet`, in the same call as the frame |
+/// | `video_input_frame_conversion_failed(failure)` | `arrival.flags.contains(ArrivalFlags::FRAME_LOST)`, with the timing in `arrival.context` |
+/// | `video_input_frame_and_audio_arrived(frame, packet, timing)` | `frame_arrived(arrival)` |
+/// | a wrapper forwarding each method to an inner callback | forward `change` and `arrival` as they are, or a changed copy from `arrival.with_video_frame` |
+/// | a wrapper that holds frames back and passes them on later | pass them as `FrameArrival::new(Some(&frame))`, adding the rest with `with_audio_packet`, `with_context` and `with_flags` |
+/// | an `Arc<dyn DeckLinkInputCallback>` passed where an `Arc<dyn InputHandler>` is expected | wrap it, as `Arc::new(callback)` |
+///
+/// Returning `CallbackResult::Fail` from `frame_arrived` is what implementing
+/// `video_input_frame_and_audio_arrived` was for, and needs nothing extra.
 pub trait DeckLinkInputCallback: Send + Sync {
     /// Called when the video input format changes (e.g. resolution, field dominance, colorspace).
     fn video_input_format_changed(
@@ -51,11 +100,127 @@ pub trait DeckLinkInputCallback: Send + Sync {
 
     /// Called when a new video frame arrives from the input.
     /// Return `true` to indicate success.
+    ///
+    /// `video_frame` is `None` only when the callback carried no video. A frame that arrived
+    /// but could not be read is reported to `video_input_frame_conversion_failed` instead.
     fn video_input_frame_arrived(&self, video_frame: Option<DecklinkVideoFrame>) -> bool;
+
+    /// Called in place of `video_input_frame_arrived` when a video frame arrived but could
+    /// not be wrapped, so the frame is lost. Such frames are also counted by
+    /// `DecklinkInputDevice::frame_conversion_failure_count`.
+    fn video_input_frame_conversion_failed(&self, _failure: FrameConversionFailure) {}
+
+    /// Called before `video_input_frame_arrived` with the timing of the frame, expressed in
+    /// the timescale of the active display mode.
+    fn video_input_frame_timing(&self, _timing: DecklinkFrameTiming) {}
+
+    /// Called when a packet of audio samples arrives from the input.
+    /// This is called before `video_input_frame_arrived` for the same callback.
+    fn audio_input_packet_arrived(&self, _audio_packet: DecklinkAudioInputPacket) {}
+
+    /// Called for each frame-arrived callback from the driver, with the video frame and the
+    /// audio packet it carried, and returns the result passed back to the driver.
+    ///
+    /// The default passes the packet to `audio_input_packet_arrived`, the timing to
+    /// `video_input_frame_timing` and the frame to `video_input_frame_arrived`, in that
+    /// order, and returns `CallbackResult::from` its result. Implement this instead to see the
+    /// audio and video of a callback together, or to return `CallbackResult::Fail`.
+    /// Callbacks that wrap another forward the separate methods, so a callback wrapped by
+    /// one is called through those instead.
+    ///
+    /// A frame that could not be read is still reported to
+    /// `video_input_frame_conversion_failed`, after its audio packet is passed to
+    /// `audio_input_packet_arrived`.
+    fn video_input_frame_and_audio_arrived(
+        &self,
+        video_frame: Option<DecklinkVideoFrame>,
+        audio_packet: Option<DecklinkAudioInputPacket>,
+        timing: Option<DecklinkFrameTiming>,
+    ) -> CallbackResult {
+        if let Some(packet) = audio_packet {
+            self.audio_input_packet_arrived(packet);
+        }
+        if let Some(timing) = timing {
+            self.video_input_frame_timing(timing);
+        }
+        CallbackResult::from(self.video_input_frame_arrived(video_frame))
+    }
+}
+
+/// The result of a frame-arrived callback, returned to the driver as an HRESULT.
+///
+/// No driver that has been checked acts on the result, so it does not slow or stop the
+/// capture. It is passed on unchanged by default, so that ports of C++ applications that
+/// return `S_FALSE` or `E_FAIL` keep their meaning for tools that trace the driver's
+/// callbacks. `DecklinkInputDevice::set_callback_return_policy` changes what is returned.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash, Default)]
+pub enum CallbackResult {
+    /// `S_OK`, returned for `true` from `video_input_frame_arrived`.
+    #[default]
+    Ok,
+    /// `S_FALSE`, returned for `false` from `video_input_frame_arrived`. C++ applications
+    /// return it when they are falling behind and dropped the frame.
+    False,
+    /// `E_FAIL`, as `SdkError::FAIL`.
+    Fail,
+}
+
+impl CallbackResult {
+    /// The HRESULT returned to the driver.
+    pub fn hresult(&self) -> std::ffi::c_int {
+        match self {
+            CallbackResult::Ok => 0,
+            CallbackResult::False => SdkError::FALSE.code(),
+            CallbackResult::Fail => SdkError::FAIL.code(),
+        }
+    }
+}
+
+impl From<bool> for CallbackResult {
+    fn from(ok: bool) -> CallbackResult {
+        if ok {
+            CallbackResult::Ok
+        } else {
+            CallbackResult::False
+        }
+    }
+}
+
+/// A video frame that arrived but could not be read.
+///
+/// The C wrapper converts each input frame to a video frame before it can be read, and the
+/// conversion can fail, such as under memory pressure. It has no other accessors for the
+/// pixel data of an input frame, so the frame cannot be recovered.
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub struct FrameConversionFailure {
+    /// The timing of the lost frame, if the display mode timescale was known.
+    pub timing: Option<DecklinkFrameTiming>,
+}
+
+pub(crate) struct InputCallbackWrapper {
+    pub(crate) handler: RwLock<Option<Arc<dyn InputHandler>>>,
+    /// Frame duration of the active display mode, shared with the input device.
+    pub(crate) frame_duration: Arc<RwLock<Option<DecklinkTime>>>,
+    /// Audio input format, shared with the input device.
+    pub(crate) audio_format: Arc<RwLock<Option<(DecklinkAudioSampleType, u32)>>>,
+    /// Internal observers of input frames, notified alongside the handler.
+    pub(crate) waiters: Mutex<Vec<Arc<FrameWaiter>>>,
+    /// Drops callbacks that arrive while streams are stopped.
+    pub(crate) gate: Arc<CallbackGate>,
+    /// The check of frame sizes against the active display mode, shared with the input device.
+    pub(crate) dimensions: Arc<DimensionCheck>,
+    /// The payload identifier of the signal, shared with the input device.
+    pub(crate) vpid: Arc<VpidTracker>,
+    /// What frame callbacks return to the driver, shared with the input device.
+    pub(crate) returns: Arc<ReturnPolicy>,
+    /// Whether a format change was notified since the last frame callback.
+    format_changed: AtomicBool,
 }
 
-pub struct InputCallbackWrapper {
-    pub handler: RwLock<Option<Arc<dyn DeckLinkInputCallback>>>,
+impl Drop for InputCallbackWrapper {
+    fn drop(&mut self) {
+        track_dropped("InputCallbackWrapper", self as *const Self);
+    }
 }
 
 extern "C" fn video_input_format_changed_callback(
@@ -66,51 +231,155 @@ extern "C" fn video_input_format_changed_callback(
 ) -> sdk::HRESULT {
     let wrapper: &InputCallbackWrapper = unsafe { &*(context as *const _) };
 
-    if let Some(handler) = &*wrapper.handler.read().unwrap() {
-        let events = DecklinkVideoInputFormatChangedEvents::from_bits_truncate(notification_events);
-        let mode_id = if new_display_mode.is_null() {
-            DecklinkDisplayModeId::Unknown
-        } else {
-            let raw = unsafe { sdk::cdecklink_display_mode_get_display_mode(new_display_mode) };
-            DecklinkDisplayModeId::from_u32(raw).unwrap_or(DecklinkDisplayModeId::Unknown)
-        };
-        let flags =
-            DecklinkDetectedVideoInputFormatFlags::from_bits_truncate(detected_signal_flags);
+    if !wrapper.gate.admit() {
+        return 0; // S_OK
+    }
+
+    // The driver only lends the mode for the callback
+    let display_mode = if new_display_mode.is_null() {
+        None
+    } else {
+        Some(unsafe { DisplayModePtr::retain(new_display_mode) })
+    };
+    if let Some(display_mode) = &display_mode {
+        update_frame_duration(wrapper, display_mode);
+    }
 
-        handler.video_input_format_changed(events, mode_id, flags);
+    let events = DecklinkVideoInputFormatChangedEvents::from_bits_truncate(notification_events);
+    let mode_id = display_mode
+        .as_ref()
+        .and_then(|m| DecklinkDisplayModeId::from_u32(m.display_mode()))
+        .unwrap_or(DecklinkDisplayModeId::Unknown);
+    if let Some(display_mode) = &display_mode {
+        // Frames of the new mode are checked against its size from now on
+        wrapper
+            .dimensions
+            .set_mode(Some((mode_id, display_mode.width(), display_mode.height())));
+    }
+    let flags = DecklinkDetectedVideoInputFormatFlags::from_bits_truncate(detected_signal_flags);
+    wrapper.format_changed.store(true, Ordering::Relaxed);
+    wrapper.vpid.invalidate();
+
+    for waiter in wrapper.waiters.lock().unwrap().iter() {
+        waiter.format_changed(events, mode_id, flags);
+    }
+
+    if let Some(handler) = &*wrapper.handler.read().unwrap() {
+        handler.format_changed(&InputFormatChange::new(events, mode_id, flags));
     }
 
     0 // S_OK
 }
 
+/// Cache the frame duration of the new display mode, so that frame timings after a format
+/// change use its timescale.
+fn update_frame_duration(wrapper: &InputCallbackWrapper, display_mode: &DisplayModePtr) {
+    *wrapper.frame_duration.write().unwrap() = display_mode
+        .frame_rate()
+        .map(|(duration, scale)| DecklinkTime::new(duration, scale));
+}
+
+fn get_frame_timing(
+    wrapper: &InputCallbackWrapper,
+    video_frame: &VideoInputFramePtr,
+) -> Option<DecklinkFrameTiming> {
+    let mode_duration = (*wrapper.frame_duration.read().unwrap())?;
+
+    let (time, duration) = video_frame.stream_time(mode_duration.scale).ok()?;
+    Some(DecklinkFrameTiming {
+        stream_time: DecklinkTime::new(time, mode_duration.scale),
+        duration: DecklinkTime::new(duration, mode_duration.scale),
+        mode_duration,
+    })
+}
+
 extern "C" fn video_input_frame_arrived_callback(
     context: *mut ::std::os::raw::c_void,
     video_frame: *mut sdk::cdecklink_video_input_frame_t,
-    _audio_packet: *mut sdk::cdecklink_audio_input_packet_t,
+    audio_packet: *mut sdk::cdecklink_audio_input_packet_t,
 ) -> sdk::HRESULT {
     let wrapper: &InputCallbackWrapper = unsafe { &*(context as *const _) };
+    let _in_flight = wrapper.gate.enter();
 
-    let mut result = true;
-    if let Some(handler) = &*wrapper.handler.read().unwrap() {
-        let frame = if video_frame.is_null() {
-            None
-        } else {
-            // Convert the input frame to a generic video frame for reading pixel data
-            let video_frame_ptr =
-                unsafe { sdk::cdecklink_video_input_frame_to_video_frame(video_frame) };
-            if video_frame_ptr.is_null() {
-                None
-            } else {
-                Some(unsafe { DecklinkVideoFrame::from(video_frame_ptr) })
-            }
-        };
+    if !wrapper.gate.admit() {
+        return 0; // S_OK
+    }
+    let sequence = wrapper
+        .gate
+        .delivered(!video_frame.is_null(), !audio_packet.is_null());
+    let mut flags = ArrivalFlags::empty();
+    if wrapper.format_changed.swap(false, Ordering::Relaxed) {
+        flags |= ArrivalFlags::AFTER_FORMAT_CHANGE;
+    }
+
+    let handler = wrapper.handler.read().unwrap();
+    let waiters = wrapper.waiters.lock().unwrap().clone();
 
-        result = handler.video_input_frame_arrived(frame);
+    let mut packet = None;
+    if handler.is_some() && !audio_packet.is_null() {
+        if let Some((sample_type, channel_count)) = *wrapper.audio_format.read().unwrap() {
+            packet = Some(unsafe {
+                DecklinkAudioInputPacket::from(audio_packet, sample_type, channel_count)
+            });
+        }
+    }
+
+    if handler.is_none() && waiters.is_empty() {
+        return 0; // S_OK
     }
 
-    if result {
-        0 // S_OK
+    let (mut frame, timing) = if video_frame.is_null() {
+        (None, None)
     } else {
-        1 // S_FALSE
+        let input_frame = unsafe { VideoInputFramePtr::retain(video_frame) };
+        let timing = get_frame_timing(wrapper, &input_frame);
+
+        // Convert the input frame to a generic video frame for reading pixel data
+        let video_frame_ptr = input_frame.as_video_frame();
+        if video_frame_ptr.is_null() {
+            // Report the lost frame, rather than passing it on as a callback without video
+            wrapper.gate.conversion_failed();
+            flags |= ArrivalFlags::FRAME_LOST;
+            (None, timing)
+        } else {
+            let frame = unsafe { DecklinkVideoFrame::from(video_frame_ptr) };
+            wrapper.vpid.frame_arrived(&frame);
+            (
+                Some(DecklinkVideoInputFrame::new(frame, input_frame)),
+                timing,
+            )
+        }
+    };
+
+    // The first frame after a format change may still be of the old mode, and is flagged as
+    // such already
+    if !flags.contains(ArrivalFlags::AFTER_FORMAT_CHANGE) {
+        let mismatch = frame
+            .as_ref()
+            .and_then(|frame| wrapper.dimensions.check(frame.video_frame(), sequence));
+        if let Some(mismatch) = mismatch {
+            flags |= ArrivalFlags::DIMENSION_MISMATCH;
+            if mismatch.dropped {
+                frame = None;
+            }
+        }
     }
+
+    if let Some(frame) = &frame {
+        for waiter in waiters.iter() {
+            waiter.frame_arrived(frame.video_frame(), timing);
+        }
+    }
+
+    let result = match &*handler {
+        Some(handler) => handler.frame_arrived(
+            &FrameArrival::new(frame.as_ref().map(DecklinkVideoInputFrame::video_frame))
+                .with_audio_packet(packet.as_ref())
+                .with_input_frame(frame.as_ref())
+                .with_context(FrameContext::new(timing, sequence))
+                .with_flags(flags),
+        ),
+        None => CallbackResult::Ok,
+    };
+    wrapper.returns.resolve(result).hresult()
 }
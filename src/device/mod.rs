@@ -1,34 +1,47 @@
-use crate::device::attributes::DecklinkDeviceAttributes;
+use crate::device::attributes::{
+    DecklinkDeviceAttributes, DecklinkDeviceInterface, DecklinkDuplexMode, DecklinkProfileId,
+};
 use crate::device::input::DecklinkInputDevice;
 use crate::device::notification::DecklinkDeviceNotification;
 use crate::device::output::DecklinkOutputDevice;
-use crate::device::status::DecklinkDeviceStatus;
+use crate::device::registry::DeviceRemoved;
+use crate::device::status::{DecklinkDeviceBusyState, DecklinkDeviceStatus};
 use crate::display_mode::{DecklinkDisplayMode, DecklinkDisplayModeId};
 use crate::frame::DecklinkPixelFormat;
-use crate::sdk;
-use crate::util::{convert_and_release_c_string, SdkError};
-use std::ptr::{null, null_mut};
-use std::sync::{Arc, Mutex, Weak};
+use crate::ptr::{DeviceIteratorPtr, DevicePtr};
+use crate::util::SdkError;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, Weak};
+use strum::IntoEnumIterator;
 
 pub mod attributes;
+pub mod custom_id;
 pub mod input;
 pub mod notification;
 pub mod output;
+pub mod registry;
+pub mod selector;
 pub mod status;
 
+/// A Decklink device.
+///
+/// Devices cannot be sent between threads. To use a device from another thread, find it there
+/// again by its `device_handle` attribute, as `DeviceMonitor` does, or send the
+/// `DecklinkInputDevice` taken from it, or the values read from its attributes and status.
 pub struct DecklinkDevice {
-    dev: *mut crate::sdk::cdecklink_device_t,
+    /// The driver object, or `None` for a detached device.
+    dev: Option<DevicePtr>,
 
     notification: Mutex<Weak<DecklinkDeviceNotification>>,
-}
 
-impl Drop for DecklinkDevice {
-    fn drop(&mut self) {
-        if !self.dev.is_null() {
-            unsafe { sdk::cdecklink_device_release(self.dev) };
-            self.dev = null_mut();
-        }
-    }
+    // The driver never changes these for the life of a device, so they are read once and
+    // never invalidated.
+    model_name: OnceLock<Option<String>>,
+    display_name: OnceLock<Option<String>>,
+    persistent_id: OnceLock<Option<i64>>,
+
+    /// Set by a `DeviceRegistry` when the device is no longer listed by the driver.
+    removed: AtomicBool,
 }
 
 #[derive(FromPrimitive, PartialEq, Debug)]
@@ -37,6 +50,84 @@ pub enum DecklinkDisplayModeSupport {
     Supported = 1,
 }
 
+/// Whether a device can capture while playing back, in its active profile.
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub struct ConcurrentCapability {
+    pub can_capture_and_playback_simultaneously: bool,
+    /// The active profile, if it is what prevents simultaneous capture and playback.
+    pub limited_by_profile: Option<DecklinkProfileId>,
+    /// The persistent id of the other sub-device sharing the same hardware, if there is
+    /// exactly one.
+    pub paired_device: Option<i64>,
+    /// What the device is currently busy with.
+    pub busy: DecklinkDeviceBusyState,
+}
+
+/// The PCIe link of a device.
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub struct PcieLink {
+    /// The link generation, 1 for Gen. 1 and so on.
+    pub gen: u32,
+    /// The number of lanes.
+    pub width: u32,
+}
+
+/// Hardware details of a device, for diagnostics. Fields are `None` if the device does not
+/// report them.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct HardwareInfo {
+    pub model_name: Option<String>,
+    /// The revision id from the device handle.
+    pub hardware_revision: Option<String>,
+    pub device_interface: Option<DecklinkDeviceInterface>,
+    pub pcie_link: Option<PcieLink>,
+}
+
+/// Health telemetry of a device. Fields are `None` if the device does not report them.
+///
+/// The SDK reports only the on-board temperature. It has no fan or over temperature status.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Default)]
+pub struct DeviceHealth {
+    /// The on-board temperature in degrees Celsius.
+    pub temperature_c: Option<i64>,
+}
+
+/// Returned when a device cannot capture while playing back.
+#[derive(Debug)]
+pub struct ConcurrencyError {
+    /// The active profile of the device, if known.
+    pub active_profile: Option<DecklinkProfileId>,
+    /// A profile of the same hardware that would allow it, if any.
+    pub permitting_profile: Option<DecklinkProfileId>,
+    /// The error from the device, if the capability could not be queried.
+    pub error: Option<SdkError>,
+}
+
+impl From<SdkError> for ConcurrencyError {
+    fn from(e: SdkError) -> Self {
+        ConcurrencyError {
+            active_profile: None,
+            permitting_profile: None,
+            error: Some(e),
+        }
+    }
+}
+
+/// The full duplex profile with the most sub-devices that a half duplex profile can be switched to.
+fn full_duplex_alternative(profile: DecklinkProfileId) -> Option<DecklinkProfileId> {
+    match profile {
+        DecklinkProfileId::OneSubDeviceHalfDuplex => {
+            Some(DecklinkProfileId::OneSubDeviceFullDuplex)
+        }
+        DecklinkProfileId::TwoSubDevicesHalfDuplex
+        | DecklinkProfileId::FourSubDevicesHalfDuplex => {
+            Some(DecklinkProfileId::TwoSubDevicesFullDuplex)
+        }
+        DecklinkProfileId::OneSubDeviceFullDuplex
+        | DecklinkProfileId::TwoSubDevicesFullDuplex => None,
+    }
+}
+
 pub trait DecklinkDeviceDisplayModes<T> {
     fn does_support_video_mode(
         &self,
@@ -46,37 +137,132 @@ pub trait DecklinkDeviceDisplayModes<T> {
     ) -> Result<(bool, Option<DecklinkDisplayModeId>), SdkError>;
 
     fn display_modes(&self) -> Result<Vec<DecklinkDisplayMode>, SdkError>;
+
+    /// Get the pixel formats supported in `mode`.
+    ///
+    /// Probing every display mode of a device takes a call per mode and pixel format, which
+    /// adds up to hundreds on devices with 8K modes. Probe modes one at a time with this,
+    /// such as when one is selected, to avoid stalling on them all at once.
+    fn supported_pixel_formats_for(
+        &self,
+        mode: DecklinkDisplayModeId,
+        flags: T,
+    ) -> Result<Vec<DecklinkPixelFormat>, SdkError>
+    where
+        T: Copy,
+    {
+        let mut formats = Vec::new();
+        for format in DecklinkPixelFormat::iter() {
+            let (supported, _) = self.does_support_video_mode(mode, format, flags)?;
+            if supported {
+                formats.push(format);
+            }
+        }
+        Ok(formats)
+    }
 }
 
 impl DecklinkDevice {
-    pub fn model_name(&self) -> Option<String> {
-        let mut s = null();
-        let result = unsafe { sdk::cdecklink_device_get_model_name(self.dev, &mut s) };
-        if SdkError::is_ok(result) {
-            Some(unsafe { convert_and_release_c_string(s) })
-        } else {
-            None
+    pub(crate) fn from(dev: Option<DevicePtr>) -> DecklinkDevice {
+        DecklinkDevice {
+            dev,
+            notification: Mutex::new(Weak::new()),
+            model_name: OnceLock::new(),
+            display_name: OnceLock::new(),
+            persistent_id: OnceLock::new(),
+            removed: AtomicBool::new(false),
         }
     }
-    pub fn display_name(&self) -> Option<String> {
-        let mut s = null();
-        let result = unsafe { sdk::cdecklink_device_get_display_name(self.dev, &mut s) };
-        if SdkError::is_ok(result) {
-            Some(unsafe { convert_and_release_c_string(s) })
+
+    /// A device with no driver object behind it, which only has the identity it is given.
+    /// Every call that would go to the driver fails as it does on a removed device.
+    pub(crate) fn detached(
+        model_name: Option<String>,
+        display_name: Option<String>,
+        persistent_id: Option<i64>,
+    ) -> DecklinkDevice {
+        let device = DecklinkDevice::from(None);
+        let _ = device.model_name.set(model_name);
+        let _ = device.display_name.set(display_name);
+        let _ = device.persistent_id.set(persistent_id);
+        device
+    }
+
+    /// The driver object, unless the device has been removed.
+    fn live(&self) -> Result<&DevicePtr, SdkError> {
+        match &self.dev {
+            Some(dev) if !self.is_removed() => Ok(dev),
+            _ => Err(SdkError::HANDLE),
+        }
+    }
+
+    pub(crate) fn mark_removed(&self) {
+        self.removed.store(true, Ordering::Release);
+    }
+
+    /// Whether a `DeviceRegistry` has seen the device removed. A removed device stays safe to
+    /// hold, but every call that goes to the driver fails with `SdkError::HANDLE`, and `input`
+    /// and `output` return `None`. Its names and persistent id are still read from the cache.
+    pub fn is_removed(&self) -> bool {
+        self.removed.load(Ordering::Acquire)
+    }
+
+    /// Fail with `DeviceRemoved` if the device has been removed, naming it from the cache.
+    pub fn check_present(&self) -> Result<(), DeviceRemoved> {
+        if self.is_removed() {
+            Err(DeviceRemoved {
+                persistent_id: self.persistent_id.get().copied().flatten(),
+                display_name: self.display_name.get().cloned().flatten(),
+            })
         } else {
-            None
+            Ok(())
         }
     }
 
+    /// The `IDeckLink` this wraps, for calls the wrapper does not cover. The wrapper still owns its
+    /// reference, so it must not be released, and must not be used after the wrapper is dropped.
+    #[cfg(feature = "raw-api")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "raw-api")))]
+    pub fn as_raw(&self) -> *mut crate::sdk::cdecklink_device_t {
+        self.dev
+            .as_ref()
+            .map_or(std::ptr::null_mut(), |dev| dev.as_ptr())
+    }
+
+    /// The model name of the device. It is read from the driver the first time, and
+    /// borrowed from the cached copy after that.
+    pub fn model_name_str(&self) -> Option<&str> {
+        self.model_name
+            .get_or_init(|| self.live().ok()?.model_name())
+            .as_deref()
+    }
+    pub fn model_name(&self) -> Option<String> {
+        self.model_name_str().map(|s| s.to_string())
+    }
+    /// The display name of the device, cached as `model_name_str` is.
+    pub fn display_name_str(&self) -> Option<&str> {
+        self.display_name
+            .get_or_init(|| self.live().ok()?.display_name())
+            .as_deref()
+    }
+    pub fn display_name(&self) -> Option<String> {
+        self.display_name_str().map(|s| s.to_string())
+    }
+    /// The persistent id attribute of the device, cached as `model_name_str` is. `None` if
+    /// the device does not have one.
+    pub fn persistent_id(&self) -> Option<i64> {
+        *self
+            .persistent_id
+            .get_or_init(|| self.get_attributes().and_then(|a| a.persistent_id()).ok())
+    }
+
     pub fn get_attributes(&self) -> Result<DecklinkDeviceAttributes, SdkError> {
-        let mut s = null_mut();
-        let r = unsafe { sdk::cdecklink_device_query_profile_attributes(self.dev, &mut s) };
-        SdkError::result_or_else(r, || DecklinkDeviceAttributes::from(s))
+        let attributes = self.live()?.profile_attributes()?;
+        Ok(DecklinkDeviceAttributes::from(attributes))
     }
     pub fn get_status(&self) -> Result<DecklinkDeviceStatus, SdkError> {
-        let mut s = null_mut();
-        let r = unsafe { sdk::cdecklink_device_query_status(self.dev, &mut s) };
-        SdkError::result_or_else(r, || DecklinkDeviceStatus::from(s))
+        let status = self.live()?.status()?;
+        Ok(DecklinkDeviceStatus::from(status))
     }
     pub fn get_notification(&self) -> Result<Arc<DecklinkDeviceNotification>, SdkError> {
         if let Ok(locked) = self.notification.lock() {
@@ -92,56 +278,111 @@ impl DecklinkDevice {
         }
     }
 
-    pub fn output(&self) -> Option<DecklinkOutputDevice> {
-        // TODO - store the result for subsequent calls
-        let mut output = null_mut();
-        let res = unsafe { sdk::cdecklink_device_query_output(self.dev, &mut output) };
-        if !SdkError::is_ok(res) || output.is_null() {
-            None
-        } else {
-            Some(DecklinkOutputDevice::from(output))
+    /// Collect the hardware details of the device. The SDK does not report firmware versions.
+    pub fn hardware_info(&self) -> HardwareInfo {
+        let attributes = self.get_attributes().ok();
+        let status = self.get_status().ok();
+
+        let pcie_link = status.as_ref().and_then(|status| {
+            match (status.pci_express_link_speed(), status.pci_express_link_width()) {
+                (Ok(gen), Ok(width)) => Some(PcieLink { gen, width }),
+                _ => None,
+            }
+        });
+
+        HardwareInfo {
+            model_name: self.model_name(),
+            hardware_revision: attributes
+                .as_ref()
+                .and_then(|a| a.device_handle().ok())
+                .and_then(|handle| handle.split(':').next().map(|s| s.to_string())),
+            device_interface: attributes.as_ref().and_then(|a| a.device_interface().ok()),
+            pcie_link,
         }
     }
 
-    pub fn input(&self) -> Option<DecklinkInputDevice> {
-        let mut input = null_mut();
-        let res = unsafe { sdk::cdecklink_device_query_input(self.dev, &mut input) };
-        if !SdkError::is_ok(res) || input.is_null() {
-            None
-        } else {
-            Some(DecklinkInputDevice::from(input))
-        }
+    /// Read the health telemetry of the device. Telemetry the device does not report is
+    /// `None`, so this can be called on any device. Fails only if the status of the device
+    /// cannot be read at all.
+    pub fn health(&self) -> Result<DeviceHealth, SdkError> {
+        let status = self.get_status()?;
+        Ok(DeviceHealth {
+            temperature_c: status.device_temperature().ok(),
+        })
     }
-}
 
-pub fn get_devices() -> Result<Vec<DecklinkDevice>, SdkError> {
-    let it = unsafe { sdk::cdecklink_create_decklink_iterator_instance() };
-    if it.is_null() {
-        Err(SdkError::FAIL)
-    } else {
-        let mut res = Vec::new();
-
-        let mut dev = null_mut();
-        loop {
-            let ok = unsafe { sdk::cdecklink_iterator_next(it, &mut dev) };
-            if SdkError::is_false(ok) {
-                break;
-            } else if SdkError::is_ok(ok) {
-                res.push(DecklinkDevice {
-                    dev,
-                    notification: Mutex::new(Weak::new()),
+    /// Check whether the device can capture while playing back, combining the duplex mode of
+    /// the active profile with the current busy state.
+    pub fn concurrent_capability(&self) -> Result<ConcurrentCapability, SdkError> {
+        let attributes = self.get_attributes()?;
+        let duplex = attributes.duplex()?;
+        let profile = attributes.profile_id().ok();
+        let busy = self
+            .get_status()
+            .and_then(|s| s.busy_state())
+            .unwrap_or(DecklinkDeviceBusyState::empty());
+
+        let paired_device = match (attributes.device_group_id(), attributes.persistent_id()) {
+            (Ok(group), Ok(id)) => {
+                let mut others = get_devices()?.into_iter().filter_map(|dev| {
+                    let attributes = dev.get_attributes().ok()?;
+                    let other_id = attributes.persistent_id().ok()?;
+                    (attributes.device_group_id().ok()? == group && other_id != id)
+                        .then_some(other_id)
                 });
-            } else {
-                unsafe {
-                    sdk::cdecklink_iterator_release(it);
+                match (others.next(), others.next()) {
+                    (Some(other), None) => Some(other),
+                    _ => None,
                 }
-                return Err(SdkError::from(ok));
             }
-        }
+            _ => None,
+        };
 
-        unsafe {
-            sdk::cdecklink_iterator_release(it);
+        Ok(ConcurrentCapability {
+            can_capture_and_playback_simultaneously: duplex == DecklinkDuplexMode::Full,
+            limited_by_profile: if duplex == DecklinkDuplexMode::Half {
+                profile
+            } else {
+                None
+            },
+            paired_device,
+            busy,
+        })
+    }
+
+    /// Fail early if the device cannot capture while playing back, naming the active profile
+    /// and a profile that would permit it.
+    pub fn require_concurrent_capture_and_playback(&self) -> Result<(), ConcurrencyError> {
+        let capability = self.concurrent_capability()?;
+        if capability.can_capture_and_playback_simultaneously {
+            Ok(())
+        } else {
+            let active_profile = self.get_attributes()?.profile_id().ok();
+            Err(ConcurrencyError {
+                active_profile,
+                permitting_profile: active_profile.and_then(full_duplex_alternative),
+                error: None,
+            })
         }
-        Ok(res)
     }
+
+    pub fn output(&self) -> Option<DecklinkOutputDevice> {
+        // TODO - store the result for subsequent calls
+        let output = self.live().ok()?.output()?;
+        Some(DecklinkOutputDevice::from(output))
+    }
+
+    pub fn input(&self) -> Option<DecklinkInputDevice> {
+        let input = self.live().ok()?.input()?;
+        Some(DecklinkInputDevice::from(input))
+    }
+}
+
+pub fn get_devices() -> Result<Vec<DecklinkDevice>, SdkError> {
+    let mut it = DeviceIteratorPtr::create().ok_or(SdkError::FAIL)?;
+    let mut res = Vec::new();
+    while let Some(dev) = it.next_device()? {
+        res.push(DecklinkDevice::from(Some(dev)));
+    }
+    Ok(res)
 }
@@ -1,4 +1,5 @@
 use crate::device::status::DecklinkStatusId;
+use crate::ptr::NotificationPtr;
 use crate::{sdk, SdkError};
 use num_traits::FromPrimitive;
 use std::ptr::null_mut;
@@ -17,7 +18,7 @@ pub enum NotificationTopic {
 // }
 
 pub struct DecklinkDeviceNotification {
-    dev: *mut sdk::cdecklink_status_t,
+    dev: NotificationPtr,
 }
 
 pub trait DecklinkDeviceNotificationExt {
@@ -39,17 +40,15 @@ impl DecklinkDeviceNotificationExt for Arc<DecklinkDeviceNotification> {
             topic: topic as u32,
         }));
 
-        let mut unsubscribe_token = null_mut(); // c++ handle, needed to call unsubscribe
+        // The handle returned is the c++ one, needed to call unsubscribe
         let result = unsafe {
-            sdk::cdecklink_notification_subscribe(
-                self.dev,
+            self.dev.subscribe(
                 topic as u32,
                 ptr as *mut std::ffi::c_void,
                 Some(notify_callback),
-                &mut unsubscribe_token,
             )
         };
-        SdkError::result_or_else(result, || DeckLinkNotificationCallbackHandle {
+        result.map(|unsubscribe_token| DeckLinkNotificationCallbackHandle {
             parent: self.clone(),
             wrapper: ptr,
             unsubscribe_token,
@@ -66,11 +65,10 @@ impl Drop for DeckLinkNotificationCallbackHandle {
     fn drop(&mut self) {
         if !self.wrapper.is_null() {
             unsafe {
-                sdk::cdecklink_notification_unsubscribe(
-                    self.parent.dev,
-                    (*self.wrapper).topic,
-                    self.unsubscribe_token,
-                );
+                let _ = self
+                    .parent
+                    .dev
+                    .unsubscribe((*self.wrapper).topic, self.unsubscribe_token);
                 drop(Box::from_raw(self.wrapper)); // Reclaim the box so it gets freed
             }
             self.wrapper = null_mut();
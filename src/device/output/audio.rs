@@ -1,5 +1,5 @@
 use crate::device::output::DecklinkOutputDevicePtr;
-use crate::{sdk, SdkError};
+use crate::SdkError;
 use std::rc::Rc;
 use std::sync::atomic::Ordering;
 
@@ -8,11 +8,8 @@ pub struct DecklinkOutputDeviceAudio {
 }
 impl Drop for DecklinkOutputDeviceAudio {
     fn drop(&mut self) {
-        // TODO - this is not safe!
-        unsafe {
-            sdk::cdecklink_output_disable_audio_output(self.ptr.dev);
-            self.ptr.audio_active.store(false, Ordering::Relaxed)
-        }
+        let _ = self.ptr.dev.disable_audio_output();
+        self.ptr.audio_active.store(false, Ordering::Relaxed)
     }
 }
 impl DecklinkOutputDeviceAudio {
@@ -24,16 +21,10 @@ impl DecklinkOutputDeviceAudio {
     //    uint32_t sampleFrameCount, uint32_t *sampleFramesWritten);
 
     pub fn begin_audio_preroll(&self) -> Result<(), SdkError> {
-        unsafe {
-            let result = sdk::cdecklink_output_begin_audio_preroll(self.ptr.dev);
-            SdkError::result(result)
-        }
+        self.ptr.dev.begin_audio_preroll()
     }
     pub fn end_audio_preroll(&self) -> Result<(), SdkError> {
-        unsafe {
-            let result = sdk::cdecklink_output_end_audio_preroll(self.ptr.dev);
-            SdkError::result(result)
-        }
+        self.ptr.dev.end_audio_preroll()
     }
 
     //    HRESULT cdecklink_output_schedule_audio_samples(cdecklink_output_t *output, void *buffer,
@@ -41,19 +32,9 @@ impl DecklinkOutputDeviceAudio {
     //    int64_t timeScale, uint32_t *sampleFramesWritten);
 
     pub fn buffered_audio_sample_frame_count(&self) -> Result<u32, SdkError> {
-        unsafe {
-            let mut count = 0;
-            let result = sdk::cdecklink_output_get_buffered_audio_sample_frame_count(
-                self.ptr.dev,
-                &mut count,
-            );
-            SdkError::result_or(result, count)
-        }
+        self.ptr.dev.buffered_audio_sample_frame_count()
     }
     pub fn flush_buffered_audio_samples(&self) -> Result<(), SdkError> {
-        unsafe {
-            let result = sdk::cdecklink_output_flush_buffered_audio_samples(self.ptr.dev);
-            SdkError::result(result)
-        }
+        self.ptr.dev.flush_buffered_audio_samples()
     }
 }
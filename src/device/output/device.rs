@@ -1,18 +1,33 @@
-use crate::sdk;
+use crate::device::output::video_callback::{unregister_callback, CallbackWrapper};
+use crate::ptr::OutputPtr;
+use std::cell::Cell;
 use std::ptr::null_mut;
 use std::rc::Rc;
-use std::sync::atomic::AtomicBool;
+use std::sync::atomic::{AtomicBool, Ordering};
 
-pub struct DecklinkOutputDevicePtr {
-    pub(crate) dev: *mut crate::sdk::cdecklink_output_t,
-    pub video_active: Rc<AtomicBool>,
-    pub audio_active: Rc<AtomicBool>,
+pub(crate) struct DecklinkOutputDevicePtr {
+    pub(crate) dev: OutputPtr,
+    pub(crate) video_active: Rc<AtomicBool>,
+    pub(crate) audio_active: Rc<AtomicBool>,
+    /// Video output was enabled by `DecklinkOutputDevice::enable_video_output`, rather than
+    /// by a handle that disables it when dropped.
+    pub(crate) video_enabled: AtomicBool,
+    /// The callback wrapper registered with the driver, or null before one is needed. It is
+    /// shared by the output and its scheduled handles, and lives as long as the output.
+    pub(crate) callback_wrapper: Cell<*mut CallbackWrapper>,
+    /// The time scale scheduled playback was started with by
+    /// `DecklinkOutputDevice::start_scheduled_playback`, while it runs.
+    pub(crate) playback_timescale: Cell<Option<i64>>,
 }
 impl Drop for DecklinkOutputDevicePtr {
     fn drop(&mut self) {
-        if !self.dev.is_null() {
-            unsafe { sdk::cdecklink_output_release(self.dev) };
-            self.dev = null_mut();
+        if let Some(timescale) = self.playback_timescale.take() {
+            let _ = self.dev.stop_scheduled_playback(0, timescale);
         }
+        if self.video_enabled.swap(false, Ordering::Relaxed) {
+            // This call blocks until all frame callbacks are complete
+            let _ = self.dev.disable_video_output();
+        }
+        unsafe { unregister_callback(&self.dev, self.callback_wrapper.replace(null_mut())) };
     }
 }
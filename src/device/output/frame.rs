@@ -0,0 +1,74 @@
+use crate::frame::{
+    frame_byte_count, DecklinkAlignedBytes, DecklinkFrameBase, DecklinkFrameFlags,
+    DecklinkPixelFormat,
+};
+use crate::ptr::MutableVideoFramePtr;
+use crate::SdkError;
+use num_traits::FromPrimitive;
+
+/// A frame in a buffer of the driver's, created by `DecklinkOutputDevice::create_video_frame`
+/// to be filled and played out.
+///
+/// Filling it in place saves the copy that `DecklinkOutputDeviceVideoSync::display_frame_copy`
+/// makes. It can be sent to another thread to be filled, but not shared.
+pub struct DecklinkVideoOutputFrame {
+    frame: MutableVideoFramePtr,
+}
+
+// Safety: The reference is counted atomically and can be released from any thread, and the
+// frame can be used on another thread as long as calls on it are not concurrent.
+unsafe impl Send for DecklinkVideoOutputFrame {}
+
+impl DecklinkVideoOutputFrame {
+    pub(crate) fn new(frame: MutableVideoFramePtr) -> Self {
+        DecklinkVideoOutputFrame { frame }
+    }
+
+    pub(crate) fn ptr(&self) -> &MutableVideoFramePtr {
+        &self.frame
+    }
+
+    /// The `IDeckLinkMutableVideoFrame` this wraps, for calls the wrapper does not cover. The
+    /// wrapper still owns its reference, so it must not be released, and must not be used after
+    /// the wrapper is dropped.
+    #[cfg(feature = "raw-api")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "raw-api")))]
+    pub fn as_raw(&self) -> *mut crate::sdk::cdecklink_mutable_video_frame_t {
+        self.frame.as_ptr()
+    }
+
+    /// The pixel data of the frame, to fill in.
+    pub fn bytes_mut(&mut self) -> Result<&mut [u8], SdkError> {
+        let byte_count = frame_byte_count(self.row_bytes(), self.height())?;
+        let bytes = self.frame.bytes()?;
+        // Safety: The buffer lives as long as the frame, and the exclusive borrow of `self`
+        // keeps the slice the only access to it
+        Ok(unsafe { std::slice::from_raw_parts_mut(bytes, byte_count) })
+    }
+}
+
+impl DecklinkFrameBase for DecklinkVideoOutputFrame {
+    fn width(&self) -> usize {
+        self.frame.width()
+    }
+    fn height(&self) -> usize {
+        self.frame.height()
+    }
+    fn row_bytes(&self) -> usize {
+        self.frame.row_bytes()
+    }
+    fn pixel_format(&self) -> DecklinkPixelFormat {
+        DecklinkPixelFormat::from_u32(self.frame.pixel_format())
+            .unwrap_or(DecklinkPixelFormat::Format8BitYUV)
+    }
+    fn flags(&self) -> DecklinkFrameFlags {
+        DecklinkFrameFlags::from_bits_truncate(self.frame.flags())
+    }
+    fn bytes(&self) -> Result<DecklinkAlignedBytes<'_>, SdkError> {
+        let byte_count = frame_byte_count(self.row_bytes(), self.height())?;
+        let bytes = self.frame.bytes()?;
+        Ok(DecklinkAlignedBytes(unsafe {
+            std::slice::from_raw_parts(bytes, byte_count)
+        }))
+    }
+}
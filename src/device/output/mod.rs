@@ -1,23 +1,26 @@
 mod audio;
 mod device;
 mod enums;
+mod frame;
 mod video;
 mod video_callback;
 
 use crate::device::output::device::DecklinkOutputDevicePtr;
 use crate::device::output::video_callback::register_callback;
-use crate::display_mode::{
-    iterate_display_modes, DecklinkDisplayMode, DecklinkDisplayModeId,
-};
-use crate::frame::DecklinkPixelFormat;
-use crate::{sdk, SdkError};
+use crate::display_mode::{collect_display_modes, DecklinkDisplayMode, DecklinkDisplayModeId};
+use crate::frame::{DecklinkFrameFlags, DecklinkPixelFormat};
+use crate::ptr::OutputPtr;
+use crate::SdkError;
 use num_traits::FromPrimitive;
+use std::cell::Cell;
 use std::ptr::null_mut;
 use std::rc::Rc;
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 
 pub use crate::device::output::audio::DecklinkOutputDeviceAudio;
 pub use crate::device::output::enums::*;
+pub use crate::device::output::frame::DecklinkVideoOutputFrame;
 pub use crate::device::output::video::{
     DecklinkOutputDeviceVideoScheduled, DecklinkOutputDeviceVideoSync,
 };
@@ -26,6 +29,10 @@ use crate::device::DecklinkDeviceDisplayModes;
 
 use self::video::DecklinkOutputDeviceVideoImpl;
 
+/// The playback interface of a device.
+///
+/// An output cannot be sent between threads, so it must be used on the thread that created it.
+/// Do the work on other threads and send the finished frames to that thread to be shown.
 pub struct DecklinkOutputDevice {
     ptr: Rc<DecklinkOutputDevicePtr>,
 }
@@ -37,76 +44,230 @@ impl DecklinkDeviceDisplayModes<enums::DecklinkVideoOutputFlags> for DecklinkOut
         pixel_format: DecklinkPixelFormat,
         flags: enums::DecklinkVideoOutputFlags,
     ) -> Result<(bool, Option<DecklinkDisplayModeId>), SdkError> {
-        let mut supported = false;
-        let mut display_mode_id: u32 = 0;
-        let result = unsafe {
-            sdk::cdecklink_output_does_support_video_mode(
-                self.ptr.dev,
-                sdk::_DecklinkVideoConnection_decklinkVideoConnectionUnspecified,
-                mode as u32,
-                pixel_format as u32,
-                sdk::_DecklinkVideoOutputConversionMode_decklinkNoVideoOutputConversion,
-                flags.bits(),
-                &mut display_mode_id,
-                &mut supported,
-            )
-        };
-        SdkError::result_or_else(result, move || {
-            let possible_mode = DecklinkDisplayModeId::from_u32(display_mode_id);
-            (supported, possible_mode)
-        })
+        let (supported, display_mode_id) =
+            self.ptr
+                .dev
+                .does_support_video_mode(mode as u32, pixel_format as u32, flags.bits())?;
+        Ok((supported, DecklinkDisplayModeId::from_u32(display_mode_id)))
     }
 
     fn display_modes(&self) -> Result<Vec<DecklinkDisplayMode>, SdkError> {
-        unsafe {
-            let mut it = null_mut();
-            let ok = sdk::cdecklink_output_get_display_mode_iterator(self.ptr.dev, &mut it);
-            if SdkError::is_ok(ok) {
-                let v = iterate_display_modes(it);
-                sdk::cdecklink_display_mode_iterator_release(it);
-                v
-            } else {
-                Err(SdkError::from(ok))
-            }
-        }
+        collect_display_modes(self.ptr.dev.display_mode_iterator()?)
     }
 }
 // TODO - this is currently a bag of methods, and it could do with some more sanity checking (eg allow schedule when video not enabled etc)
 impl DecklinkOutputDevice {
-    pub(crate) fn from(ptr: *mut crate::sdk::cdecklink_output_t) -> DecklinkOutputDevice {
+    /// The `IDeckLinkOutput` this wraps, for calls the wrapper does not cover. The wrapper still
+    /// owns its reference, so it must not be released, and must not be used after the wrapper is
+    /// dropped.
+    #[cfg(feature = "raw-api")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "raw-api")))]
+    pub fn as_raw(&self) -> *mut crate::sdk::cdecklink_output_t {
+        self.ptr.dev.as_ptr()
+    }
+
+    pub(crate) fn from(ptr: OutputPtr) -> DecklinkOutputDevice {
         DecklinkOutputDevice {
             ptr: Rc::new(DecklinkOutputDevicePtr {
                 dev: ptr,
                 video_active: Rc::new(AtomicBool::new(false)),
                 audio_active: Rc::new(AtomicBool::new(false)),
+                video_enabled: AtomicBool::new(false),
+                callback_wrapper: Cell::new(null_mut()),
+                playback_timescale: Cell::new(None),
             }),
         }
     }
 
+    /// The bytes in each row of a frame of `width` pixels in `pixel_format`, as the driver
+    /// lays it out.
+    pub fn row_bytes_for_pixel_format(
+        &self,
+        pixel_format: DecklinkPixelFormat,
+        width: usize,
+    ) -> Result<usize, SdkError> {
+        let width = i32::try_from(width).map_err(|_| SdkError::INVALIDARG)?;
+        let row_bytes = self
+            .ptr
+            .dev
+            .row_bytes_for_pixel_format(pixel_format as u32, width)?;
+        Ok(row_bytes as usize)
+    }
+
     /* Video Output */
 
-    unsafe fn enable_video_output_inner(
+    fn enable_video_output_inner(
         &self,
         mode: DecklinkDisplayModeId,
         flags: enums::DecklinkVideoOutputFlags,
-    ) -> i32 {
+    ) -> Result<(), SdkError> {
         if self.ptr.video_active.swap(true, Ordering::Relaxed) {
             // TODO - better mode
-            SdkError::ACCESSDENIED as i32
+            Err(SdkError::ACCESSDENIED)
         } else {
-            sdk::cdecklink_output_enable_video_output(self.ptr.dev, mode as u32, flags.bits())
+            self.ptr.dev.enable_video_output(mode as u32, flags.bits())
         }
     }
 
-    pub fn is_scheduled_playback_running(&self) -> Result<bool, SdkError> {
+    /// Enable video output in `mode`, for frames shown one at a time with
+    /// `display_video_frame`, or played out with `schedule_video_frame` and
+    /// `start_scheduled_playback`. It stays enabled until `disable_video_output` is called, or
+    /// the output is dropped.
+    ///
+    /// Fails with `SdkError::ACCESSDENIED` if video output is already enabled, by this or by
+    /// `enable_video_output_sync` or `enable_video_output_scheduled`.
+    pub fn enable_video_output(
+        &self,
+        mode: DecklinkDisplayModeId,
+        flags: enums::DecklinkVideoOutputFlags,
+    ) -> Result<(), SdkError> {
+        if self.ptr.video_active.swap(true, Ordering::Relaxed) {
+            return Err(SdkError::ACCESSDENIED);
+        }
+        let result = self.ptr.dev.enable_video_output(mode as u32, flags.bits());
+        if result.is_ok() {
+            self.ptr.video_enabled.store(true, Ordering::Relaxed);
+        } else {
+            self.ptr.video_active.store(false, Ordering::Relaxed);
+        }
+        result
+    }
+
+    /// Disable the video output enabled by `enable_video_output`. Does nothing if it is not
+    /// enabled, and fails with `SdkError::ACCESSDENIED` if it was enabled through a handle,
+    /// which disables it when dropped. Scheduled playback stops with it.
+    pub fn disable_video_output(&self) -> Result<(), SdkError> {
+        if self.ptr.video_enabled.swap(false, Ordering::Relaxed) {
+            // This call blocks until all frame callbacks are complete
+            let result = self.ptr.dev.disable_video_output();
+            self.ptr.video_active.store(false, Ordering::Relaxed);
+            self.ptr.playback_timescale.set(None);
+            result
+        } else if self.ptr.video_active.load(Ordering::Relaxed) {
+            Err(SdkError::ACCESSDENIED)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Create a frame in a buffer of the driver's, to fill in and show with
+    /// `display_video_frame`. Its bytes start out zeroed by the mock backend, but are
+    /// undefined with the drivers.
+    pub fn create_video_frame(
+        &self,
+        width: usize,
+        height: usize,
+        row_bytes: usize,
+        pixel_format: DecklinkPixelFormat,
+        flags: DecklinkFrameFlags,
+    ) -> Result<DecklinkVideoOutputFrame, SdkError> {
+        // The SDK takes dimensions as i32, so refuse any that would be truncated
+        let to_i32 = |value: usize| i32::try_from(value).map_err(|_| SdkError::INVALIDARG);
+        let frame = self.ptr.dev.create_video_frame(
+            to_i32(width)?,
+            to_i32(height)?,
+            to_i32(row_bytes)?,
+            pixel_format as u32,
+            flags.bits(),
+        )?;
+        Ok(DecklinkVideoOutputFrame::new(frame))
+    }
+
+    /// Show `frame` straight away, blocking until the driver has taken it. Video output must
+    /// have been enabled with `enable_video_output`, in a mode of the size of the frame.
+    pub fn display_video_frame(&self, frame: &DecklinkVideoOutputFrame) -> Result<(), SdkError> {
+        if !self.ptr.video_enabled.load(Ordering::Relaxed) {
+            return Err(SdkError::UNEXPECTED);
+        }
+        // Safety: The frame holds a reference until the call returns
         unsafe {
-            let mut running = false;
-            let result =
-                sdk::cdecklink_output_is_scheduled_playback_running(self.ptr.dev, &mut running);
-            SdkError::result_or(result, running)
+            self.ptr
+                .dev
+                .display_video_frame_sync(frame.ptr().as_video_frame())
         }
     }
 
+    /// Schedule `frame` to be shown at `display_time` for `duration`, both in units of
+    /// `timescale` per second, once scheduled playback is started. Video output must have been
+    /// enabled with `enable_video_output`, in a mode of the size of the frame.
+    ///
+    /// The driver holds the frame until it has been played out or flushed, and then passes
+    /// it to `DeckLinkVideoOutputCallback::scheduled_frame_completed` of the callback set
+    /// with `set_callback`. The frame is taken so it cannot be filled in again meanwhile.
+    pub fn schedule_video_frame(
+        &self,
+        frame: DecklinkVideoOutputFrame,
+        display_time: i64,
+        duration: i64,
+        timescale: i64,
+    ) -> Result<(), SdkError> {
+        if !self.ptr.video_enabled.load(Ordering::Relaxed) {
+            return Err(SdkError::UNEXPECTED);
+        }
+        // Safety: The frame holds a reference until the driver has taken its own
+        unsafe {
+            self.ptr.dev.schedule_video_frame(
+                frame.ptr().as_video_frame(),
+                display_time,
+                duration,
+                timescale,
+            )
+        }
+    }
+
+    /// Set the handler of scheduled frames completing and of scheduled playback stopping,
+    /// replacing any before it. The callback is registered with the driver the first time,
+    /// and unregistered when the output is dropped.
+    pub fn set_callback(
+        &self,
+        handler: Option<Arc<dyn DeckLinkVideoOutputCallback>>,
+    ) -> Result<(), SdkError> {
+        let wrapper = register_callback(&self.ptr)?;
+        unsafe {
+            *(*wrapper).handler.write().unwrap() = handler;
+        }
+        Ok(())
+    }
+
+    /// The number of scheduled frames that the driver has not finished with.
+    pub fn buffered_video_frame_count(&self) -> Result<u32, SdkError> {
+        self.ptr.dev.buffered_video_frame_count()
+    }
+
+    /// Start playing out the scheduled frames from `start_time`, in units of `timescale` per
+    /// second, at `speed` times normal speed. Video output must have been enabled with
+    /// `enable_video_output`. If playback is still running when the output is dropped, it is
+    /// stopped straight away.
+    pub fn start_scheduled_playback(
+        &self,
+        start_time: i64,
+        timescale: i64,
+        speed: f64,
+    ) -> Result<(), SdkError> {
+        if !self.ptr.video_enabled.load(Ordering::Relaxed) {
+            return Err(SdkError::UNEXPECTED);
+        }
+        self.ptr
+            .dev
+            .start_scheduled_playback(start_time, timescale, speed)?;
+        self.ptr.playback_timescale.set(Some(timescale));
+        Ok(())
+    }
+
+    /// Stop scheduled playback at `stop_time`, in units of `timescale` per second, or straight
+    /// away if 0. Returns the time it stopped at. The frames not yet played out complete as
+    /// `DecklinkOutputFrameCompletionResult::Flushed`, and then
+    /// `DeckLinkVideoOutputCallback::scheduled_playback_has_stopped` is called.
+    pub fn stop_scheduled_playback(&self, stop_time: i64, timescale: i64) -> Result<i64, SdkError> {
+        let actual_stop_time = self.ptr.dev.stop_scheduled_playback(stop_time, timescale)?;
+        self.ptr.playback_timescale.set(None);
+        Ok(actual_stop_time)
+    }
+
+    pub fn is_scheduled_playback_running(&self) -> Result<bool, SdkError> {
+        self.ptr.dev.is_scheduled_playback_running()
+    }
+
     pub fn enable_video_output_scheduled(
         &self,
         mode: DecklinkDisplayModeId,
@@ -117,14 +278,10 @@ impl DecklinkOutputDevice {
             // Don't do this if already running?
             Err(e) => Err(e),
             Ok(wrapper) => {
-                // TODO - this leaks on error
-                let result = unsafe { self.enable_video_output_inner(mode, flags) };
-                SdkError::result_or_else(result, || {
-                    let r: Box<dyn DecklinkOutputDeviceVideoScheduled> = Box::new(
-                        DecklinkOutputDeviceVideoImpl::from(&self.ptr, wrapper, timescale),
-                    );
-                    r
-                })
+                self.enable_video_output_inner(mode, flags)?;
+                Ok(Box::new(DecklinkOutputDeviceVideoImpl::from(
+                    &self.ptr, wrapper, timescale,
+                )))
             }
         }
     }
@@ -133,13 +290,12 @@ impl DecklinkOutputDevice {
         mode: DecklinkDisplayModeId,
         flags: enums::DecklinkVideoOutputFlags,
     ) -> Result<Box<dyn DecklinkOutputDeviceVideoSync>, SdkError> {
-        let result = unsafe { self.enable_video_output_inner(mode, flags) };
-        SdkError::result_or_else(result, || {
-            let r: Box<dyn DecklinkOutputDeviceVideoSync> = Box::new(
-                DecklinkOutputDeviceVideoImpl::from(&self.ptr, null_mut(), 1000),
-            );
-            r
-        })
+        self.enable_video_output_inner(mode, flags)?;
+        Ok(Box::new(DecklinkOutputDeviceVideoImpl::from(
+            &self.ptr,
+            null_mut(),
+            1000,
+        )))
     }
 
     /* Audio Output */
@@ -155,16 +311,13 @@ impl DecklinkOutputDevice {
             // TODO - better mode
             Err(SdkError::ACCESSDENIED)
         } else {
-            unsafe {
-                let result = sdk::cdecklink_output_enable_audio_output(
-                    self.ptr.dev,
-                    sample_rate as u32,
-                    sample_type as u32,
-                    channels,
-                    stream_type as u32,
-                );
-                SdkError::result_or_else(result, || DecklinkOutputDeviceAudio::from(&self.ptr))
-            }
+            self.ptr.dev.enable_audio_output(
+                sample_rate as u32,
+                sample_type as u32,
+                channels,
+                stream_type as u32,
+            )?;
+            Ok(DecklinkOutputDeviceAudio::from(&self.ptr))
         }
     }
 }
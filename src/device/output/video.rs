@@ -1,7 +1,9 @@
 use crate::device::output::video_callback::{CallbackWrapper, DeckLinkVideoOutputCallback};
 use crate::device::output::DecklinkOutputDevicePtr;
-use crate::frame::{DecklinkAlignedVec, DecklinkFrameBase, DecklinkFrameBase2};
-use crate::{sdk, SdkError};
+use crate::frame::{frame_byte_count, DecklinkAlignedVec, DecklinkFrameBase, DecklinkFrameBase2};
+use crate::memcopy::{copy_frame, CopyHint, CopyLayout};
+use crate::ptr::{CustomVideoFramePtr, MutableVideoFramePtr};
+use crate::SdkError;
 use std::ptr::null_mut;
 use std::rc::Rc;
 use std::sync::atomic::Ordering;
@@ -36,32 +38,28 @@ pub trait DecklinkOutputDeviceVideoScheduled: DecklinkOutputDeviceVideo {
 
 pub(crate) struct DecklinkOutputDeviceVideoImpl {
     ptr: Rc<DecklinkOutputDevicePtr>,
-    pub callback_wrapper: *mut CallbackWrapper,
-    pub scheduled_running: bool,
-    pub scheduled_timescale: i64,
+    pub(crate) callback_wrapper: *mut CallbackWrapper,
+    pub(crate) scheduled_running: bool,
+    pub(crate) scheduled_timescale: i64,
 }
 impl Drop for DecklinkOutputDeviceVideoImpl {
     fn drop(&mut self) {
-        // TODO - safety!
-        unsafe {
-            if self.scheduled_running {
-                let mut actual_stop = 0;
-                sdk::cdecklink_output_stop_scheduled_playback(
-                    self.ptr.dev,
-                    0,
-                    &mut actual_stop,
-                    self.scheduled_timescale,
-                );
-            }
+        if self.scheduled_running {
+            let _ = self
+                .ptr
+                .dev
+                .stop_scheduled_playback(0, self.scheduled_timescale);
+        }
 
-            // This call blocks until all frame callbacks are complete
-            sdk::cdecklink_output_disable_video_output(self.ptr.dev);
-            self.ptr.video_active.store(false, Ordering::Relaxed);
+        // This call blocks until all frame callbacks are complete
+        let _ = self.ptr.dev.disable_video_output();
+        self.ptr.video_active.store(false, Ordering::Relaxed);
 
-            if !self.callback_wrapper.is_null() {
-                drop(Box::from_raw(self.callback_wrapper)); // Reclaim the box so it gets freed
-                self.callback_wrapper = null_mut();
-            }
+        // The wrapper belongs to the output, which unregisters it when dropped, so only
+        // the handler set through this handle is let go
+        if !self.callback_wrapper.is_null() {
+            unsafe { *(*self.callback_wrapper).handler.write().unwrap() = None };
+            self.callback_wrapper = null_mut();
         }
     }
 }
@@ -72,53 +70,45 @@ impl DecklinkOutputDeviceVideoSync for DecklinkOutputDeviceVideoImpl {
     fn display_frame_copy(&self, frame: &dyn DecklinkFrameBase) -> Result<(), SdkError> {
         let decklink_frame = self.convert_decklink_frame_without_bytes(frame)?;
 
-        let mut ptr: *mut std::ffi::c_void = std::ptr::null_mut();
-        let result = unsafe {
-            sdk::cdecklink_video_buffer_get_bytes(
-                decklink_frame.ptr as *mut sdk::cdecklink_video_buffer_t,
-                &mut ptr,
-            )
-        };
-        SdkError::result::<()>(result)?;
+        let ptr = decklink_frame.bytes()?;
 
-        let byte_count = frame.row_bytes() * frame.height();
+        let byte_count = frame_byte_count(frame.row_bytes(), frame.height())?;
         let src_bytes = frame.bytes()?;
         if src_bytes.0.len() < byte_count {
             Err(SdkError::INVALIDARG)?;
         }
-        unsafe { std::ptr::copy(src_bytes.0.as_ptr(), ptr as *mut _, byte_count) };
-
-        let result = unsafe {
-            sdk::cdecklink_output_display_video_frame_sync(self.ptr.dev, decklink_frame.ptr)
-        };
-
-        SdkError::result(result)
+        // The processor does not read the driver's buffer again
+        let dst = unsafe { std::slice::from_raw_parts_mut(ptr, byte_count) };
+        copy_frame(
+            dst,
+            src_bytes.0,
+            CopyLayout::contiguous(byte_count),
+            CopyHint::StreamingWriteOnly,
+        )?;
+
+        // Safety: The frame holds a reference until the call returns
+        unsafe {
+            self.ptr
+                .dev
+                .display_video_frame_sync(decklink_frame.as_video_frame())
+        }
     }
 
     fn display_custom_frame(&self, frame: Box<dyn DecklinkFrameBase2>) -> Result<(), SdkError> {
-        let mut decklink_frame = WrappedCustomFrame { ptr: null_mut() };
-        let result = unsafe {
-            sdk::cdecklink_custom_video_frame_create_frame(
-                frame.width() as i64,
-                frame.height() as i64,
-                frame.row_bytes() as i64,
-                frame.pixel_format() as u32,
-                frame.flags().bits(),
-                &mut decklink_frame.ptr,
-            )
-        };
-        SdkError::result::<()>(result)?;
-
-        if decklink_frame.ptr.is_null() {
-            Err(SdkError::FAIL)?;
-        }
-
-        let required_bytes = frame.row_bytes() * frame.height();
+        let decklink_frame = CustomVideoFramePtr::create(
+            frame.width() as i64,
+            frame.height() as i64,
+            frame.row_bytes() as i64,
+            frame.pixel_format() as u32,
+            frame.flags().bits(),
+        )?;
+
+        let required_bytes = frame_byte_count(frame.row_bytes(), frame.height())?;
         let bytes = frame.into_avec()?;
         if bytes.len() < required_bytes {
             Err(SdkError::INVALIDARG)?;
         }
-        if required_bytes % 64 != 0 {
+        if !required_bytes.is_multiple_of(64) {
             // Must be a multiple of 64 to be valid for avx512
             Err(SdkError::INVALIDARG)?;
         }
@@ -126,19 +116,15 @@ impl DecklinkOutputDeviceVideoSync for DecklinkOutputDeviceVideoImpl {
         let context = LeakableVec::from(bytes);
 
         unsafe {
-            sdk::cdecklink_custom_video_frame_set_bytes(
-                decklink_frame.ptr,
+            decklink_frame.set_bytes(
                 context.get_data_ptr(),
                 Some(free_vec),
                 context.into_raw_ptr(),
-            )
-        };
-
-        let result = unsafe {
-            sdk::cdecklink_output_display_video_frame_sync(self.ptr.dev, decklink_frame.ptr)
-        };
-
-        SdkError::result(result)
+            )?;
+            self.ptr
+                .dev
+                .display_video_frame_sync(decklink_frame.as_video_frame())
+        }
     }
 }
 
@@ -150,37 +136,31 @@ impl DecklinkOutputDeviceVideoScheduled for DecklinkOutputDeviceVideoImpl {
         duration: i64,
     ) -> Result<(), SdkError> {
         let frame_bytes = frame.bytes()?;
-        let byte_count = frame.row_bytes() * frame.height();
+        let byte_count = frame_byte_count(frame.row_bytes(), frame.height())?;
         if frame_bytes.0.len() < byte_count {
             Err(SdkError::INVALIDARG)?;
         }
 
         let frame = self.convert_decklink_frame_without_bytes(frame)?;
 
-        let mut bytes_ptr: *mut std::ffi::c_void = std::ptr::null_mut();
-        let result = unsafe {
-            sdk::cdecklink_video_buffer_get_bytes(
-                frame.ptr as *mut sdk::cdecklink_video_buffer_t,
-                &mut bytes_ptr,
-            )
-        };
-        SdkError::result::<()>(result)?;
-        if bytes_ptr.is_null() {
-            Err(SdkError::FAIL)?;
-        }
+        let bytes_ptr = frame.bytes()?;
+        let dst = unsafe { std::slice::from_raw_parts_mut(bytes_ptr, byte_count) };
+        copy_frame(
+            dst,
+            frame_bytes.0,
+            CopyLayout::contiguous(byte_count),
+            CopyHint::StreamingWriteOnly,
+        )?;
 
-        unsafe { std::ptr::copy(frame_bytes.0.as_ptr(), bytes_ptr as *mut _, byte_count) };
-
-        let result = unsafe {
-            sdk::cdecklink_output_schedule_video_frame(
-                self.ptr.dev,
-                frame.ptr,
+        // Safety: The frame holds a reference until the driver has taken its own
+        unsafe {
+            self.ptr.dev.schedule_video_frame(
+                frame.as_video_frame(),
                 display_time,
                 duration,
                 self.scheduled_timescale,
             )
-        };
-        SdkError::result(result)
+        }
     }
 
     fn set_callback(
@@ -199,12 +179,7 @@ impl DecklinkOutputDeviceVideoScheduled for DecklinkOutputDeviceVideoImpl {
     }
 
     fn buffered_video_frame_count(&self) -> Result<u32, SdkError> {
-        unsafe {
-            let mut count = 0;
-            let result =
-                sdk::cdecklink_output_get_buffered_video_frame_count(self.ptr.dev, &mut count);
-            SdkError::result_or(result, count)
-        }
+        self.ptr.dev.buffered_video_frame_count()
     }
 
     fn start_playback(&mut self, start_time: i64, speed: f64) -> Result<(), SdkError> {
@@ -213,15 +188,9 @@ impl DecklinkOutputDeviceVideoScheduled for DecklinkOutputDeviceVideoImpl {
         } else {
             self.scheduled_running = true;
 
-            unsafe {
-                let result = sdk::cdecklink_output_start_scheduled_playback(
-                    self.ptr.dev,
-                    start_time,
-                    self.scheduled_timescale,
-                    speed,
-                );
-                SdkError::result(result)
-            }
+            self.ptr
+                .dev
+                .start_scheduled_playback(start_time, self.scheduled_timescale, speed)
         }
     }
 
@@ -229,16 +198,9 @@ impl DecklinkOutputDeviceVideoScheduled for DecklinkOutputDeviceVideoImpl {
         if self.scheduled_running {
             self.scheduled_running = false;
 
-            unsafe {
-                let mut actual_stop_time = 0;
-                let result = sdk::cdecklink_output_stop_scheduled_playback(
-                    self.ptr.dev,
-                    stop_time,
-                    &mut actual_stop_time,
-                    self.scheduled_timescale,
-                );
-                SdkError::result_or(result, actual_stop_time)
-            }
+            self.ptr
+                .dev
+                .stop_scheduled_playback(stop_time, self.scheduled_timescale)
         } else {
             Err(SdkError::FALSE)
         }
@@ -262,56 +224,16 @@ impl DecklinkOutputDeviceVideoImpl {
     pub(crate) fn convert_decklink_frame_without_bytes(
         &self,
         frame: &dyn DecklinkFrameBase,
-    ) -> Result<WrappedSdkFrame, SdkError> {
-        let mut c_frame = null_mut();
-        unsafe {
-            let res = sdk::cdecklink_output_create_video_frame(
-                self.ptr.dev,
-                frame.width() as i32,
-                frame.height() as i32,
-                frame.row_bytes() as i32,
-                frame.pixel_format() as u32,
-                frame.flags().bits(),
-                &mut c_frame,
-            );
-            SdkError::result::<()>(res)?;
-
-            if c_frame.is_null() {
-                Err(SdkError::FAIL)?;
-            }
-
-            sdk::cdecklink_video_frame_add_ref(c_frame);
-        }
-
-        Ok(WrappedSdkFrame { ptr: c_frame })
-    }
-}
-
-pub(crate) struct WrappedSdkFrame {
-    pub ptr: *mut crate::sdk::cdecklink_mutable_video_frame_t,
-}
-impl Drop for WrappedSdkFrame {
-    fn drop(&mut self) {
-        if !self.ptr.is_null() {
-            unsafe {
-                sdk::cdecklink_video_frame_release(self.ptr);
-            }
-            self.ptr = null_mut();
-        }
-    }
-}
-
-pub(crate) struct WrappedCustomFrame {
-    pub ptr: *mut crate::sdk::cdecklink_custom_video_frame_t,
-}
-impl Drop for WrappedCustomFrame {
-    fn drop(&mut self) {
-        if !self.ptr.is_null() {
-            unsafe {
-                sdk::cdecklink_custom_video_frame_release(self.ptr);
-            }
-            self.ptr = null_mut();
-        }
+    ) -> Result<MutableVideoFramePtr, SdkError> {
+        // The SDK takes dimensions as i32, so refuse any that would be truncated
+        let to_i32 = |value: usize| i32::try_from(value).map_err(|_| SdkError::INVALIDARG);
+        self.ptr.dev.create_video_frame(
+            to_i32(frame.width())?,
+            to_i32(frame.height())?,
+            to_i32(frame.row_bytes())?,
+            frame.pixel_format() as u32,
+            frame.flags().bits(),
+        )
     }
 }
 
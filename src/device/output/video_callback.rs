@@ -1,60 +1,113 @@
 use crate::device::output::enums::DecklinkOutputFrameCompletionResult;
 use crate::device::output::DecklinkOutputDevicePtr;
 use crate::frame::DecklinkVideoFrame;
+use crate::ptr::OutputPtr;
 use crate::{sdk, SdkError};
 use num_traits::FromPrimitive;
-use std::rc::Rc;
+use std::ptr::null_mut;
 use std::sync::{Arc, RwLock};
 
-pub(crate) fn free_callback_wrapper(wrapper: *mut CallbackWrapper) {
-    unsafe {
-        drop(Box::from_raw(wrapper));
+/// The callback wrapper of the output, registering it with the driver the first time. It is
+/// unregistered and freed by `unregister_callback` when the output is dropped.
+pub(crate) fn register_callback(
+    ptr: &DecklinkOutputDevicePtr,
+) -> Result<*mut CallbackWrapper, SdkError> {
+    let registered = ptr.callback_wrapper.get();
+    if !registered.is_null() {
+        return Ok(registered);
     }
-}
 
-pub fn register_callback(
-    ptr: &Rc<DecklinkOutputDevicePtr>,
-) -> Result<*mut CallbackWrapper, SdkError> {
     let callback_wrapper = Box::into_raw(Box::new(CallbackWrapper {
         handler: RwLock::new(None),
     }));
 
     let result = unsafe {
-        sdk::cdecklink_output_set_scheduled_frame_completion_callback(
-            ptr.dev,
+        ptr.dev.set_scheduled_frame_completion_callback(
             callback_wrapper as *mut std::ffi::c_void,
             Some(schedule_frame_completed_callback),
             Some(playback_stopped),
         )
     };
 
-    match SdkError::result_or(result, callback_wrapper) {
+    match result.map(|_| callback_wrapper) {
         Err(e) => {
-            free_callback_wrapper(callback_wrapper);
+            unsafe { drop(Box::from_raw(callback_wrapper)) };
             Err(e)
         }
-        Ok(v) => Ok(v),
+        Ok(v) => {
+            ptr.callback_wrapper.set(v);
+            Ok(v)
+        }
     }
 }
 
+/// Unregister the callback wrapper of `dev` from the driver and free it, so the driver cannot
+/// call into it once freed. Video output must already be disabled, which waits for the
+/// callbacks in progress.
+pub(crate) unsafe fn unregister_callback(dev: &OutputPtr, wrapper: *mut CallbackWrapper) {
+    if !wrapper.is_null() {
+        let _ = dev.set_scheduled_frame_completion_callback(null_mut(), None, None);
+        drop(Box::from_raw(wrapper));
+    }
+}
+
+/// The handler of the frames scheduled on an output, and of its scheduled playback stopping.
+///
+/// Implement `scheduled_frame_completed` and `scheduled_playback_has_stopped`. Callbacks
+/// written against the earlier `schedule_frame_completed_callback` and `playback_stopped`
+/// still work, as the new methods call them by default.
+///
+/// The methods are called on a thread of the driver, and the value returned is passed back
+/// to it: `true` returns `S_OK` and `false` returns `S_FALSE`.
 pub trait DeckLinkVideoOutputCallback {
-    fn schedule_frame_completed_callback(
+    /// Called once the driver has finished with a scheduled frame, with how it was played
+    /// out. `frame` is the frame that was scheduled, or `None` if the driver did not pass it.
+    #[allow(deprecated)]
+    fn scheduled_frame_completed(
         &self,
         frame: Option<DecklinkVideoFrame>,
         result: DecklinkOutputFrameCompletionResult,
-    ) -> bool;
-    fn playback_stopped(&self) -> bool;
+    ) -> bool {
+        self.schedule_frame_completed_callback(frame, result)
+    }
+
+    /// Called once scheduled playback has stopped, after the frames that were flushed have
+    /// completed.
+    #[allow(deprecated)]
+    fn scheduled_playback_has_stopped(&self) -> bool {
+        self.playback_stopped()
+    }
+
+    #[deprecated(
+        since = "0.1.0",
+        note = "implement `scheduled_frame_completed` instead"
+    )]
+    fn schedule_frame_completed_callback(
+        &self,
+        _frame: Option<DecklinkVideoFrame>,
+        _result: DecklinkOutputFrameCompletionResult,
+    ) -> bool {
+        true
+    }
+
+    #[deprecated(
+        since = "0.1.0",
+        note = "implement `scheduled_playback_has_stopped` instead"
+    )]
+    fn playback_stopped(&self) -> bool {
+        true
+    }
 }
 
-pub struct CallbackWrapper {
-    pub handler: RwLock<Option<Arc<dyn DeckLinkVideoOutputCallback>>>,
+pub(crate) struct CallbackWrapper {
+    pub(crate) handler: RwLock<Option<Arc<dyn DeckLinkVideoOutputCallback>>>,
 }
 extern "C" fn schedule_frame_completed_callback(
     context: *mut ::std::os::raw::c_void,
     frame: *mut sdk::cdecklink_video_frame_t,
     result: sdk::DecklinkOutputFrameCompletionResult,
 ) -> sdk::HRESULT {
-    let wrapper: &mut CallbackWrapper = unsafe { &mut *(context as *mut _) };
+    let wrapper: &CallbackWrapper = unsafe { &*(context as *mut _) };
 
     let mut res = true;
     if let Some(handler) = &*wrapper.handler.read().unwrap() {
@@ -67,7 +120,7 @@ extern "C" fn schedule_frame_completed_callback(
         let result_internal = DecklinkOutputFrameCompletionResult::from_u32(result)
             .unwrap_or(DecklinkOutputFrameCompletionResult::Completed);
 
-        res = handler.schedule_frame_completed_callback(frame_internal, result_internal);
+        res = handler.scheduled_frame_completed(frame_internal, result_internal);
     }
 
     if res {
@@ -77,11 +130,11 @@ extern "C" fn schedule_frame_completed_callback(
     }
 }
 extern "C" fn playback_stopped(context: *mut ::std::os::raw::c_void) -> sdk::HRESULT {
-    let wrapper: &mut CallbackWrapper = unsafe { &mut *(context as *mut _) };
+    let wrapper: &CallbackWrapper = unsafe { &*(context as *mut _) };
 
     let mut result = true;
     if let Some(handler) = &*wrapper.handler.read().unwrap() {
-        result = handler.playback_stopped();
+        result = handler.scheduled_playback_has_stopped();
     }
 
     if result {
@@ -0,0 +1,402 @@
+//! Enumerating devices once, and keeping one handle per device across refreshes.
+//!
+//! `get_devices` walks the driver's iterator and creates new wrappers on every call. A
+//! `DeviceRegistry` enumerates once and keys each device by its persistent id, or by its
+//! topological id or display name if it has none. The `Arc` it hands out for a device is the
+//! same one after every `refresh`, so `Arc::ptr_eq` tells devices apart, and what is cached in
+//! the device, such as its names, is kept. The identity of each device is read once, when it
+//! first appears.
+//!
+//! With the driver's discovery notifications, `refresh` only enumerates again after a device
+//! has arrived or been removed, so polling it costs no driver calls while nothing changes.
+//! Without them, every `refresh` enumerates. A device that is no longer listed is marked
+//! removed, see `DecklinkDevice::is_removed`, so a handle kept to it fails with a defined
+//! error instead of reaching hardware that has gone.
+//!
+//! Devices cannot be sent between threads, so neither can a registry. Each thread that uses
+//! devices keeps its own.
+
+use crate::device::{get_devices, DecklinkDevice};
+use crate::ptr::DiscoveryPtr;
+use crate::{sdk, SdkError};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::ptr::null_mut;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// What identifies a device from one enumeration to the next.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DeviceKey {
+    PersistentId(i64),
+    /// For a device without a persistent id.
+    TopologicalId(i64),
+    /// For a device with neither, its display name, and which of the devices with that name it
+    /// is, in the order they are listed.
+    Name {
+        name: String,
+        index: usize,
+    },
+}
+
+impl fmt::Display for DeviceKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeviceKey::PersistentId(id) => write!(f, "id:0x{:x}", id),
+            DeviceKey::TopologicalId(id) => write!(f, "topological:0x{:x}", id),
+            DeviceKey::Name { name, index: 0 } => write!(f, "name:{}", name),
+            DeviceKey::Name { name, index } => write!(f, "name:{} #{}", name, index + 1),
+        }
+    }
+}
+
+/// The identity of a device, which the driver does not change while it is attached.
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DeviceInfo {
+    pub persistent_id: Option<i64>,
+    pub topological_id: Option<i64>,
+    pub display_name: Option<String>,
+    pub model_name: Option<String>,
+    /// The `device_handle` attribute, which stays the same when a device is reattached.
+    pub device_handle: Option<String>,
+}
+
+impl DeviceInfo {
+    /// Read the identity of a device from the driver.
+    pub fn read(device: &DecklinkDevice) -> DeviceInfo {
+        let attributes = device.get_attributes().ok();
+        DeviceInfo {
+            persistent_id: device.persistent_id(),
+            topological_id: attributes.as_ref().and_then(|a| a.topological_id().ok()),
+            display_name: device.display_name(),
+            model_name: device.model_name(),
+            device_handle: attributes.as_ref().and_then(|a| a.device_handle().ok()),
+        }
+    }
+}
+
+/// A device as a `DeviceSource` lists it.
+pub struct EnumeratedDevice {
+    pub device: DecklinkDevice,
+    pub info: DeviceInfo,
+}
+
+/// Where a registry gets its devices from: the driver, or a `testing::MockDeviceSource`.
+pub trait DeviceSource {
+    /// List the devices present now, in the order the driver lists them.
+    fn enumerate(&mut self) -> Result<Vec<EnumeratedDevice>, SdkError>;
+
+    /// Whether the devices may have changed since the last `enumerate`. A source that cannot
+    /// tell returns true, so that every refresh enumerates.
+    fn may_have_changed(&self) -> bool {
+        true
+    }
+
+    /// Whether `may_have_changed` follows notifications of devices arriving and being removed.
+    fn is_event_driven(&self) -> bool {
+        false
+    }
+}
+
+/// The devices of the installed driver, with its discovery notifications when it has them.
+pub struct DriverDeviceSource {
+    discovery: Option<DiscoveryPtr>,
+    /// Counts the arrivals and removals notified, shared with the notification callbacks.
+    changes: *const AtomicU64,
+    seen: u64,
+}
+
+impl DriverDeviceSource {
+    /// Install the discovery notifications if the driver has them. Without them, each
+    /// enumeration walks the driver's device iterator.
+    pub fn new() -> DriverDeviceSource {
+        let mut source = DriverDeviceSource {
+            discovery: None,
+            changes: null_mut(),
+            seen: 0,
+        };
+        let Some(discovery) = DiscoveryPtr::create() else {
+            return source;
+        };
+        let changes = Arc::into_raw(Arc::new(AtomicU64::new(0)));
+        let result = unsafe {
+            discovery.install_device_notifications(
+                changes as *mut std::ffi::c_void,
+                Some(device_changed),
+                Some(device_changed),
+            )
+        };
+        if result.is_ok() {
+            source.discovery = Some(discovery);
+            source.changes = changes;
+        } else {
+            unsafe { drop(Arc::from_raw(changes)) };
+        }
+        source
+    }
+
+    fn changes(&self) -> Option<&AtomicU64> {
+        // Safety: The counter is freed only on drop, after the notifications are uninstalled
+        unsafe { self.changes.as_ref() }
+    }
+}
+
+impl Default for DriverDeviceSource {
+    fn default() -> Self {
+        DriverDeviceSource::new()
+    }
+}
+
+extern "C" fn device_changed(
+    context: *mut std::ffi::c_void,
+    _device: *mut sdk::cdecklink_device_t,
+) -> sdk::HRESULT {
+    // The device is not kept, so it is not referenced. The next refresh enumerates it.
+    let changes: &AtomicU64 = unsafe { &*(context as *const AtomicU64) };
+    changes.fetch_add(1, Ordering::AcqRel);
+    0
+}
+
+impl DeviceSource for DriverDeviceSource {
+    fn enumerate(&mut self) -> Result<Vec<EnumeratedDevice>, SdkError> {
+        // Read before enumerating, so that a change during it is enumerated again next time
+        let changes = self.changes().map(|c| c.load(Ordering::Acquire));
+        let devices = get_devices()?;
+        self.seen = changes.unwrap_or_default();
+        Ok(devices
+            .into_iter()
+            .map(|device| EnumeratedDevice {
+                info: DeviceInfo::read(&device),
+                device,
+            })
+            .collect())
+    }
+
+    fn may_have_changed(&self) -> bool {
+        self.changes()
+            .is_none_or(|c| c.load(Ordering::Acquire) != self.seen)
+    }
+
+    fn is_event_driven(&self) -> bool {
+        !self.changes.is_null()
+    }
+}
+
+impl Drop for DriverDeviceSource {
+    fn drop(&mut self) {
+        if let Some(discovery) = self.discovery.take() {
+            let _ = discovery.uninstall_device_notifications();
+            drop(discovery);
+            unsafe { drop(Arc::from_raw(self.changes)) };
+            self.changes = null_mut();
+        }
+    }
+}
+
+/// Returned by `DecklinkDevice::check_present` for a device that has been removed.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct DeviceRemoved {
+    pub persistent_id: Option<i64>,
+    pub display_name: Option<String>,
+}
+
+impl fmt::Display for DeviceRemoved {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.display_name, self.persistent_id) {
+            (Some(name), _) => write!(f, "device {:?} has been removed", name),
+            (None, Some(id)) => write!(f, "device id:0x{:x} has been removed", id),
+            (None, None) => write!(f, "the device has been removed"),
+        }
+    }
+}
+
+impl std::error::Error for DeviceRemoved {}
+
+/// What a refresh found, against the devices the registry held before it.
+#[derive(Default)]
+pub struct RefreshDelta {
+    pub added: Vec<Arc<DecklinkDevice>>,
+    /// Devices that are no longer listed. They are marked removed.
+    pub removed: Vec<Arc<DecklinkDevice>>,
+    pub unchanged: Vec<Arc<DecklinkDevice>>,
+    /// Whether the source was enumerated. When nothing has been notified since the last
+    /// enumeration, the refresh is answered from the registry.
+    pub enumerated: bool,
+}
+
+impl RefreshDelta {
+    /// Whether no device was added or removed.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+struct Registered {
+    key: DeviceKey,
+    info: DeviceInfo,
+    device: Arc<DecklinkDevice>,
+}
+
+/// The devices present, each with one handle that is kept across refreshes.
+pub struct DeviceRegistry {
+    source: Box<dyn DeviceSource>,
+    devices: Vec<Registered>,
+    enumerations: u64,
+}
+
+impl DeviceRegistry {
+    /// Enumerate the devices of the installed driver.
+    pub fn new() -> Result<DeviceRegistry, SdkError> {
+        DeviceRegistry::with_source(DriverDeviceSource::new())
+    }
+
+    /// Enumerate the devices of `source`.
+    pub fn with_source<S: DeviceSource + 'static>(source: S) -> Result<DeviceRegistry, SdkError> {
+        let mut registry = DeviceRegistry {
+            source: Box::new(source),
+            devices: Vec::new(),
+            enumerations: 0,
+        };
+        registry.force_refresh()?;
+        Ok(registry)
+    }
+
+    /// The devices present at the last refresh, in the order the driver lists them.
+    pub fn devices(&self) -> Vec<Arc<DecklinkDevice>> {
+        self.devices.iter().map(|d| d.device.clone()).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.devices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.devices.is_empty()
+    }
+
+    /// The keys, identities and handles of the devices, in the order the driver lists them.
+    pub fn entries(&self) -> impl Iterator<Item = (&DeviceKey, &DeviceInfo, &Arc<DecklinkDevice>)> {
+        self.devices.iter().map(|d| (&d.key, &d.info, &d.device))
+    }
+
+    pub fn get(&self, key: &DeviceKey) -> Option<Arc<DecklinkDevice>> {
+        self.devices
+            .iter()
+            .find(|d| &d.key == key)
+            .map(|d| d.device.clone())
+    }
+
+    /// The device with this `device_handle` attribute, as a reattached device keeps it.
+    pub fn find_by_handle(&self, handle: &str) -> Option<Arc<DecklinkDevice>> {
+        self.devices
+            .iter()
+            .find(|d| d.info.device_handle.as_deref() == Some(handle))
+            .map(|d| d.device.clone())
+    }
+
+    /// The key of a device from this registry.
+    pub fn key_of(&self, device: &Arc<DecklinkDevice>) -> Option<&DeviceKey> {
+        self.entry_of(device).map(|d| &d.key)
+    }
+
+    /// The cached identity of a device from this registry.
+    pub fn info(&self, device: &Arc<DecklinkDevice>) -> Option<&DeviceInfo> {
+        self.entry_of(device).map(|d| &d.info)
+    }
+
+    fn entry_of(&self, device: &Arc<DecklinkDevice>) -> Option<&Registered> {
+        self.devices.iter().find(|d| Arc::ptr_eq(&d.device, device))
+    }
+
+    /// Whether refreshes follow the driver's notifications, rather than enumerating each time.
+    pub fn is_event_driven(&self) -> bool {
+        self.source.is_event_driven()
+    }
+
+    /// The number of times the source has been enumerated.
+    pub fn enumerations(&self) -> u64 {
+        self.enumerations
+    }
+
+    /// Find the devices added and removed since the last refresh. This enumerates again only
+    /// if the source may have changed.
+    pub fn refresh(&mut self) -> Result<RefreshDelta, SdkError> {
+        if self.source.may_have_changed() {
+            self.force_refresh()
+        } else {
+            Ok(RefreshDelta {
+                unchanged: self.devices(),
+                ..RefreshDelta::default()
+            })
+        }
+    }
+
+    /// Enumerate the source again, whether or not it has notified a change.
+    ///
+    /// A device that is listed again keeps its handle, and the new wrapper the source made for
+    /// it is dropped. A device that comes back after it was removed is added with a new handle,
+    /// and the old one stays removed.
+    // An `Arc` rather than an `Rc`, so that the handles keep their type if devices become
+    // sendable. They are not sent today.
+    #[allow(clippy::arc_with_non_send_sync)]
+    pub fn force_refresh(&mut self) -> Result<RefreshDelta, SdkError> {
+        let listed = self.source.enumerate()?;
+        self.enumerations += 1;
+
+        let mut used = HashSet::new();
+        let mut names = HashMap::new();
+        let mut previous: Vec<Option<Registered>> = self.devices.drain(..).map(Some).collect();
+        let mut delta = RefreshDelta {
+            enumerated: true,
+            ..RefreshDelta::default()
+        };
+        for EnumeratedDevice { device, info } in listed {
+            let key = key(&info, &used, &mut names);
+            used.insert(key.clone());
+            let kept = previous
+                .iter_mut()
+                .find(|d| d.as_ref().is_some_and(|d| d.key == key))
+                .and_then(Option::take);
+            match kept {
+                Some(registered) => {
+                    delta.unchanged.push(registered.device.clone());
+                    self.devices.push(registered);
+                }
+                None => {
+                    let device = Arc::new(device);
+                    delta.added.push(device.clone());
+                    self.devices.push(Registered { key, info, device });
+                }
+            }
+        }
+        for registered in previous.into_iter().flatten() {
+            registered.device.mark_removed();
+            delta.removed.push(registered.device);
+        }
+        Ok(delta)
+    }
+}
+
+/// The key of a device, falling back to its name when an id is missing or already taken.
+fn key(
+    info: &DeviceInfo,
+    used: &HashSet<DeviceKey>,
+    names: &mut HashMap<String, usize>,
+) -> DeviceKey {
+    let id = info
+        .persistent_id
+        .map(DeviceKey::PersistentId)
+        .or(info.topological_id.map(DeviceKey::TopologicalId))
+        .filter(|key| !used.contains(key));
+    id.unwrap_or_else(|| {
+        let name = info.display_name.clone().unwrap_or_default();
+        let index = names.entry(name.clone()).or_default();
+        *index += 1;
+        DeviceKey::Name {
+            name,
+            index: *index - 1,
+        }
+    })
+}
@@ -0,0 +1,297 @@
+//! Choosing a device by index, persistent id or name, with one syntax everywhere.
+//!
+//! A `DeviceSelector` parses from a compact string, so the same text can be used in a config
+//! file, on a command line, or in code:
+//!
+//! - `first` is `First`
+//! - `2` is `Index(2)`
+//! - `id:0x1234` or `id:4660` is `PersistentId(0x1234)`
+//! - `sub:0x1234/1` is `SubDevice { persistent_id: 0x1234, index: 1 }`
+//! - `name:DeckLink Duo (1)` is `NameExact("DeckLink Duo (1)")`
+//! - `contains:Duo` is `NameContains("Duo")`
+//! - `label:CAM 1` is `Label("CAM 1")`
+//!
+//! Any other text is taken as an exact name, so a display name can be given on its own.
+//! Selectors format back to the same syntax.
+
+use crate::device::{get_devices, DecklinkDevice};
+use crate::SdkError;
+use std::fmt;
+use std::str::FromStr;
+
+/// Which device to use. Names are matched against the display name of each device, which
+/// tells apart the sub-devices of a card, such as "DeckLink Duo (1)".
+#[derive(PartialEq, Eq, Debug, Clone, Hash)]
+pub enum DeviceSelector {
+    /// The device at this position in the order the driver lists them. The order can
+    /// change when devices are added or removed.
+    Index(usize),
+    /// The device with this persistent id attribute.
+    PersistentId(i64),
+    NameExact(String),
+    NameContains(String),
+    /// The label assigned to the device in Desktop Video Setup.
+    Label(String),
+    /// A sub-device of a card, by the device group id the sub-devices of the card share,
+    /// and its sub-device index.
+    SubDevice {
+        persistent_id: i64,
+        index: i64,
+    },
+    /// The first device listed.
+    First,
+}
+
+/// Returned when a selector does not parse.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct ParseSelectorError {
+    pub text: String,
+    pub reason: &'static str,
+}
+
+impl fmt::Display for ParseSelectorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid device selector {:?}: {}",
+            self.text, self.reason
+        )
+    }
+}
+
+impl std::error::Error for ParseSelectorError {}
+
+fn parse_id(text: &str) -> Option<i64> {
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => i64::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+impl FromStr for DeviceSelector {
+    type Err = ParseSelectorError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let error = |reason| ParseSelectorError {
+            text: text.to_string(),
+            reason,
+        };
+
+        if text == "first" {
+            return Ok(DeviceSelector::First);
+        }
+        if let Ok(index) = text.parse() {
+            return Ok(DeviceSelector::Index(index));
+        }
+        if let Some(id) = text.strip_prefix("id:") {
+            return parse_id(id)
+                .map(DeviceSelector::PersistentId)
+                .ok_or_else(|| error("expected a persistent id, such as id:0x1234"));
+        }
+        if let Some(sub) = text.strip_prefix("sub:") {
+            return sub
+                .split_once('/')
+                .and_then(|(id, index)| Some((parse_id(id)?, index.parse().ok()?)))
+                .map(|(persistent_id, index)| DeviceSelector::SubDevice {
+                    persistent_id,
+                    index,
+                })
+                .ok_or_else(|| {
+                    error("expected a device group id and index, such as sub:0x1234/1")
+                });
+        }
+
+        let (name, selector): (_, fn(String) -> DeviceSelector) =
+            if let Some(name) = text.strip_prefix("name:") {
+                (name, DeviceSelector::NameExact)
+            } else if let Some(name) = text.strip_prefix("contains:") {
+                (name, DeviceSelector::NameContains)
+            } else if let Some(label) = text.strip_prefix("label:") {
+                (label, DeviceSelector::Label)
+            } else {
+                (text, DeviceSelector::NameExact)
+            };
+        if name.is_empty() {
+            Err(error("the name is empty"))
+        } else {
+            Ok(selector(name.to_string()))
+        }
+    }
+}
+
+impl fmt::Display for DeviceSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeviceSelector::Index(index) => write!(f, "{}", index),
+            DeviceSelector::PersistentId(id) => write!(f, "id:0x{:x}", id),
+            DeviceSelector::NameExact(name) => write!(f, "name:{}", name),
+            DeviceSelector::NameContains(name) => write!(f, "contains:{}", name),
+            DeviceSelector::Label(label) => write!(f, "label:{}", label),
+            DeviceSelector::SubDevice {
+                persistent_id,
+                index,
+            } => write!(f, "sub:0x{:x}/{}", persistent_id, index),
+            DeviceSelector::First => write!(f, "first"),
+        }
+    }
+}
+
+/// Returned when a selector does not pick out a device.
+#[derive(Debug)]
+pub enum SelectError {
+    /// No device matches. `available` describes each device that is present.
+    NoMatch {
+        selector: DeviceSelector,
+        available: Vec<String>,
+    },
+    /// More than one device matches a selector that must pick out one.
+    Ambiguous {
+        selector: DeviceSelector,
+        candidates: Vec<String>,
+    },
+    /// The selector cannot be resolved through the C bindings. Device labels are part of
+    /// the device configuration, which they do not expose.
+    Unsupported { selector: DeviceSelector },
+    /// The devices could not be listed.
+    Sdk(SdkError),
+}
+
+impl From<SdkError> for SelectError {
+    fn from(e: SdkError) -> Self {
+        SelectError::Sdk(e)
+    }
+}
+
+impl fmt::Display for SelectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SelectError::NoMatch {
+                selector,
+                available,
+            } => {
+                if available.is_empty() {
+                    write!(
+                        f,
+                        "no device matches {}, and no devices were found",
+                        selector
+                    )
+                } else {
+                    write!(
+                        f,
+                        "no device matches {}. Devices found: {}",
+                        selector,
+                        available.join(", ")
+                    )
+                }
+            }
+            SelectError::Ambiguous {
+                selector,
+                candidates,
+            } => write!(
+                f,
+                "{} matches more than one device: {}",
+                selector,
+                candidates.join(", ")
+            ),
+            SelectError::Unsupported { selector } => {
+                write!(f, "{} cannot be resolved with these bindings", selector)
+            }
+            SelectError::Sdk(e) => write!(f, "the devices could not be listed: {:?}", e),
+        }
+    }
+}
+
+impl std::error::Error for SelectError {}
+
+/// A device as it is listed in errors: its index, display name and persistent id.
+fn describe(index: usize, device: &DecklinkDevice) -> String {
+    let name = device.display_name_str().unwrap_or("unnamed device");
+    match device.persistent_id() {
+        Some(id) => format!("{}: {} (id:0x{:x})", index, name, id),
+        None => format!("{}: {}", index, name),
+    }
+}
+
+impl DeviceSelector {
+    fn matches(&self, index: usize, device: &DecklinkDevice) -> bool {
+        match self {
+            DeviceSelector::Index(i) => *i == index,
+            DeviceSelector::First => index == 0,
+            DeviceSelector::PersistentId(id) => device.persistent_id() == Some(*id),
+            DeviceSelector::NameExact(name) => device.display_name_str() == Some(name.as_str()),
+            DeviceSelector::NameContains(name) => device
+                .display_name_str()
+                .is_some_and(|n| n.contains(name.as_str())),
+            DeviceSelector::SubDevice {
+                persistent_id,
+                index,
+            } => device.get_attributes().is_ok_and(|a| {
+                a.device_group_id().ok() == Some(*persistent_id)
+                    && a.sub_device_index().ok() == Some(*index)
+            }),
+            DeviceSelector::Label(_) => false,
+        }
+    }
+
+    /// Pick from `devices`, listed in driver order.
+    fn select(
+        &self,
+        devices: Vec<DecklinkDevice>,
+    ) -> Result<Vec<(usize, DecklinkDevice)>, SelectError> {
+        if let DeviceSelector::Label(_) = self {
+            return Err(SelectError::Unsupported {
+                selector: self.clone(),
+            });
+        }
+
+        let mut available = Vec::new();
+        let mut matched = Vec::new();
+        for (index, device) in devices.into_iter().enumerate() {
+            if self.matches(index, &device) {
+                matched.push((index, device));
+            } else {
+                available.push(describe(index, &device));
+            }
+        }
+
+        if matched.is_empty() {
+            Err(SelectError::NoMatch {
+                selector: self.clone(),
+                available,
+            })
+        } else {
+            Ok(matched)
+        }
+    }
+
+    /// Find the device the selector picks out. Fails with `SelectError::Ambiguous` if a name
+    /// matches more than one device.
+    pub fn resolve(&self) -> Result<DecklinkDevice, SelectError> {
+        self.resolve_from(get_devices()?)
+    }
+
+    /// Find the device the selector picks out from `devices`, listed in driver order.
+    pub fn resolve_from(
+        &self,
+        devices: Vec<DecklinkDevice>,
+    ) -> Result<DecklinkDevice, SelectError> {
+        let mut matched = self.select(devices)?;
+        if matched.len() > 1 {
+            return Err(SelectError::Ambiguous {
+                selector: self.clone(),
+                candidates: matched
+                    .iter()
+                    .map(|(index, device)| describe(*index, device))
+                    .collect(),
+            });
+        }
+        Ok(matched.remove(0).1)
+    }
+
+    /// Find every device the selector matches, in driver order. Fails with
+    /// `SelectError::NoMatch` if there are none.
+    pub fn resolve_all(&self) -> Result<Vec<DecklinkDevice>, SelectError> {
+        let matched = self.select(get_devices()?)?;
+        Ok(matched.into_iter().map(|(_, device)| device).collect())
+    }
+}
@@ -1,15 +1,20 @@
 use crate::display_mode::DecklinkDisplayModeId;
 use crate::frame::DecklinkPixelFormat;
+use crate::ptr::StatusPtr;
 use crate::{sdk, SdkError};
 use num_traits::FromPrimitive;
-use std::os::raw::c_void;
-use std::ptr::null_mut;
 
 pub struct DecklinkDeviceStatus {
-    dev: *mut sdk::cdecklink_status_t,
+    dev: StatusPtr,
 }
 
-#[derive(FromPrimitive, PartialEq, Debug, Copy, Clone)]
+// Safety: The status interface only reads values, and the SDK allows its getters to be called
+// concurrently from any thread.
+unsafe impl Send for DecklinkDeviceStatus {}
+unsafe impl Sync for DecklinkDeviceStatus {}
+
+#[derive(EnumIter, FromPrimitive, PartialEq, Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum DecklinkStatusId {
     /// The detected video input mode (BMDDisplayMode), available on devices which support input format detection.
     DetectedVideoInputMode = sdk::_DecklinkStatusID_decklinkStatusDetectedVideoInputMode as isize,
@@ -49,6 +54,9 @@ pub enum DecklinkStatusId {
 
     /// The received EDID of a connected HDMI sink device.
     ReceivedEDID = sdk::_DecklinkStatusID_decklinkStatusReceivedEDID as isize,
+
+    /// The on-board temperature in degrees Celsius.
+    DeviceTemperature = sdk::_DecklinkStatusID_decklinkStatusDeviceTemperature as isize,
 }
 
 bitflags! {
@@ -61,13 +69,12 @@ bitflags! {
     }
 }
 
-
-impl Drop for DecklinkDeviceStatus {
-    fn drop(&mut self) {
-        if !self.dev.is_null() {
-            unsafe { sdk::cdecklink_status_release(self.dev) };
-            self.dev = null_mut();
-        }
+bitflags! {
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    pub struct DecklinkDeviceBusyState: u32 {
+        const CAPTURE_BUSY = sdk::_DecklinkDeviceBusyState_decklinkDeviceCaptureBusy;
+        const PLAYBACK_BUSY = sdk::_DecklinkDeviceBusyState_decklinkDevicePlaybackBusy;
+        const SERIAL_PORT_BUSY = sdk::_DecklinkDeviceBusyState_decklinkDeviceSerialPortBusy;
     }
 }
 
@@ -82,41 +89,51 @@ where
 }
 
 impl DecklinkDeviceStatus {
-    pub(crate) fn from(ptr: *mut sdk::cdecklink_status_t) -> DecklinkDeviceStatus {
+    pub(crate) fn from(ptr: StatusPtr) -> DecklinkDeviceStatus {
         DecklinkDeviceStatus { dev: ptr }
     }
 
     // TODO - do separate like attributes
     fn get_int(&self, id: u32) -> Result<i64, SdkError> {
-        let mut value = 0;
-        let result = unsafe { sdk::cdecklink_status_get_int(self.dev, id, &mut value) };
-        SdkError::result_or(result, value)
+        self.dev.int(id)
     }
 
     fn get_bool(&self, id: u32) -> Result<bool, SdkError> {
-        let mut value = false;
-        let result = unsafe { sdk::cdecklink_status_get_flag(self.dev, id, &mut value) };
-        SdkError::result_or(result, value)
+        self.dev.flag(id)
+    }
+
+    fn get_float(&self, id: u32) -> Result<f64, SdkError> {
+        self.dev.float(id)
+    }
+
+    fn get_string(&self, id: u32) -> Result<String, SdkError> {
+        self.dev.string(id)
     }
 
     fn get_bytes(&self, id: u32) -> Result<Vec<u8>, SdkError> {
-        let mut byte_count = 0;
-        let result =
-            unsafe { sdk::cdecklink_status_get_bytes(self.dev, id, null_mut(), &mut byte_count) };
-        if SdkError::is_ok(result) {
-            let mut bytes = vec![0; byte_count as usize];
-            let result = unsafe {
-                sdk::cdecklink_status_get_bytes(
-                    self.dev,
-                    id,
-                    bytes.as_mut_ptr() as *mut c_void,
-                    &mut byte_count,
-                )
-            };
-            SdkError::result_or(result, bytes)
-        } else {
-            Err(SdkError::from(result))
-        }
+        self.dev.bytes(id)
+    }
+
+    /// Read a flag status by its id, for status items without a typed getter.
+    /// See `crate::device::custom_id`.
+    pub fn get_flag_raw(&self, id: u32) -> Result<bool, SdkError> {
+        self.get_bool(id)
+    }
+    /// Read an integer status by its id. See `crate::device::custom_id`.
+    pub fn get_int_raw(&self, id: u32) -> Result<i64, SdkError> {
+        self.get_int(id)
+    }
+    /// Read a float status by its id. See `crate::device::custom_id`.
+    pub fn get_float_raw(&self, id: u32) -> Result<f64, SdkError> {
+        self.get_float(id)
+    }
+    /// Read a string status by its id. See `crate::device::custom_id`.
+    pub fn get_string_raw(&self, id: u32) -> Result<String, SdkError> {
+        self.get_string(id)
+    }
+    /// Read a byte status by its id. See `crate::device::custom_id`.
+    pub fn get_bytes_raw(&self, id: u32) -> Result<Vec<u8>, SdkError> {
+        self.get_bytes(id)
     }
 
     /// The detected video input mode (BMDDisplayMode), available on devices which support input format detection.
@@ -175,6 +192,11 @@ impl DecklinkDeviceStatus {
     pub fn busy(&self) -> Result<i64, SdkError> {
         self.get_int(sdk::_DecklinkStatusID_decklinkStatusBusy)
     }
+    /// The current busy state of the device, as flags.
+    pub fn busy_state(&self) -> Result<DecklinkDeviceBusyState, SdkError> {
+        self.get_int(sdk::_DecklinkStatusID_decklinkStatusBusy)
+            .map(|v| DecklinkDeviceBusyState::from_bits_truncate(v as u32))
+    }
     /// The interchangeable panel installed (BMDPanelType).
     pub fn interchangeable_panel_type(&self) -> Result<i64, SdkError> {
         self.get_int(sdk::_DecklinkStatusID_decklinkStatusInterchangeablePanelType)
@@ -193,4 +215,9 @@ impl DecklinkDeviceStatus {
     pub fn received_edid(&self) -> Result<Vec<u8>, SdkError> {
         self.get_bytes(sdk::_DecklinkStatusID_decklinkStatusReceivedEDID)
     }
+
+    /// The on-board temperature in degrees Celsius.
+    pub fn device_temperature(&self) -> Result<i64, SdkError> {
+        self.get_int(sdk::_DecklinkStatusID_decklinkStatusDeviceTemperature)
+    }
 }
@@ -0,0 +1,716 @@
+//! Sharing a pool of worker threads between the inputs of several sub-devices.
+//!
+//! A `DispatchPool` is given a `DispatchHandler` per input, its source, and installs a
+//! callback on each input that only queues the frame with the id of its source. The pool's
+//! workers take frames from the queues and call the handler of each frame's source.
+//!
+//! With `DispatchMode::Fair`, the workers serve the sources in turn, taking up to the
+//! source's weight of frames on each turn, and a source is served by one worker at a time.
+//! A source whose handler is slow therefore holds at most one worker, and the other sources
+//! keep being served by the rest. With `DispatchMode::WorkStealing`, a free worker takes the
+//! oldest queued frame of any source. That makes the most of the workers when all the
+//! handlers keep up, but a slow source can occupy every worker, and the others then drop
+//! frames while their own handlers are idle.
+//!
+//! The queue depth, dispatch latency and worker time of each source are kept in
+//! `DispatchStats`. When the oldest frame of a source has waited for longer than
+//! `DispatchConfig::starvation_threshold`, the pool raises `DispatchEvent::Starved`, naming
+//! the source that has used the most worker time.
+//!
+//! A source given a `crate::cpu::CpuMeter` with `DispatchPool::set_cpu_meter` has its
+//! callback and handler time recorded there too, from the same clock reads as its worker
+//! time, so the utilization an operator sees and the worker time starvation is blamed on
+//! agree.
+
+use crate::config::ConfigError;
+use crate::cpu::{CpuMeter, CpuStage};
+use crate::device::input::{
+    CallbackResult, DecklinkDetectedVideoInputFormatFlags, DecklinkVideoInputFormatChangedEvents,
+    FrameArrival, InputFormatChange, InputHandler,
+};
+use crate::display_mode::DecklinkDisplayModeId;
+use crate::frame::DecklinkVideoFrame;
+use crate::latency::{percentiles, StageStats};
+use crate::threads::{self, ThreadHandle};
+use crate::time::DecklinkFrameTiming;
+use crate::util::internal_thread_started;
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// An input served by a `DispatchPool`.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SourceId(u32);
+
+impl SourceId {
+    /// The index of the source's stats in `DispatchStats::sources`.
+    pub fn index(&self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// How the workers of a `DispatchPool` choose the next frame.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DispatchMode {
+    /// Serve the sources in turn, one worker per source at a time. Each source's frames are
+    /// delivered in order.
+    #[default]
+    Fair,
+    /// Take the oldest frame of any source. Frames of one source may be delivered
+    /// concurrently, and so out of order.
+    WorkStealing,
+}
+
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DispatchConfig {
+    /// The number of worker threads.
+    pub workers: usize,
+    pub mode: DispatchMode,
+    /// The number of frames and events that can be queued for each source. Once full,
+    /// further frames of the source are dropped and counted in `SourceStats::dropped`.
+    pub queue_capacity: usize,
+    /// The number of recent frames the dispatch latency percentiles of each source are
+    /// taken over.
+    pub latency_window: usize,
+    /// How long the oldest frame of a source may wait before the source is reported as
+    /// starved.
+    pub starvation_threshold: Duration,
+}
+
+impl Default for DispatchConfig {
+    fn default() -> Self {
+        DispatchConfig {
+            workers: 4,
+            mode: DispatchMode::Fair,
+            queue_capacity: 8,
+            latency_window: 512,
+            starvation_threshold: Duration::from_millis(100),
+        }
+    }
+}
+
+impl DispatchConfig {
+    pub fn builder() -> DispatchConfigBuilder {
+        DispatchConfigBuilder {
+            config: DispatchConfig::default(),
+        }
+    }
+
+    /// Check that a pool with this config could deliver frames.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let error = |field, problem| Err(ConfigError::new("DispatchConfig", field, problem));
+        if self.workers == 0 {
+            return error("workers", "must be at least 1");
+        }
+        if self.queue_capacity == 0 {
+            return error(
+                "queue_capacity",
+                "must be at least 1, or every frame would be dropped",
+            );
+        }
+        if self.latency_window == 0 {
+            return error("latency_window", "must be at least 1 frame");
+        }
+        if self.starvation_threshold.is_zero() {
+            return error(
+                "starvation_threshold",
+                "must be longer than zero, or every frame would be reported as starved",
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Builds a `DispatchConfig`, starting from the default.
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub struct DispatchConfigBuilder {
+    config: DispatchConfig,
+}
+
+impl DispatchConfigBuilder {
+    pub fn workers(mut self, workers: usize) -> Self {
+        self.config.workers = workers;
+        self
+    }
+
+    pub fn mode(mut self, mode: DispatchMode) -> Self {
+        self.config.mode = mode;
+        self
+    }
+
+    pub fn queue_capacity(mut self, queue_capacity: usize) -> Self {
+        self.config.queue_capacity = queue_capacity;
+        self
+    }
+
+    pub fn latency_window(mut self, latency_window: usize) -> Self {
+        self.config.latency_window = latency_window;
+        self
+    }
+
+    pub fn starvation_threshold(mut self, starvation_threshold: Duration) -> Self {
+        self.config.starvation_threshold = starvation_threshold;
+        self
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        self.config.validate()
+    }
+
+    pub fn build(self) -> Result<DispatchConfig, ConfigError> {
+        self.validate()?;
+        Ok(self.config)
+    }
+}
+
+/// A captured frame with the context it arrived with.
+pub struct DispatchedFrame {
+    pub frame: DecklinkVideoFrame,
+    /// The timing of the frame, if the display mode timescale was known.
+    pub timing: Option<DecklinkFrameTiming>,
+    /// When the frame arrived in the driver callback.
+    pub arrived: Instant,
+}
+
+/// Receives the frames of one source, on the workers of a `DispatchPool`.
+///
+/// A handler that panics ends the worker it was called on, and the panic is reported by
+/// `crate::threads`.
+pub trait DispatchHandler: Send + Sync {
+    fn frame_arrived(&self, source: SourceId, frame: DispatchedFrame);
+
+    /// Called when the video input format of the source changes, after the frames that
+    /// arrived before it under `DispatchMode::Fair`.
+    fn video_input_format_changed(
+        &self,
+        _source: SourceId,
+        _events: DecklinkVideoInputFormatChangedEvents,
+        _new_display_mode: DecklinkDisplayModeId,
+        _detected_signal_flags: DecklinkDetectedVideoInputFormatFlags,
+    ) {
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct SourceStats {
+    pub source: SourceId,
+    pub label: String,
+    pub weight: u32,
+    /// The frames and events queued for the source now.
+    pub queue_depth: usize,
+    /// The most frames and events queued for the source at once.
+    pub peak_queue_depth: usize,
+    /// The frames and events delivered to the source's handler.
+    pub dispatched: u64,
+    /// Frames and events dropped because the source's queue was full.
+    pub dropped: u64,
+    /// The number of workers running the source's handler now.
+    pub in_flight: usize,
+    /// The total time workers have spent in the source's handler.
+    pub worker_time: Duration,
+    /// The time from a frame arriving to its handler being called, over the last
+    /// `DispatchConfig::latency_window` frames.
+    pub dispatch_latency: StageStats,
+    /// Whether the source is starved now.
+    pub starved: bool,
+    /// The number of times the source has been starved.
+    pub starved_count: u64,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct DispatchStats {
+    pub workers: usize,
+    pub mode: DispatchMode,
+    /// The number of workers running a handler now.
+    pub busy_workers: usize,
+    /// The stats of each source, indexed by `SourceId::index`.
+    pub sources: Vec<SourceStats>,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DispatchEvent {
+    /// The oldest frame of `source` has waited for `waited`. `busiest` is the source that
+    /// has used the most worker time, `busiest_worker_time` of it.
+    Starved {
+        source: SourceId,
+        label: String,
+        waited: Duration,
+        busiest: SourceId,
+        busiest_label: String,
+        busiest_worker_time: Duration,
+    },
+    /// The frames of `source` are being served within the threshold again.
+    StarvationEnded { source: SourceId, label: String },
+}
+
+enum WorkKind {
+    Frame(DispatchedFrame),
+    FormatChanged(
+        DecklinkVideoInputFormatChangedEvents,
+        DecklinkDisplayModeId,
+        DecklinkDetectedVideoInputFormatFlags,
+    ),
+    /// Work of the crate's own, run in place of the source's handler.
+    Task(Box<dyn FnOnce() + Send>),
+}
+
+struct WorkItem {
+    source: SourceId,
+    arrived: Instant,
+    kind: WorkKind,
+}
+
+struct Source {
+    label: String,
+    weight: u32,
+    handler: Arc<dyn DispatchHandler>,
+    queue: VecDeque<WorkItem>,
+    peak_queue_depth: usize,
+    dispatched: u64,
+    dropped: u64,
+    in_flight: usize,
+    /// When each of the source's running handler calls started.
+    running_since: Vec<Instant>,
+    worker_time: Duration,
+    latencies: VecDeque<Duration>,
+    starved: bool,
+    starved_count: u64,
+    cpu: Option<CpuMeter>,
+}
+
+impl Source {
+    /// The worker time used, including the handler calls still running.
+    fn worker_time_at(&self, now: Instant) -> Duration {
+        self.worker_time
+            + self
+                .running_since
+                .iter()
+                .map(|since| now.saturating_duration_since(*since))
+                .sum::<Duration>()
+    }
+}
+
+struct PoolState {
+    sources: Vec<Source>,
+    /// The source the next turn starts from, under `DispatchMode::Fair`.
+    cursor: usize,
+    /// The frames taken from the source at `cursor` on its current turn.
+    served_in_turn: u32,
+    busy_workers: usize,
+    events: Vec<DispatchEvent>,
+    closed: bool,
+}
+
+impl PoolState {
+    fn pick(&mut self, mode: DispatchMode) -> Option<(usize, WorkItem)> {
+        let count = self.sources.len();
+        let index = match mode {
+            DispatchMode::Fair => (0..count)
+                .map(|n| (self.cursor + n) % count)
+                .find(|i| self.sources[*i].in_flight == 0 && !self.sources[*i].queue.is_empty()),
+            DispatchMode::WorkStealing => (0..count)
+                .filter(|i| !self.sources[*i].queue.is_empty())
+                .min_by_key(|i| self.sources[*i].queue[0].arrived),
+        }?;
+
+        if mode == DispatchMode::Fair {
+            if index != self.cursor {
+                self.cursor = index;
+                self.served_in_turn = 0;
+            }
+            self.served_in_turn += 1;
+            if self.served_in_turn >= self.sources[index].weight.max(1) {
+                self.cursor = (index + 1) % count;
+                self.served_in_turn = 0;
+            }
+        }
+
+        let item = self.sources[index].queue.pop_front()?;
+        Some((index, item))
+    }
+
+    /// Raise and end starvation events, given the threshold.
+    fn check_starvation(&mut self, threshold: Duration, now: Instant) {
+        for index in 0..self.sources.len() {
+            let waited = self.sources[index]
+                .queue
+                .front()
+                .map_or(Duration::ZERO, |item| {
+                    now.saturating_duration_since(item.arrived)
+                });
+            let starved = waited > threshold;
+            if starved == self.sources[index].starved {
+                continue;
+            }
+
+            let source = &mut self.sources[index];
+            source.starved = starved;
+            if starved {
+                source.starved_count += 1;
+            }
+            let label = source.label.clone();
+            if !starved {
+                self.events.push(DispatchEvent::StarvationEnded {
+                    source: SourceId(index as u32),
+                    label,
+                });
+                continue;
+            }
+
+            let (busiest, busiest_worker_time) = self
+                .sources
+                .iter()
+                .enumerate()
+                .map(|(i, s)| (i, s.worker_time_at(now)))
+                .max_by_key(|(_, time)| *time)
+                .unwrap_or((index, Duration::ZERO));
+            self.events.push(DispatchEvent::Starved {
+                source: SourceId(index as u32),
+                label,
+                waited,
+                busiest: SourceId(busiest as u32),
+                busiest_label: self.sources[busiest].label.clone(),
+                busiest_worker_time,
+            });
+        }
+    }
+}
+
+struct PoolShared {
+    config: DispatchConfig,
+    state: Mutex<PoolState>,
+    work: Condvar,
+}
+
+impl PoolShared {
+    /// Queue `kind` for `source`, returning whether it was queued rather than dropped.
+    fn push(&self, source: SourceId, kind: WorkKind) -> bool {
+        let arrived = match &kind {
+            WorkKind::Frame(frame) => frame.arrived,
+            WorkKind::FormatChanged(..) | WorkKind::Task(_) => Instant::now(),
+        };
+        let is_frame = matches!(kind, WorkKind::Frame(_));
+        let mut state = self.state.lock().unwrap();
+        let (queued, cpu) = {
+            let source_state = &mut state.sources[source.index()];
+            let cpu = source_state.cpu.clone().filter(|_| is_frame);
+            let queued = if source_state.queue.len() >= self.config.queue_capacity.max(1) {
+                source_state.dropped += 1;
+                false
+            } else {
+                source_state.queue.push_back(WorkItem {
+                    source,
+                    arrived,
+                    kind,
+                });
+                source_state.peak_queue_depth =
+                    source_state.peak_queue_depth.max(source_state.queue.len());
+                true
+            };
+            (queued, cpu)
+        };
+        state.check_starvation(self.config.starvation_threshold, arrived);
+        drop(state);
+
+        if let Some(cpu) = cpu {
+            cpu.frame_arrived();
+            cpu.record(CpuStage::Callback, arrived.elapsed());
+        }
+
+        if queued {
+            self.work.notify_one();
+        }
+        queued
+    }
+
+    fn run_worker(&self) {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            let now = Instant::now();
+            state.check_starvation(self.config.starvation_threshold, now);
+            let (index, item) = match state.pick(self.config.mode) {
+                Some(work) => work,
+                None if state.closed && state.sources.iter().all(|s| s.queue.is_empty()) => {
+                    return;
+                }
+                None => {
+                    // Wake up in time to notice a source becoming starved
+                    state = self
+                        .work
+                        .wait_timeout(state, self.config.starvation_threshold)
+                        .unwrap()
+                        .0;
+                    continue;
+                }
+            };
+
+            let source = &mut state.sources[index];
+            source.in_flight += 1;
+            source.running_since.push(now);
+            if source.latencies.len() >= self.config.latency_window.max(1) {
+                source.latencies.pop_front();
+            }
+            source
+                .latencies
+                .push_back(now.saturating_duration_since(item.arrived));
+            let handler = source.handler.clone();
+            let cpu = source.cpu.clone();
+            state.busy_workers += 1;
+            drop(state);
+
+            match item.kind {
+                WorkKind::Frame(frame) => handler.frame_arrived(item.source, frame),
+                WorkKind::FormatChanged(events, mode, flags) => {
+                    handler.video_input_format_changed(item.source, events, mode, flags)
+                }
+                WorkKind::Task(task) => task(),
+            }
+            let finished = Instant::now();
+            if let Some(cpu) = cpu {
+                cpu.record(CpuStage::Consumer, finished.saturating_duration_since(now));
+            }
+
+            state = self.state.lock().unwrap();
+            state.busy_workers -= 1;
+            let source = &mut state.sources[index];
+            source.in_flight -= 1;
+            source.dispatched += 1;
+            if let Some(position) = source.running_since.iter().position(|s| *s == now) {
+                source.running_since.swap_remove(position);
+            }
+            source.worker_time += finished.saturating_duration_since(now);
+            // The source may be free for another worker now
+            self.work.notify_all();
+        }
+    }
+}
+
+/// A pool of worker threads delivering the frames of several inputs.
+///
+/// Add a source for each input with `add_source`, and install the callback returned by
+/// `callback` with `DecklinkInputDevice::set_handler`. When the pool is dropped, the
+/// frames still queued are delivered before its workers exit.
+pub struct DispatchPool {
+    shared: Arc<PoolShared>,
+    workers: Vec<ThreadHandle>,
+}
+
+impl DispatchPool {
+    pub fn new(config: DispatchConfig) -> DispatchPool {
+        let shared = Arc::new(PoolShared {
+            config,
+            state: Mutex::new(PoolState {
+                sources: Vec::new(),
+                cursor: 0,
+                served_in_turn: 0,
+                busy_workers: 0,
+                events: Vec::new(),
+                closed: false,
+            }),
+            work: Condvar::new(),
+        });
+
+        let workers = (0..config.workers.max(1))
+            .map(|_| {
+                let shared = shared.clone();
+                threads::spawn("dispatch", move || {
+                    internal_thread_started("dispatch");
+                    shared.run_worker();
+                })
+            })
+            .collect();
+
+        DispatchPool { shared, workers }
+    }
+
+    pub fn config(&self) -> DispatchConfig {
+        self.shared.config
+    }
+
+    /// Add a source, whose frames are delivered to `handler`. Under `DispatchMode::Fair`,
+    /// up to `weight` of its frames are delivered on each of its turns. `label` names the
+    /// source in stats and events, such as the display name of its device.
+    pub fn add_source(
+        &self,
+        label: impl Into<String>,
+        weight: u32,
+        handler: Arc<dyn DispatchHandler>,
+    ) -> SourceId {
+        let mut state = self.shared.state.lock().unwrap();
+        let id = SourceId(state.sources.len() as u32);
+        state.sources.push(Source {
+            label: label.into(),
+            weight: weight.max(1),
+            handler,
+            queue: VecDeque::new(),
+            peak_queue_depth: 0,
+            dispatched: 0,
+            dropped: 0,
+            in_flight: 0,
+            running_since: Vec::new(),
+            worker_time: Duration::ZERO,
+            latencies: VecDeque::new(),
+            starved: false,
+            starved_count: 0,
+            cpu: None,
+        });
+        id
+    }
+
+    /// Add a source whose work is queued with `submit` rather than arriving from an input,
+    /// such as the conversions of a `crate::dual::DualFormatSplitter`.
+    pub(crate) fn add_task_source(&self, label: impl Into<String>, weight: u32) -> SourceId {
+        self.add_source(label, weight, Arc::new(TaskSource))
+    }
+
+    /// Queues the work of a source added with `add_task_source`.
+    pub(crate) fn submitter(&self, source: SourceId) -> TaskSubmitter {
+        TaskSubmitter {
+            shared: self.shared.clone(),
+            source,
+        }
+    }
+
+    /// Record the callback and handler time of `source` in `meter`, which also counts its
+    /// frames, or stop with `None`. Give each source its own meter, as their frame intervals
+    /// are counted separately.
+    pub fn set_cpu_meter(&self, source: SourceId, meter: Option<CpuMeter>) {
+        if let Some(source) = self
+            .shared
+            .state
+            .lock()
+            .unwrap()
+            .sources
+            .get_mut(source.index())
+        {
+            source.cpu = meter;
+        }
+    }
+
+    /// Change the weight of `source`, from its next turn.
+    pub fn set_weight(&self, source: SourceId, weight: u32) {
+        if let Some(source) = self
+            .shared
+            .state
+            .lock()
+            .unwrap()
+            .sources
+            .get_mut(source.index())
+        {
+            source.weight = weight.max(1);
+        }
+    }
+
+    /// The input callback to install on the input of `source`.
+    pub fn callback(&self, source: SourceId) -> Arc<dyn InputHandler> {
+        Arc::new(DispatchInputCallback {
+            shared: self.shared.clone(),
+            source,
+        })
+    }
+
+    pub fn stats(&self) -> DispatchStats {
+        let state = self.shared.state.lock().unwrap();
+        let now = Instant::now();
+        DispatchStats {
+            workers: self.workers.len(),
+            mode: self.shared.config.mode,
+            busy_workers: state.busy_workers,
+            sources: state
+                .sources
+                .iter()
+                .enumerate()
+                .map(|(index, source)| SourceStats {
+                    source: SourceId(index as u32),
+                    label: source.label.clone(),
+                    weight: source.weight,
+                    queue_depth: source.queue.len(),
+                    peak_queue_depth: source.peak_queue_depth,
+                    dispatched: source.dispatched,
+                    dropped: source.dropped,
+                    in_flight: source.in_flight,
+                    worker_time: source.worker_time_at(now),
+                    dispatch_latency: percentiles(source.latencies.iter().copied().collect()),
+                    starved: source.starved,
+                    starved_count: source.starved_count,
+                })
+                .collect(),
+        }
+    }
+
+    /// The events raised since the last call, oldest first.
+    pub fn take_events(&self) -> Vec<DispatchEvent> {
+        std::mem::take(&mut self.shared.state.lock().unwrap().events)
+    }
+}
+
+impl Drop for DispatchPool {
+    fn drop(&mut self) {
+        self.shared.state.lock().unwrap().closed = true;
+        self.shared.work.notify_all();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// Queues work for a source added with `DispatchPool::add_task_source`.
+pub(crate) struct TaskSubmitter {
+    shared: Arc<PoolShared>,
+    source: SourceId,
+}
+
+impl TaskSubmitter {
+    /// Queue `task` to run on a worker as the next work of the source, returning whether it
+    /// was queued. It is dropped unrun when the source's queue is full, or the pool has been
+    /// dropped.
+    pub(crate) fn submit(&self, task: Box<dyn FnOnce() + Send>) -> bool {
+        if self.shared.state.lock().unwrap().closed {
+            return false;
+        }
+        self.shared.push(self.source, WorkKind::Task(task))
+    }
+}
+
+/// The handler of a source added with `add_task_source`, whose work brings its own.
+struct TaskSource;
+
+impl DispatchHandler for TaskSource {
+    fn frame_arrived(&self, _source: SourceId, _frame: DispatchedFrame) {}
+}
+
+struct DispatchInputCallback {
+    shared: Arc<PoolShared>,
+    source: SourceId,
+}
+
+impl InputHandler for DispatchInputCallback {
+    fn format_changed(&self, change: &InputFormatChange) {
+        self.shared.push(
+            self.source,
+            WorkKind::FormatChanged(
+                change.events,
+                change.display_mode,
+                change.detected_signal_flags,
+            ),
+        );
+    }
+
+    fn frame_arrived(&self, arrival: &FrameArrival<'_>) -> CallbackResult {
+        if let Some(frame) = arrival.retain_video_frame() {
+            self.shared.push(
+                self.source,
+                WorkKind::Frame(DispatchedFrame {
+                    frame,
+                    timing: arrival.timing(),
+                    arrived: Instant::now(),
+                }),
+            );
+        }
+        CallbackResult::Ok
+    }
+}
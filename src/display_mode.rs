@@ -1,8 +1,13 @@
-use crate::{sdk, util::convert_and_release_c_string, SdkError};
+use crate::device::status::DecklinkVideoStatusFlags;
+use crate::ptr::{DisplayModeIteratorPtr, DisplayModePtr};
+use crate::reference::DecklinkReferenceExt;
+use crate::time::DecklinkTime;
+use crate::{sdk, SdkError};
 use num_traits::FromPrimitive;
-use std::ptr::{null, null_mut};
+use std::sync::OnceLock;
 
-#[derive(FromPrimitive, PartialEq, Debug, Copy, Clone)]
+#[derive(EnumIter, FromPrimitive, PartialEq, Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum DecklinkDisplayModeId {
     NTSC = sdk::_DecklinkDisplayMode_decklinkModeNTSC as isize,
     NTSC2398 = sdk::_DecklinkDisplayMode_decklinkModeNTSC2398 as isize,
@@ -20,6 +25,13 @@ pub enum DecklinkDisplayModeId {
     HD1080p50 = sdk::_DecklinkDisplayMode_decklinkModeHD1080p50 as isize,
     HD1080p5994 = sdk::_DecklinkDisplayMode_decklinkModeHD1080p5994 as isize,
     HD1080p6000 = sdk::_DecklinkDisplayMode_decklinkModeHD1080p6000 as isize,
+    HD1080p4795 = sdk::_DecklinkDisplayMode_decklinkModeHD1080p4795 as isize,
+    HD1080p48 = sdk::_DecklinkDisplayMode_decklinkModeHD1080p48 as isize,
+    HD1080p9590 = sdk::_DecklinkDisplayMode_decklinkModeHD1080p9590 as isize,
+    HD1080p96 = sdk::_DecklinkDisplayMode_decklinkModeHD1080p96 as isize,
+    HD1080p100 = sdk::_DecklinkDisplayMode_decklinkModeHD1080p100 as isize,
+    HD1080p11988 = sdk::_DecklinkDisplayMode_decklinkModeHD1080p11988 as isize,
+    HD1080p120 = sdk::_DecklinkDisplayMode_decklinkModeHD1080p120 as isize,
     HD720p50 = sdk::_DecklinkDisplayMode_decklinkModeHD720p50 as isize,
     HD720p5994 = sdk::_DecklinkDisplayMode_decklinkModeHD720p5994 as isize,
     HD720p60 = sdk::_DecklinkDisplayMode_decklinkModeHD720p60 as isize,
@@ -29,21 +41,231 @@ pub enum DecklinkDisplayModeId {
     HD2kDCI2398 = sdk::_DecklinkDisplayMode_decklinkMode2kDCI2398 as isize,
     HD2kDCI24 = sdk::_DecklinkDisplayMode_decklinkMode2kDCI24 as isize,
     HD2kDCI25 = sdk::_DecklinkDisplayMode_decklinkMode2kDCI25 as isize,
+    HD2kDCI2997 = sdk::_DecklinkDisplayMode_decklinkMode2kDCI2997 as isize,
+    HD2kDCI30 = sdk::_DecklinkDisplayMode_decklinkMode2kDCI30 as isize,
+    HD2kDCI4795 = sdk::_DecklinkDisplayMode_decklinkMode2kDCI4795 as isize,
+    HD2kDCI48 = sdk::_DecklinkDisplayMode_decklinkMode2kDCI48 as isize,
+    HD2kDCI50 = sdk::_DecklinkDisplayMode_decklinkMode2kDCI50 as isize,
+    HD2kDCI5994 = sdk::_DecklinkDisplayMode_decklinkMode2kDCI5994 as isize,
+    HD2kDCI60 = sdk::_DecklinkDisplayMode_decklinkMode2kDCI60 as isize,
+    HD2kDCI9590 = sdk::_DecklinkDisplayMode_decklinkMode2kDCI9590 as isize,
+    HD2kDCI96 = sdk::_DecklinkDisplayMode_decklinkMode2kDCI96 as isize,
+    HD2kDCI100 = sdk::_DecklinkDisplayMode_decklinkMode2kDCI100 as isize,
+    HD2kDCI11988 = sdk::_DecklinkDisplayMode_decklinkMode2kDCI11988 as isize,
+    HD2kDCI120 = sdk::_DecklinkDisplayMode_decklinkMode2kDCI120 as isize,
     UHD4K2160p2398 = sdk::_DecklinkDisplayMode_decklinkMode4K2160p2398 as isize,
     UHD4K2160p24 = sdk::_DecklinkDisplayMode_decklinkMode4K2160p24 as isize,
     UHD4K2160p25 = sdk::_DecklinkDisplayMode_decklinkMode4K2160p25 as isize,
     UHD4K2160p2997 = sdk::_DecklinkDisplayMode_decklinkMode4K2160p2997 as isize,
     UHD4K2160p30 = sdk::_DecklinkDisplayMode_decklinkMode4K2160p30 as isize,
+    UHD4K2160p4795 = sdk::_DecklinkDisplayMode_decklinkMode4K2160p4795 as isize,
+    UHD4K2160p48 = sdk::_DecklinkDisplayMode_decklinkMode4K2160p48 as isize,
     UHD4K2160p50 = sdk::_DecklinkDisplayMode_decklinkMode4K2160p50 as isize,
     UHD4K2160p5994 = sdk::_DecklinkDisplayMode_decklinkMode4K2160p5994 as isize,
     UHD4K2160p60 = sdk::_DecklinkDisplayMode_decklinkMode4K2160p60 as isize,
+    UHD4K2160p9590 = sdk::_DecklinkDisplayMode_decklinkMode4K2160p9590 as isize,
+    UHD4K2160p96 = sdk::_DecklinkDisplayMode_decklinkMode4K2160p96 as isize,
+    UHD4K2160p100 = sdk::_DecklinkDisplayMode_decklinkMode4K2160p100 as isize,
+    UHD4K2160p11988 = sdk::_DecklinkDisplayMode_decklinkMode4K2160p11988 as isize,
+    UHD4K2160p120 = sdk::_DecklinkDisplayMode_decklinkMode4K2160p120 as isize,
     UHD4KDCI2398 = sdk::_DecklinkDisplayMode_decklinkMode4kDCI2398 as isize,
     UHD4KDCI24 = sdk::_DecklinkDisplayMode_decklinkMode4kDCI24 as isize,
     UHD4KDCI25 = sdk::_DecklinkDisplayMode_decklinkMode4kDCI25 as isize,
+    UHD4KDCI2997 = sdk::_DecklinkDisplayMode_decklinkMode4kDCI2997 as isize,
+    UHD4KDCI30 = sdk::_DecklinkDisplayMode_decklinkMode4kDCI30 as isize,
+    UHD4KDCI4795 = sdk::_DecklinkDisplayMode_decklinkMode4kDCI4795 as isize,
+    UHD4KDCI48 = sdk::_DecklinkDisplayMode_decklinkMode4kDCI48 as isize,
+    UHD4KDCI50 = sdk::_DecklinkDisplayMode_decklinkMode4kDCI50 as isize,
+    UHD4KDCI5994 = sdk::_DecklinkDisplayMode_decklinkMode4kDCI5994 as isize,
+    UHD4KDCI60 = sdk::_DecklinkDisplayMode_decklinkMode4kDCI60 as isize,
+    UHD4KDCI9590 = sdk::_DecklinkDisplayMode_decklinkMode4kDCI9590 as isize,
+    UHD4KDCI96 = sdk::_DecklinkDisplayMode_decklinkMode4kDCI96 as isize,
+    UHD4KDCI100 = sdk::_DecklinkDisplayMode_decklinkMode4kDCI100 as isize,
+    UHD4KDCI11988 = sdk::_DecklinkDisplayMode_decklinkMode4kDCI11988 as isize,
+    UHD4KDCI120 = sdk::_DecklinkDisplayMode_decklinkMode4kDCI120 as isize,
+    UHD8K4320p2398 = sdk::_DecklinkDisplayMode_decklinkMode8K4320p2398 as isize,
+    UHD8K4320p24 = sdk::_DecklinkDisplayMode_decklinkMode8K4320p24 as isize,
+    UHD8K4320p25 = sdk::_DecklinkDisplayMode_decklinkMode8K4320p25 as isize,
+    UHD8K4320p2997 = sdk::_DecklinkDisplayMode_decklinkMode8K4320p2997 as isize,
+    UHD8K4320p30 = sdk::_DecklinkDisplayMode_decklinkMode8K4320p30 as isize,
+    UHD8K4320p4795 = sdk::_DecklinkDisplayMode_decklinkMode8K4320p4795 as isize,
+    UHD8K4320p48 = sdk::_DecklinkDisplayMode_decklinkMode8K4320p48 as isize,
+    UHD8K4320p50 = sdk::_DecklinkDisplayMode_decklinkMode8K4320p50 as isize,
+    UHD8K4320p5994 = sdk::_DecklinkDisplayMode_decklinkMode8K4320p5994 as isize,
+    UHD8K4320p60 = sdk::_DecklinkDisplayMode_decklinkMode8K4320p60 as isize,
+    UHD8KDCI2398 = sdk::_DecklinkDisplayMode_decklinkMode8kDCI2398 as isize,
+    UHD8KDCI24 = sdk::_DecklinkDisplayMode_decklinkMode8kDCI24 as isize,
+    UHD8KDCI25 = sdk::_DecklinkDisplayMode_decklinkMode8kDCI25 as isize,
+    UHD8KDCI2997 = sdk::_DecklinkDisplayMode_decklinkMode8kDCI2997 as isize,
+    UHD8KDCI30 = sdk::_DecklinkDisplayMode_decklinkMode8kDCI30 as isize,
+    UHD8KDCI4795 = sdk::_DecklinkDisplayMode_decklinkMode8kDCI4795 as isize,
+    UHD8KDCI48 = sdk::_DecklinkDisplayMode_decklinkMode8kDCI48 as isize,
+    UHD8KDCI50 = sdk::_DecklinkDisplayMode_decklinkMode8kDCI50 as isize,
+    UHD8KDCI5994 = sdk::_DecklinkDisplayMode_decklinkMode8kDCI5994 as isize,
+    UHD8KDCI60 = sdk::_DecklinkDisplayMode_decklinkMode8kDCI60 as isize,
+    // Computer display modes, carried over HDMI
+    PC640x480p60 = sdk::_DecklinkDisplayMode_decklinkMode640x480p60 as isize,
+    PC800x600p60 = sdk::_DecklinkDisplayMode_decklinkMode800x600p60 as isize,
+    PC1440x900p50 = sdk::_DecklinkDisplayMode_decklinkMode1440x900p50 as isize,
+    PC1440x900p60 = sdk::_DecklinkDisplayMode_decklinkMode1440x900p60 as isize,
+    PC1440x1080p50 = sdk::_DecklinkDisplayMode_decklinkMode1440x1080p50 as isize,
+    PC1440x1080p60 = sdk::_DecklinkDisplayMode_decklinkMode1440x1080p60 as isize,
+    PC1600x1200p50 = sdk::_DecklinkDisplayMode_decklinkMode1600x1200p50 as isize,
+    PC1600x1200p60 = sdk::_DecklinkDisplayMode_decklinkMode1600x1200p60 as isize,
+    PC1920x1200p50 = sdk::_DecklinkDisplayMode_decklinkMode1920x1200p50 as isize,
+    PC1920x1200p60 = sdk::_DecklinkDisplayMode_decklinkMode1920x1200p60 as isize,
+    PC1920x1440p50 = sdk::_DecklinkDisplayMode_decklinkMode1920x1440p50 as isize,
+    PC1920x1440p60 = sdk::_DecklinkDisplayMode_decklinkMode1920x1440p60 as isize,
+    PC2560x1440p50 = sdk::_DecklinkDisplayMode_decklinkMode2560x1440p50 as isize,
+    PC2560x1440p60 = sdk::_DecklinkDisplayMode_decklinkMode2560x1440p60 as isize,
+    PC2560x1600p50 = sdk::_DecklinkDisplayMode_decklinkMode2560x1600p50 as isize,
+    PC2560x1600p60 = sdk::_DecklinkDisplayMode_decklinkMode2560x1600p60 as isize,
     Unknown = sdk::_DecklinkDisplayMode_decklinkModeUnknown as isize,
 }
 
+/// Interlaced modes and the progressive modes carried as PsF in the same transport.
+const PSF_PAIRS: [(DecklinkDisplayModeId, DecklinkDisplayModeId); 3] = [
+    (
+        DecklinkDisplayModeId::HD1080i50,
+        DecklinkDisplayModeId::HD1080p25,
+    ),
+    (
+        DecklinkDisplayModeId::HD1080i5994,
+        DecklinkDisplayModeId::HD1080p2997,
+    ),
+    (
+        DecklinkDisplayModeId::HD1080i6000,
+        DecklinkDisplayModeId::HD1080p30,
+    ),
+];
+
+/// A ratio of two sizes, such as the width of a pixel to its height, or the width of a
+/// picture to its height.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Ratio {
+    pub num: u32,
+    pub den: u32,
+}
+
+impl Ratio {
+    pub const SQUARE: Ratio = Ratio { num: 1, den: 1 };
+
+    pub const fn new(num: u32, den: u32) -> Ratio {
+        Ratio { num, den }
+    }
+
+    /// The ratio in its lowest terms, such as 4:3 for 1440:1080.
+    pub fn reduced(&self) -> Ratio {
+        let (mut a, mut b) = (self.num, self.den);
+        while b != 0 {
+            (a, b) = (b, a % b);
+        }
+        if a <= 1 {
+            return *self;
+        }
+        Ratio::new(self.num / a, self.den / a)
+    }
+
+    /// The ratio as a number. Zero in either term is taken as one.
+    pub fn to_f64(&self) -> f64 {
+        self.num.max(1) as f64 / self.den.max(1) as f64
+    }
+
+    /// The pixel aspect ratio of a mode `height` rows tall, as defined for SDI standard
+    /// definition by ITU-R BT.601. `widescreen` is for anamorphic 16:9 pictures, which the
+    /// mode itself does not show. Other heights have square pixels. This is for frames whose
+    /// mode is not known, and `DecklinkDisplayModeId::pixel_aspect_ratio` for those whose is.
+    pub fn for_height(height: usize, widescreen: bool) -> Ratio {
+        let (num, den) = match (height, widescreen) {
+            (480 | 486, false) => (10, 11),
+            (480 | 486, true) => (40, 33),
+            (576, false) => (12, 11),
+            (576, true) => (16, 11),
+            _ => (1, 1),
+        };
+        Ratio { num, den }
+    }
+
+    /// The shape of a whole raster of `width` by `height` pixels of `pixel_aspect`, in its
+    /// lowest terms. Standard definition rasters are wider than their display aspect ratio,
+    /// as the picture only fills 704 of their 720 samples, and NTSC rasters of 486 lines are
+    /// taller than those of 480, which only hold the picture: 720x486 is 400:297 and 720x480
+    /// is 15:11, though both show 4:3.
+    pub fn of_raster(width: usize, height: usize, pixel_aspect: Ratio) -> Ratio {
+        let num = width as u64 * pixel_aspect.num as u64;
+        let den = height as u64 * pixel_aspect.den as u64;
+        let (mut a, mut b) = (num, den);
+        while b != 0 {
+            (a, b) = (b, a % b);
+        }
+        let a = a.max(1);
+        Ratio::new((num / a) as u32, (den / a) as u32)
+    }
+}
+
+impl Default for Ratio {
+    fn default() -> Self {
+        Ratio::SQUARE
+    }
+}
+
+impl std::fmt::Display for Ratio {
+    /// As `num:den`, the form ffmpeg takes for `-aspect` and `setsar`.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.num, self.den)
+    }
+}
+
+impl DecklinkDisplayModeId {
+    /// The pixel aspect ratio of the mode. Standard definition modes have the pixels of ITU-R
+    /// BT.601, which are wider for anamorphic 16:9 pictures, as `widescreen` says: the signal
+    /// is the same either way, so the mode cannot tell. NTSC is the same whether the driver
+    /// gives it as 486 lines or 480. Every other mode has square pixels. `None` for
+    /// `Unknown`.
+    pub fn pixel_aspect_ratio(&self, widescreen: bool) -> Option<Ratio> {
+        self.metadata().aspect.map(|a| a.pixel(widescreen))
+    }
+
+    /// The display aspect ratio of the picture the mode carries. Standard definition
+    /// pictures are 4:3, or 16:9 when `widescreen`, over the 704 samples of their active
+    /// line, so the whole raster is wider; see `Ratio::of_raster`. `None` for `Unknown`.
+    pub fn display_aspect_ratio(&self, widescreen: bool) -> Option<Ratio> {
+        self.metadata().aspect.map(|a| a.display(widescreen))
+    }
+
+    /// The progressive mode with the same frame rate and transport as this interlaced
+    /// mode, such as 1080p25 for 1080i50.
+    pub fn progressive_equivalent(&self) -> Option<DecklinkDisplayModeId> {
+        PSF_PAIRS
+            .iter()
+            .find(|(interlaced, _)| interlaced == self)
+            .map(|(_, progressive)| *progressive)
+    }
+
+    /// The interlaced mode with the same frame rate and transport as this progressive
+    /// mode, such as 1080i50 for 1080p25.
+    pub fn interlaced_equivalent(&self) -> Option<DecklinkDisplayModeId> {
+        PSF_PAIRS
+            .iter()
+            .find(|(_, progressive)| progressive == self)
+            .map(|(interlaced, _)| *interlaced)
+    }
+
+    /// The mode to capture a detected signal with. A PsF signal can be detected as the
+    /// interlaced mode sharing its transport, in which case the progressive mode is
+    /// suggested instead, so frames are not treated as two fields.
+    pub fn suggested_for(&self, detected_flags: DecklinkVideoStatusFlags) -> DecklinkDisplayModeId {
+        if detected_flags.contains(DecklinkVideoStatusFlags::PSF) {
+            self.progressive_equivalent().unwrap_or(*self)
+        } else {
+            *self
+        }
+    }
+}
+
 #[derive(FromPrimitive, PartialEq, Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum DecklinkFieldDominance {
     Unknown = sdk::_DecklinkFieldDominance_decklinkUnknownFieldDominance as isize,
     LowerFieldFirst = sdk::_DecklinkFieldDominance_decklinkLowerFieldFirst as isize,
@@ -53,6 +275,32 @@ pub enum DecklinkFieldDominance {
         sdk::_DecklinkFieldDominance_decklinkProgressiveSegmentedFrame as isize,
 }
 
+impl DecklinkFieldDominance {
+    /// Whether the picture content is progressive. PsF frames are progressive content,
+    /// even though they are transported as two segments.
+    pub fn is_progressive_content(&self) -> Option<bool> {
+        match self {
+            DecklinkFieldDominance::ProgressiveFrame
+            | DecklinkFieldDominance::ProgressiveSegmentedFrame => Some(true),
+            DecklinkFieldDominance::LowerFieldFirst | DecklinkFieldDominance::UpperFieldFirst => {
+                Some(false)
+            }
+            DecklinkFieldDominance::Unknown => None,
+        }
+    }
+
+    /// Whether frames are transported as two segments or fields.
+    pub fn is_segmented_transport(&self) -> Option<bool> {
+        match self {
+            DecklinkFieldDominance::ProgressiveSegmentedFrame
+            | DecklinkFieldDominance::LowerFieldFirst
+            | DecklinkFieldDominance::UpperFieldFirst => Some(true),
+            DecklinkFieldDominance::ProgressiveFrame => Some(false),
+            DecklinkFieldDominance::Unknown => None,
+        }
+    }
+}
+
 bitflags! {
     #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
     pub struct DecklinkDisplayModeFlag: u32 {
@@ -62,93 +310,125 @@ bitflags! {
     }
 }
 
+/// A display mode supported by a device.
+///
+/// Display modes can be sent between threads and shared, so a mode can be chosen on one
+/// thread and used on another. Cloning a mode takes another reference to it.
+#[derive(Clone)]
 pub struct DecklinkDisplayMode {
-    mode: *mut sdk::cdecklink_display_mode_t,
+    mode: DisplayModePtr,
+    // A display mode is immutable, so its name is read once and never invalidated.
+    name: OnceLock<Option<String>>,
 }
 
-impl Drop for DecklinkDisplayMode {
-    fn drop(&mut self) {
-        if !self.mode.is_null() {
-            unsafe { sdk::cdecklink_display_mode_release(self.mode) };
-            self.mode = null_mut();
+// Safety: A display mode is immutable once created, so its getters can be called from any
+// thread. Its reference count is atomic, so it can be released from any thread.
+unsafe impl Send for DecklinkDisplayMode {}
+unsafe impl Sync for DecklinkDisplayMode {}
+
+impl DecklinkDisplayMode {
+    pub(crate) fn from_ptr(mode: DisplayModePtr) -> Self {
+        DecklinkDisplayMode {
+            mode,
+            name: OnceLock::new(),
         }
     }
-}
 
-impl DecklinkDisplayMode {
+    /// The name of the mode. It is read from the driver the first time, and borrowed from
+    /// the cached copy after that.
+    pub fn name_str(&self) -> Option<&str> {
+        self.name.get_or_init(|| self.mode.name()).as_deref()
+    }
     pub fn name(&self) -> Option<String> {
-        let mut s = null();
-        let result = unsafe { sdk::cdecklink_display_mode_get_name(self.mode, &mut s) };
-        if SdkError::is_ok(result) {
-            Some(unsafe { convert_and_release_c_string(s) })
-        } else {
-            None
-        }
+        self.name_str().map(|s| s.to_string())
     }
     pub fn mode(&self) -> DecklinkDisplayModeId {
-        DecklinkDisplayModeId::from_u32(unsafe {
-            sdk::cdecklink_display_mode_get_display_mode(self.mode)
-        })
-        .unwrap_or(DecklinkDisplayModeId::Unknown)
+        DecklinkDisplayModeId::from_u32(self.mode.display_mode())
+            .unwrap_or(DecklinkDisplayModeId::Unknown)
     }
     pub fn width(&self) -> usize {
-        let width = unsafe { sdk::cdecklink_display_mode_get_width(self.mode) };
-        width as usize
+        self.mode.width()
     }
     pub fn height(&self) -> usize {
-        let height = unsafe { sdk::cdecklink_display_mode_get_height(self.mode) };
-        height as usize
+        self.mode.height()
     }
     pub fn framerate(&self) -> Option<(i64, i64)> {
-        unsafe {
-            let mut duration = 0;
-            let mut scale = 0;
-            if SdkError::is_ok(sdk::cdecklink_display_mode_get_frame_rate(
-                self.mode,
-                &mut duration,
-                &mut scale,
-            )) {
-                Some((duration, scale))
-            } else {
-                None
-            }
-        }
+        self.mode.frame_rate()
+    }
+    /// Get the duration of one frame, in the timescale of this mode.
+    pub fn frame_duration(&self) -> Option<DecklinkTime> {
+        self.framerate()
+            .map(|(duration, scale)| DecklinkTime::new(duration, scale))
     }
     pub fn field_dominance(&self) -> DecklinkFieldDominance {
-        DecklinkFieldDominance::from_u32(unsafe {
-            sdk::cdecklink_display_mode_get_field_dominance(self.mode)
-        })
-        .unwrap_or(DecklinkFieldDominance::Unknown)
+        DecklinkFieldDominance::from_u32(self.mode.field_dominance())
+            .unwrap_or(DecklinkFieldDominance::Unknown)
     }
     pub fn flags(&self) -> DecklinkDisplayModeFlag {
-        DecklinkDisplayModeFlag::from_bits_truncate(unsafe {
-            sdk::cdecklink_display_mode_get_flags(self.mode)
-        })
+        DecklinkDisplayModeFlag::from_bits_truncate(self.mode.flags())
+    }
+    /// Whether this mode carries progressive frames as PsF.
+    pub fn is_psf(&self) -> bool {
+        self.field_dominance() == DecklinkFieldDominance::ProgressiveSegmentedFrame
+    }
+    /// The pixel aspect ratio of the mode, as `DecklinkDisplayModeId::pixel_aspect_ratio`
+    /// gives it, or by the height of a mode the crate does not know. `widescreen` is for
+    /// anamorphic 16:9 standard definition.
+    pub fn pixel_aspect_ratio(&self, widescreen: bool) -> Ratio {
+        self.mode()
+            .pixel_aspect_ratio(widescreen)
+            .unwrap_or_else(|| Ratio::for_height(self.height(), widescreen))
+    }
+    /// The display aspect ratio of the picture, as `DecklinkDisplayModeId::display_aspect_ratio`
+    /// gives it, or the shape of the raster of a mode the crate does not know.
+    pub fn display_aspect_ratio(&self, widescreen: bool) -> Ratio {
+        self.mode()
+            .display_aspect_ratio(widescreen)
+            .unwrap_or_else(|| {
+                Ratio::of_raster(
+                    self.width(),
+                    self.height(),
+                    self.pixel_aspect_ratio(widescreen),
+                )
+            })
     }
 }
 
-pub(crate) unsafe fn iterate_display_modes(
-    it: *mut sdk::cdecklink_display_mode_iterator_t,
-) -> Result<Vec<DecklinkDisplayMode>, SdkError> {
-    let mut res = Vec::new();
+/// How PsF modes are treated when choosing from a list of display modes.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash, Default)]
+pub enum PsfFilter {
+    /// Keep all modes in their original order.
+    #[default]
+    Include,
+    /// Remove PsF modes.
+    Exclude,
+    /// Keep all modes, with PsF modes first.
+    Prefer,
+}
 
-    let mut mode = null_mut();
-    loop {
-        let ok2 = sdk::cdecklink_display_mode_iterator_next(it, &mut mode);
-        if SdkError::is_ok(ok2) {
-            res.push(DecklinkDisplayMode { mode })
-        } else if SdkError::is_false(ok2) {
-            break;
-        } else {
-            return Err(SdkError::from(ok2));
+/// Filter or reorder `modes` according to `filter`.
+pub fn filter_psf_modes(
+    modes: &[DecklinkDisplayMode],
+    filter: PsfFilter,
+) -> Vec<&DecklinkDisplayMode> {
+    match filter {
+        PsfFilter::Include => modes.iter().collect(),
+        PsfFilter::Exclude => modes.iter().filter(|m| !m.is_psf()).collect(),
+        PsfFilter::Prefer => {
+            let (mut psf, other): (Vec<_>, Vec<_>) = modes.iter().partition(|m| m.is_psf());
+            psf.extend(other);
+            psf
         }
     }
+}
 
+/// Collect the modes of `it`.
+pub(crate) fn collect_display_modes(
+    mut it: DisplayModeIteratorPtr,
+) -> Result<Vec<DecklinkDisplayMode>, SdkError> {
+    let mut res = Vec::new();
+    while let Some(mode) = it.next_mode()? {
+        res.push(DecklinkDisplayMode::from_ptr(mode));
+    }
     Ok(res)
 }
-
-// pub(crate) unsafe fn wrap_display_mode(
-//     ptr: *mut sdk::cdecklink_display_mode_t,
-// ) -> DecklinkDisplayMode {
-//     DecklinkDisplayMode { mode: ptr }
-// }
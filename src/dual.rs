@@ -0,0 +1,942 @@
+//! Delivering the frames of one input in two pixel formats at once.
+//!
+//! The hardware captures in one pixel format at a time. A `DualFormatSplitter` is installed
+//! as the callback of an input enabled in `DualFormatConfig::primary`, and gives its
+//! consumers each frame twice: once as captured, the primary stream, and once converted into
+//! `DualFormatConfig::secondary`, the secondary stream. Consumers subscribe to one of the
+//! streams with `DualFormatSplitter::subscribe`, and each frame is converted once however
+//! many consumers the secondary stream has, and not at all while it has none.
+//!
+//! How the secondary stream is produced is negotiated from the format frames arrive in, on
+//! the first frame and on the first frame after each format change, and reported as a
+//! `ConversionPath`:
+//!
+//! - `Alias` when the secondary format is the format captured. The secondary stream is the
+//!   primary stream, the same frames delivered again, at no cost.
+//! - `Sdk` when the drivers provide a converter, `IDeckLinkVideoConversion`. Should it fail
+//!   to convert a frame, the path falls back to `Software` from that frame on.
+//! - `Software` with `crate::convert::convert_frame`, for the pairs of formats it accepts.
+//! - `Unavailable` when neither converts the format captured. The secondary stream is given
+//!   no frames until the format changes again.
+//!
+//! Primary frames are given to consumers on the callback thread, and are never dropped by
+//! the splitter. Conversions run on a source of their own in a `crate::dispatch::DispatchPool`,
+//! so their time is accounted apart from that of the pool's inputs. Under
+//! `DispatchMode::Fair` they run one at a time, in order.
+//!
+//! # Degradation
+//!
+//! When conversions fall behind, the secondary stream is degraded, never the primary. At
+//! most `DualFormatConfig::max_pending` conversions are queued or running at once, and the
+//! secondary of a frame that arrives while that many are, or that the pool's queue for the
+//! conversions cannot take, is shed: the frame is still given to the primary stream, and
+//! counted in the secondary's `DualStreamStats::shed`. The frames already queued are kept,
+//! so the secondary stream stays in order, with gaps. `DualFormatEvent::SheddingStarted` is
+//! raised on the first frame shed, and `DualFormatEvent::SheddingEnded` once a conversion
+//! is queued again. Each frame's `FrameMeta::sequence` is the same in both streams, so a
+//! consumer can tell which frames were shed.
+
+use crate::config::ConfigError;
+use crate::convert::{can_convert, convert_frame, SdkConversion};
+use crate::cpu::{CpuMeter, CpuStage};
+use crate::device::input::{CallbackResult, FrameArrival, InputFormatChange, InputHandler};
+use crate::dispatch::{DispatchPool, TaskSubmitter};
+use crate::display_mode::{DecklinkDisplayModeId, Ratio};
+use crate::frame::{
+    DecklinkAlignedBytes, DecklinkFrameBase, DecklinkFrameFlags, DecklinkPixelFormat,
+    DecklinkVideoFrame, DecklinkVideoMutableFrame,
+};
+use crate::latency::{percentiles, StageStats};
+use crate::time::DecklinkFrameTiming;
+use crate::SdkError;
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// The number of recent conversions the conversion time percentiles are taken over.
+const CONVERSION_WINDOW: usize = 512;
+
+/// One of the two streams of a `DualFormatSplitter`.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DualStream {
+    /// The frames as captured.
+    Primary,
+    /// The frames converted into the secondary format.
+    Secondary,
+}
+
+impl fmt::Display for DualStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            DualStream::Primary => "primary",
+            DualStream::Secondary => "secondary",
+        })
+    }
+}
+
+/// How the secondary stream is produced from the frames captured. See the module
+/// documentation.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ConversionPath {
+    Alias,
+    Sdk,
+    Software,
+    Unavailable,
+}
+
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+pub struct DualFormatConfig {
+    /// The format the input is enabled in.
+    pub primary: DecklinkPixelFormat,
+    /// The format of the secondary stream.
+    pub secondary: DecklinkPixelFormat,
+    /// The most conversions queued or running at once, before the secondary of further
+    /// frames is shed.
+    pub max_pending: usize,
+    /// Use the drivers' converter when there is one, rather than only the crate's.
+    pub sdk_conversion: bool,
+    /// Standard definition frames hold anamorphic 16:9 pictures, which their mode does not
+    /// show, rather than 4:3 ones. See `FrameMeta::pixel_aspect`.
+    pub widescreen: bool,
+}
+
+impl Default for DualFormatConfig {
+    fn default() -> Self {
+        DualFormatConfig {
+            primary: DecklinkPixelFormat::Format10BitYUV,
+            secondary: DecklinkPixelFormat::Format8BitYUV,
+            max_pending: 2,
+            sdk_conversion: true,
+            widescreen: false,
+        }
+    }
+}
+
+impl DualFormatConfig {
+    pub fn builder() -> DualFormatConfigBuilder {
+        DualFormatConfigBuilder {
+            config: DualFormatConfig::default(),
+        }
+    }
+
+    /// Check that a splitter with this config could deliver both streams.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let error = |field, problem| Err(ConfigError::new("DualFormatConfig", field, problem));
+        if self.primary.bytes_per_row(1).is_none() {
+            return error("primary", "must be an uncompressed format");
+        }
+        if self.secondary.bytes_per_row(1).is_none() {
+            return error("secondary", "must be an uncompressed format");
+        }
+        if self.max_pending == 0 {
+            return error(
+                "max_pending",
+                "must be at least 1, or every secondary frame would be shed",
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Builds a `DualFormatConfig`, starting from the default.
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub struct DualFormatConfigBuilder {
+    config: DualFormatConfig,
+}
+
+impl DualFormatConfigBuilder {
+    pub fn primary(mut self, primary: DecklinkPixelFormat) -> Self {
+        self.config.primary = primary;
+        self
+    }
+
+    pub fn secondary(mut self, secondary: DecklinkPixelFormat) -> Self {
+        self.config.secondary = secondary;
+        self
+    }
+
+    pub fn max_pending(mut self, max_pending: usize) -> Self {
+        self.config.max_pending = max_pending;
+        self
+    }
+
+    pub fn sdk_conversion(mut self, sdk_conversion: bool) -> Self {
+        self.config.sdk_conversion = sdk_conversion;
+        self
+    }
+
+    pub fn widescreen(mut self, widescreen: bool) -> Self {
+        self.config.widescreen = widescreen;
+        self
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        self.config.validate()
+    }
+
+    pub fn build(self) -> Result<DualFormatConfig, ConfigError> {
+        self.validate()?;
+        Ok(self.config)
+    }
+}
+
+/// What a frame of one stream is.
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub struct FrameMeta {
+    pub stream: DualStream,
+    /// The number of the captured frame, counting from 0. The primary and secondary frames
+    /// of a captured frame have the same number.
+    pub sequence: u64,
+    /// The timing of the frame, if the display mode timescale was known.
+    pub timing: Option<DecklinkFrameTiming>,
+    /// When the frame arrived in the driver callback.
+    pub arrived: Instant,
+    /// How the frame was produced. `Alias` for the primary stream too, which is the frame
+    /// captured.
+    pub path: ConversionPath,
+    /// The time taken to convert the frame, zero when it was not converted.
+    pub conversion_time: Duration,
+    /// The shape of the frame's pixels, from its display mode and
+    /// `DualFormatConfig::widescreen`, or from its height until the input has changed mode.
+    pub pixel_aspect: Ratio,
+    /// The display aspect ratio of the frame's picture, or the shape of its raster when its
+    /// display mode is not known.
+    pub display_aspect: Ratio,
+}
+
+/// The format a stream is in, from the frame it is given with on.
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub struct StreamFormat {
+    pub stream: DualStream,
+    /// The display mode the input last changed to, or `None` if it has not changed since
+    /// the splitter was installed.
+    pub display_mode: Option<DecklinkDisplayModeId>,
+    pub pixel_format: DecklinkPixelFormat,
+    pub path: ConversionPath,
+}
+
+enum Owner {
+    Captured(DecklinkVideoFrame),
+    Converted(DecklinkVideoMutableFrame),
+}
+
+/// The pixels of a frame, shared by the consumers of both streams.
+struct FrameData {
+    width: usize,
+    height: usize,
+    row_bytes: usize,
+    pixel_format: DecklinkPixelFormat,
+    flags: DecklinkFrameFlags,
+    /// The bytes of `owner`, read once when the data is created.
+    bytes: Result<(*const u8, usize), SdkError>,
+    owner: Owner,
+}
+
+// Safety: A captured frame is only called to read it when the data is created, by the
+// converter, which is given each frame once, and when it is released. Its bytes are not
+// written while it is held, so they can be read from any thread.
+unsafe impl Send for FrameData {}
+unsafe impl Sync for FrameData {}
+
+impl FrameData {
+    fn new(owner: Owner) -> FrameData {
+        let frame: &dyn DecklinkFrameBase = match &owner {
+            Owner::Captured(frame) => frame,
+            Owner::Converted(frame) => frame,
+        };
+        FrameData {
+            width: frame.width(),
+            height: frame.height(),
+            row_bytes: frame.row_bytes(),
+            pixel_format: frame.pixel_format(),
+            flags: frame.flags(),
+            bytes: frame.bytes().map(|bytes| (bytes.0.as_ptr(), bytes.0.len())),
+            owner,
+        }
+    }
+}
+
+/// A frame of one stream. Cloning it is cheap, as the pixels are shared.
+#[derive(Clone)]
+pub struct StreamFrame {
+    meta: FrameMeta,
+    data: Arc<FrameData>,
+}
+
+impl StreamFrame {
+    pub fn meta(&self) -> &FrameMeta {
+        &self.meta
+    }
+
+    /// Whether `other` shares this frame's pixels, as the secondary frames of an `Alias`
+    /// path share those of the primary frames.
+    pub fn shares_pixels_with(&self, other: &StreamFrame) -> bool {
+        Arc::ptr_eq(&self.data, &other.data)
+    }
+}
+
+impl DecklinkFrameBase for StreamFrame {
+    fn width(&self) -> usize {
+        self.data.width
+    }
+
+    fn height(&self) -> usize {
+        self.data.height
+    }
+
+    fn row_bytes(&self) -> usize {
+        self.data.row_bytes
+    }
+
+    fn pixel_format(&self) -> DecklinkPixelFormat {
+        self.data.pixel_format
+    }
+
+    fn flags(&self) -> DecklinkFrameFlags {
+        self.data.flags
+    }
+
+    fn bytes(&self) -> Result<DecklinkAlignedBytes<'_>, SdkError> {
+        let (ptr, len) = self.data.bytes?;
+        Ok(DecklinkAlignedBytes(unsafe {
+            std::slice::from_raw_parts(ptr, len)
+        }))
+    }
+}
+
+/// Receives the frames of one stream of a `DualFormatSplitter`.
+///
+/// Primary frames are given on the input callback thread, and secondary frames on the
+/// callback thread under `ConversionPath::Alias` and on a worker of the pool otherwise.
+pub trait DualFormatConsumer: Send + Sync {
+    fn frame_arrived(&self, frame: StreamFrame);
+
+    /// Called before the first frame of the stream, and before the first frame after each
+    /// change of its format or path.
+    fn format_negotiated(&self, _format: StreamFormat) {}
+}
+
+/// Identifies a consumer subscribed to a `DualFormatSplitter`.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+pub struct SubscriptionId(u64);
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct DualStreamStats {
+    pub stream: DualStream,
+    /// The format the stream was last negotiated in.
+    pub pixel_format: Option<DecklinkPixelFormat>,
+    pub subscribers: usize,
+    /// The frames given to the stream's consumers.
+    pub delivered: u64,
+    /// The frames shed while conversions fell behind. Always 0 for the primary stream.
+    pub shed: u64,
+    /// The frames that could not be converted, including those that arrived while the path
+    /// was `Unavailable`.
+    pub failed: u64,
+    /// The time conversions took, over the last 512 of them. Zero for the primary stream.
+    pub conversion_time: StageStats,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct DualFormatStats {
+    /// The path last negotiated, `None` before the first frame.
+    pub path: Option<ConversionPath>,
+    /// The conversions queued or running now.
+    pub pending: usize,
+    pub primary: DualStreamStats,
+    pub secondary: DualStreamStats,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DualFormatEvent {
+    /// The streams were negotiated, on the first frame or after a change of format.
+    Negotiated {
+        primary: DecklinkPixelFormat,
+        secondary: DecklinkPixelFormat,
+        path: ConversionPath,
+    },
+    /// The drivers' converter failed with `error`, and conversions fell back to `path`.
+    SdkConversionFailed {
+        error: SdkError,
+        path: ConversionPath,
+    },
+    /// Conversions fell behind, and the secondary of frame `sequence` was the first shed.
+    SheddingStarted { sequence: u64 },
+    /// A conversion was queued again, after `shed` frames were shed.
+    SheddingEnded { sequence: u64, shed: u64 },
+}
+
+/// The result of a negotiation, which the conversions queued under it carry.
+struct Negotiation {
+    generation: u64,
+    display_mode: Option<DecklinkDisplayModeId>,
+    primary: DecklinkPixelFormat,
+    path: ConversionPath,
+}
+
+struct StreamState {
+    delivered: u64,
+    failed: u64,
+    shed: u64,
+}
+
+struct Subscriber {
+    id: SubscriptionId,
+    stream: DualStream,
+    consumer: Arc<dyn DualFormatConsumer>,
+}
+
+struct DualState {
+    subscribers: Vec<Subscriber>,
+    next_subscription: u64,
+    sequence: u64,
+    negotiation: Option<Arc<Negotiation>>,
+    /// The display mode of the last format change, to renegotiate with on the next frame.
+    pending_change: Option<DecklinkDisplayModeId>,
+    /// The generation of the negotiation each stream's consumers were last told of.
+    announced: [Option<u64>; 2],
+    pending: usize,
+    /// The frames shed since the last conversion was queued.
+    shedding: Option<u64>,
+    streams: [StreamState; 2],
+    conversion_times: VecDeque<Duration>,
+    events: Vec<DualFormatEvent>,
+    cpu: Option<CpuMeter>,
+}
+
+impl DualState {
+    fn consumers(&self, stream: DualStream) -> Vec<Arc<dyn DualFormatConsumer>> {
+        self.subscribers
+            .iter()
+            .filter(|s| s.stream == stream)
+            .map(|s| s.consumer.clone())
+            .collect()
+    }
+
+    /// The consumers of `stream` to tell of `negotiation` before its next frame, if they
+    /// have not been told of it yet.
+    fn announcement(
+        &mut self,
+        stream: DualStream,
+        negotiation: &Negotiation,
+        pixel_format: DecklinkPixelFormat,
+    ) -> Option<StreamFormat> {
+        let announced = &mut self.announced[stream as usize];
+        if *announced == Some(negotiation.generation) {
+            return None;
+        }
+        *announced = Some(negotiation.generation);
+        Some(StreamFormat {
+            stream,
+            display_mode: negotiation.display_mode,
+            pixel_format,
+            path: negotiation.path,
+        })
+    }
+}
+
+struct DualShared {
+    config: DualFormatConfig,
+    sdk: Option<Mutex<SdkConversion>>,
+    state: Mutex<DualState>,
+    /// Signalled when a conversion completes.
+    idle: Condvar,
+    conversions: TaskSubmitter,
+}
+
+impl DualShared {
+    fn negotiate(
+        &self,
+        state: &mut DualState,
+        primary: DecklinkPixelFormat,
+        display_mode: Option<DecklinkDisplayModeId>,
+    ) -> Arc<Negotiation> {
+        let secondary = self.config.secondary;
+        let path = if primary == secondary {
+            ConversionPath::Alias
+        } else if self.sdk.is_some() {
+            ConversionPath::Sdk
+        } else if can_convert(primary, secondary) {
+            ConversionPath::Software
+        } else {
+            ConversionPath::Unavailable
+        };
+        let generation = state.negotiation.as_ref().map_or(0, |n| n.generation + 1);
+        let negotiation = Arc::new(Negotiation {
+            generation,
+            display_mode,
+            primary,
+            path,
+        });
+        state.negotiation = Some(negotiation.clone());
+        state.events.push(DualFormatEvent::Negotiated {
+            primary,
+            secondary,
+            path,
+        });
+        negotiation
+    }
+
+    /// Give a frame to the consumers of its stream, telling them of its format first if
+    /// they have not been told of it.
+    fn deliver(
+        &self,
+        consumers: &[Arc<dyn DualFormatConsumer>],
+        format: Option<StreamFormat>,
+        frame: StreamFrame,
+    ) {
+        if let Some(format) = format {
+            for consumer in consumers {
+                consumer.format_negotiated(format);
+            }
+        }
+        for consumer in consumers {
+            consumer.frame_arrived(frame.clone());
+        }
+    }
+
+    /// Convert a captured frame, falling back from the drivers' converter to the crate's
+    /// if it fails.
+    fn convert(
+        &self,
+        data: &FrameData,
+        negotiation: &Arc<Negotiation>,
+    ) -> (Arc<Negotiation>, Option<FrameData>) {
+        let secondary = self.config.secondary;
+        if negotiation.path == ConversionPath::Sdk {
+            let (Some(sdk), Owner::Captured(frame)) = (&self.sdk, &data.owner) else {
+                return (negotiation.clone(), None);
+            };
+            let result = sdk.lock().unwrap().convert(frame, secondary);
+            match result {
+                Ok(converted) => {
+                    return (
+                        negotiation.clone(),
+                        Some(FrameData::new(Owner::Captured(converted))),
+                    )
+                }
+                Err(error) => {
+                    let mut state = self.state.lock().unwrap();
+                    // Another conversion may have fallen back already
+                    let current = state.negotiation.clone();
+                    let negotiation = match current {
+                        Some(current) if current.path == ConversionPath::Sdk => {
+                            let fallback = if can_convert(current.primary, secondary) {
+                                ConversionPath::Software
+                            } else {
+                                ConversionPath::Unavailable
+                            };
+                            let next = Arc::new(Negotiation {
+                                generation: current.generation + 1,
+                                display_mode: current.display_mode,
+                                primary: current.primary,
+                                path: fallback,
+                            });
+                            state.negotiation = Some(next.clone());
+                            state.events.push(DualFormatEvent::SdkConversionFailed {
+                                error,
+                                path: fallback,
+                            });
+                            next
+                        }
+                        Some(current) => current,
+                        None => negotiation.clone(),
+                    };
+                    drop(state);
+                    if negotiation.path != ConversionPath::Software {
+                        return (negotiation, None);
+                    }
+                    return self.convert(data, &negotiation);
+                }
+            }
+        }
+
+        let converted = match &data.owner {
+            Owner::Captured(frame) => convert_frame(frame, secondary),
+            Owner::Converted(frame) => convert_frame(frame, secondary),
+        };
+        (
+            negotiation.clone(),
+            converted
+                .ok()
+                .map(|frame| FrameData::new(Owner::Converted(frame))),
+        )
+    }
+
+    /// Convert a frame on a worker and give it to the secondary stream.
+    fn run_conversion(
+        &self,
+        data: Arc<FrameData>,
+        mut meta: FrameMeta,
+        negotiation: Arc<Negotiation>,
+    ) {
+        let started = Instant::now();
+        let (negotiation, converted) = self.convert(&data, &negotiation);
+        let conversion_time = started.elapsed();
+
+        let mut state = self.state.lock().unwrap();
+        if let Some(cpu) = &state.cpu {
+            cpu.record(CpuStage::Conversion, conversion_time);
+        }
+        let current = state.negotiation.as_ref().map(|n| n.generation);
+        let secondary = DualStream::Secondary as usize;
+        let delivery = match converted {
+            // A frame converted after the format changed again is stale
+            Some(_) if current != Some(negotiation.generation) => {
+                state.streams[secondary].shed += 1;
+                None
+            }
+            Some(converted) => {
+                if state.conversion_times.len() >= CONVERSION_WINDOW {
+                    state.conversion_times.pop_front();
+                }
+                state.conversion_times.push_back(conversion_time);
+                state.streams[secondary].delivered += 1;
+                meta.path = negotiation.path;
+                meta.conversion_time = conversion_time;
+                let format =
+                    state.announcement(DualStream::Secondary, &negotiation, self.config.secondary);
+                let consumers = state.consumers(DualStream::Secondary);
+                let frame = StreamFrame {
+                    meta,
+                    data: Arc::new(converted),
+                };
+                Some((consumers, format, frame, state.cpu.clone()))
+            }
+            None => {
+                state.streams[secondary].failed += 1;
+                None
+            }
+        };
+        drop(state);
+
+        if let Some((consumers, format, frame, cpu)) = delivery {
+            let started = Instant::now();
+            self.deliver(&consumers, format, frame);
+            if let Some(cpu) = cpu {
+                cpu.record(CpuStage::Consumer, started.elapsed());
+            }
+        }
+
+        self.state.lock().unwrap().pending -= 1;
+        self.idle.notify_all();
+    }
+}
+
+/// An input callback that gives each frame to the consumers of a primary and a secondary
+/// stream, in two pixel formats.
+///
+/// Install the callback returned by `callback` with `DecklinkInputDevice::set_handler`, on
+/// an input enabled in `DualFormatConfig::primary`.
+pub struct DualFormatSplitter {
+    shared: Arc<DualShared>,
+}
+
+impl DualFormatSplitter {
+    /// Create a splitter whose conversions run on `pool`, as a source labelled `label`.
+    pub fn new(
+        config: DualFormatConfig,
+        pool: &DispatchPool,
+        label: impl Into<String>,
+    ) -> Result<DualFormatSplitter, ConfigError> {
+        config.validate()?;
+        let source = pool.add_task_source(label, 1);
+        let sdk = if config.sdk_conversion {
+            SdkConversion::new().map(Mutex::new)
+        } else {
+            None
+        };
+        let stream = || StreamState {
+            delivered: 0,
+            failed: 0,
+            shed: 0,
+        };
+        Ok(DualFormatSplitter {
+            shared: Arc::new(DualShared {
+                config,
+                sdk,
+                state: Mutex::new(DualState {
+                    subscribers: Vec::new(),
+                    next_subscription: 0,
+                    sequence: 0,
+                    negotiation: None,
+                    pending_change: None,
+                    announced: [None, None],
+                    pending: 0,
+                    shedding: None,
+                    streams: [stream(), stream()],
+                    conversion_times: VecDeque::new(),
+                    events: Vec::new(),
+                    cpu: None,
+                }),
+                idle: Condvar::new(),
+                conversions: pool.submitter(source),
+            }),
+        })
+    }
+
+    pub fn config(&self) -> DualFormatConfig {
+        self.shared.config
+    }
+
+    /// The input callback to install on the device.
+    pub fn callback(&self) -> Arc<dyn InputHandler> {
+        Arc::new(DualInputCallback {
+            shared: self.shared.clone(),
+        })
+    }
+
+    /// Give the frames of `stream` that arrive from now on to `consumer`.
+    pub fn subscribe(
+        &self,
+        stream: DualStream,
+        consumer: Arc<dyn DualFormatConsumer>,
+    ) -> SubscriptionId {
+        let mut state = self.shared.state.lock().unwrap();
+        let id = SubscriptionId(state.next_subscription);
+        state.next_subscription += 1;
+        state.subscribers.push(Subscriber {
+            id,
+            stream,
+            consumer,
+        });
+        // The new consumer must be told of the format too
+        state.announced[stream as usize] = None;
+        id
+    }
+
+    /// Stop giving frames to a consumer, returning whether it was subscribed.
+    pub fn unsubscribe(&self, id: SubscriptionId) -> bool {
+        let mut state = self.shared.state.lock().unwrap();
+        let count = state.subscribers.len();
+        state.subscribers.retain(|s| s.id != id);
+        state.subscribers.len() != count
+    }
+
+    /// Record the callback, conversion and consumer time of the splitter in `meter`, which
+    /// also counts its frames, or stop with `None`.
+    pub fn set_cpu_meter(&self, meter: Option<CpuMeter>) {
+        self.shared.state.lock().unwrap().cpu = meter;
+    }
+
+    /// Wait for the conversions queued so far to be given to the secondary stream, or for
+    /// `timeout`, returning whether they were.
+    pub fn wait_for_conversions(&self, timeout: Duration) -> bool {
+        let state = self.shared.state.lock().unwrap();
+        let (state, _) = self
+            .shared
+            .idle
+            .wait_timeout_while(state, timeout, |state| state.pending > 0)
+            .unwrap();
+        state.pending == 0
+    }
+
+    pub fn stats(&self) -> DualFormatStats {
+        let state = self.shared.state.lock().unwrap();
+        let negotiation = state.negotiation.as_ref();
+        let stream = |stream: DualStream, pixel_format| {
+            let counts = &state.streams[stream as usize];
+            DualStreamStats {
+                stream,
+                pixel_format,
+                subscribers: state
+                    .subscribers
+                    .iter()
+                    .filter(|s| s.stream == stream)
+                    .count(),
+                delivered: counts.delivered,
+                shed: counts.shed,
+                failed: counts.failed,
+                conversion_time: match stream {
+                    DualStream::Primary => StageStats::default(),
+                    DualStream::Secondary => {
+                        percentiles(state.conversion_times.iter().copied().collect())
+                    }
+                },
+            }
+        };
+        DualFormatStats {
+            path: negotiation.map(|n| n.path),
+            pending: state.pending,
+            primary: stream(DualStream::Primary, negotiation.map(|n| n.primary)),
+            secondary: stream(
+                DualStream::Secondary,
+                negotiation.map(|_| self.shared.config.secondary),
+            ),
+        }
+    }
+
+    /// The events raised since the last call, oldest first.
+    pub fn take_events(&self) -> Vec<DualFormatEvent> {
+        std::mem::take(&mut self.shared.state.lock().unwrap().events)
+    }
+}
+
+struct DualInputCallback {
+    shared: Arc<DualShared>,
+}
+
+impl InputHandler for DualInputCallback {
+    fn format_changed(&self, change: &InputFormatChange) {
+        // Both streams are renegotiated on the next frame, in the format the input is
+        // enabled in by then
+        self.shared.state.lock().unwrap().pending_change = Some(change.display_mode);
+    }
+
+    fn frame_arrived(&self, arrival: &FrameArrival<'_>) -> CallbackResult {
+        let arrived = Instant::now();
+        let Some(frame) = arrival.retain_video_frame() else {
+            return CallbackResult::Ok;
+        };
+        let data = Arc::new(FrameData::new(Owner::Captured(frame)));
+        let shared = &self.shared;
+        let timing = arrival.timing();
+
+        let mut state = shared.state.lock().unwrap();
+        let sequence = state.sequence;
+        state.sequence += 1;
+        let negotiation = match (state.negotiation.clone(), state.pending_change.take()) {
+            (Some(negotiation), None) if negotiation.primary == data.pixel_format => negotiation,
+            (negotiation, change) => {
+                let display_mode = change.or(negotiation.and_then(|n| n.display_mode));
+                shared.negotiate(&mut state, data.pixel_format, display_mode)
+            }
+        };
+        let widescreen = shared.config.widescreen;
+        let mode = negotiation.display_mode;
+        let pixel_aspect = mode
+            .and_then(|m| m.pixel_aspect_ratio(widescreen))
+            .unwrap_or_else(|| Ratio::for_height(data.height, widescreen));
+        let display_aspect = mode
+            .and_then(|m| m.display_aspect_ratio(widescreen))
+            .unwrap_or_else(|| Ratio::of_raster(data.width, data.height, pixel_aspect));
+        let meta = FrameMeta {
+            stream: DualStream::Primary,
+            sequence,
+            timing,
+            arrived,
+            path: ConversionPath::Alias,
+            conversion_time: Duration::ZERO,
+            pixel_aspect,
+            display_aspect,
+        };
+
+        let primary = state.consumers(DualStream::Primary);
+        let primary_format =
+            state.announcement(DualStream::Primary, &negotiation, negotiation.primary);
+        if !primary.is_empty() {
+            state.streams[DualStream::Primary as usize].delivered += 1;
+        }
+
+        let secondary = state.consumers(DualStream::Secondary);
+        let secondary_index = DualStream::Secondary as usize;
+        let mut aliased = None;
+        let mut conversion = false;
+        if !secondary.is_empty() {
+            match negotiation.path {
+                ConversionPath::Alias => {
+                    state.streams[secondary_index].delivered += 1;
+                    let format = state.announcement(
+                        DualStream::Secondary,
+                        &negotiation,
+                        shared.config.secondary,
+                    );
+                    aliased = Some(format);
+                }
+                ConversionPath::Unavailable => {
+                    state.streams[secondary_index].failed += 1;
+                    // Tell the consumers there will be no frames, in place of the first
+                    if let Some(format) = state.announcement(
+                        DualStream::Secondary,
+                        &negotiation,
+                        shared.config.secondary,
+                    ) {
+                        for consumer in &secondary {
+                            consumer.format_negotiated(format);
+                        }
+                    }
+                }
+                ConversionPath::Sdk | ConversionPath::Software => {
+                    conversion = state.pending < shared.config.max_pending;
+                    if conversion {
+                        state.pending += 1;
+                    }
+                }
+            }
+        }
+        let cpu = state.cpu.clone();
+        drop(state);
+
+        if conversion {
+            let task = {
+                let shared = shared.clone();
+                let data = data.clone();
+                let meta = FrameMeta {
+                    stream: DualStream::Secondary,
+                    ..meta
+                };
+                let negotiation = negotiation.clone();
+                Box::new(move || shared.run_conversion(data, meta, negotiation))
+            };
+            if !shared.conversions.submit(task) {
+                shared.state.lock().unwrap().pending -= 1;
+                shared.idle.notify_all();
+                conversion = false;
+            }
+        }
+        let wanted = !secondary.is_empty()
+            && matches!(
+                negotiation.path,
+                ConversionPath::Sdk | ConversionPath::Software
+            );
+        if wanted {
+            let mut state = shared.state.lock().unwrap();
+            match (conversion, state.shedding) {
+                (false, shed) => {
+                    state.streams[secondary_index].shed += 1;
+                    if shed.is_none() {
+                        state
+                            .events
+                            .push(DualFormatEvent::SheddingStarted { sequence });
+                    }
+                    state.shedding = Some(shed.unwrap_or(0) + 1);
+                }
+                (true, Some(shed)) => {
+                    state
+                        .events
+                        .push(DualFormatEvent::SheddingEnded { sequence, shed });
+                    state.shedding = None;
+                }
+                (true, None) => {}
+            }
+        }
+        let callback_time = arrived.elapsed();
+
+        let consumers_started = Instant::now();
+        let frame = StreamFrame { meta, data };
+        if let Some(format) = aliased {
+            let aliased = StreamFrame {
+                meta: FrameMeta {
+                    stream: DualStream::Secondary,
+                    ..meta
+                },
+                data: frame.data.clone(),
+            };
+            shared.deliver(&primary, primary_format, frame);
+            shared.deliver(&secondary, format, aliased);
+        } else {
+            shared.deliver(&primary, primary_format, frame);
+        }
+
+        if let Some(cpu) = cpu {
+            cpu.frame_arrived();
+            cpu.record(CpuStage::Callback, callback_time);
+            cpu.record(CpuStage::Consumer, consumers_started.elapsed());
+        }
+        CallbackResult::Ok
+    }
+}
@@ -0,0 +1,1019 @@
+//! A file format for raw captures, holding the pixel data of every frame with its metadata.
+//!
+//! A `DumpWriter` writes a header describing the capture, then a record per frame, then an
+//! index of the frames. A `DumpReader` reads the index to seek to a frame by its sequence
+//! number or stream time, and reads the frames back as `DumpFrame`s, which become
+//! `crate::testing::TestFrame`s to feed any code written against `DecklinkFrameBase`.
+//! `crate::replay::replay_dump_to_mock` re-delivers them through a mock input instead, and
+//! `SegmentedDumpWriter` writes a file per segment of a recording.
+//!
+//! A file that was not finished, or was damaged, has no usable index. The reader then scans
+//! the frames from the start, and keeps those before the first damaged record. `damage`
+//! reports how many frames were intact and where the damage starts.
+//!
+//! The file format (version 1) is little endian:
+//!
+//! ```text
+//! header:   b"DLDP", version: u16, display_mode: u32, pixel_format: u32, width: u32,
+//!           height: u32, row_bytes: u32, frame_duration: i64, time_scale: i64,
+//!           checksums: u8, config_length: u32, config: [u8; config_length]
+//! record:   tag: u8, length: u64, body: [u8; length]
+//!
+//! tag 1, frame:  sequence: u64, flags: u32, width: u32, height: u32, row_bytes: u32,
+//!                pixel_format: u32,
+//!                has_timing: u8,
+//!                [stream_time: i64, duration: i64, mode_duration: i64,
+//!                 scale: i64]                                   if has_timing
+//!                has_timecode: u8,
+//!                [format: u32, hours: u8, minutes: u8, seconds: u8, frames: u8,
+//!                 flags: u32, user_bits: u32]                   if has_timecode
+//!                payload_length: u64, payload: [u8; payload_length],
+//!                [checksum: u64]                                if checksums
+//! tag 2, index:  count: u64,
+//!                count × (sequence: u64, stream_time: i64, scale: i64, offset: u64)
+//! trailer:  index_offset: u64, b"DLDX"
+//! ```
+//!
+//! A `time_scale` of zero means the frame duration is not known, and a `scale` of zero in an
+//! index entry that the frame has no timing. `config` is the JSON of the
+//! `crate::effective_config::EffectiveConfig` of the capture, or empty. The checksum is the
+//! 64-bit FNV-1a hash of the rest of the record body, and `offset` is that of the record's
+//! tag. Enum and flag values are the raw SDK values. Readers reject files with a newer
+//! version.
+
+use crate::device::input::FrameContext;
+use crate::display_mode::DecklinkDisplayModeId;
+use crate::effective_config::EffectiveConfig;
+use crate::frame::{DecklinkFrameBase, DecklinkFrameFlags, DecklinkPixelFormat};
+use crate::segment::{segment_path, SegmentInfo, SegmentPolicy, SegmentSink, Segmenter};
+use crate::testing::{FillPattern, TestFrame, TestFrameBuilder};
+use crate::time::{DecklinkFrameTiming, DecklinkTime};
+use crate::timecode::{DecklinkTimecode, DecklinkTimecodeFlags, DecklinkTimecodeFormat};
+use crate::verify::fnv1a;
+use crate::SdkError;
+use num_traits::FromPrimitive;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+const MAGIC: &[u8; 4] = b"DLDP";
+const TRAILER_MAGIC: &[u8; 4] = b"DLDX";
+
+/// The version of the dump file format that is written.
+pub const DUMP_FORMAT_VERSION: u16 = 1;
+
+const TAG_FRAME: u8 = 1;
+const TAG_INDEX: u8 = 2;
+
+const TRAILER_LEN: u64 = 12;
+const INDEX_ENTRY_LEN: u64 = 32;
+
+/// What a dump file says about the capture it holds.
+#[derive(PartialEq, Debug, Clone)]
+pub struct DumpHeader {
+    pub display_mode: DecklinkDisplayModeId,
+    pub pixel_format: DecklinkPixelFormat,
+    pub width: usize,
+    pub height: usize,
+    pub row_bytes: usize,
+    /// The duration of a frame of the display mode, if known.
+    pub frame_duration: Option<DecklinkTime>,
+    /// Whether each frame record ends with a checksum.
+    pub checksums: bool,
+    /// The JSON of the effective configuration of the capture, or empty.
+    pub config: String,
+}
+
+impl DumpHeader {
+    /// A header for frames of `display_mode`, with checksums and no configuration. The row
+    /// byte count is the smallest for the pixel format, or zero for a compressed format.
+    pub fn new(
+        display_mode: DecklinkDisplayModeId,
+        pixel_format: DecklinkPixelFormat,
+        width: usize,
+        height: usize,
+    ) -> DumpHeader {
+        let row_bytes = crate::frame::pixel_group(pixel_format)
+            .map_or(0, |(pixels, bytes)| width.div_ceil(pixels) * bytes);
+        DumpHeader {
+            display_mode,
+            pixel_format,
+            width,
+            height,
+            row_bytes,
+            frame_duration: None,
+            checksums: true,
+            config: String::new(),
+        }
+    }
+
+    pub fn row_bytes(mut self, row_bytes: usize) -> Self {
+        self.row_bytes = row_bytes;
+        self
+    }
+
+    pub fn frame_duration(mut self, duration: DecklinkTime) -> Self {
+        self.frame_duration = Some(duration);
+        self
+    }
+
+    /// Store the effective configuration of the capture.
+    pub fn effective_config(mut self, config: &EffectiveConfig) -> Self {
+        self.config = config.to_json();
+        self
+    }
+
+    pub fn checksums(mut self, checksums: bool) -> Self {
+        self.checksums = checksums;
+        self
+    }
+
+    fn write_to(&self, buf: &mut Vec<u8>) {
+        let duration = self.frame_duration.unwrap_or(DecklinkTime::new(0, 0));
+        buf.extend_from_slice(MAGIC);
+        buf.extend_from_slice(&DUMP_FORMAT_VERSION.to_le_bytes());
+        put_u32(buf, self.display_mode as u32);
+        put_u32(buf, self.pixel_format as u32);
+        put_u32(buf, self.width as u32);
+        put_u32(buf, self.height as u32);
+        put_u32(buf, self.row_bytes as u32);
+        buf.extend_from_slice(&duration.value.to_le_bytes());
+        buf.extend_from_slice(&duration.scale.to_le_bytes());
+        buf.push(self.checksums as u8);
+        put_u32(buf, self.config.len() as u32);
+        buf.extend_from_slice(self.config.as_bytes());
+    }
+}
+
+fn put_u32(buf: &mut Vec<u8>, v: u32) {
+    buf.extend_from_slice(&v.to_le_bytes());
+}
+
+/// A frame read from a dump file.
+#[derive(PartialEq, Debug, Clone)]
+pub struct DumpFrame {
+    /// The sequence number the frame was written with.
+    pub sequence: u64,
+    pub flags: DecklinkFrameFlags,
+    pub width: usize,
+    pub height: usize,
+    pub row_bytes: usize,
+    pub pixel_format: DecklinkPixelFormat,
+    pub timing: Option<DecklinkFrameTiming>,
+    pub timecode: Option<(DecklinkTimecodeFormat, DecklinkTimecode)>,
+    /// The frame buffer, including any row padding.
+    pub payload: Vec<u8>,
+}
+
+impl DumpFrame {
+    /// A test frame holding the pixel data, flags and timing of this frame.
+    pub fn into_test_frame(self) -> Result<TestFrame, SdkError> {
+        let mut builder = TestFrameBuilder::new(self.width, self.height)
+            .pixel_format(self.pixel_format)
+            .row_bytes(self.row_bytes)
+            .flags(self.flags)
+            .fill(FillPattern::Bytes(self.payload));
+        if let Some(timing) = self.timing {
+            builder = builder.timing(timing);
+        }
+        builder.build()
+    }
+}
+
+/// Why the frames of a dump file stop before its end.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+pub enum DamageReason {
+    /// The file ends inside a record.
+    Truncated,
+    /// A record does not match its checksum.
+    ChecksumMismatch,
+    /// A record has an unknown tag, or does not parse.
+    Malformed,
+    /// Every frame record is intact, but the file has no valid index, as when the writer
+    /// was not finished.
+    MissingIndex,
+}
+
+/// Where a damaged dump file stops being readable.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+pub struct Damage {
+    /// The number of frames before the damage, which can all be read.
+    pub intact_frames: u64,
+    /// The offset in the file of the first damaged record, or of the end of the file.
+    pub offset: u64,
+    pub reason: DamageReason,
+}
+
+#[derive(Debug)]
+pub enum DumpError {
+    Io(io::Error),
+    /// The file does not start with the magic of a dump file.
+    NotADump,
+    /// The file is of this newer format version.
+    NewerVersion(u16),
+    /// The header holds a value this version of the crate does not know.
+    BadHeader(&'static str),
+    /// A frame could not be read.
+    Damaged(Damage),
+}
+
+impl From<io::Error> for DumpError {
+    fn from(e: io::Error) -> Self {
+        DumpError::Io(e)
+    }
+}
+
+impl fmt::Display for DumpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DumpError::Io(e) => write!(f, "{}", e),
+            DumpError::NotADump => write!(f, "not a frame dump"),
+            DumpError::NewerVersion(version) => {
+                write!(f, "unsupported frame dump format version {}", version)
+            }
+            DumpError::BadHeader(what) => write!(f, "the frame dump header has {}", what),
+            DumpError::Damaged(damage) => write!(
+                f,
+                "the frame dump is damaged at offset {} ({:?}), after {} intact frames",
+                damage.offset, damage.reason, damage.intact_frames
+            ),
+        }
+    }
+}
+
+impl std::error::Error for DumpError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DumpError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// An entry of the index of a dump file.
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+struct IndexEntry {
+    sequence: u64,
+    /// The stream time of the frame, with a scale of zero if it has no timing.
+    time: DecklinkTime,
+    offset: u64,
+}
+
+/// Writes frames in the dump file format.
+///
+/// Nothing is seeked, so a dump can be written to a pipe. `finish` must be called to write
+/// the index. Until it is, readers see the file as missing its index, and recover the
+/// frames written.
+pub struct DumpWriter<W: Write> {
+    writer: W,
+    checksums: bool,
+    position: u64,
+    index: Vec<IndexEntry>,
+    buf: Vec<u8>,
+}
+
+impl<W: Write> DumpWriter<W> {
+    /// Write the file header and return a writer for frames.
+    pub fn new(mut writer: W, header: &DumpHeader) -> io::Result<DumpWriter<W>> {
+        let mut buf = Vec::new();
+        header.write_to(&mut buf);
+        writer.write_all(&buf)?;
+        Ok(DumpWriter {
+            writer,
+            checksums: header.checksums,
+            position: buf.len() as u64,
+            index: Vec::new(),
+            buf,
+        })
+    }
+
+    /// The number of frames written.
+    pub fn frame_count(&self) -> u64 {
+        self.index.len() as u64
+    }
+
+    /// The number of bytes written, including the header.
+    pub fn byte_count(&self) -> u64 {
+        self.position
+    }
+
+    /// Write a frame, with the sequence number and timing of `context`.
+    pub fn write_frame<F: DecklinkFrameBase + ?Sized>(
+        &mut self,
+        frame: &F,
+        context: FrameContext,
+        timecode: Option<(DecklinkTimecodeFormat, DecklinkTimecode)>,
+    ) -> io::Result<()> {
+        let bytes = frame
+            .bytes()
+            .map_err(|e| io::Error::other(format!("{:?}", e)))?;
+        let length = frame.row_bytes() * frame.height();
+        let payload = bytes
+            .0
+            .get(..length)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "frame is truncated"))?;
+
+        let buf = &mut self.buf;
+        buf.clear();
+        buf.extend_from_slice(&context.sequence.to_le_bytes());
+        put_u32(buf, frame.flags().bits());
+        put_u32(buf, frame.width() as u32);
+        put_u32(buf, frame.height() as u32);
+        put_u32(buf, frame.row_bytes() as u32);
+        put_u32(buf, frame.pixel_format() as u32);
+        match context.timing {
+            Some(t) => {
+                buf.push(1);
+                buf.extend_from_slice(&t.stream_time.value.to_le_bytes());
+                buf.extend_from_slice(&t.duration.value.to_le_bytes());
+                buf.extend_from_slice(&t.mode_duration.value.to_le_bytes());
+                buf.extend_from_slice(&t.stream_time.scale.to_le_bytes());
+            }
+            None => buf.push(0),
+        }
+        match timecode {
+            Some((format, tc)) => {
+                buf.push(1);
+                put_u32(buf, format as u32);
+                buf.extend_from_slice(&[tc.hours, tc.minutes, tc.seconds, tc.frames]);
+                put_u32(buf, tc.flags.bits());
+                put_u32(buf, tc.user_bits);
+            }
+            None => buf.push(0),
+        }
+        buf.extend_from_slice(&(payload.len() as u64).to_le_bytes());
+        let mut checksum = None;
+        if self.checksums {
+            // The payload is hashed in place rather than copied into the record first
+            let mut hash = Fnv1a::new();
+            hash.write(buf);
+            hash.write(payload);
+            checksum = Some(hash.0);
+        }
+
+        let body_len = buf.len() + payload.len() + if self.checksums { 8 } else { 0 };
+        let mut prefix = [0; 9];
+        prefix[0] = TAG_FRAME;
+        prefix[1..].copy_from_slice(&(body_len as u64).to_le_bytes());
+        self.writer.write_all(&prefix)?;
+        self.writer.write_all(buf)?;
+        self.writer.write_all(payload)?;
+        if let Some(checksum) = checksum {
+            self.writer.write_all(&checksum.to_le_bytes())?;
+        }
+
+        self.index.push(IndexEntry {
+            sequence: context.sequence,
+            time: context
+                .timing
+                .map_or(DecklinkTime::new(0, 0), |t| t.stream_time),
+            offset: self.position,
+        });
+        self.position += prefix.len() as u64 + body_len as u64;
+        Ok(())
+    }
+
+    /// Write the index and the trailer, and return the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        let index_offset = self.position;
+        let mut buf = Vec::with_capacity(17 + self.index.len() * INDEX_ENTRY_LEN as usize + 12);
+        buf.push(TAG_INDEX);
+        buf.extend_from_slice(&(8 + self.index.len() as u64 * INDEX_ENTRY_LEN).to_le_bytes());
+        buf.extend_from_slice(&(self.index.len() as u64).to_le_bytes());
+        for entry in &self.index {
+            buf.extend_from_slice(&entry.sequence.to_le_bytes());
+            buf.extend_from_slice(&entry.time.value.to_le_bytes());
+            buf.extend_from_slice(&entry.time.scale.to_le_bytes());
+            buf.extend_from_slice(&entry.offset.to_le_bytes());
+        }
+        buf.extend_from_slice(&index_offset.to_le_bytes());
+        buf.extend_from_slice(TRAILER_MAGIC);
+        self.writer.write_all(&buf)?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// `crate::verify::fnv1a`, over bytes given in pieces.
+struct Fnv1a(u64);
+
+impl Fnv1a {
+    fn new() -> Fnv1a {
+        Fnv1a(fnv1a(&[]))
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 ^= *b as u64;
+            self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+}
+
+/// Reads fields from the body of a record.
+struct Fields<'a>(&'a [u8]);
+
+impl<'a> Fields<'a> {
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        if self.0.len() < n {
+            return None;
+        }
+        let (head, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Some(head)
+    }
+    fn u8(&mut self) -> Option<u8> {
+        self.take(1).map(|b| b[0])
+    }
+    fn u32(&mut self) -> Option<u32> {
+        self.take(4)
+            .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+    }
+    fn u64(&mut self) -> Option<u64> {
+        self.take(8)
+            .map(|b| u64::from_le_bytes(b.try_into().unwrap()))
+    }
+    fn i64(&mut self) -> Option<i64> {
+        self.u64().map(|v| v as i64)
+    }
+}
+
+/// Parse the body of a frame record, without its checksum.
+fn parse_frame(body: &[u8]) -> Option<DumpFrame> {
+    let mut f = Fields(body);
+    let sequence = f.u64()?;
+    let flags = DecklinkFrameFlags::from_bits_retain(f.u32()?);
+    let width = f.u32()? as usize;
+    let height = f.u32()? as usize;
+    let row_bytes = f.u32()? as usize;
+    let pixel_format = DecklinkPixelFormat::from_u32(f.u32()?)?;
+    let timing = match f.u8()? {
+        0 => None,
+        _ => {
+            let stream_time = f.i64()?;
+            let duration = f.i64()?;
+            let mode_duration = f.i64()?;
+            let scale = f.i64()?;
+            Some(DecklinkFrameTiming {
+                stream_time: DecklinkTime::new(stream_time, scale),
+                duration: DecklinkTime::new(duration, scale),
+                mode_duration: DecklinkTime::new(mode_duration, scale),
+            })
+        }
+    };
+    let timecode = match f.u8()? {
+        0 => None,
+        _ => {
+            let format = DecklinkTimecodeFormat::from_u32(f.u32()?)?;
+            let [hours, minutes, seconds, frames]: [u8; 4] = f.take(4)?.try_into().unwrap();
+            let flags = DecklinkTimecodeFlags::from_bits_retain(f.u32()?);
+            let user_bits = f.u32()?;
+            Some((
+                format,
+                DecklinkTimecode {
+                    hours,
+                    minutes,
+                    seconds,
+                    frames,
+                    flags,
+                    user_bits,
+                },
+            ))
+        }
+    };
+    let payload_len = usize::try_from(f.u64()?).ok()?;
+    let payload = f.take(payload_len)?.to_vec();
+    if !f.0.is_empty() {
+        return None;
+    }
+    Some(DumpFrame {
+        sequence,
+        flags,
+        width,
+        height,
+        row_bytes,
+        pixel_format,
+        timing,
+        timecode,
+        payload,
+    })
+}
+
+/// Whether `a` is no later than `b`, comparing across timescales.
+fn time_le(a: DecklinkTime, b: DecklinkTime) -> bool {
+    a.value as i128 * b.scale as i128 <= b.value as i128 * a.scale as i128
+}
+
+/// Reads the frames of a dump file.
+///
+/// The reader is an iterator of the frames from the current position, which starts at the
+/// first frame and is moved with `seek_sequence` and `seek_time`. A frame that turns out to
+/// be damaged when it is read ends the iteration with `DumpError::Damaged`.
+pub struct DumpReader<R: Read + Seek> {
+    reader: R,
+    version: u16,
+    header: DumpHeader,
+    checksums: bool,
+    index: Vec<IndexEntry>,
+    /// The length of the file.
+    end: u64,
+    position: usize,
+    damage: Option<Damage>,
+}
+
+impl DumpReader<BufReader<File>> {
+    pub fn open(path: impl AsRef<Path>) -> Result<DumpReader<BufReader<File>>, DumpError> {
+        DumpReader::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read + Seek> DumpReader<R> {
+    /// Read and check the file header, then read the index, or scan for the intact frames if
+    /// there is no usable index.
+    pub fn new(mut reader: R) -> Result<DumpReader<R>, DumpError> {
+        reader.seek(SeekFrom::Start(0))?;
+        let mut fixed = [0; 47];
+        reader.read_exact(&mut fixed).map_err(|e| match e.kind() {
+            io::ErrorKind::UnexpectedEof => DumpError::NotADump,
+            _ => DumpError::Io(e),
+        })?;
+        if &fixed[..4] != MAGIC {
+            return Err(DumpError::NotADump);
+        }
+        let mut f = Fields(&fixed[4..]);
+        let version = u16::from_le_bytes(f.take(2).unwrap().try_into().unwrap());
+        if version > DUMP_FORMAT_VERSION {
+            return Err(DumpError::NewerVersion(version));
+        }
+        let display_mode = DecklinkDisplayModeId::from_u32(f.u32().unwrap())
+            .ok_or(DumpError::BadHeader("an unknown display mode"))?;
+        let pixel_format = DecklinkPixelFormat::from_u32(f.u32().unwrap())
+            .ok_or(DumpError::BadHeader("an unknown pixel format"))?;
+        let width = f.u32().unwrap() as usize;
+        let height = f.u32().unwrap() as usize;
+        let row_bytes = f.u32().unwrap() as usize;
+        let duration = DecklinkTime::new(f.i64().unwrap(), f.i64().unwrap());
+        let checksums = f.u8().unwrap() != 0;
+        let config_length = f.u32().unwrap() as u64;
+        let truncated = || {
+            DumpError::Damaged(Damage {
+                intact_frames: 0,
+                offset: fixed.len() as u64,
+                reason: DamageReason::Truncated,
+            })
+        };
+        // The length is checked against the file before anything is allocated for it
+        let end = reader.seek(SeekFrom::End(0))?;
+        if config_length > end - fixed.len() as u64 {
+            return Err(truncated());
+        }
+        reader.seek(SeekFrom::Start(fixed.len() as u64))?;
+        let mut config = vec![0; config_length as usize];
+        reader.read_exact(&mut config).map_err(|e| match e.kind() {
+            io::ErrorKind::UnexpectedEof => truncated(),
+            _ => DumpError::Io(e),
+        })?;
+        let config = String::from_utf8(config)
+            .map_err(|_| DumpError::BadHeader("a config that is not UTF-8"))?;
+
+        let header = DumpHeader {
+            display_mode,
+            pixel_format,
+            width,
+            height,
+            row_bytes,
+            frame_duration: (duration.scale > 0).then_some(duration),
+            checksums,
+            config,
+        };
+        let frames_start = reader.stream_position()?;
+        let mut dump = DumpReader {
+            reader,
+            version,
+            header,
+            checksums,
+            index: Vec::new(),
+            end,
+            position: 0,
+            damage: None,
+        };
+        if !dump.read_index(frames_start)? {
+            dump.rebuild_index(frames_start)?;
+        }
+        Ok(dump)
+    }
+
+    /// Read the index the trailer points to, returning whether there is a valid one.
+    fn read_index(&mut self, frames_start: u64) -> io::Result<bool> {
+        let end = self.end;
+        if end < frames_start + TRAILER_LEN {
+            return Ok(false);
+        }
+        let mut trailer = [0; TRAILER_LEN as usize];
+        self.reader.seek(SeekFrom::Start(end - TRAILER_LEN))?;
+        self.reader.read_exact(&mut trailer)?;
+        if &trailer[8..] != TRAILER_MAGIC {
+            return Ok(false);
+        }
+        let index_offset = u64::from_le_bytes(trailer[..8].try_into().unwrap());
+        let index_end = end - TRAILER_LEN;
+        if index_offset < frames_start || index_offset + 17 > index_end {
+            return Ok(false);
+        }
+
+        let mut prefix = [0; 17];
+        self.reader.seek(SeekFrom::Start(index_offset))?;
+        self.reader.read_exact(&mut prefix)?;
+        let length = u64::from_le_bytes(prefix[1..9].try_into().unwrap());
+        let count = u64::from_le_bytes(prefix[9..].try_into().unwrap());
+        if prefix[0] != TAG_INDEX
+            || index_offset + 9 + length != index_end
+            || count
+                .checked_mul(INDEX_ENTRY_LEN)
+                .and_then(|n| n.checked_add(8))
+                != Some(length)
+        {
+            return Ok(false);
+        }
+
+        // `length` was checked to end at the trailer, so the entries are within the file
+        let mut entries = vec![0; (length - 8) as usize];
+        self.reader.read_exact(&mut entries)?;
+        let mut index = Vec::with_capacity(count as usize);
+        for entry in entries.chunks_exact(INDEX_ENTRY_LEN as usize) {
+            let mut f = Fields(entry);
+            let entry = IndexEntry {
+                sequence: f.u64().unwrap(),
+                time: DecklinkTime::new(f.i64().unwrap(), f.i64().unwrap()),
+                offset: f.u64().unwrap(),
+            };
+            if entry.offset < frames_start || entry.offset >= index_offset {
+                return Ok(false);
+            }
+            index.push(entry);
+        }
+        self.index = index;
+        Ok(true)
+    }
+
+    /// Read the record at `offset`.
+    fn read_record(&mut self, offset: u64) -> io::Result<Result<(u8, Vec<u8>), DamageReason>> {
+        let end = self.end;
+        if offset + 9 > end {
+            return Ok(Err(DamageReason::Truncated));
+        }
+        let mut prefix = [0; 9];
+        self.reader.seek(SeekFrom::Start(offset))?;
+        self.reader.read_exact(&mut prefix)?;
+        let length = u64::from_le_bytes(prefix[1..].try_into().unwrap());
+        if length > end - offset - 9 {
+            return Ok(Err(DamageReason::Truncated));
+        }
+        let mut body = vec![0; length as usize];
+        self.reader.read_exact(&mut body)?;
+        Ok(Ok((prefix[0], body)))
+    }
+
+    /// Check the checksum of a frame record and parse it.
+    fn frame_from(&self, body: &[u8]) -> Result<DumpFrame, DamageReason> {
+        let body = if self.checksums {
+            let split = body.len().checked_sub(8).ok_or(DamageReason::Malformed)?;
+            let (body, checksum) = body.split_at(split);
+            if fnv1a(body) != u64::from_le_bytes(checksum.try_into().unwrap()) {
+                return Err(DamageReason::ChecksumMismatch);
+            }
+            body
+        } else {
+            body
+        };
+        parse_frame(body).ok_or(DamageReason::Malformed)
+    }
+
+    /// Build the index from the frames before the first damaged record.
+    fn rebuild_index(&mut self, frames_start: u64) -> io::Result<()> {
+        let mut offset = frames_start;
+        let reason = loop {
+            if offset == self.end {
+                break DamageReason::MissingIndex;
+            }
+            let (tag, body) = match self.read_record(offset)? {
+                Ok(record) => record,
+                Err(reason) => break reason,
+            };
+            match tag {
+                TAG_FRAME => {}
+                // The frames all came before it, so only the index or trailer is damaged
+                TAG_INDEX => break DamageReason::MissingIndex,
+                _ => break DamageReason::Malformed,
+            }
+            let frame = match self.frame_from(&body) {
+                Ok(frame) => frame,
+                Err(reason) => break reason,
+            };
+            self.index.push(IndexEntry {
+                sequence: frame.sequence,
+                time: frame
+                    .timing
+                    .map_or(DecklinkTime::new(0, 0), |t| t.stream_time),
+                offset,
+            });
+            offset += 9 + body.len() as u64;
+        };
+        self.damage = Some(Damage {
+            intact_frames: self.index.len() as u64,
+            offset,
+            reason,
+        });
+        Ok(())
+    }
+
+    /// The format version of the file.
+    pub fn version(&self) -> u16 {
+        self.version
+    }
+
+    pub fn header(&self) -> &DumpHeader {
+        &self.header
+    }
+
+    /// Where the file is damaged, if it is. Found when the file is opened if it has no
+    /// usable index, or else when a damaged frame is read.
+    pub fn damage(&self) -> Option<Damage> {
+        self.damage
+    }
+
+    /// The number of frames that can be read.
+    pub fn frame_count(&self) -> u64 {
+        self.index.len() as u64
+    }
+
+    /// The position of the next frame, counting from zero.
+    pub fn position(&self) -> u64 {
+        self.position as u64
+    }
+
+    /// Move to the frame at `position`, counting from zero. Returns whether there is one.
+    pub fn seek_position(&mut self, position: u64) -> bool {
+        match usize::try_from(position) {
+            Ok(position) if position < self.index.len() => {
+                self.position = position;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Move to the frame with this sequence number. Returns whether there is one.
+    ///
+    /// Sequence numbers are expected to increase. Without gaps the position is worked out
+    /// directly, otherwise it is searched for in the index.
+    pub fn seek_sequence(&mut self, sequence: u64) -> bool {
+        let first = match self.index.first() {
+            Some(entry) => entry.sequence,
+            None => return false,
+        };
+        let direct = sequence
+            .checked_sub(first)
+            .and_then(|n| usize::try_from(n).ok())
+            .filter(|&n| self.index.get(n).is_some_and(|e| e.sequence == sequence));
+        let found = direct.or_else(|| {
+            self.index
+                .binary_search_by_key(&sequence, |e| e.sequence)
+                .ok()
+        });
+        match found {
+            Some(position) => {
+                self.position = position;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Move to the frame showing at stream time `time`: the last frame whose stream time is
+    /// no later. Returns whether there is one, so `false` if `time` is before the first
+    /// frame, or the frames have no timing.
+    ///
+    /// Stream times are expected to increase. At a constant frame duration the position is
+    /// worked out directly, otherwise it is searched for in the index.
+    pub fn seek_time(&mut self, time: DecklinkTime) -> bool {
+        if time.scale <= 0 || self.index.iter().any(|e| e.time.scale <= 0) {
+            return false;
+        }
+        let first = match self.index.first() {
+            Some(entry) => entry.time,
+            None => return false,
+        };
+        if !time_le(first, time) {
+            return false;
+        }
+        let showing = |n: usize| {
+            time_le(self.index[n].time, time)
+                && self
+                    .index
+                    .get(n + 1)
+                    .is_none_or(|next| !time_le(next.time, time))
+        };
+        let duration = self.header.frame_duration.filter(|d| d.scale > 0);
+        let direct = duration.and_then(|duration| {
+            let elapsed = i128::from(time.value) * i128::from(first.scale)
+                - i128::from(first.value) * i128::from(time.scale);
+            let per_frame =
+                i128::from(duration.value) * i128::from(first.scale) * i128::from(time.scale)
+                    / i128::from(duration.scale);
+            let n = usize::try_from(elapsed.checked_div(per_frame)?).ok()?;
+            (n < self.index.len() && showing(n)).then_some(n)
+        });
+        self.position =
+            direct.unwrap_or_else(|| self.index.partition_point(|e| time_le(e.time, time)) - 1);
+        true
+    }
+
+    /// Read the frame at `position`, counting from zero, without moving.
+    pub fn read_frame(&mut self, position: u64) -> Option<Result<DumpFrame, DumpError>> {
+        let entry = *self.index.get(usize::try_from(position).ok()?)?;
+        let reason = match self.read_record(entry.offset) {
+            Ok(Ok((TAG_FRAME, body))) => match self.frame_from(&body) {
+                Ok(frame) => return Some(Ok(frame)),
+                Err(reason) => reason,
+            },
+            Ok(Ok(_)) => DamageReason::Malformed,
+            Ok(Err(reason)) => reason,
+            Err(e) => return Some(Err(DumpError::Io(e))),
+        };
+        let damage = Damage {
+            intact_frames: position,
+            offset: entry.offset,
+            reason,
+        };
+        self.damage.get_or_insert(damage);
+        Some(Err(DumpError::Damaged(damage)))
+    }
+}
+
+impl<R: Read + Seek> Iterator for DumpReader<R> {
+    type Item = Result<DumpFrame, DumpError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let frame = self.read_frame(self.position as u64)?;
+        // Stop after a damaged frame
+        self.position = match frame {
+            Ok(_) => self.position + 1,
+            Err(_) => self.index.len(),
+        };
+        Some(frame)
+    }
+}
+
+/// Writes a dump file per segment.
+///
+/// File names are made from a pattern, where `{index}` is replaced with the segment index
+/// and `{frame}` with the index of its first frame. Each file has the header given, with the
+/// size and pixel format of its first frame. Frames are numbered in order over the whole
+/// recording, and stored with the timecode set by `set_timecode` before them, if any.
+pub struct SegmentedDumpWriter {
+    pattern: String,
+    header: DumpHeader,
+    segmenter: Segmenter,
+    timecode: Option<(DecklinkTimecodeFormat, DecklinkTimecode)>,
+    current: Option<(SegmentInfo, DumpWriter<BufWriter<File>>)>,
+    finished: Vec<SegmentInfo>,
+}
+
+impl SegmentedDumpWriter {
+    pub fn new(
+        pattern: impl Into<String>,
+        policy: SegmentPolicy,
+        header: DumpHeader,
+    ) -> SegmentedDumpWriter {
+        SegmentedDumpWriter {
+            pattern: pattern.into(),
+            header,
+            segmenter: Segmenter::new(policy),
+            timecode: None,
+            current: None,
+            finished: Vec::new(),
+        }
+    }
+
+    /// The path of a segment.
+    pub fn path_for(&self, boundary: &crate::segment::SegmentBoundary) -> PathBuf {
+        segment_path(&self.pattern, boundary)
+    }
+
+    /// Note that the input format changed, so the next frame starts a new file.
+    pub fn format_changed(&mut self) {
+        self.segmenter.format_changed();
+    }
+
+    /// The segments that have been finished so far.
+    pub fn finished_segments(&self) -> &[SegmentInfo] {
+        &self.finished
+    }
+
+    /// Set the timecode stored with the next frame.
+    pub fn set_timecode(&mut self, timecode: Option<(DecklinkTimecodeFormat, DecklinkTimecode)>) {
+        self.timecode = timecode;
+    }
+
+    /// Write a frame, starting a new file first if it is at a boundary. Returns the segment
+    /// that was finished to make way for it, if any.
+    pub fn write_frame<F: DecklinkFrameBase + ?Sized>(
+        &mut self,
+        frame: &F,
+        timing: Option<&DecklinkFrameTiming>,
+    ) -> io::Result<Option<SegmentInfo>> {
+        // A frame of another size or pixel format is a format change, even if it was not
+        // reported
+        let header = &self.header;
+        if self.current.is_some()
+            && (frame.width(), frame.height(), frame.pixel_format())
+                != (header.width, header.height, header.pixel_format)
+        {
+            self.segmenter.format_changed();
+        }
+
+        let sequence = self.segmenter.frame_count();
+        let mut closed = None;
+        if let Some(boundary) = self.segmenter.frame(timing) {
+            closed = self.finish_segment()?;
+            self.header.width = frame.width();
+            self.header.height = frame.height();
+            self.header.row_bytes = frame.row_bytes();
+            self.header.pixel_format = frame.pixel_format();
+            if let Some(timing) = timing {
+                self.header.frame_duration = Some(timing.duration);
+            }
+            let path = self.path_for(&boundary);
+            let writer = DumpWriter::new(BufWriter::new(File::create(&path)?), &self.header)?;
+            self.current = Some((
+                SegmentInfo {
+                    boundary,
+                    path,
+                    frame_count: 0,
+                    byte_count: 0,
+                },
+                writer,
+            ));
+        }
+
+        let (info, writer) = self
+            .current
+            .as_mut()
+            .expect("the first frame always starts a segment");
+        writer.write_frame(
+            frame,
+            FrameContext::new(timing.copied(), sequence),
+            self.timecode.take(),
+        )?;
+        info.frame_count += 1;
+        Ok(closed)
+    }
+
+    /// Write the index and close the current segment.
+    fn finish_segment(&mut self) -> io::Result<Option<SegmentInfo>> {
+        match self.current.take() {
+            Some((mut info, writer)) => {
+                let file = writer.finish()?.into_inner()?;
+                file.sync_all()?;
+                info.byte_count = file.metadata()?.len();
+                self.finished.push(info.clone());
+                Ok(Some(info))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Finish the last file, returning every segment that was written.
+    pub fn finish(mut self) -> io::Result<Vec<SegmentInfo>> {
+        self.finish_segment()?;
+        Ok(self.finished)
+    }
+}
+
+impl SegmentSink for SegmentedDumpWriter {
+    fn write_frame(
+        &mut self,
+        frame: &dyn DecklinkFrameBase,
+        timing: Option<&DecklinkFrameTiming>,
+    ) -> io::Result<Option<SegmentInfo>> {
+        SegmentedDumpWriter::write_frame(self, frame, timing)
+    }
+
+    fn set_timecode(&mut self, timecode: Option<(DecklinkTimecodeFormat, DecklinkTimecode)>) {
+        SegmentedDumpWriter::set_timecode(self, timecode);
+    }
+
+    fn format_changed(&mut self) {
+        SegmentedDumpWriter::format_changed(self);
+    }
+
+    fn finished_segments(&self) -> &[SegmentInfo] {
+        SegmentedDumpWriter::finished_segments(self)
+    }
+
+    fn finish(self: Box<Self>) -> io::Result<Vec<SegmentInfo>> {
+        SegmentedDumpWriter::finish(*self)
+    }
+}
@@ -0,0 +1,387 @@
+//! The configuration a capture actually runs with, for bug reports and for reproducing it.
+//!
+//! What is asked of an input is not always what it does: a pixel format preference settles on
+//! one format, `CaptureColorMode::Auto` resolves to a color mode, and audio input can wait for
+//! video input. A `DecklinkInputDevice` records each value as it is negotiated, next to the
+//! one that was requested, and `DecklinkEffectiveConfigExt::effective_config` assembles them
+//! into an `EffectiveConfig`, without asking the driver again.
+//!
+//! The input does not know the device it belongs to, the driver version, the rules a
+//! `crate::format_detect::FormatDetector` applied or the `crate::dispatch::DispatchPool` its
+//! frames go to, so those are left empty for the caller to fill from values it has already
+//! read. The crate's own reports do: the capture manifest setup, the probe report and the
+//! soak summary each carry the config of their capture.
+//!
+//! User supplied components are identified by a hash of their names, such as the type name of
+//! the handler and the names of the stages of a `crate::experimental::transform::TransformChain`,
+//! so that reports of the same application can be matched without naming its types.
+//!
+//! # Serialization
+//!
+//! `EffectiveConfig::to_json` writes, and with the `serde` feature the config serializes with,
+//! the following schema (version 1):
+//!
+//! ```text
+//! {
+//!   "schema_version": 1,
+//!   "crate_version": string,
+//!   "features": [string],           // the cargo features the crate was built with
+//!   "device": { "persistent_id": number | null, "display_name": string | null } | null,
+//!   "driver_version": string | null,
+//!   // each negotiated value is { "requested": value | null, "actual": value | null }
+//!   "display_mode": negotiated string,     // DecklinkDisplayModeId variant name
+//!   "pixel_format": negotiated string,     // DecklinkPixelFormat variant name
+//!   "color_mode": negotiated string,       // CaptureColorMode variant name
+//!   "video_input_flags": number,           // DecklinkVideoInputFlags bits
+//!   "audio": negotiated { "sample_rate": string, "sample_type": string,
+//!                         "channel_count": number },
+//!   "audio_deferred": bool,                // audio input waited for video input
+//!   "allocator": string,                   // AllocatorKind variant name
+//!   "dimension_policy": string,            // DimensionPolicy variant name
+//!   "quirks": [string],                    // the names of the quirk rules applied
+//!   "dispatch": { "workers": number, "mode": string, "queue_capacity": number,
+//!                 "latency_window": number,
+//!                 "starvation_threshold": { "secs": number, "nanos": number } } | null,
+//!   "handler": string | null,              // component_hash of the handler's type name
+//!   "transform_stages": [string]           // component_hash of each stage's name, in order
+//! }
+//! ```
+//!
+//! Fields are added without changing the schema version, and a reader ignores fields it does
+//! not know. A change that an older reader would misread increases
+//! `EFFECTIVE_CONFIG_SCHEMA_VERSION`, so compare `EffectiveConfig::schema_version` with it
+//! before trusting the fields.
+
+use crate::device::input::{
+    CaptureColorMode, DecklinkAudioSampleRate, DecklinkAudioSampleType, DecklinkInputDevice,
+    DimensionPolicy,
+};
+use crate::dispatch::DispatchConfig;
+use crate::display_mode::DecklinkDisplayModeId;
+use crate::event::DeviceIdentity;
+use crate::experimental::quirks::QuirkApplied;
+use crate::experimental::transform::TransformChain;
+use crate::frame::DecklinkPixelFormat;
+use crate::manifest::{write_json_opt_string, write_json_string};
+use crate::verify::fnv1a;
+use std::fmt::Write as _;
+
+/// The version of the effective config schema that is written.
+pub const EFFECTIVE_CONFIG_SCHEMA_VERSION: u32 = 1;
+
+/// The cargo features of the crate, as `EffectiveConfig::features` lists them.
+const FEATURES: [(&str, bool); 10] = [
+    ("cuda", cfg!(feature = "cuda")),
+    ("leak-check", cfg!(feature = "leak-check")),
+    ("image-interop", cfg!(feature = "image-interop")),
+    ("thumbnail-jpeg", cfg!(feature = "thumbnail-jpeg")),
+    ("mock-backend", cfg!(feature = "mock-backend")),
+    ("container", cfg!(feature = "container")),
+    ("cli", cfg!(feature = "cli")),
+    ("realtime", cfg!(feature = "realtime")),
+    ("serde", cfg!(feature = "serde")),
+    ("raw-api", cfg!(feature = "raw-api")),
+];
+
+/// Hash the name of a user supplied component, such as a handler's type name, so reports can
+/// be matched without naming it. The hash is 16 hexadecimal digits of FNV-1a.
+pub fn component_hash(name: &str) -> String {
+    format!("{:016x}", fnv1a(name.as_bytes()))
+}
+
+/// A value as it was requested, and as it was negotiated. Either is `None` until known, and
+/// `actual` stays `None` if the request failed or is still waiting.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Negotiated<T> {
+    pub requested: Option<T>,
+    pub actual: Option<T>,
+}
+
+impl<T> Negotiated<T> {
+    /// A value that was used as requested.
+    pub fn granted(value: T) -> Negotiated<T>
+    where
+        T: Clone,
+    {
+        Negotiated {
+            requested: Some(value.clone()),
+            actual: Some(value),
+        }
+    }
+
+    /// Whether a value was negotiated that differs from the one requested.
+    pub fn differs(&self) -> bool
+    where
+        T: PartialEq,
+    {
+        self.actual.is_some() && self.requested != self.actual
+    }
+}
+
+impl<T> Default for Negotiated<T> {
+    fn default() -> Self {
+        Negotiated {
+            requested: None,
+            actual: None,
+        }
+    }
+}
+
+/// The audio input of a capture.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AudioSettings {
+    pub sample_rate: DecklinkAudioSampleRate,
+    pub sample_type: DecklinkAudioSampleType,
+    pub channel_count: u32,
+}
+
+/// Where the driver writes captured frames.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AllocatorKind {
+    /// The driver's own buffers.
+    #[default]
+    Driver,
+    /// Buffers of a `crate::allocator::VideoBufferAllocatorProvider`.
+    Provider,
+}
+
+/// The configuration a capture runs with. See the module documentation.
+#[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EffectiveConfig {
+    pub schema_version: u32,
+    /// The version of this crate.
+    pub crate_version: String,
+    /// The cargo features the crate was built with.
+    pub features: Vec<String>,
+    pub device: Option<DeviceIdentity>,
+    pub driver_version: Option<String>,
+    pub display_mode: Negotiated<DecklinkDisplayModeId>,
+    pub pixel_format: Negotiated<DecklinkPixelFormat>,
+    /// The color mode requested of `enable_video_input_with_color_mode`, and the one it
+    /// resolved to. Empty if video input was enabled another way.
+    pub color_mode: Negotiated<CaptureColorMode>,
+    /// The bits of the `DecklinkVideoInputFlags` video input was enabled with.
+    pub video_input_flags: u32,
+    pub audio: Negotiated<AudioSettings>,
+    /// Whether audio input was deferred by `request_audio_input` until video input was
+    /// enabled.
+    pub audio_deferred: bool,
+    pub allocator: AllocatorKind,
+    pub dimension_policy: DimensionPolicy,
+    /// The names of the quirk rules applied, each once, in the order they first fired.
+    pub quirks: Vec<String>,
+    pub dispatch: Option<DispatchConfig>,
+    /// The `component_hash` of the handler's `InputHandler::type_name`.
+    pub handler: Option<String>,
+    /// The `component_hash` of the name of each stage of the transform chain, in order.
+    pub transform_stages: Vec<String>,
+}
+
+impl Default for EffectiveConfig {
+    fn default() -> Self {
+        EffectiveConfig::new()
+    }
+}
+
+/// The configuration an input captures with.
+pub trait DecklinkEffectiveConfigExt {
+    /// Get the configuration this input captures with: each value as it was requested, and
+    /// as it was negotiated.
+    ///
+    /// Values are recorded as they are negotiated, so this makes no driver calls. Those of
+    /// video input are of the last time it was enabled, and remain after it is disabled. The
+    /// device, driver version, quirks, dispatch and transform stages are left empty, for the
+    /// caller to fill.
+    fn effective_config(&self) -> EffectiveConfig;
+}
+
+impl DecklinkEffectiveConfigExt for DecklinkInputDevice {
+    fn effective_config(&self) -> EffectiveConfig {
+        self.negotiated_config()
+    }
+}
+
+impl EffectiveConfig {
+    /// The config of this build of the crate, with nothing negotiated yet.
+    pub fn new() -> EffectiveConfig {
+        EffectiveConfig {
+            schema_version: EFFECTIVE_CONFIG_SCHEMA_VERSION,
+            crate_version: env!("CARGO_PKG_VERSION").to_string(),
+            features: FEATURES
+                .iter()
+                .filter(|(_, enabled)| *enabled)
+                .map(|(name, _)| name.to_string())
+                .collect(),
+            device: None,
+            driver_version: None,
+            display_mode: Negotiated::default(),
+            pixel_format: Negotiated::default(),
+            color_mode: Negotiated::default(),
+            video_input_flags: 0,
+            audio: Negotiated::default(),
+            audio_deferred: false,
+            allocator: AllocatorKind::default(),
+            dimension_policy: DimensionPolicy::default(),
+            quirks: Vec::new(),
+            dispatch: None,
+            handler: None,
+            transform_stages: Vec::new(),
+        }
+    }
+
+    /// Record the quirk rules a format detector applied, as reported by
+    /// `FormatDetector::take_applied` or the events of a recorder.
+    pub fn record_quirks<'a>(&mut self, applied: impl IntoIterator<Item = &'a QuirkApplied>) {
+        for quirk in applied {
+            if !self.quirks.contains(&quirk.rule) {
+                self.quirks.push(quirk.rule.clone());
+            }
+        }
+    }
+
+    /// Record the stages of the transform chain frames are given to.
+    pub fn record_transform_chain(&mut self, chain: &TransformChain) {
+        self.transform_stages = chain
+            .stats()
+            .iter()
+            .map(|stage| component_hash(&stage.name))
+            .collect();
+    }
+
+    /// Record video input enabled as requested, with no color mode.
+    pub(crate) fn record_video(
+        &mut self,
+        mode: DecklinkDisplayModeId,
+        pixel_format: DecklinkPixelFormat,
+        flags: u32,
+        allocator: AllocatorKind,
+    ) {
+        self.display_mode = Negotiated::granted(mode);
+        self.pixel_format = Negotiated::granted(pixel_format);
+        self.color_mode = Negotiated::default();
+        self.video_input_flags = flags;
+        self.allocator = allocator;
+    }
+
+    /// Serialize the config as JSON on one line, in the layout of the module documentation.
+    pub fn to_json(&self) -> String {
+        let mut out = String::new();
+        self.write_json(&mut out);
+        out
+    }
+
+    pub(crate) fn write_json(&self, out: &mut String) {
+        let _ = write!(
+            out,
+            "{{\"schema_version\":{},\"crate_version\":",
+            self.schema_version
+        );
+        write_json_string(out, &self.crate_version);
+        out.push_str(",\"features\":");
+        write_json_strings(out, &self.features);
+        out.push_str(",\"device\":");
+        match &self.device {
+            Some(device) => {
+                let _ = write!(
+                    out,
+                    "{{\"persistent_id\":{},\"display_name\":",
+                    device
+                        .persistent_id
+                        .map_or_else(|| "null".to_string(), |id| id.to_string())
+                );
+                write_json_opt_string(out, &device.display_name);
+                out.push('}');
+            }
+            None => out.push_str("null"),
+        }
+        out.push_str(",\"driver_version\":");
+        write_json_opt_string(out, &self.driver_version);
+        out.push_str(",\"display_mode\":");
+        write_negotiated(out, &self.display_mode, |out, mode| {
+            let _ = write!(out, "\"{:?}\"", mode);
+        });
+        out.push_str(",\"pixel_format\":");
+        write_negotiated(out, &self.pixel_format, |out, format| {
+            let _ = write!(out, "\"{:?}\"", format);
+        });
+        out.push_str(",\"color_mode\":");
+        write_negotiated(out, &self.color_mode, |out, color_mode| {
+            let _ = write!(out, "\"{:?}\"", color_mode);
+        });
+        let _ = write!(
+            out,
+            ",\"video_input_flags\":{},\"audio\":",
+            self.video_input_flags
+        );
+        write_negotiated(out, &self.audio, |out, audio| {
+            let _ = write!(
+                out,
+                "{{\"sample_rate\":\"{:?}\",\"sample_type\":\"{:?}\",\"channel_count\":{}}}",
+                audio.sample_rate, audio.sample_type, audio.channel_count
+            );
+        });
+        let _ = write!(
+            out,
+            ",\"audio_deferred\":{},\"allocator\":\"{:?}\",\"dimension_policy\":\"{:?}\",\"quirks\":",
+            self.audio_deferred, self.allocator, self.dimension_policy
+        );
+        write_json_strings(out, &self.quirks);
+        out.push_str(",\"dispatch\":");
+        match &self.dispatch {
+            Some(dispatch) => {
+                let _ = write!(
+                    out,
+                    "{{\"workers\":{},\"mode\":\"{:?}\",\"queue_capacity\":{},\"latency_window\":{},\
+                     \"starvation_threshold\":{{\"secs\":{},\"nanos\":{}}}}}",
+                    dispatch.workers,
+                    dispatch.mode,
+                    dispatch.queue_capacity,
+                    dispatch.latency_window,
+                    dispatch.starvation_threshold.as_secs(),
+                    dispatch.starvation_threshold.subsec_nanos()
+                );
+            }
+            None => out.push_str("null"),
+        }
+        out.push_str(",\"handler\":");
+        write_json_opt_string(out, &self.handler);
+        out.push_str(",\"transform_stages\":");
+        write_json_strings(out, &self.transform_stages);
+        out.push('}');
+    }
+}
+
+fn write_json_strings(out: &mut String, strings: &[String]) {
+    out.push('[');
+    for (i, s) in strings.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        write_json_string(out, s);
+    }
+    out.push(']');
+}
+
+fn write_negotiated<T>(
+    out: &mut String,
+    value: &Negotiated<T>,
+    write_value: impl Fn(&mut String, &T),
+) {
+    for (i, (name, v)) in [("requested", &value.requested), ("actual", &value.actual)]
+        .into_iter()
+        .enumerate()
+    {
+        out.push_str(if i == 0 { "{\"" } else { ",\"" });
+        out.push_str(name);
+        out.push_str("\":");
+        match v {
+            Some(v) => write_value(out, v),
+            None => out.push_str("null"),
+        }
+    }
+    out.push('}');
+}
@@ -0,0 +1,351 @@
+//! One schema for the events of every module, for services that persist or forward them.
+//!
+//! Each module reports events of its own type, such as `crate::latency::LatencyEvent` or
+//! `crate::conformance::ConformanceWarning`. Each of these converts into an `EventPayload`,
+//! and an `EventRecorder` stamps payloads into `DecklinkEvent`s, with a sequence number,
+//! the stream time and wall clock time they happened at, and the device they came from. The
+//! recorder keeps the most recent events in a ring, for a consumer to take.
+//!
+//! `DecklinkEvent::kind_str` names the kind of payload, which suits metric labels and topic
+//! names. `EventPayload` is `non_exhaustive`, as kinds are added, so match it with a wildcard
+//! arm.
+//!
+//! # Serialization
+//!
+//! With the `serde` feature, events serialize with the following schema (version 1):
+//!
+//! ```text
+//! {
+//!   "schema_version": 1,
+//!   "sequence": number,             // counts up from 0 for each EventRecorder
+//!   "stream_time": { "value": number, "scale": number } | null,
+//!   "wall_clock": { "secs_since_epoch": number, "nanos_since_epoch": number },
+//!   "device": { "persistent_id": number | null, "display_name": string | null } | null,
+//!   "kind": string,                 // DecklinkEvent::kind_str
+//!   "<kind>": { ... }               // the payload, in a field named by "kind"
+//! }
+//! ```
+//!
+//! The payload is adjacent to its tag, in a field of the same name rather than one field for
+//! every kind, so that a consumer that does not know a kind skips its payload as an unknown
+//! field and still reads the rest of the event. It deserializes as `EventPayload::Unknown`,
+//! with `DecklinkEvent::is_unknown` true. An unknown event serializes again without its
+//! payload, so forward the original data rather than re-serializing it.
+//!
+//! Kinds and fields are added without changing the schema version. A change that a consumer
+//! of an older version would misread, such as a new variant of an existing payload or a
+//! field removed or changed, increases `EVENT_SCHEMA_VERSION`, so consumers should compare
+//! `DecklinkEvent::schema_version` with it before trusting a known kind's payload.
+
+use crate::allocator::AllocatorEvent;
+use crate::audio_continuity::AudioContinuityEvent;
+use crate::capture_group::GroupEvent;
+use crate::conformance::ConformanceWarning;
+use crate::cpu::CpuEvent;
+use crate::device::input::AudioEnableEvent;
+use crate::device::DecklinkDevice;
+use crate::dispatch::DispatchEvent;
+use crate::dual::DualFormatEvent;
+use crate::experimental::quirks::QuirkApplied;
+use crate::experimental::tearing::TearingEvent;
+use crate::experimental::transform::TransformEvent;
+use crate::external::ExternalUseEvent;
+use crate::latency::LatencyEvent;
+use crate::manifest::ManifestEvent;
+use crate::settle::SettleProgress;
+use crate::soak::SoakEvent;
+use crate::threads::ThreadEvent;
+use crate::time::DecklinkTime;
+use crate::timecode::TimecodeEvent;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+/// The version of the event schema that is written.
+pub const EVENT_SCHEMA_VERSION: u32 = 1;
+
+/// The device an event came from.
+#[derive(PartialEq, Eq, Debug, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DeviceIdentity {
+    pub persistent_id: Option<i64>,
+    pub display_name: Option<String>,
+}
+
+impl DeviceIdentity {
+    pub fn of(device: &DecklinkDevice) -> DeviceIdentity {
+        DeviceIdentity {
+            persistent_id: device.persistent_id(),
+            display_name: device.display_name(),
+        }
+    }
+}
+
+macro_rules! event_payloads {
+    ($($(#[$doc:meta])* $variant:ident($payload:ty) = $kind:ident,)*) => {
+        /// What happened, as reported by the module it happened in.
+        #[derive(PartialEq, Debug, Clone)]
+        #[non_exhaustive]
+        pub enum EventPayload {
+            $($(#[$doc])* $variant($payload),)*
+            /// An event of a kind this version of the crate does not know, deserialized from
+            /// a newer one.
+            Unknown { kind: String },
+        }
+
+        impl EventPayload {
+            /// The name of the kind of payload, as the `kind` field of serialized events.
+            pub fn kind_str(&self) -> &str {
+                match self {
+                    $(EventPayload::$variant(_) => stringify!($kind),)*
+                    EventPayload::Unknown { kind } => kind,
+                }
+            }
+        }
+
+        $(
+            impl From<$payload> for EventPayload {
+                fn from(payload: $payload) -> Self {
+                    EventPayload::$variant(payload)
+                }
+            }
+        )*
+
+        #[cfg(feature = "serde")]
+        mod wire {
+            use super::*;
+
+            /// An event as it is serialized, with a field for the payload of each kind.
+            #[derive(serde::Serialize, serde::Deserialize)]
+            pub(super) struct WireEvent {
+                schema_version: u32,
+                sequence: u64,
+                stream_time: Option<DecklinkTime>,
+                wall_clock: SystemTime,
+                device: Option<DeviceIdentity>,
+                kind: String,
+                $(
+                    #[serde(default, skip_serializing_if = "Option::is_none")]
+                    $kind: Option<$payload>,
+                )*
+            }
+
+            impl From<DecklinkEvent> for WireEvent {
+                fn from(event: DecklinkEvent) -> Self {
+                    let mut wire = WireEvent {
+                        schema_version: event.schema_version,
+                        sequence: event.sequence,
+                        stream_time: event.stream_time,
+                        wall_clock: event.wall_clock,
+                        device: event.device,
+                        kind: event.payload.kind_str().to_string(),
+                        $($kind: None,)*
+                    };
+                    match event.payload {
+                        $(EventPayload::$variant(payload) => wire.$kind = Some(payload),)*
+                        EventPayload::Unknown { .. } => {}
+                    }
+                    wire
+                }
+            }
+
+            impl TryFrom<WireEvent> for DecklinkEvent {
+                type Error = String;
+
+                fn try_from(wire: WireEvent) -> Result<Self, Self::Error> {
+                    let kind = wire.kind;
+                    let missing = || format!("the event has no payload for its kind {:?}", kind);
+                    let payload = match kind.as_str() {
+                        $(stringify!($kind) => {
+                            EventPayload::$variant(wire.$kind.ok_or_else(missing)?)
+                        })*
+                        _ => EventPayload::Unknown { kind: kind.clone() },
+                    };
+                    Ok(DecklinkEvent {
+                        schema_version: wire.schema_version,
+                        sequence: wire.sequence,
+                        stream_time: wire.stream_time,
+                        wall_clock: wire.wall_clock,
+                        device: wire.device,
+                        payload,
+                    })
+                }
+            }
+        }
+    };
+}
+
+event_payloads! {
+    Conformance(ConformanceWarning) = conformance,
+    /// A transition of a `crate::settle::DetectionSettle`.
+    Settle(SettleProgress) = settle,
+    /// A quirk rule fired in a `crate::format_detect::FormatDetector`.
+    Quirk(QuirkApplied) = quirk,
+    Latency(LatencyEvent) = latency,
+    /// A stage over or back under its budget in a `crate::cpu::CpuMeter`.
+    Cpu(CpuEvent) = cpu,
+    Timecode(TimecodeEvent) = timecode,
+    AudioContinuity(AudioContinuityEvent) = audio_continuity,
+    Dispatch(DispatchEvent) = dispatch,
+    Transform(TransformEvent) = transform,
+    CaptureGroup(GroupEvent) = capture_group,
+    /// An event of a capture, as recorded in its manifest.
+    Capture(ManifestEvent) = capture,
+    ExternalUse(ExternalUseEvent) = external_use,
+    /// A frame suspected of tearing by a `crate::experimental::tearing::TearDetector`.
+    Tearing(TearingEvent) = tearing,
+    /// A thread of the crate started, exited, was joined or panicked.
+    Thread(ThreadEvent) = thread,
+    /// A negotiation or shedding of the streams of a `crate::dual::DualFormatSplitter`.
+    DualFormat(DualFormatEvent) = dual_format,
+    /// A change of the buffers the driver asks an allocator provider for.
+    Allocator(AllocatorEvent) = allocator,
+    /// Audio input of an input waiting for video input to be enabled, as the driver needs.
+    AudioEnable(AudioEnableEvent) = audio_enable,
+    /// A perturbation or an invariant violation of a `crate::soak` run.
+    Soak(SoakEvent) = soak,
+}
+
+impl EventPayload {
+    /// The manifest entry of the payload, for payloads that are recorded in a capture's
+    /// manifest.
+    pub fn manifest_event(&self) -> Option<ManifestEvent> {
+        match self {
+            EventPayload::Capture(event) => Some(event.clone()),
+            EventPayload::AudioContinuity(event) => event.manifest_event(),
+            EventPayload::CaptureGroup(event) => event.manifest_event(),
+            _ => None,
+        }
+    }
+}
+
+/// An event, stamped by an `EventRecorder`.
+#[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(into = "wire::WireEvent", try_from = "wire::WireEvent")
+)]
+pub struct DecklinkEvent {
+    /// The version of the schema the event was made with, `EVENT_SCHEMA_VERSION` for events
+    /// recorded by this version of the crate.
+    pub schema_version: u32,
+    /// The position of the event among those of its recorder, counting from zero.
+    pub sequence: u64,
+    /// The stream time the event happened at, for events tied to a frame or packet.
+    pub stream_time: Option<DecklinkTime>,
+    pub wall_clock: SystemTime,
+    pub device: Option<DeviceIdentity>,
+    pub payload: EventPayload,
+}
+
+impl DecklinkEvent {
+    /// The name of the kind of payload, as `EventPayload::kind_str`.
+    pub fn kind_str(&self) -> &str {
+        self.payload.kind_str()
+    }
+
+    /// Whether the payload is of a kind this version of the crate does not know.
+    pub fn is_unknown(&self) -> bool {
+        matches!(self.payload, EventPayload::Unknown { .. })
+    }
+}
+
+struct RecorderState {
+    next_sequence: u64,
+    events: VecDeque<DecklinkEvent>,
+    overwritten: u64,
+}
+
+/// Stamps the events of one device and keeps the most recent `capacity` of them.
+///
+/// The recorder can be shared between threads, such as a capture callback and a thread
+/// forwarding its events.
+pub struct EventRecorder {
+    device: Option<DeviceIdentity>,
+    capacity: usize,
+    state: Mutex<RecorderState>,
+}
+
+impl EventRecorder {
+    /// A recorder for events of `device`, or of no one device, keeping at least one event.
+    pub fn new(device: Option<DeviceIdentity>, capacity: usize) -> EventRecorder {
+        EventRecorder {
+            device,
+            capacity: capacity.max(1),
+            state: Mutex::new(RecorderState {
+                next_sequence: 0,
+                events: VecDeque::new(),
+                overwritten: 0,
+            }),
+        }
+    }
+
+    /// Record `payload`, which happened at `stream_time`, and return its sequence number.
+    /// The oldest event is overwritten if the ring is full.
+    pub fn record(
+        &self,
+        payload: impl Into<EventPayload>,
+        stream_time: Option<DecklinkTime>,
+    ) -> u64 {
+        let mut state = self.state.lock().unwrap();
+        let sequence = state.next_sequence;
+        state.next_sequence += 1;
+        if state.events.len() == self.capacity {
+            state.events.pop_front();
+            state.overwritten += 1;
+        }
+        state.events.push_back(DecklinkEvent {
+            schema_version: EVENT_SCHEMA_VERSION,
+            sequence,
+            stream_time,
+            wall_clock: SystemTime::now(),
+            device: self.device.clone(),
+            payload: payload.into(),
+        });
+        sequence
+    }
+
+    /// Record each of `payloads`, such as those a module's `take_events` returns.
+    pub fn record_all<P: Into<EventPayload>>(
+        &self,
+        payloads: impl IntoIterator<Item = P>,
+        stream_time: Option<DecklinkTime>,
+    ) {
+        for payload in payloads {
+            self.record(payload, stream_time);
+        }
+    }
+
+    /// Copies of the last `count` events not yet taken, oldest first, leaving them in the
+    /// ring.
+    pub fn recent(&self, count: usize) -> Vec<DecklinkEvent> {
+        let state = self.state.lock().unwrap();
+        let skip = state.events.len().saturating_sub(count);
+        state.events.iter().skip(skip).cloned().collect()
+    }
+
+    /// The number of events in the ring, at most the capacity.
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Take the events recorded since the last call, oldest first.
+    pub fn take_events(&self) -> Vec<DecklinkEvent> {
+        self.state.lock().unwrap().events.drain(..).collect()
+    }
+
+    /// The number of events overwritten before they were taken. A gap in the sequence
+    /// numbers of the events taken shows where.
+    pub fn overwritten(&self) -> u64 {
+        self.state.lock().unwrap().overwritten
+    }
+}
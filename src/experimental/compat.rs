@@ -0,0 +1,103 @@
+//! Callbacks shaped like the C++ SDK's, for porting applications built on its samples.
+//!
+//! A `ClassicInputCallback` has the two methods of `IDeckLinkInputCallback`, taking the video
+//! frame and audio packet of a callback together and returning a `CallbackResult` in place
+//! of an `HRESULT`. Wrap it in a `ClassicInputAdapter` to set it on an input.
+//!
+//! The rest of a port maps as follows:
+//!
+//! - `AddRef` on a frame to keep it after the callback returns: keep the `DecklinkVideoFrame`,
+//!   which already holds its own reference, and `Release` is dropping it. As in C++, frames
+//!   held past the callback keep buffers out of the capture pool, so hold only a few, and
+//!   count them with `crate::retention::RetentionBudget::retain`.
+//! - A frame used only during the callback needs nothing, as both are dropped when it
+//!   returns, as the driver's reference would be released.
+//! - `AddRef` on an audio packet to hand it to another thread: packets cannot leave the
+//!   callback thread, so copy the samples out with `DecklinkAudioInputPacket::bytes` and
+//!   rebuild a packet with `DecklinkAudioInputPacket::from_samples` where it is needed.
+//! - `AddRef` and `Release` on the callback object: the adapter is held in an `Arc`, which
+//!   the input keeps from `set_callback` until the callback is replaced or the input is
+//!   dropped.
+//! - `IDeckLinkVideoInputFrame::GetStreamTime` in the display mode's timescale: the `timing`
+//!   passed with the frame.
+//! - Calls on `IDeckLinkInput` from the callback, as when restarting the input after a
+//!   format change: the input cannot be shared with the callback, so send what to do to the
+//!   thread that owns it, as the `capture_preview` example does.
+//!
+//! This module is experimental, see `crate::experimental`: the adapter is expected to change
+//! as more of the SDK's callbacks are given classic forms.
+
+use crate::device::input::{
+    ArrivalFlags, CallbackResult, DecklinkAudioInputPacket, DecklinkDetectedVideoInputFormatFlags,
+    DecklinkVideoInputFormatChangedEvents, FrameArrival, InputFormatChange, InputHandler,
+};
+use crate::display_mode::DecklinkDisplayModeId;
+use crate::frame::DecklinkVideoFrame;
+use crate::time::DecklinkFrameTiming;
+use std::sync::Arc;
+
+/// The methods of `IDeckLinkInputCallback`.
+pub trait ClassicInputCallback: Send + Sync {
+    /// `VideoInputFormatChanged`. The driver ignores its result, as the C++ one does.
+    fn video_input_format_changed(
+        &self,
+        notification_events: DecklinkVideoInputFormatChangedEvents,
+        new_display_mode: DecklinkDisplayModeId,
+        detected_signal_flags: DecklinkDetectedVideoInputFormatFlags,
+    ) -> CallbackResult;
+
+    /// `VideoInputFrameArrived`. Either the frame or the packet may be `None`, when the
+    /// callback carried no video or no audio. `timing` is the stream time of the frame, if
+    /// there is one.
+    fn video_input_frame_arrived(
+        &self,
+        video_frame: Option<DecklinkVideoFrame>,
+        audio_packet: Option<DecklinkAudioInputPacket>,
+        timing: Option<DecklinkFrameTiming>,
+    ) -> CallbackResult;
+}
+
+/// Implements `InputHandler` on a `ClassicInputCallback`.
+///
+/// The result of `ClassicInputCallback::video_input_frame_arrived` is returned to the
+/// driver, also when the adapter is wrapped by another handler, such as
+/// `crate::row_bytes::RowBytesValidator::callback`.
+///
+/// Frames that arrive but cannot be read never reach the classic callback, though their
+/// audio does. They are counted by `DecklinkInputDevice::frame_conversion_failure_count`.
+pub struct ClassicInputAdapter {
+    callback: Arc<dyn ClassicInputCallback>,
+}
+
+impl ClassicInputAdapter {
+    pub fn new(callback: Arc<dyn ClassicInputCallback>) -> ClassicInputAdapter {
+        ClassicInputAdapter { callback }
+    }
+}
+
+impl InputHandler for ClassicInputAdapter {
+    fn format_changed(&self, change: &InputFormatChange) {
+        self.callback.video_input_format_changed(
+            change.events,
+            change.display_mode,
+            change.detected_signal_flags,
+        );
+    }
+
+    fn frame_arrived(&self, arrival: &FrameArrival<'_>) -> CallbackResult {
+        if arrival.flags.contains(ArrivalFlags::FRAME_LOST) {
+            // The driver is told the frame was handled, whatever the callback makes of the
+            // audio
+            if let Some(packet) = arrival.retain_audio_packet() {
+                self.callback
+                    .video_input_frame_arrived(None, Some(packet), None);
+            }
+            return CallbackResult::Ok;
+        }
+        self.callback.video_input_frame_arrived(
+            arrival.retain_video_frame(),
+            arrival.retain_audio_packet(),
+            arrival.timing(),
+        )
+    }
+}
@@ -0,0 +1,16 @@
+//! Subsystems whose api has not settled yet.
+//!
+//! Everything under `experimental` may change in a minor release: types may gain fields and
+//! variants, and functions may change their signatures. It is kept separate from the stable
+//! modules so that code built on it says so in its imports. A module leaves `experimental`
+//! once its api has held for a release, and keeps a deprecated path here for a release after
+//! that.
+
+pub mod compat;
+pub mod quirks;
+pub mod tearing;
+pub mod transform;
+
+#[cfg(feature = "container")]
+#[cfg_attr(docsrs, doc(cfg(feature = "container")))]
+pub mod mov;
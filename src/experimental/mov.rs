@@ -0,0 +1,1177 @@
+//! Writing captured video and audio into QuickTime movie files, without ffmpeg.
+//!
+//! `MovWriter` stores 8-bit YUV frames as `2vuy` and 10-bit YUV frames as `v210`, both
+//! uncompressed, with each row padded as the format requires (`v210` rows to a multiple of
+//! 48 pixels, `2vuy` rows not at all). Audio is stored as interleaved little-endian PCM.
+//!
+//! Sample times come from the stream times the driver gives, so playback is frame accurate.
+//! Frames dropped before a frame lengthen the duration of the frame before the gap, which
+//! keeps the frames after it at their own times, and gaps in the audio are filled with
+//! silence. The audio track is placed against the video by the time of its first packet,
+//! with an edit list.
+//!
+//! Sample data is written in `mdat` boxes with 64-bit sizes and chunk offsets, so files may
+//! pass 4GB. The `moov` box that indexes the samples is written when the file is finished,
+//! and also after every `MovConfig::flush_interval` of video, so a file cut short by a crash
+//! plays up to its last flush. A flush writes a new `moov` after the samples so far, turns
+//! the previous one into a `free` box, and continues the samples in a new `mdat` box that
+//! runs to the end of the file until the next flush.
+//!
+//! `SegmentedMovWriter` writes a file per segment, as placed by a
+//! `crate::segment::Segmenter`. A format change always ends a file: the file in progress
+//! ends with the last frame in the old format, and the first frame in the new format starts
+//! the next file, with the frame size, pixel format and frame duration of that frame.
+//!
+//! This module is experimental, see `crate::experimental`: `MovConfig` is expected to gain
+//! fields as more pixel formats and codecs are written.
+
+use crate::colorimetry::{Colorimetry, TransferFunction};
+use crate::config::ConfigError;
+use crate::device::input::{DecklinkAudioInputPacket, DecklinkAudioSampleType};
+use crate::display_mode::{DecklinkDisplayMode, DecklinkFieldDominance};
+use crate::frame::{DecklinkFrameBase, DecklinkPixelFormat};
+use crate::segment::{
+    segment_path, SegmentBoundary, SegmentInfo, SegmentPolicy, SegmentSink, Segmenter,
+};
+use crate::time::{DecklinkFrameTiming, DecklinkTime};
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// The longest gap in the audio that is filled with silence. A longer gap is taken to be a
+/// jump in the packet times rather than lost audio, and the audio carries on without one.
+const MAX_SILENCE: Duration = Duration::from_secs(10);
+
+/// The format of the audio track.
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub struct MovAudioConfig {
+    pub sample_rate: u32,
+    pub channels: u32,
+    pub sample_type: DecklinkAudioSampleType,
+}
+
+impl MovAudioConfig {
+    fn frame_bytes(&self) -> usize {
+        let sample_bytes = match self.sample_type {
+            DecklinkAudioSampleType::Int16 => 2,
+            DecklinkAudioSampleType::Int32 => 4,
+        };
+        self.channels as usize * sample_bytes
+    }
+}
+
+/// How a `MovWriter` writes its file. The frame size and pixel format are taken from the
+/// first frame written.
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub struct MovConfig {
+    /// The duration of a frame, whose scale is the timescale of the video track. Frames
+    /// written without timing last this long.
+    pub frame_duration: DecklinkTime,
+    /// Recorded in a `fiel` box, which is left out when the dominance is unknown.
+    pub field_dominance: DecklinkFieldDominance,
+    /// The colour matrix recorded in a `colr` box, or the usual one for the frame height
+    /// when `None`.
+    pub colorimetry: Option<Colorimetry>,
+    /// The format of the audio track, or `None` for a file with only video.
+    pub audio: Option<MovAudioConfig>,
+    /// How much video is written between flushes of the index, or `None` to write the
+    /// index only when the file is finished.
+    pub flush_interval: Option<Duration>,
+}
+
+impl MovConfig {
+    pub fn new(frame_duration: DecklinkTime) -> MovConfig {
+        MovConfig {
+            frame_duration,
+            field_dominance: DecklinkFieldDominance::Unknown,
+            colorimetry: None,
+            audio: None,
+            flush_interval: Some(Duration::from_secs(10)),
+        }
+    }
+
+    /// The config for frames in a display mode, or `None` if the mode has no frame
+    /// duration.
+    pub fn for_mode(mode: &DecklinkDisplayMode) -> Option<MovConfig> {
+        let mut config = MovConfig::new(mode.frame_duration()?);
+        config.field_dominance = mode.field_dominance();
+        Some(config)
+    }
+
+    pub fn with_audio(mut self, audio: MovAudioConfig) -> MovConfig {
+        self.audio = Some(audio);
+        self
+    }
+
+    /// Start building a config, from the defaults of `MovConfig::new`.
+    pub fn builder(frame_duration: DecklinkTime) -> MovConfigBuilder {
+        MovConfigBuilder {
+            config: MovConfig::new(frame_duration),
+        }
+    }
+
+    /// Check that a file could be written with this config.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let error = |field, problem| Err(ConfigError::new("MovConfig", field, problem));
+        if self.frame_duration.value <= 0 || self.frame_duration.scale <= 0 {
+            return error(
+                "frame_duration",
+                "must be a positive duration in a positive timescale, such as \
+                 DecklinkDisplayMode::frame_duration gives",
+            );
+        }
+        if let Some(audio) = &self.audio {
+            if audio.sample_rate == 0 {
+                return error("audio", "has a sample rate of 0");
+            }
+            if audio.channels == 0 {
+                return error(
+                    "audio",
+                    "has no channels, use None for a file without audio",
+                );
+            }
+        }
+        if self
+            .flush_interval
+            .is_some_and(|interval| interval.is_zero())
+        {
+            return error(
+                "flush_interval",
+                "must be longer than zero, or None to write the index when the file is finished",
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Builds a `MovConfig`.
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub struct MovConfigBuilder {
+    config: MovConfig,
+}
+
+impl MovConfigBuilder {
+    pub fn field_dominance(mut self, field_dominance: DecklinkFieldDominance) -> Self {
+        self.config.field_dominance = field_dominance;
+        self
+    }
+
+    pub fn colorimetry(mut self, colorimetry: Colorimetry) -> Self {
+        self.config.colorimetry = Some(colorimetry);
+        self
+    }
+
+    pub fn audio(mut self, audio: MovAudioConfig) -> Self {
+        self.config.audio = Some(audio);
+        self
+    }
+
+    pub fn flush_interval(mut self, flush_interval: Option<Duration>) -> Self {
+        self.config.flush_interval = flush_interval;
+        self
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        self.config.validate()
+    }
+
+    pub fn build(self) -> Result<MovConfig, ConfigError> {
+        self.validate()?;
+        Ok(self.config)
+    }
+}
+
+/// What was written to a finished file.
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub struct MovInfo {
+    pub video_frames: u64,
+    /// The number of gaps in the video, where frames were dropped.
+    pub video_gaps: u64,
+    /// The duration of the video track, in its timescale.
+    pub video_duration: DecklinkTime,
+    /// The number of audio sample frames, including any silence filling gaps.
+    pub audio_frames: u64,
+    pub silence_frames: u64,
+    pub byte_count: u64,
+}
+
+/// The runs of equal sample durations, as stored in an `stts` box.
+#[derive(Default)]
+struct TimeToSample {
+    runs: Vec<(u32, u32)>,
+    total: u64,
+}
+
+impl TimeToSample {
+    fn push(&mut self, duration: u32) {
+        match self.runs.last_mut() {
+            Some((count, delta)) if *delta == duration => *count += 1,
+            _ => self.runs.push((1, duration)),
+        }
+        self.total += duration as u64;
+    }
+
+    /// Lengthen the last sample, to cover a gap after it.
+    fn extend_last(&mut self, extra: u32) {
+        if let Some((count, delta)) = self.runs.last_mut() {
+            let duration = delta.saturating_add(extra);
+            if *count == 1 {
+                *delta = duration;
+            } else {
+                *count -= 1;
+                self.runs.push((1, duration));
+            }
+            self.total += extra as u64;
+        }
+    }
+}
+
+struct VideoTrack {
+    width: usize,
+    height: usize,
+    pixel_format: DecklinkPixelFormat,
+    fourcc: &'static [u8; 4],
+    /// The bytes in each stored row.
+    row_bytes: usize,
+    timescale: i64,
+    durations: TimeToSample,
+    offsets: Vec<u64>,
+    gaps: u64,
+    /// The stream time of the first frame, which the audio is placed against.
+    origin: Option<DecklinkTime>,
+    /// The stream time the next frame is expected at.
+    expected: Option<DecklinkTime>,
+}
+
+struct AudioTrack {
+    config: MovAudioConfig,
+    /// The offset and sample frame count of each chunk.
+    chunks: Vec<(u64, u32)>,
+    frames: u64,
+    silence_frames: u64,
+    /// The packet time of the first sample, in ticks of the sample rate.
+    start: Option<i64>,
+}
+
+/// The codec type and compressor name of the formats that are stored.
+fn video_format(pixel_format: DecklinkPixelFormat) -> Option<(&'static [u8; 4], &'static str)> {
+    match pixel_format {
+        DecklinkPixelFormat::Format8BitYUV => Some((b"2vuy", "Component Y'CbCr 8-bit 4:2:2")),
+        DecklinkPixelFormat::Format10BitYUV => Some((b"v210", "Component Y'CbCr 10-bit 4:2:2")),
+        _ => None,
+    }
+}
+
+fn invalid_input(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message.to_string())
+}
+
+/// Convert `value` ticks of `from` per second to ticks of `to` per second, rounding down.
+fn rescale(value: u64, from: u64, to: u64) -> u64 {
+    (value as u128 * to as u128)
+        .checked_div(from as u128)
+        .map_or(0, |v| v as u64)
+}
+
+fn put_u16(out: &mut Vec<u8>, v: u16) {
+    out.extend_from_slice(&v.to_be_bytes());
+}
+
+fn put_u32(out: &mut Vec<u8>, v: u32) {
+    out.extend_from_slice(&v.to_be_bytes());
+}
+
+fn put_u64(out: &mut Vec<u8>, v: u64) {
+    out.extend_from_slice(&v.to_be_bytes());
+}
+
+/// Write a box of type `kind`, whose contents are written by `body`.
+fn atom(out: &mut Vec<u8>, kind: &[u8; 4], body: impl FnOnce(&mut Vec<u8>)) {
+    let start = out.len();
+    out.extend_from_slice(&[0; 4]);
+    out.extend_from_slice(kind);
+    body(out);
+    let size = (out.len() - start) as u32;
+    out[start..start + 4].copy_from_slice(&size.to_be_bytes());
+}
+
+/// Write a box with a version and flags.
+fn full_atom(
+    out: &mut Vec<u8>,
+    kind: &[u8; 4],
+    version: u8,
+    flags: u32,
+    body: impl FnOnce(&mut Vec<u8>),
+) {
+    atom(out, kind, |out| {
+        put_u32(out, (version as u32) << 24 | flags);
+        body(out);
+    });
+}
+
+/// Write a counted string of `len` bytes, padded with zeros.
+fn put_pascal(out: &mut Vec<u8>, s: &str, len: usize) {
+    let bytes = &s.as_bytes()[..s.len().min(len - 1)];
+    out.push(bytes.len() as u8);
+    out.extend_from_slice(bytes);
+    out.resize(out.len() + len - 1 - bytes.len(), 0);
+}
+
+fn put_matrix(out: &mut Vec<u8>) {
+    for v in [0x10000, 0, 0, 0, 0x10000, 0, 0, 0, 0x4000_0000] {
+        put_u32(out, v);
+    }
+}
+
+/// Write a field that is 32 bits in a version 0 box and 64 bits in a version 1 box.
+fn put_sized(out: &mut Vec<u8>, version: u8, v: u64) {
+    if version == 1 {
+        put_u64(out, v);
+    } else {
+        put_u32(out, v as u32);
+    }
+}
+
+fn version_for(duration: u64) -> u8 {
+    u8::from(duration > u32::MAX as u64)
+}
+
+fn write_handler(out: &mut Vec<u8>, component: &[u8; 4], subtype: &[u8; 4], name: &str) {
+    full_atom(out, b"hdlr", 0, 0, |out| {
+        out.extend_from_slice(component);
+        out.extend_from_slice(subtype);
+        put_u32(out, 0);
+        put_u32(out, 0);
+        put_u32(out, 0);
+        put_pascal(out, name, name.len() + 1);
+    });
+}
+
+fn write_data_info(out: &mut Vec<u8>) {
+    write_handler(out, b"dhlr", b"alis", "DataHandler");
+    atom(out, b"dinf", |out| {
+        full_atom(out, b"dref", 0, 0, |out| {
+            put_u32(out, 1);
+            // The samples are in this file
+            full_atom(out, b"alis", 0, 1, |_| {});
+        });
+    });
+}
+
+struct TrackHeader {
+    id: u32,
+    /// The duration in the movie timescale, after any edits.
+    duration: u64,
+    volume: u16,
+    width: u32,
+    height: u32,
+}
+
+fn write_track_header(out: &mut Vec<u8>, header: &TrackHeader) {
+    let version = version_for(header.duration);
+    // Enabled and used in the movie
+    full_atom(out, b"tkhd", version, 0x3, |out| {
+        put_sized(out, version, 0);
+        put_sized(out, version, 0);
+        put_u32(out, header.id);
+        put_u32(out, 0);
+        put_sized(out, version, header.duration);
+        put_u64(out, 0);
+        put_u16(out, 0);
+        put_u16(out, 0);
+        put_u16(out, header.volume);
+        put_u16(out, 0);
+        put_matrix(out);
+        put_u32(out, header.width << 16);
+        put_u32(out, header.height << 16);
+    });
+}
+
+fn write_media_header(out: &mut Vec<u8>, timescale: u32, duration: u64) {
+    let version = version_for(duration);
+    full_atom(out, b"mdhd", version, 0, |out| {
+        put_sized(out, version, 0);
+        put_sized(out, version, 0);
+        put_u32(out, timescale);
+        put_sized(out, version, duration);
+        // "und", packed as ISO 639-2/T
+        put_u16(out, 0x55c4);
+        put_u16(out, 0);
+    });
+}
+
+/// Write an edit list of `(duration in the movie timescale, media time)` entries, where a
+/// media time of -1 is an empty edit.
+fn write_edits(out: &mut Vec<u8>, edits: &[(u64, i64)]) {
+    let version = u8::from(
+        edits
+            .iter()
+            .any(|(d, t)| *d > u32::MAX as u64 || *t > i32::MAX as i64),
+    );
+    atom(out, b"edts", |out| {
+        full_atom(out, b"elst", version, 0, |out| {
+            put_u32(out, edits.len() as u32);
+            for (duration, media_time) in edits {
+                put_sized(out, version, *duration);
+                put_sized(out, version, *media_time as u64);
+                put_u32(out, 0x10000);
+            }
+        });
+    });
+}
+
+fn write_chunk_offsets(out: &mut Vec<u8>, offsets: impl ExactSizeIterator<Item = u64>) {
+    full_atom(out, b"co64", 0, 0, |out| {
+        put_u32(out, offsets.len() as u32);
+        for offset in offsets {
+            put_u64(out, offset);
+        }
+    });
+}
+
+/// Writes a QuickTime movie file of uncompressed video and PCM audio.
+///
+/// The file is finished when `finish` is called, or when the writer is dropped, ignoring
+/// any error. Until then it holds the samples up to the last flush.
+pub struct MovWriter {
+    path: PathBuf,
+    config: MovConfig,
+    file: BufWriter<File>,
+    /// The offset the next byte is written at.
+    position: u64,
+    /// The offset of the `mdat` box the samples are being written into.
+    mdat_start: u64,
+    /// The offset of the last `moov` box written.
+    moov_at: Option<u64>,
+    video: Option<VideoTrack>,
+    audio: Option<AudioTrack>,
+    /// The video duration at the last flush, in the video timescale.
+    flushed_at: u64,
+    finished: bool,
+}
+
+impl MovWriter {
+    /// Create the file at `path`, replacing any file there.
+    pub fn create(path: impl AsRef<Path>, config: MovConfig) -> io::Result<MovWriter> {
+        if config.frame_duration.scale <= 0 || config.frame_duration.value <= 0 {
+            return Err(invalid_input("the frame duration must be positive"));
+        }
+        let path = path.as_ref().to_path_buf();
+        let mut file = BufWriter::new(File::create(&path)?);
+
+        let mut header = Vec::new();
+        atom(&mut header, b"ftyp", |out| {
+            out.extend_from_slice(b"qt  ");
+            put_u32(out, 0x200);
+            out.extend_from_slice(b"qt  ");
+        });
+        let mdat_start = header.len() as u64;
+        header.extend_from_slice(&open_mdat_header());
+        file.write_all(&header)?;
+
+        Ok(MovWriter {
+            path,
+            config,
+            file,
+            position: header.len() as u64,
+            mdat_start,
+            moov_at: None,
+            video: None,
+            audio: config.audio.map(|config| AudioTrack {
+                config,
+                chunks: Vec::new(),
+                frames: 0,
+                silence_frames: 0,
+                start: None,
+            }),
+            flushed_at: 0,
+            finished: false,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn config(&self) -> &MovConfig {
+        &self.config
+    }
+
+    /// Whether a frame has the size and pixel format of the frames in the file, or the
+    /// file has no frames yet.
+    pub fn accepts<F: DecklinkFrameBase + ?Sized>(&self, frame: &F) -> bool {
+        self.video.as_ref().is_none_or(|video| {
+            frame.width() == video.width
+                && frame.height() == video.height
+                && frame.pixel_format() == video.pixel_format
+        })
+    }
+
+    fn write_sample(&mut self, data: &[u8]) -> io::Result<u64> {
+        let offset = self.position;
+        self.file.write_all(data)?;
+        self.position += data.len() as u64;
+        Ok(offset)
+    }
+
+    /// Write a frame, with its timing if known. Every frame in a file must have the size and
+    /// pixel format of the first.
+    pub fn write_frame<F: DecklinkFrameBase + ?Sized>(
+        &mut self,
+        frame: &F,
+        timing: Option<&DecklinkFrameTiming>,
+    ) -> io::Result<()> {
+        if self.video.is_none() {
+            let pixel_format = frame.pixel_format();
+            let (fourcc, _) = video_format(pixel_format)
+                .ok_or_else(|| invalid_input("the pixel format cannot be stored"))?;
+            let row_bytes = pixel_format
+                .bytes_per_row(frame.width())
+                .expect("stored pixel formats are uncompressed");
+            self.video = Some(VideoTrack {
+                width: frame.width(),
+                height: frame.height(),
+                pixel_format,
+                fourcc,
+                row_bytes,
+                timescale: self.config.frame_duration.scale,
+                durations: TimeToSample::default(),
+                offsets: Vec::new(),
+                gaps: 0,
+                origin: None,
+                expected: None,
+            });
+        }
+        if !self.accepts(frame) {
+            return Err(invalid_input(
+                "the frame differs in size or pixel format from the first frame of the file",
+            ));
+        }
+        let video = self.video.as_ref().expect("set above");
+        let (row_bytes, timescale) = (video.row_bytes, video.timescale);
+        // Read the frame by its own size, even though it was checked to match the file's
+        let height = frame.height();
+        if frame.row_bytes() < row_bytes {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "the frame rows are shorter than the pixel format needs",
+            ));
+        }
+
+        let bytes = frame
+            .bytes()
+            .map_err(|e| io::Error::other(format!("{:?}", e)))?;
+        let data = bytes
+            .0
+            .get(..frame.row_bytes() * height)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "frame is truncated"))?;
+        let offset = if frame.row_bytes() == row_bytes {
+            self.write_sample(data)?
+        } else {
+            // Drop the extra padding the driver added to each row
+            let offset = self.position;
+            for row in data.chunks_exact(frame.row_bytes()) {
+                self.write_sample(&row[..row_bytes])?;
+            }
+            offset
+        };
+
+        let default_duration = self.config.frame_duration;
+        let video = self.video.as_mut().expect("set above");
+        let duration = timing
+            .and_then(|t| t.duration.rescale(timescale))
+            .unwrap_or(default_duration)
+            .value
+            .clamp(0, u32::MAX as i64) as u32;
+        if let Some(stream_time) = timing.and_then(|t| t.stream_time.rescale(timescale)) {
+            if let Some(expected) = video.expected {
+                let gap = stream_time.value - expected.value;
+                if gap > 0 {
+                    video.durations.extend_last(gap.min(u32::MAX as i64) as u32);
+                    video.gaps += 1;
+                }
+            }
+            video.origin.get_or_insert(stream_time);
+            video.expected = Some(DecklinkTime::new(
+                stream_time.value + duration as i64,
+                timescale,
+            ));
+        }
+        video.durations.push(duration);
+        video.offsets.push(offset);
+
+        if let Some(interval) = self.config.flush_interval {
+            let since_flush = video.durations.total - self.flushed_at;
+            if since_flush as u128 * 1_000_000_000 >= interval.as_nanos() * timescale as u128 {
+                self.flush()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Write a packet of audio. The packet must have the sample type and channel count of
+    /// `MovConfig::audio`.
+    pub fn write_audio(&mut self, packet: &DecklinkAudioInputPacket) -> io::Result<()> {
+        let audio = self
+            .audio
+            .as_ref()
+            .ok_or_else(|| invalid_input("the file has no audio track"))?;
+        let config = audio.config;
+        if packet.sample_type() != config.sample_type || packet.channel_count() != config.channels {
+            return Err(invalid_input(
+                "the packet differs in sample type or channel count from the audio track",
+            ));
+        }
+        let bytes = packet
+            .bytes()
+            .map_err(|e| io::Error::other(format!("{:?}", e)))?;
+        let frames = packet.sample_frame_count() as u64;
+        if frames == 0 {
+            return Ok(());
+        }
+
+        // Fill a gap since the last packet with silence, so the audio after it stays in time
+        let time = packet.packet_time(config.sample_rate as i64).ok();
+        let silence = match (audio.start, time) {
+            (Some(start), Some(time)) => {
+                let gap = time - (start + audio.frames as i64);
+                let max = MAX_SILENCE.as_secs() as i64 * config.sample_rate as i64;
+                if gap > 0 && gap <= max {
+                    gap as u64
+                } else {
+                    0
+                }
+            }
+            _ => 0,
+        };
+        if silence > 0 {
+            let zeros = vec![0; silence as usize * config.frame_bytes()];
+            let offset = self.write_sample(&zeros)?;
+            let audio = self.audio.as_mut().expect("checked above");
+            audio.chunks.push((offset, silence as u32));
+            audio.frames += silence;
+            audio.silence_frames += silence;
+        }
+
+        let offset = self.write_sample(bytes)?;
+        let audio = self.audio.as_mut().expect("checked above");
+        if audio.start.is_none() {
+            audio.start = Some(time.unwrap_or(0));
+        }
+        audio.chunks.push((offset, frames as u32));
+        audio.frames += frames;
+        Ok(())
+    }
+
+    /// The edits that place the audio against the video, in the movie timescale.
+    fn audio_edits(&self, audio: &AudioTrack, movie_scale: u64) -> Vec<(u64, i64)> {
+        let rate = audio.config.sample_rate as i64;
+        let media = rescale(audio.frames, rate as u64, movie_scale);
+        let offset = match (self.video.as_ref().and_then(|v| v.origin), audio.start) {
+            (Some(origin), Some(start)) => origin.rescale(rate).map_or(0, |o| start - o.value),
+            _ => 0,
+        };
+        if offset > 0 {
+            // The audio starts after the video
+            vec![
+                (rescale(offset as u64, rate as u64, movie_scale), -1),
+                (media, 0),
+            ]
+        } else if offset < 0 {
+            // Skip the audio from before the first frame
+            let skipped = rescale(offset.unsigned_abs(), rate as u64, movie_scale);
+            vec![(media.saturating_sub(skipped), -offset)]
+        } else {
+            vec![(media, 0)]
+        }
+    }
+
+    fn write_video_track(&self, out: &mut Vec<u8>, video: &VideoTrack, id: u32) {
+        let (_, compressor) = video_format(video.pixel_format).expect("checked when created");
+        let duration = video.durations.total;
+        atom(out, b"trak", |out| {
+            write_track_header(
+                out,
+                &TrackHeader {
+                    id,
+                    duration,
+                    volume: 0,
+                    width: video.width as u32,
+                    height: video.height as u32,
+                },
+            );
+            atom(out, b"mdia", |out| {
+                write_media_header(out, video.timescale as u32, duration);
+                write_handler(out, b"mhlr", b"vide", "VideoHandler");
+                atom(out, b"minf", |out| {
+                    // Copy mode, with an unused opcolor
+                    full_atom(out, b"vmhd", 0, 1, |out| {
+                        put_u16(out, 0x40);
+                        for _ in 0..3 {
+                            put_u16(out, 0x8000);
+                        }
+                    });
+                    write_data_info(out);
+                    atom(out, b"stbl", |out| {
+                        full_atom(out, b"stsd", 0, 0, |out| {
+                            put_u32(out, 1);
+                            atom(out, video.fourcc, |out| {
+                                self.write_video_sample_entry(out, video, compressor)
+                            });
+                        });
+                        full_atom(out, b"stts", 0, 0, |out| {
+                            put_u32(out, video.durations.runs.len() as u32);
+                            for (count, delta) in &video.durations.runs {
+                                put_u32(out, *count);
+                                put_u32(out, *delta);
+                            }
+                        });
+                        // Each frame is a chunk of its own
+                        full_atom(out, b"stsc", 0, 0, |out| {
+                            put_u32(out, 1);
+                            put_u32(out, 1);
+                            put_u32(out, 1);
+                            put_u32(out, 1);
+                        });
+                        full_atom(out, b"stsz", 0, 0, |out| {
+                            put_u32(out, (video.row_bytes * video.height) as u32);
+                            put_u32(out, video.offsets.len() as u32);
+                        });
+                        write_chunk_offsets(out, video.offsets.iter().copied());
+                    });
+                });
+            });
+        });
+    }
+
+    fn write_video_sample_entry(&self, out: &mut Vec<u8>, video: &VideoTrack, compressor: &str) {
+        out.extend_from_slice(&[0; 6]);
+        put_u16(out, 1);
+        // Version, revision, vendor, and temporal and spatial quality
+        put_u16(out, 0);
+        put_u16(out, 0);
+        put_u32(out, 0);
+        put_u32(out, 0);
+        put_u32(out, 0);
+        put_u16(out, video.width as u16);
+        put_u16(out, video.height as u16);
+        // 72 dpi
+        put_u32(out, 0x48_0000);
+        put_u32(out, 0x48_0000);
+        put_u32(out, 0);
+        put_u16(out, 1);
+        put_pascal(out, compressor, 32);
+        put_u16(out, 24);
+        put_u16(out, 0xffff);
+
+        let colorimetry = self
+            .config
+            .colorimetry
+            .unwrap_or_else(|| Colorimetry::for_height(video.height));
+        // Primaries, transfer function and matrix, as numbered in ITU-T H.273
+        let (primaries, matrix) = colorimetry.h273(video.height);
+        let transfer = TransferFunction::Sdr.h273();
+        atom(out, b"colr", |out| {
+            out.extend_from_slice(b"nclc");
+            put_u16(out, primaries.into());
+            put_u16(out, transfer.into());
+            put_u16(out, matrix.into());
+        });
+        // The number of fields, and for two, which is displayed and stored first
+        let fields = match self.config.field_dominance {
+            DecklinkFieldDominance::ProgressiveFrame
+            | DecklinkFieldDominance::ProgressiveSegmentedFrame => Some((1, 0)),
+            DecklinkFieldDominance::UpperFieldFirst => Some((2, 9)),
+            DecklinkFieldDominance::LowerFieldFirst => Some((2, 14)),
+            DecklinkFieldDominance::Unknown => None,
+        };
+        if let Some((count, order)) = fields {
+            atom(out, b"fiel", |out| {
+                out.push(count);
+                out.push(order);
+            });
+        }
+    }
+
+    fn write_audio_track(&self, out: &mut Vec<u8>, audio: &AudioTrack, id: u32, movie_scale: u64) {
+        let config = audio.config;
+        let edits = self.audio_edits(audio, movie_scale);
+        let duration = edits.iter().map(|(d, _)| d).sum();
+        atom(out, b"trak", |out| {
+            write_track_header(
+                out,
+                &TrackHeader {
+                    id,
+                    duration,
+                    volume: 0x100,
+                    width: 0,
+                    height: 0,
+                },
+            );
+            write_edits(out, &edits);
+            atom(out, b"mdia", |out| {
+                write_media_header(out, config.sample_rate, audio.frames);
+                write_handler(out, b"mhlr", b"soun", "SoundHandler");
+                atom(out, b"minf", |out| {
+                    full_atom(out, b"smhd", 0, 0, |out| {
+                        put_u16(out, 0);
+                        put_u16(out, 0);
+                    });
+                    write_data_info(out);
+                    atom(out, b"stbl", |out| {
+                        full_atom(out, b"stsd", 0, 0, |out| {
+                            put_u32(out, 1);
+                            atom(out, b"lpcm", |out| write_audio_sample_entry(out, &config));
+                        });
+                        // Each sample is one sample frame
+                        full_atom(out, b"stts", 0, 0, |out| {
+                            put_u32(out, 1);
+                            put_u32(out, audio.frames as u32);
+                            put_u32(out, 1);
+                        });
+                        full_atom(out, b"stsc", 0, 0, |out| {
+                            let mut runs: Vec<(u32, u32)> = Vec::new();
+                            for (i, (_, frames)) in audio.chunks.iter().enumerate() {
+                                if runs.last().map(|(_, f)| f) != Some(frames) {
+                                    runs.push((i as u32 + 1, *frames));
+                                }
+                            }
+                            put_u32(out, runs.len() as u32);
+                            for (first_chunk, frames) in runs {
+                                put_u32(out, first_chunk);
+                                put_u32(out, frames);
+                                put_u32(out, 1);
+                            }
+                        });
+                        full_atom(out, b"stsz", 0, 0, |out| {
+                            put_u32(out, config.frame_bytes() as u32);
+                            put_u32(out, audio.frames as u32);
+                        });
+                        write_chunk_offsets(out, audio.chunks.iter().map(|(offset, _)| *offset));
+                    });
+                });
+            });
+        });
+    }
+
+    /// The `moov` box indexing every sample written so far.
+    fn moov(&self) -> Vec<u8> {
+        let movie_scale = match (&self.video, &self.audio) {
+            (Some(video), _) => video.timescale as u64,
+            (None, Some(audio)) => audio.config.sample_rate as u64,
+            (None, None) => self.config.frame_duration.scale as u64,
+        };
+        let video_duration = self.video.as_ref().map_or(0, |v| v.durations.total);
+        let audio_duration = self.audio.as_ref().map_or(0, |a| {
+            self.audio_edits(a, movie_scale)
+                .iter()
+                .map(|(d, _)| d)
+                .sum()
+        });
+        let duration = video_duration.max(audio_duration);
+        let track_count = self.video.is_some() as u32 + self.audio.is_some() as u32;
+
+        let mut out = Vec::new();
+        atom(&mut out, b"moov", |out| {
+            let version = version_for(duration);
+            full_atom(out, b"mvhd", version, 0, |out| {
+                put_sized(out, version, 0);
+                put_sized(out, version, 0);
+                put_u32(out, movie_scale as u32);
+                put_sized(out, version, duration);
+                // Rate and volume
+                put_u32(out, 0x10000);
+                put_u16(out, 0x100);
+                out.extend_from_slice(&[0; 10]);
+                put_matrix(out);
+                // Preview, poster, selection and current times
+                out.extend_from_slice(&[0; 24]);
+                put_u32(out, track_count + 1);
+            });
+            let mut id = 1;
+            if let Some(video) = &self.video {
+                self.write_video_track(out, video, id);
+                id += 1;
+            }
+            if let Some(audio) = &self.audio {
+                self.write_audio_track(out, audio, id, movie_scale);
+            }
+        });
+        out
+    }
+
+    /// Close the `mdat` box in progress and write the index after it. Unless the file is
+    /// being finished, samples then continue in a new `mdat` box.
+    fn write_index(&mut self, last: bool) -> io::Result<()> {
+        self.file.flush()?;
+        let end = self.position;
+        let moov = self.moov();
+        let file = self.file.get_mut();
+
+        let mut header = [0; 16];
+        header[..4].copy_from_slice(&1u32.to_be_bytes());
+        header[4..8].copy_from_slice(b"mdat");
+        header[8..].copy_from_slice(&(end - self.mdat_start).to_be_bytes());
+        file.seek(SeekFrom::Start(self.mdat_start))?;
+        file.write_all(&header)?;
+
+        // The new index is written before the old one is removed, so one is always there
+        file.seek(SeekFrom::Start(end))?;
+        file.write_all(&moov)?;
+        file.sync_data()?;
+        if let Some(previous) = self.moov_at.replace(end) {
+            file.seek(SeekFrom::Start(previous + 4))?;
+            file.write_all(b"free")?;
+            file.sync_data()?;
+        }
+
+        self.position = end + moov.len() as u64;
+        file.seek(SeekFrom::Start(self.position))?;
+        if !last {
+            file.write_all(&open_mdat_header())?;
+            self.mdat_start = self.position;
+            self.position += 16;
+        }
+        self.flushed_at = self.video.as_ref().map_or(0, |v| v.durations.total);
+        Ok(())
+    }
+
+    /// Write the index of the samples so far, so they can be played if the file is not
+    /// finished.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.write_index(false)
+    }
+
+    fn info(&self) -> MovInfo {
+        MovInfo {
+            video_frames: self.video.as_ref().map_or(0, |v| v.offsets.len() as u64),
+            video_gaps: self.video.as_ref().map_or(0, |v| v.gaps),
+            video_duration: DecklinkTime::new(
+                self.video.as_ref().map_or(0, |v| v.durations.total as i64),
+                self.config.frame_duration.scale,
+            ),
+            audio_frames: self.audio.as_ref().map_or(0, |a| a.frames),
+            silence_frames: self.audio.as_ref().map_or(0, |a| a.silence_frames),
+            byte_count: self.position,
+        }
+    }
+
+    /// Write the final index and close the file.
+    pub fn finish(mut self) -> io::Result<MovInfo> {
+        self.finished = true;
+        self.write_index(true)?;
+        self.file.get_ref().sync_all()?;
+        Ok(self.info())
+    }
+}
+
+impl Drop for MovWriter {
+    fn drop(&mut self) {
+        if !self.finished {
+            let _ = self.write_index(true);
+        }
+    }
+}
+
+/// An 8 byte `wide` box, which leaves room for a 64-bit size, and the header of an `mdat`
+/// box that runs to the end of the file. Once the box is complete, the two are replaced
+/// by an `mdat` header with its 64-bit size.
+fn open_mdat_header() -> [u8; 16] {
+    let mut header = [0; 16];
+    header[..4].copy_from_slice(&8u32.to_be_bytes());
+    header[4..8].copy_from_slice(b"wide");
+    header[12..].copy_from_slice(b"mdat");
+    header
+}
+
+/// A version 2 sound sample description, of packed signed little-endian integer samples.
+fn write_audio_sample_entry(out: &mut Vec<u8>, config: &MovAudioConfig) {
+    let bits = match config.sample_type {
+        DecklinkAudioSampleType::Int16 => 16,
+        DecklinkAudioSampleType::Int32 => 32,
+    };
+    out.extend_from_slice(&[0; 6]);
+    put_u16(out, 1);
+    put_u16(out, 2);
+    put_u16(out, 0);
+    put_u32(out, 0);
+    // Fixed values of a version 2 description
+    put_u16(out, 3);
+    put_u16(out, 16);
+    put_u16(out, 0xfffe);
+    put_u16(out, 0);
+    put_u32(out, 0x10000);
+    put_u32(out, 72);
+    out.extend_from_slice(&(config.sample_rate as f64).to_bits().to_be_bytes());
+    put_u32(out, config.channels);
+    put_u32(out, 0x7f00_0000);
+    put_u32(out, bits);
+    // Signed integer and packed, and not big-endian
+    put_u32(out, 0x4 | 0x8);
+    put_u32(out, config.frame_bytes() as u32);
+    put_u32(out, 1);
+}
+
+/// Writes a movie file per segment.
+///
+/// File names are made from a pattern, as for `crate::segment::SegmentedWriter`. The
+/// driver delivers the audio captured with a frame before the frame, so audio is held until
+/// the next frame is placed, and is written into the file of that frame.
+pub struct SegmentedMovWriter {
+    pattern: String,
+    config: MovConfig,
+    segmenter: Segmenter,
+    current: Option<(SegmentInfo, MovWriter)>,
+    finished: Vec<SegmentInfo>,
+    pending_audio: Vec<DecklinkAudioInputPacket>,
+}
+
+impl SegmentedMovWriter {
+    pub fn new(
+        pattern: impl Into<String>,
+        policy: SegmentPolicy,
+        config: MovConfig,
+    ) -> SegmentedMovWriter {
+        SegmentedMovWriter {
+            pattern: pattern.into(),
+            config,
+            segmenter: Segmenter::new(policy),
+            current: None,
+            finished: Vec::new(),
+            pending_audio: Vec::new(),
+        }
+    }
+
+    /// The path of a segment.
+    pub fn path_for(&self, boundary: &SegmentBoundary) -> PathBuf {
+        segment_path(&self.pattern, boundary)
+    }
+
+    /// Note that the input format changed, so the next frame starts a new file. The field
+    /// dominance and colorimetry of the new format are not known until `set_display_mode`
+    /// is called, so until then they are left out of the new file.
+    pub fn format_changed(&mut self) {
+        self.segmenter.format_changed();
+        self.config.field_dominance = DecklinkFieldDominance::Unknown;
+        self.config.colorimetry = None;
+    }
+
+    /// Set the display mode of the files started from now on.
+    pub fn set_display_mode(&mut self, mode: &DecklinkDisplayMode) {
+        if let Some(duration) = mode.frame_duration() {
+            self.config.frame_duration = duration;
+        }
+        self.config.field_dominance = mode.field_dominance();
+    }
+
+    /// The segments that have been finished so far.
+    pub fn finished_segments(&self) -> &[SegmentInfo] {
+        &self.finished
+    }
+
+    /// Hold a packet of audio, to be written with the next frame.
+    pub fn write_audio(&mut self, packet: DecklinkAudioInputPacket) {
+        self.pending_audio.push(packet);
+    }
+
+    fn write_pending_audio(&mut self) -> io::Result<()> {
+        if let Some((_, writer)) = &mut self.current {
+            for packet in self.pending_audio.drain(..) {
+                writer.write_audio(&packet)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Write a frame, starting a new file first if it is at a boundary. Returns the segment
+    /// that was finished to make way for it, if any.
+    pub fn write_frame<F: DecklinkFrameBase + ?Sized>(
+        &mut self,
+        frame: &F,
+        timing: Option<&DecklinkFrameTiming>,
+    ) -> io::Result<Option<SegmentInfo>> {
+        // A frame of another size or pixel format is a format change, even if it was not
+        // reported
+        if let Some((_, writer)) = &self.current {
+            if !writer.accepts(frame) {
+                self.segmenter.format_changed();
+            }
+        }
+
+        let mut closed = None;
+        if let Some(boundary) = self.segmenter.frame(timing) {
+            closed = self.finish_segment()?;
+            let mut config = self.config;
+            if let Some(timing) = timing {
+                config.frame_duration = timing.duration;
+            }
+            let path = self.path_for(&boundary);
+            let writer = MovWriter::create(&path, config)?;
+            self.current = Some((
+                SegmentInfo {
+                    boundary,
+                    path,
+                    frame_count: 0,
+                    byte_count: 0,
+                },
+                writer,
+            ));
+        }
+
+        let (info, writer) = self
+            .current
+            .as_mut()
+            .expect("the first frame always starts a segment");
+        writer.write_frame(frame, timing)?;
+        info.frame_count += 1;
+        self.write_pending_audio()?;
+        Ok(closed)
+    }
+
+    fn finish_segment(&mut self) -> io::Result<Option<SegmentInfo>> {
+        match self.current.take() {
+            Some((mut info, writer)) => {
+                info.byte_count = writer.finish()?.byte_count;
+                self.finished.push(info.clone());
+                Ok(Some(info))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Finish the last file, returning every segment that was written. Audio held for a
+    /// frame that never came is written into the last file.
+    pub fn finish(mut self) -> io::Result<Vec<SegmentInfo>> {
+        self.write_pending_audio()?;
+        self.finish_segment()?;
+        Ok(self.finished)
+    }
+}
+
+impl SegmentSink for SegmentedMovWriter {
+    fn write_frame(
+        &mut self,
+        frame: &dyn DecklinkFrameBase,
+        timing: Option<&DecklinkFrameTiming>,
+    ) -> io::Result<Option<SegmentInfo>> {
+        SegmentedMovWriter::write_frame(self, frame, timing)
+    }
+
+    fn write_audio(&mut self, packet: DecklinkAudioInputPacket) -> io::Result<()> {
+        SegmentedMovWriter::write_audio(self, packet);
+        Ok(())
+    }
+
+    fn format_changed(&mut self) {
+        SegmentedMovWriter::format_changed(self);
+    }
+
+    fn finished_segments(&self) -> &[SegmentInfo] {
+        SegmentedMovWriter::finished_segments(self)
+    }
+
+    fn finish(self: Box<Self>) -> io::Result<Vec<SegmentInfo>> {
+        SegmentedMovWriter::finish(*self)
+    }
+}
@@ -0,0 +1,165 @@
+//! Workarounds for signals that confuse format detection.
+//!
+//! Some cameras emit signals that the driver's format detection misreads, such as 1080p29.97
+//! carried as PsF and detected as 1080i59.94, or a mode that flaps for a moment at the
+//! start of recording. A `QuirkRule` matches the pattern of format changes such a signal
+//! produces, and applies one of the mitigations `crate::format_detect::FormatDetector`
+//! has: treating the signal as PsF, waiting longer before acting on a change, or ignoring
+//! modes that do not last.
+//!
+//! Rules are plain data, so a facility can keep its own alongside the built-in ones, and
+//! with the `serde` feature they can be loaded from a file. `QuirkPolicy` chooses which
+//! rules a detector consults, and the detector reports each rule that fires as a
+//! `QuirkApplied`.
+//!
+//! # Contributing a quirk
+//!
+//! Record a capture that shows the problem with `crate::replay::SessionRecorder`, with
+//! format detection enabled. The session file holds only callback metadata, no pictures or
+//! sound, so it can be attached to an issue along with the camera make, model and settings.
+//! Replaying it through `FormatDetector::replay_event` with `QuirkPolicy::Off` shows the
+//! re-enables the signal causes, and a new built-in rule should make the same replay settle
+//! on the right mode without them.
+//!
+//! This module is experimental, see `crate::experimental`: rules are expected to gain
+//! conditions and actions, which changes their serialized form.
+
+use crate::device::input::DecklinkDetectedVideoInputFormatFlags;
+use crate::display_mode::DecklinkDisplayModeId;
+
+/// A pattern of format changes for a rule to match.
+///
+/// Conditions are checked when a format change is detected, against that change and the
+/// ones before it. Frame counts are of frames delivered since the detector was created.
+#[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum QuirkCondition {
+    /// The change is to `mode`, with at least the detected `flags`.
+    DetectedAs {
+        mode: DecklinkDisplayModeId,
+        flags: DecklinkDetectedVideoInputFormatFlags,
+    },
+    /// At least `changes` format changes, this one included, were detected within the last
+    /// `within_frames` frames.
+    Oscillating { changes: u32, within_frames: u32 },
+    /// The detected mode moved between an interlaced mode and the progressive mode sharing
+    /// its transport within the last `within_frames` frames, as a PsF signal can.
+    PsfPairOscillation { within_frames: u32 },
+    /// The change was detected within `within_frames` frames of the start of the capture.
+    AtStart { within_frames: u32 },
+    /// Every one of the conditions holds.
+    All(Vec<QuirkCondition>),
+}
+
+/// A mitigation for a rule to apply to the change it matched.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum QuirkAction {
+    /// Treat an interlaced mode as the progressive mode sharing its transport, so the
+    /// signal is captured as PsF.
+    ForcePsf,
+    /// Wait until no format change has been detected for this many frames before acting on
+    /// the latest one.
+    ExtendDebounce { frames: u32 },
+    /// Only act on a mode once it has been detected for this many frames in a row, so a
+    /// mode that comes and goes is never switched to.
+    IgnoreShorterThan { frames: u32 },
+}
+
+/// A named condition and the action to take when it matches.
+#[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct QuirkRule {
+    /// A short name, reported when the rule fires.
+    pub name: String,
+    pub condition: QuirkCondition,
+    pub action: QuirkAction,
+}
+
+impl QuirkRule {
+    pub fn new(name: &str, condition: QuirkCondition, action: QuirkAction) -> QuirkRule {
+        QuirkRule {
+            name: name.to_string(),
+            condition,
+            action,
+        }
+    }
+}
+
+/// Which rules a format detector consults.
+#[derive(PartialEq, Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum QuirkPolicy {
+    /// Consult no rules, and act on format changes as detected.
+    Off,
+    /// Consult the rules of `builtin_quirks`.
+    #[default]
+    BuiltIn,
+    /// Consult the built-in rules, then these.
+    BuiltInPlus(Vec<QuirkRule>),
+}
+
+impl QuirkPolicy {
+    /// The rules to consult, in order.
+    pub fn rules(&self) -> Vec<QuirkRule> {
+        match self {
+            QuirkPolicy::Off => Vec::new(),
+            QuirkPolicy::BuiltIn => builtin_quirks(),
+            QuirkPolicy::BuiltInPlus(custom) => {
+                let mut rules = builtin_quirks();
+                rules.extend(custom.iter().cloned());
+                rules
+            }
+        }
+    }
+}
+
+/// The built-in rules.
+///
+/// These match patterns seen from several vendors' cameras, rather than a particular model,
+/// and only delay or redirect re-enables, so they do not change the outcome for a signal
+/// that detects cleanly.
+pub fn builtin_quirks() -> Vec<QuirkRule> {
+    vec![
+        // PsF signals are often detected as interlaced, and some cameras alternate between
+        // the two readings as the camera and card negotiate
+        QuirkRule::new(
+            "psf-detected-as-interlaced",
+            QuirkCondition::PsfPairOscillation { within_frames: 60 },
+            QuirkAction::ForcePsf,
+        ),
+        // Several cameras briefly output another mode when recording starts
+        QuirkRule::new(
+            "flapping-at-start",
+            QuirkCondition::All(vec![
+                QuirkCondition::AtStart { within_frames: 150 },
+                QuirkCondition::Oscillating {
+                    changes: 2,
+                    within_frames: 30,
+                },
+            ]),
+            QuirkAction::ExtendDebounce { frames: 30 },
+        ),
+        // A signal that keeps changing is waited out, rather than followed
+        QuirkRule::new(
+            "repeated-transitions",
+            QuirkCondition::Oscillating {
+                changes: 3,
+                within_frames: 60,
+            },
+            QuirkAction::IgnoreShorterThan { frames: 15 },
+        ),
+    ]
+}
+
+/// A rule that fired, as reported by the format detector.
+#[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct QuirkApplied {
+    pub rule: String,
+    pub action: QuirkAction,
+    /// The frame count when the rule fired.
+    pub frame: u64,
+    /// The mode of the change the rule matched.
+    pub detected_mode: DecklinkDisplayModeId,
+}
@@ -0,0 +1,573 @@
+//! Detecting frames torn by a transfer that had not finished when the frame was delivered.
+//!
+//! Under PCIe pressure, a card can deliver a frame whose upper part is the new picture while
+//! the rows below are still those of the frame before, as the transfer into the buffer had
+//! not reached them. Nothing in the frame says so. A `TearDetector` samples a few rows of each
+//! frame, hashing each as `crate::verify::FrameFingerprint` does, and compares them with the
+//! same rows of the frame before. A frame is suspected of tearing when the sampled rows have
+//! changed down to some row, and none below it have.
+//!
+//! A still picture, or one with a still region at the bottom such as a letterbox bar, has
+//! unchanged rows too, so `TearingConfig` asks for more than the split:
+//!
+//! - At least `min_changed_rows` of the sampled rows above it changed, and at least
+//!   `min_changed_fraction` of them, so that a little motion over a still picture is not a
+//!   tear.
+//! - At least `min_unchanged_rows` sampled rows below it are unchanged.
+//! - The rows above it changed by at least `min_change_magnitude` on average, as the mean
+//!   difference of their bytes over the full scale of a byte. The last steps of a slow fade
+//!   leave the darker rows on the same codes while the lighter ones move by one, which this
+//!   keeps from being a tear.
+//! - With `require_prior_motion`, at least `min_changed_fraction` of the unchanged rows had
+//!   changed between the two frames before, as the rows of a stale region did. A region that
+//!   stays still, such as a letterbox bar or a lower third, had not.
+//!
+//! So a tear is found from the third frame of a format, and not in a picture whose lower part
+//! was still. The defaults suit moving pictures, such as a camera or a test pattern that
+//! moves everywhere. For pictures that move less, lower `min_changed_fraction` and
+//! `min_change_magnitude`, at the cost of more false positives.
+//!
+//! The split can only be placed between two sampled rows, so a `TearSuspicion` gives both:
+//! the tear is below `last_changed_row`, and at or above `tear_row`. Sample more rows, or
+//! place them with `TearingConfig::positions`, to narrow it.
+//!
+//! `TearingGuard` runs a detector on the frames an input callback passes to a primary one. It
+//! reports `TearingEvent`s, counts suspected frames in `TearingStats`, and drops them with
+//! `SuspectAction::Drop`. Otherwise they are passed on, and the primary can read the verdict
+//! on the frame it is given with `TearingGuard::current_suspicion`.
+//!
+//! This module is experimental, see `crate::experimental`: the thresholds and their defaults
+//! are expected to change as the heuristic is tuned on more hardware.
+
+use crate::config::ConfigError;
+use crate::deinterlace::FrameLayout;
+use crate::device::input::{
+    ArrivalFlags, CallbackResult, FrameArrival, InputFormatChange, InputHandler,
+};
+use crate::frame::DecklinkFrameBase;
+use crate::verify::FrameFingerprint;
+use std::sync::{Arc, Mutex};
+
+/// What a `TearingGuard` does with a frame suspected of tearing.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Default)]
+pub enum SuspectAction {
+    /// Pass it on, for the primary to check with `TearingGuard::current_suspicion`.
+    #[default]
+    Deliver,
+    /// Do not pass it on, and count it in `TearingStats::dropped`.
+    Drop,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct TearingConfig {
+    /// The number of rows sampled, spread evenly down the frame. Ignored if `positions` is
+    /// set.
+    pub rows: usize,
+    /// The rows to sample, as fractions of the height from the top, in place of `rows`
+    /// spread evenly.
+    pub positions: Vec<f64>,
+    /// The bytes of each sampled row compared to measure how much it changed, spread evenly
+    /// along it. Whether a row changed at all is told from a hash of all of it.
+    pub probe_bytes: usize,
+    /// The fewest sampled rows above the split that must have changed.
+    pub min_changed_rows: usize,
+    /// The least fraction of the sampled rows above the split that must have changed, and,
+    /// with `require_prior_motion`, of the rows below it that must have changed the frame
+    /// before.
+    pub min_changed_fraction: f64,
+    /// The fewest sampled rows below the split that must be unchanged.
+    pub min_unchanged_rows: usize,
+    /// The least mean change of the rows that changed above the split, as a fraction of the
+    /// full scale of a byte, whatever the pixel format.
+    pub min_change_magnitude: f64,
+    /// Only suspect a frame whose unchanged rows had changed between the two frames before.
+    pub require_prior_motion: bool,
+    pub on_suspect: SuspectAction,
+}
+
+impl Default for TearingConfig {
+    fn default() -> Self {
+        TearingConfig {
+            rows: 32,
+            positions: Vec::new(),
+            probe_bytes: 256,
+            min_changed_rows: 3,
+            min_changed_fraction: 0.9,
+            min_unchanged_rows: 2,
+            min_change_magnitude: 0.02,
+            require_prior_motion: true,
+            on_suspect: SuspectAction::Deliver,
+        }
+    }
+}
+
+impl TearingConfig {
+    pub fn builder() -> TearingConfigBuilder {
+        TearingConfigBuilder {
+            config: TearingConfig::default(),
+        }
+    }
+
+    /// Check that a detector with this config could suspect a frame.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let error = |field, problem| Err(ConfigError::new("TearingConfig", field, problem));
+        if self.positions.iter().any(|p| !(0.0..1.0).contains(p)) {
+            return error("positions", "must be at least 0 and less than 1");
+        }
+        if self.min_changed_rows == 0 {
+            return error("min_changed_rows", "must be at least 1");
+        }
+        if self.min_unchanged_rows == 0 {
+            return error("min_unchanged_rows", "must be at least 1");
+        }
+        if self.sample_count() < self.min_changed_rows + self.min_unchanged_rows {
+            let field = if self.positions.is_empty() {
+                "rows"
+            } else {
+                "positions"
+            };
+            return error(
+                field,
+                "must sample at least min_changed_rows and min_unchanged_rows together",
+            );
+        }
+        if self.probe_bytes == 0 {
+            return error("probe_bytes", "must be at least 1");
+        }
+        if !(self.min_changed_fraction > 0.0 && self.min_changed_fraction <= 1.0) {
+            return error("min_changed_fraction", "must be more than 0 and at most 1");
+        }
+        if !(0.0..1.0).contains(&self.min_change_magnitude) {
+            return error("min_change_magnitude", "must be at least 0 and less than 1");
+        }
+        Ok(())
+    }
+
+    fn sample_count(&self) -> usize {
+        if self.positions.is_empty() {
+            self.rows
+        } else {
+            self.positions.len()
+        }
+    }
+
+    /// The rows sampled in a frame of `height` rows, from the top down.
+    fn sample_rows(&self, height: usize) -> Vec<usize> {
+        let mut rows: Vec<usize> = if self.positions.is_empty() {
+            let count = self.rows.max(1);
+            (0..count)
+                .map(|i| (2 * i + 1) * height / (2 * count))
+                .collect()
+        } else {
+            self.positions
+                .iter()
+                .map(|p| (p.clamp(0.0, 1.0) * height as f64) as usize)
+                .collect()
+        };
+        for row in &mut rows {
+            *row = (*row).min(height - 1);
+        }
+        rows.sort_unstable();
+        rows.dedup();
+        rows
+    }
+}
+
+/// Builds a `TearingConfig`, starting from the default.
+#[derive(PartialEq, Debug, Clone)]
+pub struct TearingConfigBuilder {
+    config: TearingConfig,
+}
+
+impl TearingConfigBuilder {
+    pub fn rows(mut self, rows: usize) -> Self {
+        self.config.rows = rows;
+        self
+    }
+
+    pub fn positions(mut self, positions: Vec<f64>) -> Self {
+        self.config.positions = positions;
+        self
+    }
+
+    pub fn probe_bytes(mut self, probe_bytes: usize) -> Self {
+        self.config.probe_bytes = probe_bytes;
+        self
+    }
+
+    pub fn min_changed_rows(mut self, min_changed_rows: usize) -> Self {
+        self.config.min_changed_rows = min_changed_rows;
+        self
+    }
+
+    pub fn min_changed_fraction(mut self, min_changed_fraction: f64) -> Self {
+        self.config.min_changed_fraction = min_changed_fraction;
+        self
+    }
+
+    pub fn min_unchanged_rows(mut self, min_unchanged_rows: usize) -> Self {
+        self.config.min_unchanged_rows = min_unchanged_rows;
+        self
+    }
+
+    pub fn min_change_magnitude(mut self, min_change_magnitude: f64) -> Self {
+        self.config.min_change_magnitude = min_change_magnitude;
+        self
+    }
+
+    pub fn require_prior_motion(mut self, require_prior_motion: bool) -> Self {
+        self.config.require_prior_motion = require_prior_motion;
+        self
+    }
+
+    pub fn on_suspect(mut self, on_suspect: SuspectAction) -> Self {
+        self.config.on_suspect = on_suspect;
+        self
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        self.config.validate()
+    }
+
+    pub fn build(self) -> Result<TearingConfig, ConfigError> {
+        self.validate()?;
+        Ok(self.config)
+    }
+}
+
+/// Why a frame is suspected of tearing. Rows are counted from the top of the frame, and
+/// frames by their `sequence`, the number of frames given to the detector before them.
+#[derive(PartialEq, Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TearSuspicion {
+    pub sequence: u64,
+    /// The frame it was compared with, the one before it unless that could not be read.
+    pub previous_sequence: u64,
+    /// The lowest sampled row that changed.
+    pub last_changed_row: usize,
+    /// The first of the unchanged sampled rows below it. The stale rows begin at or above it.
+    pub tear_row: usize,
+    /// The sampled rows above the split that changed.
+    pub changed_rows: usize,
+    /// The sampled rows below the split, all unchanged.
+    pub unchanged_rows: usize,
+    /// The mean change of the rows that changed, as a fraction of the full scale of a byte.
+    pub magnitude: f64,
+}
+
+#[derive(PartialEq, Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TearingEvent {
+    /// A frame is suspected of tearing.
+    Suspected(TearSuspicion),
+}
+
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Default)]
+pub struct TearingStats {
+    /// Frames given to the detector.
+    pub frames: u64,
+    /// Frames compared with the frame before. The first frame after a reset or a format
+    /// change is not.
+    pub compared: u64,
+    pub suspected: u64,
+    /// Suspected frames a `TearingGuard` did not pass on.
+    pub dropped: u64,
+    /// Frames whose bytes could not be read, or were fewer than their layout needs.
+    pub unreadable: u64,
+    /// Times comparison started again, for a reset or a change of layout.
+    pub resets: u64,
+    /// The `TearSuspicion::tear_row` of the last frame suspected.
+    pub last_tear_row: Option<usize>,
+}
+
+/// The sampled rows of a frame.
+struct Sampled {
+    layout: FrameLayout,
+    sequence: u64,
+    rows: Vec<usize>,
+    hashes: Vec<u64>,
+    /// The probe bytes of each row in turn, `probe` of them per row.
+    probes: Vec<u8>,
+    probe: usize,
+    /// Whether each row had changed from the frame before, if it was compared with one.
+    changed: Option<Vec<bool>>,
+}
+
+impl Sampled {
+    /// The mean difference of the probe bytes of `index` from those of `other`, over the
+    /// full scale of a byte.
+    fn change(&self, other: &Sampled, index: usize) -> f64 {
+        let range = index * self.probe..(index + 1) * self.probe;
+        let total: u64 = self.probes[range.clone()]
+            .iter()
+            .zip(&other.probes[range])
+            .map(|(a, b)| a.abs_diff(*b) as u64)
+            .sum();
+        total as f64 / (255.0 * self.probe as f64)
+    }
+}
+
+/// Compares sampled rows of each frame with those of the frame before, as set out in the
+/// module docs.
+pub struct TearDetector {
+    config: TearingConfig,
+    previous: Option<Sampled>,
+    stats: TearingStats,
+    events: Vec<TearingEvent>,
+}
+
+impl TearDetector {
+    pub fn new(config: TearingConfig) -> TearDetector {
+        TearDetector {
+            config,
+            previous: None,
+            stats: TearingStats::default(),
+            events: Vec::new(),
+        }
+    }
+
+    pub fn config(&self) -> &TearingConfig {
+        &self.config
+    }
+
+    /// Change the config, starting the comparison again from the next frame.
+    pub fn set_config(&mut self, config: TearingConfig) {
+        self.config = config;
+        self.reset();
+    }
+
+    pub fn stats(&self) -> TearingStats {
+        self.stats
+    }
+
+    pub fn take_events(&mut self) -> Vec<TearingEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    /// Start the comparison again from the next frame, as after a format change or a gap
+    /// in the frames.
+    pub fn reset(&mut self) {
+        self.stats.resets += 1;
+        self.previous = None;
+    }
+
+    /// Compare `frame` with the frame before it, returning why it is suspected of tearing if
+    /// it is.
+    pub fn check(&mut self, frame: &dyn DecklinkFrameBase) -> Option<TearSuspicion> {
+        let sequence = self.stats.frames;
+        self.stats.frames += 1;
+        let Some(mut sampled) = self.sample(frame, sequence) else {
+            self.stats.unreadable += 1;
+            self.previous = None;
+            return None;
+        };
+        let previous = match self.previous.take() {
+            Some(previous) if previous.layout == sampled.layout => previous,
+            Some(_) => {
+                self.stats.resets += 1;
+                self.previous = Some(sampled);
+                return None;
+            }
+            None => {
+                self.previous = Some(sampled);
+                return None;
+            }
+        };
+
+        self.stats.compared += 1;
+        let changed: Vec<bool> = sampled
+            .hashes
+            .iter()
+            .zip(&previous.hashes)
+            .map(|(a, b)| a != b)
+            .collect();
+        let suspicion = self.judge(&previous, &sampled, &changed);
+        sampled.changed = Some(changed);
+        self.previous = Some(sampled);
+        if let Some(suspicion) = suspicion {
+            self.stats.suspected += 1;
+            self.stats.last_tear_row = Some(suspicion.tear_row);
+            self.events.push(TearingEvent::Suspected(suspicion));
+        }
+        suspicion
+    }
+
+    fn sample(&self, frame: &dyn DecklinkFrameBase, sequence: u64) -> Option<Sampled> {
+        let layout = FrameLayout::of(frame);
+        let bytes = frame.bytes().ok()?;
+        let row_bytes = layout.row_bytes;
+        if row_bytes == 0 || layout.height == 0 || bytes.0.len() < row_bytes * layout.height {
+            return None;
+        }
+
+        let rows = self.config.sample_rows(layout.height);
+        let probe = self.config.probe_bytes.clamp(1, row_bytes);
+        let mut hashes = Vec::with_capacity(rows.len());
+        let mut probes = Vec::with_capacity(rows.len() * probe);
+        for row in &rows {
+            let row = &bytes.0[row * row_bytes..(row + 1) * row_bytes];
+            hashes.push(FrameFingerprint::hash_row(row));
+            probes.extend((0..probe).map(|i| row[i * row_bytes / probe]));
+        }
+        Some(Sampled {
+            layout,
+            sequence,
+            rows,
+            hashes,
+            probes,
+            probe,
+            changed: None,
+        })
+    }
+
+    fn judge(
+        &self,
+        previous: &Sampled,
+        current: &Sampled,
+        changed: &[bool],
+    ) -> Option<TearSuspicion> {
+        let config = &self.config;
+        // Nothing changed in a still picture or a repeated frame
+        let split = changed.iter().rposition(|c| *c)? + 1;
+        let unchanged_rows = changed.len() - split;
+        let changed_rows = changed[..split].iter().filter(|c| **c).count();
+        if unchanged_rows < config.min_unchanged_rows
+            || changed_rows < config.min_changed_rows
+            || (changed_rows as f64) < config.min_changed_fraction * split as f64
+        {
+            return None;
+        }
+
+        let magnitude = (0..split)
+            .filter(|i| changed[*i])
+            .map(|i| current.change(previous, i))
+            .sum::<f64>()
+            / changed_rows as f64;
+        if magnitude < config.min_change_magnitude {
+            return None;
+        }
+
+        if config.require_prior_motion {
+            let before = previous.changed.as_ref()?;
+            let moving = before[split..].iter().filter(|c| **c).count();
+            if (moving as f64) < config.min_changed_fraction * unchanged_rows as f64 {
+                return None;
+            }
+        }
+
+        Some(TearSuspicion {
+            sequence: current.sequence,
+            previous_sequence: previous.sequence,
+            last_changed_row: current.rows[split - 1],
+            tear_row: current.rows[split],
+            changed_rows,
+            unchanged_rows,
+            magnitude,
+        })
+    }
+}
+
+struct GuardShared {
+    detector: Mutex<TearDetector>,
+    /// The verdict on the frame being passed to a primary now.
+    current: Mutex<Option<TearSuspicion>>,
+}
+
+/// Runs a `TearDetector` on the frames input callbacks pass to their primaries.
+///
+/// A guard can be cloned, and a clone kept by the primary to call `current_suspicion`. Give
+/// each guard the frames of one input, so each frame is compared with the one before it.
+#[derive(Clone)]
+pub struct TearingGuard {
+    shared: Arc<GuardShared>,
+}
+
+impl TearingGuard {
+    pub fn new(config: TearingConfig) -> TearingGuard {
+        TearingGuard {
+            shared: Arc::new(GuardShared {
+                detector: Mutex::new(TearDetector::new(config)),
+                current: Mutex::new(None),
+            }),
+        }
+    }
+
+    pub fn config(&self) -> TearingConfig {
+        self.shared.detector.lock().unwrap().config().clone()
+    }
+
+    /// Change the config, starting the comparison again from the next frame.
+    pub fn set_config(&self, config: TearingConfig) {
+        self.shared.detector.lock().unwrap().set_config(config);
+    }
+
+    /// Start the comparison again from the next frame.
+    pub fn reset(&self) {
+        self.shared.detector.lock().unwrap().reset();
+    }
+
+    pub fn stats(&self) -> TearingStats {
+        self.shared.detector.lock().unwrap().stats()
+    }
+
+    pub fn take_events(&self) -> Vec<TearingEvent> {
+        self.shared.detector.lock().unwrap().take_events()
+    }
+
+    /// Why the frame being passed to the primary now is suspected of tearing, for the primary
+    /// to read while it handles the frame. `None` for a frame that is not, and outside that
+    /// call.
+    pub fn current_suspicion(&self) -> Option<TearSuspicion> {
+        *self.shared.current.lock().unwrap()
+    }
+
+    /// An input callback that checks each frame, and passes everything to `primary` unless
+    /// `TearingConfig::on_suspect` drops the frame. Format changes reset the comparison.
+    pub fn guarded(&self, primary: Arc<dyn InputHandler>) -> Arc<dyn InputHandler> {
+        Arc::new(GuardedInputCallback {
+            primary,
+            shared: self.shared.clone(),
+        })
+    }
+}
+
+struct GuardedInputCallback {
+    primary: Arc<dyn InputHandler>,
+    shared: Arc<GuardShared>,
+}
+
+impl InputHandler for GuardedInputCallback {
+    fn format_changed(&self, change: &InputFormatChange) {
+        self.shared.detector.lock().unwrap().reset();
+        self.primary.format_changed(change);
+    }
+
+    fn frame_arrived(&self, arrival: &FrameArrival<'_>) -> CallbackResult {
+        let Some(frame) = arrival.video_frame else {
+            if arrival.flags.contains(ArrivalFlags::FRAME_LOST) {
+                // The frame before is not the one the next frame follows
+                self.shared.detector.lock().unwrap().reset();
+            }
+            return self.primary.frame_arrived(arrival);
+        };
+        let suspicion = {
+            let mut detector = self.shared.detector.lock().unwrap();
+            let suspicion = detector.check(frame);
+            if suspicion.is_some() && detector.config.on_suspect == SuspectAction::Drop {
+                detector.stats.dropped += 1;
+                drop(detector);
+                // Drop the frame, but not the audio that came with it
+                if arrival.audio_packet.is_some() {
+                    return self.primary.frame_arrived(&arrival.with_video_frame(None));
+                }
+                return CallbackResult::Ok;
+            }
+            suspicion
+        };
+
+        *self.shared.current.lock().unwrap() = suspicion;
+        let result = self.primary.frame_arrived(arrival);
+        *self.shared.current.lock().unwrap() = None;
+        result
+    }
+}
@@ -0,0 +1,770 @@
+//! User supplied processing of captured frames, between capture and a consumer.
+//!
+//! A `FrameTransform` turns a frame in one layout into a frame in another, such as cropping
+//! it or masking part of it. A `TransformChain` runs a list of them in order. Their layouts
+//! are negotiated when the chain is configured: each transform is offered the layout the one
+//! before it produces, and either says what it will produce from it or refuses it. A frame
+//! that arrives in a different layout from the one the chain was configured for configures it
+//! again first, so a format change renegotiates the chain rather than giving a transform
+//! frames it did not agree to.
+//!
+//! The buffers between the stages are allocated when the chain is configured and reused for
+//! every frame, so running a chain does not allocate. A transform that can change a frame in
+//! place, and produces the layout it is given, can say so with `FrameTransform::in_place`.
+//! It is then given the previous stage's buffer to change instead of a new one to fill,
+//! which saves a copy.
+//!
+//! A transform that fails on a frame drops that frame only. One that panics detaches the
+//! chain: it gives no more frames to its consumer, and reports `TransformEvent::Detached`.
+//! The capture, and any other consumer of it, carries on.
+//!
+//! A chain runs on the thread that drives it. `TransformTap` drives one from a tap of a
+//! `TapSplitter`, on the tap's own thread, so transforms never hold up the driver callback.
+//!
+//! A chain given a `crate::cpu::CpuMeter` records the time of its stages there as the
+//! transform stage, and a `TransformTap` records its consumer's time as the consumer stage.
+//!
+//! This module is experimental, see `crate::experimental`: the negotiation of layouts and the
+//! statistics of a stage are expected to change as transforms are added.
+
+use crate::cpu::{CpuMeter, CpuStage};
+use crate::deinterlace::FrameLayout;
+use crate::frame::{
+    pixel_group, DecklinkAlignedBytes, DecklinkFrameBase, DecklinkFrameFlags, DecklinkPixelFormat,
+    Rect, RegionCopyError,
+};
+use crate::memcopy::{copy_frame, CopyHint, CopyLayout};
+use crate::tap::{DeckLinkTapCallback, TapReport, TappedFrame};
+use crate::SdkError;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::time::{Duration, Instant};
+
+fn byte_count(layout: &FrameLayout) -> usize {
+    layout.row_bytes * layout.height
+}
+
+/// A frame given to a transform to read.
+#[derive(Debug, Copy, Clone)]
+pub struct FrameView<'a> {
+    bytes: &'a [u8],
+    layout: FrameLayout,
+    flags: DecklinkFrameFlags,
+}
+
+impl<'a> FrameView<'a> {
+    /// A view of `bytes` as a frame of `layout`. Fails if there are too few bytes.
+    pub fn new(
+        bytes: &'a [u8],
+        layout: FrameLayout,
+        flags: DecklinkFrameFlags,
+    ) -> Result<FrameView<'a>, TransformError> {
+        let len = byte_count(&layout);
+        if bytes.len() < len {
+            return Err(TransformError::BufferTooSmall);
+        }
+        Ok(FrameView {
+            bytes: &bytes[..len],
+            layout,
+            flags,
+        })
+    }
+
+    pub fn layout(&self) -> FrameLayout {
+        self.layout
+    }
+
+    /// The pixel data, `layout().row_bytes` bytes for each row.
+    pub fn data(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    /// Get row `index`, or `None` if it is past the last row.
+    pub fn row(&self, index: usize) -> Option<&'a [u8]> {
+        let start = index.checked_mul(self.layout.row_bytes)?;
+        self.bytes.get(start..start + self.layout.row_bytes)
+    }
+}
+
+impl DecklinkFrameBase for FrameView<'_> {
+    fn width(&self) -> usize {
+        self.layout.width
+    }
+    fn height(&self) -> usize {
+        self.layout.height
+    }
+    fn row_bytes(&self) -> usize {
+        self.layout.row_bytes
+    }
+    fn pixel_format(&self) -> DecklinkPixelFormat {
+        self.layout.pixel_format
+    }
+    fn flags(&self) -> DecklinkFrameFlags {
+        self.flags
+    }
+    fn bytes(&self) -> Result<DecklinkAlignedBytes<'_>, SdkError> {
+        Ok(DecklinkAlignedBytes(self.bytes))
+    }
+}
+
+/// A frame given to a transform to write, in the layout it negotiated.
+///
+/// The buffer is reused from frame to frame, so it holds whatever was written to it last.
+/// A transform that is not in place must write all of it.
+#[derive(Debug)]
+pub struct FrameBufferMut<'a> {
+    bytes: &'a mut [u8],
+    layout: FrameLayout,
+}
+
+impl<'a> FrameBufferMut<'a> {
+    /// A buffer of `bytes` for a frame of `layout`. Fails if there are too few bytes.
+    pub fn new(
+        bytes: &'a mut [u8],
+        layout: FrameLayout,
+    ) -> Result<FrameBufferMut<'a>, TransformError> {
+        let len = byte_count(&layout);
+        if bytes.len() < len {
+            return Err(TransformError::BufferTooSmall);
+        }
+        Ok(FrameBufferMut {
+            bytes: &mut bytes[..len],
+            layout,
+        })
+    }
+
+    pub fn layout(&self) -> FrameLayout {
+        self.layout
+    }
+
+    pub fn data(&self) -> &[u8] {
+        self.bytes
+    }
+
+    /// The pixel data, `layout().row_bytes` bytes for each row.
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        self.bytes
+    }
+
+    /// Get row `index` to write, or `None` if it is past the last row.
+    pub fn row_mut(&mut self, index: usize) -> Option<&mut [u8]> {
+        let start = index.checked_mul(self.layout.row_bytes)?;
+        self.bytes.get_mut(start..start + self.layout.row_bytes)
+    }
+}
+
+/// Why a transform refused a layout.
+#[derive(PartialEq, Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum NegotiationError {
+    UnsupportedPixelFormat(DecklinkPixelFormat),
+    /// The frame is the wrong size or shape for the transform.
+    UnsupportedLayout(FrameLayout),
+}
+
+impl std::fmt::Display for NegotiationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NegotiationError::UnsupportedPixelFormat(format) => {
+                write!(f, "pixel format {:?} is not supported", format)
+            }
+            NegotiationError::UnsupportedLayout(layout) => write!(
+                f,
+                "a {}x{} frame is not supported",
+                layout.width, layout.height
+            ),
+        }
+    }
+}
+
+impl std::error::Error for NegotiationError {}
+
+/// Why a transform failed on a frame.
+#[derive(Debug)]
+pub enum TransformError {
+    /// A buffer is smaller than its layout requires.
+    BufferTooSmall,
+    /// `FrameTransform::process_in_place` was called on a transform that cannot work in
+    /// place.
+    InPlaceUnsupported,
+    /// The transform failed for a reason of its own.
+    Failed(String),
+    Sdk(SdkError),
+}
+
+impl From<SdkError> for TransformError {
+    fn from(e: SdkError) -> Self {
+        TransformError::Sdk(e)
+    }
+}
+
+impl std::fmt::Display for TransformError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TransformError::BufferTooSmall => write!(f, "the buffer is too small"),
+            TransformError::InPlaceUnsupported => {
+                write!(f, "the transform cannot work in place")
+            }
+            TransformError::Failed(reason) => write!(f, "{}", reason),
+            TransformError::Sdk(e) => write!(f, "failed to read the frame: {:?}", e),
+        }
+    }
+}
+
+impl std::error::Error for TransformError {}
+
+/// A step of processing applied to every frame of a `TransformChain`.
+pub trait FrameTransform: Send {
+    /// A name for the transform, used in its statistics.
+    fn name(&self) -> &str;
+
+    /// The layout the transform produces from frames of `input`, or why it cannot take them.
+    /// Called when the chain is configured, and again whenever the input layout changes.
+    fn negotiate(&self, input: FrameLayout) -> Result<FrameLayout, NegotiationError>;
+
+    /// Whether `process_in_place` can be used instead of `process`. Only used when
+    /// `negotiate` gives back the layout it was offered.
+    fn in_place(&self) -> bool {
+        false
+    }
+
+    /// Write the transform of `input` to `output`.
+    fn process(
+        &self,
+        input: FrameView<'_>,
+        output: &mut FrameBufferMut<'_>,
+    ) -> Result<(), TransformError>;
+
+    /// Transform `frame` in place.
+    fn process_in_place(&self, _frame: &mut FrameBufferMut<'_>) -> Result<(), TransformError> {
+        Err(TransformError::InPlaceUnsupported)
+    }
+}
+
+/// How long a stage of a chain has taken, and how often it failed.
+#[derive(PartialEq, Eq, Debug, Clone, Hash)]
+pub struct TransformStageStats {
+    pub name: String,
+    /// Whether the stage runs in place for the layout the chain is configured for.
+    pub in_place: bool,
+    /// The frames the stage processed, including those it failed on.
+    pub frames: u64,
+    pub failures: u64,
+    pub total_time: Duration,
+    pub max_time: Duration,
+}
+
+impl TransformStageStats {
+    /// The average time the stage took for a frame.
+    pub fn mean_time(&self) -> Duration {
+        match self.frames {
+            0 => Duration::ZERO,
+            frames => self.total_time / frames.min(u32::MAX as u64) as u32,
+        }
+    }
+}
+
+/// Something that happened to a chain, as reported by `TransformChain::take_events`.
+#[derive(PartialEq, Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TransformEvent {
+    /// The chain was configured for frames of `input`, and produces frames of `output`.
+    Configured {
+        input: FrameLayout,
+        output: FrameLayout,
+    },
+    /// Stage `stage` refused the layout it would have been given, so frames of `input` are
+    /// dropped until the layout changes.
+    NegotiationFailed {
+        input: FrameLayout,
+        stage: usize,
+        error: NegotiationError,
+    },
+    /// Stage `stage` panicked, and the chain runs no more frames.
+    Detached { stage: usize },
+}
+
+/// Why a chain did not produce a frame.
+#[derive(Debug)]
+pub enum ChainError {
+    /// Stage `stage` refused the layout of the frame.
+    Negotiation {
+        stage: usize,
+        error: NegotiationError,
+    },
+    /// Stage `stage` failed on the frame.
+    Transform {
+        stage: usize,
+        error: TransformError,
+    },
+    /// Stage `stage` panicked on the frame, and the chain detached.
+    Panicked {
+        stage: usize,
+    },
+    /// The chain detached after an earlier panic.
+    Detached,
+    Sdk(SdkError),
+}
+
+impl From<SdkError> for ChainError {
+    fn from(e: SdkError) -> Self {
+        ChainError::Sdk(e)
+    }
+}
+
+impl std::fmt::Display for ChainError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChainError::Negotiation { stage, error } => {
+                write!(f, "stage {} refused the frame: {}", stage, error)
+            }
+            ChainError::Transform { stage, error } => {
+                write!(f, "stage {} failed: {}", stage, error)
+            }
+            ChainError::Panicked { stage } => write!(f, "stage {} panicked", stage),
+            ChainError::Detached => write!(f, "the chain has detached"),
+            ChainError::Sdk(e) => write!(f, "failed to read the frame: {:?}", e),
+        }
+    }
+}
+
+impl std::error::Error for ChainError {}
+
+struct Stage {
+    transform: Box<dyn FrameTransform>,
+    /// The layout the stage produces, once the chain is configured.
+    output: Option<FrameLayout>,
+    in_place: bool,
+    stats: TransformStageStats,
+}
+
+/// Runs frames through a list of transforms. See the module documentation.
+pub struct TransformChain {
+    stages: Vec<Stage>,
+    /// The layout the chain was last configured for, whether or not that succeeded.
+    input: Option<FrameLayout>,
+    refused: Option<(usize, NegotiationError)>,
+    /// The buffers the stages write to, in turn.
+    buffers: [Vec<u8>; 2],
+    detached: Option<usize>,
+    events: Vec<TransformEvent>,
+    cpu: Option<CpuMeter>,
+}
+
+impl Default for TransformChain {
+    fn default() -> Self {
+        TransformChain::new()
+    }
+}
+
+impl TransformChain {
+    /// A chain with no stages, which gives frames through unchanged.
+    pub fn new() -> TransformChain {
+        TransformChain {
+            stages: Vec::new(),
+            input: None,
+            refused: None,
+            buffers: [Vec::new(), Vec::new()],
+            detached: None,
+            events: Vec::new(),
+            cpu: None,
+        }
+    }
+
+    /// Add a stage after the others. The chain is configured again on the next frame.
+    pub fn push<T: FrameTransform + 'static>(&mut self, transform: T) -> &mut Self {
+        self.stages.push(Stage {
+            stats: TransformStageStats {
+                name: transform.name().to_string(),
+                in_place: false,
+                frames: 0,
+                failures: 0,
+                total_time: Duration::ZERO,
+                max_time: Duration::ZERO,
+            },
+            transform: Box::new(transform),
+            output: None,
+            in_place: false,
+        });
+        self.input = None;
+        self
+    }
+
+    /// Add a stage after the others, for building a chain in one expression.
+    pub fn with<T: FrameTransform + 'static>(mut self, transform: T) -> Self {
+        self.push(transform);
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.stages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stages.is_empty()
+    }
+
+    /// Record the time of every stage in `meter` as `CpuStage::Transform`, or stop with
+    /// `None`.
+    pub fn set_cpu_meter(&mut self, meter: Option<CpuMeter>) {
+        self.cpu = meter;
+    }
+
+    /// The meter the chain records its time in.
+    pub fn cpu_meter(&self) -> Option<&CpuMeter> {
+        self.cpu.as_ref()
+    }
+
+    /// The stage that panicked and detached the chain, if one has.
+    pub fn detached(&self) -> Option<usize> {
+        self.detached
+    }
+
+    /// The layout the chain produces, if it is configured.
+    pub fn output_layout(&self) -> Option<FrameLayout> {
+        let input = self.input?;
+        if self.refused.is_some() {
+            return None;
+        }
+        Some(self.stages.last().and_then(|s| s.output).unwrap_or(input))
+    }
+
+    /// Negotiate the layouts of every stage for frames of `input`, and allocate the buffers
+    /// between them. Returns the layout the chain produces.
+    pub fn configure(&mut self, input: FrameLayout) -> Result<FrameLayout, ChainError> {
+        if self.detached.is_some() {
+            return Err(ChainError::Detached);
+        }
+        self.input = Some(input);
+        self.refused = None;
+
+        let mut layout = input;
+        let mut largest = 0;
+        for index in 0..self.stages.len() {
+            let stage = &mut self.stages[index];
+            stage.output = None;
+            let negotiated = catch_unwind(AssertUnwindSafe(|| stage.transform.negotiate(layout)));
+            let output = match negotiated {
+                Ok(Ok(output)) => output,
+                Ok(Err(error)) => {
+                    self.refused = Some((index, error));
+                    self.events.push(TransformEvent::NegotiationFailed {
+                        input,
+                        stage: index,
+                        error,
+                    });
+                    return Err(ChainError::Negotiation {
+                        stage: index,
+                        error,
+                    });
+                }
+                Err(_) => {
+                    self.detach(index);
+                    return Err(ChainError::Panicked { stage: index });
+                }
+            };
+
+            stage.in_place = output == layout && stage.transform.in_place();
+            stage.stats.in_place = stage.in_place;
+            stage.output = Some(output);
+            // An in place stage at the start needs the frame copied to a buffer first
+            largest = largest.max(byte_count(&output)).max(byte_count(&layout));
+            layout = output;
+        }
+
+        for buffer in &mut self.buffers {
+            buffer.resize(largest, 0);
+        }
+        self.events.push(TransformEvent::Configured {
+            input,
+            output: layout,
+        });
+        Ok(layout)
+    }
+
+    /// Run `frame` through every stage, configuring the chain first if the frame's layout
+    /// is not the one it was configured for. The result borrows the chain's buffers, or the
+    /// frame itself if there are no stages.
+    pub fn run<'a>(
+        &'a mut self,
+        frame: &'a dyn DecklinkFrameBase,
+    ) -> Result<FrameView<'a>, ChainError> {
+        if self.detached.is_some() {
+            return Err(ChainError::Detached);
+        }
+        let input = FrameLayout::of(frame);
+        if self.input != Some(input) {
+            self.configure(input)?;
+        }
+        if let Some((stage, error)) = self.refused {
+            return Err(ChainError::Negotiation { stage, error });
+        }
+
+        let flags = frame.flags();
+        let source = frame.bytes()?.0;
+        let source = FrameView::new(source, input, flags)
+            .map_err(|_| ChainError::Sdk(SdkError::INVALIDARG))?;
+
+        // The buffer holding the output of the last stage run, or none for the frame itself
+        let mut current: Option<usize> = None;
+        let mut layout = input;
+        for index in 0..self.stages.len() {
+            let stage = &mut self.stages[index];
+            let output = stage.output.unwrap_or(layout);
+            let [first, second] = &mut self.buffers;
+
+            let started = Instant::now();
+            let result = if stage.in_place {
+                let buffer = match current {
+                    Some(1) => second,
+                    Some(_) => first,
+                    None => {
+                        let copy = CopyLayout::contiguous(byte_count(&layout));
+                        copy_frame(first, source.data(), copy, CopyHint::WillReadSoon)?;
+                        current = Some(0);
+                        first
+                    }
+                };
+                catch_unwind(AssertUnwindSafe(|| {
+                    let mut frame = FrameBufferMut::new(buffer, layout)?;
+                    stage.transform.process_in_place(&mut frame)
+                }))
+            } else {
+                let (from, to, next) = match current {
+                    None => (source.data(), first, 0),
+                    Some(0) => (&first[..], second, 1),
+                    Some(_) => (&second[..], first, 0),
+                };
+                current = Some(next);
+                catch_unwind(AssertUnwindSafe(|| {
+                    let from = FrameView::new(from, layout, flags)?;
+                    let mut to = FrameBufferMut::new(to, output)?;
+                    stage.transform.process(from, &mut to)
+                }))
+            };
+            let elapsed = started.elapsed();
+            if let Some(cpu) = &self.cpu {
+                cpu.record(CpuStage::Transform, elapsed);
+            }
+
+            stage.stats.frames += 1;
+            stage.stats.total_time += elapsed;
+            stage.stats.max_time = stage.stats.max_time.max(elapsed);
+            match result {
+                Ok(Ok(())) => {}
+                Ok(Err(error)) => {
+                    stage.stats.failures += 1;
+                    return Err(ChainError::Transform {
+                        stage: index,
+                        error,
+                    });
+                }
+                Err(_) => {
+                    stage.stats.failures += 1;
+                    self.detach(index);
+                    return Err(ChainError::Panicked { stage: index });
+                }
+            }
+            layout = output;
+        }
+
+        match current {
+            None => Ok(source),
+            // The buffers were sized for every stage when the chain was configured
+            Some(index) => FrameView::new(&self.buffers[index], layout, flags)
+                .map_err(|_| ChainError::Sdk(SdkError::INVALIDARG)),
+        }
+    }
+
+    /// The statistics of each stage, in order.
+    pub fn stats(&self) -> Vec<TransformStageStats> {
+        self.stages.iter().map(|s| s.stats.clone()).collect()
+    }
+
+    /// The events since the last call, oldest first.
+    pub fn take_events(&mut self) -> Vec<TransformEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    fn detach(&mut self, stage: usize) {
+        self.detached = Some(stage);
+        self.events.push(TransformEvent::Detached { stage });
+    }
+}
+
+/// Receives the frames of a `TransformTap`, once they have been through its chain.
+pub trait DeckLinkTransformCallback: Send {
+    /// Called with each frame the chain produced, and the tapped frame it was made from.
+    fn frame_transformed(&mut self, frame: FrameView<'_>, tapped: &TappedFrame);
+
+    /// Called with the events of the chain, after the frame that caused them.
+    fn chain_events(&mut self, _events: Vec<TransformEvent>) {}
+
+    /// Called once the tap has detached, with the final statistics of the chain.
+    fn tap_finished(&mut self, _report: TapReport, _stats: Vec<TransformStageStats>) {}
+}
+
+/// A tap that runs its frames through a `TransformChain` before giving them to a consumer.
+///
+/// Attach it with `TapSplitter::tap`. Frames a stage fails on are not given to the consumer.
+/// Once a stage panics, no more frames are, though the tap stays attached until its spec is
+/// satisfied or it is cancelled.
+pub struct TransformTap<C> {
+    chain: TransformChain,
+    consumer: C,
+}
+
+impl<C: DeckLinkTransformCallback> TransformTap<C> {
+    pub fn new(chain: TransformChain, consumer: C) -> TransformTap<C> {
+        TransformTap { chain, consumer }
+    }
+}
+
+impl<C: DeckLinkTransformCallback> DeckLinkTapCallback for TransformTap<C> {
+    fn frame_tapped(&mut self, frame: TappedFrame) {
+        let cpu = self.chain.cpu.clone();
+        if let Ok(view) = self.chain.run(&frame) {
+            match cpu {
+                Some(cpu) => cpu.measure(CpuStage::Consumer, || {
+                    self.consumer.frame_transformed(view, &frame)
+                }),
+                None => self.consumer.frame_transformed(view, &frame),
+            }
+        }
+        let events = self.chain.take_events();
+        if !events.is_empty() {
+            self.consumer.chain_events(events);
+        }
+    }
+
+    fn tap_finished(&mut self, report: TapReport) {
+        self.consumer.tap_finished(report, self.chain.stats());
+    }
+}
+
+fn region_error(e: RegionCopyError) -> TransformError {
+    match e {
+        RegionCopyError::DestinationTooSmall => TransformError::BufferTooSmall,
+        RegionCopyError::Sdk(e) => TransformError::Sdk(e),
+        e => TransformError::Failed(format!("{:?}", e)),
+    }
+}
+
+/// Check that `rect` lies within `layout` and on whole pixel groups of its format.
+fn check_rect(rect: Rect, layout: FrameLayout) -> Result<(usize, usize), NegotiationError> {
+    let (group_pixels, group_bytes) = pixel_group(layout.pixel_format).ok_or(
+        NegotiationError::UnsupportedPixelFormat(layout.pixel_format),
+    )?;
+    if !rect.x.is_multiple_of(group_pixels)
+        || !rect.width.is_multiple_of(group_pixels)
+        || rect.x + rect.width > layout.width
+        || rect.y + rect.height > layout.height
+    {
+        return Err(NegotiationError::UnsupportedLayout(layout));
+    }
+    Ok((group_pixels, group_bytes))
+}
+
+/// Crops frames to a rectangle, which must lie on whole pixel groups of the pixel format.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+pub struct Crop {
+    pub rect: Rect,
+}
+
+impl FrameTransform for Crop {
+    fn name(&self) -> &str {
+        "crop"
+    }
+
+    fn negotiate(&self, input: FrameLayout) -> Result<FrameLayout, NegotiationError> {
+        let (group_pixels, group_bytes) = check_rect(self.rect, input)?;
+        Ok(FrameLayout {
+            width: self.rect.width,
+            height: self.rect.height,
+            row_bytes: self.rect.width / group_pixels * group_bytes,
+            pixel_format: input.pixel_format,
+        })
+    }
+
+    fn process(
+        &self,
+        input: FrameView<'_>,
+        output: &mut FrameBufferMut<'_>,
+    ) -> Result<(), TransformError> {
+        let row_bytes = output.layout().row_bytes;
+        input
+            .copy_region_into(self.rect, output.data_mut(), row_bytes)
+            .map_err(region_error)
+    }
+}
+
+/// Paints a rectangle of every frame black, such as to hide part of the picture. Works in
+/// place, on the 8-bit packed pixel formats.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+pub struct MaskRegion {
+    pub rect: Rect,
+}
+
+impl MaskRegion {
+    /// The bytes of one pixel group of black.
+    fn black(format: DecklinkPixelFormat) -> Option<&'static [u8]> {
+        match format {
+            DecklinkPixelFormat::Format8BitYUV => Some(&[0x80, 0x10, 0x80, 0x10]),
+            DecklinkPixelFormat::Format8BitARGB => Some(&[0xff, 0, 0, 0]),
+            DecklinkPixelFormat::Format8BitBGRA => Some(&[0, 0, 0, 0xff]),
+            _ => None,
+        }
+    }
+}
+
+impl FrameTransform for MaskRegion {
+    fn name(&self) -> &str {
+        "mask"
+    }
+
+    fn negotiate(&self, input: FrameLayout) -> Result<FrameLayout, NegotiationError> {
+        if MaskRegion::black(input.pixel_format).is_none() {
+            return Err(NegotiationError::UnsupportedPixelFormat(input.pixel_format));
+        }
+        check_rect(self.rect, input)?;
+        Ok(input)
+    }
+
+    fn in_place(&self) -> bool {
+        true
+    }
+
+    fn process(
+        &self,
+        input: FrameView<'_>,
+        output: &mut FrameBufferMut<'_>,
+    ) -> Result<(), TransformError> {
+        let layout = CopyLayout::contiguous(input.data().len());
+        copy_frame(
+            output.data_mut(),
+            input.data(),
+            layout,
+            CopyHint::WillReadSoon,
+        )?;
+        self.process_in_place(output)
+    }
+
+    fn process_in_place(&self, frame: &mut FrameBufferMut<'_>) -> Result<(), TransformError> {
+        let layout = frame.layout();
+        let unsupported = || TransformError::Failed("unsupported pixel format".to_string());
+        let black = MaskRegion::black(layout.pixel_format).ok_or_else(unsupported)?;
+        let (group_pixels, group_bytes) =
+            pixel_group(layout.pixel_format).ok_or_else(unsupported)?;
+        let start = self.rect.x / group_pixels * group_bytes;
+        let len = self.rect.width / group_pixels * group_bytes;
+        for y in self.rect.y..self.rect.y + self.rect.height {
+            let row = frame.row_mut(y).ok_or(TransformError::BufferTooSmall)?;
+            let region = row
+                .get_mut(start..start + len)
+                .ok_or(TransformError::BufferTooSmall)?;
+            for group in region.chunks_exact_mut(black.len()) {
+                group.copy_from_slice(black);
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,372 @@
+//! Handing the buffers of retained frames to consumers outside the crate.
+//!
+//! When the address of a frame's buffer is given to another device's driver, such as for
+//! DMA into an FPGA or GPU, dropping the frame is too easy to get wrong as the signal that
+//! the memory is free again. `RetainedFrame::into_external_use` instead gives out the
+//! address with a `CompletionToken`, and the buffer stays valid until the token is
+//! completed. The frame stays charged to its `RetentionBudget` meanwhile, and is counted in
+//! `RetentionStats::external_frames`.
+//!
+//! A token dropped without being completed is treated as a consumer that went away. Its
+//! buffer stays valid for `ExternalUseConfig::leak_grace`, in case the device is still
+//! using it, and is then reclaimed, so a crashed consumer cannot keep buffers out of the
+//! capture pool for ever. `ExternalUseConfig::hold_timeout` also reclaims the buffers of
+//! tokens that are still held, for consumers that may hang. Both are reported as
+//! `ExternalUseEvent`s, taken with `RetentionBudget::take_external_use_events`.
+//!
+//! The frame holds the driver's reference to its buffer, so disabling the input or
+//! releasing a custom allocator provider while tokens are outstanding does not free their
+//! buffers: the driver frees each one when its token is completed or reclaimed.
+
+use crate::allocator::BufferSpec;
+use crate::frame::DecklinkFrameBase;
+use crate::retention::RetainedFrame;
+use crate::threads;
+use crate::util::internal_thread_started;
+use crate::SdkError;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub struct ExternalUseConfig {
+    /// How long the buffer of a token dropped without being completed stays valid before
+    /// it is reclaimed.
+    pub leak_grace: Duration,
+    /// Reclaim buffers held for longer than this, even though their token is still held.
+    /// The consumer must not touch a buffer after it is reclaimed, so only set this when
+    /// the consumer is known to be done with a buffer by then.
+    pub hold_timeout: Option<Duration>,
+}
+
+impl Default for ExternalUseConfig {
+    fn default() -> Self {
+        ExternalUseConfig {
+            leak_grace: Duration::from_secs(1),
+            hold_timeout: None,
+        }
+    }
+}
+
+/// The buffer of a frame in external use.
+#[derive(Debug, Copy, Clone)]
+pub struct ExternalFrameRef {
+    /// The address of the buffer, valid until the token is completed or the buffer is
+    /// reclaimed.
+    pub ptr: *const u8,
+    /// The size of the buffer in bytes.
+    pub len: usize,
+    pub spec: BufferSpec,
+    /// The number of the hand off, counted per budget, shared with the token.
+    pub sequence: u64,
+}
+
+// Safety: The reference only describes the buffer. Whoever dereferences the pointer is
+// responsible for doing so while the buffer is valid.
+unsafe impl Send for ExternalFrameRef {}
+unsafe impl Sync for ExternalFrameRef {}
+
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ReclaimReason {
+    /// The token was dropped without being completed.
+    Leaked,
+    /// The buffer was held for longer than `ExternalUseConfig::hold_timeout`.
+    HoldTimeout,
+}
+
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ExternalUseEvent {
+    Completed {
+        sequence: u64,
+        held_for: Duration,
+    },
+    /// The token was dropped without being completed, and the buffer will be reclaimed
+    /// after `reclaim_after`.
+    Leaked {
+        sequence: u64,
+        reclaim_after: Duration,
+    },
+    /// The buffer was returned to the driver without the token being completed.
+    Reclaimed {
+        sequence: u64,
+        reason: ReclaimReason,
+        held_for: Duration,
+    },
+    /// The token was completed after its buffer had been reclaimed.
+    LateCompletion {
+        sequence: u64,
+    },
+}
+
+/// What completing a token did.
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub enum CompletionOutcome {
+    /// The buffer was returned to the driver.
+    Completed,
+    /// The token had already been completed, so nothing was done.
+    AlreadyCompleted,
+    /// The buffer had already been reclaimed.
+    Reclaimed(ReclaimReason),
+}
+
+/// Marks the end of the external use of a buffer.
+///
+/// A token can be sent to the thread that learns when the consumer is done. Completing it
+/// more than once does nothing after the first time.
+pub struct CompletionToken {
+    state: Arc<ExternalState>,
+    sequence: u64,
+    completed: AtomicBool,
+}
+
+impl CompletionToken {
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
+
+    /// Return the buffer to the driver.
+    pub fn complete(&self) -> CompletionOutcome {
+        if self.completed.swap(true, Ordering::AcqRel) {
+            return CompletionOutcome::AlreadyCompleted;
+        }
+        self.state.complete(self.sequence)
+    }
+}
+
+impl Drop for CompletionToken {
+    fn drop(&mut self) {
+        if !self.completed.load(Ordering::Acquire) {
+            self.state.abandon(self.sequence);
+        }
+    }
+}
+
+struct Holding {
+    frame: RetainedFrame,
+    since: Instant,
+    /// When the buffer is reclaimed, once its token has been dropped.
+    leak_deadline: Option<Instant>,
+}
+
+impl Holding {
+    fn deadline(&self, hold_timeout: Option<Duration>) -> Option<Instant> {
+        let hold_deadline = hold_timeout.map(|timeout| self.since + timeout);
+        match (self.leak_deadline, hold_deadline) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Holdings {
+    config: ExternalUseConfig,
+    held: HashMap<u64, Holding>,
+    /// The reclaimed buffers of tokens that are still held.
+    reclaimed: HashMap<u64, ReclaimReason>,
+    events: Vec<ExternalUseEvent>,
+    reaper_running: bool,
+}
+
+/// The buffers in external use from one `RetentionBudget`.
+#[derive(Default)]
+pub(crate) struct ExternalState {
+    holdings: Mutex<Holdings>,
+    wake: Condvar,
+    next_sequence: AtomicU64,
+    leaked: AtomicU64,
+    reclaimed: AtomicU64,
+}
+
+impl ExternalState {
+    pub(crate) fn config(&self) -> ExternalUseConfig {
+        self.holdings.lock().unwrap().config
+    }
+
+    pub(crate) fn set_config(self: &Arc<Self>, config: ExternalUseConfig) {
+        let mut holdings = self.holdings.lock().unwrap();
+        holdings.config = config;
+        if !holdings.held.is_empty() {
+            self.wake_reaper(&mut holdings);
+        }
+    }
+
+    pub(crate) fn take_events(&self) -> Vec<ExternalUseEvent> {
+        std::mem::take(&mut self.holdings.lock().unwrap().events)
+    }
+
+    /// The number of buffers in external use, and the number leaked and reclaimed so far.
+    pub(crate) fn counts(&self) -> (usize, u64, u64) {
+        (
+            self.holdings.lock().unwrap().held.len(),
+            self.leaked.load(Ordering::Relaxed),
+            self.reclaimed.load(Ordering::Relaxed),
+        )
+    }
+
+    pub(crate) fn hand_off(
+        self: &Arc<Self>,
+        frame: RetainedFrame,
+    ) -> Result<(ExternalFrameRef, CompletionToken), SdkError> {
+        let bytes = frame.bytes()?;
+        let ptr = bytes.0.as_ptr();
+        let len = bytes.0.len();
+        let spec = BufferSpec {
+            buffer_size: len as u32,
+            width: frame.width() as u32,
+            height: frame.height() as u32,
+            row_bytes: frame.row_bytes() as u32,
+            pixel_format: frame.pixel_format() as u32,
+        };
+        let sequence = self.next_sequence.fetch_add(1, Ordering::Relaxed);
+
+        let mut holdings = self.holdings.lock().unwrap();
+        holdings.held.insert(
+            sequence,
+            Holding {
+                frame,
+                since: Instant::now(),
+                leak_deadline: None,
+            },
+        );
+        if holdings.config.hold_timeout.is_some() {
+            self.wake_reaper(&mut holdings);
+        }
+        drop(holdings);
+
+        Ok((
+            ExternalFrameRef {
+                ptr,
+                len,
+                spec,
+                sequence,
+            },
+            CompletionToken {
+                state: self.clone(),
+                sequence,
+                completed: AtomicBool::new(false),
+            },
+        ))
+    }
+
+    fn complete(&self, sequence: u64) -> CompletionOutcome {
+        let mut holdings = self.holdings.lock().unwrap();
+        if let Some(holding) = holdings.held.remove(&sequence) {
+            holdings.events.push(ExternalUseEvent::Completed {
+                sequence,
+                held_for: holding.since.elapsed(),
+            });
+            drop(holdings);
+            drop(holding);
+            CompletionOutcome::Completed
+        } else {
+            let reason = holdings
+                .reclaimed
+                .remove(&sequence)
+                .unwrap_or(ReclaimReason::HoldTimeout);
+            holdings
+                .events
+                .push(ExternalUseEvent::LateCompletion { sequence });
+            CompletionOutcome::Reclaimed(reason)
+        }
+    }
+
+    fn abandon(self: &Arc<Self>, sequence: u64) {
+        let mut holdings = self.holdings.lock().unwrap();
+        if holdings.reclaimed.remove(&sequence).is_some() {
+            return;
+        }
+        let grace = holdings.config.leak_grace;
+        if let Some(holding) = holdings.held.get_mut(&sequence) {
+            holding.leak_deadline = Some(Instant::now() + grace);
+        } else {
+            return;
+        }
+        holdings.events.push(ExternalUseEvent::Leaked {
+            sequence,
+            reclaim_after: grace,
+        });
+        self.leaked.fetch_add(1, Ordering::Relaxed);
+        self.wake_reaper(&mut holdings);
+    }
+
+    /// Start the reaper thread, or wake it to look at a new deadline.
+    fn wake_reaper(self: &Arc<Self>, holdings: &mut Holdings) {
+        if holdings.reaper_running {
+            self.wake.notify_one();
+            return;
+        }
+        holdings.reaper_running = true;
+        let state = self.clone();
+        // The reaper is not joined, as it exits by itself once nothing is held
+        drop(threads::spawn("reaper", move || {
+            internal_thread_started("reaper");
+            state.run_reaper();
+        }));
+    }
+
+    fn run_reaper(&self) {
+        let mut holdings = self.holdings.lock().unwrap();
+        loop {
+            let now = Instant::now();
+            let hold_timeout = holdings.config.hold_timeout;
+            let mut expired: Vec<u64> = holdings
+                .held
+                .iter()
+                .filter(|(_, h)| h.deadline(hold_timeout).is_some_and(|d| d <= now))
+                .map(|(sequence, _)| *sequence)
+                .collect();
+            expired.sort_unstable();
+
+            if !expired.is_empty() {
+                let mut frames = Vec::with_capacity(expired.len());
+                for sequence in expired {
+                    let holding = holdings.held.remove(&sequence).unwrap();
+                    let reason = if holding.leak_deadline.is_some() {
+                        ReclaimReason::Leaked
+                    } else {
+                        holdings
+                            .reclaimed
+                            .insert(sequence, ReclaimReason::HoldTimeout);
+                        ReclaimReason::HoldTimeout
+                    };
+                    holdings.events.push(ExternalUseEvent::Reclaimed {
+                        sequence,
+                        reason,
+                        held_for: now.saturating_duration_since(holding.since),
+                    });
+                    self.reclaimed.fetch_add(1, Ordering::Relaxed);
+                    frames.push(holding.frame);
+                }
+
+                // Return the buffers to the driver without holding up the tokens
+                drop(holdings);
+                drop(frames);
+                holdings = self.holdings.lock().unwrap();
+                continue;
+            }
+
+            let next = holdings
+                .held
+                .values()
+                .filter_map(|h| h.deadline(hold_timeout))
+                .min();
+            match next {
+                Some(deadline) => {
+                    holdings = self
+                        .wake
+                        .wait_timeout(holdings, deadline.saturating_duration_since(now))
+                        .unwrap()
+                        .0;
+                }
+                None => {
+                    holdings.reaper_running = false;
+                    return;
+                }
+            }
+        }
+    }
+}
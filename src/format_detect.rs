@@ -0,0 +1,261 @@
+//! Deciding when a detected format change should re-enable the input.
+//!
+//! With format detection enabled, the driver reports each change of the input signal with
+//! `InputHandler::format_changed`, and capture continues in the old mode until the input is
+//! re-enabled in the new one. Re-enabling on every report is right for a clean signal, but
+//! some signals are misdetected or flap between modes, and following them loses frames for
+//! nothing.
+//!
+//! A `FormatDetector` is given every format change and frame callback in order, and returns
+//! a `FormatDecision` when the input should be re-enabled. It waits the configured number of
+//! frames after a change before deciding, and consults the rules of a
+//! `crate::experimental::quirks::QuirkPolicy` at each change, which can redirect the change
+//! to a PsF mode or hold it for longer. A change back to the mode the input is enabled in is
+//! dropped without a decision. The detector only counts and decides, so it can be driven from an
+//! input callback or from a recorded session.
+
+use crate::device::input::{
+    DecklinkDetectedVideoInputFormatFlags, DecklinkVideoInputFormatChangedEvents,
+};
+use crate::display_mode::DecklinkDisplayModeId;
+use crate::experimental::quirks::{
+    QuirkAction, QuirkApplied, QuirkCondition, QuirkPolicy, QuirkRule,
+};
+use crate::replay::SessionEvent;
+use std::collections::VecDeque;
+
+/// The most format changes kept for matching rules against.
+const HISTORY_LIMIT: usize = 64;
+
+/// The input should be re-enabled in a new mode.
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub struct FormatDecision {
+    pub mode: DecklinkDisplayModeId,
+    pub detected_flags: DecklinkDetectedVideoInputFormatFlags,
+    /// Whether `mode` is the progressive equivalent of the detected mode, as a quirk rule
+    /// treated the signal as PsF.
+    pub psf_forced: bool,
+}
+
+/// A change waiting out its debounce.
+#[derive(Debug, Copy, Clone)]
+struct Pending {
+    mode: DecklinkDisplayModeId,
+    flags: DecklinkDetectedVideoInputFormatFlags,
+    /// Frames since the most recent format change.
+    since_change: u32,
+    /// Frames since the detected mode became `mode`.
+    in_mode: u32,
+    debounce: u32,
+    ignore_shorter: u32,
+    psf_forced: bool,
+}
+
+/// Decides when format changes should re-enable the input.
+#[derive(Debug, Clone)]
+pub struct FormatDetector {
+    debounce_frames: u32,
+    rules: Vec<QuirkRule>,
+    /// The mode and flags the input is enabled with, if known.
+    current: Option<(DecklinkDisplayModeId, DecklinkDetectedVideoInputFormatFlags)>,
+    frames: u64,
+    /// Recent format changes, as the frame count and the detected mode.
+    history: VecDeque<(u64, DecklinkDisplayModeId)>,
+    pending: Option<Pending>,
+    /// Set once a rule has forced PsF, which then holds for the rest of the capture.
+    force_psf: bool,
+    applied: Vec<QuirkApplied>,
+}
+
+impl FormatDetector {
+    /// A detector that waits `debounce_frames` frames after a change before deciding, and
+    /// consults the rules of `policy`. With no debounce, a change is decided as it arrives.
+    pub fn new(debounce_frames: u32, policy: &QuirkPolicy) -> FormatDetector {
+        FormatDetector {
+            debounce_frames,
+            rules: policy.rules(),
+            current: None,
+            frames: 0,
+            history: VecDeque::new(),
+            pending: None,
+            force_psf: false,
+            applied: Vec::new(),
+        }
+    }
+
+    /// The number of frames seen so far.
+    pub fn frame_count(&self) -> u64 {
+        self.frames
+    }
+
+    /// Whether a change is waiting to be decided.
+    pub fn is_pending(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// Note that the input is enabled in `mode`, when capture starts or after it was
+    /// re-enabled in a mode other than the last decision. Drops any pending change.
+    pub fn enabled(
+        &mut self,
+        mode: DecklinkDisplayModeId,
+        detected_flags: DecklinkDetectedVideoInputFormatFlags,
+    ) {
+        self.current = Some((mode, detected_flags));
+        self.pending = None;
+    }
+
+    /// Note a format change reported by the driver.
+    pub fn format_changed(
+        &mut self,
+        _events: DecklinkVideoInputFormatChangedEvents,
+        mode: DecklinkDisplayModeId,
+        detected_flags: DecklinkDetectedVideoInputFormatFlags,
+    ) -> Option<FormatDecision> {
+        if self.history.len() == HISTORY_LIMIT {
+            self.history.pop_front();
+        }
+        self.history.push_back((self.frames, mode));
+
+        let (mut debounce, mut ignore_shorter) = (self.debounce_frames, 0);
+        let mut fired = Vec::new();
+        for rule in &self.rules {
+            if self.matches(&rule.condition, mode, detected_flags) {
+                fired.push(QuirkApplied {
+                    rule: rule.name.clone(),
+                    action: rule.action,
+                    frame: self.frames,
+                    detected_mode: mode,
+                });
+                match rule.action {
+                    QuirkAction::ForcePsf => self.force_psf = true,
+                    QuirkAction::ExtendDebounce { frames } => debounce = debounce.max(frames),
+                    QuirkAction::IgnoreShorterThan { frames } => {
+                        ignore_shorter = ignore_shorter.max(frames)
+                    }
+                }
+            }
+        }
+        self.applied.extend(fired);
+
+        let progressive = mode.progressive_equivalent().filter(|_| self.force_psf);
+        let target = progressive.unwrap_or(mode);
+        if self.current == Some((target, detected_flags)) {
+            self.pending = None;
+            return None;
+        }
+
+        let pending = match self.pending {
+            // The same mode again, so only the wait since the last change starts over
+            Some(p) if p.mode == target && p.flags == detected_flags => Pending {
+                since_change: 0,
+                debounce: p.debounce.max(debounce),
+                ignore_shorter: p.ignore_shorter.max(ignore_shorter),
+                ..p
+            },
+            Some(p) => Pending {
+                mode: target,
+                flags: detected_flags,
+                since_change: 0,
+                in_mode: 0,
+                debounce: p.debounce.max(debounce),
+                ignore_shorter: p.ignore_shorter.max(ignore_shorter),
+                psf_forced: progressive.is_some(),
+            },
+            None => Pending {
+                mode: target,
+                flags: detected_flags,
+                since_change: 0,
+                in_mode: 0,
+                debounce,
+                ignore_shorter,
+                psf_forced: progressive.is_some(),
+            },
+        };
+        self.pending = Some(pending);
+        self.decide()
+    }
+
+    /// Note a frame callback, whether or not it had a frame.
+    pub fn frame(&mut self) -> Option<FormatDecision> {
+        self.frames += 1;
+        if let Some(pending) = &mut self.pending {
+            pending.since_change = pending.since_change.saturating_add(1);
+            pending.in_mode = pending.in_mode.saturating_add(1);
+        }
+        self.decide()
+    }
+
+    /// Feed an event of a recorded session, as `format_changed` or `frame`.
+    pub fn replay_event(&mut self, event: &SessionEvent) -> Option<FormatDecision> {
+        match event {
+            SessionEvent::FormatChanged {
+                events,
+                display_mode,
+                detected_flags,
+            } => self.format_changed(*events, *display_mode, *detected_flags),
+            SessionEvent::Frame { .. } => self.frame(),
+            SessionEvent::AudioPacket { .. } => None,
+        }
+    }
+
+    /// The rules that fired since the last call, in the order they fired.
+    pub fn take_applied(&mut self) -> Vec<QuirkApplied> {
+        std::mem::take(&mut self.applied)
+    }
+
+    fn decide(&mut self) -> Option<FormatDecision> {
+        let pending = self.pending?;
+        if pending.since_change < pending.debounce || pending.in_mode < pending.ignore_shorter {
+            return None;
+        }
+        self.pending = None;
+        self.current = Some((pending.mode, pending.flags));
+        Some(FormatDecision {
+            mode: pending.mode,
+            detected_flags: pending.flags,
+            psf_forced: pending.psf_forced,
+        })
+    }
+
+    fn changes_within(&self, frames: u32) -> impl Iterator<Item = DecklinkDisplayModeId> + '_ {
+        let since = self.frames.saturating_sub(frames as u64);
+        self.history
+            .iter()
+            .filter(move |(frame, _)| *frame >= since)
+            .map(|(_, mode)| *mode)
+    }
+
+    fn matches(
+        &self,
+        condition: &QuirkCondition,
+        mode: DecklinkDisplayModeId,
+        detected_flags: DecklinkDetectedVideoInputFormatFlags,
+    ) -> bool {
+        match condition {
+            QuirkCondition::DetectedAs {
+                mode: wanted,
+                flags,
+            } => mode == *wanted && detected_flags.contains(*flags),
+            QuirkCondition::Oscillating {
+                changes,
+                within_frames,
+            } => self.changes_within(*within_frames).count() >= *changes as usize,
+            QuirkCondition::PsfPairOscillation { within_frames } => {
+                let pair = match (mode.progressive_equivalent(), mode.interlaced_equivalent()) {
+                    (Some(progressive), _) => (mode, progressive),
+                    (_, Some(interlaced)) => (interlaced, mode),
+                    _ => return false,
+                };
+                // The mode the input is enabled in counts as seen, so a PsF signal detected
+                // as interlaced while capturing it as progressive matches
+                let mut seen: Vec<_> = self.changes_within(*within_frames).collect();
+                seen.extend(self.current.map(|(mode, _)| mode));
+                seen.contains(&pair.0) && seen.contains(&pair.1)
+            }
+            QuirkCondition::AtStart { within_frames } => self.frames <= *within_frames as u64,
+            QuirkCondition::All(conditions) => conditions
+                .iter()
+                .all(|c| self.matches(c, mode, detected_flags)),
+        }
+    }
+}
@@ -1,9 +1,13 @@
+use crate::memcopy::{copy_frame, CopyHint, CopyLayout};
+use crate::ptr::VideoFramePtr;
+use crate::reference::DecklinkReferenceExt;
+use crate::timecode::{DecklinkTimecode, DecklinkTimecodeFormat};
 use crate::{sdk, SdkError};
 use aligned_vec::{AVec, ConstAlign};
 use num_traits::FromPrimitive;
-use std::ptr::null_mut;
 
-#[derive(EnumIter, FromPrimitive, PartialEq, Debug, Copy, Clone)]
+#[derive(EnumIter, FromPrimitive, PartialEq, Eq, Debug, Copy, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum DecklinkPixelFormat {
     Format8BitYUV = sdk::_DecklinkPixelFormat_decklinkFormat8BitYUV as isize,
     Format10BitYUV = sdk::_DecklinkPixelFormat_decklinkFormat10BitYUV as isize,
@@ -18,6 +22,26 @@ pub enum DecklinkPixelFormat {
     FormatDNxHR = sdk::_DecklinkPixelFormat_decklinkFormatDNxHR as isize,
 }
 
+impl DecklinkPixelFormat {
+    /// Whether the format is one of the 10-bit RGB formats, `r210`, `R10b` or `R10l`, which
+    /// `crate::convert` unpacks.
+    pub fn is_rgb_10bit(&self) -> bool {
+        matches!(
+            self,
+            DecklinkPixelFormat::Format10BitRGB
+                | DecklinkPixelFormat::Format10BitRGBX
+                | DecklinkPixelFormat::Format10BitRGBXLE
+        )
+    }
+
+    /// The bytes in a row of `width` pixels, including the padding the driver adds to each
+    /// row, by the packing of `crate::reference`. `None` for the compressed formats, whose
+    /// frames have no fixed row size.
+    pub fn bytes_per_row(&self, width: usize) -> Option<usize> {
+        self.metadata().packing.map(|p| p.row_bytes(width))
+    }
+}
+
 bitflags! {
     #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
     pub struct DecklinkFrameFlags: u32 {
@@ -41,7 +65,128 @@ pub trait DecklinkFrameBase {
     fn flags(&self) -> DecklinkFrameFlags;
     /// Get the pixel data of the video frame
     fn bytes(&self) -> Result<DecklinkAlignedBytes<'_>, SdkError>;
+
+    /// Copy a rectangle of the video frame into `dst`, which has `dst_row_bytes` bytes per row
+    fn copy_region_into(
+        &self,
+        rect: Rect,
+        dst: &mut [u8],
+        dst_row_bytes: usize,
+    ) -> Result<(), RegionCopyError> {
+        let format = self.pixel_format();
+        let (group_pixels, group_bytes) =
+            pixel_group(format).ok_or(RegionCopyError::Sdk(SdkError::NOTIMPL))?;
+
+        if !rect.x.is_multiple_of(group_pixels) || !rect.width.is_multiple_of(group_pixels) {
+            return Err(RegionCopyError::Alignment(AlignmentError {
+                pixel_format: format,
+                required_pixels: group_pixels,
+            }));
+        }
+        if rect.x + rect.width > self.width() || rect.y + rect.height > self.height() {
+            return Err(RegionCopyError::OutOfBounds);
+        }
+
+        let row_offset = rect.x / group_pixels * group_bytes;
+        let row_len = rect.width / group_pixels * group_bytes;
+        if dst_row_bytes < row_len
+            || (rect.height > 0 && dst.len() < (rect.height - 1) * dst_row_bytes + row_len)
+        {
+            return Err(RegionCopyError::DestinationTooSmall);
+        }
+
+        // A frame whose rows are shorter than its width needs would have the region run into
+        // the next row
+        let src_row_bytes = self.row_bytes();
+        if row_offset + row_len > src_row_bytes {
+            return Err(RegionCopyError::OutOfBounds);
+        }
+        let src = self.bytes()?;
+        let src = src
+            .0
+            .get(rect.y * src_row_bytes + row_offset..)
+            .ok_or(RegionCopyError::Sdk(SdkError::INVALIDARG));
+        let src = match src {
+            Ok(src) => src,
+            Err(_) if rect.height == 0 => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        let layout = CopyLayout::rows(row_len, rect.height, src_row_bytes, dst_row_bytes);
+        copy_frame(dst, src, layout, CopyHint::WillReadSoon).map_err(RegionCopyError::Sdk)
+    }
+
+    /// Copy a rectangle of the video frame into a new tightly packed buffer
+    fn copy_region_to_vec(&self, rect: Rect) -> Result<Vec<u8>, RegionCopyError> {
+        let (group_pixels, group_bytes) =
+            pixel_group(self.pixel_format()).ok_or(RegionCopyError::Sdk(SdkError::NOTIMPL))?;
+        let row_len = rect.width / group_pixels * group_bytes;
+
+        let mut res = vec![0; row_len * rect.height];
+        self.copy_region_into(rect, &mut res, row_len)?;
+        Ok(res)
+    }
+}
+
+/// A rectangle within a video frame, in pixels
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+/// A region is not aligned to the pixel groups of its pixel format
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub struct AlignmentError {
+    pub pixel_format: DecklinkPixelFormat,
+    /// The x position and width must be a multiple of this many pixels
+    pub required_pixels: usize,
+}
+
+#[derive(Debug)]
+pub enum RegionCopyError {
+    /// The region extends past the edge of the frame
+    OutOfBounds,
+    /// The region is not aligned for the pixel format
+    Alignment(AlignmentError),
+    /// The destination buffer is too small for the region
+    DestinationTooSmall,
+    Sdk(SdkError),
+}
+
+impl From<SdkError> for RegionCopyError {
+    fn from(e: SdkError) -> Self {
+        RegionCopyError::Sdk(e)
+    }
+}
+
+/// The smallest horizontal group of pixels that can be copied for a pixel format,
+/// as (pixels, bytes). `None` for compressed formats.
+pub(crate) fn pixel_group(format: DecklinkPixelFormat) -> Option<(usize, usize)> {
+    match format {
+        // UYVY, 2 pixels share a chroma sample
+        DecklinkPixelFormat::Format8BitYUV => Some((2, 4)),
+        // v210, 6 pixels in 16 bytes, with rows padded to 48 pixels
+        DecklinkPixelFormat::Format10BitYUV => Some((48, 128)),
+        DecklinkPixelFormat::Format8BitARGB
+        | DecklinkPixelFormat::Format8BitBGRA
+        | DecklinkPixelFormat::Format10BitRGB
+        | DecklinkPixelFormat::Format10BitRGBXLE
+        | DecklinkPixelFormat::Format10BitRGBX => Some((1, 4)),
+        // 8 pixels packed in 36 bytes
+        DecklinkPixelFormat::Format12BitRGB | DecklinkPixelFormat::Format12BitRGBLE => {
+            Some((8, 36))
+        }
+        DecklinkPixelFormat::FormatH265 | DecklinkPixelFormat::FormatDNxHR => None,
+    }
+}
+/// The byte count of a frame buffer, failing with `SdkError::INVALIDARG` rather than
+/// overflowing for impossible dimensions.
+pub(crate) fn frame_byte_count(row_bytes: usize, height: usize) -> Result<usize, SdkError> {
+    row_bytes.checked_mul(height).ok_or(SdkError::INVALIDARG)
 }
+
 pub trait DecklinkFrameBase2: DecklinkFrameBase {
     /// Get the pixel data of the video frame
     fn into_avec(self: Box<Self>) -> Result<DecklinkAlignedVec, SdkError>;
@@ -54,56 +199,39 @@ pub struct DecklinkAlignedBytes<'a>(pub &'a [u8]);
 pub type DecklinkAlignedVec = AVec<u8, ConstAlign<64>>;
 
 /// This represents a video frame that has been received from a decklink device.
+///
+/// A frame can be sent to another thread to be processed, but not shared between threads.
+/// Wrap it in a `Mutex`, or copy it into a `DecklinkVideoMutableFrame`, to share it.
 pub struct DecklinkVideoFrame {
-    frame: *mut crate::sdk::cdecklink_video_frame_t,
+    frame: VideoFramePtr,
 }
 
-impl Drop for DecklinkVideoFrame {
-    fn drop(&mut self) {
-        if !self.frame.is_null() {
-            unsafe { sdk::cdecklink_video_frame_release(self.frame) };
-            self.frame = null_mut();
-        }
-    }
-}
+// Safety: The frame holds its own reference, which is counted atomically and can be released
+// from any thread. The SDK allows a frame to be used on a different thread to the callback
+// that delivered it, as long as calls on it are not concurrent.
+unsafe impl Send for DecklinkVideoFrame {}
 
 impl DecklinkFrameBase for DecklinkVideoFrame {
     /// Get the width of the video frame
     fn width(&self) -> usize {
-        assert!(!self.frame.is_null());
-
-        let width = unsafe { sdk::cdecklink_video_frame_get_width(self.frame) };
-        width as usize
+        self.frame.width()
     }
     /// Get the height of the video frame
     fn height(&self) -> usize {
-        assert!(!self.frame.is_null());
-
-        let height = unsafe { sdk::cdecklink_video_frame_get_height(self.frame) };
-        height as usize
+        self.frame.height()
     }
     /// Get the byte count per row of the video frame
     fn row_bytes(&self) -> usize {
-        assert!(!self.frame.is_null());
-
-        let row_bytes = unsafe { sdk::cdecklink_video_frame_get_row_bytes(self.frame) };
-        row_bytes as usize
+        self.frame.row_bytes()
     }
     /// Get the pixel format of the video frame
     fn pixel_format(&self) -> DecklinkPixelFormat {
-        assert!(!self.frame.is_null());
-
-        let format = unsafe { sdk::cdecklink_video_frame_get_pixel_format(self.frame) };
-
-        DecklinkPixelFormat::from_u32(format).unwrap_or(DecklinkPixelFormat::Format8BitYUV)
+        DecklinkPixelFormat::from_u32(self.frame.pixel_format())
+            .unwrap_or(DecklinkPixelFormat::Format8BitYUV)
     }
     /// Get the flags of the video frame
     fn flags(&self) -> DecklinkFrameFlags {
-        assert!(!self.frame.is_null());
-
-        let flags = unsafe { sdk::cdecklink_video_frame_get_flags(self.frame) };
-
-        DecklinkFrameFlags::from_bits_truncate(flags)
+        DecklinkFrameFlags::from_bits_truncate(self.frame.flags())
     }
 
     fn bytes(&self) -> Result<DecklinkAlignedBytes<'_>, SdkError> {
@@ -112,51 +240,76 @@ impl DecklinkFrameBase for DecklinkVideoFrame {
 }
 
 impl DecklinkVideoFrame {
+    /// The `IDeckLinkVideoFrame` this wraps, for calls the wrapper does not cover. The wrapper
+    /// still owns its reference, so it must not be released, and must not be used after the wrapper
+    /// is dropped.
+    #[cfg(feature = "raw-api")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "raw-api")))]
+    pub fn as_raw(&self) -> *mut crate::sdk::cdecklink_video_frame_t {
+        self.frame.as_ptr()
+    }
+
     /// Get the pixel data of the video frame
     pub fn bytes_to_vec(&self) -> Result<Vec<u8>, SdkError> {
-        assert!(!self.frame.is_null());
-
-        let mut bytes: *mut std::ffi::c_void = std::ptr::null_mut();
-        let result = unsafe { sdk::cdecklink_video_frame_get_bytes(self.frame, &mut bytes) };
-        SdkError::result::<()>(result)?;
-
-        assert!(!bytes.is_null());
+        let bytes = self.frame.bytes()?;
 
         let byte_count = self.row_bytes() * self.height();
         let mut result = vec![0; byte_count];
 
-        unsafe { std::ptr::copy(bytes as *const u8, result.as_mut_ptr(), byte_count) };
+        let src = unsafe { std::slice::from_raw_parts(bytes, byte_count) };
+        let copied = copy_frame(
+            &mut result,
+            src,
+            CopyLayout::contiguous(byte_count),
+            CopyHint::WillReadSoon,
+        );
 
         // End buffer access (required for v15+ IDeckLinkVideoBuffer)
-        unsafe { sdk::cdecklink_video_frame_end_access(self.frame) };
+        self.frame.end_access();
 
-        Ok(result)
+        copied.map(|_| result)
     }
 
     /// Get the pixel data of the video frame
     pub fn bytes_handle(&self) -> Result<DecklinkAlignedBytes<'_>, SdkError> {
-        assert!(!self.frame.is_null());
-
-        let mut bytes: *mut std::ffi::c_void = std::ptr::null_mut();
-        let result = unsafe { sdk::cdecklink_video_frame_get_bytes(self.frame, &mut bytes) };
-        SdkError::result::<()>(result)?;
-
-        assert!(!bytes.is_null());
+        let bytes = self.frame.bytes()?;
 
         let byte_count = self.row_bytes() * self.height();
 
-        let slice = unsafe { std::slice::from_raw_parts(bytes as *const u8, byte_count) };
+        let slice = unsafe { std::slice::from_raw_parts(bytes, byte_count) };
         Ok(DecklinkAlignedBytes(slice))
     }
 
-    // /// Get the raw pointer for the wrapped frame
-    // pub(crate) unsafe fn get_cdecklink_ptr(&self) -> *mut sdk::cdecklink_video_frame_t {
-    //     self.frame
-    // }
+    /// Get the timecode of the frame in `format`, or `None` if the frame has none.
+    pub fn timecode(
+        &self,
+        format: DecklinkTimecodeFormat,
+    ) -> Result<Option<DecklinkTimecode>, SdkError> {
+        match self.frame.timecode(format as u32)? {
+            Some(timecode) => DecklinkTimecode::read(&timecode).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// The pointer to the wrapped frame
+    pub(crate) fn frame_ptr(&self) -> &VideoFramePtr {
+        &self.frame
+    }
+    /// Get the raw pointer for the wrapped frame, which stays owned by the wrapper
+    pub(crate) fn ptr(&self) -> *mut sdk::cdecklink_video_frame_t {
+        self.frame.as_ptr()
+    }
+    /// Take another reference to the wrapped frame
+    pub(crate) fn retain(&self) -> Self {
+        Self::from_ptr(self.frame.clone())
+    }
     /// Wrap a raw pointer
     pub(crate) unsafe fn from(ptr: *mut sdk::cdecklink_video_frame_t) -> Self {
-        sdk::cdecklink_video_frame_add_ref(ptr);
-        Self { frame: ptr }
+        Self::from_ptr(VideoFramePtr::retain(ptr))
+    }
+    /// Wrap a reference to a frame
+    pub(crate) fn from_ptr(frame: VideoFramePtr) -> Self {
+        Self { frame }
     }
 }
 
@@ -192,7 +345,7 @@ impl DecklinkFrameBase for DecklinkVideoMutableFrame {
 
     fn bytes(&self) -> Result<DecklinkAlignedBytes<'_>, SdkError> {
         if let Some(bytes) = &self.bytes {
-            Ok(DecklinkAlignedBytes(&bytes))
+            Ok(DecklinkAlignedBytes(bytes))
         } else {
             Err(SdkError::FALSE)
         }
@@ -225,8 +378,21 @@ impl DecklinkVideoMutableFrame {
         }
     }
 
+    /// Create a copy of another frame, including its pixel data
+    pub fn copy_from(frame: &dyn DecklinkFrameBase) -> Result<Self, SdkError> {
+        let mut copy = Self::create(
+            frame.width(),
+            frame.height(),
+            frame.row_bytes(),
+            frame.pixel_format(),
+            frame.flags(),
+        );
+        copy.copy_bytes(frame.bytes()?.0)?;
+        Ok(copy)
+    }
+
     pub fn set_bytes(&mut self, bytes: DecklinkAlignedVec) -> Result<(), SdkError> {
-        if bytes.len() < self.row_bytes * self.height {
+        if bytes.len() < frame_byte_count(self.row_bytes, self.height)? {
             Err(SdkError::INVALIDARG)
         } else {
             self.bytes = Some(bytes);
@@ -235,7 +401,7 @@ impl DecklinkVideoMutableFrame {
     }
 
     pub fn copy_bytes(&mut self, bytes: &[u8]) -> Result<(), SdkError> {
-        let byte_count = self.row_bytes * self.height;
+        let byte_count = frame_byte_count(self.row_bytes, self.height)?;
 
         if bytes.len() < byte_count {
             Err(SdkError::INVALIDARG)
@@ -245,8 +411,13 @@ impl DecklinkVideoMutableFrame {
                     // TODO - this may not be very performant?
                     self.bytes = Some(AVec::from_slice(64, bytes));
                 } else {
-                    // AVec::new(64)
-                    current_bytes.copy_from_slice(bytes);
+                    // Copies are made to be kept, not read straight away
+                    copy_frame(
+                        current_bytes,
+                        bytes,
+                        CopyLayout::contiguous(byte_count),
+                        CopyHint::StreamingWriteOnly,
+                    )?;
                 }
             } else {
                 self.bytes = Some(AVec::from_slice(64, bytes));
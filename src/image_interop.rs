@@ -0,0 +1,457 @@
+//! Converting frames into `image` crate buffers.
+//!
+//! 8-bit RGB formats are reordered directly. YUV and the 10-bit RGB formats, `r210`, `R10b`
+//! and `R10l`, are converted from video range with the chosen colorimetry, and 10-bit
+//! sources are reduced to 8 bits with ordered dithering rather than truncation, so gradients
+//! do not band. `to_rgb16_image_with` converts to 16 bits instead, keeping the precision of
+//! 10-bit sources, as the HDR export of `crate::still` needs. `conversion_path` gives which
+//! of these ways a pixel format is converted, or the error of a format that is not supported.
+
+pub use crate::colorimetry::Colorimetry;
+use crate::convert::unpack_rgb_10bit;
+use crate::frame::{
+    DecklinkAlignedBytes, DecklinkFrameBase, DecklinkPixelFormat, DecklinkVideoFrame,
+    DecklinkVideoMutableFrame,
+};
+use crate::SdkError;
+use image::{ImageBuffer, Rgb, RgbImage, RgbaImage};
+
+/// 16-bit RGB, as `DecklinkFrameImageExt::to_rgb16_image_with` gives.
+pub type Rgb16Image = ImageBuffer<Rgb<u16>, Vec<u16>>;
+
+#[derive(Debug)]
+pub enum ImageConversionError {
+    UnsupportedPixelFormat(DecklinkPixelFormat),
+    /// The frame has fewer bytes than its dimensions require.
+    BufferTooSmall,
+    Sdk(SdkError),
+}
+
+impl From<SdkError> for ImageConversionError {
+    fn from(e: SdkError) -> Self {
+        ImageConversionError::Sdk(e)
+    }
+}
+
+impl std::fmt::Display for ImageConversionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ImageConversionError::UnsupportedPixelFormat(format) => {
+                write!(
+                    f,
+                    "pixel format {:?} cannot be converted to an image",
+                    format
+                )
+            }
+            ImageConversionError::BufferTooSmall => {
+                write!(f, "the frame is smaller than its dimensions")
+            }
+            ImageConversionError::Sdk(e) => write!(f, "failed to read the frame: {:?}", e),
+        }
+    }
+}
+
+impl std::error::Error for ImageConversionError {}
+
+/// How frames of a pixel format are converted into images, as `conversion_path` gives.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+pub enum ConversionPath {
+    /// 8-bit RGB, whose bytes are reordered.
+    Reordered,
+    /// 8-bit YUV, converted with the colorimetry and rounded.
+    Yuv8,
+    /// 10-bit YUV, converted with the colorimetry and dithered into 8 bits.
+    Yuv10,
+    /// 10-bit RGB, converted from video range and dithered into 8 bits.
+    Rgb10,
+}
+
+impl ConversionPath {
+    /// Whether 8-bit images are dithered, as they are from 10-bit sources.
+    pub fn is_dithered(&self) -> bool {
+        matches!(self, ConversionPath::Yuv10 | ConversionPath::Rgb10)
+    }
+
+    /// Whether the colorimetry given is used, as it is for YUV sources.
+    pub fn uses_colorimetry(&self) -> bool {
+        matches!(self, ConversionPath::Yuv8 | ConversionPath::Yuv10)
+    }
+}
+
+/// How frames of `format` are converted, or the error of a format that cannot be.
+pub fn conversion_path(
+    format: DecklinkPixelFormat,
+) -> Result<ConversionPath, ImageConversionError> {
+    match format {
+        DecklinkPixelFormat::Format8BitBGRA | DecklinkPixelFormat::Format8BitARGB => {
+            Ok(ConversionPath::Reordered)
+        }
+        DecklinkPixelFormat::Format8BitYUV => Ok(ConversionPath::Yuv8),
+        DecklinkPixelFormat::Format10BitYUV => Ok(ConversionPath::Yuv10),
+        DecklinkPixelFormat::Format10BitRGB
+        | DecklinkPixelFormat::Format10BitRGBX
+        | DecklinkPixelFormat::Format10BitRGBXLE => Ok(ConversionPath::Rgb10),
+        _ => Err(ImageConversionError::UnsupportedPixelFormat(format)),
+    }
+}
+
+/// A 4x4 ordered dither, as offsets in 8-bit steps.
+const BAYER: [[f32; 4]; 4] = [
+    [0.5 / 16.0, 8.5 / 16.0, 2.5 / 16.0, 10.5 / 16.0],
+    [12.5 / 16.0, 4.5 / 16.0, 14.5 / 16.0, 6.5 / 16.0],
+    [3.5 / 16.0, 11.5 / 16.0, 1.5 / 16.0, 9.5 / 16.0],
+    [15.5 / 16.0, 7.5 / 16.0, 13.5 / 16.0, 5.5 / 16.0],
+];
+
+struct Quantizer {
+    dither: bool,
+}
+
+impl Quantizer {
+    fn quantize(&self, value: f32, x: usize, y: usize) -> u8 {
+        let value = value.clamp(0.0, 1.0) * 255.0;
+        if self.dither {
+            (value + BAYER[y % 4][x % 4]).floor().min(255.0) as u8
+        } else {
+            value.round() as u8
+        }
+    }
+}
+
+struct YuvMatrix {
+    kr: f32,
+    kb: f32,
+}
+
+impl YuvMatrix {
+    fn new(colorimetry: Colorimetry) -> YuvMatrix {
+        let (kr, kb) = colorimetry.coefficients();
+        YuvMatrix { kr, kb }
+    }
+
+    /// Convert normalized luma and chroma into RGB.
+    fn to_rgb(&self, y: f32, u: f32, v: f32) -> [f32; 3] {
+        let kg = 1.0 - self.kr - self.kb;
+        [
+            y + 2.0 * (1.0 - self.kr) * v,
+            y - (2.0 * self.kb * (1.0 - self.kb) / kg) * u
+                - (2.0 * self.kr * (1.0 - self.kr) / kg) * v,
+            y + 2.0 * (1.0 - self.kb) * u,
+        ]
+    }
+}
+
+/// Unpack a v210 row into 10-bit (Y, Cb, Cr) for each pixel.
+fn unpack_v210_row(row: &[u8], width: usize, out: &mut Vec<[u16; 3]>) {
+    out.clear();
+    for group in row.chunks_exact(16) {
+        let word = |i: usize| {
+            u32::from_le_bytes([
+                group[i * 4],
+                group[i * 4 + 1],
+                group[i * 4 + 2],
+                group[i * 4 + 3],
+            ])
+        };
+        let mut samples = [0u16; 12];
+        for i in 0..4 {
+            let w = word(i);
+            samples[i * 3] = (w & 0x3ff) as u16;
+            samples[i * 3 + 1] = ((w >> 10) & 0x3ff) as u16;
+            samples[i * 3 + 2] = ((w >> 20) & 0x3ff) as u16;
+        }
+        // Cb0 Y0 Cr0 Y1 Cb2 Y2 Cr2 Y3 Cb4 Y4 Cr4 Y5
+        for pair in 0..3 {
+            let cb = samples[pair * 4];
+            let y0 = samples[pair * 4 + 1];
+            let cr = samples[pair * 4 + 2];
+            let y1 = samples[pair * 4 + 3];
+            out.push([y0, cb, cr]);
+            out.push([y1, cb, cr]);
+        }
+        if out.len() >= width {
+            break;
+        }
+    }
+    out.truncate(width);
+}
+
+/// The byte order of red, green, blue and alpha in the 8-bit RGB formats.
+fn rgb8_order(format: DecklinkPixelFormat) -> (usize, usize, usize, usize) {
+    if format == DecklinkPixelFormat::Format8BitBGRA {
+        (2, 1, 0, 3)
+    } else {
+        (1, 2, 3, 0)
+    }
+}
+
+/// The pixel data of a frame that can be converted, and its layout.
+struct FrameRows<'a> {
+    bytes: DecklinkAlignedBytes<'a>,
+    format: DecklinkPixelFormat,
+    path: ConversionPath,
+    width: usize,
+    height: usize,
+    row_bytes: usize,
+}
+
+impl FrameRows<'_> {
+    fn row(&self, y: usize) -> &[u8] {
+        &self.bytes.0[y * self.row_bytes..(y + 1) * self.row_bytes]
+    }
+}
+
+/// Check that `frame` can be converted, and get its pixel data.
+fn frame_rows<F: DecklinkFrameBase + ?Sized>(
+    frame: &F,
+) -> Result<FrameRows<'_>, ImageConversionError> {
+    let format = frame.pixel_format();
+    let width = frame.width();
+    let height = frame.height();
+    let row_bytes = frame.row_bytes();
+
+    let path = conversion_path(format)?;
+    let min_row_bytes = match path {
+        ConversionPath::Reordered | ConversionPath::Rgb10 => width * 4,
+        ConversionPath::Yuv8 => width.div_ceil(2) * 4,
+        ConversionPath::Yuv10 => width.div_ceil(6) * 16,
+    };
+
+    let bytes = frame.bytes()?;
+    if row_bytes < min_row_bytes || bytes.0.len() < row_bytes * height {
+        return Err(ImageConversionError::BufferTooSmall);
+    }
+    Ok(FrameRows {
+        bytes,
+        format,
+        path,
+        width,
+        height,
+        row_bytes,
+    })
+}
+
+/// Decodes rows of the formats that are converted rather than reordered, YUV and 10-bit
+/// RGB, into RGB from 0 to 1 over video range.
+struct RowDecoder {
+    format: DecklinkPixelFormat,
+    width: usize,
+    matrix: YuvMatrix,
+    v210: Vec<[u16; 3]>,
+    rgb10: Vec<u16>,
+}
+
+impl RowDecoder {
+    fn new(format: DecklinkPixelFormat, width: usize, colorimetry: Colorimetry) -> RowDecoder {
+        RowDecoder {
+            format,
+            width,
+            matrix: YuvMatrix::new(colorimetry),
+            v210: Vec::with_capacity(width),
+            rgb10: if format.is_rgb_10bit() {
+                vec![0; width * 3]
+            } else {
+                Vec::new()
+            },
+        }
+    }
+
+    /// Call `pixel` with the x position and RGB of each pixel of `row`.
+    fn decode(
+        &mut self,
+        row: &[u8],
+        mut pixel: impl FnMut(usize, [f32; 3]),
+    ) -> Result<(), ImageConversionError> {
+        match self.format {
+            DecklinkPixelFormat::Format8BitYUV => {
+                for x in 0..self.width {
+                    let group = &row[(x / 2) * 4..(x / 2) * 4 + 4];
+                    let luma = if x % 2 == 0 { group[1] } else { group[3] };
+                    pixel(
+                        x,
+                        self.matrix.to_rgb(
+                            (luma as f32 - 16.0) / 219.0,
+                            (group[0] as f32 - 128.0) / 224.0,
+                            (group[2] as f32 - 128.0) / 224.0,
+                        ),
+                    );
+                }
+            }
+            DecklinkPixelFormat::Format10BitYUV => {
+                unpack_v210_row(row, self.width, &mut self.v210);
+                for (x, [luma, cb, cr]) in self.v210.iter().enumerate() {
+                    pixel(
+                        x,
+                        self.matrix.to_rgb(
+                            (*luma as f32 - 64.0) / 876.0,
+                            (*cb as f32 - 512.0) / 896.0,
+                            (*cr as f32 - 512.0) / 896.0,
+                        ),
+                    );
+                }
+            }
+            DecklinkPixelFormat::Format10BitRGB
+            | DecklinkPixelFormat::Format10BitRGBX
+            | DecklinkPixelFormat::Format10BitRGBXLE => {
+                // In video range
+                unpack_rgb_10bit(self.format, row, &mut self.rgb10)
+                    .map_err(|_| ImageConversionError::BufferTooSmall)?;
+                let normalize = |component: u16| (component as f32 - 64.0) / 876.0;
+                for (x, src) in self.rgb10.chunks_exact(3).enumerate() {
+                    pixel(x, [normalize(src[0]), normalize(src[1]), normalize(src[2])]);
+                }
+            }
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+}
+
+/// Convert `frame` into tightly packed 8-bit RGB or RGBA, with `channels` of 3 or 4.
+fn convert<F: DecklinkFrameBase + ?Sized>(
+    frame: &F,
+    colorimetry: Colorimetry,
+    channels: usize,
+) -> Result<Vec<u8>, ImageConversionError> {
+    let rows = frame_rows(frame)?;
+    let (format, width) = (rows.format, rows.width);
+    let mut decoder = RowDecoder::new(format, width, colorimetry);
+    let quantizer = Quantizer {
+        dither: rows.path.is_dithered(),
+    };
+    let mut out = vec![0; width * rows.height * channels];
+
+    for y in 0..rows.height {
+        let row = rows.row(y);
+        let out_row = &mut out[y * width * channels..(y + 1) * width * channels];
+
+        match rows.path {
+            ConversionPath::Reordered => {
+                let (r, g, b, a) = rgb8_order(format);
+                for (src, dst) in row.chunks_exact(4).zip(out_row.chunks_exact_mut(channels)) {
+                    dst[0] = src[r];
+                    dst[1] = src[g];
+                    dst[2] = src[b];
+                    if channels == 4 {
+                        dst[3] = src[a];
+                    }
+                }
+            }
+            _ => decoder.decode(row, |x, rgb| {
+                let dst = &mut out_row[x * channels..(x + 1) * channels];
+                for (d, value) in dst.iter_mut().zip(rgb) {
+                    *d = quantizer.quantize(value, x, y);
+                }
+                if channels == 4 {
+                    dst[3] = 255;
+                }
+            })?,
+        }
+    }
+
+    Ok(out)
+}
+
+/// Convert `frame` into tightly packed 16-bit RGB.
+fn convert16<F: DecklinkFrameBase + ?Sized>(
+    frame: &F,
+    colorimetry: Colorimetry,
+) -> Result<Vec<u16>, ImageConversionError> {
+    let rows = frame_rows(frame)?;
+    let (format, width) = (rows.format, rows.width);
+    let mut decoder = RowDecoder::new(format, width, colorimetry);
+    let mut out = vec![0; width * rows.height * 3];
+
+    for y in 0..rows.height {
+        let row = rows.row(y);
+        let out_row = &mut out[y * width * 3..(y + 1) * width * 3];
+
+        match rows.path {
+            ConversionPath::Reordered => {
+                let (r, g, b, _) = rgb8_order(format);
+                for (src, dst) in row.chunks_exact(4).zip(out_row.chunks_exact_mut(3)) {
+                    dst[0] = src[r] as u16 * 257;
+                    dst[1] = src[g] as u16 * 257;
+                    dst[2] = src[b] as u16 * 257;
+                }
+            }
+            _ => decoder.decode(row, |x, rgb| {
+                for (d, value) in out_row[x * 3..x * 3 + 3].iter_mut().zip(rgb) {
+                    *d = (value.clamp(0.0, 1.0) * 65535.0).round() as u16;
+                }
+            })?,
+        }
+    }
+
+    Ok(out)
+}
+
+/// Conversion of any frame into `image` buffers with an explicit colorimetry.
+pub trait DecklinkFrameImageExt {
+    /// Convert to RGBA, converting YUV sources with `colorimetry`.
+    fn to_image_with(&self, colorimetry: Colorimetry) -> Result<RgbaImage, ImageConversionError>;
+    /// Convert to RGB, converting YUV sources with `colorimetry`.
+    fn to_rgb_image_with(&self, colorimetry: Colorimetry)
+        -> Result<RgbImage, ImageConversionError>;
+    /// Convert to 16-bit RGB, converting YUV sources with `colorimetry`. 10-bit sources keep
+    /// all their precision, for processing such as tone mapping that would band 8 bits.
+    fn to_rgb16_image_with(
+        &self,
+        colorimetry: Colorimetry,
+    ) -> Result<Rgb16Image, ImageConversionError>;
+}
+
+impl<T: DecklinkFrameBase + ?Sized> DecklinkFrameImageExt for T {
+    fn to_image_with(&self, colorimetry: Colorimetry) -> Result<RgbaImage, ImageConversionError> {
+        let data = convert(self, colorimetry, 4)?;
+        Ok(
+            RgbaImage::from_raw(self.width() as u32, self.height() as u32, data)
+                .expect("converted buffer matches the frame dimensions"),
+        )
+    }
+
+    fn to_rgb_image_with(
+        &self,
+        colorimetry: Colorimetry,
+    ) -> Result<RgbImage, ImageConversionError> {
+        let data = convert(self, colorimetry, 3)?;
+        Ok(
+            RgbImage::from_raw(self.width() as u32, self.height() as u32, data)
+                .expect("converted buffer matches the frame dimensions"),
+        )
+    }
+
+    fn to_rgb16_image_with(
+        &self,
+        colorimetry: Colorimetry,
+    ) -> Result<Rgb16Image, ImageConversionError> {
+        let data = convert16(self, colorimetry)?;
+        Ok(
+            Rgb16Image::from_raw(self.width() as u32, self.height() as u32, data)
+                .expect("converted buffer matches the frame dimensions"),
+        )
+    }
+}
+
+macro_rules! impl_try_from_frame {
+    ($frame:ty) => {
+        impl TryFrom<&$frame> for RgbaImage {
+            type Error = ImageConversionError;
+
+            fn try_from(frame: &$frame) -> Result<Self, Self::Error> {
+                frame.to_image_with(Colorimetry::for_height(frame.height()))
+            }
+        }
+
+        impl TryFrom<&$frame> for RgbImage {
+            type Error = ImageConversionError;
+
+            fn try_from(frame: &$frame) -> Result<Self, Self::Error> {
+                frame.to_rgb_image_with(Colorimetry::for_height(frame.height()))
+            }
+        }
+    };
+}
+
+impl_try_from_frame!(DecklinkVideoFrame);
+impl_try_from_frame!(DecklinkVideoMutableFrame);
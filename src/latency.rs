@@ -0,0 +1,459 @@
+//! Configuring capture for low latency, and measuring the latency achieved.
+//!
+//! Latency is added by every layer that holds a frame: a `crate::batch::BatchDispatcher`
+//! holds frames until a batch fills, taps queue frames for their threads, and format
+//! detection waits for a change to settle. `LatencyProfile::LowLatency` gives the settings of
+//! each layer that add the least, and checks settings chosen elsewhere against them, naming
+//! the layer that conflicts. It also gives the CPU budgets of a `crate::cpu::CpuMeter`,
+//! and checks a meter's report against them, so a stage taking too long for the profile is
+//! named by the same numbers an operator sees.
+//!
+//! A `LatencyMeter` keeps the latency of recent frames, split into the time a frame waited
+//! in a queue, the time spent converting it and the time its handler took, and reports
+//! percentiles of each. Latency is measured from when the driver callback is entered, so the
+//! time the driver took to deliver the frame is not included. With a `LatencyBound` set,
+//! the meter raises `LatencyEvent::BoundExceeded` when the 99th percentile stays above the
+//! bound, naming the stage that grew the most.
+
+use crate::batch::BatchConfig;
+use crate::cpu::{CpuBudgets, CpuReport, CpuStage};
+use crate::device::input::{CallbackResult, FrameArrival, InputFormatChange, InputHandler};
+use crate::retention::RetentionLimit;
+use crate::tap::TapSpec;
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// The most frames a tap may retain under `LatencyProfile::LowLatency`. A tap that holds
+/// more is running behind the input.
+const LOW_LATENCY_RETENTION: usize = 2;
+
+/// The share of the frame interval the driver callback may take under
+/// `LatencyProfile::LowLatency`, which handles frames in it. The rest is left for the driver
+/// to deliver the next frame on time.
+const LOW_LATENCY_CALLBACK_BUDGET: f64 = 0.5;
+
+/// The share of the frame interval every stage together may take under
+/// `LatencyProfile::LowLatency`. Past it, a frame that takes a little longer than usual
+/// delays the next.
+const LOW_LATENCY_TOTAL_BUDGET: f64 = 0.8;
+
+/// How often, in recorded frames, the bound is checked.
+const BOUND_CHECK_INTERVAL: u64 = 32;
+
+/// Settings chosen for latency or for throughput.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash, Default)]
+pub enum LatencyProfile {
+    /// The defaults of each layer, which favour not dropping frames.
+    #[default]
+    Default,
+    /// Frames are handled in the driver callback or a tap that holds at most one, every
+    /// frame is delivered, and format changes are followed as soon as they are detected.
+    LowLatency,
+}
+
+/// A setting that adds latency `LatencyProfile::LowLatency` does not allow.
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub enum LatencyConflict {
+    /// Frames are batched, so each waits for the rest of its batch.
+    Batching { max_frames: usize },
+    /// A tap is given only some frames, so the others are delayed to the next one given.
+    Decimation { decimation: u32 },
+    /// A tap may retain more frames than it should fall behind by.
+    Retention { limit: RetentionLimit },
+    /// A stage, or every stage together for `None`, takes more of each frame interval than
+    /// its budget.
+    CpuBudget {
+        stage: Option<CpuStage>,
+        per_frame: Duration,
+        budget: Duration,
+    },
+}
+
+impl fmt::Display for LatencyConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LatencyConflict::Batching { max_frames } => write!(
+                f,
+                "batching {} frames conflicts with low latency, which dispatches directly",
+                max_frames
+            ),
+            LatencyConflict::Decimation { decimation } => write!(
+                f,
+                "a tap decimated by {} conflicts with low latency, which delivers every frame",
+                decimation
+            ),
+            LatencyConflict::Retention { limit } => write!(
+                f,
+                "a tap retention of {:?} conflicts with low latency, which allows {} frames",
+                limit, LOW_LATENCY_RETENTION
+            ),
+            LatencyConflict::CpuBudget {
+                stage,
+                per_frame,
+                budget,
+            } => {
+                match stage {
+                    Some(stage) => write!(f, "the {} stage takes", stage)?,
+                    None => write!(f, "every stage together takes")?,
+                }
+                write!(
+                    f,
+                    " {:?} a frame, more than the {:?} low latency allows",
+                    per_frame, budget
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for LatencyConflict {}
+
+impl LatencyProfile {
+    /// The settings for a tap under this profile.
+    pub fn tap_spec(&self) -> TapSpec {
+        match self {
+            LatencyProfile::Default => TapSpec::default(),
+            LatencyProfile::LowLatency => TapSpec {
+                retention: RetentionLimit::Frames(1),
+                ..TapSpec::default()
+            },
+        }
+    }
+
+    /// The frames to wait after a format change before following it, for
+    /// `crate::format_detect::FormatDetector::new`.
+    pub fn detection_debounce_frames(&self) -> u32 {
+        match self {
+            LatencyProfile::Default => 2,
+            LatencyProfile::LowLatency => 0,
+        }
+    }
+
+    /// Check that frames may be batched with `config`. Under `LowLatency`, frames are
+    /// dispatched from the driver callback, so every batch configuration conflicts.
+    pub fn check_batch(&self, config: &BatchConfig) -> Result<(), LatencyConflict> {
+        match self {
+            LatencyProfile::Default => Ok(()),
+            LatencyProfile::LowLatency => Err(LatencyConflict::Batching {
+                max_frames: config.max_frames,
+            }),
+        }
+    }
+
+    /// The CPU budgets for a `crate::cpu::CpuMeter` under this profile, as fractions of the
+    /// frame interval. The default profile has none.
+    pub fn cpu_budgets(&self) -> CpuBudgets {
+        match self {
+            LatencyProfile::Default => CpuBudgets::default(),
+            LatencyProfile::LowLatency => CpuBudgets {
+                callback: Some(LOW_LATENCY_CALLBACK_BUDGET),
+                total: Some(LOW_LATENCY_TOTAL_BUDGET),
+                ..CpuBudgets::default()
+            },
+        }
+    }
+
+    /// Check the utilization in `report` against `cpu_budgets`, naming the first stage over
+    /// its budget, or every stage together.
+    pub fn check_cpu(&self, report: &CpuReport) -> Result<(), LatencyConflict> {
+        let budgets = self.cpu_budgets();
+        for stage in report.stages.iter().chain([&report.total]) {
+            let Some(budget) = budgets.get(stage.stage) else {
+                continue;
+            };
+            if stage.utilization > budget {
+                return Err(LatencyConflict::CpuBudget {
+                    stage: stage.stage,
+                    per_frame: stage.per_frame,
+                    budget: Duration::from_nanos(
+                        (report.frame_interval.as_nanos() as f64 * budget).round() as u64,
+                    ),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that a tap may be attached with `spec`.
+    pub fn check_tap(&self, spec: &TapSpec) -> Result<(), LatencyConflict> {
+        if *self == LatencyProfile::Default {
+            return Ok(());
+        }
+        if spec.decimation > 1 {
+            return Err(LatencyConflict::Decimation {
+                decimation: spec.decimation,
+            });
+        }
+        match spec.retention {
+            RetentionLimit::Frames(frames) if frames <= LOW_LATENCY_RETENTION => Ok(()),
+            limit => Err(LatencyConflict::Retention { limit }),
+        }
+    }
+}
+
+/// A part of the time between a frame arriving and its handler returning.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum LatencyStage {
+    /// Waiting in a queue for another thread, such as a tap's or a batch dispatcher's.
+    QueueWait,
+    /// Converting or copying the frame before it is handled.
+    Conversion,
+    /// The handler itself.
+    Handler,
+}
+
+/// The latency of one frame. Stages a frame did not pass through are zero.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash, Default)]
+pub struct FrameLatency {
+    pub queue_wait: Duration,
+    pub conversion: Duration,
+    pub handler: Duration,
+}
+
+impl FrameLatency {
+    pub fn total(&self) -> Duration {
+        self.queue_wait + self.conversion + self.handler
+    }
+
+    pub fn stage(&self, stage: LatencyStage) -> Duration {
+        match stage {
+            LatencyStage::QueueWait => self.queue_wait,
+            LatencyStage::Conversion => self.conversion,
+            LatencyStage::Handler => self.handler,
+        }
+    }
+}
+
+/// Percentiles of one stage, or of the total, over the frames kept by a meter.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash, Default)]
+pub struct StageStats {
+    pub p50: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash, Default)]
+pub struct LatencyStats {
+    /// The number of frames recorded since the meter was created.
+    pub frames: u64,
+    /// The number of frames the percentiles are taken over.
+    pub window: usize,
+    pub queue_wait: StageStats,
+    pub conversion: StageStats,
+    pub handler: StageStats,
+    pub total: StageStats,
+}
+
+/// A latency to raise an event for.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+pub struct LatencyBound {
+    /// The highest acceptable 99th percentile of the total latency.
+    pub p99: Duration,
+    /// How long the 99th percentile must stay above `p99` before the event is raised.
+    pub sustained: Duration,
+}
+
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum LatencyEvent {
+    /// The 99th percentile of the total latency has been above the bound for the
+    /// sustained period. `stage` is the stage whose 99th percentile grew the most since the
+    /// latency was last within the bound.
+    BoundExceeded {
+        p99: Duration,
+        stage: LatencyStage,
+        stage_p99: Duration,
+    },
+    /// The 99th percentile of the total latency is within the bound again.
+    BoundRecovered { p99: Duration },
+}
+
+struct MeterState {
+    capacity: usize,
+    samples: VecDeque<FrameLatency>,
+    frames: u64,
+    bound: Option<LatencyBound>,
+    /// When the total went above the bound, if it is above it.
+    exceeded_since: Option<Instant>,
+    reported: bool,
+    /// The stage percentiles when the total was last within the bound.
+    baseline: FrameLatency,
+    events: Vec<LatencyEvent>,
+}
+
+pub(crate) fn percentiles(mut values: Vec<Duration>) -> StageStats {
+    if values.is_empty() {
+        return StageStats::default();
+    }
+    values.sort_unstable();
+    let rank = |p: usize| values[(values.len() * p).div_ceil(100).max(1) - 1];
+    StageStats {
+        p50: rank(50),
+        p99: rank(99),
+        max: values[values.len() - 1],
+    }
+}
+
+impl MeterState {
+    fn stage_stats(&self, stage: Option<LatencyStage>) -> StageStats {
+        percentiles(
+            self.samples
+                .iter()
+                .map(|s| stage.map_or_else(|| s.total(), |stage| s.stage(stage)))
+                .collect(),
+        )
+    }
+
+    fn stage_p99s(&self) -> FrameLatency {
+        FrameLatency {
+            queue_wait: self.stage_stats(Some(LatencyStage::QueueWait)).p99,
+            conversion: self.stage_stats(Some(LatencyStage::Conversion)).p99,
+            handler: self.stage_stats(Some(LatencyStage::Handler)).p99,
+        }
+    }
+
+    fn check_bound(&mut self, bound: LatencyBound, now: Instant) {
+        let p99 = self.stage_stats(None).p99;
+        if p99 <= bound.p99 {
+            if self.reported {
+                self.events.push(LatencyEvent::BoundRecovered { p99 });
+            }
+            self.exceeded_since = None;
+            self.reported = false;
+            self.baseline = self.stage_p99s();
+            return;
+        }
+
+        let since = *self.exceeded_since.get_or_insert(now);
+        if !self.reported && now.duration_since(since) >= bound.sustained {
+            let current = self.stage_p99s();
+            let grown = |stage| {
+                current
+                    .stage(stage)
+                    .saturating_sub(self.baseline.stage(stage))
+            };
+            let stage = [
+                LatencyStage::QueueWait,
+                LatencyStage::Conversion,
+                LatencyStage::Handler,
+            ]
+            .into_iter()
+            .max_by_key(|stage| grown(*stage))
+            .unwrap_or(LatencyStage::Handler);
+            self.events.push(LatencyEvent::BoundExceeded {
+                p99,
+                stage,
+                stage_p99: current.stage(stage),
+            });
+            self.reported = true;
+        }
+    }
+}
+
+/// Keeps the latency of the most recent frames.
+///
+/// A meter can be cloned and shared, so the stages of a frame handled on several threads
+/// can be recorded together.
+#[derive(Clone)]
+pub struct LatencyMeter {
+    state: Arc<Mutex<MeterState>>,
+}
+
+impl LatencyMeter {
+    /// A meter that takes percentiles over the last `window` frames.
+    pub fn new(window: usize) -> LatencyMeter {
+        let capacity = window.max(1);
+        LatencyMeter {
+            state: Arc::new(Mutex::new(MeterState {
+                capacity,
+                samples: VecDeque::with_capacity(capacity),
+                frames: 0,
+                bound: None,
+                exceeded_since: None,
+                reported: false,
+                baseline: FrameLatency::default(),
+                events: Vec::new(),
+            })),
+        }
+    }
+
+    /// Raise events when the latency stays above `bound`, or stop with `None`.
+    pub fn set_bound(&self, bound: Option<LatencyBound>) {
+        let mut state = self.state.lock().unwrap();
+        state.bound = bound;
+        state.exceeded_since = None;
+        state.reported = false;
+    }
+
+    /// Record the latency of a frame.
+    pub fn record(&self, latency: FrameLatency) {
+        let mut state = self.state.lock().unwrap();
+        if state.samples.len() == state.capacity {
+            state.samples.pop_front();
+        }
+        state.samples.push_back(latency);
+        state.frames += 1;
+
+        if let Some(bound) = state.bound {
+            if state.frames.is_multiple_of(BOUND_CHECK_INTERVAL) {
+                state.check_bound(bound, Instant::now());
+            }
+        }
+    }
+
+    pub fn stats(&self) -> LatencyStats {
+        let state = self.state.lock().unwrap();
+        LatencyStats {
+            frames: state.frames,
+            window: state.samples.len(),
+            queue_wait: state.stage_stats(Some(LatencyStage::QueueWait)),
+            conversion: state.stage_stats(Some(LatencyStage::Conversion)),
+            handler: state.stage_stats(Some(LatencyStage::Handler)),
+            total: state.stage_stats(None),
+        }
+    }
+
+    /// The events raised since the last call, oldest first.
+    pub fn take_events(&self) -> Vec<LatencyEvent> {
+        std::mem::take(&mut self.state.lock().unwrap().events)
+    }
+
+    /// An input callback that passes everything to `primary`, and records the time it
+    /// takes to handle each frame as the handler stage.
+    pub fn measured(&self, primary: Arc<dyn InputHandler>) -> Arc<dyn InputHandler> {
+        Arc::new(MeasuredInputCallback {
+            primary,
+            meter: self.clone(),
+        })
+    }
+}
+
+struct MeasuredInputCallback {
+    primary: Arc<dyn InputHandler>,
+    meter: LatencyMeter,
+}
+
+impl InputHandler for MeasuredInputCallback {
+    fn format_changed(&self, change: &InputFormatChange) {
+        self.primary.format_changed(change);
+    }
+
+    fn frame_arrived(&self, arrival: &FrameArrival<'_>) -> CallbackResult {
+        let started = Instant::now();
+        let result = self.primary.frame_arrived(arrival);
+        if arrival.video_frame.is_some() {
+            self.meter.record(FrameLatency {
+                handler: started.elapsed(),
+                ..FrameLatency::default()
+            });
+        }
+        result
+    }
+
+    fn type_name(&self) -> &'static str {
+        self.primary.type_name()
+    }
+}
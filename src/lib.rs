@@ -1,3 +1,28 @@
+//! Rust bindings for the Blackmagic Decklink SDK.
+//!
+//! # Stability
+//!
+//! The api is in three tiers:
+//!
+//! - The modules released in 0.1.0 are stable: [`allocator`], [`connectors`], `cuda`,
+//!   [`device`], [`display_mode`] and [`frame`], with [`api_version`] and [`SdkError`] at the
+//!   crate root. So are [`time`] and [`timecode`], which the frames and timings of the stable
+//!   modules are given in. They follow semver: a minor release only adds to them, and anything
+//!   removed is deprecated for a release first.
+//! - Every other module at the crate root is experimental. They were added since 0.1.0, and
+//!   may change in any minor release until one freezes them. [`experimental`] holds the
+//!   subsystems furthest from settled, currently the frame transforms, the device quirks and
+//!   compatibility shims, the tearing detector and the QuickTime muxer, so that code built on
+//!   them says so in its imports.
+//! - `raw` re-exports the C bindings the crate is built on, and the wrappers give access to
+//!   their pointers, with the `raw-api` feature. It has no stability guarantees at all, and
+//!   follows whatever version of the SDK the crate is built against.
+//!
+//! `tests/public_api.txt` lists every public item with its tier, and the `public_api` test
+//! fails when it no longer matches the source.
+
+#![cfg_attr(docsrs, feature(doc_cfg))]
+
 #[macro_use]
 extern crate num_derive;
 #[macro_use]
@@ -17,18 +42,99 @@ extern crate strum_macros;
 mod sdk;
 
 pub mod allocator;
+pub mod audio;
+pub mod audio_continuity;
+pub mod av_offset;
+pub mod batch;
+mod capabilities;
+pub mod capture_group;
+pub mod colorimetry;
+pub mod config;
+pub mod conformance;
+pub mod convert;
 pub mod connectors;
+pub mod cpu;
+pub mod dashboard;
+#[cfg(feature = "leak-check")]
+#[cfg_attr(docsrs, doc(cfg(feature = "leak-check")))]
+pub mod debug;
+pub mod deinterlace;
 pub mod device;
+pub mod dispatch;
 pub mod display_mode;
+pub mod dual;
+pub mod dump;
+pub mod effective_config;
+pub mod event;
+pub mod experimental;
+pub mod external;
+pub mod format_detect;
 pub mod frame;
+pub mod latency;
+pub mod link;
+pub mod loopback;
+pub mod lut;
+pub mod mailbox;
+pub mod manifest;
+pub mod memcopy;
+#[cfg(feature = "mock-backend")]
+#[cfg_attr(docsrs, doc(cfg(feature = "mock-backend")))]
+pub mod mock;
+pub mod monitor;
+pub mod preflight;
+pub mod probe;
+mod ptr;
+pub mod reference;
+pub mod queue;
+pub mod replay;
+mod requirements;
+pub mod retention;
+pub mod row_bytes;
+pub mod scaffold;
+pub mod segment;
+pub mod session;
+pub mod settle;
+pub mod soak;
+pub mod tap;
+pub mod testing;
+pub mod threads;
+pub mod time;
+pub mod timecode;
+pub mod timeline;
 mod util;
+pub mod verify;
+pub mod vpid;
 
+// Optional integrations. Each depends only on the core modules above and its own
+// dependencies, so that any combination of features builds.
 #[cfg(feature = "cuda")]
+#[cfg_attr(docsrs, doc(cfg(feature = "cuda")))]
 pub mod cuda;
+#[cfg(feature = "image-interop")]
+#[cfg_attr(docsrs, doc(cfg(feature = "image-interop")))]
+pub mod image_interop;
+#[cfg(feature = "image-interop")]
+#[cfg_attr(docsrs, doc(cfg(feature = "image-interop")))]
+pub mod multiview;
+#[cfg(feature = "raw-api")]
+#[cfg_attr(docsrs, doc(cfg(feature = "raw-api")))]
+pub mod raw;
+#[cfg(feature = "realtime")]
+#[cfg_attr(docsrs, doc(cfg(feature = "realtime")))]
+pub mod realtime;
+#[cfg(feature = "image-interop")]
+#[cfg_attr(docsrs, doc(cfg(feature = "image-interop")))]
+pub mod still;
+#[cfg(feature = "image-interop")]
+#[cfg_attr(docsrs, doc(cfg(feature = "image-interop")))]
+pub mod thumbnail;
 
-use std::ptr::null;
-use util::convert_and_release_c_string;
-pub use util::SdkError;
+use ptr::ApiInformationPtr;
+pub use capabilities::{capabilities, LibraryCapabilities};
+pub use device::input::CallbackReturnHandling;
+pub use preflight::preflight;
+pub use requirements::{require, RequirementError, Requirements, UnmetRequirement};
+pub use util::{invalid_utf8_string_count, SdkError};
 
 /// Fetch the api version of the installed Decklink drivers.
 ///
@@ -36,25 +142,56 @@ pub use util::SdkError;
 ///
 /// # Examples
 ///
-/// ```
+/// ```no_run
 /// use decklink::api_version;
 /// let version = api_version().unwrap();
 /// println!("Version: {0}", version);
+/// ```
 pub fn api_version() -> Result<String, SdkError> {
-    let it = unsafe { sdk::cdecklink_create_decklink_api_information_instance() };
-    if it.is_null() {
-        Err(SdkError::FALSE)
-    } else {
-        let mut s = null();
-
-        let result = unsafe { sdk::cdecklink_api_version(it, &mut s) };
+    ApiInformationPtr::create()
+        .ok_or(SdkError::FALSE)?
+        .version()
+}
 
-        unsafe { sdk::cdecklink_iterator_release(it) };
+/// A decoded Decklink api version.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Copy, Clone, Hash)]
+pub struct ApiVersion {
+    pub major: u8,
+    pub minor: u8,
+    pub point: u8,
+}
 
-        SdkError::result::<()>(result)?;
+impl ApiVersion {
+    pub const fn new(major: u8, minor: u8, point: u8) -> ApiVersion {
+        ApiVersion {
+            major,
+            minor,
+            point,
+        }
+    }
 
-        let str = unsafe { convert_and_release_c_string(s) };
+    /// Decode the `0xMMmmpp00` form used by the SDK.
+    pub const fn from_packed(value: u32) -> ApiVersion {
+        ApiVersion {
+            major: (value >> 24) as u8,
+            minor: (value >> 16) as u8,
+            point: (value >> 8) as u8,
+        }
+    }
+}
 
-        Ok(str)
+impl std::fmt::Display for ApiVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.point)
     }
 }
+
+/// Fetch the api version of the installed Decklink drivers, in a comparable form.
+///
+/// If an error is returned, the drivers were not found on this system.
+pub fn api_version_number() -> Result<ApiVersion, SdkError> {
+    let value = ApiInformationPtr::create()
+        .ok_or(SdkError::FALSE)?
+        .int(sdk::_DecklinkAPIInformationID_decklinkAPIVersion)?;
+    Ok(ApiVersion::from_packed(value as u32))
+}
@@ -0,0 +1,153 @@
+//! Checking that a display mode and pixel format fit the SDI links that carry them.
+//!
+//! 8K modes, and 4K modes at high frame rates or in RGB, need more bandwidth than a single
+//! 12G-SDI link carries, so they are split over two or four links. A device will often
+//! accept such a mode even when the links it is connected with cannot carry it, and the
+//! picture is then lost downstream. `check_link_bandwidth` catches this before streams are
+//! started.
+//!
+//! Bandwidth is estimated from the size of the frame buffers, which slightly overstates it
+//! for packed formats such as v210, so a mode that is reported to fit always does.
+
+use crate::display_mode::DecklinkDisplayMode;
+use crate::frame::{pixel_group, DecklinkPixelFormat};
+use crate::sdk;
+use crate::time::DecklinkTime;
+use std::fmt;
+
+/// The bit rate of a 12G-SDI link, in bits per second.
+pub const SDI_12G_BIT_RATE: u64 = 11_880_000_000;
+
+/// How many SDI links carry one video signal.
+#[derive(FromPrimitive, PartialEq, Eq, Debug, Copy, Clone, Hash)]
+pub enum DecklinkLinkConfiguration {
+    SingleLink = sdk::_DecklinkLinkConfiguration_decklinkLinkConfigurationSingleLink as isize,
+    DualLink = sdk::_DecklinkLinkConfiguration_decklinkLinkConfigurationDualLink as isize,
+    QuadLink = sdk::_DecklinkLinkConfiguration_decklinkLinkConfigurationQuadLink as isize,
+}
+
+impl DecklinkLinkConfiguration {
+    pub fn link_count(&self) -> u64 {
+        match self {
+            DecklinkLinkConfiguration::SingleLink => 1,
+            DecklinkLinkConfiguration::DualLink => 2,
+            DecklinkLinkConfiguration::QuadLink => 4,
+        }
+    }
+
+    /// The bit rate of the links together, if each is a 12G-SDI link.
+    pub fn bit_rate(&self) -> u64 {
+        self.link_count() * SDI_12G_BIT_RATE
+    }
+
+    /// The fewest 12G-SDI links that carry `required` bits per second, or `None` if even
+    /// quad link cannot.
+    pub fn minimum_for(required: u64) -> Option<DecklinkLinkConfiguration> {
+        [
+            DecklinkLinkConfiguration::SingleLink,
+            DecklinkLinkConfiguration::DualLink,
+            DecklinkLinkConfiguration::QuadLink,
+        ]
+        .into_iter()
+        .find(|link| link.bit_rate() >= required)
+    }
+}
+
+/// The bits per second needed to carry frames of `width` by `height` in `pixel_format`,
+/// each lasting `frame_duration`.
+///
+/// Returns `None` for compressed pixel formats, whose bit rate depends on the content, and
+/// for a frame duration without a valid timescale.
+pub fn required_bandwidth(
+    width: usize,
+    height: usize,
+    frame_duration: DecklinkTime,
+    pixel_format: DecklinkPixelFormat,
+) -> Option<u64> {
+    let (pixels, bytes) = pixel_group(pixel_format)?;
+    if frame_duration.value <= 0 || frame_duration.scale <= 0 {
+        return None;
+    }
+
+    // Computed in u128, which any real frame size and timescale fit in, checked for the rest
+    let row_bytes = (width as u128).div_ceil(pixels as u128) * bytes as u128;
+    let bits_per_second = row_bytes
+        .checked_mul(height as u128)?
+        .checked_mul(8 * frame_duration.scale as u128)?
+        / frame_duration.value as u128;
+    u64::try_from(bits_per_second).ok()
+}
+
+/// The bits per second needed to carry `mode` in `pixel_format`.
+pub fn required_bandwidth_for_mode(
+    mode: &DecklinkDisplayMode,
+    pixel_format: DecklinkPixelFormat,
+) -> Option<u64> {
+    required_bandwidth(
+        mode.width(),
+        mode.height(),
+        mode.frame_duration()?,
+        pixel_format,
+    )
+}
+
+/// A mode and pixel format that need more bandwidth than the links carry.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+pub struct LinkBandwidthWarning {
+    /// The bits per second needed.
+    pub required: u64,
+    /// The bits per second the configured links carry.
+    pub available: u64,
+    pub link: DecklinkLinkConfiguration,
+    /// The fewest links that would carry the signal, if any do.
+    pub minimum_link: Option<DecklinkLinkConfiguration>,
+}
+
+impl fmt::Display for LinkBandwidthWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:.2} Gb/s needed but {:?} carries {:.2} Gb/s",
+            self.required as f64 / 1e9,
+            self.link,
+            self.available as f64 / 1e9
+        )?;
+        match self.minimum_link {
+            Some(link) => write!(f, ", use {:?}", link),
+            None => write!(f, ", and no link configuration is enough"),
+        }
+    }
+}
+
+/// Check that `mode` in `pixel_format` fits on `link`, assuming 12G-SDI links.
+///
+/// There is no way to read the link configuration of a device through the C bindings, so
+/// `link` must be the configuration that was set on it.
+///
+/// Returns `None` when the signal fits, or when its bandwidth cannot be estimated.
+pub fn check_link_bandwidth(
+    mode: &DecklinkDisplayMode,
+    pixel_format: DecklinkPixelFormat,
+    link: DecklinkLinkConfiguration,
+) -> Option<LinkBandwidthWarning> {
+    let required = required_bandwidth_for_mode(mode, pixel_format)?;
+    check_bandwidth(required, link)
+}
+
+/// Check that `required` bits per second fit on `link`, assuming 12G-SDI links.
+pub fn check_bandwidth(
+    required: u64,
+    link: DecklinkLinkConfiguration,
+) -> Option<LinkBandwidthWarning> {
+    let available = link.bit_rate();
+    if required <= available {
+        None
+    } else {
+        Some(LinkBandwidthWarning {
+            required,
+            available,
+            link,
+            minimum_link: DecklinkLinkConfiguration::minimum_for(required),
+        })
+    }
+}
@@ -0,0 +1,440 @@
+//! Known patterns and tones for checking a signal path end to end.
+//!
+//! A loopback test plays frames of a `LoopbackPattern` out of one connector, captures them on
+//! another, and checks each captured frame against the frame it should be. The pattern is
+//! defined as 10-bit YUV 4:2:2 codes, which SDI carries unchanged, so captured frames match
+//! exactly in `Format8BitYUV` and `Format10BitYUV`. Every code has its two low bits clear, so
+//! the 8-bit codes are exactly the 10-bit ones shifted down.
+//!
+//! The index of each frame is stamped into its top `STAMP_ROWS` rows, as 32 blocks of black
+//! for a 0 bit and white for a 1 bit, most significant first. The blocks are wide enough
+//! to survive any filtering of the picture, so dropped and repeated frames can be found even
+//! where a frame does not match, and without relying on timecode. Below the stamp are bars
+//! whose codes move with the index, so a stale frame does not match a later one.
+//!
+//! `ToneGenerator` makes a sine tone for the audio path, keeping its phase across buffers,
+//! and `tone_level` measures how much of a tone is in captured samples.
+
+use crate::frame::{
+    DecklinkFrameBase, DecklinkFrameFlags, DecklinkPixelFormat, DecklinkVideoMutableFrame,
+};
+use crate::SdkError;
+use std::f64::consts::PI;
+
+/// The rows at the top of each frame holding its index.
+pub const STAMP_ROWS: usize = 8;
+
+const STAMP_BITS: usize = 32;
+const BAR_WIDTH: usize = 16;
+const BAR_HEIGHT: usize = 16;
+
+const BLACK: u16 = 64;
+const WHITE: u16 = 940;
+const NEUTRAL_CHROMA: u16 = 512;
+/// Stamp luma at or above this is read as a 1 bit.
+const STAMP_THRESHOLD: u16 = (BLACK + WHITE) / 2;
+
+#[derive(Debug)]
+pub enum PatternError {
+    /// The pattern is only defined for the YUV formats SDI carries unchanged.
+    UnsupportedPixelFormat(DecklinkPixelFormat),
+    /// The frame is too small to hold the stamp, or its width is odd.
+    InvalidSize {
+        width: usize,
+        height: usize,
+    },
+    /// The frame does not have the size or pixel format of the pattern.
+    WrongFormat {
+        width: usize,
+        height: usize,
+        pixel_format: DecklinkPixelFormat,
+    },
+    Sdk(SdkError),
+}
+
+impl From<SdkError> for PatternError {
+    fn from(e: SdkError) -> Self {
+        PatternError::Sdk(e)
+    }
+}
+
+impl std::fmt::Display for PatternError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PatternError::UnsupportedPixelFormat(format) => {
+                write!(f, "pixel format {:?} is not an SDI YUV format", format)
+            }
+            PatternError::InvalidSize { width, height } => {
+                write!(f, "a {}x{} frame cannot hold the pattern", width, height)
+            }
+            PatternError::WrongFormat {
+                width,
+                height,
+                pixel_format,
+            } => write!(
+                f,
+                "the frame is {}x{} in {:?}, not the pattern's format",
+                width, height, pixel_format
+            ),
+            PatternError::Sdk(e) => write!(f, "failed to read the frame: {:?}", e),
+        }
+    }
+}
+
+impl std::error::Error for PatternError {}
+
+/// How a captured frame compares to the pattern frame of its stamped index.
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub struct PatternCheck {
+    /// The index read from the stamp.
+    pub index: u32,
+    /// The number of 10-bit codes that differ by more than the tolerance.
+    pub mismatched_codes: u64,
+    /// The pixel of the first mismatched code, as (x, y).
+    pub first_mismatch: Option<(usize, usize)>,
+    /// The largest difference of any code, within the tolerance or not.
+    pub largest_error: u16,
+    /// Whether the frame was flagged as having no input source.
+    pub no_input_source: bool,
+}
+
+impl PatternCheck {
+    pub fn matches(&self) -> bool {
+        self.mismatched_codes == 0 && !self.no_input_source
+    }
+}
+
+/// A deterministic test pattern, for frames of one size and pixel format.
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub struct LoopbackPattern {
+    width: usize,
+    height: usize,
+    pixel_format: DecklinkPixelFormat,
+    row_bytes: usize,
+}
+
+impl LoopbackPattern {
+    pub fn new(
+        width: usize,
+        height: usize,
+        pixel_format: DecklinkPixelFormat,
+    ) -> Result<LoopbackPattern, PatternError> {
+        if !matches!(
+            pixel_format,
+            DecklinkPixelFormat::Format8BitYUV | DecklinkPixelFormat::Format10BitYUV
+        ) {
+            return Err(PatternError::UnsupportedPixelFormat(pixel_format));
+        }
+        if width < STAMP_BITS * 2 || !width.is_multiple_of(2) || height <= STAMP_ROWS {
+            return Err(PatternError::InvalidSize { width, height });
+        }
+        let row_bytes = pixel_format
+            .bytes_per_row(width)
+            .ok_or(PatternError::UnsupportedPixelFormat(pixel_format))?;
+        Ok(LoopbackPattern {
+            width,
+            height,
+            pixel_format,
+            row_bytes,
+        })
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+    pub fn height(&self) -> usize {
+        self.height
+    }
+    pub fn pixel_format(&self) -> DecklinkPixelFormat {
+        self.pixel_format
+    }
+    pub fn row_bytes(&self) -> usize {
+        self.row_bytes
+    }
+
+    /// The codes of a row in transmission order (Cb, Y, Cr, Y), covering any padding.
+    fn row_codes(&self, index: u32, y: usize) -> Vec<u16> {
+        let count = self.row_bytes / self.bytes_per_code_group() * self.codes_per_group();
+        (0..count)
+            .map(|k| {
+                let x = (k / 4) * 2 + if k % 4 == 3 { 1 } else { 0 };
+                match k % 4 {
+                    0 => self.code(index, x, y, Component::Cb),
+                    2 => self.code(index, x, y, Component::Cr),
+                    _ => self.code(index, x, y, Component::Y),
+                }
+            })
+            .collect()
+    }
+
+    fn code(&self, index: u32, x: usize, y: usize, component: Component) -> u16 {
+        if y < STAMP_ROWS {
+            let block_width = self.width / STAMP_BITS;
+            let block = x / block_width;
+            return match component {
+                Component::Y if block < STAMP_BITS => {
+                    if (index >> (STAMP_BITS - 1 - block)) & 1 == 1 {
+                        WHITE
+                    } else {
+                        BLACK
+                    }
+                }
+                Component::Y => BLACK,
+                Component::Cb | Component::Cr => NEUTRAL_CHROMA,
+            };
+        }
+
+        let bar = x / BAR_WIDTH;
+        let row = y / BAR_HEIGHT;
+        let index = index as usize;
+        // Steps of 4 codes, from 64 and within the legal ranges
+        let step = match component {
+            Component::Y => (bar * 7 + row * 3 + index) % 219,
+            Component::Cb => (bar * 5 + index) % 225,
+            Component::Cr => (bar * 11 + row + index * 3) % 225,
+        };
+        BLACK + 4 * step as u16
+    }
+
+    fn codes_per_group(&self) -> usize {
+        match self.pixel_format {
+            DecklinkPixelFormat::Format10BitYUV => 3,
+            _ => 1,
+        }
+    }
+    fn bytes_per_code_group(&self) -> usize {
+        match self.pixel_format {
+            DecklinkPixelFormat::Format10BitYUV => 4,
+            _ => 1,
+        }
+    }
+
+    fn pack(&self, codes: &[u16], dst: &mut [u8]) {
+        match self.pixel_format {
+            DecklinkPixelFormat::Format10BitYUV => {
+                for (word, codes) in dst.chunks_exact_mut(4).zip(codes.chunks_exact(3)) {
+                    let value = codes[0] as u32 | (codes[1] as u32) << 10 | (codes[2] as u32) << 20;
+                    word.copy_from_slice(&value.to_le_bytes());
+                }
+            }
+            _ => {
+                for (byte, code) in dst.iter_mut().zip(codes) {
+                    *byte = (code >> 2) as u8;
+                }
+            }
+        }
+    }
+
+    fn unpack(&self, src: &[u8]) -> Vec<u16> {
+        match self.pixel_format {
+            DecklinkPixelFormat::Format10BitYUV => src
+                .chunks_exact(4)
+                .flat_map(|word| {
+                    let value = u32::from_le_bytes([word[0], word[1], word[2], word[3]]);
+                    [
+                        (value & 0x3ff) as u16,
+                        (value >> 10 & 0x3ff) as u16,
+                        (value >> 20 & 0x3ff) as u16,
+                    ]
+                })
+                .collect(),
+            _ => src.iter().map(|byte| (*byte as u16) << 2).collect(),
+        }
+    }
+
+    /// The frame with `index` stamped into it.
+    pub fn frame(&self, index: u32) -> Result<DecklinkVideoMutableFrame, SdkError> {
+        let mut bytes = vec![0; self.row_bytes * self.height];
+        for (y, row) in bytes.chunks_exact_mut(self.row_bytes).enumerate() {
+            self.pack(&self.row_codes(index, y), row);
+        }
+        let mut frame = DecklinkVideoMutableFrame::create(
+            self.width,
+            self.height,
+            self.row_bytes,
+            self.pixel_format,
+            DecklinkFrameFlags::empty(),
+        );
+        frame.copy_bytes(&bytes)?;
+        Ok(frame)
+    }
+
+    fn check_format<F: DecklinkFrameBase + ?Sized>(&self, frame: &F) -> Result<(), PatternError> {
+        if frame.width() != self.width
+            || frame.height() != self.height
+            || frame.pixel_format() != self.pixel_format
+            || frame.row_bytes() < self.row_bytes
+        {
+            return Err(PatternError::WrongFormat {
+                width: frame.width(),
+                height: frame.height(),
+                pixel_format: frame.pixel_format(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Read the index stamped into `frame`.
+    pub fn read_index<F: DecklinkFrameBase + ?Sized>(
+        &self,
+        frame: &F,
+    ) -> Result<u32, PatternError> {
+        self.check_format(frame)?;
+        let bytes = frame.bytes()?;
+        let row_bytes = frame.row_bytes();
+        let y = STAMP_ROWS / 2;
+        let codes = self.unpack(&bytes.0[y * row_bytes..y * row_bytes + self.row_bytes]);
+
+        let block_width = self.width / STAMP_BITS;
+        Ok((0..STAMP_BITS).fold(0u32, |index, block| {
+            let x = block * block_width + block_width / 2;
+            let luma = codes[(x / 2) * 4 + 1 + (x % 2) * 2];
+            index << 1 | (luma >= STAMP_THRESHOLD) as u32
+        }))
+    }
+
+    /// Compare `frame` to the pattern frame of the index stamped into it, counting codes
+    /// that differ by more than `tolerance`. Padding at the end of rows is not compared.
+    pub fn check<F: DecklinkFrameBase + ?Sized>(
+        &self,
+        frame: &F,
+        tolerance: u16,
+    ) -> Result<PatternCheck, PatternError> {
+        let index = self.read_index(frame)?;
+        let bytes = frame.bytes()?;
+        let row_bytes = frame.row_bytes();
+        let active_codes = self.width * 2;
+
+        let mut mismatched_codes = 0;
+        let mut first_mismatch = None;
+        let mut largest_error = 0;
+        for y in 0..self.height {
+            let start = y * row_bytes;
+            let captured = self.unpack(&bytes.0[start..start + self.row_bytes]);
+            let expected = self.row_codes(index, y);
+            for (k, (captured, expected)) in captured
+                .iter()
+                .zip(&expected)
+                .take(active_codes)
+                .enumerate()
+            {
+                let error = captured.abs_diff(*expected);
+                largest_error = largest_error.max(error);
+                if error > tolerance {
+                    mismatched_codes += 1;
+                    first_mismatch.get_or_insert((k / 2, y));
+                }
+            }
+        }
+
+        Ok(PatternCheck {
+            index,
+            mismatched_codes,
+            first_mismatch,
+            largest_error,
+            no_input_source: frame
+                .flags()
+                .contains(DecklinkFrameFlags::HAS_NO_INPUT_SOURCE),
+        })
+    }
+}
+
+#[derive(Copy, Clone)]
+enum Component {
+    Y,
+    Cb,
+    Cr,
+}
+
+/// An audio sample type a tone can be made in.
+pub trait ToneSample: Copy {
+    /// The value of a full scale sample.
+    const FULL_SCALE: f64;
+    fn from_f64(value: f64) -> Self;
+    fn to_f64(self) -> f64;
+}
+
+impl ToneSample for i16 {
+    const FULL_SCALE: f64 = i16::MAX as f64;
+    fn from_f64(value: f64) -> Self {
+        value.round() as i16
+    }
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+}
+
+impl ToneSample for i32 {
+    const FULL_SCALE: f64 = i32::MAX as f64;
+    fn from_f64(value: f64) -> Self {
+        value.round() as i32
+    }
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+}
+
+/// A sine tone, the same on every channel.
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub struct ToneGenerator {
+    frequency: f64,
+    sample_rate: f64,
+    amplitude: f64,
+    phase: f64,
+}
+
+impl ToneGenerator {
+    /// A tone of `frequency` hertz at `level_dbfs` below full scale.
+    pub fn new(frequency: f64, sample_rate: u32, level_dbfs: f64) -> ToneGenerator {
+        ToneGenerator {
+            frequency,
+            sample_rate: sample_rate as f64,
+            amplitude: 10f64.powf(level_dbfs / 20.0),
+            phase: 0.0,
+        }
+    }
+
+    pub fn frequency(&self) -> f64 {
+        self.frequency
+    }
+
+    /// Fill `samples`, interleaved with `channels` channels, with the next samples of the
+    /// tone.
+    pub fn fill<S: ToneSample>(&mut self, samples: &mut [S], channels: usize) {
+        let step = 2.0 * PI * self.frequency / self.sample_rate;
+        for frame in samples.chunks_mut(channels.max(1)) {
+            let value = S::from_f64(self.phase.sin() * self.amplitude * S::FULL_SCALE);
+            frame.fill(value);
+            self.phase = (self.phase + step) % (2.0 * PI);
+        }
+    }
+}
+
+/// The level, in dBFS, of the tone of `frequency` hertz on `channel` of `samples`,
+/// interleaved with `channels` channels. A channel without the tone measures far below
+/// the level it was generated at.
+pub fn tone_level<S: ToneSample>(
+    samples: &[S],
+    channels: usize,
+    channel: usize,
+    sample_rate: u32,
+    frequency: f64,
+) -> f64 {
+    let channels = channels.max(1);
+    let count = samples.len() / channels;
+    if count == 0 || channel >= channels {
+        return f64::NEG_INFINITY;
+    }
+
+    // The Goertzel algorithm, for the one frequency
+    let coefficient = 2.0 * (2.0 * PI * frequency / sample_rate as f64).cos();
+    let (mut previous, mut before) = (0.0, 0.0);
+    for frame in samples.chunks_exact(channels) {
+        let value = frame[channel].to_f64() / S::FULL_SCALE + coefficient * previous - before;
+        before = previous;
+        previous = value;
+    }
+    let power = previous * previous + before * before - coefficient * previous * before;
+    let amplitude = 2.0 * power.max(0.0).sqrt() / count as f64;
+    20.0 * amplitude.log10()
+}
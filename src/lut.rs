@@ -0,0 +1,415 @@
+//! Look up tables for previewing log or HDR footage, loaded from .cube files.
+//!
+//! Both 1D and 3D tables are supported. Values are interpolated linearly for 1D tables and
+//! trilinearly for 3D tables, and inputs outside of the table domain are clamped to it.
+
+use crate::frame::{DecklinkFrameBase, DecklinkPixelFormat};
+use crate::SdkError;
+use std::fmt;
+use std::path::Path;
+
+#[derive(Debug)]
+pub enum CubeError {
+    Io(std::io::Error),
+    /// The file is malformed at the given line, counting from one.
+    Parse {
+        line: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for CubeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CubeError::Io(e) => write!(f, "failed to read LUT: {}", e),
+            CubeError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+impl std::error::Error for CubeError {}
+
+impl From<std::io::Error> for CubeError {
+    fn from(e: std::io::Error) -> Self {
+        CubeError::Io(e)
+    }
+}
+
+/// A table of entries applied to each channel independently.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Lut1d {
+    domain_min: [f32; 3],
+    domain_max: [f32; 3],
+    table: Vec<[f32; 3]>,
+}
+
+/// A cube of `size`³ entries, stored with red changing fastest as in .cube files.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Lut3d {
+    size: usize,
+    domain_min: [f32; 3],
+    domain_max: [f32; 3],
+    table: Vec<[f32; 3]>,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum Lut {
+    OneD(Lut1d),
+    ThreeD(Lut3d),
+}
+
+/// Map `v` into table coordinates `0..=last` for one channel.
+fn to_index(v: f32, min: f32, max: f32, last: usize) -> f32 {
+    let t = if max > min {
+        (v - min) / (max - min)
+    } else {
+        0.0
+    };
+    t.clamp(0.0, 1.0) * last as f32
+}
+
+fn lerp(a: [f32; 3], b: [f32; 3], t: f32) -> [f32; 3] {
+    [
+        a[0] + (b[0] - a[0]) * t,
+        a[1] + (b[1] - a[1]) * t,
+        a[2] + (b[2] - a[2]) * t,
+    ]
+}
+
+impl Lut1d {
+    /// A table of `size` entries that leaves values unchanged.
+    pub fn identity(size: usize) -> Lut1d {
+        let last = size.max(2) - 1;
+        Lut1d {
+            domain_min: [0.0; 3],
+            domain_max: [1.0; 3],
+            table: (0..=last).map(|i| [i as f32 / last as f32; 3]).collect(),
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.table.len()
+    }
+    pub fn domain(&self) -> ([f32; 3], [f32; 3]) {
+        (self.domain_min, self.domain_max)
+    }
+    pub fn table(&self) -> &[[f32; 3]] {
+        &self.table
+    }
+
+    pub fn apply(&self, rgb: [f32; 3]) -> [f32; 3] {
+        let last = self.table.len() - 1;
+        let mut out = [0.0; 3];
+        for c in 0..3 {
+            let x = to_index(rgb[c], self.domain_min[c], self.domain_max[c], last);
+            let i = (x as usize).min(last - 1);
+            let t = x - i as f32;
+            let a = self.table[i][c];
+            let b = self.table[i + 1][c];
+            out[c] = a + (b - a) * t;
+        }
+        out
+    }
+}
+
+impl Lut3d {
+    /// A cube of `size`³ entries that leaves values unchanged.
+    pub fn identity(size: usize) -> Lut3d {
+        let size = size.max(2);
+        let last = (size - 1) as f32;
+        let mut table = Vec::with_capacity(size * size * size);
+        for b in 0..size {
+            for g in 0..size {
+                for r in 0..size {
+                    table.push([r as f32 / last, g as f32 / last, b as f32 / last]);
+                }
+            }
+        }
+        Lut3d {
+            size,
+            domain_min: [0.0; 3],
+            domain_max: [1.0; 3],
+            table,
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+    pub fn domain(&self) -> ([f32; 3], [f32; 3]) {
+        (self.domain_min, self.domain_max)
+    }
+    pub fn table(&self) -> &[[f32; 3]] {
+        &self.table
+    }
+
+    fn entry(&self, r: usize, g: usize, b: usize) -> [f32; 3] {
+        self.table[r + self.size * (g + self.size * b)]
+    }
+
+    pub fn apply(&self, rgb: [f32; 3]) -> [f32; 3] {
+        let last = self.size - 1;
+        let mut base = [0; 3];
+        let mut frac = [0.0; 3];
+        for c in 0..3 {
+            let x = to_index(rgb[c], self.domain_min[c], self.domain_max[c], last);
+            base[c] = (x as usize).min(last - 1);
+            frac[c] = x - base[c] as f32;
+        }
+        let [r, g, b] = base;
+
+        let c00 = lerp(self.entry(r, g, b), self.entry(r + 1, g, b), frac[0]);
+        let c10 = lerp(
+            self.entry(r, g + 1, b),
+            self.entry(r + 1, g + 1, b),
+            frac[0],
+        );
+        let c01 = lerp(
+            self.entry(r, g, b + 1),
+            self.entry(r + 1, g, b + 1),
+            frac[0],
+        );
+        let c11 = lerp(
+            self.entry(r, g + 1, b + 1),
+            self.entry(r + 1, g + 1, b + 1),
+            frac[0],
+        );
+        let c0 = lerp(c00, c10, frac[1]);
+        let c1 = lerp(c01, c11, frac[1]);
+        lerp(c0, c1, frac[2])
+    }
+}
+
+fn parse_floats<const N: usize>(line: usize, parts: &[&str]) -> Result<[f32; N], CubeError> {
+    if parts.len() != N {
+        return Err(CubeError::Parse {
+            line,
+            message: format!("expected {} values, found {}", N, parts.len()),
+        });
+    }
+    let mut res = [0.0; N];
+    for (v, part) in res.iter_mut().zip(parts) {
+        *v = part.parse().map_err(|_| CubeError::Parse {
+            line,
+            message: format!("invalid number '{}'", part),
+        })?;
+    }
+    Ok(res)
+}
+
+fn parse_size(line: usize, parts: &[&str], max: usize) -> Result<usize, CubeError> {
+    let size = match parts {
+        [s] => s.parse::<usize>().ok(),
+        _ => None,
+    };
+    match size {
+        Some(size) if (2..=max).contains(&size) => Ok(size),
+        _ => Err(CubeError::Parse {
+            line,
+            message: format!("size must be an integer from 2 to {}", max),
+        }),
+    }
+}
+
+impl Lut {
+    /// Parse the contents of a .cube file.
+    pub fn parse_cube(text: &str) -> Result<Lut, CubeError> {
+        let mut size_1d = None;
+        let mut size_3d = None;
+        let mut domain_min = [0.0; 3];
+        let mut domain_max = [1.0; 3];
+        let mut table = Vec::new();
+
+        for (i, raw) in text.lines().enumerate() {
+            let line = i + 1;
+            let content = raw.split('#').next().unwrap_or("").trim();
+            if content.is_empty() {
+                continue;
+            }
+
+            let parts: Vec<&str> = content.split_whitespace().collect();
+            let keyword = parts[0];
+            let is_data = keyword
+                .chars()
+                .next()
+                .is_some_and(|c| c.is_ascii_digit() || c == '-' || c == '+' || c == '.');
+
+            if is_data {
+                if size_1d.is_none() && size_3d.is_none() {
+                    return Err(CubeError::Parse {
+                        line,
+                        message: "table data before LUT_1D_SIZE or LUT_3D_SIZE".to_string(),
+                    });
+                }
+                table.push(parse_floats::<3>(line, &parts)?);
+                continue;
+            }
+
+            if !table.is_empty() {
+                return Err(CubeError::Parse {
+                    line,
+                    message: format!("keyword '{}' after table data", keyword),
+                });
+            }
+            match keyword {
+                "TITLE" => {}
+                "LUT_1D_SIZE" => size_1d = Some(parse_size(line, &parts[1..], 65536)?),
+                "LUT_3D_SIZE" => size_3d = Some(parse_size(line, &parts[1..], 256)?),
+                "DOMAIN_MIN" => domain_min = parse_floats::<3>(line, &parts[1..])?,
+                "DOMAIN_MAX" => domain_max = parse_floats::<3>(line, &parts[1..])?,
+                // Older tools write the domain as one range shared by all channels
+                "LUT_1D_INPUT_RANGE" | "LUT_3D_INPUT_RANGE" => {
+                    let [min, max] = parse_floats::<2>(line, &parts[1..])?;
+                    domain_min = [min; 3];
+                    domain_max = [max; 3];
+                }
+                _ => {
+                    return Err(CubeError::Parse {
+                        line,
+                        message: format!("unknown keyword '{}'", keyword),
+                    })
+                }
+            }
+            if size_1d.is_some() && size_3d.is_some() {
+                return Err(CubeError::Parse {
+                    line,
+                    message: "both LUT_1D_SIZE and LUT_3D_SIZE are given".to_string(),
+                });
+            }
+        }
+
+        let line = text.lines().count();
+        if (0..3).any(|c| domain_max[c] <= domain_min[c]) {
+            return Err(CubeError::Parse {
+                line,
+                message: "DOMAIN_MAX must be greater than DOMAIN_MIN".to_string(),
+            });
+        }
+
+        let expected = match (size_1d, size_3d) {
+            (Some(size), None) => size,
+            (None, Some(size)) => size * size * size,
+            _ => {
+                return Err(CubeError::Parse {
+                    line,
+                    message: "missing LUT_1D_SIZE or LUT_3D_SIZE".to_string(),
+                })
+            }
+        };
+        if table.len() != expected {
+            return Err(CubeError::Parse {
+                line,
+                message: format!("expected {} table entries, found {}", expected, table.len()),
+            });
+        }
+
+        Ok(match size_3d {
+            Some(size) => Lut::ThreeD(Lut3d {
+                size,
+                domain_min,
+                domain_max,
+                table,
+            }),
+            None => Lut::OneD(Lut1d {
+                domain_min,
+                domain_max,
+                table,
+            }),
+        })
+    }
+
+    /// Load a .cube file.
+    pub fn load_cube(path: &Path) -> Result<Lut, CubeError> {
+        Lut::parse_cube(&std::fs::read_to_string(path)?)
+    }
+
+    /// Apply the table to one RGB value.
+    pub fn apply(&self, rgb: [f32; 3]) -> [f32; 3] {
+        match self {
+            Lut::OneD(lut) => lut.apply(rgb),
+            Lut::ThreeD(lut) => lut.apply(rgb),
+        }
+    }
+
+    /// Apply the table in place to 8-bit pixels of `channels` bytes, where the first three are
+    /// red, green and blue. Any further channels are left unchanged.
+    pub fn apply_rgb8(&self, pixels: &mut [u8], channels: usize) {
+        if channels < 3 {
+            return;
+        }
+        for px in pixels.chunks_exact_mut(channels) {
+            let out = self.apply([
+                px[0] as f32 / 255.0,
+                px[1] as f32 / 255.0,
+                px[2] as f32 / 255.0,
+            ]);
+            for c in 0..3 {
+                px[c] = (out[c].clamp(0.0, 1.0) * 255.0).round() as u8;
+            }
+        }
+    }
+
+    /// Apply the table in place to 16-bit pixels of `channels` values, where the first three
+    /// are red, green and blue. Any further channels are left unchanged.
+    pub fn apply_rgb16(&self, pixels: &mut [u16], channels: usize) {
+        if channels < 3 {
+            return;
+        }
+        for px in pixels.chunks_exact_mut(channels) {
+            let out = self.apply([
+                px[0] as f32 / 65535.0,
+                px[1] as f32 / 65535.0,
+                px[2] as f32 / 65535.0,
+            ]);
+            for c in 0..3 {
+                px[c] = (out[c].clamp(0.0, 1.0) * 65535.0).round() as u16;
+            }
+        }
+    }
+
+    /// Convert an 8-bit YUV (UYVY, Rec. 709 video range) frame into tightly packed RGBA8
+    /// through the table.
+    ///
+    /// The colour conversion and the table are applied at full precision, and only the
+    /// result is quantized, so smooth gradients do not band.
+    pub fn convert_uyvy_to_rgba8(
+        &self,
+        frame: &dyn DecklinkFrameBase,
+    ) -> Result<Vec<u8>, SdkError> {
+        if frame.pixel_format() != DecklinkPixelFormat::Format8BitYUV {
+            return Err(SdkError::INVALIDARG);
+        }
+        let width = frame.width();
+        let height = frame.height();
+        let row_bytes = frame.row_bytes();
+        let bytes = frame.bytes()?;
+        if bytes.0.len() < row_bytes * height || row_bytes < width.div_ceil(2) * 4 {
+            return Err(SdkError::INVALIDARG);
+        }
+
+        let mut out = vec![0; width * height * 4];
+        for y in 0..height {
+            let row = &bytes.0[y * row_bytes..];
+            for x in 0..width {
+                let group = &row[(x / 2) * 4..(x / 2) * 4 + 4];
+                let luma = if x % 2 == 0 { group[1] } else { group[3] };
+
+                let yv = (luma as f32 - 16.0) / 219.0;
+                let u = (group[0] as f32 - 128.0) / 224.0;
+                let v = (group[2] as f32 - 128.0) / 224.0;
+                let rgb = self.apply([
+                    yv + 1.5748 * v,
+                    yv - 0.1873 * u - 0.4681 * v,
+                    yv + 1.8556 * u,
+                ]);
+
+                let px = &mut out[(y * width + x) * 4..(y * width + x) * 4 + 4];
+                for c in 0..3 {
+                    px[c] = (rgb[c].clamp(0.0, 1.0) * 255.0).round() as u8;
+                }
+                px[3] = 255;
+            }
+        }
+        Ok(out)
+    }
+}
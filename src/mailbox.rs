@@ -0,0 +1,415 @@
+//! Giving the newest captured frame to an application that polls for it.
+//!
+//! Some applications ask for the newest frame once per iteration of a loop of their own,
+//! such as a render loop, rather than being called with each frame. A `LatestFrameMailbox`
+//! is installed as the callback of an input, and keeps the most recent frame that arrived,
+//! and optionally the audio packet that arrived with it, replacing the one before. The loop
+//! takes it with `LatestFrameMailbox::poll`, or with `LatestFrameMailbox::poll_if_newer` to
+//! skip a frame it has already processed.
+//!
+//! The frame is kept in a slot that the callback swaps with a single atomic operation, so
+//! the callback never waits for a poll, however slow the loop. A poll takes the frame out of
+//! the slot, takes a reference to it and puts it back, unless a newer frame was put there in
+//! the meantime, so a poll is given either the frame before or the frame after, never parts
+//! of both. Polls from several threads take turns, but never wait for the callback.
+//!
+//! The mailbox holds exactly one frame. A frame replaced before any poll took it is counted
+//! in `MailboxStats::overwritten`, and a loop that polls slower than frames arrive sees gaps
+//! in `MailboxFrame::sequence`. Frames are retained through the `RetentionBudget` the
+//! mailbox is given, if any. A polled frame is charged to it until the poller drops it as
+//! well, so a budget of two frames lets the poller hold one while the next arrives. When the
+//! budget is full, the frame the mailbox holds is released to make room for the new one,
+//! and a frame the budget still refuses is not kept, and counted in `MailboxStats::refused`.
+//!
+//! When the input changes format, the frame of the old format is cleared, and polls return
+//! `MailboxPoll::FormatChanged` until a frame arrives in the new one. The mailbox closes
+//! when it is closed with `LatestFrameMailbox::close`, or when the input releases the last
+//! callback taken from it. It then releases its frame, and polls return
+//! `MailboxPoll::Closed`.
+//!
+//! `MailboxFrame::age` is measured on the host's monotonic clock, from when the callback
+//! was called, as this crate does not read the hardware reference clock.
+
+use crate::device::input::{
+    CallbackResult, DecklinkAudioInputPacket, FrameArrival, InputFormatChange, InputHandler,
+};
+use crate::frame::DecklinkVideoFrame;
+use crate::retention::{RetainedFrame, RetentionBudget};
+use crate::time::DecklinkFrameTiming;
+use std::ptr::null_mut;
+use std::sync::atomic::{AtomicBool, AtomicPtr, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// What a `LatestFrameMailbox` keeps besides the video frame.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash, Default)]
+pub struct MailboxConfig {
+    /// Keep the audio packet that arrived with each frame, for `MailboxFrame::audio_packet`.
+    pub audio: bool,
+}
+
+/// The counts of a `LatestFrameMailbox`.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Default)]
+pub struct MailboxStats {
+    /// The frames put in the mailbox.
+    pub published: u64,
+    /// The frames given to a poll at least once.
+    pub polled: u64,
+    /// The frames replaced by a newer frame before any poll took them.
+    pub overwritten: u64,
+    /// The frames cleared by a format change or by closing before any poll took them.
+    pub cleared: u64,
+    /// The frames not kept because the retention budget refused them.
+    pub refused: u64,
+    /// The format changes the input reported.
+    pub format_changes: u64,
+}
+
+/// The result of a poll of a `LatestFrameMailbox`.
+pub enum MailboxPoll {
+    /// The newest frame.
+    Frame(MailboxFrame),
+    /// No frame has arrived yet, or the frame held was released to make room for one the
+    /// retention budget then refused.
+    Empty,
+    /// The input changed format, and no frame has arrived in the new format yet.
+    FormatChanged,
+    /// The frame is no newer than the one given to `LatestFrameMailbox::poll_if_newer`.
+    Unchanged,
+    /// The mailbox was closed, and no more frames will arrive.
+    Closed,
+}
+
+impl MailboxPoll {
+    /// The frame, if the poll was given one.
+    pub fn frame(self) -> Option<MailboxFrame> {
+        match self {
+            MailboxPoll::Frame(frame) => Some(frame),
+            _ => None,
+        }
+    }
+
+    pub fn is_closed(&self) -> bool {
+        matches!(self, MailboxPoll::Closed)
+    }
+}
+
+enum Held {
+    Captured(DecklinkVideoFrame),
+    Retained(RetainedFrame),
+}
+
+/// A frame in the mailbox, and what arrived with it.
+struct Entry {
+    frame: Held,
+    audio_packet: Option<DecklinkAudioInputPacket>,
+    timing: Option<DecklinkFrameTiming>,
+    sequence: u64,
+    format_generation: u64,
+    arrived: Instant,
+    /// Whether a poll has taken the frame.
+    polled: AtomicBool,
+}
+
+// Safety: The frame and packet are only read through shared references while held, which the
+// SDK objects behind them allow from any thread, and released once, with the last
+// reference to the entry.
+unsafe impl Send for Entry {}
+unsafe impl Sync for Entry {}
+
+/// A frame taken from a `LatestFrameMailbox`. Clones share the frame, which is released
+/// with the last of them.
+#[derive(Clone)]
+pub struct MailboxFrame {
+    entry: Arc<Entry>,
+}
+
+impl MailboxFrame {
+    pub fn frame(&self) -> &DecklinkVideoFrame {
+        match &self.entry.frame {
+            Held::Captured(frame) => frame,
+            Held::Retained(retained) => retained.frame(),
+        }
+    }
+
+    /// The audio packet that arrived with the frame, if `MailboxConfig::audio` is set and
+    /// the callback carried one.
+    pub fn audio_packet(&self) -> Option<&DecklinkAudioInputPacket> {
+        self.entry.audio_packet.as_ref()
+    }
+
+    pub fn timing(&self) -> Option<DecklinkFrameTiming> {
+        self.entry.timing
+    }
+
+    /// The number of the frame among those put in the mailbox, counting from one.
+    pub fn sequence(&self) -> u64 {
+        self.entry.sequence
+    }
+
+    /// The number of format changes the input reported before the frame arrived.
+    pub fn format_generation(&self) -> u64 {
+        self.entry.format_generation
+    }
+
+    /// When the callback for the frame was called.
+    pub fn captured(&self) -> Instant {
+        self.entry.arrived
+    }
+
+    /// The time since the callback for the frame was called.
+    pub fn age(&self) -> Duration {
+        self.entry.arrived.elapsed()
+    }
+}
+
+/// Marks the slot while a poll holds its frame.
+const TAKEN: *mut Entry = std::ptr::NonNull::<Entry>::dangling().as_ptr();
+
+struct MailboxShared {
+    config: MailboxConfig,
+    budget: Option<RetentionBudget>,
+    /// The newest frame, from `Arc::into_raw`, null when there is none, or `TAKEN`.
+    slot: AtomicPtr<Entry>,
+    /// Makes polls take turns, so only one holds the frame at a time.
+    polling: Mutex<()>,
+    closed: AtomicBool,
+    /// Whether a format change emptied the slot, and no frame has arrived since.
+    format_changed: AtomicBool,
+    callbacks: AtomicUsize,
+
+    published: AtomicU64,
+    polled: AtomicU64,
+    overwritten: AtomicU64,
+    cleared: AtomicU64,
+    refused: AtomicU64,
+    format_changes: AtomicU64,
+}
+
+impl MailboxShared {
+    /// Put `entry` in the slot, or empty it, and release the frame that was there. Returns
+    /// whether that frame was released before any poll took it.
+    fn replace(&self, entry: Option<Arc<Entry>>) -> bool {
+        let ptr = entry.map_or(null_mut(), |e| Arc::into_raw(e) as *mut Entry);
+        let old = self.slot.swap(ptr, Ordering::AcqRel);
+        // A frame a poll holds has been taken, and the poll releases it
+        if old.is_null() || old == TAKEN {
+            return false;
+        }
+        let old = unsafe { Arc::from_raw(old) };
+        !old.polled.load(Ordering::Acquire)
+    }
+
+    /// Empty the slot, counting the frame in `MailboxStats::cleared` if no poll took it.
+    fn clear(&self) {
+        if self.replace(None) {
+            self.cleared.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn close(&self) {
+        self.closed.store(true, Ordering::Release);
+        self.clear();
+    }
+}
+
+impl Drop for MailboxShared {
+    fn drop(&mut self) {
+        let ptr = *self.slot.get_mut();
+        if !ptr.is_null() && ptr != TAKEN {
+            drop(unsafe { Arc::from_raw(ptr) });
+        }
+    }
+}
+
+/// Keeps the newest frame of an input for an application to poll for.
+pub struct LatestFrameMailbox {
+    shared: Arc<MailboxShared>,
+}
+
+impl LatestFrameMailbox {
+    /// Create a mailbox retaining its frames through `budget`, if given.
+    pub fn new(config: MailboxConfig, budget: Option<RetentionBudget>) -> LatestFrameMailbox {
+        LatestFrameMailbox {
+            shared: Arc::new(MailboxShared {
+                config,
+                budget,
+                slot: AtomicPtr::new(null_mut()),
+                polling: Mutex::new(()),
+                closed: AtomicBool::new(false),
+                format_changed: AtomicBool::new(false),
+                callbacks: AtomicUsize::new(0),
+                published: AtomicU64::new(0),
+                polled: AtomicU64::new(0),
+                overwritten: AtomicU64::new(0),
+                cleared: AtomicU64::new(0),
+                refused: AtomicU64::new(0),
+                format_changes: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    /// The callback to install on the input. The mailbox closes when the last callback taken
+    /// from it is dropped.
+    pub fn callback(&self) -> Arc<dyn InputHandler> {
+        self.shared.callbacks.fetch_add(1, Ordering::AcqRel);
+        Arc::new(MailboxCallback {
+            shared: self.shared.clone(),
+        })
+    }
+
+    /// The newest frame, which stays in the mailbox for later polls until a newer one
+    /// replaces it.
+    pub fn poll(&self) -> MailboxPoll {
+        let shared = &self.shared;
+        let _turn = shared.polling.lock().unwrap_or_else(|e| e.into_inner());
+        if shared.closed.load(Ordering::Acquire) {
+            return MailboxPoll::Closed;
+        }
+
+        let ptr = shared.slot.swap(TAKEN, Ordering::AcqRel);
+        if ptr.is_null() {
+            // Only put the empty slot back if the callback has not filled it since
+            let _ = shared.slot.compare_exchange(
+                TAKEN,
+                null_mut(),
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            );
+            return if shared.closed.load(Ordering::Acquire) {
+                MailboxPoll::Closed
+            } else if shared.format_changed.load(Ordering::Acquire) {
+                MailboxPoll::FormatChanged
+            } else {
+                MailboxPoll::Empty
+            };
+        }
+
+        let entry = unsafe { Arc::from_raw(ptr) };
+        if !entry.polled.swap(true, Ordering::AcqRel) {
+            shared.polled.fetch_add(1, Ordering::Relaxed);
+        }
+        let frame = MailboxFrame {
+            entry: entry.clone(),
+        };
+        let raw = Arc::into_raw(entry) as *mut Entry;
+        if shared
+            .slot
+            .compare_exchange(TAKEN, raw, Ordering::AcqRel, Ordering::Acquire)
+            .is_err()
+        {
+            // A newer frame, a format change or a close replaced it while it was taken
+            drop(unsafe { Arc::from_raw(raw) });
+        }
+        MailboxPoll::Frame(frame)
+    }
+
+    /// The newest frame, if its sequence is greater than `last_sequence`. Pass zero to be
+    /// given any frame.
+    pub fn poll_if_newer(&self, last_sequence: u64) -> MailboxPoll {
+        match self.poll() {
+            MailboxPoll::Frame(frame) if frame.sequence() <= last_sequence => {
+                MailboxPoll::Unchanged
+            }
+            poll => poll,
+        }
+    }
+
+    /// Release the frame and close the mailbox, so polls return `MailboxPoll::Closed`.
+    pub fn close(&self) {
+        self.shared.close();
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.shared.closed.load(Ordering::Acquire)
+    }
+
+    pub fn stats(&self) -> MailboxStats {
+        let shared = &self.shared;
+        MailboxStats {
+            published: shared.published.load(Ordering::Relaxed),
+            polled: shared.polled.load(Ordering::Relaxed),
+            overwritten: shared.overwritten.load(Ordering::Relaxed),
+            cleared: shared.cleared.load(Ordering::Relaxed),
+            refused: shared.refused.load(Ordering::Relaxed),
+            format_changes: shared.format_changes.load(Ordering::Relaxed),
+        }
+    }
+}
+
+struct MailboxCallback {
+    shared: Arc<MailboxShared>,
+}
+
+impl InputHandler for MailboxCallback {
+    fn format_changed(&self, _change: &InputFormatChange) {
+        let shared = &self.shared;
+        shared.format_changes.fetch_add(1, Ordering::AcqRel);
+        shared.format_changed.store(true, Ordering::Release);
+        shared.clear();
+    }
+
+    fn frame_arrived(&self, arrival: &FrameArrival<'_>) -> CallbackResult {
+        let shared = &self.shared;
+        if shared.closed.load(Ordering::Acquire) {
+            return CallbackResult::Ok;
+        }
+        let Some(video_frame) = arrival.video_frame else {
+            return CallbackResult::Ok;
+        };
+        let arrived = Instant::now();
+
+        let frame = match &shared.budget {
+            Some(budget) => {
+                let retained = budget.retain(video_frame.retain()).or_else(|_| {
+                    // The frame held is replaced anyway, so release it to make room
+                    if shared.replace(None) {
+                        shared.overwritten.fetch_add(1, Ordering::Relaxed);
+                    }
+                    budget.retain(video_frame.retain())
+                });
+                match retained {
+                    Ok(retained) => Held::Retained(retained),
+                    Err(_) => {
+                        shared.refused.fetch_add(1, Ordering::Relaxed);
+                        return CallbackResult::Ok;
+                    }
+                }
+            }
+            None => Held::Captured(video_frame.retain()),
+        };
+        let audio_packet = if shared.config.audio {
+            arrival.retain_audio_packet()
+        } else {
+            None
+        };
+        let sequence = shared.published.fetch_add(1, Ordering::AcqRel) + 1;
+        let overwritten = shared.replace(Some(Arc::new(Entry {
+            frame,
+            audio_packet,
+            timing: arrival.timing(),
+            sequence,
+            format_generation: shared.format_changes.load(Ordering::Acquire),
+            arrived,
+            polled: AtomicBool::new(false),
+        })));
+        shared.format_changed.store(false, Ordering::Release);
+        if overwritten {
+            shared.overwritten.fetch_add(1, Ordering::Relaxed);
+        }
+
+        // A close between the check above and the replace would leave the frame held
+        if shared.closed.load(Ordering::Acquire) {
+            shared.clear();
+        }
+        CallbackResult::Ok
+    }
+}
+
+impl Drop for MailboxCallback {
+    fn drop(&mut self) {
+        if self.shared.callbacks.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.shared.close();
+        }
+    }
+}
@@ -0,0 +1,475 @@
+//! A record of how a capture was made, written alongside the captured media.
+//!
+//! The manifest is written as JSON with the following schema (version 1):
+//!
+//! ```text
+//! {
+//!   "version": 1,
+//!   "finished": bool,              // false for a partial manifest written during capture
+//!   "setup": {
+//!     "device_name": string | null,
+//!     "driver_version": string | null,
+//!     "display_mode": string,      // DecklinkDisplayModeId variant name
+//!     "pixel_format": string,      // DecklinkPixelFormat variant name
+//!     "width": number,
+//!     "height": number,
+//!     "frame_duration": number | null,
+//!     "time_scale": number | null,
+//!     // the configuration the input negotiated, see crate::effective_config
+//!     "effective_config": { ... } | null
+//!   },
+//!   "totals": {
+//!     "frames_captured": number,
+//!     "frames_dropped": number,
+//!     "first_timecode": string | null,
+//!     "last_timecode": string | null,
+//!     // one entry per hardware timecode source seen during the capture
+//!     "timecode_sources": [
+//!       { "format": string,        // DecklinkTimecodeFormat variant name
+//!         "frames_with_timecode": number, "longest_gap": number,
+//!         "discontinuities": number, "duplicates": number }
+//!     ]
+//!   },
+//!   "events": [
+//!     // "elapsed_ms" is the time since the manifest was created
+//!     { "elapsed_ms": number, "type": "dropped", "first_frame": number, "count": number }
+//!     { "elapsed_ms": number, "type": "signal_lost", "frame": number }
+//!     { "elapsed_ms": number, "type": "signal_restored", "frame": number }
+//!     { "elapsed_ms": number, "type": "format_changed", "frame": number, "display_mode": string }
+//!     // "frames" arrived between the format change and the input being enabled in the new format
+//!     { "elapsed_ms": number, "type": "format_transition", "first_frame": number,
+//!       "frames": number }
+//!     { "elapsed_ms": number, "type": "mark", "frame": number, "label": string }
+//!     // recorded when the segment is finished, "reason" is a SegmentReason variant name
+//!     { "elapsed_ms": number, "type": "segment", "index": number, "first_frame": number,
+//!       "frames": number, "reason": string, "path": string }
+//!     // "offset" is in ticks of "time_scale", positive when audio is delayed against video
+//!     { "elapsed_ms": number, "type": "av_offset", "frame": number, "offset": number,
+//!       "time_scale": number }
+//!     // a capture group member, as crate::capture_group::MemberId, joined or left from "tick",
+//!     // null for a change made before the first frame
+//!     { "elapsed_ms": number, "type": "member_added", "member": number, "tick": number | null }
+//!     { "elapsed_ms": number, "type": "member_removed", "member": number, "tick": number | null }
+//!     // silence inserted into or audio cut from the start of a packet, in sample frames from
+//!     // "sample_time", an audio packet time at 48 kHz
+//!     { "elapsed_ms": number, "type": "audio_filled", "sample_time": number, "samples": number }
+//!     { "elapsed_ms": number, "type": "audio_trimmed", "sample_time": number, "samples": number }
+//!   ]
+//! }
+//! ```
+//!
+//! Fields will only be added in future versions of the same schema, never removed or changed.
+
+use crate::display_mode::DecklinkDisplayModeId;
+use crate::effective_config::EffectiveConfig;
+use crate::event::DecklinkEvent;
+use crate::frame::DecklinkPixelFormat;
+use crate::segment::SegmentInfo;
+use crate::time::DecklinkTime;
+use crate::timecode::TimecodeSourceStats;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// The version of the manifest schema that is written.
+pub const MANIFEST_VERSION: u32 = 1;
+
+/// The configuration of a capture, recorded when it starts.
+#[derive(PartialEq, Debug, Clone)]
+pub struct CaptureSetup {
+    pub device_name: Option<String>,
+    pub driver_version: Option<String>,
+    pub display_mode: DecklinkDisplayModeId,
+    pub pixel_format: DecklinkPixelFormat,
+    pub width: usize,
+    pub height: usize,
+    pub frame_duration: Option<DecklinkTime>,
+    /// From `crate::effective_config::DecklinkEffectiveConfigExt::effective_config`, once
+    /// video input is enabled.
+    pub effective_config: Option<EffectiveConfig>,
+}
+
+/// A notable event during a capture. Frame numbers count from the first captured frame.
+#[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ManifestEvent {
+    Dropped {
+        first_frame: u64,
+        count: u64,
+    },
+    SignalLost {
+        frame: u64,
+    },
+    SignalRestored {
+        frame: u64,
+    },
+    FormatChanged {
+        frame: u64,
+        display_mode: DecklinkDisplayModeId,
+    },
+    /// A format transition, as `crate::tap::FormatTransition`, recorded when it ends.
+    FormatTransition {
+        first_frame: u64,
+        frames: u64,
+    },
+    Mark {
+        frame: u64,
+        label: String,
+    },
+    /// A segment of the recording, recorded when it is finished.
+    Segment(SegmentInfo),
+    /// The audio/video offset applied from this frame on, as `crate::av_offset::AvPlan::time`.
+    AvOffset {
+        frame: u64,
+        offset: DecklinkTime,
+    },
+    /// A member joined a capture group, as `crate::capture_group::GroupEvent::MemberAdded`.
+    MemberAdded {
+        member: u32,
+        tick: Option<i64>,
+    },
+    /// A member left a capture group, as `crate::capture_group::GroupEvent::MemberRemoved`.
+    MemberRemoved {
+        member: u32,
+        tick: Option<i64>,
+    },
+    /// Silence inserted into the audio, as `crate::audio_continuity::AudioContinuityEvent::Filled`.
+    AudioFilled {
+        sample_time: i64,
+        samples: u64,
+    },
+    /// Audio cut off the start of a packet, as
+    /// `crate::audio_continuity::AudioContinuityEvent::Trimmed`.
+    AudioTrimmed {
+        sample_time: i64,
+        samples: u64,
+    },
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct ManifestEntry {
+    /// Time since the manifest was created.
+    pub elapsed: Duration,
+    pub event: ManifestEvent,
+}
+
+/// Collects the setup, events and totals of a capture, and writes them as JSON.
+pub struct CaptureManifest {
+    setup: CaptureSetup,
+    started: Instant,
+    entries: Vec<ManifestEntry>,
+    frames_captured: u64,
+    frames_dropped: u64,
+    first_timecode: Option<String>,
+    last_timecode: Option<String>,
+    timecode_sources: Vec<TimecodeSourceStats>,
+
+    autosave: Option<(PathBuf, Duration)>,
+    last_saved: Instant,
+}
+
+pub(crate) fn write_json_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+pub(crate) fn write_json_opt_string(out: &mut String, s: &Option<String>) {
+    match s {
+        Some(s) => write_json_string(out, s),
+        None => out.push_str("null"),
+    }
+}
+
+fn json_opt_number(n: Option<i64>) -> String {
+    n.map_or_else(|| "null".to_string(), |n| n.to_string())
+}
+
+impl CaptureManifest {
+    pub fn new(setup: CaptureSetup) -> CaptureManifest {
+        let now = Instant::now();
+        CaptureManifest {
+            setup,
+            started: now,
+            entries: Vec::new(),
+            frames_captured: 0,
+            frames_dropped: 0,
+            first_timecode: None,
+            last_timecode: None,
+            timecode_sources: Vec::new(),
+            autosave: None,
+            last_saved: now,
+        }
+    }
+
+    /// Rewrite a partial manifest to `path` at most once per `interval`, whenever it changes.
+    /// This allows the manifest to be recovered if the process crashes during a capture.
+    pub fn set_autosave(&mut self, path: impl Into<PathBuf>, interval: Duration) {
+        self.autosave = Some((path.into(), interval));
+    }
+
+    pub fn setup(&self) -> &CaptureSetup {
+        &self.setup
+    }
+    pub fn entries(&self) -> &[ManifestEntry] {
+        &self.entries
+    }
+    pub fn frames_captured(&self) -> u64 {
+        self.frames_captured
+    }
+    pub fn frames_dropped(&self) -> u64 {
+        self.frames_dropped
+    }
+
+    /// Record a captured frame, with its timecode if known.
+    pub fn record_frame(&mut self, timecode: Option<String>) -> std::io::Result<()> {
+        self.frames_captured += 1;
+        if timecode.is_some() {
+            if self.first_timecode.is_none() {
+                self.first_timecode = timecode.clone();
+            }
+            self.last_timecode = timecode;
+        }
+        self.maybe_autosave()
+    }
+
+    /// Set the statistics of the hardware timecode sources, as reported by a
+    /// `TimecodeTracker`, replacing any set before.
+    pub fn set_timecode_sources(&mut self, sources: Vec<TimecodeSourceStats>) {
+        self.timecode_sources = sources;
+    }
+
+    /// Record a notable event.
+    pub fn record_event(&mut self, event: ManifestEvent) -> std::io::Result<()> {
+        if let ManifestEvent::Dropped { count, .. } = &event {
+            self.frames_dropped += count;
+        }
+        self.entries.push(ManifestEntry {
+            elapsed: self.started.elapsed(),
+            event,
+        });
+        self.maybe_autosave()
+    }
+
+    /// Record the manifest entry of `event`, if its payload has one, as
+    /// `crate::event::EventPayload::manifest_event`. Returns whether it was recorded.
+    pub fn record_decklink_event(&mut self, event: &DecklinkEvent) -> std::io::Result<bool> {
+        match event.payload.manifest_event() {
+            Some(entry) => self.record_event(entry).map(|_| true),
+            None => Ok(false),
+        }
+    }
+
+    fn maybe_autosave(&mut self) -> std::io::Result<()> {
+        if let Some((path, interval)) = &self.autosave {
+            if self.last_saved.elapsed() >= *interval {
+                let path = path.clone();
+                self.write_to(&path, false)?;
+                self.last_saved = Instant::now();
+            }
+        }
+        Ok(())
+    }
+
+    /// Serialize the manifest as JSON.
+    pub fn to_json(&self, finished: bool) -> String {
+        let mut out = String::new();
+        let s = &self.setup;
+
+        let _ = write!(
+            out,
+            "{{\n  \"version\": {},\n  \"finished\": {},\n  \"setup\": {{\n    \"device_name\": ",
+            MANIFEST_VERSION, finished
+        );
+        write_json_opt_string(&mut out, &s.device_name);
+        out.push_str(",\n    \"driver_version\": ");
+        write_json_opt_string(&mut out, &s.driver_version);
+        let _ = write!(
+            out,
+            ",\n    \"display_mode\": \"{:?}\",\n    \"pixel_format\": \"{:?}\",\n    \"width\": {},\n    \"height\": {},\n",
+            s.display_mode, s.pixel_format, s.width, s.height
+        );
+        match s.frame_duration {
+            Some(d) => {
+                let _ = write!(
+                    out,
+                    "    \"frame_duration\": {},\n    \"time_scale\": {}",
+                    d.value, d.scale
+                );
+            }
+            None => out.push_str("    \"frame_duration\": null,\n    \"time_scale\": null"),
+        }
+        out.push_str(",\n    \"effective_config\": ");
+        match &s.effective_config {
+            Some(config) => config.write_json(&mut out),
+            None => out.push_str("null"),
+        }
+        out.push('\n');
+
+        let _ = write!(
+            out,
+            "  }},\n  \"totals\": {{\n    \"frames_captured\": {},\n    \"frames_dropped\": {},\n    \"first_timecode\": ",
+            self.frames_captured, self.frames_dropped
+        );
+        write_json_opt_string(&mut out, &self.first_timecode);
+        out.push_str(",\n    \"last_timecode\": ");
+        write_json_opt_string(&mut out, &self.last_timecode);
+        out.push_str(",\n    \"timecode_sources\": [");
+        for (i, source) in self.timecode_sources.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            let _ = write!(
+                out,
+                "\n      {{ \"format\": \"{:?}\", \"frames_with_timecode\": {}, \"longest_gap\": {}, \"discontinuities\": {}, \"duplicates\": {} }}",
+                source.format,
+                source.frames_with_timecode,
+                source.longest_gap,
+                source.discontinuities,
+                source.duplicates
+            );
+        }
+        if !self.timecode_sources.is_empty() {
+            out.push_str("\n    ");
+        }
+        out.push_str("]\n  },\n  \"events\": [");
+
+        for (i, entry) in self.entries.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            let _ = write!(
+                out,
+                "\n    {{ \"elapsed_ms\": {}, ",
+                entry.elapsed.as_millis()
+            );
+            match &entry.event {
+                ManifestEvent::Dropped { first_frame, count } => {
+                    let _ = write!(
+                        out,
+                        "\"type\": \"dropped\", \"first_frame\": {}, \"count\": {}",
+                        first_frame, count
+                    );
+                }
+                ManifestEvent::SignalLost { frame } => {
+                    let _ = write!(out, "\"type\": \"signal_lost\", \"frame\": {}", frame);
+                }
+                ManifestEvent::SignalRestored { frame } => {
+                    let _ = write!(out, "\"type\": \"signal_restored\", \"frame\": {}", frame);
+                }
+                ManifestEvent::FormatChanged {
+                    frame,
+                    display_mode,
+                } => {
+                    let _ = write!(
+                        out,
+                        "\"type\": \"format_changed\", \"frame\": {}, \"display_mode\": \"{:?}\"",
+                        frame, display_mode
+                    );
+                }
+                ManifestEvent::FormatTransition {
+                    first_frame,
+                    frames,
+                } => {
+                    let _ = write!(
+                        out,
+                        "\"type\": \"format_transition\", \"first_frame\": {}, \"frames\": {}",
+                        first_frame, frames
+                    );
+                }
+                ManifestEvent::Mark { frame, label } => {
+                    let _ = write!(out, "\"type\": \"mark\", \"frame\": {}, \"label\": ", frame);
+                    write_json_string(&mut out, label);
+                }
+                ManifestEvent::Segment(segment) => {
+                    let _ = write!(
+                        out,
+                        "\"type\": \"segment\", \"index\": {}, \"first_frame\": {}, \"frames\": {}, \"reason\": \"{:?}\", \"path\": ",
+                        segment.boundary.index,
+                        segment.boundary.first_frame,
+                        segment.frame_count,
+                        segment.boundary.reason
+                    );
+                    write_json_string(&mut out, &segment.path.to_string_lossy());
+                }
+                ManifestEvent::AvOffset { frame, offset } => {
+                    let _ = write!(
+                        out,
+                        "\"type\": \"av_offset\", \"frame\": {}, \"offset\": {}, \"time_scale\": {}",
+                        frame, offset.value, offset.scale
+                    );
+                }
+                ManifestEvent::MemberAdded { member, tick } => {
+                    let _ = write!(
+                        out,
+                        "\"type\": \"member_added\", \"member\": {}, \"tick\": {}",
+                        member,
+                        json_opt_number(*tick)
+                    );
+                }
+                ManifestEvent::MemberRemoved { member, tick } => {
+                    let _ = write!(
+                        out,
+                        "\"type\": \"member_removed\", \"member\": {}, \"tick\": {}",
+                        member,
+                        json_opt_number(*tick)
+                    );
+                }
+                ManifestEvent::AudioFilled {
+                    sample_time,
+                    samples,
+                } => {
+                    let _ = write!(
+                        out,
+                        "\"type\": \"audio_filled\", \"sample_time\": {}, \"samples\": {}",
+                        sample_time, samples
+                    );
+                }
+                ManifestEvent::AudioTrimmed {
+                    sample_time,
+                    samples,
+                } => {
+                    let _ = write!(
+                        out,
+                        "\"type\": \"audio_trimmed\", \"sample_time\": {}, \"samples\": {}",
+                        sample_time, samples
+                    );
+                }
+            }
+            out.push_str(" }");
+        }
+        if !self.entries.is_empty() {
+            out.push_str("\n  ");
+        }
+        out.push_str("]\n}\n");
+
+        out
+    }
+
+    /// Atomically write the manifest to `path`, by writing a temporary file and renaming it.
+    pub fn write_to(&self, path: &Path, finished: bool) -> std::io::Result<()> {
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        let tmp = PathBuf::from(tmp);
+
+        std::fs::write(&tmp, self.to_json(finished))?;
+        std::fs::rename(&tmp, path)
+    }
+
+    /// Write the finished manifest to `path`.
+    pub fn finish(self, path: &Path) -> std::io::Result<()> {
+        self.write_to(path, true)
+    }
+}
@@ -0,0 +1,349 @@
+//! The copy every frame copy of the crate goes through.
+//!
+//! Frames are large enough that copying one is limited by memory bandwidth, and a plain
+//! `memcpy` of a 2160p frame also fills the processor caches with it, evicting whatever the
+//! code that runs next was working on. When nothing will read the copy soon, as when it is
+//! handed to the driver for output or retained for a consumer on another thread, writing it
+//! with non-temporal stores goes around the caches instead.
+//!
+//! `copy_frame` takes a `CopyHint` saying which case a copy is, and picks a `CopyPath` for
+//! it:
+//!
+//! - `CopyHint::WillReadSoon` copies are always plain copies, which leave the copy in the
+//!   caches for the reader.
+//! - `CopyHint::StreamingWriteOnly` copies of at least `STREAMING_THRESHOLD` bytes use
+//!   non-temporal stores, with AVX when the processor has it and SSE2 otherwise on x86_64,
+//!   and `STNP` on aarch64. Smaller copies fit in the caches anyway, and are plain copies.
+//!
+//! On other targets every copy is a plain copy, so the hint only ever makes a copy faster.
+//! `copy_frame_using` copies with a path of the caller's choosing, for measuring them, as the
+//! `copy_throughput` example does.
+//!
+//! A `CopyLayout` describes the rows of a copy, which may be strided differently in the
+//! source and the destination. Rows that follow each other in both are copied as one.
+
+use crate::SdkError;
+
+/// Copies at least this large are made with non-temporal stores when the hint allows it.
+pub const STREAMING_THRESHOLD: usize = 256 * 1024;
+
+/// What will happen to a copy after it is made.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash, Default)]
+pub enum CopyHint {
+    /// The copy will be read soon, by the code making it, so it should stay in the caches.
+    #[default]
+    WillReadSoon,
+    /// Nothing will read the copy for a while, or the processor will not read it at all, as
+    /// with a buffer of the driver.
+    StreamingWriteOnly,
+}
+
+/// How a copy is made.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+pub enum CopyPath {
+    /// `ptr::copy_nonoverlapping`, the standard library's `memcpy`.
+    Standard,
+    /// 16 byte non-temporal stores, on x86_64.
+    StreamingSse2,
+    /// 32 byte non-temporal stores, on x86_64 processors with AVX.
+    StreamingAvx,
+    /// Non-temporal pair stores, on aarch64.
+    StreamingNeon,
+}
+
+impl CopyPath {
+    /// Whether this processor can copy with this path.
+    pub fn is_available(&self) -> bool {
+        match self {
+            CopyPath::Standard => true,
+            #[cfg(target_arch = "x86_64")]
+            CopyPath::StreamingSse2 => true,
+            #[cfg(target_arch = "x86_64")]
+            CopyPath::StreamingAvx => std::is_x86_feature_detected!("avx"),
+            #[cfg(target_arch = "aarch64")]
+            CopyPath::StreamingNeon => true,
+            #[allow(unreachable_patterns)]
+            _ => false,
+        }
+    }
+
+    /// Whether this path writes around the caches.
+    pub fn is_streaming(&self) -> bool {
+        *self != CopyPath::Standard
+    }
+}
+
+/// The paths this processor can copy with, the standard one first.
+pub fn copy_paths() -> Vec<CopyPath> {
+    [
+        CopyPath::Standard,
+        CopyPath::StreamingSse2,
+        CopyPath::StreamingAvx,
+        CopyPath::StreamingNeon,
+    ]
+    .into_iter()
+    .filter(|p| p.is_available())
+    .collect()
+}
+
+/// The path `copy_frame` takes for a copy of `bytes` bytes with `hint`.
+pub fn chosen_path(hint: CopyHint, bytes: usize) -> CopyPath {
+    if hint == CopyHint::WillReadSoon || bytes < STREAMING_THRESHOLD {
+        return CopyPath::Standard;
+    }
+    streaming_path()
+}
+
+fn streaming_path() -> CopyPath {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if CopyPath::StreamingAvx.is_available() {
+            CopyPath::StreamingAvx
+        } else {
+            CopyPath::StreamingSse2
+        }
+    }
+    #[cfg(target_arch = "aarch64")]
+    {
+        CopyPath::StreamingNeon
+    }
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+    {
+        CopyPath::Standard
+    }
+}
+
+/// The rows of a copy.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+pub struct CopyLayout {
+    /// The bytes copied from each row.
+    pub row_len: usize,
+    pub rows: usize,
+    /// The distance from the start of one row of the source to the next.
+    pub src_row_bytes: usize,
+    /// The distance from the start of one row of the destination to the next.
+    pub dst_row_bytes: usize,
+}
+
+impl CopyLayout {
+    /// `len` bytes in one piece.
+    pub fn contiguous(len: usize) -> CopyLayout {
+        CopyLayout {
+            row_len: len,
+            rows: 1,
+            src_row_bytes: len,
+            dst_row_bytes: len,
+        }
+    }
+
+    /// `rows` rows of `row_len` bytes, with the given strides.
+    pub fn rows(
+        row_len: usize,
+        rows: usize,
+        src_row_bytes: usize,
+        dst_row_bytes: usize,
+    ) -> CopyLayout {
+        CopyLayout {
+            row_len,
+            rows,
+            src_row_bytes,
+            dst_row_bytes,
+        }
+    }
+
+    /// The bytes copied.
+    pub fn bytes(&self) -> usize {
+        self.row_len * self.rows
+    }
+
+    /// The bytes a buffer with `row_bytes` bytes per row needs to hold the rows, or `None`
+    /// if the rows overlap or the size overflows.
+    fn span(&self, row_bytes: usize) -> Option<usize> {
+        if self.rows == 0 || self.row_len == 0 {
+            return Some(0);
+        }
+        if row_bytes < self.row_len {
+            return None;
+        }
+        (self.rows - 1)
+            .checked_mul(row_bytes)?
+            .checked_add(self.row_len)
+    }
+}
+
+/// Copy the rows `layout` describes from `src` into `dst`, with the path `hint` calls for.
+///
+/// Fails with `SdkError::INVALIDARG` if either buffer is too small for the rows, or the rows
+/// of either overlap.
+pub fn copy_frame(
+    dst: &mut [u8],
+    src: &[u8],
+    layout: CopyLayout,
+    hint: CopyHint,
+) -> Result<(), SdkError> {
+    copy_frame_using(chosen_path(hint, layout.bytes()), dst, src, layout)
+}
+
+/// Copy the rows `layout` describes from `src` into `dst` with `path`, whatever its size.
+///
+/// Fails with `SdkError::NOTIMPL` if this processor cannot copy with `path`, and as
+/// `copy_frame` does otherwise.
+pub fn copy_frame_using(
+    path: CopyPath,
+    dst: &mut [u8],
+    src: &[u8],
+    layout: CopyLayout,
+) -> Result<(), SdkError> {
+    if !path.is_available() {
+        return Err(SdkError::NOTIMPL);
+    }
+    let src_span = layout.span(layout.src_row_bytes);
+    let dst_span = layout.span(layout.dst_row_bytes);
+    match (src_span, dst_span) {
+        (Some(s), Some(d)) if s <= src.len() && d <= dst.len() => {}
+        _ => return Err(SdkError::INVALIDARG),
+    }
+    if layout.bytes() == 0 {
+        return Ok(());
+    }
+
+    // Rows that follow each other in both buffers are one long row
+    let (row_len, rows) =
+        if layout.src_row_bytes == layout.row_len && layout.dst_row_bytes == layout.row_len {
+            (layout.bytes(), 1)
+        } else {
+            (layout.row_len, layout.rows)
+        };
+    for row in 0..rows {
+        let src_row = &src[row * layout.src_row_bytes..][..row_len];
+        let dst_row = &mut dst[row * layout.dst_row_bytes..][..row_len];
+        copy_row(path, dst_row, src_row);
+    }
+    if path.is_streaming() {
+        // Non-temporal stores are weakly ordered, so they are fenced before anything else
+        // can see the copy
+        store_fence();
+    }
+    Ok(())
+}
+
+fn copy_row(path: CopyPath, dst: &mut [u8], src: &[u8]) {
+    debug_assert_eq!(dst.len(), src.len());
+    match path {
+        // Safety: the paths were checked to be available, and both rows are `src.len()` long
+        #[cfg(target_arch = "x86_64")]
+        CopyPath::StreamingSse2 => unsafe {
+            x86::stream_sse2(dst.as_mut_ptr(), src.as_ptr(), src.len())
+        },
+        #[cfg(target_arch = "x86_64")]
+        CopyPath::StreamingAvx => unsafe {
+            x86::stream_avx(dst.as_mut_ptr(), src.as_ptr(), src.len())
+        },
+        #[cfg(target_arch = "aarch64")]
+        CopyPath::StreamingNeon => unsafe {
+            arm::stream_neon(dst.as_mut_ptr(), src.as_ptr(), src.len())
+        },
+        _ => dst.copy_from_slice(src),
+    }
+}
+
+fn store_fence() {
+    // Safety: only called after a streaming path, which was checked to be available
+    #[cfg(target_arch = "x86_64")]
+    unsafe {
+        x86::sfence()
+    }
+    #[cfg(target_arch = "aarch64")]
+    unsafe {
+        arm::store_barrier()
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod x86 {
+    use std::arch::x86_64::*;
+    use std::ptr::copy_nonoverlapping;
+
+    /// Copy `len` bytes, storing the aligned middle of the destination 16 bytes at a time.
+    #[target_feature(enable = "sse2")]
+    pub(super) unsafe fn stream_sse2(dst: *mut u8, src: *const u8, len: usize) {
+        let head = dst.align_offset(16).min(len);
+        copy_nonoverlapping(src, dst, head);
+        let mut i = head;
+        while i + 64 <= len {
+            let a = _mm_loadu_si128(src.add(i) as *const __m128i);
+            let b = _mm_loadu_si128(src.add(i + 16) as *const __m128i);
+            let c = _mm_loadu_si128(src.add(i + 32) as *const __m128i);
+            let d = _mm_loadu_si128(src.add(i + 48) as *const __m128i);
+            _mm_stream_si128(dst.add(i) as *mut __m128i, a);
+            _mm_stream_si128(dst.add(i + 16) as *mut __m128i, b);
+            _mm_stream_si128(dst.add(i + 32) as *mut __m128i, c);
+            _mm_stream_si128(dst.add(i + 48) as *mut __m128i, d);
+            i += 64;
+        }
+        while i + 16 <= len {
+            let a = _mm_loadu_si128(src.add(i) as *const __m128i);
+            _mm_stream_si128(dst.add(i) as *mut __m128i, a);
+            i += 16;
+        }
+        copy_nonoverlapping(src.add(i), dst.add(i), len - i);
+    }
+
+    /// Copy `len` bytes, storing the aligned middle of the destination 32 bytes at a time.
+    #[target_feature(enable = "avx")]
+    pub(super) unsafe fn stream_avx(dst: *mut u8, src: *const u8, len: usize) {
+        let head = dst.align_offset(32).min(len);
+        copy_nonoverlapping(src, dst, head);
+        let mut i = head;
+        while i + 128 <= len {
+            let a = _mm256_loadu_si256(src.add(i) as *const __m256i);
+            let b = _mm256_loadu_si256(src.add(i + 32) as *const __m256i);
+            let c = _mm256_loadu_si256(src.add(i + 64) as *const __m256i);
+            let d = _mm256_loadu_si256(src.add(i + 96) as *const __m256i);
+            _mm256_stream_si256(dst.add(i) as *mut __m256i, a);
+            _mm256_stream_si256(dst.add(i + 32) as *mut __m256i, b);
+            _mm256_stream_si256(dst.add(i + 64) as *mut __m256i, c);
+            _mm256_stream_si256(dst.add(i + 96) as *mut __m256i, d);
+            i += 128;
+        }
+        while i + 32 <= len {
+            let a = _mm256_loadu_si256(src.add(i) as *const __m256i);
+            _mm256_stream_si256(dst.add(i) as *mut __m256i, a);
+            i += 32;
+        }
+        copy_nonoverlapping(src.add(i), dst.add(i), len - i);
+    }
+
+    #[target_feature(enable = "sse2")]
+    pub(super) unsafe fn sfence() {
+        _mm_sfence();
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod arm {
+    use std::arch::asm;
+    use std::ptr::copy_nonoverlapping;
+
+    /// Copy `len` bytes, 32 at a time with a non-temporal pair store.
+    pub(super) unsafe fn stream_neon(dst: *mut u8, src: *const u8, len: usize) {
+        let mut i = 0;
+        while i + 32 <= len {
+            asm!(
+                "ldp {a:q}, {b:q}, [{src}]",
+                "stnp {a:q}, {b:q}, [{dst}]",
+                src = in(reg) src.add(i),
+                dst = in(reg) dst.add(i),
+                a = out(vreg) _,
+                b = out(vreg) _,
+                options(nostack, preserves_flags),
+            );
+            i += 32;
+        }
+        copy_nonoverlapping(src.add(i), dst.add(i), len - i);
+    }
+
+    pub(super) unsafe fn store_barrier() {
+        asm!("dmb ishst", options(nostack, preserves_flags));
+    }
+}
@@ -0,0 +1,2275 @@
+//! Reference counting for every object type, and a stand-in for each function `ffi.rs` does
+//! not implement, which does nothing and returns `E_NOTIMPL` or a null or zero value, as a
+//! driver without the function would.
+//!
+//! This file is maintained by hand, against the declarations of `sdk.rs`. When the bindings
+//! are regenerated, a function they add that the crate calls fails to link with the mock
+//! backend until it is given a stand-in here, or is implemented in `ffi.rs` and its stand-in
+//! taken out.
+
+#![allow(non_snake_case)]
+
+use crate::mock::object;
+use crate::sdk::*;
+use crate::SdkError;
+use std::ptr::null_mut;
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_timecode_add_ref(
+    obj: *mut cdecklink_timecode_t,
+) -> ::std::os::raw::c_ulong {
+    object::add_ref(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_timecode_release(
+    obj: *mut cdecklink_timecode_t,
+) -> ::std::os::raw::c_ulong {
+    object::release(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_timecode_get_bcd(
+    _obj: *mut cdecklink_timecode_t,
+) -> DecklinkTimecodeBCD {
+    0
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_timecode_get_string(
+    _obj: *mut cdecklink_timecode_t,
+    _timecode: *mut *const ::std::os::raw::c_char,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_display_mode_iterator_add_ref(
+    obj: *mut cdecklink_display_mode_iterator_t,
+) -> ::std::os::raw::c_ulong {
+    object::add_ref(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_display_mode_iterator_release(
+    obj: *mut cdecklink_display_mode_iterator_t,
+) -> ::std::os::raw::c_ulong {
+    object::release(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_display_mode_add_ref(
+    obj: *mut cdecklink_display_mode_t,
+) -> ::std::os::raw::c_ulong {
+    object::add_ref(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_display_mode_release(
+    obj: *mut cdecklink_display_mode_t,
+) -> ::std::os::raw::c_ulong {
+    object::release(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_device_add_ref(
+    obj: *mut cdecklink_device_t,
+) -> ::std::os::raw::c_ulong {
+    object::add_ref(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_device_release(
+    obj: *mut cdecklink_device_t,
+) -> ::std::os::raw::c_ulong {
+    object::release(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_configuration_add_ref(
+    obj: *mut cdecklink_configuration_t,
+) -> ::std::os::raw::c_ulong {
+    object::add_ref(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_configuration_release(
+    obj: *mut cdecklink_configuration_t,
+) -> ::std::os::raw::c_ulong {
+    object::release(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_configuration_set_flag(
+    _obj: *mut cdecklink_configuration_t,
+    _cfgID: DecklinkConfigurationID,
+    _value: bool,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_configuration_get_flag(
+    _obj: *mut cdecklink_configuration_t,
+    _cfgID: DecklinkConfigurationID,
+    _value: *mut bool,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_configuration_set_int(
+    _obj: *mut cdecklink_configuration_t,
+    _cfgID: DecklinkConfigurationID,
+    _value: i64,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_configuration_get_int(
+    _obj: *mut cdecklink_configuration_t,
+    _cfgID: DecklinkConfigurationID,
+    _value: *mut i64,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_configuration_set_float(
+    _obj: *mut cdecklink_configuration_t,
+    _cfgID: DecklinkConfigurationID,
+    _value: f64,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_configuration_get_float(
+    _obj: *mut cdecklink_configuration_t,
+    _cfgID: DecklinkConfigurationID,
+    _value: *mut f64,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_configuration_set_string(
+    _obj: *mut cdecklink_configuration_t,
+    _cfgID: DecklinkConfigurationID,
+    _value: *const ::std::os::raw::c_char,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_configuration_get_string(
+    _obj: *mut cdecklink_configuration_t,
+    _cfgID: DecklinkConfigurationID,
+    _value: *mut *const ::std::os::raw::c_char,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_configuration_write_configuration_to_preferences(
+    _obj: *mut cdecklink_configuration_t,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_encoder_configuration_add_ref(
+    obj: *mut cdecklink_encoder_configuration_t,
+) -> ::std::os::raw::c_ulong {
+    object::add_ref(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_encoder_configuration_release(
+    obj: *mut cdecklink_encoder_configuration_t,
+) -> ::std::os::raw::c_ulong {
+    object::release(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_encoder_configuration_set_flag(
+    _obj: *mut cdecklink_encoder_configuration_t,
+    _cfgID: DecklinkEncoderConfigurationID,
+    _value: bool,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_encoder_configuration_get_flag(
+    _obj: *mut cdecklink_encoder_configuration_t,
+    _cfgID: DecklinkEncoderConfigurationID,
+    _value: *mut bool,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_encoder_configuration_set_int(
+    _obj: *mut cdecklink_encoder_configuration_t,
+    _cfgID: DecklinkEncoderConfigurationID,
+    _value: i64,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_encoder_configuration_get_int(
+    _obj: *mut cdecklink_encoder_configuration_t,
+    _cfgID: DecklinkEncoderConfigurationID,
+    _value: *mut i64,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_encoder_configuration_set_float(
+    _obj: *mut cdecklink_encoder_configuration_t,
+    _cfgID: DecklinkEncoderConfigurationID,
+    _value: f64,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_encoder_configuration_get_float(
+    _obj: *mut cdecklink_encoder_configuration_t,
+    _cfgID: DecklinkEncoderConfigurationID,
+    _value: *mut f64,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_encoder_configuration_set_string(
+    _obj: *mut cdecklink_encoder_configuration_t,
+    _cfgID: DecklinkEncoderConfigurationID,
+    _value: *const ::std::os::raw::c_char,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_encoder_configuration_get_string(
+    _obj: *mut cdecklink_encoder_configuration_t,
+    _cfgID: DecklinkEncoderConfigurationID,
+    _value: *mut *const ::std::os::raw::c_char,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_encoder_configuration_get_bytes(
+    _obj: *mut cdecklink_encoder_configuration_t,
+    _cfgID: DecklinkEncoderConfigurationID,
+    _buffer: *mut ::std::os::raw::c_void,
+    _bufferSize: *mut u32,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_deck_control_add_ref(
+    obj: *mut cdecklink_deck_control_t,
+) -> ::std::os::raw::c_ulong {
+    object::add_ref(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_deck_control_release(
+    obj: *mut cdecklink_deck_control_t,
+) -> ::std::os::raw::c_ulong {
+    object::release(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_deck_control_open(
+    _obj: *mut cdecklink_deck_control_t,
+    _timeScale: DecklinkTimeScale,
+    _timeValue: DecklinkTimeValue,
+    _timecodeIsDropFrame: bool,
+    _error: *mut DecklinkDeckControlError,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_deck_control_close(
+    _obj: *mut cdecklink_deck_control_t,
+    _standbyOn: bool,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_deck_control_get_current_state(
+    _obj: *mut cdecklink_deck_control_t,
+    _mode: *mut DecklinkDeckControlMode,
+    _vtrControlState: *mut DecklinkDeckControlVTRControlState,
+    _flags: *mut DecklinkDeckControlStatusFlags,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_deck_control_set_standby(
+    _obj: *mut cdecklink_deck_control_t,
+    _standbyOn: bool,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_deck_control_send_command(
+    _obj: *mut cdecklink_deck_control_t,
+    _inBuffer: *mut u8,
+    _inBufferSize: u32,
+    _outBuffer: *mut u8,
+    _outDataSize: *mut u32,
+    _outBufferSize: u32,
+    _error: *mut DecklinkDeckControlError,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_deck_control_play(
+    _obj: *mut cdecklink_deck_control_t,
+    _error: *mut DecklinkDeckControlError,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_deck_control_stop(
+    _obj: *mut cdecklink_deck_control_t,
+    _error: *mut DecklinkDeckControlError,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_deck_control_toggle_play_stop(
+    _obj: *mut cdecklink_deck_control_t,
+    _error: *mut DecklinkDeckControlError,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_deck_control_eject(
+    _obj: *mut cdecklink_deck_control_t,
+    _error: *mut DecklinkDeckControlError,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_deck_control_go_to_timecode(
+    _obj: *mut cdecklink_deck_control_t,
+    _timecode: DecklinkTimecodeBCD,
+    _error: *mut DecklinkDeckControlError,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_deck_control_fast_forward(
+    _obj: *mut cdecklink_deck_control_t,
+    _viewTape: bool,
+    _error: *mut DecklinkDeckControlError,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_deck_control_rewind(
+    _obj: *mut cdecklink_deck_control_t,
+    _viewTape: bool,
+    _error: *mut DecklinkDeckControlError,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_deck_control_step_forward(
+    _obj: *mut cdecklink_deck_control_t,
+    _error: *mut DecklinkDeckControlError,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_deck_control_step_back(
+    _obj: *mut cdecklink_deck_control_t,
+    _error: *mut DecklinkDeckControlError,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_deck_control_jog(
+    _obj: *mut cdecklink_deck_control_t,
+    _rate: f64,
+    _error: *mut DecklinkDeckControlError,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_deck_control_shuttle(
+    _obj: *mut cdecklink_deck_control_t,
+    _rate: f64,
+    _error: *mut DecklinkDeckControlError,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_deck_control_get_timecode_string(
+    _obj: *mut cdecklink_deck_control_t,
+    _currentTimeCode: *mut *const ::std::os::raw::c_char,
+    _error: *mut DecklinkDeckControlError,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_deck_control_get_timecode(
+    _obj: *mut cdecklink_deck_control_t,
+    _currentTimecode: *mut *mut cdecklink_timecode_t,
+    _error: *mut DecklinkDeckControlError,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_deck_control_get_timecode_bcd(
+    _obj: *mut cdecklink_deck_control_t,
+    _currentTimecode: *mut DecklinkTimecodeBCD,
+    _error: *mut DecklinkDeckControlError,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_deck_control_set_preroll(
+    _obj: *mut cdecklink_deck_control_t,
+    _prerollSeconds: u32,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_deck_control_get_preroll(
+    _obj: *mut cdecklink_deck_control_t,
+    _prerollSeconds: *mut u32,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_deck_control_set_export_offset(
+    _obj: *mut cdecklink_deck_control_t,
+    _exportOffsetFields: i32,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_deck_control_get_export_offset(
+    _obj: *mut cdecklink_deck_control_t,
+    _exportOffsetFields: *mut i32,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_deck_control_get_manual_export_offset(
+    _obj: *mut cdecklink_deck_control_t,
+    _deckManualExportOffsetFields: *mut i32,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_deck_control_set_capture_offset(
+    _obj: *mut cdecklink_deck_control_t,
+    _captureOffsetFields: i32,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_deck_control_get_capture_offset(
+    _obj: *mut cdecklink_deck_control_t,
+    _captureOffsetFields: *mut i32,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_deck_control_start_export(
+    _obj: *mut cdecklink_deck_control_t,
+    _inTimecode: DecklinkTimecodeBCD,
+    _outTimecode: DecklinkTimecodeBCD,
+    _exportModeOps: DecklinkDeckControlExportModeOpsFlags,
+    _error: *mut DecklinkDeckControlError,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_deck_control_start_capture(
+    _obj: *mut cdecklink_deck_control_t,
+    _useVITC: bool,
+    _inTimecode: DecklinkTimecodeBCD,
+    _outTimecode: DecklinkTimecodeBCD,
+    _error: *mut DecklinkDeckControlError,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_deck_control_get_device_id(
+    _obj: *mut cdecklink_deck_control_t,
+    _deviceId: *mut u16,
+    _error: *mut DecklinkDeckControlError,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_deck_control_abort(
+    _obj: *mut cdecklink_deck_control_t,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_deck_control_crash_record_start(
+    _obj: *mut cdecklink_deck_control_t,
+    _error: *mut DecklinkDeckControlError,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_deck_control_crash_record_stop(
+    _obj: *mut cdecklink_deck_control_t,
+    _error: *mut DecklinkDeckControlError,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_deck_control_set_callback(
+    _obj: *mut cdecklink_deck_control_t,
+    _ctx: *mut ::std::os::raw::c_void,
+    _cb0: cdecklink_deck_control_status_callback_timecode_update,
+    _cb1: cdecklink_deck_control_status_callback_vtr_control_state_changed,
+    _cb2: cdecklink_deck_control_status_callback_deck_control_event_received,
+    _cb3: cdecklink_deck_control_status_callback_deck_control_status_changed,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_video_buffer_allocator_add_ref(
+    obj: *mut cdecklink_video_buffer_allocator_t,
+) -> ::std::os::raw::c_ulong {
+    object::add_ref(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_video_buffer_allocator_release(
+    obj: *mut cdecklink_video_buffer_allocator_t,
+) -> ::std::os::raw::c_ulong {
+    object::release(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_video_buffer_allocator_provider_add_ref(
+    obj: *mut cdecklink_video_buffer_allocator_provider_t,
+) -> ::std::os::raw::c_ulong {
+    object::add_ref(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_video_buffer_allocator_provider_release(
+    obj: *mut cdecklink_video_buffer_allocator_provider_t,
+) -> ::std::os::raw::c_ulong {
+    object::release(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_iterator_add_ref(
+    obj: *mut cdecklink_iterator_t,
+) -> ::std::os::raw::c_ulong {
+    object::add_ref(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_iterator_release(
+    obj: *mut cdecklink_iterator_t,
+) -> ::std::os::raw::c_ulong {
+    object::release(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_api_information_add_ref(
+    obj: *mut cdecklink_api_information_t,
+) -> ::std::os::raw::c_ulong {
+    object::add_ref(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_api_information_release(
+    obj: *mut cdecklink_api_information_t,
+) -> ::std::os::raw::c_ulong {
+    object::release(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_api_information_get_flag(
+    _obj: *mut cdecklink_api_information_t,
+    _cfgID: DecklinkAPIInformationID,
+    _value: *mut bool,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_api_information_get_float(
+    _obj: *mut cdecklink_api_information_t,
+    _cfgID: DecklinkAPIInformationID,
+    _value: *mut f64,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_api_information_get_string(
+    _obj: *mut cdecklink_api_information_t,
+    _cfgID: DecklinkAPIInformationID,
+    _value: *mut *const ::std::os::raw::c_char,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_ip_flow_attributes_add_ref(
+    obj: *mut cdecklink_ip_flow_attributes_t,
+) -> ::std::os::raw::c_ulong {
+    object::add_ref(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_ip_flow_attributes_release(
+    obj: *mut cdecklink_ip_flow_attributes_t,
+) -> ::std::os::raw::c_ulong {
+    object::release(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_ip_flow_attributes_get_int(
+    _obj: *mut cdecklink_ip_flow_attributes_t,
+    _attrID: DecklinkIPFlowAttributeID,
+    _value: *mut i64,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_ip_flow_attributes_get_flag(
+    _obj: *mut cdecklink_ip_flow_attributes_t,
+    _attrID: DecklinkIPFlowAttributeID,
+    _value: *mut bool,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_ip_flow_attributes_get_float(
+    _obj: *mut cdecklink_ip_flow_attributes_t,
+    _attrID: DecklinkIPFlowAttributeID,
+    _value: *mut f64,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_ip_flow_attributes_get_string(
+    _obj: *mut cdecklink_ip_flow_attributes_t,
+    _attrID: DecklinkIPFlowAttributeID,
+    _value: *mut *const ::std::os::raw::c_char,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_ip_flow_status_add_ref(
+    obj: *mut cdecklink_ip_flow_status_t,
+) -> ::std::os::raw::c_ulong {
+    object::add_ref(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_ip_flow_status_release(
+    obj: *mut cdecklink_ip_flow_status_t,
+) -> ::std::os::raw::c_ulong {
+    object::release(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_ip_flow_status_get_int(
+    _obj: *mut cdecklink_ip_flow_status_t,
+    _statusID: DecklinkIPFlowStatusID,
+    _value: *mut i64,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_ip_flow_status_get_flag(
+    _obj: *mut cdecklink_ip_flow_status_t,
+    _statusID: DecklinkIPFlowStatusID,
+    _value: *mut bool,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_ip_flow_status_get_float(
+    _obj: *mut cdecklink_ip_flow_status_t,
+    _statusID: DecklinkIPFlowStatusID,
+    _value: *mut f64,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_ip_flow_status_get_string(
+    _obj: *mut cdecklink_ip_flow_status_t,
+    _statusID: DecklinkIPFlowStatusID,
+    _value: *mut *const ::std::os::raw::c_char,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_ip_flow_setting_add_ref(
+    obj: *mut cdecklink_ip_flow_setting_t,
+) -> ::std::os::raw::c_ulong {
+    object::add_ref(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_ip_flow_setting_release(
+    obj: *mut cdecklink_ip_flow_setting_t,
+) -> ::std::os::raw::c_ulong {
+    object::release(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_ip_flow_setting_get_int(
+    _obj: *mut cdecklink_ip_flow_setting_t,
+    _settingID: DecklinkIPFlowSettingID,
+    _value: *mut i64,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_ip_flow_setting_get_flag(
+    _obj: *mut cdecklink_ip_flow_setting_t,
+    _settingID: DecklinkIPFlowSettingID,
+    _value: *mut bool,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_ip_flow_setting_get_float(
+    _obj: *mut cdecklink_ip_flow_setting_t,
+    _settingID: DecklinkIPFlowSettingID,
+    _value: *mut f64,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_ip_flow_setting_get_string(
+    _obj: *mut cdecklink_ip_flow_setting_t,
+    _settingID: DecklinkIPFlowSettingID,
+    _value: *mut *const ::std::os::raw::c_char,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_ip_flow_setting_set_int(
+    _obj: *mut cdecklink_ip_flow_setting_t,
+    _settingID: DecklinkIPFlowSettingID,
+    _value: i64,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_ip_flow_setting_set_flag(
+    _obj: *mut cdecklink_ip_flow_setting_t,
+    _settingID: DecklinkIPFlowSettingID,
+    _value: bool,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_ip_flow_setting_set_float(
+    _obj: *mut cdecklink_ip_flow_setting_t,
+    _settingID: DecklinkIPFlowSettingID,
+    _value: f64,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_ip_flow_setting_set_string(
+    _obj: *mut cdecklink_ip_flow_setting_t,
+    _settingID: DecklinkIPFlowSettingID,
+    _value: *const ::std::os::raw::c_char,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_ip_flow_add_ref(
+    obj: *mut cdecklink_ip_flow_t,
+) -> ::std::os::raw::c_ulong {
+    object::add_ref(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_ip_flow_release(
+    obj: *mut cdecklink_ip_flow_t,
+) -> ::std::os::raw::c_ulong {
+    object::release(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_ip_flow_enable(_obj: *mut cdecklink_ip_flow_t) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_ip_flow_disable(_obj: *mut cdecklink_ip_flow_t) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_ip_flow_iterator_add_ref(
+    obj: *mut cdecklink_ip_flow_iterator_t,
+) -> ::std::os::raw::c_ulong {
+    object::add_ref(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_ip_flow_iterator_release(
+    obj: *mut cdecklink_ip_flow_iterator_t,
+) -> ::std::os::raw::c_ulong {
+    object::release(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_ip_flow_iterator_next(
+    _obj: *mut cdecklink_ip_flow_iterator_t,
+    _deckLinkIPFlowInstance: *mut *mut cdecklink_ip_flow_t,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_output_add_ref(
+    obj: *mut cdecklink_output_t,
+) -> ::std::os::raw::c_ulong {
+    object::add_ref(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_output_release(
+    obj: *mut cdecklink_output_t,
+) -> ::std::os::raw::c_ulong {
+    object::release(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_output_get_display_mode(
+    _obj: *mut cdecklink_output_t,
+    _displayMode: DecklinkDisplayMode,
+    _resultDisplayMode: *mut *mut cdecklink_display_mode_t,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_output_set_screen_preview_callback(
+    _obj: *mut cdecklink_output_t,
+    _ctx: *mut ::std::os::raw::c_void,
+    _cb0: cdecklink_screen_preview_callback_draw_frame,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_output_create_video_frame_with_buffer(
+    _obj: *mut cdecklink_output_t,
+    _width: i32,
+    _height: i32,
+    _rowBytes: i32,
+    _pixelFormat: DecklinkPixelFormat,
+    _flags: DecklinkFrameFlags,
+    _buffer: *mut cdecklink_video_buffer_t,
+    _outFrame: *mut *mut cdecklink_mutable_video_frame_t,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_output_create_ancillary_data(
+    _obj: *mut cdecklink_output_t,
+    _pixelFormat: DecklinkPixelFormat,
+    _outBuffer: *mut *mut cdecklink_video_frame_ancillary_t,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_output_write_audio_samples_sync(
+    _obj: *mut cdecklink_output_t,
+    _buffer: *mut ::std::os::raw::c_void,
+    _sampleFrameCount: u32,
+    _sampleFramesWritten: *mut u32,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_output_schedule_audio_samples(
+    _obj: *mut cdecklink_output_t,
+    _buffer: *mut ::std::os::raw::c_void,
+    _sampleFrameCount: u32,
+    _streamTime: DecklinkTimeValue,
+    _timeScale: DecklinkTimeScale,
+    _sampleFramesWritten: *mut u32,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_output_set_audio_callback(
+    _obj: *mut cdecklink_output_t,
+    _ctx: *mut ::std::os::raw::c_void,
+    _cb0: cdecklink_audio_output_callback_render_audio_samples,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_output_get_scheduled_stream_time(
+    _obj: *mut cdecklink_output_t,
+    _desiredTimeScale: DecklinkTimeScale,
+    _streamTime: *mut DecklinkTimeValue,
+    _playbackSpeed: *mut f64,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_output_get_reference_status(
+    _obj: *mut cdecklink_output_t,
+    _referenceStatus: *mut DecklinkReferenceStatus,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_output_get_hardware_reference_clock(
+    _obj: *mut cdecklink_output_t,
+    _desiredTimeScale: DecklinkTimeScale,
+    _hardwareTime: *mut DecklinkTimeValue,
+    _timeInFrame: *mut DecklinkTimeValue,
+    _ticksPerFrame: *mut DecklinkTimeValue,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_output_get_frame_completion_reference_timestamp(
+    _obj: *mut cdecklink_output_t,
+    _theFrame: *mut cdecklink_video_frame_t,
+    _desiredTimeScale: DecklinkTimeScale,
+    _frameCompletionTimestamp: *mut DecklinkTimeValue,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_input_add_ref(
+    obj: *mut cdecklink_input_t,
+) -> ::std::os::raw::c_ulong {
+    object::add_ref(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_input_release(
+    obj: *mut cdecklink_input_t,
+) -> ::std::os::raw::c_ulong {
+    object::release(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_input_set_screen_preview_callback(
+    _obj: *mut cdecklink_input_t,
+    _ctx: *mut ::std::os::raw::c_void,
+    _cb0: cdecklink_screen_preview_callback_draw_frame,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_input_get_hardware_reference_clock(
+    _obj: *mut cdecklink_input_t,
+    _desiredTimeScale: DecklinkTimeScale,
+    _hardwareTime: *mut DecklinkTimeValue,
+    _timeInFrame: *mut DecklinkTimeValue,
+    _ticksPerFrame: *mut DecklinkTimeValue,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_ip_extensions_add_ref(
+    obj: *mut cdecklink_ip_extensions_t,
+) -> ::std::os::raw::c_ulong {
+    object::add_ref(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_ip_extensions_release(
+    obj: *mut cdecklink_ip_extensions_t,
+) -> ::std::os::raw::c_ulong {
+    object::release(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_ip_extensions_get_deck_link_ip_flow_iterator(
+    _obj: *mut cdecklink_ip_extensions_t,
+    _iterator: *mut *mut cdecklink_ip_flow_iterator_t,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_ip_extensions_get_ip_flow_by_id(
+    _obj: *mut cdecklink_ip_extensions_t,
+    _id: DecklinkIPFlowID,
+    _flow: *mut *mut cdecklink_ip_flow_t,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_hdmi_input_edid_add_ref(
+    obj: *mut cdecklink_hdmi_input_edid_t,
+) -> ::std::os::raw::c_ulong {
+    object::add_ref(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_hdmi_input_edid_release(
+    obj: *mut cdecklink_hdmi_input_edid_t,
+) -> ::std::os::raw::c_ulong {
+    object::release(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_hdmi_input_edid_set_int(
+    _obj: *mut cdecklink_hdmi_input_edid_t,
+    _cfgID: DecklinkHDMIInputEDIDID,
+    _value: i64,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_hdmi_input_edid_get_int(
+    _obj: *mut cdecklink_hdmi_input_edid_t,
+    _cfgID: DecklinkHDMIInputEDIDID,
+    _value: *mut i64,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_hdmi_input_edid_write_to_edid(
+    _obj: *mut cdecklink_hdmi_input_edid_t,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_encoder_input_add_ref(
+    obj: *mut cdecklink_encoder_input_t,
+) -> ::std::os::raw::c_ulong {
+    object::add_ref(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_encoder_input_release(
+    obj: *mut cdecklink_encoder_input_t,
+) -> ::std::os::raw::c_ulong {
+    object::release(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_encoder_input_does_support_video_mode(
+    _obj: *mut cdecklink_encoder_input_t,
+    _connection: DecklinkVideoConnection,
+    _requestedMode: DecklinkDisplayMode,
+    _requestedCodec: DecklinkPixelFormat,
+    _requestedCodecProfile: u32,
+    _flags: DecklinkSupportedVideoModeFlags,
+    _supported: *mut bool,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_encoder_input_get_display_mode(
+    _obj: *mut cdecklink_encoder_input_t,
+    _displayMode: DecklinkDisplayMode,
+    _resultDisplayMode: *mut *mut cdecklink_display_mode_t,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_encoder_input_get_display_mode_iterator(
+    _obj: *mut cdecklink_encoder_input_t,
+    _iterator: *mut *mut cdecklink_display_mode_iterator_t,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_encoder_input_enable_video_input(
+    _obj: *mut cdecklink_encoder_input_t,
+    _displayMode: DecklinkDisplayMode,
+    _pixelFormat: DecklinkPixelFormat,
+    _flags: DecklinkVideoInputFlags,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_encoder_input_disable_video_input(
+    _obj: *mut cdecklink_encoder_input_t,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_encoder_input_get_available_packets_count(
+    _obj: *mut cdecklink_encoder_input_t,
+    _availablePacketsCount: *mut u32,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_encoder_input_enable_audio_input(
+    _obj: *mut cdecklink_encoder_input_t,
+    _audioFormat: DecklinkAudioFormat,
+    _sampleRate: DecklinkAudioSampleRate,
+    _sampleType: DecklinkAudioSampleType,
+    _channelCount: u32,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_encoder_input_disable_audio_input(
+    _obj: *mut cdecklink_encoder_input_t,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_encoder_input_get_available_audio_sample_frame_count(
+    _obj: *mut cdecklink_encoder_input_t,
+    _availableSampleFrameCount: *mut u32,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_encoder_input_start_streams(
+    _obj: *mut cdecklink_encoder_input_t,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_encoder_input_stop_streams(
+    _obj: *mut cdecklink_encoder_input_t,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_encoder_input_pause_streams(
+    _obj: *mut cdecklink_encoder_input_t,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_encoder_input_flush_streams(
+    _obj: *mut cdecklink_encoder_input_t,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_encoder_input_set_callback(
+    _obj: *mut cdecklink_encoder_input_t,
+    _ctx: *mut ::std::os::raw::c_void,
+    _cb0: cdecklink_encoder_input_callback_video_input_signal_changed,
+    _cb1: cdecklink_encoder_input_callback_video_packet_arrived,
+    _cb2: cdecklink_encoder_input_callback_audio_packet_arrived,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_encoder_input_get_hardware_reference_clock(
+    _obj: *mut cdecklink_encoder_input_t,
+    _desiredTimeScale: DecklinkTimeScale,
+    _hardwareTime: *mut DecklinkTimeValue,
+    _timeInFrame: *mut DecklinkTimeValue,
+    _ticksPerFrame: *mut DecklinkTimeValue,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_video_buffer_add_ref(
+    obj: *mut cdecklink_video_buffer_t,
+) -> ::std::os::raw::c_ulong {
+    object::add_ref(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_video_buffer_release(
+    obj: *mut cdecklink_video_buffer_t,
+) -> ::std::os::raw::c_ulong {
+    object::release(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_video_frame_add_ref(
+    obj: *mut cdecklink_video_frame_t,
+) -> ::std::os::raw::c_ulong {
+    object::add_ref(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_video_frame_release(
+    obj: *mut cdecklink_video_frame_t,
+) -> ::std::os::raw::c_ulong {
+    object::release(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_video_frame_get_ancillary_data(
+    _obj: *mut cdecklink_video_frame_t,
+    _ancillary: *mut *mut cdecklink_video_frame_ancillary_t,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_mutable_video_frame_to_video_frame(
+    _obj: *mut cdecklink_mutable_video_frame_t,
+) -> *mut cdecklink_video_frame_t {
+    null_mut()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_mutable_video_frame_add_ref(
+    obj: *mut cdecklink_mutable_video_frame_t,
+) -> ::std::os::raw::c_ulong {
+    object::add_ref(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_mutable_video_frame_release(
+    obj: *mut cdecklink_mutable_video_frame_t,
+) -> ::std::os::raw::c_ulong {
+    object::release(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_mutable_video_frame_set_flags(
+    _obj: *mut cdecklink_mutable_video_frame_t,
+    _newFlags: DecklinkFrameFlags,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_mutable_video_frame_set_timecode(
+    _obj: *mut cdecklink_mutable_video_frame_t,
+    _format: DecklinkTimecodeFormat,
+    _timecode: *mut cdecklink_timecode_t,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_mutable_video_frame_set_timecode_from_components(
+    _obj: *mut cdecklink_mutable_video_frame_t,
+    _format: DecklinkTimecodeFormat,
+    _hours: u8,
+    _minutes: u8,
+    _seconds: u8,
+    _frames: u8,
+    _flags: DecklinkTimecodeFlags,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_mutable_video_frame_set_ancillary_data(
+    _obj: *mut cdecklink_mutable_video_frame_t,
+    _ancillary: *mut cdecklink_video_frame_ancillary_t,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_mutable_video_frame_set_timecode_user_bits(
+    _obj: *mut cdecklink_mutable_video_frame_t,
+    _format: DecklinkTimecodeFormat,
+    _userBits: DecklinkTimecodeUserBits,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_video_frame3_d_extensions_add_ref(
+    obj: *mut cdecklink_video_frame3_d_extensions_t,
+) -> ::std::os::raw::c_ulong {
+    object::add_ref(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_video_frame3_d_extensions_release(
+    obj: *mut cdecklink_video_frame3_d_extensions_t,
+) -> ::std::os::raw::c_ulong {
+    object::release(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_video_frame3_d_extensions_get3_d_packing_format(
+    _obj: *mut cdecklink_video_frame3_d_extensions_t,
+) -> DecklinkVideo3DPackingFormat {
+    0
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_video_frame3_d_extensions_get_frame_for_right_eye(
+    _obj: *mut cdecklink_video_frame3_d_extensions_t,
+    _rightEyeFrame: *mut *mut cdecklink_video_frame_t,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_video_frame_metadata_extensions_add_ref(
+    obj: *mut cdecklink_video_frame_metadata_extensions_t,
+) -> ::std::os::raw::c_ulong {
+    object::add_ref(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_video_frame_metadata_extensions_release(
+    obj: *mut cdecklink_video_frame_metadata_extensions_t,
+) -> ::std::os::raw::c_ulong {
+    object::release(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_video_frame_metadata_extensions_get_int(
+    _obj: *mut cdecklink_video_frame_metadata_extensions_t,
+    _metadataID: DecklinkFrameMetadataID,
+    _value: *mut i64,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_video_frame_metadata_extensions_get_float(
+    _obj: *mut cdecklink_video_frame_metadata_extensions_t,
+    _metadataID: DecklinkFrameMetadataID,
+    _value: *mut f64,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_video_frame_metadata_extensions_get_flag(
+    _obj: *mut cdecklink_video_frame_metadata_extensions_t,
+    _metadataID: DecklinkFrameMetadataID,
+    _value: *mut bool,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_video_frame_metadata_extensions_get_string(
+    _obj: *mut cdecklink_video_frame_metadata_extensions_t,
+    _metadataID: DecklinkFrameMetadataID,
+    _value: *mut *const ::std::os::raw::c_char,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_video_frame_metadata_extensions_get_bytes(
+    _obj: *mut cdecklink_video_frame_metadata_extensions_t,
+    _metadataID: DecklinkFrameMetadataID,
+    _buffer: *mut ::std::os::raw::c_void,
+    _bufferSize: *mut u32,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_video_frame_mutable_metadata_extensions_to_video_frame_metadata_extensions(
+    _obj: *mut cdecklink_video_frame_mutable_metadata_extensions_t,
+) -> *mut cdecklink_video_frame_metadata_extensions_t {
+    null_mut()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_video_frame_mutable_metadata_extensions_add_ref(
+    obj: *mut cdecklink_video_frame_mutable_metadata_extensions_t,
+) -> ::std::os::raw::c_ulong {
+    object::add_ref(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_video_frame_mutable_metadata_extensions_release(
+    obj: *mut cdecklink_video_frame_mutable_metadata_extensions_t,
+) -> ::std::os::raw::c_ulong {
+    object::release(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_video_frame_mutable_metadata_extensions_set_int(
+    _obj: *mut cdecklink_video_frame_mutable_metadata_extensions_t,
+    _metadataID: DecklinkFrameMetadataID,
+    _value: i64,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_video_frame_mutable_metadata_extensions_set_float(
+    _obj: *mut cdecklink_video_frame_mutable_metadata_extensions_t,
+    _metadataID: DecklinkFrameMetadataID,
+    _value: f64,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_video_frame_mutable_metadata_extensions_set_flag(
+    _obj: *mut cdecklink_video_frame_mutable_metadata_extensions_t,
+    _metadataID: DecklinkFrameMetadataID,
+    _value: bool,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_video_frame_mutable_metadata_extensions_set_string(
+    _obj: *mut cdecklink_video_frame_mutable_metadata_extensions_t,
+    _metadataID: DecklinkFrameMetadataID,
+    _value: *const ::std::os::raw::c_char,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_video_frame_mutable_metadata_extensions_set_bytes(
+    _obj: *mut cdecklink_video_frame_mutable_metadata_extensions_t,
+    _metadataID: DecklinkFrameMetadataID,
+    _buffer: *mut ::std::os::raw::c_void,
+    _bufferSize: u32,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_video_input_frame_add_ref(
+    obj: *mut cdecklink_video_input_frame_t,
+) -> ::std::os::raw::c_ulong {
+    object::add_ref(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_video_input_frame_release(
+    obj: *mut cdecklink_video_input_frame_t,
+) -> ::std::os::raw::c_ulong {
+    object::release(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_ancillary_packet_add_ref(
+    obj: *mut cdecklink_ancillary_packet_t,
+) -> ::std::os::raw::c_ulong {
+    object::add_ref(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_ancillary_packet_release(
+    obj: *mut cdecklink_ancillary_packet_t,
+) -> ::std::os::raw::c_ulong {
+    object::release(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_ancillary_packet_get_data_stream_index(
+    _obj: *mut cdecklink_ancillary_packet_t,
+) -> u8 {
+    0
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_ancillary_packet_get_data_space(
+    _obj: *mut cdecklink_ancillary_packet_t,
+) -> DecklinkAncillaryDataSpace {
+    0
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_ancillary_packet_iterator_add_ref(
+    obj: *mut cdecklink_ancillary_packet_iterator_t,
+) -> ::std::os::raw::c_ulong {
+    object::add_ref(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_ancillary_packet_iterator_release(
+    obj: *mut cdecklink_ancillary_packet_iterator_t,
+) -> ::std::os::raw::c_ulong {
+    object::release(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_ancillary_packet_iterator_next(
+    _obj: *mut cdecklink_ancillary_packet_iterator_t,
+    _packet: *mut *mut cdecklink_ancillary_packet_t,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_video_frame_ancillary_packets_add_ref(
+    obj: *mut cdecklink_video_frame_ancillary_packets_t,
+) -> ::std::os::raw::c_ulong {
+    object::add_ref(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_video_frame_ancillary_packets_release(
+    obj: *mut cdecklink_video_frame_ancillary_packets_t,
+) -> ::std::os::raw::c_ulong {
+    object::release(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_video_frame_ancillary_packets_get_packet_iterator(
+    _obj: *mut cdecklink_video_frame_ancillary_packets_t,
+    _iterator: *mut *mut cdecklink_ancillary_packet_iterator_t,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_video_frame_ancillary_packets_attach_packet(
+    _obj: *mut cdecklink_video_frame_ancillary_packets_t,
+    _packet: *mut cdecklink_ancillary_packet_t,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_video_frame_ancillary_packets_detach_packet(
+    _obj: *mut cdecklink_video_frame_ancillary_packets_t,
+    _packet: *mut cdecklink_ancillary_packet_t,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_video_frame_ancillary_packets_detach_all_packets(
+    _obj: *mut cdecklink_video_frame_ancillary_packets_t,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_video_frame_ancillary_add_ref(
+    obj: *mut cdecklink_video_frame_ancillary_t,
+) -> ::std::os::raw::c_ulong {
+    object::add_ref(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_video_frame_ancillary_release(
+    obj: *mut cdecklink_video_frame_ancillary_t,
+) -> ::std::os::raw::c_ulong {
+    object::release(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_video_frame_ancillary_get_buffer_for_vertical_blanking_line(
+    _obj: *mut cdecklink_video_frame_ancillary_t,
+    _lineNumber: u32,
+    _buffer: *mut *mut ::std::os::raw::c_void,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_video_frame_ancillary_get_pixel_format(
+    _obj: *mut cdecklink_video_frame_ancillary_t,
+) -> DecklinkPixelFormat {
+    0
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_video_frame_ancillary_get_display_mode(
+    _obj: *mut cdecklink_video_frame_ancillary_t,
+) -> DecklinkDisplayMode {
+    0
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_encoder_packet_add_ref(
+    obj: *mut cdecklink_encoder_packet_t,
+) -> ::std::os::raw::c_ulong {
+    object::add_ref(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_encoder_packet_release(
+    obj: *mut cdecklink_encoder_packet_t,
+) -> ::std::os::raw::c_ulong {
+    object::release(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_encoder_packet_get_bytes(
+    _obj: *mut cdecklink_encoder_packet_t,
+    _buffer: *mut *mut ::std::os::raw::c_void,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_encoder_packet_get_size(
+    _obj: *mut cdecklink_encoder_packet_t,
+) -> ::std::os::raw::c_long {
+    0
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_encoder_packet_get_stream_time(
+    _obj: *mut cdecklink_encoder_packet_t,
+    _frameTime: *mut DecklinkTimeValue,
+    _timeScale: DecklinkTimeScale,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_encoder_packet_get_packet_type(
+    _obj: *mut cdecklink_encoder_packet_t,
+) -> DecklinkPacketType {
+    0
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_encoder_video_packet_to_encoder_packet(
+    _obj: *mut cdecklink_encoder_video_packet_t,
+) -> *mut cdecklink_encoder_packet_t {
+    null_mut()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_encoder_video_packet_add_ref(
+    obj: *mut cdecklink_encoder_video_packet_t,
+) -> ::std::os::raw::c_ulong {
+    object::add_ref(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_encoder_video_packet_release(
+    obj: *mut cdecklink_encoder_video_packet_t,
+) -> ::std::os::raw::c_ulong {
+    object::release(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_encoder_video_packet_get_pixel_format(
+    _obj: *mut cdecklink_encoder_video_packet_t,
+) -> DecklinkPixelFormat {
+    0
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_encoder_video_packet_get_hardware_reference_timestamp(
+    _obj: *mut cdecklink_encoder_video_packet_t,
+    _timeScale: DecklinkTimeScale,
+    _frameTime: *mut DecklinkTimeValue,
+    _frameDuration: *mut DecklinkTimeValue,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_encoder_video_packet_get_timecode(
+    _obj: *mut cdecklink_encoder_video_packet_t,
+    _format: DecklinkTimecodeFormat,
+    _timecode: *mut *mut cdecklink_timecode_t,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_encoder_audio_packet_to_encoder_packet(
+    _obj: *mut cdecklink_encoder_audio_packet_t,
+) -> *mut cdecklink_encoder_packet_t {
+    null_mut()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_encoder_audio_packet_add_ref(
+    obj: *mut cdecklink_encoder_audio_packet_t,
+) -> ::std::os::raw::c_ulong {
+    object::add_ref(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_encoder_audio_packet_release(
+    obj: *mut cdecklink_encoder_audio_packet_t,
+) -> ::std::os::raw::c_ulong {
+    object::release(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_encoder_audio_packet_get_audio_format(
+    _obj: *mut cdecklink_encoder_audio_packet_t,
+) -> DecklinkAudioFormat {
+    0
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_h265nal_packet_to_encoder_video_packet(
+    _obj: *mut cdecklink_h265nal_packet_t,
+) -> *mut cdecklink_encoder_video_packet_t {
+    null_mut()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_h265nal_packet_add_ref(
+    obj: *mut cdecklink_h265nal_packet_t,
+) -> ::std::os::raw::c_ulong {
+    object::add_ref(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_h265nal_packet_release(
+    obj: *mut cdecklink_h265nal_packet_t,
+) -> ::std::os::raw::c_ulong {
+    object::release(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_h265nal_packet_get_unit_type(
+    _obj: *mut cdecklink_h265nal_packet_t,
+    _unitType: *mut u8,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_h265nal_packet_get_bytes_no_prefix(
+    _obj: *mut cdecklink_h265nal_packet_t,
+    _buffer: *mut *mut ::std::os::raw::c_void,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_h265nal_packet_get_size_no_prefix(
+    _obj: *mut cdecklink_h265nal_packet_t,
+) -> ::std::os::raw::c_long {
+    0
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_audio_input_packet_add_ref(
+    obj: *mut cdecklink_audio_input_packet_t,
+) -> ::std::os::raw::c_ulong {
+    object::add_ref(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_audio_input_packet_release(
+    obj: *mut cdecklink_audio_input_packet_t,
+) -> ::std::os::raw::c_ulong {
+    object::release(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_gl_screen_preview_helper_add_ref(
+    obj: *mut cdecklink_gl_screen_preview_helper_t,
+) -> ::std::os::raw::c_ulong {
+    object::add_ref(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_gl_screen_preview_helper_release(
+    obj: *mut cdecklink_gl_screen_preview_helper_t,
+) -> ::std::os::raw::c_ulong {
+    object::release(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_gl_screen_preview_helper_initialize_gl(
+    _obj: *mut cdecklink_gl_screen_preview_helper_t,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_gl_screen_preview_helper_paint_gl(
+    _obj: *mut cdecklink_gl_screen_preview_helper_t,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_gl_screen_preview_helper_set_frame(
+    _obj: *mut cdecklink_gl_screen_preview_helper_t,
+    _theFrame: *mut cdecklink_video_frame_t,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_gl_screen_preview_helper_set3_d_preview_format(
+    _obj: *mut cdecklink_gl_screen_preview_helper_t,
+    _previewFormat: Decklink3DPreviewFormat,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_notification_add_ref(
+    obj: *mut cdecklink_notification_t,
+) -> ::std::os::raw::c_ulong {
+    object::add_ref(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_notification_release(
+    obj: *mut cdecklink_notification_t,
+) -> ::std::os::raw::c_ulong {
+    object::release(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_profile_attributes_add_ref(
+    obj: *mut cdecklink_profile_attributes_t,
+) -> ::std::os::raw::c_ulong {
+    object::add_ref(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_profile_attributes_release(
+    obj: *mut cdecklink_profile_attributes_t,
+) -> ::std::os::raw::c_ulong {
+    object::release(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_profile_iterator_add_ref(
+    obj: *mut cdecklink_profile_iterator_t,
+) -> ::std::os::raw::c_ulong {
+    object::add_ref(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_profile_iterator_release(
+    obj: *mut cdecklink_profile_iterator_t,
+) -> ::std::os::raw::c_ulong {
+    object::release(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_profile_iterator_next(
+    _obj: *mut cdecklink_profile_iterator_t,
+    _profile: *mut *mut cdecklink_profile_t,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_profile_add_ref(
+    obj: *mut cdecklink_profile_t,
+) -> ::std::os::raw::c_ulong {
+    object::add_ref(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_profile_release(
+    obj: *mut cdecklink_profile_t,
+) -> ::std::os::raw::c_ulong {
+    object::release(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_profile_get_device(
+    _obj: *mut cdecklink_profile_t,
+    _device: *mut *mut cdecklink_device_t,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_profile_is_active(
+    _obj: *mut cdecklink_profile_t,
+    _isActive: *mut bool,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_profile_set_active(_obj: *mut cdecklink_profile_t) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_profile_get_peers(
+    _obj: *mut cdecklink_profile_t,
+    _profileIterator: *mut *mut cdecklink_profile_iterator_t,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_profile_manager_add_ref(
+    obj: *mut cdecklink_profile_manager_t,
+) -> ::std::os::raw::c_ulong {
+    object::add_ref(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_profile_manager_release(
+    obj: *mut cdecklink_profile_manager_t,
+) -> ::std::os::raw::c_ulong {
+    object::release(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_profile_manager_get_profiles(
+    _obj: *mut cdecklink_profile_manager_t,
+    _profileIterator: *mut *mut cdecklink_profile_iterator_t,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_profile_manager_get_profile(
+    _obj: *mut cdecklink_profile_manager_t,
+    _profileID: DecklinkProfileID,
+    _profile: *mut *mut cdecklink_profile_t,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_profile_manager_set_callback(
+    _obj: *mut cdecklink_profile_manager_t,
+    _ctx: *mut ::std::os::raw::c_void,
+    _cb0: cdecklink_profile_callback_profile_changing,
+    _cb1: cdecklink_profile_callback_profile_activated,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_status_add_ref(
+    obj: *mut cdecklink_status_t,
+) -> ::std::os::raw::c_ulong {
+    object::add_ref(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_status_release(
+    obj: *mut cdecklink_status_t,
+) -> ::std::os::raw::c_ulong {
+    object::release(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_keyer_add_ref(
+    obj: *mut cdecklink_keyer_t,
+) -> ::std::os::raw::c_ulong {
+    object::add_ref(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_keyer_release(
+    obj: *mut cdecklink_keyer_t,
+) -> ::std::os::raw::c_ulong {
+    object::release(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_keyer_enable(
+    _obj: *mut cdecklink_keyer_t,
+    _isExternal: bool,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_keyer_set_level(
+    _obj: *mut cdecklink_keyer_t,
+    _level: u8,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_keyer_ramp_up(
+    _obj: *mut cdecklink_keyer_t,
+    _numberOfFrames: u32,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_keyer_ramp_down(
+    _obj: *mut cdecklink_keyer_t,
+    _numberOfFrames: u32,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_keyer_disable(_obj: *mut cdecklink_keyer_t) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_video_conversion_add_ref(
+    obj: *mut cdecklink_video_conversion_t,
+) -> ::std::os::raw::c_ulong {
+    object::add_ref(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_video_conversion_release(
+    obj: *mut cdecklink_video_conversion_t,
+) -> ::std::os::raw::c_ulong {
+    object::release(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_video_conversion_convert_frame(
+    _obj: *mut cdecklink_video_conversion_t,
+    _srcFrame: *mut cdecklink_video_frame_t,
+    _dstFrame: *mut cdecklink_video_frame_t,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_video_conversion_convert_new_frame(
+    _obj: *mut cdecklink_video_conversion_t,
+    _srcFrame: *mut cdecklink_video_frame_t,
+    _dstPixelFormat: DecklinkPixelFormat,
+    _dstColorspace: DecklinkColorspace,
+    _dstBuffer: *mut cdecklink_video_buffer_t,
+    _dstFrame: *mut *mut cdecklink_video_frame_t,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_discovery_add_ref(
+    obj: *mut cdecklink_discovery_t,
+) -> ::std::os::raw::c_ulong {
+    object::add_ref(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_discovery_release(
+    obj: *mut cdecklink_discovery_t,
+) -> ::std::os::raw::c_ulong {
+    object::release(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_discovery_install_device_notifications(
+    _obj: *mut cdecklink_discovery_t,
+    _ctx: *mut ::std::os::raw::c_void,
+    _cb0: cdecklink_device_notification_callback_deck_link_device_arrived,
+    _cb1: cdecklink_device_notification_callback_deck_link_device_removed,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_discovery_uninstall_device_notifications(
+    _obj: *mut cdecklink_discovery_t,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_create_decklink_discovery_instance() -> *mut cdecklink_discovery_t
+{
+    null_mut()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_create_open_gl_screen_preview_helper(
+) -> *mut cdecklink_gl_screen_preview_helper_t {
+    null_mut()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_create_open_gl3_screen_preview_helper(
+) -> *mut cdecklink_gl_screen_preview_helper_t {
+    null_mut()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_create_video_conversion_instance(
+) -> *mut cdecklink_video_conversion_t {
+    null_mut()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_create_video_frame_ancillary_packets_instance(
+) -> *mut cdecklink_video_frame_ancillary_packets_t {
+    null_mut()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_device_query_hdmi_input_edid(
+    _obj: *mut cdecklink_device_t,
+    _dst: *mut *mut cdecklink_hdmi_input_edid_t,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_device_query_encoder_input(
+    _obj: *mut cdecklink_device_t,
+    _dst: *mut *mut cdecklink_encoder_input_t,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_video_frame_query_video_frame_ancillary(
+    _obj: *mut cdecklink_video_frame_t,
+    _dst: *mut *mut cdecklink_video_frame_ancillary_t,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_encoder_video_packet_query_h265nal_packet(
+    _obj: *mut cdecklink_encoder_video_packet_t,
+    _dst: *mut *mut cdecklink_h265nal_packet_t,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_device_query_profile_manager(
+    _obj: *mut cdecklink_device_t,
+    _dst: *mut *mut cdecklink_profile_manager_t,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_custom_video_frame_add_ref(
+    obj: *mut cdecklink_custom_video_frame_t,
+) -> ::std::os::raw::c_ulong {
+    object::add_ref(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_custom_video_frame_release(
+    obj: *mut cdecklink_custom_video_frame_t,
+) -> ::std::os::raw::c_ulong {
+    object::release(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_device_query_notification(
+    _obj: *mut cdecklink_device_t,
+    _dst: *mut *mut cdecklink_notification_t,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_notification_subscribe(
+    _obj: *mut cdecklink_notification_t,
+    _topic: DecklinkNotifications,
+    _ctx: *mut ::std::os::raw::c_void,
+    _cb0: cdecklink_notification_callback_notify,
+    _handle: *mut *mut cdecklink_notification_callback_notify_handle,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_notification_unsubscribe(
+    _obj: *mut cdecklink_notification_t,
+    _topic: DecklinkNotifications,
+    _handle: *mut cdecklink_notification_callback_notify_handle,
+) -> HRESULT {
+    SdkError::NOTIMPL.code()
+}
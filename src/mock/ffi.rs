@@ -0,0 +1,1566 @@
+//! The functions of the C bindings the mock implements, over the state of the installed
+//! devices.
+
+#![allow(non_snake_case)]
+
+use crate::device::input::DecklinkVideoInputFlags;
+use crate::device::output::DecklinkVideoOutputFlags;
+use crate::display_mode::DecklinkDisplayModeId;
+use crate::frame::DecklinkPixelFormat;
+use crate::mock::object::{
+    self, Allocator, AncillaryPacket, AudioPacket, Buffer, FrameData, FrameState, Kind,
+};
+use crate::mock::{
+    allow_resource, api_version, installed_devices, lock, providers_fail, row_bytes, DeviceState,
+    InputCallback, MockFrame, ModeInfo, OutputCallback, ScheduledFrame, Values,
+};
+use crate::sdk::{self, HRESULT};
+use crate::SdkError;
+use aligned_vec::AVec;
+use num_traits::FromPrimitive;
+use std::collections::VecDeque;
+use std::ffi::c_void;
+use std::os::raw::{c_char, c_long};
+use std::ptr::null_mut;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+const S_OK: HRESULT = 0;
+const S_FALSE: HRESULT = 1;
+
+/// Whether creating `resource` is denied, as it is after `MockBackend::deny_new_resources`.
+fn denied(resource: &str) -> bool {
+    !allow_resource(resource)
+}
+
+/// Write `value` to an out parameter, if the caller gave one.
+unsafe fn put<T>(out: *mut T, value: T) {
+    if !out.is_null() {
+        *out = value;
+    }
+}
+
+unsafe fn device<'a>(obj: *mut c_void) -> &'a Arc<DeviceState> {
+    match &object::get(obj).kind {
+        Kind::Device(device)
+        | Kind::Input(device)
+        | Kind::Output(device)
+        | Kind::Attributes(device)
+        | Kind::Status(device) => device,
+        _ => panic!("the mock backend was passed an object that is not of a device"),
+    }
+}
+
+unsafe fn input<'a>(obj: *mut c_void) -> MutexGuard<'a, super::InputState> {
+    device(obj).input()
+}
+
+unsafe fn output<'a>(obj: *mut c_void) -> MutexGuard<'a, super::OutputState> {
+    device(obj).output()
+}
+
+unsafe fn frame<'a>(obj: *mut c_void) -> MutexGuard<'a, FrameState> {
+    match &object::get(obj).kind {
+        Kind::Frame(frame) => lock(frame),
+        _ => panic!("the mock backend was passed an object that is not a frame"),
+    }
+}
+
+unsafe fn audio_packet<'a>(obj: *mut c_void) -> &'a AudioPacket {
+    match &object::get(obj).kind {
+        Kind::AudioPacket(packet) => packet,
+        _ => panic!("the mock backend was passed an object that is not an audio packet"),
+    }
+}
+
+unsafe fn timecode<'a>(obj: *mut c_void) -> &'a crate::timecode::DecklinkTimecode {
+    match &object::get(obj).kind {
+        Kind::Timecode(timecode) => timecode,
+        _ => panic!("the mock backend was passed an object that is not a timecode"),
+    }
+}
+
+unsafe fn ancillary_packet<'a>(obj: *mut c_void) -> &'a AncillaryPacket {
+    match &object::get(obj).kind {
+        Kind::AncillaryPacket(packet) => packet,
+        _ => panic!("the mock backend was passed an object that is not an ancillary packet"),
+    }
+}
+
+unsafe fn mode<'a>(obj: *mut c_void) -> &'a ModeInfo {
+    match &object::get(obj).kind {
+        Kind::DisplayMode(mode) => mode,
+        _ => panic!("the mock backend was passed an object that is not a display mode"),
+    }
+}
+
+unsafe fn values<'a>(obj: *mut c_void) -> MutexGuard<'a, Values> {
+    match &object::get(obj).kind {
+        Kind::Status(device) => lock(&device.status),
+        Kind::Attributes(device) => lock(&device.attributes),
+        _ => panic!("the mock backend was passed an object that has no values"),
+    }
+}
+
+fn lookup<T: Clone>(values: &std::collections::HashMap<u32, T>, id: u32, out: *mut T) -> HRESULT {
+    match values.get(&id) {
+        Some(value) => {
+            unsafe { put(out, value.clone()) };
+            S_OK
+        }
+        None => SdkError::NOTIMPL.code(),
+    }
+}
+
+/// The supported mode for a request, as the driver checks it.
+fn supports(
+    (modes, pixel_formats): (&[DecklinkDisplayModeId], &[DecklinkPixelFormat]),
+    mode: sdk::DecklinkDisplayMode,
+    pixel_format: sdk::DecklinkPixelFormat,
+) -> Option<(DecklinkDisplayModeId, DecklinkPixelFormat)> {
+    let mode = DecklinkDisplayModeId::from_u32(mode)?;
+    let pixel_format = DecklinkPixelFormat::from_u32(pixel_format)?;
+    if modes.contains(&mode) && pixel_formats.contains(&pixel_format) {
+        Some((mode, pixel_format))
+    } else {
+        None
+    }
+}
+
+/// Whether the input supports `mode` in `pixel_format`, in every mode or in that mode only.
+fn input_supports(
+    state: &super::InputState,
+    mode: sdk::DecklinkDisplayMode,
+    pixel_format: sdk::DecklinkPixelFormat,
+) -> Option<(DecklinkDisplayModeId, DecklinkPixelFormat)> {
+    supports((&state.modes, &state.pixel_formats), mode, pixel_format).or_else(|| {
+        let mode = DecklinkDisplayModeId::from_u32(mode)?;
+        let pixel_format = DecklinkPixelFormat::from_u32(pixel_format)?;
+        let in_mode = state.mode_pixel_formats.contains(&(mode, pixel_format));
+        (state.modes.contains(&mode) && in_mode).then_some((mode, pixel_format))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_free_string(str_: *const c_char) {
+    object::free_string(str_)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_create_decklink_iterator_instance(
+) -> *mut sdk::cdecklink_iterator_t {
+    if denied("device list") {
+        return null_mut();
+    }
+    object::create(Kind::Iterator(Mutex::new(VecDeque::from(
+        installed_devices(),
+    ))))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_iterator_next(
+    obj: *mut sdk::cdecklink_iterator_t,
+    deckLinkInstance: *mut *mut sdk::cdecklink_device_t,
+) -> HRESULT {
+    let next = match &object::get(obj).kind {
+        Kind::Iterator(devices) => lock(devices).pop_front(),
+        _ => panic!("the mock backend was passed an object that is not an iterator"),
+    };
+    match next {
+        Some(device) => {
+            put(deckLinkInstance, object::create(Kind::Device(device)));
+            S_OK
+        }
+        None => S_FALSE,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_create_decklink_api_information_instance(
+) -> *mut sdk::cdecklink_api_information_t {
+    if denied("api information") {
+        return null_mut();
+    }
+    match api_version() {
+        Some(_) => object::create(Kind::ApiInformation),
+        None => null_mut(),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_api_version(
+    _it: *mut sdk::cdecklink_iterator_t,
+    str_: *mut *const c_char,
+) -> HRESULT {
+    match api_version() {
+        Some(version) => {
+            put(str_, object::string(&version.to_string()));
+            S_OK
+        }
+        None => SdkError::FAIL.code(),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_api_information_get_int(
+    _obj: *mut sdk::cdecklink_api_information_t,
+    cfgID: sdk::DecklinkAPIInformationID,
+    value: *mut i64,
+) -> HRESULT {
+    match api_version() {
+        Some(version) if cfgID == sdk::_DecklinkAPIInformationID_decklinkAPIVersion => {
+            let packed = ((version.major as u32) << 24)
+                | ((version.minor as u32) << 16)
+                | ((version.point as u32) << 8);
+            put(value, packed as i64);
+            S_OK
+        }
+        _ => SdkError::NOTIMPL.code(),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_device_get_model_name(
+    obj: *mut sdk::cdecklink_device_t,
+    modelName: *mut *const c_char,
+) -> HRESULT {
+    put(modelName, object::string(&device(obj).model_name));
+    S_OK
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_device_get_display_name(
+    obj: *mut sdk::cdecklink_device_t,
+    displayName: *mut *const c_char,
+) -> HRESULT {
+    put(displayName, object::bytes_string(&device(obj).display_name));
+    S_OK
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_device_query_input(
+    obj: *mut sdk::cdecklink_device_t,
+    dst: *mut *mut sdk::cdecklink_input_t,
+) -> HRESULT {
+    let device = device(obj);
+    if device.input.is_none() {
+        return SdkError::NOINTERFACE.code();
+    }
+    if denied("input") {
+        return SdkError::ACCESSDENIED.code();
+    }
+    put(dst, object::create(Kind::Input(device.clone())));
+    S_OK
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_device_query_output(
+    obj: *mut sdk::cdecklink_device_t,
+    dst: *mut *mut sdk::cdecklink_output_t,
+) -> HRESULT {
+    let device = device(obj);
+    if device.output.is_none() {
+        return SdkError::NOINTERFACE.code();
+    }
+    if denied("output") {
+        return SdkError::ACCESSDENIED.code();
+    }
+    put(dst, object::create(Kind::Output(device.clone())));
+    S_OK
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_device_query_status(
+    obj: *mut sdk::cdecklink_device_t,
+    dst: *mut *mut sdk::cdecklink_status_t,
+) -> HRESULT {
+    let device = device(obj);
+    if !device.attached.load(Ordering::SeqCst) {
+        return SdkError::FAIL.code();
+    }
+    if denied("status") {
+        return SdkError::ACCESSDENIED.code();
+    }
+    put(dst, object::create(Kind::Status(device.clone())));
+    S_OK
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_device_query_profile_attributes(
+    obj: *mut sdk::cdecklink_device_t,
+    dst: *mut *mut sdk::cdecklink_profile_attributes_t,
+) -> HRESULT {
+    let device = device(obj);
+    if !device.attached.load(Ordering::SeqCst) {
+        return SdkError::FAIL.code();
+    }
+    if denied("attributes") {
+        return SdkError::ACCESSDENIED.code();
+    }
+    put(dst, object::create(Kind::Attributes(device.clone())));
+    S_OK
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_status_get_flag(
+    obj: *mut sdk::cdecklink_status_t,
+    statusID: sdk::DecklinkStatusID,
+    value: *mut bool,
+) -> HRESULT {
+    lookup(&values(obj).flags, statusID, value)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_status_get_int(
+    obj: *mut sdk::cdecklink_status_t,
+    statusID: sdk::DecklinkStatusID,
+    value: *mut i64,
+) -> HRESULT {
+    lookup(&values(obj).ints, statusID, value)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_status_get_float(
+    obj: *mut sdk::cdecklink_status_t,
+    statusID: sdk::DecklinkStatusID,
+    value: *mut f64,
+) -> HRESULT {
+    lookup(&values(obj).floats, statusID, value)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_status_get_string(
+    obj: *mut sdk::cdecklink_status_t,
+    statusID: sdk::DecklinkStatusID,
+    value: *mut *const c_char,
+) -> HRESULT {
+    match values(obj).strings.get(&statusID) {
+        Some(s) => {
+            put(value, object::string(s));
+            S_OK
+        }
+        None => SdkError::NOTIMPL.code(),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_status_get_bytes(
+    obj: *mut sdk::cdecklink_status_t,
+    statusID: sdk::DecklinkStatusID,
+    buffer: *mut c_void,
+    bufferSize: *mut u32,
+) -> HRESULT {
+    let values = values(obj);
+    let bytes = match values.bytes.get(&statusID) {
+        Some(bytes) => bytes,
+        None => return SdkError::NOTIMPL.code(),
+    };
+    if bufferSize.is_null() {
+        return SdkError::POINTER.code();
+    }
+    if !buffer.is_null() {
+        let len = bytes.len().min(*bufferSize as usize);
+        std::ptr::copy_nonoverlapping(bytes.as_ptr(), buffer as *mut u8, len);
+    }
+    *bufferSize = bytes.len() as u32;
+    S_OK
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_profile_attributes_get_flag(
+    obj: *mut sdk::cdecklink_profile_attributes_t,
+    cfgID: sdk::DecklinkAttributeID,
+    value: *mut bool,
+) -> HRESULT {
+    lookup(&values(obj).flags, cfgID, value)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_profile_attributes_get_int(
+    obj: *mut sdk::cdecklink_profile_attributes_t,
+    cfgID: sdk::DecklinkAttributeID,
+    value: *mut i64,
+) -> HRESULT {
+    lookup(&values(obj).ints, cfgID, value)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_profile_attributes_get_float(
+    obj: *mut sdk::cdecklink_profile_attributes_t,
+    cfgID: sdk::DecklinkAttributeID,
+    value: *mut f64,
+) -> HRESULT {
+    lookup(&values(obj).floats, cfgID, value)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_profile_attributes_get_string(
+    obj: *mut sdk::cdecklink_profile_attributes_t,
+    cfgID: sdk::DecklinkAttributeID,
+    value: *mut *const c_char,
+) -> HRESULT {
+    match values(obj).strings.get(&cfgID) {
+        Some(s) => {
+            put(value, object::string(s));
+            S_OK
+        }
+        None => SdkError::NOTIMPL.code(),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_input_does_support_video_mode(
+    obj: *mut sdk::cdecklink_input_t,
+    _connection: sdk::DecklinkVideoConnection,
+    requestedMode: sdk::DecklinkDisplayMode,
+    requestedPixelFormat: sdk::DecklinkPixelFormat,
+    _conversionMode: sdk::DecklinkVideoInputConversionMode,
+    _flags: sdk::DecklinkSupportedVideoModeFlags,
+    actualMode: *mut sdk::DecklinkDisplayMode,
+    supported: *mut bool,
+) -> HRESULT {
+    let mut state = input(obj);
+    state.support_queries += 1;
+    let is_supported = input_supports(&state, requestedMode, requestedPixelFormat).is_some();
+    put(supported, is_supported);
+    if is_supported {
+        put(actualMode, requestedMode);
+    }
+    S_OK
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_input_get_display_mode(
+    obj: *mut sdk::cdecklink_input_t,
+    displayMode: sdk::DecklinkDisplayMode,
+    resultDisplayMode: *mut *mut sdk::cdecklink_display_mode_t,
+) -> HRESULT {
+    let state = input(obj);
+    let mode =
+        DecklinkDisplayModeId::from_u32(displayMode).filter(|mode| state.modes.contains(mode));
+    match mode {
+        Some(mode) => {
+            put(
+                resultDisplayMode,
+                object::create(Kind::DisplayMode(ModeInfo::on_device(
+                    mode,
+                    &state.psf_modes,
+                ))),
+            );
+            S_OK
+        }
+        None => SdkError::INVALIDARG.code(),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_input_get_display_mode_iterator(
+    obj: *mut sdk::cdecklink_input_t,
+    iterator: *mut *mut sdk::cdecklink_display_mode_iterator_t,
+) -> HRESULT {
+    if denied("display mode iterator") {
+        return SdkError::ACCESSDENIED.code();
+    }
+    let state = input(obj);
+    let modes = state
+        .modes
+        .iter()
+        .map(|m| ModeInfo::on_device(*m, &state.psf_modes))
+        .collect();
+    put(
+        iterator,
+        object::create(Kind::DisplayModeIterator(Mutex::new(modes))),
+    );
+    S_OK
+}
+
+unsafe fn enable_video(
+    obj: *mut sdk::cdecklink_input_t,
+    mode: sdk::DecklinkDisplayMode,
+    pixel_format: sdk::DecklinkPixelFormat,
+    flags: sdk::DecklinkVideoInputFlags,
+    provider: *mut sdk::cdecklink_video_buffer_allocator_provider_t,
+) -> HRESULT {
+    let mut state = input(obj);
+    if state.video.is_some() || denied("video input") {
+        return SdkError::ACCESSDENIED.code();
+    }
+    let (mode, pixel_format) = match input_supports(&state, mode, pixel_format) {
+        Some(supported) => supported,
+        None => return SdkError::INVALIDARG.code(),
+    };
+    state.video = Some((
+        mode,
+        pixel_format,
+        DecklinkVideoInputFlags::from_bits_truncate(flags),
+    ));
+    if !provider.is_null() {
+        object::add_ref(provider);
+        state.provider = provider;
+    }
+    S_OK
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_input_enable_video_input(
+    obj: *mut sdk::cdecklink_input_t,
+    displayMode: sdk::DecklinkDisplayMode,
+    pixelFormat: sdk::DecklinkPixelFormat,
+    flags: sdk::DecklinkVideoInputFlags,
+) -> HRESULT {
+    enable_video(obj, displayMode, pixelFormat, flags, null_mut())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_input_enable_video_input_with_allocator_provider(
+    obj: *mut sdk::cdecklink_input_t,
+    displayMode: sdk::DecklinkDisplayMode,
+    pixelFormat: sdk::DecklinkPixelFormat,
+    flags: sdk::DecklinkVideoInputFlags,
+    allocatorProvider: *mut sdk::cdecklink_video_buffer_allocator_provider_t,
+) -> HRESULT {
+    enable_video(obj, displayMode, pixelFormat, flags, allocatorProvider)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_input_disable_video_input(
+    obj: *mut sdk::cdecklink_input_t,
+) -> HRESULT {
+    let released = input(obj).disable_video();
+    for ptr in released {
+        object::release(ptr);
+    }
+    S_OK
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_input_enable_audio_input(
+    obj: *mut sdk::cdecklink_input_t,
+    sampleRate: sdk::DecklinkAudioSampleRate,
+    sampleType: sdk::DecklinkAudioSampleType,
+    channelCount: u32,
+) -> HRESULT {
+    let mut state = input(obj);
+    if state.audio.is_some() || denied("audio input") {
+        return SdkError::ACCESSDENIED.code();
+    }
+    if state.audio_requires_video_first && state.video.is_none() {
+        return SdkError::FAIL.code();
+    }
+    state.audio = Some((sampleRate, sampleType, channelCount));
+    state.audio_frames = 0;
+    S_OK
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_input_disable_audio_input(
+    obj: *mut sdk::cdecklink_input_t,
+) -> HRESULT {
+    input(obj).audio = None;
+    S_OK
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_input_set_callback(
+    obj: *mut sdk::cdecklink_input_t,
+    ctx: *mut c_void,
+    cb0: sdk::cdecklink_input_callback_video_input_format_changed,
+    cb1: sdk::cdecklink_input_callback_video_input_frame_arrived,
+) -> HRESULT {
+    let mut state = input(obj);
+    let registering = state.callback.is_none() && (cb0.is_some() || cb1.is_some());
+    if registering && denied("input callback") {
+        return SdkError::ACCESSDENIED.code();
+    }
+    state.callback = if cb0.is_none() && cb1.is_none() {
+        None
+    } else {
+        Some(InputCallback {
+            context: ctx,
+            format_changed: cb0,
+            frame_arrived: cb1,
+        })
+    };
+    S_OK
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_input_start_streams(
+    obj: *mut sdk::cdecklink_input_t,
+) -> HRESULT {
+    let mut state = input(obj);
+    if state.video.is_none() && state.audio.is_none() {
+        return SdkError::UNEXPECTED.code();
+    }
+    if state.streaming {
+        return SdkError::ACCESSDENIED.code();
+    }
+    state.streaming = true;
+    state.paused = false;
+    S_OK
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_input_stop_streams(obj: *mut sdk::cdecklink_input_t) -> HRESULT {
+    let mut state = input(obj);
+    state.streaming = false;
+    state.paused = false;
+    state.buffered.clear();
+    S_OK
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_input_pause_streams(
+    obj: *mut sdk::cdecklink_input_t,
+) -> HRESULT {
+    let mut state = input(obj);
+    state.paused = !state.paused;
+    S_OK
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_input_flush_streams(
+    obj: *mut sdk::cdecklink_input_t,
+) -> HRESULT {
+    input(obj).buffered.clear();
+    S_OK
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_input_get_available_video_frame_count(
+    obj: *mut sdk::cdecklink_input_t,
+    availableFrameCount: *mut u32,
+) -> HRESULT {
+    let deliver = {
+        let state = input(obj);
+        put(availableFrameCount, state.buffered.len() as u32);
+        state.deliver_after_count
+    };
+    if deliver {
+        let input = super::MockInput {
+            device: device(obj).clone(),
+        };
+        input.deliver_buffered();
+    }
+    S_OK
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_input_get_available_audio_sample_frame_count(
+    obj: *mut sdk::cdecklink_input_t,
+    availableSampleFrameCount: *mut u32,
+) -> HRESULT {
+    device(obj);
+    put(availableSampleFrameCount, 0);
+    S_OK
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_display_mode_iterator_next(
+    obj: *mut sdk::cdecklink_display_mode_iterator_t,
+    deckLinkDisplayMode: *mut *mut sdk::cdecklink_display_mode_t,
+) -> HRESULT {
+    let next = match &object::get(obj).kind {
+        Kind::DisplayModeIterator(modes) => lock(modes).pop_front(),
+        _ => panic!("the mock backend was passed an object that is not a mode iterator"),
+    };
+    match next {
+        Some(mode) => {
+            put(deckLinkDisplayMode, object::create(Kind::DisplayMode(mode)));
+            S_OK
+        }
+        None => S_FALSE,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_display_mode_get_width(
+    obj: *mut sdk::cdecklink_display_mode_t,
+) -> c_long {
+    mode(obj).width as c_long
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_display_mode_get_height(
+    obj: *mut sdk::cdecklink_display_mode_t,
+) -> c_long {
+    mode(obj).height as c_long
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_display_mode_get_name(
+    obj: *mut sdk::cdecklink_display_mode_t,
+    name: *mut *const c_char,
+) -> HRESULT {
+    put(name, object::string(mode(obj).name));
+    S_OK
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_display_mode_get_display_mode(
+    obj: *mut sdk::cdecklink_display_mode_t,
+) -> sdk::DecklinkDisplayMode {
+    mode(obj).id as sdk::DecklinkDisplayMode
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_display_mode_get_frame_rate(
+    obj: *mut sdk::cdecklink_display_mode_t,
+    frameDuration: *mut sdk::DecklinkTimeValue,
+    timeScale: *mut sdk::DecklinkTimeScale,
+) -> HRESULT {
+    let mode = mode(obj);
+    put(frameDuration, mode.duration);
+    put(timeScale, mode.scale);
+    S_OK
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_display_mode_get_field_dominance(
+    obj: *mut sdk::cdecklink_display_mode_t,
+) -> sdk::DecklinkFieldDominance {
+    mode(obj).dominance as sdk::DecklinkFieldDominance
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_display_mode_get_flags(
+    obj: *mut sdk::cdecklink_display_mode_t,
+) -> sdk::DecklinkDisplayModeFlags {
+    mode(obj);
+    0
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_video_input_frame_to_video_frame(
+    obj: *mut sdk::cdecklink_video_input_frame_t,
+) -> *mut sdk::cdecklink_video_frame_t {
+    // A captured frame is a video frame, with no reference of its own to give
+    if frame(obj).converts {
+        obj
+    } else {
+        null_mut()
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_audio_input_packet_get_sample_frame_count(
+    obj: *mut sdk::cdecklink_audio_input_packet_t,
+) -> c_long {
+    audio_packet(obj).frames as c_long
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_audio_input_packet_get_bytes(
+    obj: *mut sdk::cdecklink_audio_input_packet_t,
+    buffer: *mut *mut c_void,
+) -> HRESULT {
+    put(buffer, audio_packet(obj).bytes.as_ptr() as *mut c_void);
+    S_OK
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_audio_input_packet_get_packet_time(
+    obj: *mut sdk::cdecklink_audio_input_packet_t,
+    packetTime: *mut sdk::DecklinkTimeValue,
+    timeScale: sdk::DecklinkTimeScale,
+) -> HRESULT {
+    let packet = audio_packet(obj);
+    if timeScale <= 0 {
+        return SdkError::INVALIDARG.code();
+    }
+    put(
+        packetTime,
+        (packet.time as i128 * timeScale as i128 / packet.sample_rate.max(1) as i128) as i64,
+    );
+    S_OK
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_timecode_get_components(
+    obj: *mut sdk::cdecklink_timecode_t,
+    hours: *mut u8,
+    minutes: *mut u8,
+    seconds: *mut u8,
+    frames: *mut u8,
+) -> HRESULT {
+    let timecode = timecode(obj);
+    put(hours, timecode.hours);
+    put(minutes, timecode.minutes);
+    put(seconds, timecode.seconds);
+    put(frames, timecode.frames);
+    S_OK
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_timecode_get_flags(
+    obj: *mut sdk::cdecklink_timecode_t,
+) -> sdk::DecklinkTimecodeFlags {
+    timecode(obj).flags.bits()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_timecode_get_timecode_user_bits(
+    obj: *mut sdk::cdecklink_timecode_t,
+    userBits: *mut sdk::DecklinkTimecodeUserBits,
+) -> HRESULT {
+    put(userBits, timecode(obj).user_bits);
+    S_OK
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_video_input_frame_get_stream_time(
+    obj: *mut sdk::cdecklink_video_input_frame_t,
+    frameTime: *mut sdk::DecklinkTimeValue,
+    frameDuration: *mut sdk::DecklinkTimeValue,
+    timeScale: sdk::DecklinkTimeScale,
+) -> HRESULT {
+    let (time, duration, scale) = match frame(obj).stream_time {
+        Some(stream_time) => stream_time,
+        None => return SdkError::FAIL.code(),
+    };
+    if timeScale <= 0 {
+        return SdkError::INVALIDARG.code();
+    }
+    // The driver converts to the time scale asked for, truncating
+    let convert = |value: i64| (value as i128 * timeScale as i128 / scale as i128) as i64;
+    put(frameTime, convert(time));
+    put(frameDuration, convert(duration));
+    S_OK
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_video_input_frame_get_hardware_reference_timestamp(
+    obj: *mut sdk::cdecklink_video_input_frame_t,
+    timeScale: sdk::DecklinkTimeScale,
+    frameTime: *mut sdk::DecklinkTimeValue,
+    frameDuration: *mut sdk::DecklinkTimeValue,
+) -> HRESULT {
+    let (time, duration, scale) = match frame(obj).hardware_reference_time {
+        Some(hardware_reference_time) => hardware_reference_time,
+        None => return SdkError::FAIL.code(),
+    };
+    if timeScale <= 0 {
+        return SdkError::INVALIDARG.code();
+    }
+    let convert = |value: i64| (value as i128 * timeScale as i128 / scale as i128) as i64;
+    put(frameTime, convert(time));
+    put(frameDuration, convert(duration));
+    S_OK
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_video_frame_get_timecode(
+    obj: *mut sdk::cdecklink_video_frame_t,
+    format: sdk::DecklinkTimecodeFormat,
+    timecode: *mut *mut sdk::cdecklink_timecode_t,
+) -> HRESULT {
+    use crate::timecode::DecklinkTimecodeFormat as Format;
+
+    let frame = frame(obj);
+    let find = |format: Format| {
+        frame
+            .timecodes
+            .iter()
+            .find(|(f, _)| *f == format as u32)
+            .map(|(_, timecode)| *timecode)
+    };
+    // Any RP188 timecode is the first of them the frame has
+    let found = if format == Format::RP188Any as u32 {
+        [
+            Format::RP188VITC1,
+            Format::RP188VITC2,
+            Format::RP188LTC,
+            Format::RP188HighFrameRate,
+        ]
+        .into_iter()
+        .find_map(find)
+    } else {
+        Format::from_u32(format).and_then(find)
+    };
+    match found {
+        Some(found) => {
+            put(timecode, object::create(Kind::Timecode(found)));
+            S_OK
+        }
+        None => {
+            put(timecode, null_mut());
+            S_FALSE
+        }
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_video_frame_query_video_frame_ancillary_packets(
+    obj: *mut sdk::cdecklink_video_frame_t,
+    dst: *mut *mut sdk::cdecklink_video_frame_ancillary_packets_t,
+) -> HRESULT {
+    let packets = frame(obj).ancillary.clone();
+    put(dst, object::create(Kind::AncillaryPackets(packets)));
+    S_OK
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_video_frame_ancillary_packets_get_first_packet_by_id(
+    obj: *mut sdk::cdecklink_video_frame_ancillary_packets_t,
+    DID: u8,
+    SDID: u8,
+    packet: *mut *mut sdk::cdecklink_ancillary_packet_t,
+) -> HRESULT {
+    let found = match &object::get(obj).kind {
+        Kind::AncillaryPackets(packets) => packets
+            .iter()
+            .find(|p| p.did == DID && p.sdid == SDID)
+            .cloned(),
+        _ => panic!("the mock backend was passed an object that is not of ancillary packets"),
+    };
+    match found {
+        Some(found) => {
+            put(packet, object::create(Kind::AncillaryPacket(found)));
+            S_OK
+        }
+        None => {
+            put(packet, null_mut());
+            S_FALSE
+        }
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_ancillary_packet_get_bytes(
+    obj: *mut sdk::cdecklink_ancillary_packet_t,
+    format: sdk::DecklinkAncillaryPacketFormat,
+    data: *mut *const c_void,
+    size: *mut u32,
+) -> HRESULT {
+    // The driver converts to the other formats, which the crate does not read
+    if format != sdk::_DecklinkAncillaryPacketFormat_decklinkAncillaryPacketFormatUInt8 {
+        return SdkError::NOTIMPL.code();
+    }
+    let packet = ancillary_packet(obj);
+    put(data, packet.bytes.as_ptr() as *const c_void);
+    put(size, packet.bytes.len() as u32);
+    S_OK
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_ancillary_packet_get_did(
+    obj: *mut sdk::cdecklink_ancillary_packet_t,
+) -> u8 {
+    ancillary_packet(obj).did
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_ancillary_packet_get_sdid(
+    obj: *mut sdk::cdecklink_ancillary_packet_t,
+) -> u8 {
+    ancillary_packet(obj).sdid
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_ancillary_packet_get_line_number(
+    obj: *mut sdk::cdecklink_ancillary_packet_t,
+) -> u32 {
+    ancillary_packet(obj).line
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_video_frame_get_width(
+    obj: *mut sdk::cdecklink_video_frame_t,
+) -> c_long {
+    frame(obj).width as c_long
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_video_frame_get_height(
+    obj: *mut sdk::cdecklink_video_frame_t,
+) -> c_long {
+    frame(obj).height as c_long
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_video_frame_get_row_bytes(
+    obj: *mut sdk::cdecklink_video_frame_t,
+) -> c_long {
+    frame(obj).row_bytes as c_long
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_video_frame_get_pixel_format(
+    obj: *mut sdk::cdecklink_video_frame_t,
+) -> sdk::DecklinkPixelFormat {
+    frame(obj).pixel_format
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_video_frame_get_flags(
+    obj: *mut sdk::cdecklink_video_frame_t,
+) -> sdk::DecklinkFrameFlags {
+    frame(obj).flags
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_video_frame_get_bytes(
+    obj: *mut sdk::cdecklink_video_frame_t,
+    buffer: *mut *mut c_void,
+) -> HRESULT {
+    let bytes = frame(obj).bytes();
+    put(buffer, bytes);
+    if bytes.is_null() {
+        SdkError::FAIL.code()
+    } else {
+        S_OK
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_video_frame_end_access(
+    obj: *mut sdk::cdecklink_video_frame_t,
+) -> HRESULT {
+    object::get(obj);
+    S_OK
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_custom_video_frame_create_frame(
+    width: c_long,
+    height: c_long,
+    row_bytes: c_long,
+    pixel_format: sdk::DecklinkPixelFormat,
+    flags: sdk::DecklinkFrameFlags,
+    frame: *mut *mut sdk::cdecklink_custom_video_frame_t,
+) -> HRESULT {
+    if width <= 0 || height <= 0 || row_bytes <= 0 {
+        return SdkError::INVALIDARG.code();
+    }
+    put(
+        frame,
+        object::create(Kind::Frame(Mutex::new(FrameState {
+            width: width as usize,
+            height: height as usize,
+            row_bytes: row_bytes as usize,
+            pixel_format,
+            flags,
+            stream_time: None,
+            hardware_reference_time: None,
+            timecodes: Vec::new(),
+            converts: true,
+            ancillary: Vec::new(),
+            data: FrameData::Empty,
+        }))),
+    );
+    S_OK
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_custom_video_frame_set_bytes(
+    obj: *mut sdk::cdecklink_custom_video_frame_t,
+    buffer: *mut c_void,
+    finalizer: sdk::cdecklink_custom_video_frame_free_bytes,
+    context: *mut c_void,
+) -> HRESULT {
+    let mut frame = frame(obj);
+    frame.release_data();
+    frame.data = FrameData::Custom {
+        bytes: buffer,
+        finalizer,
+        context,
+    };
+    S_OK
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_custom_video_frame_get_width(
+    obj: *mut sdk::cdecklink_custom_video_frame_t,
+) -> c_long {
+    cdecklink_video_frame_get_width(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_custom_video_frame_get_height(
+    obj: *mut sdk::cdecklink_custom_video_frame_t,
+) -> c_long {
+    cdecklink_video_frame_get_height(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_custom_video_frame_get_row_bytes(
+    obj: *mut sdk::cdecklink_custom_video_frame_t,
+) -> c_long {
+    cdecklink_video_frame_get_row_bytes(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_custom_video_frame_get_pixel_format(
+    obj: *mut sdk::cdecklink_custom_video_frame_t,
+) -> sdk::DecklinkPixelFormat {
+    cdecklink_video_frame_get_pixel_format(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_custom_video_frame_get_flags(
+    obj: *mut sdk::cdecklink_custom_video_frame_t,
+) -> sdk::DecklinkFrameFlags {
+    cdecklink_video_frame_get_flags(obj)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_custom_video_frame_get_bytes(
+    obj: *mut sdk::cdecklink_custom_video_frame_t,
+    buffer: *mut *mut c_void,
+) -> HRESULT {
+    cdecklink_video_frame_get_bytes(obj, buffer)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_custom_video_buffer_create(
+    context: *mut c_void,
+    get_bytes: sdk::cdecklink_custom_video_buffer_get_bytes_fn,
+    start_access: sdk::cdecklink_custom_video_buffer_start_access_fn,
+    end_access: sdk::cdecklink_custom_video_buffer_end_access_fn,
+    release_fn: sdk::cdecklink_custom_video_buffer_release_fn,
+    out_buffer: *mut *mut sdk::cdecklink_video_buffer_t,
+) -> HRESULT {
+    if out_buffer.is_null() {
+        return SdkError::POINTER.code();
+    }
+    *out_buffer = object::create(Kind::Buffer(Buffer {
+        context,
+        get_bytes,
+        start_access,
+        end_access,
+        release: release_fn,
+    }));
+    S_OK
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_custom_video_buffer_allocator_create(
+    context: *mut c_void,
+    allocate: sdk::cdecklink_custom_video_buffer_allocator_allocate_fn,
+    release_fn: sdk::cdecklink_custom_video_buffer_allocator_release_fn,
+    out_allocator: *mut *mut sdk::cdecklink_video_buffer_allocator_t,
+) -> HRESULT {
+    if out_allocator.is_null() {
+        return SdkError::POINTER.code();
+    }
+    *out_allocator = object::create(Kind::Allocator(Allocator {
+        context,
+        allocate,
+        release: release_fn,
+    }));
+    S_OK
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_custom_video_buffer_allocator_provider_create(
+    context: *mut c_void,
+    get_allocator: sdk::cdecklink_custom_video_buffer_allocator_provider_get_allocator_fn,
+    release_fn: sdk::cdecklink_custom_video_buffer_allocator_provider_release_fn,
+    out_provider: *mut *mut sdk::cdecklink_video_buffer_allocator_provider_t,
+) -> HRESULT {
+    if out_provider.is_null() {
+        return SdkError::POINTER.code();
+    }
+    if denied("allocator provider") {
+        return SdkError::ACCESSDENIED.code();
+    }
+    if providers_fail() {
+        return SdkError::OUTOFMEMORY.code();
+    }
+    *out_provider = object::create(Kind::Provider(object::Provider {
+        context,
+        get_allocator,
+        release: release_fn,
+    }));
+    S_OK
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_video_buffer_get_bytes(
+    obj: *mut sdk::cdecklink_video_buffer_t,
+    buffer: *mut *mut c_void,
+) -> HRESULT {
+    match &object::get(obj).kind {
+        Kind::Buffer(Buffer {
+            context,
+            get_bytes: Some(get_bytes),
+            ..
+        }) => get_bytes(*context, buffer),
+        // The frames the driver creates are video buffers too
+        Kind::Frame(_) => cdecklink_video_frame_get_bytes(obj, buffer),
+        _ => SdkError::NOTIMPL.code(),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_video_buffer_start_access(
+    obj: *mut sdk::cdecklink_video_buffer_t,
+    flags: sdk::DecklinkBufferAccessFlags,
+) -> HRESULT {
+    match &object::get(obj).kind {
+        Kind::Buffer(Buffer {
+            context,
+            start_access: Some(start_access),
+            ..
+        }) => start_access(*context, flags),
+        _ => S_OK,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_video_buffer_end_access(
+    obj: *mut sdk::cdecklink_video_buffer_t,
+    flags: sdk::DecklinkBufferAccessFlags,
+) -> HRESULT {
+    match &object::get(obj).kind {
+        Kind::Buffer(Buffer {
+            context,
+            end_access: Some(end_access),
+            ..
+        }) => end_access(*context, flags),
+        _ => S_OK,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_video_buffer_allocator_allocate_video_buffer(
+    obj: *mut sdk::cdecklink_video_buffer_allocator_t,
+    allocatedBuffer: *mut *mut sdk::cdecklink_video_buffer_t,
+) -> HRESULT {
+    match &object::get(obj).kind {
+        Kind::Allocator(Allocator {
+            context,
+            allocate: Some(allocate),
+            ..
+        }) => allocate(*context, allocatedBuffer),
+        _ => SdkError::NOTIMPL.code(),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_video_buffer_allocator_provider_get_video_buffer_allocator(
+    obj: *mut sdk::cdecklink_video_buffer_allocator_provider_t,
+    bufferSize: u32,
+    width: u32,
+    height: u32,
+    rowBytes: u32,
+    pixelFormat: sdk::DecklinkPixelFormat,
+    allocator: *mut *mut sdk::cdecklink_video_buffer_allocator_t,
+) -> HRESULT {
+    match &object::get(obj).kind {
+        Kind::Provider(object::Provider {
+            context,
+            get_allocator: Some(get_allocator),
+            ..
+        }) => get_allocator(
+            *context,
+            bufferSize,
+            width,
+            height,
+            rowBytes,
+            pixelFormat,
+            allocator,
+        ),
+        _ => SdkError::NOTIMPL.code(),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_output_does_support_video_mode(
+    obj: *mut sdk::cdecklink_output_t,
+    _connection: sdk::DecklinkVideoConnection,
+    requestedMode: sdk::DecklinkDisplayMode,
+    requestedPixelFormat: sdk::DecklinkPixelFormat,
+    _conversionMode: sdk::DecklinkVideoOutputConversionMode,
+    _flags: sdk::DecklinkSupportedVideoModeFlags,
+    actualMode: *mut sdk::DecklinkDisplayMode,
+    supported: *mut bool,
+) -> HRESULT {
+    let state = output(obj);
+    let is_supported = supports(
+        (&state.modes, &state.pixel_formats),
+        requestedMode,
+        requestedPixelFormat,
+    )
+    .is_some();
+    put(supported, is_supported);
+    if is_supported {
+        put(actualMode, requestedMode);
+    }
+    S_OK
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_output_row_bytes_for_pixel_format(
+    _obj: *mut sdk::cdecklink_output_t,
+    pixelFormat: sdk::DecklinkPixelFormat,
+    width: i32,
+    rowBytes: *mut i32,
+) -> HRESULT {
+    match (
+        DecklinkPixelFormat::from_u32(pixelFormat),
+        usize::try_from(width),
+    ) {
+        (Some(pixel_format), Ok(width)) => {
+            put(rowBytes, row_bytes(pixel_format, width) as i32);
+            S_OK
+        }
+        _ => SdkError::INVALIDARG.code(),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_output_get_display_mode_iterator(
+    obj: *mut sdk::cdecklink_output_t,
+    iterator: *mut *mut sdk::cdecklink_display_mode_iterator_t,
+) -> HRESULT {
+    if denied("display mode iterator") {
+        return SdkError::ACCESSDENIED.code();
+    }
+    let state = output(obj);
+    let modes = state
+        .modes
+        .iter()
+        .map(|m| ModeInfo::on_device(*m, &state.psf_modes))
+        .collect();
+    put(
+        iterator,
+        object::create(Kind::DisplayModeIterator(Mutex::new(modes))),
+    );
+    S_OK
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_output_enable_video_output(
+    obj: *mut sdk::cdecklink_output_t,
+    displayMode: sdk::DecklinkDisplayMode,
+    flags: sdk::DecklinkVideoOutputFlags,
+) -> HRESULT {
+    let mut state = output(obj);
+    if state.video.is_some() {
+        return SdkError::ACCESSDENIED.code();
+    }
+    match DecklinkDisplayModeId::from_u32(displayMode) {
+        Some(mode) if state.modes.contains(&mode) => {
+            state.video = Some((mode, DecklinkVideoOutputFlags::from_bits_truncate(flags)));
+            S_OK
+        }
+        _ => SdkError::INVALIDARG.code(),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_output_disable_video_output(
+    obj: *mut sdk::cdecklink_output_t,
+) -> HRESULT {
+    let released = output(obj).disable_video();
+    for ptr in released {
+        object::release(ptr);
+    }
+    S_OK
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_output_create_video_frame(
+    obj: *mut sdk::cdecklink_output_t,
+    width: i32,
+    height: i32,
+    rowBytes: i32,
+    pixelFormat: sdk::DecklinkPixelFormat,
+    flags: sdk::DecklinkFrameFlags,
+    outFrame: *mut *mut sdk::cdecklink_mutable_video_frame_t,
+) -> HRESULT {
+    device(obj);
+    if width <= 0 || height <= 0 || rowBytes <= 0 {
+        return SdkError::INVALIDARG.code();
+    }
+    let len = rowBytes as usize * height as usize;
+    put(
+        outFrame,
+        object::create(Kind::Frame(Mutex::new(FrameState {
+            width: width as usize,
+            height: height as usize,
+            row_bytes: rowBytes as usize,
+            pixel_format: pixelFormat,
+            flags,
+            stream_time: None,
+            hardware_reference_time: None,
+            timecodes: Vec::new(),
+            converts: true,
+            ancillary: Vec::new(),
+            data: FrameData::Owned(AVec::from_slice(64, &vec![0; len])),
+        }))),
+    );
+    S_OK
+}
+
+/// Check that a frame can be output in the enabled mode.
+fn check_output_frame(state: &super::OutputState, frame: &FrameState) -> HRESULT {
+    let mode = match state.video {
+        Some((mode, _)) => ModeInfo::of(mode),
+        None => return SdkError::UNEXPECTED.code(),
+    };
+    let supported = DecklinkPixelFormat::from_u32(frame.pixel_format)
+        .is_some_and(|f| state.pixel_formats.contains(&f));
+    if frame.width != mode.width || frame.height != mode.height || !supported {
+        return SdkError::INVALIDARG.code();
+    }
+    S_OK
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_output_display_video_frame_sync(
+    obj: *mut sdk::cdecklink_output_t,
+    theFrame: *mut sdk::cdecklink_video_frame_t,
+) -> HRESULT {
+    let mut state = output(obj);
+    let frame = frame(theFrame);
+    let result = check_output_frame(&state, &frame);
+    if SdkError::is_ok(result) {
+        state.displayed.push(MockFrame::copy_of(&frame));
+    }
+    result
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_output_schedule_video_frame(
+    obj: *mut sdk::cdecklink_output_t,
+    theFrame: *mut sdk::cdecklink_video_frame_t,
+    displayTime: sdk::DecklinkTimeValue,
+    displayDuration: sdk::DecklinkTimeValue,
+    _timeScale: sdk::DecklinkTimeScale,
+) -> HRESULT {
+    let mut state = output(obj);
+    let result = check_output_frame(&state, &frame(theFrame));
+    if SdkError::is_ok(result) {
+        object::add_ref(theFrame);
+        state.scheduled.push_back(ScheduledFrame {
+            frame: theFrame,
+            display_time: displayTime,
+            duration: displayDuration,
+        });
+    }
+    result
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_output_set_scheduled_frame_completion_callback(
+    obj: *mut sdk::cdecklink_output_t,
+    ctx: *mut c_void,
+    cb0: sdk::cdecklink_video_output_callback_scheduled_frame_completed,
+    cb1: sdk::cdecklink_video_output_callback_scheduled_playback_has_stopped,
+) -> HRESULT {
+    output(obj).callback = if cb0.is_none() && cb1.is_none() {
+        None
+    } else {
+        Some(OutputCallback {
+            context: ctx,
+            completed: cb0,
+            stopped: cb1,
+        })
+    };
+    S_OK
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_output_get_buffered_video_frame_count(
+    obj: *mut sdk::cdecklink_output_t,
+    bufferedFrameCount: *mut u32,
+) -> HRESULT {
+    put(bufferedFrameCount, output(obj).scheduled.len() as u32);
+    S_OK
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_output_start_scheduled_playback(
+    obj: *mut sdk::cdecklink_output_t,
+    playbackStartTime: sdk::DecklinkTimeValue,
+    timeScale: sdk::DecklinkTimeScale,
+    playbackSpeed: f64,
+) -> HRESULT {
+    let mut state = output(obj);
+    if state.video.is_none() {
+        return SdkError::UNEXPECTED.code();
+    }
+    state.playback = Some((playbackStartTime, timeScale, playbackSpeed));
+    S_OK
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_output_stop_scheduled_playback(
+    obj: *mut sdk::cdecklink_output_t,
+    stopPlaybackAtTime: sdk::DecklinkTimeValue,
+    actualStopTime: *mut sdk::DecklinkTimeValue,
+    _timeScale: sdk::DecklinkTimeScale,
+) -> HRESULT {
+    output(obj).playback = None;
+    put(actualStopTime, stopPlaybackAtTime);
+    S_OK
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_output_is_scheduled_playback_running(
+    obj: *mut sdk::cdecklink_output_t,
+    active: *mut bool,
+) -> HRESULT {
+    put(active, output(obj).playback.is_some());
+    S_OK
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_output_enable_audio_output(
+    obj: *mut sdk::cdecklink_output_t,
+    sampleRate: sdk::DecklinkAudioSampleRate,
+    sampleType: sdk::DecklinkAudioSampleType,
+    channelCount: u32,
+    streamType: sdk::DecklinkAudioOutputStreamType,
+) -> HRESULT {
+    let mut state = output(obj);
+    if state.audio.is_some() {
+        return SdkError::ACCESSDENIED.code();
+    }
+    state.audio = Some((sampleRate, sampleType, channelCount, streamType));
+    S_OK
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_output_disable_audio_output(
+    obj: *mut sdk::cdecklink_output_t,
+) -> HRESULT {
+    output(obj).audio = None;
+    S_OK
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_output_begin_audio_preroll(
+    obj: *mut sdk::cdecklink_output_t,
+) -> HRESULT {
+    device(obj);
+    S_OK
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_output_end_audio_preroll(
+    obj: *mut sdk::cdecklink_output_t,
+) -> HRESULT {
+    device(obj);
+    S_OK
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_output_get_buffered_audio_sample_frame_count(
+    obj: *mut sdk::cdecklink_output_t,
+    bufferedSampleFrameCount: *mut u32,
+) -> HRESULT {
+    device(obj);
+    put(bufferedSampleFrameCount, 0);
+    S_OK
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cdecklink_output_flush_buffered_audio_samples(
+    obj: *mut sdk::cdecklink_output_t,
+) -> HRESULT {
+    device(obj);
+    S_OK
+}
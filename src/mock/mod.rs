@@ -0,0 +1,1494 @@
+//! A mock backend standing in for the DeckLink drivers, for testing without hardware.
+//!
+//! Enabled by the `mock-backend` feature, which builds the crate without the C library. The
+//! functions of the C bindings are implemented here instead, over devices a test installs
+//! with `MockBackend::install`, so code written against the rest of the crate runs
+//! unchanged. The test plays the part of the driver, delivering frames and format changes to
+//! the callback of an input through `MockInput`.
+//!
+//! ```
+//! use decklink::device::get_devices;
+//! use decklink::mock::{MockBackend, MockDevice};
+//!
+//! let _backend = MockBackend::install(vec![MockDevice::new("DeckLink Mini Recorder")]);
+//! let devices = get_devices().unwrap();
+//! assert_eq!(
+//!     devices[0].display_name().as_deref(),
+//!     Some("DeckLink Mini Recorder")
+//! );
+//! ```
+//!
+//! The functions the mock does not implement return `SdkError::NOTIMPL`, or a null or zero
+//! value, as an older driver without them would.
+//!
+//! A backend holds a lock from `install` until it is dropped, so tests in one binary that use
+//! the mock run one at a time.
+
+mod defaults;
+mod ffi;
+mod object;
+
+use crate::connectors::DecklinkVideoConnection;
+use crate::device::attributes::{
+    DecklinkDeviceInterface, DecklinkDuplexMode, DecklinkProfileId, DecklinkVideoIOSupport,
+};
+use crate::device::custom_id::CustomValue;
+use crate::device::input::{
+    CallbackReturnHandling, DecklinkDetectedVideoInputFormatFlags, DecklinkVideoInputFlags,
+    DecklinkVideoInputFormatChangedEvents,
+};
+use crate::device::output::{DecklinkOutputFrameCompletionResult, DecklinkVideoOutputFlags};
+use crate::device::status::DecklinkStatusId;
+use crate::display_mode::{DecklinkDisplayModeId, DecklinkFieldDominance};
+use crate::frame::{DecklinkFrameFlags, DecklinkPixelFormat};
+use crate::reference::DecklinkReferenceExt;
+use crate::timecode::{DecklinkTimecode, DecklinkTimecodeFormat};
+use crate::vpid::Vpid;
+use crate::{sdk, ApiVersion, SdkError};
+use aligned_vec::AVec;
+use num_traits::FromPrimitive;
+use object::{AncillaryPacket, AudioPacket, FrameData, FrameState, Kind};
+use std::collections::{HashMap, VecDeque};
+use std::ffi::c_void;
+use std::ptr::null_mut;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// The modes a mock input supports unless it is given others.
+pub const DEFAULT_MODES: [DecklinkDisplayModeId; 15] = [
+    DecklinkDisplayModeId::NTSC,
+    DecklinkDisplayModeId::PAL,
+    DecklinkDisplayModeId::HD720p50,
+    DecklinkDisplayModeId::HD720p5994,
+    DecklinkDisplayModeId::HD720p60,
+    DecklinkDisplayModeId::HD1080i50,
+    DecklinkDisplayModeId::HD1080i5994,
+    DecklinkDisplayModeId::HD1080p2398,
+    DecklinkDisplayModeId::HD1080p24,
+    DecklinkDisplayModeId::HD1080p25,
+    DecklinkDisplayModeId::HD1080p2997,
+    DecklinkDisplayModeId::HD1080p30,
+    DecklinkDisplayModeId::HD1080p50,
+    DecklinkDisplayModeId::HD1080p5994,
+    DecklinkDisplayModeId::HD1080p6000,
+];
+
+/// The 4K and 8K modes that `MockDevice::eight_k` supports besides `DEFAULT_MODES`.
+pub const EIGHT_K_MODES: [DecklinkDisplayModeId; 50] = [
+    DecklinkDisplayModeId::UHD4K2160p2398,
+    DecklinkDisplayModeId::UHD4K2160p24,
+    DecklinkDisplayModeId::UHD4K2160p25,
+    DecklinkDisplayModeId::UHD4K2160p2997,
+    DecklinkDisplayModeId::UHD4K2160p30,
+    DecklinkDisplayModeId::UHD4K2160p4795,
+    DecklinkDisplayModeId::UHD4K2160p48,
+    DecklinkDisplayModeId::UHD4K2160p50,
+    DecklinkDisplayModeId::UHD4K2160p5994,
+    DecklinkDisplayModeId::UHD4K2160p60,
+    DecklinkDisplayModeId::UHD4K2160p9590,
+    DecklinkDisplayModeId::UHD4K2160p96,
+    DecklinkDisplayModeId::UHD4K2160p100,
+    DecklinkDisplayModeId::UHD4K2160p11988,
+    DecklinkDisplayModeId::UHD4K2160p120,
+    DecklinkDisplayModeId::UHD4KDCI2398,
+    DecklinkDisplayModeId::UHD4KDCI24,
+    DecklinkDisplayModeId::UHD4KDCI25,
+    DecklinkDisplayModeId::UHD4KDCI2997,
+    DecklinkDisplayModeId::UHD4KDCI30,
+    DecklinkDisplayModeId::UHD4KDCI4795,
+    DecklinkDisplayModeId::UHD4KDCI48,
+    DecklinkDisplayModeId::UHD4KDCI50,
+    DecklinkDisplayModeId::UHD4KDCI5994,
+    DecklinkDisplayModeId::UHD4KDCI60,
+    DecklinkDisplayModeId::UHD4KDCI9590,
+    DecklinkDisplayModeId::UHD4KDCI96,
+    DecklinkDisplayModeId::UHD4KDCI100,
+    DecklinkDisplayModeId::UHD4KDCI11988,
+    DecklinkDisplayModeId::UHD4KDCI120,
+    DecklinkDisplayModeId::UHD8K4320p2398,
+    DecklinkDisplayModeId::UHD8K4320p24,
+    DecklinkDisplayModeId::UHD8K4320p25,
+    DecklinkDisplayModeId::UHD8K4320p2997,
+    DecklinkDisplayModeId::UHD8K4320p30,
+    DecklinkDisplayModeId::UHD8K4320p4795,
+    DecklinkDisplayModeId::UHD8K4320p48,
+    DecklinkDisplayModeId::UHD8K4320p50,
+    DecklinkDisplayModeId::UHD8K4320p5994,
+    DecklinkDisplayModeId::UHD8K4320p60,
+    DecklinkDisplayModeId::UHD8KDCI2398,
+    DecklinkDisplayModeId::UHD8KDCI24,
+    DecklinkDisplayModeId::UHD8KDCI25,
+    DecklinkDisplayModeId::UHD8KDCI2997,
+    DecklinkDisplayModeId::UHD8KDCI30,
+    DecklinkDisplayModeId::UHD8KDCI4795,
+    DecklinkDisplayModeId::UHD8KDCI48,
+    DecklinkDisplayModeId::UHD8KDCI50,
+    DecklinkDisplayModeId::UHD8KDCI5994,
+    DecklinkDisplayModeId::UHD8KDCI60,
+];
+
+/// The pixel formats a mock input supports unless it is given others, those of a recorder
+/// that only captures YUV.
+pub const DEFAULT_PIXEL_FORMATS: [DecklinkPixelFormat; 2] = [
+    DecklinkPixelFormat::Format8BitYUV,
+    DecklinkPixelFormat::Format10BitYUV,
+];
+
+/// The pixel formats a mock output supports unless it is given others.
+pub const DEFAULT_OUTPUT_PIXEL_FORMATS: [DecklinkPixelFormat; 5] = [
+    DecklinkPixelFormat::Format8BitYUV,
+    DecklinkPixelFormat::Format10BitYUV,
+    DecklinkPixelFormat::Format8BitARGB,
+    DecklinkPixelFormat::Format8BitBGRA,
+    DecklinkPixelFormat::Format10BitRGB,
+];
+
+/// The driver version the mock reports unless the test sets another.
+pub const DEFAULT_API_VERSION: ApiVersion = ApiVersion::new(14, 2, 1);
+
+static INSTALL_LOCK: Mutex<()> = Mutex::new(());
+static DEVICES: Mutex<Vec<Arc<DeviceState>>> = Mutex::new(Vec::new());
+/// The driver version reported, or `None` to act as a system without drivers.
+static API_VERSION: Mutex<Option<ApiVersion>> = Mutex::new(Some(DEFAULT_API_VERSION));
+/// The resources asked for since new ones were denied, or `None` while they are allowed.
+static DENIED: Mutex<Option<Vec<String>>> = Mutex::new(None);
+/// Whether allocator providers fail to be created, while other resources are.
+static FAIL_PROVIDERS: AtomicBool = AtomicBool::new(false);
+/// The handling of callback results reported in place of the findings for the driver version.
+static CALLBACK_RETURNS: Mutex<Option<CallbackReturnHandling>> = Mutex::new(None);
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    // A test that panicked while holding a lock must not fail the tests after it
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// A device for `MockBackend::install` to list.
+#[derive(Debug, Clone)]
+pub struct MockDevice {
+    display_name: Vec<u8>,
+    model_name: String,
+    input: Option<(Vec<DecklinkDisplayModeId>, Vec<DecklinkPixelFormat>)>,
+    mode_pixel_formats: Vec<(DecklinkDisplayModeId, DecklinkPixelFormat)>,
+    psf_modes: Vec<DecklinkDisplayModeId>,
+    audio_requires_video_first: bool,
+    output: Option<(Vec<DecklinkDisplayModeId>, Vec<DecklinkPixelFormat>)>,
+    status: Values,
+    attributes: Values,
+}
+
+impl MockDevice {
+    /// A device named `display_name`, with an input supporting `DEFAULT_MODES` in
+    /// `DEFAULT_PIXEL_FORMATS`, and no output.
+    pub fn new(display_name: &str) -> MockDevice {
+        MockDevice {
+            display_name: display_name.as_bytes().to_vec(),
+            model_name: display_name.to_string(),
+            input: Some((DEFAULT_MODES.to_vec(), DEFAULT_PIXEL_FORMATS.to_vec())),
+            mode_pixel_formats: Vec::new(),
+            psf_modes: Vec::new(),
+            audio_requires_video_first: false,
+            output: None,
+            status: Values::default(),
+            attributes: Values::default(),
+        }
+    }
+
+    /// A device like an 8K Pro named `display_name`, with an input and an output supporting
+    /// `DEFAULT_MODES` and `EIGHT_K_MODES` in `DEFAULT_OUTPUT_PIXEL_FORMATS`.
+    pub fn eight_k(display_name: &str) -> MockDevice {
+        let modes: Vec<_> = DEFAULT_MODES
+            .iter()
+            .chain(&EIGHT_K_MODES)
+            .copied()
+            .collect();
+        MockDevice::new(display_name)
+            .modes(&modes)
+            .pixel_formats(&DEFAULT_OUTPUT_PIXEL_FORMATS)
+            .output_modes(&modes)
+            .format_detection(true)
+    }
+
+    /// Set the display name to `bytes`, which need not be UTF-8, as a driver in another
+    /// locale may report them.
+    pub fn display_name_bytes(mut self, bytes: &[u8]) -> Self {
+        self.display_name = bytes.to_vec();
+        self
+    }
+
+    /// Set the model name, which is the display name unless this is called.
+    pub fn model_name(mut self, model_name: &str) -> Self {
+        self.model_name = model_name.to_string();
+        self
+    }
+
+    /// Set the display modes the input supports.
+    pub fn modes(mut self, modes: &[DecklinkDisplayModeId]) -> Self {
+        self.input.get_or_insert_with(Default::default).0 = modes.to_vec();
+        self
+    }
+
+    /// Set the pixel formats the input supports, in every one of its modes.
+    pub fn pixel_formats(mut self, pixel_formats: &[DecklinkPixelFormat]) -> Self {
+        self.input.get_or_insert_with(Default::default).1 = pixel_formats.to_vec();
+        self
+    }
+
+    /// Also support `pixel_formats` on the input in `mode` only.
+    pub fn mode_pixel_formats(
+        mut self,
+        mode: DecklinkDisplayModeId,
+        pixel_formats: &[DecklinkPixelFormat],
+    ) -> Self {
+        self.mode_pixel_formats.extend(
+            pixel_formats
+                .iter()
+                .map(|pixel_format| (mode, *pixel_format)),
+        );
+        self
+    }
+
+    /// Give the device no input, as a playback only device.
+    pub fn without_input(mut self) -> Self {
+        self.input = None;
+        self
+    }
+
+    /// Give the device an output supporting `DEFAULT_MODES` in `DEFAULT_OUTPUT_PIXEL_FORMATS`.
+    pub fn with_output(mut self) -> Self {
+        self.output = Some((
+            DEFAULT_MODES.to_vec(),
+            DEFAULT_OUTPUT_PIXEL_FORMATS.to_vec(),
+        ));
+        self
+    }
+
+    /// Report `modes` as PsF on the input and output, with a segmented frame field dominance.
+    pub fn psf_modes(mut self, modes: &[DecklinkDisplayModeId]) -> Self {
+        self.psf_modes = modes.to_vec();
+        self
+    }
+
+    /// Fail enabling audio input with `SdkError::FAIL` unless video input is enabled, as
+    /// some drivers do.
+    pub fn audio_requires_video_first(mut self, requires: bool) -> Self {
+        self.audio_requires_video_first = requires;
+        self
+    }
+
+    /// Set the display modes the output supports, giving the device an output.
+    pub fn output_modes(mut self, modes: &[DecklinkDisplayModeId]) -> Self {
+        self.output
+            .get_or_insert_with(|| (Vec::new(), DEFAULT_OUTPUT_PIXEL_FORMATS.to_vec()))
+            .0 = modes.to_vec();
+        self
+    }
+
+    /// Set the pixel formats the output supports, giving the device an output.
+    pub fn output_pixel_formats(mut self, pixel_formats: &[DecklinkPixelFormat]) -> Self {
+        self.output
+            .get_or_insert_with(|| (DEFAULT_MODES.to_vec(), Vec::new()))
+            .1 = pixel_formats.to_vec();
+        self
+    }
+
+    /// Report `value` for the integer status `id`.
+    pub fn status_int(mut self, id: DecklinkStatusId, value: i64) -> Self {
+        self.status.ints.insert(id as u32, value);
+        self
+    }
+
+    /// Report `value` for the flag status `id`.
+    pub fn status_flag(mut self, id: DecklinkStatusId, value: bool) -> Self {
+        self.status.flags.insert(id as u32, value);
+        self
+    }
+
+    /// Report `profile` as the active profile, in which the device has `duplex` mode.
+    pub fn profile(mut self, profile: DecklinkProfileId, duplex: DecklinkDuplexMode) -> Self {
+        let ints = &mut self.attributes.ints;
+        ints.insert(sdk::_DecklinkAttributeID_decklinkProfileID, profile as i64);
+        ints.insert(sdk::_DecklinkAttributeID_decklinkDuplex, duplex as i64);
+        self
+    }
+
+    /// Make the device the sub-device `persistent_id` of the hardware `device_group_id`.
+    pub fn sub_device(mut self, device_group_id: i64, persistent_id: i64) -> Self {
+        let ints = &mut self.attributes.ints;
+        ints.insert(
+            sdk::_DecklinkAttributeID_decklinkDeviceGroupID,
+            device_group_id,
+        );
+        ints.insert(
+            sdk::_DecklinkAttributeID_decklinkPersistentID,
+            persistent_id,
+        );
+        self
+    }
+
+    /// Number the sub-device within its card.
+    pub fn sub_device_index(mut self, index: i64) -> Self {
+        self.attributes
+            .ints
+            .insert(sdk::_DecklinkAttributeID_decklinkSubDeviceIndex, index);
+        self
+    }
+
+    /// Report `inputs` and `outputs` as the video connections of the device.
+    pub fn video_connections(
+        mut self,
+        inputs: DecklinkVideoConnection,
+        outputs: DecklinkVideoConnection,
+    ) -> Self {
+        let ints = &mut self.attributes.ints;
+        ints.insert(
+            sdk::_DecklinkAttributeID_decklinkVideoInputConnections,
+            inputs.bits() as i64,
+        );
+        ints.insert(
+            sdk::_DecklinkAttributeID_decklinkVideoOutputConnections,
+            outputs.bits() as i64,
+        );
+        self
+    }
+
+    /// Report whether the device supports input format detection.
+    pub fn format_detection(mut self, supported: bool) -> Self {
+        self.attributes.flags.insert(
+            sdk::_DecklinkAttributeID_decklinkSupportsInputFormatDetection,
+            supported,
+        );
+        self
+    }
+
+    /// Report `support` as the capture and playback capability of the device.
+    pub fn video_io_support(mut self, support: DecklinkVideoIOSupport) -> Self {
+        self.attributes.ints.insert(
+            sdk::_DecklinkAttributeID_decklinkVideoIOSupport,
+            support.bits() as i64,
+        );
+        self
+    }
+
+    /// Report `interface` as the interface the device is connected with.
+    pub fn device_interface(mut self, interface: DecklinkDeviceInterface) -> Self {
+        self.attributes.ints.insert(
+            sdk::_DecklinkAttributeID_decklinkDeviceInterface,
+            interface as i64,
+        );
+        self
+    }
+
+    /// Report `handle` as the device handle.
+    pub fn device_handle(mut self, handle: &str) -> Self {
+        self.attributes.strings.insert(
+            sdk::_DecklinkAttributeID_decklinkDeviceHandle,
+            handle.to_string(),
+        );
+        self
+    }
+
+    /// Report `value` for the attribute `id`, which need not be one this crate wraps.
+    pub fn raw_attribute(mut self, id: u32, value: CustomValue) -> Self {
+        self.attributes.insert(id, value);
+        self
+    }
+
+    /// Report `value` for the status `id`, which need not be one this crate wraps.
+    pub fn raw_status(mut self, id: u32, value: CustomValue) -> Self {
+        self.status.insert(id, value);
+        self
+    }
+}
+
+/// The devices the mock lists, from `install` until it is dropped.
+pub struct MockBackend {
+    devices: Vec<Arc<DeviceState>>,
+    _lock: MutexGuard<'static, ()>,
+}
+
+impl MockBackend {
+    /// List `devices`, in order, until the backend is dropped, with drivers of
+    /// `DEFAULT_API_VERSION` installed. Waits for any other backend to be dropped first.
+    pub fn install(devices: Vec<MockDevice>) -> MockBackend {
+        let guard = lock(&INSTALL_LOCK);
+        *lock(&API_VERSION) = Some(DEFAULT_API_VERSION);
+        *lock(&DENIED) = None;
+        *lock(&CALLBACK_RETURNS) = None;
+        FAIL_PROVIDERS.store(false, Ordering::SeqCst);
+        let devices: Vec<_> = devices
+            .into_iter()
+            .map(|d| Arc::new(DeviceState::new(d)))
+            .collect();
+        *lock(&DEVICES) = devices.clone();
+        MockBackend {
+            devices,
+            _lock: guard,
+        }
+    }
+
+    /// Report `version` as the version of the installed drivers, or act as a system without
+    /// drivers if it is `None`.
+    pub fn set_api_version(&self, version: Option<ApiVersion>) {
+        *lock(&API_VERSION) = version;
+    }
+
+    /// Report the installed drivers as handling frame-arrived results as `handling`, as a
+    /// hardware report would once recorded, or as the findings for their version say if it
+    /// is `None`.
+    pub fn set_callback_returns(&self, handling: Option<CallbackReturnHandling>) {
+        *lock(&CALLBACK_RETURNS) = handling;
+    }
+
+    /// Report `value` for the integer status `id` of device `index` from now on.
+    pub fn set_status_int(&self, index: usize, id: DecklinkStatusId, value: i64) {
+        lock(&self.devices[index].status)
+            .ints
+            .insert(id as u32, value);
+    }
+
+    /// Report `value` for the flag status `id` of device `index` from now on.
+    pub fn set_status_flag(&self, index: usize, id: DecklinkStatusId, value: bool) {
+        lock(&self.devices[index].status)
+            .flags
+            .insert(id as u32, value);
+    }
+
+    /// Report `value` for the status `id` of device `index` from now on, which need not be one
+    /// this crate wraps.
+    pub fn set_raw_status(&self, index: usize, id: u32, value: CustomValue) {
+        lock(&self.devices[index].status).insert(id, value);
+    }
+
+    /// Stop reporting the status `id` of device `index`, as a device does for a status that
+    /// does not currently apply.
+    pub fn clear_status(&self, index: usize, id: DecklinkStatusId) {
+        let mut status = lock(&self.devices[index].status);
+        status.flags.remove(&(id as u32));
+        status.ints.remove(&(id as u32));
+    }
+
+    /// Remove device `index`, as when it is unplugged. It is no longer listed, and querying its
+    /// status or attributes fails, until it is attached again.
+    pub fn detach(&self, index: usize) {
+        self.devices[index].attached.store(false, Ordering::SeqCst);
+    }
+
+    /// Attach device `index` again after `detach`.
+    pub fn attach(&self, index: usize) {
+        self.devices[index].attached.store(true, Ordering::SeqCst);
+    }
+
+    /// The input of device `index`, to act as its driver.
+    ///
+    /// Panics if there is no such device, or it has no input.
+    pub fn input(&self, index: usize) -> MockInput {
+        let device = self.devices[index].clone();
+        assert!(device.input.is_some(), "mock device {} has no input", index);
+        MockInput { device }
+    }
+
+    /// The output of device `index`, to act as its driver.
+    ///
+    /// Panics if there is no such device, or it has no output.
+    pub fn output(&self, index: usize) -> MockOutput {
+        let device = self.devices[index].clone();
+        assert!(
+            device.output.is_some(),
+            "mock device {} has no output",
+            index
+        );
+        MockOutput { device }
+    }
+
+    /// Refuse to create resources from now on, as a driver in a sandbox entered after
+    /// `crate::preflight` would, and record each one asked for.
+    ///
+    /// Listing devices, opening an interface, listing display modes, enabling video or audio
+    /// input, registering an input callback and creating an allocator provider then fail with
+    /// `SdkError::ACCESSDENIED`. Frames, packets and the buffers of allocators are created by
+    /// the driver as frames arrive, and still are. Threads the crate starts are recorded, but
+    /// start all the same, as the crate cannot capture without them.
+    pub fn deny_new_resources(&self) {
+        *lock(&DENIED) = Some(Vec::new());
+    }
+
+    /// Fail the creation of allocator providers with `SdkError::OUTOFMEMORY` while `fail` is
+    /// set, creating every other resource as before.
+    pub fn fail_allocator_providers(&self, fail: bool) {
+        FAIL_PROVIDERS.store(fail, Ordering::SeqCst);
+    }
+
+    /// Create resources again after `deny_new_resources`, forgetting those asked for.
+    pub fn allow_new_resources(&self) {
+        *lock(&DENIED) = None;
+    }
+
+    /// The resources asked for since `deny_new_resources`, in order, such as `"input"` or
+    /// `"thread decklink-tap-0"`.
+    pub fn denied_resources(&self) -> Vec<String> {
+        lock(&DENIED).clone().unwrap_or_default()
+    }
+
+    /// The number of objects and strings the mock has handed to the crate that have not been
+    /// released. Once every wrapper is dropped this is zero, unless the crate leaked one.
+    pub fn live_objects() -> usize {
+        object::live()
+    }
+}
+
+impl Drop for MockBackend {
+    fn drop(&mut self) {
+        lock(&DEVICES).clear();
+        *lock(&DENIED) = None;
+    }
+}
+
+/// What came of delivering a callback to an input.
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub enum Delivery {
+    /// The input was not streaming, or had no callback, so nothing was called.
+    NotDelivered,
+    /// The callback was called, and returned this HRESULT.
+    Returned(i32),
+}
+
+impl Delivery {
+    /// Whether the callback was called and returned `S_OK`.
+    pub fn is_ok(&self) -> bool {
+        *self == Delivery::Returned(0)
+    }
+}
+
+/// A frame for `MockInput::deliver_frame`.
+#[derive(PartialEq, Debug, Clone)]
+pub struct MockFrame {
+    width: usize,
+    height: usize,
+    row_bytes: usize,
+    pixel_format: DecklinkPixelFormat,
+    flags: DecklinkFrameFlags,
+    stream_time: Option<(i64, i64, i64)>,
+    hardware_reference_time: Option<(i64, i64, i64)>,
+    timecodes: Vec<(DecklinkTimecodeFormat, DecklinkTimecode)>,
+    converts: bool,
+    ancillary: Vec<AncillaryPacket>,
+    bytes: Vec<u8>,
+}
+
+impl MockFrame {
+    /// A frame of `width` by `height` in `pixel_format`, filled with zeros, with the row bytes
+    /// the driver uses for the format.
+    pub fn new(width: usize, height: usize, pixel_format: DecklinkPixelFormat) -> MockFrame {
+        let row_bytes = row_bytes(pixel_format, width);
+        MockFrame {
+            width,
+            height,
+            row_bytes,
+            pixel_format,
+            flags: DecklinkFrameFlags::empty(),
+            stream_time: None,
+            hardware_reference_time: None,
+            timecodes: Vec::new(),
+            converts: true,
+            ancillary: Vec::new(),
+            bytes: vec![0; row_bytes * height],
+        }
+    }
+
+    /// A frame of the size of `mode`.
+    pub fn for_mode(mode: DecklinkDisplayModeId, pixel_format: DecklinkPixelFormat) -> MockFrame {
+        let info = ModeInfo::of(mode);
+        MockFrame::new(info.width, info.height, pixel_format)
+    }
+
+    /// Set the flags of the frame.
+    pub fn flags(mut self, flags: DecklinkFrameFlags) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    /// Flag the frame as captured with no input source, as frames without a signal are.
+    pub fn no_signal(self) -> Self {
+        let flags = self.flags | DecklinkFrameFlags::HAS_NO_INPUT_SOURCE;
+        self.flags(flags)
+    }
+
+    /// Set the stream time and duration of the frame, in ticks of `scale` per second. A frame
+    /// without them fails to report a stream time.
+    pub fn stream_time(mut self, time: i64, duration: i64, scale: i64) -> Self {
+        self.stream_time = Some((time, duration, scale));
+        self
+    }
+
+    /// Set the hardware reference timestamp and duration of the frame, in ticks of `scale` per
+    /// second. A frame without them fails to report a hardware reference timestamp.
+    pub fn hardware_reference_timestamp(mut self, time: i64, duration: i64, scale: i64) -> Self {
+        self.hardware_reference_time = Some((time, duration, scale));
+        self
+    }
+
+    /// Set the timecode of the frame in `format`, replacing any set before. A frame has no
+    /// timecode in the formats not set.
+    pub fn timecode(mut self, format: DecklinkTimecodeFormat, timecode: DecklinkTimecode) -> Self {
+        self.timecodes.retain(|(f, _)| *f != format);
+        self.timecodes.push((format, timecode));
+        self
+    }
+
+    /// Add an ancillary packet with the user data words `bytes` to the frame, as captured from
+    /// `line` of its vertical blanking.
+    pub fn ancillary_packet(mut self, did: u8, sdid: u8, line: u32, bytes: &[u8]) -> Self {
+        self.ancillary.push(AncillaryPacket {
+            did,
+            sdid,
+            line,
+            bytes: bytes.to_vec(),
+        });
+        self
+    }
+
+    /// Add the SMPTE ST 352 payload identifier `bytes` to the frame, on line 10 as sources
+    /// insert it, replacing any added before.
+    pub fn vpid(mut self, bytes: [u8; 4]) -> Self {
+        self.ancillary
+            .retain(|packet| (packet.did, packet.sdid) != (Vpid::DID, Vpid::SDID));
+        self.ancillary_packet(Vpid::DID, Vpid::SDID, 10, &bytes)
+    }
+
+    /// Set the bytes of each row, padding rows with zeros or cutting them short, as a driver
+    /// that pads rows further does.
+    pub fn row_bytes(mut self, row_bytes: usize) -> Self {
+        let mut bytes = vec![0; row_bytes * self.height];
+        let keep = row_bytes.min(self.row_bytes);
+        if keep > 0 {
+            for (dst, src) in bytes
+                .chunks_exact_mut(row_bytes)
+                .zip(self.bytes.chunks_exact(self.row_bytes))
+            {
+                dst[..keep].copy_from_slice(&src[..keep]);
+            }
+        }
+        self.row_bytes = row_bytes;
+        self.bytes = bytes;
+        self
+    }
+
+    /// Fail the conversion of the captured frame to a video frame, as a driver under memory
+    /// pressure can, so the frame cannot be read.
+    pub fn conversion_fails(mut self) -> Self {
+        self.converts = false;
+        self
+    }
+
+    /// Fill every byte of the frame with `value`.
+    pub fn fill(mut self, value: u8) -> Self {
+        self.bytes.fill(value);
+        self
+    }
+
+    /// The width of the frame in pixels.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// The height of the frame in pixels.
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// The pixel format of the frame.
+    pub fn pixel_format(&self) -> DecklinkPixelFormat {
+        self.pixel_format
+    }
+
+    /// The bytes of the frame.
+    pub fn data(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// A copy of a frame the crate handed to an output.
+    fn copy_of(frame: &FrameState) -> MockFrame {
+        let len = frame.row_bytes * frame.height;
+        let ptr = frame.bytes();
+        let bytes = if ptr.is_null() {
+            vec![0; len]
+        } else {
+            unsafe { std::slice::from_raw_parts(ptr as *const u8, len) }.to_vec()
+        };
+        MockFrame {
+            width: frame.width,
+            height: frame.height,
+            row_bytes: frame.row_bytes,
+            pixel_format: DecklinkPixelFormat::from_u32(frame.pixel_format)
+                .unwrap_or(DecklinkPixelFormat::Format8BitYUV),
+            flags: DecklinkFrameFlags::from_bits_truncate(frame.flags),
+            stream_time: frame.stream_time,
+            hardware_reference_time: frame.hardware_reference_time,
+            timecodes: Vec::new(),
+            converts: true,
+            ancillary: Vec::new(),
+            bytes,
+        }
+    }
+
+    /// Set the bytes of the frame, which are cut or padded with zeros to its size.
+    pub fn bytes(mut self, bytes: &[u8]) -> Self {
+        let len = self.bytes.len();
+        self.bytes = bytes.to_vec();
+        self.bytes.resize(len, 0);
+        self
+    }
+}
+
+/// The driver's side of the input of a mock device.
+#[derive(Clone)]
+pub struct MockInput {
+    device: Arc<DeviceState>,
+}
+
+impl MockInput {
+    fn state(&self) -> MutexGuard<'_, InputState> {
+        self.device.input()
+    }
+
+    /// The mode, pixel format and flags video input is enabled with, if it is.
+    pub fn video(
+        &self,
+    ) -> Option<(
+        DecklinkDisplayModeId,
+        DecklinkPixelFormat,
+        DecklinkVideoInputFlags,
+    )> {
+        self.state().video
+    }
+
+    /// Whether video input is enabled with an allocator provider.
+    pub fn has_allocator_provider(&self) -> bool {
+        !self.state().provider.is_null()
+    }
+
+    /// The number of allocators the input holds, one for each buffer spec it asked the
+    /// allocator provider for since video input was enabled.
+    pub fn allocator_count(&self) -> usize {
+        self.state().allocators.len()
+    }
+
+    /// Ask the allocator provider for buffers `bytes` larger than the frames from now on, as
+    /// some drivers do in the middle of a capture, such as after ancillary data is toggled.
+    /// Frames are then captured into buffers of a new spec for the same mode, while frames
+    /// captured before keep the buffers they were captured into.
+    pub fn set_buffer_padding(&self, bytes: usize) {
+        self.state().buffer_padding = bytes;
+    }
+
+    /// The number of times the crate asked whether the input supports a mode.
+    pub fn support_queries(&self) -> usize {
+        self.state().support_queries
+    }
+
+    /// Deliver callbacks even while streams are stopped or paused, as some drivers do for a
+    /// frame or two after streams are stopped.
+    pub fn set_late_callbacks(&self, late_callbacks: bool) {
+        self.state().late_callbacks = late_callbacks;
+    }
+
+    /// Deliver the oldest buffered frame each time the crate reads the available video frame
+    /// count, after the count is taken and before the read returns, as a driver emptying its
+    /// buffer between two calls of the crate does.
+    pub fn set_deliver_after_count(&self, deliver_after_count: bool) {
+        self.state().deliver_after_count = deliver_after_count;
+    }
+
+    /// Whether audio input is enabled.
+    pub fn is_audio_enabled(&self) -> bool {
+        self.state().audio.is_some()
+    }
+
+    /// Whether a callback is set.
+    pub fn has_callback(&self) -> bool {
+        self.state().callback.is_some()
+    }
+
+    /// Whether streams are started, and not paused.
+    pub fn is_streaming(&self) -> bool {
+        let state = self.state();
+        state.streaming && !state.paused
+    }
+
+    /// Whether streams are started and paused.
+    pub fn is_paused(&self) -> bool {
+        let state = self.state();
+        state.streaming && state.paused
+    }
+
+    /// A frame in the mode and pixel format video input is enabled with.
+    ///
+    /// Panics if video input is not enabled.
+    pub fn frame(&self) -> MockFrame {
+        let (mode, pixel_format, _) = self.video().expect("video input is not enabled");
+        MockFrame::for_mode(mode, pixel_format)
+    }
+
+    /// Deliver `frame` to the callback, as a frame captured while streaming. With an allocator
+    /// provider, the frame is captured into a buffer from it.
+    pub fn deliver_frame(&self, frame: MockFrame) -> Delivery {
+        self.deliver(Some(&frame), None, false)
+    }
+
+    /// Buffer `frame` in the driver, as a frame captured but not yet delivered. The
+    /// available video frame count is the number of buffered frames.
+    pub fn buffer_frame(&self, frame: MockFrame) {
+        self.state().buffered.push_back(frame);
+    }
+
+    /// The number of frames buffered by `buffer_frame` and not yet delivered or discarded.
+    pub fn buffered_frames(&self) -> usize {
+        self.state().buffered.len()
+    }
+
+    /// Deliver the oldest buffered frame to the callback. Buffered frames are delivered while
+    /// streams are paused, as they were captured before the pause.
+    pub fn deliver_buffered(&self) -> Delivery {
+        let frame = {
+            let mut state = self.state();
+            if state.callback.is_none() || !state.delivers_buffered_frames() {
+                return Delivery::NotDelivered;
+            }
+            match state.buffered.pop_front() {
+                Some(frame) => frame,
+                None => return Delivery::NotDelivered,
+            }
+        };
+        self.deliver(Some(&frame), None, true)
+    }
+
+    /// Deliver `frame` together with a packet of interleaved audio samples in one callback,
+    /// as the driver does for audio captured alongside a frame. See `deliver_frame` and
+    /// `deliver_audio`.
+    ///
+    /// Panics if audio input is not enabled.
+    pub fn deliver_frame_with_audio(&self, frame: MockFrame, bytes: &[u8]) -> Delivery {
+        self.deliver(Some(&frame), Some(bytes), false)
+    }
+
+    fn deliver(&self, frame: Option<&MockFrame>, audio: Option<&[u8]>, buffered: bool) -> Delivery {
+        let (callback, provider, allocator, packet) = {
+            let mut state = self.state();
+            let delivers = if buffered {
+                state.delivers_buffered_frames()
+            } else {
+                state.delivers_frames()
+            };
+            let callback = match state.callback {
+                Some(callback) if delivers => callback,
+                _ => return Delivery::NotDelivered,
+            };
+            let allocator = frame.and_then(|frame| {
+                let spec = spec_of(frame, state.buffer_padding);
+                state.allocators.get(&spec).copied()
+            });
+            let packet = audio.map(|bytes| state.audio_packet(bytes));
+            (callback, state.provider, allocator, packet)
+        };
+
+        let frame_ptr = match frame {
+            Some(frame) => {
+                let data = if provider.is_null() {
+                    Ok(FrameData::Owned(AVec::from_slice(64, &frame.bytes)))
+                } else {
+                    self.capture_into_buffer(frame, provider, allocator)
+                };
+                let data = match data {
+                    Ok(data) => data,
+                    // The driver drops a frame it has no buffer for
+                    Err(_) => return Delivery::NotDelivered,
+                };
+                object::create(Kind::Frame(Mutex::new(FrameState {
+                    width: frame.width,
+                    height: frame.height,
+                    row_bytes: frame.row_bytes,
+                    pixel_format: frame.pixel_format as u32,
+                    flags: frame.flags.bits(),
+                    stream_time: frame.stream_time,
+                    hardware_reference_time: frame.hardware_reference_time,
+                    timecodes: frame
+                        .timecodes
+                        .iter()
+                        .map(|(format, timecode)| (*format as u32, *timecode))
+                        .collect(),
+                    converts: frame.converts,
+                    ancillary: frame.ancillary.clone(),
+                    data,
+                })))
+            }
+            None => null_mut(),
+        };
+        let packet_ptr = match packet {
+            Some(packet) => object::create(Kind::AudioPacket(packet)),
+            None => null_mut(),
+        };
+
+        let result = match callback.frame_arrived {
+            Some(frame_arrived) => unsafe {
+                frame_arrived(callback.context, frame_ptr, packet_ptr)
+            },
+            None => 0,
+        };
+        for ptr in [frame_ptr, packet_ptr] {
+            if !ptr.is_null() {
+                unsafe { object::release(ptr) };
+            }
+        }
+        Delivery::Returned(result)
+    }
+
+    fn capture_into_buffer(
+        &self,
+        frame: &MockFrame,
+        provider: *mut c_void,
+        allocator: Option<*mut c_void>,
+    ) -> Result<FrameData, SdkError> {
+        let spec = spec_of(frame, self.state().buffer_padding);
+        let allocator = match allocator {
+            Some(allocator) => allocator,
+            None => {
+                let mut allocator = null_mut();
+                let result = unsafe {
+                    ffi::cdecklink_video_buffer_allocator_provider_get_video_buffer_allocator(
+                        provider,
+                        spec.0,
+                        spec.1,
+                        spec.2,
+                        spec.3,
+                        spec.4,
+                        &mut allocator,
+                    )
+                };
+                SdkError::result::<()>(result)?;
+                self.state().allocators.insert(spec, allocator);
+                allocator
+            }
+        };
+
+        let mut buffer = null_mut();
+        let result = unsafe {
+            ffi::cdecklink_video_buffer_allocator_allocate_video_buffer(allocator, &mut buffer)
+        };
+        SdkError::result::<()>(result)?;
+        let mut bytes = null_mut();
+        unsafe {
+            let result = ffi::cdecklink_video_buffer_start_access(buffer, 0);
+            if SdkError::is_ok(result) {
+                let result = ffi::cdecklink_video_buffer_get_bytes(buffer, &mut bytes);
+                if SdkError::is_ok(result) && !bytes.is_null() {
+                    std::ptr::copy_nonoverlapping(
+                        frame.bytes.as_ptr(),
+                        bytes as *mut u8,
+                        frame.bytes.len(),
+                    );
+                }
+                ffi::cdecklink_video_buffer_end_access(buffer, 0);
+            }
+        }
+        if bytes.is_null() {
+            unsafe { object::release(buffer) };
+            return Err(SdkError::POINTER);
+        }
+        Ok(FrameData::Buffer(buffer, bytes))
+    }
+
+    /// Deliver a packet of interleaved audio samples to the callback, in the sample type and
+    /// channel count audio input is enabled with, as a packet captured with no video frame.
+    /// The packet time counts the sample frames delivered since audio input was enabled.
+    ///
+    /// Panics if audio input is not enabled.
+    pub fn deliver_audio(&self, bytes: &[u8]) -> Delivery {
+        self.deliver(None, Some(bytes), false)
+    }
+
+    /// Move the packet time of the next audio packet on by `frames` sample frames, as for
+    /// samples lost before they reached the driver.
+    pub fn skip_audio(&self, frames: i64) {
+        self.state().audio_frames += frames;
+    }
+
+    /// Deliver a change of format to the callback, as the driver does when it detects one
+    /// while streaming with format detection enabled.
+    pub fn deliver_format_change(
+        &self,
+        events: DecklinkVideoInputFormatChangedEvents,
+        mode: DecklinkDisplayModeId,
+        flags: DecklinkDetectedVideoInputFormatFlags,
+    ) -> Delivery {
+        let callback = {
+            let state = self.state();
+            match state.callback {
+                Some(callback) if state.streaming || state.late_callbacks => callback,
+                _ => return Delivery::NotDelivered,
+            }
+        };
+        let ptr = object::create(Kind::DisplayMode(ModeInfo::of(mode)));
+        let result = match callback.format_changed {
+            Some(format_changed) => unsafe {
+                format_changed(callback.context, events.bits(), ptr, flags.bits())
+            },
+            None => 0,
+        };
+        unsafe { object::release(ptr) };
+        Delivery::Returned(result)
+    }
+}
+
+/// The driver's side of the output of a mock device.
+#[derive(Clone)]
+pub struct MockOutput {
+    device: Arc<DeviceState>,
+}
+
+impl MockOutput {
+    fn state(&self) -> MutexGuard<'_, OutputState> {
+        self.device.output()
+    }
+
+    /// The mode and flags video output is enabled with, if it is.
+    pub fn video(&self) -> Option<(DecklinkDisplayModeId, DecklinkVideoOutputFlags)> {
+        self.state().video
+    }
+
+    /// Whether audio output is enabled.
+    pub fn is_audio_enabled(&self) -> bool {
+        self.state().audio.is_some()
+    }
+
+    /// Copies of the frames displayed synchronously so far, oldest first.
+    pub fn displayed(&self) -> Vec<MockFrame> {
+        self.state().displayed.clone()
+    }
+
+    /// The number of scheduled frames that have not completed.
+    pub fn scheduled(&self) -> usize {
+        self.state().scheduled.len()
+    }
+
+    /// The display time and duration of each scheduled frame that has not completed, in the
+    /// time scale it was scheduled with.
+    pub fn scheduled_times(&self) -> Vec<(i64, i64)> {
+        self.state()
+            .scheduled
+            .iter()
+            .map(|f| (f.display_time, f.duration))
+            .collect()
+    }
+
+    /// Whether scheduled playback is running.
+    pub fn is_playing(&self) -> bool {
+        self.state().playback.is_some()
+    }
+
+    /// Complete the oldest scheduled frame with `result`, calling the completion callback as
+    /// the driver does once it has been output. Without a scheduled frame nothing is called.
+    pub fn complete_next(&self, result: DecklinkOutputFrameCompletionResult) -> Delivery {
+        let (frame, callback) = {
+            let mut state = self.state();
+            match state.scheduled.pop_front() {
+                Some(frame) => (frame.frame, state.callback),
+                None => return Delivery::NotDelivered,
+            }
+        };
+        let delivery = match callback {
+            Some(OutputCallback {
+                context,
+                completed: Some(completed),
+                ..
+            }) => Delivery::Returned(unsafe { completed(context, frame, result as u32) }),
+            _ => Delivery::NotDelivered,
+        };
+        unsafe { object::release(frame) };
+        delivery
+    }
+
+    /// Call the callback for scheduled playback having stopped.
+    pub fn playback_stopped(&self) -> Delivery {
+        match self.state().callback {
+            Some(OutputCallback {
+                context,
+                stopped: Some(stopped),
+                ..
+            }) => Delivery::Returned(unsafe { stopped(context) }),
+            _ => Delivery::NotDelivered,
+        }
+    }
+}
+
+/// The key of an allocator: buffer size, width, height, row bytes and pixel format.
+type Spec = (u32, u32, u32, u32, u32);
+
+fn spec_of(frame: &MockFrame, padding: usize) -> Spec {
+    (
+        (frame.row_bytes * frame.height + padding) as u32,
+        frame.width as u32,
+        frame.height as u32,
+        frame.row_bytes as u32,
+        frame.pixel_format as u32,
+    )
+}
+
+/// The bytes of a row of `width` pixels in `pixel_format`, as the driver lays them out.
+fn row_bytes(pixel_format: DecklinkPixelFormat, width: usize) -> usize {
+    match pixel_format {
+        DecklinkPixelFormat::Format8BitYUV => width * 2,
+        DecklinkPixelFormat::Format10BitYUV => width.div_ceil(48) * 128,
+        DecklinkPixelFormat::Format8BitARGB | DecklinkPixelFormat::Format8BitBGRA => width * 4,
+        DecklinkPixelFormat::Format10BitRGB
+        | DecklinkPixelFormat::Format10BitRGBXLE
+        | DecklinkPixelFormat::Format10BitRGBX => width.div_ceil(64) * 256,
+        DecklinkPixelFormat::Format12BitRGB | DecklinkPixelFormat::Format12BitRGBLE => {
+            width.div_ceil(8) * 36
+        }
+        DecklinkPixelFormat::FormatH265 | DecklinkPixelFormat::FormatDNxHR => 0,
+    }
+}
+
+/// Values of status ids or attributes, by the raw id.
+#[derive(Debug, Clone, Default)]
+struct Values {
+    flags: HashMap<u32, bool>,
+    ints: HashMap<u32, i64>,
+    floats: HashMap<u32, f64>,
+    strings: HashMap<u32, String>,
+    bytes: HashMap<u32, Vec<u8>>,
+}
+
+impl Values {
+    fn insert(&mut self, id: u32, value: CustomValue) {
+        match value {
+            CustomValue::Flag(v) => {
+                self.flags.insert(id, v);
+            }
+            CustomValue::Int(v) => {
+                self.ints.insert(id, v);
+            }
+            CustomValue::Float(v) => {
+                self.floats.insert(id, v);
+            }
+            CustomValue::String(v) => {
+                self.strings.insert(id, v);
+            }
+            CustomValue::Bytes(v) => {
+                self.bytes.insert(id, v);
+            }
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+struct InputCallback {
+    context: *mut c_void,
+    format_changed: sdk::cdecklink_input_callback_video_input_format_changed,
+    frame_arrived: sdk::cdecklink_input_callback_video_input_frame_arrived,
+}
+
+struct InputState {
+    modes: Vec<DecklinkDisplayModeId>,
+    pixel_formats: Vec<DecklinkPixelFormat>,
+    /// Pixel formats supported in one mode only.
+    mode_pixel_formats: Vec<(DecklinkDisplayModeId, DecklinkPixelFormat)>,
+    /// Modes reported as PsF.
+    psf_modes: Vec<DecklinkDisplayModeId>,
+    /// The number of times the crate asked whether a mode is supported.
+    support_queries: usize,
+    video: Option<(
+        DecklinkDisplayModeId,
+        DecklinkPixelFormat,
+        DecklinkVideoInputFlags,
+    )>,
+    /// The sample rate, sample type and channel count audio input is enabled with.
+    audio: Option<(u32, u32, u32)>,
+    /// Whether audio input is only enabled while video input is.
+    audio_requires_video_first: bool,
+    /// The sample frames delivered since audio input was enabled.
+    audio_frames: i64,
+    /// The provider video input was enabled with, which the input holds a reference to.
+    provider: *mut c_void,
+    /// The allocators the provider gave, which the input holds a reference to each of.
+    allocators: HashMap<Spec, *mut c_void>,
+    /// The bytes buffers are asked for beyond the size of a frame.
+    buffer_padding: usize,
+    streaming: bool,
+    paused: bool,
+    /// Frames captured but not yet delivered, which stopping or flushing the streams discards.
+    buffered: VecDeque<MockFrame>,
+    /// Whether callbacks are delivered while streams are stopped or paused.
+    late_callbacks: bool,
+    /// Whether a buffered frame is delivered each time the available video frame count is read.
+    deliver_after_count: bool,
+    callback: Option<InputCallback>,
+}
+
+// Safety: the pointers are mock objects, and the callback context is the crate's callback
+// wrapper, which are safe to use from any thread
+unsafe impl Send for InputState {}
+
+impl InputState {
+    fn delivers_frames(&self) -> bool {
+        (self.streaming && !self.paused) || self.late_callbacks
+    }
+
+    /// Whether buffered frames are delivered, which they still are while paused.
+    fn delivers_buffered_frames(&self) -> bool {
+        self.streaming || self.late_callbacks
+    }
+
+    /// A packet of `bytes` in the enabled audio format, timed after the packets before it.
+    fn audio_packet(&mut self, bytes: &[u8]) -> AudioPacket {
+        let (sample_rate, sample_type, channels) = self.audio.expect("audio input is not enabled");
+        let sample_size =
+            if sample_type == sdk::_DecklinkAudioSampleType_decklinkAudioSampleType16bitInteger {
+                2
+            } else {
+                4
+            };
+        let frames = bytes.len() / (sample_size * channels.max(1) as usize);
+        let packet = AudioPacket {
+            bytes: AVec::from_slice(64, bytes),
+            frames,
+            time: self.audio_frames,
+            sample_rate,
+        };
+        self.audio_frames += frames as i64;
+        packet
+    }
+
+    /// Disable video, returning the provider and allocators to release once unlocked, as
+    /// releasing them calls back into the crate.
+    fn disable_video(&mut self) -> Vec<*mut c_void> {
+        self.video = None;
+        let mut released: Vec<_> = self.allocators.drain().map(|(_, a)| a).collect();
+        if !self.provider.is_null() {
+            released.push(std::mem::replace(&mut self.provider, null_mut()));
+        }
+        released
+    }
+}
+
+#[derive(Copy, Clone)]
+struct OutputCallback {
+    context: *mut c_void,
+    completed: sdk::cdecklink_video_output_callback_scheduled_frame_completed,
+    stopped: sdk::cdecklink_video_output_callback_scheduled_playback_has_stopped,
+}
+
+struct ScheduledFrame {
+    /// The frame, which the output holds a reference to until it completes.
+    frame: *mut c_void,
+    display_time: i64,
+    duration: i64,
+}
+
+struct OutputState {
+    modes: Vec<DecklinkDisplayModeId>,
+    pixel_formats: Vec<DecklinkPixelFormat>,
+    /// Modes reported as PsF.
+    psf_modes: Vec<DecklinkDisplayModeId>,
+    video: Option<(DecklinkDisplayModeId, DecklinkVideoOutputFlags)>,
+    audio: Option<(u32, u32, u32, u32)>,
+    displayed: Vec<MockFrame>,
+    scheduled: VecDeque<ScheduledFrame>,
+    /// The start time, time scale and speed of scheduled playback, while it runs.
+    playback: Option<(i64, i64, f64)>,
+    callback: Option<OutputCallback>,
+}
+
+// Safety: as for InputState
+unsafe impl Send for OutputState {}
+
+impl OutputState {
+    /// Disable video, returning the scheduled frames to release once unlocked. The callback
+    /// stays registered, as with the drivers.
+    fn disable_video(&mut self) -> Vec<*mut c_void> {
+        self.video = None;
+        self.playback = None;
+        self.scheduled.drain(..).map(|f| f.frame).collect()
+    }
+}
+
+pub(crate) struct DeviceState {
+    display_name: Vec<u8>,
+    model_name: String,
+    status: Mutex<Values>,
+    attributes: Mutex<Values>,
+    /// False while the device is unplugged.
+    pub(crate) attached: AtomicBool,
+    input: Option<Mutex<InputState>>,
+    input_opens: AtomicUsize,
+    output: Option<Mutex<OutputState>>,
+    output_opens: AtomicUsize,
+}
+
+impl DeviceState {
+    fn new(device: MockDevice) -> DeviceState {
+        DeviceState {
+            display_name: device.display_name,
+            model_name: device.model_name,
+            status: Mutex::new(device.status),
+            attributes: Mutex::new(device.attributes),
+            attached: AtomicBool::new(true),
+            input: device.input.map(|(modes, pixel_formats)| {
+                Mutex::new(InputState {
+                    modes,
+                    pixel_formats,
+                    mode_pixel_formats: device.mode_pixel_formats,
+                    psf_modes: device.psf_modes.clone(),
+                    support_queries: 0,
+                    video: None,
+                    audio: None,
+                    audio_requires_video_first: device.audio_requires_video_first,
+                    audio_frames: 0,
+                    provider: null_mut(),
+                    allocators: HashMap::new(),
+                    buffer_padding: 0,
+                    streaming: false,
+                    paused: false,
+                    buffered: VecDeque::new(),
+                    late_callbacks: false,
+                    deliver_after_count: false,
+                    callback: None,
+                })
+            }),
+            input_opens: AtomicUsize::new(0),
+            output: device.output.map(|(modes, pixel_formats)| {
+                Mutex::new(OutputState {
+                    modes,
+                    pixel_formats,
+                    psf_modes: device.psf_modes,
+                    video: None,
+                    audio: None,
+                    displayed: Vec::new(),
+                    scheduled: VecDeque::new(),
+                    playback: None,
+                    callback: None,
+                })
+            }),
+            output_opens: AtomicUsize::new(0),
+        }
+    }
+
+    fn input(&self) -> MutexGuard<'_, InputState> {
+        lock(self.input.as_ref().expect("the mock device has no input"))
+    }
+
+    fn output(&self) -> MutexGuard<'_, OutputState> {
+        lock(self.output.as_ref().expect("the mock device has no output"))
+    }
+
+    pub(crate) fn input_opened(&self) {
+        self.input_opens.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// With the last reference to its input gone, the driver stops and disables it.
+    pub(crate) fn input_closed(&self) {
+        if self.input_opens.fetch_sub(1, Ordering::SeqCst) == 1 {
+            let released = {
+                let mut state = self.input();
+                state.streaming = false;
+                state.paused = false;
+                state.callback = None;
+                state.audio = None;
+                state.disable_video()
+            };
+            for ptr in released {
+                unsafe { object::release(ptr) };
+            }
+        }
+    }
+
+    pub(crate) fn output_opened(&self) {
+        self.output_opens.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// With the last reference to its output gone, the driver disables it.
+    pub(crate) fn output_closed(&self) {
+        if self.output_opens.fetch_sub(1, Ordering::SeqCst) == 1 {
+            let released = {
+                let mut state = self.output();
+                state.audio = None;
+                state.callback = None;
+                state.disable_video()
+            };
+            for ptr in released {
+                unsafe { object::release(ptr) };
+            }
+        }
+    }
+}
+
+/// What the mock reports for a display mode.
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct ModeInfo {
+    id: DecklinkDisplayModeId,
+    name: &'static str,
+    width: usize,
+    height: usize,
+    duration: i64,
+    scale: i64,
+    dominance: DecklinkFieldDominance,
+}
+
+impl ModeInfo {
+    fn of(id: DecklinkDisplayModeId) -> ModeInfo {
+        let m = id.metadata();
+        ModeInfo {
+            id,
+            name: m.name,
+            width: m.width,
+            height: m.height,
+            duration: m.frame_duration,
+            scale: m.time_scale,
+            dominance: m.field_dominance,
+        }
+    }
+
+    /// The mode as a device reporting `psf_modes` as PsF lists it.
+    fn on_device(id: DecklinkDisplayModeId, psf_modes: &[DecklinkDisplayModeId]) -> ModeInfo {
+        let mut info = ModeInfo::of(id);
+        if psf_modes.contains(&id) {
+            info.dominance = DecklinkFieldDominance::ProgressiveSegmentedFrame;
+        }
+        info
+    }
+}
+
+/// The version of the installed drivers, if any.
+pub(crate) fn api_version() -> Option<ApiVersion> {
+    *lock(&API_VERSION)
+}
+
+/// Whether allocator providers are to fail to be created.
+pub(crate) fn providers_fail() -> bool {
+    FAIL_PROVIDERS.load(Ordering::SeqCst)
+}
+
+/// The handling of callback results set with `MockBackend::set_callback_returns`, if any.
+pub(crate) fn callback_returns() -> Option<CallbackReturnHandling> {
+    *lock(&CALLBACK_RETURNS)
+}
+
+/// Whether `resource` may be created, recording it if new resources are denied.
+pub(crate) fn allow_resource(resource: &str) -> bool {
+    match lock(&DENIED).as_mut() {
+        Some(denied) => {
+            denied.push(resource.to_string());
+            false
+        }
+        None => true,
+    }
+}
+
+/// The devices an iterator created now lists.
+fn installed_devices() -> Vec<Arc<DeviceState>> {
+    lock(&DEVICES)
+        .iter()
+        .filter(|device| device.attached.load(Ordering::SeqCst))
+        .cloned()
+        .collect()
+}
@@ -0,0 +1,236 @@
+//! The objects the mock hands out in place of the driver's, behind the opaque pointers of the
+//! C bindings. Every object is reference counted as the driver's are, and is counted while it
+//! is alive so a test can check that the crate released all of them.
+
+use crate::mock::{DeviceState, ModeInfo};
+use crate::sdk;
+use crate::timecode::DecklinkTimecode;
+use aligned_vec::{AVec, ConstAlign};
+use std::collections::VecDeque;
+use std::ffi::{c_void, CString};
+use std::os::raw::{c_char, c_ulong};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// The objects and strings handed to the crate that have not been released.
+static LIVE: AtomicUsize = AtomicUsize::new(0);
+
+pub(crate) fn live() -> usize {
+    LIVE.load(Ordering::SeqCst)
+}
+
+pub(crate) struct MockObject {
+    refs: AtomicUsize,
+    pub(crate) kind: Kind,
+}
+
+// Safety: the raw pointers an object holds are the contexts and buffers of the crate's own
+// bridges, which are Send and Sync, and everything else is behind a Mutex
+unsafe impl Send for MockObject {}
+unsafe impl Sync for MockObject {}
+
+pub(crate) enum Kind {
+    Iterator(Mutex<VecDeque<Arc<DeviceState>>>),
+    ApiInformation,
+    Device(Arc<DeviceState>),
+    Input(Arc<DeviceState>),
+    Output(Arc<DeviceState>),
+    Attributes(Arc<DeviceState>),
+    Status(Arc<DeviceState>),
+    DisplayModeIterator(Mutex<VecDeque<ModeInfo>>),
+    DisplayMode(ModeInfo),
+    Frame(Mutex<FrameState>),
+    AncillaryPackets(Vec<AncillaryPacket>),
+    AncillaryPacket(AncillaryPacket),
+    AudioPacket(AudioPacket),
+    Timecode(DecklinkTimecode),
+    Provider(Provider),
+    Allocator(Allocator),
+    Buffer(Buffer),
+}
+
+/// A video frame, captured by a mock input or created by the crate.
+pub(crate) struct FrameState {
+    pub width: usize,
+    pub height: usize,
+    pub row_bytes: usize,
+    pub pixel_format: sdk::DecklinkPixelFormat,
+    pub flags: sdk::DecklinkFrameFlags,
+    /// The stream time and duration of a captured frame, in ticks of the time scale.
+    pub stream_time: Option<(i64, i64, i64)>,
+    /// The hardware reference timestamp and duration of a captured frame, in ticks of the
+    /// time scale.
+    pub hardware_reference_time: Option<(i64, i64, i64)>,
+    /// The timecodes of a captured frame, by format.
+    pub timecodes: Vec<(sdk::DecklinkTimecodeFormat, DecklinkTimecode)>,
+    /// Whether a captured frame converts to a video frame, as one does unless the driver
+    /// fails to.
+    pub converts: bool,
+    /// The ancillary packets of a captured frame.
+    pub ancillary: Vec<AncillaryPacket>,
+    pub data: FrameData,
+}
+
+/// An ancillary packet of a captured frame, with the user data words of its payload.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub(crate) struct AncillaryPacket {
+    pub did: u8,
+    pub sdid: u8,
+    pub line: u32,
+    pub bytes: Vec<u8>,
+}
+
+/// A packet of audio samples captured by a mock input.
+pub(crate) struct AudioPacket {
+    pub bytes: AVec<u8, ConstAlign<64>>,
+    pub frames: usize,
+    /// The time of the first sample frame, in ticks of the sample rate.
+    pub time: i64,
+    pub sample_rate: u32,
+}
+
+pub(crate) enum FrameData {
+    /// No bytes have been set on a frame the crate created.
+    Empty,
+    /// Bytes the mock filled in.
+    Owned(AVec<u8, ConstAlign<64>>),
+    /// A buffer from the allocator of a provider, which the frame holds a reference to.
+    Buffer(*mut c_void, *mut c_void),
+    /// Bytes the crate set, with the function to free them when the frame is released.
+    Custom {
+        bytes: *mut c_void,
+        finalizer: sdk::cdecklink_custom_video_frame_free_bytes,
+        context: *mut c_void,
+    },
+}
+
+impl FrameState {
+    pub fn bytes(&self) -> *mut c_void {
+        match &self.data {
+            FrameData::Empty => std::ptr::null_mut(),
+            FrameData::Owned(bytes) => bytes.as_ptr() as *mut c_void,
+            FrameData::Buffer(_, bytes) => *bytes,
+            FrameData::Custom { bytes, .. } => *bytes,
+        }
+    }
+
+    pub fn release_data(&mut self) {
+        match std::mem::replace(&mut self.data, FrameData::Empty) {
+            FrameData::Buffer(buffer, _) => unsafe {
+                release(buffer);
+            },
+            FrameData::Custom {
+                bytes,
+                finalizer: Some(finalizer),
+                context,
+            } => unsafe { finalizer(bytes, context) },
+            _ => {}
+        }
+    }
+}
+
+pub(crate) struct Provider {
+    pub context: *mut c_void,
+    pub get_allocator: sdk::cdecklink_custom_video_buffer_allocator_provider_get_allocator_fn,
+    pub release: sdk::cdecklink_custom_video_buffer_allocator_provider_release_fn,
+}
+
+pub(crate) struct Allocator {
+    pub context: *mut c_void,
+    pub allocate: sdk::cdecklink_custom_video_buffer_allocator_allocate_fn,
+    pub release: sdk::cdecklink_custom_video_buffer_allocator_release_fn,
+}
+
+pub(crate) struct Buffer {
+    pub context: *mut c_void,
+    pub get_bytes: sdk::cdecklink_custom_video_buffer_get_bytes_fn,
+    pub start_access: sdk::cdecklink_custom_video_buffer_start_access_fn,
+    pub end_access: sdk::cdecklink_custom_video_buffer_end_access_fn,
+    pub release: sdk::cdecklink_custom_video_buffer_release_fn,
+}
+
+/// Create an object with one reference, owned by the caller.
+pub(crate) fn create(kind: Kind) -> *mut c_void {
+    match &kind {
+        Kind::Input(device) => device.input_opened(),
+        Kind::Output(device) => device.output_opened(),
+        _ => {}
+    }
+    LIVE.fetch_add(1, Ordering::SeqCst);
+    Box::into_raw(Box::new(MockObject {
+        refs: AtomicUsize::new(1),
+        kind,
+    })) as *mut c_void
+}
+
+/// The object behind a pointer the mock handed out.
+///
+/// # Safety
+/// `ptr` must be a live object created by `create`.
+pub(crate) unsafe fn get<'a>(ptr: *mut c_void) -> &'a MockObject {
+    assert!(
+        !ptr.is_null(),
+        "a null object was passed to the mock backend"
+    );
+    &*(ptr as *const MockObject)
+}
+
+/// # Safety
+/// `ptr` must be a live object created by `create`.
+pub(crate) unsafe fn add_ref(ptr: *mut c_void) -> c_ulong {
+    (get(ptr).refs.fetch_add(1, Ordering::SeqCst) + 1) as c_ulong
+}
+
+/// Drop a reference, destroying the object with its last one.
+///
+/// # Safety
+/// `ptr` must be a live object created by `create`, and the caller must own a reference.
+pub(crate) unsafe fn release(ptr: *mut c_void) -> c_ulong {
+    let remaining = get(ptr).refs.fetch_sub(1, Ordering::SeqCst) - 1;
+    if remaining == 0 {
+        let object = Box::from_raw(ptr as *mut MockObject);
+        LIVE.fetch_sub(1, Ordering::SeqCst);
+        match &object.kind {
+            Kind::Input(device) => device.input_closed(),
+            Kind::Output(device) => device.output_closed(),
+            Kind::Frame(frame) => frame.lock().unwrap().release_data(),
+            Kind::Provider(Provider {
+                context,
+                release: Some(release),
+                ..
+            })
+            | Kind::Allocator(Allocator {
+                context,
+                release: Some(release),
+                ..
+            })
+            | Kind::Buffer(Buffer {
+                context,
+                release: Some(release),
+                ..
+            }) => release(*context),
+            _ => {}
+        }
+    }
+    remaining as c_ulong
+}
+
+/// Copy a string for the crate, which frees it with `cdecklink_free_string`.
+pub(crate) fn string(value: &str) -> *const c_char {
+    bytes_string(value.as_bytes())
+}
+
+/// A string of `bytes`, which need not be UTF-8.
+pub(crate) fn bytes_string(bytes: &[u8]) -> *const c_char {
+    LIVE.fetch_add(1, Ordering::SeqCst);
+    CString::new(bytes).unwrap_or_default().into_raw()
+}
+
+/// # Safety
+/// `ptr` must be a string created by `string` that has not been freed.
+pub(crate) unsafe fn free_string(ptr: *const c_char) {
+    if !ptr.is_null() {
+        LIVE.fetch_sub(1, Ordering::SeqCst);
+        drop(CString::from_raw(ptr as *mut c_char));
+    }
+}
@@ -0,0 +1,129 @@
+//! Echo captured frames to an output as a low rate confidence monitor.
+//!
+//! Intended for cards without hardware pass-through. The input callback offers each
+//! captured frame to an `EchoSlot`, which keeps a copy of the latest one at the configured
+//! echo rate. The thread owning the output then calls `MonitorEcho::poll` to display it.
+
+use crate::device::output::{
+    DecklinkOutputDevice, DecklinkOutputDeviceVideoSync, DecklinkVideoOutputFlags,
+};
+use crate::display_mode::DecklinkDisplayModeId;
+use crate::frame::{DecklinkVideoFrame, DecklinkVideoMutableFrame};
+use crate::SdkError;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+struct EchoSlotState {
+    frame: Option<DecklinkVideoMutableFrame>,
+    last_taken: Option<Instant>,
+}
+
+/// Holds a copy of the most recently captured frame, decimated to the echo rate.
+///
+/// This is safe to share with an input callback.
+pub struct EchoSlot {
+    interval: Duration,
+    state: Mutex<EchoSlotState>,
+}
+
+impl EchoSlot {
+    fn new(interval: Duration) -> EchoSlot {
+        EchoSlot {
+            interval,
+            state: Mutex::new(EchoSlotState {
+                frame: None,
+                last_taken: None,
+            }),
+        }
+    }
+
+    /// Offer a captured frame. The frame is only copied if the echo interval has elapsed
+    /// since the previous copy. Returns whether the frame was retained.
+    pub fn offer(&self, frame: &DecklinkVideoFrame) -> Result<bool, SdkError> {
+        let mut state = self.state.lock().map_err(|_| SdkError::HANDLE)?;
+        let now = Instant::now();
+        if let Some(last) = state.last_taken {
+            if now.duration_since(last) < self.interval {
+                return Ok(false);
+            }
+        }
+
+        state.frame = Some(DecklinkVideoMutableFrame::copy_from(frame)?);
+        state.last_taken = Some(now);
+        Ok(true)
+    }
+
+    fn take(&self) -> Option<DecklinkVideoMutableFrame> {
+        self.state.lock().ok().and_then(|mut s| s.frame.take())
+    }
+}
+
+/// Displays the latest captured frame on an output using synchronous playback.
+///
+/// Output is disabled when this is dropped or suspended.
+pub struct MonitorEcho {
+    mode: DecklinkDisplayModeId,
+    slot: Arc<EchoSlot>,
+    video: Option<Box<dyn DecklinkOutputDeviceVideoSync>>,
+}
+
+impl MonitorEcho {
+    /// Enable synchronous video output in `mode` and echo frames at `echo_fps` frames per second.
+    ///
+    /// Fails with `SdkError::ACCESSDENIED` if the output is already in use.
+    pub fn new(
+        output: &DecklinkOutputDevice,
+        mode: DecklinkDisplayModeId,
+        echo_fps: f64,
+    ) -> Result<MonitorEcho, SdkError> {
+        if echo_fps <= 0.0 || !echo_fps.is_finite() {
+            return Err(SdkError::INVALIDARG);
+        }
+
+        let video = output.enable_video_output_sync(mode, DecklinkVideoOutputFlags::empty())?;
+        Ok(MonitorEcho {
+            mode,
+            slot: Arc::new(EchoSlot::new(Duration::from_secs_f64(1.0 / echo_fps))),
+            video: Some(video),
+        })
+    }
+
+    /// The slot that captured frames should be offered to.
+    pub fn slot(&self) -> Arc<EchoSlot> {
+        self.slot.clone()
+    }
+
+    /// Whether the echo has given up the output.
+    pub fn is_suspended(&self) -> bool {
+        self.video.is_none()
+    }
+
+    /// Display the latest retained frame, if there is a new one.
+    /// Returns whether a frame was displayed. Does nothing while suspended.
+    pub fn poll(&self) -> Result<bool, SdkError> {
+        if let Some(video) = &self.video {
+            if let Some(frame) = self.slot.take() {
+                video.display_frame_copy(&frame)?;
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Disable video output so that something else can use it.
+    pub fn suspend(&mut self) {
+        self.video = None;
+    }
+
+    /// Re-enable video output after a `suspend`.
+    ///
+    /// Fails with `SdkError::ACCESSDENIED` if the output is still in use.
+    pub fn resume(&mut self, output: &DecklinkOutputDevice) -> Result<(), SdkError> {
+        if self.video.is_none() {
+            self.video = Some(
+                output.enable_video_output_sync(self.mode, DecklinkVideoOutputFlags::empty())?,
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,977 @@
+//! Several inputs composited into one picture, as a multiviewer shows them.
+//!
+//! A `Multiviewer` lays tiles out over an output frame by a `MultiviewLayout`, either a grid
+//! or rectangles placed by the application. Each tile is fed by a `TileFeed`, attached to
+//! the `crate::tap::TapSplitter` of an input as the consumer of a tap with no end. The feed
+//! converts the frames it is given with `crate::still`, scales them to fit the tile at their
+//! display aspect ratio with the scaler of `crate::thumbnail`, all on the tap's thread, and
+//! keeps only the latest picture. A feed given frames faster than the output is composited
+//! skips the frames in between rather than scaling them.
+//!
+//! The compositor thread composites at the fixed rate of `MultiviewConfig::frame_interval`,
+//! whatever the rates of the sources. It draws into the back one of two frames, gives it
+//! to the `MultiviewOutput`, and then swaps it to the front, where `Multiviewer::latest`
+//! reads it. It only takes a tile's lock for long enough to take its latest picture, so a
+//! slow or stalled source never holds up the output. Ticks that fall due while a composite
+//! is still being drawn or output are skipped, and counted in `MultiviewStats::missed`.
+//!
+//! A tile shows a slate with a red border instead of its picture once its source has
+//! stalled for `MultiviewConfig::signal_timeout`, as soon as a frame arrives flagged with
+//! no input source, and while no feed is attached to it. A feed is detached when its tap
+//! finishes, such as when its device is removed, and a new feed can be made for the tile
+//! when it comes back. Each tile is labelled in its top left corner, in a small built-in
+//! bitmap font that has upper case letters, digits and a little punctuation.
+//!
+//! Composites are 8-bit BGRA. They can be given to a function, or written raw to a file or
+//! a pipe with `RawOutput`, such as to the standard input of an encoder.
+
+use crate::config::ConfigError;
+use crate::display_mode::Ratio;
+use crate::frame::{DecklinkFrameBase, DecklinkFrameFlags};
+use crate::still::{to_sdr_image, SourceColor, ToneMapOperator};
+use crate::tap::{DeckLinkTapCallback, TapReport, TappedFrame};
+use crate::threads::{self, ThreadHandle};
+use crate::thumbnail::scale;
+use crate::util::internal_thread_started;
+use image::RgbImage;
+use std::io::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// The colour outside the tiles, and around pictures that do not fill their tile.
+const BACKGROUND: [u8; 3] = [0, 0, 0];
+const SLATE: [u8; 3] = [48, 48, 48];
+const SLATE_TEXT: [u8; 3] = [192, 192, 192];
+const BORDER: [u8; 3] = [255, 0, 0];
+const LABEL_BOX: [u8; 3] = [0, 0, 0];
+const LABEL_TEXT: [u8; 3] = [255, 255, 255];
+
+/// The gap between the edges of a tile and its label.
+const LABEL_MARGIN: u32 = 4;
+
+/// The text of the slate.
+const SLATE_LABEL: &str = "NO SIGNAL";
+
+/// The width and height of a glyph of the built-in font, and the width of a character,
+/// which includes the gap after it.
+const GLYPH_WIDTH: u32 = 5;
+const GLYPH_HEIGHT: u32 = 7;
+const CHAR_WIDTH: u32 = 6;
+
+/// A rectangle of the output frame, in pixels.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+pub struct TileRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl TileRect {
+    pub fn new(x: u32, y: u32, width: u32, height: u32) -> TileRect {
+        TileRect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// Whether the pixel at `x`, `y` is in the rectangle.
+    pub fn contains(&self, x: u32, y: u32) -> bool {
+        x >= self.x && y >= self.y && x - self.x < self.width && y - self.y < self.height
+    }
+}
+
+/// Where the tiles of a multiviewer are.
+#[derive(PartialEq, Eq, Debug, Clone, Hash)]
+pub enum MultiviewLayout {
+    /// `columns` by `rows` tiles of the same size, numbered a row at a time from the top
+    /// left.
+    Grid { columns: u32, rows: u32 },
+    /// Tiles at these positions, which may overlap. Later tiles are drawn over earlier ones.
+    Rects(Vec<TileRect>),
+}
+
+impl MultiviewLayout {
+    /// Four tiles in a 2x2 grid.
+    pub fn quad() -> MultiviewLayout {
+        MultiviewLayout::grid(2, 2)
+    }
+
+    /// Nine tiles in a 3x3 grid.
+    pub fn nine() -> MultiviewLayout {
+        MultiviewLayout::grid(3, 3)
+    }
+
+    /// Sixteen tiles in a 4x4 grid.
+    pub fn sixteen() -> MultiviewLayout {
+        MultiviewLayout::grid(4, 4)
+    }
+
+    pub fn grid(columns: u32, rows: u32) -> MultiviewLayout {
+        MultiviewLayout::Grid { columns, rows }
+    }
+
+    pub fn tile_count(&self) -> usize {
+        match self {
+            MultiviewLayout::Grid { columns, rows } => *columns as usize * *rows as usize,
+            MultiviewLayout::Rects(rects) => rects.len(),
+        }
+    }
+
+    /// The tiles of an output frame `width` by `height` pixels. Grid tiles are as equal
+    /// as the pixels allow, and cover the frame without gaps.
+    pub fn tiles(&self, width: u32, height: u32) -> Vec<TileRect> {
+        match self {
+            MultiviewLayout::Grid { columns, rows } => {
+                let (columns, rows) = (*columns as u64, *rows as u64);
+                let edge = |i: u64, count: u64, length: u32| (i * length as u64 / count) as u32;
+                let mut tiles = Vec::with_capacity(self.tile_count());
+                for row in 0..rows {
+                    let (top, bottom) = (edge(row, rows, height), edge(row + 1, rows, height));
+                    for column in 0..columns {
+                        let left = edge(column, columns, width);
+                        let right = edge(column + 1, columns, width);
+                        tiles.push(TileRect::new(left, top, right - left, bottom - top));
+                    }
+                }
+                tiles
+            }
+            MultiviewLayout::Rects(rects) => rects.clone(),
+        }
+    }
+}
+
+/// How a multiviewer composites.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct MultiviewConfig {
+    /// The size of the output frame.
+    pub width: u32,
+    pub height: u32,
+    pub layout: MultiviewLayout,
+    /// How often the compositor thread composites, which sets the output rate.
+    pub frame_interval: Duration,
+    /// How long a tile keeps showing the last picture of a source that has stopped
+    /// delivering frames, before it shows the slate.
+    pub signal_timeout: Duration,
+    /// Whether tiles are labelled.
+    pub labels: bool,
+}
+
+impl Default for MultiviewConfig {
+    fn default() -> Self {
+        MultiviewConfig {
+            width: 1920,
+            height: 1080,
+            layout: MultiviewLayout::quad(),
+            frame_interval: Duration::from_millis(40),
+            signal_timeout: Duration::from_millis(500),
+            labels: true,
+        }
+    }
+}
+
+impl MultiviewConfig {
+    pub fn builder() -> MultiviewConfigBuilder {
+        MultiviewConfigBuilder {
+            config: MultiviewConfig::default(),
+        }
+    }
+
+    /// Check that a multiviewer could composite with this config.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let error = |field, problem| Err(ConfigError::new("MultiviewConfig", field, problem));
+        if self.width == 0 {
+            return error("width", "must be at least 1");
+        }
+        if self.height == 0 {
+            return error("height", "must be at least 1");
+        }
+        match &self.layout {
+            MultiviewLayout::Grid { columns, rows } => {
+                if *columns == 0 || *rows == 0 {
+                    return error("layout", "must have at least 1 column and 1 row");
+                }
+                if *columns > self.width || *rows > self.height {
+                    return error("layout", "must not have more columns or rows than pixels");
+                }
+            }
+            MultiviewLayout::Rects(rects) => {
+                if rects.is_empty() {
+                    return error("layout", "must have at least 1 tile");
+                }
+                let outside = |r: &TileRect| {
+                    r.x as u64 + r.width as u64 > self.width as u64
+                        || r.y as u64 + r.height as u64 > self.height as u64
+                };
+                if rects.iter().any(|r| r.width == 0 || r.height == 0) {
+                    return error("layout", "must not have empty tiles");
+                }
+                if rects.iter().any(outside) {
+                    return error("layout", "must not have tiles outside the output frame");
+                }
+            }
+        }
+        if self.frame_interval.is_zero() {
+            return error("frame_interval", "must be longer than zero");
+        }
+        if self.signal_timeout.is_zero() {
+            return error("signal_timeout", "must be longer than zero");
+        }
+        Ok(())
+    }
+}
+
+/// Builds a `MultiviewConfig`, starting from the default.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct MultiviewConfigBuilder {
+    config: MultiviewConfig,
+}
+
+impl MultiviewConfigBuilder {
+    pub fn size(mut self, width: u32, height: u32) -> Self {
+        self.config.width = width;
+        self.config.height = height;
+        self
+    }
+
+    pub fn layout(mut self, layout: MultiviewLayout) -> Self {
+        self.config.layout = layout;
+        self
+    }
+
+    pub fn frame_interval(mut self, frame_interval: Duration) -> Self {
+        self.config.frame_interval = frame_interval;
+        self
+    }
+
+    pub fn signal_timeout(mut self, signal_timeout: Duration) -> Self {
+        self.config.signal_timeout = signal_timeout;
+        self
+    }
+
+    pub fn labels(mut self, labels: bool) -> Self {
+        self.config.labels = labels;
+        self
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        self.config.validate()
+    }
+
+    pub fn build(self) -> Result<MultiviewConfig, ConfigError> {
+        self.validate()?;
+        Ok(self.config)
+    }
+}
+
+/// A composited frame.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct CompositeFrame {
+    pub width: u32,
+    pub height: u32,
+    /// The pixels, in 8-bit BGRA with the rows packed.
+    pub data: Vec<u8>,
+    /// The number of frames the compositor thread composited before this one. Frames
+    /// composited with `Multiviewer::composite` are numbered zero.
+    pub sequence: u64,
+    /// When the frame was composited.
+    pub composited_at: Instant,
+}
+
+impl CompositeFrame {
+    fn blank(width: u32, height: u32) -> CompositeFrame {
+        CompositeFrame {
+            width,
+            height,
+            data: vec![0; width as usize * height as usize * 4],
+            sequence: 0,
+            composited_at: Instant::now(),
+        }
+    }
+
+    pub fn row_bytes(&self) -> usize {
+        self.width as usize * 4
+    }
+
+    /// The blue, green, red and alpha of the pixel at `x`, `y`.
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        let offset = y as usize * self.row_bytes() + x as usize * 4;
+        let mut pixel = [0; 4];
+        pixel.copy_from_slice(&self.data[offset..offset + 4]);
+        pixel
+    }
+
+    /// The red, green and blue of the pixel at `x`, `y`.
+    pub fn rgb(&self, x: u32, y: u32) -> [u8; 3] {
+        let [b, g, r, _] = self.pixel(x, y);
+        [r, g, b]
+    }
+
+    fn put(&mut self, x: u32, y: u32, [r, g, b]: [u8; 3]) {
+        let offset = y as usize * self.row_bytes() + x as usize * 4;
+        self.data[offset..offset + 4].copy_from_slice(&[b, g, r, 255]);
+    }
+
+    /// Fill `rect`, clipped to `within`.
+    fn fill(&mut self, rect: TileRect, within: TileRect, color: [u8; 3]) {
+        let Some(rect) = clip(rect, within) else {
+            return;
+        };
+        for y in rect.y..rect.y + rect.height {
+            for x in rect.x..rect.x + rect.width {
+                self.put(x, y, color);
+            }
+        }
+    }
+}
+
+/// Where composited frames go. Called on the compositor thread with each frame as soon as
+/// it is composited.
+pub trait MultiviewOutput: Send {
+    fn composited(&mut self, frame: &CompositeFrame) -> std::io::Result<()>;
+}
+
+impl<F: FnMut(&CompositeFrame) + Send> MultiviewOutput for F {
+    fn composited(&mut self, frame: &CompositeFrame) -> std::io::Result<()> {
+        self(frame);
+        Ok(())
+    }
+}
+
+/// Writes each composite as raw BGRA, one frame after another with nothing between, as
+/// `ffmpeg -f rawvideo -pix_fmt bgra` reads.
+pub struct RawOutput<W> {
+    writer: W,
+}
+
+impl<W: Write + Send> RawOutput<W> {
+    pub fn new(writer: W) -> RawOutput<W> {
+        RawOutput { writer }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write + Send> MultiviewOutput for RawOutput<W> {
+    fn composited(&mut self, frame: &CompositeFrame) -> std::io::Result<()> {
+        self.writer.write_all(&frame.data)?;
+        self.writer.flush()
+    }
+}
+
+/// What a tile is showing.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+pub enum TileSignal {
+    /// The latest picture of its source.
+    Live,
+    /// The slate, because the latest frame of its source was flagged with no input source.
+    NoSignal,
+    /// The slate, because its source has delivered no frame that could be shown for the
+    /// signal timeout.
+    Stalled,
+    /// The slate, because no feed is attached to it.
+    Detached,
+}
+
+/// Counts of what a tile has been given.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct TileStats {
+    pub label: String,
+    pub signal: TileSignal,
+    /// Frames given to the tile's feed.
+    pub frames: u64,
+    /// Frames converted and scaled for the tile.
+    pub scaled: u64,
+    /// Frames that arrived within a frame interval of the last scaled frame, and were not
+    /// scaled.
+    pub skipped: u64,
+    /// Frames that could not be converted.
+    pub failed: u64,
+    /// How long ago the latest frame arrived, if one has.
+    pub last_frame_age: Option<Duration>,
+}
+
+/// Counts of what a multiviewer has done.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct MultiviewStats {
+    /// Frames composited by the compositor thread.
+    pub composited: u64,
+    /// Ticks skipped because the compositor thread was still busy with an earlier one.
+    pub missed: u64,
+    /// Composites the output failed to take.
+    pub output_errors: u64,
+    /// The most recent output failure, if there was one.
+    pub last_output_error: Option<String>,
+    pub tiles: Vec<TileStats>,
+}
+
+/// The latest picture of a tile, scaled to fit it, and where in the tile it goes.
+struct Picture {
+    image: RgbImage,
+    x: u32,
+    y: u32,
+}
+
+#[derive(Default)]
+struct Tile {
+    label: String,
+    /// Which feed the tile takes frames from, counting the feeds made for it.
+    feed: u64,
+    attached: bool,
+    picture: Option<Arc<Picture>>,
+    last_frame: Option<Instant>,
+    no_signal: bool,
+    frames: u64,
+    scaled: u64,
+    skipped: u64,
+    failed: u64,
+}
+
+impl Tile {
+    fn signal(&self, now: Instant, timeout: Duration) -> TileSignal {
+        if !self.attached {
+            return TileSignal::Detached;
+        }
+        if self.no_signal {
+            return TileSignal::NoSignal;
+        }
+        match self.last_frame {
+            Some(last) if now.saturating_duration_since(last) < timeout => {
+                if self.picture.is_some() {
+                    TileSignal::Live
+                } else {
+                    TileSignal::Stalled
+                }
+            }
+            _ => TileSignal::Stalled,
+        }
+    }
+}
+
+struct Shared {
+    config: MultiviewConfig,
+    rects: Vec<TileRect>,
+    tiles: Vec<Mutex<Tile>>,
+    /// The most recent composite of the compositor thread.
+    front: Mutex<Option<CompositeFrame>>,
+    stopping: Mutex<bool>,
+    stop_requested: Condvar,
+    composited: AtomicU64,
+    missed: AtomicU64,
+    output_errors: AtomicU64,
+    last_output_error: Mutex<Option<String>>,
+}
+
+impl Shared {
+    fn composite_into(&self, frame: &mut CompositeFrame, now: Instant) {
+        for pixel in frame.data.chunks_exact_mut(4) {
+            pixel.copy_from_slice(&[BACKGROUND[2], BACKGROUND[1], BACKGROUND[0], 255]);
+        }
+        for (tile, &rect) in self.tiles.iter().zip(&self.rects) {
+            // Only the picture is taken under the lock, so feeds are never held up for long
+            let (signal, picture, label) = {
+                let tile = tile.lock().unwrap();
+                let signal = tile.signal(now, self.config.signal_timeout);
+                (signal, tile.picture.clone(), tile.label.clone())
+            };
+            match (signal, picture) {
+                (TileSignal::Live, Some(picture)) => draw_picture(frame, rect, &picture),
+                _ => draw_slate(frame, rect),
+            }
+            if self.config.labels && !label.is_empty() {
+                let (label_rect, scale) = label_rect(rect, &label);
+                frame.fill(label_rect, rect, LABEL_BOX);
+                draw_text(
+                    frame,
+                    label_rect.x + scale,
+                    label_rect.y + scale,
+                    scale,
+                    &label,
+                    rect,
+                    LABEL_TEXT,
+                );
+            }
+        }
+        frame.composited_at = now;
+    }
+
+    fn stats(&self) -> MultiviewStats {
+        let now = Instant::now();
+        let tiles = self
+            .tiles
+            .iter()
+            .map(|tile| {
+                let tile = tile.lock().unwrap();
+                TileStats {
+                    label: tile.label.clone(),
+                    signal: tile.signal(now, self.config.signal_timeout),
+                    frames: tile.frames,
+                    scaled: tile.scaled,
+                    skipped: tile.skipped,
+                    failed: tile.failed,
+                    last_frame_age: tile.last_frame.map(|t| now.saturating_duration_since(t)),
+                }
+            })
+            .collect();
+        MultiviewStats {
+            composited: self.composited.load(Ordering::Acquire),
+            missed: self.missed.load(Ordering::Acquire),
+            output_errors: self.output_errors.load(Ordering::Acquire),
+            last_output_error: self.last_output_error.lock().unwrap().clone(),
+            tiles,
+        }
+    }
+}
+
+/// Composites the pictures of several inputs into one frame at a fixed rate.
+///
+/// ```no_run
+/// # use decklink::multiview::*;
+/// # use decklink::tap::{TapSpec, TapSplitter};
+/// # fn run(splitters: &[TapSplitter]) -> Result<(), decklink::config::ConfigError> {
+/// let mut viewer = Multiviewer::new(MultiviewConfig::default())?;
+/// let taps: Vec<_> = splitters
+///     .iter()
+///     .enumerate()
+///     .map(|(i, splitter)| {
+///         let feed = viewer.feed(i, &format!("CAM {}", i + 1));
+///         splitter.tap(feed, TapSpec::default())
+///     })
+///     .collect();
+/// viewer.start(RawOutput::new(std::io::stdout()));
+/// # Ok(())
+/// # }
+/// ```
+pub struct Multiviewer {
+    shared: Arc<Shared>,
+    compositor: Option<ThreadHandle>,
+}
+
+impl Multiviewer {
+    /// Create a multiviewer, with no feeds attached to its tiles. It composites once
+    /// started.
+    pub fn new(config: MultiviewConfig) -> Result<Multiviewer, ConfigError> {
+        config.validate()?;
+        let rects = config.layout.tiles(config.width, config.height);
+        let tiles = rects.iter().map(|_| Mutex::default()).collect();
+        Ok(Multiviewer {
+            shared: Arc::new(Shared {
+                config,
+                rects,
+                tiles,
+                front: Mutex::new(None),
+                stopping: Mutex::new(false),
+                stop_requested: Condvar::new(),
+                composited: AtomicU64::new(0),
+                missed: AtomicU64::new(0),
+                output_errors: AtomicU64::new(0),
+                last_output_error: Mutex::new(None),
+            }),
+            compositor: None,
+        })
+    }
+
+    pub fn config(&self) -> &MultiviewConfig {
+        &self.shared.config
+    }
+
+    /// Where each tile is in the output frame.
+    pub fn tiles(&self) -> &[TileRect] {
+        &self.shared.rects
+    }
+
+    /// Where the label of `tile` is drawn, if it has one and labels are on. The label is
+    /// clipped to the tile.
+    pub fn label_rect(&self, tile: usize) -> Option<TileRect> {
+        let label = self.shared.tiles[tile].lock().unwrap().label.clone();
+        if !self.shared.config.labels || label.is_empty() {
+            return None;
+        }
+        let rect = self.shared.rects[tile];
+        let (label_rect, _) = label_rect(rect, &label);
+        clip(label_rect, rect)
+    }
+
+    /// Make a feed for `tile`, labelled `label`, to be attached to a tap. A feed made
+    /// earlier for the tile is detached from it, and the frames it is given from then on
+    /// are dropped. Panics if there is no such tile.
+    pub fn feed(&self, tile: usize, label: &str) -> TileFeed {
+        let mut state = self.shared.tiles[tile].lock().unwrap();
+        state.feed += 1;
+        state.attached = true;
+        state.label = label.to_string();
+        state.picture = None;
+        state.last_frame = None;
+        state.no_signal = false;
+        TileFeed {
+            shared: self.shared.clone(),
+            tile,
+            feed: state.feed,
+            last_scaled: None,
+            pixel_aspect: Ratio::SQUARE,
+        }
+    }
+
+    /// Start the compositor thread, which gives each composite to `output`. Does nothing
+    /// if it is already running.
+    pub fn start(&mut self, output: impl MultiviewOutput + 'static) {
+        if self.compositor.is_some() {
+            return;
+        }
+        *self.shared.stopping.lock().unwrap() = false;
+        let shared = self.shared.clone();
+        self.compositor = Some(threads::spawn("multiview", move || {
+            run_compositor(&shared, output)
+        }));
+    }
+
+    /// Stop the compositor thread once it has output the composite in hand.
+    pub fn stop(&mut self) {
+        *self.shared.stopping.lock().unwrap() = true;
+        self.shared.stop_requested.notify_all();
+        if let Some(compositor) = self.compositor.take() {
+            let _ = compositor.join();
+        }
+    }
+
+    /// Composite a frame now, on the calling thread, as the compositor thread would.
+    pub fn composite(&self) -> CompositeFrame {
+        let config = &self.shared.config;
+        let mut frame = CompositeFrame::blank(config.width, config.height);
+        self.shared.composite_into(&mut frame, Instant::now());
+        frame
+    }
+
+    /// A copy of the most recent composite of the compositor thread, if it has made one.
+    pub fn latest(&self) -> Option<CompositeFrame> {
+        self.shared.front.lock().unwrap().clone()
+    }
+
+    pub fn stats(&self) -> MultiviewStats {
+        self.shared.stats()
+    }
+}
+
+impl Drop for Multiviewer {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+fn run_compositor(shared: &Shared, mut output: impl MultiviewOutput) {
+    internal_thread_started("multiview");
+    let config = &shared.config;
+    let interval = config.frame_interval;
+    let mut back = CompositeFrame::blank(config.width, config.height);
+    let mut sequence = 0;
+    let mut due = Instant::now();
+    loop {
+        {
+            let mut stopping = shared.stopping.lock().unwrap();
+            loop {
+                if *stopping {
+                    return;
+                }
+                let now = Instant::now();
+                if now >= due {
+                    break;
+                }
+                stopping = shared
+                    .stop_requested
+                    .wait_timeout(stopping, due - now)
+                    .unwrap()
+                    .0;
+            }
+        }
+
+        shared.composite_into(&mut back, Instant::now());
+        back.sequence = sequence;
+        sequence += 1;
+        if let Err(e) = output.composited(&back) {
+            shared.output_errors.fetch_add(1, Ordering::AcqRel);
+            *shared.last_output_error.lock().unwrap() = Some(e.to_string());
+        }
+        shared.composited.fetch_add(1, Ordering::AcqRel);
+        {
+            let mut front = shared.front.lock().unwrap();
+            match front.as_mut() {
+                Some(front) => std::mem::swap(front, &mut back),
+                None => *front = Some(back.clone()),
+            }
+        }
+
+        // Ticks that fell due while compositing are skipped, keeping to the original cadence
+        due += interval;
+        let now = Instant::now();
+        if now > due {
+            let behind = (now - due).as_nanos() / interval.as_nanos();
+            let behind = u32::try_from(behind).unwrap_or(u32::MAX);
+            if behind > 0 {
+                shared.missed.fetch_add(behind as u64, Ordering::AcqRel);
+                due += interval.saturating_mul(behind);
+            }
+        }
+    }
+}
+
+/// Feeds the frames of a tap to a tile of a `Multiviewer`.
+pub struct TileFeed {
+    shared: Arc<Shared>,
+    tile: usize,
+    feed: u64,
+    /// When the last frame scaled for the tile arrived.
+    last_scaled: Option<Instant>,
+    pixel_aspect: Ratio,
+}
+
+impl TileFeed {
+    /// Fit pictures to the tile at their display aspect ratio, for sources whose pixels are
+    /// `pixel_aspect` rather than square, such as those of
+    /// `crate::display_mode::DecklinkDisplayMode::pixel_aspect_ratio`.
+    pub fn with_pixel_aspect(mut self, pixel_aspect: Ratio) -> TileFeed {
+        self.pixel_aspect = pixel_aspect;
+        self
+    }
+
+    /// Mark the tile detached, unless another feed has been made for it since.
+    fn detach(&self) {
+        let mut tile = self.shared.tiles[self.tile].lock().unwrap();
+        if tile.feed == self.feed {
+            tile.attached = false;
+            tile.picture = None;
+        }
+    }
+
+    fn picture(&self, frame: &TappedFrame) -> Option<Picture> {
+        let rect = self.shared.rects[self.tile];
+        let source = SourceColor::sdr_for_height(frame.height());
+        let image = to_sdr_image(frame, source, ToneMapOperator::default()).ok()?;
+        let display_width = (image.width() as f64 * self.pixel_aspect.to_f64()).round() as u32;
+        let (width, height) = fit(display_width, image.height(), rect.width, rect.height);
+        Some(Picture {
+            image: scale(&image, None, width, height),
+            x: (rect.width - width) / 2,
+            y: (rect.height - height) / 2,
+        })
+    }
+}
+
+impl DeckLinkTapCallback for TileFeed {
+    fn frame_tapped(&mut self, frame: TappedFrame) {
+        {
+            let mut tile = self.shared.tiles[self.tile].lock().unwrap();
+            if tile.feed != self.feed {
+                return;
+            }
+            tile.frames += 1;
+            tile.last_frame = Some(frame.arrived);
+            tile.no_signal = frame
+                .flags()
+                .contains(DecklinkFrameFlags::HAS_NO_INPUT_SOURCE);
+            if tile.no_signal {
+                return;
+            }
+            let interval = self.shared.config.frame_interval;
+            if let Some(last) = self.last_scaled {
+                if frame.arrived.saturating_duration_since(last) < interval {
+                    tile.skipped += 1;
+                    return;
+                }
+            }
+        }
+
+        // Converted and scaled without the lock, which the compositor takes every tick
+        let picture = self.picture(&frame);
+        let mut tile = self.shared.tiles[self.tile].lock().unwrap();
+        if tile.feed != self.feed {
+            return;
+        }
+        match picture {
+            Some(picture) => {
+                tile.picture = Some(Arc::new(picture));
+                tile.scaled += 1;
+                self.last_scaled = Some(frame.arrived);
+            }
+            None => tile.failed += 1,
+        }
+    }
+
+    fn tap_finished(&mut self, _report: TapReport) {
+        self.detach();
+    }
+}
+
+impl Drop for TileFeed {
+    fn drop(&mut self) {
+        self.detach();
+    }
+}
+
+/// The largest size with the aspect ratio of `width` by `height` that fits in `max_width`
+/// by `max_height`.
+fn fit(width: u32, height: u32, max_width: u32, max_height: u32) -> (u32, u32) {
+    if width == 0 || height == 0 {
+        return (max_width, max_height);
+    }
+    let scale = (max_width as f64 / width as f64).min(max_height as f64 / height as f64);
+    (
+        ((width as f64 * scale).round() as u32).clamp(1, max_width),
+        ((height as f64 * scale).round() as u32).clamp(1, max_height),
+    )
+}
+
+/// The part of `rect` inside `clip`, if any of it is.
+fn clip(rect: TileRect, clip: TileRect) -> Option<TileRect> {
+    let left = rect.x.max(clip.x);
+    let top = rect.y.max(clip.y);
+    let right = (rect.x + rect.width).min(clip.x + clip.width);
+    let bottom = (rect.y + rect.height).min(clip.y + clip.height);
+    (right > left && bottom > top).then(|| TileRect::new(left, top, right - left, bottom - top))
+}
+
+/// The scale the built-in font is drawn at in a tile `height` pixels tall.
+fn text_scale(height: u32) -> u32 {
+    (height / 120).clamp(1, 4)
+}
+
+/// The box the label of a tile at `rect` is drawn in, before clipping, and the scale of its
+/// text. The text is inset in the box by one scaled pixel.
+fn label_rect(rect: TileRect, label: &str) -> (TileRect, u32) {
+    let scale = text_scale(rect.height);
+    let chars = label.chars().count() as u32;
+    let label_rect = TileRect::new(
+        rect.x + LABEL_MARGIN,
+        rect.y + LABEL_MARGIN,
+        (chars * CHAR_WIDTH + 1) * scale,
+        (GLYPH_HEIGHT + 2) * scale,
+    );
+    (label_rect, scale)
+}
+
+fn draw_picture(frame: &mut CompositeFrame, rect: TileRect, picture: &Picture) {
+    let (width, height) = picture.image.dimensions();
+    let raw = picture.image.as_raw();
+    let row_bytes = frame.row_bytes();
+    for y in 0..height.min(rect.height.saturating_sub(picture.y)) {
+        let source = &raw[(y * width * 3) as usize..((y + 1) * width * 3) as usize];
+        let start =
+            (rect.y + picture.y + y) as usize * row_bytes + (rect.x + picture.x) as usize * 4;
+        let row = &mut frame.data[start..start + width as usize * 4];
+        for (out, rgb) in row.chunks_exact_mut(4).zip(source.chunks_exact(3)) {
+            out.copy_from_slice(&[rgb[2], rgb[1], rgb[0], 255]);
+        }
+    }
+}
+
+fn draw_slate(frame: &mut CompositeFrame, rect: TileRect) {
+    frame.fill(rect, rect, SLATE);
+    let scale = text_scale(rect.height);
+    let text_width = (SLATE_LABEL.len() as u32 * CHAR_WIDTH - 1) * scale;
+    let x = rect.x + rect.width.saturating_sub(text_width) / 2;
+    let y = rect.y + rect.height.saturating_sub(GLYPH_HEIGHT * scale) / 2;
+    draw_text(frame, x, y, scale, SLATE_LABEL, rect, SLATE_TEXT);
+
+    let thickness = (rect.height.min(rect.width) / 60).max(2);
+    let (right, bottom) = (rect.x + rect.width, rect.y + rect.height);
+    let edges = [
+        TileRect::new(rect.x, rect.y, rect.width, thickness),
+        TileRect::new(
+            rect.x,
+            bottom.saturating_sub(thickness),
+            rect.width,
+            thickness,
+        ),
+        TileRect::new(rect.x, rect.y, thickness, rect.height),
+        TileRect::new(
+            right.saturating_sub(thickness),
+            rect.y,
+            thickness,
+            rect.height,
+        ),
+    ];
+    for edge in edges {
+        frame.fill(edge, rect, BORDER);
+    }
+}
+
+/// Draw `text` with its top left corner at `x`, `y`, clipped to `clip`.
+fn draw_text(
+    frame: &mut CompositeFrame,
+    x: u32,
+    y: u32,
+    scale: u32,
+    text: &str,
+    clip: TileRect,
+    color: [u8; 3],
+) {
+    for (i, c) in text.chars().enumerate() {
+        let left = x + i as u32 * CHAR_WIDTH * scale;
+        for (row, bits) in glyph(c).iter().enumerate() {
+            for column in 0..GLYPH_WIDTH {
+                if bits & (1 << (GLYPH_WIDTH - 1 - column)) == 0 {
+                    continue;
+                }
+                let dot =
+                    TileRect::new(left + column * scale, y + row as u32 * scale, scale, scale);
+                frame.fill(dot, clip, color);
+            }
+        }
+    }
+}
+
+/// The rows of the 5x7 glyph of `c`, top first, with the leftmost pixel in bit 4. Lower
+/// case letters are drawn in upper case, and characters the font lacks as `?`.
+fn glyph(c: char) -> [u8; 7] {
+    match c.to_ascii_uppercase() {
+        ' ' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+        '-' => [0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00],
+        '.' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C],
+        ':' => [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x0C, 0x00],
+        '/' => [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00],
+        '_' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1F],
+        '(' => [0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02],
+        ')' => [0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08],
+        '0' => [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E],
+        '1' => [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        '2' => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F],
+        '3' => [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E],
+        '4' => [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02],
+        '5' => [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E],
+        '6' => [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E],
+        '7' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+        '8' => [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E],
+        '9' => [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C],
+        'A' => [0x0E, 0x11, 0x11, 0x11, 0x1F, 0x11, 0x11],
+        'B' => [0x1E, 0x11, 0x11, 0x1E, 0x11, 0x11, 0x1E],
+        'C' => [0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E],
+        'D' => [0x1C, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1C],
+        'E' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x1F],
+        'F' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x10],
+        'G' => [0x0E, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0F],
+        'H' => [0x11, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11],
+        'I' => [0x0E, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        'J' => [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0C],
+        'K' => [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11],
+        'L' => [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1F],
+        'M' => [0x11, 0x1B, 0x15, 0x15, 0x11, 0x11, 0x11],
+        'N' => [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11],
+        'O' => [0x0E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'P' => [0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10, 0x10],
+        'Q' => [0x0E, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0D],
+        'R' => [0x1E, 0x11, 0x11, 0x1E, 0x14, 0x12, 0x11],
+        'S' => [0x0F, 0x10, 0x10, 0x0E, 0x01, 0x01, 0x1E],
+        'T' => [0x1F, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04],
+        'U' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'V' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x0A, 0x04],
+        'W' => [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0A],
+        'X' => [0x11, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x11],
+        'Y' => [0x11, 0x11, 0x11, 0x0A, 0x04, 0x04, 0x04],
+        'Z' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1F],
+        _ => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04],
+    }
+}
@@ -0,0 +1,483 @@
+//! Opening everything a capture needs before the process locks itself down.
+//!
+//! A capture daemon that drops privileges, installs a seccomp filter or enters namespaces has
+//! to create its DeckLink resources first. The driver sets much of itself up lazily, on the
+//! first use of an interface, and that setup fails inside a sandbox with errors that do not
+//! say why. `preflight` takes each input of a capture as far as it can go without starting
+//! its streams, and returns a `Preflight` holding the inputs, with a `PreflightReport` of
+//! what was created for the application to build its sandbox policy from. After lockdown,
+//! `Preflight::start` only starts the streams.
+//!
+//! For each `PreflightSpec`, preflight:
+//!
+//! - lists the devices, which loads the driver's API, and resolves the device
+//! - opens its input interface and lists the display modes of the input
+//! - enables video input, through the allocator provider of the spec if it has one, and
+//!   audio input if the spec asks for it
+//! - registers the handler of the spec as the callback of the input
+//! - reads the status of the device once, so the driver sets up its status queries
+//!
+//! The report lists what was opened for each input, the threads of the crate started while
+//! preflighting, and on Linux the file descriptors the process opened and the memory it
+//! locked meanwhile, read from `/proc/self`. Elsewhere those two are `None`.
+//!
+//! With the `mock-backend` feature, `crate::mock::MockBackend::deny_new_resources` makes the
+//! mock driver refuse every new resource, as a sandbox would, so a test can check that a
+//! capture runs from start to stop on what was preflighted alone.
+//!
+//! # What preflight cannot do for you
+//!
+//! Some operations still need resources or privileges after preflight, and a sandbox policy
+//! has to allow for them or the application has to avoid them:
+//!
+//! - The driver is reached through the descriptors it opened while preflighting, which on
+//!   Linux are device nodes under `/dev/blackmagic`. The sandbox must keep allowing `ioctl`
+//!   and `mmap` on them.
+//! - The driver allocates its capture buffers as the streams start, and asks an allocator
+//!   provider for allocators only then. A provider that must pin or lock memory should do
+//!   so when it is created, before lockdown, and locked memory counts against
+//!   `RLIMIT_MEMLOCK` unless `CAP_IPC_LOCK` is kept.
+//! - Raising the scheduling of threads with `crate::realtime` needs `CAP_SYS_NICE` or a
+//!   large enough `RLIMIT_RTPRIO`. Apply it before dropping either.
+//! - Re-enabling an input, as following a format change does, enables it anew. So does
+//!   opening a device that was plugged in after lockdown, which also lists the devices again.
+//! - Taps, dispatch pools, batch dispatchers and the other parts of the crate that run on
+//!   threads of their own start them when they are created. Create them before lockdown
+//!   too. The reaper of `crate::external` starts whenever buffers are held again after it
+//!   has exited, so a sandbox must allow it to start threads if `crate::external` is used.
+
+use crate::allocator::VideoBufferAllocatorProvider;
+use crate::device::input::{
+    DecklinkAudioSampleRate, DecklinkAudioSampleType, DecklinkInputDevice, DecklinkVideoInputFlags,
+    InputHandler,
+};
+use crate::device::selector::{DeviceSelector, SelectError};
+use crate::device::{DecklinkDevice, DecklinkDeviceDisplayModes};
+use crate::display_mode::DecklinkDisplayModeId;
+use crate::frame::DecklinkPixelFormat;
+use crate::threads;
+use crate::SdkError;
+use std::fmt;
+use std::sync::Arc;
+
+/// An input to preflight, and how it will capture.
+#[derive(Clone)]
+pub struct PreflightSpec {
+    pub device: DeviceSelector,
+    pub mode: DecklinkDisplayModeId,
+    pub pixel_format: DecklinkPixelFormat,
+    pub flags: DecklinkVideoInputFlags,
+    /// The sample rate, sample type and channel count of audio input, if it is captured.
+    pub audio: Option<(DecklinkAudioSampleRate, DecklinkAudioSampleType, u32)>,
+    /// The provider video input is enabled with, if not the driver's own allocator.
+    pub allocator: Option<Arc<dyn VideoBufferAllocatorProvider>>,
+    pub handler: Arc<dyn InputHandler>,
+}
+
+impl PreflightSpec {
+    /// Capture video in `mode` and `pixel_format` from the device `device` picks out, with
+    /// no flags and no audio.
+    pub fn new(
+        device: DeviceSelector,
+        mode: DecklinkDisplayModeId,
+        pixel_format: DecklinkPixelFormat,
+        handler: Arc<dyn InputHandler>,
+    ) -> PreflightSpec {
+        PreflightSpec {
+            device,
+            mode,
+            pixel_format,
+            flags: DecklinkVideoInputFlags::empty(),
+            audio: None,
+            allocator: None,
+            handler,
+        }
+    }
+
+    pub fn flags(mut self, flags: DecklinkVideoInputFlags) -> PreflightSpec {
+        self.flags = flags;
+        self
+    }
+
+    pub fn audio(
+        mut self,
+        sample_rate: DecklinkAudioSampleRate,
+        sample_type: DecklinkAudioSampleType,
+        channels: u32,
+    ) -> PreflightSpec {
+        self.audio = Some((sample_rate, sample_type, channels));
+        self
+    }
+
+    pub fn allocator(mut self, provider: Arc<dyn VideoBufferAllocatorProvider>) -> PreflightSpec {
+        self.allocator = Some(provider);
+        self
+    }
+}
+
+/// A step of preflighting an input, which creates a resource of the driver.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PreflightStep {
+    /// The devices were listed and the device resolved.
+    Device,
+    /// The input interface of the device was opened.
+    Input,
+    /// The display modes of the input were listed.
+    DisplayModes,
+    /// Video input was enabled.
+    VideoInput,
+    /// Video input was enabled through an allocator provider, which was registered with the
+    /// driver.
+    AllocatorProvider,
+    /// Audio input was enabled.
+    AudioInput,
+    /// The handler was registered as the callback of the input.
+    Callback,
+    /// The status of the device was read.
+    Status,
+}
+
+impl fmt::Display for PreflightStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let step = match self {
+            PreflightStep::Device => "device",
+            PreflightStep::Input => "input",
+            PreflightStep::DisplayModes => "display modes",
+            PreflightStep::VideoInput => "video input",
+            PreflightStep::AllocatorProvider => "allocator provider",
+            PreflightStep::AudioInput => "audio input",
+            PreflightStep::Callback => "callback",
+            PreflightStep::Status => "status",
+        };
+        f.write_str(step)
+    }
+}
+
+/// What was created for one input.
+#[derive(PartialEq, Eq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct InputPreflight {
+    /// The display name of the device.
+    pub device: String,
+    /// The steps taken, in order.
+    pub steps: Vec<PreflightStep>,
+    /// Why reading the status failed, if it did. Preflight carries on without it, as some
+    /// devices report no status until they have a signal.
+    pub status_error: Option<String>,
+}
+
+/// A file descriptor the process opened while preflighting.
+#[derive(PartialEq, Eq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OpenedFd {
+    pub fd: i32,
+    /// What it refers to, such as a device node, or `socket:[1234]`.
+    pub target: String,
+}
+
+/// What `preflight` created, for building a sandbox policy from.
+#[derive(PartialEq, Eq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PreflightReport {
+    /// One for each spec, in order.
+    pub inputs: Vec<InputPreflight>,
+    /// The names of the threads of the crate that started while preflighting.
+    pub threads_started: Vec<String>,
+    /// The file descriptors the process opened while preflighting, or `None` where they
+    /// cannot be listed. Descriptors opened by other threads meanwhile are listed too.
+    pub fds_opened: Option<Vec<OpenedFd>>,
+    /// How much more memory the process had locked after preflighting than before, or
+    /// `None` where it cannot be read.
+    pub memory_locked: Option<u64>,
+}
+
+impl fmt::Display for PreflightReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for input in &self.inputs {
+            let steps: Vec<_> = input.steps.iter().map(|s| s.to_string()).collect();
+            writeln!(f, "{}: {}", input.device, steps.join(", "))?;
+            if let Some(error) = &input.status_error {
+                writeln!(f, "  status not read: {}", error)?;
+            }
+        }
+        writeln!(f, "threads started: {}", self.threads_started.len())?;
+        for name in &self.threads_started {
+            writeln!(f, "  {}", name)?;
+        }
+        match &self.fds_opened {
+            Some(fds) => {
+                writeln!(f, "file descriptors opened: {}", fds.len())?;
+                for fd in fds {
+                    writeln!(f, "  {} -> {}", fd.fd, fd.target)?;
+                }
+            }
+            None => writeln!(f, "file descriptors opened: unknown")?,
+        }
+        match self.memory_locked {
+            Some(bytes) => writeln!(f, "memory locked: {} bytes", bytes),
+            None => writeln!(f, "memory locked: unknown"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum PreflightError {
+    /// The device of spec `spec` could not be found.
+    Select { spec: usize, error: SelectError },
+    /// The device of spec `spec` has no input.
+    NoInput { spec: usize, device: String },
+    /// A step of preflighting spec `spec` failed.
+    Step {
+        spec: usize,
+        step: PreflightStep,
+        error: SdkError,
+    },
+}
+
+impl fmt::Display for PreflightError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PreflightError::Select { spec, error } => {
+                write!(f, "failed to find the device of input {}: {}", spec, error)
+            }
+            PreflightError::NoInput { spec, device } => {
+                write!(f, "the device of input {}, {}, has no input", spec, device)
+            }
+            PreflightError::Step { spec, step, error } => {
+                write!(
+                    f,
+                    "failed to preflight the {} of input {}: {:?}",
+                    step, spec, error
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for PreflightError {}
+
+/// Inputs that have been preflighted, ready to start.
+pub struct Preflight {
+    inputs: Vec<DecklinkInputDevice>,
+    _devices: Vec<DecklinkDevice>,
+    report: PreflightReport,
+}
+
+impl Preflight {
+    pub fn report(&self) -> &PreflightReport {
+        &self.report
+    }
+
+    /// The inputs, in the order of their specs.
+    pub fn inputs(&self) -> &[DecklinkInputDevice] {
+        &self.inputs
+    }
+
+    /// Start the streams of every input. If one fails to start, the inputs started before
+    /// it are stopped again.
+    pub fn start(&self) -> Result<(), SdkError> {
+        for (i, input) in self.inputs.iter().enumerate() {
+            if let Err(e) = input.start_streams() {
+                for started in &self.inputs[..i] {
+                    let _ = started.stop_streams();
+                }
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Stop the streams of every input, returning the first error after trying them all.
+    pub fn stop(&self) -> Result<(), SdkError> {
+        let mut result = Ok(());
+        for input in &self.inputs {
+            let stopped = input.stop_streams();
+            if result.is_ok() {
+                result = stopped;
+            }
+        }
+        result
+    }
+
+    /// The inputs, in the order of their specs, for an application that runs them itself.
+    pub fn into_inputs(self) -> Vec<DecklinkInputDevice> {
+        self.inputs
+    }
+}
+
+/// Open and enable the input of each spec, without starting its streams. See the module
+/// documentation. If any step fails, whatever was opened is released again.
+pub fn preflight(specs: Vec<PreflightSpec>) -> Result<Preflight, PreflightError> {
+    let threads_before: Vec<_> = threads::threads().into_iter().map(|t| t.name).collect();
+    let fds_before = sys::open_fds();
+    let locked_before = sys::locked_bytes();
+
+    let mut inputs = Vec::with_capacity(specs.len());
+    let mut devices = Vec::with_capacity(specs.len());
+    let mut reports = Vec::with_capacity(specs.len());
+    for (i, spec) in specs.into_iter().enumerate() {
+        let (device, input, report) = preflight_input(i, spec)?;
+        devices.push(device);
+        inputs.push(input);
+        reports.push(report);
+    }
+
+    let threads_started = threads::threads()
+        .into_iter()
+        .map(|t| t.name)
+        .filter(|name| !threads_before.contains(name))
+        .collect();
+    let fds_opened = fds_before.zip(sys::open_fds()).map(|(before, after)| {
+        after
+            .into_iter()
+            .filter(|fd| !before.contains(fd))
+            .collect()
+    });
+    let memory_locked = locked_before
+        .zip(sys::locked_bytes())
+        .map(|(before, after)| after.saturating_sub(before));
+
+    Ok(Preflight {
+        inputs,
+        _devices: devices,
+        report: PreflightReport {
+            inputs: reports,
+            threads_started,
+            fds_opened,
+            memory_locked,
+        },
+    })
+}
+
+fn preflight_input(
+    spec_index: usize,
+    spec: PreflightSpec,
+) -> Result<(DecklinkDevice, DecklinkInputDevice, InputPreflight), PreflightError> {
+    let step_failed = |step| {
+        move |error| PreflightError::Step {
+            spec: spec_index,
+            step,
+            error,
+        }
+    };
+    let device = spec
+        .device
+        .resolve()
+        .map_err(|error| PreflightError::Select {
+            spec: spec_index,
+            error,
+        })?;
+    let name = device.display_name().unwrap_or_default();
+    let mut steps = vec![PreflightStep::Device];
+
+    let mut input = device.input().ok_or_else(|| PreflightError::NoInput {
+        spec: spec_index,
+        device: name.clone(),
+    })?;
+    steps.push(PreflightStep::Input);
+
+    input
+        .display_modes()
+        .map_err(step_failed(PreflightStep::DisplayModes))?;
+    steps.push(PreflightStep::DisplayModes);
+
+    match spec.allocator {
+        Some(provider) => {
+            input
+                .enable_video_input_with_allocator(
+                    spec.mode,
+                    spec.pixel_format,
+                    spec.flags,
+                    provider,
+                )
+                .map_err(step_failed(PreflightStep::AllocatorProvider))?;
+            steps.push(PreflightStep::VideoInput);
+            steps.push(PreflightStep::AllocatorProvider);
+        }
+        None => {
+            input
+                .enable_video_input(spec.mode, spec.pixel_format, spec.flags)
+                .map_err(step_failed(PreflightStep::VideoInput))?;
+            steps.push(PreflightStep::VideoInput);
+        }
+    }
+
+    if let Some((sample_rate, sample_type, channels)) = spec.audio {
+        input
+            .enable_audio_input(sample_rate, sample_type, channels)
+            .map_err(step_failed(PreflightStep::AudioInput))?;
+        steps.push(PreflightStep::AudioInput);
+    }
+
+    input
+        .set_handler(Some(spec.handler))
+        .map_err(step_failed(PreflightStep::Callback))?;
+    steps.push(PreflightStep::Callback);
+
+    // The answer does not matter, only that the driver has been asked
+    let status_error = match device
+        .get_status()
+        .and_then(|status| status.current_video_input_mode())
+    {
+        Ok(_) => None,
+        Err(e) => Some(format!("{:?}", e)),
+    };
+    steps.push(PreflightStep::Status);
+
+    let report = InputPreflight {
+        device: name,
+        steps,
+        status_error,
+    };
+    Ok((device, input, report))
+}
+
+#[cfg(target_os = "linux")]
+mod sys {
+    use super::OpenedFd;
+
+    /// The file descriptors the process has open, with what they refer to.
+    pub(super) fn open_fds() -> Option<Vec<OpenedFd>> {
+        let entries = std::fs::read_dir("/proc/self/fd").ok()?;
+        let mut fds: Vec<_> = entries
+            .filter_map(|entry| {
+                let entry = entry.ok()?;
+                let fd = entry.file_name().to_str()?.parse().ok()?;
+                let target = std::fs::read_link(entry.path()).ok()?;
+                Some(OpenedFd {
+                    fd,
+                    target: target.to_string_lossy().into_owned(),
+                })
+            })
+            .collect();
+        fds.sort_by_key(|fd| fd.fd);
+        Some(fds)
+    }
+
+    /// The memory of the process that is locked, from `VmLck` in `/proc/self/status`.
+    pub(super) fn locked_bytes() -> Option<u64> {
+        let status = std::fs::read_to_string("/proc/self/status").ok()?;
+        let line = status.lines().find(|l| l.starts_with("VmLck:"))?;
+        let kb: u64 = line["VmLck:".len()..]
+            .trim()
+            .trim_end_matches("kB")
+            .trim()
+            .parse()
+            .ok()?;
+        Some(kb * 1024)
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod sys {
+    use super::OpenedFd;
+
+    pub(super) fn open_fds() -> Option<Vec<OpenedFd>> {
+        None
+    }
+
+    pub(super) fn locked_bytes() -> Option<u64> {
+        None
+    }
+}
@@ -0,0 +1,984 @@
+//! Checking which of the wrapped APIs work on a device.
+//!
+//! `run_all` runs a series of probes against a device, from reading attributes and status up
+//! to briefly capturing from a live signal, and records whether each passed, failed or was
+//! skipped as not applicable. The report is meant to be pasted into bug reports, so its
+//! layout, probe names and JSON keys are kept stable. `PROBE_REPORT_VERSION` is increased
+//! whenever they change.
+//!
+//! Probes only read settings, or enable an input and disable it again, so the device is left
+//! as it was found. Each probe is isolated, so an error or panic in one is recorded and the
+//! remaining probes still run. Probes run on the calling thread, as devices cannot be sent
+//! between threads, so probes that wait for the device do so for a bounded time.
+
+use crate::allocator::{
+    BufferSpec, VideoBuffer, VideoBufferAllocator, VideoBufferAllocatorProvider,
+};
+use crate::device::input::{
+    CallbackResult, DecklinkAudioInputPacket, DecklinkAudioSampleRate, DecklinkAudioSampleType,
+    DecklinkInputDevice, DecklinkVideoInputFlags, FrameArrival, InputFormatChange, InputHandler,
+};
+use crate::device::{DecklinkDevice, DecklinkDeviceDisplayModes};
+use crate::display_mode::DecklinkDisplayModeId;
+use crate::effective_config::{DecklinkEffectiveConfigExt, EffectiveConfig};
+use crate::event::DeviceIdentity;
+use crate::frame::{DecklinkFrameBase, DecklinkFrameFlags, DecklinkPixelFormat};
+use crate::manifest::{write_json_opt_string, write_json_string};
+use crate::timecode::{frame_rate_of, TimecodeTracker, TimecodeTrackerConfig};
+use crate::vpid::{DecklinkVpidExt, Vpid};
+use crate::SdkError;
+use crate::{api_version, capabilities};
+use std::ffi::c_void;
+use std::fmt::Write;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use strum::IntoEnumIterator;
+
+/// The version of the report layout, included in its text and JSON forms.
+pub const PROBE_REPORT_VERSION: u32 = 5;
+
+/// Why a probe was not run.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+pub enum SkipReason {
+    /// The probe needs a live input signal, and none was present.
+    NoSignal,
+    /// The device or this crate does not support what the probe checks.
+    Unsupported,
+}
+
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+pub enum ProbeOutcome {
+    Pass,
+    /// The probe failed, with the HRESULT returned by the driver. There is no HRESULT if the
+    /// probe panicked or overran its time bound.
+    Fail {
+        hresult: Option<i32>,
+    },
+    Skipped(SkipReason),
+}
+
+impl ProbeOutcome {
+    fn label(&self) -> &'static str {
+        match self {
+            ProbeOutcome::Pass => "pass",
+            ProbeOutcome::Fail { .. } => "fail",
+            ProbeOutcome::Skipped(SkipReason::NoSignal) => "skipped (no signal)",
+            ProbeOutcome::Skipped(SkipReason::Unsupported) => "skipped (unsupported)",
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct ProbeResult {
+    /// The name of the probe, as listed in `PROBE_NAMES`.
+    pub name: &'static str,
+    pub outcome: ProbeOutcome,
+    /// What the probe found, or why it failed or was skipped.
+    pub detail: String,
+    pub duration: Duration,
+}
+
+/// The pixel formats supported in one display mode.
+#[derive(PartialEq, Debug, Clone)]
+pub struct ModeSupport {
+    pub mode: DecklinkDisplayModeId,
+    pub name: Option<String>,
+    pub pixel_formats: Vec<DecklinkPixelFormat>,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct ProbeReport {
+    pub device_name: Option<String>,
+    pub model_name: Option<String>,
+    pub driver_version: Option<String>,
+    /// The SMPTE ST 352 payload identifier of the live capture, if the source sent one.
+    pub vpid: Option<Vpid>,
+    /// The configuration the live capture negotiated, if it ran.
+    pub effective_config: Option<EffectiveConfig>,
+    pub results: Vec<ProbeResult>,
+    /// The capture support matrix, empty if the device has no input.
+    pub support_matrix: Vec<ModeSupport>,
+}
+
+impl ProbeReport {
+    /// Get the result of the probe named `name`.
+    pub fn result(&self, name: &str) -> Option<&ProbeResult> {
+        self.results.iter().find(|r| r.name == name)
+    }
+
+    pub fn failures(&self) -> impl Iterator<Item = &ProbeResult> {
+        self.results
+            .iter()
+            .filter(|r| matches!(r.outcome, ProbeOutcome::Fail { .. }))
+    }
+
+    /// Serialize the report as JSON.
+    pub fn to_json(&self) -> String {
+        let mut out = String::new();
+        let _ = write!(
+            out,
+            "{{\n  \"version\": {},\n  \"device_name\": ",
+            PROBE_REPORT_VERSION
+        );
+        write_json_opt_string(&mut out, &self.device_name);
+        out.push_str(",\n  \"model_name\": ");
+        write_json_opt_string(&mut out, &self.model_name);
+        out.push_str(",\n  \"driver_version\": ");
+        write_json_opt_string(&mut out, &self.driver_version);
+        out.push_str(",\n  \"vpid\": ");
+        match &self.vpid {
+            Some(vpid) => {
+                let [byte1, byte2, byte3, byte4] = vpid.bytes;
+                let _ = write!(
+                    out,
+                    "{{ \"bytes\": \"{:02X} {:02X} {:02X} {:02X}\", \"description\": ",
+                    byte1, byte2, byte3, byte4
+                );
+                write_json_string(&mut out, &vpid.describe());
+                out.push_str(" }");
+            }
+            None => out.push_str("null"),
+        }
+        out.push_str(",\n  \"effective_config\": ");
+        match &self.effective_config {
+            Some(config) => config.write_json(&mut out),
+            None => out.push_str("null"),
+        }
+        out.push_str(",\n  \"probes\": [");
+
+        for (i, result) in self.results.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            let (outcome, hresult, reason) = match result.outcome {
+                ProbeOutcome::Pass => ("pass", None, None),
+                ProbeOutcome::Fail { hresult } => ("fail", hresult, None),
+                ProbeOutcome::Skipped(SkipReason::NoSignal) => ("skipped", None, Some("no_signal")),
+                ProbeOutcome::Skipped(SkipReason::Unsupported) => {
+                    ("skipped", None, Some("unsupported"))
+                }
+            };
+            let _ = write!(
+                out,
+                "\n    {{ \"name\": \"{}\", \"outcome\": \"{}\", ",
+                result.name, outcome
+            );
+            match hresult {
+                Some(code) => {
+                    let _ = write!(out, "\"hresult\": \"0x{:08x}\", ", code as u32);
+                }
+                None => out.push_str("\"hresult\": null, "),
+            }
+            match reason {
+                Some(reason) => {
+                    let _ = write!(out, "\"skip_reason\": \"{}\", ", reason);
+                }
+                None => out.push_str("\"skip_reason\": null, "),
+            }
+            let _ = write!(
+                out,
+                "\"duration_ms\": {}, \"detail\": ",
+                result.duration.as_millis()
+            );
+            write_json_string(&mut out, &result.detail);
+            out.push_str(" }");
+        }
+        if !self.results.is_empty() {
+            out.push_str("\n  ");
+        }
+        out.push_str("],\n  \"support_matrix\": [");
+
+        for (i, support) in self.support_matrix.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            let _ = write!(out, "\n    {{ \"mode\": \"{:?}\", \"name\": ", support.mode);
+            write_json_opt_string(&mut out, &support.name);
+            out.push_str(", \"pixel_formats\": [");
+            for (j, format) in support.pixel_formats.iter().enumerate() {
+                if j > 0 {
+                    out.push_str(", ");
+                }
+                let _ = write!(out, "\"{:?}\"", format);
+            }
+            out.push_str("] }");
+        }
+        if !self.support_matrix.is_empty() {
+            out.push_str("\n  ");
+        }
+        out.push_str("]\n}\n");
+
+        out
+    }
+}
+
+impl std::fmt::Display for ProbeReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let show = |value: &Option<String>| value.clone().unwrap_or_else(|| "Unknown".to_string());
+        writeln!(
+            f,
+            "Decklink probe report (version {})",
+            PROBE_REPORT_VERSION
+        )?;
+        writeln!(f, "{0: <16} {1}", "Device", show(&self.device_name))?;
+        writeln!(f, "{0: <16} {1}", "Model", show(&self.model_name))?;
+        writeln!(f, "{0: <16} {1}", "Driver", show(&self.driver_version))?;
+        writeln!(
+            f,
+            "{0: <16} {1}",
+            "VPID",
+            show(&self.vpid.as_ref().map(|v| v.to_string()))
+        )?;
+        writeln!(f)?;
+
+        for result in &self.results {
+            let outcome = match result.outcome {
+                ProbeOutcome::Fail {
+                    hresult: Some(code),
+                } => format!("fail 0x{:08x}", code as u32),
+                outcome => outcome.label().to_string(),
+            };
+            writeln!(
+                f,
+                "{0: <22} {1: <22} {2: >6}ms  {3}",
+                result.name,
+                outcome,
+                result.duration.as_millis(),
+                result.detail
+            )?;
+        }
+
+        if !self.support_matrix.is_empty() {
+            writeln!(f)?;
+            writeln!(f, "Capture support matrix")?;
+            for support in &self.support_matrix {
+                let formats: Vec<String> = support
+                    .pixel_formats
+                    .iter()
+                    .map(|p| format!("{:?}", p))
+                    .collect();
+                writeln!(
+                    f,
+                    "  {0: <24} {1}",
+                    support
+                        .name
+                        .clone()
+                        .unwrap_or_else(|| format!("{:?}", support.mode)),
+                    formats.join(", ")
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Options for `run_all_with`.
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub struct ProbeOptions {
+    /// How long to capture from a live signal.
+    pub capture_duration: Duration,
+    /// A probe that takes longer than this is recorded as failed.
+    pub probe_timeout: Duration,
+}
+
+impl Default for ProbeOptions {
+    fn default() -> Self {
+        ProbeOptions {
+            capture_duration: Duration::from_secs(3),
+            probe_timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+/// The names of the probes, in the order they run.
+pub const PROBE_NAMES: [&str; 13] = [
+    "attributes",
+    "status",
+    "health",
+    "configuration",
+    "display_modes",
+    "support_matrix",
+    "row_bytes",
+    "format_detection",
+    "allocator_round_trip",
+    "live_signal",
+    "timecode",
+    "vanc",
+    "audio_channels",
+];
+
+/// What a probe found.
+struct Finding {
+    outcome: ProbeOutcome,
+    detail: String,
+}
+
+impl Finding {
+    fn pass(detail: impl Into<String>) -> Result<Finding, SdkError> {
+        Ok(Finding {
+            outcome: ProbeOutcome::Pass,
+            detail: detail.into(),
+        })
+    }
+    fn fail(detail: impl Into<String>) -> Result<Finding, SdkError> {
+        Ok(Finding {
+            outcome: ProbeOutcome::Fail { hresult: None },
+            detail: detail.into(),
+        })
+    }
+    fn skip(reason: SkipReason, detail: impl Into<String>) -> Result<Finding, SdkError> {
+        Ok(Finding {
+            outcome: ProbeOutcome::Skipped(reason),
+            detail: detail.into(),
+        })
+    }
+}
+
+/// What was seen while capturing from a live signal.
+#[derive(Default)]
+struct CaptureCounts {
+    frames: AtomicU32,
+    no_signal_frames: AtomicU32,
+    format_changes: AtomicU32,
+    audio_packets: AtomicU32,
+    /// A bit for each audio channel that carried a non-zero sample.
+    audible_channels: AtomicUsize,
+    last_mode: Mutex<Option<DecklinkDisplayModeId>>,
+    timecode: Mutex<Option<TimecodeTracker>>,
+}
+
+impl CaptureCounts {
+    fn count_audio(&self, audio_packet: &DecklinkAudioInputPacket) {
+        self.audio_packets.fetch_add(1, Ordering::Relaxed);
+
+        let channels = audio_packet.channel_count() as usize;
+        let bytes = match audio_packet.bytes() {
+            Ok(bytes) if channels > 0 => bytes,
+            _ => return,
+        };
+        // Audio is enabled as interleaved 32-bit samples
+        let mut audible = 0usize;
+        for (i, sample) in bytes.chunks_exact(4).enumerate() {
+            if sample != [0, 0, 0, 0] {
+                audible |= 1 << ((i % channels) % usize::BITS as usize);
+            }
+        }
+        self.audible_channels.fetch_or(audible, Ordering::Relaxed);
+    }
+}
+
+impl InputHandler for CaptureCounts {
+    fn format_changed(&self, change: &InputFormatChange) {
+        self.format_changes.fetch_add(1, Ordering::Relaxed);
+        *self.last_mode.lock().unwrap() = Some(change.display_mode);
+    }
+
+    fn frame_arrived(&self, arrival: &FrameArrival<'_>) -> CallbackResult {
+        if let Some(packet) = arrival.audio_packet {
+            self.count_audio(packet);
+        }
+        if let Some(frame) = arrival.video_frame {
+            if frame
+                .flags()
+                .contains(DecklinkFrameFlags::HAS_NO_INPUT_SOURCE)
+            {
+                self.no_signal_frames.fetch_add(1, Ordering::Relaxed);
+            } else {
+                self.frames.fetch_add(1, Ordering::Relaxed);
+            }
+            if let Some(tracker) = self.timecode.lock().unwrap().as_mut() {
+                tracker.observe_frame(frame);
+            }
+        }
+        CallbackResult::Ok
+    }
+}
+
+/// A buffer in ordinary heap memory.
+struct HeapBuffer {
+    data: Mutex<Vec<u8>>,
+}
+
+impl VideoBuffer for HeapBuffer {
+    fn get_bytes(&self) -> Result<*mut c_void, SdkError> {
+        Ok(self.data.lock().unwrap().as_mut_ptr() as *mut c_void)
+    }
+}
+
+struct HeapAllocator {
+    buffer_size: usize,
+    allocated: Arc<AtomicU32>,
+}
+
+impl VideoBufferAllocator for HeapAllocator {
+    fn allocate(&self) -> Result<Box<dyn VideoBuffer>, SdkError> {
+        self.allocated.fetch_add(1, Ordering::Relaxed);
+        Ok(Box::new(HeapBuffer {
+            data: Mutex::new(vec![0; self.buffer_size]),
+        }))
+    }
+}
+
+/// Hands out heap buffers, counting how many allocators and buffers were requested.
+#[derive(Default)]
+pub(crate) struct HeapAllocatorProvider {
+    allocators: AtomicU32,
+    allocated: Arc<AtomicU32>,
+}
+
+impl VideoBufferAllocatorProvider for HeapAllocatorProvider {
+    fn get_allocator(&self, spec: BufferSpec) -> Result<Arc<dyn VideoBufferAllocator>, SdkError> {
+        self.allocators.fetch_add(1, Ordering::Relaxed);
+        Ok(Arc::new(HeapAllocator {
+            buffer_size: spec.buffer_size as usize,
+            allocated: self.allocated.clone(),
+        }))
+    }
+}
+
+/// State shared between the probes.
+struct Prober<'a> {
+    device: &'a DecklinkDevice,
+    options: ProbeOptions,
+    input: Option<DecklinkInputDevice>,
+    support_matrix: Vec<ModeSupport>,
+    format_detection: bool,
+    capture: Option<Arc<CaptureCounts>>,
+    audio_channels: u32,
+    vpid: Option<Vpid>,
+    effective_config: Option<EffectiveConfig>,
+}
+
+impl<'a> Prober<'a> {
+    fn input(&mut self) -> Result<&mut DecklinkInputDevice, Finding> {
+        self.input.as_mut().ok_or_else(|| Finding {
+            outcome: ProbeOutcome::Skipped(SkipReason::Unsupported),
+            detail: "the device has no input".to_string(),
+        })
+    }
+
+    /// A display mode the device can capture, preferring the detected mode.
+    fn capture_mode(&self) -> Option<DecklinkDisplayModeId> {
+        let detected = self
+            .device
+            .get_status()
+            .and_then(|s| s.detected_video_input_mode())
+            .ok();
+        let supports_yuv = |mode: &DecklinkDisplayModeId| {
+            self.support_matrix.iter().any(|s| {
+                s.mode == *mode
+                    && s.pixel_formats
+                        .contains(&DecklinkPixelFormat::Format8BitYUV)
+            })
+        };
+        detected.filter(supports_yuv).or_else(|| {
+            self.support_matrix
+                .iter()
+                .map(|s| s.mode)
+                .find(supports_yuv)
+        })
+    }
+
+    fn attributes(&mut self) -> Result<Finding, SdkError> {
+        let attributes = self.device.get_attributes()?;
+        let reads = [
+            attributes.supports_input_format_detection().is_ok(),
+            attributes.has_reference_input().is_ok(),
+            attributes.maximum_audio_channels().is_ok(),
+            attributes.persistent_id().is_ok(),
+            attributes.video_input_connections().is_ok(),
+            attributes.video_output_connections().is_ok(),
+            attributes.profile_id().is_ok(),
+            attributes.duplex().is_ok(),
+            attributes.video_io_support().is_ok(),
+            attributes.device_interface().is_ok(),
+            attributes.vendor_name().is_ok(),
+            attributes.device_handle().is_ok(),
+        ];
+        let readable = reads.iter().filter(|r| **r).count();
+        Finding::pass(format!(
+            "{} of {} attributes readable, interface {:?}",
+            readable,
+            reads.len(),
+            attributes.device_interface().ok()
+        ))
+    }
+
+    fn status(&mut self) -> Result<Finding, SdkError> {
+        let status = self.device.get_status()?;
+        let reads = [
+            status.busy_state().is_ok(),
+            status.video_input_signal_locked().is_ok(),
+            status.detected_video_input_mode().is_ok(),
+            status.reference_signal_locked().is_ok(),
+            status.pci_express_link_width().is_ok(),
+            status.device_temperature().is_ok(),
+        ];
+        let readable = reads.iter().filter(|r| **r).count();
+        Finding::pass(format!(
+            "{} of {} status items readable, signal locked {:?}",
+            readable,
+            reads.len(),
+            status.video_input_signal_locked().ok()
+        ))
+    }
+
+    fn health(&mut self) -> Result<Finding, SdkError> {
+        match self.device.health()?.temperature_c {
+            Some(temperature) => Finding::pass(format!("temperature {} C", temperature)),
+            None => Finding::skip(
+                SkipReason::Unsupported,
+                "the device does not report its temperature",
+            ),
+        }
+    }
+
+    fn configuration(&mut self) -> Result<Finding, SdkError> {
+        Finding::skip(
+            SkipReason::Unsupported,
+            "configuration is not wrapped by this crate",
+        )
+    }
+
+    fn display_modes(&mut self) -> Result<Finding, SdkError> {
+        let input = match self.input() {
+            Ok(input) => input,
+            Err(finding) => return Ok(finding),
+        };
+        let modes = input.display_modes()?;
+        self.support_matrix = modes
+            .iter()
+            .map(|mode| ModeSupport {
+                mode: mode.mode(),
+                name: mode.name(),
+                pixel_formats: Vec::new(),
+            })
+            .collect();
+        Finding::pass(format!("{} input display modes", modes.len()))
+    }
+
+    fn support_matrix(&mut self) -> Result<Finding, SdkError> {
+        let input = match self.input.as_ref() {
+            Some(input) if !self.support_matrix.is_empty() => input,
+            _ => return Finding::skip(SkipReason::Unsupported, "no input display modes to check"),
+        };
+
+        // Devices with 8K modes have hundreds of combinations, so stop at the probe timeout
+        // rather than stalling the whole run on this probe
+        let deadline = Instant::now() + self.options.probe_timeout;
+        let mode_count = self.support_matrix.len();
+        let mut supported = 0;
+        let mut checked = 0;
+        for (i, support) in self.support_matrix.iter_mut().enumerate() {
+            if Instant::now() >= deadline {
+                return Finding::fail(format!(
+                    "timed out after checking {} of {} modes",
+                    i, mode_count
+                ));
+            }
+            support.pixel_formats = input
+                .supported_pixel_formats_for(support.mode, DecklinkVideoInputFlags::empty())?;
+            checked += DecklinkPixelFormat::iter().count();
+            supported += support.pixel_formats.len();
+        }
+        Finding::pass(format!(
+            "{} of {} mode and pixel format combinations supported",
+            supported, checked
+        ))
+    }
+
+    fn row_bytes(&mut self) -> Result<Finding, SdkError> {
+        let input = match self.input.as_ref() {
+            Some(input)
+                if self
+                    .support_matrix
+                    .iter()
+                    .any(|s| !s.pixel_formats.is_empty()) =>
+            {
+                input
+            }
+            _ => {
+                return Finding::skip(
+                    SkipReason::Unsupported,
+                    "no supported mode and pixel format combinations to audit",
+                )
+            }
+        };
+        // The SDK reports row sizes only through the output interface
+        let output = match self.device.output() {
+            Some(output) => output,
+            None => {
+                return Finding::skip(
+                    SkipReason::Unsupported,
+                    "the device has no output to report row sizes",
+                )
+            }
+        };
+
+        let modes = input.display_modes()?;
+        let deadline = Instant::now() + self.options.probe_timeout;
+        let mut checked = 0;
+        let mut mismatches = Vec::new();
+        for support in &self.support_matrix {
+            let width = match modes.iter().find(|m| m.mode() == support.mode) {
+                Some(mode) => mode.width(),
+                None => continue,
+            };
+            for pixel_format in &support.pixel_formats {
+                if Instant::now() >= deadline {
+                    return Finding::fail(format!(
+                        "timed out after auditing {} row sizes",
+                        checked
+                    ));
+                }
+                let expected = match pixel_format.bytes_per_row(width) {
+                    Some(expected) => expected,
+                    None => continue,
+                };
+                let reported = output.row_bytes_for_pixel_format(*pixel_format, width)?;
+                checked += 1;
+                if reported != expected {
+                    mismatches.push(format!(
+                        "{} {:?} has {} bytes a row, not {}",
+                        support.name.as_deref().unwrap_or("unknown mode"),
+                        pixel_format,
+                        reported,
+                        expected
+                    ));
+                }
+            }
+        }
+
+        if mismatches.is_empty() {
+            Finding::pass(format!("{} row sizes match the driver", checked))
+        } else {
+            Finding::fail(format!(
+                "{} of {} row sizes differ from the driver: {}",
+                mismatches.len(),
+                checked,
+                mismatches.join("; ")
+            ))
+        }
+    }
+
+    fn format_detection(&mut self) -> Result<Finding, SdkError> {
+        self.format_detection = self
+            .device
+            .get_attributes()?
+            .supports_input_format_detection()?;
+        if self.format_detection {
+            Finding::pass("input format detection supported")
+        } else {
+            Finding::skip(
+                SkipReason::Unsupported,
+                "input format detection not supported",
+            )
+        }
+    }
+
+    fn allocator_round_trip(&mut self) -> Result<Finding, SdkError> {
+        if !capabilities().allocator_provider {
+            return Finding::skip(
+                SkipReason::Unsupported,
+                "the drivers do not support custom allocators",
+            );
+        }
+        let mode = match self.capture_mode() {
+            Some(mode) => mode,
+            None => {
+                return Finding::skip(
+                    SkipReason::Unsupported,
+                    "no display mode supports 8-bit YUV capture",
+                )
+            }
+        };
+        let input = match self.input() {
+            Ok(input) => input,
+            Err(finding) => return Ok(finding),
+        };
+
+        let provider = Arc::new(HeapAllocatorProvider::default());
+        input.enable_video_input_with_allocator(
+            mode,
+            DecklinkPixelFormat::Format8BitYUV,
+            DecklinkVideoInputFlags::empty(),
+            provider.clone(),
+        )?;
+        input.disable_video_input()?;
+
+        Finding::pass(format!(
+            "enabled and disabled {:?} with a heap allocator, {} allocators and {} buffers requested",
+            mode,
+            provider.allocators.load(Ordering::Relaxed),
+            provider.allocated.load(Ordering::Relaxed)
+        ))
+    }
+
+    fn live_signal(&mut self) -> Result<Finding, SdkError> {
+        let locked = self
+            .device
+            .get_status()
+            .and_then(|s| s.video_input_signal_locked())
+            .unwrap_or(false);
+        if !locked {
+            return Finding::skip(SkipReason::NoSignal, "no input signal is locked");
+        }
+        let mode = match self.capture_mode() {
+            Some(mode) => mode,
+            None => {
+                return Finding::skip(
+                    SkipReason::Unsupported,
+                    "no display mode supports 8-bit YUV capture",
+                )
+            }
+        };
+        let audio_channels = self
+            .device
+            .get_attributes()
+            .and_then(|a| a.maximum_audio_channels())
+            .ok()
+            .and_then(|max| [16, 8, 2].into_iter().find(|c| *c as i64 <= max))
+            .unwrap_or(2);
+        let flags = if self.format_detection {
+            DecklinkVideoInputFlags::ENABLE_FORMAT_DETECTION
+        } else {
+            DecklinkVideoInputFlags::empty()
+        };
+        let duration = self.options.capture_duration;
+
+        let input = match self.input() {
+            Ok(input) => input,
+            Err(finding) => return Ok(finding),
+        };
+        let frame_rate = input
+            .display_modes()
+            .ok()
+            .and_then(|modes| modes.into_iter().find(|m| m.mode() == mode))
+            .and_then(|m| m.frame_duration())
+            .map_or(30, frame_rate_of);
+        let counts = Arc::new(CaptureCounts::default());
+        *counts.timecode.lock().unwrap() =
+            Some(TimecodeTracker::new(TimecodeTrackerConfig::new(frame_rate)));
+        input.set_handler(Some(counts.clone()))?;
+        input.enable_video_input(mode, DecklinkPixelFormat::Format8BitYUV, flags)?;
+
+        // Always disable input again, so the device is left as it was found
+        let captured = input
+            .enable_audio_input(
+                DecklinkAudioSampleRate::Rate48kHz,
+                DecklinkAudioSampleType::Int32,
+                audio_channels,
+            )
+            .and_then(|_| input.start_streams())
+            .map(|_| {
+                std::thread::sleep(duration);
+                let _ = input.stop_streams_quiesced(None);
+            });
+        // Read before disabling, which forgets it
+        let vpid = input.vpid().ok().flatten();
+        let effective_config = input.effective_config();
+        let _ = input.disable_audio_input();
+        let _ = input.disable_video_input();
+        let _ = input.set_handler(None);
+        captured?;
+
+        self.audio_channels = audio_channels;
+        self.capture = Some(counts.clone());
+        self.vpid = vpid;
+        self.effective_config = Some(effective_config);
+
+        let frames = counts.frames.load(Ordering::Relaxed);
+        let detail = format!(
+            "{} frames in {:?} at {:?} ({} without a source, {} format changes{})",
+            frames,
+            duration,
+            mode,
+            counts.no_signal_frames.load(Ordering::Relaxed),
+            counts.format_changes.load(Ordering::Relaxed),
+            match *counts.last_mode.lock().unwrap() {
+                Some(mode) => format!(", last detected {:?}", mode),
+                None => String::new(),
+            }
+        );
+        if frames > 0 {
+            Finding::pass(detail)
+        } else {
+            Ok(Finding {
+                outcome: ProbeOutcome::Fail { hresult: None },
+                detail,
+            })
+        }
+    }
+
+    fn timecode(&mut self) -> Result<Finding, SdkError> {
+        let counts = match &self.capture {
+            Some(counts) if counts.frames.load(Ordering::Relaxed) > 0 => counts,
+            _ => return Finding::skip(SkipReason::NoSignal, "no live capture to inspect"),
+        };
+        let tracker = counts.timecode.lock().unwrap();
+        let tracker = match tracker.as_ref() {
+            Some(tracker) => tracker,
+            None => return Finding::skip(SkipReason::NoSignal, "no live capture to inspect"),
+        };
+        let present: Vec<String> = tracker
+            .stats()
+            .iter()
+            .filter(|s| s.frames_with_timecode > 0)
+            .map(|s| {
+                format!(
+                    "{:?} on {} frames, {} discontinuities",
+                    s.format, s.frames_with_timecode, s.discontinuities
+                )
+            })
+            .collect();
+        if present.is_empty() {
+            return Ok(Finding {
+                outcome: ProbeOutcome::Fail { hresult: None },
+                detail: format!("no timecode in {} frames", tracker.frame_count()),
+            });
+        }
+        Finding::pass(present.join("; "))
+    }
+
+    fn vanc(&mut self) -> Result<Finding, SdkError> {
+        Finding::skip(
+            SkipReason::Unsupported,
+            "VANC packets are not wrapped by this crate",
+        )
+    }
+
+    fn audio_channels(&mut self) -> Result<Finding, SdkError> {
+        let counts = match &self.capture {
+            Some(counts) if counts.frames.load(Ordering::Relaxed) > 0 => counts,
+            _ => return Finding::skip(SkipReason::NoSignal, "no live capture to inspect"),
+        };
+        let packets = counts.audio_packets.load(Ordering::Relaxed);
+        if packets == 0 {
+            return Ok(Finding {
+                outcome: ProbeOutcome::Fail { hresult: None },
+                detail: format!(
+                    "no audio packets with {} channels enabled",
+                    self.audio_channels
+                ),
+            });
+        }
+
+        let audible = counts.audible_channels.load(Ordering::Relaxed);
+        let channels: Vec<String> = (0..self.audio_channels)
+            .filter(|c| audible & (1 << c) != 0)
+            .map(|c| (c + 1).to_string())
+            .collect();
+        Finding::pass(format!(
+            "{} packets with {} channels enabled, audio on channels [{}]",
+            packets,
+            self.audio_channels,
+            channels.join(", ")
+        ))
+    }
+}
+
+fn panic_message(payload: Box<dyn std::any::Any + Send>) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+/// Run every probe against `device` with the default options.
+pub fn run_all(device: &DecklinkDevice) -> ProbeReport {
+    run_all_with(device, ProbeOptions::default())
+}
+
+/// Run every probe against `device`.
+pub fn run_all_with(device: &DecklinkDevice, options: ProbeOptions) -> ProbeReport {
+    let mut prober = Prober {
+        device,
+        options,
+        input: device.input(),
+        support_matrix: Vec::new(),
+        format_detection: false,
+        capture: None,
+        audio_channels: 0,
+        vpid: None,
+        effective_config: None,
+    };
+
+    type Probe<'a> = fn(&mut Prober<'a>) -> Result<Finding, SdkError>;
+    let probes: [Probe<'_>; 13] = [
+        Prober::attributes,
+        Prober::status,
+        Prober::health,
+        Prober::configuration,
+        Prober::display_modes,
+        Prober::support_matrix,
+        Prober::row_bytes,
+        Prober::format_detection,
+        Prober::allocator_round_trip,
+        Prober::live_signal,
+        Prober::timecode,
+        Prober::vanc,
+        Prober::audio_channels,
+    ];
+
+    let mut results = Vec::with_capacity(probes.len());
+    for (name, probe) in PROBE_NAMES.into_iter().zip(probes) {
+        let start = Instant::now();
+        let finding = catch_unwind(AssertUnwindSafe(|| probe(&mut prober)));
+        let duration = start.elapsed();
+
+        let mut finding = match finding {
+            Ok(Ok(finding)) => finding,
+            Ok(Err(e)) => Finding {
+                outcome: ProbeOutcome::Fail {
+                    hresult: Some(e.code()),
+                },
+                detail: format!("{:?}", e),
+            },
+            Err(payload) => Finding {
+                outcome: ProbeOutcome::Fail { hresult: None },
+                detail: format!("panicked: {}", panic_message(payload)),
+            },
+        };
+        if duration > options.probe_timeout && finding.outcome == ProbeOutcome::Pass {
+            finding = Finding {
+                outcome: ProbeOutcome::Fail { hresult: None },
+                detail: format!(
+                    "took longer than {:?}: {}",
+                    options.probe_timeout, finding.detail
+                ),
+            };
+        }
+
+        results.push(ProbeResult {
+            name,
+            outcome: finding.outcome,
+            detail: finding.detail,
+            duration,
+        });
+    }
+
+    let device_name = device.display_name();
+    let driver_version = api_version().ok();
+    let effective_config = prober.effective_config.map(|mut config| {
+        config.device = Some(DeviceIdentity {
+            persistent_id: device.persistent_id(),
+            display_name: device_name.clone(),
+        });
+        config.driver_version = driver_version.clone();
+        config
+    });
+    ProbeReport {
+        device_name,
+        model_name: device.model_name(),
+        driver_version,
+        vpid: prober.vpid,
+        effective_config,
+        results,
+        support_matrix: prober.support_matrix,
+    }
+}
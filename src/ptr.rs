@@ -0,0 +1,1486 @@
+//! Owned pointers to the C objects of the SDK.
+//!
+//! Every C object the crate holds a reference to is held by one of the pointer types here,
+//! such as `DisplayModePtr`, rather than by a raw pointer. The pointer types own that
+//! reference: they release it when dropped, take another with `clone`, and register with the
+//! `leak-check` accounting as they do so. This module and `sdk` are the only places the SDK is
+//! called from, so the reference counting of the crate can be audited in one place.
+//!
+//! Each pointer type only exposes the calls the wrappers need of its object, as safe methods
+//! where the object being live is all the call needs, and as documented unsafe methods
+//! otherwise.
+//!
+//! In debug builds, a pointer is nulled when its reference is released, and any use of it
+//! after that panics, rather than passing a dangling pointer to the driver.
+//!
+//! No other module of the crate calls the SDK directly, which `tests/ffi_boundary.rs` checks.
+
+use crate::sdk;
+use crate::util::{convert_c_string, track_created, track_dropped, SdkError};
+use std::ffi::c_char;
+use std::marker::PhantomData;
+use std::ptr::{null, null_mut};
+
+/// Copy a string the SDK gave out, and free it.
+unsafe fn convert_and_release_c_string(ptr: *const c_char) -> String {
+    let str = convert_c_string(ptr);
+    sdk::cdecklink_free_string(ptr);
+    str
+}
+
+/// A class of C object, with the calls that manage its reference count.
+///
+/// # Safety
+/// `add_ref` and `release` must increment and decrement the reference count of a live object
+/// of the class, as `IUnknown::AddRef` and `IUnknown::Release` do.
+pub(crate) unsafe trait FfiObject {
+    /// The C type of the object.
+    type Raw;
+    /// The name the object is accounted as by `leak-check`.
+    const NAME: &'static str;
+
+    unsafe fn add_ref(ptr: *mut Self::Raw);
+    unsafe fn release(ptr: *mut Self::Raw);
+}
+
+/// One reference to a C object of class `K`.
+pub(crate) struct Owned<K: FfiObject> {
+    ptr: *mut K::Raw,
+    kind: PhantomData<K>,
+}
+
+impl<K: FfiObject> Owned<K> {
+    /// Take ownership of a reference the SDK gave out.
+    ///
+    /// # Safety
+    /// `ptr` must be a live object of the class, and the reference must not be released by
+    /// anything else.
+    pub(crate) unsafe fn from_raw(ptr: *mut K::Raw) -> Self {
+        debug_assert!(!ptr.is_null(), "{} created from a null pointer", K::NAME);
+        track_created(K::NAME, ptr);
+        Owned {
+            ptr,
+            kind: PhantomData,
+        }
+    }
+
+    /// Take ownership of a reference the SDK gave out, or `None` if it gave out none.
+    ///
+    /// # Safety
+    /// As `from_raw`, when `ptr` is not null.
+    pub(crate) unsafe fn from_nullable(ptr: *mut K::Raw) -> Option<Self> {
+        if ptr.is_null() {
+            None
+        } else {
+            Some(Self::from_raw(ptr))
+        }
+    }
+
+    /// The object, to pass to the SDK. The reference is still owned by `self`.
+    pub(crate) fn as_ptr(&self) -> *mut K::Raw {
+        debug_assert!(!self.ptr.is_null(), "{} used after release", K::NAME);
+        self.ptr
+    }
+
+    /// Hand the reference over to the SDK, which releases it.
+    pub(crate) fn into_raw(mut self) -> *mut K::Raw {
+        let ptr = self.as_ptr();
+        track_dropped(K::NAME, ptr);
+        self.ptr = null_mut();
+        ptr
+    }
+
+    /// Release the reference now, rather than when `self` is dropped. Releasing it twice does
+    /// nothing.
+    pub(crate) fn release(&mut self) {
+        if !self.ptr.is_null() {
+            track_dropped(K::NAME, self.ptr);
+            // Safety: `from_raw` and `clone` took a reference, which nothing else releases
+            unsafe { K::release(self.ptr) };
+            self.ptr = null_mut();
+        }
+    }
+}
+
+impl<K: FfiObject> Clone for Owned<K> {
+    fn clone(&self) -> Self {
+        let ptr = self.as_ptr();
+        // Safety: `self` holds a reference, so the object is live
+        unsafe {
+            K::add_ref(ptr);
+            Self::from_raw(ptr)
+        }
+    }
+}
+
+impl<K: FfiObject> Drop for Owned<K> {
+    fn drop(&mut self) {
+        self.release();
+    }
+}
+
+/// Declare a class of C object, and the pointer type that owns a reference to one.
+macro_rules! ffi_object {
+    ($(#[$meta:meta])* $ptr:ident, $kind:ident, $raw:ty, $name:literal, $add_ref:ident, $release:ident) => {
+        pub(crate) enum $kind {}
+
+        unsafe impl FfiObject for $kind {
+            type Raw = $raw;
+            const NAME: &'static str = $name;
+
+            unsafe fn add_ref(ptr: *mut $raw) {
+                sdk::$add_ref(ptr);
+            }
+            unsafe fn release(ptr: *mut $raw) {
+                sdk::$release(ptr);
+            }
+        }
+
+        $(#[$meta])*
+        pub(crate) type $ptr = Owned<$kind>;
+    };
+}
+
+ffi_object!(
+    /// The iterator over the devices of the drivers.
+    DeviceIteratorPtr,
+    DeviceIteratorKind,
+    sdk::cdecklink_iterator_t,
+    "DecklinkIterator",
+    cdecklink_iterator_add_ref,
+    cdecklink_iterator_release
+);
+
+ffi_object!(
+    /// A device.
+    DevicePtr,
+    DeviceKind,
+    sdk::cdecklink_device_t,
+    "DecklinkDevice",
+    cdecklink_device_add_ref,
+    cdecklink_device_release
+);
+
+ffi_object!(
+    /// The attributes of a device, in its active profile.
+    ProfileAttributesPtr,
+    ProfileAttributesKind,
+    sdk::cdecklink_profile_attributes_t,
+    "DecklinkDeviceAttributes",
+    cdecklink_profile_attributes_add_ref,
+    cdecklink_profile_attributes_release
+);
+
+ffi_object!(
+    /// The status of a device.
+    StatusPtr,
+    StatusKind,
+    sdk::cdecklink_status_t,
+    "DecklinkDeviceStatus",
+    cdecklink_status_add_ref,
+    cdecklink_status_release
+);
+
+ffi_object!(
+    /// The notifications of a device.
+    NotificationPtr,
+    NotificationKind,
+    sdk::cdecklink_notification_t,
+    "DecklinkDeviceNotification",
+    cdecklink_notification_add_ref,
+    cdecklink_notification_release
+);
+
+ffi_object!(
+    /// The notifications of devices arriving and being removed.
+    DiscoveryPtr,
+    DiscoveryKind,
+    sdk::cdecklink_discovery_t,
+    "DecklinkDiscovery",
+    cdecklink_discovery_add_ref,
+    cdecklink_discovery_release
+);
+
+ffi_object!(
+    /// A display mode.
+    DisplayModePtr,
+    DisplayModeKind,
+    sdk::cdecklink_display_mode_t,
+    "DecklinkDisplayMode",
+    cdecklink_display_mode_add_ref,
+    cdecklink_display_mode_release
+);
+
+ffi_object!(
+    /// An iterator over the display modes of an input or output.
+    DisplayModeIteratorPtr,
+    DisplayModeIteratorKind,
+    sdk::cdecklink_display_mode_iterator_t,
+    "DisplayModeIterator",
+    cdecklink_display_mode_iterator_add_ref,
+    cdecklink_display_mode_iterator_release
+);
+
+ffi_object!(
+    /// The timecode of a frame.
+    TimecodePtr,
+    TimecodeKind,
+    sdk::cdecklink_timecode_t,
+    "Timecode",
+    cdecklink_timecode_add_ref,
+    cdecklink_timecode_release
+);
+
+ffi_object!(
+    /// The ancillary packets of a frame.
+    AncillaryPacketsPtr,
+    AncillaryPacketsKind,
+    sdk::cdecklink_video_frame_ancillary_packets_t,
+    "AncillaryPackets",
+    cdecklink_video_frame_ancillary_packets_add_ref,
+    cdecklink_video_frame_ancillary_packets_release
+);
+
+ffi_object!(
+    /// One ancillary packet of a frame.
+    AncillaryPacketPtr,
+    AncillaryPacketKind,
+    sdk::cdecklink_ancillary_packet_t,
+    "AncillaryPacket",
+    cdecklink_ancillary_packet_add_ref,
+    cdecklink_ancillary_packet_release
+);
+
+ffi_object!(
+    /// A video frame, as the wrappers read one.
+    VideoFramePtr,
+    VideoFrameKind,
+    sdk::cdecklink_video_frame_t,
+    "DecklinkVideoFrame",
+    cdecklink_video_frame_add_ref,
+    cdecklink_video_frame_release
+);
+
+ffi_object!(
+    /// A frame captured by an input.
+    VideoInputFramePtr,
+    VideoInputFrameKind,
+    sdk::cdecklink_video_input_frame_t,
+    "DecklinkVideoInputFrame",
+    cdecklink_video_input_frame_add_ref,
+    cdecklink_video_input_frame_release
+);
+
+ffi_object!(
+    /// A frame an output created, to be filled and played out.
+    MutableVideoFramePtr,
+    MutableVideoFrameKind,
+    sdk::cdecklink_mutable_video_frame_t,
+    "DecklinkVideoOutputFrame",
+    cdecklink_mutable_video_frame_add_ref,
+    cdecklink_mutable_video_frame_release
+);
+
+ffi_object!(
+    /// The capture interface of a device.
+    InputPtr,
+    InputKind,
+    sdk::cdecklink_input_t,
+    "DecklinkInputDevice",
+    cdecklink_input_add_ref,
+    cdecklink_input_release
+);
+
+ffi_object!(
+    /// The playback interface of a device.
+    OutputPtr,
+    OutputKind,
+    sdk::cdecklink_output_t,
+    "DecklinkOutputDevice",
+    cdecklink_output_add_ref,
+    cdecklink_output_release
+);
+
+ffi_object!(
+    /// A frame over a buffer of the crate's, to be played out.
+    CustomVideoFramePtr,
+    CustomVideoFrameKind,
+    sdk::cdecklink_custom_video_frame_t,
+    "DecklinkCustomVideoFrame",
+    cdecklink_custom_video_frame_add_ref,
+    cdecklink_custom_video_frame_release
+);
+
+ffi_object!(
+    /// A packet of audio samples captured by an input.
+    AudioInputPacketPtr,
+    AudioInputPacketKind,
+    sdk::cdecklink_audio_input_packet_t,
+    "DecklinkAudioInputPacket",
+    cdecklink_audio_input_packet_add_ref,
+    cdecklink_audio_input_packet_release
+);
+
+ffi_object!(
+    /// A buffer of frame data made by `crate::allocator`.
+    VideoBufferPtr,
+    VideoBufferKind,
+    sdk::cdecklink_video_buffer_t,
+    "VideoBuffer",
+    cdecklink_video_buffer_add_ref,
+    cdecklink_video_buffer_release
+);
+
+ffi_object!(
+    /// An allocator of video buffers made by `crate::allocator`.
+    VideoBufferAllocatorPtr,
+    VideoBufferAllocatorKind,
+    sdk::cdecklink_video_buffer_allocator_t,
+    "VideoBufferAllocator",
+    cdecklink_video_buffer_allocator_add_ref,
+    cdecklink_video_buffer_allocator_release
+);
+
+ffi_object!(
+    /// The allocator provider video input was enabled with, made by `crate::allocator`.
+    VideoBufferAllocatorProviderPtr,
+    VideoBufferAllocatorProviderKind,
+    sdk::cdecklink_video_buffer_allocator_provider_t,
+    "VideoBufferAllocatorProvider",
+    cdecklink_video_buffer_allocator_provider_add_ref,
+    cdecklink_video_buffer_allocator_provider_release
+);
+
+ffi_object!(
+    /// The driver's converter between pixel formats.
+    VideoConversionPtr,
+    VideoConversionKind,
+    sdk::cdecklink_video_conversion_t,
+    "SdkConversion",
+    cdecklink_video_conversion_add_ref,
+    cdecklink_video_conversion_release
+);
+
+ffi_object!(
+    /// The information the drivers give about themselves.
+    ApiInformationPtr,
+    ApiInformationKind,
+    sdk::cdecklink_api_information_t,
+    "DecklinkApiInformation",
+    cdecklink_api_information_add_ref,
+    cdecklink_api_information_release
+);
+
+impl DeviceIteratorPtr {
+    /// The iterator over the devices of the drivers, or `None` if none are installed.
+    pub(crate) fn create() -> Option<Self> {
+        unsafe { Self::from_nullable(sdk::cdecklink_create_decklink_iterator_instance()) }
+    }
+
+    /// The next device, or `None` once every device has been given.
+    pub(crate) fn next_device(&mut self) -> Result<Option<DevicePtr>, SdkError> {
+        let mut device = null_mut();
+        let result = unsafe { sdk::cdecklink_iterator_next(self.as_ptr(), &mut device) };
+        if SdkError::is_false(result) {
+            Ok(None)
+        } else {
+            SdkError::result::<()>(result)?;
+            unsafe { DevicePtr::from_nullable(device) }
+                .map(Some)
+                .ok_or(SdkError::POINTER)
+        }
+    }
+}
+
+impl DevicePtr {
+    pub(crate) fn model_name(&self) -> Option<String> {
+        let mut s = null();
+        let result = unsafe { sdk::cdecklink_device_get_model_name(self.as_ptr(), &mut s) };
+        if SdkError::is_ok(result) {
+            Some(unsafe { convert_and_release_c_string(s) })
+        } else {
+            None
+        }
+    }
+    pub(crate) fn display_name(&self) -> Option<String> {
+        let mut s = null();
+        let result = unsafe { sdk::cdecklink_device_get_display_name(self.as_ptr(), &mut s) };
+        if SdkError::is_ok(result) {
+            Some(unsafe { convert_and_release_c_string(s) })
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn profile_attributes(&self) -> Result<ProfileAttributesPtr, SdkError> {
+        let mut attributes = null_mut();
+        let result = unsafe {
+            sdk::cdecklink_device_query_profile_attributes(self.as_ptr(), &mut attributes)
+        };
+        SdkError::result::<()>(result)?;
+        unsafe { ProfileAttributesPtr::from_nullable(attributes) }.ok_or(SdkError::POINTER)
+    }
+    pub(crate) fn status(&self) -> Result<StatusPtr, SdkError> {
+        let mut status = null_mut();
+        let result = unsafe { sdk::cdecklink_device_query_status(self.as_ptr(), &mut status) };
+        SdkError::result::<()>(result)?;
+        unsafe { StatusPtr::from_nullable(status) }.ok_or(SdkError::POINTER)
+    }
+
+    /// The playback interface of the device, or `None` if it has none.
+    pub(crate) fn output(&self) -> Option<OutputPtr> {
+        let mut output = null_mut();
+        let result = unsafe { sdk::cdecklink_device_query_output(self.as_ptr(), &mut output) };
+        if SdkError::is_ok(result) {
+            unsafe { OutputPtr::from_nullable(output) }
+        } else {
+            None
+        }
+    }
+    /// The capture interface of the device, or `None` if it has none.
+    pub(crate) fn input(&self) -> Option<InputPtr> {
+        let mut input = null_mut();
+        let result = unsafe { sdk::cdecklink_device_query_input(self.as_ptr(), &mut input) };
+        if SdkError::is_ok(result) {
+            unsafe { InputPtr::from_nullable(input) }
+        } else {
+            None
+        }
+    }
+}
+
+impl ProfileAttributesPtr {
+    pub(crate) fn flag(&self, id: sdk::DecklinkAttributeID) -> Result<bool, SdkError> {
+        let mut value = false;
+        let result =
+            unsafe { sdk::cdecklink_profile_attributes_get_flag(self.as_ptr(), id, &mut value) };
+        SdkError::result_or(result, value)
+    }
+    pub(crate) fn int(&self, id: sdk::DecklinkAttributeID) -> Result<i64, SdkError> {
+        let mut value = 0;
+        let result =
+            unsafe { sdk::cdecklink_profile_attributes_get_int(self.as_ptr(), id, &mut value) };
+        SdkError::result_or(result, value)
+    }
+    pub(crate) fn float(&self, id: sdk::DecklinkAttributeID) -> Result<f64, SdkError> {
+        let mut value = 0.0;
+        let result =
+            unsafe { sdk::cdecklink_profile_attributes_get_float(self.as_ptr(), id, &mut value) };
+        SdkError::result_or(result, value)
+    }
+    /// A string attribute, which is released once copied.
+    pub(crate) fn string(&self, id: sdk::DecklinkAttributeID) -> Result<String, SdkError> {
+        let mut value = null();
+        let result =
+            unsafe { sdk::cdecklink_profile_attributes_get_string(self.as_ptr(), id, &mut value) };
+        SdkError::result_or_else(result, || unsafe { convert_and_release_c_string(value) })
+    }
+    /// A string attribute the driver keeps ownership of, such as the vendor name.
+    pub(crate) fn borrowed_string(&self, id: sdk::DecklinkAttributeID) -> Result<String, SdkError> {
+        let mut value = null();
+        let result =
+            unsafe { sdk::cdecklink_profile_attributes_get_string(self.as_ptr(), id, &mut value) };
+        SdkError::result_or_else(result, || unsafe { convert_c_string(value) })
+    }
+}
+
+impl StatusPtr {
+    pub(crate) fn int(&self, id: sdk::DecklinkStatusID) -> Result<i64, SdkError> {
+        let mut value = 0;
+        let result = unsafe { sdk::cdecklink_status_get_int(self.as_ptr(), id, &mut value) };
+        SdkError::result_or(result, value)
+    }
+    pub(crate) fn flag(&self, id: sdk::DecklinkStatusID) -> Result<bool, SdkError> {
+        let mut value = false;
+        let result = unsafe { sdk::cdecklink_status_get_flag(self.as_ptr(), id, &mut value) };
+        SdkError::result_or(result, value)
+    }
+    pub(crate) fn float(&self, id: sdk::DecklinkStatusID) -> Result<f64, SdkError> {
+        let mut value = 0.0;
+        let result = unsafe { sdk::cdecklink_status_get_float(self.as_ptr(), id, &mut value) };
+        SdkError::result_or(result, value)
+    }
+    pub(crate) fn string(&self, id: sdk::DecklinkStatusID) -> Result<String, SdkError> {
+        let mut value = null();
+        let result = unsafe { sdk::cdecklink_status_get_string(self.as_ptr(), id, &mut value) };
+        SdkError::result_or_else(result, || unsafe { convert_and_release_c_string(value) })
+    }
+    /// A status item of bytes, read by asking for its size first.
+    pub(crate) fn bytes(&self, id: sdk::DecklinkStatusID) -> Result<Vec<u8>, SdkError> {
+        let mut byte_count = 0;
+        let result = unsafe {
+            sdk::cdecklink_status_get_bytes(self.as_ptr(), id, null_mut(), &mut byte_count)
+        };
+        SdkError::result::<()>(result)?;
+        let mut bytes = vec![0; byte_count as usize];
+        let result = unsafe {
+            sdk::cdecklink_status_get_bytes(
+                self.as_ptr(),
+                id,
+                bytes.as_mut_ptr() as *mut std::ffi::c_void,
+                &mut byte_count,
+            )
+        };
+        SdkError::result_or(result, bytes)
+    }
+}
+
+impl NotificationPtr {
+    /// Subscribe `notify` to `topic`, returning the handle to unsubscribe it with.
+    ///
+    /// # Safety
+    /// `context` must be what `notify` expects, and stay live until it is unsubscribed.
+    pub(crate) unsafe fn subscribe(
+        &self,
+        topic: sdk::DecklinkNotifications,
+        context: *mut std::ffi::c_void,
+        notify: sdk::cdecklink_notification_callback_notify,
+    ) -> Result<*mut sdk::cdecklink_notification_callback_notify_handle, SdkError> {
+        let mut handle = null_mut();
+        let result = sdk::cdecklink_notification_subscribe(
+            self.as_ptr(),
+            topic,
+            context,
+            notify,
+            &mut handle,
+        );
+        SdkError::result_or(result, handle)
+    }
+
+    /// Unsubscribe what `subscribe` subscribed to `topic`.
+    ///
+    /// # Safety
+    /// `handle` must have been returned by `subscribe` for `topic`, and not unsubscribed yet.
+    pub(crate) unsafe fn unsubscribe(
+        &self,
+        topic: sdk::DecklinkNotifications,
+        handle: *mut sdk::cdecklink_notification_callback_notify_handle,
+    ) -> Result<(), SdkError> {
+        let result = sdk::cdecklink_notification_unsubscribe(self.as_ptr(), topic, handle);
+        SdkError::result(result)
+    }
+}
+
+impl DiscoveryPtr {
+    /// The discovery of the drivers, or `None` if they have none.
+    pub(crate) fn create() -> Option<Self> {
+        unsafe { Self::from_nullable(sdk::cdecklink_create_decklink_discovery_instance()) }
+    }
+
+    /// Register the functions the driver calls back with `context` as devices arrive and are
+    /// removed.
+    ///
+    /// # Safety
+    /// `context` must be what the functions expect, and stay live until
+    /// `uninstall_device_notifications`.
+    pub(crate) unsafe fn install_device_notifications(
+        &self,
+        context: *mut std::ffi::c_void,
+        arrived: sdk::cdecklink_device_notification_callback_deck_link_device_arrived,
+        removed: sdk::cdecklink_device_notification_callback_deck_link_device_removed,
+    ) -> Result<(), SdkError> {
+        let result = sdk::cdecklink_discovery_install_device_notifications(
+            self.as_ptr(),
+            context,
+            arrived,
+            removed,
+        );
+        SdkError::result(result)
+    }
+    pub(crate) fn uninstall_device_notifications(&self) -> Result<(), SdkError> {
+        let result =
+            unsafe { sdk::cdecklink_discovery_uninstall_device_notifications(self.as_ptr()) };
+        SdkError::result(result)
+    }
+}
+
+impl DisplayModePtr {
+    /// Take a reference of its own to a display mode the driver lent to a callback.
+    ///
+    /// # Safety
+    /// `mode` must be a live display mode.
+    pub(crate) unsafe fn retain(mode: *mut sdk::cdecklink_display_mode_t) -> Self {
+        sdk::cdecklink_display_mode_add_ref(mode);
+        Self::from_raw(mode)
+    }
+
+    pub(crate) fn name(&self) -> Option<String> {
+        let mut s = null();
+        let result = unsafe { sdk::cdecklink_display_mode_get_name(self.as_ptr(), &mut s) };
+        if SdkError::is_ok(result) {
+            Some(unsafe { convert_and_release_c_string(s) })
+        } else {
+            None
+        }
+    }
+    pub(crate) fn display_mode(&self) -> u32 {
+        unsafe { sdk::cdecklink_display_mode_get_display_mode(self.as_ptr()) }
+    }
+    pub(crate) fn width(&self) -> usize {
+        unsafe { sdk::cdecklink_display_mode_get_width(self.as_ptr()) as usize }
+    }
+    pub(crate) fn height(&self) -> usize {
+        unsafe { sdk::cdecklink_display_mode_get_height(self.as_ptr()) as usize }
+    }
+    /// The duration of a frame and the time scale it is in.
+    pub(crate) fn frame_rate(&self) -> Option<(i64, i64)> {
+        let mut duration = 0;
+        let mut scale = 0;
+        let result = unsafe {
+            sdk::cdecklink_display_mode_get_frame_rate(self.as_ptr(), &mut duration, &mut scale)
+        };
+        if SdkError::is_ok(result) {
+            Some((duration, scale))
+        } else {
+            None
+        }
+    }
+    pub(crate) fn field_dominance(&self) -> u32 {
+        unsafe { sdk::cdecklink_display_mode_get_field_dominance(self.as_ptr()) }
+    }
+    pub(crate) fn flags(&self) -> u32 {
+        unsafe { sdk::cdecklink_display_mode_get_flags(self.as_ptr()) }
+    }
+}
+
+impl DisplayModeIteratorPtr {
+    /// The next display mode, or `None` once every mode has been given.
+    pub(crate) fn next_mode(&mut self) -> Result<Option<DisplayModePtr>, SdkError> {
+        let mut mode = null_mut();
+        let result = unsafe { sdk::cdecklink_display_mode_iterator_next(self.as_ptr(), &mut mode) };
+        if SdkError::is_false(result) {
+            Ok(None)
+        } else {
+            SdkError::result::<()>(result)?;
+            Ok(unsafe { DisplayModePtr::from_nullable(mode) })
+        }
+    }
+}
+
+impl TimecodePtr {
+    /// The hours, minutes, seconds and frames of the timecode.
+    pub(crate) fn components(&self) -> Result<(u8, u8, u8, u8), SdkError> {
+        let (mut hours, mut minutes, mut seconds, mut frames) = (0, 0, 0, 0);
+        let result = unsafe {
+            sdk::cdecklink_timecode_get_components(
+                self.as_ptr(),
+                &mut hours,
+                &mut minutes,
+                &mut seconds,
+                &mut frames,
+            )
+        };
+        SdkError::result_or(result, (hours, minutes, seconds, frames))
+    }
+    pub(crate) fn flags(&self) -> u32 {
+        unsafe { sdk::cdecklink_timecode_get_flags(self.as_ptr()) }
+    }
+    pub(crate) fn user_bits(&self) -> Result<u32, SdkError> {
+        let mut user_bits = 0;
+        let result = unsafe {
+            sdk::cdecklink_timecode_get_timecode_user_bits(self.as_ptr(), &mut user_bits)
+        };
+        SdkError::result_or(result, user_bits)
+    }
+}
+
+impl AncillaryPacketsPtr {
+    /// The ancillary packets of `frame`, or `None` if it has none.
+    ///
+    /// # Safety
+    /// `frame` must be a live frame.
+    pub(crate) unsafe fn of_frame(
+        frame: *mut sdk::cdecklink_video_frame_t,
+    ) -> Result<Option<Self>, SdkError> {
+        let mut packets = null_mut();
+        let result =
+            sdk::cdecklink_video_frame_query_video_frame_ancillary_packets(frame, &mut packets);
+        SdkError::result::<()>(result)?;
+        Ok(Self::from_nullable(packets))
+    }
+
+    /// The first packet with the data id `did` and secondary data id `sdid`, or `None` if
+    /// there is none.
+    pub(crate) fn first_by_id(
+        &self,
+        did: u8,
+        sdid: u8,
+    ) -> Result<Option<AncillaryPacketPtr>, SdkError> {
+        let mut packet = null_mut();
+        let result = unsafe {
+            sdk::cdecklink_video_frame_ancillary_packets_get_first_packet_by_id(
+                self.as_ptr(),
+                did,
+                sdid,
+                &mut packet,
+            )
+        };
+        if SdkError::is_false(result) {
+            Ok(None)
+        } else {
+            SdkError::result::<()>(result)?;
+            Ok(unsafe { AncillaryPacketPtr::from_nullable(packet) })
+        }
+    }
+}
+
+impl AncillaryPacketPtr {
+    /// The user data words of the packet, as 8-bit values. The words are owned by the
+    /// packet, so are copied out.
+    pub(crate) fn bytes(&self) -> Result<Vec<u8>, SdkError> {
+        let mut data = null();
+        let mut size = 0;
+        let result = unsafe {
+            sdk::cdecklink_ancillary_packet_get_bytes(
+                self.as_ptr(),
+                sdk::_DecklinkAncillaryPacketFormat_decklinkAncillaryPacketFormatUInt8,
+                &mut data,
+                &mut size,
+            )
+        };
+        SdkError::result::<()>(result)?;
+        if data.is_null() {
+            return Ok(Vec::new());
+        }
+        // Safety: The packet owns `size` bytes at `data` for as long as it is live
+        Ok(unsafe { std::slice::from_raw_parts(data as *const u8, size as usize) }.to_vec())
+    }
+}
+
+impl VideoFramePtr {
+    /// Take a reference of its own to a frame that is lent, or owned by another wrapper.
+    ///
+    /// # Safety
+    /// `frame` must be a live frame.
+    pub(crate) unsafe fn retain(frame: *mut sdk::cdecklink_video_frame_t) -> Self {
+        sdk::cdecklink_video_frame_add_ref(frame);
+        Self::from_raw(frame)
+    }
+
+    pub(crate) fn width(&self) -> usize {
+        unsafe { sdk::cdecklink_video_frame_get_width(self.as_ptr()) as usize }
+    }
+    pub(crate) fn height(&self) -> usize {
+        unsafe { sdk::cdecklink_video_frame_get_height(self.as_ptr()) as usize }
+    }
+    pub(crate) fn row_bytes(&self) -> usize {
+        unsafe { sdk::cdecklink_video_frame_get_row_bytes(self.as_ptr()) as usize }
+    }
+    pub(crate) fn pixel_format(&self) -> u32 {
+        unsafe { sdk::cdecklink_video_frame_get_pixel_format(self.as_ptr()) }
+    }
+    pub(crate) fn flags(&self) -> u32 {
+        unsafe { sdk::cdecklink_video_frame_get_flags(self.as_ptr()) }
+    }
+
+    /// The buffer of the frame, of `row_bytes() * height()` bytes, which lives as long as the
+    /// frame does. Drivers from 15.0 expect `end_access` once it has been read.
+    pub(crate) fn bytes(&self) -> Result<*const u8, SdkError> {
+        let mut bytes = null_mut();
+        let result = unsafe { sdk::cdecklink_video_frame_get_bytes(self.as_ptr(), &mut bytes) };
+        SdkError::result::<()>(result)?;
+        if bytes.is_null() {
+            return Err(SdkError::POINTER);
+        }
+        Ok(bytes as *const u8)
+    }
+    /// End the access to the buffer that `bytes` began.
+    pub(crate) fn end_access(&self) {
+        unsafe { sdk::cdecklink_video_frame_end_access(self.as_ptr()) };
+    }
+
+    /// The timecode of the frame in `format`, or `None` if it has none.
+    pub(crate) fn timecode(&self, format: u32) -> Result<Option<TimecodePtr>, SdkError> {
+        let mut timecode = null_mut();
+        let result = unsafe {
+            sdk::cdecklink_video_frame_get_timecode(self.as_ptr(), format, &mut timecode)
+        };
+        if SdkError::is_false(result) {
+            return Ok(None);
+        }
+        SdkError::result::<()>(result)?;
+        Ok(unsafe { TimecodePtr::from_nullable(timecode) })
+    }
+}
+
+impl VideoInputFramePtr {
+    /// Take a reference of its own to a frame the driver lent to a callback.
+    ///
+    /// # Safety
+    /// `frame` must be a live frame.
+    pub(crate) unsafe fn retain(frame: *mut sdk::cdecklink_video_input_frame_t) -> Self {
+        sdk::cdecklink_video_input_frame_add_ref(frame);
+        Self::from_raw(frame)
+    }
+
+    /// The stream time and duration of the frame, in ticks of `scale` per second.
+    pub(crate) fn stream_time(&self, scale: i64) -> Result<(i64, i64), SdkError> {
+        let mut time = 0;
+        let mut duration = 0;
+        let result = unsafe {
+            sdk::cdecklink_video_input_frame_get_stream_time(
+                self.as_ptr(),
+                &mut time,
+                &mut duration,
+                scale,
+            )
+        };
+        SdkError::result_or(result, (time, duration))
+    }
+
+    /// The time the frame was captured and its duration by the hardware reference clock, in
+    /// ticks of `scale` per second.
+    pub(crate) fn hardware_reference_timestamp(&self, scale: i64) -> Result<(i64, i64), SdkError> {
+        let mut time = 0;
+        let mut duration = 0;
+        let result = unsafe {
+            sdk::cdecklink_video_input_frame_get_hardware_reference_timestamp(
+                self.as_ptr(),
+                scale,
+                &mut time,
+                &mut duration,
+            )
+        };
+        SdkError::result_or(result, (time, duration))
+    }
+
+    /// The frame, as the video frame its pixels are read through, or null if the driver
+    /// cannot give one. No reference is taken, so it is only live as long as `self` is.
+    pub(crate) fn as_video_frame(&self) -> *mut sdk::cdecklink_video_frame_t {
+        unsafe { sdk::cdecklink_video_input_frame_to_video_frame(self.as_ptr()) }
+    }
+}
+
+impl MutableVideoFramePtr {
+    /// The frame, as the video frame the calls that play one out take. The reference is still
+    /// owned by `self`.
+    pub(crate) fn as_video_frame(&self) -> *mut sdk::cdecklink_video_frame_t {
+        // A mutable frame is a video frame, and the C types of both are opaque
+        self.as_ptr() as *mut sdk::cdecklink_video_frame_t
+    }
+
+    pub(crate) fn width(&self) -> usize {
+        unsafe { sdk::cdecklink_video_frame_get_width(self.as_video_frame()) as usize }
+    }
+    pub(crate) fn height(&self) -> usize {
+        unsafe { sdk::cdecklink_video_frame_get_height(self.as_video_frame()) as usize }
+    }
+    pub(crate) fn row_bytes(&self) -> usize {
+        unsafe { sdk::cdecklink_video_frame_get_row_bytes(self.as_video_frame()) as usize }
+    }
+    pub(crate) fn pixel_format(&self) -> u32 {
+        unsafe { sdk::cdecklink_video_frame_get_pixel_format(self.as_video_frame()) }
+    }
+    pub(crate) fn flags(&self) -> u32 {
+        unsafe { sdk::cdecklink_video_frame_get_flags(self.as_video_frame()) }
+    }
+
+    /// The buffer of the frame, of `row_bytes() * height()` bytes, which lives as long as the
+    /// frame does.
+    pub(crate) fn bytes(&self) -> Result<*mut u8, SdkError> {
+        let mut bytes = null_mut();
+        let result =
+            unsafe { sdk::cdecklink_video_frame_get_bytes(self.as_video_frame(), &mut bytes) };
+        SdkError::result::<()>(result)?;
+        if bytes.is_null() {
+            return Err(SdkError::POINTER);
+        }
+        Ok(bytes as *mut u8)
+    }
+}
+
+impl InputPtr {
+    /// Whether the input can capture `mode` in `pixel_format` with `flags`, and the mode it
+    /// would capture instead if not.
+    pub(crate) fn does_support_video_mode(
+        &self,
+        mode: u32,
+        pixel_format: u32,
+        flags: u32,
+    ) -> Result<(bool, u32), SdkError> {
+        let mut supported = false;
+        let mut actual_mode = 0;
+        let result = unsafe {
+            sdk::cdecklink_input_does_support_video_mode(
+                self.as_ptr(),
+                sdk::_DecklinkVideoConnection_decklinkVideoConnectionUnspecified,
+                mode,
+                pixel_format,
+                sdk::_DecklinkVideoInputConversionMode_decklinkNoVideoInputConversion,
+                flags,
+                &mut actual_mode,
+                &mut supported,
+            )
+        };
+        SdkError::result_or(result, (supported, actual_mode))
+    }
+
+    pub(crate) fn display_mode_iterator(&self) -> Result<DisplayModeIteratorPtr, SdkError> {
+        let mut it = null_mut();
+        let result =
+            unsafe { sdk::cdecklink_input_get_display_mode_iterator(self.as_ptr(), &mut it) };
+        SdkError::result::<()>(result)?;
+        unsafe { DisplayModeIteratorPtr::from_nullable(it) }.ok_or(SdkError::POINTER)
+    }
+
+    /// The display mode `mode`, or `None` if the input does not have it.
+    pub(crate) fn display_mode(&self, mode: u32) -> Option<DisplayModePtr> {
+        let mut display_mode = null_mut();
+        let result = unsafe {
+            sdk::cdecklink_input_get_display_mode(self.as_ptr(), mode, &mut display_mode)
+        };
+        if SdkError::is_ok(result) {
+            unsafe { DisplayModePtr::from_nullable(display_mode) }
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn enable_video_input(
+        &self,
+        mode: u32,
+        pixel_format: u32,
+        flags: u32,
+    ) -> Result<(), SdkError> {
+        let result = unsafe {
+            sdk::cdecklink_input_enable_video_input(self.as_ptr(), mode, pixel_format, flags)
+        };
+        SdkError::result(result)
+    }
+
+    /// Enable video input with frames allocated by `provider`. The driver takes a reference of
+    /// its own to the provider, which it holds until video input is disabled.
+    pub(crate) fn enable_video_input_with_allocator_provider(
+        &self,
+        mode: u32,
+        pixel_format: u32,
+        flags: u32,
+        provider: &VideoBufferAllocatorProviderPtr,
+    ) -> Result<(), SdkError> {
+        let result = unsafe {
+            sdk::cdecklink_input_enable_video_input_with_allocator_provider(
+                self.as_ptr(),
+                mode,
+                pixel_format,
+                flags,
+                provider.as_ptr(),
+            )
+        };
+        SdkError::result(result)
+    }
+
+    pub(crate) fn disable_video_input(&self) -> Result<(), SdkError> {
+        SdkError::result(unsafe { sdk::cdecklink_input_disable_video_input(self.as_ptr()) })
+    }
+
+    pub(crate) fn enable_audio_input(
+        &self,
+        sample_rate: u32,
+        sample_type: u32,
+        channel_count: u32,
+    ) -> Result<(), SdkError> {
+        let result = unsafe {
+            sdk::cdecklink_input_enable_audio_input(
+                self.as_ptr(),
+                sample_rate,
+                sample_type,
+                channel_count,
+            )
+        };
+        SdkError::result(result)
+    }
+
+    pub(crate) fn disable_audio_input(&self) -> Result<(), SdkError> {
+        SdkError::result(unsafe { sdk::cdecklink_input_disable_audio_input(self.as_ptr()) })
+    }
+
+    pub(crate) fn start_streams(&self) -> Result<(), SdkError> {
+        SdkError::result(unsafe { sdk::cdecklink_input_start_streams(self.as_ptr()) })
+    }
+    pub(crate) fn stop_streams(&self) -> Result<(), SdkError> {
+        SdkError::result(unsafe { sdk::cdecklink_input_stop_streams(self.as_ptr()) })
+    }
+    pub(crate) fn pause_streams(&self) -> Result<(), SdkError> {
+        SdkError::result(unsafe { sdk::cdecklink_input_pause_streams(self.as_ptr()) })
+    }
+    pub(crate) fn flush_streams(&self) -> Result<(), SdkError> {
+        SdkError::result(unsafe { sdk::cdecklink_input_flush_streams(self.as_ptr()) })
+    }
+
+    pub(crate) fn available_video_frame_count(&self) -> Result<u32, SdkError> {
+        let mut count = 0;
+        let result = unsafe {
+            sdk::cdecklink_input_get_available_video_frame_count(self.as_ptr(), &mut count)
+        };
+        SdkError::result_or(result, count)
+    }
+    pub(crate) fn available_audio_sample_frame_count(&self) -> Result<u32, SdkError> {
+        let mut count = 0;
+        let result = unsafe {
+            sdk::cdecklink_input_get_available_audio_sample_frame_count(self.as_ptr(), &mut count)
+        };
+        SdkError::result_or(result, count)
+    }
+
+    /// Register the functions the driver calls back with `context`, or unregister them with
+    /// `None`.
+    ///
+    /// # Safety
+    /// `context` must be what the functions expect, and stay live until they are unregistered
+    /// or the input is released.
+    pub(crate) unsafe fn set_callback(
+        &self,
+        context: *mut std::ffi::c_void,
+        format_changed: sdk::cdecklink_input_callback_video_input_format_changed,
+        frame_arrived: sdk::cdecklink_input_callback_video_input_frame_arrived,
+    ) -> Result<(), SdkError> {
+        let result = sdk::cdecklink_input_set_callback(
+            self.as_ptr(),
+            context,
+            format_changed,
+            frame_arrived,
+        );
+        SdkError::result(result)
+    }
+}
+
+impl VideoBufferPtr {
+    /// Create a buffer whose calls are forwarded to the functions given, with `context`.
+    ///
+    /// # Safety
+    /// `context` must be what the functions expect. The buffer owns it once created, and
+    /// passes it to `release` when its last reference is released.
+    pub(crate) unsafe fn create_custom(
+        context: *mut std::ffi::c_void,
+        get_bytes: sdk::cdecklink_custom_video_buffer_get_bytes_fn,
+        start_access: sdk::cdecklink_custom_video_buffer_start_access_fn,
+        end_access: sdk::cdecklink_custom_video_buffer_end_access_fn,
+        release: sdk::cdecklink_custom_video_buffer_release_fn,
+    ) -> Result<Self, SdkError> {
+        let mut buffer = null_mut();
+        let result = sdk::cdecklink_custom_video_buffer_create(
+            context,
+            get_bytes,
+            start_access,
+            end_access,
+            release,
+            &mut buffer,
+        );
+        SdkError::result::<()>(result)?;
+        Self::from_nullable(buffer).ok_or(SdkError::POINTER)
+    }
+}
+
+impl VideoBufferAllocatorPtr {
+    /// Create an allocator whose calls are forwarded to the functions given, with `context`.
+    ///
+    /// # Safety
+    /// As `VideoBufferPtr::create_custom`.
+    pub(crate) unsafe fn create_custom(
+        context: *mut std::ffi::c_void,
+        allocate: sdk::cdecklink_custom_video_buffer_allocator_allocate_fn,
+        release: sdk::cdecklink_custom_video_buffer_allocator_release_fn,
+    ) -> Result<Self, SdkError> {
+        let mut allocator = null_mut();
+        let result = sdk::cdecklink_custom_video_buffer_allocator_create(
+            context,
+            allocate,
+            release,
+            &mut allocator,
+        );
+        SdkError::result::<()>(result)?;
+        Self::from_nullable(allocator).ok_or(SdkError::POINTER)
+    }
+}
+
+impl VideoBufferAllocatorProviderPtr {
+    /// Create a provider whose calls are forwarded to the functions given, with `context`.
+    ///
+    /// # Safety
+    /// As `VideoBufferPtr::create_custom`.
+    pub(crate) unsafe fn create_custom(
+        context: *mut std::ffi::c_void,
+        get_allocator: sdk::cdecklink_custom_video_buffer_allocator_provider_get_allocator_fn,
+        release: sdk::cdecklink_custom_video_buffer_allocator_provider_release_fn,
+    ) -> Result<Self, SdkError> {
+        let mut provider = null_mut();
+        let result = sdk::cdecklink_custom_video_buffer_allocator_provider_create(
+            context,
+            get_allocator,
+            release,
+            &mut provider,
+        );
+        SdkError::result::<()>(result)?;
+        Self::from_nullable(provider).ok_or(SdkError::POINTER)
+    }
+}
+
+impl OutputPtr {
+    /// Whether the output can play out `mode` in `pixel_format` with `flags`, and the mode it
+    /// would play out instead if not.
+    pub(crate) fn does_support_video_mode(
+        &self,
+        mode: u32,
+        pixel_format: u32,
+        flags: u32,
+    ) -> Result<(bool, u32), SdkError> {
+        let mut supported = false;
+        let mut actual_mode = 0;
+        let result = unsafe {
+            sdk::cdecklink_output_does_support_video_mode(
+                self.as_ptr(),
+                sdk::_DecklinkVideoConnection_decklinkVideoConnectionUnspecified,
+                mode,
+                pixel_format,
+                sdk::_DecklinkVideoOutputConversionMode_decklinkNoVideoOutputConversion,
+                flags,
+                &mut actual_mode,
+                &mut supported,
+            )
+        };
+        SdkError::result_or(result, (supported, actual_mode))
+    }
+
+    pub(crate) fn display_mode_iterator(&self) -> Result<DisplayModeIteratorPtr, SdkError> {
+        let mut it = null_mut();
+        let result =
+            unsafe { sdk::cdecklink_output_get_display_mode_iterator(self.as_ptr(), &mut it) };
+        SdkError::result::<()>(result)?;
+        unsafe { DisplayModeIteratorPtr::from_nullable(it) }.ok_or(SdkError::POINTER)
+    }
+
+    pub(crate) fn row_bytes_for_pixel_format(
+        &self,
+        pixel_format: u32,
+        width: i32,
+    ) -> Result<i32, SdkError> {
+        let mut row_bytes = 0;
+        let result = unsafe {
+            sdk::cdecklink_output_row_bytes_for_pixel_format(
+                self.as_ptr(),
+                pixel_format,
+                width,
+                &mut row_bytes,
+            )
+        };
+        SdkError::result_or(result, row_bytes)
+    }
+
+    /// Create a frame with a buffer of the driver's.
+    pub(crate) fn create_video_frame(
+        &self,
+        width: i32,
+        height: i32,
+        row_bytes: i32,
+        pixel_format: u32,
+        flags: u32,
+    ) -> Result<MutableVideoFramePtr, SdkError> {
+        let mut frame = null_mut();
+        let result = unsafe {
+            sdk::cdecklink_output_create_video_frame(
+                self.as_ptr(),
+                width,
+                height,
+                row_bytes,
+                pixel_format,
+                flags,
+                &mut frame,
+            )
+        };
+        SdkError::result::<()>(result)?;
+        unsafe { MutableVideoFramePtr::from_nullable(frame) }.ok_or(SdkError::FAIL)
+    }
+
+    pub(crate) fn enable_video_output(&self, mode: u32, flags: u32) -> Result<(), SdkError> {
+        let result =
+            unsafe { sdk::cdecklink_output_enable_video_output(self.as_ptr(), mode, flags) };
+        SdkError::result(result)
+    }
+    /// Disable video output, blocking until the frame callbacks in progress are complete.
+    pub(crate) fn disable_video_output(&self) -> Result<(), SdkError> {
+        SdkError::result(unsafe { sdk::cdecklink_output_disable_video_output(self.as_ptr()) })
+    }
+
+    /// Show `frame` straight away, blocking until the driver has taken it.
+    ///
+    /// # Safety
+    /// `frame` must be a live frame.
+    pub(crate) unsafe fn display_video_frame_sync(
+        &self,
+        frame: *mut sdk::cdecklink_video_frame_t,
+    ) -> Result<(), SdkError> {
+        let result = sdk::cdecklink_output_display_video_frame_sync(self.as_ptr(), frame);
+        SdkError::result(result)
+    }
+
+    /// Schedule `frame` to be played out. The driver takes a reference of its own to it, which
+    /// it holds until the frame has completed.
+    ///
+    /// # Safety
+    /// `frame` must be a live frame.
+    pub(crate) unsafe fn schedule_video_frame(
+        &self,
+        frame: *mut sdk::cdecklink_video_frame_t,
+        display_time: i64,
+        duration: i64,
+        timescale: i64,
+    ) -> Result<(), SdkError> {
+        let result = sdk::cdecklink_output_schedule_video_frame(
+            self.as_ptr(),
+            frame,
+            display_time,
+            duration,
+            timescale,
+        );
+        SdkError::result(result)
+    }
+
+    pub(crate) fn buffered_video_frame_count(&self) -> Result<u32, SdkError> {
+        let mut count = 0;
+        let result = unsafe {
+            sdk::cdecklink_output_get_buffered_video_frame_count(self.as_ptr(), &mut count)
+        };
+        SdkError::result_or(result, count)
+    }
+
+    pub(crate) fn start_scheduled_playback(
+        &self,
+        start_time: i64,
+        timescale: i64,
+        speed: f64,
+    ) -> Result<(), SdkError> {
+        let result = unsafe {
+            sdk::cdecklink_output_start_scheduled_playback(
+                self.as_ptr(),
+                start_time,
+                timescale,
+                speed,
+            )
+        };
+        SdkError::result(result)
+    }
+    /// Stop scheduled playback at `stop_time`, or straight away if 0, returning the time it
+    /// stopped at.
+    pub(crate) fn stop_scheduled_playback(
+        &self,
+        stop_time: i64,
+        timescale: i64,
+    ) -> Result<i64, SdkError> {
+        let mut actual_stop_time = 0;
+        let result = unsafe {
+            sdk::cdecklink_output_stop_scheduled_playback(
+                self.as_ptr(),
+                stop_time,
+                &mut actual_stop_time,
+                timescale,
+            )
+        };
+        SdkError::result_or(result, actual_stop_time)
+    }
+    pub(crate) fn is_scheduled_playback_running(&self) -> Result<bool, SdkError> {
+        let mut running = false;
+        let result = unsafe {
+            sdk::cdecklink_output_is_scheduled_playback_running(self.as_ptr(), &mut running)
+        };
+        SdkError::result_or(result, running)
+    }
+
+    /// Register the functions the driver calls back with `context` as frames complete and
+    /// playback stops, or unregister them with `None`.
+    ///
+    /// # Safety
+    /// `context` must be what the functions expect, and stay live until they are unregistered
+    /// or the output is released.
+    pub(crate) unsafe fn set_scheduled_frame_completion_callback(
+        &self,
+        context: *mut std::ffi::c_void,
+        completed: sdk::cdecklink_video_output_callback_scheduled_frame_completed,
+        stopped: sdk::cdecklink_video_output_callback_scheduled_playback_has_stopped,
+    ) -> Result<(), SdkError> {
+        let result = sdk::cdecklink_output_set_scheduled_frame_completion_callback(
+            self.as_ptr(),
+            context,
+            completed,
+            stopped,
+        );
+        SdkError::result(result)
+    }
+
+    pub(crate) fn enable_audio_output(
+        &self,
+        sample_rate: u32,
+        sample_type: u32,
+        channel_count: u32,
+        stream_type: u32,
+    ) -> Result<(), SdkError> {
+        let result = unsafe {
+            sdk::cdecklink_output_enable_audio_output(
+                self.as_ptr(),
+                sample_rate,
+                sample_type,
+                channel_count,
+                stream_type,
+            )
+        };
+        SdkError::result(result)
+    }
+    pub(crate) fn disable_audio_output(&self) -> Result<(), SdkError> {
+        SdkError::result(unsafe { sdk::cdecklink_output_disable_audio_output(self.as_ptr()) })
+    }
+    pub(crate) fn begin_audio_preroll(&self) -> Result<(), SdkError> {
+        SdkError::result(unsafe { sdk::cdecklink_output_begin_audio_preroll(self.as_ptr()) })
+    }
+    pub(crate) fn end_audio_preroll(&self) -> Result<(), SdkError> {
+        SdkError::result(unsafe { sdk::cdecklink_output_end_audio_preroll(self.as_ptr()) })
+    }
+    pub(crate) fn buffered_audio_sample_frame_count(&self) -> Result<u32, SdkError> {
+        let mut count = 0;
+        let result = unsafe {
+            sdk::cdecklink_output_get_buffered_audio_sample_frame_count(self.as_ptr(), &mut count)
+        };
+        SdkError::result_or(result, count)
+    }
+    pub(crate) fn flush_buffered_audio_samples(&self) -> Result<(), SdkError> {
+        let result = unsafe { sdk::cdecklink_output_flush_buffered_audio_samples(self.as_ptr()) };
+        SdkError::result(result)
+    }
+}
+
+impl CustomVideoFramePtr {
+    /// Create a frame with no buffer, to be given one with `set_bytes`.
+    pub(crate) fn create(
+        width: i64,
+        height: i64,
+        row_bytes: i64,
+        pixel_format: u32,
+        flags: u32,
+    ) -> Result<Self, SdkError> {
+        let mut frame = null_mut();
+        let result = unsafe {
+            sdk::cdecklink_custom_video_frame_create_frame(
+                width,
+                height,
+                row_bytes,
+                pixel_format,
+                flags,
+                &mut frame,
+            )
+        };
+        SdkError::result::<()>(result)?;
+        unsafe { Self::from_nullable(frame) }.ok_or(SdkError::FAIL)
+    }
+
+    /// Give the frame `buffer`, which `finalizer` is called with `context` to free once the
+    /// frame is released.
+    ///
+    /// # Safety
+    /// `buffer` must be large enough for the frame, and stay live until `finalizer` is called.
+    pub(crate) unsafe fn set_bytes(
+        &self,
+        buffer: *mut std::ffi::c_void,
+        finalizer: sdk::cdecklink_custom_video_frame_free_bytes,
+        context: *mut std::ffi::c_void,
+    ) -> Result<(), SdkError> {
+        let result =
+            sdk::cdecklink_custom_video_frame_set_bytes(self.as_ptr(), buffer, finalizer, context);
+        SdkError::result(result)
+    }
+
+    /// The frame, as the video frame the calls that play one out take. The reference is still
+    /// owned by `self`.
+    pub(crate) fn as_video_frame(&self) -> *mut sdk::cdecklink_video_frame_t {
+        // A custom frame is a video frame, and the C types of both are opaque
+        self.as_ptr() as *mut sdk::cdecklink_video_frame_t
+    }
+}
+
+impl AudioInputPacketPtr {
+    /// Take a reference of its own to a packet the driver lent to a callback.
+    ///
+    /// # Safety
+    /// `packet` must be a live packet.
+    pub(crate) unsafe fn retain(packet: *mut sdk::cdecklink_audio_input_packet_t) -> Self {
+        sdk::cdecklink_audio_input_packet_add_ref(packet);
+        Self::from_raw(packet)
+    }
+
+    pub(crate) fn sample_frame_count(&self) -> usize {
+        let count =
+            unsafe { sdk::cdecklink_audio_input_packet_get_sample_frame_count(self.as_ptr()) };
+        count.max(0) as usize
+    }
+
+    /// The samples of the packet, which live as long as the packet does.
+    pub(crate) fn bytes(&self) -> Result<*const u8, SdkError> {
+        let mut bytes = null_mut();
+        let result =
+            unsafe { sdk::cdecklink_audio_input_packet_get_bytes(self.as_ptr(), &mut bytes) };
+        SdkError::result::<()>(result)?;
+        if bytes.is_null() {
+            return Err(SdkError::POINTER);
+        }
+        Ok(bytes as *const u8)
+    }
+
+    /// The time of the first sample of the packet, in ticks of `scale` per second.
+    pub(crate) fn packet_time(&self, scale: i64) -> Result<i64, SdkError> {
+        let mut time = 0;
+        let result = unsafe {
+            sdk::cdecklink_audio_input_packet_get_packet_time(self.as_ptr(), &mut time, scale)
+        };
+        SdkError::result_or(result, time)
+    }
+}
+
+impl VideoConversionPtr {
+    /// The driver's converter, or `None` when the drivers have none.
+    pub(crate) fn create() -> Option<Self> {
+        unsafe { Self::from_nullable(sdk::cdecklink_create_video_conversion_instance()) }
+    }
+
+    /// Convert `frame` into a new frame in `pixel_format` and `colorspace`.
+    pub(crate) fn convert_new_frame(
+        &self,
+        frame: &VideoFramePtr,
+        pixel_format: u32,
+        colorspace: u32,
+    ) -> Result<VideoFramePtr, SdkError> {
+        let mut converted = null_mut();
+        let result = unsafe {
+            sdk::cdecklink_video_conversion_convert_new_frame(
+                self.as_ptr(),
+                frame.as_ptr(),
+                pixel_format,
+                colorspace,
+                null_mut(),
+                &mut converted,
+            )
+        };
+        SdkError::result::<()>(result)?;
+        unsafe { VideoFramePtr::from_nullable(converted) }.ok_or(SdkError::FAIL)
+    }
+}
+
+impl ApiInformationPtr {
+    /// The information of the installed drivers, or `None` if there are none.
+    pub(crate) fn create() -> Option<Self> {
+        unsafe { Self::from_nullable(sdk::cdecklink_create_decklink_api_information_instance()) }
+    }
+
+    /// The version of the drivers, as they write it.
+    pub(crate) fn version(&self) -> Result<String, SdkError> {
+        let mut s = null();
+        let result = unsafe {
+            sdk::cdecklink_api_version(self.as_ptr() as *mut sdk::cdecklink_iterator_t, &mut s)
+        };
+        SdkError::result::<()>(result)?;
+        Ok(unsafe { convert_and_release_c_string(s) })
+    }
+
+    pub(crate) fn int(&self, id: sdk::DecklinkAPIInformationID) -> Result<i64, SdkError> {
+        let mut value = 0;
+        let result =
+            unsafe { sdk::cdecklink_api_information_get_int(self.as_ptr(), id, &mut value) };
+        SdkError::result_or(result, value)
+    }
+}
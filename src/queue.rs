@@ -0,0 +1,327 @@
+//! A bounded queue for handing frames out of the input callback.
+//!
+//! The producer never blocks or takes a lock while the consumer is running, so it is safe to
+//! push from a driver callback. Consumers can poll, or park until an item arrives.
+
+use crate::device::input::{ConsumerPressure, PressureSource};
+use std::cell::UnsafeCell;
+use std::mem::MaybeUninit;
+use std::sync::atomic::{fence, AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread::Thread;
+use std::time::{Duration, Instant};
+
+/// What a push does when the queue is full.
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub enum OverflowPolicy {
+    /// Remove the oldest item to make room for the new one.
+    DropOldest,
+    /// Keep the queued items and reject the new one.
+    RejectNewest,
+}
+
+#[derive(PartialEq, Eq, Debug)]
+pub enum PushResult<T> {
+    /// The item was queued.
+    Accepted,
+    /// The item was queued, and this oldest item was removed to make room for it.
+    DroppedOldest(T),
+    /// The item was not queued, since the queue is full or closed.
+    Rejected(T),
+}
+
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub enum PopError {
+    /// No item arrived in time.
+    Timeout,
+    /// The queue is closed and empty.
+    Closed,
+}
+
+struct Slot<T> {
+    /// The position the slot is ready to be written at (`seq == pos`) or read
+    /// at (`seq == pos + 1`).
+    seq: AtomicUsize,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+/// A fixed capacity queue intended for one producer and one consumer.
+///
+/// Each slot carries a sequence number, so a slot is only reused once the consumer has
+/// finished moving its item out. With `OverflowPolicy::DropOldest`, the producer evicts
+/// the oldest item itself when the queue is full.
+pub struct FrameQueue<T> {
+    slots: Box<[Slot<T>]>,
+    head: AtomicUsize,
+    tail: AtomicUsize,
+    policy: OverflowPolicy,
+
+    closed: AtomicBool,
+    consumer_parked: AtomicBool,
+    consumer: Mutex<Option<Thread>>,
+
+    accepted: AtomicU64,
+    dropped: AtomicU64,
+    /// The pushes in a row that dropped or were rejected, up to the latest.
+    consecutive_dropped: AtomicU64,
+}
+
+unsafe impl<T: Send> Send for FrameQueue<T> {}
+unsafe impl<T: Send> Sync for FrameQueue<T> {}
+
+impl<T> FrameQueue<T> {
+    /// Create a queue holding up to `capacity` items. Capacities below two are raised to
+    /// two, since a single slot's sequence number cannot tell a full slot from a free one.
+    pub fn new(capacity: usize, policy: OverflowPolicy) -> FrameQueue<T> {
+        let capacity = capacity.max(2);
+        let slots = (0..capacity)
+            .map(|i| Slot {
+                seq: AtomicUsize::new(i),
+                value: UnsafeCell::new(MaybeUninit::uninit()),
+            })
+            .collect();
+
+        FrameQueue {
+            slots,
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+            policy,
+            closed: AtomicBool::new(false),
+            consumer_parked: AtomicBool::new(false),
+            consumer: Mutex::new(None),
+            accepted: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+            consecutive_dropped: AtomicU64::new(0),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+    pub fn policy(&self) -> OverflowPolicy {
+        self.policy
+    }
+
+    /// The number of queued items. This may be stale by the time it is returned.
+    pub fn len(&self) -> usize {
+        let tail = self.tail.load(Ordering::Acquire);
+        let head = self.head.load(Ordering::Acquire);
+        tail.wrapping_sub(head).min(self.capacity())
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The number of items that have been queued.
+    pub fn accepted_count(&self) -> u64 {
+        self.accepted.load(Ordering::Relaxed)
+    }
+    /// The number of items that were dropped or rejected because the queue was full.
+    pub fn dropped_count(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// The number of pushes in a row, up to the latest, that dropped an item or were rejected
+    /// because the queue was full.
+    pub fn consecutive_dropped_count(&self) -> u64 {
+        self.consecutive_dropped.load(Ordering::Relaxed)
+    }
+
+    fn try_enqueue(&self, value: T) -> Result<(), T> {
+        let mut pos = self.tail.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.slots[pos % self.slots.len()];
+            let seq = slot.seq.load(Ordering::Acquire);
+            let diff = seq.wrapping_sub(pos) as isize;
+            if diff == 0 {
+                match self.tail.compare_exchange_weak(
+                    pos,
+                    pos.wrapping_add(1),
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        unsafe { (*slot.value.get()).write(value) };
+                        slot.seq.store(pos.wrapping_add(1), Ordering::Release);
+                        return Ok(());
+                    }
+                    Err(current) => pos = current,
+                }
+            } else if diff < 0 {
+                return Err(value);
+            } else {
+                pos = self.tail.load(Ordering::Relaxed);
+            }
+        }
+    }
+
+    fn try_dequeue(&self) -> Option<T> {
+        let mut pos = self.head.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.slots[pos % self.slots.len()];
+            let seq = slot.seq.load(Ordering::Acquire);
+            let diff = seq.wrapping_sub(pos.wrapping_add(1)) as isize;
+            if diff == 0 {
+                match self.head.compare_exchange_weak(
+                    pos,
+                    pos.wrapping_add(1),
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        let value = unsafe { (*slot.value.get()).assume_init_read() };
+                        slot.seq
+                            .store(pos.wrapping_add(self.slots.len()), Ordering::Release);
+                        return Some(value);
+                    }
+                    Err(current) => pos = current,
+                }
+            } else if diff < 0 {
+                return None;
+            } else {
+                pos = self.head.load(Ordering::Relaxed);
+            }
+        }
+    }
+
+    fn wake_consumer(&self) {
+        // Order the enqueue before checking for a parked consumer, which orders its flag
+        // before checking the queue again
+        fence(Ordering::SeqCst);
+        if self.consumer_parked.load(Ordering::SeqCst) {
+            if let Some(thread) = self.consumer.lock().unwrap().as_ref() {
+                thread.unpark();
+            }
+        }
+    }
+
+    /// Queue an item, applying the overflow policy if the queue is full. Never blocks.
+    pub fn push(&self, value: T) -> PushResult<T> {
+        if self.closed.load(Ordering::Acquire) {
+            return PushResult::Rejected(value);
+        }
+
+        let mut evicted = None;
+        let mut value = value;
+        loop {
+            match self.try_enqueue(value) {
+                Ok(()) => break,
+                Err(v) => value = v,
+            }
+
+            match self.policy {
+                OverflowPolicy::RejectNewest => {
+                    self.dropped.fetch_add(1, Ordering::Relaxed);
+                    self.consecutive_dropped.fetch_add(1, Ordering::Relaxed);
+                    return PushResult::Rejected(value);
+                }
+                OverflowPolicy::DropOldest => {
+                    if evicted.is_none() {
+                        evicted = self.try_dequeue();
+                        if evicted.is_some() {
+                            self.dropped.fetch_add(1, Ordering::Relaxed);
+                        }
+                    } else {
+                        // The consumer is still moving out the item in the slot we need
+                        std::hint::spin_loop();
+                    }
+                }
+            }
+        }
+
+        self.accepted.fetch_add(1, Ordering::Relaxed);
+        if evicted.is_some() {
+            self.consecutive_dropped.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.consecutive_dropped.store(0, Ordering::Relaxed);
+        }
+        self.wake_consumer();
+        match evicted {
+            Some(old) => PushResult::DroppedOldest(old),
+            None => PushResult::Accepted,
+        }
+    }
+
+    /// Take the oldest item without waiting.
+    pub fn try_pop(&self) -> Option<T> {
+        self.try_dequeue()
+    }
+
+    /// Wait for an item. Returns `None` once the queue is closed and empty.
+    pub fn pop(&self) -> Option<T> {
+        self.wait(None).ok()
+    }
+
+    /// Wait up to `timeout` for an item.
+    pub fn pop_timeout(&self, timeout: Duration) -> Result<T, PopError> {
+        self.wait(Some(Instant::now() + timeout))
+    }
+
+    fn wait(&self, deadline: Option<Instant>) -> Result<T, PopError> {
+        loop {
+            if let Some(value) = self.try_dequeue() {
+                return Ok(value);
+            }
+            if self.closed.load(Ordering::Acquire) {
+                // An item may have been pushed just before closing
+                return self.try_dequeue().ok_or(PopError::Closed);
+            }
+
+            *self.consumer.lock().unwrap() = Some(std::thread::current());
+            self.consumer_parked.store(true, Ordering::SeqCst);
+            fence(Ordering::SeqCst);
+
+            // Check again, in case an item arrived or the queue was closed before we could
+            // be woken
+            if let Some(value) = self.try_dequeue() {
+                self.consumer_parked.store(false, Ordering::SeqCst);
+                return Ok(value);
+            }
+            if self.closed.load(Ordering::SeqCst) {
+                self.consumer_parked.store(false, Ordering::SeqCst);
+                continue;
+            }
+
+            match deadline {
+                None => std::thread::park(),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        self.consumer_parked.store(false, Ordering::SeqCst);
+                        return Err(PopError::Timeout);
+                    }
+                    std::thread::park_timeout(deadline - now);
+                }
+            }
+            self.consumer_parked.store(false, Ordering::SeqCst);
+        }
+    }
+
+    /// Close the queue. Further pushes are rejected, and waiting consumers wake up once the
+    /// remaining items have been taken.
+    pub fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.consumer.lock().unwrap().as_ref() {
+            thread.unpark();
+        }
+    }
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Acquire)
+    }
+}
+
+impl<T: Send> PressureSource for FrameQueue<T> {
+    fn pressure(&self) -> ConsumerPressure {
+        ConsumerPressure {
+            queue_saturation: self.len() as f32 / self.capacity() as f32,
+            retention_exhausted: false,
+            consecutive_drops: self.consecutive_dropped_count(),
+        }
+    }
+}
+
+impl<T> Drop for FrameQueue<T> {
+    fn drop(&mut self) {
+        while self.try_dequeue().is_some() {}
+    }
+}
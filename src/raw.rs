@@ -0,0 +1,12 @@
+//! The C bindings of the SDK, as the crate calls them.
+//!
+//! These are generated by bindgen from the C wrapper of the SDK, and change with it, so nothing
+//! here is covered by semver. They are for calls the wrappers do not make yet: the wrappers give
+//! their pointers out with `as_raw`. Every function here is unsafe to call, and reference
+//! counting follows the SDK's rules rather than the wrappers'.
+//!
+//! Calls are made in the order the driver needs, which the wrappers otherwise take care of.
+//! Some drivers fail `cdecklink_input_enable_audio_input` with `E_FAIL` unless video input
+//! is enabled already, see `crate::device::input::DecklinkInputDevice::enable_audio_input`.
+
+pub use crate::sdk::*;
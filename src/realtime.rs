@@ -0,0 +1,860 @@
+//! Running a capture under real-time scheduling with locked memory, and checking that it is.
+//!
+//! Capture processes are often run with `SCHED_FIFO` and `mlockall`, and a setup that is only
+//! partly right shows as rare dropped frames rather than as an error: a page fault on an
+//! unlocked buffer in the DMA path, or a thread of the crate left at normal priority while
+//! the driver's callback thread is elevated.
+//!
+//! - `apply` locks the process memory and elevates the crate's threads from a
+//!   `RealtimeConfig`, reporting what failed without failing itself unless asked to.
+//! - `elevate_internal_threads` sets the scheduling of the threads the crate starts on the
+//!   frame path, which are tap, batch, hashing and thumbnail threads. Each applies it as it
+//!   starts, so call it before the capture starts. `thread_elevations` reports the outcome
+//!   for each kind.
+//! - `lock_allocator_memory` wraps an allocator provider so that each buffer it allocates is
+//!   locked into memory. CUDA pinned memory, from `crate::cuda`, is page locked by the CUDA
+//!   driver already, and needs no wrapping.
+//! - `observe_callback_thread` wraps an input callback to record the scheduling of the
+//!   driver's callback thread.
+//! - `realtime_readiness` checks the resource limits, locked memory and scheduling of the
+//!   crate's threads, and lists what is wrong with what to do about it.
+//!
+//! Only Linux is supported. Elsewhere every operation fails with
+//! `RealtimeError::Unsupported`, and the readiness report says so.
+
+use crate::allocator::{
+    BufferSpec, VideoBuffer, VideoBufferAllocator, VideoBufferAllocatorProvider,
+};
+use crate::config::ConfigError;
+use crate::device::input::{CallbackResult, FrameArrival, InputFormatChange, InputHandler};
+use crate::SdkError;
+use std::ffi::c_void;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// A thread scheduling policy.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+pub enum SchedPolicy {
+    /// `SCHED_OTHER`, the normal time sharing policy.
+    Other,
+    /// `SCHED_FIFO`.
+    Fifo,
+    /// `SCHED_RR`.
+    RoundRobin,
+}
+
+impl SchedPolicy {
+    pub fn is_realtime(&self) -> bool {
+        !matches!(self, SchedPolicy::Other)
+    }
+}
+
+/// The scheduling of a thread.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+pub struct ThreadScheduling {
+    pub policy: SchedPolicy,
+    pub priority: i32,
+}
+
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+pub enum RealtimeError {
+    /// Real-time scheduling and memory locking are not supported on this platform.
+    Unsupported,
+    /// The priority is outside the range of the policy.
+    InvalidPriority { min: i32, max: i32 },
+    /// The call failed with this OS error, usually `EPERM` for a missing privilege or
+    /// `ENOMEM` for a memlock limit that is too low.
+    Os(i32),
+}
+
+impl fmt::Display for RealtimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RealtimeError::Unsupported => write!(f, "not supported on this platform"),
+            RealtimeError::InvalidPriority { min, max } => {
+                write!(f, "the priority must be from {} to {}", min, max)
+            }
+            RealtimeError::Os(errno) => {
+                write!(f, "{}", std::io::Error::from_raw_os_error(*errno))
+            }
+        }
+    }
+}
+
+impl std::error::Error for RealtimeError {}
+
+/// How to run a capture in real time, for `apply`.
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub struct RealtimeConfig {
+    /// The scheduling for the crate's threads, and for the calling thread.
+    pub scheduling: ThreadScheduling,
+    /// Elevate the calling thread too, as the thread that will own the input.
+    pub elevate_current_thread: bool,
+    /// Lock all current and future memory of the process with `mlockall`.
+    pub lock_all_memory: bool,
+    /// Make `apply` fail if anything could not be applied, rather than only reporting it.
+    pub required: bool,
+}
+
+impl RealtimeConfig {
+    /// `SCHED_FIFO` at `priority` for the crate's threads, with the process memory locked.
+    pub fn fifo(priority: i32) -> RealtimeConfig {
+        RealtimeConfig {
+            scheduling: ThreadScheduling {
+                policy: SchedPolicy::Fifo,
+                priority,
+            },
+            elevate_current_thread: false,
+            lock_all_memory: true,
+            required: false,
+        }
+    }
+
+    /// Start building a config for `scheduling`, with nothing else applied.
+    pub fn builder(scheduling: ThreadScheduling) -> RealtimeConfigBuilder {
+        RealtimeConfigBuilder {
+            config: RealtimeConfig {
+                scheduling,
+                elevate_current_thread: false,
+                lock_all_memory: false,
+                required: false,
+            },
+        }
+    }
+
+    /// Check that the priority is in the range of the policy. Where real-time scheduling
+    /// is not supported, this is left to `apply` to report.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        match sys::check_priority(self.scheduling) {
+            Err(RealtimeError::InvalidPriority { min, max }) => Err(ConfigError::new(
+                "RealtimeConfig",
+                "scheduling",
+                format!(
+                    "has priority {}, but {:?} takes priorities from {} to {}",
+                    self.scheduling.priority, self.scheduling.policy, min, max
+                ),
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Builds a `RealtimeConfig`.
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub struct RealtimeConfigBuilder {
+    config: RealtimeConfig,
+}
+
+impl RealtimeConfigBuilder {
+    pub fn elevate_current_thread(mut self, elevate_current_thread: bool) -> Self {
+        self.config.elevate_current_thread = elevate_current_thread;
+        self
+    }
+
+    pub fn lock_all_memory(mut self, lock_all_memory: bool) -> Self {
+        self.config.lock_all_memory = lock_all_memory;
+        self
+    }
+
+    pub fn required(mut self, required: bool) -> Self {
+        self.config.required = required;
+        self
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        self.config.validate()
+    }
+
+    pub fn build(self) -> Result<RealtimeConfig, ConfigError> {
+        self.validate()?;
+        Ok(self.config)
+    }
+}
+
+/// What `apply` could not do.
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct RealtimeOutcome {
+    /// The error from `mlockall`, if memory was to be locked.
+    pub lock_all_memory: Option<RealtimeError>,
+    /// The error from elevating the calling thread, if it was to be elevated.
+    pub current_thread: Option<RealtimeError>,
+    /// The error from checking the scheduling for the crate's threads.
+    pub internal_threads: Option<RealtimeError>,
+}
+
+impl RealtimeOutcome {
+    pub fn is_complete(&self) -> bool {
+        self.lock_all_memory.is_none()
+            && self.current_thread.is_none()
+            && self.internal_threads.is_none()
+    }
+}
+
+/// Lock the memory of the process and elevate the crate's threads as `config` says.
+///
+/// Anything that fails is reported in the outcome, and the rest is still applied. Only if
+/// `RealtimeConfig::required` is set is an incomplete outcome returned as an error.
+pub fn apply(config: &RealtimeConfig) -> Result<RealtimeOutcome, RealtimeOutcome> {
+    let mut outcome = RealtimeOutcome::default();
+    if config.lock_all_memory {
+        outcome.lock_all_memory = sys::lock_all().err();
+    }
+    outcome.internal_threads = elevate_internal_threads(config.scheduling).err();
+    if config.elevate_current_thread {
+        outcome.current_thread = elevate_current_thread(config.scheduling).err();
+    }
+
+    if config.required && !outcome.is_complete() {
+        Err(outcome)
+    } else {
+        Ok(outcome)
+    }
+}
+
+/// Set the scheduling of the calling thread.
+pub fn elevate_current_thread(scheduling: ThreadScheduling) -> Result<(), RealtimeError> {
+    sys::check_priority(scheduling)?;
+    sys::set_current(scheduling)
+}
+
+/// The scheduling of the calling thread.
+pub fn current_thread_scheduling() -> Result<ThreadScheduling, RealtimeError> {
+    sys::current()
+}
+
+/// The outcome of elevating the threads of one kind that the crate started.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct ThreadElevation {
+    /// The kind of thread, such as `"tap"`.
+    pub role: &'static str,
+    pub started: u64,
+    /// Threads that were elevated.
+    pub elevated: u64,
+    pub failed: u64,
+    pub last_error: Option<RealtimeError>,
+    /// The scheduling the latest thread of this kind ran with.
+    pub last_scheduling: Option<ThreadScheduling>,
+}
+
+static INTERNAL_SCHEDULING: Mutex<Option<ThreadScheduling>> = Mutex::new(None);
+static ELEVATIONS: Mutex<Vec<ThreadElevation>> = Mutex::new(Vec::new());
+
+/// Run the threads the crate starts from now on with `scheduling`.
+///
+/// Fails without changing anything if the priority is out of range for the policy. Whether
+/// each thread could be elevated is reported by `thread_elevations`.
+pub fn elevate_internal_threads(scheduling: ThreadScheduling) -> Result<(), RealtimeError> {
+    sys::check_priority(scheduling)?;
+    *INTERNAL_SCHEDULING.lock().unwrap() = Some(scheduling);
+    Ok(())
+}
+
+/// Run the threads the crate starts from now on with the scheduling they are started with.
+pub fn reset_internal_threads() {
+    *INTERNAL_SCHEDULING.lock().unwrap() = None;
+}
+
+/// The outcome of elevating each kind of thread the crate has started.
+pub fn thread_elevations() -> Vec<ThreadElevation> {
+    ELEVATIONS.lock().unwrap().clone()
+}
+
+/// Called at the start of each thread the crate starts on the frame path.
+pub(crate) fn internal_thread_started(role: &'static str) {
+    let requested = *INTERNAL_SCHEDULING.lock().unwrap();
+    let result = requested.map(sys::set_current);
+    let scheduling = sys::current().ok();
+
+    let mut elevations = ELEVATIONS.lock().unwrap();
+    let index = match elevations.iter().position(|e| e.role == role) {
+        Some(index) => index,
+        None => {
+            elevations.push(ThreadElevation {
+                role,
+                started: 0,
+                elevated: 0,
+                failed: 0,
+                last_error: None,
+                last_scheduling: None,
+            });
+            elevations.len() - 1
+        }
+    };
+    let elevation = &mut elevations[index];
+    elevation.started += 1;
+    elevation.last_scheduling = scheduling;
+    match result {
+        Some(Ok(())) => elevation.elevated += 1,
+        Some(Err(error)) => {
+            elevation.failed += 1;
+            elevation.last_error = Some(error);
+        }
+        None => {}
+    }
+}
+
+/// Counts of the buffers locked by `lock_allocator_memory`.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Default)]
+pub struct MemoryLockStats {
+    pub locked_buffers: u64,
+    pub locked_bytes: u64,
+    /// Buffers that could not be locked, and are used unlocked.
+    pub failed_buffers: u64,
+}
+
+#[derive(Default)]
+struct LockCounters {
+    locked_buffers: AtomicU64,
+    locked_bytes: AtomicU64,
+    failed_buffers: AtomicU64,
+    last_error: Mutex<Option<RealtimeError>>,
+}
+
+/// An allocator provider whose buffers are locked into memory.
+pub struct LockedProvider {
+    provider: Arc<dyn VideoBufferAllocatorProvider>,
+    counters: Arc<LockCounters>,
+}
+
+/// Wrap `provider` so that each buffer it allocates is locked into memory with `mlock`
+/// until it is freed. A buffer that cannot be locked is still used, and counted in
+/// `MemoryLockStats::failed_buffers`.
+pub fn lock_allocator_memory(
+    provider: Arc<dyn VideoBufferAllocatorProvider>,
+) -> Arc<LockedProvider> {
+    Arc::new(LockedProvider {
+        provider,
+        counters: Arc::new(LockCounters::default()),
+    })
+}
+
+impl LockedProvider {
+    pub fn stats(&self) -> MemoryLockStats {
+        MemoryLockStats {
+            locked_buffers: self.counters.locked_buffers.load(Ordering::Relaxed),
+            locked_bytes: self.counters.locked_bytes.load(Ordering::Relaxed),
+            failed_buffers: self.counters.failed_buffers.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Why the latest buffer that could not be locked was not.
+    pub fn last_error(&self) -> Option<RealtimeError> {
+        *self.counters.last_error.lock().unwrap()
+    }
+}
+
+impl VideoBufferAllocatorProvider for LockedProvider {
+    fn get_allocator(&self, spec: BufferSpec) -> Result<Arc<dyn VideoBufferAllocator>, SdkError> {
+        Ok(Arc::new(LockedAllocator {
+            allocator: self.provider.get_allocator(spec)?,
+            size: spec.buffer_size as usize,
+            counters: self.counters.clone(),
+        }))
+    }
+}
+
+struct LockedAllocator {
+    allocator: Arc<dyn VideoBufferAllocator>,
+    size: usize,
+    counters: Arc<LockCounters>,
+}
+
+impl VideoBufferAllocator for LockedAllocator {
+    fn allocate(&self) -> Result<Box<dyn VideoBuffer>, SdkError> {
+        let buffer = self.allocator.allocate()?;
+        let address = buffer.get_bytes()? as usize;
+        let locked = match sys::lock(address as *const c_void, self.size) {
+            Ok(()) => {
+                self.counters.locked_buffers.fetch_add(1, Ordering::Relaxed);
+                self.counters
+                    .locked_bytes
+                    .fetch_add(self.size as u64, Ordering::Relaxed);
+                true
+            }
+            Err(error) => {
+                self.counters.failed_buffers.fetch_add(1, Ordering::Relaxed);
+                *self.counters.last_error.lock().unwrap() = Some(error);
+                false
+            }
+        };
+        Ok(Box::new(LockedBuffer {
+            buffer,
+            address,
+            size: self.size,
+            locked,
+            counters: self.counters.clone(),
+        }))
+    }
+}
+
+struct LockedBuffer {
+    buffer: Box<dyn VideoBuffer>,
+    /// The address of the buffer, kept as an integer so the buffer remains `Send`.
+    address: usize,
+    size: usize,
+    locked: bool,
+    counters: Arc<LockCounters>,
+}
+
+impl VideoBuffer for LockedBuffer {
+    fn get_bytes(&self) -> Result<*mut c_void, SdkError> {
+        self.buffer.get_bytes()
+    }
+
+    fn start_access(&self, flags: u32) -> Result<(), SdkError> {
+        self.buffer.start_access(flags)
+    }
+
+    fn end_access(&self, flags: u32) -> Result<(), SdkError> {
+        self.buffer.end_access(flags)
+    }
+}
+
+impl Drop for LockedBuffer {
+    fn drop(&mut self) {
+        if self.locked {
+            let _ = sys::unlock(self.address as *const c_void, self.size);
+            self.counters.locked_buffers.fetch_sub(1, Ordering::Relaxed);
+            self.counters
+                .locked_bytes
+                .fetch_sub(self.size as u64, Ordering::Relaxed);
+        }
+    }
+}
+
+const CAP_IPC_LOCK: u32 = 14;
+const CAP_SYS_NICE: u32 = 23;
+
+/// The smallest memlock limit that is not reported, room for a pool of eight 4K frames in
+/// 10-bit YUV.
+const MIN_MEMLOCK: u64 = 256 << 20;
+
+static CALLBACK_SCHEDULING: Mutex<Option<ThreadScheduling>> = Mutex::new(None);
+
+/// Wrap `primary` to record the scheduling of the driver's callback thread, from the first
+/// callback after each format change, for `realtime_readiness`.
+pub fn observe_callback_thread(primary: Arc<dyn InputHandler>) -> Arc<dyn InputHandler> {
+    Arc::new(ObservingCallback {
+        primary,
+        observe_next: AtomicBool::new(true),
+    })
+}
+
+struct ObservingCallback {
+    primary: Arc<dyn InputHandler>,
+    observe_next: AtomicBool,
+}
+
+impl ObservingCallback {
+    fn observe(&self) {
+        if self.observe_next.swap(false, Ordering::Relaxed) {
+            *CALLBACK_SCHEDULING.lock().unwrap() = sys::current().ok();
+        }
+    }
+}
+
+impl InputHandler for ObservingCallback {
+    fn format_changed(&self, change: &InputFormatChange) {
+        self.observe_next.store(true, Ordering::Relaxed);
+        self.primary.format_changed(change);
+    }
+
+    fn frame_arrived(&self, arrival: &FrameArrival<'_>) -> CallbackResult {
+        self.observe();
+        self.primary.frame_arrived(arrival)
+    }
+}
+
+/// A resource limit, with `None` for unlimited.
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub struct ResourceLimit {
+    pub soft: Option<u64>,
+    pub hard: Option<u64>,
+}
+
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash, PartialOrd, Ord)]
+pub enum FindingSeverity {
+    /// Worth knowing, but not a cause of dropped frames by itself.
+    Notice,
+    /// Likely to cause dropped frames under load.
+    Problem,
+}
+
+/// Something wrong with the real-time setup, and what to do about it.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct ReadinessFinding {
+    pub severity: FindingSeverity,
+    pub message: String,
+    pub remedy: &'static str,
+}
+
+impl fmt::Display for ReadinessFinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            FindingSeverity::Notice => "notice",
+            FindingSeverity::Problem => "problem",
+        };
+        write!(f, "{}: {} ({})", severity, self.message, self.remedy)
+    }
+}
+
+/// The real-time setup of the process, from `realtime_readiness`.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct ReadinessReport {
+    pub memlock_limit: Option<ResourceLimit>,
+    pub rtprio_limit: Option<ResourceLimit>,
+    /// The memory of the process that is locked, from `VmLck` in `/proc/self/status`.
+    pub locked_bytes: Option<u64>,
+    /// Whether the process has `CAP_IPC_LOCK`, which lifts the memlock limit.
+    pub can_lock_unlimited: bool,
+    /// Whether the process has `CAP_SYS_NICE`, which lifts the rtprio limit.
+    pub can_set_any_priority: bool,
+    /// The scheduling requested with `elevate_internal_threads`.
+    pub requested: Option<ThreadScheduling>,
+    pub threads: Vec<ThreadElevation>,
+    /// The scheduling of the driver's callback thread, if `observe_callback_thread` has
+    /// seen a callback.
+    pub callback_thread: Option<ThreadScheduling>,
+    pub findings: Vec<ReadinessFinding>,
+}
+
+impl ReadinessReport {
+    /// Whether nothing likely to cause dropped frames was found.
+    pub fn is_ready(&self) -> bool {
+        self.findings
+            .iter()
+            .all(|f| f.severity != FindingSeverity::Problem)
+    }
+
+    /// The findings for the limits, capabilities and scheduling in the report, as
+    /// `realtime_readiness` lists them. For checking a report recorded on another machine.
+    pub fn evaluate(&self) -> Vec<ReadinessFinding> {
+        readiness_findings(self, sys::SUPPORTED)
+    }
+}
+
+impl fmt::Display for ReadinessReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.findings.is_empty() {
+            return writeln!(f, "ready for real-time capture");
+        }
+        for finding in &self.findings {
+            writeln!(f, "{}", finding)?;
+        }
+        Ok(())
+    }
+}
+
+/// Check the resource limits, locked memory and thread scheduling of the process.
+pub fn realtime_readiness() -> ReadinessReport {
+    let requested = *INTERNAL_SCHEDULING.lock().unwrap();
+    let mut report = ReadinessReport {
+        memlock_limit: sys::memlock_limit().ok(),
+        rtprio_limit: sys::rtprio_limit().ok(),
+        locked_bytes: sys::locked_bytes(),
+        can_lock_unlimited: sys::has_capability(CAP_IPC_LOCK),
+        can_set_any_priority: sys::has_capability(CAP_SYS_NICE),
+        requested,
+        threads: thread_elevations(),
+        callback_thread: *CALLBACK_SCHEDULING.lock().unwrap(),
+        findings: Vec::new(),
+    };
+    report.findings = report.evaluate();
+    report
+}
+
+fn readiness_findings(report: &ReadinessReport, supported: bool) -> Vec<ReadinessFinding> {
+    let mut findings = Vec::new();
+    let mut find = |severity, message: String, remedy| {
+        findings.push(ReadinessFinding {
+            severity,
+            message,
+            remedy,
+        })
+    };
+    if !supported {
+        find(
+            FindingSeverity::Notice,
+            "real-time scheduling and memory locking are not supported on this platform"
+                .to_string(),
+            "run the capture on Linux to use them",
+        );
+        return findings;
+    }
+
+    if let Some(limit) = report.memlock_limit.filter(|_| !report.can_lock_unlimited) {
+        if limit.soft.is_some_and(|soft| soft < MIN_MEMLOCK) {
+            find(
+                FindingSeverity::Problem,
+                format!(
+                    "the memlock limit is {} bytes, too low to lock frame buffers",
+                    limit.soft.unwrap_or(0)
+                ),
+                "raise memlock in /etc/security/limits.conf, or LimitMEMLOCK for a systemd service",
+            );
+        }
+    }
+    if report.locked_bytes == Some(0) {
+        find(
+            FindingSeverity::Problem,
+            "no memory of the process is locked, so frame buffers can be paged out".to_string(),
+            "apply a RealtimeConfig with lock_all_memory, or wrap the allocator provider with lock_allocator_memory",
+        );
+    }
+
+    let realtime_wanted = report.requested.is_some_and(|s| s.policy.is_realtime())
+        || report
+            .callback_thread
+            .is_some_and(|s| s.policy.is_realtime());
+    if realtime_wanted
+        && !report.can_set_any_priority
+        && report.rtprio_limit.is_some_and(|l| l.soft == Some(0))
+    {
+        find(
+            FindingSeverity::Problem,
+            "the rtprio limit is 0, so threads cannot be given real-time priorities".to_string(),
+            "raise rtprio in /etc/security/limits.conf, or LimitRTPRIO for a systemd service, or grant CAP_SYS_NICE",
+        );
+    }
+
+    for thread in &report.threads {
+        if thread.failed > 0 {
+            find(
+                FindingSeverity::Problem,
+                format!(
+                    "{} of {} {} threads could not be elevated: {}",
+                    thread.failed,
+                    thread.started,
+                    thread.role,
+                    thread
+                        .last_error
+                        .map_or_else(String::new, |e| e.to_string())
+                ),
+                "grant the process CAP_SYS_NICE or a non-zero rtprio limit",
+            );
+        }
+    }
+
+    if let Some(callback) = report.callback_thread {
+        let internal_elevated = report.requested.is_some_and(|s| s.policy.is_realtime());
+        if callback.policy.is_realtime() && !internal_elevated {
+            find(
+                FindingSeverity::Problem,
+                format!(
+                    "the driver's callback thread runs {:?} at priority {}, but the crate's threads are not elevated",
+                    callback.policy, callback.priority
+                ),
+                "call elevate_internal_threads before starting the capture",
+            );
+        }
+        if let Some(requested) = report.requested {
+            if requested.policy.is_realtime()
+                && callback.policy.is_realtime()
+                && requested.priority > callback.priority
+            {
+                find(
+                    FindingSeverity::Notice,
+                    format!(
+                        "the crate's threads run above the driver's callback thread, at {} against {}",
+                        requested.priority, callback.priority
+                    ),
+                    "give the crate's threads a priority at or below the driver's, so they cannot starve it",
+                );
+            }
+        }
+    } else if report.requested.is_some() {
+        find(
+            FindingSeverity::Notice,
+            "the scheduling of the driver's callback thread has not been observed".to_string(),
+            "wrap the input callback with observe_callback_thread and check again once frames arrive",
+        );
+    }
+
+    findings
+}
+
+#[cfg(target_os = "linux")]
+mod sys {
+    use super::{RealtimeError, ResourceLimit, SchedPolicy, ThreadScheduling};
+    use std::ffi::c_void;
+
+    pub(super) const SUPPORTED: bool = true;
+
+    fn last_error() -> RealtimeError {
+        RealtimeError::Os(std::io::Error::last_os_error().raw_os_error().unwrap_or(0))
+    }
+
+    fn policy_value(policy: SchedPolicy) -> libc::c_int {
+        match policy {
+            SchedPolicy::Other => libc::SCHED_OTHER,
+            SchedPolicy::Fifo => libc::SCHED_FIFO,
+            SchedPolicy::RoundRobin => libc::SCHED_RR,
+        }
+    }
+
+    pub(super) fn check_priority(scheduling: ThreadScheduling) -> Result<(), RealtimeError> {
+        let policy = policy_value(scheduling.policy);
+        let min = unsafe { libc::sched_get_priority_min(policy) };
+        let max = unsafe { libc::sched_get_priority_max(policy) };
+        if scheduling.priority < min || scheduling.priority > max {
+            Err(RealtimeError::InvalidPriority { min, max })
+        } else {
+            Ok(())
+        }
+    }
+
+    pub(super) fn set_current(scheduling: ThreadScheduling) -> Result<(), RealtimeError> {
+        let param = libc::sched_param {
+            sched_priority: scheduling.priority,
+        };
+        let result = unsafe {
+            libc::pthread_setschedparam(
+                libc::pthread_self(),
+                policy_value(scheduling.policy),
+                &param,
+            )
+        };
+        match result {
+            0 => Ok(()),
+            errno => Err(RealtimeError::Os(errno)),
+        }
+    }
+
+    pub(super) fn current() -> Result<ThreadScheduling, RealtimeError> {
+        let mut policy = 0;
+        let mut param = libc::sched_param { sched_priority: 0 };
+        let result =
+            unsafe { libc::pthread_getschedparam(libc::pthread_self(), &mut policy, &mut param) };
+        if result != 0 {
+            return Err(RealtimeError::Os(result));
+        }
+        let policy = match policy {
+            libc::SCHED_FIFO => SchedPolicy::Fifo,
+            libc::SCHED_RR => SchedPolicy::RoundRobin,
+            _ => SchedPolicy::Other,
+        };
+        Ok(ThreadScheduling {
+            policy,
+            priority: param.sched_priority,
+        })
+    }
+
+    pub(super) fn lock_all() -> Result<(), RealtimeError> {
+        match unsafe { libc::mlockall(libc::MCL_CURRENT | libc::MCL_FUTURE) } {
+            0 => Ok(()),
+            _ => Err(last_error()),
+        }
+    }
+
+    pub(super) fn lock(address: *const c_void, size: usize) -> Result<(), RealtimeError> {
+        match unsafe { libc::mlock(address, size) } {
+            0 => Ok(()),
+            _ => Err(last_error()),
+        }
+    }
+
+    pub(super) fn unlock(address: *const c_void, size: usize) -> Result<(), RealtimeError> {
+        match unsafe { libc::munlock(address, size) } {
+            0 => Ok(()),
+            _ => Err(last_error()),
+        }
+    }
+
+    fn to_limit(result: libc::c_int, limit: libc::rlimit) -> Result<ResourceLimit, RealtimeError> {
+        if result != 0 {
+            return Err(last_error());
+        }
+        // rlim_t is narrower than u64 on some targets
+        #[allow(clippy::unnecessary_cast)]
+        let value = |v: libc::rlim_t| (v != libc::RLIM_INFINITY).then_some(v as u64);
+        Ok(ResourceLimit {
+            soft: value(limit.rlim_cur),
+            hard: value(limit.rlim_max),
+        })
+    }
+
+    const NO_LIMIT: libc::rlimit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+
+    pub(super) fn memlock_limit() -> Result<ResourceLimit, RealtimeError> {
+        let mut limit = NO_LIMIT;
+        to_limit(
+            unsafe { libc::getrlimit(libc::RLIMIT_MEMLOCK, &mut limit) },
+            limit,
+        )
+    }
+
+    pub(super) fn rtprio_limit() -> Result<ResourceLimit, RealtimeError> {
+        let mut limit = NO_LIMIT;
+        to_limit(
+            unsafe { libc::getrlimit(libc::RLIMIT_RTPRIO, &mut limit) },
+            limit,
+        )
+    }
+
+    /// A field of `/proc/self/status`.
+    fn status_field(name: &str) -> Option<String> {
+        let status = std::fs::read_to_string("/proc/self/status").ok()?;
+        let value = status
+            .lines()
+            .find_map(|l| l.strip_prefix(name)?.strip_prefix(':'))?;
+        Some(value.trim().to_string())
+    }
+
+    pub(super) fn locked_bytes() -> Option<u64> {
+        let kb: u64 = status_field("VmLck")?
+            .trim_end_matches("kB")
+            .trim()
+            .parse()
+            .ok()?;
+        Some(kb * 1024)
+    }
+
+    pub(super) fn has_capability(capability: u32) -> bool {
+        status_field("CapEff")
+            .and_then(|caps| u64::from_str_radix(&caps, 16).ok())
+            .is_some_and(|caps| caps & (1 << capability) != 0)
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod sys {
+    use super::{RealtimeError, ResourceLimit, ThreadScheduling};
+    use std::ffi::c_void;
+
+    pub(super) const SUPPORTED: bool = false;
+
+    pub(super) fn check_priority(_scheduling: ThreadScheduling) -> Result<(), RealtimeError> {
+        Err(RealtimeError::Unsupported)
+    }
+    pub(super) fn set_current(_scheduling: ThreadScheduling) -> Result<(), RealtimeError> {
+        Err(RealtimeError::Unsupported)
+    }
+    pub(super) fn current() -> Result<ThreadScheduling, RealtimeError> {
+        Err(RealtimeError::Unsupported)
+    }
+    pub(super) fn lock_all() -> Result<(), RealtimeError> {
+        Err(RealtimeError::Unsupported)
+    }
+    pub(super) fn lock(_address: *const c_void, _size: usize) -> Result<(), RealtimeError> {
+        Err(RealtimeError::Unsupported)
+    }
+    pub(super) fn unlock(_address: *const c_void, _size: usize) -> Result<(), RealtimeError> {
+        Err(RealtimeError::Unsupported)
+    }
+    pub(super) fn memlock_limit() -> Result<ResourceLimit, RealtimeError> {
+        Err(RealtimeError::Unsupported)
+    }
+    pub(super) fn rtprio_limit() -> Result<ResourceLimit, RealtimeError> {
+        Err(RealtimeError::Unsupported)
+    }
+    pub(super) fn locked_bytes() -> Option<u64> {
+        None
+    }
+    pub(super) fn has_capability(_capability: u32) -> bool {
+        false
+    }
+}
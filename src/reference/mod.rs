@@ -0,0 +1,1001 @@
+//! What the crate knows of each pixel format, display mode and status id, as tables that
+//! code and documentation both read.
+//!
+//! Each enum finds its entry with `DecklinkReferenceExt::metadata`, and `all` gathers the
+//! tables for programs that present them, such as a UI offering a choice of mode;
+//! `decklink-cli reference` prints them as JSON. `DecklinkPixelFormat::bytes_per_row`, the aspect ratios of display modes and the
+//! modes of the mock backend are read from these tables, and the tables below are rendered
+//! from them by `ReferenceTable::markdown`, which `tests/reference.rs` checks against the
+//! files included here. Device attributes and configuration are read by their SDK ids, which
+//! have no enum in this crate, so they have no table.
+//!
+//! # Pixel formats
+//!
+#![doc = include_str!("pixel_formats.md")]
+//!
+//! # Display modes
+//!
+#![doc = include_str!("display_modes.md")]
+//!
+//! # Status ids
+//!
+#![doc = include_str!("status_ids.md")]
+
+use crate::device::status::DecklinkStatusId;
+use crate::display_mode::{DecklinkDisplayModeId, DecklinkFieldDominance, Ratio};
+use crate::frame::DecklinkPixelFormat;
+use crate::manifest::write_json_string;
+use std::fmt::Write;
+
+/// How a pixel format packs its rows: each group of `pixels` takes `bytes`, and rows are
+/// padded to a whole number of groups.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct RowPacking {
+    pub pixels: usize,
+    pub bytes: usize,
+}
+
+impl RowPacking {
+    /// The bytes in a row of `width` pixels, padding included.
+    pub const fn row_bytes(&self, width: usize) -> usize {
+        width.div_ceil(self.pixels) * self.bytes
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct PixelFormatMetadata {
+    pub format: DecklinkPixelFormat,
+    pub name: &'static str,
+    pub sdk_constant: &'static str,
+    /// `None` for 8-bit ARGB, which the SDK gives as the number 32.
+    pub fourcc: Option<&'static str>,
+    pub description: &'static str,
+    /// The bits of each component. `None` for the compressed formats.
+    pub bit_depth: Option<u8>,
+    pub rgb: bool,
+    /// `None` for the compressed formats, whose frames have no fixed row size.
+    pub packing: Option<RowPacking>,
+}
+
+/// The pixel and display aspect ratios of a mode. They differ with `widescreen` only for
+/// standard definition, where the same signal carries a 4:3 or an anamorphic 16:9 picture.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ModeAspect {
+    pub pixel: Ratio,
+    pub widescreen_pixel: Ratio,
+    pub display: Ratio,
+    pub widescreen_display: Ratio,
+}
+
+impl ModeAspect {
+    pub fn pixel(&self, widescreen: bool) -> Ratio {
+        if widescreen {
+            self.widescreen_pixel
+        } else {
+            self.pixel
+        }
+    }
+
+    pub fn display(&self, widescreen: bool) -> Ratio {
+        if widescreen {
+            self.widescreen_display
+        } else {
+            self.display
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct DisplayModeMetadata {
+    pub mode: DecklinkDisplayModeId,
+    /// The name the driver gives the mode.
+    pub name: &'static str,
+    pub sdk_constant: &'static str,
+    pub description: &'static str,
+    pub width: usize,
+    pub height: usize,
+    /// The frame rate is `time_scale / frame_duration`, as the driver gives it.
+    pub frame_duration: i64,
+    pub time_scale: i64,
+    /// As most devices report it. Some report the progressive modes they carry as PsF
+    /// instead.
+    pub field_dominance: DecklinkFieldDominance,
+    /// `None` for `Unknown`.
+    pub aspect: Option<ModeAspect>,
+}
+
+/// How the value of a status id is read from `DecklinkDeviceStatus`.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum StatusValueKind {
+    Flag,
+    Int,
+    Float,
+    String,
+    Bytes,
+}
+
+#[derive(PartialEq, Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct StatusIdMetadata {
+    pub id: DecklinkStatusId,
+    pub sdk_constant: &'static str,
+    pub description: &'static str,
+    pub kind: StatusValueKind,
+}
+
+/// Every table, as `all` gives them.
+#[derive(Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Reference {
+    pub pixel_formats: &'static [PixelFormatMetadata],
+    pub display_modes: &'static [DisplayModeMetadata],
+    pub status_ids: &'static [StatusIdMetadata],
+}
+
+/// Every table, in the order of each enum's variants.
+pub fn all() -> Reference {
+    Reference {
+        pixel_formats: PIXEL_FORMATS,
+        display_modes: DISPLAY_MODES,
+        status_ids: STATUS_IDS,
+    }
+}
+
+/// The entry of a pixel format, display mode or status id in these tables.
+pub trait DecklinkReferenceExt {
+    type Metadata: 'static;
+
+    /// The value's entry, which every variant has.
+    fn metadata(&self) -> &'static Self::Metadata;
+}
+
+impl DecklinkReferenceExt for DecklinkPixelFormat {
+    type Metadata = PixelFormatMetadata;
+
+    fn metadata(&self) -> &'static PixelFormatMetadata {
+        PIXEL_FORMATS
+            .iter()
+            .find(|m| m.format == *self)
+            .expect("every pixel format has metadata")
+    }
+}
+
+impl DecklinkReferenceExt for DecklinkDisplayModeId {
+    type Metadata = DisplayModeMetadata;
+
+    fn metadata(&self) -> &'static DisplayModeMetadata {
+        DISPLAY_MODES
+            .iter()
+            .find(|m| m.mode == *self)
+            .expect("every display mode has metadata")
+    }
+}
+
+impl DecklinkReferenceExt for DecklinkStatusId {
+    type Metadata = StatusIdMetadata;
+
+    fn metadata(&self) -> &'static StatusIdMetadata {
+        STATUS_IDS
+            .iter()
+            .find(|m| m.id == *self)
+            .expect("every status id has metadata")
+    }
+}
+
+const fn packing(pixels: usize, bytes: usize) -> Option<RowPacking> {
+    Some(RowPacking { pixels, bytes })
+}
+
+pub(crate) const PIXEL_FORMATS: &[PixelFormatMetadata] = &[
+    PixelFormatMetadata {
+        format: DecklinkPixelFormat::Format8BitYUV,
+        name: "8-bit YUV",
+        sdk_constant: "bmdFormat8BitYUV",
+        fourcc: Some("2vuy"),
+        description: "4:2:2 Y'CbCr, each pair of pixels as Cb Y Cr Y in four bytes",
+        bit_depth: Some(8),
+        rgb: false,
+        packing: packing(2, 4),
+    },
+    PixelFormatMetadata {
+        format: DecklinkPixelFormat::Format10BitYUV,
+        name: "10-bit YUV",
+        sdk_constant: "bmdFormat10BitYUV",
+        fourcc: Some("v210"),
+        description: "4:2:2 Y'CbCr, six pixels in four little endian words of three \
+                      components, with rows padded to 48 pixels",
+        bit_depth: Some(10),
+        rgb: false,
+        packing: packing(48, 128),
+    },
+    PixelFormatMetadata {
+        format: DecklinkPixelFormat::Format8BitARGB,
+        name: "8-bit ARGB",
+        sdk_constant: "bmdFormat8BitARGB",
+        fourcc: None,
+        description: "RGB with alpha, a byte each in the order A R G B",
+        bit_depth: Some(8),
+        rgb: true,
+        packing: packing(1, 4),
+    },
+    PixelFormatMetadata {
+        format: DecklinkPixelFormat::Format8BitBGRA,
+        name: "8-bit BGRA",
+        sdk_constant: "bmdFormat8BitBGRA",
+        fourcc: Some("BGRA"),
+        description: "RGB with alpha, a byte each in the order B G R A",
+        bit_depth: Some(8),
+        rgb: true,
+        packing: packing(1, 4),
+    },
+    PixelFormatMetadata {
+        format: DecklinkPixelFormat::Format10BitRGB,
+        name: "10-bit RGB",
+        sdk_constant: "bmdFormat10BitRGB",
+        fourcc: Some("r210"),
+        description: "RGB in video range, a big endian word per pixel with the unused bits \
+                      at the top, with rows padded to 64 pixels",
+        bit_depth: Some(10),
+        rgb: true,
+        packing: packing(64, 256),
+    },
+    PixelFormatMetadata {
+        format: DecklinkPixelFormat::Format12BitRGB,
+        name: "12-bit RGB",
+        sdk_constant: "bmdFormat12BitRGB",
+        fourcc: Some("R12B"),
+        description: "RGB, eight pixels in nine big endian words",
+        bit_depth: Some(12),
+        rgb: true,
+        packing: packing(8, 36),
+    },
+    PixelFormatMetadata {
+        format: DecklinkPixelFormat::Format12BitRGBLE,
+        name: "12-bit RGB LE",
+        sdk_constant: "bmdFormat12BitRGBLE",
+        fourcc: Some("R12L"),
+        description: "RGB, eight pixels in nine little endian words",
+        bit_depth: Some(12),
+        rgb: true,
+        packing: packing(8, 36),
+    },
+    PixelFormatMetadata {
+        format: DecklinkPixelFormat::Format10BitRGBXLE,
+        name: "10-bit RGBX LE",
+        sdk_constant: "bmdFormat10BitRGBXLE",
+        fourcc: Some("R10l"),
+        description: "RGB in video range, a little endian word per pixel with the unused \
+                      bits at the bottom, with rows padded to 64 pixels",
+        bit_depth: Some(10),
+        rgb: true,
+        packing: packing(64, 256),
+    },
+    PixelFormatMetadata {
+        format: DecklinkPixelFormat::Format10BitRGBX,
+        name: "10-bit RGBX",
+        sdk_constant: "bmdFormat10BitRGBX",
+        fourcc: Some("R10b"),
+        description: "RGB in video range, a big endian word per pixel with the unused bits \
+                      at the bottom, with rows padded to 64 pixels",
+        bit_depth: Some(10),
+        rgb: true,
+        packing: packing(64, 256),
+    },
+    PixelFormatMetadata {
+        format: DecklinkPixelFormat::FormatH265,
+        name: "H.265",
+        sdk_constant: "bmdFormatH265",
+        fourcc: Some("hev1"),
+        description: "H.265 compressed video, from devices with an encoder",
+        bit_depth: None,
+        rgb: false,
+        packing: None,
+    },
+    PixelFormatMetadata {
+        format: DecklinkPixelFormat::FormatDNxHR,
+        name: "DNxHR",
+        sdk_constant: "bmdFormatDNxHR",
+        fourcc: Some("AVdh"),
+        description: "Avid DNxHR compressed video",
+        bit_depth: None,
+        rgb: false,
+        packing: None,
+    },
+];
+
+/// The aspect of a mode of square pixels filling a raster of `num:den`.
+const fn square(num: u32, den: u32) -> Option<ModeAspect> {
+    Some(ModeAspect {
+        pixel: Ratio::SQUARE,
+        widescreen_pixel: Ratio::SQUARE,
+        display: Ratio::new(num, den),
+        widescreen_display: Ratio::new(num, den),
+    })
+}
+
+/// Standard definition has the pixels of ITU-R BT.601, the same whether NTSC is given as 486
+/// lines or 480, and shows 4:3 or 16:9 over the 704 samples of its active line.
+const fn standard_definition(pixel: Ratio, widescreen_pixel: Ratio) -> Option<ModeAspect> {
+    Some(ModeAspect {
+        pixel,
+        widescreen_pixel,
+        display: Ratio::new(4, 3),
+        widescreen_display: Ratio::new(16, 9),
+    })
+}
+
+const NTSC: Option<ModeAspect> = standard_definition(Ratio::new(10, 11), Ratio::new(40, 33));
+const PAL: Option<ModeAspect> = standard_definition(Ratio::new(12, 11), Ratio::new(16, 11));
+/// 720p, 1080, 2160p and 4320p, and 2560x1440.
+const WIDE: Option<ModeAspect> = square(16, 9);
+/// 2048x1556, the full aperture of a film scan.
+const FULL_APERTURE: Option<ModeAspect> = square(512, 389);
+const DCI: Option<ModeAspect> = square(256, 135);
+const FOUR_BY_THREE: Option<ModeAspect> = square(4, 3);
+const SIXTEEN_BY_TEN: Option<ModeAspect> = square(8, 5);
+const NONE: Option<ModeAspect> = None;
+
+/// One entry per mode, as `mode => name, SDK constant, description, width, height, frame
+/// duration, time scale, field dominance, aspect;`.
+macro_rules! display_modes {
+    ($(
+        $mode:ident => $name:literal, $sdk:literal, $description:literal,
+        $width:literal, $height:literal, $duration:literal, $scale:literal,
+        $dominance:ident, $aspect:ident;
+    )*) => {
+        &[$(
+            DisplayModeMetadata {
+                mode: DecklinkDisplayModeId::$mode,
+                name: $name,
+                sdk_constant: $sdk,
+                description: $description,
+                width: $width,
+                height: $height,
+                frame_duration: $duration,
+                time_scale: $scale,
+                field_dominance: DecklinkFieldDominance::$dominance,
+                aspect: $aspect,
+            },
+        )*]
+    };
+}
+
+pub(crate) const DISPLAY_MODES: &[DisplayModeMetadata] = display_modes! {
+    NTSC => "NTSC", "bmdModeNTSC",
+        "720x486 interlaced at 29.97 frames per second, lower field first",
+        720, 486, 1001, 30000, LowerFieldFirst, NTSC;
+    NTSC2398 => "NTSC 23.98", "bmdModeNTSC2398",
+        "720x486 interlaced at 23.98 frames per second, lower field first",
+        720, 486, 1001, 24000, LowerFieldFirst, NTSC;
+    PAL => "PAL", "bmdModePAL",
+        "720x576 interlaced at 25 frames per second, upper field first",
+        720, 576, 1000, 25000, UpperFieldFirst, PAL;
+    NTSCp => "NTSC Progressive", "bmdModeNTSCp",
+        "720x486 progressive at 59.94 frames per second",
+        720, 486, 1001, 60000, ProgressiveFrame, NTSC;
+    PALp => "PAL Progressive", "bmdModePALp",
+        "720x576 progressive at 50 frames per second",
+        720, 576, 1000, 50000, ProgressiveFrame, PAL;
+    HD1080p2398 => "1080p23.98", "bmdModeHD1080p2398",
+        "1920x1080 progressive at 23.98 frames per second",
+        1920, 1080, 1001, 24000, ProgressiveFrame, WIDE;
+    HD1080p24 => "1080p24", "bmdModeHD1080p24",
+        "1920x1080 progressive at 24 frames per second",
+        1920, 1080, 1000, 24000, ProgressiveFrame, WIDE;
+    HD1080p25 => "1080p25", "bmdModeHD1080p25",
+        "1920x1080 progressive at 25 frames per second",
+        1920, 1080, 1000, 25000, ProgressiveFrame, WIDE;
+    HD1080p2997 => "1080p29.97", "bmdModeHD1080p2997",
+        "1920x1080 progressive at 29.97 frames per second",
+        1920, 1080, 1001, 30000, ProgressiveFrame, WIDE;
+    HD1080p30 => "1080p30", "bmdModeHD1080p30",
+        "1920x1080 progressive at 30 frames per second",
+        1920, 1080, 1000, 30000, ProgressiveFrame, WIDE;
+    HD1080i50 => "1080i50", "bmdModeHD1080i50",
+        "1920x1080 interlaced at 25 frames per second, upper field first",
+        1920, 1080, 1000, 25000, UpperFieldFirst, WIDE;
+    HD1080i5994 => "1080i59.94", "bmdModeHD1080i5994",
+        "1920x1080 interlaced at 29.97 frames per second, upper field first",
+        1920, 1080, 1001, 30000, UpperFieldFirst, WIDE;
+    HD1080i6000 => "1080i60", "bmdModeHD1080i6000",
+        "1920x1080 interlaced at 30 frames per second, upper field first",
+        1920, 1080, 1000, 30000, UpperFieldFirst, WIDE;
+    HD1080p50 => "1080p50", "bmdModeHD1080p50",
+        "1920x1080 progressive at 50 frames per second",
+        1920, 1080, 1000, 50000, ProgressiveFrame, WIDE;
+    HD1080p5994 => "1080p59.94", "bmdModeHD1080p5994",
+        "1920x1080 progressive at 59.94 frames per second",
+        1920, 1080, 1001, 60000, ProgressiveFrame, WIDE;
+    HD1080p6000 => "1080p60", "bmdModeHD1080p6000",
+        "1920x1080 progressive at 60 frames per second",
+        1920, 1080, 1000, 60000, ProgressiveFrame, WIDE;
+    HD1080p4795 => "1080p47.95", "bmdModeHD1080p4795",
+        "1920x1080 progressive at 47.95 frames per second",
+        1920, 1080, 1001, 48000, ProgressiveFrame, WIDE;
+    HD1080p48 => "1080p48", "bmdModeHD1080p48",
+        "1920x1080 progressive at 48 frames per second",
+        1920, 1080, 1000, 48000, ProgressiveFrame, WIDE;
+    HD1080p9590 => "1080p95.90", "bmdModeHD1080p9590",
+        "1920x1080 progressive at 95.9 frames per second",
+        1920, 1080, 1001, 96000, ProgressiveFrame, WIDE;
+    HD1080p96 => "1080p96", "bmdModeHD1080p96",
+        "1920x1080 progressive at 96 frames per second",
+        1920, 1080, 1000, 96000, ProgressiveFrame, WIDE;
+    HD1080p100 => "1080p100", "bmdModeHD1080p100",
+        "1920x1080 progressive at 100 frames per second",
+        1920, 1080, 1000, 100000, ProgressiveFrame, WIDE;
+    HD1080p11988 => "1080p119.88", "bmdModeHD1080p11988",
+        "1920x1080 progressive at 119.88 frames per second",
+        1920, 1080, 1001, 120000, ProgressiveFrame, WIDE;
+    HD1080p120 => "1080p120", "bmdModeHD1080p120",
+        "1920x1080 progressive at 120 frames per second",
+        1920, 1080, 1000, 120000, ProgressiveFrame, WIDE;
+    HD720p50 => "720p50", "bmdModeHD720p50",
+        "1280x720 progressive at 50 frames per second",
+        1280, 720, 1000, 50000, ProgressiveFrame, WIDE;
+    HD720p5994 => "720p59.94", "bmdModeHD720p5994",
+        "1280x720 progressive at 59.94 frames per second",
+        1280, 720, 1001, 60000, ProgressiveFrame, WIDE;
+    HD720p60 => "720p60", "bmdModeHD720p60",
+        "1280x720 progressive at 60 frames per second",
+        1280, 720, 1000, 60000, ProgressiveFrame, WIDE;
+    HD2k2398 => "2K 23.98", "bmdMode2k2398",
+        "2048x1556 progressive at 23.98 frames per second",
+        2048, 1556, 1001, 24000, ProgressiveFrame, FULL_APERTURE;
+    HD2k24 => "2K 24", "bmdMode2k24",
+        "2048x1556 progressive at 24 frames per second",
+        2048, 1556, 1000, 24000, ProgressiveFrame, FULL_APERTURE;
+    HD2k25 => "2K 25", "bmdMode2k25",
+        "2048x1556 progressive at 25 frames per second",
+        2048, 1556, 1000, 25000, ProgressiveFrame, FULL_APERTURE;
+    HD2kDCI2398 => "2K DCI 23.98", "bmdMode2kDCI2398",
+        "2048x1080 progressive at 23.98 frames per second",
+        2048, 1080, 1001, 24000, ProgressiveFrame, DCI;
+    HD2kDCI24 => "2K DCI 24", "bmdMode2kDCI24",
+        "2048x1080 progressive at 24 frames per second",
+        2048, 1080, 1000, 24000, ProgressiveFrame, DCI;
+    HD2kDCI25 => "2K DCI 25", "bmdMode2kDCI25",
+        "2048x1080 progressive at 25 frames per second",
+        2048, 1080, 1000, 25000, ProgressiveFrame, DCI;
+    HD2kDCI2997 => "2K DCI 29.97", "bmdMode2kDCI2997",
+        "2048x1080 progressive at 29.97 frames per second",
+        2048, 1080, 1001, 30000, ProgressiveFrame, DCI;
+    HD2kDCI30 => "2K DCI 30", "bmdMode2kDCI30",
+        "2048x1080 progressive at 30 frames per second",
+        2048, 1080, 1000, 30000, ProgressiveFrame, DCI;
+    HD2kDCI4795 => "2K DCI 47.95", "bmdMode2kDCI4795",
+        "2048x1080 progressive at 47.95 frames per second",
+        2048, 1080, 1001, 48000, ProgressiveFrame, DCI;
+    HD2kDCI48 => "2K DCI 48", "bmdMode2kDCI48",
+        "2048x1080 progressive at 48 frames per second",
+        2048, 1080, 1000, 48000, ProgressiveFrame, DCI;
+    HD2kDCI50 => "2K DCI 50", "bmdMode2kDCI50",
+        "2048x1080 progressive at 50 frames per second",
+        2048, 1080, 1000, 50000, ProgressiveFrame, DCI;
+    HD2kDCI5994 => "2K DCI 59.94", "bmdMode2kDCI5994",
+        "2048x1080 progressive at 59.94 frames per second",
+        2048, 1080, 1001, 60000, ProgressiveFrame, DCI;
+    HD2kDCI60 => "2K DCI 60", "bmdMode2kDCI60",
+        "2048x1080 progressive at 60 frames per second",
+        2048, 1080, 1000, 60000, ProgressiveFrame, DCI;
+    HD2kDCI9590 => "2K DCI 95.90", "bmdMode2kDCI9590",
+        "2048x1080 progressive at 95.9 frames per second",
+        2048, 1080, 1001, 96000, ProgressiveFrame, DCI;
+    HD2kDCI96 => "2K DCI 96", "bmdMode2kDCI96",
+        "2048x1080 progressive at 96 frames per second",
+        2048, 1080, 1000, 96000, ProgressiveFrame, DCI;
+    HD2kDCI100 => "2K DCI 100", "bmdMode2kDCI100",
+        "2048x1080 progressive at 100 frames per second",
+        2048, 1080, 1000, 100000, ProgressiveFrame, DCI;
+    HD2kDCI11988 => "2K DCI 119.88", "bmdMode2kDCI11988",
+        "2048x1080 progressive at 119.88 frames per second",
+        2048, 1080, 1001, 120000, ProgressiveFrame, DCI;
+    HD2kDCI120 => "2K DCI 120", "bmdMode2kDCI120",
+        "2048x1080 progressive at 120 frames per second",
+        2048, 1080, 1000, 120000, ProgressiveFrame, DCI;
+    UHD4K2160p2398 => "2160p23.98", "bmdMode4K2160p2398",
+        "3840x2160 progressive at 23.98 frames per second",
+        3840, 2160, 1001, 24000, ProgressiveFrame, WIDE;
+    UHD4K2160p24 => "2160p24", "bmdMode4K2160p24",
+        "3840x2160 progressive at 24 frames per second",
+        3840, 2160, 1000, 24000, ProgressiveFrame, WIDE;
+    UHD4K2160p25 => "2160p25", "bmdMode4K2160p25",
+        "3840x2160 progressive at 25 frames per second",
+        3840, 2160, 1000, 25000, ProgressiveFrame, WIDE;
+    UHD4K2160p2997 => "2160p29.97", "bmdMode4K2160p2997",
+        "3840x2160 progressive at 29.97 frames per second",
+        3840, 2160, 1001, 30000, ProgressiveFrame, WIDE;
+    UHD4K2160p30 => "2160p30", "bmdMode4K2160p30",
+        "3840x2160 progressive at 30 frames per second",
+        3840, 2160, 1000, 30000, ProgressiveFrame, WIDE;
+    UHD4K2160p4795 => "2160p47.95", "bmdMode4K2160p4795",
+        "3840x2160 progressive at 47.95 frames per second",
+        3840, 2160, 1001, 48000, ProgressiveFrame, WIDE;
+    UHD4K2160p48 => "2160p48", "bmdMode4K2160p48",
+        "3840x2160 progressive at 48 frames per second",
+        3840, 2160, 1000, 48000, ProgressiveFrame, WIDE;
+    UHD4K2160p50 => "2160p50", "bmdMode4K2160p50",
+        "3840x2160 progressive at 50 frames per second",
+        3840, 2160, 1000, 50000, ProgressiveFrame, WIDE;
+    UHD4K2160p5994 => "2160p59.94", "bmdMode4K2160p5994",
+        "3840x2160 progressive at 59.94 frames per second",
+        3840, 2160, 1001, 60000, ProgressiveFrame, WIDE;
+    UHD4K2160p60 => "2160p60", "bmdMode4K2160p60",
+        "3840x2160 progressive at 60 frames per second",
+        3840, 2160, 1000, 60000, ProgressiveFrame, WIDE;
+    UHD4K2160p9590 => "2160p95.90", "bmdMode4K2160p9590",
+        "3840x2160 progressive at 95.9 frames per second",
+        3840, 2160, 1001, 96000, ProgressiveFrame, WIDE;
+    UHD4K2160p96 => "2160p96", "bmdMode4K2160p96",
+        "3840x2160 progressive at 96 frames per second",
+        3840, 2160, 1000, 96000, ProgressiveFrame, WIDE;
+    UHD4K2160p100 => "2160p100", "bmdMode4K2160p100",
+        "3840x2160 progressive at 100 frames per second",
+        3840, 2160, 1000, 100000, ProgressiveFrame, WIDE;
+    UHD4K2160p11988 => "2160p119.88", "bmdMode4K2160p11988",
+        "3840x2160 progressive at 119.88 frames per second",
+        3840, 2160, 1001, 120000, ProgressiveFrame, WIDE;
+    UHD4K2160p120 => "2160p120", "bmdMode4K2160p120",
+        "3840x2160 progressive at 120 frames per second",
+        3840, 2160, 1000, 120000, ProgressiveFrame, WIDE;
+    UHD4KDCI2398 => "4K DCI 23.98", "bmdMode4kDCI2398",
+        "4096x2160 progressive at 23.98 frames per second",
+        4096, 2160, 1001, 24000, ProgressiveFrame, DCI;
+    UHD4KDCI24 => "4K DCI 24", "bmdMode4kDCI24",
+        "4096x2160 progressive at 24 frames per second",
+        4096, 2160, 1000, 24000, ProgressiveFrame, DCI;
+    UHD4KDCI25 => "4K DCI 25", "bmdMode4kDCI25",
+        "4096x2160 progressive at 25 frames per second",
+        4096, 2160, 1000, 25000, ProgressiveFrame, DCI;
+    UHD4KDCI2997 => "4K DCI 29.97", "bmdMode4kDCI2997",
+        "4096x2160 progressive at 29.97 frames per second",
+        4096, 2160, 1001, 30000, ProgressiveFrame, DCI;
+    UHD4KDCI30 => "4K DCI 30", "bmdMode4kDCI30",
+        "4096x2160 progressive at 30 frames per second",
+        4096, 2160, 1000, 30000, ProgressiveFrame, DCI;
+    UHD4KDCI4795 => "4K DCI 47.95", "bmdMode4kDCI4795",
+        "4096x2160 progressive at 47.95 frames per second",
+        4096, 2160, 1001, 48000, ProgressiveFrame, DCI;
+    UHD4KDCI48 => "4K DCI 48", "bmdMode4kDCI48",
+        "4096x2160 progressive at 48 frames per second",
+        4096, 2160, 1000, 48000, ProgressiveFrame, DCI;
+    UHD4KDCI50 => "4K DCI 50", "bmdMode4kDCI50",
+        "4096x2160 progressive at 50 frames per second",
+        4096, 2160, 1000, 50000, ProgressiveFrame, DCI;
+    UHD4KDCI5994 => "4K DCI 59.94", "bmdMode4kDCI5994",
+        "4096x2160 progressive at 59.94 frames per second",
+        4096, 2160, 1001, 60000, ProgressiveFrame, DCI;
+    UHD4KDCI60 => "4K DCI 60", "bmdMode4kDCI60",
+        "4096x2160 progressive at 60 frames per second",
+        4096, 2160, 1000, 60000, ProgressiveFrame, DCI;
+    UHD4KDCI9590 => "4K DCI 95.90", "bmdMode4kDCI9590",
+        "4096x2160 progressive at 95.9 frames per second",
+        4096, 2160, 1001, 96000, ProgressiveFrame, DCI;
+    UHD4KDCI96 => "4K DCI 96", "bmdMode4kDCI96",
+        "4096x2160 progressive at 96 frames per second",
+        4096, 2160, 1000, 96000, ProgressiveFrame, DCI;
+    UHD4KDCI100 => "4K DCI 100", "bmdMode4kDCI100",
+        "4096x2160 progressive at 100 frames per second",
+        4096, 2160, 1000, 100000, ProgressiveFrame, DCI;
+    UHD4KDCI11988 => "4K DCI 119.88", "bmdMode4kDCI11988",
+        "4096x2160 progressive at 119.88 frames per second",
+        4096, 2160, 1001, 120000, ProgressiveFrame, DCI;
+    UHD4KDCI120 => "4K DCI 120", "bmdMode4kDCI120",
+        "4096x2160 progressive at 120 frames per second",
+        4096, 2160, 1000, 120000, ProgressiveFrame, DCI;
+    UHD8K4320p2398 => "4320p23.98", "bmdMode8K4320p2398",
+        "7680x4320 progressive at 23.98 frames per second",
+        7680, 4320, 1001, 24000, ProgressiveFrame, WIDE;
+    UHD8K4320p24 => "4320p24", "bmdMode8K4320p24",
+        "7680x4320 progressive at 24 frames per second",
+        7680, 4320, 1000, 24000, ProgressiveFrame, WIDE;
+    UHD8K4320p25 => "4320p25", "bmdMode8K4320p25",
+        "7680x4320 progressive at 25 frames per second",
+        7680, 4320, 1000, 25000, ProgressiveFrame, WIDE;
+    UHD8K4320p2997 => "4320p29.97", "bmdMode8K4320p2997",
+        "7680x4320 progressive at 29.97 frames per second",
+        7680, 4320, 1001, 30000, ProgressiveFrame, WIDE;
+    UHD8K4320p30 => "4320p30", "bmdMode8K4320p30",
+        "7680x4320 progressive at 30 frames per second",
+        7680, 4320, 1000, 30000, ProgressiveFrame, WIDE;
+    UHD8K4320p4795 => "4320p47.95", "bmdMode8K4320p4795",
+        "7680x4320 progressive at 47.95 frames per second",
+        7680, 4320, 1001, 48000, ProgressiveFrame, WIDE;
+    UHD8K4320p48 => "4320p48", "bmdMode8K4320p48",
+        "7680x4320 progressive at 48 frames per second",
+        7680, 4320, 1000, 48000, ProgressiveFrame, WIDE;
+    UHD8K4320p50 => "4320p50", "bmdMode8K4320p50",
+        "7680x4320 progressive at 50 frames per second",
+        7680, 4320, 1000, 50000, ProgressiveFrame, WIDE;
+    UHD8K4320p5994 => "4320p59.94", "bmdMode8K4320p5994",
+        "7680x4320 progressive at 59.94 frames per second",
+        7680, 4320, 1001, 60000, ProgressiveFrame, WIDE;
+    UHD8K4320p60 => "4320p60", "bmdMode8K4320p60",
+        "7680x4320 progressive at 60 frames per second",
+        7680, 4320, 1000, 60000, ProgressiveFrame, WIDE;
+    UHD8KDCI2398 => "8K DCI 23.98", "bmdMode8kDCI2398",
+        "8192x4320 progressive at 23.98 frames per second",
+        8192, 4320, 1001, 24000, ProgressiveFrame, DCI;
+    UHD8KDCI24 => "8K DCI 24", "bmdMode8kDCI24",
+        "8192x4320 progressive at 24 frames per second",
+        8192, 4320, 1000, 24000, ProgressiveFrame, DCI;
+    UHD8KDCI25 => "8K DCI 25", "bmdMode8kDCI25",
+        "8192x4320 progressive at 25 frames per second",
+        8192, 4320, 1000, 25000, ProgressiveFrame, DCI;
+    UHD8KDCI2997 => "8K DCI 29.97", "bmdMode8kDCI2997",
+        "8192x4320 progressive at 29.97 frames per second",
+        8192, 4320, 1001, 30000, ProgressiveFrame, DCI;
+    UHD8KDCI30 => "8K DCI 30", "bmdMode8kDCI30",
+        "8192x4320 progressive at 30 frames per second",
+        8192, 4320, 1000, 30000, ProgressiveFrame, DCI;
+    UHD8KDCI4795 => "8K DCI 47.95", "bmdMode8kDCI4795",
+        "8192x4320 progressive at 47.95 frames per second",
+        8192, 4320, 1001, 48000, ProgressiveFrame, DCI;
+    UHD8KDCI48 => "8K DCI 48", "bmdMode8kDCI48",
+        "8192x4320 progressive at 48 frames per second",
+        8192, 4320, 1000, 48000, ProgressiveFrame, DCI;
+    UHD8KDCI50 => "8K DCI 50", "bmdMode8kDCI50",
+        "8192x4320 progressive at 50 frames per second",
+        8192, 4320, 1000, 50000, ProgressiveFrame, DCI;
+    UHD8KDCI5994 => "8K DCI 59.94", "bmdMode8kDCI5994",
+        "8192x4320 progressive at 59.94 frames per second",
+        8192, 4320, 1001, 60000, ProgressiveFrame, DCI;
+    UHD8KDCI60 => "8K DCI 60", "bmdMode8kDCI60",
+        "8192x4320 progressive at 60 frames per second",
+        8192, 4320, 1000, 60000, ProgressiveFrame, DCI;
+    PC640x480p60 => "640x480p60", "bmdMode640x480p60",
+        "640x480 progressive at 60 frames per second",
+        640, 480, 1000, 60000, ProgressiveFrame, FOUR_BY_THREE;
+    PC800x600p60 => "800x600p60", "bmdMode800x600p60",
+        "800x600 progressive at 60 frames per second",
+        800, 600, 1000, 60000, ProgressiveFrame, FOUR_BY_THREE;
+    PC1440x900p50 => "1440x900p50", "bmdMode1440x900p50",
+        "1440x900 progressive at 50 frames per second",
+        1440, 900, 1000, 50000, ProgressiveFrame, SIXTEEN_BY_TEN;
+    PC1440x900p60 => "1440x900p60", "bmdMode1440x900p60",
+        "1440x900 progressive at 60 frames per second",
+        1440, 900, 1000, 60000, ProgressiveFrame, SIXTEEN_BY_TEN;
+    PC1440x1080p50 => "1440x1080p50", "bmdMode1440x1080p50",
+        "1440x1080 progressive at 50 frames per second",
+        1440, 1080, 1000, 50000, ProgressiveFrame, FOUR_BY_THREE;
+    PC1440x1080p60 => "1440x1080p60", "bmdMode1440x1080p60",
+        "1440x1080 progressive at 60 frames per second",
+        1440, 1080, 1000, 60000, ProgressiveFrame, FOUR_BY_THREE;
+    PC1600x1200p50 => "1600x1200p50", "bmdMode1600x1200p50",
+        "1600x1200 progressive at 50 frames per second",
+        1600, 1200, 1000, 50000, ProgressiveFrame, FOUR_BY_THREE;
+    PC1600x1200p60 => "1600x1200p60", "bmdMode1600x1200p60",
+        "1600x1200 progressive at 60 frames per second",
+        1600, 1200, 1000, 60000, ProgressiveFrame, FOUR_BY_THREE;
+    PC1920x1200p50 => "1920x1200p50", "bmdMode1920x1200p50",
+        "1920x1200 progressive at 50 frames per second",
+        1920, 1200, 1000, 50000, ProgressiveFrame, SIXTEEN_BY_TEN;
+    PC1920x1200p60 => "1920x1200p60", "bmdMode1920x1200p60",
+        "1920x1200 progressive at 60 frames per second",
+        1920, 1200, 1000, 60000, ProgressiveFrame, SIXTEEN_BY_TEN;
+    PC1920x1440p50 => "1920x1440p50", "bmdMode1920x1440p50",
+        "1920x1440 progressive at 50 frames per second",
+        1920, 1440, 1000, 50000, ProgressiveFrame, FOUR_BY_THREE;
+    PC1920x1440p60 => "1920x1440p60", "bmdMode1920x1440p60",
+        "1920x1440 progressive at 60 frames per second",
+        1920, 1440, 1000, 60000, ProgressiveFrame, FOUR_BY_THREE;
+    PC2560x1440p50 => "2560x1440p50", "bmdMode2560x1440p50",
+        "2560x1440 progressive at 50 frames per second",
+        2560, 1440, 1000, 50000, ProgressiveFrame, WIDE;
+    PC2560x1440p60 => "2560x1440p60", "bmdMode2560x1440p60",
+        "2560x1440 progressive at 60 frames per second",
+        2560, 1440, 1000, 60000, ProgressiveFrame, WIDE;
+    PC2560x1600p50 => "2560x1600p50", "bmdMode2560x1600p50",
+        "2560x1600 progressive at 50 frames per second",
+        2560, 1600, 1000, 50000, ProgressiveFrame, SIXTEEN_BY_TEN;
+    PC2560x1600p60 => "2560x1600p60", "bmdMode2560x1600p60",
+        "2560x1600 progressive at 60 frames per second",
+        2560, 1600, 1000, 60000, ProgressiveFrame, SIXTEEN_BY_TEN;
+    Unknown => "Unknown", "bmdModeUnknown",
+        "No mode, as reported before one is detected",
+        0, 0, 0, 0, Unknown, NONE;
+};
+
+pub(crate) const STATUS_IDS: &[StatusIdMetadata] = &[
+    StatusIdMetadata {
+        id: DecklinkStatusId::DetectedVideoInputMode,
+        sdk_constant: "bmdDeckLinkStatusDetectedVideoInputMode",
+        description: "The detected video input mode, on devices which detect the input format",
+        kind: StatusValueKind::Int,
+    },
+    StatusIdMetadata {
+        id: DecklinkStatusId::DetectedVideoInputFlags,
+        sdk_constant: "bmdDeckLinkStatusDetectedVideoInputFormatFlags",
+        description: "The flags of the detected video input format",
+        kind: StatusValueKind::Int,
+    },
+    StatusIdMetadata {
+        id: DecklinkStatusId::CurrentVideoInputMode,
+        sdk_constant: "bmdDeckLinkStatusCurrentVideoInputMode",
+        description: "The current video input mode",
+        kind: StatusValueKind::Int,
+    },
+    StatusIdMetadata {
+        id: DecklinkStatusId::CurrentVideoInputPixelFormat,
+        sdk_constant: "bmdDeckLinkStatusCurrentVideoInputPixelFormat",
+        description: "The current video input pixel format",
+        kind: StatusValueKind::Int,
+    },
+    StatusIdMetadata {
+        id: DecklinkStatusId::CurrentVideoInputFlags,
+        sdk_constant: "bmdDeckLinkStatusCurrentVideoInputFlags",
+        description: "The current video input flags",
+        kind: StatusValueKind::Int,
+    },
+    StatusIdMetadata {
+        id: DecklinkStatusId::CurrentVideoOutputMode,
+        sdk_constant: "bmdDeckLinkStatusCurrentVideoOutputMode",
+        description: "The current video output mode",
+        kind: StatusValueKind::Int,
+    },
+    StatusIdMetadata {
+        id: DecklinkStatusId::CurrentVideoOutputFlags,
+        sdk_constant: "bmdDeckLinkStatusCurrentVideoOutputFlags",
+        description: "The current video output flags",
+        kind: StatusValueKind::Int,
+    },
+    StatusIdMetadata {
+        id: DecklinkStatusId::PCIExpressLinkWidth,
+        sdk_constant: "bmdDeckLinkStatusPCIExpressLinkWidth",
+        description: "The PCIe link width, x1, x4 and so on",
+        kind: StatusValueKind::Int,
+    },
+    StatusIdMetadata {
+        id: DecklinkStatusId::PCIExpressLinkSpeed,
+        sdk_constant: "bmdDeckLinkStatusPCIExpressLinkSpeed",
+        description: "The PCIe link speed, Gen. 1, Gen. 2 and so on",
+        kind: StatusValueKind::Int,
+    },
+    StatusIdMetadata {
+        id: DecklinkStatusId::LastVideoOutputPixelFormat,
+        sdk_constant: "bmdDeckLinkStatusLastVideoOutputPixelFormat",
+        description: "The last video output pixel format",
+        kind: StatusValueKind::Int,
+    },
+    StatusIdMetadata {
+        id: DecklinkStatusId::ReferenceSignalMode,
+        sdk_constant: "bmdDeckLinkStatusReferenceSignalMode",
+        description: "The detected reference input mode, on devices which detect it",
+        kind: StatusValueKind::Int,
+    },
+    StatusIdMetadata {
+        id: DecklinkStatusId::ReferenceSignalFlags,
+        sdk_constant: "bmdDeckLinkStatusReferenceSignalFlags",
+        description: "The detected reference input flags, on devices which detect them",
+        kind: StatusValueKind::Int,
+    },
+    StatusIdMetadata {
+        id: DecklinkStatusId::Busy,
+        sdk_constant: "bmdDeckLinkStatusBusy",
+        description: "Whether capture, playback or the serial port are in use",
+        kind: StatusValueKind::Int,
+    },
+    StatusIdMetadata {
+        id: DecklinkStatusId::InterchangeablePanelType,
+        sdk_constant: "bmdDeckLinkStatusInterchangeablePanelType",
+        description: "The interchangeable panel installed",
+        kind: StatusValueKind::Int,
+    },
+    StatusIdMetadata {
+        id: DecklinkStatusId::VideoInputSignalLocked,
+        sdk_constant: "bmdDeckLinkStatusVideoInputSignalLocked",
+        description: "Whether the video input signal is locked",
+        kind: StatusValueKind::Flag,
+    },
+    StatusIdMetadata {
+        id: DecklinkStatusId::ReferenceSignalLocked,
+        sdk_constant: "bmdDeckLinkStatusReferenceSignalLocked",
+        description: "Whether the reference input signal is locked",
+        kind: StatusValueKind::Flag,
+    },
+    StatusIdMetadata {
+        id: DecklinkStatusId::ReceivedEDID,
+        sdk_constant: "bmdDeckLinkStatusReceivedEDID",
+        description: "The EDID of a connected HDMI sink",
+        kind: StatusValueKind::Bytes,
+    },
+    StatusIdMetadata {
+        id: DecklinkStatusId::DeviceTemperature,
+        sdk_constant: "bmdDeckLinkStatusDeviceTemperature",
+        description: "The on-board temperature in degrees Celsius",
+        kind: StatusValueKind::Int,
+    },
+];
+
+/// A table of `Reference`, for rendering into documentation.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+pub enum ReferenceTable {
+    PixelFormats,
+    DisplayModes,
+    StatusIds,
+}
+
+impl ReferenceTable {
+    pub const ALL: [ReferenceTable; 3] = [
+        ReferenceTable::PixelFormats,
+        ReferenceTable::DisplayModes,
+        ReferenceTable::StatusIds,
+    ];
+
+    /// The file in `src/reference` the documentation includes the table from.
+    pub fn file_name(&self) -> &'static str {
+        match self {
+            ReferenceTable::PixelFormats => "pixel_formats.md",
+            ReferenceTable::DisplayModes => "display_modes.md",
+            ReferenceTable::StatusIds => "status_ids.md",
+        }
+    }
+
+    /// The table as a markdown table.
+    pub fn markdown(&self) -> String {
+        let mut out = String::new();
+        match self {
+            ReferenceTable::PixelFormats => {
+                out.push_str("| Format | FourCC | SDK constant | Bit depth | Row packing |");
+                out.push_str(" Description |\n|---|---|---|---|---|---|\n");
+                for m in PIXEL_FORMATS {
+                    let fourcc = m.fourcc.map_or("-".to_string(), |f| format!("`{}`", f));
+                    let depth = m.bit_depth.map_or("-".to_string(), |d| d.to_string());
+                    let packing = match m.packing {
+                        Some(p) if p.pixels == 1 => format!("{} bytes per pixel", p.bytes),
+                        Some(p) => format!("{} bytes per {} pixels", p.bytes, p.pixels),
+                        None => "compressed".to_string(),
+                    };
+                    let _ = writeln!(
+                        out,
+                        "| `{:?}` | {} | `{}` | {} | {} | {} |",
+                        m.format, fourcc, m.sdk_constant, depth, packing, m.description
+                    );
+                }
+            }
+            ReferenceTable::DisplayModes => {
+                out.push_str("| Mode | Name | SDK constant | Size | Frame rate |");
+                out.push_str(" Field dominance | Pixel aspect | Display aspect |\n");
+                out.push_str("|---|---|---|---|---|---|---|---|\n");
+                for m in DISPLAY_MODES {
+                    let _ = write!(
+                        out,
+                        "| `{:?}` | {} | `{}` | ",
+                        m.mode, m.name, m.sdk_constant
+                    );
+                    match m.aspect {
+                        Some(a) => {
+                            let _ = writeln!(
+                                out,
+                                "{}x{} | {}/{} | {:?} | {} | {} |",
+                                m.width,
+                                m.height,
+                                m.time_scale,
+                                m.frame_duration,
+                                m.field_dominance,
+                                aspect_cell(a.pixel, a.widescreen_pixel),
+                                aspect_cell(a.display, a.widescreen_display)
+                            );
+                        }
+                        None => out.push_str("- | - | - | - | - |\n"),
+                    }
+                }
+            }
+            ReferenceTable::StatusIds => {
+                out.push_str("| Status | SDK constant | Value | Description |\n");
+                out.push_str("|---|---|---|---|\n");
+                for m in STATUS_IDS {
+                    let _ = writeln!(
+                        out,
+                        "| `{:?}` | `{}` | {:?} | {} |",
+                        m.id, m.sdk_constant, m.kind, m.description
+                    );
+                }
+            }
+        }
+        out
+    }
+}
+
+/// A ratio, followed by the widescreen one where that differs.
+fn aspect_cell(ratio: Ratio, widescreen: Ratio) -> String {
+    if ratio == widescreen {
+        ratio.to_string()
+    } else {
+        format!("{} ({} widescreen)", ratio, widescreen)
+    }
+}
+
+fn write_json_ratio(out: &mut String, ratio: Ratio) {
+    let _ = write!(out, "{{\"num\": {}, \"den\": {}}}", ratio.num, ratio.den);
+}
+
+fn write_json_opt_str(out: &mut String, s: Option<&str>) {
+    match s {
+        Some(s) => write_json_string(out, s),
+        None => out.push_str("null"),
+    }
+}
+
+impl Reference {
+    /// The tables as JSON, in the form serde gives them, for programs built without it.
+    pub fn to_json(&self) -> String {
+        let mut out = String::from("{\n  \"pixel_formats\": [");
+        for (i, m) in self.pixel_formats.iter().enumerate() {
+            out.push_str(if i > 0 { ",\n    " } else { "\n    " });
+            let _ = write!(out, "{{\"format\": \"{:?}\", \"name\": ", m.format);
+            write_json_string(&mut out, m.name);
+            out.push_str(", \"sdk_constant\": ");
+            write_json_string(&mut out, m.sdk_constant);
+            out.push_str(", \"fourcc\": ");
+            write_json_opt_str(&mut out, m.fourcc);
+            out.push_str(", \"description\": ");
+            write_json_string(&mut out, m.description);
+            match m.bit_depth {
+                Some(depth) => {
+                    let _ = write!(out, ", \"bit_depth\": {}", depth);
+                }
+                None => out.push_str(", \"bit_depth\": null"),
+            }
+            let _ = write!(out, ", \"rgb\": {}, \"packing\": ", m.rgb);
+            match m.packing {
+                Some(p) => {
+                    let _ = write!(
+                        out,
+                        "{{\"pixels\": {}, \"bytes\": {}}}}}",
+                        p.pixels, p.bytes
+                    );
+                }
+                None => out.push_str("null}"),
+            }
+        }
+
+        out.push_str("\n  ],\n  \"display_modes\": [");
+        for (i, m) in self.display_modes.iter().enumerate() {
+            out.push_str(if i > 0 { ",\n    " } else { "\n    " });
+            let _ = write!(out, "{{\"mode\": \"{:?}\", \"name\": ", m.mode);
+            write_json_string(&mut out, m.name);
+            out.push_str(", \"sdk_constant\": ");
+            write_json_string(&mut out, m.sdk_constant);
+            out.push_str(", \"description\": ");
+            write_json_string(&mut out, m.description);
+            let _ = write!(
+                out,
+                ", \"width\": {}, \"height\": {}, \"frame_duration\": {}, \"time_scale\": {}, \
+                 \"field_dominance\": \"{:?}\", \"aspect\": ",
+                m.width, m.height, m.frame_duration, m.time_scale, m.field_dominance
+            );
+            match m.aspect {
+                Some(a) => {
+                    out.push_str("{\"pixel\": ");
+                    write_json_ratio(&mut out, a.pixel);
+                    out.push_str(", \"widescreen_pixel\": ");
+                    write_json_ratio(&mut out, a.widescreen_pixel);
+                    out.push_str(", \"display\": ");
+                    write_json_ratio(&mut out, a.display);
+                    out.push_str(", \"widescreen_display\": ");
+                    write_json_ratio(&mut out, a.widescreen_display);
+                    out.push_str("}}");
+                }
+                None => out.push_str("null}"),
+            }
+        }
+
+        out.push_str("\n  ],\n  \"status_ids\": [");
+        for (i, m) in self.status_ids.iter().enumerate() {
+            out.push_str(if i > 0 { ",\n    " } else { "\n    " });
+            let _ = write!(out, "{{\"id\": \"{:?}\", \"sdk_constant\": ", m.id);
+            write_json_string(&mut out, m.sdk_constant);
+            out.push_str(", \"description\": ");
+            write_json_string(&mut out, m.description);
+            let _ = write!(out, ", \"kind\": \"{:?}\"}}", m.kind);
+        }
+        out.push_str("\n  ]\n}\n");
+        out
+    }
+}
@@ -0,0 +1,545 @@
+//! Recording the sequence of input callbacks, so a capture session can be reproduced.
+//!
+//! A `SessionRecorder` is installed as the input callback, and logs every callback with its
+//! metadata and the time since the previous one. Pixel and sample data are not recorded.
+//! A `SessionReader` reads the events back, and `replay` re-delivers them with their original
+//! pacing or as fast as possible. With the `mock-backend` feature, `replay_to_mock` re-delivers
+//! them through the callback of a mock input instead.
+//!
+//! To reproduce the pixel data as well, record a `crate::dump` file, which
+//! `replay_dump_to_mock` re-delivers through a mock input in the same way.
+//!
+//! The file format (version 1) is little endian:
+//!
+//! ```text
+//! header:  b"DLSR", version: u16
+//! record:  tag: u8, delta_ns: u64, payload
+//!
+//! tag 1, format changed:  events: u32, display_mode: u32, detected_flags: u32
+//! tag 2, frame:           has_frame: u8,
+//!                         [width: u32, height: u32, row_bytes: u32, pixel_format: u32,
+//!                          flags: u32]                                     if has_frame
+//!                         has_timing: u8,
+//!                         [stream_time: i64, duration: i64, mode_duration: i64,
+//!                          scale: i64]                                     if has_timing
+//! tag 3, audio packet:    sample_type: u32, channel_count: u32, sample_frame_count: u32
+//! ```
+//!
+//! Enum and flag values are the raw SDK values. Readers reject files with a newer version.
+
+use crate::device::input::{
+    ArrivalFlags, CallbackResult, DecklinkAudioSampleType, DecklinkDetectedVideoInputFormatFlags,
+    DecklinkVideoInputFormatChangedEvents, FrameArrival, InputFormatChange, InputHandler,
+};
+use crate::display_mode::DecklinkDisplayModeId;
+use crate::frame::{DecklinkFrameBase, DecklinkFrameFlags, DecklinkPixelFormat};
+use crate::time::{DecklinkFrameTiming, DecklinkTime};
+use num_traits::FromPrimitive;
+use std::io::{self, Read, Write};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const MAGIC: &[u8; 4] = b"DLSR";
+
+/// The version of the session file format that is written.
+pub const SESSION_FORMAT_VERSION: u16 = 1;
+
+const TAG_FORMAT_CHANGED: u8 = 1;
+const TAG_FRAME: u8 = 2;
+const TAG_AUDIO: u8 = 3;
+
+/// The metadata of a video frame.
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub struct RecordedFrame {
+    pub width: usize,
+    pub height: usize,
+    pub row_bytes: usize,
+    pub pixel_format: DecklinkPixelFormat,
+    pub flags: DecklinkFrameFlags,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum SessionEvent {
+    FormatChanged {
+        events: DecklinkVideoInputFormatChangedEvents,
+        display_mode: DecklinkDisplayModeId,
+        detected_flags: DecklinkDetectedVideoInputFormatFlags,
+    },
+    /// A frame callback. `frame` is `None` if the callback had no video frame.
+    Frame {
+        frame: Option<RecordedFrame>,
+        timing: Option<DecklinkFrameTiming>,
+    },
+    AudioPacket {
+        sample_type: DecklinkAudioSampleType,
+        channel_count: u32,
+        sample_frame_count: usize,
+    },
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct RecordedEvent {
+    /// Time since the previous event, or since recording started for the first event.
+    pub delta: Duration,
+    pub event: SessionEvent,
+}
+
+/// Writes session events in the session file format.
+pub struct SessionWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> SessionWriter<W> {
+    /// Write the file header and return a writer for events.
+    pub fn new(mut writer: W) -> io::Result<SessionWriter<W>> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&SESSION_FORMAT_VERSION.to_le_bytes())?;
+        Ok(SessionWriter { writer })
+    }
+
+    pub fn write_event(&mut self, event: &RecordedEvent) -> io::Result<()> {
+        let mut buf = Vec::with_capacity(64);
+        let delta_ns = u64::try_from(event.delta.as_nanos()).unwrap_or(u64::MAX);
+        let u32_le = |buf: &mut Vec<u8>, v: u32| buf.extend_from_slice(&v.to_le_bytes());
+
+        match &event.event {
+            SessionEvent::FormatChanged {
+                events,
+                display_mode,
+                detected_flags,
+            } => {
+                buf.push(TAG_FORMAT_CHANGED);
+                buf.extend_from_slice(&delta_ns.to_le_bytes());
+                u32_le(&mut buf, events.bits());
+                u32_le(&mut buf, *display_mode as u32);
+                u32_le(&mut buf, detected_flags.bits());
+            }
+            SessionEvent::Frame { frame, timing } => {
+                buf.push(TAG_FRAME);
+                buf.extend_from_slice(&delta_ns.to_le_bytes());
+                match frame {
+                    Some(f) => {
+                        buf.push(1);
+                        u32_le(&mut buf, f.width as u32);
+                        u32_le(&mut buf, f.height as u32);
+                        u32_le(&mut buf, f.row_bytes as u32);
+                        u32_le(&mut buf, f.pixel_format as u32);
+                        u32_le(&mut buf, f.flags.bits());
+                    }
+                    None => buf.push(0),
+                }
+                match timing {
+                    Some(t) => {
+                        buf.push(1);
+                        buf.extend_from_slice(&t.stream_time.value.to_le_bytes());
+                        buf.extend_from_slice(&t.duration.value.to_le_bytes());
+                        buf.extend_from_slice(&t.mode_duration.value.to_le_bytes());
+                        buf.extend_from_slice(&t.stream_time.scale.to_le_bytes());
+                    }
+                    None => buf.push(0),
+                }
+            }
+            SessionEvent::AudioPacket {
+                sample_type,
+                channel_count,
+                sample_frame_count,
+            } => {
+                buf.push(TAG_AUDIO);
+                buf.extend_from_slice(&delta_ns.to_le_bytes());
+                u32_le(&mut buf, *sample_type as u32);
+                u32_le(&mut buf, *channel_count);
+                u32_le(&mut buf, *sample_frame_count as u32);
+            }
+        }
+
+        self.writer.write_all(&buf)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+struct RecorderState<W: Write> {
+    writer: SessionWriter<W>,
+    last_event: Instant,
+    error: Option<io::Error>,
+}
+
+/// An input callback that records every callback, and forwards it to an inner handler.
+///
+/// Writing happens on the callback thread, so the writer should be buffered. Write errors
+/// stop the recording, and can be retrieved with `take_error`.
+pub struct SessionRecorder<W: Write + Send> {
+    state: Mutex<RecorderState<W>>,
+    inner: Option<Arc<dyn InputHandler>>,
+}
+
+impl<W: Write + Send> SessionRecorder<W> {
+    pub fn new(writer: W, inner: Option<Arc<dyn InputHandler>>) -> io::Result<SessionRecorder<W>> {
+        Ok(SessionRecorder {
+            state: Mutex::new(RecorderState {
+                writer: SessionWriter::new(writer)?,
+                last_event: Instant::now(),
+                error: None,
+            }),
+            inner,
+        })
+    }
+
+    fn record(&self, event: SessionEvent) {
+        let mut state = self.state.lock().unwrap();
+        if state.error.is_some() {
+            return;
+        }
+
+        let now = Instant::now();
+        let delta = now - state.last_event;
+        state.last_event = now;
+        if let Err(e) = state.writer.write_event(&RecordedEvent { delta, event }) {
+            state.error = Some(e);
+        }
+    }
+
+    /// Flush the writer.
+    pub fn flush(&self) -> io::Result<()> {
+        self.state.lock().unwrap().writer.flush()
+    }
+
+    /// Take the error that stopped the recording, if any.
+    pub fn take_error(&self) -> Option<io::Error> {
+        self.state.lock().unwrap().error.take()
+    }
+}
+
+impl<W: Write + Send> InputHandler for SessionRecorder<W> {
+    fn format_changed(&self, change: &InputFormatChange) {
+        self.record(SessionEvent::FormatChanged {
+            events: change.events,
+            display_mode: change.display_mode,
+            detected_flags: change.detected_signal_flags,
+        });
+        if let Some(inner) = &self.inner {
+            inner.format_changed(change);
+        }
+    }
+
+    fn frame_arrived(&self, arrival: &FrameArrival<'_>) -> CallbackResult {
+        if let Some(packet) = arrival.audio_packet {
+            self.record(SessionEvent::AudioPacket {
+                sample_type: packet.sample_type(),
+                channel_count: packet.channel_count(),
+                sample_frame_count: packet.sample_frame_count(),
+            });
+        }
+        // A lost frame was never delivered to a callback, so there is nothing to replay
+        if !arrival.flags.contains(ArrivalFlags::FRAME_LOST) {
+            self.record(SessionEvent::Frame {
+                frame: arrival.video_frame.map(|f| RecordedFrame {
+                    width: f.width(),
+                    height: f.height(),
+                    row_bytes: f.row_bytes(),
+                    pixel_format: f.pixel_format(),
+                    flags: f.flags(),
+                }),
+                timing: arrival.timing(),
+            });
+        }
+        match &self.inner {
+            Some(inner) => inner.frame_arrived(arrival),
+            None => CallbackResult::Ok,
+        }
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Reads the events of a recorded session.
+pub struct SessionReader<R: Read> {
+    reader: R,
+    version: u16,
+}
+
+impl<R: Read> SessionReader<R> {
+    /// Read and check the file header.
+    pub fn new(mut reader: R) -> io::Result<SessionReader<R>> {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data("not a recorded session"));
+        }
+        let mut version = [0; 2];
+        reader.read_exact(&mut version)?;
+        let version = u16::from_le_bytes(version);
+        if version > SESSION_FORMAT_VERSION {
+            return Err(invalid_data("unsupported session format version"));
+        }
+        Ok(SessionReader { reader, version })
+    }
+
+    /// The format version of the file.
+    pub fn version(&self) -> u16 {
+        self.version
+    }
+
+    fn read_u8(&mut self) -> io::Result<u8> {
+        let mut buf = [0; 1];
+        self.reader.read_exact(&mut buf)?;
+        Ok(buf[0])
+    }
+    fn read_u32(&mut self) -> io::Result<u32> {
+        let mut buf = [0; 4];
+        self.reader.read_exact(&mut buf)?;
+        Ok(u32::from_le_bytes(buf))
+    }
+    fn read_u64(&mut self) -> io::Result<u64> {
+        let mut buf = [0; 8];
+        self.reader.read_exact(&mut buf)?;
+        Ok(u64::from_le_bytes(buf))
+    }
+    fn read_i64(&mut self) -> io::Result<i64> {
+        self.read_u64().map(|v| v as i64)
+    }
+
+    /// Read the next event, or `None` at the end of the file.
+    pub fn next_event(&mut self) -> io::Result<Option<RecordedEvent>> {
+        let mut tag = [0; 1];
+        if self.reader.read(&mut tag)? == 0 {
+            return Ok(None);
+        }
+        let delta = Duration::from_nanos(self.read_u64()?);
+
+        let event = match tag[0] {
+            TAG_FORMAT_CHANGED => SessionEvent::FormatChanged {
+                events: DecklinkVideoInputFormatChangedEvents::from_bits_retain(self.read_u32()?),
+                display_mode: DecklinkDisplayModeId::from_u32(self.read_u32()?)
+                    .ok_or_else(|| invalid_data("unknown display mode"))?,
+                detected_flags: DecklinkDetectedVideoInputFormatFlags::from_bits_retain(
+                    self.read_u32()?,
+                ),
+            },
+            TAG_FRAME => {
+                let frame = if self.read_u8()? != 0 {
+                    Some(RecordedFrame {
+                        width: self.read_u32()? as usize,
+                        height: self.read_u32()? as usize,
+                        row_bytes: self.read_u32()? as usize,
+                        pixel_format: DecklinkPixelFormat::from_u32(self.read_u32()?)
+                            .ok_or_else(|| invalid_data("unknown pixel format"))?,
+                        flags: DecklinkFrameFlags::from_bits_retain(self.read_u32()?),
+                    })
+                } else {
+                    None
+                };
+                let timing = if self.read_u8()? != 0 {
+                    let stream_time = self.read_i64()?;
+                    let duration = self.read_i64()?;
+                    let mode_duration = self.read_i64()?;
+                    let scale = self.read_i64()?;
+                    Some(DecklinkFrameTiming {
+                        stream_time: DecklinkTime::new(stream_time, scale),
+                        duration: DecklinkTime::new(duration, scale),
+                        mode_duration: DecklinkTime::new(mode_duration, scale),
+                    })
+                } else {
+                    None
+                };
+                SessionEvent::Frame { frame, timing }
+            }
+            TAG_AUDIO => SessionEvent::AudioPacket {
+                sample_type: DecklinkAudioSampleType::from_u32(self.read_u32()?)
+                    .ok_or_else(|| invalid_data("unknown audio sample type"))?,
+                channel_count: self.read_u32()?,
+                sample_frame_count: self.read_u32()? as usize,
+            },
+            _ => return Err(invalid_data("unknown event tag")),
+        };
+
+        Ok(Some(RecordedEvent { delta, event }))
+    }
+}
+
+impl<R: Read> Iterator for SessionReader<R> {
+    type Item = io::Result<RecordedEvent>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_event().transpose()
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub enum ReplayPace {
+    /// Wait for the recorded time between events.
+    OriginalSpeed,
+    AsFastAsPossible,
+}
+
+/// Wait until the recorded time of the next event, `delta` after the previous one. Events are
+/// scheduled from the start, so handler time does not accumulate as drift.
+fn wait_for(due: &mut Instant, delta: Duration, pace: ReplayPace) {
+    if pace == ReplayPace::OriginalSpeed {
+        *due += delta;
+        let now = Instant::now();
+        if *due > now {
+            std::thread::sleep(*due - now);
+        }
+    }
+}
+
+/// Deliver every event of a recorded session to `handler`, in order.
+pub fn replay<R: Read, F: FnMut(&RecordedEvent)>(
+    reader: SessionReader<R>,
+    pace: ReplayPace,
+    mut handler: F,
+) -> io::Result<()> {
+    let mut due = Instant::now();
+    for event in reader {
+        let event = event?;
+        wait_for(&mut due, event.delta, pace);
+        handler(&event);
+    }
+    Ok(())
+}
+
+/// Re-deliver a recorded session through the callback of a mock input, returning the number
+/// of callbacks the input delivered.
+///
+/// Frames are filled with zeros and audio packets with silence, with the recorded metadata.
+/// An audio packet is delivered in one callback with the frame callback recorded after it, as
+/// the driver delivered them. The frame timing is only reproduced if video input is enabled
+/// in the recorded mode.
+#[cfg(feature = "mock-backend")]
+pub fn replay_to_mock<R: Read>(
+    reader: SessionReader<R>,
+    input: &crate::mock::MockInput,
+    pace: ReplayPace,
+) -> io::Result<usize> {
+    use crate::mock::{Delivery, MockFrame};
+
+    let mut due = Instant::now();
+    let mut delivered = 0;
+    let mut audio: Option<Vec<u8>> = None;
+    for event in reader {
+        let event = event?;
+        wait_for(&mut due, event.delta, pace);
+
+        let delivery = match event.event {
+            SessionEvent::FormatChanged {
+                events,
+                display_mode,
+                detected_flags,
+            } => input.deliver_format_change(events, display_mode, detected_flags),
+            SessionEvent::AudioPacket {
+                sample_type,
+                channel_count,
+                sample_frame_count,
+            } => {
+                let sample_size = match sample_type {
+                    DecklinkAudioSampleType::Int16 => 2,
+                    DecklinkAudioSampleType::Int32 => 4,
+                };
+                let len = sample_frame_count * channel_count as usize * sample_size;
+                // Delivered with the frame callback that follows it
+                audio = Some(vec![0; len]);
+                continue;
+            }
+            SessionEvent::Frame { frame, timing } => {
+                let frame = frame.map(|f| {
+                    let mut frame = MockFrame::new(f.width, f.height, f.pixel_format)
+                        .row_bytes(f.row_bytes)
+                        .flags(f.flags);
+                    if let Some(timing) = timing {
+                        frame = frame.stream_time(
+                            timing.stream_time.value,
+                            timing.duration.value,
+                            timing.stream_time.scale,
+                        );
+                    }
+                    frame
+                });
+                match (frame, audio.take()) {
+                    (Some(frame), Some(audio)) => input.deliver_frame_with_audio(frame, &audio),
+                    (Some(frame), None) => input.deliver_frame(frame),
+                    (None, Some(audio)) => input.deliver_audio(&audio),
+                    (None, None) => {
+                        return Err(invalid_data(
+                            "frame callback without a frame or an audio packet",
+                        ))
+                    }
+                }
+            }
+        };
+        if let Delivery::Returned(_) = delivery {
+            delivered += 1;
+        }
+    }
+    Ok(delivered)
+}
+
+/// Re-deliver the frames of a dump file through the callback of a mock input, with their pixel
+/// data, returning the number of callbacks the input delivered.
+///
+/// At `ReplayPace::OriginalSpeed`, frames are spaced by the difference of their stream times,
+/// or by the frame duration of the dump for frames without timing. Delivery stops with
+/// `DumpError::Damaged` at a damaged frame, after the frames before it.
+#[cfg(feature = "mock-backend")]
+pub fn replay_dump_to_mock<R: Read + io::Seek>(
+    reader: crate::dump::DumpReader<R>,
+    input: &crate::mock::MockInput,
+    pace: ReplayPace,
+) -> Result<usize, crate::dump::DumpError> {
+    use crate::mock::{Delivery, MockFrame};
+
+    let as_duration = |t: DecklinkTime| {
+        Duration::from_nanos((t.value.max(0) as u128 * 1_000_000_000 / t.scale as u128) as u64)
+    };
+    let frame_duration = reader.header().frame_duration;
+    let mut due = Instant::now();
+    let mut delivered = 0;
+    let mut previous: Option<DecklinkTime> = None;
+    for frame in reader {
+        let frame = frame?;
+        let time = frame.timing.map(|t| t.stream_time).filter(|t| t.scale > 0);
+        let delta = match (previous, time) {
+            (None, _) => Duration::ZERO,
+            (Some(previous), Some(time)) => {
+                time.rescale(previous.scale).map_or(Duration::ZERO, |time| {
+                    as_duration(DecklinkTime::new(
+                        time.value - previous.value,
+                        previous.scale,
+                    ))
+                })
+            }
+            (Some(_), None) => frame_duration
+                .filter(|d| d.scale > 0)
+                .map_or(Duration::ZERO, as_duration),
+        };
+        wait_for(&mut due, delta, pace);
+        previous = time.or(previous);
+
+        let mut mock = MockFrame::new(frame.width, frame.height, frame.pixel_format)
+            .row_bytes(frame.row_bytes)
+            .bytes(&frame.payload)
+            .flags(frame.flags);
+        if let Some(timing) = frame.timing {
+            mock = mock.stream_time(
+                timing.stream_time.value,
+                timing.duration.value,
+                timing.stream_time.scale,
+            );
+        }
+        if let Some((format, timecode)) = frame.timecode {
+            mock = mock.timecode(format, timecode);
+        }
+        if let Delivery::Returned(_) = input.deliver_frame(mock) {
+            delivered += 1;
+        }
+    }
+    Ok(delivered)
+}
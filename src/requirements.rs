@@ -0,0 +1,207 @@
+//! Checking up front that the drivers and a device provide what an application needs.
+
+use crate::capabilities::{capabilities, ALLOCATOR_API_VERSION, PROFILES_API_VERSION};
+use crate::device::attributes::DecklinkVideoIOSupport;
+use crate::device::DecklinkDevice;
+use crate::ApiVersion;
+use std::fmt;
+
+/// What an application needs from the drivers and, optionally, a device.
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct Requirements {
+    /// The minimum version of the installed drivers.
+    pub min_driver: Option<ApiVersion>,
+    /// The drivers must support custom video buffer allocators.
+    pub needs_allocator_api: bool,
+    /// The drivers must support device profiles.
+    pub needs_profiles: bool,
+    /// The device must support input format detection.
+    pub needs_format_detection: bool,
+    /// The device must be able to capture video.
+    pub needs_capture: bool,
+    /// The device must be able to play back video.
+    pub needs_playback: bool,
+    /// The device must be connected with at least this many PCIe lanes.
+    pub min_pcie_width: Option<u32>,
+}
+
+/// A requirement that was not met.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum UnmetRequirement {
+    /// No drivers are installed.
+    DriverMissing,
+    DriverTooOld {
+        installed: ApiVersion,
+        required: ApiVersion,
+    },
+    AllocatorApiUnavailable {
+        installed: ApiVersion,
+    },
+    ProfilesUnavailable {
+        installed: ApiVersion,
+    },
+    /// A device requirement was given, but no device was.
+    NoDevice,
+    FormatDetectionUnsupported,
+    CaptureUnsupported,
+    PlaybackUnsupported,
+    PcieLinkTooNarrow {
+        width: Option<u32>,
+        required: u32,
+    },
+}
+
+impl fmt::Display for UnmetRequirement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UnmetRequirement::DriverMissing => write!(f, "the Decklink drivers are not installed"),
+            UnmetRequirement::DriverTooOld {
+                installed,
+                required,
+            } => write!(
+                f,
+                "drivers {} are installed, but {} or newer is required",
+                installed, required
+            ),
+            UnmetRequirement::AllocatorApiUnavailable { installed } => write!(
+                f,
+                "drivers {} do not support custom buffer allocators, {} or newer is required",
+                installed, ALLOCATOR_API_VERSION
+            ),
+            UnmetRequirement::ProfilesUnavailable { installed } => write!(
+                f,
+                "drivers {} do not support device profiles, {} or newer is required",
+                installed, PROFILES_API_VERSION
+            ),
+            UnmetRequirement::NoDevice => write!(f, "no device was found"),
+            UnmetRequirement::FormatDetectionUnsupported => {
+                write!(f, "the device does not support input format detection")
+            }
+            UnmetRequirement::CaptureUnsupported => write!(f, "the device cannot capture"),
+            UnmetRequirement::PlaybackUnsupported => write!(f, "the device cannot play back"),
+            UnmetRequirement::PcieLinkTooNarrow {
+                width: Some(width),
+                required,
+            } => write!(
+                f,
+                "the device has a x{} PCIe link, but x{} is required",
+                width, required
+            ),
+            UnmetRequirement::PcieLinkTooNarrow {
+                width: None,
+                required,
+            } => write!(
+                f,
+                "the device does not report a PCIe link, but x{} is required",
+                required
+            ),
+        }
+    }
+}
+
+/// Every requirement that was not met.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct RequirementError {
+    pub unmet: Vec<UnmetRequirement>,
+}
+
+impl fmt::Display for RequirementError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unmet requirements: ")?;
+        for (i, unmet) in self.unmet.iter().enumerate() {
+            if i > 0 {
+                write!(f, "; ")?;
+            }
+            write!(f, "{}", unmet)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for RequirementError {}
+
+impl Requirements {
+    fn needs_device(&self) -> bool {
+        self.needs_format_detection
+            || self.needs_capture
+            || self.needs_playback
+            || self.min_pcie_width.is_some()
+    }
+}
+
+/// Check `requirements` against the installed drivers and `device`, reporting every unmet
+/// requirement at once rather than stopping at the first.
+///
+/// Device requirements are reported as `UnmetRequirement::NoDevice` if `device` is `None`.
+pub fn require(
+    device: Option<&DecklinkDevice>,
+    requirements: &Requirements,
+) -> Result<(), RequirementError> {
+    let mut unmet = Vec::new();
+
+    let capabilities = capabilities();
+    match capabilities.driver_version {
+        None => unmet.push(UnmetRequirement::DriverMissing),
+        Some(installed) => {
+            if let Some(required) = requirements.min_driver {
+                if installed < required {
+                    unmet.push(UnmetRequirement::DriverTooOld {
+                        installed,
+                        required,
+                    });
+                }
+            }
+            if requirements.needs_allocator_api && !capabilities.allocator_provider {
+                unmet.push(UnmetRequirement::AllocatorApiUnavailable { installed });
+            }
+            if requirements.needs_profiles && !capabilities.profiles {
+                unmet.push(UnmetRequirement::ProfilesUnavailable { installed });
+            }
+        }
+    }
+
+    match device {
+        None => {
+            if requirements.needs_device() {
+                unmet.push(UnmetRequirement::NoDevice);
+            }
+        }
+        Some(device) => {
+            let attributes = device.get_attributes().ok();
+
+            if requirements.needs_format_detection
+                && !attributes
+                    .as_ref()
+                    .and_then(|a| a.supports_input_format_detection().ok())
+                    .unwrap_or(false)
+            {
+                unmet.push(UnmetRequirement::FormatDetectionUnsupported);
+            }
+
+            let io_support = attributes
+                .as_ref()
+                .and_then(|a| a.video_io_support().ok())
+                .unwrap_or(DecklinkVideoIOSupport::empty());
+            if requirements.needs_capture && !io_support.contains(DecklinkVideoIOSupport::CAPTURE) {
+                unmet.push(UnmetRequirement::CaptureUnsupported);
+            }
+            if requirements.needs_playback && !io_support.contains(DecklinkVideoIOSupport::PLAYBACK)
+            {
+                unmet.push(UnmetRequirement::PlaybackUnsupported);
+            }
+
+            if let Some(required) = requirements.min_pcie_width {
+                let width = device.hardware_info().pcie_link.map(|link| link.width);
+                if width.is_none_or(|width| width < required) {
+                    unmet.push(UnmetRequirement::PcieLinkTooNarrow { width, required });
+                }
+            }
+        }
+    }
+
+    if unmet.is_empty() {
+        Ok(())
+    } else {
+        Err(RequirementError { unmet })
+    }
+}
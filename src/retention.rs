@@ -0,0 +1,394 @@
+//! Limiting how many captured frames are retained past the input callback.
+//!
+//! A `DecklinkVideoFrame` holds a buffer from the driver's capture pool until it is dropped.
+//! If too many are held, the pool runs dry and capture stalls without any error. Pools are
+//! usually only a few frames deep, and the depth varies between devices and display modes,
+//! so a budget of 2 to 4 frames is a sensible place to start. Use a custom allocator from
+//! `crate::allocator` when frames need to be held for longer.
+//!
+//! Frames retained through `RetentionBudget::retain` are counted against the budget until
+//! they are dropped, or until their external use ends when handed on with
+//! `RetainedFrame::into_external_use`. Frames held any other way are not tracked.
+
+use crate::device::input::{ConsumerPressure, PressureSource};
+use crate::external::{
+    CompletionToken, ExternalFrameRef, ExternalState, ExternalUseConfig, ExternalUseEvent,
+};
+use crate::frame::{
+    DecklinkAlignedBytes, DecklinkFrameBase, DecklinkFrameFlags, DecklinkPixelFormat,
+    DecklinkVideoFrame,
+};
+use crate::SdkError;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+pub enum RetentionLimit {
+    /// At most this many frames may be retained.
+    Frames(usize),
+    /// At most this many bytes of frame data may be retained.
+    Bytes(usize),
+}
+
+/// What happens to a retain that would exceed the budget.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash, Default)]
+pub enum RetentionMode {
+    /// Refuse the retain with `RetentionBudgetExceeded`.
+    #[default]
+    Strict,
+    /// Allow the retain, counting it in `RetentionStats::exceeded_count`.
+    Lenient,
+}
+
+/// A retain was refused because the budget is exhausted.
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub struct RetentionBudgetExceeded {
+    pub limit: RetentionLimit,
+    pub retained_frames: usize,
+    pub retained_bytes: usize,
+}
+
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Default)]
+pub struct RetentionStats {
+    pub retained_frames: usize,
+    pub retained_bytes: usize,
+    /// The most frames retained at once.
+    pub peak_frames: usize,
+    /// The number of retains that were over budget, refused or not.
+    pub exceeded_count: u64,
+    /// The total time the budget has been fully used.
+    pub time_at_budget: Duration,
+    /// The retained frames whose buffers are in external use. These are also counted in
+    /// `retained_frames`.
+    pub external_frames: usize,
+    /// The number of completion tokens dropped without being completed.
+    pub leaked_count: u64,
+    /// The number of buffers in external use reclaimed without being completed.
+    pub reclaimed_count: u64,
+}
+
+struct BudgetInner {
+    limit: RetentionLimit,
+    mode: RetentionMode,
+    parent: Option<Arc<BudgetInner>>,
+
+    frames: AtomicUsize,
+    bytes: AtomicUsize,
+    peak_frames: AtomicUsize,
+    exceeded: AtomicU64,
+
+    epoch: Instant,
+    /// Nanoseconds since `epoch` at which the budget became fully used, plus one, or zero.
+    at_budget_since: AtomicU64,
+    at_budget_total: AtomicU64,
+
+    external: Arc<ExternalState>,
+}
+
+impl BudgetInner {
+    fn usage(&self) -> usize {
+        self.counter().load(Ordering::Acquire)
+    }
+    fn maximum(&self) -> usize {
+        match self.limit {
+            RetentionLimit::Frames(max) | RetentionLimit::Bytes(max) => max,
+        }
+    }
+    fn cost(&self, bytes: usize) -> usize {
+        match self.limit {
+            RetentionLimit::Frames(_) => 1,
+            RetentionLimit::Bytes(_) => bytes,
+        }
+    }
+    fn now(&self) -> u64 {
+        self.epoch.elapsed().as_nanos() as u64 + 1
+    }
+
+    fn error(&self) -> RetentionBudgetExceeded {
+        RetentionBudgetExceeded {
+            limit: self.limit,
+            retained_frames: self.frames.load(Ordering::Acquire),
+            retained_bytes: self.bytes.load(Ordering::Acquire),
+        }
+    }
+
+    /// Whether this budget or a parent is fully used.
+    fn exhausted(&self) -> bool {
+        self.usage() >= self.maximum() || self.parent.as_ref().is_some_and(|p| p.exhausted())
+    }
+
+    fn counter(&self) -> &AtomicUsize {
+        match self.limit {
+            RetentionLimit::Frames(_) => &self.frames,
+            RetentionLimit::Bytes(_) => &self.bytes,
+        }
+    }
+
+    /// Add `cost` to the limited counter, unless it would go over the limit in strict mode.
+    /// Returns whether the limit was exceeded.
+    fn reserve(&self, cost: usize) -> Result<bool, ()> {
+        let counter = self.counter();
+        let mut current = counter.load(Ordering::Acquire);
+        loop {
+            let over = current + cost > self.maximum();
+            if over && self.mode == RetentionMode::Strict {
+                return Err(());
+            }
+            match counter.compare_exchange_weak(
+                current,
+                current + cost,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => return Ok(over),
+                Err(actual) => current = actual,
+            }
+        }
+    }
+
+    /// Charge a frame to this budget and its parents.
+    fn charge(&self, bytes: usize) -> Result<(), RetentionBudgetExceeded> {
+        if let Some(parent) = &self.parent {
+            parent.charge(bytes)?;
+        }
+
+        match self.reserve(self.cost(bytes)) {
+            Ok(over) => {
+                if over {
+                    self.exceeded.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+            Err(()) => {
+                self.exceeded.fetch_add(1, Ordering::Relaxed);
+                if let Some(parent) = &self.parent {
+                    parent.release(bytes);
+                }
+                return Err(self.error());
+            }
+        }
+
+        // The limited counter was updated by `reserve`
+        let frames = match self.limit {
+            RetentionLimit::Frames(_) => {
+                self.bytes.fetch_add(bytes, Ordering::AcqRel);
+                self.frames.load(Ordering::Acquire)
+            }
+            RetentionLimit::Bytes(_) => self.frames.fetch_add(1, Ordering::AcqRel) + 1,
+        };
+        self.peak_frames.fetch_max(frames, Ordering::Relaxed);
+
+        if self.usage() >= self.maximum() {
+            let _ = self.at_budget_since.compare_exchange(
+                0,
+                self.now(),
+                Ordering::AcqRel,
+                Ordering::Relaxed,
+            );
+        }
+        Ok(())
+    }
+
+    fn release(&self, bytes: usize) {
+        self.frames.fetch_sub(1, Ordering::AcqRel);
+        self.bytes.fetch_sub(bytes, Ordering::AcqRel);
+
+        if self.usage() < self.maximum() {
+            let since = self.at_budget_since.swap(0, Ordering::AcqRel);
+            if since != 0 {
+                self.at_budget_total
+                    .fetch_add(self.now().saturating_sub(since), Ordering::Relaxed);
+            }
+        }
+
+        if let Some(parent) = &self.parent {
+            parent.release(bytes);
+        }
+    }
+}
+
+/// A limit on retained frames, shared by everything that retains them.
+///
+/// Checks are a few atomic operations, so it is cheap to retain from the input callback.
+/// Sub-budgets let a component such as a frame queue have its own limit, while still
+/// counting against the overall budget.
+#[derive(Clone)]
+pub struct RetentionBudget {
+    inner: Arc<BudgetInner>,
+}
+
+impl RetentionBudget {
+    pub fn new(limit: RetentionLimit, mode: RetentionMode) -> RetentionBudget {
+        RetentionBudget::create(limit, mode, None)
+    }
+
+    fn create(
+        limit: RetentionLimit,
+        mode: RetentionMode,
+        parent: Option<Arc<BudgetInner>>,
+    ) -> RetentionBudget {
+        RetentionBudget {
+            inner: Arc::new(BudgetInner {
+                limit,
+                mode,
+                parent,
+                frames: AtomicUsize::new(0),
+                bytes: AtomicUsize::new(0),
+                peak_frames: AtomicUsize::new(0),
+                exceeded: AtomicU64::new(0),
+                epoch: Instant::now(),
+                at_budget_since: AtomicU64::new(0),
+                at_budget_total: AtomicU64::new(0),
+                external: Arc::default(),
+            }),
+        }
+    }
+
+    /// Create a budget whose frames also count against this one.
+    pub fn sub_budget(&self, limit: RetentionLimit) -> RetentionBudget {
+        RetentionBudget::create(limit, self.inner.mode, Some(self.inner.clone()))
+    }
+
+    pub fn limit(&self) -> RetentionLimit {
+        self.inner.limit
+    }
+    pub fn mode(&self) -> RetentionMode {
+        self.inner.mode
+    }
+
+    /// Retain `frame`, counting it against the budget until the returned frame is dropped.
+    pub fn retain(
+        &self,
+        frame: DecklinkVideoFrame,
+    ) -> Result<RetainedFrame, RetentionBudgetExceeded> {
+        let bytes = frame.row_bytes() * frame.height();
+        self.inner.charge(bytes)?;
+        Ok(RetainedFrame {
+            frame,
+            bytes,
+            budget: self.inner.clone(),
+        })
+    }
+
+    /// Charge `bytes` of frame data held some other way, such as a copy of a frame, against
+    /// the budget until the returned charge is dropped.
+    pub(crate) fn charge(&self, bytes: usize) -> Result<RetentionCharge, RetentionBudgetExceeded> {
+        self.inner.charge(bytes)?;
+        Ok(RetentionCharge {
+            bytes,
+            budget: self.inner.clone(),
+        })
+    }
+
+    /// Whether this budget, or one it counts against, is fully used, so that retaining
+    /// another frame would exceed it.
+    pub fn is_exhausted(&self) -> bool {
+        self.inner.exhausted()
+    }
+
+    pub fn stats(&self) -> RetentionStats {
+        let inner = &self.inner;
+        let mut time_at_budget = inner.at_budget_total.load(Ordering::Relaxed);
+        let since = inner.at_budget_since.load(Ordering::Acquire);
+        if since != 0 {
+            time_at_budget += inner.now().saturating_sub(since);
+        }
+
+        let (external_frames, leaked_count, reclaimed_count) = inner.external.counts();
+
+        RetentionStats {
+            retained_frames: inner.frames.load(Ordering::Acquire),
+            retained_bytes: inner.bytes.load(Ordering::Acquire),
+            peak_frames: inner.peak_frames.load(Ordering::Relaxed),
+            exceeded_count: inner.exceeded.load(Ordering::Relaxed),
+            time_at_budget: Duration::from_nanos(time_at_budget),
+            external_frames,
+            leaked_count,
+            reclaimed_count,
+        }
+    }
+
+    pub fn external_use_config(&self) -> ExternalUseConfig {
+        self.inner.external.config()
+    }
+    /// Set how the buffers of frames retained from this budget and handed on with
+    /// `RetainedFrame::into_external_use` are reclaimed. Buffers already in external use
+    /// follow the new config too.
+    pub fn set_external_use_config(&self, config: ExternalUseConfig) {
+        self.inner.external.set_config(config);
+    }
+
+    /// The completions, leaks and reclaims of buffers in external use since the last call.
+    pub fn take_external_use_events(&self) -> Vec<ExternalUseEvent> {
+        self.inner.external.take_events()
+    }
+}
+
+impl PressureSource for RetentionBudget {
+    fn pressure(&self) -> ConsumerPressure {
+        ConsumerPressure {
+            retention_exhausted: self.is_exhausted(),
+            ..ConsumerPressure::default()
+        }
+    }
+}
+
+/// A captured frame counted against a `RetentionBudget`.
+pub struct RetainedFrame {
+    frame: DecklinkVideoFrame,
+    bytes: usize,
+    budget: Arc<BudgetInner>,
+}
+
+impl RetainedFrame {
+    pub fn frame(&self) -> &DecklinkVideoFrame {
+        &self.frame
+    }
+
+    /// Hand the frame's buffer to a consumer outside the crate, such as another device's
+    /// driver. The buffer stays valid, and the frame stays charged to its budget, until the
+    /// token is completed. See `crate::external`.
+    pub fn into_external_use(self) -> Result<(ExternalFrameRef, CompletionToken), SdkError> {
+        let external = self.budget.external.clone();
+        external.hand_off(self)
+    }
+}
+
+impl Drop for RetainedFrame {
+    fn drop(&mut self) {
+        self.budget.release(self.bytes);
+    }
+}
+
+/// Frame data counted against a `RetentionBudget` by `RetentionBudget::charge`.
+pub(crate) struct RetentionCharge {
+    bytes: usize,
+    budget: Arc<BudgetInner>,
+}
+
+impl Drop for RetentionCharge {
+    fn drop(&mut self) {
+        self.budget.release(self.bytes);
+    }
+}
+
+impl DecklinkFrameBase for RetainedFrame {
+    fn width(&self) -> usize {
+        self.frame.width()
+    }
+    fn height(&self) -> usize {
+        self.frame.height()
+    }
+    fn row_bytes(&self) -> usize {
+        self.frame.row_bytes()
+    }
+    fn pixel_format(&self) -> DecklinkPixelFormat {
+        self.frame.pixel_format()
+    }
+    fn flags(&self) -> DecklinkFrameFlags {
+        self.frame.flags()
+    }
+    fn bytes(&self) -> Result<DecklinkAlignedBytes<'_>, SdkError> {
+        self.frame.bytes()
+    }
+}
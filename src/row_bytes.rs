@@ -0,0 +1,255 @@
+//! Checking that the row size the crate expects for a pixel format matches the driver's.
+//!
+//! A buffer sized from the wrong row size holds all but the last few rows of a frame, so the
+//! mistake shows only as occasional garbage at the bottom of the picture. A
+//! `RowBytesValidator` compares `DecklinkPixelFormat::bytes_per_row` with the row size the
+//! driver gives. With a custom allocator, that is the `row_bytes` of each `BufferSpec`, which
+//! the driver asks for when video input is enabled. Otherwise it is the row size of the first
+//! frame after streams start, and of the first frame after each format change.
+//!
+//! A mismatch is recorded as a `RowBytesMismatch`, naming both sizes and the likely cause,
+//! for the application to report. Under `RowBytesPolicy::Strict`, the allocator request is
+//! refused, so enabling video input fails, and frames are not delivered while their row size
+//! is wrong.
+
+use crate::allocator::{BufferSpec, VideoBufferAllocator, VideoBufferAllocatorProvider};
+use crate::device::input::{CallbackResult, FrameArrival, InputFormatChange, InputHandler};
+use crate::frame::{DecklinkFrameBase, DecklinkPixelFormat};
+use crate::SdkError;
+use num_traits::FromPrimitive;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// What to do when the driver's row size differs from the expected one.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash, Default)]
+pub enum RowBytesPolicy {
+    /// Record the mismatch and carry on with the driver's row size.
+    #[default]
+    Warn,
+    /// Record the mismatch, refuse the allocator request and hold back the frames.
+    Strict,
+}
+
+/// Where the driver's row size was read from.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+pub enum RowBytesSource {
+    /// The `BufferSpec` of an allocator request.
+    Allocator,
+    /// An arriving frame.
+    Frame,
+}
+
+/// A row size given by the driver that differs from the one the crate expects.
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub struct RowBytesMismatch {
+    pub pixel_format: DecklinkPixelFormat,
+    pub width: usize,
+    /// The row size from `DecklinkPixelFormat::bytes_per_row`.
+    pub expected: usize,
+    /// The row size given by the driver.
+    pub reported: usize,
+    pub source: RowBytesSource,
+}
+
+impl RowBytesMismatch {
+    /// The likely cause of the mismatch.
+    pub fn likely_cause(&self) -> &'static str {
+        if self.reported > self.expected {
+            "the driver pads rows more than expected, so buffers sized from the width of \
+             the frame are too small and lose its last rows"
+        } else {
+            "the driver pads rows less than expected, so rows read with the expected size \
+             drift further into the frame with each row"
+        }
+    }
+}
+
+impl fmt::Display for RowBytesMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let source = match self.source {
+            RowBytesSource::Allocator => "allocator request",
+            RowBytesSource::Frame => "frame",
+        };
+        write!(
+            f,
+            "{:?} at {} pixels wide has {} bytes a row in the {}, but {} were expected: {}",
+            self.pixel_format,
+            self.width,
+            self.reported,
+            source,
+            self.expected,
+            self.likely_cause()
+        )
+    }
+}
+
+struct ValidatorShared {
+    policy: RowBytesPolicy,
+    mismatches: Mutex<Vec<RowBytesMismatch>>,
+    /// Set until the first frame after streams start or a format change has been checked.
+    check_next_frame: AtomicBool,
+    /// Set while the row size of the current format is wrong, under `RowBytesPolicy::Strict`.
+    holding_frames: AtomicBool,
+}
+
+/// Compares the row sizes given by the driver with `DecklinkPixelFormat::bytes_per_row`.
+///
+/// Clones share their state, so one can be kept to read the mismatches while another is
+/// wrapped around the provider or callback.
+#[derive(Clone)]
+pub struct RowBytesValidator {
+    shared: Arc<ValidatorShared>,
+}
+
+impl RowBytesValidator {
+    pub fn new(policy: RowBytesPolicy) -> RowBytesValidator {
+        RowBytesValidator {
+            shared: Arc::new(ValidatorShared {
+                policy,
+                mismatches: Mutex::new(Vec::new()),
+                check_next_frame: AtomicBool::new(true),
+                holding_frames: AtomicBool::new(false),
+            }),
+        }
+    }
+
+    pub fn policy(&self) -> RowBytesPolicy {
+        self.shared.policy
+    }
+
+    /// Compare a row size given by the driver with the expected one, recording a mismatch.
+    /// Compressed formats have no expected row size, so always pass.
+    pub fn check(
+        &self,
+        pixel_format: DecklinkPixelFormat,
+        width: usize,
+        reported: usize,
+        source: RowBytesSource,
+    ) -> Result<(), RowBytesMismatch> {
+        match pixel_format.bytes_per_row(width) {
+            Some(expected) if expected != reported => {
+                let mismatch = RowBytesMismatch {
+                    pixel_format,
+                    width,
+                    expected,
+                    reported,
+                    source,
+                };
+                self.shared.mismatches.lock().unwrap().push(mismatch);
+                Err(mismatch)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Check the row size of an allocator request. Requests for a pixel format this crate
+    /// does not know pass.
+    pub fn check_spec(&self, spec: &BufferSpec) -> Result<(), RowBytesMismatch> {
+        match DecklinkPixelFormat::from_u32(spec.pixel_format) {
+            Some(pixel_format) => self.check(
+                pixel_format,
+                spec.width as usize,
+                spec.row_bytes as usize,
+                RowBytesSource::Allocator,
+            ),
+            None => Ok(()),
+        }
+    }
+
+    /// Check the row size of a frame.
+    pub fn check_frame(&self, frame: &dyn DecklinkFrameBase) -> Result<(), RowBytesMismatch> {
+        self.check(
+            frame.pixel_format(),
+            frame.width(),
+            frame.row_bytes(),
+            RowBytesSource::Frame,
+        )
+    }
+
+    /// Whether frames are being held back, as the row size of the current format is wrong
+    /// under `RowBytesPolicy::Strict`.
+    pub fn holding_frames(&self) -> bool {
+        self.shared.holding_frames.load(Ordering::Relaxed)
+    }
+
+    /// The mismatches found since the last call.
+    pub fn take_mismatches(&self) -> Vec<RowBytesMismatch> {
+        std::mem::take(&mut *self.shared.mismatches.lock().unwrap())
+    }
+
+    /// Wrap `provider`, to check the row size of each allocator request before passing it
+    /// on. Under `RowBytesPolicy::Strict` a request with the wrong row size fails with
+    /// `SdkError::INVALIDARG`.
+    pub fn provider(
+        &self,
+        provider: Arc<dyn VideoBufferAllocatorProvider>,
+    ) -> Arc<dyn VideoBufferAllocatorProvider> {
+        Arc::new(ValidatingProvider {
+            validator: self.clone(),
+            provider,
+        })
+    }
+
+    /// Wrap `primary`, to check the row size of the first frame after streams start and of
+    /// the first frame after each format change. Under `RowBytesPolicy::Strict`, frames are
+    /// not passed on until a format change brings a frame with the right row size.
+    pub fn callback(&self, primary: Arc<dyn InputHandler>) -> Arc<dyn InputHandler> {
+        Arc::new(ValidatingInputCallback {
+            validator: self.clone(),
+            primary,
+        })
+    }
+}
+
+struct ValidatingProvider {
+    validator: RowBytesValidator,
+    provider: Arc<dyn VideoBufferAllocatorProvider>,
+}
+
+impl VideoBufferAllocatorProvider for ValidatingProvider {
+    fn get_allocator(&self, spec: BufferSpec) -> Result<Arc<dyn VideoBufferAllocator>, SdkError> {
+        if self.validator.check_spec(&spec).is_err()
+            && self.validator.policy() == RowBytesPolicy::Strict
+        {
+            return Err(SdkError::INVALIDARG);
+        }
+        self.provider.get_allocator(spec)
+    }
+}
+
+struct ValidatingInputCallback {
+    validator: RowBytesValidator,
+    primary: Arc<dyn InputHandler>,
+}
+
+impl InputHandler for ValidatingInputCallback {
+    fn format_changed(&self, change: &InputFormatChange) {
+        self.validator
+            .shared
+            .check_next_frame
+            .store(true, Ordering::Relaxed);
+        self.primary.format_changed(change);
+    }
+
+    fn frame_arrived(&self, arrival: &FrameArrival<'_>) -> CallbackResult {
+        let shared = &self.validator.shared;
+        if let Some(frame) = arrival.video_frame {
+            if shared.check_next_frame.swap(false, Ordering::Relaxed) {
+                let mismatched = self.validator.check_frame(frame).is_err();
+                shared.holding_frames.store(
+                    mismatched && shared.policy == RowBytesPolicy::Strict,
+                    Ordering::Relaxed,
+                );
+            }
+            if shared.holding_frames.load(Ordering::Relaxed) {
+                // Hold the frame back, but not the audio that came with it
+                if arrival.audio_packet.is_some() {
+                    return self.primary.frame_arrived(&arrival.with_video_frame(None));
+                }
+                return CallbackResult::Ok;
+            }
+        }
+        self.primary.frame_arrived(arrival)
+    }
+}
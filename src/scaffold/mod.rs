@@ -0,0 +1,311 @@
+//! Generating a small cargo project that captures with this crate, as a starting point.
+//!
+//! `write_project` writes a project with one capture in `src/lib.rs`: it picks the device with
+//! a `crate::device::selector::DeviceSelector`, enables the input in a mode of it, records
+//! every frame in the input callback, hands frames to a preview stub through a
+//! `crate::tap::TapSplitter`, waits for the first frame with
+//! `crate::device::input::DecklinkInputDevice::wait_first_frame`, and stops when a
+//! `crate::device::input::CancellationToken` is cancelled. `src/main.rs` runs it from the
+//! command line, and `tests/mock.rs` runs it against the mock backend. Its README links
+//! each part to the documentation of this crate.
+//!
+//! `ScaffoldOptions` adds audio capture, capture into CUDA pinned memory, and a tokio
+//! `main`. The generated code only refers to the features and dependencies the options
+//! enable.
+//!
+//! The project is rendered from the templates in `src/scaffold/templates`, and
+//! `tests/scaffold.rs` builds and tests the projects of several combinations of options, so
+//! the templates do not fall behind the api.
+//!
+//! The `decklink scaffold` subcommand of the command line tool calls `write_project`.
+
+use crate::config::ConfigError;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const CARGO_TOML: &str = include_str!("templates/Cargo.toml.tmpl");
+const LIB_RS: &str = include_str!("templates/lib.rs.tmpl");
+const MAIN_RS: &str = include_str!("templates/main.rs.tmpl");
+const MOCK_RS: &str = include_str!("templates/mock.rs.tmpl");
+const README_MD: &str = include_str!("templates/README.md.tmpl");
+
+/// Where the generated project gets this crate from.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum DecklinkDependency {
+    /// A version from crates.io, such as `"0.1.0"`.
+    Version(String),
+    /// A checkout of this crate.
+    Path(PathBuf),
+}
+
+impl Default for DecklinkDependency {
+    /// The version of this crate.
+    fn default() -> Self {
+        DecklinkDependency::Version(env!("CARGO_PKG_VERSION").to_string())
+    }
+}
+
+/// What the generated project does.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct ScaffoldOptions {
+    /// The name of the package, which must be a valid crate name.
+    pub name: String,
+    /// Capture two channels of 48kHz audio along with the video.
+    pub audio: bool,
+    /// Capture into CUDA pinned memory, with the `cuda` feature.
+    pub cuda: bool,
+    /// Run the capture from a tokio `main`, stopping it on Ctrl-C rather than on enter.
+    pub tokio: bool,
+    pub decklink: DecklinkDependency,
+}
+
+impl Default for ScaffoldOptions {
+    fn default() -> Self {
+        ScaffoldOptions {
+            name: "decklink-capture".to_string(),
+            audio: false,
+            cuda: false,
+            tokio: false,
+            decklink: DecklinkDependency::default(),
+        }
+    }
+}
+
+impl ScaffoldOptions {
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let mut chars = self.name.chars();
+        let starts_well = chars.next().is_some_and(|c| c.is_ascii_alphabetic());
+        if !starts_well || !chars.all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+            return Err(ConfigError::new(
+                "ScaffoldOptions",
+                "name",
+                "must start with a letter and hold only letters, digits, - and _",
+            ));
+        }
+        if self.name == "decklink" {
+            return Err(ConfigError::new(
+                "ScaffoldOptions",
+                "name",
+                "must not be decklink, which the project depends on",
+            ));
+        }
+        if let DecklinkDependency::Version(version) = &self.decklink {
+            if version.is_empty() || version.contains('"') {
+                return Err(ConfigError::new(
+                    "ScaffoldOptions",
+                    "decklink",
+                    "must be a version requirement",
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// The features of this crate the project enables, besides `mock-backend` for its tests.
+    fn features(&self) -> Vec<&'static str> {
+        let mut features = Vec::new();
+        if self.cuda {
+            features.push("cuda");
+        }
+        features
+    }
+
+    /// The value of the `decklink` dependency in the manifest, with `extra` features.
+    fn dependency(&self, extra: &[&'static str]) -> String {
+        let source = match &self.decklink {
+            DecklinkDependency::Version(version) => format!("version = \"{}\"", version),
+            DecklinkDependency::Path(path) => format!("path = {}", toml_string(path)),
+        };
+        let features: Vec<_> = self
+            .features()
+            .into_iter()
+            .chain(extra.iter().copied())
+            .map(|f| format!("\"{}\"", f))
+            .collect();
+        if features.is_empty() {
+            format!("{{ {} }}", source)
+        } else {
+            format!("{{ {}, features = [{}] }}", source, features.join(", "))
+        }
+    }
+
+    fn condition(&self, name: &str) -> Option<bool> {
+        match name {
+            "audio" => Some(self.audio),
+            "cuda" => Some(self.cuda),
+            "tokio" => Some(self.tokio),
+            _ => None,
+        }
+    }
+
+    fn variable(&self, name: &str) -> Option<String> {
+        match name {
+            "name" => Some(self.name.clone()),
+            "crate" => Some(self.name.replace('-', "_")),
+            "decklink" => Some(self.dependency(&[])),
+            "decklink_mock" => Some(self.dependency(&["mock-backend"])),
+            "docs" => Some(format!(
+                "https://docs.rs/decklink/{}/decklink",
+                env!("CARGO_PKG_VERSION")
+            )),
+            _ => None,
+        }
+    }
+}
+
+/// A file of a generated project.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct ScaffoldFile {
+    /// The path of the file within the project.
+    pub path: PathBuf,
+    pub contents: String,
+}
+
+#[derive(Debug)]
+pub enum ScaffoldError {
+    Config(ConfigError),
+    /// A file of the project already exists, and was left alone.
+    Exists(PathBuf),
+    Io(io::Error),
+}
+
+impl fmt::Display for ScaffoldError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScaffoldError::Config(e) => e.fmt(f),
+            ScaffoldError::Exists(path) => write!(f, "{} already exists", path.display()),
+            ScaffoldError::Io(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for ScaffoldError {}
+
+impl From<ConfigError> for ScaffoldError {
+    fn from(e: ConfigError) -> Self {
+        ScaffoldError::Config(e)
+    }
+}
+
+impl From<io::Error> for ScaffoldError {
+    fn from(e: io::Error) -> Self {
+        ScaffoldError::Io(e)
+    }
+}
+
+/// The files of the project `options` describe, without writing them.
+pub fn render(options: &ScaffoldOptions) -> Result<Vec<ScaffoldFile>, ConfigError> {
+    options.validate()?;
+    let files = [
+        ("Cargo.toml", CARGO_TOML),
+        ("README.md", README_MD),
+        ("src/lib.rs", LIB_RS),
+        ("src/main.rs", MAIN_RS),
+        ("tests/mock.rs", MOCK_RS),
+    ];
+    Ok(files
+        .into_iter()
+        .map(|(path, template)| ScaffoldFile {
+            path: PathBuf::from(path),
+            contents: render_template(template, options),
+        })
+        .collect())
+}
+
+/// Write the project `options` describe into `dir`, creating it if needed, and return the
+/// paths of the files written. Nothing is written if any of the files already exists.
+pub fn write_project(
+    dir: impl AsRef<Path>,
+    options: &ScaffoldOptions,
+) -> Result<Vec<PathBuf>, ScaffoldError> {
+    let dir = dir.as_ref();
+    let files = render(options)?;
+    if let Some(existing) = files
+        .iter()
+        .map(|f| dir.join(&f.path))
+        .find(|path| path.exists())
+    {
+        return Err(ScaffoldError::Exists(existing));
+    }
+
+    let mut written = Vec::with_capacity(files.len());
+    for file in files {
+        let path = dir.join(&file.path);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&path, file.contents)?;
+        written.push(path);
+    }
+    Ok(written)
+}
+
+/// Render a template. `{{#if option}}` and `{{#if !option}}` on lines of their own keep
+/// the lines up to the matching `{{/if}}` when the option is on or off, and `{{variable}}`
+/// is replaced by its value. The templates are part of the crate, so anything unknown in
+/// them is a bug, and panics.
+fn render_template(template: &str, options: &ScaffoldOptions) -> String {
+    let mut out = String::with_capacity(template.len());
+    // Whether each enclosing block is kept
+    let mut kept: Vec<bool> = Vec::new();
+    for line in template.lines() {
+        let trimmed = line.trim();
+        if let Some(condition) = trimmed
+            .strip_prefix("{{#if ")
+            .and_then(|c| c.strip_suffix("}}"))
+        {
+            let (negated, name) = match condition.strip_prefix('!') {
+                Some(name) => (true, name),
+                None => (false, condition),
+            };
+            let value = options
+                .condition(name)
+                .unwrap_or_else(|| panic!("unknown scaffold condition {}", name));
+            kept.push(value != negated);
+            continue;
+        }
+        if trimmed == "{{/if}}" {
+            kept.pop().expect("/if without #if in a scaffold template");
+            continue;
+        }
+        if kept.iter().all(|k| *k) {
+            substitute(line, options, &mut out);
+            out.push('\n');
+        }
+    }
+    assert!(kept.is_empty(), "unclosed #if in a scaffold template");
+    out
+}
+
+fn substitute(line: &str, options: &ScaffoldOptions, out: &mut String) {
+    let mut rest = line;
+    while let Some(start) = rest.find("{{") {
+        let end = rest[start..]
+            .find("}}")
+            .map(|e| start + e)
+            .expect("unclosed {{ in a scaffold template");
+        let name = &rest[start + 2..end];
+        let value = options
+            .variable(name)
+            .unwrap_or_else(|| panic!("unknown scaffold variable {}", name));
+        out.push_str(&rest[..start]);
+        out.push_str(&value);
+        rest = &rest[end + 2..];
+    }
+    out.push_str(rest);
+}
+
+/// A TOML basic string holding `path`.
+fn toml_string(path: &Path) -> String {
+    let mut out = String::from('"');
+    for c in path.to_string_lossy().chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
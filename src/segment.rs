@@ -0,0 +1,344 @@
+//! Splitting a long capture into segments at frame accurate boundaries.
+//!
+//! A `Segmenter` is given every captured frame in order, and decides which segment each one
+//! belongs to. Every frame is placed in exactly one segment, and the frame at a boundary is
+//! the first frame of the new segment. Durations are summed from the frame durations
+//! reported by the driver rather than measured with a clock, so segments do not drift from
+//! the frames they contain.
+//!
+//! A new segment is also started, whatever the policy, at the first frame after a format
+//! change or a discontinuity in the stream time, so that each segment holds a single format
+//! and a continuous run of frames. `SegmentedWriter` uses a `Segmenter` to write raw frame
+//! data into a file per segment. Recorders write through the `SegmentSink` trait, so that
+//! other writers, such as `crate::experimental::mov::SegmentedMovWriter` or
+//! `crate::dump::SegmentedDumpWriter`, can take its place.
+
+use crate::device::input::DecklinkAudioInputPacket;
+use crate::frame::DecklinkFrameBase;
+use crate::time::{DecklinkFrameTiming, DecklinkTime};
+use crate::timecode::{DecklinkTimecode, DecklinkTimecodeFormat};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// When to start a new segment.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+pub enum SegmentPolicy {
+    /// Start a new segment after this many frames.
+    EveryFrames(u64),
+    /// Start a new segment once the frames in the current one add up to this duration.
+    EveryDuration(Duration),
+}
+
+/// Why a segment was started.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SegmentReason {
+    /// The first segment of the capture.
+    Start,
+    /// The previous segment reached the length set by the policy.
+    Policy,
+    /// The input format changed.
+    FormatChanged,
+    /// The stream time of the frame did not follow on from the previous frame, so frames
+    /// were dropped or the stream was restarted.
+    Discontinuity,
+}
+
+/// The start of a new segment.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SegmentBoundary {
+    /// The index of the new segment, counting from zero.
+    pub index: u64,
+    /// The index of the first frame of the segment, counting from zero over the capture.
+    pub first_frame: u64,
+    pub reason: SegmentReason,
+}
+
+/// Assigns frames to segments.
+#[derive(Debug, Clone)]
+pub struct Segmenter {
+    policy: SegmentPolicy,
+    /// The next segment index, or zero before the first frame.
+    next_index: u64,
+    frames: u64,
+    frames_in_segment: u64,
+    /// The summed frame durations of the current segment, in the display mode timescale.
+    duration_in_segment: DecklinkTime,
+    format_changed: bool,
+    /// The stream time the next frame is expected at.
+    expected: Option<DecklinkTime>,
+}
+
+impl Segmenter {
+    pub fn new(policy: SegmentPolicy) -> Segmenter {
+        Segmenter {
+            policy,
+            next_index: 0,
+            frames: 0,
+            frames_in_segment: 0,
+            duration_in_segment: DecklinkTime::new(0, 0),
+            format_changed: false,
+            expected: None,
+        }
+    }
+
+    pub fn policy(&self) -> SegmentPolicy {
+        self.policy
+    }
+
+    /// The number of frames seen so far.
+    pub fn frame_count(&self) -> u64 {
+        self.frames
+    }
+
+    /// Note that the input format changed, so the next frame starts a new segment.
+    pub fn format_changed(&mut self) {
+        self.format_changed = true;
+    }
+
+    fn policy_reached(&self) -> bool {
+        match self.policy {
+            SegmentPolicy::EveryFrames(n) => self.frames_in_segment >= n.max(1),
+            SegmentPolicy::EveryDuration(d) => {
+                let elapsed = self.duration_in_segment;
+                elapsed.scale > 0
+                    && elapsed.value as i128 * 1_000_000_000
+                        >= d.as_nanos() as i128 * elapsed.scale as i128
+            }
+        }
+    }
+
+    fn is_discontinuous(&self, timing: Option<&DecklinkFrameTiming>) -> bool {
+        match (self.expected, timing) {
+            (Some(expected), Some(timing)) => match expected.rescale(timing.stream_time.scale) {
+                Some(expected) => (timing.stream_time.value - expected.value).abs() > 1,
+                None => false,
+            },
+            _ => false,
+        }
+    }
+
+    /// Place the next frame, with its timing if known. Returns the new segment if the frame
+    /// starts one.
+    ///
+    /// Without timing, durations cannot be summed or discontinuities seen, so only frame
+    /// counts and format changes start segments.
+    pub fn frame(&mut self, timing: Option<&DecklinkFrameTiming>) -> Option<SegmentBoundary> {
+        let reason = if self.next_index == 0 {
+            Some(SegmentReason::Start)
+        } else if self.format_changed {
+            Some(SegmentReason::FormatChanged)
+        } else if self.is_discontinuous(timing) {
+            Some(SegmentReason::Discontinuity)
+        } else if self.policy_reached() {
+            Some(SegmentReason::Policy)
+        } else {
+            None
+        };
+
+        let boundary = reason.map(|reason| {
+            let boundary = SegmentBoundary {
+                index: self.next_index,
+                first_frame: self.frames,
+                reason,
+            };
+            self.next_index += 1;
+            self.frames_in_segment = 0;
+            self.duration_in_segment = DecklinkTime::new(0, 0);
+            self.format_changed = false;
+            boundary
+        });
+
+        self.frames += 1;
+        self.frames_in_segment += 1;
+        if let Some(timing) = timing {
+            if self.duration_in_segment.scale == 0 {
+                self.duration_in_segment.scale = timing.duration.scale;
+            }
+            if let Some(duration) = timing.duration.rescale(self.duration_in_segment.scale) {
+                self.duration_in_segment.value += duration.value;
+            }
+            self.expected = Some(DecklinkTime::new(
+                timing.stream_time.value + timing.duration.value,
+                timing.stream_time.scale,
+            ));
+        } else {
+            self.expected = None;
+        }
+
+        boundary
+    }
+}
+
+/// A segment written by `SegmentedWriter`.
+#[derive(PartialEq, Eq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SegmentInfo {
+    pub boundary: SegmentBoundary,
+    pub path: PathBuf,
+    pub frame_count: u64,
+    pub byte_count: u64,
+}
+
+/// The path of a segment, from a pattern where `{index}` is replaced with the segment index
+/// and `{frame}` with the index of its first frame.
+pub(crate) fn segment_path(pattern: &str, boundary: &SegmentBoundary) -> PathBuf {
+    PathBuf::from(
+        pattern
+            .replace("{index}", &format!("{:05}", boundary.index))
+            .replace("{frame}", &boundary.first_frame.to_string()),
+    )
+}
+
+/// Where a recorder writes the frames, and any audio, of a segmented recording.
+pub trait SegmentSink {
+    /// Write a frame, starting a new segment first if it is at a boundary. Returns the
+    /// segment that was finished to make way for it, if any.
+    fn write_frame(
+        &mut self,
+        frame: &dyn DecklinkFrameBase,
+        timing: Option<&DecklinkFrameTiming>,
+    ) -> io::Result<Option<SegmentInfo>>;
+
+    /// Write the audio captured with the next frame. Sinks that store only video drop it.
+    fn write_audio(&mut self, _packet: DecklinkAudioInputPacket) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Set the timecode of the next frame. Sinks that do not store timecode ignore it.
+    fn set_timecode(&mut self, _timecode: Option<(DecklinkTimecodeFormat, DecklinkTimecode)>) {}
+
+    /// Note that the input format changed, so the next frame starts a new segment.
+    fn format_changed(&mut self);
+
+    /// The segments that have been finished so far.
+    fn finished_segments(&self) -> &[SegmentInfo];
+
+    /// Finish the last segment, returning every segment that was written.
+    fn finish(self: Box<Self>) -> io::Result<Vec<SegmentInfo>>;
+}
+
+/// Writes raw frame data into a file per segment.
+///
+/// File names are made from a pattern, where `{index}` is replaced with the segment index
+/// and `{frame}` with the index of its first frame. Frame buffers are written as they are,
+/// including any row padding, with no header.
+pub struct SegmentedWriter {
+    pattern: String,
+    segmenter: Segmenter,
+    current: Option<(SegmentInfo, BufWriter<File>)>,
+    finished: Vec<SegmentInfo>,
+}
+
+impl SegmentedWriter {
+    pub fn new(pattern: impl Into<String>, policy: SegmentPolicy) -> SegmentedWriter {
+        SegmentedWriter {
+            pattern: pattern.into(),
+            segmenter: Segmenter::new(policy),
+            current: None,
+            finished: Vec::new(),
+        }
+    }
+
+    /// The path of a segment.
+    pub fn path_for(&self, boundary: &SegmentBoundary) -> PathBuf {
+        segment_path(&self.pattern, boundary)
+    }
+
+    /// Note that the input format changed, so the next frame starts a new segment.
+    pub fn format_changed(&mut self) {
+        self.segmenter.format_changed();
+    }
+
+    /// The segments that have been finished so far.
+    pub fn finished_segments(&self) -> &[SegmentInfo] {
+        &self.finished
+    }
+
+    /// Write a frame, starting a new segment first if it is at a boundary. Returns the
+    /// segment that was finished to make way for it, if any.
+    pub fn write_frame<F: DecklinkFrameBase + ?Sized>(
+        &mut self,
+        frame: &F,
+        timing: Option<&DecklinkFrameTiming>,
+    ) -> io::Result<Option<SegmentInfo>> {
+        let bytes = frame
+            .bytes()
+            .map_err(|e| io::Error::other(format!("{:?}", e)))?;
+        let length = frame.row_bytes() * frame.height();
+        let data = bytes
+            .0
+            .get(..length)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "frame is truncated"))?;
+
+        let mut closed = None;
+        if let Some(boundary) = self.segmenter.frame(timing) {
+            closed = self.finish_segment()?;
+            let path = self.path_for(&boundary);
+            let file = BufWriter::new(File::create(&path)?);
+            self.current = Some((
+                SegmentInfo {
+                    boundary,
+                    path,
+                    frame_count: 0,
+                    byte_count: 0,
+                },
+                file,
+            ));
+        }
+
+        let (info, file) = self
+            .current
+            .as_mut()
+            .expect("the first frame always starts a segment");
+        file.write_all(data)?;
+        info.frame_count += 1;
+        info.byte_count += data.len() as u64;
+
+        Ok(closed)
+    }
+
+    /// Flush and close the current segment.
+    fn finish_segment(&mut self) -> io::Result<Option<SegmentInfo>> {
+        match self.current.take() {
+            Some((info, mut file)) => {
+                file.flush()?;
+                file.into_inner()?.sync_all()?;
+                self.finished.push(info.clone());
+                Ok(Some(info))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Finish the last segment, returning every segment that was written.
+    pub fn finish(mut self) -> io::Result<Vec<SegmentInfo>> {
+        self.finish_segment()?;
+        Ok(self.finished)
+    }
+}
+
+impl SegmentSink for SegmentedWriter {
+    fn write_frame(
+        &mut self,
+        frame: &dyn DecklinkFrameBase,
+        timing: Option<&DecklinkFrameTiming>,
+    ) -> io::Result<Option<SegmentInfo>> {
+        SegmentedWriter::write_frame(self, frame, timing)
+    }
+
+    fn format_changed(&mut self) {
+        SegmentedWriter::format_changed(self);
+    }
+
+    fn finished_segments(&self) -> &[SegmentInfo] {
+        SegmentedWriter::finished_segments(self)
+    }
+
+    fn finish(self: Box<Self>) -> io::Result<Vec<SegmentInfo>> {
+        SegmentedWriter::finish(*self)
+    }
+}
@@ -0,0 +1,539 @@
+//! Capturing an exact number of frames, or an exact length of stream time.
+//!
+//! Stopping a capture by hand once enough frames have arrived races with the callbacks in
+//! flight: the driver can deliver another frame between the count being reached and streams
+//! being stopped, and frames it had buffered are delivered while streams drain. A
+//! `CaptureSession` decides instead in the callback, which the driver makes one at a time,
+//! so the count is exact whatever the timing of the stop. Frames arriving after the limit,
+//! while streams stop and drain, are not passed on, and are counted in
+//! `ExactCapture::over_limit`.
+//!
+//! A `Limit::Frames(n)` capture passes on exactly `n` frames. A `Limit::Duration(d)` capture
+//! passes on every frame whose stream time starts before the first frame's stream time plus
+//! `d`. Taking the interval of a frame as running from just after its start to its end, the
+//! last frame passed on is the one whose interval contains the endpoint, so 10 seconds at 25
+//! frames per second is exactly 250 frames. A source that drops frames passes on fewer, and
+//! one whose frames have no stream time cannot be captured for a duration.
+//!
+//! Audio is trimmed to the same boundary, to the sample, by the packet times the driver
+//! gives: samples before the start of the first frame and after the end of the last are
+//! removed, from the packets of the callbacks up to the one carrying the last frame. Audio
+//! is not trimmed for frames without a stream time.
+//!
+//! A capture that ends before the limit, as when the signal is lost, reports how far short
+//! it fell in `ExactCapture::under_delivered`.
+//!
+//! ```no_run
+//! # use decklink::device::input::{DecklinkInputDevice, InputHandler};
+//! # use decklink::session::{CaptureSession, Limit};
+//! # use std::sync::Arc;
+//! # fn run(input: &mut DecklinkInputDevice, handler: Arc<dyn InputHandler>) {
+//! let capture = CaptureSession::new(input, handler)
+//!     .capture_exact(Limit::Frames(300))
+//!     .unwrap();
+//! assert!(capture.delivered == 300 || capture.under_delivered.is_some());
+//! # }
+//! ```
+
+use crate::device::input::{
+    ArrivalFlags, CallbackResult, CallbackReturnPolicy, CancellationToken,
+    DecklinkAudioInputPacket, DecklinkInputDevice, FrameArrival, FrameContext, InputFormatChange,
+    InputHandler,
+};
+use crate::frame::{DecklinkFrameBase, DecklinkFrameFlags};
+use crate::time::DecklinkTime;
+use crate::SdkError;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// The rate of captured audio, the only rate the SDK captures at.
+const AUDIO_SAMPLE_RATE: i64 = 48000;
+
+/// How much a `CaptureSession` captures.
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub enum Limit {
+    /// Exactly this many frames.
+    Frames(u64),
+    /// The frames starting within this much stream time of the first.
+    Duration(Duration),
+}
+
+/// Why a capture ended before its limit.
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub enum UnderDeliveryReason {
+    /// A frame arrived without an input source.
+    SignalLost,
+    /// No frame arrived for `CaptureSession::with_frame_timeout`.
+    TimedOut,
+    /// The capture was cancelled through its token.
+    Cancelled,
+    /// A `Limit::Duration` capture had a frame without a stream time to measure it by.
+    NoStreamTime,
+    /// The source dropped frames, so the stream time ran out before the count was reached.
+    FramesDropped,
+}
+
+/// How far short of its limit a capture fell.
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub struct UnderDelivery {
+    pub reason: UnderDeliveryReason,
+    /// The frames missing to reach the limit, or `None` for a `Limit::Duration` capture that
+    /// never learnt the frame duration.
+    pub missing_frames: Option<u64>,
+}
+
+/// What a `CaptureSession::capture_exact` passed on.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Default)]
+pub struct ExactCapture {
+    /// The frames passed on to the handler.
+    pub delivered: u64,
+    /// The context of the first and last frames passed on.
+    pub first: Option<FrameContext>,
+    pub last: Option<FrameContext>,
+    /// The frames that arrived after the limit, and were not passed on.
+    pub over_limit: u64,
+    /// The audio sample frames passed on, after trimming.
+    pub audio_sample_frames: u64,
+    /// The format changes passed on.
+    pub format_changes: u64,
+    /// Why and by how much the capture fell short of its limit, if it did.
+    pub under_delivered: Option<UnderDelivery>,
+}
+
+impl ExactCapture {
+    /// Whether the capture reached its limit.
+    pub fn is_complete(&self) -> bool {
+        self.under_delivered.is_none()
+    }
+}
+
+/// Runs captures that pass an exact number of frames, or an exact length of stream time, on
+/// to a handler.
+///
+/// Enable video, and audio if it is wanted, on the input before capturing. Each capture
+/// installs its own callback, starts streams and stops them again, and leaves the handler
+/// of the session installed as the callback of the input when it returns.
+pub struct CaptureSession<'a> {
+    input: &'a mut DecklinkInputDevice,
+    handler: Arc<dyn InputHandler>,
+    frame_timeout: Duration,
+    drain_timeout: Duration,
+    stop_on_signal_loss: bool,
+    cancel: Option<CancellationToken>,
+    return_policy: Option<CallbackReturnPolicy>,
+}
+
+impl<'a> CaptureSession<'a> {
+    /// A session capturing from `input` into `handler`. Captures end as under-delivered if
+    /// the signal is lost, or no frame arrives for two seconds.
+    pub fn new(
+        input: &'a mut DecklinkInputDevice,
+        handler: Arc<dyn InputHandler>,
+    ) -> CaptureSession<'a> {
+        CaptureSession {
+            input,
+            handler,
+            frame_timeout: Duration::from_secs(2),
+            drain_timeout: Duration::from_secs(1),
+            stop_on_signal_loss: true,
+            cancel: None,
+            return_policy: None,
+        }
+    }
+
+    /// End a capture as under-delivered when no frame has arrived for `timeout`.
+    pub fn with_frame_timeout(self, timeout: Duration) -> Self {
+        CaptureSession {
+            frame_timeout: timeout,
+            ..self
+        }
+    }
+
+    /// Whether a frame without an input source ends a capture. When it does not, frames
+    /// without a signal are passed on and counted like any other.
+    pub fn with_stop_on_signal_loss(self, stop: bool) -> Self {
+        CaptureSession {
+            stop_on_signal_loss: stop,
+            ..self
+        }
+    }
+
+    /// End a capture as under-delivered when `cancel` is cancelled.
+    pub fn with_cancel(self, cancel: CancellationToken) -> Self {
+        CaptureSession {
+            cancel: Some(cancel),
+            ..self
+        }
+    }
+
+    /// Set `policy` as the callback return policy of the input when a capture starts, deciding
+    /// what its callbacks return to the driver. It stays set after the capture. Without it, the
+    /// policy the input has is kept. See `CallbackReturnPolicy`.
+    pub fn with_return_policy(self, policy: CallbackReturnPolicy) -> Self {
+        CaptureSession {
+            return_policy: Some(policy),
+            ..self
+        }
+    }
+
+    /// Capture up to `limit`, passing exactly the frames within it on to the handler, and
+    /// stop streams.
+    pub fn capture_exact(&mut self, limit: Limit) -> Result<ExactCapture, SdkError> {
+        if limit == Limit::Frames(0) {
+            return Ok(ExactCapture::default());
+        }
+
+        let shared = Arc::new(LimiterShared {
+            primary: self.handler.clone(),
+            limit,
+            stop_on_signal_loss: self.stop_on_signal_loss,
+            state: Mutex::new(LimiterState::new()),
+            finished: Condvar::new(),
+        });
+        if let Some(policy) = self.return_policy {
+            self.input.set_callback_return_policy(policy);
+        }
+        self.input.set_handler(Some(Arc::new(LimiterCallback {
+            shared: shared.clone(),
+        })))?;
+        let capture = self.run(&shared);
+        let restored = self.input.set_handler(Some(self.handler.clone()));
+        let capture = capture?;
+        restored?;
+        Ok(capture)
+    }
+
+    fn run(&self, shared: &LimiterShared) -> Result<ExactCapture, SdkError> {
+        self.input.start_streams()?;
+        if let Some(reason) = shared.wait(self.frame_timeout, self.cancel.as_ref()) {
+            // Pass nothing more on, even what the drain delivers
+            let mut state = shared.state.lock().unwrap();
+            if !state.done {
+                state.done = true;
+                state.reason = Some(reason);
+            }
+        }
+        let drained = self.input.stop_streams_drained(self.drain_timeout, None);
+
+        drained?;
+        Ok(shared.state.lock().unwrap().report(shared.limit))
+    }
+}
+
+/// The stream time interval of a frame, in ticks of the scale of the first frame.
+#[derive(Copy, Clone)]
+struct Interval {
+    start: i64,
+    end: i64,
+}
+
+struct LimiterState {
+    delivered: u64,
+    first: Option<FrameContext>,
+    last: Option<FrameContext>,
+    over_limit: u64,
+    audio_sample_frames: u64,
+    format_changes: u64,
+    /// The scale of the stream times of the first frame, which the others are measured in.
+    scale: Option<i64>,
+    /// The start of the first frame, and the end of the last frame passed on.
+    start: Option<i64>,
+    end: Option<i64>,
+    /// The frame duration of the last frame, in `scale`.
+    frame_duration: Option<i64>,
+    /// For a `Limit::Duration` capture, the stream time it ends at, in `scale`.
+    endpoint: Option<i64>,
+    /// No more frames are passed on.
+    done: bool,
+    /// The last frame has been passed on, or the capture has ended without it.
+    finished: bool,
+    reason: Option<UnderDeliveryReason>,
+    last_frame_at: Instant,
+}
+
+impl LimiterState {
+    fn new() -> LimiterState {
+        LimiterState {
+            delivered: 0,
+            first: None,
+            last: None,
+            over_limit: 0,
+            audio_sample_frames: 0,
+            format_changes: 0,
+            scale: None,
+            start: None,
+            end: None,
+            frame_duration: None,
+            endpoint: None,
+            done: false,
+            finished: false,
+            reason: None,
+            last_frame_at: Instant::now(),
+        }
+    }
+
+    /// The interval of a frame with `arrival`'s timing, in the scale of the first frame.
+    fn interval(&mut self, arrival: &FrameArrival<'_>) -> Option<Interval> {
+        let timing = arrival.timing()?;
+        let scale = *self.scale.get_or_insert(timing.stream_time.scale);
+        let start = timing.stream_time.rescale(scale)?.value;
+        let duration = timing.duration.rescale(scale)?.value;
+        self.frame_duration = Some(duration);
+        Some(Interval {
+            start,
+            end: start + duration,
+        })
+    }
+
+    /// Convert a time in the scale of the first frame to audio sample frames.
+    fn samples(&self, ticks: i64) -> Option<i64> {
+        DecklinkTime::new(ticks, self.scale?)
+            .rescale(AUDIO_SAMPLE_RATE)
+            .map(|t| t.value)
+    }
+
+    /// Trim `packet` to the samples between the start of the first frame and, if the
+    /// capture ends with this callback, the end of the last.
+    fn trim(
+        &self,
+        packet: &DecklinkAudioInputPacket,
+        ends: bool,
+    ) -> Result<Option<DecklinkAudioInputPacket>, SdkError> {
+        let from = self.start.and_then(|start| self.samples(start));
+        let to = if ends {
+            self.end.and_then(|end| self.samples(end))
+        } else {
+            None
+        };
+        if from.is_none() && to.is_none() {
+            return Ok(None);
+        }
+
+        let time = packet.packet_time(AUDIO_SAMPLE_RATE)?;
+        let count = packet.sample_frame_count() as i64;
+        let first = from.map_or(0, |from| (from - time).clamp(0, count));
+        let last = to.map_or(count, |to| (to - time).clamp(first, count));
+        if (first, last) == (0, count) {
+            return Ok(None);
+        }
+
+        let bytes = packet.bytes()?;
+        let frame_bytes = bytes.len().checked_div(count as usize).unwrap_or(0);
+        Ok(Some(DecklinkAudioInputPacket::from_samples(
+            packet.sample_type(),
+            packet.channel_count(),
+            bytes[first as usize * frame_bytes..last as usize * frame_bytes].to_vec(),
+            DecklinkTime::new(time + first, AUDIO_SAMPLE_RATE),
+        )))
+    }
+
+    fn report(&self, limit: Limit) -> ExactCapture {
+        let missing_frames = match limit {
+            Limit::Frames(frames) => Some(frames.saturating_sub(self.delivered)),
+            Limit::Duration(_) => match (self.endpoint, self.end, self.frame_duration) {
+                (Some(endpoint), Some(end), Some(duration)) if duration > 0 => {
+                    Some(((endpoint - end).max(0) as u64).div_ceil(duration as u64))
+                }
+                _ => None,
+            },
+        };
+        let reached = missing_frames == Some(0);
+        ExactCapture {
+            delivered: self.delivered,
+            first: self.first,
+            last: self.last,
+            over_limit: self.over_limit,
+            audio_sample_frames: self.audio_sample_frames,
+            format_changes: self.format_changes,
+            under_delivered: if reached {
+                None
+            } else {
+                Some(UnderDelivery {
+                    reason: self.reason.unwrap_or(UnderDeliveryReason::TimedOut),
+                    missing_frames,
+                })
+            },
+        }
+    }
+}
+
+struct LimiterShared {
+    primary: Arc<dyn InputHandler>,
+    limit: Limit,
+    stop_on_signal_loss: bool,
+    state: Mutex<LimiterState>,
+    finished: Condvar,
+}
+
+impl LimiterShared {
+    /// Block until the capture has finished, returning why it ended early if no frame
+    /// arrived for `frame_timeout` or it was cancelled.
+    fn wait(
+        &self,
+        frame_timeout: Duration,
+        cancel: Option<&CancellationToken>,
+    ) -> Option<UnderDeliveryReason> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if state.finished {
+                return None;
+            }
+            if cancel.is_some_and(|c| c.is_cancelled()) {
+                return Some(UnderDeliveryReason::Cancelled);
+            }
+            let idle = state.last_frame_at.elapsed();
+            if idle >= frame_timeout {
+                return Some(UnderDeliveryReason::TimedOut);
+            }
+            // Wake up now and then to notice a cancellation
+            let wait = (frame_timeout - idle).min(Duration::from_millis(20));
+            state = self.finished.wait_timeout(state, wait).unwrap().0;
+        }
+    }
+
+    /// End the capture for `reason`, which is reported if it fell short of the limit.
+    fn end(&self, state: &mut LimiterState, reason: UnderDeliveryReason) {
+        state.done = true;
+        state.finished = true;
+        state.reason = Some(reason);
+        self.finished.notify_all();
+    }
+}
+
+/// What is passed on of a frame-arrived callback.
+enum Decision {
+    Drop,
+    /// Pass it on, with the audio trimmed, and whether it carries the last frame.
+    Pass(Option<DecklinkAudioInputPacket>, bool),
+}
+
+struct LimiterCallback {
+    shared: Arc<LimiterShared>,
+}
+
+impl LimiterCallback {
+    fn decide(&self, arrival: &FrameArrival<'_>) -> Decision {
+        let shared = &self.shared;
+        let mut state = shared.state.lock().unwrap();
+        if state.done {
+            if arrival.video_frame.is_some() {
+                state.over_limit += 1;
+            }
+            return Decision::Drop;
+        }
+
+        let frame = match arrival.video_frame {
+            Some(frame) => frame,
+            // A callback with audio alone, or a frame that was lost or dropped, is passed on
+            // once the capture has started
+            None if state.first.is_none() => return Decision::Drop,
+            None => return Decision::Pass(self.audio(&mut state, arrival, false), false),
+        };
+        if shared.stop_on_signal_loss
+            && frame
+                .flags()
+                .contains(DecklinkFrameFlags::HAS_NO_INPUT_SOURCE)
+        {
+            shared.end(&mut state, UnderDeliveryReason::SignalLost);
+            return Decision::Drop;
+        }
+        state.last_frame_at = Instant::now();
+
+        let interval = state.interval(arrival);
+        let last = match shared.limit {
+            Limit::Frames(frames) => state.delivered + 1 >= frames,
+            Limit::Duration(duration) => {
+                let Some(interval) = interval else {
+                    shared.end(&mut state, UnderDeliveryReason::NoStreamTime);
+                    return Decision::Drop;
+                };
+                let scale = state.scale.unwrap_or(1) as i128;
+                let endpoint = *state.endpoint.get_or_insert_with(|| {
+                    let ticks = (duration.as_nanos() as i128 * scale + 500_000_000) / 1_000_000_000;
+                    interval.start + ticks as i64
+                });
+                if interval.start >= endpoint {
+                    // A source that dropped frames skipped past the endpoint
+                    state.over_limit += 1;
+                    shared.end(&mut state, UnderDeliveryReason::FramesDropped);
+                    return Decision::Drop;
+                }
+                interval.end >= endpoint
+            }
+        };
+
+        if state.first.is_none() {
+            state.first = Some(arrival.context);
+            state.start = interval.map(|i| i.start);
+        }
+        state.last = Some(arrival.context);
+        state.delivered += 1;
+        if let Some(interval) = interval {
+            state.end = Some(interval.end);
+        }
+        if last {
+            state.done = true;
+        }
+        Decision::Pass(self.audio(&mut state, arrival, last), last)
+    }
+
+    /// The audio of `arrival`, trimmed if it needs to be, counting what is passed on.
+    fn audio(
+        &self,
+        state: &mut LimiterState,
+        arrival: &FrameArrival<'_>,
+        ends: bool,
+    ) -> Option<DecklinkAudioInputPacket> {
+        let packet = arrival.audio_packet?;
+        // A packet whose time cannot be read is passed on whole
+        let trimmed = state.trim(packet, ends).ok().flatten();
+        state.audio_sample_frames += trimmed.as_ref().unwrap_or(packet).sample_frame_count() as u64;
+        trimmed
+    }
+}
+
+impl InputHandler for LimiterCallback {
+    fn format_changed(&self, change: &InputFormatChange) {
+        {
+            let mut state = self.shared.state.lock().unwrap();
+            if state.done {
+                return;
+            }
+            state.format_changes += 1;
+        }
+        self.shared.primary.format_changed(change);
+    }
+
+    fn frame_arrived(&self, arrival: &FrameArrival<'_>) -> CallbackResult {
+        let (trimmed, last) = match self.decide(arrival) {
+            Decision::Drop => return CallbackResult::Ok,
+            Decision::Pass(trimmed, last) => (trimmed, last),
+        };
+        let mut passed = *arrival;
+        if let Some(packet) = &trimmed {
+            // Audio that was trimmed away entirely is not passed on
+            let kept = packet.sample_frame_count() > 0;
+            passed = passed.with_audio_packet(kept.then_some(packet));
+        }
+        let result = if passed.video_frame.is_some()
+            || passed.audio_packet.is_some()
+            || passed.flags.contains(ArrivalFlags::FRAME_LOST)
+        {
+            self.shared.primary.frame_arrived(&passed)
+        } else {
+            CallbackResult::Ok
+        };
+
+        // The capture finishes once the handler has had the last frame
+        if last {
+            let mut state = self.shared.state.lock().unwrap();
+            state.finished = true;
+            self.shared.finished.notify_all();
+        }
+        result
+    }
+
+    fn type_name(&self) -> &'static str {
+        self.shared.primary.type_name()
+    }
+}
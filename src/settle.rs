@@ -0,0 +1,406 @@
+//! Waiting for format detection to settle on a mode before using the input.
+//!
+//! A `DetectionSettle` follows an input from when it is enabled until its signal has been
+//! stable for long enough: waiting for a signal, then for the driver to detect its format,
+//! then for the input to be re-enabled in that format, then for a number of frames in a row
+//! with a signal. Format changes are decided by a `crate::format_detect::FormatDetector`, so
+//! the debounce and quirk rules apply as they do during capture.
+//!
+//! Each phase has its own timeout, and the settle can be cancelled at any point. Either way
+//! it fails, and `SettleConfig::on_abandon` says what the input is left in: re-enabled in the
+//! last mode that had a stable signal, or disabled. Each transition is recorded as a
+//! `SettleProgress`, with the time since the settle started.
+//!
+//! Like the format detector, the settle only counts and decides. It is given every format
+//! change and frame in order, with the time each happened, and returns a `SettleAction` when
+//! the input should be re-enabled or disabled, which the caller carries out. This is how
+//! `DecklinkInputDevice::wait_first_frame` waits for its first frame.
+
+use crate::device::input::{
+    DecklinkDetectedVideoInputFormatFlags, DecklinkVideoInputFormatChangedEvents,
+};
+use crate::display_mode::DecklinkDisplayModeId;
+use crate::experimental::quirks::QuirkPolicy;
+use crate::format_detect::{FormatDecision, FormatDetector};
+use std::time::{Duration, Instant};
+
+/// A phase of settling, which has its own timeout.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SettlePhase {
+    WaitingForSignal,
+    DetectingFormat,
+    Reconfiguring,
+    Stabilizing,
+}
+
+/// Why settling failed.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SettleFailure {
+    /// The phase lasted longer than its timeout.
+    Timeout(SettlePhase),
+    Cancelled,
+}
+
+#[derive(PartialEq, Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SettleState {
+    /// No frame with a signal has arrived since the input was enabled, or since the last
+    /// one that had a signal.
+    WaitingForSignal,
+    /// The driver reported a format change, which is waiting out the debounce.
+    DetectingFormat,
+    /// The input should be re-enabled in this mode, and has not been yet.
+    Reconfiguring {
+        mode: DecklinkDisplayModeId,
+        flags: DecklinkDetectedVideoInputFormatFlags,
+    },
+    /// Frames with a signal are arriving, `seen` in a row of the `needed`.
+    Stabilizing {
+        seen: u32,
+        needed: u32,
+    },
+    /// The signal has been stable in this mode for long enough. The mode is
+    /// `DecklinkDisplayModeId::Unknown` if the settle was not told the mode it started in,
+    /// and no format change was seen.
+    Settled {
+        mode: DecklinkDisplayModeId,
+        format: DecklinkDetectedVideoInputFormatFlags,
+    },
+    Failed {
+        reason: SettleFailure,
+    },
+}
+
+impl SettleState {
+    /// The phase, or `None` once settling has finished.
+    pub fn phase(&self) -> Option<SettlePhase> {
+        match self {
+            SettleState::WaitingForSignal => Some(SettlePhase::WaitingForSignal),
+            SettleState::DetectingFormat => Some(SettlePhase::DetectingFormat),
+            SettleState::Reconfiguring { .. } => Some(SettlePhase::Reconfiguring),
+            SettleState::Stabilizing { .. } => Some(SettlePhase::Stabilizing),
+            SettleState::Settled { .. } | SettleState::Failed { .. } => None,
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.phase().is_none()
+    }
+}
+
+/// How long each phase may last, or `None` for no limit.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Default)]
+pub struct SettleTimeouts {
+    pub waiting_for_signal: Option<Duration>,
+    pub detecting_format: Option<Duration>,
+    pub reconfiguring: Option<Duration>,
+    pub stabilizing: Option<Duration>,
+}
+
+impl SettleTimeouts {
+    pub fn for_phase(&self, phase: SettlePhase) -> Option<Duration> {
+        match phase {
+            SettlePhase::WaitingForSignal => self.waiting_for_signal,
+            SettlePhase::DetectingFormat => self.detecting_format,
+            SettlePhase::Reconfiguring => self.reconfiguring,
+            SettlePhase::Stabilizing => self.stabilizing,
+        }
+    }
+}
+
+/// What the input is left in when settling fails.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash, Default)]
+pub enum AbandonPolicy {
+    /// Enabled in the last mode that had a stable signal, or the mode it was enabled in
+    /// at the start if none has.
+    #[default]
+    KeepLastGood,
+    Disabled,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct SettleConfig {
+    /// The frames with a signal needed in a row to settle, before any format change.
+    pub stable_frames: u32,
+    /// The frames with a signal needed in a row to settle after a format change.
+    pub stable_frames_after_change: u32,
+    /// The debounce of the format detector, in frames.
+    pub debounce_frames: u32,
+    pub quirks: QuirkPolicy,
+    /// Whether the input is re-enabled on a format change, with `SettleAction::Reenable`.
+    /// Without, a decided change goes straight to stabilizing, as when the caller only
+    /// watches an input that something else re-enables.
+    pub reconfigure: bool,
+    pub timeouts: SettleTimeouts,
+    pub on_abandon: AbandonPolicy,
+}
+
+impl Default for SettleConfig {
+    fn default() -> Self {
+        SettleConfig {
+            stable_frames: 3,
+            stable_frames_after_change: 3,
+            debounce_frames: 0,
+            quirks: QuirkPolicy::BuiltIn,
+            reconfigure: true,
+            timeouts: SettleTimeouts::default(),
+            on_abandon: AbandonPolicy::KeepLastGood,
+        }
+    }
+}
+
+/// What the caller should do to the input.
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub enum SettleAction {
+    /// Re-enable the input in this mode, then call `DetectionSettle::reconfigured`.
+    Reenable {
+        mode: DecklinkDisplayModeId,
+        flags: DecklinkDetectedVideoInputFormatFlags,
+    },
+    /// Disable the input.
+    Disable,
+}
+
+/// A transition of a settle.
+#[derive(PartialEq, Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SettleProgress {
+    /// The time since the settle started.
+    pub elapsed: Duration,
+    /// The state entered.
+    pub state: SettleState,
+}
+
+type Format = (DecklinkDisplayModeId, DecklinkDetectedVideoInputFormatFlags);
+
+/// Follows an input until its format has settled.
+#[derive(Debug, Clone)]
+pub struct DetectionSettle {
+    config: SettleConfig,
+    detector: FormatDetector,
+    state: SettleState,
+    started: Instant,
+    phase_started: Instant,
+    /// The format the input is enabled in.
+    enabled: Format,
+    initial: Format,
+    /// The last format that settled.
+    last_good: Option<Format>,
+    /// The frames needed by the next stabilizing phase.
+    needed: u32,
+    progress: Vec<SettleProgress>,
+}
+
+impl DetectionSettle {
+    /// Start following an input that was enabled in `mode` at `now`. Pass
+    /// `DecklinkDisplayModeId::Unknown` if the mode is not known, in which case no format
+    /// change is taken to be a change back to it.
+    pub fn new(
+        config: SettleConfig,
+        mode: DecklinkDisplayModeId,
+        flags: DecklinkDetectedVideoInputFormatFlags,
+        now: Instant,
+    ) -> DetectionSettle {
+        let mut detector = FormatDetector::new(config.debounce_frames, &config.quirks);
+        if mode != DecklinkDisplayModeId::Unknown {
+            detector.enabled(mode, flags);
+        }
+        let needed = config.stable_frames;
+        DetectionSettle {
+            config,
+            detector,
+            state: SettleState::WaitingForSignal,
+            started: now,
+            phase_started: now,
+            enabled: (mode, flags),
+            initial: (mode, flags),
+            last_good: None,
+            needed,
+            progress: vec![SettleProgress {
+                elapsed: Duration::ZERO,
+                state: SettleState::WaitingForSignal,
+            }],
+        }
+    }
+
+    pub fn state(&self) -> SettleState {
+        self.state
+    }
+
+    /// The mode the input is enabled in, as far as the settle knows.
+    pub fn enabled_mode(&self) -> DecklinkDisplayModeId {
+        self.enabled.0
+    }
+
+    /// The transitions since the last call, including the initial state on the first.
+    pub fn take_progress(&mut self) -> Vec<SettleProgress> {
+        std::mem::take(&mut self.progress)
+    }
+
+    /// The detector deciding format changes, for the quirk rules that fired.
+    pub fn detector_mut(&mut self) -> &mut FormatDetector {
+        &mut self.detector
+    }
+
+    fn transition(&mut self, state: SettleState, now: Instant) {
+        if state.phase() != self.state.phase() {
+            self.phase_started = now;
+        }
+        self.state = state;
+        self.progress.push(SettleProgress {
+            elapsed: now.saturating_duration_since(self.started),
+            state,
+        });
+    }
+
+    /// Note a format change reported by the driver at `now`.
+    pub fn format_changed(
+        &mut self,
+        events: DecklinkVideoInputFormatChangedEvents,
+        mode: DecklinkDisplayModeId,
+        flags: DecklinkDetectedVideoInputFormatFlags,
+        now: Instant,
+    ) -> Option<SettleAction> {
+        if self.state.is_finished() {
+            return None;
+        }
+        if let Some(action) = self.poll(now) {
+            return Some(action);
+        }
+        let decision = self.detector.format_changed(events, mode, flags);
+        if self.state != SettleState::DetectingFormat {
+            self.transition(SettleState::DetectingFormat, now);
+        }
+        self.decided(decision, now)
+    }
+
+    /// Note a frame callback at `now`, and whether its frame had a signal.
+    pub fn frame(&mut self, has_signal: bool, now: Instant) -> Option<SettleAction> {
+        if self.state.is_finished() {
+            return None;
+        }
+        if let Some(action) = self.poll(now) {
+            return Some(action);
+        }
+        if let Some(decision) = self.detector.frame() {
+            return self.decided(Some(decision), now);
+        }
+
+        match self.state {
+            SettleState::WaitingForSignal if has_signal => self.stable_frame(0, now),
+            SettleState::Stabilizing { seen, .. } if has_signal => self.stable_frame(seen, now),
+            SettleState::Stabilizing { needed, .. } => {
+                // The count starts over once the signal returns
+                self.needed = needed;
+                self.transition(SettleState::WaitingForSignal, now);
+            }
+            _ => {}
+        }
+        None
+    }
+
+    /// Note that the input was re-enabled in `mode`, after `SettleAction::Reenable`.
+    pub fn reconfigured(
+        &mut self,
+        mode: DecklinkDisplayModeId,
+        flags: DecklinkDetectedVideoInputFormatFlags,
+        now: Instant,
+    ) {
+        self.enabled = (mode, flags);
+        self.detector.enabled(mode, flags);
+        if matches!(self.state, SettleState::Reconfiguring { .. }) {
+            self.stabilize(self.config.stable_frames_after_change, now);
+        }
+    }
+
+    /// Fail the settle if its phase has lasted longer than its timeout at `now`.
+    pub fn poll(&mut self, now: Instant) -> Option<SettleAction> {
+        let phase = self.state.phase()?;
+        let timeout = self.config.timeouts.for_phase(phase)?;
+        if now.saturating_duration_since(self.phase_started) > timeout {
+            self.fail(SettleFailure::Timeout(phase), now)
+        } else {
+            None
+        }
+    }
+
+    /// Cancel the settle at `now`, leaving the input as `SettleConfig::on_abandon` says.
+    pub fn cancel(&mut self, now: Instant) -> Option<SettleAction> {
+        if self.state.is_finished() {
+            return None;
+        }
+        self.fail(SettleFailure::Cancelled, now)
+    }
+
+    fn decided(&mut self, decision: Option<FormatDecision>, now: Instant) -> Option<SettleAction> {
+        match decision {
+            Some(decision) if self.config.reconfigure => {
+                self.transition(
+                    SettleState::Reconfiguring {
+                        mode: decision.mode,
+                        flags: decision.detected_flags,
+                    },
+                    now,
+                );
+                Some(SettleAction::Reenable {
+                    mode: decision.mode,
+                    flags: decision.detected_flags,
+                })
+            }
+            Some(decision) => {
+                self.enabled = (decision.mode, decision.detected_flags);
+                self.stabilize(self.config.stable_frames_after_change, now);
+                None
+            }
+            // A change back to the enabled mode, which the detector dropped
+            None if !self.detector.is_pending() => {
+                self.stabilize(self.config.stable_frames_after_change, now);
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn stabilize(&mut self, needed: u32, now: Instant) {
+        self.needed = needed;
+        if needed == 0 {
+            self.settle(now);
+        } else {
+            self.transition(SettleState::Stabilizing { seen: 0, needed }, now);
+        }
+    }
+
+    fn stable_frame(&mut self, seen: u32, now: Instant) {
+        let seen = seen + 1;
+        if seen >= self.needed {
+            self.settle(now);
+        } else {
+            let needed = self.needed;
+            self.transition(SettleState::Stabilizing { seen, needed }, now);
+        }
+    }
+
+    fn settle(&mut self, now: Instant) {
+        self.last_good = Some(self.enabled);
+        let (mode, format) = self.enabled;
+        self.transition(SettleState::Settled { mode, format }, now);
+    }
+
+    fn fail(&mut self, reason: SettleFailure, now: Instant) -> Option<SettleAction> {
+        self.transition(SettleState::Failed { reason }, now);
+        match self.config.on_abandon {
+            AbandonPolicy::Disabled => Some(SettleAction::Disable),
+            AbandonPolicy::KeepLastGood => {
+                let (mode, flags) = self.last_good.unwrap_or(self.initial);
+                if mode == self.enabled.0 || mode == DecklinkDisplayModeId::Unknown {
+                    None
+                } else {
+                    self.enabled = (mode, flags);
+                    Some(SettleAction::Reenable { mode, flags })
+                }
+            }
+        }
+    }
+}
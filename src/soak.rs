@@ -0,0 +1,1177 @@
+//! Long captures that check the invariants of the crate as they run, for regressions that
+//! only show after hours.
+//!
+//! A soak run captures with one of a few `SoakProfile`s, and every `SoakConfig::sample_interval`
+//! takes a `SoakSample` of the counters, retained frames, live objects, resident memory,
+//! latency and thread health. An `InvariantChecker` checks each sample against the ones
+//! before it:
+//!
+//! - the live objects stay within `SoakThresholds::live_object_slack` of the count after the
+//!   warmup, under the `leak-check` feature or on the mock backend;
+//! - the retention budget is respected, and never refuses a frame, as the run holds one frame
+//!   fewer than it allows;
+//! - the counters never go back, and frames arrive no faster than the display mode allows;
+//! - resident memory grows by no more than `SoakThresholds::rss_slope` an hour, on Linux;
+//! - the 99th percentile of the handler latency does not trend upward;
+//! - the event ring stays within its capacity;
+//! - no thread of the crate panics.
+//!
+//! The first violation ends the run with a `SoakFailure`, which has the violating sample, the
+//! one before it and the last events recorded. The run ends with a `SoakSummary`, whose
+//! `SoakSummary::to_json` suits scripts and CI jobs.
+//!
+//! `run_device` captures from a device in real time. With the `mock-backend` feature,
+//! `run_mock` captures from a mock device as fast as frames can be handled, and takes the
+//! duration as the stream time of the frames delivered, so a day runs in minutes. A mock run
+//! also perturbs the capture every `SoakConfig::perturb_every`, cycling through a format
+//! change, a loss of signal and the removal and return of the device, to exercise the paths
+//! that recover from each.
+//!
+//! When `SoakConfig::checkpoint` is set, the run appends a line of JSON to that file for its
+//! start, each sample and perturbation, a failure, and the summary, written as they happen,
+//! so a run that crashes still leaves its last sample behind.
+
+use crate::allocator;
+use crate::device::input::{
+    CallbackResult, DecklinkInputDevice, DecklinkVideoInputFlags, FrameArrival, InputFormatChange,
+    InputHandler,
+};
+use crate::device::DecklinkDevice;
+use crate::display_mode::DecklinkDisplayModeId;
+use crate::effective_config::{DecklinkEffectiveConfigExt, EffectiveConfig};
+use crate::event::{DecklinkEvent, DeviceIdentity, EventRecorder};
+use crate::frame::{DecklinkFrameBase, DecklinkFrameFlags, DecklinkPixelFormat};
+use crate::latency::LatencyMeter;
+use crate::manifest::write_json_string;
+use crate::probe::HeapAllocatorProvider;
+use crate::reference::DecklinkReferenceExt;
+use crate::retention::{RetainedFrame, RetentionBudget, RetentionLimit, RetentionMode};
+use crate::threads;
+use crate::{api_version, SdkError};
+use std::collections::VecDeque;
+use std::fmt;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// The frames of handler latency the percentiles of a sample are taken over.
+const LATENCY_WINDOW: usize = 1000;
+
+/// The most events a `SoakFailure` keeps from the event ring.
+const FAILURE_EVENTS: usize = 64;
+
+/// How a soak run captures.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash, Default)]
+pub enum SoakProfile {
+    /// Hold the last three frames, as a pipeline of a few stages would.
+    #[default]
+    Default,
+    /// Hold only the last frame, as a low latency application would.
+    LowLatency,
+    /// Hold the last three frames, captured into buffers from a custom allocator.
+    Allocator,
+}
+
+impl SoakProfile {
+    /// The name of the profile, as the `decklink soak --profile` option takes it.
+    pub fn name(&self) -> &'static str {
+        match self {
+            SoakProfile::Default => "default",
+            SoakProfile::LowLatency => "lowlatency",
+            SoakProfile::Allocator => "allocator",
+        }
+    }
+
+    /// The frames held past the callback.
+    pub fn held_frames(&self) -> usize {
+        match self {
+            SoakProfile::LowLatency => 1,
+            SoakProfile::Default | SoakProfile::Allocator => 3,
+        }
+    }
+}
+
+/// The limits an `InvariantChecker` holds samples to.
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub struct SoakThresholds {
+    /// How many more live objects than after the warmup are allowed, for objects that come
+    /// and go with each frame.
+    pub live_object_slack: usize,
+    /// The most frames a second, or `None` to not check the rate. The runs use one and a half
+    /// times the rate of the faster display mode when this is `None`.
+    pub max_frame_rate: Option<f64>,
+    /// The most resident memory may grow an hour, in bytes, as the slope of the samples of
+    /// the trend window.
+    pub rss_slope: u64,
+    /// The most the 99th percentile latency of the last quarter of the trend window may be, as
+    /// a multiple of that of the first quarter.
+    pub latency_growth: f64,
+    /// A latency below which growth is not a trend, as timer noise doubles a short one.
+    pub latency_floor: Duration,
+    /// The samples after the warmup that trends are taken over.
+    pub trend_window: usize,
+    /// The samples a trend needs before it is checked.
+    pub min_trend_samples: usize,
+}
+
+impl Default for SoakThresholds {
+    fn default() -> Self {
+        SoakThresholds {
+            live_object_slack: 32,
+            max_frame_rate: None,
+            rss_slope: 64 * 1024 * 1024,
+            latency_growth: 2.0,
+            latency_floor: Duration::from_millis(1),
+            trend_window: 360,
+            min_trend_samples: 30,
+        }
+    }
+}
+
+/// A change made to a mock capture during a soak run.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Perturbation {
+    /// The input detects the alternate display mode, and the capture is enabled again in it.
+    FormatChange,
+    /// Frames arrive without an input source for `SoakConfig::signal_loss`.
+    SignalLoss,
+    /// The device is removed and attached again, and the capture opened again.
+    DeviceRemoval,
+}
+
+impl Perturbation {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Perturbation::FormatChange => "format_change",
+            Perturbation::SignalLoss => "signal_loss",
+            Perturbation::DeviceRemoval => "device_removal",
+        }
+    }
+}
+
+/// The perturbations a mock run cycles through.
+pub const PERTURBATIONS: [Perturbation; 3] = [
+    Perturbation::FormatChange,
+    Perturbation::SignalLoss,
+    Perturbation::DeviceRemoval,
+];
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct SoakConfig {
+    /// How long to run for, in stream time on the mock backend.
+    pub duration: Duration,
+    pub profile: SoakProfile,
+    pub mode: DecklinkDisplayModeId,
+    pub pixel_format: DecklinkPixelFormat,
+    /// The display mode a format change switches to, and back from, on the mock backend.
+    pub alternate_mode: DecklinkDisplayModeId,
+    pub sample_interval: Duration,
+    /// How long the capture runs before the live objects are counted for the baseline and
+    /// trends are taken.
+    pub warmup: Duration,
+    pub thresholds: SoakThresholds,
+    /// How often to perturb a mock capture, or `None` for never. Captures from a device are
+    /// not perturbed.
+    pub perturb_every: Option<Duration>,
+    /// How long a signal loss lasts.
+    pub signal_loss: Duration,
+    /// A file to append the progress of the run to, as lines of JSON.
+    pub checkpoint: Option<PathBuf>,
+    /// The events kept for a failure report.
+    pub event_capacity: usize,
+}
+
+impl SoakConfig {
+    /// A run of `duration` with the default profile, in 1080p25 8-bit YUV.
+    pub fn new(duration: Duration) -> SoakConfig {
+        SoakConfig {
+            duration,
+            profile: SoakProfile::Default,
+            mode: DecklinkDisplayModeId::HD1080p25,
+            pixel_format: DecklinkPixelFormat::Format8BitYUV,
+            alternate_mode: DecklinkDisplayModeId::HD1080i50,
+            sample_interval: Duration::from_secs(10),
+            warmup: Duration::from_secs(60),
+            thresholds: SoakThresholds::default(),
+            perturb_every: None,
+            signal_loss: Duration::from_secs(1),
+            checkpoint: None,
+            event_capacity: 256,
+        }
+    }
+}
+
+/// The state of a soak run at one point.
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct SoakSample {
+    /// The position of the sample in the run, counting from zero.
+    pub index: u64,
+    /// The time since the run started, in stream time on the mock backend.
+    pub elapsed: Duration,
+    /// The frames with an input source that arrived.
+    pub frames: u64,
+    pub no_signal_frames: u64,
+    pub format_changes: u64,
+    /// The frames the retention budget refused.
+    pub refused_frames: u64,
+    /// `DecklinkInputDevice::suppressed_callback_count`, summed over the inputs of the run.
+    pub suppressed_callbacks: u64,
+    pub perturbations: u64,
+    /// The wrappers alive, under the `leak-check` feature, or the objects the mock backend
+    /// has handed out, or `None` when neither can be counted.
+    pub live_objects: Option<usize>,
+    pub retained_frames: usize,
+    /// The frames the retention budget allows.
+    pub retention_limit: usize,
+    /// The resident memory of the process, on Linux.
+    pub rss_bytes: Option<u64>,
+    /// The 99th percentile of the handler latency over the last frames.
+    pub latency_p99: Duration,
+    /// The events in the ring, and its capacity.
+    pub events_kept: usize,
+    pub event_capacity: usize,
+    /// `crate::threads::ThreadHealth::panicked`.
+    pub threads_panicked: u64,
+}
+
+impl SoakSample {
+    /// The counters that must never go back, by name.
+    pub fn counters(&self) -> [(&'static str, u64); 6] {
+        [
+            ("frames", self.frames),
+            ("no_signal_frames", self.no_signal_frames),
+            ("format_changes", self.format_changes),
+            ("refused_frames", self.refused_frames),
+            ("suppressed_callbacks", self.suppressed_callbacks),
+            ("perturbations", self.perturbations),
+        ]
+    }
+
+    pub fn to_json(&self) -> String {
+        let mut out = String::new();
+        self.write_json(&mut out);
+        out
+    }
+
+    fn write_json(&self, out: &mut String) {
+        let _ = write!(
+            out,
+            "{{\"index\":{},\"elapsed_ms\":{}",
+            self.index,
+            self.elapsed.as_millis()
+        );
+        for (name, value) in self.counters() {
+            let _ = write!(out, ",\"{}\":{}", name, value);
+        }
+        let _ = write!(
+            out,
+            ",\"live_objects\":{},\"retained_frames\":{},\"retention_limit\":{},\
+             \"rss_bytes\":{},\"latency_p99_us\":{},\"events_kept\":{},\"event_capacity\":{},\
+             \"threads_panicked\":{}}}",
+            json_opt(self.live_objects),
+            self.retained_frames,
+            self.retention_limit,
+            json_opt(self.rss_bytes),
+            self.latency_p99.as_micros(),
+            self.events_kept,
+            self.event_capacity,
+            self.threads_panicked
+        );
+    }
+}
+
+fn json_opt<T: fmt::Display>(value: Option<T>) -> String {
+    value.map_or_else(|| "null".to_string(), |v| v.to_string())
+}
+
+/// An invariant a sample broke.
+#[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum SoakViolation {
+    /// More objects are alive than after the warmup, by more than the slack.
+    LiveObjectsGrew { baseline: usize, live: usize },
+    /// More frames are retained than the budget allows.
+    RetentionExceeded { retained: usize, limit: usize },
+    /// The budget refused frames, though the run holds fewer than it allows, so it lost
+    /// count of frames that were released.
+    FramesRefused { refused: u64 },
+    /// A counter is lower than in the sample before.
+    CounterWentBack {
+        counter: String,
+        previous: u64,
+        current: u64,
+    },
+    /// Frames arrived faster than the display mode allows.
+    CounterTooFast {
+        counter: String,
+        per_second: f64,
+        limit: f64,
+    },
+    /// Resident memory grows by more than the threshold an hour.
+    MemoryGrowth { bytes_per_hour: f64, limit: u64 },
+    /// The 99th percentile latency of the last quarter of the trend window is more than the
+    /// allowed growth over that of the first.
+    LatencyTrend {
+        earlier_p99: Duration,
+        recent_p99: Duration,
+    },
+    /// The event ring holds more events than its capacity.
+    EventRingGrew { kept: usize, capacity: usize },
+    /// A thread of the crate panicked during the run.
+    ThreadDied { panicked: u64 },
+}
+
+impl SoakViolation {
+    /// The name of the invariant, as in the `kind` of the JSON of a failure.
+    pub fn kind_str(&self) -> &'static str {
+        match self {
+            SoakViolation::LiveObjectsGrew { .. } => "live_objects_grew",
+            SoakViolation::RetentionExceeded { .. } => "retention_exceeded",
+            SoakViolation::FramesRefused { .. } => "frames_refused",
+            SoakViolation::CounterWentBack { .. } => "counter_went_back",
+            SoakViolation::CounterTooFast { .. } => "counter_too_fast",
+            SoakViolation::MemoryGrowth { .. } => "memory_growth",
+            SoakViolation::LatencyTrend { .. } => "latency_trend",
+            SoakViolation::EventRingGrew { .. } => "event_ring_grew",
+            SoakViolation::ThreadDied { .. } => "thread_died",
+        }
+    }
+}
+
+impl fmt::Display for SoakViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SoakViolation::LiveObjectsGrew { baseline, live } => write!(
+                f,
+                "{} objects are alive, up from {} after the warmup",
+                live, baseline
+            ),
+            SoakViolation::RetentionExceeded { retained, limit } => write!(
+                f,
+                "{} frames are retained, over the budget of {}",
+                retained, limit
+            ),
+            SoakViolation::FramesRefused { refused } => write!(
+                f,
+                "the retention budget refused {} frames while it had room for them",
+                refused
+            ),
+            SoakViolation::CounterWentBack {
+                counter,
+                previous,
+                current,
+            } => write!(f, "{} went back from {} to {}", counter, previous, current),
+            SoakViolation::CounterTooFast {
+                counter,
+                per_second,
+                limit,
+            } => write!(
+                f,
+                "{} rose by {:.1} a second, over the limit of {:.1}",
+                counter, per_second, limit
+            ),
+            SoakViolation::MemoryGrowth {
+                bytes_per_hour,
+                limit,
+            } => write!(
+                f,
+                "resident memory grows by {:.0} bytes an hour, over the limit of {}",
+                bytes_per_hour, limit
+            ),
+            SoakViolation::LatencyTrend {
+                earlier_p99,
+                recent_p99,
+            } => write!(
+                f,
+                "the 99th percentile latency rose from {:?} to {:?}",
+                earlier_p99, recent_p99
+            ),
+            SoakViolation::EventRingGrew { kept, capacity } => write!(
+                f,
+                "the event ring holds {} events, over its capacity of {}",
+                kept, capacity
+            ),
+            SoakViolation::ThreadDied { panicked } => {
+                write!(f, "{} threads of the crate panicked", panicked)
+            }
+        }
+    }
+}
+
+/// What happened in a soak run, as recorded in its event ring.
+#[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SoakEvent {
+    Perturbed { perturbation: Perturbation },
+    Violated(SoakViolation),
+}
+
+/// Checks each sample of a run against those before it.
+pub struct InvariantChecker {
+    thresholds: SoakThresholds,
+    warmup: Duration,
+    first: Option<SoakSample>,
+    previous: Option<SoakSample>,
+    live_baseline: Option<usize>,
+    /// The samples after the warmup, for trends.
+    trend: VecDeque<SoakSample>,
+}
+
+impl InvariantChecker {
+    pub fn new(thresholds: SoakThresholds, warmup: Duration) -> InvariantChecker {
+        InvariantChecker {
+            thresholds,
+            warmup,
+            first: None,
+            previous: None,
+            live_baseline: None,
+            trend: VecDeque::new(),
+        }
+    }
+
+    /// Check `sample`, which follows the samples checked before.
+    pub fn check(&mut self, sample: &SoakSample) -> Result<(), SoakViolation> {
+        let result = self.check_sample(sample);
+        if self.first.is_none() {
+            self.first = Some(sample.clone());
+        }
+        self.previous = Some(sample.clone());
+        result
+    }
+
+    /// The live objects after the warmup, once it is over.
+    pub fn live_baseline(&self) -> Option<usize> {
+        self.live_baseline
+    }
+
+    fn check_sample(&mut self, sample: &SoakSample) -> Result<(), SoakViolation> {
+        // The first sample is the baseline, so threads that panicked before it are not counted
+        let panicked_before = self
+            .first
+            .as_ref()
+            .map_or(sample.threads_panicked, |s| s.threads_panicked);
+        if sample.threads_panicked > panicked_before {
+            return Err(SoakViolation::ThreadDied {
+                panicked: sample.threads_panicked - panicked_before,
+            });
+        }
+        if sample.retained_frames > sample.retention_limit {
+            return Err(SoakViolation::RetentionExceeded {
+                retained: sample.retained_frames,
+                limit: sample.retention_limit,
+            });
+        }
+        if sample.refused_frames > 0 {
+            return Err(SoakViolation::FramesRefused {
+                refused: sample.refused_frames,
+            });
+        }
+        if sample.events_kept > sample.event_capacity {
+            return Err(SoakViolation::EventRingGrew {
+                kept: sample.events_kept,
+                capacity: sample.event_capacity,
+            });
+        }
+
+        if let Some(previous) = &self.previous {
+            for ((counter, before), (_, now)) in
+                previous.counters().into_iter().zip(sample.counters())
+            {
+                if now < before {
+                    return Err(SoakViolation::CounterWentBack {
+                        counter: counter.to_string(),
+                        previous: before,
+                        current: now,
+                    });
+                }
+            }
+            let seconds = sample
+                .elapsed
+                .saturating_sub(previous.elapsed)
+                .as_secs_f64();
+            let limit = self.thresholds.max_frame_rate.filter(|_| seconds > 0.0);
+            if let Some(limit) = limit {
+                let arrived = (sample.frames + sample.no_signal_frames)
+                    - (previous.frames + previous.no_signal_frames);
+                let per_second = arrived as f64 / seconds;
+                if per_second > limit {
+                    return Err(SoakViolation::CounterTooFast {
+                        counter: "frames".to_string(),
+                        per_second,
+                        limit,
+                    });
+                }
+            }
+        }
+
+        if sample.elapsed < self.warmup {
+            return Ok(());
+        }
+        if let Some(live) = sample.live_objects {
+            let baseline = *self.live_baseline.get_or_insert(live);
+            if live > baseline + self.thresholds.live_object_slack {
+                return Err(SoakViolation::LiveObjectsGrew { baseline, live });
+            }
+        }
+
+        if self.trend.len() == self.thresholds.trend_window.max(1) {
+            self.trend.pop_front();
+        }
+        self.trend.push_back(sample.clone());
+        if self.trend.len() < self.thresholds.min_trend_samples.max(2) {
+            return Ok(());
+        }
+        self.check_memory()?;
+        self.check_latency()
+    }
+
+    /// The least squares slope of resident memory over the trend window.
+    fn check_memory(&self) -> Result<(), SoakViolation> {
+        let points: Vec<(f64, f64)> = self
+            .trend
+            .iter()
+            .filter_map(|s| Some((s.elapsed.as_secs_f64() / 3600.0, s.rss_bytes? as f64)))
+            .collect();
+        if points.len() < self.thresholds.min_trend_samples.max(2) {
+            return Ok(());
+        }
+        let n = points.len() as f64;
+        let mean_x = points.iter().map(|p| p.0).sum::<f64>() / n;
+        let mean_y = points.iter().map(|p| p.1).sum::<f64>() / n;
+        let (mut covariance, mut variance) = (0.0, 0.0);
+        for (x, y) in &points {
+            covariance += (x - mean_x) * (y - mean_y);
+            variance += (x - mean_x) * (x - mean_x);
+        }
+        if variance == 0.0 {
+            return Ok(());
+        }
+        let bytes_per_hour = covariance / variance;
+        if bytes_per_hour > self.thresholds.rss_slope as f64 {
+            return Err(SoakViolation::MemoryGrowth {
+                bytes_per_hour,
+                limit: self.thresholds.rss_slope,
+            });
+        }
+        Ok(())
+    }
+
+    /// The mean 99th percentile of the last quarter of the trend window against the first.
+    fn check_latency(&self) -> Result<(), SoakViolation> {
+        let quarter = (self.trend.len() / 4).max(1);
+        let mean = |samples: std::collections::vec_deque::Iter<'_, SoakSample>| {
+            samples.map(|s| s.latency_p99).sum::<Duration>() / quarter as u32
+        };
+        let earlier_p99 = mean(self.trend.range(..quarter));
+        let recent_p99 = mean(self.trend.range(self.trend.len() - quarter..));
+        if recent_p99 > self.thresholds.latency_floor
+            && recent_p99.as_secs_f64() > earlier_p99.as_secs_f64() * self.thresholds.latency_growth
+        {
+            return Err(SoakViolation::LatencyTrend {
+                earlier_p99,
+                recent_p99,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// The first invariant a run broke.
+#[derive(PartialEq, Debug, Clone)]
+pub struct SoakFailure {
+    pub violation: SoakViolation,
+    /// The sample that broke it.
+    pub sample: SoakSample,
+    /// The sample before, if any.
+    pub previous: Option<SoakSample>,
+    /// The last events of the run, oldest first, ending with the violation.
+    pub events: Vec<DecklinkEvent>,
+}
+
+impl SoakFailure {
+    pub fn to_json(&self) -> String {
+        let mut out = String::new();
+        self.write_json(&mut out);
+        out
+    }
+
+    fn write_json(&self, out: &mut String) {
+        let _ = write!(
+            out,
+            "{{\"kind\":\"{}\",\"message\":",
+            self.violation.kind_str()
+        );
+        write_json_string(out, &self.violation.to_string());
+        out.push_str(",\"sample\":");
+        self.sample.write_json(out);
+        out.push_str(",\"previous\":");
+        match &self.previous {
+            Some(previous) => previous.write_json(out),
+            None => out.push_str("null"),
+        }
+        out.push_str(",\"events\":[");
+        for (i, event) in self.events.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            let _ = write!(out, "{{\"sequence\":{},\"kind\":", event.sequence);
+            write_json_string(out, event.kind_str());
+            out.push_str(",\"detail\":");
+            write_json_string(out, &format!("{:?}", event.payload));
+            out.push('}');
+        }
+        out.push_str("]}");
+    }
+}
+
+impl fmt::Display for SoakFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "sample {} at {:?}: {}",
+            self.sample.index, self.sample.elapsed, self.violation
+        )
+    }
+}
+
+/// The result of a soak run.
+#[derive(PartialEq, Debug, Clone)]
+pub struct SoakSummary {
+    pub profile: SoakProfile,
+    /// Whether the run was on the mock backend, where `elapsed` is stream time.
+    pub mock: bool,
+    pub elapsed: Duration,
+    pub samples: u64,
+    pub frames: u64,
+    pub perturbations: u64,
+    pub peak_rss_bytes: Option<u64>,
+    pub peak_retained_frames: usize,
+    /// The 99th percentile of the handler latency at the last sample.
+    pub latency_p99: Duration,
+    pub failure: Option<SoakFailure>,
+    /// The configuration of the input when it was last opened.
+    pub effective_config: Option<EffectiveConfig>,
+}
+
+impl SoakSummary {
+    pub fn passed(&self) -> bool {
+        self.failure.is_none()
+    }
+
+    pub fn to_json(&self) -> String {
+        let mut out = String::new();
+        let _ = write!(
+            out,
+            "{{\"profile\":\"{}\",\"mock\":{},\"passed\":{},\"elapsed_ms\":{},\"samples\":{},\
+             \"frames\":{},\"perturbations\":{},\"peak_rss_bytes\":{},\
+             \"peak_retained_frames\":{},\"latency_p99_us\":{},\"failure\":",
+            self.profile.name(),
+            self.mock,
+            self.passed(),
+            self.elapsed.as_millis(),
+            self.samples,
+            self.frames,
+            self.perturbations,
+            json_opt(self.peak_rss_bytes),
+            self.peak_retained_frames,
+            self.latency_p99.as_micros()
+        );
+        match &self.failure {
+            Some(failure) => failure.write_json(&mut out),
+            None => out.push_str("null"),
+        }
+        out.push_str(",\"effective_config\":");
+        match &self.effective_config {
+            Some(config) => config.write_json(&mut out),
+            None => out.push_str("null"),
+        }
+        out.push('}');
+        out
+    }
+}
+
+#[derive(Debug)]
+pub enum SoakError {
+    /// The capture could not be set up or changed.
+    Sdk(SdkError),
+    /// The checkpoint file could not be written.
+    Io(io::Error),
+}
+
+impl From<SdkError> for SoakError {
+    fn from(e: SdkError) -> Self {
+        SoakError::Sdk(e)
+    }
+}
+
+impl From<io::Error> for SoakError {
+    fn from(e: io::Error) -> Self {
+        SoakError::Io(e)
+    }
+}
+
+impl fmt::Display for SoakError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SoakError::Sdk(e) => write!(f, "the device reported an error: {:?}", e),
+            SoakError::Io(e) => write!(f, "the checkpoint could not be written: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for SoakError {}
+
+/// Counts the frames of a run and holds the last few of them through the budget.
+struct SoakHandler {
+    budget: RetentionBudget,
+    held: usize,
+    frames: Mutex<VecDeque<RetainedFrame>>,
+    arrived: AtomicU64,
+    no_signal: AtomicU64,
+    format_changes: AtomicU64,
+    refused: AtomicU64,
+}
+
+impl SoakHandler {
+    fn new(profile: SoakProfile) -> SoakHandler {
+        let held = profile.held_frames();
+        SoakHandler {
+            budget: RetentionBudget::new(RetentionLimit::Frames(held + 1), RetentionMode::Strict),
+            held,
+            frames: Mutex::new(VecDeque::new()),
+            arrived: AtomicU64::new(0),
+            no_signal: AtomicU64::new(0),
+            format_changes: AtomicU64::new(0),
+            refused: AtomicU64::new(0),
+        }
+    }
+
+    /// Release the frames held, before the input that captured them goes away.
+    fn release(&self) {
+        self.frames.lock().unwrap().clear();
+    }
+}
+
+impl InputHandler for SoakHandler {
+    fn format_changed(&self, _change: &InputFormatChange) {
+        self.format_changes.fetch_add(1, Ordering::Relaxed);
+        self.release();
+    }
+
+    fn frame_arrived(&self, arrival: &FrameArrival<'_>) -> CallbackResult {
+        let Some(frame) = arrival.video_frame else {
+            return CallbackResult::Ok;
+        };
+        if frame
+            .flags()
+            .contains(DecklinkFrameFlags::HAS_NO_INPUT_SOURCE)
+        {
+            self.no_signal.fetch_add(1, Ordering::Relaxed);
+            return CallbackResult::Ok;
+        }
+        self.arrived.fetch_add(1, Ordering::Relaxed);
+
+        let mut frames = self.frames.lock().unwrap();
+        if frames.len() == self.held {
+            frames.pop_front();
+        }
+        match self.budget.retain(frame.retain()) {
+            Ok(retained) => frames.push_back(retained),
+            Err(_) => {
+                self.refused.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        CallbackResult::Ok
+    }
+}
+
+/// The state of a run, whatever it captures from.
+struct Run<'a> {
+    config: &'a SoakConfig,
+    mock: bool,
+    handler: Arc<SoakHandler>,
+    meter: LatencyMeter,
+    recorder: EventRecorder,
+    checker: InvariantChecker,
+    checkpoint: Option<File>,
+    previous: Option<SoakSample>,
+    samples: u64,
+    perturbations: u64,
+    /// The suppressed callbacks of the inputs closed before the current one.
+    suppressed_before: u64,
+    peak_rss_bytes: Option<u64>,
+    peak_retained_frames: usize,
+    latency_p99: Duration,
+    failure: Option<SoakFailure>,
+    effective_config: Option<EffectiveConfig>,
+}
+
+impl<'a> Run<'a> {
+    fn new(config: &'a SoakConfig, mock: bool) -> Result<Run<'a>, SoakError> {
+        let mut thresholds = config.thresholds;
+        if thresholds.max_frame_rate.is_none() {
+            let rate = frame_rate(config.mode).max(frame_rate(config.alternate_mode));
+            thresholds.max_frame_rate = Some(rate * 1.5);
+        }
+        let checkpoint = match &config.checkpoint {
+            Some(path) => Some(File::options().create(true).append(true).open(path)?),
+            None => None,
+        };
+        let mut run = Run {
+            config,
+            mock,
+            handler: Arc::new(SoakHandler::new(config.profile)),
+            meter: LatencyMeter::new(LATENCY_WINDOW),
+            recorder: EventRecorder::new(None, config.event_capacity),
+            checker: InvariantChecker::new(thresholds, config.warmup),
+            checkpoint,
+            previous: None,
+            samples: 0,
+            perturbations: 0,
+            suppressed_before: 0,
+            peak_rss_bytes: None,
+            peak_retained_frames: 0,
+            latency_p99: Duration::ZERO,
+            failure: None,
+            effective_config: None,
+        };
+        let mut line = String::new();
+        let _ = write!(
+            line,
+            "{{\"entry\":\"start\",\"profile\":\"{}\",\"mock\":{},\"duration_ms\":{},\
+             \"mode\":\"{:?}\",\"pixel_format\":\"{:?}\"}}",
+            config.profile.name(),
+            mock,
+            config.duration.as_millis(),
+            config.mode,
+            config.pixel_format
+        );
+        run.write_checkpoint(&line)?;
+        Ok(run)
+    }
+
+    fn write_checkpoint(&mut self, line: &str) -> Result<(), SoakError> {
+        if let Some(file) = &mut self.checkpoint {
+            // Unbuffered, so the line is on disk if the process dies after
+            writeln!(file, "{}", line)?;
+        }
+        Ok(())
+    }
+
+    /// Open the input of `device` and start capturing in `mode`.
+    fn open(
+        &mut self,
+        device: &DecklinkDevice,
+        mode: DecklinkDisplayModeId,
+    ) -> Result<DecklinkInputDevice, SoakError> {
+        let mut input = device.input().ok_or(SdkError::NOINTERFACE)?;
+        input.set_handler(Some(self.meter.measured(self.handler.clone())))?;
+        self.enable(&mut input, mode)?;
+        input.start_streams()?;
+
+        let mut config = input.effective_config();
+        config.device = Some(DeviceIdentity::of(device));
+        config.driver_version = api_version().ok();
+        self.effective_config = Some(config);
+        Ok(input)
+    }
+
+    fn enable(
+        &self,
+        input: &mut DecklinkInputDevice,
+        mode: DecklinkDisplayModeId,
+    ) -> Result<(), SdkError> {
+        let flags = DecklinkVideoInputFlags::empty();
+        match self.config.profile {
+            SoakProfile::Allocator => input.enable_video_input_with_allocator(
+                mode,
+                self.config.pixel_format,
+                flags,
+                Arc::new(HeapAllocatorProvider::default()),
+            ),
+            _ => input.enable_video_input(mode, self.config.pixel_format, flags),
+        }
+    }
+
+    /// Take a sample at `elapsed`, and check it. Returns whether the run should go on.
+    fn sample(
+        &mut self,
+        elapsed: Duration,
+        input: &DecklinkInputDevice,
+        live_objects: Option<usize>,
+    ) -> Result<bool, SoakError> {
+        self.recorder.record_all(self.meter.take_events(), None);
+        self.recorder.record_all(threads::take_events(), None);
+        self.recorder.record_all(allocator::take_events(), None);
+
+        let retention = self.handler.budget.stats();
+        let sample = SoakSample {
+            index: self.samples,
+            elapsed,
+            frames: self.handler.arrived.load(Ordering::Relaxed),
+            no_signal_frames: self.handler.no_signal.load(Ordering::Relaxed),
+            format_changes: self.handler.format_changes.load(Ordering::Relaxed),
+            refused_frames: self.handler.refused.load(Ordering::Relaxed),
+            suppressed_callbacks: self.suppressed_before + input.suppressed_callback_count(),
+            perturbations: self.perturbations,
+            live_objects,
+            retained_frames: retention.retained_frames,
+            retention_limit: self.handler.held + 1,
+            rss_bytes: sys::resident_bytes(),
+            latency_p99: self.meter.stats().total.p99,
+            events_kept: self.recorder.len(),
+            event_capacity: self.recorder.capacity(),
+            threads_panicked: threads::health().panicked,
+        };
+        self.samples += 1;
+        self.peak_rss_bytes = self.peak_rss_bytes.max(sample.rss_bytes);
+        self.peak_retained_frames = self.peak_retained_frames.max(retention.peak_frames);
+        self.latency_p99 = sample.latency_p99;
+
+        let mut line = String::from("{\"entry\":\"sample\",\"sample\":");
+        sample.write_json(&mut line);
+        line.push('}');
+        self.write_checkpoint(&line)?;
+
+        if let Err(violation) = self.checker.check(&sample) {
+            self.recorder
+                .record(SoakEvent::Violated(violation.clone()), None);
+            let failure = SoakFailure {
+                violation,
+                sample: sample.clone(),
+                previous: self.previous.take(),
+                events: self.recorder.recent(FAILURE_EVENTS),
+            };
+            let mut line = String::from("{\"entry\":\"failure\",\"failure\":");
+            failure.write_json(&mut line);
+            line.push('}');
+            self.write_checkpoint(&line)?;
+            self.failure = Some(failure);
+            return Ok(false);
+        }
+        self.previous = Some(sample);
+        Ok(true)
+    }
+
+    fn finish(mut self, elapsed: Duration) -> Result<SoakSummary, SoakError> {
+        self.handler.release();
+        let summary = SoakSummary {
+            profile: self.config.profile,
+            mock: self.mock,
+            elapsed,
+            samples: self.samples,
+            frames: self.handler.arrived.load(Ordering::Relaxed),
+            perturbations: self.perturbations,
+            peak_rss_bytes: self.peak_rss_bytes,
+            peak_retained_frames: self.peak_retained_frames,
+            latency_p99: self.latency_p99,
+            failure: self.failure.take(),
+            effective_config: self.effective_config.take(),
+        };
+        let line = format!(
+            "{{\"entry\":\"summary\",\"summary\":{}}}",
+            summary.to_json()
+        );
+        self.write_checkpoint(&line)?;
+        Ok(summary)
+    }
+}
+
+#[cfg(feature = "mock-backend")]
+impl Run<'_> {
+    fn perturbed(
+        &mut self,
+        elapsed: Duration,
+        perturbation: Perturbation,
+    ) -> Result<(), SoakError> {
+        self.perturbations += 1;
+        self.recorder
+            .record(SoakEvent::Perturbed { perturbation }, None);
+        let line = format!(
+            "{{\"entry\":\"perturbation\",\"kind\":\"{}\",\"elapsed_ms\":{}}}",
+            perturbation.name(),
+            elapsed.as_millis()
+        );
+        self.write_checkpoint(&line)
+    }
+}
+
+fn frame_rate(mode: DecklinkDisplayModeId) -> f64 {
+    let metadata = mode.metadata();
+    if metadata.frame_duration == 0 {
+        return 0.0;
+    }
+    metadata.time_scale as f64 / metadata.frame_duration as f64
+}
+
+#[cfg(feature = "mock-backend")]
+fn frame_duration(mode: DecklinkDisplayModeId) -> Duration {
+    let metadata = mode.metadata();
+    if metadata.time_scale == 0 {
+        return Duration::from_secs(1);
+    }
+    Duration::from_nanos((metadata.frame_duration * 1_000_000_000 / metadata.time_scale) as u64)
+}
+
+/// The live wrappers, under the `leak-check` feature.
+fn live_wrappers() -> Option<usize> {
+    #[cfg(feature = "leak-check")]
+    {
+        Some(crate::debug::live_objects().iter().map(|(_, n)| n).sum())
+    }
+    #[cfg(not(feature = "leak-check"))]
+    {
+        None
+    }
+}
+
+/// Capture from `device` in real time for `config.duration`, checking each sample.
+///
+/// The capture is not perturbed. Fails only if the capture cannot be set up or the checkpoint
+/// cannot be written: a broken invariant ends the run early, with the failure in the summary.
+pub fn run_device(device: &DecklinkDevice, config: &SoakConfig) -> Result<SoakSummary, SoakError> {
+    let mut run = Run::new(config, false)?;
+    let mut input = run.open(device, config.mode)?;
+    let started = Instant::now();
+    let interval = config.sample_interval.max(Duration::from_millis(1));
+    loop {
+        let elapsed = started.elapsed();
+        let Some(left) = config.duration.checked_sub(elapsed) else {
+            break;
+        };
+        std::thread::sleep(left.min(interval));
+        if !run.sample(started.elapsed(), &input, live_wrappers())? {
+            break;
+        }
+    }
+    let elapsed = started.elapsed();
+    input.stop_streams()?;
+    input.set_handler(None)?;
+    run.finish(elapsed)
+}
+
+/// Capture from a mock device, installed for the run, as fast as frames can be handled, for
+/// `config.duration` of stream time, perturbing the capture every `config.perturb_every`.
+///
+/// The mock lists one device with `config.mode` and `config.alternate_mode`, so waits for any
+/// other `crate::mock::MockBackend` to be dropped first.
+#[cfg(feature = "mock-backend")]
+#[cfg_attr(docsrs, doc(cfg(feature = "mock-backend")))]
+pub fn run_mock(config: &SoakConfig) -> Result<SoakSummary, SoakError> {
+    use crate::device::get_devices;
+    use crate::device::input::{
+        DecklinkDetectedVideoInputFormatFlags, DecklinkVideoInputFormatChangedEvents,
+    };
+    use crate::mock::{MockBackend, MockDevice, MockFrame};
+
+    let backend = MockBackend::install(vec![MockDevice::new("DeckLink Soak")
+        .modes(&[config.mode, config.alternate_mode])
+        .pixel_formats(&[config.pixel_format])]);
+    let mock = backend.input(0);
+    let live = || live_wrappers().or(Some(MockBackend::live_objects()));
+
+    let mut run = Run::new(config, true)?;
+    let mut device = get_devices()?.into_iter().next().ok_or(SdkError::FAIL)?;
+    let mut mode = config.mode;
+    let mut input = run.open(&device, mode)?;
+    let mut frame = MockFrame::for_mode(mode, config.pixel_format);
+    let mut duration = frame_duration(mode);
+
+    let interval = config.sample_interval.max(duration);
+    let mut elapsed = Duration::ZERO;
+    let mut next_sample = interval;
+    let mut next_perturbation = config.perturb_every;
+    let mut perturbations = PERTURBATIONS.iter().copied().cycle();
+    while elapsed < config.duration {
+        mock.deliver_frame(frame.clone());
+        elapsed += duration;
+
+        if next_perturbation.is_some_and(|at| elapsed >= at) {
+            let perturbation = perturbations.next().unwrap_or(Perturbation::FormatChange);
+            run.perturbed(elapsed, perturbation)?;
+            match perturbation {
+                Perturbation::FormatChange => {
+                    mode = if mode == config.mode {
+                        config.alternate_mode
+                    } else {
+                        config.mode
+                    };
+                    mock.deliver_format_change(
+                        DecklinkVideoInputFormatChangedEvents::DISPLAY_MODE_CHANGED,
+                        mode,
+                        DecklinkDetectedVideoInputFormatFlags::YCBCR_422,
+                    );
+                    input.stop_streams()?;
+                    input.disable_video_input()?;
+                    run.enable(&mut input, mode)?;
+                    input.start_streams()?;
+                    frame = MockFrame::for_mode(mode, config.pixel_format);
+                    duration = frame_duration(mode);
+                }
+                Perturbation::SignalLoss => {
+                    let lost = frame.clone().no_signal();
+                    let end = elapsed + config.signal_loss;
+                    while elapsed < end {
+                        mock.deliver_frame(lost.clone());
+                        elapsed += duration;
+                    }
+                }
+                Perturbation::DeviceRemoval => {
+                    run.suppressed_before += input.suppressed_callback_count();
+                    run.handler.release();
+                    drop(input);
+                    drop(device);
+                    backend.detach(0);
+                    if !get_devices()?.is_empty() {
+                        return Err(SdkError::FAIL.into());
+                    }
+                    backend.attach(0);
+                    device = get_devices()?.into_iter().next().ok_or(SdkError::FAIL)?;
+                    input = run.open(&device, mode)?;
+                }
+            }
+            next_perturbation = config.perturb_every.map(|every| elapsed + every);
+        }
+
+        if elapsed >= next_sample {
+            if !run.sample(elapsed, &input, live())? {
+                break;
+            }
+            while next_sample <= elapsed {
+                next_sample += interval;
+            }
+        }
+    }
+    input.stop_streams()?;
+    input.set_handler(None)?;
+    run.finish(elapsed)
+}
+
+#[cfg(target_os = "linux")]
+mod sys {
+    /// The resident memory of the process, from `VmRSS` in `/proc/self/status`.
+    pub(super) fn resident_bytes() -> Option<u64> {
+        let status = std::fs::read_to_string("/proc/self/status").ok()?;
+        let line = status.lines().find(|l| l.starts_with("VmRSS:"))?;
+        let kb: u64 = line["VmRSS:".len()..]
+            .trim()
+            .trim_end_matches("kB")
+            .trim()
+            .parse()
+            .ok()?;
+        Some(kb * 1024)
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod sys {
+    pub(super) fn resident_bytes() -> Option<u64> {
+        None
+    }
+}
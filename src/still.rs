@@ -0,0 +1,481 @@
+//! Still images of frames, exported with the colour of HDR and wide gamut sources kept.
+//!
+//! `crate::image_interop` converts frames into the signal values they hold, which is right
+//! for SDR Rec. 709 and 601. A PQ or HLG frame shown as if it were SDR looks washed out, and
+//! a Rec. 2020 frame shown with Rec. 709 primaries looks dull. Given the `SourceColor` of a
+//! frame, an `ExportColorPolicy` chooses between two ways of getting it right:
+//!
+//! * `ExportColorPolicy::ToneMap` converts the picture into an 8-bit SDR Rec. 709 PNG, which
+//!   any viewer shows as intended. HDR is mapped into SDR by a `ToneMapOperator`, and Rec.
+//!   2020 colours are converted into Rec. 709 ones, clipping those outside it. Like every
+//!   other image the crate exports, the PNG has no colour tags, leaving viewers to show it as
+//!   the SDR they assume.
+//! * `ExportColorPolicy::Hdr16` keeps the signal as it is, in a 16-bit PNG with a `cICP`
+//!   chunk giving its primaries and transfer function, which colour managed viewers follow.
+//!
+//! 10-bit sources are converted through `DecklinkFrameImageExt::to_rgb16_image_with`, so
+//! neither policy truncates them to 8 bits before tone mapping. The SDK does not give the HDR
+//! metadata of captured frames, so the transfer function of a source is not known from its
+//! frames, and is part of the `SourceColor` given. `encode_png` and `save_png` report how
+//! each still was made in a `StillExport`.
+//!
+//! Standard definition frames do not have square pixels. `encode_png_with_aspect` either
+//! tags their shape in a `pHYs` chunk, which viewers that follow it stretch the picture by,
+//! or resamples them across to square pixels, as `PixelAspectPolicy` says.
+//!
+//! # Tone mapping
+//!
+//! PQ is decoded into display light with the EOTF of ST 2084. HLG is decoded with the inverse
+//! OETF and the OOTF of BT.2100 for its 1000 cd/m² reference display. Light is taken relative
+//! to the HDR reference white of BT.2408, 203 cd/m², which 75% HLG and 58% PQ give, and each
+//! component is mapped by the operator to SDR light from 0 to 1. That is encoded for a BT.1886
+//! display, with a gamma of 2.4, and rounded to 8 bits. Grey at reference white and at 1000
+//! cd/m² comes out as:
+//!
+//! | Operator   | Reference white | 1000 cd/m² | Brighter   |
+//! |------------|-----------------|------------|------------|
+//! | `Clip`     | 255             | 255        | 255        |
+//! | `Reinhard` | 194             | 255        | 255        |
+//! | `Bt2408`   | 230             | 255        | 255        |
+//!
+//! `Clip` shows reference white as SDR white, and clips everything brighter. `Reinhard`
+//! compresses all of the light up to 1000 cd/m², keeping highlight detail but darkening the
+//! whole picture. `Bt2408` is the EETF of BT.2408 annex 5, mapping 0 to 1000 cd/m² onto 0 to
+//! 203 cd/m², which leaves light well below reference white as it is and rolls off above.
+
+use crate::colorimetry::{Colorimetry, TransferFunction};
+use crate::display_mode::Ratio;
+use crate::frame::DecklinkFrameBase;
+use crate::image_interop::{
+    conversion_path, ConversionPath, DecklinkFrameImageExt, ImageConversionError,
+};
+use crate::thumbnail::area_weights;
+use image::{ExtendedColorType, ImageEncoder, ImageError, RgbImage};
+use std::path::Path;
+
+/// The HDR reference white of BT.2408, in cd/m².
+const REFERENCE_WHITE: f64 = 203.0;
+/// The peak of the BT.2100 reference display, in cd/m², which HDR is mapped down from.
+const HDR_PEAK: f64 = 1000.0;
+/// The gamma of a BT.1886 display.
+const SDR_GAMMA: f64 = 2.4;
+
+/// Rec. 2020 linear light into Rec. 709, as in BT.2087 but to seven places, so that each row
+/// sums to one and greys stay grey.
+const REC2020_TO_REC709: [[f64; 3]; 3] = [
+    [1.6604910, -0.5876411, -0.0728499],
+    [-0.1245505, 1.1328999, -0.0083494],
+    [-0.0181508, -0.1005789, 1.1187297],
+];
+
+/// The colour a frame was made in.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+pub struct SourceColor {
+    pub colorimetry: Colorimetry,
+    pub transfer: TransferFunction,
+}
+
+impl SourceColor {
+    /// SDR in the usual colorimetry for a frame height.
+    pub fn sdr_for_height(height: usize) -> SourceColor {
+        SourceColor {
+            colorimetry: Colorimetry::for_height(height),
+            transfer: TransferFunction::Sdr,
+        }
+    }
+
+    /// BT.2100 PQ, with Rec. 2020 colorimetry.
+    pub fn pq() -> SourceColor {
+        SourceColor {
+            colorimetry: Colorimetry::Rec2020,
+            transfer: TransferFunction::Pq,
+        }
+    }
+
+    /// BT.2100 HLG, with Rec. 2020 colorimetry.
+    pub fn hlg() -> SourceColor {
+        SourceColor {
+            colorimetry: Colorimetry::Rec2020,
+            transfer: TransferFunction::Hlg,
+        }
+    }
+
+    fn is_hdr(&self) -> bool {
+        self.transfer != TransferFunction::Sdr
+    }
+}
+
+/// How HDR light is mapped into SDR. See the module documentation for each.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash, Default)]
+pub enum ToneMapOperator {
+    Clip,
+    Reinhard,
+    #[default]
+    Bt2408,
+}
+
+/// What an exported still is made of.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+pub enum ExportColorPolicy {
+    /// An 8-bit SDR Rec. 709 image, with HDR sources tone mapped by the operator.
+    ToneMap(ToneMapOperator),
+    /// A 16-bit image of the source signal, tagged with its colour.
+    Hdr16,
+}
+
+impl Default for ExportColorPolicy {
+    fn default() -> Self {
+        ExportColorPolicy::ToneMap(ToneMapOperator::default())
+    }
+}
+
+/// What is done with the shape of a frame's pixels when it is exported.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+pub enum PixelAspectPolicy {
+    /// Keep every pixel, and tag the image with a `pHYs` chunk giving the shape of its
+    /// pixels. Square pixels are not tagged.
+    Tag(Ratio),
+    /// Resample the picture across to square pixels, keeping its height.
+    Resample(Ratio),
+}
+
+impl Default for PixelAspectPolicy {
+    fn default() -> Self {
+        PixelAspectPolicy::Tag(Ratio::SQUARE)
+    }
+}
+
+/// How a still was exported, as `encode_png` and `save_png` report.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+pub struct StillExport {
+    /// How the frame was converted into RGB. 16-bit exports are never dithered, whatever
+    /// this says of 8-bit ones.
+    pub path: ConversionPath,
+    /// The operator HDR was tone mapped with, or `None` for an SDR source or a 16-bit export.
+    pub tone_mapped: Option<ToneMapOperator>,
+    /// Whether Rec. 2020 primaries were converted into Rec. 709 ones.
+    pub primaries_converted: bool,
+    /// The `cICP` chunk data the PNG is tagged with, or `None` for an untagged SDR export.
+    pub cicp: Option<[u8; 4]>,
+    /// The shape of the pixels of the image, which is square once resampled. The PNG is
+    /// tagged with it unless it is square.
+    pub pixel_aspect: Ratio,
+}
+
+#[derive(Debug)]
+pub enum StillError {
+    Conversion(ImageConversionError),
+    Encode(ImageError),
+    Io(std::io::Error),
+}
+
+impl From<ImageConversionError> for StillError {
+    fn from(e: ImageConversionError) -> Self {
+        StillError::Conversion(e)
+    }
+}
+
+impl std::fmt::Display for StillError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StillError::Conversion(e) => write!(f, "failed to convert the frame: {}", e),
+            StillError::Encode(e) => write!(f, "failed to encode the image: {}", e),
+            StillError::Io(e) => write!(f, "failed to write the image: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for StillError {}
+
+// The constants of ST 2084
+const PQ_M1: f64 = 2610.0 / 16384.0;
+const PQ_M2: f64 = 2523.0 / 4096.0 * 128.0;
+const PQ_C1: f64 = 3424.0 / 4096.0;
+const PQ_C2: f64 = 2413.0 / 4096.0 * 32.0;
+const PQ_C3: f64 = 2392.0 / 4096.0 * 32.0;
+
+/// The light of an ST 2084 signal, in cd/m².
+fn pq_eotf(signal: f64) -> f64 {
+    let p = signal.clamp(0.0, 1.0).powf(1.0 / PQ_M2);
+    10000.0 * ((p - PQ_C1).max(0.0) / (PQ_C2 - PQ_C3 * p)).powf(1.0 / PQ_M1)
+}
+
+/// The ST 2084 signal of light in cd/m².
+fn pq_inverse_eotf(light: f64) -> f64 {
+    let y = (light / 10000.0).clamp(0.0, 1.0).powf(PQ_M1);
+    ((PQ_C1 + PQ_C2 * y) / (1.0 + PQ_C3 * y)).powf(PQ_M2)
+}
+
+/// The scene light of an HLG signal, from 0 to 1.
+fn hlg_inverse_oetf(signal: f64) -> f64 {
+    const A: f64 = 0.17883277;
+    const B: f64 = 0.28466892;
+    const C: f64 = 0.55991073;
+    let signal = signal.clamp(0.0, 1.0);
+    if signal <= 0.5 {
+        signal * signal / 3.0
+    } else {
+        (((signal - C) / A).exp() + B) / 12.0
+    }
+}
+
+/// The EETF of BT.2408 annex 5, mapping light from 0 to `HDR_PEAK` onto 0 to
+/// `REFERENCE_WHITE`, both in cd/m². With no black level to lift, it has no toe.
+fn bt2408_eetf(light: f64) -> f64 {
+    let source_black = pq_inverse_eotf(0.0);
+    let range = pq_inverse_eotf(HDR_PEAK) - source_black;
+    let e1 = ((pq_inverse_eotf(light) - source_black) / range).clamp(0.0, 1.0);
+    let max_lum = (pq_inverse_eotf(REFERENCE_WHITE) - source_black) / range;
+    let knee = 1.5 * max_lum - 0.5;
+    let e2 = if e1 < knee {
+        e1
+    } else {
+        // A Hermite spline from the knee up to the target peak
+        let t = (e1 - knee) / (1.0 - knee);
+        let (t2, t3) = (t * t, t * t * t);
+        (2.0 * t3 - 3.0 * t2 + 1.0) * knee
+            + (t3 - 2.0 * t2 + t) * (1.0 - knee)
+            + (-2.0 * t3 + 3.0 * t2) * max_lum
+    };
+    pq_eotf(e2 * range + source_black)
+}
+
+impl ToneMapOperator {
+    /// Map light relative to reference white into SDR light from 0 to 1.
+    fn apply(&self, light: f64) -> f64 {
+        let light = light.max(0.0);
+        let mapped = match self {
+            ToneMapOperator::Clip => light,
+            ToneMapOperator::Reinhard => {
+                let white = HDR_PEAK / REFERENCE_WHITE;
+                light * (1.0 + light / (white * white)) / (1.0 + light)
+            }
+            ToneMapOperator::Bt2408 => bt2408_eetf(light * REFERENCE_WHITE) / REFERENCE_WHITE,
+        };
+        mapped.min(1.0)
+    }
+}
+
+/// Map a pixel of `source`, as full range signal from 0 to 1 such as `to_rgb16_image_with`
+/// gives, into SDR Rec. 709 signal from 0 to 1. HDR is tone mapped with `operator`, and SDR
+/// only has its primaries converted.
+pub fn tone_map(rgb: [f32; 3], source: SourceColor, operator: ToneMapOperator) -> [f32; 3] {
+    let signal = rgb.map(|c| c as f64);
+    // Light relative to reference white, or for SDR, to SDR white
+    let light = match source.transfer {
+        TransferFunction::Sdr => signal.map(|c| c.clamp(0.0, 1.0).powf(SDR_GAMMA)),
+        TransferFunction::Pq => signal.map(|c| pq_eotf(c) / REFERENCE_WHITE),
+        TransferFunction::Hlg => {
+            let scene = signal.map(hlg_inverse_oetf);
+            let (kr, kb) = source.colorimetry.coefficients();
+            let (kr, kb) = (kr as f64, kb as f64);
+            let luminance = kr * scene[0] + (1.0 - kr - kb) * scene[1] + kb * scene[2];
+            // The OOTF, with the system gamma of 1.2 for a 1000 cd/m² display
+            let gain = HDR_PEAK * luminance.powf(0.2) / REFERENCE_WHITE;
+            scene.map(|c| c * gain)
+        }
+    };
+    let light = if source.colorimetry == Colorimetry::Rec2020 {
+        REC2020_TO_REC709.map(|row| row[0] * light[0] + row[1] * light[1] + row[2] * light[2])
+    } else {
+        light
+    };
+    let mapped = if source.is_hdr() {
+        light.map(|c| operator.apply(c))
+    } else {
+        light.map(|c| c.clamp(0.0, 1.0))
+    };
+    mapped.map(|c| c.powf(1.0 / SDR_GAMMA) as f32)
+}
+
+/// Convert `frame` of `source` into an 8-bit SDR Rec. 709 image, tone mapping HDR with
+/// `operator`. SDR Rec. 601 and 709 frames are converted as `to_rgb_image_with` does.
+pub fn to_sdr_image<F: DecklinkFrameBase + ?Sized>(
+    frame: &F,
+    source: SourceColor,
+    operator: ToneMapOperator,
+) -> Result<RgbImage, ImageConversionError> {
+    if !source.is_hdr() && source.colorimetry != Colorimetry::Rec2020 {
+        return frame.to_rgb_image_with(source.colorimetry);
+    }
+    let wide = frame.to_rgb16_image_with(source.colorimetry)?;
+    let mut out = RgbImage::new(wide.width(), wide.height());
+    for (src, dst) in wide.pixels().zip(out.pixels_mut()) {
+        let rgb = tone_map(src.0.map(|c| c as f32 / 65535.0), source, operator);
+        dst.0 = rgb.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8);
+    }
+    Ok(out)
+}
+
+/// The `cICP` chunk data describing a 16-bit export of a frame of `source` `height` lines
+/// high: its primaries and transfer function, the identity matrix of RGB, and full range.
+pub fn cicp(source: SourceColor, height: usize) -> [u8; 4] {
+    let (primaries, _) = source.colorimetry.h273(height);
+    [primaries, source.transfer.h273(), 0, 1]
+}
+
+/// The CRC of PNG chunks.
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+/// Insert a chunk just after the header of `png`, ahead of the image data as `cICP` must be.
+fn insert_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    // The signature, then the header chunk of a length, type, 13 bytes of data and a CRC
+    let at = 8 + 4 + 4 + 13 + 4;
+    let mut chunk = Vec::with_capacity(12 + data.len());
+    chunk.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    chunk.extend_from_slice(kind);
+    chunk.extend_from_slice(data);
+    let crc = crc32(&chunk[4..]);
+    chunk.extend_from_slice(&crc.to_be_bytes());
+    png.splice(at..at, chunk);
+}
+
+/// Encode `frame` of `source` as a PNG, following `policy`, and report how it was exported.
+pub fn encode_png<F: DecklinkFrameBase + ?Sized>(
+    frame: &F,
+    source: SourceColor,
+    policy: ExportColorPolicy,
+) -> Result<(Vec<u8>, StillExport), StillError> {
+    encode_png_with_aspect(frame, source, policy, PixelAspectPolicy::default())
+}
+
+/// The `pHYs` chunk data giving the shape of pixels of `pixel_aspect`: the pixels per unit
+/// across and down, in no particular unit.
+fn phys(pixel_aspect: Ratio) -> [u8; 9] {
+    let Ratio { num, den } = pixel_aspect.reduced();
+    let mut data = [0; 9];
+    data[..4].copy_from_slice(&den.max(1).to_be_bytes());
+    data[4..8].copy_from_slice(&num.max(1).to_be_bytes());
+    data
+}
+
+/// Stretch the rows of an image of `channels` components per pixel from `width` pixels to
+/// `out_width`, by averaging the area each output pixel covers.
+fn resample_across<T: Copy + Into<f32>>(
+    raw: &[T],
+    width: usize,
+    channels: usize,
+    out_width: usize,
+    from_f32: impl Fn(f32) -> T,
+) -> Vec<T> {
+    let columns = area_weights(width, out_width);
+    let mut out = Vec::with_capacity(raw.len() / width.max(1) * out_width);
+    for row in raw.chunks_exact(width * channels) {
+        for weights in &columns {
+            for c in 0..channels {
+                let value: f32 = weights
+                    .iter()
+                    .map(|&(s, weight)| row[s * channels + c].into() * weight)
+                    .sum();
+                out.push(from_f32(value));
+            }
+        }
+    }
+    out
+}
+
+/// Encode `frame` of `source` as a PNG, following `policy` for its colour and `aspect` for
+/// the shape of its pixels, and report how it was exported.
+pub fn encode_png_with_aspect<F: DecklinkFrameBase + ?Sized>(
+    frame: &F,
+    source: SourceColor,
+    policy: ExportColorPolicy,
+    aspect: PixelAspectPolicy,
+) -> Result<(Vec<u8>, StillExport), StillError> {
+    let mut out = Vec::new();
+    let (pixel_aspect, resample_to) = match aspect {
+        PixelAspectPolicy::Tag(pixel_aspect) => (pixel_aspect, None),
+        PixelAspectPolicy::Resample(pixel_aspect) if pixel_aspect.reduced() == Ratio::SQUARE => {
+            (Ratio::SQUARE, None)
+        }
+        PixelAspectPolicy::Resample(pixel_aspect) => {
+            let width = (frame.width() as f64 * pixel_aspect.to_f64()).round() as usize;
+            (Ratio::SQUARE, Some(width.max(1)))
+        }
+    };
+    let mut export = StillExport {
+        path: conversion_path(frame.pixel_format())?,
+        tone_mapped: None,
+        primaries_converted: false,
+        cicp: None,
+        pixel_aspect,
+    };
+    match policy {
+        ExportColorPolicy::ToneMap(operator) => {
+            export.tone_mapped = source.is_hdr().then_some(operator);
+            export.primaries_converted = source.colorimetry == Colorimetry::Rec2020;
+            let image = to_sdr_image(frame, source, operator)?;
+            let (mut width, height) = image.dimensions();
+            let mut raw = image.into_raw();
+            if let Some(out_width) = resample_to {
+                raw = resample_across(&raw, width as usize, 3, out_width, |v| {
+                    v.round().clamp(0.0, 255.0) as u8
+                });
+                width = out_width as u32;
+            }
+            image::codecs::png::PngEncoder::new(&mut out)
+                .write_image(&raw, width, height, ExtendedColorType::Rgb8)
+                .map_err(StillError::Encode)?;
+        }
+        ExportColorPolicy::Hdr16 => {
+            let image = frame.to_rgb16_image_with(source.colorimetry)?;
+            let (mut width, height) = image.dimensions();
+            let mut raw = image.into_raw();
+            if let Some(out_width) = resample_to {
+                raw = resample_across(&raw, width as usize, 3, out_width, |v| {
+                    v.round().clamp(0.0, 65535.0) as u16
+                });
+                width = out_width as u32;
+            }
+            let bytes: Vec<u8> = raw.iter().flat_map(|c| c.to_ne_bytes()).collect();
+            image::codecs::png::PngEncoder::new(&mut out)
+                .write_image(&bytes, width, height, ExtendedColorType::Rgb16)
+                .map_err(StillError::Encode)?;
+            let tag = cicp(source, frame.height());
+            insert_chunk(&mut out, b"cICP", &tag);
+            export.cicp = Some(tag);
+        }
+    }
+    if pixel_aspect.reduced() != Ratio::SQUARE {
+        insert_chunk(&mut out, b"pHYs", &phys(pixel_aspect));
+    }
+    Ok((out, export))
+}
+
+/// Encode `frame` of `source` as a PNG following `policy`, write it to `path`, and report
+/// how it was exported.
+pub fn save_png<F: DecklinkFrameBase + ?Sized>(
+    frame: &F,
+    source: SourceColor,
+    policy: ExportColorPolicy,
+    path: impl AsRef<Path>,
+) -> Result<StillExport, StillError> {
+    save_png_with_aspect(frame, source, policy, PixelAspectPolicy::default(), path)
+}
+
+/// Encode `frame` of `source` as a PNG following `policy` and `aspect`, write it to `path`,
+/// and report how it was exported.
+pub fn save_png_with_aspect<F: DecklinkFrameBase + ?Sized>(
+    frame: &F,
+    source: SourceColor,
+    policy: ExportColorPolicy,
+    aspect: PixelAspectPolicy,
+    path: impl AsRef<Path>,
+) -> Result<StillExport, StillError> {
+    let (png, export) = encode_png_with_aspect(frame, source, policy, aspect)?;
+    std::fs::write(path, png).map_err(StillError::Io)?;
+    Ok(export)
+}
@@ -0,0 +1,904 @@
+//! Diverting copies of captured frames to a secondary consumer for a short while.
+//!
+//! A `TapSplitter` is installed as the input callback in place of the primary callback,
+//! which it passes every callback on to unchanged. `TapSplitter::tap` attaches a tap, which
+//! is given copies of the frames that arrive until its `TapSpec` is satisfied or it is
+//! cancelled, and is then detached. Taps run on their own threads, so a slow tap does not
+//! hold up the primary callback. It drops copies instead, counting them in
+//! `TapReport::dropped`.
+//!
+//! Copies are counted against a `RetentionBudget` for each tap, so several taps can run at
+//! once without holding more frame data than allowed. While no tap is attached, each frame
+//! costs two atomic operations, one to number it and one to find there are no taps.
+//!
+//! `TapSplitter::finish` tears every tap down together. It stops offering frames to all of
+//! the taps at once, so each of them is offered the same last frame, then waits for each
+//! tap to be given the frames queued for it and for `DeckLinkTapCallback::tap_finished` to
+//! return, so a tap writing a file can complete it. A tap that panics is detached on its
+//! own, and the panic is reported rather than passed on, so the other taps carry on.
+//!
+//! Each tap chooses with `TapSpec::format_change` what it is given while the input changes
+//! format. The transition lasts from the driver reporting the change until the application
+//! has enabled the input in the new format and calls `TapSplitter::format_applied`. Every
+//! tap is told of the change with `DeckLinkTapCallback::format_changed`, in order with its
+//! frames. A `CleanCut` tap is then given no more frames until the transition ends, so it
+//! never sees a frame of the old format after the change. A `DeliverThrough` tap is given
+//! the frames of the transition too, marked with `TappedFrame::transitional`. A
+//! `PauseUntilStable` tap waits after the transition for a number of frames in a row with
+//! a signal and the same layout. The policy of a tap can be changed through its handle.
+//!
+//! To end a capture, stop the streams and then finish the splitter. Frames that arrived
+//! before the streams stopped are still given to the taps while they drain. Frames that
+//! arrive after `finish`, if the streams are left running, go to the primary callback only.
+
+use crate::config::ConfigError;
+use crate::deinterlace::FrameLayout;
+use crate::device::input::{
+    CallbackResult, DecklinkDetectedVideoInputFormatFlags, DecklinkVideoInputFormatChangedEvents,
+    FrameArrival, InputFormatChange, InputHandler,
+};
+use crate::display_mode::DecklinkDisplayModeId;
+use crate::frame::{
+    DecklinkAlignedBytes, DecklinkFrameBase, DecklinkFrameFlags, DecklinkPixelFormat,
+    DecklinkVideoFrame, DecklinkVideoMutableFrame,
+};
+use crate::queue::{FrameQueue, OverflowPolicy, PopError, PushResult};
+use crate::retention::{RetentionBudget, RetentionCharge, RetentionLimit, RetentionMode};
+use crate::threads::{self, ThreadHandle};
+use crate::time::DecklinkFrameTiming;
+use crate::util::internal_thread_started;
+use crate::SdkError;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// The number of copies that can wait for a tap's thread. The retention limit of the tap
+/// usually bounds them first.
+const TAP_QUEUE_CAPACITY: usize = 64;
+
+/// When a tap detaches, and which frames it is given.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+pub struct TapSpec {
+    /// Detach after this many frames have been given to the tap.
+    pub frames: Option<u32>,
+    /// Detach once this long has passed since the tap was attached.
+    pub duration: Option<Duration>,
+    /// Give the tap one frame in every `decimation`. Zero is treated as one, but refused by
+    /// `validate`.
+    pub decimation: u32,
+    /// The most frame data the tap may hold at once, counted against the splitter's budget
+    /// as well, if it has one.
+    pub retention: RetentionLimit,
+    /// Which frames the tap is given while the input changes format.
+    pub format_change: FormatChangePolicy,
+}
+
+impl Default for TapSpec {
+    fn default() -> Self {
+        TapSpec {
+            frames: None,
+            duration: None,
+            decimation: 1,
+            retention: RetentionLimit::Frames(4),
+            format_change: FormatChangePolicy::DeliverThrough,
+        }
+    }
+}
+
+impl TapSpec {
+    /// A tap for the next `frames` frames.
+    pub fn frames(frames: u32) -> TapSpec {
+        TapSpec {
+            frames: Some(frames),
+            ..TapSpec::default()
+        }
+    }
+
+    pub fn builder() -> TapSpecBuilder {
+        TapSpecBuilder {
+            spec: TapSpec::default(),
+        }
+    }
+
+    /// Check that a tap with this spec could be given frames.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let error = |field, problem| Err(ConfigError::new("TapSpec", field, problem));
+        if self.frames == Some(0) {
+            return error(
+                "frames",
+                "must be at least 1, or None to tap until cancelled",
+            );
+        }
+        if self.duration.is_some_and(|duration| duration.is_zero()) {
+            return error(
+                "duration",
+                "must be longer than zero, or None to tap until cancelled",
+            );
+        }
+        if self.decimation == 0 {
+            return error("decimation", "must be at least 1, which gives every frame");
+        }
+        match self.retention {
+            RetentionLimit::Frames(0) => {
+                return error("retention", "must allow at least 1 frame");
+            }
+            RetentionLimit::Bytes(0) => {
+                return error("retention", "must allow more than 0 bytes");
+            }
+            _ => {}
+        }
+        if self.format_change == (FormatChangePolicy::PauseUntilStable { frames: 0 }) {
+            return error(
+                "format_change",
+                "must wait for at least 1 stable frame, or be CleanCut to wait for none",
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Builds a `TapSpec`, starting from the default.
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub struct TapSpecBuilder {
+    spec: TapSpec,
+}
+
+impl TapSpecBuilder {
+    pub fn frames(mut self, frames: u32) -> Self {
+        self.spec.frames = Some(frames);
+        self
+    }
+
+    pub fn duration(mut self, duration: Duration) -> Self {
+        self.spec.duration = Some(duration);
+        self
+    }
+
+    pub fn decimation(mut self, decimation: u32) -> Self {
+        self.spec.decimation = decimation;
+        self
+    }
+
+    pub fn retention(mut self, retention: RetentionLimit) -> Self {
+        self.spec.retention = retention;
+        self
+    }
+
+    pub fn format_change(mut self, format_change: FormatChangePolicy) -> Self {
+        self.spec.format_change = format_change;
+        self
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        self.spec.validate()
+    }
+
+    pub fn build(self) -> Result<TapSpec, ConfigError> {
+        self.validate()?;
+        Ok(self.spec)
+    }
+}
+
+/// Which frames a tap is given while the input changes format. See the module
+/// documentation.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+pub enum FormatChangePolicy {
+    /// Give no frames from the format change until the transition ends.
+    CleanCut,
+    /// Give every frame, marking those of the transition as transitional.
+    DeliverThrough,
+    /// Give no frames from the format change until the transition has ended and `frames`
+    /// frames in a row have arrived with a signal and the same layout. The last of them is
+    /// the first given.
+    PauseUntilStable { frames: u32 },
+}
+
+/// A format change, as a tap is told of it.
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub struct FormatChange {
+    pub events: DecklinkVideoInputFormatChangedEvents,
+    pub display_mode: DecklinkDisplayModeId,
+    pub detected_signal_flags: DecklinkDetectedVideoInputFormatFlags,
+    /// The `TappedFrame::sequence` the next frame to arrive will have.
+    pub sequence: u64,
+}
+
+/// A transition between formats, from the driver reporting a change until
+/// `TapSplitter::format_applied`.
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub struct FormatTransition {
+    /// The mode the input changed to, the last one reported if it changed more than once.
+    pub display_mode: DecklinkDisplayModeId,
+    /// The sequence number of the first frame to arrive after the change.
+    pub first_sequence: u64,
+    /// The frames that arrived during the transition.
+    pub frames: u64,
+    pub duration: Duration,
+}
+
+/// Why a tap detached.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+pub enum TapEnd {
+    /// The tap was given `TapSpec::frames` frames.
+    FramesReached,
+    /// `TapSpec::duration` passed.
+    DurationReached,
+    /// The tap was cancelled through its handle.
+    Cancelled,
+    /// The splitter was dropped.
+    Closed,
+    /// The splitter was finished with `TapSplitter::finish`.
+    Finished,
+    /// The tap panicked, and the frames still queued for it were discarded.
+    Panicked,
+}
+
+/// What a tap was given, reported when it detaches.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+pub struct TapReport {
+    pub end: TapEnd,
+    /// The frames given to the tap.
+    pub delivered: u64,
+    /// Frames that were due to the tap but not given to it, because its retention limit was
+    /// reached, its queue was full, or the frame could not be copied.
+    pub dropped: u64,
+    /// The `TappedFrame::sequence` of the last frame given to the tap.
+    pub last_sequence: Option<u64>,
+}
+
+/// A copy of a captured frame, given to a tap.
+pub struct TappedFrame {
+    frame: DecklinkVideoMutableFrame,
+    /// The timing of the frame, if the display mode timescale was known.
+    pub timing: Option<DecklinkFrameTiming>,
+    /// The index of the frame among the frames that arrived since the tap was attached,
+    /// counting from zero, so that frames skipped by decimation or dropped are seen as gaps.
+    pub index: u64,
+    /// The number of the frame among the frames that arrived at the splitter, counting from
+    /// zero, which is the same for every tap given the frame.
+    pub sequence: u64,
+    /// When the frame arrived in the driver callback.
+    pub arrived: Instant,
+    /// Whether the frame arrived during a format transition. Only taps with
+    /// `FormatChangePolicy::DeliverThrough` are given such frames.
+    pub transitional: bool,
+    _charge: RetentionCharge,
+}
+
+impl TappedFrame {
+    pub fn frame(&self) -> &DecklinkVideoMutableFrame {
+        &self.frame
+    }
+}
+
+impl DecklinkFrameBase for TappedFrame {
+    fn width(&self) -> usize {
+        self.frame.width()
+    }
+    fn height(&self) -> usize {
+        self.frame.height()
+    }
+    fn row_bytes(&self) -> usize {
+        self.frame.row_bytes()
+    }
+    fn pixel_format(&self) -> DecklinkPixelFormat {
+        self.frame.pixel_format()
+    }
+    fn flags(&self) -> DecklinkFrameFlags {
+        self.frame.flags()
+    }
+    fn bytes(&self) -> Result<DecklinkAlignedBytes<'_>, SdkError> {
+        self.frame.bytes()
+    }
+}
+
+/// Receives the frames of a tap, on the tap's own thread.
+pub trait DeckLinkTapCallback: Send {
+    /// Called with each frame given to the tap, in the order they arrived.
+    fn frame_tapped(&mut self, frame: TappedFrame);
+
+    /// Called when the input changes format, in order with the frames, whatever the
+    /// tap's `FormatChangePolicy`.
+    fn format_changed(&mut self, _change: FormatChange) {}
+
+    /// Called once the tap has detached, after its last frame.
+    fn tap_finished(&mut self, _report: TapReport) {}
+}
+
+/// What is queued for a tap's thread.
+enum TapItem {
+    Frame(TappedFrame),
+    FormatChanged(FormatChange),
+}
+
+struct TapShared {
+    queue: FrameQueue<TapItem>,
+    format_change: Mutex<FormatChangePolicy>,
+    end: Mutex<Option<TapEnd>>,
+    delivered: AtomicU64,
+    dropped: AtomicU64,
+    /// One more than the sequence number of the last frame given to the tap, or zero.
+    last_sequence: AtomicU64,
+    panicked: AtomicBool,
+    /// Set once the tap's thread has delivered its last frame and finished the consumer.
+    done: Mutex<bool>,
+    done_changed: Condvar,
+}
+
+impl TapShared {
+    /// Detach the tap, unless it already has. Frames already queued are still delivered.
+    fn finish(&self, end: TapEnd) {
+        let mut current = self.end.lock().unwrap();
+        if current.is_none() {
+            *current = Some(end);
+            self.queue.close();
+        }
+    }
+
+    fn is_finished(&self) -> bool {
+        self.end.lock().unwrap().is_some()
+    }
+
+    fn report(&self) -> Option<TapReport> {
+        let end = (*self.end.lock().unwrap())?;
+        Some(self.report_with(end))
+    }
+
+    fn report_with(&self, end: TapEnd) -> TapReport {
+        TapReport {
+            end,
+            delivered: self.delivered.load(Ordering::Acquire),
+            dropped: self.dropped.load(Ordering::Acquire),
+            last_sequence: self.last_sequence.load(Ordering::Acquire).checked_sub(1),
+        }
+    }
+
+    /// Detach the tap after its consumer panicked, discarding the frames still queued.
+    fn consumer_panicked(&self) {
+        self.panicked.store(true, Ordering::Release);
+        // A panic is reported even if the tap was already finishing
+        *self.end.lock().unwrap() = Some(TapEnd::Panicked);
+        self.queue.close();
+        // Discard what is still queued, giving its retention back
+        while self.queue.try_pop().is_some() {}
+    }
+
+    fn set_done(&self) {
+        *self.done.lock().unwrap() = true;
+        self.done_changed.notify_all();
+    }
+
+    /// Wait up to `timeout` for the tap's thread to finish. Returns whether it did.
+    fn wait_done(&self, timeout: Duration) -> bool {
+        let done = self.done.lock().unwrap();
+        let (done, _) = self
+            .done_changed
+            .wait_timeout_while(done, timeout, |done| !*done)
+            .unwrap();
+        *done
+    }
+}
+
+/// A tap as the splitter sees it.
+struct ActiveTap {
+    spec: TapSpec,
+    attached: Instant,
+    budget: RetentionBudget,
+    /// Frames seen since the tap was attached, for decimation.
+    seen: u64,
+    /// A format change the tap's queue had no room for, queued before its next frame.
+    pending_change: Option<FormatChange>,
+    /// Frames in a row with a signal and the same layout since the last format change, for
+    /// `FormatChangePolicy::PauseUntilStable`, or `None` once the tap has resumed.
+    settling: Option<(Option<FrameLayout>, u32)>,
+    shared: Arc<TapShared>,
+}
+
+impl ActiveTap {
+    /// Queue a format change for the tap.
+    fn format_changed(&mut self, change: FormatChange) {
+        self.pending_change = Some(change);
+        self.settling = Some((None, 0));
+        self.queue_pending_change();
+    }
+
+    /// Queue the pending format change, if any. Returns whether none is left pending.
+    fn queue_pending_change(&mut self) -> bool {
+        if let Some(change) = self.pending_change.take() {
+            if let PushResult::Rejected(TapItem::FormatChanged(change)) =
+                self.shared.queue.push(TapItem::FormatChanged(change))
+            {
+                self.pending_change = Some(change);
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Whether the tap's `FormatChangePolicy` lets it be given `frame`.
+    fn admits(&mut self, frame: &DecklinkVideoFrame, transitional: bool) -> bool {
+        let policy = *self.shared.format_change.lock().unwrap();
+        match policy {
+            FormatChangePolicy::DeliverThrough => true,
+            FormatChangePolicy::CleanCut => !transitional,
+            FormatChangePolicy::PauseUntilStable { frames } => {
+                let (layout, count) = match &mut self.settling {
+                    None => return true,
+                    Some(_) if transitional => return false,
+                    Some(settling) => settling,
+                };
+                let signal = !frame
+                    .flags()
+                    .contains(DecklinkFrameFlags::HAS_NO_INPUT_SOURCE);
+                let current = FrameLayout::of(frame);
+                if !signal {
+                    *count = 0;
+                } else if *layout == Some(current) {
+                    *count += 1;
+                } else {
+                    *layout = Some(current);
+                    *count = 1;
+                }
+                if *count < frames.max(1) {
+                    return false;
+                }
+                self.settling = None;
+                true
+            }
+        }
+    }
+
+    /// Offer a frame to the tap. Returns false once the tap has detached.
+    fn offer(
+        &mut self,
+        frame: &DecklinkVideoFrame,
+        timing: Option<DecklinkFrameTiming>,
+        sequence: u64,
+        transitional: bool,
+    ) -> bool {
+        if self.shared.is_finished() {
+            return false;
+        }
+        if let Some(duration) = self.spec.duration {
+            if self.attached.elapsed() >= duration {
+                self.shared.finish(TapEnd::DurationReached);
+                return false;
+            }
+        }
+
+        let index = self.seen;
+        self.seen += 1;
+        if !self.admits(frame, transitional) {
+            return true;
+        }
+        if !self.queue_pending_change() {
+            // The change must reach the tap before any frame after it
+            self.shared.dropped.fetch_add(1, Ordering::AcqRel);
+            return true;
+        }
+        if !index.is_multiple_of(self.spec.decimation.max(1) as u64) {
+            return true;
+        }
+
+        let copy = self
+            .budget
+            .charge(frame.row_bytes() * frame.height())
+            .ok()
+            .and_then(|charge| {
+                let copy = DecklinkVideoMutableFrame::copy_from(frame).ok()?;
+                Some(TappedFrame {
+                    frame: copy,
+                    timing,
+                    index,
+                    sequence,
+                    arrived: Instant::now(),
+                    transitional,
+                    _charge: charge,
+                })
+            });
+        let accepted = match copy {
+            Some(copy) => matches!(
+                self.shared.queue.push(TapItem::Frame(copy)),
+                PushResult::Accepted
+            ),
+            None => false,
+        };
+        if !accepted {
+            self.shared.dropped.fetch_add(1, Ordering::AcqRel);
+            return true;
+        }
+
+        let delivered = self.shared.delivered.fetch_add(1, Ordering::AcqRel) + 1;
+        match self.spec.frames {
+            Some(frames) if delivered >= frames as u64 => {
+                self.shared.finish(TapEnd::FramesReached);
+                false
+            }
+            _ => true,
+        }
+    }
+}
+
+struct SplitterShared {
+    budget: Option<RetentionBudget>,
+    /// The sequence number of the next frame to arrive.
+    sequence: AtomicU64,
+    /// One more than the sequence number of the last frame offered to the taps, or zero.
+    /// Only changed with `taps` locked.
+    last_offered: AtomicU64,
+    /// Set by `TapSplitter::finish`, after which no frames are offered to taps.
+    finished: AtomicBool,
+    /// The number of attached taps, checked before anything else for each frame.
+    active: AtomicUsize,
+    taps: Mutex<Vec<ActiveTap>>,
+    /// The format transition in progress, and when it started.
+    transition: Mutex<Option<(FormatTransition, Instant)>>,
+    /// The transitions that have ended since `TapSplitter::take_transitions` was last called.
+    transitions: Mutex<Vec<FormatTransition>>,
+}
+
+/// An input callback that passes everything on to a primary callback, and gives copies of
+/// frames to the taps attached to it.
+///
+/// Install the callback returned by `callback` with `DecklinkInputDevice::set_handler`.
+/// When the splitter is dropped, its taps detach with `TapEnd::Closed`.
+pub struct TapSplitter {
+    shared: Arc<SplitterShared>,
+    primary: Arc<dyn InputHandler>,
+}
+
+impl TapSplitter {
+    /// Create a splitter in front of `primary`. If `budget` is given, the frames held by
+    /// every tap also count against it.
+    pub fn new(primary: Arc<dyn InputHandler>, budget: Option<RetentionBudget>) -> TapSplitter {
+        TapSplitter {
+            shared: Arc::new(SplitterShared {
+                budget,
+                sequence: AtomicU64::new(0),
+                last_offered: AtomicU64::new(0),
+                finished: AtomicBool::new(false),
+                active: AtomicUsize::new(0),
+                taps: Mutex::new(Vec::new()),
+                transition: Mutex::new(None),
+                transitions: Mutex::new(Vec::new()),
+            }),
+            primary,
+        }
+    }
+
+    /// The input callback to install on the device.
+    pub fn callback(&self) -> Arc<dyn InputHandler> {
+        Arc::new(SplitterInputCallback {
+            shared: self.shared.clone(),
+            primary: self.primary.clone(),
+        })
+    }
+
+    /// The number of taps currently attached.
+    pub fn active_taps(&self) -> usize {
+        let taps = self.shared.taps.lock().unwrap();
+        taps.iter().filter(|tap| !tap.shared.is_finished()).count()
+    }
+
+    /// Attach a tap, which is given copies of the frames that arrive from now on until
+    /// `spec` is satisfied or it is cancelled.
+    pub fn tap<C>(&self, consumer: C, spec: TapSpec) -> TapHandle
+    where
+        C: DeckLinkTapCallback + 'static,
+    {
+        let budget = match &self.shared.budget {
+            Some(budget) => budget.sub_budget(spec.retention),
+            None => RetentionBudget::new(spec.retention, RetentionMode::Strict),
+        };
+        let shared = Arc::new(TapShared {
+            queue: FrameQueue::new(TAP_QUEUE_CAPACITY, OverflowPolicy::RejectNewest),
+            format_change: Mutex::new(spec.format_change),
+            end: Mutex::new(None),
+            delivered: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+            last_sequence: AtomicU64::new(0),
+            panicked: AtomicBool::new(false),
+            done: Mutex::new(false),
+            done_changed: Condvar::new(),
+        });
+
+        let attached = Instant::now();
+        let deadline = spec.duration.map(|d| attached + d);
+        let thread = {
+            let shared = shared.clone();
+            threads::spawn("tap", move || run_tap(consumer, shared, deadline))
+        };
+
+        let mut taps = self.shared.taps.lock().unwrap();
+        if self.shared.finished.load(Ordering::Acquire) {
+            shared.finish(TapEnd::Finished);
+        }
+        taps.push(ActiveTap {
+            spec,
+            attached,
+            budget,
+            seen: 0,
+            pending_change: None,
+            settling: None,
+            shared: shared.clone(),
+        });
+        self.shared.active.store(taps.len(), Ordering::Release);
+
+        TapHandle {
+            shared,
+            thread: Some(thread),
+        }
+    }
+
+    /// End the format transition in progress, once the input has been enabled in the new
+    /// format. Frames that arrive from now on are given to `CleanCut` taps again, and count
+    /// towards the frames `PauseUntilStable` taps wait for.
+    pub fn format_applied(&self) {
+        if let Some((mut transition, started)) = self.shared.transition.lock().unwrap().take() {
+            transition.frames =
+                self.shared.sequence.load(Ordering::Acquire) - transition.first_sequence;
+            transition.duration = started.elapsed();
+            self.shared.transitions.lock().unwrap().push(transition);
+        }
+    }
+
+    /// Whether a format transition is in progress.
+    pub fn in_transition(&self) -> bool {
+        self.shared.transition.lock().unwrap().is_some()
+    }
+
+    /// The format transitions that have ended since the last call, oldest first.
+    pub fn take_transitions(&self) -> Vec<FormatTransition> {
+        std::mem::take(&mut *self.shared.transitions.lock().unwrap())
+    }
+
+    /// Stop offering frames to every tap at once, and wait for each to drain and finish.
+    ///
+    /// Each tap is given up to `timeout` to be given the frames already queued for it and
+    /// to return from `tap_finished`, measured from when the previous tap finished or ran
+    /// out of time. Taps attached after this detach straight away.
+    pub fn finish(&self, timeout: Duration) -> SplitterFinishReport {
+        let (taps, final_sequence) = {
+            let mut taps = self.shared.taps.lock().unwrap();
+            // Frames are offered with the lock held, so none is offered after this
+            self.shared.finished.store(true, Ordering::Release);
+            self.shared.active.store(0, Ordering::Release);
+            let last = self.shared.last_offered.load(Ordering::Acquire);
+            (taps.drain(..).collect::<Vec<_>>(), last.checked_sub(1))
+        };
+
+        for tap in &taps {
+            tap.shared.finish(TapEnd::Finished);
+        }
+        let taps = taps
+            .iter()
+            .map(|tap| {
+                let started = Instant::now();
+                let drained = tap.shared.wait_done(timeout);
+                let end = (*tap.shared.end.lock().unwrap()).unwrap_or(TapEnd::Finished);
+                TapFinishReport {
+                    report: tap.shared.report_with(end),
+                    drained,
+                    drain_time: started.elapsed(),
+                    panicked: tap.shared.panicked.load(Ordering::Acquire),
+                }
+            })
+            .collect();
+
+        SplitterFinishReport {
+            final_sequence,
+            taps,
+        }
+    }
+}
+
+/// How a tap finished, as reported by `TapSplitter::finish`.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+pub struct TapFinishReport {
+    pub report: TapReport,
+    /// Whether the tap finished within the timeout. If not, its thread is still delivering
+    /// frames, and `report` is as it stood at the timeout.
+    pub drained: bool,
+    /// How long the tap took to finish, or the timeout if it did not.
+    pub drain_time: Duration,
+    /// Whether the tap panicked in `frame_tapped` or `tap_finished`.
+    pub panicked: bool,
+}
+
+/// What `TapSplitter::finish` found.
+#[derive(PartialEq, Eq, Debug, Clone, Hash)]
+pub struct SplitterFinishReport {
+    /// The sequence number of the last frame offered to the taps, which is the same for
+    /// every tap that was attached when it arrived.
+    pub final_sequence: Option<u64>,
+    /// The taps that were attached, in the order they were attached.
+    pub taps: Vec<TapFinishReport>,
+}
+
+impl Drop for TapSplitter {
+    fn drop(&mut self) {
+        let mut taps = self.shared.taps.lock().unwrap();
+        for tap in taps.drain(..) {
+            tap.shared.finish(TapEnd::Closed);
+        }
+        self.shared.active.store(0, Ordering::Release);
+    }
+}
+
+fn run_tap<C: DeckLinkTapCallback>(
+    mut consumer: C,
+    shared: Arc<TapShared>,
+    deadline: Option<Instant>,
+) {
+    internal_thread_started("tap");
+    loop {
+        let frame = match deadline {
+            None => shared.queue.pop().ok_or(PopError::Closed),
+            Some(deadline) => {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                if remaining.is_zero() {
+                    shared.finish(TapEnd::DurationReached);
+                    shared.queue.try_pop().ok_or(PopError::Closed)
+                } else {
+                    shared.queue.pop_timeout(remaining)
+                }
+            }
+        };
+
+        match frame {
+            Ok(TapItem::Frame(frame)) => {
+                let sequence = frame.sequence;
+                if catch_unwind(AssertUnwindSafe(|| consumer.frame_tapped(frame))).is_err() {
+                    shared.consumer_panicked();
+                    break;
+                }
+                shared.last_sequence.store(sequence + 1, Ordering::Release);
+            }
+            Ok(TapItem::FormatChanged(change)) => {
+                if catch_unwind(AssertUnwindSafe(|| consumer.format_changed(change))).is_err() {
+                    shared.consumer_panicked();
+                    break;
+                }
+            }
+            Err(PopError::Timeout) => shared.finish(TapEnd::DurationReached),
+            Err(PopError::Closed) => break,
+        }
+    }
+
+    if !shared.panicked.load(Ordering::Acquire) {
+        if let Some(report) = shared.report() {
+            if catch_unwind(AssertUnwindSafe(|| consumer.tap_finished(report))).is_err() {
+                shared.panicked.store(true, Ordering::Release);
+            }
+        }
+    }
+    shared.set_done();
+}
+
+/// Controls an attached tap. Dropping the handle cancels the tap and waits for its thread.
+pub struct TapHandle {
+    shared: Arc<TapShared>,
+    thread: Option<ThreadHandle>,
+}
+
+impl TapHandle {
+    /// Detach the tap early. Frames already copied for it are still delivered.
+    pub fn cancel(&self) {
+        self.shared.finish(TapEnd::Cancelled);
+    }
+
+    /// Whether the tap has detached. Its thread may still be delivering the last frames.
+    pub fn is_finished(&self) -> bool {
+        self.shared.is_finished()
+    }
+
+    /// The frames given to the tap so far.
+    pub fn delivered(&self) -> u64 {
+        self.shared.delivered.load(Ordering::Acquire)
+    }
+
+    /// Change which frames the tap is given during format changes, from the next frame on.
+    pub fn set_format_change_policy(&self, policy: FormatChangePolicy) {
+        *self.shared.format_change.lock().unwrap() = policy;
+    }
+
+    /// Wait for the tap to detach and deliver its last frame.
+    pub fn join(mut self) -> TapReport {
+        self.wait()
+    }
+
+    fn wait(&mut self) -> TapReport {
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        self.shared
+            .report()
+            .unwrap_or_else(|| self.shared.report_with(TapEnd::Closed))
+    }
+}
+
+impl Drop for TapHandle {
+    fn drop(&mut self) {
+        if self.thread.is_some() {
+            self.cancel();
+            self.wait();
+        }
+    }
+}
+
+struct SplitterInputCallback {
+    shared: Arc<SplitterShared>,
+    primary: Arc<dyn InputHandler>,
+}
+
+impl SplitterInputCallback {
+    fn offer(
+        &self,
+        frame: &DecklinkVideoFrame,
+        timing: Option<DecklinkFrameTiming>,
+        sequence: u64,
+    ) {
+        let transitional = self.shared.transition.lock().unwrap().is_some();
+        let mut taps = self.shared.taps.lock().unwrap();
+        if self.shared.finished.load(Ordering::Acquire) {
+            return;
+        }
+        self.shared
+            .last_offered
+            .store(sequence + 1, Ordering::Release);
+        taps.retain_mut(|tap| tap.offer(frame, timing, sequence, transitional));
+        self.shared.active.store(taps.len(), Ordering::Release);
+    }
+}
+
+impl InputHandler for SplitterInputCallback {
+    fn format_changed(&self, change: &InputFormatChange) {
+        let new_display_mode = change.display_mode;
+        let sequence = self.shared.sequence.load(Ordering::Acquire);
+        {
+            let mut transition = self.shared.transition.lock().unwrap();
+            match transition.as_mut() {
+                // A change during a transition extends it
+                Some((transition, _)) => transition.display_mode = new_display_mode,
+                None => {
+                    *transition = Some((
+                        FormatTransition {
+                            display_mode: new_display_mode,
+                            first_sequence: sequence,
+                            frames: 0,
+                            duration: Duration::ZERO,
+                        },
+                        Instant::now(),
+                    ))
+                }
+            }
+        }
+
+        let tapped = FormatChange {
+            events: change.events,
+            display_mode: new_display_mode,
+            detected_signal_flags: change.detected_signal_flags,
+            sequence,
+        };
+        let mut taps = self.shared.taps.lock().unwrap();
+        if !self.shared.finished.load(Ordering::Acquire) {
+            for tap in taps.iter_mut() {
+                tap.format_changed(tapped);
+            }
+        }
+        drop(taps);
+
+        self.primary.format_changed(change);
+    }
+
+    fn frame_arrived(&self, arrival: &FrameArrival<'_>) -> CallbackResult {
+        if let Some(frame) = arrival.video_frame {
+            let sequence = self.shared.sequence.fetch_add(1, Ordering::AcqRel);
+            if self.shared.active.load(Ordering::Acquire) != 0 {
+                self.offer(frame, arrival.timing(), sequence);
+            }
+        }
+        self.primary.frame_arrived(arrival)
+    }
+}
@@ -0,0 +1,469 @@
+//! Synthetic frames for testing frame processing code without hardware.
+//!
+//! `TestFrameBuilder` makes frames that implement `DecklinkFrameBase`, the trait that
+//! captured frames implement, so code written against that trait can be tested with them.
+//!
+//! ```
+//! use decklink::frame::{DecklinkFrameBase, DecklinkPixelFormat};
+//! use decklink::testing::{FillPattern, TestFrameBuilder};
+//!
+//! let frame = TestFrameBuilder::new(1920, 1080)
+//!     .pixel_format(DecklinkPixelFormat::Format8BitBGRA)
+//!     .fill(FillPattern::Solid(0x80))
+//!     .frame_index(3, 1000, 25000)
+//!     .build()
+//!     .unwrap();
+//! assert_eq!(frame.row_bytes(), 1920 * 4);
+//! assert_eq!(frame.timing().unwrap().stream_time.value, 3000);
+//! ```
+//!
+//! `DecklinkVideoFrame` wraps a frame owned by the driver, so one cannot be created from a
+//! test frame. With the `mock-backend` feature, `TestCallbackDriver` instead plays test
+//! frames, format changes and frames without a signal to an `InputHandler` through
+//! the mock driver, which hands the handler `DecklinkVideoFrame`s holding their bytes.
+//!
+//! `MockDeviceSource` lists devices for a `DeviceRegistry`, which a test adds and removes to
+//! check how code follows devices arriving and going.
+
+use crate::device::registry::{DeviceInfo, DeviceSource, EnumeratedDevice};
+use crate::device::DecklinkDevice;
+use crate::frame::{
+    frame_byte_count, pixel_group, DecklinkAlignedBytes, DecklinkFrameBase, DecklinkFrameFlags,
+    DecklinkPixelFormat, DecklinkVideoMutableFrame,
+};
+use crate::time::{DecklinkFrameTiming, DecklinkTime};
+use crate::SdkError;
+#[cfg(feature = "mock-backend")]
+use crate::{
+    device::get_devices,
+    device::input::{
+        DecklinkDetectedVideoInputFormatFlags, DecklinkVideoInputFlags,
+        DecklinkVideoInputFormatChangedEvents, InputHandler,
+    },
+    display_mode::DecklinkDisplayModeId,
+    mock::{MockBackend, MockDevice, MockFrame, DEFAULT_MODES},
+};
+use std::sync::{Arc, Mutex};
+#[cfg(feature = "mock-backend")]
+use std::time::Duration;
+
+/// How the pixel data of a test frame is filled.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum FillPattern {
+    /// Every byte has this value.
+    Solid(u8),
+    /// Bytes ramp from 0 at the start of each row to 255 at its end.
+    Gradient,
+    /// Bytes count up from 0 through the whole buffer, wrapping at 256, so every offset can
+    /// be told apart.
+    Counting,
+    /// These bytes, which must cover every row.
+    Bytes(Vec<u8>),
+}
+
+/// Builds a `TestFrame`.
+#[derive(PartialEq, Debug, Clone)]
+pub struct TestFrameBuilder {
+    width: usize,
+    height: usize,
+    row_bytes: Option<usize>,
+    pixel_format: DecklinkPixelFormat,
+    flags: DecklinkFrameFlags,
+    fill: FillPattern,
+    timing: Option<DecklinkFrameTiming>,
+}
+
+impl TestFrameBuilder {
+    /// Start building an 8-bit YUV frame of `width` by `height`, filled with zeros.
+    pub fn new(width: usize, height: usize) -> TestFrameBuilder {
+        TestFrameBuilder {
+            width,
+            height,
+            row_bytes: None,
+            pixel_format: DecklinkPixelFormat::Format8BitYUV,
+            flags: DecklinkFrameFlags::empty(),
+            fill: FillPattern::Solid(0),
+            timing: None,
+        }
+    }
+
+    pub fn pixel_format(mut self, pixel_format: DecklinkPixelFormat) -> Self {
+        self.pixel_format = pixel_format;
+        self
+    }
+
+    /// Set the byte count per row. Defaults to the smallest that the pixel format allows.
+    pub fn row_bytes(mut self, row_bytes: usize) -> Self {
+        self.row_bytes = Some(row_bytes);
+        self
+    }
+
+    pub fn flags(mut self, flags: DecklinkFrameFlags) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    /// Flag the frame as having no input source, as frames captured without a signal are.
+    pub fn no_input_source(mut self) -> Self {
+        self.flags |= DecklinkFrameFlags::HAS_NO_INPUT_SOURCE;
+        self
+    }
+
+    pub fn fill(mut self, fill: FillPattern) -> Self {
+        self.fill = fill;
+        self
+    }
+
+    /// Set the timing of the frame.
+    pub fn timing(mut self, timing: DecklinkFrameTiming) -> Self {
+        self.timing = Some(timing);
+        self
+    }
+
+    /// Set the timing of the frame to that of frame `index` of a stream with frames of
+    /// `duration` ticks, in a timescale of `scale` ticks per second.
+    pub fn frame_index(self, index: i64, duration: i64, scale: i64) -> Self {
+        let duration = DecklinkTime::new(duration, scale);
+        self.timing(DecklinkFrameTiming {
+            stream_time: DecklinkTime::new(index * duration.value, scale),
+            duration,
+            mode_duration: duration,
+        })
+    }
+
+    /// Build the frame. Fails with `SdkError::INVALIDARG` if the row byte count or the fill
+    /// bytes are too small, and with `SdkError::NOTIMPL` if no row byte count was set for
+    /// a compressed pixel format.
+    pub fn build(self) -> Result<TestFrame, SdkError> {
+        let min_row_bytes = pixel_group(self.pixel_format)
+            .map(|(pixels, bytes)| self.width.div_ceil(pixels) * bytes);
+        let row_bytes = match (self.row_bytes, min_row_bytes) {
+            (Some(row_bytes), Some(min)) if row_bytes < min => return Err(SdkError::INVALIDARG),
+            (Some(row_bytes), _) => row_bytes,
+            (None, Some(min)) => min,
+            (None, None) => return Err(SdkError::NOTIMPL),
+        };
+
+        let byte_count = frame_byte_count(row_bytes, self.height)?;
+        let bytes = match self.fill {
+            FillPattern::Solid(value) => vec![value; byte_count],
+            FillPattern::Gradient => (0..byte_count)
+                .map(|i| ((i % row_bytes) * 255 / row_bytes.saturating_sub(1).max(1)) as u8)
+                .collect(),
+            FillPattern::Counting => (0..byte_count).map(|i| i as u8).collect(),
+            FillPattern::Bytes(bytes) => bytes,
+        };
+
+        let mut frame = DecklinkVideoMutableFrame::create(
+            self.width,
+            self.height,
+            row_bytes,
+            self.pixel_format,
+            self.flags,
+        );
+        frame.copy_bytes(&bytes)?;
+
+        Ok(TestFrame {
+            frame,
+            timing: self.timing,
+        })
+    }
+}
+
+/// A synthetic frame, with optional timing.
+pub struct TestFrame {
+    frame: DecklinkVideoMutableFrame,
+    timing: Option<DecklinkFrameTiming>,
+}
+
+impl TestFrame {
+    /// The timing the frame was built with, as a captured frame would report it.
+    pub fn timing(&self) -> Option<DecklinkFrameTiming> {
+        self.timing
+    }
+
+    pub fn frame(&self) -> &DecklinkVideoMutableFrame {
+        &self.frame
+    }
+
+    pub fn into_frame(self) -> DecklinkVideoMutableFrame {
+        self.frame
+    }
+}
+
+impl DecklinkFrameBase for TestFrame {
+    fn width(&self) -> usize {
+        self.frame.width()
+    }
+    fn height(&self) -> usize {
+        self.frame.height()
+    }
+    fn row_bytes(&self) -> usize {
+        self.frame.row_bytes()
+    }
+    fn pixel_format(&self) -> DecklinkPixelFormat {
+        self.frame.pixel_format()
+    }
+    fn flags(&self) -> DecklinkFrameFlags {
+        self.frame.flags()
+    }
+    fn bytes(&self) -> Result<DecklinkAlignedBytes<'_>, SdkError> {
+        self.frame.bytes()
+    }
+}
+
+/// A step of the script a `TestCallbackDriver` plays.
+#[cfg(feature = "mock-backend")]
+pub enum ScriptStep {
+    /// Deliver this frame, with its timing if it has one.
+    Frame(TestFrame),
+    /// Deliver a frame of the current mode flagged as having no input source, as the driver
+    /// does while there is no signal.
+    NoSignal,
+    /// Deliver a change of format to this mode, with these detected signal flags.
+    FormatChange(DecklinkDisplayModeId, DecklinkDetectedVideoInputFormatFlags),
+    /// Wait this long before the next step.
+    Wait(Duration),
+}
+
+/// Plays a script of frames and format changes to an `InputHandler`, as the driver of
+/// a capturing input would.
+///
+/// `run` installs a mock backend with one input while the script plays, so it must not be
+/// called while a test holds another `MockBackend`.
+///
+/// ```
+/// # use decklink::device::input::*;
+/// # use decklink::display_mode::DecklinkDisplayModeId;
+/// # use decklink::frame::{DecklinkFrameBase, DecklinkPixelFormat};
+/// # use decklink::testing::{TestCallbackDriver, TestFrameBuilder};
+/// # use std::sync::{atomic::{AtomicUsize, Ordering}, Arc};
+/// struct Counter(AtomicUsize);
+/// impl InputHandler for Counter {
+///     fn frame_arrived(&self, arrival: &FrameArrival<'_>) -> CallbackResult {
+///         self.0.fetch_add(arrival.video_frame.unwrap().width(), Ordering::SeqCst);
+///         CallbackResult::Ok
+///     }
+/// }
+///
+/// let counter = Arc::new(Counter(AtomicUsize::new(0)));
+/// TestCallbackDriver::new(DecklinkDisplayModeId::HD1080p25, DecklinkPixelFormat::Format8BitYUV)
+///     .frame(TestFrameBuilder::new(64, 2).build().unwrap())
+///     .frame(TestFrameBuilder::new(32, 2).build().unwrap())
+///     .run(counter.clone())
+///     .unwrap();
+/// assert_eq!(counter.0.load(Ordering::SeqCst), 96);
+/// ```
+#[cfg(feature = "mock-backend")]
+pub struct TestCallbackDriver {
+    mode: DecklinkDisplayModeId,
+    pixel_format: DecklinkPixelFormat,
+    pace: Option<Duration>,
+    steps: Vec<ScriptStep>,
+}
+
+#[cfg(feature = "mock-backend")]
+impl TestCallbackDriver {
+    /// Start a script for an input capturing in `mode` and `pixel_format`, with format
+    /// detection enabled.
+    pub fn new(mode: DecklinkDisplayModeId, pixel_format: DecklinkPixelFormat) -> Self {
+        TestCallbackDriver {
+            mode,
+            pixel_format,
+            pace: None,
+            steps: Vec::new(),
+        }
+    }
+
+    /// Wait `pace` after every frame, as between frames of a live signal. Frames are
+    /// delivered one after another by default.
+    pub fn pace(mut self, pace: Duration) -> Self {
+        self.pace = Some(pace);
+        self
+    }
+
+    pub fn step(mut self, step: ScriptStep) -> Self {
+        self.steps.push(step);
+        self
+    }
+
+    pub fn frame(self, frame: TestFrame) -> Self {
+        self.step(ScriptStep::Frame(frame))
+    }
+
+    /// Deliver `count` frames without a signal.
+    pub fn no_signal(self, count: usize) -> Self {
+        (0..count).fold(self, |driver, _| driver.step(ScriptStep::NoSignal))
+    }
+
+    /// Deliver a change of format to `mode`. Frames without a signal that follow are of
+    /// that mode.
+    pub fn format_change(
+        self,
+        mode: DecklinkDisplayModeId,
+        flags: DecklinkDetectedVideoInputFormatFlags,
+    ) -> Self {
+        self.step(ScriptStep::FormatChange(mode, flags))
+    }
+
+    pub fn wait(self, duration: Duration) -> Self {
+        self.step(ScriptStep::Wait(duration))
+    }
+
+    /// Play the script to `callback` on the calling thread, returning once every step has
+    /// been delivered and the input has stopped.
+    ///
+    /// Fails if the mode or pixel format cannot be enabled, and with `SdkError::FAIL` if
+    /// the callback did not return success for a step.
+    pub fn run(self, callback: Arc<dyn InputHandler>) -> Result<(), SdkError> {
+        let mut modes = DEFAULT_MODES.to_vec();
+        if !modes.contains(&self.mode) {
+            modes.push(self.mode);
+        }
+        let backend = MockBackend::install(vec![MockDevice::new("DeckLink Test Driver")
+            .modes(&modes)
+            .pixel_formats(&[self.pixel_format])]);
+        let devices = get_devices()?;
+        let mut input = devices[0].input().ok_or(SdkError::NOINTERFACE)?;
+        input.enable_video_input(
+            self.mode,
+            self.pixel_format,
+            DecklinkVideoInputFlags::ENABLE_FORMAT_DETECTION,
+        )?;
+        input.set_handler(Some(callback))?;
+        input.start_streams()?;
+
+        let mock = backend.input(0);
+        let mut mode = self.mode;
+        let mut result = Ok(());
+        for step in self.steps {
+            let delivery = match step {
+                ScriptStep::Frame(frame) => mock.deliver_frame(mock_frame(&frame)),
+                ScriptStep::NoSignal => {
+                    mock.deliver_frame(MockFrame::for_mode(mode, self.pixel_format).no_signal())
+                }
+                ScriptStep::FormatChange(new_mode, flags) => {
+                    mode = new_mode;
+                    mock.deliver_format_change(
+                        DecklinkVideoInputFormatChangedEvents::DISPLAY_MODE_CHANGED,
+                        new_mode,
+                        flags,
+                    )
+                }
+                ScriptStep::Wait(duration) => {
+                    std::thread::sleep(duration);
+                    continue;
+                }
+            };
+            if !delivery.is_ok() {
+                result = Err(SdkError::FAIL);
+            }
+            if let Some(pace) = self.pace {
+                std::thread::sleep(pace);
+            }
+        }
+
+        input.stop_streams()?;
+        result
+    }
+}
+
+/// The frame the mock driver delivers for `frame`.
+#[cfg(feature = "mock-backend")]
+fn mock_frame(frame: &TestFrame) -> MockFrame {
+    let bytes = frame.bytes().map(|b| b.0.to_vec()).unwrap_or_default();
+    let mut mock = MockFrame::new(frame.width(), frame.height(), frame.pixel_format())
+        .row_bytes(frame.row_bytes())
+        .bytes(&bytes)
+        .flags(frame.flags());
+    if let Some(timing) = frame.timing {
+        mock = mock.stream_time(
+            timing.stream_time.value,
+            timing.duration.value,
+            timing.stream_time.scale,
+        );
+    }
+    mock
+}
+
+#[derive(Default)]
+struct MockSourceState {
+    devices: Vec<DeviceInfo>,
+    event_driven: bool,
+    changed: bool,
+    enumerations: u64,
+}
+
+/// A `DeviceSource` of devices that a test adds and removes, for a `DeviceRegistry` with no
+/// hardware.
+///
+/// Clones share their devices, so a test keeps one to change while the registry owns another.
+/// The devices listed have no driver object behind them. Their names and persistent id are
+/// the ones given, and every call that would go to the driver fails with `SdkError::HANDLE`.
+#[derive(Clone, Default)]
+pub struct MockDeviceSource {
+    state: Arc<Mutex<MockSourceState>>,
+}
+
+impl MockDeviceSource {
+    pub fn new() -> MockDeviceSource {
+        MockDeviceSource::default()
+    }
+
+    /// Report a change only after a device is added or removed, as the driver does with its
+    /// discovery notifications. Otherwise every refresh enumerates.
+    pub fn event_driven(self) -> Self {
+        self.state.lock().unwrap().event_driven = true;
+        self
+    }
+
+    pub fn add(&self, info: DeviceInfo) {
+        let mut state = self.state.lock().unwrap();
+        state.devices.push(info);
+        state.changed = true;
+    }
+
+    /// Remove the devices `matches` picks, returning how many there were.
+    pub fn remove<F: Fn(&DeviceInfo) -> bool>(&self, matches: F) -> usize {
+        let mut state = self.state.lock().unwrap();
+        let before = state.devices.len();
+        state.devices.retain(|info| !matches(info));
+        let removed = before - state.devices.len();
+        state.changed |= removed > 0;
+        removed
+    }
+
+    /// The number of times the devices have been listed.
+    pub fn enumerations(&self) -> u64 {
+        self.state.lock().unwrap().enumerations
+    }
+}
+
+impl DeviceSource for MockDeviceSource {
+    fn enumerate(&mut self) -> Result<Vec<EnumeratedDevice>, SdkError> {
+        let mut state = self.state.lock().unwrap();
+        state.enumerations += 1;
+        state.changed = false;
+        Ok(state
+            .devices
+            .iter()
+            .map(|info| EnumeratedDevice {
+                device: DecklinkDevice::detached(
+                    info.model_name.clone(),
+                    info.display_name.clone(),
+                    info.persistent_id,
+                ),
+                info: info.clone(),
+            })
+            .collect())
+    }
+
+    fn may_have_changed(&self) -> bool {
+        let state = self.state.lock().unwrap();
+        !state.event_driven || state.changed
+    }
+
+    fn is_event_driven(&self) -> bool {
+        self.state.lock().unwrap().event_driven
+    }
+}
@@ -0,0 +1,395 @@
+//! The threads the crate starts, and what became of them.
+//!
+//! Taps, batch dispatchers, dispatch pools, hashers, thumbnail encoders, device monitors and
+//! the reaper of buffers in external use each run on threads of their own. Every one is
+//! started here, named `decklink-{role}-{n}`, such as `decklink-dispatch-0`, with `n`
+//! counting the threads of each role from 0. Linux shows only the first 15 bytes of a name to
+//! tools such as `top`, while the full name is the one panics and debuggers report.
+//!
+//! - `threads` lists the threads running now, and those that have exited but have not been
+//!   joined yet.
+//! - `take_events` takes the `ThreadEvent`s raised as threads start, exit and are joined, and
+//!   when one panics.
+//! - `health` reports whether any thread has panicked. A panic that escapes a thread's work,
+//!   from a handler of a `crate::dispatch::DispatchPool` for example, is caught, reported as a
+//!   `ThreadPanicked` and ends only that thread, rather than going unnoticed.
+//! - `install_panic_hook` wraps the process panic hook, so that the panics of these threads
+//!   also carry where they happened.
+//! - `set_thread_spawner` has threads started by the application, for one whose threads must
+//!   all be created through its own runtime, such as to set their affinity.
+//!
+//! Each thread is joined by the object that started it when that object is dropped. The one
+//! exception is the reaper of `crate::external`, which exits by itself once no buffers are
+//! held, and is listed until then. With the `leak-check` feature, each thread is tracked as
+//! an `InternalThread` until it is joined, so `crate::debug::assert_no_leaks` also reports
+//! threads that were never joined.
+
+use crate::util::{track_created, track_dropped};
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fmt;
+use std::io;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// The most events kept for `take_events`. Older ones are discarded.
+const EVENT_CAPACITY: usize = 1024;
+
+/// A thread of the crate, as listed by `threads`.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct ThreadInfo {
+    pub name: String,
+    /// The kind of thread, such as `"dispatch"` or `"tap"`.
+    pub role: &'static str,
+    /// Whether the thread is still running. A thread that has exited is listed until it is
+    /// joined.
+    pub alive: bool,
+    pub spawned_at: Instant,
+}
+
+/// A thread of the crate that panicked.
+#[derive(PartialEq, Eq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ThreadPanicked {
+    pub name: String,
+    pub role: String,
+    /// The message the thread panicked with, if it was a string.
+    pub payload: String,
+    /// Where the thread panicked, as `file:line:column`, when `install_panic_hook` has been
+    /// called.
+    pub location: Option<String>,
+}
+
+impl fmt::Display for ThreadPanicked {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "thread '{}' panicked", self.name)?;
+        if let Some(location) = &self.location {
+            write!(f, " at {}", location)?;
+        }
+        write!(f, ": {}", self.payload)
+    }
+}
+
+impl std::error::Error for ThreadPanicked {}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ThreadEvent {
+    Spawned {
+        name: String,
+        role: String,
+    },
+    /// The thread's work returned or panicked, after `lifetime`.
+    Exited {
+        name: String,
+        role: String,
+        lifetime: Duration,
+    },
+    /// The thread was joined by the object that started it, which waited for `waited`.
+    Joined {
+        name: String,
+        role: String,
+        waited: Duration,
+    },
+    Panicked(ThreadPanicked),
+}
+
+/// Whether the threads of the crate have all run without panicking.
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct ThreadHealth {
+    /// The threads that have panicked since the process started.
+    pub panicked: u64,
+    pub last_panic: Option<ThreadPanicked>,
+}
+
+impl ThreadHealth {
+    pub fn is_healthy(&self) -> bool {
+        self.panicked == 0
+    }
+}
+
+/// Starts the threads of the crate, in place of `std::thread::Builder`.
+///
+/// `spawn` must run `body` on a new thread, which should be given `name`, and return once it
+/// has been started. The thread is joined by waiting for `body` to return, so it may be
+/// pooled or kept by the runtime afterwards.
+pub trait ThreadSpawner: Send + Sync {
+    fn spawn(
+        &self,
+        name: &str,
+        role: &'static str,
+        body: Box<dyn FnOnce() + Send>,
+    ) -> io::Result<()>;
+}
+
+struct EntryState {
+    exited: Option<Instant>,
+    panic: Option<ThreadPanicked>,
+    /// Set by `install_panic_hook`'s hook before the panic unwinds.
+    location: Option<String>,
+    /// The handle was dropped without joining, so the entry is removed when the thread
+    /// exits.
+    detached: bool,
+}
+
+struct ThreadEntry {
+    name: String,
+    role: &'static str,
+    spawned_at: Instant,
+    state: Mutex<EntryState>,
+    exited: Condvar,
+}
+
+impl ThreadEntry {
+    fn info(&self) -> ThreadInfo {
+        ThreadInfo {
+            name: self.name.clone(),
+            role: self.role,
+            alive: self.state.lock().unwrap().exited.is_none(),
+            spawned_at: self.spawned_at,
+        }
+    }
+}
+
+struct Registry {
+    entries: Vec<Arc<ThreadEntry>>,
+    /// The threads started of each role, for their names.
+    counts: Vec<(&'static str, u64)>,
+    events: VecDeque<ThreadEvent>,
+    health: ThreadHealth,
+}
+
+impl Registry {
+    fn push_event(&mut self, event: ThreadEvent) {
+        if self.events.len() >= EVENT_CAPACITY {
+            self.events.pop_front();
+        }
+        self.events.push_back(event);
+    }
+
+    fn remove(&mut self, entry: &Arc<ThreadEntry>) {
+        if let Some(index) = self.entries.iter().position(|e| Arc::ptr_eq(e, entry)) {
+            self.entries.remove(index);
+            track_dropped("InternalThread", Arc::as_ptr(entry));
+        }
+    }
+}
+
+static REGISTRY: Mutex<Registry> = Mutex::new(Registry {
+    entries: Vec::new(),
+    counts: Vec::new(),
+    events: VecDeque::new(),
+    health: ThreadHealth {
+        panicked: 0,
+        last_panic: None,
+    },
+});
+static SPAWNER: Mutex<Option<Arc<dyn ThreadSpawner>>> = Mutex::new(None);
+
+/// Run `f` with the entry of the crate thread this is, if it is one, for the panic hook.
+fn with_current<R>(f: impl FnOnce(&RefCell<Option<Arc<ThreadEntry>>>) -> R) -> Option<R> {
+    thread_local! {
+        static CURRENT: RefCell<Option<Arc<ThreadEntry>>> = const { RefCell::new(None) };
+    }
+    CURRENT.try_with(f).ok()
+}
+
+fn lock_registry() -> std::sync::MutexGuard<'static, Registry> {
+    // A panic while holding the lock must not stop the accounting
+    REGISTRY.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// The threads of the crate running now, and those that have exited and not been joined,
+/// oldest first.
+pub fn threads() -> Vec<ThreadInfo> {
+    lock_registry().entries.iter().map(|e| e.info()).collect()
+}
+
+/// The events raised since the last call, oldest first. Only the most recent 1024 are kept.
+pub fn take_events() -> Vec<ThreadEvent> {
+    lock_registry().events.drain(..).collect()
+}
+
+pub fn health() -> ThreadHealth {
+    lock_registry().health.clone()
+}
+
+/// Start the threads the crate starts from now on with `spawner`, or with
+/// `std::thread::Builder` again with `None`.
+pub fn set_thread_spawner(spawner: Option<Arc<dyn ThreadSpawner>>) {
+    *SPAWNER.lock().unwrap() = spawner;
+}
+
+/// Wrap the process panic hook to record where the threads of the crate panic, in
+/// `ThreadPanicked::location`. The previous hook still runs for every panic, so the panic
+/// message is printed as before. Call it once, after installing any hook of your own.
+pub fn install_panic_hook() {
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        with_current(|current| {
+            if let (Some(entry), Some(location)) = (&*current.borrow(), info.location()) {
+                entry.state.lock().unwrap().location = Some(location.to_string());
+            }
+        });
+        previous(info);
+    }));
+}
+
+/// A thread started with `spawn`. Dropping the handle detaches the thread, which stays
+/// listed until it exits.
+pub(crate) struct ThreadHandle {
+    entry: Arc<ThreadEntry>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl ThreadHandle {
+    /// Wait for the thread to exit, returning the panic it ended with, if any.
+    pub(crate) fn join(mut self) -> Result<(), ThreadPanicked> {
+        let started = Instant::now();
+        if let Some(thread) = self.thread.take() {
+            // The body's panics are caught, so this returns once the thread has exited
+            let _ = thread.join();
+        }
+        let mut state = self.entry.state.lock().unwrap();
+        while state.exited.is_none() {
+            state = self.entry.exited.wait(state).unwrap();
+        }
+        let panic = state.panic.clone();
+        drop(state);
+
+        let mut registry = lock_registry();
+        registry.remove(&self.entry);
+        registry.push_event(ThreadEvent::Joined {
+            name: self.entry.name.clone(),
+            role: self.entry.role.to_string(),
+            waited: started.elapsed(),
+        });
+        match panic {
+            Some(panic) => Err(panic),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for ThreadHandle {
+    fn drop(&mut self) {
+        // After a join the entry is gone already, and this does nothing
+        let mut registry = lock_registry();
+        let mut state = self.entry.state.lock().unwrap();
+        if state.exited.is_some() {
+            drop(state);
+            registry.remove(&self.entry);
+        } else {
+            state.detached = true;
+        }
+    }
+}
+
+/// Start a thread of `role` that runs `body`, named after its role. Panics if the thread
+/// cannot be started, as `std::thread::spawn` does.
+pub(crate) fn spawn<F>(role: &'static str, body: F) -> ThreadHandle
+where
+    F: FnOnce() + Send + 'static,
+{
+    let mut registry = lock_registry();
+    let index = match registry.counts.iter().position(|(r, _)| *r == role) {
+        Some(index) => index,
+        None => {
+            registry.counts.push((role, 0));
+            registry.counts.len() - 1
+        }
+    };
+    let name = format!("decklink-{}-{}", role, registry.counts[index].1);
+    registry.counts[index].1 += 1;
+
+    let entry = Arc::new(ThreadEntry {
+        name: name.clone(),
+        role,
+        spawned_at: Instant::now(),
+        state: Mutex::new(EntryState {
+            exited: None,
+            panic: None,
+            location: None,
+            detached: false,
+        }),
+        exited: Condvar::new(),
+    });
+    track_created("InternalThread", Arc::as_ptr(&entry));
+    registry.entries.push(entry.clone());
+    registry.push_event(ThreadEvent::Spawned {
+        name: name.clone(),
+        role: role.to_string(),
+    });
+    // The thread may exit and take the lock before the spawner returns
+    drop(registry);
+    #[cfg(feature = "mock-backend")]
+    crate::mock::allow_resource(&format!("thread {}", name));
+
+    let run = {
+        let entry = entry.clone();
+        move || run(entry, body)
+    };
+    let spawner = SPAWNER.lock().unwrap().clone();
+    let spawned = match spawner {
+        Some(spawner) => spawner.spawn(&name, role, Box::new(run)).map(|()| None),
+        None => std::thread::Builder::new()
+            .name(name.clone())
+            .spawn(run)
+            .map(Some),
+    };
+    match spawned {
+        Ok(thread) => ThreadHandle { entry, thread },
+        Err(e) => {
+            lock_registry().remove(&entry);
+            panic!("failed to spawn thread {}: {}", name, e);
+        }
+    }
+}
+
+fn run<F: FnOnce()>(entry: Arc<ThreadEntry>, body: F) {
+    with_current(|current| *current.borrow_mut() = Some(entry.clone()));
+    let result = catch_unwind(AssertUnwindSafe(body));
+    with_current(|current| current.borrow_mut().take());
+
+    // The registry is locked first, as `threads` does, and before waking a join, so that
+    // the exit is reported before the join is
+    let mut registry = lock_registry();
+    let mut state = entry.state.lock().unwrap();
+    let panic = result.err().map(|payload| ThreadPanicked {
+        name: entry.name.clone(),
+        role: entry.role.to_string(),
+        payload: panic_message(payload),
+        location: state.location.take(),
+    });
+    state.panic = panic.clone();
+    state.exited = Some(Instant::now());
+    let detached = state.detached;
+    entry.exited.notify_all();
+    drop(state);
+
+    registry.push_event(ThreadEvent::Exited {
+        name: entry.name.clone(),
+        role: entry.role.to_string(),
+        lifetime: entry.spawned_at.elapsed(),
+    });
+    if let Some(panic) = panic {
+        registry.health.panicked += 1;
+        registry.health.last_panic = Some(panic.clone());
+        registry.push_event(ThreadEvent::Panicked(panic));
+    }
+    if detached {
+        registry.remove(&entry);
+    }
+}
+
+fn panic_message(payload: Box<dyn std::any::Any + Send>) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
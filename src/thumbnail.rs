@@ -0,0 +1,522 @@
+//! Small preview images taken from a capture every so often.
+//!
+//! A `ThumbnailPipeline` is attached to a `crate::tap::TapSplitter` as the consumer of a tap
+//! with no end. It picks frames at a fixed interval, converts them with
+//! `crate::image_interop`, scales them to fit a box and encodes them, all on its own encoder
+//! thread. At most one thumbnail is in flight: a frame that falls due while the previous
+//! thumbnail is still being encoded or written is skipped rather than queued, so a slow sink
+//! delays thumbnails but never holds up the tap, let alone the capture.
+//!
+//! Interlaced frames are scaled from a single field, chosen by a `DeinterlacePolicy`, so
+//! thumbnails of moving pictures do not comb. Standard definition modes do not have square
+//! pixels, and their thumbnails are stretched to the display aspect ratio by the pixel
+//! aspect ratio of the mode, which `ThumbnailSpec::for_mode` takes.
+//!
+//! PNG is always available. JPEG needs the `thumbnail-jpeg` feature.
+
+use crate::colorimetry::{Colorimetry, TransferFunction};
+use crate::deinterlace::DeinterlacePolicy;
+use crate::display_mode::{DecklinkDisplayMode, DecklinkFieldDominance, Ratio};
+use crate::frame::DecklinkFrameBase;
+use crate::image_interop::ImageConversionError;
+use crate::queue::{FrameQueue, OverflowPolicy};
+use crate::still::{to_sdr_image, SourceColor, ToneMapOperator};
+use crate::tap::{DeckLinkTapCallback, TapReport, TappedFrame};
+use crate::threads::{self, ThreadHandle};
+use crate::time::DecklinkTime;
+use crate::util::internal_thread_started;
+use image::{ExtendedColorType, ImageEncoder, ImageError, RgbImage};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// How often a thumbnail is taken.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+pub enum ThumbnailInterval {
+    /// Take a thumbnail of the first frame that arrives at least this long after the frame
+    /// of the previous thumbnail.
+    Every(Duration),
+    /// Take a thumbnail of one frame in every `n`, counted from the frame of the previous
+    /// thumbnail. Zero is treated as one.
+    EveryFrames(u32),
+}
+
+/// The shape of the pixels of a source, as the ratio of their width to their height.
+pub type PixelAspect = Ratio;
+
+/// How thumbnails are encoded.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+pub enum ThumbnailFormat {
+    Png,
+    /// JPEG with a quality from 1 to 100.
+    #[cfg(feature = "thumbnail-jpeg")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "thumbnail-jpeg")))]
+    Jpeg {
+        quality: u8,
+    },
+}
+
+impl ThumbnailFormat {
+    /// The file extension for the format.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ThumbnailFormat::Png => "png",
+            #[cfg(feature = "thumbnail-jpeg")]
+            ThumbnailFormat::Jpeg { .. } => "jpg",
+        }
+    }
+}
+
+/// How thumbnails are made.
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub struct ThumbnailSpec {
+    pub interval: ThumbnailInterval,
+    /// The box thumbnails are scaled to fit in, keeping their display aspect ratio. They
+    /// are never scaled up.
+    pub max_width: u32,
+    pub max_height: u32,
+    pub format: ThumbnailFormat,
+    /// Which field interlaced frames are scaled from, with `field_dominance`. Frames are
+    /// scaled from both fields if the policy leaves them woven.
+    pub deinterlace: DeinterlacePolicy,
+    pub field_dominance: DecklinkFieldDominance,
+    pub pixel_aspect: Ratio,
+    /// The colorimetry YUV frames are converted with, or `None` for the usual colorimetry
+    /// for their height.
+    pub colorimetry: Option<Colorimetry>,
+    /// The transfer function of the frames. HDR frames are tone mapped into SDR, as
+    /// `crate::still` does.
+    pub transfer: TransferFunction,
+    /// The operator HDR frames are tone mapped with.
+    pub tone_map: ToneMapOperator,
+}
+
+impl ThumbnailSpec {
+    /// A PNG thumbnail at `interval`, fitting 320x180, of frames with square pixels.
+    pub fn new(interval: ThumbnailInterval) -> ThumbnailSpec {
+        ThumbnailSpec {
+            interval,
+            max_width: 320,
+            max_height: 180,
+            format: ThumbnailFormat::Png,
+            deinterlace: DeinterlacePolicy::Auto,
+            field_dominance: DecklinkFieldDominance::ProgressiveFrame,
+            pixel_aspect: Ratio::SQUARE,
+            colorimetry: None,
+            transfer: TransferFunction::Sdr,
+            tone_map: ToneMapOperator::default(),
+        }
+    }
+
+    /// Take the field dominance and pixel aspect ratio of `mode`. `widescreen` is for
+    /// anamorphic 16:9 standard definition sources.
+    pub fn for_mode(mut self, mode: &DecklinkDisplayMode, widescreen: bool) -> ThumbnailSpec {
+        self.field_dominance = mode.field_dominance();
+        self.pixel_aspect = mode.pixel_aspect_ratio(widescreen);
+        self
+    }
+
+    /// The size of the thumbnail of a frame `width` by `height` pixels.
+    pub fn thumbnail_size(&self, width: usize, height: usize) -> (u32, u32) {
+        let display_width = width as f64 * self.pixel_aspect.to_f64();
+        let display_height = height as f64;
+        if display_width <= 0.0 || display_height <= 0.0 {
+            return (0, 0);
+        }
+
+        let scale = (self.max_width as f64 / display_width)
+            .min(self.max_height as f64 / display_height)
+            .min(1.0);
+        (
+            ((display_width * scale).round() as u32).max(1),
+            ((display_height * scale).round() as u32).max(1),
+        )
+    }
+}
+
+/// What a thumbnail was taken of.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+pub struct ThumbnailMeta {
+    /// The number of thumbnails taken before this one.
+    pub sequence: u64,
+    /// The index of the frame in the tap, as in `TappedFrame::index`.
+    pub frame_index: u64,
+    /// The stream time of the frame, if it was known.
+    pub stream_time: Option<DecklinkTime>,
+    /// When the frame arrived.
+    pub arrived: Instant,
+    /// The wall clock time the frame was converted at.
+    pub captured_at: SystemTime,
+    pub source_width: usize,
+    pub source_height: usize,
+    pub width: u32,
+    pub height: u32,
+    pub format: ThumbnailFormat,
+}
+
+/// A function given each encoded thumbnail and what it was taken of.
+pub type ThumbnailCallback = Box<dyn FnMut(&[u8], &ThumbnailMeta) + Send>;
+
+/// Where encoded thumbnails go.
+pub enum ThumbnailSink {
+    /// Write each thumbnail into this directory, named after the wall clock time it was
+    /// taken at in milliseconds and its sequence number, such as
+    /// `thumb-1760400000000-000042.png`.
+    Directory(PathBuf),
+    /// Give each thumbnail to a function, on the encoder thread.
+    Callback(ThumbnailCallback),
+}
+
+impl ThumbnailSink {
+    /// Sink into the directory `path`, which must exist.
+    pub fn directory(path: impl AsRef<Path>) -> ThumbnailSink {
+        ThumbnailSink::Directory(path.as_ref().to_path_buf())
+    }
+
+    fn write(&mut self, bytes: &[u8], meta: &ThumbnailMeta) -> Result<(), ThumbnailError> {
+        match self {
+            ThumbnailSink::Directory(dir) => {
+                let millis = meta
+                    .captured_at
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |d| d.as_millis());
+                let name = format!(
+                    "thumb-{}-{:06}.{}",
+                    millis,
+                    meta.sequence,
+                    meta.format.extension()
+                );
+                std::fs::write(dir.join(name), bytes).map_err(ThumbnailError::Io)
+            }
+            ThumbnailSink::Callback(callback) => {
+                callback(bytes, meta);
+                Ok(())
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ThumbnailError {
+    Conversion(ImageConversionError),
+    Encode(ImageError),
+    Io(std::io::Error),
+}
+
+impl std::fmt::Display for ThumbnailError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ThumbnailError::Conversion(e) => write!(f, "failed to convert the frame: {}", e),
+            ThumbnailError::Encode(e) => write!(f, "failed to encode the thumbnail: {}", e),
+            ThumbnailError::Io(e) => write!(f, "failed to write the thumbnail: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ThumbnailError {}
+
+#[derive(Default)]
+struct Counters {
+    written: AtomicU64,
+    skipped: AtomicU64,
+    failed: AtomicU64,
+    last_error: Mutex<Option<String>>,
+}
+
+/// Counts of what a pipeline has done, which can be read while it is attached to a tap.
+#[derive(Clone, Default)]
+pub struct ThumbnailStats {
+    counters: Arc<Counters>,
+}
+
+impl ThumbnailStats {
+    /// Thumbnails given to the sink.
+    pub fn written(&self) -> u64 {
+        self.counters.written.load(Ordering::Acquire)
+    }
+
+    /// Frames that fell due while the previous thumbnail was in flight.
+    pub fn skipped(&self) -> u64 {
+        self.counters.skipped.load(Ordering::Acquire)
+    }
+
+    /// Thumbnails that could not be converted, encoded or written.
+    pub fn failed(&self) -> u64 {
+        self.counters.failed.load(Ordering::Acquire)
+    }
+
+    /// The most recent failure, if there was one.
+    pub fn last_error(&self) -> Option<String> {
+        self.counters.last_error.lock().unwrap().clone()
+    }
+}
+
+struct Job {
+    frame: TappedFrame,
+    sequence: u64,
+}
+
+/// A tap consumer that takes thumbnails of the frames it is given.
+///
+/// ```no_run
+/// # use decklink::tap::{TapSpec, TapSplitter};
+/// # use decklink::thumbnail::*;
+/// # use std::time::Duration;
+/// # fn run(splitter: &TapSplitter) {
+/// let spec = ThumbnailSpec::new(ThumbnailInterval::Every(Duration::from_secs(5)));
+/// let pipeline = ThumbnailPipeline::new(spec, ThumbnailSink::directory("thumbs"));
+/// let stats = pipeline.stats();
+/// let tap = splitter.tap(pipeline, TapSpec::default());
+/// # }
+/// ```
+pub struct ThumbnailPipeline {
+    interval: ThumbnailInterval,
+    stats: ThumbnailStats,
+    /// Set from when a frame is handed to the encoder until its thumbnail is done.
+    busy: Arc<AtomicBool>,
+    jobs: Arc<FrameQueue<Job>>,
+    encoder: Option<ThreadHandle>,
+    /// The frame of the previous thumbnail, by index and arrival.
+    last: Option<(u64, Instant)>,
+    sequence: u64,
+}
+
+impl ThumbnailPipeline {
+    /// Create a pipeline, and start its encoder thread.
+    pub fn new(spec: ThumbnailSpec, sink: ThumbnailSink) -> ThumbnailPipeline {
+        let stats = ThumbnailStats::default();
+        let busy = Arc::new(AtomicBool::new(false));
+        let jobs = Arc::new(FrameQueue::new(1, OverflowPolicy::RejectNewest));
+
+        let encoder = {
+            let stats = stats.clone();
+            let busy = busy.clone();
+            let jobs = jobs.clone();
+            threads::spawn("thumbnail", move || {
+                run_encoder(spec, sink, &jobs, &busy, &stats)
+            })
+        };
+
+        ThumbnailPipeline {
+            interval: spec.interval,
+            stats,
+            busy,
+            jobs,
+            encoder: Some(encoder),
+            last: None,
+            sequence: 0,
+        }
+    }
+
+    pub fn stats(&self) -> ThumbnailStats {
+        self.stats.clone()
+    }
+
+    fn is_due(&self, frame: &TappedFrame) -> bool {
+        let Some((index, arrived)) = self.last else {
+            return true;
+        };
+        match self.interval {
+            ThumbnailInterval::Every(interval) => {
+                frame.arrived.saturating_duration_since(arrived) >= interval
+            }
+            ThumbnailInterval::EveryFrames(n) => frame.index - index >= n.max(1) as u64,
+        }
+    }
+
+    /// Stop the encoder thread once it has finished the thumbnail in flight.
+    fn stop(&mut self) {
+        self.jobs.close();
+        if let Some(encoder) = self.encoder.take() {
+            let _ = encoder.join();
+        }
+    }
+}
+
+impl DeckLinkTapCallback for ThumbnailPipeline {
+    fn frame_tapped(&mut self, frame: TappedFrame) {
+        if !self.is_due(&frame) {
+            return;
+        }
+        // The interval restarts from this frame only if it is taken, so that a frame that
+        // comes free after a skip is taken straight away
+        if self.busy.swap(true, Ordering::AcqRel) {
+            self.stats.counters.skipped.fetch_add(1, Ordering::AcqRel);
+            return;
+        }
+
+        self.last = Some((frame.index, frame.arrived));
+        let job = Job {
+            frame,
+            sequence: self.sequence,
+        };
+        self.sequence += 1;
+        // The encoder is idle, so the queue is empty unless it has been closed
+        let _ = self.jobs.push(job);
+    }
+
+    fn tap_finished(&mut self, _report: TapReport) {
+        self.stop();
+    }
+}
+
+impl Drop for ThumbnailPipeline {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+fn run_encoder(
+    spec: ThumbnailSpec,
+    mut sink: ThumbnailSink,
+    jobs: &FrameQueue<Job>,
+    busy: &AtomicBool,
+    stats: &ThumbnailStats,
+) {
+    internal_thread_started("thumbnail");
+    while let Some(job) = jobs.pop() {
+        let result = make_thumbnail(&spec, &job).and_then(|(bytes, meta)| {
+            // The frame is no longer needed, so its retention is given back before writing
+            drop(job.frame);
+            sink.write(&bytes, &meta)
+        });
+        match result {
+            Ok(()) => {
+                stats.counters.written.fetch_add(1, Ordering::AcqRel);
+            }
+            Err(e) => {
+                stats.counters.failed.fetch_add(1, Ordering::AcqRel);
+                *stats.counters.last_error.lock().unwrap() = Some(e.to_string());
+            }
+        }
+        busy.store(false, Ordering::Release);
+    }
+}
+
+fn make_thumbnail(
+    spec: &ThumbnailSpec,
+    job: &Job,
+) -> Result<(Vec<u8>, ThumbnailMeta), ThumbnailError> {
+    let frame = &job.frame;
+    let colorimetry = spec
+        .colorimetry
+        .unwrap_or_else(|| Colorimetry::for_height(frame.height()));
+    let source = SourceColor {
+        colorimetry,
+        transfer: spec.transfer,
+    };
+    let image = to_sdr_image(frame, source, spec.tone_map).map_err(ThumbnailError::Conversion)?;
+    let captured_at = SystemTime::now();
+
+    let (width, height) = spec.thumbnail_size(frame.width(), frame.height());
+    let first_row = spec
+        .deinterlace
+        .field(spec.field_dominance)
+        .map(|field| field.first_row());
+    let scaled = scale(&image, first_row, width, height);
+
+    let mut bytes = Vec::new();
+    encode(spec.format, &scaled, &mut bytes).map_err(ThumbnailError::Encode)?;
+
+    let meta = ThumbnailMeta {
+        sequence: job.sequence,
+        frame_index: frame.index,
+        stream_time: frame.timing.map(|t| t.stream_time),
+        arrived: frame.arrived,
+        captured_at,
+        source_width: frame.width(),
+        source_height: frame.height(),
+        width,
+        height,
+        format: spec.format,
+    };
+    Ok((bytes, meta))
+}
+
+fn encode(format: ThumbnailFormat, image: &RgbImage, out: &mut Vec<u8>) -> Result<(), ImageError> {
+    let (width, height) = image.dimensions();
+    match format {
+        ThumbnailFormat::Png => image::codecs::png::PngEncoder::new(out).write_image(
+            image.as_raw(),
+            width,
+            height,
+            ExtendedColorType::Rgb8,
+        ),
+        #[cfg(feature = "thumbnail-jpeg")]
+        ThumbnailFormat::Jpeg { quality } => {
+            image::codecs::jpeg::JpegEncoder::new_with_quality(out, quality.clamp(1, 100))
+                .write_image(image.as_raw(), width, height, ExtendedColorType::Rgb8)
+        }
+    }
+}
+
+/// For each output position, the source positions it covers and how much of each.
+pub(crate) fn area_weights(source: usize, output: usize) -> Vec<Vec<(usize, f32)>> {
+    let ratio = source as f64 / output as f64;
+    (0..output)
+        .map(|o| {
+            let start = o as f64 * ratio;
+            let end = (start + ratio).min(source as f64);
+            let mut weights = Vec::new();
+            let mut s = start.floor() as usize;
+            while (s as f64) < end && s < source {
+                let covered = (end.min(s as f64 + 1.0) - start.max(s as f64)) as f32;
+                if covered > 0.0 {
+                    weights.push((s, covered / ratio as f32));
+                }
+                s += 1;
+            }
+            weights
+        })
+        .collect()
+}
+
+/// Scale `image` to `width` by `height` by averaging the area each output pixel covers.
+/// With `first_row`, only every other row from it is used, which is a single field.
+pub(crate) fn scale(
+    image: &RgbImage,
+    first_row: Option<usize>,
+    width: u32,
+    height: u32,
+) -> RgbImage {
+    let (source_width, source_height) = (image.width() as usize, image.height() as usize);
+    let rows: Vec<usize> = match first_row {
+        Some(first) if source_height > 1 => (first.min(source_height - 1)..source_height)
+            .step_by(2)
+            .collect(),
+        _ => (0..source_height).collect(),
+    };
+    let (width, height) = (width as usize, height as usize);
+    let source = image.as_raw();
+
+    // Scale each row across, then the rows down
+    let columns = area_weights(source_width, width);
+    let mut across = vec![0f32; rows.len() * width * 3];
+    for (r, &y) in rows.iter().enumerate() {
+        let row = &source[y * source_width * 3..(y + 1) * source_width * 3];
+        for (x, weights) in columns.iter().enumerate() {
+            let out = &mut across[(r * width + x) * 3..(r * width + x + 1) * 3];
+            for &(s, weight) in weights {
+                for (o, &v) in out.iter_mut().zip(&row[s * 3..s * 3 + 3]) {
+                    *o += v as f32 * weight;
+                }
+            }
+        }
+    }
+
+    let mut out = vec![0u8; width * height * 3];
+    for (y, weights) in area_weights(rows.len(), height).iter().enumerate() {
+        let row = &mut out[y * width * 3..(y + 1) * width * 3];
+        for (i, o) in row.iter_mut().enumerate() {
+            let value: f32 = weights
+                .iter()
+                .map(|&(r, weight)| across[r * width * 3 + i] * weight)
+                .sum();
+            *o = value.round().clamp(0.0, 255.0) as u8;
+        }
+    }
+
+    RgbImage::from_raw(width as u32, height as u32, out)
+        .expect("scaled buffer matches the thumbnail dimensions")
+}
@@ -0,0 +1,72 @@
+//! Time values expressed in a DeckLink timescale.
+
+/// A time value in ticks of `scale` ticks per second.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DecklinkTime {
+    /// The number of ticks.
+    pub value: i64,
+    /// The number of ticks per second.
+    pub scale: i64,
+}
+
+impl DecklinkTime {
+    pub fn new(value: i64, scale: i64) -> DecklinkTime {
+        DecklinkTime { value, scale }
+    }
+
+    /// Convert to another timescale, rounding to the nearest tick (halfway cases round away
+    /// from zero).
+    ///
+    /// Returns `None` if either scale is not positive or the result does not fit in an `i64`.
+    pub fn rescale(&self, scale: i64) -> Option<DecklinkTime> {
+        if self.scale <= 0 || scale <= 0 {
+            return None;
+        }
+        if self.scale == scale {
+            return Some(*self);
+        }
+
+        let num = self.value as i128 * scale as i128;
+        let den = self.scale as i128;
+        let half = den / 2;
+        let rounded = if num >= 0 {
+            (num + half) / den
+        } else {
+            (num - half) / den
+        };
+
+        i64::try_from(rounded)
+            .ok()
+            .map(|value| DecklinkTime { value, scale })
+    }
+
+    /// The time in seconds.
+    pub fn as_secs_f64(&self) -> f64 {
+        self.value as f64 / self.scale as f64
+    }
+}
+
+/// Timing of a captured frame, expressed in the timescale of the active display mode.
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub struct DecklinkFrameTiming {
+    /// The stream time of the frame.
+    pub stream_time: DecklinkTime,
+    /// The duration of the frame as reported by the driver.
+    pub duration: DecklinkTime,
+    /// The frame duration of the active display mode.
+    pub mode_duration: DecklinkTime,
+}
+
+impl DecklinkFrameTiming {
+    /// The stream time converted to another timescale. See `DecklinkTime::rescale`.
+    pub fn stream_time_in(&self, scale: i64) -> Option<DecklinkTime> {
+        self.stream_time.rescale(scale)
+    }
+
+    /// Whether the driver reported duration differs from the display mode frame duration
+    /// by more than one tick. This indicates a variable rate source, or a stale display mode.
+    pub fn duration_mismatch(&self) -> bool {
+        (self.duration.value - self.mode_duration.value).abs() > 1
+    }
+}
@@ -0,0 +1,448 @@
+//! Reading frame timecode, and following the timecode sources of a capture.
+//!
+//! A source can carry timecode in several places at once: RP188 VITC1 and VITC2 and RP188
+//! LTC in the ancillary data of SDI, and VITC in the vertical interval of analogue video. A
+//! `TimecodeTracker` polls a set of them on every frame, keeps statistics for each, and
+//! reports when a source appears, disappears or is discontinuous.
+//!
+//! `TimecodeTracker::best` gives the timecode to use for a frame. It is taken from the first
+//! source present in the order the sources were configured, but once a source is chosen it
+//! is kept for as long as it is present, so a source that comes and goes does not make the
+//! choice flap. A more preferred source is only returned to once it has been present for
+//! `TimecodeTrackerConfig::return_after` frames in a row.
+//!
+//! Continuity is checked against the cadence the timecode counts at, which is the frame rate
+//! for rates up to 30 frames per second. Above that, RP188 timecode other than
+//! `RP188HighFrameRate` counts pairs of frames, and the second frame of a pair is marked
+//! with `DecklinkTimecodeFlags::FIELD_MARK`. Drop frame timecode, at 30 or 60 frames per
+//! second, skips the first two or four frame numbers of every minute except every tenth.
+
+use crate::ptr::TimecodePtr;
+use crate::time::DecklinkTime;
+use crate::{sdk, SdkError};
+use std::fmt;
+
+#[derive(FromPrimitive, PartialEq, Eq, Debug, Copy, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DecklinkTimecodeFormat {
+    RP188VITC1 = sdk::_DecklinkTimecodeFormat_decklinkTimecodeRP188VITC1 as isize,
+    RP188VITC2 = sdk::_DecklinkTimecodeFormat_decklinkTimecodeRP188VITC2 as isize,
+    RP188LTC = sdk::_DecklinkTimecodeFormat_decklinkTimecodeRP188LTC as isize,
+    RP188HighFrameRate = sdk::_DecklinkTimecodeFormat_decklinkTimecodeRP188HighFrameRate as isize,
+    /// Whichever RP188 timecode the driver finds first.
+    RP188Any = sdk::_DecklinkTimecodeFormat_decklinkTimecodeRP188Any as isize,
+    VITC = sdk::_DecklinkTimecodeFormat_decklinkTimecodeVITC as isize,
+    VITCField2 = sdk::_DecklinkTimecodeFormat_decklinkTimecodeVITCField2 as isize,
+    Serial = sdk::_DecklinkTimecodeFormat_decklinkTimecodeSerial as isize,
+}
+
+bitflags! {
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub struct DecklinkTimecodeFlags: u32 {
+        const IS_DROP_FRAME = sdk::_DecklinkTimecodeFlags_decklinkTimecodeIsDropFrame;
+        const FIELD_MARK = sdk::_DecklinkTimecodeFlags_decklinkTimecodeFieldMark;
+        const COLOR_FRAME = sdk::_DecklinkTimecodeFlags_decklinkTimecodeColorFrame;
+        const EMBED_RECORDING_TRIGGER = sdk::_DecklinkTimecodeFlags_decklinkTimecodeEmbedRecordingTrigger;
+        const RECORDING_TRIGGERED = sdk::_DecklinkTimecodeFlags_decklinkTimecodeRecordingTriggered;
+    }
+}
+
+/// A timecode read from a frame.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DecklinkTimecode {
+    pub hours: u8,
+    pub minutes: u8,
+    pub seconds: u8,
+    pub frames: u8,
+    pub flags: DecklinkTimecodeFlags,
+    pub user_bits: u32,
+}
+
+/// The frame numbers dropped at the start of each minute, for drop frame timecode counting
+/// `rate` frames per second.
+fn dropped_per_minute(rate: u32, drop_frame: bool) -> u64 {
+    if drop_frame && rate.is_multiple_of(30) {
+        rate as u64 / 15
+    } else {
+        0
+    }
+}
+
+/// The number of frames in a day of timecode counting `rate` frames per second.
+fn frames_per_day(rate: u32, drop_frame: bool) -> u64 {
+    rate.max(1) as u64 * 86_400 - dropped_per_minute(rate, drop_frame) * (1440 - 144)
+}
+
+impl DecklinkTimecode {
+    pub fn is_drop_frame(&self) -> bool {
+        self.flags.contains(DecklinkTimecodeFlags::IS_DROP_FRAME)
+    }
+
+    /// The number of frames since midnight, for timecode counting `rate` frames per second.
+    pub fn frame_number(&self, rate: u32) -> u64 {
+        let rate = rate as u64;
+        let minutes = self.hours as u64 * 60 + self.minutes as u64;
+        let drop = dropped_per_minute(rate as u32, self.is_drop_frame());
+        (minutes * 60 + self.seconds as u64) * rate + self.frames as u64
+            - drop * (minutes - minutes / 10)
+    }
+
+    /// The timecode `number` frames after midnight, wrapping at 24 hours, for timecode
+    /// counting `rate` frames per second. Only the drop frame flag is set.
+    pub fn from_frame_number(number: u64, rate: u32, drop_frame: bool) -> DecklinkTimecode {
+        let drop = dropped_per_minute(rate, drop_frame);
+        let mut number = number % frames_per_day(rate, drop_frame);
+        let rate = rate.max(1) as u64;
+
+        // Put the dropped frame numbers back, to count as if none were dropped
+        if drop > 0 {
+            let per_minute = rate * 60 - drop;
+            let per_ten_minutes = rate * 600 - drop * 9;
+            let tens = number / per_ten_minutes;
+            let rest = number % per_ten_minutes;
+            number += drop * 9 * tens;
+            if rest > drop {
+                number += drop * ((rest - drop) / per_minute);
+            }
+        }
+
+        DecklinkTimecode {
+            hours: (number / (rate * 3600)) as u8,
+            minutes: (number / (rate * 60) % 60) as u8,
+            seconds: (number / rate % 60) as u8,
+            frames: (number % rate) as u8,
+            flags: if drop_frame {
+                DecklinkTimecodeFlags::IS_DROP_FRAME
+            } else {
+                DecklinkTimecodeFlags::empty()
+            },
+            user_bits: 0,
+        }
+    }
+
+    /// Read a timecode object.
+    pub(crate) fn read(timecode: &TimecodePtr) -> Result<Self, SdkError> {
+        let (hours, minutes, seconds, frames) = timecode.components()?;
+        // User bits are optional, so a failure to read them leaves them clear
+        let user_bits = timecode.user_bits().unwrap_or(0);
+
+        Ok(DecklinkTimecode {
+            hours,
+            minutes,
+            seconds,
+            frames,
+            flags: DecklinkTimecodeFlags::from_bits_truncate(timecode.flags()),
+            user_bits,
+        })
+    }
+}
+
+impl fmt::Display for DecklinkTimecode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let separator = if self.is_drop_frame() { ';' } else { ':' };
+        write!(
+            f,
+            "{:02}:{:02}:{:02}{}{:02}",
+            self.hours, self.minutes, self.seconds, separator, self.frames
+        )
+    }
+}
+
+/// The frame rate, rounded to whole frames per second, of frames of `duration`.
+pub fn frame_rate_of(duration: DecklinkTime) -> u32 {
+    if duration.value <= 0 {
+        return 0;
+    }
+    ((duration.scale + duration.value / 2) / duration.value) as u32
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Hash)]
+pub struct TimecodeTrackerConfig {
+    /// The sources to poll, most preferred first.
+    pub formats: Vec<DecklinkTimecodeFormat>,
+    /// The frame rate of the capture, in whole frames per second, as `frame_rate_of` gives.
+    pub frame_rate: u32,
+    /// The frames a more preferred source must be present for in a row before it is chosen
+    /// over the source in use.
+    pub return_after: u32,
+}
+
+impl TimecodeTrackerConfig {
+    /// The RP188 sources, then VITC, for a capture at `frame_rate`.
+    pub fn new(frame_rate: u32) -> TimecodeTrackerConfig {
+        TimecodeTrackerConfig {
+            formats: vec![
+                DecklinkTimecodeFormat::RP188VITC1,
+                DecklinkTimecodeFormat::RP188LTC,
+                DecklinkTimecodeFormat::RP188VITC2,
+                DecklinkTimecodeFormat::VITC,
+            ],
+            frame_rate,
+            return_after: frame_rate.max(1),
+        }
+    }
+}
+
+/// What a tracker has seen of one source.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+pub struct TimecodeSourceStats {
+    pub format: DecklinkTimecodeFormat,
+    pub frames_with_timecode: u64,
+    /// The most frames in a row without timecode, after the source first appeared.
+    pub longest_gap: u64,
+    /// Timecodes that did not follow on from the previous one.
+    pub discontinuities: u64,
+    /// Timecodes that repeated the previous one, which are also counted as discontinuities.
+    pub duplicates: u64,
+}
+
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TimecodeEvent {
+    Appeared {
+        format: DecklinkTimecodeFormat,
+        frame: u64,
+    },
+    Disappeared {
+        format: DecklinkTimecodeFormat,
+        frame: u64,
+    },
+    /// The timecode did not follow on from the previous one of the source.
+    Jump {
+        format: DecklinkTimecodeFormat,
+        frame: u64,
+        expected: DecklinkTimecode,
+        actual: DecklinkTimecode,
+    },
+    /// The timecode repeated the previous one of the source.
+    Duplicate {
+        format: DecklinkTimecodeFormat,
+        frame: u64,
+        timecode: DecklinkTimecode,
+    },
+    /// `best` moved to another source, or to none.
+    BestChanged {
+        from: Option<DecklinkTimecodeFormat>,
+        to: Option<DecklinkTimecodeFormat>,
+        frame: u64,
+    },
+}
+
+/// The last timecode of a source, and its position in half steps of the timecode count, so
+/// sources counting pairs of frames advance by one half step a frame.
+#[derive(Debug, Copy, Clone)]
+struct LastTimecode {
+    timecode: DecklinkTimecode,
+    half_steps: u64,
+    frame: u64,
+}
+
+#[derive(Debug, Clone)]
+struct SourceState {
+    stats: TimecodeSourceStats,
+    current: Option<DecklinkTimecode>,
+    last: Option<LastTimecode>,
+    gap: u64,
+    /// Frames in a row the source has been present for.
+    present_run: u64,
+}
+
+/// Follows the timecode sources of a capture. See the module documentation.
+#[derive(Debug, Clone)]
+pub struct TimecodeTracker {
+    config: TimecodeTrackerConfig,
+    sources: Vec<SourceState>,
+    /// The index into `sources` of the source `best` uses.
+    best: Option<usize>,
+    frames: u64,
+    events: Vec<TimecodeEvent>,
+}
+
+impl TimecodeTracker {
+    pub fn new(config: TimecodeTrackerConfig) -> TimecodeTracker {
+        let sources = config
+            .formats
+            .iter()
+            .map(|format| SourceState {
+                stats: TimecodeSourceStats {
+                    format: *format,
+                    frames_with_timecode: 0,
+                    longest_gap: 0,
+                    discontinuities: 0,
+                    duplicates: 0,
+                },
+                current: None,
+                last: None,
+                gap: 0,
+                present_run: 0,
+            })
+            .collect();
+        TimecodeTracker {
+            config,
+            sources,
+            best: None,
+            frames: 0,
+            events: Vec::new(),
+        }
+    }
+
+    pub fn config(&self) -> &TimecodeTrackerConfig {
+        &self.config
+    }
+
+    /// The number of frames observed.
+    pub fn frame_count(&self) -> u64 {
+        self.frames
+    }
+
+    /// Poll every configured source of `frame`. A source that cannot be read is taken as
+    /// absent.
+    pub fn observe_frame(&mut self, frame: &crate::frame::DecklinkVideoFrame) {
+        self.observe(|format| frame.timecode(format).ok().flatten());
+    }
+
+    /// Observe a frame, with `read` giving the timecode of each configured source, or
+    /// `None` where the frame has none.
+    pub fn observe<F>(&mut self, mut read: F)
+    where
+        F: FnMut(DecklinkTimecodeFormat) -> Option<DecklinkTimecode>,
+    {
+        let frame = self.frames;
+        self.frames += 1;
+        for index in 0..self.sources.len() {
+            let timecode = read(self.sources[index].stats.format);
+            self.update_source(index, frame, timecode);
+        }
+        self.choose_best(frame);
+    }
+
+    /// The timecode of the last frame observed, from the source chosen for it.
+    pub fn best(&self) -> Option<(DecklinkTimecodeFormat, DecklinkTimecode)> {
+        let source = &self.sources[self.best?];
+        source
+            .current
+            .map(|timecode| (source.stats.format, timecode))
+    }
+
+    /// The statistics of each source, in the configured order.
+    pub fn stats(&self) -> Vec<TimecodeSourceStats> {
+        self.sources.iter().map(|s| s.stats).collect()
+    }
+
+    /// The events since the last call, oldest first.
+    pub fn take_events(&mut self) -> Vec<TimecodeEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    /// The rate a source counts at, and whether it counts pairs of frames.
+    fn cadence(&self, format: DecklinkTimecodeFormat) -> (u32, bool) {
+        let rate = self.config.frame_rate.max(1);
+        if rate > 30 && format != DecklinkTimecodeFormat::RP188HighFrameRate {
+            (rate / 2, true)
+        } else {
+            (rate, false)
+        }
+    }
+
+    fn update_source(&mut self, index: usize, frame: u64, timecode: Option<DecklinkTimecode>) {
+        let format = self.sources[index].stats.format;
+        let (rate, paired) = self.cadence(format);
+        let source = &mut self.sources[index];
+        let was_present = source.current.is_some();
+        source.current = timecode;
+
+        let timecode = match timecode {
+            Some(timecode) => timecode,
+            None => {
+                if was_present {
+                    self.events
+                        .push(TimecodeEvent::Disappeared { format, frame });
+                }
+                if source.last.is_some() {
+                    source.gap += 1;
+                    source.stats.longest_gap = source.stats.longest_gap.max(source.gap);
+                }
+                source.present_run = 0;
+                return;
+            }
+        };
+
+        if !was_present {
+            self.events.push(TimecodeEvent::Appeared { format, frame });
+        }
+        source.stats.frames_with_timecode += 1;
+        source.gap = 0;
+        source.present_run += 1;
+
+        let field = paired && timecode.flags.contains(DecklinkTimecodeFlags::FIELD_MARK);
+        let half_steps = timecode.frame_number(rate) * 2 + field as u64;
+        if let Some(last) = source.last {
+            let step = if paired { 1 } else { 2 };
+            // Timecode wraps at midnight, which follows on
+            let per_day = frames_per_day(rate, last.timecode.is_drop_frame()) * 2;
+            let expected_steps = (last.half_steps + (frame - last.frame) * step) % per_day;
+            if half_steps == last.half_steps {
+                source.stats.duplicates += 1;
+                source.stats.discontinuities += 1;
+                self.events.push(TimecodeEvent::Duplicate {
+                    format,
+                    frame,
+                    timecode,
+                });
+            } else if half_steps != expected_steps {
+                let mut expected = DecklinkTimecode::from_frame_number(
+                    expected_steps / 2,
+                    rate,
+                    last.timecode.is_drop_frame(),
+                );
+                if expected_steps % 2 == 1 {
+                    expected.flags |= DecklinkTimecodeFlags::FIELD_MARK;
+                }
+                source.stats.discontinuities += 1;
+                self.events.push(TimecodeEvent::Jump {
+                    format,
+                    frame,
+                    expected,
+                    actual: timecode,
+                });
+            }
+        }
+        source.last = Some(LastTimecode {
+            timecode,
+            half_steps,
+            frame,
+        });
+    }
+
+    fn choose_best(&mut self, frame: u64) {
+        let present = |s: &SourceState| s.current.is_some();
+        let chosen = match self.best {
+            Some(current) if present(&self.sources[current]) => {
+                // Return to a more preferred source once it has been present for long enough
+                self.sources[..current]
+                    .iter()
+                    .position(|s| present(s) && s.present_run >= self.config.return_after as u64)
+                    .unwrap_or(current)
+            }
+            _ => match self.sources.iter().position(present) {
+                Some(index) => index,
+                None => return self.set_best(None, frame),
+            },
+        };
+        self.set_best(Some(chosen), frame);
+    }
+
+    fn set_best(&mut self, best: Option<usize>, frame: u64) {
+        if best != self.best {
+            let format = |index: Option<usize>| index.map(|i| self.sources[i].stats.format);
+            self.events.push(TimecodeEvent::BestChanged {
+                from: format(self.best),
+                to: format(best),
+                frame,
+            });
+            self.best = best;
+        }
+    }
+}
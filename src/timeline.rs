@@ -0,0 +1,227 @@
+//! Finding captured frames by time.
+//!
+//! A `FrameTimeline` keeps the intervals of the most recent frames of a capture, so that a
+//! time, such as a timecode to take a still at or the time of an external event, can be
+//! mapped to the frame shown at that time. Each frame covers the half open interval from its
+//! stream time up to, but not including, its stream time plus its duration.
+//!
+//! Frames are grouped into runs. A new run starts at the first frame after a format change
+//! or a discontinuity in the stream time, as `crate::segment` splits segments. Queries never
+//! join frames of different runs, so a gap left by dropped frames or a restarted stream is
+//! not covered by the frames on either side of it.
+
+use crate::time::{DecklinkFrameTiming, DecklinkTime};
+use std::collections::VecDeque;
+
+/// A frame recorded in a `FrameTimeline`.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+pub struct FrameLocator {
+    /// The sequence number the frame was recorded with.
+    pub sequence: u64,
+    /// The run the frame belongs to, counting from zero.
+    pub run: u64,
+    pub stream_time: DecklinkTime,
+    pub duration: DecklinkTime,
+    /// The hardware reference time of the frame, if it was recorded with one.
+    pub hardware_time: Option<DecklinkTime>,
+}
+
+impl FrameLocator {
+    /// The stream time just after the frame.
+    pub fn end(&self) -> DecklinkTime {
+        DecklinkTime::new(
+            self.stream_time.value.saturating_add(self.duration.value),
+            self.stream_time.scale,
+        )
+    }
+
+    /// `time` converted to the timescale of the frame, rounding to the nearest tick.
+    fn local(&self, time: DecklinkTime) -> Option<i64> {
+        time.rescale(self.stream_time.scale).map(|t| t.value)
+    }
+
+    /// Whether the frame was shown at `time`.
+    pub fn contains(&self, time: DecklinkTime) -> bool {
+        match self.local(time) {
+            Some(t) => t >= self.stream_time.value && t < self.end().value,
+            None => false,
+        }
+    }
+
+    /// Whether a frame with `timing` is the frame this locates, as when waiting for a frame
+    /// to arrive before capturing it.
+    pub fn matches(&self, timing: &DecklinkFrameTiming) -> bool {
+        match timing.stream_time.rescale(self.stream_time.scale) {
+            Some(t) => (t.value - self.stream_time.value).abs() <= 1,
+            None => false,
+        }
+    }
+
+    /// How far `time` is from the frame, in ticks of its timescale: zero if the frame
+    /// contains it, otherwise the distance to its nearest edge.
+    fn distance(&self, time: DecklinkTime) -> Option<i64> {
+        let t = self.local(time)?;
+        Some(if t < self.stream_time.value {
+            self.stream_time.value - t
+        } else if t >= self.end().value {
+            t - self.end().value
+        } else {
+            0
+        })
+    }
+}
+
+/// The intervals of the most recent frames of a capture.
+#[derive(Debug, Clone)]
+pub struct FrameTimeline {
+    capacity: usize,
+    frames: VecDeque<FrameLocator>,
+    /// The run of the next frame, or zero before the first frame.
+    next_run: u64,
+    /// Whether the next frame starts a new run whatever its stream time.
+    new_run: bool,
+    /// The stream time the next frame is expected at.
+    expected: Option<DecklinkTime>,
+}
+
+impl FrameTimeline {
+    /// Create a timeline that keeps the last `capacity` frames, and at least one.
+    pub fn new(capacity: usize) -> FrameTimeline {
+        let capacity = capacity.max(1);
+        FrameTimeline {
+            capacity,
+            frames: VecDeque::with_capacity(capacity),
+            next_run: 0,
+            new_run: false,
+            expected: None,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Note that the input format changed, so the next frame starts a new run.
+    pub fn format_changed(&mut self) {
+        self.new_run = true;
+    }
+
+    /// Note that frames were lost in a way the stream time does not show, such as frames
+    /// dropped in the input callback, so the next frame starts a new run.
+    pub fn discontinuity(&mut self) {
+        self.new_run = true;
+    }
+
+    fn is_discontinuous(&self, timing: &DecklinkFrameTiming) -> bool {
+        match self.expected {
+            Some(expected) => match expected.rescale(timing.stream_time.scale) {
+                Some(expected) => (timing.stream_time.value - expected.value).abs() > 1,
+                None => true,
+            },
+            None => false,
+        }
+    }
+
+    /// Record the next frame, with the sequence number it will be known by, such as its
+    /// index in a `crate::retention` or `crate::queue` buffer. The oldest frame is forgotten
+    /// once the timeline is full.
+    pub fn record(
+        &mut self,
+        sequence: u64,
+        timing: &DecklinkFrameTiming,
+        hardware_time: Option<DecklinkTime>,
+    ) -> FrameLocator {
+        if self.next_run == 0 || self.new_run || self.is_discontinuous(timing) {
+            self.next_run += 1;
+            self.new_run = false;
+        }
+
+        let frame = FrameLocator {
+            sequence,
+            run: self.next_run - 1,
+            stream_time: timing.stream_time,
+            duration: timing.duration,
+            hardware_time,
+        };
+        self.expected = Some(frame.end());
+
+        if self.frames.len() == self.capacity {
+            self.frames.pop_front();
+        }
+        self.frames.push_back(frame);
+        frame
+    }
+
+    /// The recorded frames, oldest first.
+    pub fn frames(&self) -> impl Iterator<Item = &FrameLocator> {
+        self.frames.iter()
+    }
+
+    /// The most recently recorded frame.
+    pub fn latest(&self) -> Option<FrameLocator> {
+        self.frames.back().copied()
+    }
+
+    /// The frame shown at `time`. If the stream time restarted, so that more than one run
+    /// covers `time`, the most recent frame is returned.
+    pub fn frame_at(&self, time: DecklinkTime) -> Option<FrameLocator> {
+        self.frames.iter().rev().find(|f| f.contains(time)).copied()
+    }
+
+    /// The frame nearest to `time`, if it is no further than `tolerance` from it. A frame
+    /// that contains `time` is always nearest. Of two frames equally near, as on either side
+    /// of a gap, the more recent is returned.
+    pub fn nearest(&self, time: DecklinkTime, tolerance: DecklinkTime) -> Option<FrameLocator> {
+        let mut nearest: Option<(i64, FrameLocator)> = None;
+        for frame in self.frames.iter().rev() {
+            let (Some(distance), Some(tolerance)) = (frame.distance(time), frame.local(tolerance))
+            else {
+                continue;
+            };
+            if distance > tolerance {
+                continue;
+            }
+            // Compare in seconds, as frames of different runs may not share a timescale
+            let seconds = distance as f64 / frame.stream_time.scale as f64;
+            let better = match nearest {
+                Some((d, ref f)) => seconds < d as f64 / f.stream_time.scale as f64,
+                None => true,
+            };
+            if better {
+                nearest = Some((distance, *frame));
+            }
+        }
+        nearest.map(|(_, frame)| frame)
+    }
+
+    /// The frames shown at any time from `from` up to, but not including, `to`, oldest first.
+    /// An empty range has no frames.
+    ///
+    /// Only frames of a single run are returned: the most recent run with a frame in the
+    /// range. Check the first and last frames returned to see whether they cover the whole
+    /// range, or whether it runs into a gap.
+    pub fn range(&self, from: DecklinkTime, to: DecklinkTime) -> Vec<FrameLocator> {
+        let overlaps = |f: &FrameLocator| match (f.local(from), f.local(to)) {
+            (Some(from), Some(to)) => from < to && f.stream_time.value < to && f.end().value > from,
+            _ => false,
+        };
+
+        let run = match self.frames.iter().rev().find(|f| overlaps(f)) {
+            Some(frame) => frame.run,
+            None => return Vec::new(),
+        };
+        self.frames
+            .iter()
+            .filter(|f| f.run == run && overlaps(f))
+            .copied()
+            .collect()
+    }
+}
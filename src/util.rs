@@ -1,8 +1,27 @@
 use num_traits::FromPrimitive;
 use std::ffi::CStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+#[cfg(feature = "leak-check")]
+pub(crate) use crate::debug::{track_created, track_dropped};
+
+#[cfg(not(feature = "leak-check"))]
+#[inline(always)]
+pub(crate) fn track_created<T>(_type_name: &'static str, _ptr: *const T) {}
+#[cfg(not(feature = "leak-check"))]
+#[inline(always)]
+pub(crate) fn track_dropped<T>(_type_name: &'static str, _ptr: *const T) {}
+
+#[cfg(feature = "realtime")]
+pub(crate) use crate::realtime::internal_thread_started;
+
+#[cfg(not(feature = "realtime"))]
+#[inline(always)]
+pub(crate) fn internal_thread_started(_role: &'static str) {}
 
 // TODO - refactor the error type to abstract away weird errors?
-#[derive(Debug, FromPrimitive)]
+#[derive(PartialEq, Eq, Debug, Copy, Clone, FromPrimitive)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[allow(overflowing_literals)]
 pub enum SdkError {
     FALSE = 0x0000_0001,
@@ -71,12 +90,22 @@ impl SdkError {
     }
 }
 
-pub(crate) unsafe fn convert_c_string(ptr: *const ::std::os::raw::c_char) -> String {
-    CStr::from_ptr(ptr).to_str().unwrap_or_default().to_string()
+static INVALID_UTF8_STRINGS: AtomicU64 = AtomicU64::new(0);
+
+/// The number of strings from the driver that were not valid UTF-8. Their invalid bytes
+/// are replaced with U+FFFD, so a device label in another encoding is still shown, if
+/// garbled.
+pub fn invalid_utf8_string_count() -> u64 {
+    INVALID_UTF8_STRINGS.load(Ordering::Relaxed)
 }
 
-pub(crate) unsafe fn convert_and_release_c_string(ptr: *const ::std::os::raw::c_char) -> String {
-    let str = convert_c_string(ptr);
-    crate::sdk::cdecklink_free_string(ptr);
-    str
+pub(crate) unsafe fn convert_c_string(ptr: *const ::std::os::raw::c_char) -> String {
+    let bytes = CStr::from_ptr(ptr).to_bytes();
+    match std::str::from_utf8(bytes) {
+        Ok(s) => s.to_string(),
+        Err(_) => {
+            INVALID_UTF8_STRINGS.fetch_add(1, Ordering::Relaxed);
+            String::from_utf8_lossy(bytes).into_owned()
+        }
+    }
 }
@@ -0,0 +1,394 @@
+//! Comparing the content of frames captured through redundant paths.
+//!
+//! Each frame is reduced to a `FrameFingerprint` of one hash per row. An `AsyncHasher` computes
+//! fingerprints on worker threads and feeds them to a `RedundancyComparator`, which matches
+//! frames from different sources by timestamp and reports a `Verdict` for each pair.
+
+use crate::frame::{DecklinkFrameBase, DecklinkPixelFormat, DecklinkVideoMutableFrame};
+use crate::threads::{self, ThreadHandle};
+use crate::util::internal_thread_started;
+use crate::SdkError;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+
+/// Identifies where a frame was captured from.
+pub type SourceId = u32;
+
+/// Hashes of the content of a frame, one per row.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct FrameFingerprint {
+    pub width: usize,
+    pub height: usize,
+    pub pixel_format: DecklinkPixelFormat,
+    pub row_hashes: Vec<u64>,
+}
+
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for b in bytes {
+        hash ^= *b as u64;
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    hash
+}
+
+impl FrameFingerprint {
+    /// Compute the fingerprint of a frame.
+    pub fn compute(frame: &dyn DecklinkFrameBase) -> Result<FrameFingerprint, SdkError> {
+        let bytes = frame.bytes()?;
+        let row_bytes = frame.row_bytes();
+        if row_bytes == 0 || bytes.0.len() < row_bytes * frame.height() {
+            return Err(SdkError::INVALIDARG);
+        }
+
+        let row_hashes = bytes
+            .0
+            .chunks_exact(row_bytes)
+            .take(frame.height())
+            .map(FrameFingerprint::hash_row)
+            .collect();
+
+        Ok(FrameFingerprint {
+            width: frame.width(),
+            height: frame.height(),
+            pixel_format: frame.pixel_format(),
+            row_hashes,
+        })
+    }
+
+    /// The hash of one row, as `compute` hashes each row, for checks that sample a few rows
+    /// of a frame rather than hashing all of them.
+    pub fn hash_row(row: &[u8]) -> u64 {
+        fnv1a(row)
+    }
+
+    /// The first row that differs between two fingerprints of the same format.
+    pub fn first_different_row(&self, other: &FrameFingerprint) -> Option<usize> {
+        self.row_hashes
+            .iter()
+            .zip(other.row_hashes.iter())
+            .position(|(a, b)| a != b)
+    }
+}
+
+/// The result of comparing matched frames from two sources.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum Verdict {
+    /// The frames at `timestamp` have identical content.
+    Identical {
+        sources: (SourceId, SourceId),
+        timestamp: i64,
+    },
+    /// The frames at `timestamp` differ, first at `first_different_row`.
+    Different {
+        sources: (SourceId, SourceId),
+        timestamp: i64,
+        first_different_row: usize,
+    },
+    /// The frames cannot be compared since their size or pixel format differ.
+    FormatMismatch {
+        sources: (SourceId, SourceId),
+        timestamp: i64,
+    },
+    /// `present` has a frame at `timestamp`, but `missing` had no frame within the window.
+    MissingCounterpart {
+        present: SourceId,
+        missing: SourceId,
+        timestamp: i64,
+    },
+}
+
+/// Rolling statistics for a pair of sources.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub struct PairStats {
+    pub identical: u64,
+    pub different: u64,
+    pub format_mismatches: u64,
+    pub missing: u64,
+    /// Number of consecutive missing counterparts, reset by any matched pair.
+    pub missing_streak: u64,
+}
+
+impl PairStats {
+    /// The fraction of matched pairs that were identical.
+    pub fn agreement(&self) -> f64 {
+        let total = self.identical + self.different + self.format_mismatches;
+        if total == 0 {
+            1.0
+        } else {
+            self.identical as f64 / total as f64
+        }
+    }
+}
+
+struct PendingFrame {
+    timestamp: i64,
+    fingerprint: Arc<FrameFingerprint>,
+    matched: HashSet<SourceId>,
+}
+
+fn pair_key(a: SourceId, b: SourceId) -> (SourceId, SourceId) {
+    if a <= b {
+        (a, b)
+    } else {
+        (b, a)
+    }
+}
+
+/// Matches fingerprints from different sources whose timestamps are within `window` of each
+/// other, and compares them.
+///
+/// A source is only expected to have counterparts once it has delivered its first frame, so
+/// sources may start at different times. Each frame is matched with the closest frame of the
+/// other source.
+pub struct RedundancyComparator {
+    window: i64,
+    pending: HashMap<SourceId, VecDeque<PendingFrame>>,
+    first: HashMap<SourceId, i64>,
+    latest: HashMap<SourceId, i64>,
+    stats: HashMap<(SourceId, SourceId), PairStats>,
+}
+
+impl RedundancyComparator {
+    pub fn new(window: i64) -> RedundancyComparator {
+        RedundancyComparator {
+            window: window.max(0),
+            pending: HashMap::new(),
+            first: HashMap::new(),
+            latest: HashMap::new(),
+            stats: HashMap::new(),
+        }
+    }
+
+    /// Get the statistics of a pair of sources.
+    pub fn stats(&self, a: SourceId, b: SourceId) -> PairStats {
+        self.stats.get(&pair_key(a, b)).copied().unwrap_or_default()
+    }
+
+    /// Add the fingerprint of a frame, returning any verdicts that can now be made.
+    pub fn insert(
+        &mut self,
+        source: SourceId,
+        timestamp: i64,
+        fingerprint: Arc<FrameFingerprint>,
+    ) -> Vec<Verdict> {
+        let mut verdicts = Vec::new();
+        let mut entry = PendingFrame {
+            timestamp,
+            fingerprint,
+            matched: HashSet::new(),
+        };
+
+        for (other, frames) in self.pending.iter_mut() {
+            if *other == source {
+                continue;
+            }
+            let counterpart = frames
+                .iter_mut()
+                .filter(|f| {
+                    !f.matched.contains(&source) && (f.timestamp - timestamp).abs() <= self.window
+                })
+                .min_by_key(|f| (f.timestamp - timestamp).abs());
+            if let Some(counterpart) = counterpart {
+                counterpart.matched.insert(source);
+                entry.matched.insert(*other);
+
+                let sources = pair_key(source, *other);
+                let a = &entry.fingerprint;
+                let b = &counterpart.fingerprint;
+                let verdict = if a.width != b.width
+                    || a.height != b.height
+                    || a.pixel_format != b.pixel_format
+                {
+                    Verdict::FormatMismatch { sources, timestamp }
+                } else if let Some(row) = a.first_different_row(b) {
+                    Verdict::Different {
+                        sources,
+                        timestamp,
+                        first_different_row: row,
+                    }
+                } else {
+                    Verdict::Identical { sources, timestamp }
+                };
+                verdicts.push(verdict);
+            }
+        }
+
+        self.first.entry(source).or_insert(timestamp);
+        let latest = self.latest.entry(source).or_insert(timestamp);
+        *latest = (*latest).max(timestamp);
+        self.pending.entry(source).or_default().push_back(entry);
+
+        self.expire(&mut verdicts);
+        for verdict in &verdicts {
+            self.record(verdict);
+        }
+        verdicts
+    }
+
+    /// Drop frames that can no longer be matched, reporting missing counterparts.
+    fn expire(&mut self, verdicts: &mut Vec<Verdict>) {
+        let window = self.window;
+        let (first, latest) = (&self.first, &self.latest);
+        for (source, frames) in self.pending.iter_mut() {
+            while let Some(front) = frames.front() {
+                // Until another source starts, a frame is only kept for as long as one could
+                // still match it
+                let mut others = latest
+                    .iter()
+                    .filter(|(other, _)| *other != source)
+                    .peekable();
+                let expired = if others.peek().is_none() {
+                    latest[source] > front.timestamp + window
+                } else {
+                    others.all(|(_, latest)| *latest > front.timestamp + window)
+                };
+                if !expired {
+                    break;
+                }
+
+                for (other, other_first) in first {
+                    // A source that started after the frame is not missing it
+                    if other != source
+                        && *other_first <= front.timestamp + window
+                        && !front.matched.contains(other)
+                    {
+                        verdicts.push(Verdict::MissingCounterpart {
+                            present: *source,
+                            missing: *other,
+                            timestamp: front.timestamp,
+                        });
+                    }
+                }
+                frames.pop_front();
+            }
+        }
+    }
+
+    fn record(&mut self, verdict: &Verdict) {
+        let key = match verdict {
+            Verdict::Identical { sources, .. }
+            | Verdict::Different { sources, .. }
+            | Verdict::FormatMismatch { sources, .. } => *sources,
+            Verdict::MissingCounterpart {
+                present, missing, ..
+            } => pair_key(*present, *missing),
+        };
+        let stats = self.stats.entry(key).or_default();
+        match verdict {
+            Verdict::Identical { .. } => {
+                stats.identical += 1;
+                stats.missing_streak = 0;
+            }
+            Verdict::Different { .. } => {
+                stats.different += 1;
+                stats.missing_streak = 0;
+            }
+            Verdict::FormatMismatch { .. } => {
+                stats.format_mismatches += 1;
+                stats.missing_streak = 0;
+            }
+            Verdict::MissingCounterpart { .. } => {
+                stats.missing += 1;
+                stats.missing_streak += 1;
+            }
+        }
+    }
+}
+
+struct HashJob {
+    source: SourceId,
+    timestamp: i64,
+    frame: DecklinkVideoMutableFrame,
+}
+
+/// Computes frame fingerprints on a pool of worker threads, and feeds them to a shared
+/// `RedundancyComparator`.
+///
+/// Submitting only queues the frame, so it is cheap enough to call from an input callback.
+/// Verdicts are delivered to the receiver returned by `new`. Fingerprint errors are dropped.
+pub struct AsyncHasher {
+    jobs: Option<Sender<HashJob>>,
+    workers: Vec<ThreadHandle>,
+    comparator: Arc<Mutex<RedundancyComparator>>,
+}
+
+impl AsyncHasher {
+    pub fn new(
+        comparator: RedundancyComparator,
+        worker_count: usize,
+    ) -> (AsyncHasher, Receiver<Verdict>) {
+        let (job_tx, job_rx) = channel::<HashJob>();
+        let (verdict_tx, verdict_rx) = channel();
+        let job_rx = Arc::new(Mutex::new(job_rx));
+        let comparator = Arc::new(Mutex::new(comparator));
+
+        let workers = (0..worker_count.max(1))
+            .map(|_| {
+                let job_rx = job_rx.clone();
+                let verdict_tx = verdict_tx.clone();
+                let comparator = comparator.clone();
+                threads::spawn("hasher", move || {
+                    internal_thread_started("hasher");
+                    loop {
+                        let job = match job_rx.lock().unwrap().recv() {
+                            Ok(job) => job,
+                            Err(_) => return,
+                        };
+                        if let Ok(fingerprint) = FrameFingerprint::compute(&job.frame) {
+                            let verdicts = comparator.lock().unwrap().insert(
+                                job.source,
+                                job.timestamp,
+                                Arc::new(fingerprint),
+                            );
+                            for verdict in verdicts {
+                                let _ = verdict_tx.send(verdict);
+                            }
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        (
+            AsyncHasher {
+                jobs: Some(job_tx),
+                workers,
+                comparator,
+            },
+            verdict_rx,
+        )
+    }
+
+    /// Queue a frame from `source` for hashing.
+    pub fn submit(
+        &self,
+        source: SourceId,
+        timestamp: i64,
+        frame: DecklinkVideoMutableFrame,
+    ) -> Result<(), SdkError> {
+        let jobs = self.jobs.as_ref().ok_or(SdkError::HANDLE)?;
+        jobs.send(HashJob {
+            source,
+            timestamp,
+            frame,
+        })
+        .map_err(|_| SdkError::HANDLE)
+    }
+
+    /// Get the statistics of a pair of sources.
+    pub fn stats(&self, a: SourceId, b: SourceId) -> PairStats {
+        self.comparator.lock().unwrap().stats(a, b)
+    }
+}
+
+impl Drop for AsyncHasher {
+    fn drop(&mut self) {
+        // Closing the queue stops the workers once it is drained
+        self.jobs = None;
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
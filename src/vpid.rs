@@ -0,0 +1,548 @@
+//! The video payload identifier of SMPTE ST 352, which an SDI source carries in the vertical
+//! ancillary space of each frame to say how the picture is mapped onto the link.
+//!
+//! The VPID tells apart signals that detect as the same display mode: 1080p50 over 3G Level A
+//! or Level B, or 2160p over four 3G links or one 12G link. It is read from the ancillary
+//! packets of captured frames, and the input keeps the one of its signal, read again as the
+//! signal changes. `DecklinkVpidExt` gives both.
+//!
+//! Each byte is decoded with the tables of ST 352 and the interface standards that extend
+//! them. A code the tables do not have decodes to the `Unknown` form of its field, holding the
+//! raw value, and `Vpid::bytes` always keeps the payload as it was received.
+
+use crate::colorimetry::{Colorimetry, TransferFunction};
+use crate::device::input::DecklinkInputDevice;
+use crate::frame::{DecklinkFrameBase, DecklinkFrameFlags, DecklinkVideoFrame};
+use crate::ptr::AncillaryPacketsPtr;
+use crate::SdkError;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{PoisonError, RwLock, TryLockError};
+
+/// The payload and picture the first byte of a VPID identifies, by the interface standard that
+/// carries it.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+pub enum VpidStandard {
+    /// 483 or 576 line video at 270 Mb/s, SMPTE ST 259.
+    Sd,
+    /// 483 or 576 line video at 540 Mb/s, SMPTE ST 344.
+    Sd540,
+    /// 720 line video at 1.5 Gb/s, SMPTE ST 292-1.
+    Hd720,
+    /// 1080 line video at 1.5 Gb/s, SMPTE ST 292-1.
+    Hd1080,
+    /// 1080 line video over two 1.5 Gb/s links, SMPTE ST 372.
+    Hd1080DualLink,
+    /// 720 line video at 3 Gb/s Level A, SMPTE ST 425-1.
+    Hd720Level3A,
+    /// 1080 line video at 3 Gb/s Level A, SMPTE ST 425-1.
+    Hd1080Level3A,
+    /// 1080 line video of a dual link mapped onto 3 Gb/s Level B, SMPTE ST 425-1.
+    Hd1080Level3B,
+    /// Two 720 line streams at 3 Gb/s Level B, SMPTE ST 425-1.
+    Hd720Level3BDualStream,
+    /// Two 1080 line streams at 3 Gb/s Level B, SMPTE ST 425-1.
+    Hd1080Level3BDualStream,
+    /// Two 483 or 576 line streams at 3 Gb/s Level B, SMPTE ST 425-1.
+    SdLevel3BDualStream,
+    /// 1080 line video over two 3 Gb/s Level A links, SMPTE ST 425-3.
+    Hd1080Dual3A,
+    /// 1080 line video over two 3 Gb/s Level B links, SMPTE ST 425-3.
+    Hd1080Dual3B,
+    /// 2160 line video over four 3 Gb/s Level A links, SMPTE ST 425-5.
+    Uhd2160Quad3A,
+    /// 2160 line video over four 3 Gb/s Level B links, SMPTE ST 425-5.
+    Uhd2160Quad3B,
+    /// 2160 line video at 6 Gb/s, SMPTE ST 2081-10.
+    Uhd2160Level6G,
+    /// 1080 line video at 6 Gb/s, SMPTE ST 2081-10.
+    Hd1080Level6G,
+    /// 2160 line video at 12 Gb/s, SMPTE ST 2082-10.
+    Uhd2160Level12G,
+    /// A first byte the tables do not have, or a version 0 payload, as received.
+    Unknown(u8),
+}
+
+impl VpidStandard {
+    /// The standard of the first byte of a payload. Only version 1 payloads, with the top bit
+    /// set, are decoded.
+    pub fn from_byte(byte: u8) -> VpidStandard {
+        match byte {
+            0x81 => VpidStandard::Sd,
+            0x82 => VpidStandard::Sd540,
+            0x84 => VpidStandard::Hd720,
+            0x85 => VpidStandard::Hd1080,
+            0x87 => VpidStandard::Hd1080DualLink,
+            0x88 => VpidStandard::Hd720Level3A,
+            0x89 => VpidStandard::Hd1080Level3A,
+            0x8A => VpidStandard::Hd1080Level3B,
+            0x8B => VpidStandard::Hd720Level3BDualStream,
+            0x8C => VpidStandard::Hd1080Level3BDualStream,
+            0x8D => VpidStandard::SdLevel3BDualStream,
+            0x94 => VpidStandard::Hd1080Dual3A,
+            0x95 => VpidStandard::Hd1080Dual3B,
+            0x98 => VpidStandard::Uhd2160Quad3A,
+            0x99 => VpidStandard::Uhd2160Quad3B,
+            0xC0 => VpidStandard::Uhd2160Level6G,
+            0xC1 => VpidStandard::Hd1080Level6G,
+            0xCE => VpidStandard::Uhd2160Level12G,
+            byte => VpidStandard::Unknown(byte),
+        }
+    }
+
+    /// The name engineers give the transport, as `Vpid::describe` starts with.
+    pub fn transport(&self) -> &'static str {
+        match self {
+            VpidStandard::Sd => "SD",
+            VpidStandard::Sd540 => "SD 540M",
+            VpidStandard::Hd720 | VpidStandard::Hd1080 => "HD",
+            VpidStandard::Hd1080DualLink => "HD dual-link",
+            VpidStandard::Hd720Level3A | VpidStandard::Hd1080Level3A => "3G-A",
+            VpidStandard::Hd1080Level3B => "3G-B",
+            VpidStandard::Hd720Level3BDualStream
+            | VpidStandard::Hd1080Level3BDualStream
+            | VpidStandard::SdLevel3BDualStream => "3G-B dual-stream",
+            VpidStandard::Hd1080Dual3A => "dual 3G-A",
+            VpidStandard::Hd1080Dual3B => "dual 3G-B",
+            VpidStandard::Uhd2160Quad3A => "quad 3G-A",
+            VpidStandard::Uhd2160Quad3B => "quad 3G-B",
+            VpidStandard::Uhd2160Level6G | VpidStandard::Hd1080Level6G => "6G",
+            VpidStandard::Uhd2160Level12G => "12G",
+            VpidStandard::Unknown(_) => "unknown",
+        }
+    }
+
+    /// The active lines of the picture, or `None` for standard definition, whose line count
+    /// follows its rate, and for unknown standards.
+    pub fn lines(&self) -> Option<u32> {
+        match self {
+            VpidStandard::Hd720
+            | VpidStandard::Hd720Level3A
+            | VpidStandard::Hd720Level3BDualStream => Some(720),
+            VpidStandard::Hd1080
+            | VpidStandard::Hd1080DualLink
+            | VpidStandard::Hd1080Level3A
+            | VpidStandard::Hd1080Level3B
+            | VpidStandard::Hd1080Level3BDualStream
+            | VpidStandard::Hd1080Dual3A
+            | VpidStandard::Hd1080Dual3B
+            | VpidStandard::Hd1080Level6G => Some(1080),
+            VpidStandard::Uhd2160Quad3A
+            | VpidStandard::Uhd2160Quad3B
+            | VpidStandard::Uhd2160Level6G
+            | VpidStandard::Uhd2160Level12G => Some(2160),
+            _ => None,
+        }
+    }
+
+    /// The number of physical links the picture is carried over.
+    pub fn links(&self) -> u8 {
+        match self {
+            VpidStandard::Hd1080DualLink
+            | VpidStandard::Hd1080Dual3A
+            | VpidStandard::Hd1080Dual3B => 2,
+            VpidStandard::Uhd2160Quad3A | VpidStandard::Uhd2160Quad3B => 4,
+            _ => 1,
+        }
+    }
+}
+
+/// How the picture and its transport are scanned, from bits 7 and 6 of the second byte.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+pub enum VpidScan {
+    Interlaced,
+    Progressive,
+    /// A progressive picture carried as two segments of an interlaced transport.
+    SegmentedFrame,
+    /// The two bits as received, for a progressive transport of an interlaced picture, which
+    /// ST 352 does not define.
+    Unknown(u8),
+}
+
+/// The frame rate of the picture, from the low four bits of the second byte.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+pub enum VpidPictureRate {
+    Rate23_98,
+    Rate24,
+    Rate47_95,
+    Rate25,
+    Rate29_97,
+    Rate30,
+    Rate48,
+    Rate50,
+    Rate59_94,
+    Rate60,
+    Rate96,
+    Rate100,
+    Rate119_88,
+    Rate120,
+    /// A code that names no rate, as received.
+    Unknown(u8),
+}
+
+impl VpidPictureRate {
+    fn from_code(code: u8) -> VpidPictureRate {
+        match code {
+            0x2 => VpidPictureRate::Rate23_98,
+            0x3 => VpidPictureRate::Rate24,
+            0x4 => VpidPictureRate::Rate47_95,
+            0x5 => VpidPictureRate::Rate25,
+            0x6 => VpidPictureRate::Rate29_97,
+            0x7 => VpidPictureRate::Rate30,
+            0x8 => VpidPictureRate::Rate48,
+            0x9 => VpidPictureRate::Rate50,
+            0xA => VpidPictureRate::Rate59_94,
+            0xB => VpidPictureRate::Rate60,
+            0xC => VpidPictureRate::Rate96,
+            0xD => VpidPictureRate::Rate100,
+            0xE => VpidPictureRate::Rate119_88,
+            0xF => VpidPictureRate::Rate120,
+            code => VpidPictureRate::Unknown(code),
+        }
+    }
+
+    /// The frames a second, as a numerator and denominator.
+    pub fn frame_rate(&self) -> Option<(u32, u32)> {
+        Some(match self {
+            VpidPictureRate::Rate23_98 => (24000, 1001),
+            VpidPictureRate::Rate24 => (24, 1),
+            VpidPictureRate::Rate47_95 => (48000, 1001),
+            VpidPictureRate::Rate25 => (25, 1),
+            VpidPictureRate::Rate29_97 => (30000, 1001),
+            VpidPictureRate::Rate30 => (30, 1),
+            VpidPictureRate::Rate48 => (48, 1),
+            VpidPictureRate::Rate50 => (50, 1),
+            VpidPictureRate::Rate59_94 => (60000, 1001),
+            VpidPictureRate::Rate60 => (60, 1),
+            VpidPictureRate::Rate96 => (96, 1),
+            VpidPictureRate::Rate100 => (100, 1),
+            VpidPictureRate::Rate119_88 => (120000, 1001),
+            VpidPictureRate::Rate120 => (120, 1),
+            VpidPictureRate::Unknown(_) => return None,
+        })
+    }
+}
+
+/// The sampling structure of the picture, from the low four bits of the third byte.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+pub enum VpidSampling {
+    YCbCr422,
+    YCbCr444,
+    Gbr444,
+    YCbCr420,
+    YCbCrA4224,
+    YCbCrA4444,
+    GbrA4444,
+    /// 4:2:2 YCbCr with a data channel in place of alpha.
+    YCbCrD4224,
+    YCbCrD4444,
+    GbrD4444,
+    Xyz444,
+    /// A reserved code, as received.
+    Unknown(u8),
+}
+
+impl VpidSampling {
+    fn from_code(code: u8) -> VpidSampling {
+        match code {
+            0x0 => VpidSampling::YCbCr422,
+            0x1 => VpidSampling::YCbCr444,
+            0x2 => VpidSampling::Gbr444,
+            0x3 => VpidSampling::YCbCr420,
+            0x4 => VpidSampling::YCbCrA4224,
+            0x5 => VpidSampling::YCbCrA4444,
+            0x6 => VpidSampling::GbrA4444,
+            0x8 => VpidSampling::YCbCrD4224,
+            0x9 => VpidSampling::YCbCrD4444,
+            0xA => VpidSampling::GbrD4444,
+            0xE => VpidSampling::Xyz444,
+            code => VpidSampling::Unknown(code),
+        }
+    }
+
+    /// The components and their sampling, as "YCbCr 4:2:2".
+    pub fn name(&self) -> &'static str {
+        match self {
+            VpidSampling::YCbCr422 => "YCbCr 4:2:2",
+            VpidSampling::YCbCr444 => "YCbCr 4:4:4",
+            VpidSampling::Gbr444 => "GBR 4:4:4",
+            VpidSampling::YCbCr420 => "YCbCr 4:2:0",
+            VpidSampling::YCbCrA4224 => "YCbCrA 4:2:2:4",
+            VpidSampling::YCbCrA4444 => "YCbCrA 4:4:4:4",
+            VpidSampling::GbrA4444 => "GBRA 4:4:4:4",
+            VpidSampling::YCbCrD4224 => "YCbCrD 4:2:2:4",
+            VpidSampling::YCbCrD4444 => "YCbCrD 4:4:4:4",
+            VpidSampling::GbrD4444 => "GBRD 4:4:4:4",
+            VpidSampling::Xyz444 => "XYZ 4:4:4",
+            VpidSampling::Unknown(_) => "unknown sampling",
+        }
+    }
+}
+
+/// The bits of each sample, from the low two bits of the fourth byte.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+pub enum VpidBitDepth {
+    Bits8,
+    Bits10,
+    Bits12,
+    /// The reserved code, as received.
+    Unknown(u8),
+}
+
+impl VpidBitDepth {
+    pub fn bits(&self) -> Option<u8> {
+        match self {
+            VpidBitDepth::Bits8 => Some(8),
+            VpidBitDepth::Bits10 => Some(10),
+            VpidBitDepth::Bits12 => Some(12),
+            VpidBitDepth::Unknown(_) => None,
+        }
+    }
+}
+
+/// A decoded SMPTE ST 352 video payload identifier.
+#[derive(PartialEq, Eq, Debug, Copy, Clone, Hash)]
+pub struct Vpid {
+    /// The payload as received.
+    pub bytes: [u8; 4],
+    pub standard: VpidStandard,
+    pub scan: VpidScan,
+    pub picture_rate: VpidPictureRate,
+    pub sampling: VpidSampling,
+    /// The colorimetry of bits 5 and 4 of the third byte, or `None` for the code reserved for
+    /// colorimetry signalled elsewhere and for unknown colorimetry. Sources that predate the
+    /// field leave it clear, which reads as Rec. 709.
+    pub colorimetry: Option<Colorimetry>,
+    /// The transfer function of bits 5 and 4 of the second byte, or `None` if unspecified.
+    /// Sources that predate the field leave it clear, which reads as SDR.
+    pub transfer: Option<TransferFunction>,
+    pub bit_depth: VpidBitDepth,
+    /// The link of a multi-link transport this payload was read from, from 1, out of the
+    /// `VpidStandard::links` of the standard. Single links are link 1.
+    pub link: u8,
+}
+
+impl Vpid {
+    /// The data identifier of the ancillary packet that carries the payload.
+    pub const DID: u8 = 0x41;
+    /// The secondary data identifier of the ancillary packet that carries the payload.
+    pub const SDID: u8 = 0x01;
+
+    /// Decode the four bytes of a payload.
+    pub fn decode(bytes: [u8; 4]) -> Vpid {
+        let [byte1, byte2, byte3, byte4] = bytes;
+        let scan = match byte2 >> 6 {
+            0b00 => VpidScan::Interlaced,
+            0b11 => VpidScan::Progressive,
+            0b01 => VpidScan::SegmentedFrame,
+            bits => VpidScan::Unknown(bits),
+        };
+        let colorimetry = match (byte3 >> 4) & 0b11 {
+            0b00 => Some(Colorimetry::Rec709),
+            0b10 => Some(Colorimetry::Rec2020),
+            _ => None,
+        };
+        let transfer = match (byte2 >> 4) & 0b11 {
+            0b00 => Some(TransferFunction::Sdr),
+            0b01 => Some(TransferFunction::Hlg),
+            0b10 => Some(TransferFunction::Pq),
+            _ => None,
+        };
+        let bit_depth = match byte4 & 0b11 {
+            0b00 => VpidBitDepth::Bits8,
+            0b01 => VpidBitDepth::Bits10,
+            0b10 => VpidBitDepth::Bits12,
+            bits => VpidBitDepth::Unknown(bits),
+        };
+        let standard = VpidStandard::from_byte(byte1);
+        Vpid {
+            bytes,
+            standard,
+            scan,
+            picture_rate: VpidPictureRate::from_code(byte2 & 0x0F),
+            sampling: VpidSampling::from_code(byte3 & 0x0F),
+            colorimetry,
+            transfer,
+            bit_depth,
+            // Dual links number theirs with bit 6, and quad links with bits 7 and 6
+            link: ((byte4 >> 6) & (standard.links() - 1)) + 1,
+        }
+    }
+
+    /// Decode the user data words of an ancillary packet, or `None` if it has fewer than four.
+    pub fn from_packet(data: &[u8]) -> Option<Vpid> {
+        let bytes: [u8; 4] = data.get(..4)?.try_into().ok()?;
+        Some(Vpid::decode(bytes))
+    }
+
+    /// The picture, as "1080p50", "1080i50" or "1080PsF25". Interlaced pictures are named by
+    /// their field rate and segmented frames by their frame rate, as engineers name them.
+    /// Standard definition is 480 or 576 lines by its rate.
+    pub fn picture(&self) -> String {
+        let lines = match (self.standard.lines(), self.standard) {
+            (Some(lines), _) => lines.to_string(),
+            (None, VpidStandard::Unknown(_)) => "?".to_string(),
+            (None, _) if self.picture_rate == VpidPictureRate::Rate25 => "576".to_string(),
+            (None, _) => "480".to_string(),
+        };
+        let (scan, fields) = match self.scan {
+            VpidScan::Progressive => ("p", 1),
+            VpidScan::Interlaced => ("i", 2),
+            VpidScan::SegmentedFrame => ("PsF", 1),
+            VpidScan::Unknown(_) => ("?", 1),
+        };
+        let rate = match self.picture_rate.frame_rate() {
+            Some((numerator, denominator)) => {
+                let numerator = numerator * fields;
+                if numerator % denominator == 0 {
+                    (numerator / denominator).to_string()
+                } else {
+                    format!("{:.2}", numerator as f64 / denominator as f64)
+                }
+            }
+            None => "?".to_string(),
+        };
+        format!("{}{}{}", lines, scan, rate)
+    }
+
+    /// The transport description engineers expect, as "3G-B 1080p50 YCbCr 4:2:2 10-bit".
+    /// Colorimetry other than Rec. 709, a transfer function other than SDR and the link of a
+    /// multi-link transport follow. An unknown standard is given by its first byte.
+    pub fn describe(&self) -> String {
+        let mut description = match self.standard {
+            VpidStandard::Unknown(byte) => format!("unknown 0x{:02X}", byte),
+            standard => standard.transport().to_string(),
+        };
+        description.push(' ');
+        description.push_str(&self.picture());
+        description.push(' ');
+        description.push_str(self.sampling.name());
+        match self.bit_depth.bits() {
+            Some(bits) => description.push_str(&format!(" {}-bit", bits)),
+            None => description.push_str(" unknown depth"),
+        }
+        if self.colorimetry == Some(Colorimetry::Rec2020) {
+            description.push_str(" BT.2020");
+        }
+        match self.transfer {
+            Some(TransferFunction::Hlg) => description.push_str(" HLG"),
+            Some(TransferFunction::Pq) => description.push_str(" PQ"),
+            _ => {}
+        }
+        if self.standard.links() > 1 {
+            description.push_str(&format!(" link {}", self.link));
+        }
+        description
+    }
+}
+
+impl fmt::Display for Vpid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [byte1, byte2, byte3, byte4] = self.bytes;
+        write!(
+            f,
+            "{} ({:02X} {:02X} {:02X} {:02X})",
+            self.describe(),
+            byte1,
+            byte2,
+            byte3,
+            byte4
+        )
+    }
+}
+
+/// Read the VPID from the ancillary packets of a frame, or `None` if they have none.
+pub(crate) fn read(packets: &AncillaryPacketsPtr) -> Result<Option<Vpid>, SdkError> {
+    match packets.first_by_id(Vpid::DID, Vpid::SDID)? {
+        Some(packet) => Ok(Vpid::from_packet(&packet.bytes()?)),
+        None => Ok(None),
+    }
+}
+
+/// The SMPTE ST 352 payload identifier of a captured frame, or of the signal an input
+/// captures.
+pub trait DecklinkVpidExt {
+    fn vpid(&self) -> Result<Option<Vpid>, SdkError>;
+}
+
+impl DecklinkVpidExt for DecklinkVideoFrame {
+    /// Get the payload identifier the frame was captured with, or `None` if its ancillary
+    /// packets have none, as frames of an HDMI input or a source that does not insert one do
+    /// not.
+    fn vpid(&self) -> Result<Option<Vpid>, SdkError> {
+        assert!(!self.ptr().is_null());
+
+        let packets = unsafe { AncillaryPacketsPtr::of_frame(self.ptr()) }?;
+        packets.map_or(Ok(None), |packets| read(&packets))
+    }
+}
+
+impl DecklinkVpidExt for DecklinkInputDevice {
+    /// Get the payload identifier of the signal being captured, or `None` if it has none or
+    /// video input is disabled. It names the transport of the signal.
+    ///
+    /// It is not read from every frame passed to a handler, but from the first after video
+    /// input is enabled, after a format change, and after the signal is lost or comes back.
+    ///
+    /// Fails with the error of reading that frame's ancillary packets, as `SdkError::NOTIMPL`
+    /// from a driver without them.
+    fn vpid(&self) -> Result<Option<Vpid>, SdkError> {
+        self.signal_vpid()
+    }
+}
+
+/// The VPID an input keeps for `DecklinkVpidExt::vpid`, shared between the input and its
+/// callback.
+///
+/// Reading the ancillary packets of every frame is not free, and a source only changes its
+/// payload identifier with its signal, so it is read from the first frame after video input is
+/// enabled, after a format change, and when the signal comes or goes. The lock is never waited
+/// on in the callback: when it is held, the payload is read again from the next frame.
+pub(crate) struct VpidTracker {
+    last: RwLock<Result<Option<Vpid>, SdkError>>,
+    /// Whether the payload is to be read from the next frame.
+    stale: AtomicBool,
+    /// Whether the last frame read had no input source.
+    no_source: AtomicBool,
+}
+
+impl VpidTracker {
+    pub(crate) fn new() -> VpidTracker {
+        VpidTracker {
+            last: RwLock::new(Ok(None)),
+            stale: AtomicBool::new(true),
+            no_source: AtomicBool::new(false),
+        }
+    }
+
+    pub(crate) fn get(&self) -> Result<Option<Vpid>, SdkError> {
+        *self.last.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Forget the payload, as video input is disabled, reading it again from the next frame.
+    pub(crate) fn clear(&self) {
+        *self.last.write().unwrap_or_else(PoisonError::into_inner) = Ok(None);
+        self.stale.store(true, Ordering::Relaxed);
+    }
+
+    /// Read the payload again from the next frame, as the format of the signal changed.
+    pub(crate) fn invalidate(&self) {
+        self.stale.store(true, Ordering::Relaxed);
+    }
+
+    /// Read the payload of a frame passed to the callback, if it is due.
+    pub(crate) fn frame_arrived(&self, frame: &DecklinkVideoFrame) {
+        let no_source = frame
+            .flags()
+            .contains(DecklinkFrameFlags::HAS_NO_INPUT_SOURCE);
+        let signal_changed = self.no_source.swap(no_source, Ordering::Relaxed) != no_source;
+        if !self.stale.swap(false, Ordering::Relaxed) && !signal_changed {
+            return;
+        }
+        match self.last.try_write() {
+            Ok(mut last) => *last = frame.vpid(),
+            Err(TryLockError::Poisoned(e)) => *e.into_inner() = frame.vpid(),
+            Err(TryLockError::WouldBlock) => self.stale.store(true, Ordering::Relaxed),
+        }
+    }
+}
@@ -0,0 +1,491 @@
+//! One allocator provider serving concurrent requests, from the threads of one mock device
+//! and from several mock devices, and a driver changing the buffer spec of a mode.
+#![cfg(feature = "mock-backend")]
+
+mod common;
+
+use decklink::allocator::{
+    self, null_buffer_count, AllocatorEvent, BufferSpec, MeteredProvider, VideoBuffer,
+    VideoBufferAllocator, VideoBufferAllocatorProvider,
+};
+use decklink::device::get_devices;
+use decklink::device::input::{
+    CallbackResult, DeckLinkInputCallback, DecklinkDetectedVideoInputFormatFlags,
+    DecklinkInputDevice, DecklinkVideoInputFlags, DecklinkVideoInputFormatChangedEvents,
+    FrameArrival, InputHandler,
+};
+use decklink::display_mode::DecklinkDisplayModeId;
+use decklink::frame::{DecklinkPixelFormat, DecklinkVideoFrame};
+use decklink::mock::{MockBackend, MockDevice, MockFrame};
+use decklink::{ApiVersion, SdkError};
+use std::collections::HashMap;
+use std::ffi::c_void;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Barrier, Mutex};
+use std::time::{Duration, Instant};
+
+const FORMAT: DecklinkPixelFormat = DecklinkPixelFormat::Format8BitYUV;
+/// Frames this wide take `SLOW` to get an allocator for, and others no time at all.
+const SLOW_WIDTH: usize = 48;
+const FAST_WIDTH: usize = 64;
+const SLOW: Duration = Duration::from_millis(300);
+
+struct HeapBuffer(Mutex<Vec<u8>>);
+
+impl VideoBuffer for HeapBuffer {
+    fn get_bytes(&self) -> Result<*mut c_void, SdkError> {
+        Ok(self.0.lock().unwrap().as_mut_ptr() as *mut c_void)
+    }
+}
+
+struct HeapAllocator(usize);
+
+impl VideoBufferAllocator for HeapAllocator {
+    fn allocate(&self) -> Result<Box<dyn VideoBuffer>, SdkError> {
+        Ok(Box::new(HeapBuffer(Mutex::new(vec![0; self.0]))))
+    }
+}
+
+/// Records the spec of every allocator it creates, taking `SLOW` for wide frames.
+#[derive(Default)]
+struct SlowProvider {
+    created: Mutex<Vec<BufferSpec>>,
+}
+
+impl SlowProvider {
+    fn created(&self, width: usize) -> usize {
+        let created = self.created.lock().unwrap();
+        created.iter().filter(|s| s.width as usize == width).count()
+    }
+}
+
+impl VideoBufferAllocatorProvider for SlowProvider {
+    fn get_allocator(&self, spec: BufferSpec) -> Result<Arc<dyn VideoBufferAllocator>, SdkError> {
+        if spec.width as usize == SLOW_WIDTH {
+            std::thread::sleep(SLOW);
+        }
+        self.created.lock().unwrap().push(spec);
+        Ok(Arc::new(HeapAllocator(spec.buffer_size as usize)))
+    }
+}
+
+/// Gives buffers that break their contract with a null pointer.
+struct NullProvider;
+
+struct NullBuffer;
+
+impl VideoBuffer for NullBuffer {
+    fn get_bytes(&self) -> Result<*mut c_void, SdkError> {
+        Ok(std::ptr::null_mut())
+    }
+}
+
+impl VideoBufferAllocator for NullProvider {
+    fn allocate(&self) -> Result<Box<dyn VideoBuffer>, SdkError> {
+        Ok(Box::new(NullBuffer))
+    }
+}
+
+impl VideoBufferAllocatorProvider for NullProvider {
+    fn get_allocator(&self, _spec: BufferSpec) -> Result<Arc<dyn VideoBufferAllocator>, SdkError> {
+        Ok(Arc::new(NullProvider))
+    }
+}
+
+#[derive(Default)]
+struct Capture {
+    frames: AtomicUsize,
+}
+
+impl DeckLinkInputCallback for Capture {
+    fn video_input_format_changed(
+        &self,
+        _events: DecklinkVideoInputFormatChangedEvents,
+        _new_display_mode: DecklinkDisplayModeId,
+        _detected_signal_flags: DecklinkDetectedVideoInputFormatFlags,
+    ) {
+    }
+
+    fn video_input_frame_arrived(&self, video_frame: Option<DecklinkVideoFrame>) -> bool {
+        let frame = video_frame.unwrap();
+        assert!(frame.bytes_to_vec().unwrap().iter().all(|b| *b == 0x80));
+        self.frames.fetch_add(1, Ordering::SeqCst);
+        true
+    }
+}
+
+/// Sub-devices of one card, with drivers new enough to take an allocator provider.
+fn backend(devices: usize) -> MockBackend {
+    let backend = MockBackend::install(
+        (0..devices)
+            .map(|i| MockDevice::new(&format!("DeckLink Duo 2 ({})", i + 1)))
+            .collect(),
+    );
+    backend.set_api_version(Some(ApiVersion::new(14, 3, 0)));
+    backend
+}
+
+/// Start capturing from every device through `provider`.
+fn start(
+    provider: Arc<dyn VideoBufferAllocatorProvider>,
+) -> (Vec<DecklinkInputDevice>, Arc<Capture>) {
+    let capture = Arc::new(Capture::default());
+    let inputs = get_devices()
+        .unwrap()
+        .iter()
+        .map(|device| {
+            let mut input = device.input().unwrap();
+            input
+                .enable_video_input_with_allocator(
+                    DecklinkDisplayModeId::NTSC,
+                    FORMAT,
+                    DecklinkVideoInputFlags::empty(),
+                    provider.clone(),
+                )
+                .unwrap();
+            common::start_streams(&mut input, capture.clone());
+            input
+        })
+        .collect();
+    (inputs, capture)
+}
+
+fn frame(width: usize) -> MockFrame {
+    MockFrame::new(width, 2, FORMAT).fill(0x80)
+}
+
+/// Deliver frames at once, each `(device, width)` from its own thread, returning how long
+/// each delivery took.
+fn deliver_at_once(backend: &MockBackend, deliveries: &[(usize, usize)]) -> Vec<Duration> {
+    let barrier = Barrier::new(deliveries.len());
+    std::thread::scope(|scope| {
+        let threads: Vec<_> = deliveries
+            .iter()
+            .map(|&(device, width)| {
+                let mock = backend.input(device);
+                let barrier = &barrier;
+                scope.spawn(move || {
+                    barrier.wait();
+                    let start = Instant::now();
+                    assert!(mock.deliver_frame(frame(width)).is_ok());
+                    start.elapsed()
+                })
+            })
+            .collect();
+        threads.into_iter().map(|t| t.join().unwrap()).collect()
+    })
+}
+
+#[test]
+fn concurrent_first_requests_share_one_allocator() {
+    let backend = backend(1);
+    let provider = Arc::new(SlowProvider::default());
+    let (_inputs, capture) = start(provider.clone());
+
+    // Driver threads of one device ask for the same spec at once
+    deliver_at_once(&backend, &[(0, SLOW_WIDTH); 4]);
+    assert_eq!(provider.created(SLOW_WIDTH), 1);
+    assert_eq!(capture.frames.load(Ordering::SeqCst), 4);
+
+    // Later frames use the cached allocator
+    let mock = backend.input(0);
+    assert!(mock.deliver_frame(frame(SLOW_WIDTH)).is_ok());
+    assert_eq!(provider.created(SLOW_WIDTH), 1);
+}
+
+#[test]
+fn a_slow_allocator_does_not_hold_up_other_specs() {
+    let backend = backend(1);
+    let provider = Arc::new(SlowProvider::default());
+    let (_inputs, capture) = start(provider.clone());
+
+    let elapsed = deliver_at_once(&backend, &[(0, SLOW_WIDTH), (0, FAST_WIDTH)]);
+    assert!(elapsed[0] >= SLOW);
+    assert!(elapsed[1] < SLOW / 2, "{:?}", elapsed);
+    assert_eq!(
+        (provider.created(SLOW_WIDTH), provider.created(FAST_WIDTH)),
+        (1, 1)
+    );
+    assert_eq!(capture.frames.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn one_provider_serves_several_devices() {
+    let backend = backend(2);
+    let provider = Arc::new(SlowProvider::default());
+    let (_inputs, capture) = start(provider.clone());
+
+    // A slow first allocation on one device does not stall the other
+    let elapsed = deliver_at_once(&backend, &[(0, SLOW_WIDTH), (1, FAST_WIDTH)]);
+    assert!(elapsed[1] < SLOW / 2, "{:?}", elapsed);
+
+    // Each device keeps its own cache, so both ask once for a spec they share
+    deliver_at_once(
+        &backend,
+        &[
+            (0, FAST_WIDTH),
+            (0, FAST_WIDTH),
+            (1, SLOW_WIDTH),
+            (1, SLOW_WIDTH),
+        ],
+    );
+    assert_eq!(
+        (provider.created(SLOW_WIDTH), provider.created(FAST_WIDTH)),
+        (2, 2)
+    );
+    assert_eq!(capture.frames.load(Ordering::SeqCst), 6);
+}
+
+#[test]
+fn metered_provider_times_each_spec() {
+    let backend = backend(1);
+    let metered = Arc::new(MeteredProvider::new(Arc::new(SlowProvider::default())));
+    let (_inputs, _capture) = start(metered.clone());
+
+    let mock = backend.input(0);
+    for _ in 0..3 {
+        assert!(mock.deliver_frame(frame(SLOW_WIDTH)).is_ok());
+    }
+    assert!(mock.deliver_frame(frame(FAST_WIDTH)).is_ok());
+
+    let mut timings = metered.timings();
+    timings.sort_by_key(|t| t.spec.width);
+    let counts: Vec<_> = timings
+        .iter()
+        .map(|t| (t.spec.width, t.get_allocator_calls, t.allocations))
+        .collect();
+    assert_eq!(
+        counts,
+        [(SLOW_WIDTH as u32, 1, 3), (FAST_WIDTH as u32, 1, 1)]
+    );
+    assert!(timings[0].slowest_get_allocator >= SLOW);
+    assert_eq!(
+        timings[0].get_allocator_time,
+        timings[0].slowest_get_allocator
+    );
+    assert!(timings[1].slowest_get_allocator < SLOW);
+    assert!(timings[0].allocation_time >= timings[0].slowest_allocation);
+}
+
+#[test]
+fn null_buffers_are_refused_and_counted() {
+    let backend = backend(1);
+    let (_inputs, capture) = start(Arc::new(NullProvider));
+
+    let before = null_buffer_count();
+    assert!(!backend.input(0).deliver_frame(frame(FAST_WIDTH)).is_ok());
+    assert_eq!(null_buffer_count(), before + 1);
+    assert_eq!(capture.frames.load(Ordering::SeqCst), 0);
+}
+
+/// Counts the live buffers of each buffer size, as a pooling provider accounts its memory.
+#[derive(Default)]
+struct CountingProvider {
+    created: Mutex<Vec<BufferSpec>>,
+    live: Arc<Mutex<HashMap<u32, usize>>>,
+}
+
+impl CountingProvider {
+    fn live(&self, buffer_size: u32) -> usize {
+        self.live
+            .lock()
+            .unwrap()
+            .get(&buffer_size)
+            .copied()
+            .unwrap_or(0)
+    }
+}
+
+struct CountedBuffer {
+    buffer: HeapBuffer,
+    size: u32,
+    live: Arc<Mutex<HashMap<u32, usize>>>,
+}
+
+impl VideoBuffer for CountedBuffer {
+    fn get_bytes(&self) -> Result<*mut c_void, SdkError> {
+        self.buffer.get_bytes()
+    }
+}
+
+impl Drop for CountedBuffer {
+    fn drop(&mut self) {
+        *self.live.lock().unwrap().get_mut(&self.size).unwrap() -= 1;
+    }
+}
+
+struct CountingAllocator {
+    size: u32,
+    live: Arc<Mutex<HashMap<u32, usize>>>,
+}
+
+impl VideoBufferAllocator for CountingAllocator {
+    fn allocate(&self) -> Result<Box<dyn VideoBuffer>, SdkError> {
+        *self.live.lock().unwrap().entry(self.size).or_default() += 1;
+        Ok(Box::new(CountedBuffer {
+            buffer: HeapBuffer(Mutex::new(vec![0; self.size as usize])),
+            size: self.size,
+            live: self.live.clone(),
+        }))
+    }
+}
+
+impl VideoBufferAllocatorProvider for CountingProvider {
+    fn get_allocator(&self, spec: BufferSpec) -> Result<Arc<dyn VideoBufferAllocator>, SdkError> {
+        self.created.lock().unwrap().push(spec);
+        Ok(Arc::new(CountingAllocator {
+            size: spec.buffer_size,
+            live: self.live.clone(),
+        }))
+    }
+}
+
+/// Keeps every frame that arrives.
+#[derive(Default)]
+struct Keeper {
+    kept: Mutex<Vec<DecklinkVideoFrame>>,
+}
+
+impl InputHandler for Keeper {
+    fn frame_arrived(&self, arrival: &FrameArrival<'_>) -> CallbackResult {
+        self.kept
+            .lock()
+            .unwrap()
+            .extend(arrival.retain_video_frame());
+        CallbackResult::Ok
+    }
+}
+
+fn start_keeping(
+    provider: Arc<dyn VideoBufferAllocatorProvider>,
+) -> (DecklinkInputDevice, Arc<Keeper>) {
+    let keeper = Arc::new(Keeper::default());
+    let mut input = get_devices().unwrap()[0].input().unwrap();
+    input
+        .enable_video_input_with_allocator(
+            DecklinkDisplayModeId::NTSC,
+            FORMAT,
+            DecklinkVideoInputFlags::empty(),
+            provider,
+        )
+        .unwrap();
+    common::start_streams(&mut input, keeper.clone());
+    (input, keeper)
+}
+
+/// The spec changes for frames `FAST_WIDTH` wide, among the events taken. The events are
+/// process wide, so other tests may have raised some of them.
+fn spec_changes() -> Vec<(BufferSpec, BufferSpec)> {
+    allocator::take_events()
+        .into_iter()
+        .filter_map(|event| match event {
+            AllocatorEvent::SpecChanged { previous, current } => Some((previous, current)),
+            _ => None,
+        })
+        .filter(|(previous, _)| previous.width as usize == FAST_WIDTH)
+        .collect()
+}
+
+const PADDING: usize = 64;
+
+#[test]
+fn a_buffer_size_change_keeps_both_generations_of_buffers() {
+    let backend = backend(1);
+    let provider = Arc::new(CountingProvider::default());
+    let (input, keeper) = start_keeping(provider.clone());
+    let mock = backend.input(0);
+
+    for _ in 0..2 {
+        assert!(mock.deliver_frame(frame(FAST_WIDTH)).is_ok());
+    }
+    mock.set_buffer_padding(PADDING);
+    assert!(mock.deliver_frame(frame(FAST_WIDTH)).is_ok());
+
+    let created = provider.created.lock().unwrap().clone();
+    assert_eq!(created.len(), 2);
+    let (old, new) = (created[0], created[1]);
+    assert!(old.same_frames(&new));
+    assert_eq!(new.buffer_size, old.buffer_size + PADDING as u32);
+    assert!(spec_changes().contains(&(old, new)));
+    assert_eq!(mock.allocator_count(), 2);
+
+    // Frames captured before the change keep their buffers, which stay readable
+    assert_eq!(
+        (
+            provider.live(old.buffer_size),
+            provider.live(new.buffer_size)
+        ),
+        (2, 1)
+    );
+    for frame in keeper.kept.lock().unwrap().iter() {
+        assert!(frame.bytes_to_vec().unwrap().iter().all(|b| *b == 0x80));
+    }
+
+    // Both generations are released, the old first as its frames are dropped
+    keeper.kept.lock().unwrap().drain(..2);
+    assert_eq!(
+        (
+            provider.live(old.buffer_size),
+            provider.live(new.buffer_size)
+        ),
+        (0, 1)
+    );
+    drop(input);
+    keeper.kept.lock().unwrap().clear();
+    assert_eq!(
+        (
+            provider.live(old.buffer_size),
+            provider.live(new.buffer_size)
+        ),
+        (0, 0)
+    );
+}
+
+#[test]
+fn a_metered_provider_times_both_specs_of_a_mode() {
+    let backend = backend(1);
+    let metered = Arc::new(MeteredProvider::new(Arc::new(CountingProvider::default())));
+    let (_input, _keeper) = start_keeping(metered.clone());
+    let mock = backend.input(0);
+
+    assert!(mock.deliver_frame(frame(FAST_WIDTH)).is_ok());
+    mock.set_buffer_padding(PADDING);
+    assert!(mock.deliver_frame(frame(FAST_WIDTH)).is_ok());
+    assert!(mock.deliver_frame(frame(FAST_WIDTH)).is_ok());
+
+    let mut timings = metered.timings();
+    timings.sort_by_key(|t| t.spec.buffer_size);
+    let counts: Vec<_> = timings
+        .iter()
+        .map(|t| (t.get_allocator_calls, t.allocations))
+        .collect();
+    assert_eq!(counts, [(1, 1), (1, 2)]);
+}
+
+/// Captures into pinned memory, which needs a CUDA device.
+#[cfg(feature = "cuda")]
+mod cuda {
+    use super::*;
+    use cudarc::driver::CudaContext;
+    use decklink::cuda::CudaAllocatorProvider;
+
+    #[test]
+    #[ignore = "needs a CUDA device"]
+    fn pinned_buffers_of_both_specs_stay_readable() {
+        let backend = backend(1);
+        let provider = Arc::new(CudaAllocatorProvider::new(CudaContext::new(0).unwrap()));
+        let (input, keeper) = start_keeping(provider);
+        let mock = backend.input(0);
+
+        assert!(mock.deliver_frame(frame(FAST_WIDTH)).is_ok());
+        mock.set_buffer_padding(PADDING);
+        assert!(mock.deliver_frame(frame(FAST_WIDTH)).is_ok());
+        assert_eq!(mock.allocator_count(), 2);
+
+        for frame in keeper.kept.lock().unwrap().iter() {
+            assert!(frame.bytes_to_vec().unwrap().iter().all(|b| *b == 0x80));
+        }
+        drop(input);
+        keeper.kept.lock().unwrap().clear();
+    }
+}
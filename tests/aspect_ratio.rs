@@ -0,0 +1,237 @@
+//! The pixel and display aspect ratio of every mode, and the aspect of frames a
+//! `DualFormatSplitter` gives.
+
+#[cfg(feature = "mock-backend")]
+mod common;
+
+use decklink::display_mode::{DecklinkDisplayModeId, Ratio};
+
+const NTSC: [DecklinkDisplayModeId; 3] = [
+    DecklinkDisplayModeId::NTSC,
+    DecklinkDisplayModeId::NTSC2398,
+    DecklinkDisplayModeId::NTSCp,
+];
+const PAL: [DecklinkDisplayModeId; 2] = [DecklinkDisplayModeId::PAL, DecklinkDisplayModeId::PALp];
+
+#[test]
+fn standard_definition_follows_bt601() {
+    for mode in NTSC {
+        assert_eq!(mode.pixel_aspect_ratio(false), Some(Ratio::new(10, 11)));
+        assert_eq!(mode.pixel_aspect_ratio(true), Some(Ratio::new(40, 33)));
+    }
+    for mode in PAL {
+        assert_eq!(mode.pixel_aspect_ratio(false), Some(Ratio::new(12, 11)));
+        assert_eq!(mode.pixel_aspect_ratio(true), Some(Ratio::new(16, 11)));
+    }
+    for mode in NTSC.iter().chain(&PAL) {
+        assert_eq!(mode.display_aspect_ratio(false), Some(Ratio::new(4, 3)));
+        assert_eq!(mode.display_aspect_ratio(true), Some(Ratio::new(16, 9)));
+    }
+    assert_eq!(
+        DecklinkDisplayModeId::Unknown.pixel_aspect_ratio(false),
+        None
+    );
+    assert_eq!(
+        DecklinkDisplayModeId::Unknown.display_aspect_ratio(false),
+        None
+    );
+}
+
+#[test]
+fn ntsc_rasters_of_486_and_480_lines_show_the_same_picture() {
+    let pixel = Ratio::for_height(486, false);
+    assert_eq!(pixel, Ratio::for_height(480, false));
+    assert_eq!(Ratio::of_raster(720, 486, pixel), Ratio::new(400, 297));
+    assert_eq!(Ratio::of_raster(720, 480, pixel), Ratio::new(15, 11));
+    // Over the 704 samples of the active line, the 480 lines of picture are 4:3
+    assert_eq!(Ratio::of_raster(704, 480, pixel), Ratio::new(4, 3));
+    let wide = Ratio::for_height(480, true);
+    assert_eq!(Ratio::of_raster(704, 480, wide), Ratio::new(16, 9));
+    assert_eq!(
+        Ratio::of_raster(704, 576, Ratio::for_height(576, true)),
+        Ratio::new(16, 9)
+    );
+}
+
+#[test]
+fn ratios_reduce_and_print_as_ffmpeg_takes_them() {
+    assert_eq!(Ratio::new(1440, 1080).reduced(), Ratio::new(4, 3));
+    assert_eq!(Ratio::new(0, 0).reduced(), Ratio::new(0, 0));
+    assert_eq!(Ratio::new(40, 33).to_string(), "40:33");
+    assert_eq!(Ratio::default(), Ratio::SQUARE);
+}
+
+#[cfg(feature = "mock-backend")]
+mod mock {
+    use super::common;
+    use decklink::device::get_devices;
+    use decklink::device::input::{
+        DecklinkDetectedVideoInputFormatFlags, DecklinkVideoInputFlags,
+        DecklinkVideoInputFormatChangedEvents,
+    };
+    use decklink::device::DecklinkDeviceDisplayModes;
+    use decklink::dispatch::{DispatchConfig, DispatchPool};
+    use decklink::display_mode::{DecklinkDisplayModeId, Ratio};
+    use decklink::dual::{
+        DualFormatConfig, DualFormatConsumer, DualFormatSplitter, DualStream, StreamFrame,
+    };
+    use decklink::frame::DecklinkPixelFormat;
+    use decklink::mock::{MockBackend, MockDevice, MockFrame, DEFAULT_MODES, EIGHT_K_MODES};
+    use std::sync::mpsc::{channel, Sender};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    /// The modes in neither `DEFAULT_MODES` nor `EIGHT_K_MODES`.
+    const OTHER_MODES: [DecklinkDisplayModeId; 44] = [
+        DecklinkDisplayModeId::NTSC2398,
+        DecklinkDisplayModeId::NTSCp,
+        DecklinkDisplayModeId::PALp,
+        DecklinkDisplayModeId::HD1080p4795,
+        DecklinkDisplayModeId::HD1080p48,
+        DecklinkDisplayModeId::HD1080p9590,
+        DecklinkDisplayModeId::HD1080p96,
+        DecklinkDisplayModeId::HD1080p100,
+        DecklinkDisplayModeId::HD1080p11988,
+        DecklinkDisplayModeId::HD1080p120,
+        DecklinkDisplayModeId::HD2k2398,
+        DecklinkDisplayModeId::HD2k24,
+        DecklinkDisplayModeId::HD2k25,
+        DecklinkDisplayModeId::HD2kDCI2398,
+        DecklinkDisplayModeId::HD2kDCI24,
+        DecklinkDisplayModeId::HD2kDCI25,
+        DecklinkDisplayModeId::HD2kDCI2997,
+        DecklinkDisplayModeId::HD2kDCI30,
+        DecklinkDisplayModeId::HD2kDCI4795,
+        DecklinkDisplayModeId::HD2kDCI48,
+        DecklinkDisplayModeId::HD2kDCI50,
+        DecklinkDisplayModeId::HD2kDCI5994,
+        DecklinkDisplayModeId::HD2kDCI60,
+        DecklinkDisplayModeId::HD2kDCI9590,
+        DecklinkDisplayModeId::HD2kDCI96,
+        DecklinkDisplayModeId::HD2kDCI100,
+        DecklinkDisplayModeId::HD2kDCI11988,
+        DecklinkDisplayModeId::HD2kDCI120,
+        DecklinkDisplayModeId::PC640x480p60,
+        DecklinkDisplayModeId::PC800x600p60,
+        DecklinkDisplayModeId::PC1440x900p50,
+        DecklinkDisplayModeId::PC1440x900p60,
+        DecklinkDisplayModeId::PC1440x1080p50,
+        DecklinkDisplayModeId::PC1440x1080p60,
+        DecklinkDisplayModeId::PC1600x1200p50,
+        DecklinkDisplayModeId::PC1600x1200p60,
+        DecklinkDisplayModeId::PC1920x1200p50,
+        DecklinkDisplayModeId::PC1920x1200p60,
+        DecklinkDisplayModeId::PC1920x1440p50,
+        DecklinkDisplayModeId::PC1920x1440p60,
+        DecklinkDisplayModeId::PC2560x1440p50,
+        DecklinkDisplayModeId::PC2560x1440p60,
+        DecklinkDisplayModeId::PC2560x1600p50,
+        DecklinkDisplayModeId::PC2560x1600p60,
+    ];
+
+    /// Every mode but standard definition fills its raster with square pixels, so the table
+    /// agrees with the size of each mode the driver gives.
+    #[test]
+    fn every_other_mode_has_square_pixels_filling_the_raster() {
+        let modes: Vec<_> = DEFAULT_MODES
+            .iter()
+            .chain(&EIGHT_K_MODES)
+            .chain(&OTHER_MODES)
+            .copied()
+            .collect();
+        let _backend = MockBackend::install(vec![MockDevice::new("DeckLink 8K Pro").modes(&modes)]);
+        let input = get_devices().unwrap()[0].input().unwrap();
+        let display_modes = input.display_modes().unwrap();
+        assert_eq!(display_modes.len(), modes.len());
+
+        for mode in &display_modes {
+            // PC 640x480 is as tall as NTSC, but has square pixels
+            let sd = mode.width() == 720 && [480, 486, 576].contains(&mode.height());
+            assert_eq!(
+                mode.mode().pixel_aspect_ratio(false).unwrap() != Ratio::SQUARE,
+                sd,
+                "{:?}",
+                mode.mode()
+            );
+            if sd {
+                continue;
+            }
+            for widescreen in [false, true] {
+                assert_eq!(mode.pixel_aspect_ratio(widescreen), Ratio::SQUARE);
+                assert_eq!(
+                    mode.display_aspect_ratio(widescreen),
+                    Ratio::of_raster(mode.width(), mode.height(), Ratio::SQUARE),
+                    "{:?}",
+                    mode.mode()
+                );
+            }
+        }
+    }
+
+    /// Sends the pixel and display aspect ratio of each frame to the test.
+    struct Aspects(Mutex<Sender<(Ratio, Ratio)>>);
+
+    impl DualFormatConsumer for Aspects {
+        fn frame_arrived(&self, frame: StreamFrame) {
+            let meta = frame.meta();
+            let _ = self
+                .0
+                .lock()
+                .unwrap()
+                .send((meta.pixel_aspect, meta.display_aspect));
+        }
+    }
+
+    #[test]
+    fn frames_carry_the_aspect_of_their_mode() {
+        let backend = common::mini_recorder();
+        let mut input = get_devices().unwrap()[0].input().unwrap();
+        let format = DecklinkPixelFormat::Format8BitYUV;
+        input
+            .enable_video_input(
+                DecklinkDisplayModeId::PAL,
+                format,
+                DecklinkVideoInputFlags::empty(),
+            )
+            .unwrap();
+
+        let pool = DispatchPool::new(DispatchConfig::builder().workers(1).build().unwrap());
+        let config = DualFormatConfig::builder()
+            .primary(format)
+            .secondary(format)
+            .widescreen(true)
+            .build()
+            .unwrap();
+        let splitter = DualFormatSplitter::new(config, &pool, "aspect").unwrap();
+        let (sender, aspects) = channel();
+        splitter.subscribe(DualStream::Primary, Arc::new(Aspects(Mutex::new(sender))));
+        common::start_streams(&mut input, splitter.callback());
+
+        // Before a format change the mode is not known, and the aspect is taken by height
+        let mock = backend.input(0);
+        assert!(mock
+            .deliver_frame(MockFrame::for_mode(DecklinkDisplayModeId::PAL, format))
+            .is_ok());
+        let wide_pal = Ratio::new(16, 11);
+        assert_eq!(
+            aspects.recv_timeout(Duration::from_secs(5)).unwrap(),
+            (wide_pal, Ratio::of_raster(720, 576, wide_pal))
+        );
+
+        assert!(mock
+            .deliver_format_change(
+                DecklinkVideoInputFormatChangedEvents::DISPLAY_MODE_CHANGED,
+                DecklinkDisplayModeId::NTSC,
+                DecklinkDetectedVideoInputFormatFlags::YCBCR_422,
+            )
+            .is_ok());
+        assert!(mock
+            .deliver_frame(MockFrame::for_mode(DecklinkDisplayModeId::NTSC, format))
+            .is_ok());
+        assert_eq!(
+            aspects.recv_timeout(Duration::from_secs(5)).unwrap(),
+            (Ratio::new(40, 33), Ratio::new(16, 9))
+        );
+        input.stop_streams().unwrap();
+    }
+}
@@ -0,0 +1,404 @@
+//! Reconciling synthetic audio packet streams with crafted gaps, overlaps and resets.
+
+#[cfg(feature = "mock-backend")]
+mod common;
+
+use decklink::audio_continuity::{AudioContinuityConfig, AudioContinuityEvent, AudioReconciler};
+use decklink::device::input::{DecklinkAudioInputPacket, DecklinkAudioSampleType};
+use decklink::manifest::ManifestEvent;
+use decklink::time::DecklinkTime;
+
+const CHANNELS: u32 = 2;
+
+/// A packet of `samples` sample frames at `time`, each sample the number of its frame.
+fn packet(
+    sample_type: DecklinkAudioSampleType,
+    time: i64,
+    samples: usize,
+) -> DecklinkAudioInputPacket {
+    let mut bytes = Vec::new();
+    for frame in 0..samples {
+        for _ in 0..CHANNELS {
+            let value = (time + frame as i64) as i32;
+            match sample_type {
+                DecklinkAudioSampleType::Int16 => bytes.extend((value as i16).to_ne_bytes()),
+                DecklinkAudioSampleType::Int32 => bytes.extend(value.to_ne_bytes()),
+            }
+        }
+    }
+    DecklinkAudioInputPacket::from_samples(
+        sample_type,
+        CHANNELS,
+        bytes,
+        DecklinkTime::new(time, 48000),
+    )
+}
+
+/// The time and length of each packet passed on.
+fn spans(packets: &[DecklinkAudioInputPacket]) -> Vec<(i64, usize)> {
+    packets
+        .iter()
+        .map(|p| (p.packet_time(48000).unwrap(), p.sample_frame_count()))
+        .collect()
+}
+
+fn filling() -> AudioContinuityConfig {
+    AudioContinuityConfig {
+        fill_gaps: true,
+        fill_dropped: true,
+        trim_overlaps: true,
+        ..AudioContinuityConfig::default()
+    }
+}
+
+#[test]
+fn continuous_stream_passes_unchanged() {
+    let mut reconciler = AudioReconciler::new(filling());
+    for i in 0..10 {
+        let out = reconciler.reconcile(packet(DecklinkAudioSampleType::Int16, i * 1920, 1920));
+        assert_eq!(spans(&out), [(i * 1920, 1920)]);
+    }
+    assert!(reconciler.take_events().is_empty());
+    let stats = reconciler.stats();
+    assert_eq!(stats.received_samples, 19200);
+    assert_eq!(stats.expected_samples, 19200);
+    assert_eq!(stats.missing_samples(), 0);
+}
+
+#[test]
+fn gap_is_detected_and_filled_exactly() {
+    for sample_type in [
+        DecklinkAudioSampleType::Int16,
+        DecklinkAudioSampleType::Int32,
+    ] {
+        let mut reconciler = AudioReconciler::new(filling());
+        reconciler.reconcile(packet(sample_type, 0, 1920));
+        let out = reconciler.reconcile(packet(sample_type, 1960, 1920));
+
+        assert_eq!(spans(&out), [(1920, 40), (1960, 1920)]);
+        let sample_bytes = match sample_type {
+            DecklinkAudioSampleType::Int16 => 2,
+            DecklinkAudioSampleType::Int32 => 4,
+        };
+        assert_eq!(out[0].bytes().unwrap(), vec![0; 40 * 2 * sample_bytes]);
+        assert_eq!(
+            reconciler.take_events(),
+            [
+                AudioContinuityEvent::Gap {
+                    sample_time: 1920,
+                    samples: 40
+                },
+                AudioContinuityEvent::Filled {
+                    sample_time: 1920,
+                    samples: 40
+                },
+            ]
+        );
+        let stats = reconciler.stats();
+        assert_eq!(
+            (stats.gaps, stats.gap_samples, stats.filled_samples),
+            (1, 40, 40)
+        );
+        assert_eq!(stats.missing_samples(), 40);
+    }
+}
+
+#[test]
+fn gap_is_only_reported_without_filling() {
+    let mut reconciler = AudioReconciler::new(AudioContinuityConfig::default());
+    reconciler.reconcile(packet(DecklinkAudioSampleType::Int32, 0, 1600));
+    let out = reconciler.reconcile(packet(DecklinkAudioSampleType::Int32, 1601, 1600));
+    assert_eq!(spans(&out), [(1601, 1600)]);
+    assert_eq!(
+        reconciler.take_events(),
+        [AudioContinuityEvent::Gap {
+            sample_time: 1600,
+            samples: 1
+        }]
+    );
+}
+
+#[test]
+fn overlap_is_trimmed_exactly() {
+    let mut reconciler = AudioReconciler::new(filling());
+    reconciler.reconcile(packet(DecklinkAudioSampleType::Int32, 0, 1920));
+    let out = reconciler.reconcile(packet(DecklinkAudioSampleType::Int32, 1900, 1920));
+
+    assert_eq!(spans(&out), [(1920, 1900)]);
+    // The first sample passed on is that of frame 1920
+    let first = i32::from_ne_bytes(out[0].bytes().unwrap()[..4].try_into().unwrap());
+    assert_eq!(first, 1920);
+    assert_eq!(
+        reconciler.take_events(),
+        [
+            AudioContinuityEvent::Overlap {
+                sample_time: 1900,
+                samples: 20
+            },
+            AudioContinuityEvent::Trimmed {
+                sample_time: 1900,
+                samples: 20
+            },
+        ]
+    );
+    assert_eq!(reconciler.stats().missing_samples(), -20);
+
+    // A packet entirely inside what has passed is not passed on
+    assert!(reconciler
+        .reconcile(packet(DecklinkAudioSampleType::Int32, 2000, 100))
+        .is_empty());
+    let out = reconciler.reconcile(packet(DecklinkAudioSampleType::Int32, 3820, 100));
+    assert_eq!(spans(&out), [(3820, 100)]);
+}
+
+#[test]
+fn dropped_packets_are_counted_apart_from_gaps() {
+    let config = AudioContinuityConfig {
+        fill_dropped: true,
+        ..AudioContinuityConfig::default()
+    };
+    let mut reconciler = AudioReconciler::new(config);
+    reconciler.reconcile(packet(DecklinkAudioSampleType::Int16, 0, 100));
+    reconciler.dropped(&packet(DecklinkAudioSampleType::Int16, 100, 100));
+    // A genuine gap after the dropped packet, which is not filled
+    let out = reconciler.reconcile(packet(DecklinkAudioSampleType::Int16, 250, 100));
+
+    assert_eq!(spans(&out), [(100, 100), (250, 100)]);
+    assert_eq!(
+        reconciler.take_events(),
+        [
+            AudioContinuityEvent::Dropped {
+                sample_time: 100,
+                samples: 100
+            },
+            AudioContinuityEvent::Gap {
+                sample_time: 200,
+                samples: 50
+            },
+            AudioContinuityEvent::Filled {
+                sample_time: 100,
+                samples: 100
+            },
+        ]
+    );
+    let stats = reconciler.stats();
+    assert_eq!((stats.dropped_packets, stats.dropped_samples), (1, 100));
+    assert_eq!((stats.gaps, stats.gap_samples), (1, 50));
+    assert_eq!(stats.missing_samples(), 50);
+}
+
+#[test]
+fn fills_before_a_packet_are_merged() {
+    let mut reconciler = AudioReconciler::new(filling());
+    reconciler.reconcile(packet(DecklinkAudioSampleType::Int16, 0, 100));
+    reconciler.dropped(&packet(DecklinkAudioSampleType::Int16, 130, 100));
+    let out = reconciler.reconcile(packet(DecklinkAudioSampleType::Int16, 230, 100));
+    assert_eq!(spans(&out), [(100, 130), (230, 100)]);
+}
+
+#[test]
+fn reset_starts_again() {
+    let mut reconciler = AudioReconciler::new(filling());
+    reconciler.reconcile(packet(DecklinkAudioSampleType::Int16, 0, 100));
+    reconciler.dropped(&packet(DecklinkAudioSampleType::Int16, 100, 100));
+    reconciler.reset();
+
+    // The silence owed for the dropped packet is not passed on after the reset
+    let out = reconciler.reconcile(packet(DecklinkAudioSampleType::Int16, 5000, 100));
+    assert_eq!(spans(&out), [(5000, 100)]);
+    let out = reconciler.reconcile(packet(DecklinkAudioSampleType::Int16, 5110, 100));
+    assert_eq!(spans(&out), [(5100, 10), (5110, 100)]);
+
+    let stats = reconciler.stats();
+    assert_eq!(stats.resets, 1);
+    assert_eq!(stats.gaps, 1);
+    assert_eq!(stats.filled_samples, 10);
+}
+
+#[test]
+fn format_change_starts_again() {
+    let mut reconciler = AudioReconciler::new(filling());
+    reconciler.reconcile(packet(DecklinkAudioSampleType::Int16, 0, 100));
+    let out = reconciler.reconcile(packet(DecklinkAudioSampleType::Int32, 500, 100));
+    assert_eq!(spans(&out), [(500, 100)]);
+    assert!(reconciler.take_events().is_empty());
+}
+
+#[test]
+fn jumps_beyond_max_fill_are_discontinuities() {
+    let mut reconciler = AudioReconciler::new(filling());
+    reconciler.reconcile(packet(DecklinkAudioSampleType::Int16, 0, 100));
+    let out = reconciler.reconcile(packet(DecklinkAudioSampleType::Int16, 48000 * 60, 100));
+    assert_eq!(spans(&out), [(48000 * 60, 100)]);
+    assert_eq!(
+        reconciler.take_events(),
+        [AudioContinuityEvent::Discontinuity {
+            sample_time: 48000 * 60,
+            expected: 100
+        }]
+    );
+    assert_eq!(reconciler.stats().missing_samples(), 0);
+}
+
+#[test]
+fn tolerance_ignores_small_differences() {
+    let config = AudioContinuityConfig {
+        tolerance: 2,
+        ..filling()
+    };
+    let mut reconciler = AudioReconciler::new(config);
+    reconciler.reconcile(packet(DecklinkAudioSampleType::Int16, 0, 100));
+    let out = reconciler.reconcile(packet(DecklinkAudioSampleType::Int16, 102, 100));
+    assert_eq!(spans(&out), [(102, 100)]);
+    let out = reconciler.reconcile(packet(DecklinkAudioSampleType::Int16, 205, 100));
+    assert_eq!(spans(&out), [(202, 3), (205, 100)]);
+}
+
+#[test]
+fn insertions_are_recorded_in_the_manifest() {
+    let filled = AudioContinuityEvent::Filled {
+        sample_time: 1920,
+        samples: 40,
+    };
+    assert_eq!(
+        filled.manifest_event(),
+        Some(ManifestEvent::AudioFilled {
+            sample_time: 1920,
+            samples: 40
+        })
+    );
+    let gap = AudioContinuityEvent::Gap {
+        sample_time: 1920,
+        samples: 40,
+    };
+    assert_eq!(gap.manifest_event(), None);
+}
+
+#[cfg(feature = "mock-backend")]
+mod mock {
+    use super::common;
+    use super::*;
+    use decklink::audio_continuity::AudioContinuity;
+    use decklink::device::input::{
+        DeckLinkInputCallback, DecklinkAudioSampleRate, DecklinkDetectedVideoInputFormatFlags,
+        DecklinkVideoInputFlags, DecklinkVideoInputFormatChangedEvents,
+    };
+    use decklink::display_mode::DecklinkDisplayModeId;
+    use decklink::frame::{DecklinkPixelFormat, DecklinkVideoFrame};
+    use std::sync::{Arc, Mutex};
+
+    /// A packet passed on, copied out of it.
+    struct Passed {
+        time: i64,
+        samples: usize,
+        sample_type: DecklinkAudioSampleType,
+        bytes: Vec<u8>,
+    }
+
+    /// Keeps the packets passed on to it.
+    #[derive(Default)]
+    struct Collector {
+        packets: Mutex<Vec<Passed>>,
+    }
+
+    impl Collector {
+        fn take(&self) -> Vec<Passed> {
+            std::mem::take(&mut *self.packets.lock().unwrap())
+        }
+    }
+
+    impl DeckLinkInputCallback for Collector {
+        fn video_input_format_changed(
+            &self,
+            _events: DecklinkVideoInputFormatChangedEvents,
+            _new_display_mode: DecklinkDisplayModeId,
+            _detected_signal_flags: DecklinkDetectedVideoInputFormatFlags,
+        ) {
+        }
+
+        fn video_input_frame_arrived(&self, _video_frame: Option<DecklinkVideoFrame>) -> bool {
+            true
+        }
+
+        fn audio_input_packet_arrived(&self, audio_packet: DecklinkAudioInputPacket) {
+            self.packets.lock().unwrap().push(Passed {
+                time: audio_packet.packet_time(48000).unwrap(),
+                samples: audio_packet.sample_frame_count(),
+                sample_type: audio_packet.sample_type(),
+                bytes: audio_packet.bytes().unwrap().to_vec(),
+            });
+        }
+    }
+
+    #[test]
+    fn samples_lost_before_the_driver_are_filled() {
+        for sample_type in [
+            DecklinkAudioSampleType::Int16,
+            DecklinkAudioSampleType::Int32,
+        ] {
+            let backend = common::mini_recorder();
+            let collector = Arc::new(Collector::default());
+            let continuity = AudioContinuity::new(collector.clone(), filling());
+            let mut input = common::open_input(
+                DecklinkDisplayModeId::HD1080p25,
+                DecklinkPixelFormat::Format8BitYUV,
+                DecklinkVideoInputFlags::empty(),
+            );
+            input
+                .enable_audio_input(DecklinkAudioSampleRate::Rate48kHz, sample_type, CHANNELS)
+                .unwrap();
+            common::start_streams(&mut input, continuity.callback());
+
+            let size = match sample_type {
+                DecklinkAudioSampleType::Int16 => 2,
+                DecklinkAudioSampleType::Int32 => 4,
+            };
+            let samples = vec![1u8; 1920 * CHANNELS as usize * size];
+            let mock = backend.input(0);
+            assert!(mock.deliver_audio(&samples).is_ok());
+            mock.skip_audio(40);
+            assert!(mock.deliver_audio(&samples).is_ok());
+
+            let packets = collector.take();
+            let spans: Vec<_> = packets.iter().map(|p| (p.time, p.samples)).collect();
+            assert_eq!(spans, [(0, 1920), (1920, 40), (1960, 1920)]);
+            let silence = &packets[1];
+            assert_eq!(silence.sample_type, sample_type);
+            assert_eq!(silence.bytes.len(), 40 * CHANNELS as usize * size);
+            assert!(silence.bytes.iter().all(|b| *b == 0));
+            assert_eq!(
+                continuity.take_events(),
+                [
+                    AudioContinuityEvent::Gap {
+                        sample_time: 1920,
+                        samples: 40
+                    },
+                    AudioContinuityEvent::Filled {
+                        sample_time: 1920,
+                        samples: 40
+                    },
+                ]
+            );
+
+            // Packet times start again after a format change, which is not a gap
+            assert!(mock
+                .deliver_format_change(
+                    DecklinkVideoInputFormatChangedEvents::DISPLAY_MODE_CHANGED,
+                    DecklinkDisplayModeId::HD1080p50,
+                    DecklinkDetectedVideoInputFormatFlags::YCBCR_422,
+                )
+                .is_ok());
+            mock.skip_audio(4800);
+            assert!(mock.deliver_audio(&samples).is_ok());
+            let packets = collector.take();
+            assert_eq!((packets[0].time, packets[0].samples), (3880 + 4800, 1920));
+            assert_eq!(packets.len(), 1);
+            assert!(continuity.take_events().is_empty());
+            let stats = continuity.stats();
+            assert_eq!((stats.gaps, stats.filled_samples, stats.resets), (1, 40, 1));
+
+            input.stop_streams().unwrap();
+            drop(backend);
+        }
+    }
+}
@@ -0,0 +1,128 @@
+//! Audio input enabled before video input, on drivers that need video input first and on
+//! those that do not.
+#![cfg(feature = "mock-backend")]
+
+mod common;
+
+use decklink::device::get_devices;
+use decklink::device::input::{
+    AudioEnableEvent, AudioInputState, CallbackResult, DecklinkAudioSampleRate,
+    DecklinkAudioSampleType, DecklinkVideoInputFlags, FrameArrival, InputHandler,
+};
+use decklink::display_mode::DecklinkDisplayModeId;
+use decklink::event::EventRecorder;
+use decklink::frame::DecklinkPixelFormat;
+use decklink::mock::{MockBackend, MockDevice, MockFrame};
+use decklink::SdkError;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+const MODE: DecklinkDisplayModeId = DecklinkDisplayModeId::HD1080p25;
+const FORMAT: DecklinkPixelFormat = DecklinkPixelFormat::Format8BitYUV;
+const RATE: DecklinkAudioSampleRate = DecklinkAudioSampleRate::Rate48kHz;
+const SAMPLE_TYPE: DecklinkAudioSampleType = DecklinkAudioSampleType::Int16;
+
+/// Counts the sample frames of the audio packets that arrive.
+#[derive(Default)]
+struct AudioCounter(AtomicUsize);
+
+impl InputHandler for AudioCounter {
+    fn frame_arrived(&self, arrival: &FrameArrival<'_>) -> CallbackResult {
+        if let Some(packet) = arrival.audio_packet {
+            self.0
+                .fetch_add(packet.sample_frame_count(), Ordering::Relaxed);
+        }
+        CallbackResult::Ok
+    }
+}
+
+fn recorder(audio_requires_video_first: bool) -> MockDevice {
+    MockDevice::new("DeckLink Mini Recorder").audio_requires_video_first(audio_requires_video_first)
+}
+
+#[test]
+fn requested_audio_captures_with_either_driver() {
+    for requires in [false, true] {
+        let backend = MockBackend::install(vec![recorder(requires)]);
+        let mut input = get_devices().unwrap()[0].input().unwrap();
+        assert_eq!(input.audio_requires_video_first(), None);
+
+        let state = input.request_audio_input(RATE, SAMPLE_TYPE, 2).unwrap();
+        let expected = if requires {
+            AudioInputState::Deferred
+        } else {
+            AudioInputState::Enabled
+        };
+        assert_eq!(state, expected);
+        assert_eq!(input.audio_requires_video_first(), Some(requires));
+        assert_eq!(input.audio_input_pending(), requires);
+
+        input
+            .enable_video_input(MODE, FORMAT, DecklinkVideoInputFlags::empty())
+            .unwrap();
+        assert!(!input.audio_input_pending());
+        assert_eq!(input.deferred_audio_input_count(), requires as u64);
+
+        let events = EventRecorder::new(None, 8);
+        events.record_all(input.take_audio_enable_events(), None);
+        let kinds: Vec<_> = events
+            .take_events()
+            .iter()
+            .map(|e| e.kind_str().to_string())
+            .collect();
+        assert_eq!(kinds.len(), if requires { 2 } else { 0 });
+        assert!(kinds.iter().all(|k| k == "audio_enable"));
+
+        let counter = Arc::new(AudioCounter::default());
+        common::start_streams(&mut input, counter.clone());
+        let samples = [0u8; 16];
+        assert!(backend
+            .input(0)
+            .deliver_frame_with_audio(MockFrame::new(48, 2, FORMAT), &samples)
+            .is_ok());
+        assert_eq!(counter.0.load(Ordering::Relaxed), 4);
+        input.stop_streams().unwrap();
+    }
+}
+
+#[test]
+fn audio_before_video_fails_where_the_driver_needs_video_first() {
+    let _backend = MockBackend::install(vec![recorder(true)]);
+    let mut input = get_devices().unwrap()[0].input().unwrap();
+
+    assert_eq!(
+        input.enable_audio_input(RATE, SAMPLE_TYPE, 2),
+        Err(SdkError::FAIL)
+    );
+    assert_eq!(input.audio_requires_video_first(), Some(true));
+
+    input
+        .enable_video_input(MODE, FORMAT, DecklinkVideoInputFlags::empty())
+        .unwrap();
+    assert_eq!(input.enable_audio_input(RATE, SAMPLE_TYPE, 2), Ok(()));
+    // Nothing was deferred, as the raw call does not wait for video input
+    assert_eq!(input.deferred_audio_input_count(), 0);
+    assert!(input.take_audio_enable_events().is_empty());
+}
+
+#[test]
+fn a_deferred_audio_input_is_dropped_when_audio_is_disabled() {
+    let _backend = MockBackend::install(vec![recorder(true)]);
+    let mut input = get_devices().unwrap()[0].input().unwrap();
+
+    assert_eq!(
+        input.request_audio_input(RATE, SAMPLE_TYPE, 2),
+        Ok(AudioInputState::Deferred)
+    );
+    input.disable_audio_input().unwrap();
+    assert!(!input.audio_input_pending());
+
+    input
+        .enable_video_input(MODE, FORMAT, DecklinkVideoInputFlags::empty())
+        .unwrap();
+    assert_eq!(input.deferred_audio_input_count(), 0);
+    assert_eq!(
+        input.take_audio_enable_events(),
+        [AudioEnableEvent::Deferred]
+    );
+}
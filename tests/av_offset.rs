@@ -0,0 +1,362 @@
+//! Resolving audio/video offsets, and applying them to a mock capture of aligned audio and
+//! video.
+
+#[cfg(feature = "mock-backend")]
+mod common;
+
+use decklink::av_offset::{AvOffset, AvOffsetError, AvPlan, MAX_AV_OFFSET};
+use decklink::time::DecklinkTime;
+
+const FRAME_25: DecklinkTime = DecklinkTime {
+    value: 1000,
+    scale: 25000,
+};
+const FRAME_2997: DecklinkTime = DecklinkTime {
+    value: 1001,
+    scale: 30000,
+};
+
+fn plan(offset: AvOffset, frame_duration: DecklinkTime) -> (i64, usize, usize) {
+    let plan = AvPlan::new(offset, frame_duration).unwrap();
+    (
+        plan.offset_samples,
+        plan.video_delay_frames,
+        plan.audio_delay_samples,
+    )
+}
+
+#[test]
+fn offsets_are_resolved_into_delays() {
+    // Positive offsets delay audio only
+    assert_eq!(plan(AvOffset::Frames(2), FRAME_25), (3840, 0, 3840));
+    assert_eq!(plan(AvOffset::Fields(1), FRAME_25), (960, 0, 960));
+    assert_eq!(
+        plan(AvOffset::Time(DecklinkTime::new(10, 1000)), FRAME_25),
+        (480, 0, 480)
+    );
+    // Negative offsets delay whole frames, and audio by the remainder
+    assert_eq!(plan(AvOffset::Frames(-2), FRAME_25), (-3840, 2, 0));
+    assert_eq!(
+        plan(AvOffset::Time(DecklinkTime::new(-10, 1000)), FRAME_25),
+        (-480, 1, 1440)
+    );
+    // A frame at 29.97 is not a whole number of samples
+    assert_eq!(plan(AvOffset::Frames(-1), FRAME_2997), (-1602, 1, 0));
+    assert_eq!(plan(AvOffset::Frames(1), FRAME_2997), (1602, 0, 1602));
+    assert_eq!(plan(AvOffset::Fields(-3), FRAME_2997), (-2402, 2, 801));
+
+    let plan = AvPlan::new(AvOffset::Frames(-1), FRAME_25).unwrap();
+    assert_eq!(plan.time(), DecklinkTime::new(-1920, 48000));
+    assert_eq!(AvOffset::default(), AvOffset::ZERO);
+}
+
+#[test]
+fn offsets_are_limited_to_a_second() {
+    assert_eq!(plan(AvOffset::Frames(25), FRAME_25).0, 48000);
+    assert_eq!(plan(AvOffset::Frames(-25), FRAME_25).1, 25);
+    for offset in [
+        AvOffset::Frames(26),
+        AvOffset::Fields(-51),
+        AvOffset::Time(DecklinkTime::new(1001, 1000)),
+    ] {
+        assert_eq!(
+            AvPlan::new(offset, FRAME_25),
+            Err(AvOffsetError::TooLarge {
+                offset,
+                max: MAX_AV_OFFSET
+            })
+        );
+    }
+    assert_eq!(
+        AvPlan::new(AvOffset::Frames(1), DecklinkTime::new(0, 25000)),
+        Err(AvOffsetError::InvalidFrameDuration(DecklinkTime::new(
+            0, 25000
+        )))
+    );
+}
+
+#[cfg(feature = "mock-backend")]
+mod mock {
+    use super::common;
+    use super::FRAME_25;
+    use decklink::av_offset::{AvAligner, AvOffset};
+    use decklink::device::input::{
+        DeckLinkInputCallback, DecklinkAudioInputPacket, DecklinkAudioSampleRate,
+        DecklinkAudioSampleType, DecklinkDetectedVideoInputFormatFlags, DecklinkInputDevice,
+        DecklinkVideoInputFlags, DecklinkVideoInputFormatChangedEvents,
+    };
+    use decklink::display_mode::DecklinkDisplayModeId;
+    use decklink::frame::{DecklinkFrameBase, DecklinkPixelFormat, DecklinkVideoFrame};
+    use decklink::mock::{MockBackend, MockFrame, MockInput};
+    use decklink::retention::{RetentionBudget, RetentionLimit, RetentionMode};
+    use decklink::time::{DecklinkFrameTiming, DecklinkTime};
+    use std::sync::{Arc, Mutex};
+
+    const MODE: DecklinkDisplayModeId = DecklinkDisplayModeId::HD1080p25;
+    const FORMAT: DecklinkPixelFormat = DecklinkPixelFormat::Format8BitYUV;
+    const CHANNELS: usize = 2;
+    /// Each frame is 40 ms, 1920 samples at 48 kHz.
+    const PACKET_FRAMES: usize = 1920;
+
+    /// What the primary callback was given.
+    #[derive(Default)]
+    struct Primary {
+        /// The number of each frame, by its first byte, with its stream time.
+        frames: Mutex<Vec<(Option<u8>, Option<i64>)>>,
+        /// The first channel of every sample frame, and the time of each packet.
+        samples: Mutex<Vec<i16>>,
+        packet_times: Mutex<Vec<i64>>,
+        timing: Mutex<Option<DecklinkFrameTiming>>,
+    }
+
+    impl Primary {
+        fn frames(&self) -> Vec<(Option<u8>, Option<i64>)> {
+            self.frames.lock().unwrap().clone()
+        }
+
+        fn samples(&self) -> Vec<i16> {
+            self.samples.lock().unwrap().clone()
+        }
+    }
+
+    impl DeckLinkInputCallback for Primary {
+        fn video_input_format_changed(
+            &self,
+            _events: DecklinkVideoInputFormatChangedEvents,
+            _new_display_mode: DecklinkDisplayModeId,
+            _detected_signal_flags: DecklinkDetectedVideoInputFormatFlags,
+        ) {
+        }
+
+        fn video_input_frame_timing(&self, timing: DecklinkFrameTiming) {
+            *self.timing.lock().unwrap() = Some(timing);
+        }
+
+        fn video_input_frame_arrived(&self, video_frame: Option<DecklinkVideoFrame>) -> bool {
+            let number = video_frame.map(|f| f.bytes().unwrap().0[0]);
+            let time = self
+                .timing
+                .lock()
+                .unwrap()
+                .take()
+                .map(|t| t.stream_time.value);
+            self.frames.lock().unwrap().push((number, time));
+            true
+        }
+
+        fn audio_input_packet_arrived(&self, audio_packet: DecklinkAudioInputPacket) {
+            assert_eq!(audio_packet.sample_frame_count(), PACKET_FRAMES);
+            let bytes = audio_packet.bytes().unwrap();
+            self.samples.lock().unwrap().extend(
+                bytes
+                    .chunks(2 * CHANNELS)
+                    .map(|frame| i16::from_ne_bytes([frame[0], frame[1]])),
+            );
+            self.packet_times
+                .lock()
+                .unwrap()
+                .push(audio_packet.packet_time(48000).unwrap());
+        }
+    }
+
+    fn start(backend: &MockBackend, aligner: &AvAligner) -> (DecklinkInputDevice, MockInput) {
+        let mut input = common::open_input(
+            MODE,
+            FORMAT,
+            DecklinkVideoInputFlags::ENABLE_FORMAT_DETECTION,
+        );
+        input
+            .enable_audio_input(
+                DecklinkAudioSampleRate::Rate48kHz,
+                DecklinkAudioSampleType::Int16,
+                CHANNELS as u32,
+            )
+            .unwrap();
+        common::start_streams(&mut input, aligner.callback());
+        (input, backend.input(0))
+    }
+
+    /// Deliver frames `frames`, each with the 40 ms of audio captured alongside it. Sample `i`
+    /// of the capture has the value `i + 1`, so that silence can be told apart.
+    fn deliver(mock: &MockInput, frames: std::ops::Range<u8>) {
+        for n in frames {
+            let first = n as usize * PACKET_FRAMES;
+            let bytes: Vec<u8> = (first..first + PACKET_FRAMES)
+                .flat_map(|i| [(i + 1) as i16; CHANNELS])
+                .flat_map(i16::to_ne_bytes)
+                .collect();
+            let frame =
+                MockFrame::new(48, 2, FORMAT)
+                    .fill(n)
+                    .stream_time(n as i64 * 1000, 1000, 25000);
+            assert!(mock.deliver_frame_with_audio(frame, &bytes).is_ok());
+        }
+    }
+
+    /// The samples `first..last` of the capture, by their values, after `silence` silent
+    /// samples.
+    fn samples(silence: usize, first: usize, last: usize) -> Vec<i16> {
+        let mut samples = vec![0; silence];
+        samples.extend((first..last).map(|i| (i + 1) as i16));
+        samples
+    }
+
+    fn frame(n: u8) -> (Option<u8>, Option<i64>) {
+        (Some(n), Some(n as i64 * 1000))
+    }
+
+    fn aligner(primary: &Arc<Primary>, offset: AvOffset) -> AvAligner {
+        let aligner = AvAligner::new(primary.clone(), None);
+        aligner.set_offset(offset, FRAME_25).unwrap();
+        aligner
+    }
+
+    #[test]
+    fn zero_offset_passes_callbacks_through() {
+        let backend = common::mini_recorder();
+        let primary = Arc::new(Primary::default());
+        let aligner = aligner(&primary, AvOffset::ZERO);
+        assert_eq!(aligner.plan(), None);
+        let (_input, mock) = start(&backend, &aligner);
+
+        // Each frame and packet arrives in the callback it was captured in
+        deliver(&mock, 0..1);
+        assert_eq!(primary.frames(), [frame(0)]);
+        assert_eq!(primary.samples(), samples(0, 0, PACKET_FRAMES));
+        deliver(&mock, 1..3);
+        assert_eq!(primary.frames(), [frame(0), frame(1), frame(2)]);
+        assert_eq!(primary.samples(), samples(0, 0, 3 * PACKET_FRAMES));
+        assert_eq!(*primary.packet_times.lock().unwrap(), [0, 1920, 3840]);
+    }
+
+    #[test]
+    fn positive_offsets_delay_audio_to_the_sample() {
+        for (offset, delay) in [
+            (AvOffset::Frames(1), PACKET_FRAMES),
+            (AvOffset::Time(DecklinkTime::new(10, 1000)), 480),
+            (AvOffset::Fields(3), 2880),
+        ] {
+            let backend = common::mini_recorder();
+            let primary = Arc::new(Primary::default());
+            let aligner = aligner(&primary, offset);
+            let (_input, mock) = start(&backend, &aligner);
+
+            deliver(&mock, 0..4);
+            assert_eq!(
+                primary.frames(),
+                [frame(0), frame(1), frame(2), frame(3)],
+                "{:?}",
+                offset
+            );
+            assert_eq!(
+                primary.samples(),
+                samples(delay, 0, 4 * PACKET_FRAMES - delay),
+                "{:?}",
+                offset
+            );
+            // Delayed packets keep the times of the callbacks they are passed on in
+            assert_eq!(*primary.packet_times.lock().unwrap(), [0, 1920, 3840, 5760]);
+        }
+    }
+
+    #[test]
+    fn negative_offsets_delay_video() {
+        let backend = common::mini_recorder();
+        let primary = Arc::new(Primary::default());
+        let aligner = aligner(&primary, AvOffset::Frames(-2));
+        let (_input, mock) = start(&backend, &aligner);
+
+        deliver(&mock, 0..4);
+        // Frames are passed on two callbacks later, with the stream time of the callback
+        assert_eq!(
+            primary.frames(),
+            [
+                (None, None),
+                (None, None),
+                (Some(0), Some(2000)),
+                (Some(1), Some(3000))
+            ]
+        );
+        assert_eq!(primary.samples(), samples(0, 0, 4 * PACKET_FRAMES));
+    }
+
+    #[test]
+    fn negative_offsets_between_frames_delay_audio_by_the_remainder() {
+        let backend = common::mini_recorder();
+        let primary = Arc::new(Primary::default());
+        let offset = AvOffset::Time(DecklinkTime::new(-10, 1000));
+        let aligner = aligner(&primary, offset);
+        let (_input, mock) = start(&backend, &aligner);
+
+        deliver(&mock, 0..3);
+        assert_eq!(
+            primary.frames(),
+            [(None, None), (Some(0), Some(1000)), (Some(1), Some(2000))]
+        );
+        // Frame 0 is passed on with the audio from 10 ms after its own
+        assert_eq!(
+            primary.samples(),
+            samples(1440, 0, 3 * PACKET_FRAMES - 1440)
+        );
+        assert_eq!(primary.samples()[PACKET_FRAMES], 480 + 1);
+    }
+
+    #[test]
+    fn flushes_and_format_changes_empty_the_delay_lines() {
+        let backend = common::mini_recorder();
+        let primary = Arc::new(Primary::default());
+        let aligner = aligner(&primary, AvOffset::Time(DecklinkTime::new(10, 1000)));
+        let (_input, mock) = start(&backend, &aligner);
+        let resets = aligner.stats().resets;
+
+        deliver(&mock, 0..2);
+        aligner.flush();
+        let stats = aligner.stats();
+        assert_eq!((stats.resets, stats.samples_discarded), (resets + 1, 480));
+
+        // The line fills again with silence from the flush
+        deliver(&mock, 2..3);
+        let mut expected = samples(480, 0, 2 * PACKET_FRAMES - 480);
+        expected.extend(samples(480, 2 * PACKET_FRAMES, 3 * PACKET_FRAMES - 480));
+        assert_eq!(primary.samples(), expected);
+
+        assert!(mock
+            .deliver_format_change(
+                DecklinkVideoInputFormatChangedEvents::DISPLAY_MODE_CHANGED,
+                MODE,
+                DecklinkDetectedVideoInputFormatFlags::YCBCR_422,
+            )
+            .is_ok());
+        let stats = aligner.stats();
+        assert_eq!((stats.resets, stats.samples_discarded), (resets + 2, 960));
+        assert_eq!(
+            aligner.offset(),
+            AvOffset::Time(DecklinkTime::new(10, 1000))
+        );
+    }
+
+    #[test]
+    fn delayed_frames_are_held_within_the_budget() {
+        let backend = common::mini_recorder();
+        let primary = Arc::new(Primary::default());
+        let budget = RetentionBudget::new(RetentionLimit::Frames(1), RetentionMode::Strict);
+        let aligner = AvAligner::new(primary.clone(), Some(budget));
+        aligner.set_offset(AvOffset::Frames(-2), FRAME_25).unwrap();
+        let (_input, mock) = start(&backend, &aligner);
+
+        // Frames 1 and 2 arrive while frame 0 is held, and frame 4 while frame 3 is, so they
+        // are passed on as callbacks without a frame
+        deliver(&mock, 0..5);
+        assert_eq!(
+            primary.frames(),
+            [
+                (None, None),
+                (None, None),
+                (Some(0), Some(2000)),
+                (None, None),
+                (None, None)
+            ]
+        );
+        assert_eq!(aligner.stats().frames_dropped, 3);
+    }
+}
@@ -0,0 +1,433 @@
+//! What frame callbacks return to the driver under each return policy, as the layers behind
+//! the handler fall behind.
+
+#[cfg(feature = "mock-backend")]
+mod common;
+
+use decklink::device::input::{ConsumerPressure, PressureThresholds};
+use decklink::{ApiVersion, CallbackReturnHandling, LibraryCapabilities};
+
+#[test]
+fn any_threshold_reached_is_enough() {
+    let thresholds = PressureThresholds::default();
+    assert!(!thresholds.reached_by(&ConsumerPressure::default()));
+    assert!(!thresholds.reached_by(&ConsumerPressure {
+        queue_saturation: 0.5,
+        ..ConsumerPressure::default()
+    }));
+    assert!(thresholds.reached_by(&ConsumerPressure {
+        queue_saturation: 1.0,
+        ..ConsumerPressure::default()
+    }));
+    assert!(thresholds.reached_by(&ConsumerPressure {
+        consecutive_drops: 1,
+        ..ConsumerPressure::default()
+    }));
+
+    let exhausted = ConsumerPressure {
+        retention_exhausted: true,
+        ..ConsumerPressure::default()
+    };
+    assert!(thresholds.reached_by(&exhausted));
+    let thresholds = PressureThresholds {
+        retention_exhausted: false,
+        ..thresholds
+    };
+    assert!(!thresholds.reached_by(&exhausted));
+}
+
+#[test]
+fn pressures_combine_to_the_worst_of_each() {
+    let queue = ConsumerPressure {
+        queue_saturation: 0.75,
+        retention_exhausted: false,
+        consecutive_drops: 2,
+    };
+    let budget = ConsumerPressure {
+        queue_saturation: 0.25,
+        retention_exhausted: true,
+        consecutive_drops: 0,
+    };
+    assert_eq!(
+        queue.combine(budget),
+        ConsumerPressure {
+            queue_saturation: 0.75,
+            retention_exhausted: true,
+            consecutive_drops: 2,
+        }
+    );
+}
+
+#[test]
+fn no_driver_is_known_to_ignore_results_until_observed() {
+    for version in [
+        ApiVersion::new(9, 9, 0),
+        ApiVersion::new(10, 11, 4),
+        ApiVersion::new(14, 2, 1),
+        ApiVersion::new(15, 0, 0),
+    ] {
+        assert_eq!(
+            LibraryCapabilities::for_version(version).callback_returns,
+            CallbackReturnHandling::Unknown
+        );
+    }
+    assert_eq!(
+        CallbackReturnHandling::default(),
+        CallbackReturnHandling::Unknown
+    );
+}
+
+#[cfg(feature = "mock-backend")]
+mod mock {
+    use super::common;
+    use decklink::device::input::{
+        CallbackResult, CallbackReturnPolicy, CallbackReturnStats, ConsumerPressure,
+        DeckLinkInputCallback, DecklinkDetectedVideoInputFormatFlags, DecklinkInputDevice,
+        DecklinkVideoInputFlags, DecklinkVideoInputFormatChangedEvents, FrameArrival, InputHandler,
+        PressureThresholds,
+    };
+    use decklink::display_mode::DecklinkDisplayModeId;
+    use decklink::frame::{DecklinkPixelFormat, DecklinkVideoFrame};
+    use decklink::mock::{Delivery, MockBackend, MockFrame, MockInput};
+    use decklink::queue::{FrameQueue, OverflowPolicy};
+    use decklink::retention::{RetainedFrame, RetentionBudget, RetentionLimit, RetentionMode};
+    use decklink::session::{CaptureSession, Limit};
+    use decklink::{ApiVersion, CallbackReturnHandling, SdkError};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+
+    const MODE: DecklinkDisplayModeId = DecklinkDisplayModeId::HD1080p25;
+    const FORMAT: DecklinkPixelFormat = DecklinkPixelFormat::Format8BitYUV;
+
+    const S_OK: Delivery = Delivery::Returned(0);
+    const S_FALSE: Delivery = Delivery::Returned(1);
+    const E_FAIL: Delivery = Delivery::Returned(SdkError::FAIL as i32);
+
+    fn backend() -> MockBackend {
+        common::mini_recorder()
+    }
+
+    /// A backend whose drivers are reported to ignore callback results.
+    fn ignoring_backend() -> MockBackend {
+        let backend = backend();
+        backend.set_callback_returns(Some(CallbackReturnHandling::Ignored));
+        backend
+    }
+
+    fn start(
+        backend: &MockBackend,
+        handler: Arc<dyn InputHandler>,
+    ) -> (DecklinkInputDevice, MockInput) {
+        let mut input = common::open_input(MODE, FORMAT, DecklinkVideoInputFlags::empty());
+        common::start_streams(&mut input, handler);
+        (input, backend.input(0))
+    }
+
+    fn frame(n: i64) -> MockFrame {
+        MockFrame::new(48, 2, FORMAT).stream_time(n * 1000, 1000, 25000)
+    }
+
+    /// Deliver `count` frames, returning what each callback returned.
+    fn deliver(mock: &MockInput, count: i64) -> Vec<Delivery> {
+        (0..count).map(|n| mock.deliver_frame(frame(n))).collect()
+    }
+
+    /// Returns `result` for each frame, after queueing it or retaining it against a budget.
+    struct Consumer {
+        result: Mutex<CallbackResult>,
+        queue: Option<Arc<FrameQueue<DecklinkVideoFrame>>>,
+        budget: Option<RetentionBudget>,
+        retained: Mutex<Vec<RetainedFrame>>,
+    }
+
+    impl Consumer {
+        fn returning(result: CallbackResult) -> Consumer {
+            Consumer {
+                result: Mutex::new(result),
+                queue: None,
+                budget: None,
+                retained: Mutex::new(Vec::new()),
+            }
+        }
+
+        fn queueing(queue: &Arc<FrameQueue<DecklinkVideoFrame>>) -> Consumer {
+            Consumer {
+                queue: Some(queue.clone()),
+                ..Consumer::returning(CallbackResult::Ok)
+            }
+        }
+
+        fn retaining(budget: &RetentionBudget) -> Consumer {
+            Consumer {
+                budget: Some(budget.clone()),
+                ..Consumer::returning(CallbackResult::Ok)
+            }
+        }
+    }
+
+    impl InputHandler for Consumer {
+        fn frame_arrived(&self, arrival: &FrameArrival<'_>) -> CallbackResult {
+            if let Some(frame) = arrival.retain_video_frame() {
+                if let Some(queue) = &self.queue {
+                    queue.push(frame);
+                } else if let Some(budget) = &self.budget {
+                    if let Ok(frame) = budget.retain(frame) {
+                        self.retained.lock().unwrap().push(frame);
+                    }
+                }
+            }
+            *self.result.lock().unwrap()
+        }
+    }
+
+    #[test]
+    fn the_handler_result_is_returned_by_default() {
+        let backend = backend();
+        let consumer = Arc::new(Consumer::returning(CallbackResult::Ok));
+        let (input, mock) = start(&backend, consumer.clone());
+        assert!(matches!(
+            input.callback_return_policy(),
+            CallbackReturnPolicy::HandlerResult
+        ));
+
+        assert_eq!(mock.deliver_frame(frame(0)), S_OK);
+        *consumer.result.lock().unwrap() = CallbackResult::False;
+        assert_eq!(mock.deliver_frame(frame(1)), S_FALSE);
+        *consumer.result.lock().unwrap() = CallbackResult::Fail;
+        assert_eq!(mock.deliver_frame(frame(2)), E_FAIL);
+        assert_eq!(
+            input.callback_return_stats(),
+            CallbackReturnStats {
+                returned_false: 1,
+                returned_fail: 1,
+                ..CallbackReturnStats::default()
+            }
+        );
+    }
+
+    /// A callback of the first version, accepting frames while `accept` is set.
+    struct Legacy {
+        accept: AtomicBool,
+    }
+
+    impl DeckLinkInputCallback for Legacy {
+        fn video_input_format_changed(
+            &self,
+            _events: DecklinkVideoInputFormatChangedEvents,
+            _new_display_mode: DecklinkDisplayModeId,
+            _detected_signal_flags: DecklinkDetectedVideoInputFormatFlags,
+        ) {
+        }
+
+        fn video_input_frame_arrived(&self, _video_frame: Option<DecklinkVideoFrame>) -> bool {
+            self.accept.load(Ordering::SeqCst)
+        }
+    }
+
+    #[test]
+    fn legacy_callbacks_keep_their_meaning() {
+        let backend = ignoring_backend();
+        let legacy = Arc::new(Legacy {
+            accept: AtomicBool::new(false),
+        });
+        let (input, mock) = start(&backend, legacy.clone());
+        assert_eq!(mock.deliver_frame(frame(0)), S_FALSE);
+        legacy.accept.store(true, Ordering::SeqCst);
+        assert_eq!(mock.deliver_frame(frame(1)), S_OK);
+
+        // Without pressure, propagating it leaves the result to the callback
+        input.set_callback_return_policy(CallbackReturnPolicy::PropagateConsumerPressure(
+            PressureThresholds::default(),
+        ));
+        assert_eq!(mock.deliver_frame(frame(2)), S_OK);
+        legacy.accept.store(false, Ordering::SeqCst);
+        assert_eq!(mock.deliver_frame(frame(3)), S_FALSE);
+    }
+
+    #[test]
+    fn always_ok_overrides_the_handler() {
+        let backend = backend();
+        let consumer = Arc::new(Consumer::returning(CallbackResult::Fail));
+        let (input, mock) = start(&backend, consumer);
+        input.set_callback_return_policy(CallbackReturnPolicy::AlwaysOk);
+
+        assert_eq!(deliver(&mock, 3), [S_OK, S_OK, S_OK]);
+        let stats = input.callback_return_stats();
+        assert_eq!((stats.returned_fail, stats.overridden), (0, 3));
+    }
+
+    #[test]
+    fn a_saturated_queue_returns_s_false_until_it_drains() {
+        let backend = ignoring_backend();
+        let queue = Arc::new(FrameQueue::new(2, OverflowPolicy::RejectNewest));
+        let (input, mock) = start(&backend, Arc::new(Consumer::queueing(&queue)));
+        input.add_pressure_source(queue.clone());
+        input.set_callback_return_policy(CallbackReturnPolicy::PropagateConsumerPressure(
+            PressureThresholds::default(),
+        ));
+
+        // Half full, then full, then rejecting
+        assert_eq!(deliver(&mock, 3), [S_OK, S_FALSE, S_FALSE]);
+        assert_eq!(queue.consecutive_dropped_count(), 1);
+
+        queue.try_pop().unwrap();
+        queue.try_pop().unwrap();
+        assert_eq!(mock.deliver_frame(frame(3)), S_OK);
+
+        let stats = input.callback_return_stats();
+        assert_eq!(
+            stats,
+            CallbackReturnStats {
+                returned_false: 2,
+                overridden: 2,
+                pressured: 2,
+                driver_handling: CallbackReturnHandling::Ignored,
+                ..CallbackReturnStats::default()
+            }
+        );
+    }
+
+    #[test]
+    fn lower_thresholds_pass_pressure_on_sooner() {
+        let backend = ignoring_backend();
+        let queue = Arc::new(FrameQueue::new(4, OverflowPolicy::DropOldest));
+        let (input, mock) = start(&backend, Arc::new(Consumer::queueing(&queue)));
+        input.add_pressure_source(queue.clone());
+        input.set_callback_return_policy(CallbackReturnPolicy::PropagateConsumerPressure(
+            PressureThresholds {
+                queue_saturation: 0.5,
+                ..PressureThresholds::default()
+            },
+        ));
+
+        assert_eq!(deliver(&mock, 3), [S_OK, S_FALSE, S_FALSE]);
+
+        // Only drops in a row count, from a queue that stays full
+        input.clear_pressure_sources();
+        input.add_pressure_source(queue.clone());
+        input.set_callback_return_policy(CallbackReturnPolicy::PropagateConsumerPressure(
+            PressureThresholds {
+                queue_saturation: 2.0,
+                consecutive_drops: 2,
+                ..PressureThresholds::default()
+            },
+        ));
+        // Filling the last slot, then dropping the oldest twice
+        assert_eq!(deliver(&mock, 3), [S_OK, S_OK, S_FALSE]);
+        assert_eq!(queue.consecutive_dropped_count(), 2);
+        assert_eq!(queue.dropped_count(), 2);
+    }
+
+    #[test]
+    fn an_exhausted_retention_budget_returns_s_false() {
+        let backend = ignoring_backend();
+        let budget = RetentionBudget::new(RetentionLimit::Frames(2), RetentionMode::Strict);
+        let consumer = Arc::new(Consumer::retaining(&budget));
+        let (input, mock) = start(&backend, consumer.clone());
+        input.add_pressure_source(Arc::new(budget.clone()));
+        input.set_callback_return_policy(CallbackReturnPolicy::PropagateConsumerPressure(
+            PressureThresholds::default(),
+        ));
+
+        assert_eq!(deliver(&mock, 3), [S_OK, S_FALSE, S_FALSE]);
+        assert!(budget.is_exhausted());
+        consumer.retained.lock().unwrap().clear();
+        assert!(!budget.is_exhausted());
+        // The next frame is retained again, leaving room for one more
+        assert_eq!(mock.deliver_frame(frame(3)), S_OK);
+
+        // A sub-budget is exhausted along with the budget it counts against
+        let sub = budget.sub_budget(RetentionLimit::Frames(8));
+        assert!(!sub.is_exhausted());
+        assert_eq!(mock.deliver_frame(frame(4)), S_FALSE);
+        assert!(sub.is_exhausted());
+    }
+
+    #[test]
+    fn unobserved_drivers_are_not_sent_pressure() {
+        let backend = backend();
+        let queue = Arc::new(FrameQueue::new(2, OverflowPolicy::RejectNewest));
+        let consumer = Arc::new(Consumer::queueing(&queue));
+        let (input, mock) = start(&backend, consumer.clone());
+        input.add_pressure_source(queue.clone());
+        input.set_callback_return_policy(CallbackReturnPolicy::PropagateConsumerPressure(
+            PressureThresholds::default(),
+        ));
+
+        assert_eq!(deliver(&mock, 3), [S_OK, S_OK, S_OK]);
+        // The handler's own result is still returned
+        *consumer.result.lock().unwrap() = CallbackResult::Fail;
+        assert_eq!(mock.deliver_frame(frame(3)), E_FAIL);
+
+        assert_eq!(
+            input.callback_return_stats(),
+            CallbackReturnStats {
+                returned_fail: 1,
+                pressured: 3,
+                withheld: 3,
+                driver_handling: CallbackReturnHandling::Unknown,
+                ..CallbackReturnStats::default()
+            }
+        );
+    }
+
+    /// Fails callbacks once two frames in a row were dropped, and passes others through.
+    fn fail_on_repeated_drops(
+        result: CallbackResult,
+        pressure: &ConsumerPressure,
+    ) -> CallbackResult {
+        if pressure.consecutive_drops >= 2 {
+            CallbackResult::Fail
+        } else {
+            result
+        }
+    }
+
+    #[test]
+    fn a_custom_policy_decides_on_any_driver() {
+        let backend = backend();
+        backend.set_api_version(Some(ApiVersion::new(15, 0, 0)));
+        let queue = Arc::new(FrameQueue::new(2, OverflowPolicy::RejectNewest));
+        let (input, mock) = start(&backend, Arc::new(Consumer::queueing(&queue)));
+        input.add_pressure_source(queue.clone());
+        input.set_callback_return_policy(CallbackReturnPolicy::Custom(fail_on_repeated_drops));
+
+        assert_eq!(deliver(&mock, 5), [S_OK, S_OK, S_OK, E_FAIL, E_FAIL]);
+        let stats = input.callback_return_stats();
+        assert_eq!((stats.returned_fail, stats.overridden), (2, 2));
+        assert_eq!(stats.driver_handling, CallbackReturnHandling::Unknown);
+    }
+
+    #[test]
+    fn a_session_sets_its_policy_for_the_capture() {
+        let backend = backend();
+        let mut input = common::open_input(MODE, FORMAT, DecklinkVideoInputFlags::empty());
+        let mock = backend.input(0);
+        let driver = {
+            let mock = mock.clone();
+            thread::spawn(move || {
+                while !mock.is_streaming() {
+                    thread::sleep(Duration::from_millis(1));
+                }
+                deliver(&mock, 3)
+            })
+        };
+
+        let capture = CaptureSession::new(
+            &mut input,
+            Arc::new(Consumer::returning(CallbackResult::Fail)),
+        )
+        .with_return_policy(CallbackReturnPolicy::AlwaysOk)
+        .capture_exact(Limit::Frames(3))
+        .unwrap();
+
+        assert_eq!(capture.delivered, 3);
+        assert_eq!(driver.join().unwrap(), [S_OK, S_OK, S_OK]);
+        assert!(matches!(
+            input.callback_return_policy(),
+            CallbackReturnPolicy::AlwaysOk
+        ));
+    }
+}
@@ -0,0 +1,305 @@
+//! Batched delivery of frames from a mock input.
+#![cfg(feature = "mock-backend")]
+
+mod common;
+
+use decklink::batch::{BatchConfig, BatchDispatcher, BatchedFrame, DeckLinkInputBatchCallback};
+use decklink::device::input::{
+    DeckLinkInputCallback, DecklinkDetectedVideoInputFormatFlags, DecklinkInputDevice,
+    DecklinkVideoInputFlags, DecklinkVideoInputFormatChangedEvents, InputHandler,
+};
+use decklink::display_mode::DecklinkDisplayModeId;
+use decklink::frame::{DecklinkFrameBase, DecklinkPixelFormat, DecklinkVideoFrame};
+use decklink::mock::{MockBackend, MockDevice, MockFrame, MockInput};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const MODE: DecklinkDisplayModeId = DecklinkDisplayModeId::HD1080p25;
+const FORMAT: DecklinkPixelFormat = DecklinkPixelFormat::Format8BitYUV;
+const WAIT: Duration = Duration::from_secs(5);
+
+#[derive(PartialEq, Debug)]
+enum Entry {
+    /// A batch, as the first byte of each frame.
+    Batch(Vec<u8>),
+    FormatChanged(DecklinkDisplayModeId),
+    SignalLost,
+    SignalRestored,
+}
+
+/// Sends what it is given to the test, optionally waiting for a go-ahead on each batch.
+struct Handler {
+    entries: Sender<Entry>,
+    gate: Option<Mutex<Receiver<()>>>,
+}
+
+impl DeckLinkInputBatchCallback for Handler {
+    fn frames_arrived(&mut self, frames: &[BatchedFrame]) {
+        if let Some(gate) = &self.gate {
+            let _ = gate.lock().unwrap().recv();
+        }
+        let bytes = frames
+            .iter()
+            .map(|f| f.frame.bytes().unwrap().0[0])
+            .collect();
+        let _ = self.entries.send(Entry::Batch(bytes));
+    }
+
+    fn video_input_format_changed(
+        &mut self,
+        _events: DecklinkVideoInputFormatChangedEvents,
+        new_display_mode: DecklinkDisplayModeId,
+        _detected_signal_flags: DecklinkDetectedVideoInputFormatFlags,
+    ) {
+        let _ = self.entries.send(Entry::FormatChanged(new_display_mode));
+    }
+
+    fn signal_lost(&mut self) {
+        let _ = self.entries.send(Entry::SignalLost);
+    }
+
+    fn signal_restored(&mut self) {
+        let _ = self.entries.send(Entry::SignalRestored);
+    }
+}
+
+fn start(
+    config: BatchConfig,
+) -> (
+    MockBackend,
+    DecklinkInputDevice,
+    BatchDispatcher,
+    Receiver<Entry>,
+) {
+    let backend = common::mini_recorder();
+    let (sender, entries) = channel();
+    let dispatcher = BatchDispatcher::new(
+        config,
+        Handler {
+            entries: sender,
+            gate: None,
+        },
+    );
+    let input = start_input(dispatcher.callback());
+    (backend, input, dispatcher, entries)
+}
+
+fn start_input(callback: Arc<dyn InputHandler>) -> DecklinkInputDevice {
+    let mut input = common::open_input(
+        MODE,
+        FORMAT,
+        DecklinkVideoInputFlags::ENABLE_FORMAT_DETECTION,
+    );
+    common::start_streams(&mut input, callback);
+    input
+}
+
+/// Frame `n`, filled with `n`.
+fn deliver(mock: &MockInput, n: u8) {
+    let frame = MockFrame::new(48, 2, FORMAT)
+        .fill(n)
+        .stream_time(n as i64 * 1000, 1000, 25000);
+    assert!(mock.deliver_frame(frame).is_ok());
+}
+
+fn next(entries: &Receiver<Entry>) -> Entry {
+    entries.recv_timeout(WAIT).unwrap()
+}
+
+fn config(max_frames: usize, max_latency: Duration) -> BatchConfig {
+    BatchConfig {
+        max_frames,
+        max_latency,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn full_batches_preserve_order() {
+    let (backend, _input, dispatcher, entries) = start(config(4, WAIT));
+    let mock = backend.input(0);
+
+    for n in 1..=8 {
+        deliver(&mock, n);
+    }
+    assert_eq!(next(&entries), Entry::Batch(vec![1, 2, 3, 4]));
+    assert_eq!(next(&entries), Entry::Batch(vec![5, 6, 7, 8]));
+
+    let stats = dispatcher.stats();
+    assert_eq!((stats.batches, stats.frames, stats.flushed_full), (2, 8, 2));
+    assert_eq!(stats.batch_sizes, [0, 0, 0, 0, 2]);
+    assert_eq!(stats.average_batch_size(), 4.0);
+}
+
+#[test]
+fn partial_batch_is_delivered_after_the_latency_bound() {
+    let (backend, _input, dispatcher, entries) = start(config(4, Duration::from_millis(30)));
+    let mock = backend.input(0);
+
+    let start = Instant::now();
+    deliver(&mock, 1);
+    deliver(&mock, 2);
+    assert_eq!(next(&entries), Entry::Batch(vec![1, 2]));
+    assert!(start.elapsed() >= Duration::from_millis(30));
+
+    let stats = dispatcher.stats();
+    assert_eq!((stats.batches, stats.flushed_latency), (1, 1));
+    assert_eq!(stats.batch_sizes, [0, 0, 1]);
+}
+
+#[test]
+fn events_flush_the_pending_batch_first() {
+    let (backend, _input, dispatcher, entries) = start(config(4, WAIT));
+    let mock = backend.input(0);
+
+    deliver(&mock, 1);
+    deliver(&mock, 2);
+    assert!(mock
+        .deliver_format_change(
+            DecklinkVideoInputFormatChangedEvents::DISPLAY_MODE_CHANGED,
+            MODE,
+            DecklinkDetectedVideoInputFormatFlags::YCBCR_422,
+        )
+        .is_ok());
+    deliver(&mock, 3);
+    assert!(mock
+        .deliver_frame(MockFrame::new(48, 2, FORMAT).fill(4).no_signal())
+        .is_ok());
+    deliver(&mock, 5);
+
+    assert_eq!(next(&entries), Entry::Batch(vec![1, 2]));
+    assert_eq!(next(&entries), Entry::FormatChanged(MODE));
+    assert_eq!(next(&entries), Entry::Batch(vec![3]));
+    assert_eq!(next(&entries), Entry::SignalLost);
+    assert_eq!(next(&entries), Entry::Batch(vec![4]));
+    assert_eq!(next(&entries), Entry::SignalRestored);
+    assert_eq!(dispatcher.stats().flushed_event, 3);
+}
+
+#[test]
+fn teardown_delivers_a_partial_batch() {
+    let (backend, input, dispatcher, entries) = start(config(4, WAIT));
+    let mock = backend.input(0);
+
+    for n in 1..=6 {
+        deliver(&mock, n);
+    }
+    assert_eq!(next(&entries), Entry::Batch(vec![1, 2, 3, 4]));
+    drop(input);
+
+    // Frames 5 and 6 are still pending, and are delivered before the drop returns
+    drop(dispatcher);
+    assert_eq!(entries.try_recv().unwrap(), Entry::Batch(vec![5, 6]));
+    assert!(entries.try_recv().is_err());
+}
+
+#[test]
+fn frames_are_dropped_when_the_queue_is_full() {
+    let backend = common::mini_recorder();
+    let (sender, entries) = channel();
+    let (go, gate) = channel();
+    let dispatcher = BatchDispatcher::new(
+        BatchConfig {
+            max_frames: 1,
+            max_latency: WAIT,
+            queue_capacity: 2,
+        },
+        Handler {
+            entries: sender,
+            gate: Some(Mutex::new(gate)),
+        },
+    );
+    let _input = start_input(dispatcher.callback());
+    let mock = backend.input(0);
+
+    // The handler holds the first frame, until the queue behind it is full
+    deliver(&mock, 1);
+    let mut delivered = 1;
+    while dispatcher.stats().dropped == 0 {
+        deliver(&mock, 2);
+        delivered += 1;
+    }
+    for _ in 0..delivered {
+        go.send(()).unwrap();
+    }
+    drop(dispatcher);
+
+    let batches: Vec<_> = entries.try_iter().collect();
+    assert_eq!(batches[0], Entry::Batch(vec![1]));
+    // No more than the frame being handled and a full queue were kept
+    assert!(batches.len() <= 3, "{:?}", batches);
+    assert_eq!(batches.len() + 1, delivered);
+}
+
+/// Counts the driver callbacks' handler calls of a plain callback.
+struct PerFrame(AtomicU64);
+
+impl DeckLinkInputCallback for PerFrame {
+    fn video_input_format_changed(
+        &self,
+        _events: DecklinkVideoInputFormatChangedEvents,
+        _new_display_mode: DecklinkDisplayModeId,
+        _detected_signal_flags: DecklinkDetectedVideoInputFormatFlags,
+    ) {
+    }
+
+    fn video_input_frame_arrived(&self, video_frame: Option<DecklinkVideoFrame>) -> bool {
+        self.0.fetch_add(
+            video_frame.unwrap().bytes().unwrap().0[0] as u64,
+            Ordering::Relaxed,
+        );
+        true
+    }
+}
+
+/// Compares the handler calls and the time spent in the driver callback with and without
+/// batching, at a simulated 120 frames per second.
+#[test]
+#[ignore = "benchmark"]
+fn batching_overhead_at_120fps() {
+    const FRAMES: u32 = 600;
+    let interval = Duration::from_secs(1) / 120;
+    let run = |mock: &MockInput| {
+        let mut in_callback = Duration::ZERO;
+        let start = Instant::now();
+        for n in 0..FRAMES {
+            let due = start + interval * n;
+            std::thread::sleep(due.saturating_duration_since(Instant::now()));
+            let frame = MockFrame::new(48, 2, FORMAT).fill(1);
+            let before = Instant::now();
+            assert!(mock.deliver_frame(frame).is_ok());
+            in_callback += before.elapsed();
+        }
+        in_callback / FRAMES
+    };
+
+    let plain = {
+        let backend = MockBackend::install(vec![MockDevice::new("DeckLink 8K Pro")]);
+        let counter = Arc::new(PerFrame(AtomicU64::new(0)));
+        let _input = start_input(counter.clone());
+        let per_frame = run(&backend.input(0));
+        assert_eq!(counter.0.load(Ordering::Relaxed), FRAMES as u64);
+        per_frame
+    };
+
+    let (backend, input, dispatcher, entries) = start(config(8, Duration::from_millis(100)));
+    let batched = run(&backend.input(0));
+    drop(input);
+    let stats = dispatcher.stats();
+    drop(dispatcher);
+    let handler_calls = entries.try_iter().count();
+
+    println!(
+        "per frame: {:?} in the driver callback, {} handler calls",
+        plain, FRAMES
+    );
+    println!(
+        "batched: {:?} in the driver callback, {} handler calls, {:.1} frames per batch",
+        batched,
+        handler_calls,
+        stats.average_batch_size()
+    );
+    assert!(handler_calls as u32 <= FRAMES / 4);
+}
@@ -0,0 +1,375 @@
+//! Grouping the frames of several inputs by tick, with members joining and leaving.
+
+use decklink::capture_group::{tick_of, AlignedFrameSet, GroupFrameAligner, MemberId};
+use decklink::time::{DecklinkFrameTiming, DecklinkTime};
+
+/// The tick and frames of each set, with the missing members.
+fn summary<F: Copy>(sets: &[AlignedFrameSet<F>]) -> Vec<(i64, Vec<Option<F>>, Vec<MemberId>)> {
+    sets.iter()
+        .map(|set| (set.tick, set.frames.clone(), set.missing.clone()))
+        .collect()
+}
+
+#[test]
+fn a_tick_is_given_out_once_every_member_has_a_frame() {
+    let mut aligner = GroupFrameAligner::new(2, 2);
+    let (a, first_a) = aligner.add_member(None);
+    let (b, _) = aligner.add_member(None);
+    assert_eq!((a.index(), b.index(), first_a), (0, 1, None));
+
+    assert!(aligner.push(a, 10, 'a').is_empty());
+    assert!(aligner.push(a, 11, 'a').is_empty());
+    let sets = aligner.push(b, 10, 'b');
+    assert_eq!(summary(&sets), [(10, vec![Some('a'), Some('b')], vec![])]);
+    let sets = aligner.push(b, 11, 'b');
+    assert_eq!(summary(&sets), [(11, vec![Some('a'), Some('b')], vec![])]);
+
+    // Late and duplicated frames are discarded
+    assert!(aligner.push(a, 10, 'x').is_empty());
+    assert!(aligner.push(a, 12, 'a').is_empty());
+    assert!(aligner.push(a, 12, 'y').is_empty());
+    assert_eq!(aligner.discarded_count(), 2);
+    let sets = aligner.push(b, 12, 'b');
+    assert_eq!(summary(&sets), [(12, vec![Some('y'), Some('b')], vec![])]);
+}
+
+#[test]
+fn a_missing_frame_is_given_out_after_the_pending_ticks() {
+    let mut aligner = GroupFrameAligner::new(0, 2);
+    let (a, _) = aligner.add_member(None);
+    let (b, _) = aligner.add_member(None);
+    assert!(aligner.push(a, 0, 0).is_empty());
+    assert_eq!(aligner.push(b, 0, 1).len(), 1);
+
+    // b misses tick 1, which is given out once tick 3 is seen
+    assert!(aligner.push(a, 1, 0).is_empty());
+    assert!(aligner.push(a, 2, 0).is_empty());
+    assert_eq!(aligner.push(b, 2, 1).len(), 0);
+    let sets = aligner.push(a, 3, 0);
+    assert_eq!(
+        summary(&sets),
+        [
+            (1, vec![Some(0), None], vec![b]),
+            (2, vec![Some(0), Some(1)], vec![]),
+        ]
+    );
+}
+
+#[test]
+fn members_keep_their_slots_as_others_join_and_leave() {
+    let mut aligner = GroupFrameAligner::new(2, 2);
+    let (a, _) = aligner.add_member(None);
+    let (b, _) = aligner.add_member(None);
+    let mut sets = Vec::new();
+    for tick in 0..3 {
+        sets.extend(aligner.push(a, tick, 'a'));
+        sets.extend(aligner.push(b, tick, 'b'));
+    }
+
+    // c joins after tick 2, and is placed from tick 5
+    assert_eq!(aligner.change_tick(), Some(3));
+    let (c, first_c) = aligner.add_member(aligner.change_tick());
+    assert_eq!((c.index(), first_c), (2, Some(5)));
+    for tick in 3..6 {
+        sets.extend(aligner.push(a, tick, 'a'));
+        sets.extend(aligner.push(b, tick, 'b'));
+        sets.extend(aligner.push(c, tick, 'c'));
+    }
+    // b leaves after tick 5
+    aligner.remove_member(b, aligner.change_tick());
+    for tick in 6..8 {
+        sets.extend(aligner.push(a, tick, 'a'));
+        sets.extend(aligner.push(b, tick, 'b'));
+        sets.extend(aligner.push(c, tick, 'c'));
+    }
+
+    let frames: Vec<_> = summary(&sets)
+        .into_iter()
+        .map(|(tick, frames, missing)| {
+            assert!(missing.is_empty());
+            (tick, frames)
+        })
+        .collect();
+    let ab = vec![Some('a'), Some('b'), None];
+    assert_eq!(
+        frames,
+        [
+            (0, vec![Some('a'), Some('b')]),
+            (1, vec![Some('a'), Some('b')]),
+            (2, vec![Some('a'), Some('b')]),
+            (3, ab.clone()),
+            (4, ab),
+            (5, vec![Some('a'), Some('b'), Some('c')]),
+            (6, vec![Some('a'), None, Some('c')]),
+            (7, vec![Some('a'), None, Some('c')]),
+        ]
+    );
+    // c's frames while warming up and b's after it left
+    assert_eq!(aligner.discarded_count(), 4);
+}
+
+#[test]
+fn ticks_are_stream_times_in_whole_frames() {
+    let frame = DecklinkTime::new(1001, 30000);
+    let timing = |value| DecklinkFrameTiming {
+        stream_time: DecklinkTime::new(value, 60000),
+        duration: DecklinkTime::new(2002, 60000),
+        mode_duration: frame,
+    };
+    assert_eq!(tick_of(&timing(0), frame), Some(0));
+    assert_eq!(tick_of(&timing(2002 * 7), frame), Some(7));
+    // Rounded to the nearest
+    assert_eq!(tick_of(&timing(2002 * 7 + 1000), frame), Some(7));
+    assert_eq!(tick_of(&timing(2002 * 7 + 1002), frame), Some(8));
+    assert_eq!(tick_of(&timing(0), DecklinkTime::new(0, 30000)), None);
+}
+
+#[cfg(feature = "mock-backend")]
+mod mock {
+    use super::*;
+    use decklink::capture_group::{CaptureGroup, CaptureGroupConfig, GroupEvent, InputSpec};
+    use decklink::device::get_devices;
+    use decklink::device::input::DecklinkVideoInputFlags;
+    use decklink::device::DecklinkDevice;
+    use decklink::display_mode::DecklinkDisplayModeId;
+    use decklink::frame::{DecklinkFrameBase, DecklinkPixelFormat, DecklinkVideoFrame};
+    use decklink::manifest::ManifestEvent;
+    use decklink::mock::{Delivery, MockBackend, MockDevice, MockFrame, MockInput};
+    use decklink::SdkError;
+    use std::time::Duration;
+
+    const MODE: DecklinkDisplayModeId = DecklinkDisplayModeId::HD1080p25;
+    const FORMAT: DecklinkPixelFormat = DecklinkPixelFormat::Format8BitYUV;
+
+    /// Four cameras, the last of which cannot capture in `MODE`.
+    fn backend() -> (MockBackend, Vec<DecklinkDevice>) {
+        let backend = MockBackend::install(vec![
+            MockDevice::new("Camera A"),
+            MockDevice::new("Camera B"),
+            MockDevice::new("Camera C"),
+            MockDevice::new("Camera D").modes(&[DecklinkDisplayModeId::HD720p50]),
+        ]);
+        (backend, get_devices().unwrap())
+    }
+
+    fn group() -> CaptureGroup {
+        let mut config = CaptureGroupConfig::new(DecklinkTime::new(1000, 25000));
+        config.warm_up_ticks = 2;
+        config.queue_capacity = 64;
+        CaptureGroup::new(config)
+    }
+
+    fn spec(device: DecklinkDevice) -> InputSpec {
+        InputSpec {
+            device,
+            mode: MODE,
+            pixel_format: FORMAT,
+            flags: DecklinkVideoInputFlags::empty(),
+            synchronize: true,
+        }
+    }
+
+    /// Deliver the frame of `tick` from a camera, filled with the camera's number.
+    fn deliver(mock: &MockInput, camera: u8, tick: i64) -> Delivery {
+        mock.deliver_frame(MockFrame::new(48, 2, FORMAT).fill(camera).stream_time(
+            tick * 1000,
+            1000,
+            25000,
+        ))
+    }
+
+    /// The sets given out so far, as the camera each frame came from.
+    fn take_sets(group: &CaptureGroup) -> Vec<(i64, Vec<Option<u8>>, Vec<MemberId>)> {
+        let camera = |frame: &Option<DecklinkVideoFrame>| {
+            frame.as_ref().map(|frame| frame.bytes().unwrap().0[0])
+        };
+        let mut sets = Vec::new();
+        while let Ok(set) = group.pop_set(Duration::ZERO) {
+            sets.push((
+                set.tick,
+                set.frames.iter().map(camera).collect(),
+                set.missing,
+            ));
+        }
+        sets
+    }
+
+    #[test]
+    fn a_member_added_while_capturing_joins_after_its_warm_up() {
+        let (backend, devices) = backend();
+        let mut devices = devices.into_iter();
+        let mut group = group();
+        let a = group.add_member(spec(devices.next().unwrap())).unwrap();
+        let b = group.add_member(spec(devices.next().unwrap())).unwrap();
+        let (mock_a, mock_b) = (backend.input(0), backend.input(1));
+        assert!(mock_a.is_streaming() && mock_b.is_streaming());
+        assert_eq!(
+            mock_a.video(),
+            Some((
+                MODE,
+                FORMAT,
+                DecklinkVideoInputFlags::SYNCHRONIZE_TO_CAPTURE_GROUP
+            ))
+        );
+        for tick in 0..3 {
+            assert!(deliver(&mock_a, 0, tick).is_ok());
+            assert!(deliver(&mock_b, 1, tick).is_ok());
+        }
+
+        // c joins while the others capture, and its first frames are not placed
+        let c = group.add_member(spec(devices.next().unwrap())).unwrap();
+        assert_eq!(c.index(), 2);
+        let mock_c = backend.input(2);
+        for tick in 3..9 {
+            assert!(deliver(&mock_a, 0, tick).is_ok());
+            assert!(deliver(&mock_b, 1, tick).is_ok());
+            assert!(deliver(&mock_c, 2, tick).is_ok());
+        }
+        let sets = take_sets(&group);
+        let ticks: Vec<_> = sets.iter().map(|(tick, _, _)| *tick).collect();
+        assert_eq!(ticks, (0..9).collect::<Vec<_>>());
+        for (tick, frames, missing) in &sets {
+            let expected = match tick {
+                0..=2 => vec![Some(0), Some(1)],
+                3..=4 => vec![Some(0), Some(1), None],
+                _ => vec![Some(0), Some(1), Some(2)],
+            };
+            assert_eq!(*frames, expected, "tick {}", tick);
+            assert!(missing.is_empty());
+        }
+        assert_eq!(group.members(), [a, b, c]);
+
+        let events = group.take_events();
+        assert!(matches!(
+            events[..],
+            [
+                GroupEvent::MemberAdded {
+                    tick: None,
+                    first_tick: None,
+                    ..
+                },
+                GroupEvent::MemberAdded { .. },
+                GroupEvent::MemberAdded {
+                    member,
+                    tick: Some(3),
+                    first_tick: Some(5),
+                },
+            ] if member == c
+        ));
+        assert_eq!(
+            events[2].manifest_event(),
+            Some(ManifestEvent::MemberAdded {
+                member: 2,
+                tick: Some(3),
+            })
+        );
+    }
+
+    #[test]
+    fn a_member_removed_while_capturing_leaves_the_others_running() {
+        let (backend, devices) = backend();
+        let mut devices = devices.into_iter();
+        let mut group = group();
+        let a = group.add_member(spec(devices.next().unwrap())).unwrap();
+        let b = group.add_member(spec(devices.next().unwrap())).unwrap();
+        let (mock_a, mock_b) = (backend.input(0), backend.input(1));
+        for tick in 0..3 {
+            assert!(deliver(&mock_a, 0, tick).is_ok());
+            assert!(deliver(&mock_b, 1, tick).is_ok());
+        }
+
+        group.remove_member(b).unwrap();
+        assert!(!mock_b.is_streaming());
+        assert!(!mock_b.has_callback());
+        assert_eq!(mock_b.video(), None);
+        assert!(mock_a.is_streaming());
+        assert_eq!(deliver(&mock_b, 1, 3), Delivery::NotDelivered);
+        for tick in 3..6 {
+            assert!(deliver(&mock_a, 0, tick).is_ok());
+        }
+
+        // No tick of a is repeated or skipped across the change
+        let sets = take_sets(&group);
+        let ticks: Vec<_> = sets.iter().map(|(tick, _, _)| *tick).collect();
+        assert_eq!(ticks, [0, 1, 2, 3, 4, 5]);
+        for (tick, frames, missing) in &sets {
+            let b_frame = if *tick < 3 { Some(1) } else { None };
+            assert_eq!(*frames, [Some(0), b_frame]);
+            assert!(missing.is_empty());
+        }
+        assert_eq!(group.members(), [a]);
+        assert!(matches!(group.remove_member(b), Err(SdkError::INVALIDARG)));
+
+        let events = group.take_events();
+        let removed = events.last().unwrap();
+        assert!(matches!(
+            removed,
+            GroupEvent::MemberRemoved { member, tick: Some(3) } if *member == b
+        ));
+        assert_eq!(
+            removed.manifest_event(),
+            Some(ManifestEvent::MemberRemoved {
+                member: 1,
+                tick: Some(3),
+            })
+        );
+    }
+
+    #[test]
+    fn a_member_that_fails_to_start_is_rolled_back() {
+        let (backend, devices) = backend();
+        let mut devices: Vec<_> = devices.into_iter().map(Some).collect();
+        let mut group = group();
+        let a = group.add_member(spec(devices[0].take().unwrap())).unwrap();
+        let mock_a = backend.input(0);
+        assert!(deliver(&mock_a, 0, 0).is_ok());
+
+        // Camera D does not have the mode
+        let error = group
+            .add_member(spec(devices[3].take().unwrap()))
+            .unwrap_err();
+        assert!(matches!(error, SdkError::INVALIDARG));
+        let mock_d = backend.input(3);
+        assert!(!mock_d.is_streaming());
+        assert!(!mock_d.has_callback());
+        assert_eq!(mock_d.video(), None);
+        assert_eq!(group.members(), [a]);
+        assert!(matches!(
+            group.take_events()[..],
+            [
+                GroupEvent::MemberAdded { .. },
+                GroupEvent::MemberAddFailed {
+                    error: SdkError::INVALIDARG
+                },
+            ]
+        ));
+
+        // a kept capturing, and the failed member took no slot
+        assert!(mock_a.is_streaming());
+        assert!(deliver(&mock_a, 0, 1).is_ok());
+        let b = group.add_member(spec(devices[1].take().unwrap())).unwrap();
+        assert_eq!(b.index(), 1);
+        assert!(deliver(&mock_a, 0, 2).is_ok());
+        let sets = take_sets(&group);
+        assert_eq!(
+            sets,
+            [
+                (0, vec![Some(0)], vec![]),
+                (1, vec![Some(0)], vec![]),
+                (2, vec![Some(0), None], vec![]),
+            ]
+        );
+    }
+
+    #[test]
+    fn dropping_the_group_stops_every_member() {
+        let (backend, devices) = backend();
+        let mut group = group();
+        for device in devices.into_iter().take(2) {
+            group.add_member(spec(device)).unwrap();
+        }
+        drop(group);
+        assert!(!backend.input(0).is_streaming());
+        assert!(!backend.input(1).has_callback());
+    }
+}
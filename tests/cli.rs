@@ -0,0 +1,697 @@
+//! The subcommands of the `decklink` tool against mock devices, run in process by including
+//! the binary's source as a module.
+#![cfg(all(feature = "cli", feature = "mock-backend"))]
+
+mod common;
+
+#[allow(dead_code)]
+#[path = "../src/bin/decklink-cli.rs"]
+mod cli;
+
+use clap::{CommandFactory, Parser};
+use decklink::connectors::DecklinkVideoConnection;
+use decklink::device::input::{
+    DecklinkDetectedVideoInputFormatFlags, DecklinkVideoInputFormatChangedEvents,
+};
+use decklink::display_mode::DecklinkDisplayModeId;
+use decklink::frame::DecklinkPixelFormat;
+use decklink::mock::{MockBackend, MockDevice, MockFrame};
+use decklink::timecode::{DecklinkTimecode, DecklinkTimecodeFormat};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// Run the tool with `args`, returning its exit code and output, or the failure message.
+fn run(args: &[&str]) -> (u8, String) {
+    let cli =
+        cli::Cli::try_parse_from(std::iter::once("decklink").chain(args.iter().copied())).unwrap();
+    let mut out = Vec::new();
+    match cli::run(cli, &mut out) {
+        Ok(code) => (code, String::from_utf8(out).unwrap()),
+        Err(failure) => (failure.code, failure.message),
+    }
+}
+
+fn recorder() -> MockDevice {
+    MockDevice::new("DeckLink Mini Recorder")
+        .sub_device(7, 42)
+        .format_detection(true)
+        .video_connections(
+            DecklinkVideoConnection::SDI | DecklinkVideoConnection::HDMI,
+            DecklinkVideoConnection::empty(),
+        )
+}
+
+/// A directory under the system temp dir, emptied for one test.
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("decklink-cli-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Delivers frames in the enabled pixel format to the input of device 0 whenever it
+/// streams, until dropped. With
+/// `detected`, a change to that mode is delivered first each time streaming starts.
+struct Source {
+    done: Arc<AtomicBool>,
+    /// The pixel format of the last frame delivered.
+    last_format: Arc<Mutex<Option<DecklinkPixelFormat>>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Source {
+    fn start(backend: &MockBackend, detected: Option<DecklinkDisplayModeId>) -> Source {
+        let mock = backend.input(0);
+        let done = Arc::new(AtomicBool::new(false));
+        let last_format = Arc::new(Mutex::new(None));
+        let thread = {
+            let done = done.clone();
+            let last_format = last_format.clone();
+            thread::spawn(move || {
+                let mut n = 0;
+                let mut was_streaming = false;
+                while !done.load(Ordering::SeqCst) {
+                    let streaming = mock.is_streaming();
+                    if streaming && !was_streaming {
+                        if let Some(mode) = detected {
+                            mock.deliver_format_change(
+                                DecklinkVideoInputFormatChangedEvents::DISPLAY_MODE_CHANGED,
+                                mode,
+                                DecklinkDetectedVideoInputFormatFlags::YCBCR_422,
+                            );
+                        }
+                    }
+                    was_streaming = streaming;
+                    if streaming {
+                        let pixel_format = mock
+                            .video()
+                            .map_or(DecklinkPixelFormat::Format8BitYUV, |(_, format, _)| format);
+                        let frame = MockFrame::new(48, 2, pixel_format)
+                            .fill(0x80)
+                            .stream_time(n * 1000, 1000, 25000)
+                            .vpid([0x85, 0x05, 0x00, 0x01]);
+                        mock.deliver_frame(frame);
+                        *last_format.lock().unwrap() = Some(pixel_format);
+                        n += 1;
+                    }
+                    thread::sleep(Duration::from_millis(5));
+                }
+            })
+        };
+        Source {
+            done,
+            last_format,
+            thread: Some(thread),
+        }
+    }
+
+    fn last_format(&self) -> Option<DecklinkPixelFormat> {
+        *self.last_format.lock().unwrap()
+    }
+}
+
+impl Drop for Source {
+    fn drop(&mut self) {
+        self.done.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            thread.join().unwrap();
+        }
+    }
+}
+
+#[test]
+fn help_in_the_readme_is_current() {
+    let help = cli::Cli::command().render_long_help().to_string();
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/README.md");
+    let readme = std::fs::read_to_string(path).unwrap();
+    let start = readme.find("<!-- cli-help -->\n```text\n").unwrap() + 26;
+    let end = readme.find("```\n<!-- /cli-help -->").unwrap();
+
+    if std::env::var_os("UPDATE_CLI_HELP").is_some() {
+        let updated = format!(
+            "{}{}\n{}",
+            &readme[..start],
+            help.trim_end(),
+            &readme[end..]
+        );
+        std::fs::write(path, updated).unwrap();
+    } else {
+        assert_eq!(
+            readme[start..end].trim_end(),
+            help.trim_end(),
+            "run with UPDATE_CLI_HELP=1 to update the README"
+        );
+    }
+}
+
+#[test]
+fn missing_drivers_are_reported() {
+    let backend = MockBackend::install(vec![recorder()]);
+    backend.set_api_version(None);
+    for args in [&["list"][..], &["report"], &["probe", "0"]] {
+        assert_eq!(run(args).0, cli::EXIT_NO_DRIVER);
+    }
+}
+
+#[test]
+fn scaffold_writes_a_project_without_the_drivers() {
+    let backend = MockBackend::install(vec![]);
+    backend.set_api_version(None);
+    let dir = temp_dir("scaffold");
+    let project = dir.join("capture");
+    let (code, output) = run(&["scaffold", project.to_str().unwrap(), "--audio"]);
+
+    assert_eq!(code, 0);
+    assert!(output.contains("Cargo.toml"));
+    let manifest = std::fs::read_to_string(project.join("Cargo.toml")).unwrap();
+    assert!(manifest.contains("name = \"decklink-capture\""));
+    assert!(project.join("tests/mock.rs").exists());
+
+    // A second run leaves the project alone
+    let (code, message) = run(&["scaffold", project.to_str().unwrap()]);
+    assert_eq!(code, cli::EXIT_FAILED);
+    assert!(message.ends_with("already exists"), "{}", message);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn reference_prints_the_tables_without_the_drivers() {
+    let backend = MockBackend::install(vec![]);
+    backend.set_api_version(None);
+    let (code, output) = run(&["reference"]);
+
+    assert_eq!(code, 0);
+    let json: serde_json::Value = serde_json::from_str(&output).unwrap();
+    assert_eq!(json["display_modes"][0]["sdk_constant"], "bmdModeNTSC");
+    assert_eq!(
+        json["pixel_formats"].as_array().unwrap().len(),
+        decklink::reference::all().pixel_formats.len()
+    );
+}
+
+#[test]
+fn missing_devices_are_reported() {
+    let _backend = MockBackend::install(vec![recorder()]);
+    for args in [
+        &["probe", "1"][..],
+        &["still", "DeckLink Duo", "--out", "frame.png"],
+        &["record", "99", "--out", "dir"],
+        &["config", "backup", "1"],
+        &["scan", "1"],
+    ] {
+        let (code, message) = run(args);
+        assert_eq!(code, cli::EXIT_NO_DEVICE, "{:?}", args);
+        assert!(message.starts_with("no device matches"), "{}", message);
+    }
+}
+
+#[test]
+fn devices_are_chosen_with_selectors() {
+    let _backend = MockBackend::install(vec![recorder(), recorder()]);
+    // A bare number is an index, even when it is also a persistent id
+    let (code, message) = run(&["probe", "42"]);
+    assert_eq!(code, cli::EXIT_NO_DEVICE);
+    assert!(message.starts_with("no device matches 42"), "{}", message);
+
+    let (code, message) = run(&["probe", "DeckLink Mini Recorder"]);
+    assert_eq!(code, cli::EXIT_NO_DEVICE);
+    assert_eq!(
+        message.trim_end(),
+        "name:DeckLink Mini Recorder matches more than one device: \
+         0: DeckLink Mini Recorder (id:0x2a), 1: DeckLink Mini Recorder (id:0x2a)"
+    );
+    assert_eq!(run(&["probe", "1"]).0, 0);
+    assert_eq!(run(&["probe", "label:CAM 1"]).0, cli::EXIT_UNSUPPORTED);
+}
+
+#[test]
+fn list_shows_the_devices() {
+    let _backend = MockBackend::install(vec![recorder()]);
+    assert_eq!(
+        run(&["list"]),
+        (
+            0,
+            "0: DeckLink Mini Recorder (DeckLink Mini Recorder)\n   \
+             persistent id: 42\n   \
+             video inputs:  DecklinkVideoConnection(SDI | HDMI)\n   \
+             video outputs: DecklinkVideoConnection(0x0)\n"
+                .to_string()
+        )
+    );
+    assert_eq!(
+        run(&["list", "--json"]).1,
+        "[\n  {\"index\": 0, \"display_name\": \"DeckLink Mini Recorder\", \
+         \"model_name\": \"DeckLink Mini Recorder\", \"persistent_id\": 42, \
+         \"video_inputs\": \"DecklinkVideoConnection(SDI | HDMI)\", \
+         \"video_outputs\": \"DecklinkVideoConnection(0x0)\"}\n]\n"
+    );
+}
+
+#[test]
+fn list_and_report_without_devices() {
+    let _backend = MockBackend::install(Vec::new());
+    assert_eq!(
+        run(&["list"]),
+        (0, "No Decklink devices were found\n".to_string())
+    );
+    assert_eq!(run(&["list", "--json"]), (0, "[\n]\n".to_string()));
+    assert_eq!(
+        run(&["report"]),
+        (
+            0,
+            "{\"driver_version\": \"14.2.1\", \
+             \"capabilities\": {\"allocator_provider\": false, \"profiles\": true}, \
+             \"devices\": [\n]}\n"
+                .to_string()
+        )
+    );
+}
+
+#[test]
+fn report_describes_each_device() {
+    let _backend = MockBackend::install(vec![recorder()]);
+    let (code, report) = run(&["report"]);
+    assert_eq!(code, 0);
+    assert!(report.starts_with(
+        "{\"driver_version\": \"14.2.1\", \
+         \"capabilities\": {\"allocator_provider\": false, \"profiles\": true}, \
+         \"devices\": ["
+    ));
+    assert!(report.contains("\"display_name\": \"DeckLink Mini Recorder\""));
+    assert!(report.contains("\"persistent_id\": 42"));
+    assert!(report.contains("\"temperature_c\": null"));
+}
+
+#[test]
+fn probe_reports_the_device() {
+    let _backend = MockBackend::install(vec![recorder()]);
+    let (code, text) = run(&["probe", "id:42"]);
+    assert_eq!(code, 0, "{}", text);
+    assert!(text.starts_with("Decklink probe report"));
+
+    let (code, json) = run(&["probe", "DeckLink Mini Recorder", "--json"]);
+    assert_eq!(code, 0);
+    assert!(json.starts_with('{'));
+    assert!(json.contains("\"support_matrix\""));
+}
+
+#[test]
+fn still_follows_the_detected_format() {
+    let backend = MockBackend::install(vec![recorder()]);
+    let _source = Source::start(&backend, Some(DecklinkDisplayModeId::HD1080p25));
+    let path = temp_dir("still").join("frame.png");
+
+    let (code, out) = run(&["still", "0", "--out", path.to_str().unwrap()]);
+    assert_eq!(code, 0, "{}", out);
+    assert_eq!(out, format!("Captured HD1080p25 to {}\n", path.display()));
+    let png = std::fs::read(&path).unwrap();
+    assert_eq!(&png[1..4], b"PNG");
+}
+
+#[test]
+fn hdr_stills_are_captured_in_ten_bits_and_tagged() {
+    let backend = MockBackend::install(vec![recorder()]);
+    let source = Source::start(&backend, Some(DecklinkDisplayModeId::HD1080p25));
+    let dir = temp_dir("still-hdr");
+
+    let path = dir.join("hdr16.png");
+    let (code, out) = run(&[
+        "still",
+        "0",
+        "--transfer",
+        "pq",
+        "--color-policy",
+        "hdr16",
+        "--out",
+        path.to_str().unwrap(),
+    ]);
+    assert_eq!(code, 0, "{}", out);
+    let png = std::fs::read(&path).unwrap();
+    // A 16-bit RGB header, then the cICP chunk of Rec. 2020 primaries with PQ
+    assert_eq!(&png[24..26], [16, 2]);
+    assert_eq!(
+        &png[33..45],
+        [0, 0, 0, 4, b'c', b'I', b'C', b'P', 9, 16, 0, 1]
+    );
+    assert_eq!(
+        source.last_format(),
+        Some(DecklinkPixelFormat::Format10BitYUV)
+    );
+
+    // Tone mapped to 8 bits, with nothing to tag
+    let path = dir.join("sdr.png");
+    let (code, out) = run(&[
+        "still",
+        "0",
+        "--transfer",
+        "hlg",
+        "--out",
+        path.to_str().unwrap(),
+    ]);
+    assert_eq!(code, 0, "{}", out);
+    assert!(out.ends_with("Tone mapped with Bt2408\n"), "{}", out);
+    let png = std::fs::read(&path).unwrap();
+    assert_eq!(&png[24..26], [8, 2]);
+    assert_ne!(&png[37..41], b"cICP");
+}
+
+#[test]
+fn still_needs_a_mode_without_format_detection() {
+    let _backend = common::mini_recorder();
+    let path = temp_dir("still-mode").join("frame.png");
+    let (code, _) = run(&["still", "0", "--out", path.to_str().unwrap()]);
+    assert_eq!(code, cli::EXIT_UNSUPPORTED);
+
+    let (code, message) = run(&["still", "0", "--mode", "1080p99", "--out", "frame.png"]);
+    assert_eq!(code, cli::EXIT_FAILED);
+    assert_eq!(message, "unknown display mode \"1080p99\"");
+}
+
+#[test]
+fn still_times_out_without_a_signal() {
+    let _backend = MockBackend::install(vec![recorder()]);
+    let (code, message) = run(&[
+        "still",
+        "0",
+        "--mode",
+        "1080p25",
+        "--timeout",
+        "0",
+        "--out",
+        "frame.png",
+    ]);
+    assert_eq!(code, cli::EXIT_FAILED);
+    assert_eq!(message, "no frame with a signal arrived in time");
+}
+
+#[test]
+fn record_writes_segments_and_a_manifest() {
+    let backend = MockBackend::install(vec![recorder()]);
+    let _source = Source::start(&backend, None);
+    let dir = temp_dir("record");
+
+    let (code, out) = run(&[
+        "record",
+        "0",
+        "--mode",
+        "1080p25",
+        "--seconds",
+        "1",
+        "--segment-frames",
+        "10",
+        "--out",
+        dir.to_str().unwrap(),
+    ]);
+    assert_eq!(code, 0, "{}", out);
+    assert!(out.starts_with("Recorded "), "{}", out);
+
+    let manifest = std::fs::read_to_string(dir.join("manifest.json")).unwrap();
+    assert!(manifest.contains("HD1080p25"), "{}", manifest);
+    let segments = std::fs::read_dir(&dir)
+        .unwrap()
+        .filter(|e| {
+            let name = e.as_ref().unwrap().file_name();
+            name.to_string_lossy().starts_with("segment_")
+        })
+        .count();
+    assert!(segments >= 2, "{} segments", segments);
+}
+
+#[test]
+fn record_stops_at_exactly_the_frames_asked_for() {
+    let backend = MockBackend::install(vec![recorder()]);
+    let _source = Source::start(&backend, None);
+    let dir = temp_dir("record-frames");
+
+    let (code, out) = run(&[
+        "record",
+        "0",
+        "--mode",
+        "1080p25",
+        "--frames",
+        "12",
+        "--segment-frames",
+        "5",
+        "--out",
+        dir.to_str().unwrap(),
+    ]);
+    assert_eq!(code, 0, "{}", out);
+    assert!(
+        out.starts_with("Recorded 12 frames in 3 segments"),
+        "{}",
+        out
+    );
+    assert!(!out.contains("short"), "{}", out);
+}
+
+#[test]
+fn record_keeps_the_frames_buffered_at_the_stop() {
+    let backend = MockBackend::install(vec![recorder()]);
+    let mock = backend.input(0);
+    for n in 0..3 {
+        mock.buffer_frame(
+            MockFrame::new(48, 2, DecklinkPixelFormat::Format8BitYUV)
+                .fill(0x80)
+                .stream_time(n * 1000, 1000, 25000)
+                .timecode(
+                    DecklinkTimecodeFormat::RP188VITC1,
+                    DecklinkTimecode::from_frame_number(900_000 + n as u64, 25, false),
+                ),
+        );
+    }
+    // The driver delivers its buffer once capture is paused for the stop
+    let driver = thread::spawn(move || {
+        while !mock.is_paused() {
+            thread::sleep(Duration::from_millis(1));
+        }
+        while mock.deliver_buffered().is_ok() {}
+    });
+    let dir = temp_dir("record-drain");
+
+    let (code, out) = run(&[
+        "record",
+        "0",
+        "--mode",
+        "1080p25",
+        "--seconds",
+        "0",
+        "--out",
+        dir.to_str().unwrap(),
+    ]);
+    driver.join().unwrap();
+    assert_eq!(code, 0, "{}", out);
+    assert!(
+        out.starts_with("Recorded 3 frames in 1 segments"),
+        "{}",
+        out
+    );
+
+    // The manifest has the timecode the frames carried
+    let manifest = std::fs::read_to_string(dir.join("manifest.json")).unwrap();
+    assert!(
+        manifest.contains("\"first_timecode\": \"10:00:00:00\""),
+        "{}",
+        manifest
+    );
+    assert!(
+        manifest.contains("\"last_timecode\": \"10:00:00:02\""),
+        "{}",
+        manifest
+    );
+    assert!(
+        manifest.contains(
+            "{ \"format\": \"RP188VITC1\", \"frames_with_timecode\": 3, \"longest_gap\": 0, \"discontinuities\": 0, \"duplicates\": 0 }"
+        ),
+        "{}",
+        manifest
+    );
+}
+
+#[test]
+fn record_writes_movie_segments() {
+    let backend = MockBackend::install(vec![recorder()]);
+    let mock = backend.input(0);
+    for n in 0..3 {
+        mock.buffer_frame(
+            MockFrame::new(48, 2, DecklinkPixelFormat::Format8BitYUV)
+                .fill(0x80)
+                .stream_time(n * 1000, 1000, 25000),
+        );
+    }
+    let driver = thread::spawn(move || {
+        while !mock.is_paused() {
+            thread::sleep(Duration::from_millis(1));
+        }
+        while mock.deliver_buffered().is_ok() {}
+    });
+    let dir = temp_dir("record-mov");
+
+    let (code, out) = run(&[
+        "record",
+        "0",
+        "--mode",
+        "1080p25",
+        "--seconds",
+        "0",
+        "--container",
+        "mov",
+        "--out",
+        dir.to_str().unwrap(),
+    ]);
+    driver.join().unwrap();
+    assert_eq!(code, 0, "{}", out);
+    assert!(
+        out.starts_with("Recorded 3 frames in 1 segments"),
+        "{}",
+        out
+    );
+
+    let movie = std::fs::read(dir.join("segment_00000.mov")).unwrap();
+    assert_eq!(&movie[4..8], b"ftyp");
+    // The finished segment is in the manifest
+    let manifest = std::fs::read_to_string(dir.join("manifest.json")).unwrap();
+    assert!(
+        manifest.contains(
+            "\"type\": \"segment\", \"index\": 0, \"first_frame\": 0, \"frames\": 3, \"reason\": \"Start\""
+        ),
+        "{}",
+        manifest
+    );
+}
+
+#[test]
+fn record_writes_dumps_that_inspect_and_play_read() {
+    let backend = MockBackend::install(vec![recorder().with_output()]);
+    let mock = backend.input(0);
+    for n in 0..3 {
+        // Of the size of the mode, as the output only plays those
+        mock.buffer_frame(
+            MockFrame::for_mode(
+                DecklinkDisplayModeId::HD1080p25,
+                DecklinkPixelFormat::Format8BitYUV,
+            )
+            .fill(0x80)
+            .stream_time(n * 1000, 1000, 25000),
+        );
+    }
+    let driver = thread::spawn(move || {
+        while !mock.is_paused() {
+            thread::sleep(Duration::from_millis(1));
+        }
+        while mock.deliver_buffered().is_ok() {}
+    });
+    let dir = temp_dir("record-dump");
+
+    let (code, out) = run(&[
+        "record",
+        "0",
+        "--mode",
+        "1080p25",
+        "--seconds",
+        "0",
+        "--container",
+        "dump",
+        "--out",
+        dir.to_str().unwrap(),
+    ]);
+    driver.join().unwrap();
+    assert_eq!(code, 0, "{}", out);
+    let file = dir.join("segment_00000.dump");
+
+    let (code, out) = run(&["inspect", file.to_str().unwrap(), "--json"]);
+    assert_eq!(code, 0, "{}", out);
+    assert!(
+        out.contains(
+            "\"frames\": 3, \"first_sequence\": 0, \"last_sequence\": 2, \"damage\": null"
+        ),
+        "{}",
+        out
+    );
+    assert!(out.contains("\"config\": {\"schema_version\""), "{}", out);
+
+    // Cut into the payload of the last frame
+    let bytes = std::fs::read(&file).unwrap();
+    let cut = dir.join("cut.dump");
+    std::fs::write(&cut, &bytes[..bytes.len() - 12 - (17 + 3 * 32) - 20]).unwrap();
+    let (code, out) = run(&["inspect", cut.to_str().unwrap()]);
+    assert_eq!(code, cli::EXIT_FAILED);
+    assert!(out.contains("2 frames, sequence 0 to 1"), "{}", out);
+    assert!(
+        out.contains("(Truncated), after 2 intact frames"),
+        "{}",
+        out
+    );
+
+    let (code, out) = run(&["play", "0", file.to_str().unwrap()]);
+    assert_eq!(code, 0, "{}", out);
+    assert!(out.starts_with("Played 3 frames"), "{}", out);
+    assert_eq!(backend.output(0).displayed().len(), 3);
+    assert_eq!(backend.output(0).video(), None);
+}
+
+#[test]
+fn config_is_unsupported() {
+    let _backend = MockBackend::install(vec![recorder()]);
+    for action in ["backup", "restore", "diff"] {
+        let (code, message) = run(&["config", action, "0", "config.txt"]);
+        assert_eq!(code, cli::EXIT_UNSUPPORTED);
+        assert!(message.starts_with("device configuration cannot be"));
+    }
+}
+
+#[test]
+fn scan_reports_the_detected_signal() {
+    let backend = MockBackend::install(vec![recorder()]);
+    let _source = Source::start(&backend, Some(DecklinkDisplayModeId::HD1080i50));
+
+    assert_eq!(
+        run(&["scan", "0", "--json"]),
+        (
+            0,
+            "{\"connections\": \"DecklinkVideoConnection(SDI | HDMI)\", \"signal\": true, \
+             \"detected_mode\": \"HD1080i50\", \"vpid\": {\"bytes\": \"85 05 00 01\", \
+             \"description\": \"HD 1080i50 YCbCr 4:2:2 10-bit\"}}\n"
+                .to_string()
+        )
+    );
+}
+
+#[test]
+fn scan_without_a_signal() {
+    let _backend = MockBackend::install(vec![recorder()]);
+    assert_eq!(
+        run(&["scan", "0", "--timeout", "0"]),
+        (
+            0,
+            "Input connections: DecklinkVideoConnection(SDI | HDMI)\nNo signal detected\n"
+                .to_string()
+        )
+    );
+}
+
+#[test]
+fn soak_runs_a_perturbed_mock_device_without_the_drivers() {
+    // The run installs a mock device of its own
+    let (code, output) = run(&[
+        "soak",
+        "--hours",
+        "0.01",
+        "--mode",
+        "NTSC",
+        "--profile",
+        "lowlatency",
+        "--perturb-every",
+        "5",
+    ]);
+    assert_eq!(code, 0, "{}", output);
+    assert!(output.starts_with("{\"profile\":\"lowlatency\",\"mock\":true,\"passed\":true"));
+    assert!(output.contains("\"failure\":null"));
+
+    assert_eq!(
+        run(&["soak", "--mode", "8K"]),
+        (cli::EXIT_FAILED, "unknown display mode \"8K\"".to_string())
+    );
+}
@@ -0,0 +1,240 @@
+//! Pairing capture pixel formats with the chroma sampling of the signal.
+
+#[cfg(feature = "mock-backend")]
+mod common;
+
+use decklink::device::input::{
+    CaptureColorMode, ColorModeError, DecklinkDetectedVideoInputFormatFlags,
+};
+use decklink::frame::DecklinkPixelFormat;
+use strum::IntoEnumIterator;
+
+const RGB: DecklinkDetectedVideoInputFormatFlags = DecklinkDetectedVideoInputFormatFlags::RGB_444;
+const YCBCR: DecklinkDetectedVideoInputFormatFlags =
+    DecklinkDetectedVideoInputFormatFlags::YCBCR_422;
+
+#[test]
+fn auto_follows_the_detected_signal() {
+    assert_eq!(
+        CaptureColorMode::Auto.resolve(RGB),
+        CaptureColorMode::RGB444
+    );
+    assert_eq!(
+        CaptureColorMode::Auto.resolve(YCBCR),
+        CaptureColorMode::YCbCr422
+    );
+    assert_eq!(
+        CaptureColorMode::Auto.resolve(DecklinkDetectedVideoInputFormatFlags::empty()),
+        CaptureColorMode::YCbCr422
+    );
+    // A fixed mode is kept whatever the signal
+    assert_eq!(
+        CaptureColorMode::YCbCr422.resolve(RGB),
+        CaptureColorMode::YCbCr422
+    );
+    assert_eq!(
+        CaptureColorMode::RGB444.resolve(YCBCR),
+        CaptureColorMode::RGB444
+    );
+}
+
+#[test]
+fn every_format_has_the_color_mode_it_captures_in() {
+    for pixel_format in DecklinkPixelFormat::iter() {
+        let mode = CaptureColorMode::of_pixel_format(pixel_format);
+        match pixel_format {
+            DecklinkPixelFormat::Format8BitYUV | DecklinkPixelFormat::Format10BitYUV => {
+                assert_eq!(mode, Some(CaptureColorMode::YCbCr422))
+            }
+            DecklinkPixelFormat::FormatH265 | DecklinkPixelFormat::FormatDNxHR => {
+                assert_eq!(mode, None)
+            }
+            _ => assert_eq!(mode, Some(CaptureColorMode::RGB444), "{:?}", pixel_format),
+        }
+
+        assert_eq!(
+            CaptureColorMode::Auto.is_compatible(pixel_format),
+            mode.is_some()
+        );
+        for fixed in [CaptureColorMode::YCbCr422, CaptureColorMode::RGB444] {
+            assert_eq!(fixed.is_compatible(pixel_format), mode == Some(fixed));
+            assert_eq!(
+                fixed.pixel_formats().contains(&pixel_format),
+                mode == Some(fixed)
+            );
+        }
+    }
+    assert_eq!(
+        CaptureColorMode::YCbCr422.pixel_formats()[0],
+        DecklinkPixelFormat::Format10BitYUV
+    );
+}
+
+#[test]
+fn incompatible_error_lists_the_formats_that_would_work() {
+    let error = ColorModeError::Incompatible {
+        color_mode: CaptureColorMode::YCbCr422,
+        pixel_format: DecklinkPixelFormat::Format8BitBGRA,
+    };
+    assert_eq!(
+        error.to_string(),
+        "YCbCr422 requires one of Format10BitYUV, Format8BitYUV; Format8BitBGRA requested"
+    );
+}
+
+#[cfg(feature = "mock-backend")]
+mod mock {
+    use super::common;
+    use super::{RGB, YCBCR};
+    use decklink::device::get_devices;
+    use decklink::device::input::{
+        CaptureColorMode, ColorModeError, DecklinkInputDevice, DecklinkVideoInputFlags,
+    };
+    use decklink::display_mode::DecklinkDisplayModeId;
+    use decklink::frame::DecklinkPixelFormat;
+    use decklink::mock::{MockBackend, MockDevice, DEFAULT_MODES};
+    use strum::IntoEnumIterator;
+
+    const MODE: DecklinkDisplayModeId = DecklinkDisplayModeId::HD1080p25;
+    const COLOR_MODES: [CaptureColorMode; 3] = [
+        CaptureColorMode::Auto,
+        CaptureColorMode::YCbCr422,
+        CaptureColorMode::RGB444,
+    ];
+
+    fn input() -> DecklinkInputDevice {
+        get_devices().unwrap()[0].input().unwrap()
+    }
+
+    #[test]
+    fn every_color_mode_and_pixel_format_pairing() {
+        // The device captures every pixel format, so that only the pairing decides
+        let pixel_formats: Vec<_> = DecklinkPixelFormat::iter().collect();
+        let device = MockDevice::new("DeckLink 4K Extreme").pixel_formats(&pixel_formats);
+        let backend = MockBackend::install(vec![device]);
+        let mock = backend.input(0);
+
+        for color_mode in COLOR_MODES {
+            for pixel_format in DecklinkPixelFormat::iter() {
+                let mut input = input();
+                let result = input.enable_video_input_with_color_mode(
+                    MODE,
+                    color_mode,
+                    Some(pixel_format),
+                    None,
+                    DecklinkVideoInputFlags::empty(),
+                );
+                if color_mode.is_compatible(pixel_format) {
+                    assert_eq!(result.unwrap(), pixel_format);
+                    assert_eq!(
+                        mock.video(),
+                        Some((MODE, pixel_format, DecklinkVideoInputFlags::empty()))
+                    );
+                    input.disable_video_input().unwrap();
+                } else {
+                    // Rejected before the device is touched
+                    assert!(
+                        matches!(
+                            result,
+                            Err(ColorModeError::Incompatible { color_mode: c, pixel_format: p })
+                                if c == color_mode && p == pixel_format
+                        ),
+                        "{:?} {:?}",
+                        color_mode,
+                        pixel_format
+                    );
+                    assert_eq!(mock.video(), None);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn auto_is_resolved_before_checking_the_format() {
+        let backend = MockBackend::install(vec![MockDevice::new("DeckLink 4K Extreme")]);
+        let mut input = input();
+
+        let result = input.enable_video_input_with_color_mode(
+            MODE,
+            CaptureColorMode::Auto,
+            Some(DecklinkPixelFormat::Format8BitYUV),
+            Some(RGB),
+            DecklinkVideoInputFlags::empty(),
+        );
+        assert!(matches!(
+            result,
+            Err(ColorModeError::Incompatible {
+                color_mode: CaptureColorMode::RGB444,
+                ..
+            })
+        ));
+        assert_eq!(backend.input(0).video(), None);
+    }
+
+    #[test]
+    fn format_is_chosen_from_those_the_device_supports() {
+        let device = MockDevice::new("DeckLink 4K Extreme")
+            .modes(&DEFAULT_MODES)
+            .pixel_formats(&[
+                DecklinkPixelFormat::Format8BitYUV,
+                DecklinkPixelFormat::Format10BitRGB,
+                DecklinkPixelFormat::Format8BitBGRA,
+            ]);
+        let backend = MockBackend::install(vec![device]);
+        let mock = backend.input(0);
+
+        let chosen = |color_mode, detected| {
+            let mut input = input();
+            let pixel_format = input
+                .enable_video_input_with_color_mode(
+                    MODE,
+                    color_mode,
+                    None,
+                    detected,
+                    DecklinkVideoInputFlags::empty(),
+                )
+                .unwrap();
+            assert_eq!(mock.video().unwrap().1, pixel_format);
+            input.disable_video_input().unwrap();
+            pixel_format
+        };
+        assert_eq!(
+            chosen(CaptureColorMode::YCbCr422, None),
+            DecklinkPixelFormat::Format8BitYUV
+        );
+        assert_eq!(
+            chosen(CaptureColorMode::RGB444, None),
+            DecklinkPixelFormat::Format10BitRGB
+        );
+        assert_eq!(
+            chosen(CaptureColorMode::Auto, Some(RGB)),
+            DecklinkPixelFormat::Format10BitRGB
+        );
+        assert_eq!(
+            chosen(CaptureColorMode::Auto, Some(YCBCR)),
+            DecklinkPixelFormat::Format8BitYUV
+        );
+    }
+
+    #[test]
+    fn color_mode_without_a_supported_format_fails() {
+        // The default mock input only captures YUV
+        let backend = common::mini_recorder();
+        let mut input = input();
+
+        let result = input.enable_video_input_with_color_mode(
+            MODE,
+            CaptureColorMode::RGB444,
+            None,
+            None,
+            DecklinkVideoInputFlags::empty(),
+        );
+        assert!(matches!(
+            result,
+            Err(ColorModeError::Unsupported {
+                color_mode: CaptureColorMode::RGB444
+            })
+        ));
+        assert_eq!(backend.input(0).video(), None);
+    }
+}
@@ -0,0 +1,60 @@
+//! Fixtures shared by the tests that capture from a mock input.
+//!
+//! Each test crate includes this with `mod common;` and uses what it needs of it.
+#![allow(dead_code)]
+
+use decklink::device::get_devices;
+use decklink::device::input::{
+    DecklinkAudioSampleRate, DecklinkAudioSampleType, DecklinkInputDevice, DecklinkVideoInputFlags,
+    InputHandler,
+};
+use decklink::display_mode::DecklinkDisplayModeId;
+use decklink::frame::DecklinkPixelFormat;
+use decklink::mock::{MockBackend, MockDevice, MockInput};
+use std::sync::Arc;
+
+/// Installs a backend of a single DeckLink Mini Recorder.
+pub fn mini_recorder() -> MockBackend {
+    MockBackend::install(vec![MockDevice::new("DeckLink Mini Recorder")])
+}
+
+/// The input of the first device, with video enabled.
+pub fn open_input(
+    mode: DecklinkDisplayModeId,
+    format: DecklinkPixelFormat,
+    flags: DecklinkVideoInputFlags,
+) -> DecklinkInputDevice {
+    let mut input = get_devices().unwrap()[0].input().unwrap();
+    input.enable_video_input(mode, format, flags).unwrap();
+    input
+}
+
+/// Enables 16-bit stereo audio at 48kHz.
+pub fn enable_stereo_audio(input: &mut DecklinkInputDevice) {
+    input
+        .enable_audio_input(
+            DecklinkAudioSampleRate::Rate48kHz,
+            DecklinkAudioSampleType::Int16,
+            2,
+        )
+        .unwrap();
+}
+
+/// Registers `handler` and starts the streams.
+pub fn start_streams(input: &mut DecklinkInputDevice, handler: Arc<dyn InputHandler>) {
+    input.set_handler(Some(handler)).unwrap();
+    input.start_streams().unwrap();
+}
+
+/// Starts capturing from the input of the first device, returning it with the mock driving it.
+pub fn start_input(
+    backend: &MockBackend,
+    mode: DecklinkDisplayModeId,
+    format: DecklinkPixelFormat,
+    flags: DecklinkVideoInputFlags,
+    handler: Arc<dyn InputHandler>,
+) -> (DecklinkInputDevice, MockInput) {
+    let mut input = open_input(mode, format, flags);
+    start_streams(&mut input, handler);
+    (input, backend.input(0))
+}
@@ -0,0 +1,219 @@
+//! The results frame callbacks return to the driver, and C++ style callbacks.
+
+#[cfg(feature = "mock-backend")]
+mod common;
+
+use decklink::device::input::CallbackResult;
+use decklink::SdkError;
+
+#[test]
+fn each_result_maps_to_one_hresult() {
+    assert_eq!(CallbackResult::Ok.hresult(), 0);
+    assert_eq!(CallbackResult::False.hresult(), 1);
+    assert_eq!(CallbackResult::Fail.hresult(), SdkError::FAIL as i32);
+    assert_eq!(CallbackResult::from(true), CallbackResult::Ok);
+    assert_eq!(CallbackResult::from(false), CallbackResult::False);
+    assert_eq!(CallbackResult::default(), CallbackResult::Ok);
+}
+
+#[cfg(feature = "mock-backend")]
+mod mock {
+    use super::common;
+    use super::*;
+    use decklink::device::input::{
+        DeckLinkInputCallback, DecklinkAudioInputPacket, DecklinkDetectedVideoInputFormatFlags,
+        DecklinkInputDevice, DecklinkVideoInputFlags, DecklinkVideoInputFormatChangedEvents,
+        InputHandler,
+    };
+    use decklink::display_mode::DecklinkDisplayModeId;
+    use decklink::experimental::compat::{ClassicInputAdapter, ClassicInputCallback};
+    use decklink::frame::{DecklinkFrameBase, DecklinkPixelFormat, DecklinkVideoFrame};
+    use decklink::mock::{Delivery, MockBackend, MockFrame, MockInput};
+    use decklink::tap::TapSplitter;
+    use decklink::time::DecklinkFrameTiming;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    const FORMAT: DecklinkPixelFormat = DecklinkPixelFormat::Format8BitYUV;
+
+    fn start(
+        backend: &MockBackend,
+        callback: Arc<dyn InputHandler>,
+    ) -> (DecklinkInputDevice, MockInput) {
+        let mut input = common::open_input(
+            DecklinkDisplayModeId::HD1080p25,
+            FORMAT,
+            DecklinkVideoInputFlags::empty(),
+        );
+        common::enable_stereo_audio(&mut input);
+        common::start_streams(&mut input, callback);
+        (input, backend.input(0))
+    }
+
+    fn frame(n: u8) -> MockFrame {
+        MockFrame::new(48, 2, FORMAT)
+            .fill(n)
+            .stream_time(n as i64 * 1000, 1000, 25000)
+    }
+
+    /// A stereo packet of `frames` sample frames.
+    fn audio(frames: usize) -> Vec<u8> {
+        vec![0; frames * 4]
+    }
+
+    /// A native callback that accepts frames while `accept` is set.
+    struct Native {
+        accept: AtomicBool,
+    }
+
+    impl DeckLinkInputCallback for Native {
+        fn video_input_format_changed(
+            &self,
+            _events: DecklinkVideoInputFormatChangedEvents,
+            _new_display_mode: DecklinkDisplayModeId,
+            _detected_signal_flags: DecklinkDetectedVideoInputFormatFlags,
+        ) {
+        }
+
+        fn video_input_frame_arrived(&self, _video_frame: Option<DecklinkVideoFrame>) -> bool {
+            self.accept.load(Ordering::SeqCst)
+        }
+    }
+
+    #[test]
+    fn true_and_false_return_s_ok_and_s_false() {
+        let backend = common::mini_recorder();
+        let native = Arc::new(Native {
+            accept: AtomicBool::new(true),
+        });
+        let (_input, mock) = start(&backend, native.clone());
+        assert_eq!(mock.deliver_frame(frame(1)), Delivery::Returned(0));
+        native.accept.store(false, Ordering::SeqCst);
+        assert_eq!(mock.deliver_frame(frame(2)), Delivery::Returned(1));
+        assert_eq!(mock.deliver_audio(&audio(4)), Delivery::Returned(1));
+    }
+
+    /// What one call of a classic callback received: the first byte of the frame, the
+    /// sample frames of the packet and the stream time of the frame, in 25000ths.
+    type Call = (Option<u8>, Option<usize>, Option<i64>);
+
+    /// Records its calls and returns `result` for each frame.
+    struct Classic {
+        result: Mutex<CallbackResult>,
+        calls: Mutex<Vec<Call>>,
+        changes: Mutex<Vec<DecklinkDisplayModeId>>,
+    }
+
+    impl Classic {
+        fn new() -> Arc<Classic> {
+            Arc::new(Classic {
+                result: Mutex::new(CallbackResult::Ok),
+                calls: Mutex::new(Vec::new()),
+                changes: Mutex::new(Vec::new()),
+            })
+        }
+
+        fn take_calls(&self) -> Vec<Call> {
+            std::mem::take(&mut *self.calls.lock().unwrap())
+        }
+    }
+
+    impl ClassicInputCallback for Classic {
+        fn video_input_format_changed(
+            &self,
+            _notification_events: DecklinkVideoInputFormatChangedEvents,
+            new_display_mode: DecklinkDisplayModeId,
+            _detected_signal_flags: DecklinkDetectedVideoInputFormatFlags,
+        ) -> CallbackResult {
+            self.changes.lock().unwrap().push(new_display_mode);
+            CallbackResult::Fail
+        }
+
+        fn video_input_frame_arrived(
+            &self,
+            video_frame: Option<DecklinkVideoFrame>,
+            audio_packet: Option<DecklinkAudioInputPacket>,
+            timing: Option<DecklinkFrameTiming>,
+        ) -> CallbackResult {
+            self.calls.lock().unwrap().push((
+                video_frame.map(|frame| frame.bytes().unwrap().0[0]),
+                audio_packet.map(|packet| packet.sample_frame_count()),
+                timing.and_then(|timing| timing.stream_time.rescale(25000).map(|t| t.value)),
+            ));
+            *self.result.lock().unwrap()
+        }
+    }
+
+    #[test]
+    fn a_classic_callback_sees_the_video_and_audio_of_a_callback_together() {
+        let backend = common::mini_recorder();
+        let classic = Classic::new();
+        let adapter = Arc::new(ClassicInputAdapter::new(classic.clone()));
+        let (_input, mock) = start(&backend, adapter);
+
+        assert_eq!(
+            mock.deliver_frame_with_audio(frame(1), &audio(1920)),
+            Delivery::Returned(0)
+        );
+        *classic.result.lock().unwrap() = CallbackResult::False;
+        assert_eq!(mock.deliver_frame(frame(2)), Delivery::Returned(1));
+        *classic.result.lock().unwrap() = CallbackResult::Fail;
+        assert_eq!(
+            mock.deliver_audio(&audio(960)),
+            Delivery::Returned(SdkError::FAIL as i32)
+        );
+        // A frame that cannot be read is not passed on, though its audio is
+        assert_eq!(
+            mock.deliver_frame_with_audio(frame(3).conversion_fails(), &audio(480)),
+            Delivery::Returned(0)
+        );
+        assert_eq!(
+            classic.take_calls(),
+            [
+                (Some(1), Some(1920), Some(1000)),
+                (Some(2), None, Some(2000)),
+                (None, Some(960), None),
+                (None, Some(480), None),
+            ]
+        );
+
+        // The result of a format change is not passed back
+        assert!(mock
+            .deliver_format_change(
+                DecklinkVideoInputFormatChangedEvents::DISPLAY_MODE_CHANGED,
+                DecklinkDisplayModeId::HD1080p50,
+                DecklinkDetectedVideoInputFormatFlags::YCBCR_422,
+            )
+            .is_ok());
+        assert_eq!(
+            *classic.changes.lock().unwrap(),
+            [DecklinkDisplayModeId::HD1080p50]
+        );
+    }
+
+    #[test]
+    fn a_wrapped_classic_callback_sees_the_same_calls() {
+        let backend = common::mini_recorder();
+        let classic = Classic::new();
+        let adapter = Arc::new(ClassicInputAdapter::new(classic.clone()));
+        let splitter = TapSplitter::new(adapter, None);
+        let (_input, mock) = start(&backend, splitter.callback());
+
+        assert_eq!(
+            mock.deliver_frame_with_audio(frame(1), &audio(1920)),
+            Delivery::Returned(0)
+        );
+        *classic.result.lock().unwrap() = CallbackResult::Fail;
+        assert_eq!(
+            mock.deliver_frame(frame(2)),
+            Delivery::Returned(SdkError::FAIL as i32)
+        );
+        assert_eq!(
+            classic.take_calls(),
+            [
+                (Some(1), Some(1920), Some(1000)),
+                (Some(2), None, Some(2000))
+            ]
+        );
+    }
+}
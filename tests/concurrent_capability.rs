@@ -0,0 +1,190 @@
+//! Whether mock sub-devices of a Duo style card can capture while playing back.
+#![cfg(feature = "mock-backend")]
+
+mod common;
+
+use decklink::device::attributes::{DecklinkDuplexMode, DecklinkProfileId};
+use decklink::device::get_devices;
+use decklink::device::status::{DecklinkDeviceBusyState, DecklinkStatusId};
+use decklink::device::ConcurrentCapability;
+use decklink::mock::{MockBackend, MockDevice};
+use decklink::SdkError;
+
+const GROUP: i64 = 0x5a10;
+
+/// The sub-devices `ids` of one card, in `profile` with `duplex` mode.
+fn card(profile: DecklinkProfileId, duplex: DecklinkDuplexMode, ids: &[i64]) -> Vec<MockDevice> {
+    ids.iter()
+        .map(|id| {
+            MockDevice::new(&format!("DeckLink Duo 2 ({})", id))
+                .with_output()
+                .profile(profile, duplex)
+                .sub_device(GROUP, *id)
+        })
+        .collect()
+}
+
+#[test]
+fn full_duplex_sub_devices_capture_while_playing_back() {
+    let _backend = MockBackend::install(card(
+        DecklinkProfileId::TwoSubDevicesFullDuplex,
+        DecklinkDuplexMode::Full,
+        &[1, 2],
+    ));
+    let devices = get_devices().unwrap();
+
+    assert_eq!(
+        devices[0].concurrent_capability().unwrap(),
+        ConcurrentCapability {
+            can_capture_and_playback_simultaneously: true,
+            limited_by_profile: None,
+            paired_device: Some(2),
+            busy: DecklinkDeviceBusyState::empty(),
+        }
+    );
+    assert_eq!(
+        devices[1].concurrent_capability().unwrap().paired_device,
+        Some(1)
+    );
+    assert!(devices[0].require_concurrent_capture_and_playback().is_ok());
+}
+
+#[test]
+fn half_duplex_profile_limits_and_names_the_full_duplex_one() {
+    let _backend = MockBackend::install(card(
+        DecklinkProfileId::TwoSubDevicesHalfDuplex,
+        DecklinkDuplexMode::Half,
+        &[1, 2],
+    ));
+    let devices = get_devices().unwrap();
+
+    let capability = devices[0].concurrent_capability().unwrap();
+    assert!(!capability.can_capture_and_playback_simultaneously);
+    assert_eq!(
+        capability.limited_by_profile,
+        Some(DecklinkProfileId::TwoSubDevicesHalfDuplex)
+    );
+    assert_eq!(capability.paired_device, Some(2));
+
+    let error = devices[0]
+        .require_concurrent_capture_and_playback()
+        .unwrap_err();
+    assert_eq!(
+        error.active_profile,
+        Some(DecklinkProfileId::TwoSubDevicesHalfDuplex)
+    );
+    assert_eq!(
+        error.permitting_profile,
+        Some(DecklinkProfileId::TwoSubDevicesFullDuplex)
+    );
+    assert!(error.error.is_none());
+}
+
+#[test]
+fn one_half_duplex_sub_device_is_pointed_at_one_full_duplex() {
+    let _backend = MockBackend::install(card(
+        DecklinkProfileId::OneSubDeviceHalfDuplex,
+        DecklinkDuplexMode::Half,
+        &[1],
+    ));
+    let devices = get_devices().unwrap();
+
+    assert_eq!(
+        devices[0].concurrent_capability().unwrap().paired_device,
+        None
+    );
+    let error = devices[0]
+        .require_concurrent_capture_and_playback()
+        .unwrap_err();
+    assert_eq!(
+        error.permitting_profile,
+        Some(DecklinkProfileId::OneSubDeviceFullDuplex)
+    );
+}
+
+#[test]
+fn four_sub_devices_have_no_single_pair() {
+    let _backend = MockBackend::install(card(
+        DecklinkProfileId::FourSubDevicesHalfDuplex,
+        DecklinkDuplexMode::Half,
+        &[1, 2, 3, 4],
+    ));
+    let devices = get_devices().unwrap();
+
+    let capability = devices[2].concurrent_capability().unwrap();
+    assert_eq!(capability.paired_device, None);
+    assert_eq!(
+        capability.limited_by_profile,
+        Some(DecklinkProfileId::FourSubDevicesHalfDuplex)
+    );
+    assert_eq!(
+        devices[2]
+            .require_concurrent_capture_and_playback()
+            .unwrap_err()
+            .permitting_profile,
+        Some(DecklinkProfileId::TwoSubDevicesFullDuplex)
+    );
+}
+
+#[test]
+fn simplex_and_inactive_are_not_limited_by_the_profile() {
+    let mut devices = card(
+        DecklinkProfileId::TwoSubDevicesHalfDuplex,
+        DecklinkDuplexMode::Simplex,
+        &[1],
+    );
+    devices.extend(card(
+        DecklinkProfileId::TwoSubDevicesHalfDuplex,
+        DecklinkDuplexMode::Inactive,
+        &[2],
+    ));
+    let _backend = MockBackend::install(devices);
+    let devices = get_devices().unwrap();
+
+    for device in &devices {
+        let capability = device.concurrent_capability().unwrap();
+        assert!(!capability.can_capture_and_playback_simultaneously);
+        assert_eq!(capability.limited_by_profile, None);
+        assert!(device.require_concurrent_capture_and_playback().is_err());
+    }
+}
+
+#[test]
+fn busy_state_is_reported() {
+    let devices = card(
+        DecklinkProfileId::TwoSubDevicesFullDuplex,
+        DecklinkDuplexMode::Full,
+        &[1],
+    )
+    .into_iter()
+    .map(|d| {
+        d.status_int(
+            DecklinkStatusId::Busy,
+            DecklinkDeviceBusyState::CAPTURE_BUSY.bits() as i64,
+        )
+    })
+    .collect();
+    let _backend = MockBackend::install(devices);
+    let devices = get_devices().unwrap();
+
+    assert_eq!(
+        devices[0].concurrent_capability().unwrap().busy,
+        DecklinkDeviceBusyState::CAPTURE_BUSY
+    );
+}
+
+#[test]
+fn device_without_a_duplex_mode_fails() {
+    let _backend = common::mini_recorder();
+    let devices = get_devices().unwrap();
+
+    assert!(matches!(
+        devices[0].concurrent_capability(),
+        Err(SdkError::NOTIMPL)
+    ));
+    let error = devices[0]
+        .require_concurrent_capture_and_playback()
+        .unwrap_err();
+    assert!(matches!(error.error, Some(SdkError::NOTIMPL)));
+    assert_eq!(error.active_profile, None);
+}
@@ -0,0 +1,259 @@
+//! The messages of config validation, which users paste into issues, so they must not
+//! change by accident.
+
+use decklink::batch::BatchConfig;
+use decklink::capture_group::CaptureGroupConfig;
+use decklink::cpu::{CpuBudgets, CpuConfig};
+use decklink::dispatch::DispatchConfig;
+use decklink::retention::RetentionLimit;
+use decklink::tap::{FormatChangePolicy, TapSpec};
+use decklink::time::DecklinkTime;
+use std::time::Duration;
+
+fn message<T>(result: Result<T, decklink::config::ConfigError>) -> String {
+    match result {
+        Ok(_) => panic!("the config was accepted"),
+        Err(error) => error.to_string(),
+    }
+}
+
+#[test]
+fn defaults_are_valid() {
+    assert!(DispatchConfig::builder().build().is_ok());
+    assert!(BatchConfig::builder().build().is_ok());
+    assert!(TapSpec::builder().build().is_ok());
+    assert!(CaptureGroupConfig::builder(DecklinkTime::new(1000, 25000))
+        .build()
+        .is_ok());
+    assert!(CpuConfig::builder().build().is_ok());
+}
+
+#[test]
+fn dispatch() {
+    assert_eq!(
+        message(DispatchConfig::builder().workers(0).build()),
+        "invalid DispatchConfig: workers must be at least 1"
+    );
+    assert_eq!(
+        message(DispatchConfig::builder().queue_capacity(0).build()),
+        "invalid DispatchConfig: queue_capacity must be at least 1, or every frame would be dropped"
+    );
+    assert_eq!(
+        message(DispatchConfig::builder().latency_window(0).build()),
+        "invalid DispatchConfig: latency_window must be at least 1 frame"
+    );
+    assert_eq!(
+        message(
+            DispatchConfig::builder()
+                .starvation_threshold(Duration::ZERO)
+                .build()
+        ),
+        "invalid DispatchConfig: starvation_threshold must be longer than zero, or every frame would be reported as starved"
+    );
+}
+
+#[test]
+fn batch() {
+    assert_eq!(
+        message(BatchConfig::builder().max_frames(0).build()),
+        "invalid BatchConfig: max_frames must be at least 1"
+    );
+    assert_eq!(
+        message(BatchConfig::builder().queue_capacity(0).build()),
+        "invalid BatchConfig: queue_capacity must be at least 1, or every frame would be dropped"
+    );
+    assert_eq!(
+        message(BatchConfig::builder().max_latency(Duration::ZERO).build()),
+        "invalid BatchConfig: max_latency is zero, so batches are delivered before a second frame can join them; set max_frames to 1 to deliver frames one at a time"
+    );
+    assert!(BatchConfig::builder()
+        .max_latency(Duration::ZERO)
+        .max_frames(1)
+        .build()
+        .is_ok());
+}
+
+#[test]
+fn tap() {
+    assert_eq!(
+        message(TapSpec::builder().frames(0).build()),
+        "invalid TapSpec: frames must be at least 1, or None to tap until cancelled"
+    );
+    assert_eq!(
+        message(TapSpec::builder().duration(Duration::ZERO).build()),
+        "invalid TapSpec: duration must be longer than zero, or None to tap until cancelled"
+    );
+    assert_eq!(
+        message(TapSpec::builder().decimation(0).build()),
+        "invalid TapSpec: decimation must be at least 1, which gives every frame"
+    );
+    assert_eq!(
+        message(
+            TapSpec::builder()
+                .retention(RetentionLimit::Frames(0))
+                .build()
+        ),
+        "invalid TapSpec: retention must allow at least 1 frame"
+    );
+    assert_eq!(
+        message(
+            TapSpec::builder()
+                .retention(RetentionLimit::Bytes(0))
+                .build()
+        ),
+        "invalid TapSpec: retention must allow more than 0 bytes"
+    );
+    assert_eq!(
+        message(
+            TapSpec::builder()
+                .format_change(FormatChangePolicy::PauseUntilStable { frames: 0 })
+                .build()
+        ),
+        "invalid TapSpec: format_change must wait for at least 1 stable frame, or be CleanCut to wait for none"
+    );
+}
+
+#[test]
+fn capture_group() {
+    assert_eq!(
+        message(CaptureGroupConfig::builder(DecklinkTime::new(0, 25000)).build()),
+        "invalid CaptureGroupConfig: frame_duration must be a positive duration in a positive timescale, such as DecklinkDisplayMode::frame_duration gives"
+    );
+    assert_eq!(
+        message(
+            CaptureGroupConfig::builder(DecklinkTime::new(1000, 25000))
+                .queue_capacity(0)
+                .build()
+        ),
+        "invalid CaptureGroupConfig: queue_capacity must be at least 1, or every set would be dropped"
+    );
+}
+
+#[test]
+fn cpu() {
+    assert_eq!(
+        message(CpuConfig::builder().window(0).build()),
+        "invalid CpuConfig: window must be at least 1 frame"
+    );
+    assert_eq!(
+        message(
+            CpuConfig::builder()
+                .budgets(CpuBudgets {
+                    transform: Some(0.0),
+                    ..CpuBudgets::default()
+                })
+                .build()
+        ),
+        "invalid CpuConfig: budgets must be more than zero"
+    );
+    assert_eq!(
+        message(CpuConfig::builder().hysteresis(1.0).build()),
+        "invalid CpuConfig: hysteresis must be at least 0 and less than 1"
+    );
+}
+
+#[cfg(feature = "mock-backend")]
+#[test]
+fn input_spec() {
+    use decklink::capture_group::InputSpec;
+    use decklink::device::get_devices;
+    use decklink::device::input::DecklinkVideoInputFlags;
+    use decklink::display_mode::DecklinkDisplayModeId;
+    use decklink::frame::DecklinkPixelFormat;
+    use decklink::mock::{MockBackend, MockDevice};
+
+    let _backend = MockBackend::install(vec![MockDevice::new("DeckLink Quad 2 (1)")]);
+    let builder = || {
+        InputSpec::builder()
+            .device(get_devices().unwrap().remove(0))
+            .mode(
+                DecklinkDisplayModeId::HD1080p25,
+                DecklinkPixelFormat::Format10BitYUV,
+            )
+    };
+
+    let spec = builder().synchronize(true).build().unwrap();
+    assert_eq!(spec.device.display_name().unwrap(), "DeckLink Quad 2 (1)");
+    assert_eq!(
+        (spec.mode, spec.pixel_format, spec.flags, spec.synchronize),
+        (
+            DecklinkDisplayModeId::HD1080p25,
+            DecklinkPixelFormat::Format10BitYUV,
+            DecklinkVideoInputFlags::empty(),
+            true
+        )
+    );
+    assert!(spec.validate().is_ok());
+
+    let flags = DecklinkVideoInputFlags::SYNCHRONIZE_TO_CAPTURE_GROUP;
+    assert!(builder().flags(flags).synchronize(true).build().is_ok());
+    let expected = "invalid InputSpec: flags include SYNCHRONIZE_TO_CAPTURE_GROUP, but synchronize is false; set synchronize instead of the flag";
+    assert_eq!(message(builder().flags(flags).validate()), expected);
+    assert_eq!(message(builder().flags(flags).build()), expected);
+
+    // A spec written out by hand is checked the same
+    let mut spec = builder().build().unwrap();
+    spec.flags = flags;
+    assert_eq!(message(spec.validate()), expected);
+}
+
+#[cfg(all(feature = "realtime", target_os = "linux"))]
+#[test]
+fn realtime() {
+    use decklink::realtime::{RealtimeConfig, SchedPolicy, ThreadScheduling};
+
+    assert!(RealtimeConfig::fifo(50).validate().is_ok());
+    assert_eq!(
+        message(
+            RealtimeConfig::builder(ThreadScheduling {
+                policy: SchedPolicy::Fifo,
+                priority: 0,
+            })
+            .build()
+        ),
+        "invalid RealtimeConfig: scheduling has priority 0, but Fifo takes priorities from 1 to 99"
+    );
+}
+
+#[cfg(feature = "container")]
+#[test]
+fn mov() {
+    use decklink::device::input::DecklinkAudioSampleType;
+    use decklink::experimental::mov::{MovAudioConfig, MovConfig};
+
+    let duration = DecklinkTime::new(1000, 25000);
+    assert!(MovConfig::builder(duration).build().is_ok());
+    assert_eq!(
+        message(MovConfig::builder(DecklinkTime::new(1000, 0)).build()),
+        "invalid MovConfig: frame_duration must be a positive duration in a positive timescale, such as DecklinkDisplayMode::frame_duration gives"
+    );
+    let audio = MovAudioConfig {
+        sample_rate: 48000,
+        channels: 0,
+        sample_type: DecklinkAudioSampleType::Int32,
+    };
+    assert_eq!(
+        message(MovConfig::builder(duration).audio(audio).build()),
+        "invalid MovConfig: audio has no channels, use None for a file without audio"
+    );
+    assert_eq!(
+        message(
+            MovConfig::builder(duration)
+                .audio(MovAudioConfig {
+                    sample_rate: 0,
+                    channels: 2,
+                    ..audio
+                })
+                .build()
+        ),
+        "invalid MovConfig: audio has a sample rate of 0"
+    );
+    assert_eq!(
+        message(
+            MovConfig::builder(duration)
+                .flush_interval(Some(Duration::ZERO))
+                .build()
+        ),
+        "invalid MovConfig: flush_interval must be longer than zero, or None to write the index when the file is finished"
+    );
+}
@@ -0,0 +1,304 @@
+//! Warnings for capture settings of a mock input that do not match the signal.
+#![cfg(feature = "mock-backend")]
+
+mod common;
+
+use decklink::conformance::{
+    ConformanceChecker, ConformanceWarning, ConformanceWarningKind, EnabledConfig,
+};
+use decklink::device::get_devices;
+use decklink::device::input::{
+    CaptureColorMode, DeckLinkInputCallback, DecklinkAudioInputPacket, DecklinkAudioSampleRate,
+    DecklinkAudioSampleType, DecklinkDetectedVideoInputFormatFlags, DecklinkInputDevice,
+    DecklinkVideoInputFlags, DecklinkVideoInputFormatChangedEvents,
+};
+use decklink::device::DecklinkDeviceDisplayModes;
+use decklink::display_mode::{DecklinkDisplayModeId, DecklinkFieldDominance};
+use decklink::frame::{DecklinkPixelFormat, DecklinkVideoFrame};
+use decklink::mock::{MockBackend, MockInput};
+use std::sync::Mutex;
+
+const MODE: DecklinkDisplayModeId = DecklinkDisplayModeId::HD1080p25;
+const FORMAT: DecklinkPixelFormat = DecklinkPixelFormat::Format8BitYUV;
+const CHANNELS: usize = 8;
+/// Each audio packet is 40 ms at 48 kHz.
+const PACKET_FRAMES: usize = 1920;
+
+/// Feeds every callback to a checker, collecting the warnings.
+struct Checker {
+    checker: Mutex<ConformanceChecker>,
+    warnings: Mutex<Vec<ConformanceWarning>>,
+}
+
+impl Checker {
+    fn take(&self) -> Vec<ConformanceWarning> {
+        std::mem::take(&mut *self.warnings.lock().unwrap())
+    }
+}
+
+impl DeckLinkInputCallback for Checker {
+    fn video_input_format_changed(
+        &self,
+        _events: DecklinkVideoInputFormatChangedEvents,
+        new_display_mode: DecklinkDisplayModeId,
+        detected_signal_flags: DecklinkDetectedVideoInputFormatFlags,
+    ) {
+        let warnings = self
+            .checker
+            .lock()
+            .unwrap()
+            .check_format(new_display_mode, detected_signal_flags);
+        self.warnings.lock().unwrap().extend(warnings);
+    }
+
+    fn video_input_frame_arrived(&self, _video_frame: Option<DecklinkVideoFrame>) -> bool {
+        true
+    }
+
+    fn audio_input_packet_arrived(&self, audio_packet: DecklinkAudioInputPacket) {
+        let warnings = self
+            .checker
+            .lock()
+            .unwrap()
+            .check_audio_packet(&audio_packet)
+            .unwrap();
+        self.warnings.lock().unwrap().extend(warnings);
+    }
+}
+
+/// Capture in `MODE` with 8 channels of audio, checking every audio packet, with channels
+/// silent after 3 packets.
+fn start(
+    backend: &MockBackend,
+    configure: impl FnOnce(&mut ConformanceChecker),
+) -> (DecklinkInputDevice, MockInput, std::sync::Arc<Checker>) {
+    let devices = get_devices().unwrap();
+    let mut input = devices[0].input().unwrap();
+    let mut checker = ConformanceChecker::new(
+        EnabledConfig {
+            display_mode: MODE,
+            pixel_format: FORMAT,
+        },
+        &input.display_modes().unwrap(),
+        3 * PACKET_FRAMES as u64,
+    );
+    checker.set_audio_sample_interval(1);
+    configure(&mut checker);
+    let checker = std::sync::Arc::new(Checker {
+        checker: Mutex::new(checker),
+        warnings: Mutex::new(Vec::new()),
+    });
+
+    input
+        .enable_video_input(
+            MODE,
+            FORMAT,
+            DecklinkVideoInputFlags::ENABLE_FORMAT_DETECTION,
+        )
+        .unwrap();
+    input
+        .enable_audio_input(
+            DecklinkAudioSampleRate::Rate48kHz,
+            DecklinkAudioSampleType::Int16,
+            CHANNELS as u32,
+        )
+        .unwrap();
+    input.set_callback(Some(checker.clone())).unwrap();
+    input.start_streams().unwrap();
+    (input, backend.input(0), checker)
+}
+
+fn detect(
+    mock: &MockInput,
+    mode: DecklinkDisplayModeId,
+    bits: DecklinkDetectedVideoInputFormatFlags,
+) {
+    assert!(mock
+        .deliver_format_change(
+            DecklinkVideoInputFormatChangedEvents::DISPLAY_MODE_CHANGED,
+            mode,
+            DecklinkDetectedVideoInputFormatFlags::YCBCR_422 | bits,
+        )
+        .is_ok());
+}
+
+/// A packet with audio on the first `channels_with_audio` channels only.
+fn audio(mock: &MockInput, channels_with_audio: usize) {
+    let mut samples = vec![0i16; PACKET_FRAMES * CHANNELS];
+    for (i, sample) in samples.iter_mut().enumerate() {
+        if i % CHANNELS < channels_with_audio {
+            *sample = 1000;
+        }
+    }
+    let bytes: Vec<u8> = samples.iter().flat_map(|s| s.to_ne_bytes()).collect();
+    assert!(mock.deliver_audio(&bytes).is_ok());
+}
+
+const BIT_DEPTH_8: DecklinkDetectedVideoInputFormatFlags =
+    DecklinkDetectedVideoInputFormatFlags::BIT_DEPTH_8;
+
+#[test]
+fn mode_mismatch_is_reported_once() {
+    let backend = common::mini_recorder();
+    let (_input, mock, checker) = start(&backend, |_| {});
+
+    detect(&mock, DecklinkDisplayModeId::HD1080p5994, BIT_DEPTH_8);
+    detect(&mock, DecklinkDisplayModeId::HD1080p5994, BIT_DEPTH_8);
+    assert_eq!(
+        checker.take(),
+        vec![ConformanceWarning::ModeMismatch {
+            enabled: MODE,
+            detected: DecklinkDisplayModeId::HD1080p5994,
+        }]
+    );
+
+    // Reported again once the condition clears and returns
+    detect(&mock, MODE, BIT_DEPTH_8);
+    assert!(checker.take().is_empty());
+    detect(&mock, DecklinkDisplayModeId::HD1080p24, BIT_DEPTH_8);
+    assert_eq!(
+        checker.take(),
+        vec![ConformanceWarning::ModeMismatch {
+            enabled: MODE,
+            detected: DecklinkDisplayModeId::HD1080p24,
+        }]
+    );
+}
+
+#[test]
+fn interlaced_source_is_reported() {
+    let backend = common::mini_recorder();
+    let (_input, mock, checker) = start(&backend, |checker| {
+        checker.suppress(ConformanceWarningKind::ModeMismatch)
+    });
+
+    detect(&mock, DecklinkDisplayModeId::HD1080i50, BIT_DEPTH_8);
+    detect(&mock, DecklinkDisplayModeId::HD1080i50, BIT_DEPTH_8);
+    assert_eq!(
+        checker.take(),
+        vec![ConformanceWarning::InterlaceMismatch {
+            enabled: DecklinkFieldDominance::ProgressiveFrame,
+            detected: DecklinkFieldDominance::UpperFieldFirst,
+        }]
+    );
+}
+
+#[test]
+fn bit_depth_downgrade_is_reported() {
+    let backend = common::mini_recorder();
+    let (_input, mock, checker) = start(&backend, |_| {});
+
+    detect(&mock, MODE, BIT_DEPTH_8);
+    assert!(checker.take().is_empty());
+    let bit_depth_10 = DecklinkDetectedVideoInputFormatFlags::BIT_DEPTH_10;
+    detect(&mock, MODE, bit_depth_10);
+    detect(&mock, MODE, bit_depth_10);
+    assert_eq!(
+        checker.take(),
+        vec![ConformanceWarning::BitDepthDowngrade {
+            capture_bits: 8,
+            detected_bits: 10,
+        }]
+    );
+}
+
+#[test]
+fn color_mode_mismatch_is_reported() {
+    let backend = common::mini_recorder();
+    let (_input, mock, checker) = start(&backend, |_| {});
+
+    // An RGB 4:4:4 source captured in a YUV format
+    for _ in 0..2 {
+        assert!(mock
+            .deliver_format_change(
+                DecklinkVideoInputFormatChangedEvents::COLORSPACE_CHANGED,
+                MODE,
+                DecklinkDetectedVideoInputFormatFlags::RGB_444 | BIT_DEPTH_8,
+            )
+            .is_ok());
+    }
+    assert_eq!(
+        checker.take(),
+        vec![ConformanceWarning::ColorModeMismatch {
+            capture: CaptureColorMode::YCbCr422,
+            detected: CaptureColorMode::RGB444,
+        }]
+    );
+    detect(&mock, MODE, BIT_DEPTH_8);
+    assert!(checker.take().is_empty());
+}
+
+#[test]
+fn silent_audio_channels_are_reported() {
+    let backend = common::mini_recorder();
+    let (_input, mock, checker) = start(&backend, |_| {});
+
+    // The source embeds 2 of the 8 channels
+    audio(&mock, 2);
+    audio(&mock, 2);
+    assert!(checker.take().is_empty());
+    for _ in 0..5 {
+        audio(&mock, 2);
+    }
+    assert_eq!(
+        checker.take(),
+        vec![ConformanceWarning::SilentAudioChannels {
+            channels: vec![2, 3, 4, 5, 6, 7],
+        }]
+    );
+
+    // Audio on every channel clears the condition
+    audio(&mock, CHANNELS);
+    for _ in 0..3 {
+        audio(&mock, 4);
+    }
+    assert_eq!(
+        checker.take(),
+        vec![ConformanceWarning::SilentAudioChannels {
+            channels: vec![4, 5, 6, 7],
+        }]
+    );
+}
+
+#[test]
+fn audio_is_sampled() {
+    let backend = common::mini_recorder();
+    let (_input, mock, checker) = start(&backend, |checker| checker.set_audio_sample_interval(3));
+
+    // Only every third packet is inspected, and stands for the two skipped before it
+    audio(&mock, 2);
+    audio(&mock, 2);
+    assert!(checker.take().is_empty());
+    audio(&mock, 2);
+    assert_eq!(
+        checker.take(),
+        vec![ConformanceWarning::SilentAudioChannels {
+            channels: vec![2, 3, 4, 5, 6, 7],
+        }]
+    );
+}
+
+#[test]
+fn suppressed_kinds_are_not_reported() {
+    let backend = common::mini_recorder();
+    let (_input, mock, checker) = start(&backend, |checker| {
+        checker.suppress(ConformanceWarningKind::SilentAudioChannels);
+        checker.suppress(ConformanceWarningKind::BitDepthDowngrade);
+    });
+
+    for _ in 0..5 {
+        audio(&mock, 2);
+    }
+    detect(
+        &mock,
+        DecklinkDisplayModeId::HD1080p5994,
+        DecklinkDetectedVideoInputFormatFlags::BIT_DEPTH_12,
+    );
+    assert_eq!(
+        checker.take(),
+        vec![ConformanceWarning::ModeMismatch {
+            enabled: MODE,
+            detected: DecklinkDisplayModeId::HD1080p5994,
+        }]
+    );
+}
@@ -0,0 +1,276 @@
+//! Packing and unpacking the 10-bit RGB formats, and converting frames between formats.
+
+use decklink::convert::{
+    can_convert, convert_frame, frame_to_rgb16, pack_rgb_10bit, r10b_to_rgb16, r10l_to_rgb16,
+    r210_to_rgb16, rgb16_to_r10b, rgb16_to_r10l, rgb16_to_r210, rgb_10bit_row_bytes,
+    unpack_rgb_10bit, ConvertError,
+};
+use decklink::frame::{
+    DecklinkFrameBase, DecklinkFrameFlags, DecklinkPixelFormat, DecklinkVideoMutableFrame,
+};
+
+const FORMATS: [DecklinkPixelFormat; 3] = [
+    DecklinkPixelFormat::Format10BitRGB,
+    DecklinkPixelFormat::Format10BitRGBX,
+    DecklinkPixelFormat::Format10BitRGBXLE,
+];
+
+/// Random 10-bit components from a fixed seed, so failures reproduce.
+fn random_components(count: usize) -> Vec<u16> {
+    let mut state = 0x2545_f491_4f6c_dd1du64;
+    (0..count)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state >> 32) as u16 & 0x3ff
+        })
+        .collect()
+}
+
+#[test]
+fn random_triples_round_trip_losslessly() {
+    let components = random_components(3 * 4096);
+    for format in FORMATS {
+        let mut packed = vec![0; 4 * 4096];
+        pack_rgb_10bit(format, &components, &mut packed).unwrap();
+        let mut unpacked = vec![0; components.len()];
+        unpack_rgb_10bit(format, &packed, &mut unpacked).unwrap();
+        assert_eq!(unpacked, components, "{:?}", format);
+    }
+}
+
+#[test]
+fn named_functions_match_the_format_dispatch() {
+    let components = random_components(3 * 64);
+    let mut expected = vec![0; 4 * 64];
+    let mut packed = vec![0; 4 * 64];
+    let mut unpacked = vec![0; 3 * 64];
+    type Pair = (
+        fn(&[u16], &mut [u8]) -> Result<(), ConvertError>,
+        fn(&[u8], &mut [u16]) -> Result<(), ConvertError>,
+    );
+    let pairs: [Pair; 3] = [
+        (rgb16_to_r210, r210_to_rgb16),
+        (rgb16_to_r10b, r10b_to_rgb16),
+        (rgb16_to_r10l, r10l_to_rgb16),
+    ];
+    for (format, (pack, unpack)) in FORMATS.into_iter().zip(pairs) {
+        pack_rgb_10bit(format, &components, &mut expected).unwrap();
+        pack(&components, &mut packed).unwrap();
+        assert_eq!(packed, expected, "{:?}", format);
+        unpack(&packed, &mut unpacked).unwrap();
+        assert_eq!(unpacked, components, "{:?}", format);
+    }
+}
+
+#[test]
+fn words_have_the_documented_layouts() {
+    // Red 940, green 64, blue 502, which put a distinct pattern in every byte
+    let rgb = [940, 64, 502];
+    let mut bytes = [0; 4];
+
+    rgb16_to_r210(&rgb, &mut bytes).unwrap();
+    assert_eq!(bytes, [0x3a, 0xc1, 0x01, 0xf6]);
+    rgb16_to_r10b(&rgb, &mut bytes).unwrap();
+    assert_eq!(bytes, [0xeb, 0x04, 0x07, 0xd8]);
+    rgb16_to_r10l(&rgb, &mut bytes).unwrap();
+    assert_eq!(bytes, [0xd8, 0x07, 0x04, 0xeb]);
+}
+
+#[test]
+fn one_word_read_as_each_format_differs() {
+    // Video range white in R10l, read as the other two formats as well
+    let bytes = [0xb0, 0xce, 0x3a, 0xeb];
+    let mut rgb = [0; 3];
+
+    r10l_to_rgb16(&bytes, &mut rgb).unwrap();
+    assert_eq!(rgb, [940, 940, 940]);
+    r10b_to_rgb16(&bytes, &mut rgb).unwrap();
+    assert_eq!(rgb, [707, 227, 698]);
+    r210_to_rgb16(&bytes, &mut rgb).unwrap();
+    assert_eq!(rgb, [780, 910, 747]);
+}
+
+#[test]
+fn unused_bits_are_ignored_and_left_clear() {
+    let mut rgb = [0; 3];
+    r210_to_rgb16(&[0xc0, 0, 0, 0], &mut rgb).unwrap();
+    assert_eq!(rgb, [0, 0, 0]);
+    r10b_to_rgb16(&[0, 0, 0, 0x03], &mut rgb).unwrap();
+    assert_eq!(rgb, [0, 0, 0]);
+    r10l_to_rgb16(&[0x03, 0, 0, 0], &mut rgb).unwrap();
+    assert_eq!(rgb, [0, 0, 0]);
+
+    // Components above 10 bits are clamped rather than spilling into the unused bits
+    let mut bytes = [0; 4];
+    rgb16_to_r210(&[0xffff, 0xffff, 0xffff], &mut bytes).unwrap();
+    assert_eq!(bytes, [0x3f, 0xff, 0xff, 0xff]);
+    rgb16_to_r10b(&[0xffff, 0xffff, 0xffff], &mut bytes).unwrap();
+    assert_eq!(bytes, [0xff, 0xff, 0xff, 0xfc]);
+    rgb16_to_r10l(&[0xffff, 0xffff, 0xffff], &mut bytes).unwrap();
+    assert_eq!(bytes, [0xfc, 0xff, 0xff, 0xff]);
+}
+
+#[test]
+fn short_buffers_and_other_formats_are_rejected() {
+    assert!(matches!(
+        r210_to_rgb16(&[0; 7], &mut [0; 6]),
+        Err(ConvertError::BufferTooSmall)
+    ));
+    assert!(matches!(
+        rgb16_to_r10l(&[0; 6], &mut [0; 7]),
+        Err(ConvertError::BufferTooSmall)
+    ));
+    assert!(matches!(
+        unpack_rgb_10bit(DecklinkPixelFormat::Format10BitYUV, &[0; 4], &mut [0; 3]),
+        Err(ConvertError::UnsupportedPixelFormat(
+            DecklinkPixelFormat::Format10BitYUV
+        ))
+    ));
+}
+
+#[test]
+fn ten_bit_rgb_formats_are_identified() {
+    for format in FORMATS {
+        assert!(format.is_rgb_10bit());
+    }
+    assert!(!DecklinkPixelFormat::Format10BitYUV.is_rgb_10bit());
+    assert!(!DecklinkPixelFormat::Format12BitRGB.is_rgb_10bit());
+    assert!(!DecklinkPixelFormat::Format8BitBGRA.is_rgb_10bit());
+}
+
+#[test]
+fn rows_are_padded_to_256_bytes() {
+    assert_eq!(rgb_10bit_row_bytes(0), 0);
+    assert_eq!(rgb_10bit_row_bytes(1), 256);
+    assert_eq!(rgb_10bit_row_bytes(64), 256);
+    assert_eq!(rgb_10bit_row_bytes(65), 512);
+    assert_eq!(rgb_10bit_row_bytes(1920), 7680);
+    assert_eq!(rgb_10bit_row_bytes(720), 3072);
+}
+
+#[test]
+fn frames_are_unpacked_without_their_padding() {
+    let (width, height) = (70, 2);
+    let row_bytes = rgb_10bit_row_bytes(width);
+    let components = random_components(width * height * 3);
+    for format in FORMATS {
+        let mut bytes = vec![0xff; row_bytes * height];
+        for (row, src) in bytes
+            .chunks_exact_mut(row_bytes)
+            .zip(components.chunks_exact(width * 3))
+        {
+            pack_rgb_10bit(format, src, row).unwrap();
+        }
+        let mut frame = DecklinkVideoMutableFrame::create(
+            width,
+            height,
+            row_bytes,
+            format,
+            DecklinkFrameFlags::empty(),
+        );
+        frame.copy_bytes(&bytes).unwrap();
+        assert_eq!(frame_to_rgb16(&frame).unwrap(), components, "{:?}", format);
+    }
+
+    // Rows too short for their pixels
+    let mut frame = DecklinkVideoMutableFrame::create(
+        width,
+        height,
+        width * 4 - 1,
+        DecklinkPixelFormat::Format10BitRGBX,
+        DecklinkFrameFlags::empty(),
+    );
+    frame
+        .copy_bytes(&vec![0; (width * 4 - 1) * height])
+        .unwrap();
+    assert!(matches!(
+        frame_to_rgb16(&frame),
+        Err(ConvertError::BufferTooSmall)
+    ));
+}
+
+fn frame_of(format: DecklinkPixelFormat, width: usize, bytes: &[u8]) -> DecklinkVideoMutableFrame {
+    let row_bytes = format.bytes_per_row(width).unwrap();
+    let mut frame = DecklinkVideoMutableFrame::create(
+        width,
+        bytes.len() / row_bytes,
+        row_bytes,
+        format,
+        DecklinkFrameFlags::empty(),
+    );
+    frame.copy_bytes(bytes).unwrap();
+    frame
+}
+
+#[test]
+fn v210_converts_to_uyvy_keeping_the_component_order() {
+    // Eight pixels, a whole group of six and part of the next, in a row padded to 48 pixels
+    let width = 8;
+    let components: Vec<u32> = (0..16).map(|n| 64 + n * 40 + 1).collect();
+    let mut row = vec![
+        0;
+        DecklinkPixelFormat::Format10BitYUV
+            .bytes_per_row(width)
+            .unwrap()
+    ];
+    for (word, chunk) in row.chunks_exact_mut(4).zip(components.chunks(3)) {
+        let value = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |word, (i, c)| word | c << (i * 10));
+        word.copy_from_slice(&value.to_le_bytes());
+    }
+
+    let frame = frame_of(DecklinkPixelFormat::Format10BitYUV, width, &row);
+    let converted = convert_frame(&frame, DecklinkPixelFormat::Format8BitYUV).unwrap();
+    assert_eq!(converted.pixel_format(), DecklinkPixelFormat::Format8BitYUV);
+    assert_eq!(converted.row_bytes(), 16);
+    let expected: Vec<u8> = components.iter().map(|c| ((c + 2) >> 2) as u8).collect();
+    assert_eq!(converted.bytes().unwrap().0, &expected[..]);
+}
+
+#[test]
+fn ten_bit_rgb_converts_to_full_range_8bit_rgb() {
+    let width = 3;
+    let components = [64, 502, 940, 940, 64, 0, 1023, 64, 64];
+    let mut row = vec![0; rgb_10bit_row_bytes(width)];
+    rgb16_to_r210(&components, &mut row).unwrap();
+    let frame = frame_of(DecklinkPixelFormat::Format10BitRGB, width, &row);
+
+    let bgra = convert_frame(&frame, DecklinkPixelFormat::Format8BitBGRA).unwrap();
+    assert_eq!(
+        bgra.bytes().unwrap().0,
+        &[255, 128, 0, 255, 0, 0, 255, 255, 0, 0, 255, 255]
+    );
+    let argb = convert_frame(&frame, DecklinkPixelFormat::Format8BitARGB).unwrap();
+    assert_eq!(
+        argb.bytes().unwrap().0,
+        &[255, 0, 128, 255, 255, 255, 0, 0, 255, 255, 0, 0]
+    );
+    let back = convert_frame(&argb, DecklinkPixelFormat::Format8BitBGRA).unwrap();
+    assert_eq!(back.bytes().unwrap().0, bgra.bytes().unwrap().0);
+
+    let r10b = convert_frame(&frame, DecklinkPixelFormat::Format10BitRGBX).unwrap();
+    assert_eq!(frame_to_rgb16(&r10b).unwrap(), components);
+}
+
+#[test]
+fn unsupported_conversions_are_refused() {
+    use DecklinkPixelFormat::*;
+    assert!(can_convert(Format10BitYUV, Format8BitYUV));
+    assert!(can_convert(Format8BitYUV, Format8BitYUV));
+    assert!(!can_convert(Format8BitYUV, Format10BitYUV));
+    assert!(!can_convert(Format10BitYUV, Format8BitBGRA));
+    assert!(!can_convert(FormatH265, FormatH265));
+
+    let frame = frame_of(Format8BitYUV, 2, &[0; 4]);
+    assert!(matches!(
+        convert_frame(&frame, Format10BitYUV),
+        Err(ConvertError::UnsupportedConversion {
+            from: Format8BitYUV,
+            to: Format10BitYUV
+        })
+    ));
+}
@@ -0,0 +1,403 @@
+//! Attributing processor time to the stages of capture, and the budget events raised from it.
+
+#[cfg(feature = "mock-backend")]
+mod common;
+
+use decklink::cpu::{CpuBudgets, CpuConfig, CpuEvent, CpuMeter, CpuStage};
+use decklink::deinterlace::FrameLayout;
+use decklink::experimental::transform::{
+    FrameBufferMut, FrameTransform, FrameView, NegotiationError, TransformChain, TransformError,
+};
+use decklink::frame::DecklinkPixelFormat;
+use decklink::latency::{LatencyConflict, LatencyProfile};
+use decklink::testing::TestFrameBuilder;
+use std::time::Duration;
+
+const INTERVAL: Duration = Duration::from_millis(10);
+
+fn ms(ms: u64) -> Duration {
+    Duration::from_millis(ms)
+}
+
+fn meter(window: usize, budgets: CpuBudgets) -> CpuMeter {
+    CpuMeter::new(
+        CpuConfig::builder()
+            .frame_interval(INTERVAL)
+            .window(window)
+            .budgets(budgets)
+            .sustained(ms(30))
+            .hysteresis(0.1)
+            .build()
+            .unwrap(),
+    )
+}
+
+fn close(a: f64, b: f64) -> bool {
+    (a - b).abs() < 1e-6
+}
+
+#[test]
+fn stages_are_fractions_of_the_frame_interval() {
+    let meter = meter(8, CpuBudgets::default());
+    for _ in 0..8 {
+        meter.record(CpuStage::Callback, ms(1));
+        meter.record(CpuStage::Transform, ms(6));
+        meter.frame_arrived();
+    }
+    // Time recorded after the last frame is not in the report until the next
+    meter.record(CpuStage::Consumer, ms(9));
+
+    let report = meter.report();
+    assert_eq!((report.frames, report.window), (8, 8));
+    assert!(close(report.stage(CpuStage::Callback).utilization, 0.1));
+    assert!(close(report.stage(CpuStage::Transform).utilization, 0.6));
+    assert_eq!(report.stage(CpuStage::Transform).per_frame, ms(6));
+    assert_eq!(report.stage(CpuStage::Consumer).utilization, 0.0);
+    assert!(close(report.total.utilization, 0.7));
+    assert_eq!(report.busiest_stage(), CpuStage::Transform);
+    assert_eq!(
+        report.stage(CpuStage::Transform).to_string(),
+        "transform is using 60% of the 10.0ms frame interval"
+    );
+}
+
+#[test]
+fn threads_are_added_up_and_split() {
+    let meter = meter(4, CpuBudgets::default());
+    for _ in 0..4 {
+        meter.frame_arrived();
+        std::thread::scope(|scope| {
+            for time in [ms(6), ms(2)] {
+                let meter = meter.clone();
+                scope.spawn(move || meter.record(CpuStage::Consumer, time));
+            }
+        });
+    }
+    meter.frame_arrived();
+
+    let consumer = meter.report().stage(CpuStage::Consumer).clone();
+    assert!(close(consumer.utilization, 0.8));
+    // Every frame was handled by two new threads
+    assert_eq!(consumer.workers.len(), 8);
+    let busiest = consumer.busiest_worker().unwrap();
+    // A thread handled a single frame of the four in the window
+    assert!(close(busiest.utilization, 0.15));
+    assert!(close(
+        consumer.workers.iter().map(|w| w.utilization).sum::<f64>(),
+        consumer.utilization
+    ));
+}
+
+#[test]
+fn budget_events_are_sustained_and_recover_with_hysteresis() {
+    let meter = meter(
+        1,
+        CpuBudgets {
+            consumer: Some(0.5),
+            ..CpuBudgets::default()
+        },
+    );
+    let frame = |time| {
+        meter.record(CpuStage::Consumer, time);
+        meter.frame_arrived();
+        meter.take_events()
+    };
+
+    // Over the budget for less than the sustained three frames
+    assert!(frame(ms(6)).is_empty());
+    assert!(frame(ms(6)).is_empty());
+    assert!(frame(ms(4)).is_empty());
+    assert!(frame(ms(6)).is_empty());
+    assert!(frame(ms(6)).is_empty());
+    assert!(matches!(
+        frame(ms(6))[..],
+        [CpuEvent::BudgetExceeded {
+            stage: Some(CpuStage::Consumer),
+            busiest: CpuStage::Consumer,
+            ..
+        }]
+    ));
+    assert!(meter.report().stage(CpuStage::Consumer).over_budget);
+    assert!(frame(ms(7)).is_empty());
+
+    // Under the budget, but not by the hysteresis
+    assert!(frame(ms(45) / 10).is_empty());
+    assert!(frame(ms(6)).is_empty());
+    assert!(matches!(
+        frame(ms(3))[..],
+        [CpuEvent::BudgetRecovered {
+            stage: Some(CpuStage::Consumer),
+            ..
+        }]
+    ));
+    assert!(!meter.report().stage(CpuStage::Consumer).over_budget);
+}
+
+#[test]
+fn total_budget_names_the_busiest_stage() {
+    let meter = meter(
+        1,
+        CpuBudgets {
+            total: Some(0.9),
+            ..CpuBudgets::default()
+        },
+    );
+    let mut events = Vec::new();
+    for _ in 0..3 {
+        meter.record(CpuStage::Callback, ms(2));
+        meter.record(CpuStage::Conversion, ms(5));
+        meter.record(CpuStage::Consumer, ms(3));
+        meter.frame_arrived();
+        events.extend(meter.take_events());
+    }
+    assert!(matches!(
+        events[..],
+        [CpuEvent::BudgetExceeded {
+            stage: None,
+            busiest: CpuStage::Conversion,
+            ..
+        }]
+    ));
+}
+
+/// Copies frames through, taking at least `delay` for each.
+struct Slow {
+    delay: Duration,
+}
+
+impl FrameTransform for Slow {
+    fn name(&self) -> &str {
+        "slow"
+    }
+
+    fn negotiate(&self, input: FrameLayout) -> Result<FrameLayout, NegotiationError> {
+        Ok(input)
+    }
+
+    fn process(
+        &self,
+        input: FrameView<'_>,
+        output: &mut FrameBufferMut<'_>,
+    ) -> Result<(), TransformError> {
+        std::thread::sleep(self.delay);
+        output.data_mut().copy_from_slice(input.data());
+        Ok(())
+    }
+}
+
+#[test]
+fn slowed_transform_is_attributed_to_its_stage() {
+    let meter = meter(4, CpuBudgets::default());
+    let mut chain = TransformChain::new()
+        .with(Slow { delay: ms(2) })
+        .with(Slow { delay: ms(1) });
+    chain.set_cpu_meter(Some(meter.clone()));
+    let frame = TestFrameBuilder::new(16, 2)
+        .pixel_format(DecklinkPixelFormat::Format8BitBGRA)
+        .build()
+        .unwrap();
+
+    for _ in 0..4 {
+        meter.measure(CpuStage::Consumer, || {
+            chain.run(&frame).map(|_| ()).unwrap()
+        });
+        meter.frame_arrived();
+    }
+    let report = meter.report();
+    let transform = report.stage(CpuStage::Transform);
+    assert!(transform.per_frame >= ms(3));
+    assert_eq!(transform.workers.len(), 1);
+    // The consumer here is measured around the chain, so it includes the chain's time
+    assert!(report.stage(CpuStage::Consumer).per_frame >= transform.per_frame);
+    assert_eq!(report.stage(CpuStage::Callback).per_frame, Duration::ZERO);
+}
+
+#[test]
+fn low_latency_checks_the_report() {
+    let meter = meter(2, LatencyProfile::LowLatency.cpu_budgets());
+    for _ in 0..2 {
+        meter.record(CpuStage::Callback, ms(7));
+        meter.frame_arrived();
+    }
+    let report = meter.report();
+    assert_eq!(LatencyProfile::Default.check_cpu(&report), Ok(()));
+    let conflict = LatencyProfile::LowLatency.check_cpu(&report).unwrap_err();
+    assert_eq!(
+        conflict,
+        LatencyConflict::CpuBudget {
+            stage: Some(CpuStage::Callback),
+            per_frame: ms(7),
+            budget: ms(5),
+        }
+    );
+    assert_eq!(
+        conflict.to_string(),
+        "the callback stage takes 7ms a frame, more than the 5ms low latency allows"
+    );
+}
+
+#[cfg(feature = "mock-backend")]
+mod mock {
+    use super::common;
+    use super::*;
+    use decklink::device::input::{
+        DeckLinkInputCallback, DecklinkDetectedVideoInputFormatFlags, DecklinkInputDevice,
+        DecklinkVideoInputFlags, DecklinkVideoInputFormatChangedEvents, InputHandler,
+    };
+    use decklink::dispatch::{
+        DispatchConfig, DispatchHandler, DispatchPool, DispatchedFrame, SourceId,
+    };
+    use decklink::display_mode::DecklinkDisplayModeId;
+    use decklink::frame::DecklinkVideoFrame;
+    use decklink::mock::MockFrame;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Arc;
+    use std::time::Instant;
+
+    /// The frame interval of `MODE`.
+    const MODE_INTERVAL: Duration = Duration::from_millis(40);
+    const MODE: DecklinkDisplayModeId = DecklinkDisplayModeId::HD1080p25;
+    const FORMAT: DecklinkPixelFormat = DecklinkPixelFormat::Format8BitYUV;
+
+    /// Takes `delay` milliseconds over each frame.
+    #[derive(Default)]
+    struct Slowed {
+        delay: AtomicU64,
+    }
+
+    impl Slowed {
+        fn sleep(&self) {
+            std::thread::sleep(ms(self.delay.load(Ordering::SeqCst)));
+        }
+    }
+
+    impl DeckLinkInputCallback for Slowed {
+        fn video_input_format_changed(
+            &self,
+            _events: DecklinkVideoInputFormatChangedEvents,
+            _new_display_mode: DecklinkDisplayModeId,
+            _detected_signal_flags: DecklinkDetectedVideoInputFormatFlags,
+        ) {
+        }
+
+        fn video_input_frame_arrived(&self, _video_frame: Option<DecklinkVideoFrame>) -> bool {
+            self.sleep();
+            true
+        }
+    }
+
+    impl DispatchHandler for Slowed {
+        fn frame_arrived(&self, _source: SourceId, _frame: DispatchedFrame) {
+            self.sleep();
+        }
+    }
+
+    fn start(callback: Arc<dyn InputHandler>) -> DecklinkInputDevice {
+        let mut input = common::open_input(MODE, FORMAT, DecklinkVideoInputFlags::empty());
+        common::start_streams(&mut input, callback);
+        input
+    }
+
+    fn frame() -> MockFrame {
+        MockFrame::new(48, 2, FORMAT).fill(0x80)
+    }
+
+    #[test]
+    fn a_slowed_callback_raises_and_clears_its_alert() {
+        let backend = common::mini_recorder();
+        let meter = CpuMeter::new(
+            CpuConfig::builder()
+                .frame_interval(MODE_INTERVAL)
+                .window(1)
+                .budgets(CpuBudgets {
+                    callback: Some(0.5),
+                    ..CpuBudgets::default()
+                })
+                .sustained(MODE_INTERVAL * 2)
+                .hysteresis(0.1)
+                .build()
+                .unwrap(),
+        );
+        let slowed = Arc::new(Slowed::default());
+        let _input = start(meter.measured(slowed.clone()));
+        let mock = backend.input(0);
+
+        // Each frame is measured once the next arrives. 30ms and then 17ms are over the
+        // budget and within the hysteresis of it, however long the sleeps overrun.
+        let mut events = Vec::new();
+        for delay in [30, 30, 30, 17, 17, 0, 0] {
+            slowed.delay.store(delay, Ordering::SeqCst);
+            assert!(mock.deliver_frame(frame()).is_ok());
+            events.push(meter.take_events());
+        }
+
+        assert!(events[..2].iter().all(Vec::is_empty), "{events:?}");
+        match events[2][..] {
+            [CpuEvent::BudgetExceeded {
+                stage: Some(CpuStage::Callback),
+                utilization,
+                busiest: CpuStage::Callback,
+                ..
+            }] => assert!(utilization >= 0.75),
+            _ => panic!("{events:?}"),
+        }
+        assert!(events[3..6].iter().all(Vec::is_empty), "{events:?}");
+        assert!(matches!(
+            events[6][..],
+            [CpuEvent::BudgetRecovered {
+                stage: Some(CpuStage::Callback),
+                ..
+            }]
+        ));
+        let report = meter.report();
+        assert_eq!(report.frames, 7);
+        assert!(!report.stage(CpuStage::Callback).over_budget);
+    }
+
+    #[test]
+    fn a_slowed_handler_is_attributed_to_the_consumer_stage() {
+        let backend = common::mini_recorder();
+        let pool = DispatchPool::new(DispatchConfig {
+            workers: 2,
+            ..DispatchConfig::default()
+        });
+        let slowed = Arc::new(Slowed::default());
+        slowed.delay.store(20, Ordering::SeqCst);
+        let source = pool.add_source("slowed", 1, slowed);
+        let meter = CpuMeter::new(
+            CpuConfig::builder()
+                .frame_interval(MODE_INTERVAL)
+                .window(4)
+                .build()
+                .unwrap(),
+        );
+        pool.set_cpu_meter(source, Some(meter.clone()));
+        let _input = start(pool.callback(source));
+        let mock = backend.input(0);
+
+        // One frame at a time, so each handler's time falls in the interval of its frame
+        let deadline = Instant::now() + Duration::from_secs(10);
+        for n in 1..=5 {
+            assert!(mock.deliver_frame(frame()).is_ok());
+            while pool.stats().sources[0].dispatched < n || pool.stats().sources[0].in_flight > 0 {
+                assert!(Instant::now() < deadline);
+                std::thread::sleep(ms(1));
+            }
+        }
+
+        let report = meter.report();
+        assert_eq!((report.frames, report.window), (5, 4));
+        let consumer = report.stage(CpuStage::Consumer);
+        assert!(consumer.utilization >= 0.5, "{report}");
+        assert!(consumer.per_frame >= ms(20), "{report}");
+        assert_eq!(report.busiest_stage(), CpuStage::Consumer);
+        // The callback only queues the frame
+        assert!(
+            report.stage(CpuStage::Callback).per_frame < ms(20),
+            "{report}"
+        );
+        // The consumer time is the worker time starvation is measured with
+        let consumer_time: Duration = consumer.workers.iter().map(|w| w.time).sum();
+        assert!(consumer_time <= pool.stats().sources[0].worker_time);
+    }
+}
@@ -0,0 +1,205 @@
+//! Holding frames in a `CudaCopyTicket` until their copy completes, with a mocked completion,
+//! and copies into device memory, which need a CUDA device.
+#![cfg(feature = "cuda")]
+
+use decklink::cuda::{CopyCompletion, CudaCopyTicket};
+use decklink::frame::{
+    DecklinkAlignedBytes, DecklinkFrameBase, DecklinkFrameFlags, DecklinkPixelFormat,
+};
+use decklink::SdkError;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+type Log = Rc<RefCell<Vec<&'static str>>>;
+
+/// Two rows of 8 BGRA pixels, `row_bytes` apart, that log when they are dropped.
+struct Frame {
+    row_bytes: usize,
+    bytes: Vec<u8>,
+    log: Log,
+}
+
+impl Frame {
+    fn new(row_bytes: usize, log: &Log) -> Frame {
+        Frame {
+            row_bytes,
+            bytes: (0..2 * row_bytes).map(|i| i as u8).collect(),
+            log: log.clone(),
+        }
+    }
+}
+
+impl DecklinkFrameBase for Frame {
+    fn width(&self) -> usize {
+        8
+    }
+    fn height(&self) -> usize {
+        2
+    }
+    fn row_bytes(&self) -> usize {
+        self.row_bytes
+    }
+    fn pixel_format(&self) -> DecklinkPixelFormat {
+        DecklinkPixelFormat::Format8BitBGRA
+    }
+    fn flags(&self) -> DecklinkFrameFlags {
+        DecklinkFrameFlags::empty()
+    }
+    fn bytes(&self) -> Result<DecklinkAlignedBytes<'_>, SdkError> {
+        Ok(DecklinkAlignedBytes(&self.bytes))
+    }
+}
+
+impl Drop for Frame {
+    fn drop(&mut self) {
+        self.log.borrow_mut().push("frame dropped");
+    }
+}
+
+/// A completion that fires when the test says so, and logs waits for it.
+struct Completion {
+    complete: Rc<Cell<bool>>,
+    log: Log,
+}
+
+impl CopyCompletion for Completion {
+    fn is_complete(&self) -> Result<bool, SdkError> {
+        Ok(self.complete.get())
+    }
+
+    fn synchronize(&self) -> Result<(), SdkError> {
+        self.log.borrow_mut().push("synchronized");
+        self.complete.set(true);
+        Ok(())
+    }
+}
+
+fn ticket() -> (CudaCopyTicket<Frame, Completion>, Rc<Cell<bool>>, Log) {
+    let log = Log::default();
+    let complete = Rc::new(Cell::new(false));
+    let frame = Frame::new(32, &log);
+    let completion = Completion {
+        complete: complete.clone(),
+        log: log.clone(),
+    };
+    (
+        CudaCopyTicket::new(frame, completion, 32, 64),
+        complete,
+        log,
+    )
+}
+
+#[test]
+fn ticket_reports_completion() {
+    let (ticket, complete, log) = ticket();
+    assert_eq!((ticket.pitch(), ticket.byte_count()), (32, 64));
+
+    assert!(!ticket.is_complete().unwrap());
+    complete.set(true);
+    assert!(ticket.is_complete().unwrap());
+    assert!(log.borrow().is_empty());
+}
+
+#[test]
+fn synchronize_returns_the_frame() {
+    let (ticket, _complete, log) = ticket();
+
+    let frame = ticket.synchronize().unwrap();
+    assert_eq!(*log.borrow(), ["synchronized"]);
+    assert_eq!(frame.bytes().unwrap().0.len(), 64);
+
+    drop(frame);
+    assert_eq!(*log.borrow(), ["synchronized", "frame dropped"]);
+}
+
+#[test]
+fn dropped_ticket_waits_before_releasing_the_frame() {
+    let (ticket, _complete, log) = ticket();
+
+    drop(ticket);
+    assert_eq!(*log.borrow(), ["synchronized", "frame dropped"]);
+}
+
+/// Copies into device memory, which need a CUDA device.
+mod device {
+    use super::{Frame, Log};
+    use cudarc::driver::{CudaContext, DevicePtr};
+    use decklink::cuda::{copy_frame_to_device, CudaCopyLayout};
+    use decklink::SdkError;
+
+    #[test]
+    #[ignore = "needs a CUDA device"]
+    fn frame_is_copied_with_its_padding() {
+        let context = CudaContext::new(0).unwrap();
+        let stream = context.default_stream();
+        let dst = stream.alloc_zeros::<u8>(96).unwrap();
+        let (ptr, _sync) = dst.device_ptr(&stream);
+
+        let log = Log::default();
+        let ticket = unsafe {
+            copy_frame_to_device(
+                Frame::new(48, &log),
+                ptr,
+                96,
+                &stream,
+                CudaCopyLayout::Pitched,
+            )
+        }
+        .unwrap();
+        assert_eq!((ticket.pitch(), ticket.byte_count()), (48, 96));
+        let frame = ticket.synchronize().unwrap();
+        assert_eq!(stream.clone_dtoh(&dst).unwrap(), frame.bytes);
+    }
+
+    #[test]
+    #[ignore = "needs a CUDA device"]
+    fn packed_copy_strips_the_padding() {
+        let context = CudaContext::new(0).unwrap();
+        let stream = context.default_stream();
+        let dst = stream.alloc_zeros::<u8>(64).unwrap();
+        let (ptr, _sync) = dst.device_ptr(&stream);
+
+        let log = Log::default();
+        let ticket = unsafe {
+            copy_frame_to_device(
+                Frame::new(48, &log),
+                ptr,
+                64,
+                &stream,
+                CudaCopyLayout::Packed,
+            )
+        }
+        .unwrap();
+        assert_eq!((ticket.pitch(), ticket.byte_count()), (32, 64));
+        let frame = ticket.synchronize().unwrap();
+        let expected: Vec<u8> = frame
+            .bytes
+            .chunks(48)
+            .flat_map(|row| row[..32].to_vec())
+            .collect();
+        assert_eq!(stream.clone_dtoh(&dst).unwrap(), expected);
+    }
+
+    #[test]
+    #[ignore = "needs a CUDA device"]
+    fn small_destination_is_rejected() {
+        let context = CudaContext::new(0).unwrap();
+        let stream = context.default_stream();
+        let dst = stream.alloc_zeros::<u8>(95).unwrap();
+        let (ptr, _sync) = dst.device_ptr(&stream);
+
+        let log = Log::default();
+        let result = unsafe {
+            copy_frame_to_device(
+                Frame::new(48, &log),
+                ptr,
+                95,
+                &stream,
+                CudaCopyLayout::Pitched,
+            )
+        };
+        assert!(matches!(result, Err(SdkError::INVALIDARG)));
+        // The frame was released without being held
+        assert_eq!(*log.borrow(), ["frame dropped"]);
+    }
+}
@@ -0,0 +1,254 @@
+//! Invalidating a `CudaAllocatorProvider` before its context is destroyed, with pinned memory
+//! faked over the heap, and the whole recovery sequence on a card and a GPU.
+#![cfg(feature = "cuda")]
+
+#[cfg(feature = "mock-backend")]
+mod common;
+
+use decklink::allocator::{self, AllocatorEvent, BufferSpec, VideoBufferAllocatorProvider};
+use decklink::cuda::{CudaAllocatorProvider, InvalidateError, PinnedMemory, GPU_LOST};
+use decklink::SdkError;
+use std::collections::HashMap;
+use std::ffi::c_void;
+use std::sync::{Arc, Mutex};
+
+/// Pinned memory on the heap, which records what it frees.
+#[derive(Default)]
+struct HeapMemory {
+    /// The size of each allocation not freed yet, by address.
+    live: Mutex<HashMap<usize, usize>>,
+    freed: Mutex<Vec<usize>>,
+}
+
+impl PinnedMemory for HeapMemory {
+    fn alloc_host(&self, size: usize) -> Result<*mut c_void, SdkError> {
+        let ptr = Box::into_raw(vec![0u8; size].into_boxed_slice()) as *mut u8;
+        self.live.lock().unwrap().insert(ptr as usize, size);
+        Ok(ptr as *mut c_void)
+    }
+
+    unsafe fn free_host(&self, ptr: *mut c_void) {
+        let size = self.live.lock().unwrap().remove(&(ptr as usize)).unwrap();
+        drop(Box::from_raw(std::ptr::slice_from_raw_parts_mut(
+            ptr as *mut u8,
+            size,
+        )));
+        self.freed.lock().unwrap().push(size);
+    }
+}
+
+const SPEC: BufferSpec = BufferSpec {
+    buffer_size: 4096,
+    width: 32,
+    height: 32,
+    row_bytes: 128,
+    pixel_format: 0x3276_7579,
+};
+
+fn provider() -> (CudaAllocatorProvider, Arc<HeapMemory>) {
+    let memory = Arc::new(HeapMemory::default());
+    (CudaAllocatorProvider::with_memory(memory.clone()), memory)
+}
+
+#[test]
+fn buffers_are_freed_while_the_provider_is_valid() {
+    let (provider, memory) = provider();
+    let allocator = provider.get_allocator(SPEC).unwrap();
+    let buffer = allocator.allocate().unwrap();
+    assert!(!buffer.get_bytes().unwrap().is_null());
+    assert_eq!(provider.live_buffer_count(), 1);
+
+    drop(buffer);
+    assert_eq!(*memory.freed.lock().unwrap(), [4096]);
+    assert_eq!(provider.live_buffer_count(), 0);
+    assert_eq!(provider.leaked_buffer_count(), 0);
+}
+
+#[test]
+fn invalidate_waits_for_the_allocators_to_be_released() {
+    let (provider, _memory) = provider();
+    let allocator = provider.get_allocator(SPEC).unwrap();
+
+    assert_eq!(
+        provider.invalidate(),
+        Err(InvalidateError::InUse { allocators: 1 })
+    );
+    assert!(!provider.is_invalidated());
+
+    drop(allocator);
+    assert_eq!(provider.invalidate(), Ok(()));
+    assert!(provider.is_invalidated());
+    assert!(matches!(provider.get_allocator(SPEC), Err(GPU_LOST)));
+}
+
+#[test]
+fn orphaned_buffers_fail_and_are_leaked_when_dropped() {
+    let spec = BufferSpec {
+        buffer_size: 4100,
+        ..SPEC
+    };
+    let (provider, memory) = provider();
+    let allocator = provider.get_allocator(spec).unwrap();
+    let buffers = [allocator.allocate().unwrap(), allocator.allocate().unwrap()];
+    drop(allocator);
+    provider.invalidate().unwrap();
+
+    for buffer in &buffers {
+        assert_eq!(buffer.get_bytes(), Err(GPU_LOST));
+        assert_eq!(buffer.start_access(0), Err(GPU_LOST));
+    }
+    drop(buffers);
+
+    // Nothing was freed into the destroyed context
+    assert!(memory.freed.lock().unwrap().is_empty());
+    assert_eq!(provider.live_buffer_count(), 0);
+    assert_eq!(provider.leaked_buffer_count(), 2);
+    // The events are process wide, and only this test takes them
+    let leaks = allocator::take_events()
+        .into_iter()
+        .filter(|e| *e == AllocatorEvent::BufferLeaked { bytes: 4100 })
+        .count();
+    assert_eq!(leaks, 2);
+}
+
+/// The recovery sequence through the mock driver: buffers held by the application across
+/// the reset are orphaned, and capture resumes with a new provider.
+#[cfg(feature = "mock-backend")]
+mod driver {
+    use super::common;
+    use super::HeapMemory;
+    use decklink::cuda::{CudaAllocatorProvider, InvalidateError};
+    use decklink::device::get_devices;
+    use decklink::device::input::{
+        CallbackResult, DecklinkInputDevice, DecklinkVideoInputFlags, FrameArrival, InputHandler,
+    };
+    use decklink::display_mode::DecklinkDisplayModeId;
+    use decklink::frame::{DecklinkPixelFormat, DecklinkVideoFrame};
+    use decklink::mock::{MockBackend, MockDevice, MockFrame};
+    use decklink::ApiVersion;
+    use std::sync::{Arc, Mutex};
+
+    const FORMAT: DecklinkPixelFormat = DecklinkPixelFormat::Format8BitYUV;
+
+    #[derive(Default)]
+    struct Keeper {
+        kept: Mutex<Vec<DecklinkVideoFrame>>,
+    }
+
+    impl InputHandler for Keeper {
+        fn frame_arrived(&self, arrival: &FrameArrival<'_>) -> CallbackResult {
+            self.kept
+                .lock()
+                .unwrap()
+                .extend(arrival.retain_video_frame());
+            CallbackResult::Ok
+        }
+    }
+
+    fn start(input: &mut DecklinkInputDevice, provider: Arc<CudaAllocatorProvider>) {
+        input
+            .enable_video_input_with_allocator(
+                DecklinkDisplayModeId::NTSC,
+                FORMAT,
+                DecklinkVideoInputFlags::empty(),
+                provider,
+            )
+            .unwrap();
+        input.start_streams().unwrap();
+    }
+
+    #[test]
+    fn capture_recovers_with_a_new_provider() {
+        let backend = common::mini_recorder();
+        backend.set_api_version(Some(ApiVersion::new(14, 3, 0)));
+        let mock = backend.input(0);
+        let keeper = Arc::new(Keeper::default());
+        let mut input = get_devices().unwrap()[0].input().unwrap();
+        input.set_callback(Some(keeper.clone())).unwrap();
+
+        let lost = Arc::new(CudaAllocatorProvider::with_memory(Arc::new(
+            HeapMemory::default(),
+        )));
+        start(&mut input, lost.clone());
+        assert!(mock.deliver_frame(MockFrame::new(48, 2, FORMAT)).is_ok());
+        let held = keeper.kept.lock().unwrap().len();
+        assert_eq!(held, 1);
+
+        // The order is checked: the input still holds the provider's allocators
+        input.stop_streams().unwrap();
+        assert!(matches!(
+            lost.invalidate(),
+            Err(InvalidateError::InUse { .. })
+        ));
+        input.disable_video_input().unwrap();
+        lost.invalidate().unwrap();
+
+        // A new context, and a new provider for it
+        let memory = Arc::new(HeapMemory::default());
+        let provider = Arc::new(CudaAllocatorProvider::with_memory(memory.clone()));
+        start(&mut input, provider.clone());
+        assert!(mock.deliver_frame(MockFrame::new(48, 2, FORMAT)).is_ok());
+        assert_eq!(keeper.kept.lock().unwrap().len(), 2);
+        assert_eq!(provider.live_buffer_count(), 1);
+
+        // The frame held across the reset leaks its buffer, the new one is freed
+        keeper.kept.lock().unwrap().clear();
+        assert_eq!(lost.leaked_buffer_count(), 1);
+        assert_eq!(provider.leaked_buffer_count(), 0);
+        drop(input);
+        assert_eq!(memory.freed.lock().unwrap().len(), 1);
+    }
+}
+
+/// The recovery sequence on a card and a GPU, for maintainers. Only runs when
+/// `DECKLINK_CUDA_INPUT` names an input with a signal, as a `DeviceSelector`, and passes
+/// without touching hardware otherwise.
+#[cfg(not(feature = "mock-backend"))]
+mod hardware {
+    use cudarc::driver::CudaContext;
+    use decklink::cuda::CudaAllocatorProvider;
+    use decklink::device::input::{DecklinkVideoInputFlags, FirstFrameOptions};
+    use decklink::device::selector::DeviceSelector;
+    use decklink::device::DecklinkDeviceDisplayModes;
+    use decklink::frame::DecklinkPixelFormat;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[test]
+    fn capture_recovers_after_the_context_is_recreated() {
+        let Some(selector) = std::env::var_os("DECKLINK_CUDA_INPUT") else {
+            return;
+        };
+        let selector: DeviceSelector = selector.to_str().unwrap().parse().unwrap();
+        let device = selector.resolve().unwrap();
+        let mut input = device.input().unwrap();
+        let mode = input.display_modes().unwrap()[0].mode();
+
+        for round in 0..2 {
+            let context = CudaContext::new(0).unwrap();
+            let provider = Arc::new(CudaAllocatorProvider::new(context));
+            input
+                .enable_video_input_with_allocator(
+                    mode,
+                    DecklinkPixelFormat::Format8BitYUV,
+                    DecklinkVideoInputFlags::empty(),
+                    provider.clone(),
+                )
+                .unwrap();
+            input.start_streams().unwrap();
+            let options = FirstFrameOptions {
+                timeout: Duration::from_secs(5),
+                ..FirstFrameOptions::default()
+            };
+            let first = input.wait_first_frame(options, None);
+            assert!(first.is_ok(), "no frame in round {}: {:?}", round, first);
+
+            input.stop_streams().unwrap();
+            input.disable_video_input().unwrap();
+            provider.invalidate().unwrap();
+            // The context is destroyed with the last reference to it, which the provider
+            // and any buffers still held keep
+            drop(provider);
+        }
+    }
+}
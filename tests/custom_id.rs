@@ -0,0 +1,213 @@
+//! Reading attribute and status ids by number, and showing registered ids by name.
+
+use decklink::device::custom_id::{
+    custom_id, custom_id_name, register_custom_id, CustomId, CustomIdKind, CustomIdScope,
+    CustomReading, CustomValue,
+};
+use decklink::SdkError;
+
+#[test]
+fn values_and_readings_are_displayed() {
+    let id = CustomId {
+        scope: CustomIdScope::Status,
+        id: 0x7374_3031,
+        name: "Link checksum".to_string(),
+        kind: CustomIdKind::Bytes,
+    };
+    let reading = |value| CustomReading {
+        id: id.clone(),
+        value,
+    };
+    assert_eq!(
+        reading(Some(CustomValue::Bytes(vec![0x0a, 0xff]))).to_string(),
+        "Link checksum (0x73743031): 0aff"
+    );
+    assert_eq!(
+        reading(Some(CustomValue::String("SDI \"A\"".to_string()))).to_string(),
+        "Link checksum (0x73743031): \"SDI \\\"A\\\"\""
+    );
+    assert_eq!(
+        reading(None).to_string(),
+        "Link checksum (0x73743031): unavailable"
+    );
+    assert_eq!(CustomValue::Float(1.5).to_string(), "1.5");
+    assert_eq!(CustomValue::Flag(true).to_string(), "true");
+}
+
+#[test]
+fn registering_again_replaces_the_name() {
+    let id = 0x7465_7374;
+    register_custom_id(CustomIdScope::Status, id, "Fan speed", CustomIdKind::Int).unwrap();
+    register_custom_id(CustomIdScope::Status, id, "Fan RPM", CustomIdKind::Float).unwrap();
+
+    let custom = custom_id(CustomIdScope::Status, id).unwrap();
+    assert_eq!(
+        (custom.name.as_str(), custom.kind),
+        ("Fan RPM", CustomIdKind::Float)
+    );
+    assert_eq!(custom_id_name(CustomIdScope::Status, id), "Fan RPM");
+    // Scopes are separate
+    assert_eq!(custom_id(CustomIdScope::Attribute, id), None);
+    assert_eq!(custom_id_name(CustomIdScope::Attribute, id), "0x74657374");
+}
+
+#[test]
+fn attributes_cannot_hold_bytes() {
+    let result = register_custom_id(
+        CustomIdScope::Attribute,
+        0x6279_7465,
+        "Blob",
+        CustomIdKind::Bytes,
+    );
+    assert!(matches!(result, Err(SdkError::INVALIDARG)));
+    assert_eq!(custom_id(CustomIdScope::Attribute, 0x6279_7465), None);
+}
+
+#[cfg(feature = "mock-backend")]
+mod mock {
+    use decklink::dashboard::{DashboardField, DeviceDashboard};
+    use decklink::device::custom_id::{
+        register_custom_id, CustomIdKind, CustomIdScope, CustomReading, CustomValue,
+    };
+    use decklink::device::get_devices;
+    use decklink::device::status::DecklinkStatusId;
+    use decklink::mock::{MockBackend, MockDevice};
+    use decklink::SdkError;
+
+    /// Ids as the SDK headers define them, from their four characters.
+    const fn four_cc(id: &[u8; 4]) -> u32 {
+        u32::from_be_bytes(*id)
+    }
+    const PERSISTENT_ID: u32 = four_cc(b"peid");
+    const DEVICE_HANDLE: u32 = four_cc(b"devh");
+    /// Ids of a newer SDK than this crate wraps.
+    const FAN_SPEED: u32 = four_cc(b"xfan");
+    const CORE_VOLTAGE: u32 = four_cc(b"xvlt");
+    const SERIAL: u32 = four_cc(b"xser");
+    const CALIBRATION: u32 = four_cc(b"xcal");
+    const HAS_FAN: u32 = four_cc(b"xhfn");
+    const LINK_ERRORS: u32 = four_cc(b"xlnk");
+
+    fn device() -> MockDevice {
+        MockDevice::new("DeckLink 8K Pro")
+            .sub_device(0x5a10, 42)
+            .device_handle("1f:00.0")
+            .status_int(DecklinkStatusId::DeviceTemperature, 47)
+            .raw_attribute(HAS_FAN, CustomValue::Flag(true))
+            .raw_attribute(SERIAL, CustomValue::String("8K-0042".to_string()))
+            .raw_status(FAN_SPEED, CustomValue::Int(2400))
+            .raw_status(CORE_VOLTAGE, CustomValue::Float(0.95))
+            .raw_status(CALIBRATION, CustomValue::Bytes(vec![1, 2, 3]))
+    }
+
+    #[test]
+    fn typed_and_raw_reads_of_an_id_agree() {
+        let backend = MockBackend::install(vec![device()]);
+        let devices = get_devices().unwrap();
+        let attributes = devices[0].get_attributes().unwrap();
+        let status = devices[0].get_status().unwrap();
+        let temperature = DecklinkStatusId::DeviceTemperature as u32;
+
+        assert_eq!(attributes.persistent_id().unwrap(), 42);
+        assert_eq!(attributes.get_int_raw(PERSISTENT_ID).unwrap(), 42);
+        assert_eq!(attributes.device_handle().unwrap(), "1f:00.0");
+        assert_eq!(attributes.get_string_raw(DEVICE_HANDLE).unwrap(), "1f:00.0");
+
+        assert_eq!(status.device_temperature().unwrap(), 47);
+        assert_eq!(status.get_int_raw(temperature).unwrap(), 47);
+        backend.set_status_int(0, DecklinkStatusId::DeviceTemperature, 52);
+        assert_eq!(status.get_int_raw(temperature).unwrap(), 52);
+        assert_eq!(status.device_temperature().unwrap(), 52);
+
+        // Both paths fail the same way once the status is gone
+        backend.clear_status(0, DecklinkStatusId::DeviceTemperature);
+        assert!(matches!(
+            status.device_temperature(),
+            Err(SdkError::NOTIMPL)
+        ));
+        assert!(matches!(
+            status.get_int_raw(temperature),
+            Err(SdkError::NOTIMPL)
+        ));
+    }
+
+    #[test]
+    fn unwrapped_ids_are_read_raw() {
+        let _backend = MockBackend::install(vec![device()]);
+        let devices = get_devices().unwrap();
+        let attributes = devices[0].get_attributes().unwrap();
+        let status = devices[0].get_status().unwrap();
+
+        assert!(attributes.get_flag_raw(HAS_FAN).unwrap());
+        assert_eq!(attributes.get_string_raw(SERIAL).unwrap(), "8K-0042");
+        assert!(matches!(
+            attributes.get_float_raw(SERIAL),
+            Err(SdkError::NOTIMPL)
+        ));
+        assert_eq!(status.get_int_raw(FAN_SPEED).unwrap(), 2400);
+        assert_eq!(status.get_float_raw(CORE_VOLTAGE).unwrap(), 0.95);
+        assert_eq!(status.get_bytes_raw(CALIBRATION).unwrap(), [1, 2, 3]);
+        assert!(matches!(
+            status.get_string_raw(FAN_SPEED),
+            Err(SdkError::NOTIMPL)
+        ));
+    }
+
+    #[test]
+    fn registered_ids_are_shown_by_name_on_the_dashboard() {
+        let backend = MockBackend::install(vec![device()]);
+        let devices = get_devices().unwrap();
+        for (scope, id, name, kind) in [
+            (
+                CustomIdScope::Attribute,
+                SERIAL,
+                "Serial",
+                CustomIdKind::String,
+            ),
+            (
+                CustomIdScope::Status,
+                FAN_SPEED,
+                "Fan speed",
+                CustomIdKind::Int,
+            ),
+            (
+                CustomIdScope::Status,
+                CALIBRATION,
+                "Calibration",
+                CustomIdKind::Bytes,
+            ),
+            (
+                CustomIdScope::Status,
+                LINK_ERRORS,
+                "Link errors",
+                CustomIdKind::Int,
+            ),
+        ] {
+            register_custom_id(scope, id, name, kind).unwrap();
+        }
+        // Other tests register ids of their own
+        let ours = |dashboard: &DeviceDashboard| -> Vec<CustomReading> {
+            let ids = [SERIAL, FAN_SPEED, CALIBRATION, LINK_ERRORS];
+            let mut readings = dashboard.custom.clone();
+            readings.retain(|r| ids.contains(&r.id.id));
+            readings
+        };
+
+        let before = DeviceDashboard::read(&devices[0]).unwrap();
+        let shown: Vec<String> = ours(&before).iter().map(|r| r.to_string()).collect();
+        assert_eq!(
+            shown,
+            [
+                "Serial (0x78736572): \"8K-0042\"",
+                "Fan speed (0x7866616e): 2400",
+                "Calibration (0x7863616c): 010203",
+                "Link errors (0x786c6e6b): unavailable",
+            ]
+        );
+
+        backend.set_raw_status(0, FAN_SPEED, CustomValue::Int(3100));
+        let after = DeviceDashboard::read(&devices[0]).unwrap();
+        assert_eq!(after.changed_fields(&before), [DashboardField::Custom]);
+        assert_eq!(ours(&after)[1].value, Some(CustomValue::Int(3100)));
+    }
+}
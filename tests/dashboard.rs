@@ -0,0 +1,302 @@
+//! Keeping the dashboard of a mock device current as its status changes.
+#![cfg(feature = "mock-backend")]
+
+mod common;
+
+use decklink::dashboard::{
+    DashboardDelta, DashboardField, DeviceDashboard, DeviceMonitor, DeviceMonitorConfig,
+    TemperatureAlert, TemperatureThreshold,
+};
+use decklink::device::attributes::{DecklinkDuplexMode, DecklinkProfileId};
+use decklink::device::status::{DecklinkDeviceBusyState, DecklinkStatusId};
+use decklink::device::{get_devices, DeviceHealth};
+use decklink::display_mode::DecklinkDisplayModeId;
+use decklink::mock::{MockBackend, MockDevice};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::thread::sleep;
+use std::time::Duration;
+
+const CONFIG: DeviceMonitorConfig = DeviceMonitorConfig {
+    interval: Duration::from_millis(5),
+    debounce: Duration::from_millis(50),
+    temperature_threshold: None,
+};
+
+/// Long enough for any change to have been reported.
+const SETTLE: Duration = Duration::from_secs(2);
+
+fn recorder() -> MockDevice {
+    MockDevice::new("DeckLink Mini Recorder 4K")
+        .device_handle("1f:00.0:/dev/blackmagic/io0")
+        .profile(
+            DecklinkProfileId::OneSubDeviceHalfDuplex,
+            DecklinkDuplexMode::Half,
+        )
+        .status_flag(DecklinkStatusId::VideoInputSignalLocked, false)
+        .status_flag(DecklinkStatusId::ReferenceSignalLocked, false)
+        .status_int(DecklinkStatusId::Busy, 0)
+        .status_int(DecklinkStatusId::DeviceTemperature, 41)
+}
+
+fn expected() -> DeviceDashboard {
+    DeviceDashboard {
+        present: true,
+        label: Some("DeckLink Mini Recorder 4K".to_string()),
+        signal_locked: Some(false),
+        detected_mode: None,
+        reference_locked: Some(false),
+        busy: Some(DecklinkDeviceBusyState::empty()),
+        temperature: Some(41),
+        profile: Some(DecklinkProfileId::OneSubDeviceHalfDuplex),
+        custom: Vec::new(),
+    }
+}
+
+/// Monitor the first device, sending each delta to the receiver.
+fn monitor() -> (DeviceMonitor, Receiver<DashboardDelta>) {
+    monitor_with(CONFIG)
+}
+
+fn monitor_with(config: DeviceMonitorConfig) -> (DeviceMonitor, Receiver<DashboardDelta>) {
+    let devices = get_devices().unwrap();
+    let (sender, receiver) = channel();
+    let monitor = DeviceMonitor::new(&devices[0], config, move |delta| {
+        sender.send(delta.clone()).unwrap();
+    })
+    .unwrap();
+    (monitor, receiver)
+}
+
+fn next(receiver: &Receiver<DashboardDelta>) -> DashboardDelta {
+    receiver.recv_timeout(SETTLE).unwrap()
+}
+
+/// Assert nothing is reported for several debounce periods.
+fn assert_quiet(receiver: &Receiver<DashboardDelta>) {
+    assert_eq!(
+        receiver.recv_timeout(CONFIG.debounce * 4).unwrap_err(),
+        RecvTimeoutError::Timeout
+    );
+}
+
+#[test]
+fn initial_dashboard_is_read_without_a_callback() {
+    let _backend = MockBackend::install(vec![recorder()]);
+    let (monitor, receiver) = monitor();
+
+    assert_eq!(monitor.current(), expected());
+    assert_eq!(*monitor.dashboard().read().unwrap(), expected());
+    assert_quiet(&receiver);
+    assert!(monitor.poll_cost().polls > 1);
+}
+
+#[test]
+fn changes_are_reported_as_deltas() {
+    let backend = MockBackend::install(vec![recorder()]);
+    let (monitor, receiver) = monitor();
+
+    // The signal locks in a detected mode
+    backend.set_status_flag(0, DecklinkStatusId::VideoInputSignalLocked, true);
+    backend.set_status_int(
+        0,
+        DecklinkStatusId::DetectedVideoInputMode,
+        DecklinkDisplayModeId::HD1080i50 as i64,
+    );
+    let delta = next(&receiver);
+    assert_eq!(
+        delta.changed,
+        [DashboardField::SignalLocked, DashboardField::DetectedMode]
+    );
+    assert_eq!(delta.previous, expected());
+    let locked = DeviceDashboard {
+        signal_locked: Some(true),
+        detected_mode: Some(DecklinkDisplayModeId::HD1080i50),
+        ..expected()
+    };
+    assert_eq!(delta.current, locked);
+    assert_eq!(monitor.current(), locked);
+
+    // The card warms up and starts capturing
+    backend.set_status_int(0, DecklinkStatusId::DeviceTemperature, 55);
+    backend.set_status_int(
+        0,
+        DecklinkStatusId::Busy,
+        DecklinkDeviceBusyState::CAPTURE_BUSY.bits() as i64,
+    );
+    let delta = next(&receiver);
+    assert_eq!(
+        delta.changed,
+        [DashboardField::Busy, DashboardField::Temperature]
+    );
+    assert_eq!(delta.current.temperature, Some(55));
+
+    // The temperature stops being reported
+    backend.clear_status(0, DecklinkStatusId::DeviceTemperature);
+    let delta = next(&receiver);
+    assert_eq!(delta.changed, [DashboardField::Temperature]);
+    assert_eq!(delta.current.temperature, None);
+    assert_quiet(&receiver);
+}
+
+#[test]
+fn flapping_shorter_than_the_debounce_is_not_reported() {
+    let backend = MockBackend::install(vec![recorder()]);
+    let (monitor, receiver) = monitor();
+
+    for _ in 0..5 {
+        backend.set_status_flag(0, DecklinkStatusId::ReferenceSignalLocked, true);
+        sleep(CONFIG.debounce / 5);
+        backend.set_status_flag(0, DecklinkStatusId::ReferenceSignalLocked, false);
+        sleep(CONFIG.debounce / 5);
+    }
+    assert_quiet(&receiver);
+
+    // Once it holds, it is reported once
+    backend.set_status_flag(0, DecklinkStatusId::ReferenceSignalLocked, true);
+    assert_eq!(next(&receiver).changed, [DashboardField::ReferenceLocked]);
+    assert_quiet(&receiver);
+    assert_eq!(monitor.current().reference_locked, Some(true));
+}
+
+#[test]
+fn removed_device_is_unknown_until_it_returns() {
+    let backend = MockBackend::install(vec![recorder()]);
+    let (monitor, receiver) = monitor();
+
+    backend.detach(0);
+    let delta = next(&receiver);
+    assert_eq!(delta.current, DeviceDashboard::default());
+    assert_eq!(
+        delta.changed,
+        [
+            DashboardField::Present,
+            DashboardField::Label,
+            DashboardField::SignalLocked,
+            DashboardField::ReferenceLocked,
+            DashboardField::Busy,
+            DashboardField::Temperature,
+            DashboardField::Profile,
+        ]
+    );
+    assert!(!monitor.current().present);
+
+    // The device comes back with a signal
+    backend.set_status_flag(0, DecklinkStatusId::VideoInputSignalLocked, true);
+    backend.attach(0);
+    let delta = next(&receiver);
+    assert_eq!(delta.previous, DeviceDashboard::default());
+    assert_eq!(
+        delta.current,
+        DeviceDashboard {
+            signal_locked: Some(true),
+            ..expected()
+        }
+    );
+    assert_quiet(&receiver);
+}
+
+#[test]
+fn dropping_the_monitor_stops_its_thread() {
+    let _backend = MockBackend::install(vec![recorder()]);
+    let (monitor, receiver) = monitor();
+    let cost = monitor.poll_cost();
+    drop(monitor);
+
+    // The callback, and the sender it holds, went with the thread
+    assert_eq!(
+        receiver.recv_timeout(SETTLE).unwrap_err(),
+        RecvTimeoutError::Disconnected
+    );
+    assert!(cost.max >= cost.last);
+    assert!(cost.polls == 0 || cost.average() <= cost.max);
+}
+
+#[test]
+fn device_without_a_handle_cannot_be_monitored() {
+    let _backend = common::mini_recorder();
+    let devices = get_devices().unwrap();
+    assert!(DeviceMonitor::new(&devices[0], CONFIG, |_| {}).is_err());
+}
+
+#[test]
+fn health_reports_the_temperature_if_known() {
+    let backend = MockBackend::install(vec![recorder(), MockDevice::new("DeckLink Duo 2")]);
+    let devices = get_devices().unwrap();
+
+    assert_eq!(
+        devices[0].health().unwrap(),
+        DeviceHealth {
+            temperature_c: Some(41)
+        }
+    );
+    // A device without telemetry is not an error
+    assert_eq!(devices[1].health().unwrap(), DeviceHealth::default());
+
+    backend.detach(0);
+    assert!(devices[0].health().is_err());
+}
+
+#[test]
+fn temperature_alerts_have_hysteresis() {
+    let backend = MockBackend::install(vec![recorder()]);
+    let (_monitor, receiver) = monitor_with(DeviceMonitorConfig {
+        temperature_threshold: Some(TemperatureThreshold {
+            limit_c: 60,
+            hysteresis_c: 5,
+        }),
+        ..CONFIG
+    });
+    let reach = |temperature_c| {
+        backend.set_status_int(0, DecklinkStatusId::DeviceTemperature, temperature_c);
+        let delta = next(&receiver);
+        assert_eq!(delta.current.temperature, Some(temperature_c));
+        delta.temperature_alert
+    };
+
+    assert_eq!(reach(60), None);
+    assert_eq!(
+        reach(61),
+        Some(TemperatureAlert::OverLimit { temperature_c: 61 })
+    );
+    assert_eq!(reach(63), None);
+    // Back under the limit, but not by the hysteresis
+    assert_eq!(reach(57), None);
+    assert_eq!(
+        reach(55),
+        Some(TemperatureAlert::Recovered { temperature_c: 55 })
+    );
+    assert_eq!(reach(58), None);
+    assert_eq!(
+        reach(62),
+        Some(TemperatureAlert::OverLimit { temperature_c: 62 })
+    );
+}
+
+#[test]
+fn absent_temperature_raises_no_alert() {
+    let backend = MockBackend::install(vec![recorder()]);
+    let (_monitor, receiver) = monitor_with(DeviceMonitorConfig {
+        temperature_threshold: Some(TemperatureThreshold {
+            limit_c: 40,
+            hysteresis_c: 5,
+        }),
+        ..CONFIG
+    });
+
+    // The device starts over the limit, but alerts are only raised on a known temperature
+    backend.clear_status(0, DecklinkStatusId::DeviceTemperature);
+    let delta = next(&receiver);
+    assert_eq!(delta.current.temperature, None);
+    assert_eq!(delta.temperature_alert, None);
+    backend.set_status_int(0, DecklinkStatusId::DeviceTemperature, 41);
+    assert_eq!(
+        next(&receiver).temperature_alert,
+        Some(TemperatureAlert::OverLimit { temperature_c: 41 })
+    );
+
+    // Losing the reading leaves the alert standing, so no recovery is reported
+    backend.clear_status(0, DecklinkStatusId::DeviceTemperature);
+    assert_eq!(next(&receiver).temperature_alert, None);
+    backend.set_status_int(0, DecklinkStatusId::DeviceTemperature, 43);
+    assert_eq!(next(&receiver).temperature_alert, None);
+}
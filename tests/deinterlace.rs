@@ -0,0 +1,208 @@
+//! Splitting and line doubling the fields of synthetic interlaced frames.
+
+use decklink::deinterlace::{
+    bob, first_field, split_fields, weave, DeinterlaceError, DeinterlacePolicy, Field, FrameLayout,
+};
+use decklink::display_mode::DecklinkFieldDominance;
+use decklink::frame::{
+    DecklinkFrameBase, DecklinkFrameFlags, DecklinkPixelFormat, DecklinkVideoMutableFrame,
+};
+
+/// 4 BGRA pixels per row, padded to 20 bytes.
+fn layout(height: usize) -> FrameLayout {
+    FrameLayout {
+        width: 4,
+        height,
+        row_bytes: 20,
+        pixel_format: DecklinkPixelFormat::Format8BitBGRA,
+    }
+}
+
+/// A woven frame whose rows are tagged with their field and row within the field: upper
+/// field rows hold 10, 30, 50…, lower field rows 100, 120, 140….
+fn woven(height: usize) -> Vec<u8> {
+    (0..height)
+        .flat_map(|y| {
+            let value = if y % 2 == 0 { 10 + 10 * y } else { 90 + 10 * y };
+            vec![value as u8; 20]
+        })
+        .collect()
+}
+
+fn row_values(bytes: &[u8]) -> Vec<u8> {
+    bytes.chunks(20).map(|row| row[0]).collect()
+}
+
+#[test]
+fn fields_are_split_in_temporal_order() {
+    let bytes = woven(6);
+
+    let (first, second) =
+        split_fields(&bytes, layout(6), DecklinkFieldDominance::UpperFieldFirst).unwrap();
+    assert_eq!(
+        (first.field(), second.field()),
+        (Field::Upper, Field::Lower)
+    );
+    assert_eq!(first.rows().map(|r| r[0]).collect::<Vec<_>>(), [10, 30, 50]);
+    assert_eq!(
+        second.rows().map(|r| r[0]).collect::<Vec<_>>(),
+        [100, 120, 140]
+    );
+
+    let (first, second) =
+        split_fields(&bytes, layout(6), DecklinkFieldDominance::LowerFieldFirst).unwrap();
+    assert_eq!(
+        (first.field(), second.field()),
+        (Field::Lower, Field::Upper)
+    );
+    assert_eq!(first.row(0).unwrap()[0], 100);
+    assert_eq!(second.row(0).unwrap()[0], 10);
+}
+
+#[test]
+fn field_views_have_a_doubled_stride() {
+    let bytes = woven(5);
+    let (upper, lower) =
+        split_fields(&bytes, layout(5), DecklinkFieldDominance::UpperFieldFirst).unwrap();
+
+    assert_eq!((upper.width(), upper.height()), (4, 3));
+    assert_eq!(lower.height(), 2);
+    assert_eq!((upper.row_bytes(), upper.stride()), (20, 40));
+    assert_eq!(upper.pixel_format(), DecklinkPixelFormat::Format8BitBGRA);
+    assert_eq!(upper.row(2).unwrap()[0], 50);
+    assert!(upper.row(3).is_none());
+    assert!(lower.row(2).is_none());
+}
+
+#[test]
+fn field_order_follows_the_dominance() {
+    use DecklinkFieldDominance as D;
+    assert_eq!(first_field(D::UpperFieldFirst), Some(Field::Upper));
+    assert_eq!(first_field(D::LowerFieldFirst), Some(Field::Lower));
+    assert_eq!(first_field(D::ProgressiveFrame), Some(Field::Upper));
+    assert_eq!(
+        first_field(D::ProgressiveSegmentedFrame),
+        Some(Field::Upper)
+    );
+    assert_eq!(first_field(D::Unknown), None);
+
+    let bytes = woven(4);
+    assert!(matches!(
+        split_fields(&bytes, layout(4), D::Unknown),
+        Err(DeinterlaceError::UnknownFieldOrder)
+    ));
+}
+
+#[test]
+fn bob_interpolates_the_missing_rows() {
+    let bytes = woven(6);
+    let mut out = vec![0; bytes.len()];
+
+    bob(&bytes, layout(6), Field::Upper, &mut out).unwrap();
+    // The last row has no field row below it, so it repeats the one above
+    assert_eq!(row_values(&out), [10, 20, 30, 40, 50, 50]);
+
+    bob(&bytes, layout(6), Field::Lower, &mut out).unwrap();
+    // The first row has no field row above it
+    assert_eq!(row_values(&out), [100, 100, 110, 120, 130, 140]);
+    assert_eq!(out.len(), 6 * 20);
+}
+
+#[test]
+fn bob_rounds_the_average() {
+    let mut bytes = vec![0; 3 * 20];
+    bytes[40..].fill(3);
+    let mut out = vec![0; bytes.len()];
+    bob(&bytes, layout(3), Field::Upper, &mut out).unwrap();
+    assert_eq!(row_values(&out), [0, 2, 3]);
+}
+
+#[test]
+fn weave_passes_the_frame_through() {
+    let bytes = woven(4);
+    let mut out = vec![0; bytes.len() + 7];
+    weave(&bytes, layout(4), &mut out).unwrap();
+    assert_eq!(out[..bytes.len()], bytes[..]);
+}
+
+#[test]
+fn other_formats_and_small_buffers_are_rejected() {
+    let bytes = woven(4);
+    let mut out = vec![0; bytes.len()];
+
+    let v210 = FrameLayout {
+        pixel_format: DecklinkPixelFormat::Format10BitYUV,
+        ..layout(4)
+    };
+    assert!(matches!(
+        bob(&bytes, v210, Field::Upper, &mut out),
+        Err(DeinterlaceError::UnsupportedPixelFormat(
+            DecklinkPixelFormat::Format10BitYUV
+        ))
+    ));
+    assert!(matches!(
+        split_fields(&bytes, layout(5), DecklinkFieldDominance::UpperFieldFirst),
+        Err(DeinterlaceError::BufferTooSmall)
+    ));
+    assert!(matches!(
+        weave(&bytes, layout(4), &mut out[1..]),
+        Err(DeinterlaceError::BufferTooSmall)
+    ));
+}
+
+#[test]
+fn auto_policy_only_deinterlaces_interlaced_modes() {
+    use DecklinkFieldDominance as D;
+    assert_eq!(
+        DeinterlacePolicy::Auto.field(D::UpperFieldFirst),
+        Some(Field::Upper)
+    );
+    assert_eq!(
+        DeinterlacePolicy::Auto.field(D::LowerFieldFirst),
+        Some(Field::Lower)
+    );
+    assert_eq!(DeinterlacePolicy::Auto.field(D::ProgressiveFrame), None);
+    assert_eq!(
+        DeinterlacePolicy::Auto.field(D::ProgressiveSegmentedFrame),
+        None
+    );
+    assert_eq!(DeinterlacePolicy::default().field(D::UpperFieldFirst), None);
+    assert_eq!(
+        DeinterlacePolicy::BobBottomFirst.field(D::ProgressiveFrame),
+        Some(Field::Lower)
+    );
+
+    let mut bytes = woven(4);
+    let mut scratch = Vec::new();
+    DeinterlacePolicy::Auto
+        .apply_in_place(&mut bytes, layout(4), D::ProgressiveFrame, &mut scratch)
+        .unwrap();
+    assert_eq!(bytes, woven(4));
+    DeinterlacePolicy::Auto
+        .apply_in_place(&mut bytes, layout(4), D::LowerFieldFirst, &mut scratch)
+        .unwrap();
+    assert_eq!(row_values(&bytes), [100, 100, 110, 120]);
+}
+
+#[test]
+fn policy_gives_a_progressive_copy_of_a_frame() {
+    let mut frame = DecklinkVideoMutableFrame::create(
+        4,
+        4,
+        20,
+        DecklinkPixelFormat::Format8BitBGRA,
+        DecklinkFrameFlags::empty(),
+    );
+    frame.copy_bytes(&woven(4)).unwrap();
+
+    let copy = DeinterlacePolicy::Auto
+        .apply(&frame, DecklinkFieldDominance::UpperFieldFirst)
+        .unwrap();
+    assert_eq!((copy.width(), copy.height(), copy.row_bytes()), (4, 4, 20));
+    assert_eq!(row_values(copy.bytes().unwrap().0), [10, 20, 30, 30]);
+
+    let copy = DeinterlacePolicy::None
+        .apply(&frame, DecklinkFieldDominance::UpperFieldFirst)
+        .unwrap();
+    assert_eq!(copy.bytes().unwrap().0, woven(4));
+}
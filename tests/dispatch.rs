@@ -0,0 +1,327 @@
+//! Eight mock inputs sharing the workers of a `DispatchPool`, one of them with a slow
+//! handler.
+#![cfg(feature = "mock-backend")]
+
+mod common;
+
+use decklink::device::get_devices;
+use decklink::device::input::{
+    DecklinkDetectedVideoInputFormatFlags, DecklinkInputDevice, DecklinkVideoInputFlags,
+    DecklinkVideoInputFormatChangedEvents,
+};
+use decklink::dispatch::{
+    DispatchConfig, DispatchEvent, DispatchHandler, DispatchMode, DispatchPool, DispatchStats,
+    DispatchedFrame, SourceId,
+};
+use decklink::display_mode::DecklinkDisplayModeId;
+use decklink::frame::{DecklinkFrameBase, DecklinkPixelFormat};
+use decklink::mock::{MockBackend, MockDevice, MockFrame};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const FORMAT: DecklinkPixelFormat = DecklinkPixelFormat::Format8BitYUV;
+const SOURCES: usize = 8;
+/// The handler of the first source takes this long for each frame.
+const SLOW: Duration = Duration::from_millis(100);
+/// The interval between frames of each input, 50 fps.
+const INTERVAL: Duration = Duration::from_millis(20);
+
+/// Records what it is given, taking `delay` for each frame.
+#[derive(Default)]
+struct Recorder {
+    delay: Duration,
+    /// The first byte of each frame, in the order they were handled.
+    frames: Mutex<Vec<u8>>,
+    changes: Mutex<Vec<(usize, DecklinkDisplayModeId)>>,
+}
+
+impl DispatchHandler for Recorder {
+    fn frame_arrived(&self, _source: SourceId, frame: DispatchedFrame) {
+        std::thread::sleep(self.delay);
+        let first = frame.frame.bytes().unwrap().0[0];
+        self.frames.lock().unwrap().push(first);
+    }
+
+    fn video_input_format_changed(
+        &self,
+        _source: SourceId,
+        _events: DecklinkVideoInputFormatChangedEvents,
+        new_display_mode: DecklinkDisplayModeId,
+        _detected_signal_flags: DecklinkDetectedVideoInputFormatFlags,
+    ) {
+        let frames = self.frames.lock().unwrap().len();
+        self.changes
+            .lock()
+            .unwrap()
+            .push((frames, new_display_mode));
+    }
+}
+
+fn backend(count: usize) -> MockBackend {
+    MockBackend::install(
+        (0..count)
+            .map(|i| MockDevice::new(&format!("DeckLink 8K Pro ({})", i + 1)))
+            .collect(),
+    )
+}
+
+/// Start every input of the backend on a source of `pool`, with the handlers given.
+fn start(
+    pool: &DispatchPool,
+    handlers: &[Arc<Recorder>],
+) -> (Vec<DecklinkInputDevice>, Vec<SourceId>) {
+    let devices = get_devices().unwrap();
+    let mut inputs = Vec::new();
+    let mut sources = Vec::new();
+    for (device, handler) in devices.iter().zip(handlers) {
+        let source = pool.add_source(device.display_name().unwrap(), 1, handler.clone());
+        let mut input = device.input().unwrap();
+        input
+            .enable_video_input(
+                DecklinkDisplayModeId::HD1080p50,
+                FORMAT,
+                DecklinkVideoInputFlags::empty(),
+            )
+            .unwrap();
+        common::start_streams(&mut input, pool.callback(source));
+        inputs.push(input);
+        sources.push(source);
+    }
+    (inputs, sources)
+}
+
+fn frame(n: u8) -> MockFrame {
+    MockFrame::new(48, 2, FORMAT).fill(n)
+}
+
+/// Run eight inputs at 50 fps for a second, on four workers in `mode`, the first with a
+/// handler taking `SLOW` per frame. Gives the stats and events once every frame is
+/// served, and the handlers.
+fn simulate(mode: DispatchMode) -> (DispatchStats, Vec<DispatchEvent>, Vec<Arc<Recorder>>) {
+    let backend = backend(SOURCES);
+    let pool = DispatchPool::new(DispatchConfig {
+        workers: 4,
+        mode,
+        queue_capacity: 8,
+        starvation_threshold: Duration::from_millis(60),
+        ..DispatchConfig::default()
+    });
+    let handlers: Vec<_> = (0..SOURCES)
+        .map(|i| {
+            Arc::new(Recorder {
+                delay: if i == 0 { SLOW } else { Duration::ZERO },
+                ..Recorder::default()
+            })
+        })
+        .collect();
+    let (_inputs, _) = start(&pool, &handlers);
+
+    let start = Instant::now();
+    std::thread::scope(|scope| {
+        for i in 0..SOURCES {
+            let mock = backend.input(i);
+            scope.spawn(move || {
+                for n in 0..50u32 {
+                    let due = start + INTERVAL * n;
+                    std::thread::sleep(due.saturating_duration_since(Instant::now()));
+                    assert!(mock.deliver_frame(frame(n as u8)).is_ok());
+                }
+            });
+        }
+    });
+
+    // Wait for the sources with fast handlers to be served what they queued
+    let deadline = Instant::now() + Duration::from_secs(10);
+    let stats = loop {
+        let stats = pool.stats();
+        let served = stats.sources[1..]
+            .iter()
+            .all(|source| source.queue_depth == 0 && source.in_flight == 0);
+        if served || Instant::now() > deadline {
+            break stats;
+        }
+        std::thread::sleep(Duration::from_millis(5));
+    };
+    (stats, pool.take_events(), handlers)
+}
+
+#[test]
+fn fair_dispatch_keeps_the_other_sources_served() {
+    let (stats, events, handlers) = simulate(DispatchMode::Fair);
+    assert_eq!((stats.workers, stats.mode), (4, DispatchMode::Fair));
+
+    // The slow source holds one worker, and drops what it cannot take
+    let slow = &stats.sources[0];
+    assert_eq!(slow.label, "DeckLink 8K Pro (1)");
+    assert!(slow.dropped > 0, "{slow:?}");
+    assert!(slow.worker_time >= SLOW * 5, "{slow:?}");
+    assert_eq!(slow.peak_queue_depth, 8);
+
+    for (source, handler) in stats.sources[1..].iter().zip(&handlers[1..]) {
+        assert_eq!(source.dropped, 0, "{source:?}");
+        assert_eq!(source.dispatched, 50, "{source:?}");
+        assert!(
+            source.dispatch_latency.p99 < Duration::from_millis(50),
+            "{source:?}"
+        );
+        assert!(!source.starved && source.starved_count == 0, "{source:?}");
+        // One source at a time keeps the frames of each in order
+        assert_eq!(
+            *handler.frames.lock().unwrap(),
+            (0..50).collect::<Vec<u8>>()
+        );
+    }
+    // Only the slow source fell behind
+    for event in events {
+        match event {
+            DispatchEvent::Starved { source, .. }
+            | DispatchEvent::StarvationEnded { source, .. } => {
+                assert_eq!(source.index(), 0)
+            }
+        }
+    }
+}
+
+#[test]
+fn work_stealing_lets_the_slow_source_starve_the_others() {
+    let (stats, events, _) = simulate(DispatchMode::WorkStealing);
+
+    let healthy = &stats.sources[1..];
+    let worst_p99 = healthy
+        .iter()
+        .map(|source| source.dispatch_latency.p99)
+        .max()
+        .unwrap();
+    let dropped: u64 = healthy.iter().map(|source| source.dropped).sum();
+    assert!(
+        worst_p99 > Duration::from_millis(80) || dropped > 0,
+        "{stats:?}"
+    );
+
+    // The starved sources are told apart from the one holding the workers
+    let starved: Vec<_> = events
+        .iter()
+        .filter_map(|event| match event {
+            DispatchEvent::Starved {
+                source,
+                busiest,
+                busiest_label,
+                ..
+            } if source.index() != 0 => Some((*busiest, busiest_label.clone())),
+            _ => None,
+        })
+        .collect();
+    assert!(!starved.is_empty(), "{events:?}");
+    for (busiest, label) in starved {
+        assert_eq!(busiest.index(), 0);
+        assert_eq!(label, "DeckLink 8K Pro (1)");
+    }
+    assert!(healthy.iter().any(|source| source.starved_count > 0));
+}
+
+/// Holds the worker that takes its frame until `gate` is unlocked.
+struct Blocking(Arc<Mutex<()>>);
+
+impl DispatchHandler for Blocking {
+    fn frame_arrived(&self, _source: SourceId, _frame: DispatchedFrame) {
+        drop(self.0.lock().unwrap());
+    }
+}
+
+/// Notes the index of its source in `order` for each frame.
+struct Ordered(usize, Arc<Mutex<Vec<usize>>>);
+
+impl DispatchHandler for Ordered {
+    fn frame_arrived(&self, _source: SourceId, _frame: DispatchedFrame) {
+        self.1.lock().unwrap().push(self.0);
+    }
+}
+
+#[test]
+fn weights_give_a_source_more_frames_per_turn() {
+    let backend = backend(3);
+    let pool = DispatchPool::new(DispatchConfig {
+        workers: 1,
+        queue_capacity: 16,
+        ..DispatchConfig::default()
+    });
+    let gate = Arc::new(Mutex::new(()));
+    let order = Arc::new(Mutex::new(Vec::new()));
+    let handlers: [Arc<dyn DispatchHandler>; 3] = [
+        Arc::new(Blocking(gate.clone())),
+        Arc::new(Ordered(1, order.clone())),
+        Arc::new(Ordered(2, order.clone())),
+    ];
+
+    let devices = get_devices().unwrap();
+    let mut inputs = Vec::new();
+    for (i, (device, handler)) in devices.iter().zip(handlers).enumerate() {
+        let source = pool.add_source(format!("source {i}"), 1, handler);
+        let mut input = device.input().unwrap();
+        input
+            .enable_video_input(
+                DecklinkDisplayModeId::HD1080p50,
+                FORMAT,
+                DecklinkVideoInputFlags::empty(),
+            )
+            .unwrap();
+        common::start_streams(&mut input, pool.callback(source));
+        inputs.push((input, source));
+    }
+    pool.set_weight(inputs[2].1, 3);
+
+    // The one worker is held by the first source while the others queue their frames
+    let held = gate.lock().unwrap();
+    assert!(backend.input(0).deliver_frame(frame(0)).is_ok());
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while pool.stats().busy_workers == 0 {
+        assert!(Instant::now() < deadline);
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    for n in 0..6 {
+        assert!(backend.input(1).deliver_frame(frame(n)).is_ok());
+        assert!(backend.input(2).deliver_frame(frame(n)).is_ok());
+    }
+    let stats = pool.stats();
+    assert_eq!(stats.sources[2].weight, 3);
+    assert_eq!(
+        (stats.sources[1].queue_depth, stats.sources[2].queue_depth),
+        (6, 6)
+    );
+    assert_eq!(stats.sources[0].in_flight, 1);
+    drop(held);
+
+    // Dropping the pool delivers what is queued
+    drop(pool);
+    assert_eq!(*order.lock().unwrap(), [1, 2, 2, 2, 1, 2, 2, 2, 1, 1, 1, 1]);
+}
+
+#[test]
+fn format_changes_follow_the_frames_before_them() {
+    let backend = backend(1);
+    let pool = DispatchPool::new(DispatchConfig::default());
+    let handler = Arc::new(Recorder {
+        delay: Duration::from_millis(5),
+        ..Recorder::default()
+    });
+    let (_inputs, _) = start(&pool, std::slice::from_ref(&handler));
+    let mock = backend.input(0);
+    for n in 0..3 {
+        assert!(mock.deliver_frame(frame(n)).is_ok());
+    }
+    assert!(mock
+        .deliver_format_change(
+            DecklinkVideoInputFormatChangedEvents::DISPLAY_MODE_CHANGED,
+            DecklinkDisplayModeId::HD1080p25,
+            DecklinkDetectedVideoInputFormatFlags::YCBCR_422,
+        )
+        .is_ok());
+    assert!(mock.deliver_frame(frame(3)).is_ok());
+    drop(pool);
+
+    assert_eq!(*handler.frames.lock().unwrap(), [0, 1, 2, 3]);
+    assert_eq!(
+        *handler.changes.lock().unwrap(),
+        [(3, DecklinkDisplayModeId::HD1080p25)]
+    );
+}
@@ -0,0 +1,239 @@
+//! Delivering the frames a mock driver still has buffered before stopping capture.
+#![cfg(feature = "mock-backend")]
+
+mod common;
+
+use decklink::device::input::{
+    CancellationToken, DeckLinkInputCallback, DecklinkAudioInputPacket,
+    DecklinkDetectedVideoInputFormatFlags, DecklinkInputDevice, DecklinkVideoInputFlags,
+    DecklinkVideoInputFormatChangedEvents, DrainReport,
+};
+use decklink::display_mode::DecklinkDisplayModeId;
+use decklink::frame::{DecklinkFrameBase, DecklinkPixelFormat, DecklinkVideoFrame};
+use decklink::mock::{MockBackend, MockFrame, MockInput};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+const MODE: DecklinkDisplayModeId = DecklinkDisplayModeId::HD1080p25;
+const FORMAT: DecklinkPixelFormat = DecklinkPixelFormat::Format8BitYUV;
+
+/// Notes the first byte of each frame it receives.
+#[derive(Default)]
+struct Handler {
+    frames: Mutex<Vec<u8>>,
+}
+
+impl DeckLinkInputCallback for Handler {
+    fn video_input_format_changed(
+        &self,
+        _events: DecklinkVideoInputFormatChangedEvents,
+        _new_display_mode: DecklinkDisplayModeId,
+        _detected_signal_flags: DecklinkDetectedVideoInputFormatFlags,
+    ) {
+    }
+
+    fn video_input_frame_arrived(&self, video_frame: Option<DecklinkVideoFrame>) -> bool {
+        let first = video_frame.unwrap().bytes().unwrap().0[0];
+        self.frames.lock().unwrap().push(first);
+        true
+    }
+
+    fn audio_input_packet_arrived(&self, _audio_packet: DecklinkAudioInputPacket) {}
+}
+
+fn start(backend: &MockBackend) -> (DecklinkInputDevice, MockInput, Arc<Handler>) {
+    let handler = Arc::new(Handler::default());
+    let (input, mock) = common::start_input(
+        backend,
+        MODE,
+        FORMAT,
+        DecklinkVideoInputFlags::empty(),
+        handler.clone(),
+    );
+    (input, mock, handler)
+}
+
+fn frame(value: u8) -> MockFrame {
+    MockFrame::new(48, 2, FORMAT).fill(value)
+}
+
+/// Buffer frames `1..=count` in the driver.
+fn buffer(mock: &MockInput, count: u8) {
+    for value in 1..=count {
+        mock.buffer_frame(frame(value));
+    }
+}
+
+/// Once capture is paused, deliver the buffered frames one every few milliseconds, as a
+/// driver emptying its buffer does.
+fn drain_when_paused(mock: &MockInput) -> JoinHandle<()> {
+    let mock = mock.clone();
+    thread::spawn(move || {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !mock.is_paused() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(1));
+        }
+        while mock.deliver_buffered().is_ok() {
+            thread::sleep(Duration::from_millis(2));
+        }
+    })
+}
+
+#[test]
+fn buffered_frames_reach_the_handler_before_the_stop() {
+    let backend = common::mini_recorder();
+    let (input, mock, handler) = start(&backend);
+
+    assert!(mock.deliver_frame(frame(9)).is_ok());
+    buffer(&mock, 5);
+    assert_eq!(input.available_video_frame_count().unwrap(), 5);
+    let driver = drain_when_paused(&mock);
+
+    let report = input
+        .stop_streams_drained(Duration::from_secs(5), None)
+        .unwrap();
+    driver.join().unwrap();
+
+    assert_eq!(
+        report,
+        DrainReport {
+            video_frames: 5,
+            ..Default::default()
+        }
+    );
+    assert_eq!(*handler.frames.lock().unwrap(), [9, 1, 2, 3, 4, 5]);
+    assert!(!mock.is_streaming() && !mock.is_paused());
+}
+
+#[test]
+fn nothing_buffered_stops_at_once() {
+    let backend = common::mini_recorder();
+    let (input, mock, _handler) = start(&backend);
+
+    let start = Instant::now();
+    let report = input
+        .stop_streams_drained(Duration::from_secs(5), None)
+        .unwrap();
+    assert_eq!(report, DrainReport::default());
+    assert!(start.elapsed() < Duration::from_secs(1));
+    assert!(!mock.is_streaming());
+}
+
+#[test]
+fn a_frame_delivered_while_the_buffer_is_counted_is_not_waited_for_twice() {
+    let backend = common::mini_recorder();
+    let (input, mock, handler) = start(&backend);
+
+    // Each read of the buffered count is followed by a delivery before it returns, so one
+    // lands between the drain's reads of what was delivered and what is buffered
+    buffer(&mock, 3);
+    mock.set_deliver_after_count(true);
+    let start = Instant::now();
+    let report = input
+        .stop_streams_drained(Duration::from_secs(5), None)
+        .unwrap();
+
+    assert!(start.elapsed() < Duration::from_secs(1));
+    assert_eq!(
+        report,
+        DrainReport {
+            video_frames: 3,
+            ..Default::default()
+        }
+    );
+    assert_eq!(*handler.frames.lock().unwrap(), [1, 2, 3]);
+}
+
+#[test]
+fn drain_gives_up_at_the_timeout() {
+    let backend = common::mini_recorder();
+    let (input, mock, handler) = start(&backend);
+
+    // The driver never delivers what it buffered
+    buffer(&mock, 3);
+    let start = Instant::now();
+    let report = input
+        .stop_streams_drained(Duration::from_millis(30), None)
+        .unwrap();
+
+    assert!(start.elapsed() >= Duration::from_millis(30));
+    assert_eq!(
+        report,
+        DrainReport {
+            timed_out: true,
+            ..Default::default()
+        }
+    );
+    // Stopping discarded the buffer
+    assert_eq!(mock.buffered_frames(), 0);
+    assert!(handler.frames.lock().unwrap().is_empty());
+}
+
+#[test]
+fn drain_can_be_cancelled() {
+    let backend = common::mini_recorder();
+    let (input, mock, _handler) = start(&backend);
+
+    buffer(&mock, 3);
+    let cancel = CancellationToken::new();
+    cancel.cancel();
+    let report = input
+        .stop_streams_drained(Duration::from_secs(5), Some(&cancel))
+        .unwrap();
+
+    assert_eq!(
+        report,
+        DrainReport {
+            cancelled: true,
+            ..Default::default()
+        }
+    );
+    assert!(!mock.is_streaming());
+}
+
+#[test]
+fn stragglers_after_the_drain_are_suppressed() {
+    let backend = common::mini_recorder();
+    let (input, mock, handler) = start(&backend);
+    mock.set_late_callbacks(true);
+
+    buffer(&mock, 2);
+    let driver = drain_when_paused(&mock);
+    let report = input
+        .stop_streams_drained(Duration::from_secs(5), None)
+        .unwrap();
+    driver.join().unwrap();
+    assert_eq!(report.video_frames, 2);
+
+    // A driver calling back after the stop is not forwarded
+    let suppressed = input.suppressed_callback_count();
+    assert!(mock.deliver_frame(frame(7)).is_ok());
+    assert_eq!(*handler.frames.lock().unwrap(), [1, 2]);
+    assert_eq!(input.suppressed_callback_count(), suppressed + 1);
+}
+
+#[test]
+fn capture_restarts_after_a_drain() {
+    let backend = common::mini_recorder();
+    let (input, mock, handler) = start(&backend);
+
+    buffer(&mock, 1);
+    let driver = drain_when_paused(&mock);
+    input
+        .stop_streams_drained(Duration::from_secs(5), None)
+        .unwrap();
+    driver.join().unwrap();
+
+    input.start_streams().unwrap();
+    assert!(mock.is_streaming());
+    assert!(mock.deliver_frame(frame(3)).is_ok());
+    assert_eq!(*handler.frames.lock().unwrap(), [1, 3]);
+    // Counts start again for the next drain
+    assert_eq!(
+        input
+            .stop_streams_drained(Duration::from_secs(5), None)
+            .unwrap(),
+        DrainReport::default()
+    );
+}
@@ -0,0 +1,386 @@
+//! Delivering the frames of a mock input in two pixel formats.
+#![cfg(feature = "mock-backend")]
+
+mod common;
+
+use decklink::convert::convert_frame;
+use decklink::device::input::{
+    DecklinkDetectedVideoInputFormatFlags, DecklinkInputDevice, DecklinkVideoInputFlags,
+    DecklinkVideoInputFormatChangedEvents,
+};
+use decklink::dispatch::{DispatchConfig, DispatchPool};
+use decklink::display_mode::DecklinkDisplayModeId;
+use decklink::dual::{
+    ConversionPath, DualFormatConfig, DualFormatConsumer, DualFormatEvent, DualFormatSplitter,
+    DualStream, StreamFormat, StreamFrame,
+};
+use decklink::frame::{
+    DecklinkFrameBase, DecklinkFrameFlags, DecklinkPixelFormat, DecklinkVideoMutableFrame,
+};
+use decklink::mock::{MockBackend, MockFrame, MockInput};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const MODE: DecklinkDisplayModeId = DecklinkDisplayModeId::HD1080p25;
+const PRIMARY: DecklinkPixelFormat = DecklinkPixelFormat::Format10BitYUV;
+const SECONDARY: DecklinkPixelFormat = DecklinkPixelFormat::Format8BitYUV;
+const WAIT: Duration = Duration::from_secs(5);
+
+#[derive(PartialEq, Debug)]
+enum Seen {
+    Format(StreamFormat),
+    /// The sequence and pixel format of a frame.
+    Frame(u64, DecklinkPixelFormat),
+}
+
+/// Records what a consumer is given, optionally holding its first frame until the test
+/// releases it.
+#[derive(Default)]
+struct Recorder {
+    seen: Mutex<Vec<Seen>>,
+    frames: Mutex<Vec<StreamFrame>>,
+    hold: Mutex<Option<Receiver<()>>>,
+}
+
+impl Recorder {
+    fn held() -> (Arc<Recorder>, Sender<()>) {
+        let (release, hold) = channel();
+        let recorder = Recorder {
+            hold: Mutex::new(Some(hold)),
+            ..Recorder::default()
+        };
+        (Arc::new(recorder), release)
+    }
+
+    fn sequences(&self) -> Vec<u64> {
+        self.frames
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|f| f.meta().sequence)
+            .collect()
+    }
+}
+
+impl DualFormatConsumer for Recorder {
+    fn frame_arrived(&self, frame: StreamFrame) {
+        let hold = self.hold.lock().unwrap().take();
+        if let Some(hold) = hold {
+            let _ = hold.recv();
+        }
+        let meta = *frame.meta();
+        self.seen
+            .lock()
+            .unwrap()
+            .push(Seen::Frame(meta.sequence, frame.pixel_format()));
+        self.frames.lock().unwrap().push(frame);
+    }
+
+    fn format_negotiated(&self, format: StreamFormat) {
+        self.seen.lock().unwrap().push(Seen::Format(format));
+    }
+}
+
+fn pool() -> DispatchPool {
+    DispatchPool::new(DispatchConfig::builder().workers(2).build().unwrap())
+}
+
+fn start(backend: &MockBackend, splitter: &DualFormatSplitter) -> (DecklinkInputDevice, MockInput) {
+    common::start_input(
+        backend,
+        MODE,
+        splitter.config().primary,
+        DecklinkVideoInputFlags::empty(),
+        splitter.callback(),
+    )
+}
+
+/// A frame in `format` filled with `n`.
+fn frame(n: u8, format: DecklinkPixelFormat) -> MockFrame {
+    MockFrame::new(96, 2, format).fill(n)
+}
+
+fn deliver(mock: &MockInput, frames: std::ops::Range<u8>, format: DecklinkPixelFormat) {
+    for n in frames {
+        assert!(mock.deliver_frame(frame(n, format)).is_ok());
+    }
+}
+
+#[test]
+fn only_the_streams_subscribed_to_are_delivered() {
+    let backend = common::mini_recorder();
+    let pool = pool();
+    let splitter = DualFormatSplitter::new(DualFormatConfig::default(), &pool, "dual").unwrap();
+    let (_input, mock) = start(&backend, &splitter);
+
+    // Nothing is converted without a consumer of the secondary stream
+    deliver(&mock, 0..2, PRIMARY);
+    let stats = splitter.stats();
+    assert_eq!(stats.path, Some(ConversionPath::Software));
+    assert_eq!(stats.primary.delivered, 0);
+    assert_eq!(stats.secondary.delivered, 0);
+    assert_eq!(stats.secondary.shed, 0);
+
+    let secondary = Arc::new(Recorder::default());
+    let secondary_id = splitter.subscribe(DualStream::Secondary, secondary.clone());
+    deliver(&mock, 2..4, PRIMARY);
+    assert!(splitter.wait_for_conversions(WAIT));
+    assert_eq!(secondary.sequences(), [2, 3]);
+    assert_eq!(splitter.stats().primary.delivered, 0);
+
+    let first = Arc::new(Recorder::default());
+    let second = Arc::new(Recorder::default());
+    splitter.subscribe(DualStream::Primary, first.clone());
+    splitter.subscribe(DualStream::Primary, second.clone());
+    deliver(&mock, 4..6, PRIMARY);
+    assert!(splitter.wait_for_conversions(WAIT));
+    assert_eq!(first.sequences(), [4, 5]);
+    assert_eq!(second.sequences(), [4, 5]);
+    assert_eq!(secondary.sequences(), [2, 3, 4, 5]);
+    for (a, b) in first
+        .frames
+        .lock()
+        .unwrap()
+        .iter()
+        .zip(second.frames.lock().unwrap().iter())
+    {
+        assert!(a.shares_pixels_with(b));
+        assert_eq!(a.pixel_format(), PRIMARY);
+    }
+
+    assert!(splitter.unsubscribe(secondary_id));
+    assert!(!splitter.unsubscribe(secondary_id));
+    deliver(&mock, 6..7, PRIMARY);
+    assert!(splitter.wait_for_conversions(WAIT));
+    let stats = splitter.stats();
+    assert_eq!(stats.primary.subscribers, 2);
+    assert_eq!(stats.primary.delivered, 3);
+    assert_eq!(stats.secondary.subscribers, 0);
+    assert_eq!(stats.secondary.delivered, 4);
+    assert_eq!(stats.secondary.shed, 0);
+    assert_eq!(secondary.sequences(), [2, 3, 4, 5]);
+}
+
+#[test]
+fn secondary_frames_are_converted_once_for_every_consumer() {
+    let backend = common::mini_recorder();
+    let pool = pool();
+    let splitter = DualFormatSplitter::new(DualFormatConfig::default(), &pool, "dual").unwrap();
+    let first = Arc::new(Recorder::default());
+    let second = Arc::new(Recorder::default());
+    splitter.subscribe(DualStream::Secondary, first.clone());
+    splitter.subscribe(DualStream::Secondary, second.clone());
+    let (_input, mock) = start(&backend, &splitter);
+
+    let captured = frame(0x5a, PRIMARY).stream_time(0, 1000, 25000);
+    assert!(mock.deliver_frame(captured.clone()).is_ok());
+    assert!(splitter.wait_for_conversions(WAIT));
+
+    let mut source = DecklinkVideoMutableFrame::create(
+        captured.width(),
+        captured.height(),
+        PRIMARY.bytes_per_row(captured.width()).unwrap(),
+        PRIMARY,
+        DecklinkFrameFlags::empty(),
+    );
+    source.copy_bytes(captured.data()).unwrap();
+    let expected = convert_frame(&source, SECONDARY).unwrap();
+
+    let frames = first.frames.lock().unwrap();
+    let frame = &frames[0];
+    assert!(frame.shares_pixels_with(&second.frames.lock().unwrap()[0]));
+    assert_eq!(frame.pixel_format(), SECONDARY);
+    assert_eq!(frame.bytes().unwrap().0, expected.bytes().unwrap().0);
+    let meta = frame.meta();
+    assert_eq!(meta.stream, DualStream::Secondary);
+    assert_eq!(meta.path, ConversionPath::Software);
+    assert!(meta.timing.is_some());
+    assert_eq!(splitter.stats().secondary.delivered, 1);
+}
+
+#[test]
+fn matching_formats_alias_the_primary_frames() {
+    let backend = common::mini_recorder();
+    let pool = pool();
+    let config = DualFormatConfig::builder()
+        .primary(SECONDARY)
+        .secondary(SECONDARY)
+        .build()
+        .unwrap();
+    let splitter = DualFormatSplitter::new(config, &pool, "dual").unwrap();
+    let primary = Arc::new(Recorder::default());
+    let secondary = Arc::new(Recorder::default());
+    splitter.subscribe(DualStream::Primary, primary.clone());
+    splitter.subscribe(DualStream::Secondary, secondary.clone());
+    let (_input, mock) = start(&backend, &splitter);
+
+    deliver(&mock, 0..3, SECONDARY);
+    assert_eq!(splitter.stats().path, Some(ConversionPath::Alias));
+    // Nothing was queued for the pool
+    assert_eq!(splitter.stats().pending, 0);
+    assert_eq!(pool.stats().sources[0].dispatched, 0);
+    let primary = primary.frames.lock().unwrap();
+    let secondary = secondary.frames.lock().unwrap();
+    assert_eq!(secondary.len(), 3);
+    for (p, s) in primary.iter().zip(secondary.iter()) {
+        assert!(p.shares_pixels_with(s));
+        assert_eq!(s.meta().sequence, p.meta().sequence);
+        assert_eq!(s.meta().path, ConversionPath::Alias);
+        assert_eq!(s.meta().conversion_time, Duration::ZERO);
+    }
+}
+
+#[test]
+fn overloaded_secondary_is_shed_while_the_primary_is_lossless() {
+    let backend = common::mini_recorder();
+    let pool = pool();
+    let config = DualFormatConfig::builder().max_pending(2).build().unwrap();
+    let splitter = DualFormatSplitter::new(config, &pool, "dual").unwrap();
+    let primary = Arc::new(Recorder::default());
+    let (secondary, release) = Recorder::held();
+    splitter.subscribe(DualStream::Primary, primary.clone());
+    splitter.subscribe(DualStream::Secondary, secondary.clone());
+    let (_input, mock) = start(&backend, &splitter);
+
+    // The first conversion holds its worker, and the second waits behind it
+    deliver(&mock, 0..6, PRIMARY);
+    assert_eq!(primary.sequences(), [0, 1, 2, 3, 4, 5]);
+    assert_eq!(splitter.stats().pending, 2);
+    release.send(()).unwrap();
+    assert!(splitter.wait_for_conversions(WAIT));
+    assert_eq!(secondary.sequences(), [0, 1]);
+
+    deliver(&mock, 6..7, PRIMARY);
+    assert!(splitter.wait_for_conversions(WAIT));
+    assert_eq!(secondary.sequences(), [0, 1, 6]);
+    assert_eq!(primary.sequences(), [0, 1, 2, 3, 4, 5, 6]);
+
+    let stats = splitter.stats();
+    assert_eq!(stats.primary.delivered, 7);
+    assert_eq!(stats.primary.shed, 0);
+    assert_eq!(stats.secondary.delivered, 3);
+    assert_eq!(stats.secondary.shed, 4);
+    assert_eq!(
+        splitter.take_events(),
+        [
+            DualFormatEvent::Negotiated {
+                primary: PRIMARY,
+                secondary: SECONDARY,
+                path: ConversionPath::Software,
+            },
+            DualFormatEvent::SheddingStarted { sequence: 2 },
+            DualFormatEvent::SheddingEnded {
+                sequence: 6,
+                shed: 4
+            },
+        ]
+    );
+}
+
+#[test]
+fn both_streams_are_renegotiated_on_a_format_change() {
+    let backend = common::mini_recorder();
+    let pool = pool();
+    let splitter = DualFormatSplitter::new(DualFormatConfig::default(), &pool, "dual").unwrap();
+    let primary = Arc::new(Recorder::default());
+    let secondary = Arc::new(Recorder::default());
+    splitter.subscribe(DualStream::Primary, primary.clone());
+    splitter.subscribe(DualStream::Secondary, secondary.clone());
+    let (_input, mock) = start(&backend, &splitter);
+    let change = |mode| {
+        assert!(mock
+            .deliver_format_change(
+                DecklinkVideoInputFormatChangedEvents::DISPLAY_MODE_CHANGED,
+                mode,
+                DecklinkDetectedVideoInputFormatFlags::YCBCR_422,
+            )
+            .is_ok());
+    };
+
+    deliver(&mock, 0..2, PRIMARY);
+    assert!(splitter.wait_for_conversions(WAIT));
+    // The input is enabled in the secondary format after the change, so it is aliased
+    change(DecklinkDisplayModeId::HD720p50);
+    deliver(&mock, 2..4, SECONDARY);
+    // And then in RGB, which the secondary format cannot be converted from
+    change(DecklinkDisplayModeId::HD1080p50);
+    deliver(&mock, 4..5, DecklinkPixelFormat::Format10BitRGB);
+    assert!(splitter.wait_for_conversions(WAIT));
+
+    let format = |stream, display_mode, pixel_format, path| {
+        Seen::Format(StreamFormat {
+            stream,
+            display_mode,
+            pixel_format,
+            path,
+        })
+    };
+    let hd720 = Some(DecklinkDisplayModeId::HD720p50);
+    let hd1080 = Some(DecklinkDisplayModeId::HD1080p50);
+    let rgb = DecklinkPixelFormat::Format10BitRGB;
+    assert_eq!(
+        *primary.seen.lock().unwrap(),
+        [
+            format(DualStream::Primary, None, PRIMARY, ConversionPath::Software),
+            Seen::Frame(0, PRIMARY),
+            Seen::Frame(1, PRIMARY),
+            format(DualStream::Primary, hd720, SECONDARY, ConversionPath::Alias),
+            Seen::Frame(2, SECONDARY),
+            Seen::Frame(3, SECONDARY),
+            format(
+                DualStream::Primary,
+                hd1080,
+                rgb,
+                ConversionPath::Unavailable
+            ),
+            Seen::Frame(4, rgb),
+        ]
+    );
+    assert_eq!(
+        *secondary.seen.lock().unwrap(),
+        [
+            format(
+                DualStream::Secondary,
+                None,
+                SECONDARY,
+                ConversionPath::Software
+            ),
+            Seen::Frame(0, SECONDARY),
+            Seen::Frame(1, SECONDARY),
+            format(
+                DualStream::Secondary,
+                hd720,
+                SECONDARY,
+                ConversionPath::Alias
+            ),
+            Seen::Frame(2, SECONDARY),
+            Seen::Frame(3, SECONDARY),
+            format(
+                DualStream::Secondary,
+                hd1080,
+                SECONDARY,
+                ConversionPath::Unavailable
+            ),
+        ]
+    );
+    let stats = splitter.stats();
+    assert_eq!(stats.path, Some(ConversionPath::Unavailable));
+    assert_eq!(stats.primary.pixel_format, Some(rgb));
+    assert_eq!(stats.secondary.failed, 1);
+    assert_eq!(stats.secondary.delivered, 4);
+}
+
+#[test]
+fn configs_that_cannot_deliver_are_refused() {
+    let pool = pool();
+    let compressed = DualFormatConfig {
+        secondary: DecklinkPixelFormat::FormatH265,
+        ..DualFormatConfig::default()
+    };
+    let error = DualFormatSplitter::new(compressed, &pool, "dual")
+        .err()
+        .unwrap();
+    assert_eq!(error.field, "secondary");
+    assert!(DualFormatConfig::builder().max_pending(0).build().is_err());
+}
@@ -0,0 +1,424 @@
+//! Writing raw captures to the frame dump format, and reading them back.
+
+#[cfg(feature = "mock-backend")]
+mod common;
+
+use decklink::device::input::FrameContext;
+use decklink::display_mode::DecklinkDisplayModeId;
+use decklink::dump::{
+    DamageReason, DumpError, DumpFrame, DumpHeader, DumpReader, DumpWriter, SegmentedDumpWriter,
+    DUMP_FORMAT_VERSION,
+};
+use decklink::frame::{DecklinkFrameBase, DecklinkFrameFlags, DecklinkPixelFormat};
+use decklink::segment::{SegmentPolicy, SegmentSink};
+use decklink::testing::{FillPattern, TestFrame, TestFrameBuilder};
+use decklink::time::{DecklinkFrameTiming, DecklinkTime};
+use decklink::timecode::{DecklinkTimecode, DecklinkTimecodeFormat};
+use std::io::Cursor;
+
+const MODE: DecklinkDisplayModeId = DecklinkDisplayModeId::HD1080p25;
+const FORMAT: DecklinkPixelFormat = DecklinkPixelFormat::Format8BitYUV;
+
+fn timing(n: i64) -> DecklinkFrameTiming {
+    DecklinkFrameTiming {
+        stream_time: DecklinkTime::new(n * 1000, 25000),
+        duration: DecklinkTime::new(1000, 25000),
+        mode_duration: DecklinkTime::new(1000, 25000),
+    }
+}
+
+fn header() -> DumpHeader {
+    DumpHeader::new(MODE, FORMAT, 8, 2).frame_duration(DecklinkTime::new(1000, 25000))
+}
+
+/// A small frame whose bytes all have the value `n`.
+fn frame(n: i64) -> TestFrame {
+    TestFrameBuilder::new(8, 2)
+        .pixel_format(FORMAT)
+        .fill(FillPattern::Solid(n as u8))
+        .timing(timing(n))
+        .build()
+        .unwrap()
+}
+
+/// A finished dump of frames with these sequence numbers, at the stream time of their index.
+fn dump(sequences: &[u64]) -> Vec<u8> {
+    let mut writer = DumpWriter::new(Vec::new(), &header()).unwrap();
+    for (n, sequence) in sequences.iter().enumerate() {
+        let n = n as i64;
+        writer
+            .write_frame(
+                &frame(n),
+                FrameContext::new(Some(timing(n)), *sequence),
+                None,
+            )
+            .unwrap();
+    }
+    writer.finish().unwrap()
+}
+
+fn read(bytes: &[u8]) -> DumpReader<Cursor<&[u8]>> {
+    DumpReader::new(Cursor::new(bytes)).unwrap()
+}
+
+#[test]
+fn frames_round_trip() {
+    let timecode = DecklinkTimecode::from_frame_number(900_000, 25, false);
+    let no_signal = TestFrameBuilder::new(8, 2)
+        .pixel_format(FORMAT)
+        .no_input_source()
+        .build()
+        .unwrap();
+    let config = "{\"schema_version\":1}".to_string();
+    let mut header = header();
+    header.config = config.clone();
+
+    let mut writer = DumpWriter::new(Vec::new(), &header).unwrap();
+    writer
+        .write_frame(
+            &frame(7),
+            FrameContext::new(Some(timing(7)), 0),
+            Some((DecklinkTimecodeFormat::RP188LTC, timecode)),
+        )
+        .unwrap();
+    writer
+        .write_frame(&no_signal, FrameContext::new(None, 1), None)
+        .unwrap();
+    assert_eq!(writer.frame_count(), 2);
+    let bytes = writer.finish().unwrap();
+
+    let mut reader = read(&bytes);
+    assert_eq!(reader.version(), DUMP_FORMAT_VERSION);
+    assert_eq!(reader.header(), &header);
+    assert_eq!(reader.header().config, config);
+    assert_eq!(reader.frame_count(), 2);
+    let frames: Vec<DumpFrame> = reader.by_ref().map(Result::unwrap).collect();
+    assert_eq!(reader.damage(), None);
+
+    assert_eq!(
+        frames[0],
+        DumpFrame {
+            sequence: 0,
+            flags: DecklinkFrameFlags::empty(),
+            width: 8,
+            height: 2,
+            row_bytes: 16,
+            pixel_format: FORMAT,
+            timing: Some(timing(7)),
+            timecode: Some((DecklinkTimecodeFormat::RP188LTC, timecode)),
+            payload: vec![7; 32],
+        }
+    );
+    assert_eq!(frames[1].sequence, 1);
+    assert_eq!(frames[1].flags, DecklinkFrameFlags::HAS_NO_INPUT_SOURCE);
+    assert_eq!((frames[1].timing, frames[1].timecode), (None, None));
+}
+
+#[test]
+fn the_header_is_little_endian() {
+    let bytes = DumpWriter::new(Vec::new(), &header().checksums(false))
+        .unwrap()
+        .finish()
+        .unwrap();
+
+    let mut expected = b"DLDP".to_vec();
+    expected.extend_from_slice(&[1, 0]);
+    expected.extend_from_slice(&(MODE as u32).to_le_bytes());
+    expected.extend_from_slice(&(FORMAT as u32).to_le_bytes());
+    expected.extend_from_slice(&[8, 0, 0, 0, 2, 0, 0, 0, 16, 0, 0, 0]);
+    expected.extend_from_slice(&[0xE8, 0x03, 0, 0, 0, 0, 0, 0]);
+    expected.extend_from_slice(&[0xA8, 0x61, 0, 0, 0, 0, 0, 0]);
+    // No checksums and no config
+    expected.extend_from_slice(&[0, 0, 0, 0, 0]);
+    // An empty index, and the trailer pointing to it
+    expected.extend_from_slice(&[2, 8, 0, 0, 0, 0, 0, 0, 0]);
+    expected.extend_from_slice(&[0; 8]);
+    expected.extend_from_slice(&[47, 0, 0, 0, 0, 0, 0, 0]);
+    expected.extend_from_slice(b"DLDX");
+    assert_eq!(bytes, expected);
+}
+
+#[test]
+fn frame_records_are_little_endian() {
+    let bytes = dump(&[0x0102_0304]);
+    // The tag and length of the record follow the 47 bytes of the header
+    let record = &bytes[47..];
+    assert_eq!(record[0], 1);
+    let length = u64::from_le_bytes(record[1..9].try_into().unwrap());
+    assert_eq!(length as usize, 8 + 5 * 4 + 1 + 32 + 1 + 8 + 32 + 8);
+    assert_eq!(&record[9..17], &[4, 3, 2, 1, 0, 0, 0, 0]);
+    // The stream time scale of the timing
+    assert_eq!(
+        &record[9 + 28 + 1 + 24..][..8],
+        &[0xA8, 0x61, 0, 0, 0, 0, 0, 0]
+    );
+}
+
+#[test]
+fn a_truncated_file_keeps_the_frames_before_the_damage() {
+    let mut writer = DumpWriter::new(Vec::new(), &header()).unwrap();
+    let mut third = 0;
+    for n in 0..4 {
+        if n == 2 {
+            third = writer.byte_count();
+        }
+        writer
+            .write_frame(
+                &frame(n),
+                FrameContext::new(Some(timing(n)), n as u64),
+                None,
+            )
+            .unwrap();
+    }
+    let bytes = writer.finish().unwrap();
+
+    // Cut in the middle of the third frame
+    let reader = read(&bytes[..third as usize + 20]);
+    assert_eq!(reader.frame_count(), 2);
+    let damage = reader.damage().unwrap();
+    assert_eq!(damage.intact_frames, 2);
+    assert_eq!(damage.offset, third);
+    assert_eq!(damage.reason, DamageReason::Truncated);
+    let sequences: Vec<u64> = reader.map(|f| f.unwrap().sequence).collect();
+    assert_eq!(sequences, [0, 1]);
+}
+
+#[test]
+fn an_unfinished_file_is_missing_its_index() {
+    let mut writer = DumpWriter::new(Vec::new(), &header()).unwrap();
+    for n in 0..3 {
+        writer
+            .write_frame(
+                &frame(n),
+                FrameContext::new(Some(timing(n)), n as u64),
+                None,
+            )
+            .unwrap();
+    }
+    let length = writer.byte_count();
+    let bytes = writer.finish().unwrap();
+
+    let reader = read(&bytes[..length as usize]);
+    let damage = reader.damage().unwrap();
+    assert_eq!(
+        (damage.intact_frames, damage.offset, damage.reason),
+        (3, length, DamageReason::MissingIndex)
+    );
+    assert_eq!(reader.count(), 3);
+
+    // A damaged trailer loses only the index
+    let reader = read(&bytes[..bytes.len() - 1]);
+    assert_eq!(reader.damage().unwrap().reason, DamageReason::MissingIndex);
+    assert_eq!(reader.frame_count(), 3);
+}
+
+#[test]
+fn a_corrupt_frame_fails_its_checksum() {
+    let mut bytes = dump(&[0, 1, 2]);
+    // A payload byte of the second frame, after the 47 byte header, the 119 byte record of
+    // the first frame and the 79 bytes of the second before its payload
+    assert_eq!(bytes[47 + 119 + 79], 1);
+    bytes[47 + 119 + 79] = 0xFF;
+
+    // The index still points to every frame, so the damage is found when reading
+    let mut reader = read(&bytes);
+    assert_eq!(reader.frame_count(), 3);
+    assert_eq!(reader.next().unwrap().unwrap().sequence, 0);
+    match reader.next() {
+        Some(Err(DumpError::Damaged(damage))) => {
+            assert_eq!(damage.intact_frames, 1);
+            assert_eq!(damage.reason, DamageReason::ChecksumMismatch);
+        }
+        _ => panic!("the damaged frame was read"),
+    }
+    assert!(reader.next().is_none());
+    assert_eq!(reader.damage().unwrap().intact_frames, 1);
+
+    // Without the index, the scan stops at it
+    let reader = read(&bytes[..bytes.len() - 12]);
+    let damage = reader.damage().unwrap();
+    assert_eq!(
+        (damage.intact_frames, damage.reason),
+        (1, DamageReason::ChecksumMismatch)
+    );
+}
+
+#[test]
+fn a_config_longer_than_the_file_is_truncation() {
+    let mut bytes = dump(&[0]);
+    // The config length is the last field of the 47 byte header
+    bytes[43..47].copy_from_slice(&u32::MAX.to_le_bytes());
+    match DumpReader::new(Cursor::new(&bytes)) {
+        Err(DumpError::Damaged(damage)) => {
+            assert_eq!(
+                (damage.intact_frames, damage.offset, damage.reason),
+                (0, 47, DamageReason::Truncated)
+            );
+        }
+        _ => panic!("the header was read"),
+    }
+}
+
+#[test]
+fn files_that_are_not_dumps_are_rejected() {
+    assert!(matches!(
+        DumpReader::new(Cursor::new(b"DLSR\x01\x00")),
+        Err(DumpError::NotADump)
+    ));
+
+    let mut bytes = dump(&[0]);
+    bytes[4] = 2;
+    assert!(matches!(
+        DumpReader::new(Cursor::new(&bytes)),
+        Err(DumpError::NewerVersion(2))
+    ));
+}
+
+#[test]
+fn frames_are_found_by_sequence() {
+    let bytes = dump(&[10, 11, 12, 13, 14]);
+    let mut reader = read(&bytes);
+    assert!(reader.seek_sequence(13));
+    assert_eq!(reader.position(), 3);
+    assert_eq!(reader.next().unwrap().unwrap().sequence, 13);
+    assert!(!reader.seek_sequence(9));
+    assert!(!reader.seek_sequence(15));
+
+    // With gaps from dropped frames
+    let bytes = dump(&[0, 1, 5, 6, 9]);
+    let mut reader = read(&bytes);
+    assert!(reader.seek_sequence(6));
+    assert_eq!(reader.next().unwrap().unwrap().sequence, 6);
+    assert!(!reader.seek_sequence(2));
+}
+
+#[test]
+fn frames_are_found_by_stream_time() {
+    let bytes = dump(&[0, 1, 2, 3, 4]);
+    let mut reader = read(&bytes);
+    // Between the stream times of the fourth and fifth frames, in another timescale
+    assert!(reader.seek_time(DecklinkTime::new(140, 1000)));
+    assert_eq!(reader.position(), 3);
+    assert!(reader.seek_time(DecklinkTime::new(4000, 25000)));
+    assert_eq!(reader.position(), 4);
+    assert!(reader.seek_time(DecklinkTime::new(1, 1)));
+    assert_eq!(reader.position(), 4);
+    assert!(!reader.seek_time(DecklinkTime::new(-1, 25000)));
+
+    // Without a constant frame duration the index is searched
+    let mut writer = DumpWriter::new(Vec::new(), &header()).unwrap();
+    for (n, time) in [0, 1000, 3000, 3500].into_iter().enumerate() {
+        let timing = DecklinkFrameTiming {
+            stream_time: DecklinkTime::new(time, 25000),
+            ..timing(0)
+        };
+        writer
+            .write_frame(&frame(0), FrameContext::new(Some(timing), n as u64), None)
+            .unwrap();
+    }
+    let bytes = writer.finish().unwrap();
+    let mut reader = read(&bytes);
+    assert!(reader.seek_time(DecklinkTime::new(3200, 25000)));
+    assert_eq!(reader.position(), 2);
+}
+
+#[test]
+fn dumped_frames_become_test_frames() {
+    let bytes = dump(&[0, 1]);
+    let mut reader = read(&bytes);
+    reader.seek_position(1);
+    let frame = reader.next().unwrap().unwrap().into_test_frame().unwrap();
+    assert_eq!((frame.width(), frame.height()), (8, 2));
+    assert_eq!(frame.pixel_format(), FORMAT);
+    assert_eq!(frame.timing(), Some(timing(1)));
+    assert_eq!(frame.bytes().unwrap().0, &[1; 32][..]);
+}
+
+#[test]
+fn segmented_dumps_number_frames_over_the_recording() {
+    let dir = std::env::temp_dir().join(format!("decklink-dump-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let mut writer: Box<dyn SegmentSink> = Box::new(SegmentedDumpWriter::new(
+        dir.join("segment_{index}.dump").to_string_lossy(),
+        SegmentPolicy::EveryFrames(2),
+        header(),
+    ));
+    for n in 0..5 {
+        let timecode = DecklinkTimecode::from_frame_number(n as u64, 25, false);
+        writer.set_timecode(Some((DecklinkTimecodeFormat::RP188VITC1, timecode)));
+        writer.write_frame(&frame(n), Some(&timing(n))).unwrap();
+    }
+    let segments = writer.finish().unwrap();
+    assert_eq!(segments.len(), 3);
+
+    let mut sequences = Vec::new();
+    for segment in &segments {
+        let reader = DumpReader::open(&segment.path).unwrap();
+        assert_eq!(reader.damage(), None);
+        assert_eq!(
+            segment.byte_count,
+            std::fs::metadata(&segment.path).unwrap().len()
+        );
+        for frame in reader {
+            let frame = frame.unwrap();
+            assert_eq!(
+                frame.timecode.unwrap().1,
+                DecklinkTimecode::from_frame_number(frame.sequence, 25, false)
+            );
+            sequences.push(frame.sequence);
+        }
+    }
+    assert_eq!(sequences, [0, 1, 2, 3, 4]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(feature = "mock-backend")]
+mod mock {
+    use super::common;
+    use super::{dump, read, FORMAT, MODE};
+    use decklink::device::input::{
+        CallbackResult, DecklinkVideoInputFlags, FrameArrival, InputHandler,
+    };
+    use decklink::frame::DecklinkFrameBase;
+    use decklink::replay::{replay_dump_to_mock, ReplayPace};
+    use std::sync::{Arc, Mutex};
+
+    /// Keeps the first byte and stream time of every frame.
+    #[derive(Default)]
+    struct Frames(Mutex<Vec<(u8, Option<i64>)>>);
+
+    impl InputHandler for Frames {
+        fn frame_arrived(&self, arrival: &FrameArrival<'_>) -> CallbackResult {
+            let frame = arrival.video_frame.unwrap();
+            self.0.lock().unwrap().push((
+                frame.bytes().unwrap().0[0],
+                arrival.timing().map(|t| t.stream_time.value),
+            ));
+            CallbackResult::Ok
+        }
+    }
+
+    #[test]
+    fn dumps_replay_through_a_mock_input() {
+        let backend = common::mini_recorder();
+        let mut input = common::open_input(MODE, FORMAT, DecklinkVideoInputFlags::empty());
+        let frames = Arc::new(Frames::default());
+        common::start_streams(&mut input, frames.clone());
+
+        let bytes = dump(&[0, 1, 2]);
+        let delivered = replay_dump_to_mock(
+            read(&bytes),
+            &backend.input(0),
+            ReplayPace::AsFastAsPossible,
+        )
+        .unwrap();
+        assert_eq!(delivered, 3);
+        assert_eq!(
+            *frames.0.lock().unwrap(),
+            [(0, Some(0)), (1, Some(1000)), (2, Some(2000))]
+        );
+        input.stop_streams().unwrap();
+    }
+}
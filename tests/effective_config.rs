@@ -0,0 +1,379 @@
+//! The effective configuration of a capture, its JSON form, and what an input records of its
+//! negotiation.
+
+use decklink::device::input::{
+    CaptureColorMode, DecklinkAudioSampleRate, DecklinkAudioSampleType, DimensionPolicy,
+};
+use decklink::dispatch::{DispatchConfig, DispatchMode};
+use decklink::display_mode::DecklinkDisplayModeId;
+use decklink::effective_config::{
+    component_hash, AllocatorKind, AudioSettings, EffectiveConfig, Negotiated,
+    EFFECTIVE_CONFIG_SCHEMA_VERSION,
+};
+use decklink::event::DeviceIdentity;
+use decklink::experimental::quirks::{QuirkAction, QuirkApplied};
+use decklink::experimental::transform::{Crop, MaskRegion, TransformChain};
+use decklink::frame::{DecklinkPixelFormat, Rect};
+use std::time::Duration;
+
+/// A config with every field set, as a capture through a dispatch pool would have.
+fn populated() -> EffectiveConfig {
+    let mut config = EffectiveConfig::new();
+    config.device = Some(DeviceIdentity {
+        persistent_id: Some(0x1234),
+        display_name: Some("DeckLink \"Duo\" 2".to_string()),
+    });
+    config.driver_version = Some("14.2.1".to_string());
+    config.display_mode = Negotiated::granted(DecklinkDisplayModeId::HD1080i50);
+    config.pixel_format = Negotiated {
+        requested: Some(DecklinkPixelFormat::Format12BitRGB),
+        actual: Some(DecklinkPixelFormat::Format10BitYUV),
+    };
+    config.color_mode = Negotiated {
+        requested: Some(CaptureColorMode::Auto),
+        actual: Some(CaptureColorMode::YCbCr422),
+    };
+    config.video_input_flags = 1;
+    config.audio = Negotiated::granted(AudioSettings {
+        sample_rate: DecklinkAudioSampleRate::Rate48kHz,
+        sample_type: DecklinkAudioSampleType::Int32,
+        channel_count: 16,
+    });
+    config.audio_deferred = true;
+    config.allocator = AllocatorKind::Provider;
+    config.dimension_policy = DimensionPolicy::Strict;
+    config.record_quirks(&[
+        quirk("psf-camera"),
+        quirk("psf-camera"),
+        quirk("startup-flap"),
+    ]);
+    config.dispatch = Some(
+        DispatchConfig::builder()
+            .workers(2)
+            .mode(DispatchMode::WorkStealing)
+            .queue_capacity(4)
+            .latency_window(64)
+            .starvation_threshold(Duration::from_millis(250))
+            .build()
+            .unwrap(),
+    );
+    config.handler = Some(component_hash("my_app::Recorder"));
+    let rect = Rect {
+        x: 0,
+        y: 0,
+        width: 64,
+        height: 32,
+    };
+    config.record_transform_chain(
+        &TransformChain::new()
+            .with(Crop { rect })
+            .with(MaskRegion { rect }),
+    );
+    config
+}
+
+fn quirk(rule: &str) -> QuirkApplied {
+    QuirkApplied {
+        rule: rule.to_string(),
+        action: QuirkAction::ForcePsf,
+        frame: 3,
+        detected_mode: DecklinkDisplayModeId::HD1080i5994,
+    }
+}
+
+#[test]
+fn a_new_config_describes_the_build() {
+    let config = EffectiveConfig::new();
+    assert_eq!(config.schema_version, EFFECTIVE_CONFIG_SCHEMA_VERSION);
+    assert_eq!(config.crate_version, env!("CARGO_PKG_VERSION"));
+    assert_eq!(
+        config.features.contains(&"serde".to_string()),
+        cfg!(feature = "serde")
+    );
+    assert_eq!(
+        config.features.contains(&"mock-backend".to_string()),
+        cfg!(feature = "mock-backend")
+    );
+    assert_eq!(config.display_mode, Negotiated::default());
+    assert_eq!(config.allocator, AllocatorKind::Driver);
+}
+
+#[test]
+fn components_are_identified_by_hashes_of_their_names() {
+    // FNV-1a of nothing
+    assert_eq!(component_hash(""), "cbf29ce484222325");
+    assert_ne!(component_hash("crop"), component_hash("mask"));
+
+    let config = populated();
+    assert_eq!(
+        config.transform_stages,
+        [component_hash("crop"), component_hash("mask")]
+    );
+    assert!(!config.to_json().contains("Recorder"));
+    // Each rule is listed once
+    assert_eq!(config.quirks, ["psf-camera", "startup-flap"]);
+}
+
+#[test]
+fn a_negotiated_value_differs_once_known() {
+    let pending = Negotiated {
+        requested: Some(DecklinkPixelFormat::Format10BitYUV),
+        actual: None,
+    };
+    assert!(!pending.differs());
+    assert!(!Negotiated::granted(DecklinkPixelFormat::Format10BitYUV).differs());
+    assert!(populated().pixel_format.differs());
+}
+
+/// Every option of the builders and setters of a capture in this release must be recorded,
+/// so that adding an option without recording it fails here.
+#[test]
+fn every_capture_option_is_recorded() {
+    let json: serde_json::Value = serde_json::from_str(&populated().to_json()).unwrap();
+
+    // The options of the input, by the methods that set them
+    for (option, field) in [
+        ("enable_video_input mode", "display_mode"),
+        ("enable_video_input pixel_format", "pixel_format"),
+        ("enable_video_input flags", "video_input_flags"),
+        ("PixelFormatPreference", "pixel_format"),
+        ("CaptureColorMode", "color_mode"),
+        ("enable_audio_input", "audio"),
+        ("request_audio_input", "audio_deferred"),
+        ("enable_video_input_with_allocator", "allocator"),
+        ("set_dimension_policy", "dimension_policy"),
+        ("set_callback", "handler"),
+        ("FormatDetector quirks", "quirks"),
+        ("TransformChain", "transform_stages"),
+        ("DispatchPool", "dispatch"),
+    ] {
+        let value = &json[field];
+        let unset = [
+            serde_json::Value::Null,
+            serde_json::json!([]),
+            serde_json::json!({ "requested": null, "actual": null }),
+        ];
+        assert!(
+            !unset.contains(value),
+            "{} is not recorded in {}",
+            option,
+            field
+        );
+    }
+    for option in [
+        "workers",
+        "mode",
+        "queue_capacity",
+        "latency_window",
+        "starvation_threshold",
+    ] {
+        assert!(
+            !json["dispatch"][option].is_null(),
+            "DispatchConfigBuilder::{} is not recorded",
+            option
+        );
+    }
+}
+
+#[test]
+fn json_is_one_line_in_the_documented_layout() {
+    let json = populated().to_json();
+    assert!(!json.contains('\n'));
+    let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(value["schema_version"], EFFECTIVE_CONFIG_SCHEMA_VERSION);
+    assert_eq!(value["device"]["display_name"], "DeckLink \"Duo\" 2");
+    assert_eq!(value["pixel_format"]["requested"], "Format12BitRGB");
+    assert_eq!(value["pixel_format"]["actual"], "Format10BitYUV");
+    assert_eq!(value["audio"]["actual"]["channel_count"], 16);
+    assert_eq!(
+        value["dispatch"]["starvation_threshold"]["nanos"],
+        250_000_000
+    );
+    assert_eq!(value["handler"], component_hash("my_app::Recorder"));
+
+    let empty: serde_json::Value = serde_json::from_str(&EffectiveConfig::new().to_json()).unwrap();
+    assert!(empty["device"].is_null());
+    assert!(empty["display_mode"]["actual"].is_null());
+    assert!(empty["dispatch"].is_null());
+}
+
+#[cfg(feature = "serde")]
+mod serialized {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        for config in [EffectiveConfig::new(), populated()] {
+            let json = serde_json::to_string(&config).unwrap();
+            let back: EffectiveConfig = serde_json::from_str(&json).unwrap();
+            assert_eq!(back, config);
+        }
+    }
+
+    #[test]
+    fn to_json_writes_the_serialized_layout() {
+        for config in [EffectiveConfig::new(), populated()] {
+            let written: serde_json::Value = serde_json::from_str(&config.to_json()).unwrap();
+            assert_eq!(written, serde_json::to_value(&config).unwrap());
+            let back: EffectiveConfig = serde_json::from_str(&config.to_json()).unwrap();
+            assert_eq!(back, config);
+        }
+    }
+
+    #[test]
+    fn future_fields_are_skipped() {
+        let mut value = serde_json::to_value(populated()).unwrap();
+        let object = value.as_object_mut().unwrap();
+        object.insert(
+            "schema_version".to_string(),
+            (EFFECTIVE_CONFIG_SCHEMA_VERSION + 1).into(),
+        );
+        object.insert(
+            "genlock".to_string(),
+            serde_json::json!({ "source": "tri-level" }),
+        );
+
+        let config: EffectiveConfig = serde_json::from_value(value).unwrap();
+        // A reader compares the version before trusting the fields, which are still read
+        assert!(config.schema_version > EFFECTIVE_CONFIG_SCHEMA_VERSION);
+        assert_eq!(config.driver_version.as_deref(), Some("14.2.1"));
+    }
+}
+
+#[cfg(feature = "mock-backend")]
+mod mock {
+    use super::*;
+    use decklink::device::get_devices;
+    use decklink::device::input::{
+        AudioInputState, CallbackResult, DecklinkVideoInputFlags, FrameArrival, InputHandler,
+        PixelFormatPreference,
+    };
+    use decklink::effective_config::DecklinkEffectiveConfigExt;
+    use decklink::mock::{MockBackend, MockDevice};
+    use std::sync::Arc;
+
+    const MODE: DecklinkDisplayModeId = DecklinkDisplayModeId::HD1080p25;
+
+    struct Ignore;
+
+    impl InputHandler for Ignore {
+        fn frame_arrived(&self, _arrival: &FrameArrival<'_>) -> CallbackResult {
+            CallbackResult::Ok
+        }
+    }
+
+    fn device() -> MockDevice {
+        MockDevice::new("DeckLink SDI 4K")
+            .modes(&[MODE])
+            .pixel_formats(&[DecklinkPixelFormat::Format10BitYUV])
+            .audio_requires_video_first(true)
+    }
+
+    #[test]
+    fn the_input_records_what_it_negotiates() {
+        let _backend = MockBackend::install(vec![device()]);
+        let devices = get_devices().unwrap();
+        let mut input = devices[0].input().unwrap();
+        input.set_handler(Some(Arc::new(Ignore))).unwrap();
+        input.set_dimension_policy(DimensionPolicy::Strict);
+        assert_eq!(
+            input
+                .request_audio_input(
+                    DecklinkAudioSampleRate::Rate48kHz,
+                    DecklinkAudioSampleType::Int16,
+                    2,
+                )
+                .unwrap(),
+            AudioInputState::Deferred
+        );
+        let config = input.effective_config();
+        assert!(config.audio_deferred);
+        assert_eq!(config.audio.actual, None);
+
+        let preference = PixelFormatPreference::PreferredWithFallback(vec![
+            DecklinkPixelFormat::Format12BitRGB,
+            DecklinkPixelFormat::Format10BitYUV,
+        ]);
+        input
+            .enable_video_input_with_preference(
+                MODE,
+                &preference,
+                DecklinkVideoInputFlags::ENABLE_FORMAT_DETECTION,
+            )
+            .unwrap();
+        input.start_streams().unwrap();
+
+        let config = input.effective_config();
+        assert_eq!(config.display_mode, Negotiated::granted(MODE));
+        assert_eq!(
+            config.pixel_format,
+            Negotiated {
+                requested: Some(DecklinkPixelFormat::Format12BitRGB),
+                actual: Some(DecklinkPixelFormat::Format10BitYUV),
+            }
+        );
+        assert_eq!(
+            config.video_input_flags,
+            DecklinkVideoInputFlags::ENABLE_FORMAT_DETECTION.bits()
+        );
+        assert_eq!(
+            config.audio.actual.map(|a| a.sample_type),
+            Some(DecklinkAudioSampleType::Int16)
+        );
+        assert!(config.audio_deferred);
+        assert_eq!(config.allocator, AllocatorKind::Driver);
+        assert_eq!(config.dimension_policy, DimensionPolicy::Strict);
+        assert_eq!(
+            config.handler,
+            Some(component_hash(std::any::type_name::<Ignore>()))
+        );
+        // Left for the caller
+        assert_eq!(config.device, None);
+        assert_eq!(config.driver_version, None);
+
+        input.stop_streams().unwrap();
+        input.disable_audio_input().unwrap();
+        input.disable_video_input().unwrap();
+        let config = input.effective_config();
+        assert_eq!(config.audio, Negotiated::default());
+        assert!(!config.audio_deferred);
+        // Video input is described as last enabled
+        assert_eq!(config.display_mode, Negotiated::granted(MODE));
+        drop(input);
+        drop(devices);
+        assert_eq!(MockBackend::live_objects(), 0);
+    }
+
+    #[test]
+    fn a_color_mode_is_recorded_with_what_it_resolved_to() {
+        let _backend = MockBackend::install(vec![device()]);
+        let devices = get_devices().unwrap();
+        let mut input = devices[0].input().unwrap();
+        input
+            .enable_video_input_with_color_mode(
+                MODE,
+                CaptureColorMode::Auto,
+                None,
+                None,
+                DecklinkVideoInputFlags::empty(),
+            )
+            .unwrap();
+        let config = input.effective_config();
+        assert_eq!(
+            config.color_mode,
+            Negotiated {
+                requested: Some(CaptureColorMode::Auto),
+                actual: Some(CaptureColorMode::Auto),
+            }
+        );
+        assert_eq!(config.pixel_format.requested, None);
+        assert_eq!(
+            config.pixel_format.actual,
+            Some(DecklinkPixelFormat::Format10BitYUV)
+        );
+        input.disable_video_input().unwrap();
+        drop(input);
+        drop(devices);
+        assert_eq!(MockBackend::live_objects(), 0);
+    }
+}
@@ -0,0 +1,256 @@
+//! Recording module events into the unified schema, and reading them back.
+
+#[cfg(feature = "mock-backend")]
+mod common;
+
+use decklink::audio_continuity::AudioContinuityEvent;
+use decklink::event::{DeviceIdentity, EventPayload, EventRecorder, EVENT_SCHEMA_VERSION};
+use decklink::latency::{LatencyEvent, LatencyStage};
+use decklink::manifest::ManifestEvent;
+use decklink::time::DecklinkTime;
+use std::time::Duration;
+
+fn device() -> DeviceIdentity {
+    DeviceIdentity {
+        persistent_id: Some(0x1234),
+        display_name: Some("DeckLink Duo (1)".to_string()),
+    }
+}
+
+fn exceeded() -> LatencyEvent {
+    LatencyEvent::BoundExceeded {
+        p99: Duration::from_millis(40),
+        stage: LatencyStage::QueueWait,
+        stage_p99: Duration::from_millis(25),
+    }
+}
+
+#[test]
+fn module_events_are_stamped_in_order() {
+    let recorder = EventRecorder::new(Some(device()), 16);
+    let time = DecklinkTime::new(1000, 25000);
+    assert_eq!(recorder.record(exceeded(), Some(time)), 0);
+    recorder.record_all(
+        [
+            AudioContinuityEvent::Gap {
+                sample_time: 1920,
+                samples: 40,
+            },
+            AudioContinuityEvent::Filled {
+                sample_time: 1920,
+                samples: 40,
+            },
+        ],
+        None,
+    );
+
+    let events = recorder.take_events();
+    assert_eq!(
+        events.iter().map(|e| e.sequence).collect::<Vec<_>>(),
+        [0, 1, 2]
+    );
+    assert_eq!(
+        events.iter().map(|e| e.kind_str()).collect::<Vec<_>>(),
+        ["latency", "audio_continuity", "audio_continuity"]
+    );
+    assert!(events
+        .iter()
+        .all(|e| e.schema_version == EVENT_SCHEMA_VERSION
+            && e.device == Some(device())
+            && !e.is_unknown()));
+    assert_eq!(events[0].stream_time, Some(time));
+    assert_eq!(events[0].payload, EventPayload::Latency(exceeded()));
+    assert!(events[0].wall_clock <= events[2].wall_clock);
+    assert!(recorder.take_events().is_empty());
+}
+
+#[test]
+fn full_ring_overwrites_the_oldest() {
+    let recorder = EventRecorder::new(None, 2);
+    for frame in 0..5 {
+        recorder.record(ManifestEvent::SignalLost { frame }, None);
+    }
+    let events = recorder.take_events();
+    assert_eq!(
+        events.iter().map(|e| e.sequence).collect::<Vec<_>>(),
+        [3, 4]
+    );
+    assert_eq!(recorder.overwritten(), 3);
+    // Sequence numbers carry on after a take
+    assert_eq!(recorder.record(exceeded(), None), 5);
+}
+
+#[test]
+fn manifest_entries_come_from_events() {
+    let filled = EventPayload::from(AudioContinuityEvent::Filled {
+        sample_time: 1920,
+        samples: 40,
+    });
+    assert_eq!(
+        filled.manifest_event(),
+        Some(ManifestEvent::AudioFilled {
+            sample_time: 1920,
+            samples: 40
+        })
+    );
+    let mark = ManifestEvent::Mark {
+        frame: 7,
+        label: "slate".to_string(),
+    };
+    assert_eq!(
+        EventPayload::from(mark.clone()).manifest_event(),
+        Some(mark)
+    );
+    assert_eq!(EventPayload::from(exceeded()).manifest_event(), None);
+}
+
+#[cfg(feature = "serde")]
+mod serialized {
+    use super::*;
+    use decklink::event::DecklinkEvent;
+
+    fn recorded() -> Vec<DecklinkEvent> {
+        let recorder = EventRecorder::new(Some(device()), 16);
+        recorder.record(exceeded(), Some(DecklinkTime::new(1000, 25000)));
+        recorder.record(
+            ManifestEvent::Mark {
+                frame: 7,
+                label: "slate".to_string(),
+            },
+            None,
+        );
+        recorder.take_events()
+    }
+
+    #[test]
+    fn round_trip() {
+        for event in recorded() {
+            let json = serde_json::to_string(&event).unwrap();
+            let back: DecklinkEvent = serde_json::from_str(&json).unwrap();
+            assert_eq!(back, event);
+        }
+    }
+
+    #[test]
+    fn payload_is_adjacent_to_its_kind() {
+        let value = serde_json::to_value(&recorded()[0]).unwrap();
+        assert_eq!(value["schema_version"], EVENT_SCHEMA_VERSION);
+        assert_eq!(value["sequence"], 0);
+        assert_eq!(value["kind"], "latency");
+        assert_eq!(value["stream_time"]["scale"], 25000);
+        assert_eq!(value["device"]["persistent_id"], 0x1234);
+        assert!(value["latency"].is_object());
+        // Only the payload of the event's own kind is written
+        assert!(value.get("capture").is_none());
+    }
+
+    #[test]
+    fn future_kind_is_unknown() {
+        let mut value = serde_json::to_value(&recorded()[0]).unwrap();
+        let object = value.as_object_mut().unwrap();
+        object.remove("latency");
+        object.insert("kind".to_string(), "genlock".into());
+        object.insert(
+            "genlock".to_string(),
+            serde_json::json!({ "Lost": { "offset": 12 } }),
+        );
+
+        let event: DecklinkEvent = serde_json::from_value(value).unwrap();
+        assert!(event.is_unknown());
+        assert_eq!(event.kind_str(), "genlock");
+        assert_eq!(
+            event.payload,
+            EventPayload::Unknown {
+                kind: "genlock".to_string()
+            }
+        );
+        // The rest of the event is still read
+        assert_eq!(event.sequence, 0);
+        assert_eq!(event.device, Some(device()));
+    }
+
+    #[test]
+    fn known_kind_without_its_payload_is_an_error() {
+        let mut value = serde_json::to_value(&recorded()[0]).unwrap();
+        value.as_object_mut().unwrap().remove("latency");
+        let error = serde_json::from_value::<DecklinkEvent>(value).unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("no payload for its kind \"latency\""));
+    }
+}
+
+#[cfg(feature = "mock-backend")]
+mod mock {
+    use super::common;
+    use super::*;
+    use decklink::audio_continuity::{AudioContinuity, AudioContinuityConfig};
+    use decklink::device::get_devices;
+    use decklink::device::input::{
+        DeckLinkInputCallback, DecklinkDetectedVideoInputFormatFlags, DecklinkVideoInputFlags,
+        DecklinkVideoInputFormatChangedEvents,
+    };
+    use decklink::display_mode::DecklinkDisplayModeId;
+    use decklink::frame::{DecklinkPixelFormat, DecklinkVideoFrame};
+    use decklink::mock::{MockBackend, MockDevice};
+    use std::sync::Arc;
+
+    struct Discard;
+
+    impl DeckLinkInputCallback for Discard {
+        fn video_input_format_changed(
+            &self,
+            _events: DecklinkVideoInputFormatChangedEvents,
+            _new_display_mode: DecklinkDisplayModeId,
+            _detected_signal_flags: DecklinkDetectedVideoInputFormatFlags,
+        ) {
+        }
+
+        fn video_input_frame_arrived(&self, _video_frame: Option<DecklinkVideoFrame>) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn events_of_a_capture_carry_its_device() {
+        let backend = MockBackend::install(vec![
+            MockDevice::new("DeckLink Duo (1)").sub_device(1, 0x1234)
+        ]);
+        let identity = DeviceIdentity::of(&get_devices().unwrap()[0]);
+        assert_eq!(identity, device());
+
+        let mut input = get_devices().unwrap()[0].input().unwrap();
+        let continuity = AudioContinuity::new(Arc::new(Discard), AudioContinuityConfig::default());
+        input
+            .enable_video_input(
+                DecklinkDisplayModeId::HD1080p25,
+                DecklinkPixelFormat::Format8BitYUV,
+                DecklinkVideoInputFlags::empty(),
+            )
+            .unwrap();
+        common::enable_stereo_audio(&mut input);
+        common::start_streams(&mut input, continuity.callback());
+        let mock = backend.input(0);
+        let samples = vec![0u8; 1920 * 2 * 2];
+        assert!(mock.deliver_audio(&samples).is_ok());
+        mock.skip_audio(40);
+        assert!(mock.deliver_audio(&samples).is_ok());
+        input.stop_streams().unwrap();
+
+        let recorder = EventRecorder::new(Some(identity), 16);
+        recorder.record_all(continuity.take_events(), None);
+        let events = recorder.take_events();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].device, Some(device()));
+        assert_eq!(events[0].kind_str(), "audio_continuity");
+        assert_eq!(
+            events[0].payload,
+            EventPayload::AudioContinuity(AudioContinuityEvent::Gap {
+                sample_time: 1920,
+                samples: 40,
+            })
+        );
+        // A gap left unfilled changes nothing in the files
+        assert_eq!(events[0].payload.manifest_event(), None);
+    }
+}
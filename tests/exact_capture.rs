@@ -0,0 +1,358 @@
+//! Captures of an exact number of frames, or length of stream time, from a mock driver that
+//! keeps delivering past the limit.
+#![cfg(feature = "mock-backend")]
+
+mod common;
+
+use decklink::device::input::{
+    ArrivalFlags, CallbackResult, DecklinkAudioSampleRate, DecklinkAudioSampleType,
+    DecklinkDetectedVideoInputFormatFlags, DecklinkInputDevice, DecklinkVideoInputFlags,
+    DecklinkVideoInputFormatChangedEvents, FrameArrival, InputFormatChange, InputHandler,
+};
+use decklink::display_mode::DecklinkDisplayModeId;
+use decklink::frame::DecklinkPixelFormat;
+use decklink::mock::{Delivery, MockBackend, MockFrame, MockInput};
+use decklink::session::{CaptureSession, Limit, UnderDelivery, UnderDeliveryReason};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+const MODE: DecklinkDisplayModeId = DecklinkDisplayModeId::HD1080p25;
+const FORMAT: DecklinkPixelFormat = DecklinkPixelFormat::Format8BitYUV;
+const CHANNELS: u32 = 2;
+
+/// Notes what the capture passes on.
+#[derive(Default)]
+struct Collector {
+    /// The stream time of each frame.
+    frames: Mutex<Vec<i64>>,
+    lost: AtomicUsize,
+    /// The time, sample frame count and first byte of each audio packet.
+    audio: Mutex<Vec<(i64, usize, u8)>>,
+    format_changes: AtomicUsize,
+}
+
+impl Collector {
+    fn frames(&self) -> Vec<i64> {
+        self.frames.lock().unwrap().clone()
+    }
+}
+
+impl InputHandler for Collector {
+    fn format_changed(&self, _change: &InputFormatChange) {
+        self.format_changes.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn frame_arrived(&self, arrival: &FrameArrival<'_>) -> CallbackResult {
+        if arrival.video_frame.is_some() {
+            let time = arrival.timing().map_or(-1, |t| t.stream_time.value);
+            self.frames.lock().unwrap().push(time);
+        }
+        if arrival.flags.contains(ArrivalFlags::FRAME_LOST) {
+            self.lost.fetch_add(1, Ordering::SeqCst);
+        }
+        if let Some(packet) = arrival.audio_packet {
+            let bytes = packet.bytes().unwrap();
+            self.audio.lock().unwrap().push((
+                packet.packet_time(48000).unwrap(),
+                packet.sample_frame_count(),
+                bytes[0],
+            ));
+        }
+        CallbackResult::Ok
+    }
+}
+
+fn open(backend: &MockBackend) -> (DecklinkInputDevice, MockInput) {
+    let input = common::open_input(
+        MODE,
+        FORMAT,
+        DecklinkVideoInputFlags::ENABLE_FORMAT_DETECTION,
+    );
+    (input, backend.input(0))
+}
+
+/// A callback of the mock driver, returning whether it reached the input.
+type Step = Box<dyn FnOnce(&MockInput) -> bool + Send>;
+
+/// The `n`th frame of a 25 fps source, starting at stream time `start`.
+fn frame(start: i64, n: i64) -> MockFrame {
+    MockFrame::new(48, 2, FORMAT).stream_time(start + n * 1000, 1000, 25000)
+}
+
+/// Once streams start, make the callbacks of `steps`, returning how many were delivered.
+fn source(mock: &MockInput, steps: Vec<Step>) -> JoinHandle<usize> {
+    let mock = mock.clone();
+    thread::spawn(move || {
+        while !mock.is_streaming() {
+            thread::sleep(Duration::from_millis(1));
+        }
+        steps
+            .into_iter()
+            .map(|step| step(&mock))
+            .filter(|delivered| *delivered)
+            .count()
+    })
+}
+
+fn frames(start: i64, range: std::ops::Range<i64>) -> Vec<Step> {
+    range
+        .map(|n| {
+            Box::new(move |mock: &MockInput| {
+                mock.deliver_frame(frame(start, n)) != Delivery::NotDelivered
+            }) as Step
+        })
+        .collect()
+}
+
+#[test]
+fn a_frame_limit_passes_on_exactly_that_many_frames() {
+    let backend = common::mini_recorder();
+    let (mut input, mock) = open(&backend);
+    // The driver keeps calling back after streams stop
+    mock.set_late_callbacks(true);
+    let collector = Arc::new(Collector::default());
+    let driver = source(&mock, frames(0, 0..30));
+
+    let capture = CaptureSession::new(&mut input, collector.clone())
+        .capture_exact(Limit::Frames(10))
+        .unwrap();
+    let delivered = driver.join().unwrap();
+
+    assert_eq!(
+        collector.frames(),
+        (0..10).map(|n| n * 1000).collect::<Vec<_>>()
+    );
+    assert_eq!(capture.delivered, 10);
+    assert!(capture.is_complete());
+    assert_eq!(capture.first.unwrap().timing.unwrap().stream_time.value, 0);
+    assert_eq!(
+        capture.last.unwrap().timing.unwrap().stream_time.value,
+        9000
+    );
+    // Every frame past the limit was either held back or suppressed after the stop
+    assert_eq!(delivered, 30);
+    assert_eq!(
+        capture.delivered + capture.over_limit + input.suppressed_callback_count(),
+        delivered as u64
+    );
+    assert!(!mock.is_streaming());
+}
+
+#[test]
+fn lost_frames_near_the_limit_are_not_counted() {
+    let backend = common::mini_recorder();
+    let (mut input, mock) = open(&backend);
+    let collector = Arc::new(Collector::default());
+    let mut steps = frames(0, 0..4);
+    steps.push(Box::new(|mock: &MockInput| {
+        mock.deliver_frame(frame(0, 4).conversion_fails()).is_ok()
+    }));
+    steps.extend(frames(0, 5..10));
+    let driver = source(&mock, steps);
+
+    let capture = CaptureSession::new(&mut input, collector.clone())
+        .capture_exact(Limit::Frames(5))
+        .unwrap();
+    driver.join().unwrap();
+
+    // The lost frame is passed on as lost, and the limit is made up by the next
+    assert_eq!(collector.frames(), [0, 1000, 2000, 3000, 5000]);
+    assert_eq!(collector.lost.load(Ordering::SeqCst), 1);
+    assert_eq!(capture.delivered, 5);
+    assert!(capture.is_complete());
+}
+
+#[test]
+fn a_duration_limit_ends_short_when_the_source_drops_the_last_frame() {
+    let backend = common::mini_recorder();
+    let (mut input, mock) = open(&backend);
+    let collector = Arc::new(Collector::default());
+    let mut steps = frames(0, 0..9);
+    steps.extend(frames(0, 10..15));
+    let driver = source(&mock, steps);
+
+    let capture = CaptureSession::new(&mut input, collector.clone())
+        .capture_exact(Limit::Duration(Duration::from_millis(400)))
+        .unwrap();
+    driver.join().unwrap();
+
+    assert_eq!(collector.frames().len(), 9);
+    assert_eq!(capture.delivered, 9);
+    assert_eq!(
+        capture.under_delivered,
+        Some(UnderDelivery {
+            reason: UnderDeliveryReason::FramesDropped,
+            missing_frames: Some(1),
+        })
+    );
+}
+
+#[test]
+fn a_format_change_just_before_the_limit_is_passed_on() {
+    let backend = common::mini_recorder();
+    let (mut input, mock) = open(&backend);
+    mock.set_late_callbacks(true);
+    let collector = Arc::new(Collector::default());
+    let change = || {
+        Box::new(|mock: &MockInput| {
+            mock.deliver_format_change(
+                DecklinkVideoInputFormatChangedEvents::DISPLAY_MODE_CHANGED,
+                DecklinkDisplayModeId::HD1080p50,
+                DecklinkDetectedVideoInputFormatFlags::YCBCR_422,
+            )
+            .is_ok()
+        }) as Step
+    };
+    let mut steps = frames(0, 0..4);
+    steps.push(change());
+    steps.extend(frames(0, 4..8));
+    steps.push(change());
+    let driver = source(&mock, steps);
+
+    let capture = CaptureSession::new(&mut input, collector.clone())
+        .capture_exact(Limit::Frames(5))
+        .unwrap();
+    driver.join().unwrap();
+
+    assert_eq!(capture.delivered, 5);
+    assert_eq!(collector.frames().len(), 5);
+    // The change after the limit is not passed on
+    assert_eq!(capture.format_changes, 1);
+    assert_eq!(collector.format_changes.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn a_duration_limit_ends_on_the_frame_containing_the_endpoint() {
+    for (millis, expected) in [(200, 5), (190, 5), (210, 6)] {
+        let backend = common::mini_recorder();
+        let (mut input, mock) = open(&backend);
+        let collector = Arc::new(Collector::default());
+        // Stream time does not start at zero
+        let driver = source(&mock, frames(3000, 0..20));
+
+        let capture = CaptureSession::new(&mut input, collector.clone())
+            .capture_exact(Limit::Duration(Duration::from_millis(millis)))
+            .unwrap();
+        driver.join().unwrap();
+
+        assert_eq!(capture.delivered, expected, "{} ms", millis);
+        assert_eq!(
+            collector.frames(),
+            (0..expected as i64)
+                .map(|n| 3000 + n * 1000)
+                .collect::<Vec<_>>()
+        );
+        assert!(capture.is_complete());
+    }
+}
+
+#[test]
+fn audio_is_trimmed_to_the_frames() {
+    let backend = common::mini_recorder();
+    let (mut input, mock) = open(&backend);
+    input
+        .enable_audio_input(
+            DecklinkAudioSampleRate::Rate48kHz,
+            DecklinkAudioSampleType::Int16,
+            CHANNELS,
+        )
+        .unwrap();
+    let collector = Arc::new(Collector::default());
+    // Packets of 2880 sample frames, each with the index of its sample frame in every byte,
+    // and frames of 1920 sample frames, starting half a frame into the audio
+    let steps = (0..6)
+        .map(|n| {
+            Box::new(move |mock: &MockInput| {
+                let samples: Vec<u8> = (n * 2880..(n + 1) * 2880)
+                    .flat_map(|i| [(i % 251) as u8; 2 * CHANNELS as usize])
+                    .collect();
+                mock.deliver_frame_with_audio(frame(500, n), &samples)
+                    .is_ok()
+            }) as Step
+        })
+        .collect();
+    let driver = source(&mock, steps);
+
+    let capture = CaptureSession::new(&mut input, collector.clone())
+        .capture_exact(Limit::Frames(3))
+        .unwrap();
+    driver.join().unwrap();
+
+    // Frames span sample frames 960 to 6720
+    assert_eq!(
+        *collector.audio.lock().unwrap(),
+        [
+            (960, 1920, (960 % 251) as u8),
+            (2880, 2880, (2880 % 251) as u8),
+            (5760, 960, (5760 % 251) as u8),
+        ]
+    );
+    assert_eq!(capture.audio_sample_frames, 3 * 1920);
+}
+
+#[test]
+fn losing_the_signal_ends_the_capture_short() {
+    let backend = common::mini_recorder();
+    let (mut input, mock) = open(&backend);
+    let collector = Arc::new(Collector::default());
+    let mut steps = frames(0, 0..3);
+    steps.extend((3..10).map(|n| {
+        Box::new(move |mock: &MockInput| mock.deliver_frame(frame(0, n).no_signal()).is_ok())
+            as Step
+    }));
+    let driver = source(&mock, steps);
+
+    let capture = CaptureSession::new(&mut input, collector.clone())
+        .capture_exact(Limit::Frames(10))
+        .unwrap();
+    driver.join().unwrap();
+
+    assert_eq!(collector.frames().len(), 3);
+    assert_eq!(
+        capture.under_delivered,
+        Some(UnderDelivery {
+            reason: UnderDeliveryReason::SignalLost,
+            missing_frames: Some(7),
+        })
+    );
+}
+
+#[test]
+fn a_source_that_stops_times_out() {
+    let backend = common::mini_recorder();
+    let (mut input, mock) = open(&backend);
+    let collector = Arc::new(Collector::default());
+    let driver = source(&mock, frames(0, 0..2));
+
+    let capture = CaptureSession::new(&mut input, collector.clone())
+        .with_frame_timeout(Duration::from_millis(50))
+        .capture_exact(Limit::Frames(10))
+        .unwrap();
+    driver.join().unwrap();
+
+    assert_eq!(capture.delivered, 2);
+    assert_eq!(
+        capture.under_delivered,
+        Some(UnderDelivery {
+            reason: UnderDeliveryReason::TimedOut,
+            missing_frames: Some(8),
+        })
+    );
+    assert!(!mock.is_streaming());
+}
+
+#[test]
+fn no_frames_captures_nothing() {
+    let backend = common::mini_recorder();
+    let (mut input, mock) = open(&backend);
+    let collector = Arc::new(Collector::default());
+
+    let capture = CaptureSession::new(&mut input, collector)
+        .capture_exact(Limit::Frames(0))
+        .unwrap();
+    assert_eq!(capture.delivered, 0);
+    assert!(capture.is_complete());
+    assert!(!mock.is_streaming());
+}
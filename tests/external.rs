@@ -0,0 +1,331 @@
+//! Handing the buffers of retained frames of a mock input to an external consumer, with
+//! completion tokens.
+#![cfg(feature = "mock-backend")]
+
+mod common;
+
+use decklink::allocator::{
+    BufferSpec, VideoBuffer, VideoBufferAllocator, VideoBufferAllocatorProvider,
+};
+use decklink::device::get_devices;
+use decklink::device::input::{
+    DeckLinkInputCallback, DecklinkDetectedVideoInputFormatFlags, DecklinkInputDevice,
+    DecklinkVideoInputFlags, DecklinkVideoInputFormatChangedEvents,
+};
+use decklink::display_mode::DecklinkDisplayModeId;
+use decklink::external::{
+    CompletionOutcome, CompletionToken, ExternalFrameRef, ExternalUseConfig, ExternalUseEvent,
+    ReclaimReason,
+};
+use decklink::frame::{DecklinkPixelFormat, DecklinkVideoFrame};
+use decklink::mock::{MockBackend, MockDevice, MockFrame, MockInput};
+use decklink::retention::{RetainedFrame, RetentionBudget, RetentionLimit, RetentionMode};
+use decklink::{ApiVersion, SdkError};
+use std::ffi::c_void;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const FORMAT: DecklinkPixelFormat = DecklinkPixelFormat::Format8BitYUV;
+/// The size of each delivered frame, 48 pixels by 2 rows.
+const FRAME_BYTES: usize = 96 * 2;
+
+/// Retains every frame it is given.
+struct Retaining {
+    budget: RetentionBudget,
+    retained: Mutex<Vec<RetainedFrame>>,
+}
+
+impl Retaining {
+    fn take(&self) -> RetainedFrame {
+        self.retained.lock().unwrap().remove(0)
+    }
+}
+
+impl DeckLinkInputCallback for Retaining {
+    fn video_input_format_changed(
+        &self,
+        _events: DecklinkVideoInputFormatChangedEvents,
+        _new_display_mode: DecklinkDisplayModeId,
+        _detected_signal_flags: DecklinkDetectedVideoInputFormatFlags,
+    ) {
+    }
+
+    fn video_input_frame_arrived(&self, video_frame: Option<DecklinkVideoFrame>) -> bool {
+        let frame = self.budget.retain(video_frame.unwrap()).unwrap();
+        self.retained.lock().unwrap().push(frame);
+        true
+    }
+}
+
+fn start(budget: &RetentionBudget) -> (DecklinkInputDevice, Arc<Retaining>) {
+    let retaining = Arc::new(Retaining {
+        budget: budget.clone(),
+        retained: Mutex::new(Vec::new()),
+    });
+    let mut input = common::open_input(
+        DecklinkDisplayModeId::HD1080p25,
+        FORMAT,
+        DecklinkVideoInputFlags::empty(),
+    );
+    common::start_streams(&mut input, retaining.clone());
+    (input, retaining)
+}
+
+fn budget(config: ExternalUseConfig) -> RetentionBudget {
+    let budget = RetentionBudget::new(RetentionLimit::Frames(8), RetentionMode::Strict);
+    budget.set_external_use_config(config);
+    budget
+}
+
+fn deliver(mock: &MockInput, value: u8) {
+    assert!(mock
+        .deliver_frame(MockFrame::new(48, 2, FORMAT).fill(value))
+        .is_ok());
+}
+
+/// The contents of the buffer of `external`.
+fn contents(external: &ExternalFrameRef) -> Vec<u8> {
+    unsafe { std::slice::from_raw_parts(external.ptr, external.len) }.to_vec()
+}
+
+/// Wait for the events of `budget` to include a reclaim, giving every event up to it.
+fn wait_for_reclaim(budget: &RetentionBudget) -> Vec<ExternalUseEvent> {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    let mut events = Vec::new();
+    while Instant::now() < deadline {
+        events.extend(budget.take_external_use_events());
+        if events
+            .iter()
+            .any(|e| matches!(e, ExternalUseEvent::Reclaimed { .. }))
+        {
+            return events;
+        }
+        std::thread::sleep(Duration::from_millis(5));
+    }
+    panic!("no buffer was reclaimed: {events:?}");
+}
+
+#[test]
+fn tokens_are_send() {
+    fn is_send<T: Send>() {}
+    is_send::<CompletionToken>();
+    is_send::<ExternalFrameRef>();
+}
+
+#[test]
+fn a_completed_buffer_is_released_once() {
+    let backend = common::mini_recorder();
+    let budget = budget(ExternalUseConfig::default());
+    let (_input, retaining) = start(&budget);
+    let mock = backend.input(0);
+    deliver(&mock, 1);
+    deliver(&mock, 2);
+
+    let (first, first_token) = retaining.take().into_external_use().unwrap();
+    let (second, second_token) = retaining.take().into_external_use().unwrap();
+    assert_eq!((first.sequence, second.sequence), (0, 1));
+    assert_eq!(first_token.sequence(), 0);
+    assert_eq!(first.len, FRAME_BYTES);
+    assert_eq!(
+        first.spec,
+        BufferSpec {
+            buffer_size: FRAME_BYTES as u32,
+            width: 48,
+            height: 2,
+            row_bytes: 96,
+            pixel_format: FORMAT as u32,
+        }
+    );
+    assert!(contents(&first).iter().all(|b| *b == 1));
+    assert!(contents(&second).iter().all(|b| *b == 2));
+
+    // Frames in external use stay charged to the budget
+    let stats = budget.stats();
+    assert_eq!((stats.retained_frames, stats.external_frames), (2, 2));
+
+    // Completed from the thread of the consumer
+    let outcome = std::thread::spawn(move || first_token.complete())
+        .join()
+        .unwrap();
+    assert_eq!(outcome, CompletionOutcome::Completed);
+    assert_eq!(second_token.complete(), CompletionOutcome::Completed);
+    assert_eq!(second_token.complete(), CompletionOutcome::AlreadyCompleted);
+    drop(second_token);
+
+    let stats = budget.stats();
+    assert_eq!((stats.retained_frames, stats.external_frames), (0, 0));
+    assert_eq!((stats.leaked_count, stats.reclaimed_count), (0, 0));
+    let sequences: Vec<_> = budget
+        .take_external_use_events()
+        .into_iter()
+        .map(|event| match event {
+            ExternalUseEvent::Completed { sequence, .. } => sequence,
+            event => panic!("unexpected {event:?}"),
+        })
+        .collect();
+    assert_eq!(sequences, [0, 1]);
+}
+
+#[test]
+fn a_dropped_token_is_reclaimed_after_the_grace() {
+    let backend = common::mini_recorder();
+    let grace = Duration::from_millis(50);
+    let budget = budget(ExternalUseConfig {
+        leak_grace: grace,
+        hold_timeout: None,
+    });
+    let (_input, retaining) = start(&budget);
+    deliver(&backend.input(0), 1);
+
+    let (external, token) = retaining.take().into_external_use().unwrap();
+    let dropped = Instant::now();
+    drop(token);
+    // Still valid during the grace
+    assert!(contents(&external).iter().all(|b| *b == 1));
+    let stats = budget.stats();
+    assert_eq!((stats.external_frames, stats.leaked_count), (1, 1));
+
+    let events = wait_for_reclaim(&budget);
+    assert!(dropped.elapsed() >= grace);
+    assert_eq!(
+        events[0],
+        ExternalUseEvent::Leaked {
+            sequence: 0,
+            reclaim_after: grace,
+        }
+    );
+    assert!(matches!(
+        events[1..],
+        [ExternalUseEvent::Reclaimed {
+            sequence: 0,
+            reason: ReclaimReason::Leaked,
+            ..
+        }]
+    ));
+    let stats = budget.stats();
+    assert_eq!((stats.retained_frames, stats.external_frames), (0, 0));
+    assert_eq!((stats.leaked_count, stats.reclaimed_count), (1, 1));
+}
+
+#[test]
+fn a_held_token_is_reclaimed_after_the_hold_timeout() {
+    let backend = common::mini_recorder();
+    let budget = budget(ExternalUseConfig {
+        hold_timeout: Some(Duration::from_millis(50)),
+        ..ExternalUseConfig::default()
+    });
+    let (_input, retaining) = start(&budget);
+    deliver(&backend.input(0), 1);
+
+    let (_external, token) = retaining.take().into_external_use().unwrap();
+    let events = wait_for_reclaim(&budget);
+    match events[..] {
+        [ExternalUseEvent::Reclaimed {
+            sequence: 0,
+            reason: ReclaimReason::HoldTimeout,
+            held_for,
+        }] => assert!(held_for >= Duration::from_millis(50)),
+        _ => panic!("unexpected {events:?}"),
+    }
+    assert_eq!(budget.stats().retained_frames, 0);
+
+    // Completing afterwards is reported as late, and dropping the token is not a leak
+    assert_eq!(
+        token.complete(),
+        CompletionOutcome::Reclaimed(ReclaimReason::HoldTimeout)
+    );
+    drop(token);
+    assert_eq!(
+        budget.take_external_use_events(),
+        [ExternalUseEvent::LateCompletion { sequence: 0 }]
+    );
+    let stats = budget.stats();
+    assert_eq!((stats.leaked_count, stats.reclaimed_count), (0, 1));
+}
+
+/// Counts the buffers it allocates that are still alive.
+#[derive(Default)]
+struct Counting {
+    live: Arc<AtomicUsize>,
+}
+
+struct CountedBuffer {
+    bytes: Mutex<Vec<u8>>,
+    live: Arc<AtomicUsize>,
+}
+
+impl VideoBuffer for CountedBuffer {
+    fn get_bytes(&self) -> Result<*mut c_void, SdkError> {
+        Ok(self.bytes.lock().unwrap().as_mut_ptr() as *mut c_void)
+    }
+}
+
+impl Drop for CountedBuffer {
+    fn drop(&mut self) {
+        self.live.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+struct CountedAllocator {
+    size: usize,
+    live: Arc<AtomicUsize>,
+}
+
+impl VideoBufferAllocator for CountedAllocator {
+    fn allocate(&self) -> Result<Box<dyn VideoBuffer>, SdkError> {
+        self.live.fetch_add(1, Ordering::SeqCst);
+        Ok(Box::new(CountedBuffer {
+            bytes: Mutex::new(vec![0; self.size]),
+            live: self.live.clone(),
+        }))
+    }
+}
+
+impl VideoBufferAllocatorProvider for Counting {
+    fn get_allocator(&self, spec: BufferSpec) -> Result<Arc<dyn VideoBufferAllocator>, SdkError> {
+        Ok(Arc::new(CountedAllocator {
+            size: spec.buffer_size as usize,
+            live: self.live.clone(),
+        }))
+    }
+}
+
+#[test]
+fn provider_teardown_defers_to_outstanding_tokens() {
+    let backend = MockBackend::install(vec![MockDevice::new("DeckLink Duo 2 (1)")]);
+    backend.set_api_version(Some(ApiVersion::new(14, 3, 0)));
+    let budget = budget(ExternalUseConfig::default());
+    let provider = Arc::new(Counting::default());
+    let live = provider.live.clone();
+
+    let mut input = get_devices().unwrap()[0].input().unwrap();
+    let retaining = Arc::new(Retaining {
+        budget: budget.clone(),
+        retained: Mutex::new(Vec::new()),
+    });
+    input
+        .enable_video_input_with_allocator(
+            DecklinkDisplayModeId::NTSC,
+            FORMAT,
+            DecklinkVideoInputFlags::empty(),
+            provider,
+        )
+        .unwrap();
+    input.set_callback(Some(retaining.clone())).unwrap();
+    input.start_streams().unwrap();
+    deliver(&backend.input(0), 7);
+    let (external, token) = retaining.take().into_external_use().unwrap();
+    assert_eq!(live.load(Ordering::SeqCst), 1);
+
+    // Tearing the input and its provider down leaves the buffer to its token
+    input.stop_streams().unwrap();
+    input.disable_video_input().unwrap();
+    drop(input);
+    drop(retaining);
+    assert_eq!(live.load(Ordering::SeqCst), 1);
+    assert!(contents(&external).iter().all(|b| *b == 7));
+
+    assert_eq!(token.complete(), CompletionOutcome::Completed);
+    assert_eq!(live.load(Ordering::SeqCst), 0);
+}
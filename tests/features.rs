@@ -0,0 +1,65 @@
+//! The feature flags, and the types shared between them.
+
+use decklink::colorimetry::Colorimetry;
+
+const MANIFEST: &str = include_str!("../Cargo.toml");
+const CHECK_FEATURES: &str = include_str!("../check-features.sh");
+const README: &str = include_str!("../README.md");
+
+/// The features declared in the manifest, with what each enables.
+fn features() -> Vec<(&'static str, &'static str)> {
+    let start = MANIFEST.find("[features]\n").unwrap() + "[features]\n".len();
+    let end = start + MANIFEST[start..].find("\n[").unwrap();
+    MANIFEST[start..end]
+        .lines()
+        .filter(|line| !line.starts_with('#') && !line.is_empty())
+        .map(|line| line.split_once(" = ").unwrap())
+        .collect()
+}
+
+#[test]
+fn the_default_build_has_no_optional_dependencies() {
+    assert!(features().contains(&("default", "[]")));
+}
+
+#[test]
+fn every_feature_is_checked_and_documented() {
+    let checked = CHECK_FEATURES
+        .lines()
+        .find_map(|line| line.strip_prefix("FEATURES=("))
+        .unwrap()
+        .trim_end_matches(')');
+    let checked: Vec<&str> = checked.split(' ').collect();
+
+    for (feature, _) in features() {
+        if feature == "default" {
+            continue;
+        }
+        // cuda needs the CUDA toolkit, so it is only checked with --with-cuda
+        assert!(
+            feature == "cuda" || checked.contains(&feature),
+            "check-features.sh does not check {}",
+            feature
+        );
+        assert!(
+            README.contains(&format!("* `{}`", feature)),
+            "the README does not describe {}",
+            feature
+        );
+    }
+}
+
+#[test]
+fn colorimetry_is_part_of_the_core() {
+    assert_eq!(Colorimetry::for_height(486), Colorimetry::Rec601);
+    assert_eq!(Colorimetry::for_height(576), Colorimetry::Rec601);
+    assert_eq!(Colorimetry::for_height(720), Colorimetry::Rec709);
+    assert_eq!(Colorimetry::Rec709.coefficients(), (0.2126, 0.0722));
+}
+
+#[cfg(feature = "image-interop")]
+#[test]
+fn image_interop_shares_the_core_colorimetry() {
+    let colorimetry: Colorimetry = decklink::image_interop::Colorimetry::Rec2020;
+    assert_eq!(colorimetry.coefficients(), (0.2627, 0.0593));
+}
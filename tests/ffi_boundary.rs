@@ -0,0 +1,197 @@
+//! The SDK is only called through the pointer types of the crate, which own the references to
+//! its objects. No other module calls it, and the mock backend checks that the pointer types take
+//! and release as many references as before.
+//!
+//! The tests only call into the mock backend, which is written in Rust, so they also run under
+//! Miri: `cargo +nightly miri test --features mock-backend,leak-check --test ffi_boundary`, with
+//! `MIRIFLAGS=-Zmiri-disable-isolation` for the test that reads the sources.
+
+use std::path::{Path, PathBuf};
+
+/// The modules that own the calls, relative to `src`.
+const POINTER_MODULES: [&str; 2] = ["ptr.rs", "sdk.rs"];
+
+/// The Rust files under `dir`.
+fn sources(dir: &Path, out: &mut Vec<PathBuf>) {
+    for entry in std::fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            sources(&path, out);
+        } else if path.extension().is_some_and(|ext| ext == "rs") {
+            out.push(path);
+        }
+    }
+}
+
+/// Whether `line` of code calls a function of the SDK, ignoring comments. Naming one of its
+/// types is not a call.
+fn calls_sdk(line: &str) -> bool {
+    let code = line.split("//").next().unwrap();
+    code.match_indices("sdk::cdecklink_").any(|(at, name)| {
+        code[at + name.len()..]
+            .trim_start_matches(|c: char| c.is_alphanumeric() || c == '_')
+            .trim_start()
+            .starts_with('(')
+    })
+}
+
+#[test]
+fn the_sdk_is_only_called_through_the_pointer_types() {
+    let src = Path::new(env!("CARGO_MANIFEST_DIR")).join("src");
+    let mut paths = Vec::new();
+    sources(&src, &mut paths);
+    assert!(!paths.is_empty());
+
+    let mut calls = Vec::new();
+    for path in paths {
+        let module = path
+            .strip_prefix(&src)
+            .unwrap()
+            .to_string_lossy()
+            .replace('\\', "/");
+        if POINTER_MODULES.contains(&module.as_str()) {
+            continue;
+        }
+        let text = std::fs::read_to_string(&path).unwrap();
+        calls.extend(
+            text.lines()
+                .enumerate()
+                .filter(|(_, line)| calls_sdk(line))
+                .map(|(number, _)| format!("src/{}:{}", module, number + 1)),
+        );
+    }
+
+    assert!(
+        calls.is_empty(),
+        "the SDK is called outside the pointer types of `ptr`:\n{}",
+        calls.join("\n")
+    );
+}
+
+#[test]
+fn calls_are_told_from_types_and_comments() {
+    assert!(calls_sdk(
+        "    let width = unsafe { sdk::cdecklink_display_mode_get_width(self.mode) };"
+    ));
+    assert!(calls_sdk("        sdk::cdecklink_input_release ("));
+    assert!(!calls_sdk("    mode: *mut sdk::cdecklink_display_mode_t,"));
+    assert!(!calls_sdk("// sdk::cdecklink_display_mode_release(mode)"));
+}
+
+#[cfg(feature = "mock-backend")]
+mod mock {
+    use decklink::device::get_devices;
+    use decklink::device::input::{
+        CallbackResult, DecklinkVideoInputFlags, FrameArrival, InputHandler,
+    };
+    use decklink::device::DecklinkDeviceDisplayModes;
+    use decklink::display_mode::DecklinkDisplayModeId;
+    use decklink::frame::DecklinkPixelFormat;
+    use decklink::mock::{MockBackend, MockDevice, MockFrame};
+    use decklink::timecode::{DecklinkTimecode, DecklinkTimecodeFlags, DecklinkTimecodeFormat};
+    use decklink::vpid::DecklinkVpidExt;
+    use std::sync::{Arc, Mutex};
+
+    const MODES: [DecklinkDisplayModeId; 3] = [
+        DecklinkDisplayModeId::HD1080i50,
+        DecklinkDisplayModeId::HD1080p25,
+        DecklinkDisplayModeId::HD720p50,
+    ];
+    const FORMAT: DecklinkPixelFormat = DecklinkPixelFormat::Format8BitYUV;
+
+    fn install() -> MockBackend {
+        MockBackend::install(vec![MockDevice::new("DeckLink SDI 4K").modes(&MODES)])
+    }
+
+    #[test]
+    fn cloned_display_modes_hold_their_own_reference() {
+        let _backend = install();
+        let devices = get_devices().unwrap();
+        let modes = devices[0].input().unwrap().display_modes().unwrap();
+        let ids: Vec<_> = modes.iter().map(|m| m.mode()).collect();
+        assert_eq!(ids, MODES);
+
+        let clones = modes.clone();
+        #[cfg(feature = "leak-check")]
+        assert!(decklink::debug::live_objects().contains(&("DecklinkDisplayMode", 6)));
+
+        // Each clone outlives the mode it was cloned from
+        let names: Vec<_> = modes.iter().map(|m| m.name()).collect();
+        drop(modes);
+        assert_eq!(clones.iter().map(|m| m.name()).collect::<Vec<_>>(), names);
+        assert_eq!((clones[1].width(), clones[1].height()), (1920, 1080));
+        #[cfg(feature = "leak-check")]
+        assert!(decklink::debug::live_objects().contains(&("DecklinkDisplayMode", 3)));
+
+        drop(clones);
+        drop(devices);
+        assert_eq!(MockBackend::live_objects(), 0);
+        #[cfg(feature = "leak-check")]
+        assert!(decklink::debug::assert_no_leaks().is_ok());
+    }
+
+    type Read = (Option<DecklinkTimecode>, Option<[u8; 4]>);
+
+    /// Reads the timecode and payload identifier of every frame.
+    #[derive(Default)]
+    struct Reader {
+        read: Mutex<Vec<Read>>,
+    }
+
+    impl InputHandler for Reader {
+        fn frame_arrived(&self, arrival: &FrameArrival<'_>) -> CallbackResult {
+            if let Some(frame) = arrival.video_frame {
+                let timecode = frame.timecode(DecklinkTimecodeFormat::RP188LTC).unwrap();
+                let vpid = frame.vpid().unwrap().map(|v| v.bytes);
+                self.read.lock().unwrap().push((timecode, vpid));
+            }
+            CallbackResult::Ok
+        }
+    }
+
+    #[test]
+    fn objects_read_from_frames_are_released() {
+        let backend = install();
+        let devices = get_devices().unwrap();
+        let mut input = devices[0].input().unwrap();
+        let reader = Arc::new(Reader::default());
+        input.set_handler(Some(reader.clone())).unwrap();
+        input
+            .enable_video_input(MODES[1], FORMAT, DecklinkVideoInputFlags::empty())
+            .unwrap();
+        input.start_streams().unwrap();
+
+        let timecode = DecklinkTimecode {
+            hours: 10,
+            minutes: 0,
+            seconds: 1,
+            frames: 2,
+            flags: DecklinkTimecodeFlags::empty(),
+            user_bits: 0,
+        };
+        let mock = backend.input(0);
+        let frame = || MockFrame::for_mode(MODES[1], FORMAT);
+        assert!(mock
+            .deliver_frame(
+                frame()
+                    .timecode(DecklinkTimecodeFormat::RP188LTC, timecode)
+                    .vpid([0x85, 0xC5, 0x00, 0x01])
+            )
+            .is_ok());
+        assert!(mock.deliver_frame(frame()).is_ok());
+        input.stop_streams().unwrap();
+
+        assert_eq!(
+            *reader.read.lock().unwrap(),
+            [
+                (Some(timecode), Some([0x85, 0xC5, 0x00, 0x01])),
+                (None, None)
+            ]
+        );
+        drop(input);
+        drop(devices);
+        assert_eq!(MockBackend::live_objects(), 0);
+        #[cfg(feature = "leak-check")]
+        assert!(decklink::debug::assert_no_leaks().is_ok());
+    }
+}
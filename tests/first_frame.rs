@@ -0,0 +1,297 @@
+//! Waiting for the first frame of a mock input with `wait_first_frame`.
+#![cfg(feature = "mock-backend")]
+
+mod common;
+
+use decklink::device::input::{
+    CancellationToken, DeckLinkInputCallback, DecklinkDetectedVideoInputFormatFlags,
+    DecklinkInputDevice, DecklinkVideoInputFlags, DecklinkVideoInputFormatChangedEvents,
+    FirstFrameError, FirstFrameOptions,
+};
+use decklink::display_mode::DecklinkDisplayModeId;
+use decklink::frame::{DecklinkFrameBase, DecklinkPixelFormat, DecklinkVideoFrame};
+use decklink::mock::{MockBackend, MockFrame, MockInput};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+const MODE: DecklinkDisplayModeId = DecklinkDisplayModeId::HD1080p25;
+const FORMAT: DecklinkPixelFormat = DecklinkPixelFormat::Format8BitYUV;
+
+/// Long enough for the wait to have begun before the driver delivers anything.
+const WAIT_BEGUN: Duration = Duration::from_millis(200);
+
+/// Counts the frames a handler of the user receives.
+#[derive(Default)]
+struct Counter {
+    frames: AtomicUsize,
+    format_changes: AtomicUsize,
+}
+
+impl DeckLinkInputCallback for Counter {
+    fn video_input_format_changed(
+        &self,
+        _events: DecklinkVideoInputFormatChangedEvents,
+        _new_display_mode: DecklinkDisplayModeId,
+        _detected_signal_flags: DecklinkDetectedVideoInputFormatFlags,
+    ) {
+        self.format_changes.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn video_input_frame_arrived(&self, _video_frame: Option<DecklinkVideoFrame>) -> bool {
+        self.frames.fetch_add(1, Ordering::SeqCst);
+        true
+    }
+}
+
+fn start(backend: &MockBackend) -> (DecklinkInputDevice, MockInput) {
+    let input = common::open_input(
+        MODE,
+        FORMAT,
+        DecklinkVideoInputFlags::ENABLE_FORMAT_DETECTION,
+    );
+    input.start_streams().unwrap();
+    (input, backend.input(0))
+}
+
+/// Act as the driver on another thread once the wait has begun.
+fn drive(mock: MockInput, driver: impl FnOnce(&MockInput) + Send + 'static) -> JoinHandle<()> {
+    std::thread::spawn(move || {
+        std::thread::sleep(WAIT_BEGUN);
+        driver(&mock);
+    })
+}
+
+fn options(timeout: Duration) -> FirstFrameOptions {
+    FirstFrameOptions {
+        timeout,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn first_frame_is_returned() {
+    let backend = common::mini_recorder();
+    let (mut input, mock) = start(&backend);
+    let counter = Arc::new(Counter::default());
+    input.set_callback(Some(counter.clone())).unwrap();
+
+    let driver = drive(mock, |mock| {
+        for value in 1..=3 {
+            let frame = mock
+                .frame()
+                .fill(value)
+                .stream_time(value as i64 * 1000, 1000, 25000);
+            assert!(mock.deliver_frame(frame).is_ok());
+        }
+    });
+    let first = input
+        .wait_first_frame(options(Duration::from_secs(10)), None)
+        .unwrap();
+    driver.join().unwrap();
+
+    let expected = MockFrame::for_mode(MODE, FORMAT).fill(1);
+    assert_eq!(first.frame.width(), expected.width());
+    assert_eq!(first.frame.height(), expected.height());
+    assert_eq!(first.frame.pixel_format(), FORMAT);
+    assert_eq!(first.frame.bytes().unwrap().0, expected.data());
+    assert_eq!(first.timing.unwrap().stream_time.value, 1000);
+    assert!(first.waited >= WAIT_BEGUN / 2);
+    assert!(first.waited < Duration::from_secs(10));
+
+    // The handler of the user received every frame
+    assert_eq!(counter.frames.load(Ordering::SeqCst), 3);
+}
+
+#[test]
+fn timeout_counts_no_signal_frames() {
+    let backend = common::mini_recorder();
+    let (mut input, mock) = start(&backend);
+
+    let driver = drive(mock, |mock| {
+        // Small frames, which are quick to deliver well within the timeout
+        for _ in 0..5 {
+            let frame = MockFrame::new(48, 2, FORMAT).no_signal();
+            assert!(mock.deliver_frame(frame).is_ok());
+        }
+    });
+    let result = input.wait_first_frame(options(WAIT_BEGUN * 4), None);
+    driver.join().unwrap();
+
+    assert!(matches!(
+        result,
+        Err(FirstFrameError::Timeout {
+            no_signal_frames_seen: 5,
+            format_changes_seen: 0,
+        })
+    ));
+}
+
+#[test]
+fn no_signal_frames_can_be_accepted() {
+    let backend = common::mini_recorder();
+    let (mut input, mock) = start(&backend);
+
+    let driver = drive(mock, |mock| {
+        assert!(mock.deliver_frame(mock.frame().no_signal()).is_ok());
+    });
+    let options = FirstFrameOptions {
+        timeout: Duration::from_secs(10),
+        accept_no_signal: true,
+        ..Default::default()
+    };
+    let first = input.wait_first_frame(options, None).unwrap();
+    driver.join().unwrap();
+
+    assert!(first
+        .frame
+        .flags()
+        .contains(decklink::frame::DecklinkFrameFlags::HAS_NO_INPUT_SOURCE));
+}
+
+#[test]
+fn frames_after_a_format_change_are_skipped() {
+    let backend = common::mini_recorder();
+    let (mut input, mock) = start(&backend);
+    let counter = Arc::new(Counter::default());
+    input.set_callback(Some(counter.clone())).unwrap();
+
+    let driver = drive(mock, |mock| {
+        assert!(mock
+            .deliver_format_change(
+                DecklinkVideoInputFormatChangedEvents::DISPLAY_MODE_CHANGED,
+                DecklinkDisplayModeId::HD1080p5994,
+                DecklinkDetectedVideoInputFormatFlags::YCBCR_422,
+            )
+            .is_ok());
+        let frame = MockFrame::for_mode(DecklinkDisplayModeId::HD1080p5994, FORMAT);
+        for value in 1..=3 {
+            assert!(mock.deliver_frame(frame.clone().fill(value)).is_ok());
+        }
+    });
+    let options = FirstFrameOptions {
+        timeout: Duration::from_secs(10),
+        frames_to_skip_after_format_change: 2,
+        ..Default::default()
+    };
+    let first = input.wait_first_frame(options, None).unwrap();
+    driver.join().unwrap();
+
+    assert!(first.frame.bytes().unwrap().0.iter().all(|b| *b == 3));
+    assert_eq!(counter.format_changes.load(Ordering::SeqCst), 1);
+    assert_eq!(counter.frames.load(Ordering::SeqCst), 3);
+}
+
+#[test]
+fn a_lost_signal_starts_the_skip_over() {
+    let backend = common::mini_recorder();
+    let (mut input, mock) = start(&backend);
+
+    let driver = drive(mock, |mock| {
+        assert!(mock
+            .deliver_format_change(
+                DecklinkVideoInputFormatChangedEvents::DISPLAY_MODE_CHANGED,
+                DecklinkDisplayModeId::HD1080p5994,
+                DecklinkDetectedVideoInputFormatFlags::YCBCR_422,
+            )
+            .is_ok());
+        let frame = MockFrame::new(48, 2, FORMAT);
+        assert!(mock.deliver_frame(frame.clone().fill(1)).is_ok());
+        assert!(mock.deliver_frame(frame.clone().no_signal()).is_ok());
+        for value in 2..=4 {
+            assert!(mock.deliver_frame(frame.clone().fill(value)).is_ok());
+        }
+    });
+    let options = FirstFrameOptions {
+        timeout: Duration::from_secs(10),
+        frames_to_skip_after_format_change: 2,
+        ..Default::default()
+    };
+    let first = input.wait_first_frame(options, None).unwrap();
+    driver.join().unwrap();
+
+    // The frame without a signal came before the skip was through, so two more are skipped
+    assert!(first.frame.bytes().unwrap().0.iter().all(|b| *b == 4));
+}
+
+#[test]
+fn timeout_counts_format_changes() {
+    let backend = common::mini_recorder();
+    let (mut input, mock) = start(&backend);
+
+    let driver = drive(mock, |mock| {
+        for mode in [
+            DecklinkDisplayModeId::HD1080p5994,
+            DecklinkDisplayModeId::HD1080p25,
+        ] {
+            assert!(mock
+                .deliver_format_change(
+                    DecklinkVideoInputFormatChangedEvents::DISPLAY_MODE_CHANGED,
+                    mode,
+                    DecklinkDetectedVideoInputFormatFlags::YCBCR_422,
+                )
+                .is_ok());
+            assert!(mock.deliver_frame(mock.frame()).is_ok());
+        }
+    });
+    let options = FirstFrameOptions {
+        timeout: WAIT_BEGUN * 4,
+        frames_to_skip_after_format_change: 2,
+        ..Default::default()
+    };
+    let result = input.wait_first_frame(options, None);
+    driver.join().unwrap();
+
+    assert!(matches!(
+        result,
+        Err(FirstFrameError::Timeout {
+            no_signal_frames_seen: 0,
+            format_changes_seen: 2,
+        })
+    ));
+}
+
+#[test]
+fn wait_is_cancelled() {
+    let backend = common::mini_recorder();
+    let (mut input, _mock) = start(&backend);
+
+    let token = CancellationToken::new();
+    let canceller = {
+        let token = token.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(WAIT_BEGUN);
+            token.cancel();
+        })
+    };
+    let started = std::time::Instant::now();
+    let result = input.wait_first_frame(options(Duration::from_secs(30)), Some(&token));
+    canceller.join().unwrap();
+
+    match result {
+        Err(FirstFrameError::Cancelled(cancelled)) => assert!(cancelled.is_cancelled()),
+        _ => panic!("the wait was not cancelled"),
+    }
+    assert!(started.elapsed() < Duration::from_secs(10));
+}
+
+#[test]
+fn frames_before_the_wait_are_not_returned() {
+    let backend = common::mini_recorder();
+    let (mut input, mock) = start(&backend);
+    let counter = Arc::new(Counter::default());
+    input.set_callback(Some(counter.clone())).unwrap();
+
+    assert!(mock.deliver_frame(mock.frame().fill(1)).is_ok());
+    let driver = drive(mock, |mock| {
+        assert!(mock.deliver_frame(mock.frame().fill(2)).is_ok());
+    });
+    let first = input
+        .wait_first_frame(options(Duration::from_secs(10)), None)
+        .unwrap();
+    driver.join().unwrap();
+
+    assert!(first.frame.bytes().unwrap().0.iter().all(|b| *b == 2));
+    assert_eq!(counter.frames.load(Ordering::SeqCst), 2);
+}
@@ -0,0 +1,346 @@
+//! Deciding when format changes re-enable the input, with and without quirk rules, over
+//! replayed sessions of problem signals.
+
+#[cfg(feature = "mock-backend")]
+mod common;
+
+use decklink::device::input::{
+    DecklinkDetectedVideoInputFormatFlags, DecklinkVideoInputFormatChangedEvents,
+};
+use decklink::display_mode::DecklinkDisplayModeId;
+use decklink::experimental::quirks::{
+    builtin_quirks, QuirkAction, QuirkCondition, QuirkPolicy, QuirkRule,
+};
+use decklink::format_detect::FormatDetector;
+use decklink::replay::{
+    replay, RecordedEvent, ReplayPace, SessionEvent, SessionReader, SessionWriter,
+};
+use std::time::Duration;
+
+use DecklinkDisplayModeId::{HD1080i5994, HD1080p24, HD1080p25, HD1080p2997, HD1080p5994};
+
+const FLAGS: DecklinkDetectedVideoInputFormatFlags =
+    DecklinkDetectedVideoInputFormatFlags::YCBCR_422
+        .union(DecklinkDetectedVideoInputFormatFlags::BIT_DEPTH_10);
+
+/// A session of the signal changing to each mode after the given number of frames, then
+/// `tail` frames more.
+fn session(changes: &[(u32, DecklinkDisplayModeId)], tail: u32) -> Vec<u8> {
+    let mut events = Vec::new();
+    let frames = |count| {
+        (0..count).map(|_| SessionEvent::Frame {
+            frame: None,
+            timing: None,
+        })
+    };
+    for (count, mode) in changes {
+        events.extend(frames(*count));
+        events.push(SessionEvent::FormatChanged {
+            events: DecklinkVideoInputFormatChangedEvents::DISPLAY_MODE_CHANGED,
+            display_mode: *mode,
+            detected_flags: FLAGS,
+        });
+    }
+    events.extend(frames(tail));
+
+    let mut writer = SessionWriter::new(Vec::new()).unwrap();
+    for event in events {
+        let event = RecordedEvent {
+            delta: Duration::from_millis(20),
+            event,
+        };
+        writer.write_event(&event).unwrap();
+    }
+    writer.into_inner()
+}
+
+/// Replay `session` through `detector`, giving the frame count and mode of each decision.
+fn decisions(detector: &mut FormatDetector, session: &[u8]) -> Vec<(u64, DecklinkDisplayModeId)> {
+    let mut decisions = Vec::new();
+    let reader = SessionReader::new(session).unwrap();
+    replay(reader, ReplayPace::AsFastAsPossible, |event| {
+        if let Some(decision) = detector.replay_event(&event.event) {
+            assert_eq!(decision.detected_flags, FLAGS);
+            decisions.push((detector.frame_count(), decision.mode));
+        }
+    })
+    .unwrap();
+    decisions
+}
+
+fn detector(
+    debounce_frames: u32,
+    policy: &QuirkPolicy,
+    mode: DecklinkDisplayModeId,
+) -> FormatDetector {
+    let mut detector = FormatDetector::new(debounce_frames, policy);
+    detector.enabled(mode, FLAGS);
+    detector
+}
+
+fn applied(detector: &mut FormatDetector) -> Vec<String> {
+    detector
+        .take_applied()
+        .into_iter()
+        .map(|a| a.rule)
+        .collect()
+}
+
+#[test]
+fn clean_changes_are_decided_after_the_debounce() {
+    let session = session(&[(200, HD1080p5994)], 10);
+
+    let mut now = detector(0, &QuirkPolicy::BuiltIn, HD1080p25);
+    assert_eq!(decisions(&mut now, &session), [(200, HD1080p5994)]);
+    let mut debounced = detector(4, &QuirkPolicy::BuiltIn, HD1080p25);
+    assert_eq!(decisions(&mut debounced, &session), [(204, HD1080p5994)]);
+    // The built-in rules leave a signal that changes cleanly alone
+    assert!(applied(&mut debounced).is_empty());
+    assert!(!debounced.is_pending());
+}
+
+#[test]
+fn changes_back_to_the_enabled_mode_are_dropped() {
+    let session = session(&[(200, HD1080p5994), (2, HD1080p25)], 10);
+
+    let mut detector = detector(4, &QuirkPolicy::Off, HD1080p25);
+    assert!(decisions(&mut detector, &session).is_empty());
+    assert!(!detector.is_pending());
+}
+
+#[test]
+fn psf_detected_as_interlaced_is_kept_progressive() {
+    // A 1080p29.97 PsF signal captured as progressive, then detected as interlaced
+    let session = session(&[(200, HD1080i5994)], 10);
+
+    let mut off = detector(0, &QuirkPolicy::Off, HD1080p2997);
+    assert_eq!(decisions(&mut off, &session), [(200, HD1080i5994)]);
+
+    let mut builtin = detector(0, &QuirkPolicy::BuiltIn, HD1080p2997);
+    assert!(decisions(&mut builtin, &session).is_empty());
+    let fired = builtin.take_applied();
+    assert_eq!(fired.len(), 1);
+    assert_eq!(fired[0].rule, "psf-detected-as-interlaced");
+    assert_eq!(fired[0].action, QuirkAction::ForcePsf);
+    assert_eq!((fired[0].frame, fired[0].detected_mode), (200, HD1080i5994));
+}
+
+#[test]
+fn psf_oscillation_settles_on_the_progressive_mode() {
+    // Captured in the wrong mode, the signal alternates between its two readings
+    let session = session(&[(5, HD1080i5994), (3, HD1080p2997), (3, HD1080i5994)], 40);
+
+    let mut off = detector(0, &QuirkPolicy::Off, HD1080p25);
+    assert_eq!(
+        decisions(&mut off, &session),
+        [(5, HD1080i5994), (8, HD1080p2997), (11, HD1080i5994)]
+    );
+
+    // The first reading is followed, then the pair is recognised, and the signal is captured
+    // as PsF once it stops flapping, ending in the progressive mode
+    let mut builtin = detector(0, &QuirkPolicy::BuiltIn, HD1080p25);
+    assert_eq!(
+        decisions(&mut builtin, &session),
+        [(5, HD1080i5994), (41, HD1080p2997)]
+    );
+    assert_eq!(
+        applied(&mut builtin),
+        [
+            "psf-detected-as-interlaced",
+            "flapping-at-start",
+            "psf-detected-as-interlaced",
+            "flapping-at-start",
+            "repeated-transitions",
+        ]
+    );
+}
+
+#[test]
+fn flapping_at_start_is_waited_out() {
+    // The mode flaps twice as recording starts, before settling back
+    let session = session(&[(2, HD1080p5994), (8, HD1080p24), (8, HD1080p25)], 60);
+
+    let mut off = detector(5, &QuirkPolicy::Off, HD1080p25);
+    assert_eq!(
+        decisions(&mut off, &session),
+        [(7, HD1080p5994), (15, HD1080p24), (23, HD1080p25)]
+    );
+
+    let mut builtin = detector(5, &QuirkPolicy::BuiltIn, HD1080p25);
+    assert_eq!(
+        decisions(&mut builtin, &session),
+        [(7, HD1080p5994), (48, HD1080p25)]
+    );
+    assert_eq!(
+        applied(&mut builtin),
+        [
+            "flapping-at-start",
+            "flapping-at-start",
+            "repeated-transitions"
+        ]
+    );
+}
+
+#[test]
+fn custom_rules_follow_the_built_in_ones() {
+    let custom = QuirkRule::new(
+        "slow-lock",
+        QuirkCondition::DetectedAs {
+            mode: HD1080p5994,
+            flags: DecklinkDetectedVideoInputFormatFlags::BIT_DEPTH_10,
+        },
+        QuirkAction::ExtendDebounce { frames: 50 },
+    );
+    let policy = QuirkPolicy::BuiltInPlus(vec![custom.clone()]);
+    let mut rules = builtin_quirks();
+    rules.push(custom);
+    assert_eq!(policy.rules(), rules);
+    assert!(QuirkPolicy::Off.rules().is_empty());
+    assert_eq!(QuirkPolicy::default(), QuirkPolicy::BuiltIn);
+
+    let session = session(&[(200, HD1080p5994)], 60);
+    let mut detector = detector(0, &policy, HD1080p25);
+    assert_eq!(decisions(&mut detector, &session), [(250, HD1080p5994)]);
+    assert_eq!(applied(&mut detector), ["slow-lock"]);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn rules_round_trip_through_serde() {
+    let policy = QuirkPolicy::BuiltInPlus(builtin_quirks());
+    let json = serde_json::to_string(&policy).unwrap();
+    assert_eq!(serde_json::from_str::<QuirkPolicy>(&json).unwrap(), policy);
+
+    let rule: QuirkRule = serde_json::from_str(
+        r#"{
+            "name": "slow-lock",
+            "condition": { "DetectedAs": { "mode": "HD1080p5994", "flags": "BIT_DEPTH_10" } },
+            "action": { "ExtendDebounce": { "frames": 50 } }
+        }"#,
+    )
+    .unwrap();
+    assert_eq!(
+        rule.condition,
+        QuirkCondition::DetectedAs {
+            mode: HD1080p5994,
+            flags: DecklinkDetectedVideoInputFormatFlags::BIT_DEPTH_10,
+        }
+    );
+}
+
+#[cfg(feature = "mock-backend")]
+mod mock {
+    use super::common;
+    use super::*;
+    use decklink::device::input::{
+        DeckLinkInputCallback, DecklinkInputDevice, DecklinkVideoInputFlags, InputHandler,
+    };
+    use decklink::format_detect::FormatDecision;
+    use decklink::frame::{DecklinkPixelFormat, DecklinkVideoFrame};
+    use decklink::mock::{MockBackend, MockFrame, MockInput};
+    use decklink::replay::SessionRecorder;
+    use std::io::{self, Write};
+    use std::sync::{Arc, Mutex};
+
+    const FORMAT: DecklinkPixelFormat = DecklinkPixelFormat::Format10BitYUV;
+
+    /// A detector followed live, with the decisions it made.
+    struct Live {
+        detector: Mutex<FormatDetector>,
+        decisions: Mutex<Vec<(u64, DecklinkDisplayModeId)>>,
+    }
+
+    impl Live {
+        fn note(&self, detector: &FormatDetector, decision: Option<FormatDecision>) {
+            if let Some(decision) = decision {
+                let decided = (detector.frame_count(), decision.mode);
+                self.decisions.lock().unwrap().push(decided);
+            }
+        }
+    }
+
+    impl DeckLinkInputCallback for Live {
+        fn video_input_format_changed(
+            &self,
+            events: DecklinkVideoInputFormatChangedEvents,
+            new_display_mode: DecklinkDisplayModeId,
+            detected_signal_flags: DecklinkDetectedVideoInputFormatFlags,
+        ) {
+            let mut detector = self.detector.lock().unwrap();
+            let decision = detector.format_changed(events, new_display_mode, detected_signal_flags);
+            self.note(&detector, decision);
+        }
+
+        fn video_input_frame_arrived(&self, _video_frame: Option<DecklinkVideoFrame>) -> bool {
+            let mut detector = self.detector.lock().unwrap();
+            let decision = detector.frame();
+            self.note(&detector, decision);
+            true
+        }
+    }
+
+    /// A session file the test can read once recording is done.
+    #[derive(Clone, Default)]
+    struct SharedFile(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedFile {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn start(
+        backend: &MockBackend,
+        callback: Arc<dyn InputHandler>,
+    ) -> (DecklinkInputDevice, MockInput) {
+        let mut input = common::open_input(
+            HD1080p25,
+            FORMAT,
+            DecklinkVideoInputFlags::ENABLE_FORMAT_DETECTION,
+        );
+        common::start_streams(&mut input, callback);
+        (input, backend.input(0))
+    }
+
+    #[test]
+    fn a_recorded_capture_replays_to_the_live_decisions() {
+        let backend = common::mini_recorder();
+        let live = Arc::new(Live {
+            detector: Mutex::new(detector(5, &QuirkPolicy::BuiltIn, HD1080p25)),
+            decisions: Mutex::new(Vec::new()),
+        });
+        let file = SharedFile::default();
+        let recorder = Arc::new(SessionRecorder::new(file.clone(), Some(live.clone())).unwrap());
+        let (_input, mock) = start(&backend, recorder.clone());
+
+        // A camera flapping as it starts recording
+        let frames = |count| {
+            for _ in 0..count {
+                assert!(mock.deliver_frame(MockFrame::new(48, 2, FORMAT)).is_ok());
+            }
+        };
+        for (count, mode) in [(2, HD1080p5994), (8, HD1080p24), (8, HD1080p25)] {
+            frames(count);
+            assert!(mock
+                .deliver_format_change(
+                    DecklinkVideoInputFormatChangedEvents::DISPLAY_MODE_CHANGED,
+                    mode,
+                    FLAGS,
+                )
+                .is_ok());
+        }
+        frames(60);
+        recorder.flush().unwrap();
+        assert!(recorder.take_error().is_none());
+
+        let live = live.decisions.lock().unwrap().clone();
+        assert_eq!(live, [(7, HD1080p5994), (48, HD1080p25)]);
+        let session = file.0.lock().unwrap().clone();
+        let mut replayed = detector(5, &QuirkPolicy::BuiltIn, HD1080p25);
+        assert_eq!(decisions(&mut replayed, &session), live);
+    }
+}
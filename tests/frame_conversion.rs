@@ -0,0 +1,121 @@
+//! Captured frames a mock driver fails to convert for reading.
+#![cfg(feature = "mock-backend")]
+
+mod common;
+
+use decklink::device::input::{
+    DeckLinkInputCallback, DecklinkDetectedVideoInputFormatFlags, DecklinkInputDevice,
+    DecklinkVideoInputFlags, DecklinkVideoInputFormatChangedEvents, FrameConversionFailure,
+    InputHandler,
+};
+use decklink::display_mode::DecklinkDisplayModeId;
+use decklink::frame::{DecklinkFrameBase, DecklinkPixelFormat, DecklinkVideoFrame};
+use decklink::mock::{MockBackend, MockFrame, MockInput};
+use decklink::tap::TapSplitter;
+use decklink::time::DecklinkTime;
+use std::sync::{Arc, Mutex};
+
+const MODE: DecklinkDisplayModeId = DecklinkDisplayModeId::HD1080p25;
+const FORMAT: DecklinkPixelFormat = DecklinkPixelFormat::Format8BitYUV;
+
+#[derive(PartialEq, Debug)]
+enum Arrived {
+    /// The first byte of a frame.
+    Frame(u8),
+    NoVideo,
+    /// The stream time of a frame that could not be read, in ticks of 25000 per second.
+    ConversionFailed(Option<DecklinkTime>),
+}
+
+/// Records the frame callbacks the handler receives, in order.
+#[derive(Default)]
+struct Recorder {
+    arrived: Mutex<Vec<Arrived>>,
+}
+
+impl DeckLinkInputCallback for Recorder {
+    fn video_input_format_changed(
+        &self,
+        _events: DecklinkVideoInputFormatChangedEvents,
+        _new_display_mode: DecklinkDisplayModeId,
+        _detected_signal_flags: DecklinkDetectedVideoInputFormatFlags,
+    ) {
+    }
+
+    fn video_input_frame_arrived(&self, video_frame: Option<DecklinkVideoFrame>) -> bool {
+        let arrived = match video_frame {
+            Some(frame) => Arrived::Frame(frame.bytes().unwrap().0[0]),
+            None => Arrived::NoVideo,
+        };
+        self.arrived.lock().unwrap().push(arrived);
+        true
+    }
+
+    fn video_input_frame_conversion_failed(&self, failure: FrameConversionFailure) {
+        let stream_time = failure
+            .timing
+            .and_then(|timing| timing.stream_time_in(25000));
+        self.arrived
+            .lock()
+            .unwrap()
+            .push(Arrived::ConversionFailed(stream_time));
+    }
+}
+
+fn start(
+    backend: &MockBackend,
+    callback: Arc<dyn InputHandler>,
+) -> (DecklinkInputDevice, MockInput) {
+    let mut input = common::open_input(MODE, FORMAT, DecklinkVideoInputFlags::empty());
+    common::enable_stereo_audio(&mut input);
+    common::start_streams(&mut input, callback);
+    (input, backend.input(0))
+}
+
+/// Deliver a frame, a frame that fails to convert, a callback with no video and another
+/// frame.
+fn deliver(mock: &MockInput) {
+    let frame = |n: u8| {
+        MockFrame::new(48, 2, FORMAT)
+            .fill(n)
+            .stream_time(n as i64 * 1000, 1000, 25000)
+    };
+    assert!(mock.deliver_frame(frame(1)).is_ok());
+    assert!(mock.deliver_frame(frame(2).conversion_fails()).is_ok());
+    assert!(mock.deliver_audio(&[]).is_ok());
+    assert!(mock.deliver_frame(frame(3)).is_ok());
+}
+
+const EXPECTED: [Arrived; 4] = [
+    Arrived::Frame(1),
+    Arrived::ConversionFailed(Some(DecklinkTime {
+        value: 2000,
+        scale: 25000,
+    })),
+    Arrived::NoVideo,
+    Arrived::Frame(3),
+];
+
+#[test]
+fn a_failed_conversion_is_reported_rather_than_passed_on_as_no_video() {
+    let backend = common::mini_recorder();
+    let recorder = Arc::new(Recorder::default());
+    let (input, mock) = start(&backend, recorder.clone());
+    assert_eq!(input.frame_conversion_failure_count(), 0);
+
+    deliver(&mock);
+    assert_eq!(*recorder.arrived.lock().unwrap(), EXPECTED);
+    assert_eq!(input.frame_conversion_failure_count(), 1);
+}
+
+#[test]
+fn a_splitter_passes_a_failed_conversion_on() {
+    let backend = common::mini_recorder();
+    let recorder = Arc::new(Recorder::default());
+    let splitter = TapSplitter::new(recorder.clone(), None);
+    let (input, mock) = start(&backend, splitter.callback());
+
+    deliver(&mock);
+    assert_eq!(*recorder.arrived.lock().unwrap(), EXPECTED);
+    assert_eq!(input.frame_conversion_failure_count(), 1);
+}
@@ -0,0 +1,329 @@
+//! Frames whose size differs from that of the enabled display mode, under both policies, and
+//! the copy paths reading them by their own size.
+#![cfg(feature = "mock-backend")]
+
+mod common;
+
+use decklink::convert::convert_frame;
+use decklink::device::input::{
+    ArrivalFlags, CallbackResult, DeckLinkInputCallback, DecklinkAudioInputPacket,
+    DecklinkDetectedVideoInputFormatFlags, DecklinkInputDevice, DecklinkVideoInputFlags,
+    DecklinkVideoInputFormatChangedEvents, DimensionMismatch, DimensionPolicy, FrameArrival,
+    FrameDimensions, InputHandler,
+};
+use decklink::display_mode::DecklinkDisplayModeId;
+use decklink::frame::{
+    DecklinkFrameBase, DecklinkPixelFormat, DecklinkVideoFrame, Rect, RegionCopyError,
+};
+use decklink::mock::{MockBackend, MockFrame, MockInput};
+use std::sync::{Arc, Mutex};
+
+const MODE: DecklinkDisplayModeId = DecklinkDisplayModeId::HD1080p25;
+const FORMAT: DecklinkPixelFormat = DecklinkPixelFormat::Format8BitYUV;
+
+/// Two sample frames of 16-bit stereo.
+const SAMPLES: [u8; 8] = [1, 0, 2, 0, 3, 0, 4, 0];
+
+/// Records the flags of each callback and keeps its frame.
+#[derive(Default)]
+struct Recorder {
+    seen: Mutex<Vec<(ArrivalFlags, Option<DecklinkVideoFrame>, bool)>>,
+}
+
+impl InputHandler for Recorder {
+    fn frame_arrived(&self, arrival: &FrameArrival<'_>) -> CallbackResult {
+        self.seen.lock().unwrap().push((
+            arrival.flags,
+            arrival.retain_video_frame(),
+            arrival.audio_packet.is_some(),
+        ));
+        CallbackResult::Ok
+    }
+}
+
+impl Recorder {
+    fn flags(&self) -> Vec<ArrivalFlags> {
+        self.seen.lock().unwrap().iter().map(|s| s.0).collect()
+    }
+
+    fn sizes(&self) -> Vec<Option<(usize, usize)>> {
+        let seen = self.seen.lock().unwrap();
+        seen.iter()
+            .map(|s| s.1.as_ref().map(|f| (f.width(), f.height())))
+            .collect()
+    }
+}
+
+fn start(
+    backend: &MockBackend,
+    policy: DimensionPolicy,
+    handler: Arc<dyn InputHandler>,
+) -> (DecklinkInputDevice, MockInput) {
+    let mut input = common::open_input(MODE, FORMAT, DecklinkVideoInputFlags::empty());
+    common::enable_stereo_audio(&mut input);
+    input.set_dimension_policy(policy);
+    common::start_streams(&mut input, handler);
+    (input, backend.input(0))
+}
+
+/// A 720p frame, as a driver glitch might deliver while 1080p is enabled.
+fn small() -> MockFrame {
+    MockFrame::new(1280, 720, FORMAT).fill(7)
+}
+
+fn full() -> MockFrame {
+    MockFrame::for_mode(MODE, FORMAT)
+}
+
+fn mismatch(sequence: u64, dropped: bool) -> DimensionMismatch {
+    DimensionMismatch {
+        mode: MODE,
+        pixel_format: FORMAT,
+        expected: FrameDimensions {
+            width: 1920,
+            height: 1080,
+            row_bytes: 3840,
+        },
+        reported: FrameDimensions {
+            width: 1280,
+            height: 720,
+            row_bytes: 2560,
+        },
+        sequence,
+        dropped,
+    }
+}
+
+#[test]
+fn a_permissive_input_delivers_mismatched_frames_flagged() {
+    let backend = common::mini_recorder();
+    let recorder = Arc::new(Recorder::default());
+    let (input, mock) = start(&backend, DimensionPolicy::default(), recorder.clone());
+    assert_eq!(input.dimension_policy(), DimensionPolicy::Permissive);
+
+    assert!(mock.deliver_frame(full()).is_ok());
+    assert!(mock.deliver_frame(small()).is_ok());
+    assert!(mock.deliver_frame(full()).is_ok());
+
+    assert_eq!(
+        recorder.flags(),
+        [
+            ArrivalFlags::empty(),
+            ArrivalFlags::DIMENSION_MISMATCH,
+            ArrivalFlags::empty(),
+        ]
+    );
+    assert_eq!(
+        recorder.sizes(),
+        [Some((1920, 1080)), Some((1280, 720)), Some((1920, 1080))]
+    );
+    assert_eq!(input.dimension_mismatch_count(), 1);
+    let mismatches = input.take_dimension_mismatches();
+    assert_eq!(mismatches, [mismatch(1, false)]);
+    assert_eq!(
+        mismatches[0].to_string(),
+        "frame 1 is 1280x720 with 2560 bytes a row, but HD1080p25 in Format8BitYUV is \
+         1920x1080 with at least 3840"
+    );
+    assert!(input.take_dimension_mismatches().is_empty());
+}
+
+#[test]
+fn a_strict_input_drops_mismatched_frames_but_not_their_audio() {
+    let backend = common::mini_recorder();
+    let recorder = Arc::new(Recorder::default());
+    let (input, mock) = start(&backend, DimensionPolicy::Strict, recorder.clone());
+
+    assert!(mock.deliver_frame_with_audio(small(), &SAMPLES).is_ok());
+    assert!(mock.deliver_frame(full()).is_ok());
+
+    assert_eq!(
+        recorder.flags(),
+        [ArrivalFlags::DIMENSION_MISMATCH, ArrivalFlags::empty()]
+    );
+    assert_eq!(recorder.sizes(), [None, Some((1920, 1080))]);
+    assert!(recorder.seen.lock().unwrap()[0].2);
+    assert_eq!(input.dimension_mismatch_count(), 1);
+    assert_eq!(input.take_dimension_mismatches(), [mismatch(0, true)]);
+}
+
+#[test]
+fn rows_shorter_than_the_mode_needs_are_mismatched() {
+    let backend = common::mini_recorder();
+    let recorder = Arc::new(Recorder::default());
+    let (input, mock) = start(&backend, DimensionPolicy::Strict, recorder.clone());
+
+    // Padding past the row size is left to the row size check
+    assert!(mock.deliver_frame(full().row_bytes(4096)).is_ok());
+    assert!(mock.deliver_frame(full().row_bytes(2560)).is_ok());
+
+    assert_eq!(recorder.sizes(), [Some((1920, 1080)), None]);
+    let mismatches = input.take_dimension_mismatches();
+    assert_eq!(mismatches.len(), 1);
+    assert_eq!(mismatches[0].reported.row_bytes, 2560);
+    assert_eq!(mismatches[0].expected.row_bytes, 3840);
+}
+
+#[test]
+fn frames_are_checked_against_the_mode_of_the_last_format_change() {
+    let backend = common::mini_recorder();
+    let recorder = Arc::new(Recorder::default());
+    let (input, mock) = start(&backend, DimensionPolicy::Strict, recorder.clone());
+
+    assert!(mock
+        .deliver_format_change(
+            DecklinkVideoInputFormatChangedEvents::DISPLAY_MODE_CHANGED,
+            DecklinkDisplayModeId::HD720p50,
+            DecklinkDetectedVideoInputFormatFlags::YCBCR_422,
+        )
+        .is_ok());
+    // The first frame after the change may still be of the old mode
+    assert!(mock.deliver_frame(full()).is_ok());
+    assert!(mock.deliver_frame(small()).is_ok());
+    assert!(mock.deliver_frame(full()).is_ok());
+
+    assert_eq!(
+        recorder.flags(),
+        [
+            ArrivalFlags::AFTER_FORMAT_CHANGE,
+            ArrivalFlags::empty(),
+            ArrivalFlags::DIMENSION_MISMATCH,
+        ]
+    );
+    assert_eq!(
+        recorder.sizes(),
+        [Some((1920, 1080)), Some((1280, 720)), None]
+    );
+    let mismatches = input.take_dimension_mismatches();
+    assert_eq!(mismatches.len(), 1);
+    assert_eq!(mismatches[0].mode, DecklinkDisplayModeId::HD720p50);
+    assert_eq!(
+        mismatches[0].reported,
+        FrameDimensions {
+            width: 1920,
+            height: 1080,
+            row_bytes: 3840,
+        }
+    );
+}
+
+#[test]
+fn frames_are_not_checked_once_video_input_is_disabled() {
+    let backend = common::mini_recorder();
+    let recorder = Arc::new(Recorder::default());
+    let (mut input, mock) = start(&backend, DimensionPolicy::Strict, recorder.clone());
+
+    input.stop_streams().unwrap();
+    input.disable_video_input().unwrap();
+    input.start_streams().unwrap();
+    assert!(mock.deliver_frame(small()).is_ok());
+
+    assert_eq!(recorder.sizes(), [Some((1280, 720))]);
+    assert_eq!(input.dimension_mismatch_count(), 0);
+}
+
+/// Counts what an older callback is called with.
+#[derive(Default)]
+struct OldCallback {
+    frames: Mutex<Vec<Option<(usize, usize)>>>,
+    packets: Mutex<usize>,
+}
+
+impl DeckLinkInputCallback for OldCallback {
+    fn video_input_format_changed(
+        &self,
+        _notification_events: DecklinkVideoInputFormatChangedEvents,
+        _new_display_mode: DecklinkDisplayModeId,
+        _detected_signal_flags: DecklinkDetectedVideoInputFormatFlags,
+    ) {
+    }
+
+    fn video_input_frame_arrived(&self, video_frame: Option<DecklinkVideoFrame>) -> bool {
+        self.frames
+            .lock()
+            .unwrap()
+            .push(video_frame.map(|f| (f.width(), f.height())));
+        true
+    }
+
+    fn audio_input_packet_arrived(&self, _audio_packet: DecklinkAudioInputPacket) {
+        *self.packets.lock().unwrap() += 1;
+    }
+}
+
+#[test]
+fn an_older_callback_sees_the_audio_of_a_dropped_frame_only() {
+    let backend = common::mini_recorder();
+    let callback = Arc::new(OldCallback::default());
+    let (_input, mock) = start(&backend, DimensionPolicy::Strict, callback.clone());
+
+    assert!(mock.deliver_frame_with_audio(small(), &SAMPLES).is_ok());
+    assert!(mock.deliver_frame_with_audio(full(), &SAMPLES).is_ok());
+
+    assert_eq!(*callback.frames.lock().unwrap(), [Some((1920, 1080))]);
+    assert_eq!(*callback.packets.lock().unwrap(), 2);
+}
+
+#[test]
+fn copies_of_a_mismatched_frame_use_its_own_size() {
+    let backend = common::mini_recorder();
+    let recorder = Arc::new(Recorder::default());
+    let (_input, mock) = start(&backend, DimensionPolicy::Permissive, recorder.clone());
+    assert!(mock.deliver_frame(small()).is_ok());
+    let frame = recorder.seen.lock().unwrap()[0].1.take().unwrap();
+
+    assert_eq!(frame.bytes_to_vec().unwrap(), vec![7; 2560 * 720]);
+
+    let whole = Rect {
+        x: 0,
+        y: 0,
+        width: 1280,
+        height: 720,
+    };
+    assert_eq!(frame.copy_region_to_vec(whole).unwrap().len(), 2560 * 720);
+    // The region of the mode is past the edge of the frame
+    let mode = Rect {
+        width: 1920,
+        height: 1080,
+        ..whole
+    };
+    assert!(matches!(
+        frame.copy_region_to_vec(mode),
+        Err(RegionCopyError::OutOfBounds)
+    ));
+
+    // 8-bit YUV only converts to itself, which still copies it row by row
+    let copy = convert_frame(&frame, DecklinkPixelFormat::Format8BitYUV).unwrap();
+    assert_eq!((copy.width(), copy.height()), (1280, 720));
+    assert_eq!(copy.bytes().unwrap().0, vec![7; 2560 * 720]);
+}
+
+#[cfg(feature = "container")]
+#[test]
+fn a_recording_refuses_a_frame_of_another_size() {
+    use decklink::experimental::mov::{MovConfig, MovWriter};
+    use decklink::time::DecklinkTime;
+
+    let backend = common::mini_recorder();
+    let recorder = Arc::new(Recorder::default());
+    let (_input, mock) = start(&backend, DimensionPolicy::Permissive, recorder.clone());
+    assert!(mock.deliver_frame(full()).is_ok());
+    assert!(mock.deliver_frame(small()).is_ok());
+    assert!(mock.deliver_frame(full()).is_ok());
+    let frames: Vec<_> = std::mem::take(&mut *recorder.seen.lock().unwrap())
+        .into_iter()
+        .map(|s| s.1.unwrap())
+        .collect();
+
+    let path = std::env::temp_dir().join(format!("decklink-dimensions-{}.mov", std::process::id()));
+    let mut writer =
+        MovWriter::create(&path, MovConfig::new(DecklinkTime::new(1000, 25000))).unwrap();
+    writer.write_frame(&frames[0], None).unwrap();
+    let error = writer.write_frame(&frames[1], None).unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    writer.write_frame(&frames[2], None).unwrap();
+    let info = writer.finish().unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(info.video_frames, 2);
+}
@@ -0,0 +1,364 @@
+//! Frame timings in the timescale of the active display mode, and the time arithmetic behind
+//! them.
+
+#[cfg(feature = "mock-backend")]
+mod common;
+
+use decklink::time::{DecklinkFrameTiming, DecklinkTime};
+
+#[test]
+fn rescale_rounds_to_the_nearest_tick() {
+    // One frame of 29.97 in milliseconds is 33.37
+    let frame = DecklinkTime::new(1001, 30000);
+    assert_eq!(frame.rescale(1000), Some(DecklinkTime::new(33, 1000)));
+    assert_eq!(frame.rescale(60000), Some(DecklinkTime::new(2002, 60000)));
+    assert_eq!(
+        DecklinkTime::new(2, 3).rescale(1),
+        Some(DecklinkTime::new(1, 1))
+    );
+    assert_eq!(
+        DecklinkTime::new(-2, 3).rescale(1),
+        Some(DecklinkTime::new(-1, 1))
+    );
+    assert_eq!(frame.rescale(30000), Some(frame));
+}
+
+#[test]
+fn rescale_rounds_halves_away_from_zero() {
+    assert_eq!(
+        DecklinkTime::new(1, 2).rescale(1),
+        Some(DecklinkTime::new(1, 1))
+    );
+    assert_eq!(
+        DecklinkTime::new(-1, 2).rescale(1),
+        Some(DecklinkTime::new(-1, 1))
+    );
+    assert_eq!(
+        DecklinkTime::new(3, 2).rescale(1),
+        Some(DecklinkTime::new(2, 1))
+    );
+    assert_eq!(
+        DecklinkTime::new(-3, 2).rescale(1),
+        Some(DecklinkTime::new(-2, 1))
+    );
+}
+
+#[test]
+fn rescale_rejects_invalid_scales_and_overflow() {
+    let time = DecklinkTime::new(1000, 25000);
+    assert_eq!(time.rescale(0), None);
+    assert_eq!(time.rescale(-1000), None);
+    assert_eq!(DecklinkTime::new(1000, 0).rescale(1000), None);
+    assert_eq!(DecklinkTime::new(1000, -25000).rescale(1000), None);
+
+    assert_eq!(DecklinkTime::new(i64::MAX, 1).rescale(2), None);
+    assert_eq!(DecklinkTime::new(i64::MIN, 1).rescale(2), None);
+    // The intermediate product may exceed an i64 as long as the result does not
+    assert_eq!(
+        DecklinkTime::new(i64::MAX, 1000).rescale(500),
+        Some(DecklinkTime::new(i64::MAX / 2 + 1, 500))
+    );
+}
+
+#[test]
+fn time_in_seconds() {
+    assert_eq!(DecklinkTime::new(50000, 25000).as_secs_f64(), 2.0);
+    assert_eq!(DecklinkTime::new(-1, 4).as_secs_f64(), -0.25);
+}
+
+fn timing(duration: i64, mode_duration: i64) -> DecklinkFrameTiming {
+    DecklinkFrameTiming {
+        stream_time: DecklinkTime::new(3003, 30000),
+        duration: DecklinkTime::new(duration, 30000),
+        mode_duration: DecklinkTime::new(mode_duration, 30000),
+    }
+}
+
+#[test]
+fn duration_mismatch_allows_one_tick() {
+    assert!(!timing(1001, 1001).duration_mismatch());
+    assert!(!timing(1000, 1001).duration_mismatch());
+    assert!(!timing(1002, 1001).duration_mismatch());
+    assert!(timing(999, 1001).duration_mismatch());
+    assert!(timing(1003, 1001).duration_mismatch());
+    assert!(timing(2002, 1001).duration_mismatch());
+}
+
+#[test]
+fn stream_time_in_another_scale() {
+    let timing = timing(1001, 1001);
+    assert_eq!(
+        timing.stream_time_in(1000),
+        Some(DecklinkTime::new(100, 1000))
+    );
+    assert_eq!(timing.stream_time_in(0), None);
+}
+
+#[cfg(feature = "mock-backend")]
+mod mock {
+    use super::common;
+    use decklink::device::get_devices;
+    use decklink::device::input::{
+        CallbackResult, DeckLinkInputCallback, DecklinkDetectedVideoInputFormatFlags,
+        DecklinkInputDevice, DecklinkVideoInputFlags, DecklinkVideoInputFormatChangedEvents,
+        DecklinkVideoInputFrame, FrameArrival, InputHandler,
+    };
+    use decklink::display_mode::DecklinkDisplayModeId;
+    use decklink::frame::{DecklinkFrameBase, DecklinkPixelFormat, DecklinkVideoFrame};
+    use decklink::mock::{MockBackend, MockFrame};
+    use decklink::time::{DecklinkFrameTiming, DecklinkTime};
+    use decklink::SdkError;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    const FORMAT: DecklinkPixelFormat = DecklinkPixelFormat::Format8BitYUV;
+
+    /// Records the timing of every frame, and counts the frames.
+    #[derive(Default)]
+    struct Timings {
+        timings: Mutex<Vec<DecklinkFrameTiming>>,
+        frames: AtomicUsize,
+    }
+
+    impl Timings {
+        fn take(&self) -> Vec<DecklinkFrameTiming> {
+            std::mem::take(&mut *self.timings.lock().unwrap())
+        }
+    }
+
+    impl DeckLinkInputCallback for Timings {
+        fn video_input_format_changed(
+            &self,
+            _events: DecklinkVideoInputFormatChangedEvents,
+            _new_display_mode: DecklinkDisplayModeId,
+            _detected_signal_flags: DecklinkDetectedVideoInputFormatFlags,
+        ) {
+        }
+
+        fn video_input_frame_arrived(&self, _video_frame: Option<DecklinkVideoFrame>) -> bool {
+            self.frames.fetch_add(1, Ordering::SeqCst);
+            true
+        }
+
+        fn video_input_frame_timing(&self, timing: DecklinkFrameTiming) {
+            self.timings.lock().unwrap().push(timing);
+        }
+    }
+
+    fn start(
+        mode: DecklinkDisplayModeId,
+        flags: DecklinkVideoInputFlags,
+    ) -> (DecklinkInputDevice, Arc<Timings>) {
+        let devices = get_devices().unwrap();
+        let mut input = devices[0].input().unwrap();
+        let timings = Arc::new(Timings::default());
+        input.enable_video_input(mode, FORMAT, flags).unwrap();
+        common::start_streams(&mut input, timings.clone());
+        (input, timings)
+    }
+
+    fn at(value: i64, scale: i64) -> DecklinkTime {
+        DecklinkTime::new(value, scale)
+    }
+
+    #[test]
+    fn timing_is_in_the_timescale_of_the_mode() {
+        let backend = common::mini_recorder();
+        let (_input, timings) = start(
+            DecklinkDisplayModeId::HD1080p25,
+            DecklinkVideoInputFlags::empty(),
+        );
+        let mock = backend.input(0);
+
+        assert!(mock
+            .deliver_frame(mock.frame().stream_time(2000, 1000, 25000))
+            .is_ok());
+        // The driver converts a time kept in another scale to the one asked for
+        assert!(mock
+            .deliver_frame(mock.frame().stream_time(120, 40, 1000))
+            .is_ok());
+
+        let timings = timings.take();
+        assert_eq!(
+            timings,
+            vec![
+                DecklinkFrameTiming {
+                    stream_time: at(2000, 25000),
+                    duration: at(1000, 25000),
+                    mode_duration: at(1000, 25000),
+                },
+                DecklinkFrameTiming {
+                    stream_time: at(3000, 25000),
+                    duration: at(1000, 25000),
+                    mode_duration: at(1000, 25000),
+                },
+            ]
+        );
+        assert!(timings.iter().all(|t| !t.duration_mismatch()));
+        assert_eq!(timings[1].stream_time_in(1000), Some(at(120, 1000)));
+    }
+
+    #[test]
+    fn timing_follows_a_format_change() {
+        let backend = common::mini_recorder();
+        let (_input, timings) = start(
+            DecklinkDisplayModeId::HD1080p25,
+            DecklinkVideoInputFlags::ENABLE_FORMAT_DETECTION,
+        );
+        let mock = backend.input(0);
+
+        // A 59.94 source arrives while the 25 mode is still active
+        let frame = MockFrame::for_mode(DecklinkDisplayModeId::HD1080p25, FORMAT);
+        assert!(mock
+            .deliver_frame(frame.clone().stream_time(2002, 1001, 60000))
+            .is_ok());
+        assert!(mock
+            .deliver_format_change(
+                DecklinkVideoInputFormatChangedEvents::DISPLAY_MODE_CHANGED,
+                DecklinkDisplayModeId::HD1080p5994,
+                DecklinkDetectedVideoInputFormatFlags::empty(),
+            )
+            .is_ok());
+        assert!(mock
+            .deliver_frame(frame.stream_time(3003, 1001, 60000))
+            .is_ok());
+
+        let timings = timings.take();
+        // Before the change the time is truncated to the 25 base, and too long for the mode
+        assert_eq!(
+            timings[0],
+            DecklinkFrameTiming {
+                stream_time: at(834, 25000),
+                duration: at(417, 25000),
+                mode_duration: at(1000, 25000),
+            }
+        );
+        assert!(timings[0].duration_mismatch());
+        // After it both are in the 59.94 base
+        assert_eq!(
+            timings[1],
+            DecklinkFrameTiming {
+                stream_time: at(3003, 60000),
+                duration: at(1001, 60000),
+                mode_duration: at(1001, 60000),
+            }
+        );
+        assert!(!timings[1].duration_mismatch());
+    }
+
+    #[test]
+    fn reenabling_uses_the_new_mode() {
+        let backend = common::mini_recorder();
+        let (mut input, timings) = start(
+            DecklinkDisplayModeId::HD1080p25,
+            DecklinkVideoInputFlags::empty(),
+        );
+        let mock = backend.input(0);
+
+        input.stop_streams().unwrap();
+        input.disable_video_input().unwrap();
+        input
+            .enable_video_input(
+                DecklinkDisplayModeId::NTSC,
+                FORMAT,
+                DecklinkVideoInputFlags::empty(),
+            )
+            .unwrap();
+        input.start_streams().unwrap();
+
+        assert!(mock
+            .deliver_frame(mock.frame().stream_time(1001, 1001, 30000))
+            .is_ok());
+        assert_eq!(
+            timings.take(),
+            vec![DecklinkFrameTiming {
+                stream_time: at(1001, 30000),
+                duration: at(1001, 30000),
+                mode_duration: at(1001, 30000),
+            }]
+        );
+    }
+
+    #[test]
+    fn frame_without_stream_time_has_no_timing() {
+        let backend = common::mini_recorder();
+        let (_input, timings) = start(
+            DecklinkDisplayModeId::HD1080p25,
+            DecklinkVideoInputFlags::empty(),
+        );
+        let mock = backend.input(0);
+
+        assert!(mock.deliver_frame(mock.frame()).is_ok());
+        assert_eq!(timings.frames.load(Ordering::SeqCst), 1);
+        assert!(timings.take().is_empty());
+    }
+
+    type Times = Result<(i64, i64), SdkError>;
+
+    /// Reads the stream and hardware reference times of each captured frame, and keeps
+    /// the last.
+    #[derive(Default)]
+    struct InputFrames {
+        times: Mutex<Vec<(Times, Times)>>,
+        last: Mutex<Option<DecklinkVideoInputFrame>>,
+    }
+
+    impl InputHandler for InputFrames {
+        fn frame_arrived(&self, arrival: &FrameArrival<'_>) -> CallbackResult {
+            if let Some(frame) = arrival.input_frame {
+                assert_eq!(frame.width(), arrival.video_frame.unwrap().width());
+                self.times.lock().unwrap().push((
+                    frame.stream_time(1000),
+                    frame.hardware_reference_timestamp(1000),
+                ));
+            }
+            *self.last.lock().unwrap() = arrival.retain_input_frame();
+            CallbackResult::Ok
+        }
+    }
+
+    #[test]
+    fn input_frames_give_their_stream_time_and_hardware_reference_timestamp() {
+        let backend = common::mini_recorder();
+        let devices = get_devices().unwrap();
+        let mut input = devices[0].input().unwrap();
+        let frames = Arc::new(InputFrames::default());
+        input
+            .enable_video_input(
+                DecklinkDisplayModeId::HD1080p25,
+                FORMAT,
+                DecklinkVideoInputFlags::empty(),
+            )
+            .unwrap();
+        common::start_streams(&mut input, frames.clone());
+
+        let mock = backend.input(0);
+        assert!(mock
+            .deliver_frame(
+                mock.frame()
+                    .stream_time(2000, 1000, 25000)
+                    .hardware_reference_timestamp(90_000_000, 3600, 90000)
+            )
+            .is_ok());
+        assert!(mock.deliver_frame(mock.frame()).is_ok());
+        input.stop_streams().unwrap();
+
+        assert_eq!(
+            *frames.times.lock().unwrap(),
+            [
+                (Ok((80, 40)), Ok((1_000_000, 40))),
+                (Err(SdkError::FAIL), Err(SdkError::FAIL))
+            ]
+        );
+        // A retained frame can still be read after the callback
+        let last = frames.last.lock().unwrap().take().unwrap();
+        assert_eq!(last.height(), 1080);
+        assert_eq!(last.video_frame().height(), 1080);
+        assert_eq!(last.stream_time(1000), Err(SdkError::FAIL));
+
+        drop(last);
+        drop(input);
+        drop(devices);
+        assert_eq!(MockBackend::live_objects(), 0);
+    }
+}
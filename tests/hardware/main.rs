@@ -0,0 +1,1088 @@
+//! End to end tests through a loopback cable, for machines with a card.
+//!
+//! The tests only touch hardware when `DECKLINK_LOOPBACK` names the devices to use, as
+//! `<output selector>:out,<input selector>:in` in the syntax of `DeviceSelector`, for example
+//! `DECKLINK_LOOPBACK="DeckLink Duo (1):out,DeckLink Duo (2):in"`. A single selector uses
+//! the output and input of one device, cabled to each other. Without it the test passes
+//! without looking for devices, so `cargo test` stays hardware free.
+//!
+//! Each run plays `LoopbackPattern` frames out of the output and checks the frames captured
+//! on the input, in each pixel format, then changes the output to a second mode and checks
+//! that format detection follows it. `DECKLINK_LOOPBACK_SECONDS` sets how long each format
+//! is captured for, 3 seconds by default. A report is written to `loopback.json` and
+//! `loopback.xml` in `DECKLINK_LOOPBACK_REPORT`, or `target/loopback-report`, naming the
+//! stage of the signal path each failure is attributed to.
+//!
+//! The capture is repeated with every frame callback returning `S_OK`, `S_FALSE` and then
+//! `E_FAIL` to the driver, and the frames that arrive are recorded in the `callback_returns`
+//! cases, with the driver version. These seed the table of
+//! `LibraryCapabilities::callback_returns`, and fail if a driver listed there as ignoring the
+//! results loses frames while they are returned.
+//!
+//! Every captured frame is also checked for tearing by a `TearDetector`. With
+//! `DECKLINK_LOOPBACK_STRESS` set to a number of threads, a `stress` case captures again while
+//! those threads copy buffers far larger than the caches, so that the card's transfers into
+//! memory compete with them, and fails if a frame is suspected of tearing. Run a GPU or disk
+//! load alongside it to load the PCIe links themselves.
+
+mod report;
+
+use decklink::device::input::{
+    CallbackResult, CallbackReturnPolicy, CallbackReturnStats, ConsumerPressure,
+    DeckLinkInputCallback, DecklinkDetectedVideoInputFormatFlags, DecklinkInputDevice,
+    DecklinkVideoInputFlags, DecklinkVideoInputFormatChangedEvents,
+};
+use decklink::device::output::{DecklinkOutputDeviceVideoScheduled, DecklinkVideoOutputFlags};
+use decklink::device::selector::DeviceSelector;
+use decklink::device::{DecklinkDevice, DecklinkDeviceDisplayModes};
+use decklink::display_mode::{DecklinkDisplayMode, DecklinkDisplayModeId};
+use decklink::experimental::tearing::{TearDetector, TearSuspicion, TearingConfig};
+use decklink::frame::{
+    DecklinkFrameBase, DecklinkFrameFlags, DecklinkPixelFormat, DecklinkVideoFrame,
+};
+use decklink::loopback::{LoopbackPattern, PatternCheck};
+use decklink::timecode::{frame_rate_of, DecklinkTimecodeFormat};
+use decklink::CallbackReturnHandling;
+use report::{CaseResult, Outcome, Report, Stage};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// The pixel formats each mode is checked in. SDI carries both unchanged.
+const PIXEL_FORMATS: [DecklinkPixelFormat; 2] = [
+    DecklinkPixelFormat::Format8BitYUV,
+    DecklinkPixelFormat::Format10BitYUV,
+];
+
+/// The modes to test in, most preferred first, if both devices support them.
+const PREFERRED_MODES: [DecklinkDisplayModeId; 6] = [
+    DecklinkDisplayModeId::HD1080i50,
+    DecklinkDisplayModeId::HD720p50,
+    DecklinkDisplayModeId::HD1080p25,
+    DecklinkDisplayModeId::HD1080i5994,
+    DecklinkDisplayModeId::PAL,
+    DecklinkDisplayModeId::NTSC,
+];
+
+/// Frames captured while the input locks to the signal, which are not checked.
+const SETTLE_FRAMES: usize = 5;
+/// The frames kept scheduled ahead of playback.
+const PREROLL_FRAMES: u32 = 4;
+/// How long to wait for the input to follow a change of output mode.
+const FORMAT_CHANGE_TIMEOUT: Duration = Duration::from_secs(5);
+/// The size of each of the two buffers a stress thread copies between.
+const STRESS_BUFFER_BYTES: usize = 256 << 20;
+
+#[test]
+fn loopback() {
+    let spec = match std::env::var("DECKLINK_LOOPBACK") {
+        Ok(spec) if !spec.is_empty() => spec,
+        _ => {
+            eprintln!("DECKLINK_LOOPBACK is not set, skipping the hardware loopback tests");
+            return;
+        }
+    };
+    let seconds = std::env::var("DECKLINK_LOOPBACK_SECONDS")
+        .ok()
+        .map(|s| {
+            s.parse()
+                .expect("DECKLINK_LOOPBACK_SECONDS is not a number")
+        })
+        .unwrap_or(3);
+    let report_dir = std::env::var("DECKLINK_LOOPBACK_REPORT")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from("target/loopback-report"));
+
+    let (output_selector, input_selector) = parse_spec(&spec).unwrap();
+    let output_device = output_selector
+        .resolve()
+        .unwrap_or_else(|e| panic!("Failed to find the output device: {}", e));
+    let input_device = input_selector
+        .resolve()
+        .unwrap_or_else(|e| panic!("Failed to find the input device: {}", e));
+
+    let mut run = LoopbackRun {
+        output_device,
+        input_device,
+        capture_time: Duration::from_secs(seconds),
+        report: Report {
+            output_device: output_selector.to_string(),
+            input_device: input_selector.to_string(),
+            cases: Vec::new(),
+        },
+    };
+    run.run_all();
+
+    run.report
+        .write(&report_dir)
+        .expect("Failed to write the loopback report");
+    for case in &run.report.cases {
+        eprintln!("{}: {:?}", case.name, case.outcome);
+    }
+    let failures: Vec<String> = run
+        .report
+        .failures()
+        .iter()
+        .map(|case| match &case.outcome {
+            Outcome::Failed { stage, message } => {
+                format!("{} failed at the {:?} stage: {}", case.name, stage, message)
+            }
+            _ => unreachable!(),
+        })
+        .collect();
+    assert!(
+        failures.is_empty(),
+        "{} loopback cases failed, see {}\n{}",
+        failures.len(),
+        report_dir.display(),
+        failures.join("\n")
+    );
+}
+
+/// Split `DECKLINK_LOOPBACK` into the output and input selectors.
+fn parse_spec(spec: &str) -> Result<(DeviceSelector, DeviceSelector), String> {
+    let parse = |s: &str| {
+        s.trim()
+            .parse::<DeviceSelector>()
+            .map_err(|e| format!("invalid device selector {:?}: {:?}", s, e))
+    };
+
+    let mut output = None;
+    let mut input = None;
+    for part in spec.split(',') {
+        match part.rsplit_once(':') {
+            Some((selector, "out")) => output = Some(parse(selector)?),
+            Some((selector, "in")) => input = Some(parse(selector)?),
+            _ if !spec.contains(',') => {
+                let selector = parse(part)?;
+                return Ok((selector.clone(), selector));
+            }
+            _ => return Err(format!("{:?} is not <selector>:out or <selector>:in", part)),
+        }
+    }
+    match (output, input) {
+        (Some(output), Some(input)) => Ok((output, input)),
+        _ => Err("DECKLINK_LOOPBACK needs both an :out and an :in device".to_string()),
+    }
+}
+
+/// What the input callback saw during a run.
+#[derive(Default)]
+struct Captured {
+    frames: usize,
+    no_signal_frames: usize,
+    /// The stamped index of each frame with a signal, in arrival order.
+    indices: Vec<u32>,
+    /// The full pattern checks, of as many frames as the verifier kept up with.
+    checks: Vec<PatternCheck>,
+    /// The RP188 timecode frame number of each frame with a signal, if it had one.
+    timecodes: Vec<Option<u64>>,
+    errors: Vec<String>,
+    format_changes: Vec<DecklinkDisplayModeId>,
+    /// The frames with a signal suspected of tearing.
+    tears: Vec<TearSuspicion>,
+}
+
+struct Collector {
+    pattern: LoopbackPattern,
+    frame_rate: u32,
+    captured: Arc<Mutex<Captured>>,
+    /// Frames handed to the verifier thread, which are skipped when it is busy.
+    verifier: Mutex<SyncSender<DecklinkVideoFrame>>,
+    tearing: Mutex<TearDetector>,
+}
+
+impl DeckLinkInputCallback for Collector {
+    fn video_input_format_changed(
+        &self,
+        _events: DecklinkVideoInputFormatChangedEvents,
+        new_display_mode: DecklinkDisplayModeId,
+        _detected_signal_flags: DecklinkDetectedVideoInputFormatFlags,
+    ) {
+        self.captured
+            .lock()
+            .unwrap()
+            .format_changes
+            .push(new_display_mode);
+    }
+
+    fn video_input_frame_arrived(&self, video_frame: Option<DecklinkVideoFrame>) -> bool {
+        let frame = match video_frame {
+            Some(frame) => frame,
+            None => return true,
+        };
+
+        let mut captured = self.captured.lock().unwrap();
+        captured.frames += 1;
+        if frame
+            .flags()
+            .contains(DecklinkFrameFlags::HAS_NO_INPUT_SOURCE)
+        {
+            captured.no_signal_frames += 1;
+            return true;
+        }
+        if let Some(suspicion) = self.tearing.lock().unwrap().check(&frame) {
+            captured.tears.push(suspicion);
+        }
+        match self.pattern.read_index(&frame) {
+            Ok(index) => captured.indices.push(index),
+            Err(e) => captured.errors.push(e.to_string()),
+        }
+        let timecode = frame
+            .timecode(DecklinkTimecodeFormat::RP188Any)
+            .ok()
+            .flatten()
+            .map(|timecode| timecode.frame_number(self.frame_rate));
+        captured.timecodes.push(timecode);
+        drop(captured);
+
+        // The frame is dropped unchecked if the verifier is still busy with the last one
+        let _ = self.verifier.lock().unwrap().try_send(frame);
+        true
+    }
+}
+
+/// The tearing checks for the pattern. Its bars move by a few codes a frame, which the
+/// default `min_change_magnitude` takes for a fade, and arrive exactly, so any change counts.
+fn tearing_config() -> TearingConfig {
+    TearingConfig::builder()
+        .min_change_magnitude(0.001)
+        .build()
+        .unwrap()
+}
+
+/// The number of threads `DECKLINK_LOOPBACK_STRESS` asks for, if it is set.
+fn stress_threads() -> Option<usize> {
+    let threads = std::env::var("DECKLINK_LOOPBACK_STRESS").ok()?;
+    Some(
+        threads
+            .parse()
+            .expect("DECKLINK_LOOPBACK_STRESS is not a number"),
+    )
+}
+
+/// Check the frames sent by the collector until it is dropped.
+fn run_verifier(
+    pattern: LoopbackPattern,
+    frames: Receiver<DecklinkVideoFrame>,
+    captured: Arc<Mutex<Captured>>,
+) {
+    for frame in frames {
+        let result = pattern.check(&frame, 0);
+        let mut captured = captured.lock().unwrap();
+        match result {
+            Ok(check) => captured.checks.push(check),
+            Err(e) => captured.errors.push(e.to_string()),
+        }
+    }
+}
+
+/// A failure with the stage it is attributed to.
+type StageResult<T> = Result<T, (Stage, String)>;
+
+struct LoopbackRun {
+    output_device: DecklinkDevice,
+    input_device: DecklinkDevice,
+    capture_time: Duration,
+    report: Report,
+}
+
+impl LoopbackRun {
+    fn run_all(&mut self) {
+        let started = Instant::now();
+        let modes = match self.common_modes() {
+            Ok(modes) => modes,
+            Err((stage, message)) => {
+                self.report.cases.push(CaseResult {
+                    name: "negotiate".to_string(),
+                    outcome: Outcome::Failed { stage, message },
+                    duration: started.elapsed(),
+                    details: Vec::new(),
+                });
+                return;
+            }
+        };
+        let mode = &modes[0];
+        let mode_name = mode.name().unwrap_or_else(|| format!("{:?}", mode.mode()));
+
+        let mut timecodes = Vec::new();
+        for pixel_format in PIXEL_FORMATS {
+            let started = Instant::now();
+            let name = format!("pattern/{}/{:?}", mode_name, pixel_format);
+            let (outcome, details) = match self.capture(mode, pixel_format) {
+                Ok(captured) => {
+                    timecodes.extend(captured.timecodes.iter().copied());
+                    judge_pattern(&captured)
+                }
+                Err((stage, message)) => (Outcome::Failed { stage, message }, Vec::new()),
+            };
+            self.report.cases.push(CaseResult {
+                name,
+                outcome,
+                duration: started.elapsed(),
+                details,
+            });
+        }
+
+        let started = Instant::now();
+        let (outcome, details) = match stress_threads() {
+            Some(threads) => match self.capture_under_stress(mode, threads) {
+                Ok(captured) => {
+                    let (outcome, mut details) = judge_pattern(&captured);
+                    details.push(("stress_threads".to_string(), threads.to_string()));
+                    (outcome, details)
+                }
+                Err((stage, message)) => (Outcome::Failed { stage, message }, Vec::new()),
+            },
+            None => (
+                Outcome::Skipped {
+                    reason: "DECKLINK_LOOPBACK_STRESS is not set".to_string(),
+                },
+                Vec::new(),
+            ),
+        };
+        self.report.cases.push(CaseResult {
+            name: format!("stress/{}", mode_name),
+            outcome,
+            duration: started.elapsed(),
+            details,
+        });
+
+        // What the driver does while every callback returns each result, against the frames
+        // that arrive while they return S_OK
+        let mut baseline = None;
+        for result in [
+            CallbackResult::Ok,
+            CallbackResult::False,
+            CallbackResult::Fail,
+        ] {
+            let started = Instant::now();
+            let (outcome, details) = match self.capture_returning(mode, result) {
+                Ok((captured, stats)) => {
+                    let frames = captured.indices.len();
+                    judge_returns(&captured, &stats, *baseline.get_or_insert(frames))
+                }
+                Err((stage, message)) => (Outcome::Failed { stage, message }, Vec::new()),
+            };
+            self.report.cases.push(CaseResult {
+                name: format!("callback_returns/{}/{:?}", mode_name, result),
+                outcome,
+                duration: started.elapsed(),
+                details,
+            });
+        }
+
+        self.report.cases.push(CaseResult {
+            name: format!("timecode/{}", mode_name),
+            outcome: judge_timecode(&timecodes),
+            duration: Duration::ZERO,
+            details: vec![(
+                "frames_with_timecode".to_string(),
+                timecodes.iter().flatten().count().to_string(),
+            )],
+        });
+
+        // The output wrapper has no way to write audio samples yet
+        self.report.cases.push(CaseResult {
+            name: format!("audio/{}", mode_name),
+            outcome: Outcome::Skipped {
+                reason: "the output path does not support writing audio samples".to_string(),
+            },
+            duration: Duration::ZERO,
+            details: Vec::new(),
+        });
+
+        let started = Instant::now();
+        let (name, outcome) = match modes.get(1) {
+            Some(second) => (
+                format!(
+                    "format_change/{}->{}",
+                    mode_name,
+                    second
+                        .name()
+                        .unwrap_or_else(|| format!("{:?}", second.mode()))
+                ),
+                match self.format_change(mode, second) {
+                    Ok(outcome) => outcome,
+                    Err((stage, message)) => Outcome::Failed { stage, message },
+                },
+            ),
+            None => (
+                "format_change".to_string(),
+                Outcome::Skipped {
+                    reason: "the devices have only one mode in common".to_string(),
+                },
+            ),
+        };
+        self.report.cases.push(CaseResult {
+            name,
+            outcome,
+            duration: started.elapsed(),
+            details: Vec::new(),
+        });
+    }
+
+    /// The modes both devices support in every tested pixel format, most preferred first.
+    fn common_modes(&self) -> StageResult<Vec<DecklinkDisplayMode>> {
+        let output = self
+            .output_device
+            .output()
+            .ok_or((Stage::Output, "the output device has no output".to_string()))?;
+        let input = self
+            .input_device
+            .input()
+            .ok_or((Stage::Capture, "the input device has no input".to_string()))?;
+
+        let supported = |mode: DecklinkDisplayModeId| {
+            PIXEL_FORMATS.iter().all(|format| {
+                matches!(
+                    output.does_support_video_mode(
+                        mode,
+                        *format,
+                        DecklinkVideoOutputFlags::empty()
+                    ),
+                    Ok((true, _))
+                ) && matches!(
+                    input.does_support_video_mode(mode, *format, DecklinkVideoInputFlags::empty()),
+                    Ok((true, _))
+                )
+            })
+        };
+
+        let mut modes: Vec<DecklinkDisplayMode> = output
+            .display_modes()
+            .map_err(|e| {
+                (
+                    Stage::Output,
+                    format!("failed to list output modes: {:?}", e),
+                )
+            })?
+            .into_iter()
+            .filter(|mode| mode.frame_duration().is_some() && supported(mode.mode()))
+            .collect();
+        modes.sort_by_key(|mode| {
+            PREFERRED_MODES
+                .iter()
+                .position(|preferred| *preferred == mode.mode())
+                .unwrap_or(PREFERRED_MODES.len())
+        });
+        if modes.is_empty() {
+            return Err((
+                Stage::Capture,
+                "the devices have no mode in common in the tested pixel formats".to_string(),
+            ));
+        }
+        Ok(modes)
+    }
+
+    fn enable_input(
+        &self,
+        mode: &DecklinkDisplayMode,
+        pixel_format: DecklinkPixelFormat,
+        flags: DecklinkVideoInputFlags,
+    ) -> StageResult<(DecklinkInputDevice, Arc<Mutex<Captured>>)> {
+        let mut input = self
+            .input_device
+            .input()
+            .ok_or((Stage::Capture, "the input device has no input".to_string()))?;
+        let pattern = LoopbackPattern::new(mode.width(), mode.height(), pixel_format)
+            .map_err(|e| (Stage::Verification, e.to_string()))?;
+
+        let captured = Arc::new(Mutex::new(Captured::default()));
+        let (sender, receiver) = sync_channel(2);
+        {
+            let captured = captured.clone();
+            std::thread::spawn(move || run_verifier(pattern, receiver, captured));
+        }
+        let collector = Collector {
+            pattern,
+            frame_rate: frame_rate_of(mode.frame_duration().unwrap()),
+            captured: captured.clone(),
+            verifier: Mutex::new(sender),
+            tearing: Mutex::new(TearDetector::new(tearing_config())),
+        };
+
+        input.set_callback(Some(Arc::new(collector))).map_err(|e| {
+            (
+                Stage::Capture,
+                format!("failed to set the callback: {:?}", e),
+            )
+        })?;
+        input
+            .enable_video_input(mode.mode(), pixel_format, flags)
+            .map_err(|e| {
+                (
+                    Stage::Capture,
+                    format!("failed to enable the input: {:?}", e),
+                )
+            })?;
+        input.start_streams().map_err(|e| {
+            (
+                Stage::Capture,
+                format!("failed to start the input: {:?}", e),
+            )
+        })?;
+        Ok((input, captured))
+    }
+
+    /// Play the pattern in `mode` until `until` returns true or the capture time is up.
+    fn play<F: FnMut() -> bool>(
+        &self,
+        mode: &DecklinkDisplayMode,
+        pixel_format: DecklinkPixelFormat,
+        mut until: F,
+    ) -> StageResult<()> {
+        let output = self
+            .output_device
+            .output()
+            .ok_or((Stage::Output, "the output device has no output".to_string()))?;
+        let duration = mode.frame_duration().unwrap();
+        let pattern = LoopbackPattern::new(mode.width(), mode.height(), pixel_format)
+            .map_err(|e| (Stage::Verification, e.to_string()))?;
+        let mut video: Box<dyn DecklinkOutputDeviceVideoScheduled> = output
+            .enable_video_output_scheduled(
+                mode.mode(),
+                DecklinkVideoOutputFlags::empty(),
+                duration.scale,
+            )
+            .map_err(|e| {
+                (
+                    Stage::Output,
+                    format!("failed to enable the output: {:?}", e),
+                )
+            })?;
+
+        let mut index = 0u32;
+        let mut schedule = |video: &dyn DecklinkOutputDeviceVideoScheduled| -> StageResult<()> {
+            let frame = pattern
+                .frame(index)
+                .map_err(|e| (Stage::Output, format!("failed to make frame: {:?}", e)))?;
+            video
+                .schedule_frame_copy(&frame, index as i64 * duration.value, duration.value)
+                .map_err(|e| (Stage::Output, format!("failed to schedule frame: {:?}", e)))?;
+            index += 1;
+            Ok(())
+        };
+
+        for _ in 0..PREROLL_FRAMES {
+            schedule(&*video)?;
+        }
+        video
+            .start_playback(0, 1.0)
+            .map_err(|e| (Stage::Output, format!("failed to start playback: {:?}", e)))?;
+
+        let started = Instant::now();
+        while started.elapsed() < self.capture_time && !until() {
+            let buffered = video
+                .buffered_video_frame_count()
+                .map_err(|e| (Stage::Output, format!("failed to read the buffer: {:?}", e)))?;
+            for _ in buffered..PREROLL_FRAMES {
+                schedule(&*video)?;
+            }
+            std::thread::sleep(Duration::from_millis(2));
+        }
+        // Dropping the output stops playback and disables it
+        drop(video);
+        Ok(())
+    }
+
+    fn capture(
+        &self,
+        mode: &DecklinkDisplayMode,
+        pixel_format: DecklinkPixelFormat,
+    ) -> StageResult<Captured> {
+        self.capture_with_policy(mode, pixel_format, CallbackReturnPolicy::default())
+            .map(|(captured, _)| captured)
+    }
+
+    /// Capture in 8-bit YUV with every frame callback returning `result` to the driver.
+    fn capture_returning(
+        &self,
+        mode: &DecklinkDisplayMode,
+        result: CallbackResult,
+    ) -> StageResult<(Captured, CallbackReturnStats)> {
+        let decide: fn(CallbackResult, &ConsumerPressure) -> CallbackResult = match result {
+            CallbackResult::Ok => |_, _| CallbackResult::Ok,
+            CallbackResult::False => |_, _| CallbackResult::False,
+            CallbackResult::Fail => |_, _| CallbackResult::Fail,
+        };
+        self.capture_with_policy(
+            mode,
+            DecklinkPixelFormat::Format8BitYUV,
+            CallbackReturnPolicy::Custom(decide),
+        )
+    }
+
+    fn capture_with_policy(
+        &self,
+        mode: &DecklinkDisplayMode,
+        pixel_format: DecklinkPixelFormat,
+        policy: CallbackReturnPolicy,
+    ) -> StageResult<(Captured, CallbackReturnStats)> {
+        let (input, captured) =
+            self.enable_input(mode, pixel_format, DecklinkVideoInputFlags::empty())?;
+        // Streams are started, but nothing is played until the policy is set
+        input.set_callback_return_policy(policy);
+        let played = self.play(mode, pixel_format, || false);
+        input.stop_streams().ok();
+        let stats = input.callback_return_stats();
+        drop(input);
+        played?;
+
+        // Leave the verifier time to finish the frame it has
+        std::thread::sleep(Duration::from_millis(200));
+        let captured = std::mem::take(&mut *captured.lock().unwrap());
+        Ok((captured, stats))
+    }
+
+    /// Capture in 8-bit YUV while `threads` threads copy between buffers far larger than the
+    /// caches.
+    fn capture_under_stress(
+        &self,
+        mode: &DecklinkDisplayMode,
+        threads: usize,
+    ) -> StageResult<Captured> {
+        let stop = Arc::new(AtomicBool::new(false));
+        let loads: Vec<_> = (0..threads)
+            .map(|_| {
+                let stop = stop.clone();
+                std::thread::spawn(move || {
+                    let mut from = vec![1u8; STRESS_BUFFER_BYTES];
+                    let mut to = vec![0u8; STRESS_BUFFER_BYTES];
+                    while !stop.load(Ordering::Relaxed) {
+                        to.copy_from_slice(&from);
+                        std::hint::black_box(&mut to);
+                        std::mem::swap(&mut from, &mut to);
+                    }
+                })
+            })
+            .collect();
+        let captured = self.capture(mode, DecklinkPixelFormat::Format8BitYUV);
+        stop.store(true, Ordering::Relaxed);
+        for load in loads {
+            load.join().ok();
+        }
+        captured
+    }
+
+    /// Capture `from` with format detection, then switch the output to `to`.
+    fn format_change(
+        &self,
+        from: &DecklinkDisplayMode,
+        to: &DecklinkDisplayMode,
+    ) -> StageResult<Outcome> {
+        let pixel_format = DecklinkPixelFormat::Format10BitYUV;
+        let (mut input, captured) = self.enable_input(
+            from,
+            pixel_format,
+            DecklinkVideoInputFlags::ENABLE_FORMAT_DETECTION,
+        )?;
+
+        let mut seen_from = false;
+        self.play(from, pixel_format, || {
+            seen_from = captured.lock().unwrap().indices.len() > SETTLE_FRAMES;
+            seen_from
+        })?;
+        if !seen_from {
+            input.stop_streams().ok();
+            return Err((
+                Stage::Cable,
+                format!("no pattern frames arrived in {:?}", from.mode()),
+            ));
+        }
+
+        // Play the second mode, re-enabling the input when detection reports it
+        let deadline = Instant::now() + FORMAT_CHANGE_TIMEOUT;
+        let mut detected = false;
+        let mut reenable_error = None;
+        self.play(to, pixel_format, || {
+            if !detected && captured.lock().unwrap().format_changes.contains(&to.mode()) {
+                detected = true;
+                let result = input
+                    .pause_streams()
+                    .and_then(|_| input.disable_video_input())
+                    .and_then(|_| {
+                        input.enable_video_input(
+                            to.mode(),
+                            pixel_format,
+                            DecklinkVideoInputFlags::ENABLE_FORMAT_DETECTION,
+                        )
+                    })
+                    .and_then(|_| input.flush_streams())
+                    .and_then(|_| input.start_streams());
+                if let Err(e) = result {
+                    reenable_error = Some(e);
+                    return true;
+                }
+                captured.lock().unwrap().indices.clear();
+            }
+            let settled = detected && captured.lock().unwrap().indices.len() > SETTLE_FRAMES;
+            settled || Instant::now() > deadline
+        })?;
+        input.stop_streams().ok();
+
+        if let Some(e) = reenable_error {
+            return Err((
+                Stage::Capture,
+                format!("failed to re-enable the input in {:?}: {:?}", to.mode(), e),
+            ));
+        }
+        if !detected {
+            return Ok(Outcome::Failed {
+                stage: Stage::Capture,
+                message: format!(
+                    "format detection did not report {:?} within {:?}, it reported {:?}",
+                    to.mode(),
+                    FORMAT_CHANGE_TIMEOUT,
+                    captured.lock().unwrap().format_changes
+                ),
+            });
+        }
+        if captured.lock().unwrap().indices.len() <= SETTLE_FRAMES {
+            return Ok(Outcome::Failed {
+                stage: Stage::Verification,
+                message: format!(
+                    "no pattern frames arrived after changing to {:?}",
+                    to.mode()
+                ),
+            });
+        }
+        Ok(Outcome::Passed)
+    }
+}
+
+/// Attribute the frames of a capture to a stage, or pass them.
+fn judge_pattern(captured: &Captured) -> (Outcome, Vec<(String, String)>) {
+    let indices = captured.indices.get(SETTLE_FRAMES..).unwrap_or(&[]);
+    let gaps = indices
+        .windows(2)
+        .filter(|pair| pair[1] != pair[0].wrapping_add(1))
+        .count();
+    let checks = captured
+        .checks
+        .iter()
+        .filter(|c| c.index as usize >= SETTLE_FRAMES);
+    let mismatched: Vec<&PatternCheck> = checks.clone().filter(|c| !c.matches()).collect();
+    let tears: Vec<&TearSuspicion> = captured
+        .tears
+        .iter()
+        .filter(|t| t.sequence as usize >= SETTLE_FRAMES)
+        .collect();
+    let details = vec![
+        ("frames".to_string(), captured.frames.to_string()),
+        (
+            "no_signal_frames".to_string(),
+            captured.no_signal_frames.to_string(),
+        ),
+        ("checked_frames".to_string(), checks.count().to_string()),
+        (
+            "mismatched_frames".to_string(),
+            mismatched.len().to_string(),
+        ),
+        ("index_discontinuities".to_string(), gaps.to_string()),
+        ("suspected_tears".to_string(), tears.len().to_string()),
+        ("errors".to_string(), captured.errors.len().to_string()),
+    ];
+
+    let failed = |stage, message| Outcome::Failed { stage, message };
+    let outcome = if captured.frames == 0 {
+        failed(Stage::Capture, "no frames arrived".to_string())
+    } else if captured.no_signal_frames == captured.frames {
+        failed(
+            Stage::Cable,
+            "every frame arrived without an input signal".to_string(),
+        )
+    } else if let Some(error) = captured.errors.first() {
+        failed(Stage::Verification, error.clone())
+    } else if indices.is_empty() {
+        failed(
+            Stage::Cable,
+            "the signal was lost before the input settled".to_string(),
+        )
+    } else if let Some(check) = mismatched.first() {
+        failed(
+            Stage::Verification,
+            format!(
+                "frame {} has {} mismatched codes, the first at {:?}, by up to {}",
+                check.index, check.mismatched_codes, check.first_mismatch, check.largest_error
+            ),
+        )
+    } else if let Some(tear) = tears.first() {
+        failed(
+            Stage::Capture,
+            format!(
+                "{} frames are suspected of tearing, the first below row {} and by row {}",
+                tears.len(),
+                tear.last_changed_row,
+                tear.tear_row
+            ),
+        )
+    } else if gaps > 0 {
+        failed(
+            Stage::Verification,
+            format!("{} dropped or repeated frames in the stamped indices", gaps),
+        )
+    } else {
+        Outcome::Passed
+    };
+    (outcome, details)
+}
+
+/// Record what the driver did while callbacks returned a result, failing only where it
+/// contradicts `LibraryCapabilities::callback_returns`. `baseline` is the frames with a
+/// signal that arrived while callbacks returned `S_OK`.
+fn judge_returns(
+    captured: &Captured,
+    stats: &CallbackReturnStats,
+    baseline: usize,
+) -> (Outcome, Vec<(String, String)>) {
+    let frames = captured.indices.len();
+    let gaps = captured
+        .indices
+        .get(SETTLE_FRAMES..)
+        .unwrap_or(&[])
+        .windows(2)
+        .filter(|pair| pair[1] != pair[0].wrapping_add(1))
+        .count();
+    let details = vec![
+        (
+            "driver_version".to_string(),
+            decklink::api_version().unwrap_or_default(),
+        ),
+        (
+            "driver_handling".to_string(),
+            format!("{:?}", stats.driver_handling),
+        ),
+        ("frames".to_string(), frames.to_string()),
+        ("baseline_frames".to_string(), baseline.to_string()),
+        ("index_discontinuities".to_string(), gaps.to_string()),
+        (
+            "returned_false".to_string(),
+            stats.returned_false.to_string(),
+        ),
+        ("returned_fail".to_string(), stats.returned_fail.to_string()),
+    ];
+
+    // A driver not yet checked is recorded, whatever it did
+    let outcome = if stats.driver_handling != CallbackReturnHandling::Ignored {
+        Outcome::Passed
+    } else if frames < baseline * 9 / 10 || gaps > 0 {
+        Outcome::Failed {
+            stage: Stage::Capture,
+            message: format!(
+                "the drivers are listed as ignoring callback results, but {} frames arrived \
+                 against {} returning S_OK, with {} discontinuities",
+                frames, baseline, gaps
+            ),
+        }
+    } else {
+        Outcome::Passed
+    };
+    (outcome, details)
+}
+
+/// Check the captured timecode counts up by one frame at a time, where there is any.
+fn judge_timecode(timecodes: &[Option<u64>]) -> Outcome {
+    let present: Vec<u64> = timecodes.iter().flatten().copied().collect();
+    if present.is_empty() {
+        // Output frames cannot carry timecode through this crate yet
+        return Outcome::Skipped {
+            reason: "no timecode was captured, and the output path cannot embed it".to_string(),
+        };
+    }
+    let jumps = present
+        .windows(2)
+        .filter(|pair| pair[1] != pair[0] + 1)
+        .count();
+    // Each capture starts again, so one jump between the pixel format runs is expected
+    if jumps > PIXEL_FORMATS.len() - 1 {
+        Outcome::Failed {
+            stage: Stage::Verification,
+            message: format!("the captured timecode jumped {} times", jumps),
+        }
+    } else {
+        Outcome::Passed
+    }
+}
+
+#[test]
+fn loopback_specs_name_an_output_and_an_input() {
+    let name = |name: &str| DeviceSelector::NameExact(name.to_string());
+    assert_eq!(
+        parse_spec("name:DeckLink Duo (1):out,name:DeckLink Duo (2):in"),
+        Ok((name("DeckLink Duo (1)"), name("DeckLink Duo (2)")))
+    );
+    assert_eq!(
+        parse_spec("1:in, 0:out"),
+        Ok((DeviceSelector::Index(0), DeviceSelector::Index(1)))
+    );
+    // One device cabled to itself
+    assert_eq!(
+        parse_spec("DeckLink Mini Recorder"),
+        Ok((
+            name("DeckLink Mini Recorder"),
+            name("DeckLink Mini Recorder")
+        ))
+    );
+    assert!(parse_spec("0:out,1:sideways").is_err());
+    assert!(parse_spec("0:out,1:out").is_err());
+}
+
+fn check(index: u32, mismatched_codes: u64) -> PatternCheck {
+    PatternCheck {
+        index,
+        mismatched_codes,
+        first_mismatch: (mismatched_codes > 0).then_some((0, 0)),
+        largest_error: mismatched_codes as u16,
+        no_input_source: false,
+    }
+}
+
+/// What is captured of `frames` frames with a signal, stamped with these indices, each
+/// checked with `mismatched` codes.
+fn captured(indices: Vec<u32>, mismatched: u64) -> Captured {
+    Captured {
+        frames: indices.len(),
+        checks: indices.iter().map(|i| check(*i, mismatched)).collect(),
+        timecodes: vec![None; indices.len()],
+        indices,
+        ..Captured::default()
+    }
+}
+
+fn stage(outcome: &Outcome) -> Option<Stage> {
+    match outcome {
+        Outcome::Failed { stage, .. } => Some(*stage),
+        _ => None,
+    }
+}
+
+#[test]
+fn each_failure_is_attributed_to_a_stage() {
+    let (outcome, details) = judge_pattern(&captured((0..20).collect(), 0));
+    assert_eq!(outcome, Outcome::Passed);
+    assert!(details.contains(&("checked_frames".to_string(), "15".to_string())));
+
+    let nothing = Captured::default();
+    assert_eq!(stage(&judge_pattern(&nothing).0), Some(Stage::Capture));
+
+    let dark = Captured {
+        frames: 10,
+        no_signal_frames: 10,
+        ..Captured::default()
+    };
+    assert_eq!(stage(&judge_pattern(&dark).0), Some(Stage::Cable));
+
+    // Lost before the input settled
+    let lost = Captured {
+        frames: 10,
+        no_signal_frames: 7,
+        ..captured((0..3).collect(), 0)
+    };
+    assert_eq!(stage(&judge_pattern(&lost).0), Some(Stage::Cable));
+
+    // The frames that settle the input are not checked
+    let mut unsettled = captured((0..20).collect(), 0);
+    unsettled.checks[SETTLE_FRAMES - 1] = check(SETTLE_FRAMES as u32 - 1, 9);
+    assert_eq!(judge_pattern(&unsettled).0, Outcome::Passed);
+
+    let corrupted = captured((0..20).collect(), 3);
+    let (outcome, _) = judge_pattern(&corrupted);
+    match outcome {
+        Outcome::Failed {
+            stage: Stage::Verification,
+            message,
+        } => assert!(message.contains("3 mismatched codes"), "{message}"),
+        outcome => panic!("unexpected {outcome:?}"),
+    }
+
+    let mut dropped: Vec<u32> = (0..20).collect();
+    dropped.remove(12);
+    assert_eq!(
+        stage(&judge_pattern(&captured(dropped, 0)).0),
+        Some(Stage::Verification)
+    );
+}
+
+#[test]
+fn timecode_is_checked_only_when_captured() {
+    assert!(matches!(
+        judge_timecode(&[None, None]),
+        Outcome::Skipped { .. }
+    ));
+    // One jump between the runs of each pixel format
+    let runs = [Some(10), Some(11), Some(12), Some(50), Some(51)];
+    assert_eq!(judge_timecode(&runs), Outcome::Passed);
+    let jumpy = [Some(10), Some(12), Some(13), Some(50)];
+    assert_eq!(stage(&judge_timecode(&jumpy)), Some(Stage::Verification));
+}
+
+#[test]
+fn reports_name_the_failing_stage() {
+    let report = Report {
+        output_device: "name:DeckLink Duo (1)".to_string(),
+        input_device: "name:DeckLink Duo (2)".to_string(),
+        cases: vec![
+            CaseResult {
+                name: "pattern <8-bit>".to_string(),
+                outcome: Outcome::Passed,
+                duration: Duration::from_millis(1500),
+                details: vec![("frames".to_string(), "75".to_string())],
+            },
+            CaseResult {
+                name: "pattern \"10-bit\"".to_string(),
+                outcome: Outcome::Failed {
+                    stage: Stage::Cable,
+                    message: "every frame arrived without an input signal".to_string(),
+                },
+                duration: Duration::ZERO,
+                details: Vec::new(),
+            },
+            CaseResult {
+                name: "audio".to_string(),
+                outcome: Outcome::Skipped {
+                    reason: "cannot write samples".to_string(),
+                },
+                duration: Duration::ZERO,
+                details: Vec::new(),
+            },
+        ],
+    };
+    assert_eq!(report.failures().len(), 1);
+
+    let json = report.to_json();
+    assert!(json.starts_with(
+        "{\"output_device\":\"name:DeckLink Duo (1)\",\"input_device\":\"name:DeckLink Duo (2)\""
+    ));
+    assert!(json.contains(
+        "{\"name\":\"pattern <8-bit>\",\"outcome\":\"passed\",\"stage\":null,\"message\":null,\"seconds\":1.500,\"details\":{\"frames\":\"75\"}}"
+    ));
+    assert!(json.contains(
+        "\"name\":\"pattern \\\"10-bit\\\"\",\"outcome\":\"failed\",\"stage\":\"cable\""
+    ));
+    assert!(json
+        .contains("\"outcome\":\"skipped\",\"stage\":null,\"message\":\"cannot write samples\""));
+
+    let junit = report.to_junit();
+    assert!(junit.contains("tests=\"3\" failures=\"1\" skipped=\"1\" time=\"1.500\""));
+    assert!(junit.contains("name=\"pattern &lt;8-bit&gt;\""));
+    assert!(junit.contains("<system-out>frames=75</system-out>"));
+    assert!(junit.contains(
+        "<failure type=\"cable\" message=\"every frame arrived without an input signal\"/>"
+    ));
+    assert!(junit.contains("<skipped message=\"cannot write samples\"/>"));
+
+    let dir = std::env::temp_dir().join(format!("decklink-loopback-{}", std::process::id()));
+    report.write(&dir).unwrap();
+    assert_eq!(
+        std::fs::read_to_string(dir.join("loopback.json")).unwrap(),
+        json
+    );
+    assert_eq!(
+        std::fs::read_to_string(dir.join("loopback.xml")).unwrap(),
+        junit
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,194 @@
+//! The results of a loopback run, written as JSON and as JUnit XML.
+
+use std::fmt::Write as _;
+use std::path::Path;
+use std::time::Duration;
+
+/// The part of the signal path a failure is attributed to.
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub enum Stage {
+    /// Enabling the output, or scheduling and playing frames, failed.
+    Output,
+    /// Frames arrived, but without a signal, so nothing reached the input.
+    Cable,
+    /// Enabling or starting the input failed, or no frames arrived at all.
+    Capture,
+    /// Frames arrived with a signal, but not the one that was played.
+    Verification,
+}
+
+impl Stage {
+    fn name(&self) -> &'static str {
+        match self {
+            Stage::Output => "output",
+            Stage::Cable => "cable",
+            Stage::Capture => "capture",
+            Stage::Verification => "verification",
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum Outcome {
+    Passed,
+    Failed { stage: Stage, message: String },
+    Skipped { reason: String },
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct CaseResult {
+    pub name: String,
+    pub outcome: Outcome,
+    pub duration: Duration,
+    /// Counts and measurements for the report, such as frames captured.
+    pub details: Vec<(String, String)>,
+}
+
+impl CaseResult {
+    pub fn failed(&self) -> bool {
+        matches!(self.outcome, Outcome::Failed { .. })
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    pub output_device: String,
+    pub input_device: String,
+    pub cases: Vec<CaseResult>,
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+impl Report {
+    pub fn failures(&self) -> Vec<&CaseResult> {
+        self.cases.iter().filter(|c| c.failed()).collect()
+    }
+
+    pub fn to_json(&self) -> String {
+        let cases: Vec<String> = self
+            .cases
+            .iter()
+            .map(|case| {
+                let (outcome, stage, message) = match &case.outcome {
+                    Outcome::Passed => ("passed", None, None),
+                    Outcome::Failed { stage, message } => {
+                        ("failed", Some(stage.name()), Some(message.as_str()))
+                    }
+                    Outcome::Skipped { reason } => ("skipped", None, Some(reason.as_str())),
+                };
+                let details: Vec<String> = case
+                    .details
+                    .iter()
+                    .map(|(k, v)| format!("{}:{}", json_string(k), json_string(v)))
+                    .collect();
+                format!(
+                    "{{\"name\":{},\"outcome\":\"{}\",\"stage\":{},\"message\":{},\"seconds\":{:.3},\"details\":{{{}}}}}",
+                    json_string(&case.name),
+                    outcome,
+                    stage.map_or_else(|| "null".to_string(), json_string),
+                    message.map_or_else(|| "null".to_string(), json_string),
+                    case.duration.as_secs_f64(),
+                    details.join(",")
+                )
+            })
+            .collect();
+        format!(
+            "{{\"output_device\":{},\"input_device\":{},\"cases\":[{}]}}\n",
+            json_string(&self.output_device),
+            json_string(&self.input_device),
+            cases.join(",")
+        )
+    }
+
+    pub fn to_junit(&self) -> String {
+        let failures = self.failures().len();
+        let skipped = self
+            .cases
+            .iter()
+            .filter(|c| matches!(c.outcome, Outcome::Skipped { .. }))
+            .count();
+        let total: f64 = self.cases.iter().map(|c| c.duration.as_secs_f64()).sum();
+
+        let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        let _ = writeln!(
+            out,
+            "<testsuite name=\"decklink-loopback\" tests=\"{}\" failures=\"{}\" skipped=\"{}\" time=\"{:.3}\">",
+            self.cases.len(),
+            failures,
+            skipped,
+            total
+        );
+        let _ = writeln!(
+            out,
+            "  <properties><property name=\"output_device\" value=\"{}\"/><property name=\"input_device\" value=\"{}\"/></properties>",
+            xml_escape(&self.output_device),
+            xml_escape(&self.input_device)
+        );
+        for case in &self.cases {
+            let _ = write!(
+                out,
+                "  <testcase classname=\"loopback\" name=\"{}\" time=\"{:.3}\">",
+                xml_escape(&case.name),
+                case.duration.as_secs_f64()
+            );
+            match &case.outcome {
+                Outcome::Passed => {}
+                Outcome::Failed { stage, message } => {
+                    let _ = write!(
+                        out,
+                        "<failure type=\"{}\" message=\"{}\"/>",
+                        stage.name(),
+                        xml_escape(message)
+                    );
+                }
+                Outcome::Skipped { reason } => {
+                    let _ = write!(out, "<skipped message=\"{}\"/>", xml_escape(reason));
+                }
+            }
+            if !case.details.is_empty() {
+                let details: Vec<String> = case
+                    .details
+                    .iter()
+                    .map(|(k, v)| format!("{}={}", k, v))
+                    .collect();
+                let _ = write!(
+                    out,
+                    "<system-out>{}</system-out>",
+                    xml_escape(&details.join("\n"))
+                );
+            }
+            out.push_str("</testcase>\n");
+        }
+        out.push_str("</testsuite>\n");
+        out
+    }
+
+    /// Write `loopback.json` and `loopback.xml` to `dir`.
+    pub fn write(&self, dir: &Path) -> std::io::Result<()> {
+        std::fs::create_dir_all(dir)?;
+        std::fs::write(dir.join("loopback.json"), self.to_json())?;
+        std::fs::write(dir.join("loopback.xml"), self.to_junit())
+    }
+}
@@ -0,0 +1,236 @@
+//! Converting synthetic frames into `image` buffers.
+#![cfg(feature = "image-interop")]
+
+use decklink::frame::{DecklinkFrameFlags, DecklinkPixelFormat, DecklinkVideoMutableFrame};
+use decklink::image_interop::{Colorimetry, DecklinkFrameImageExt, ImageConversionError};
+use decklink::lut::{Lut, Lut1d};
+use image::{RgbImage, RgbaImage};
+
+fn synthetic(
+    width: usize,
+    height: usize,
+    row_bytes: usize,
+    pixel_format: DecklinkPixelFormat,
+    bytes: &[u8],
+) -> DecklinkVideoMutableFrame {
+    let mut frame = DecklinkVideoMutableFrame::create(
+        width,
+        height,
+        row_bytes,
+        pixel_format,
+        DecklinkFrameFlags::empty(),
+    );
+    let mut padded = bytes.to_vec();
+    padded.resize(row_bytes * height, 0);
+    frame.copy_bytes(&padded).unwrap();
+    frame
+}
+
+/// Pack rows of 6 pixels of 10-bit (Y, Cb, Cr) into v210, sharing the chroma of each pair.
+fn v210(rows: &[[[u16; 3]; 6]]) -> Vec<u8> {
+    rows.iter()
+        .flat_map(|px| {
+            let samples = [
+                px[0][1], px[0][0], px[0][2], px[1][0], px[2][1], px[2][0], px[2][2], px[3][0],
+                px[4][1], px[4][0], px[4][2], px[5][0],
+            ];
+            samples
+                .chunks(3)
+                .flat_map(|s| {
+                    (s[0] as u32 | (s[1] as u32) << 10 | (s[2] as u32) << 20).to_le_bytes()
+                })
+                .collect::<Vec<u8>>()
+        })
+        .collect()
+}
+
+#[test]
+fn rgb_formats_are_reordered_with_their_stride() {
+    // Two BGRA pixels per row, padded to 12 bytes
+    let bgra = [
+        1, 2, 3, 4, 5, 6, 7, 8, 0, 0, 0, 0, //
+        9, 10, 11, 12, 13, 14, 15, 16,
+    ];
+    let frame = synthetic(2, 2, 12, DecklinkPixelFormat::Format8BitBGRA, &bgra);
+
+    let image = RgbaImage::try_from(&frame).unwrap();
+    assert_eq!(image.dimensions(), (2, 2));
+    assert_eq!(
+        image.into_raw(),
+        [3, 2, 1, 4, 7, 6, 5, 8, 11, 10, 9, 12, 15, 14, 13, 16]
+    );
+    let image = RgbImage::try_from(&frame).unwrap();
+    assert_eq!(image.into_raw(), [3, 2, 1, 7, 6, 5, 11, 10, 9, 15, 14, 13]);
+
+    let argb = synthetic(1, 1, 4, DecklinkPixelFormat::Format8BitARGB, &[4, 1, 2, 3]);
+    assert_eq!(RgbaImage::try_from(&argb).unwrap().into_raw(), [1, 2, 3, 4]);
+}
+
+#[test]
+fn uyvy_matches_the_lut_converter_to_within_rounding() {
+    // Every luma, with chroma sweeping the range, in rows of 16 pixels
+    let bytes: Vec<u8> = (0..256 / 2)
+        .flat_map(|i| {
+            let y = (i * 2) as u8;
+            [(i * 7 % 256) as u8, y, (255 - i * 3 % 256) as u8, y + 1]
+        })
+        .collect();
+    let frame = synthetic(16, 16, 32, DecklinkPixelFormat::Format8BitYUV, &bytes);
+
+    let reference = Lut::OneD(Lut1d::identity(2))
+        .convert_uyvy_to_rgba8(&frame)
+        .unwrap();
+    let image = frame.to_image_with(Colorimetry::Rec709).unwrap().into_raw();
+    assert_eq!(image.len(), reference.len());
+    for (i, (a, b)) in image.iter().zip(&reference).enumerate() {
+        assert!(a.abs_diff(*b) <= 1, "byte {}: {} != {}", i, a, b);
+    }
+}
+
+#[test]
+fn uyvy_is_converted_from_video_range() {
+    let frame = synthetic(
+        4,
+        1,
+        8,
+        DecklinkPixelFormat::Format8BitYUV,
+        &[128, 16, 128, 235, 128, 126, 128, 126],
+    );
+    let image = frame.to_rgb_image_with(Colorimetry::Rec709).unwrap();
+    assert_eq!(
+        image.into_raw(),
+        [0, 0, 0, 255, 255, 255, 128, 128, 128, 128, 128, 128]
+    );
+}
+
+#[test]
+fn colorimetry_follows_the_frame_height() {
+    assert_eq!(Colorimetry::for_height(486), Colorimetry::Rec601);
+    assert_eq!(Colorimetry::for_height(576), Colorimetry::Rec601);
+    assert_eq!(Colorimetry::for_height(720), Colorimetry::Rec709);
+
+    // A saturated blue pixel converts differently with each matrix
+    let pixel = [240, 41, 110, 41];
+    let sd = synthetic(
+        2,
+        486,
+        4,
+        DecklinkPixelFormat::Format8BitYUV,
+        &pixel.repeat(486),
+    );
+    let hd = synthetic(
+        2,
+        720,
+        4,
+        DecklinkPixelFormat::Format8BitYUV,
+        &pixel.repeat(720),
+    );
+    let sd_pixel = RgbaImage::try_from(&sd).unwrap().get_pixel(0, 0).0;
+    let hd_pixel = RgbaImage::try_from(&hd).unwrap().get_pixel(0, 0).0;
+    assert_eq!(
+        sd_pixel,
+        sd.to_image_with(Colorimetry::Rec601)
+            .unwrap()
+            .get_pixel(0, 0)
+            .0
+    );
+    assert_eq!(
+        hd_pixel,
+        hd.to_image_with(Colorimetry::Rec709)
+            .unwrap()
+            .get_pixel(0, 0)
+            .0
+    );
+    assert_ne!(sd_pixel, hd_pixel);
+    assert_ne!(
+        hd.to_image_with(Colorimetry::Rec2020)
+            .unwrap()
+            .get_pixel(0, 0)
+            .0,
+        hd_pixel
+    );
+}
+
+#[test]
+fn v210_black_and_white_are_exact() {
+    let black = [64, 512, 512];
+    let white = [940, 512, 512];
+    let bytes = v210(&[[black, black, white, white, black, white]]);
+    let frame = synthetic(6, 1, 16, DecklinkPixelFormat::Format10BitYUV, &bytes);
+
+    let image = frame.to_rgb_image_with(Colorimetry::Rec709).unwrap();
+    let luma: Vec<u8> = image.into_raw().chunks(3).map(|px| px[0]).collect();
+    assert_eq!(luma, [0, 0, 255, 255, 0, 255]);
+}
+
+#[test]
+fn ten_bit_sources_are_dithered_rather_than_truncated() {
+    // A grey between two 8-bit steps: 64 + 876 * 100.25 / 255
+    let grey = [64 + 344, 512, 512];
+    let bytes = v210(&[[grey; 6]; 4]).repeat(1);
+    let frame = synthetic(6, 4, 16, DecklinkPixelFormat::Format10BitYUV, &bytes);
+
+    let image = frame.to_rgb_image_with(Colorimetry::Rec709).unwrap();
+    let values: Vec<u8> = image.into_raw().chunks(3).map(|px| px[0]).collect();
+    assert!(values.iter().all(|v| *v == 100 || *v == 101));
+    assert!(values.contains(&100) && values.contains(&101));
+    let mean = values.iter().map(|v| *v as f32).sum::<f32>() / values.len() as f32;
+    let expected = 344.0 * 255.0 / 876.0;
+    assert!((mean - expected).abs() < 0.2, "{} != {}", mean, expected);
+}
+
+#[test]
+fn r210_is_converted_from_video_range() {
+    let word = |r: u32, g: u32, b: u32| (r << 20 | g << 10 | b).to_be_bytes();
+    let bytes: Vec<u8> = [word(940, 64, 64), word(64, 940, 940)].concat();
+    let frame = synthetic(2, 1, 8, DecklinkPixelFormat::Format10BitRGB, &bytes);
+
+    assert_eq!(
+        frame.to_image_with(Colorimetry::Rec709).unwrap().into_raw(),
+        [255, 0, 0, 255, 0, 255, 255, 255]
+    );
+}
+
+#[test]
+fn r10b_and_r10l_are_converted_with_their_own_layouts() {
+    let word = |r: u32, g: u32, b: u32| r << 22 | g << 12 | b << 2;
+    let words = [word(940, 64, 64), word(64, 940, 940)];
+    let r10b: Vec<u8> = words.iter().flat_map(|w| w.to_be_bytes()).collect();
+    let r10l: Vec<u8> = words.iter().flat_map(|w| w.to_le_bytes()).collect();
+
+    for (format, bytes) in [
+        (DecklinkPixelFormat::Format10BitRGBX, r10b),
+        (DecklinkPixelFormat::Format10BitRGBXLE, r10l),
+    ] {
+        let frame = synthetic(2, 1, 256, format, &bytes);
+        assert_eq!(
+            frame.to_image_with(Colorimetry::Rec709).unwrap().into_raw(),
+            [255, 0, 0, 255, 0, 255, 255, 255],
+            "{:?}",
+            format
+        );
+    }
+}
+
+#[test]
+fn unsupported_formats_are_named() {
+    let frame = synthetic(16, 1, 64, DecklinkPixelFormat::Format12BitRGB, &[]);
+    let error = RgbaImage::try_from(&frame).unwrap_err();
+    assert!(matches!(
+        error,
+        ImageConversionError::UnsupportedPixelFormat(DecklinkPixelFormat::Format12BitRGB)
+    ));
+    assert_eq!(
+        error.to_string(),
+        "pixel format Format12BitRGB cannot be converted to an image"
+    );
+}
+
+#[test]
+fn short_rows_are_rejected() {
+    let frame = synthetic(4, 1, 12, DecklinkPixelFormat::Format8BitBGRA, &[]);
+    assert!(matches!(
+        frame.to_image_with(Colorimetry::Rec709),
+        Err(ImageConversionError::BufferTooSmall)
+    ));
+}
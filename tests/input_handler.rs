@@ -0,0 +1,274 @@
+//! Input handlers, and the callbacks of the older trait passed through them.
+#![cfg(feature = "mock-backend")]
+
+mod common;
+
+use decklink::device::input::{
+    ArrivalFlags, CallbackResult, DeckLinkInputCallback, DecklinkAudioInputPacket,
+    DecklinkDetectedVideoInputFormatFlags, DecklinkInputDevice, DecklinkVideoInputFlags,
+    DecklinkVideoInputFormatChangedEvents, FrameArrival, FrameConversionFailure, InputFormatChange,
+    InputHandler,
+};
+use decklink::display_mode::DecklinkDisplayModeId;
+use decklink::frame::{DecklinkFrameBase, DecklinkPixelFormat, DecklinkVideoFrame};
+use decklink::mock::{MockBackend, MockFrame, MockInput};
+use decklink::time::DecklinkFrameTiming;
+use std::sync::{Arc, Mutex};
+
+const MODE: DecklinkDisplayModeId = DecklinkDisplayModeId::HD1080p25;
+const FORMAT: DecklinkPixelFormat = DecklinkPixelFormat::Format8BitYUV;
+
+/// Two sample frames of 16-bit stereo.
+const SAMPLES: [u8; 8] = [1, 0, 2, 0, 3, 0, 4, 0];
+
+#[derive(PartialEq, Debug)]
+struct Seen {
+    /// The first byte of the frame.
+    frame: Option<u8>,
+    sample_frames: Option<usize>,
+    /// The stream time, in ticks of 25000 per second.
+    stream_time: Option<i64>,
+    sequence: u64,
+    flags: ArrivalFlags,
+}
+
+/// Records what each callback carries, and keeps the frames.
+#[derive(Default)]
+struct Recorder {
+    changes: Mutex<Vec<InputFormatChange>>,
+    seen: Mutex<Vec<Seen>>,
+    kept: Mutex<Vec<DecklinkVideoFrame>>,
+}
+
+impl InputHandler for Recorder {
+    fn format_changed(&self, change: &InputFormatChange) {
+        self.changes.lock().unwrap().push(*change);
+    }
+
+    fn frame_arrived(&self, arrival: &FrameArrival<'_>) -> CallbackResult {
+        self.seen.lock().unwrap().push(Seen {
+            frame: arrival.video_frame.map(|f| f.bytes().unwrap().0[0]),
+            sample_frames: arrival.audio_packet.map(|p| p.sample_frame_count()),
+            stream_time: arrival
+                .timing()
+                .and_then(|t| t.stream_time_in(25000))
+                .map(|t| t.value),
+            sequence: arrival.context.sequence,
+            flags: arrival.flags,
+        });
+        self.kept
+            .lock()
+            .unwrap()
+            .extend(arrival.retain_video_frame());
+        CallbackResult::Ok
+    }
+}
+
+fn start(
+    backend: &MockBackend,
+    handler: Arc<dyn InputHandler>,
+) -> (DecklinkInputDevice, MockInput) {
+    let mut input = common::open_input(
+        MODE,
+        FORMAT,
+        DecklinkVideoInputFlags::ENABLE_FORMAT_DETECTION,
+    );
+    common::enable_stereo_audio(&mut input);
+    common::start_streams(&mut input, handler);
+    (input, backend.input(0))
+}
+
+fn frame(n: u8) -> MockFrame {
+    MockFrame::for_mode(MODE, FORMAT)
+        .fill(n)
+        .stream_time(n as i64 * 1000, 1000, 25000)
+}
+
+#[test]
+fn a_callback_carries_its_frame_audio_and_context() {
+    let backend = common::mini_recorder();
+    let recorder = Arc::new(Recorder::default());
+    let (input, mock) = start(&backend, recorder.clone());
+
+    assert!(mock.deliver_frame_with_audio(frame(1), &SAMPLES).is_ok());
+    assert!(mock.deliver_audio(&SAMPLES).is_ok());
+    assert!(mock.deliver_frame(frame(2).conversion_fails()).is_ok());
+    assert!(mock.deliver_frame(frame(3)).is_ok());
+
+    assert_eq!(
+        *recorder.seen.lock().unwrap(),
+        [
+            Seen {
+                frame: Some(1),
+                sample_frames: Some(2),
+                stream_time: Some(1000),
+                sequence: 0,
+                flags: ArrivalFlags::empty(),
+            },
+            Seen {
+                frame: None,
+                sample_frames: Some(2),
+                stream_time: None,
+                sequence: 1,
+                flags: ArrivalFlags::empty(),
+            },
+            Seen {
+                frame: None,
+                sample_frames: None,
+                stream_time: Some(2000),
+                sequence: 2,
+                flags: ArrivalFlags::FRAME_LOST,
+            },
+            Seen {
+                frame: Some(3),
+                sample_frames: None,
+                stream_time: Some(3000),
+                sequence: 3,
+                flags: ArrivalFlags::empty(),
+            },
+        ]
+    );
+    assert_eq!(input.frame_conversion_failure_count(), 1);
+}
+
+#[test]
+fn the_first_callback_after_a_format_change_is_flagged() {
+    let backend = common::mini_recorder();
+    let recorder = Arc::new(Recorder::default());
+    let (_input, mock) = start(&backend, recorder.clone());
+
+    assert!(mock.deliver_frame(frame(1)).is_ok());
+    assert!(mock
+        .deliver_format_change(
+            DecklinkVideoInputFormatChangedEvents::DISPLAY_MODE_CHANGED,
+            DecklinkDisplayModeId::HD1080i50,
+            DecklinkDetectedVideoInputFormatFlags::empty(),
+        )
+        .is_ok());
+    assert!(mock.deliver_frame(frame(2)).is_ok());
+    assert!(mock.deliver_frame(frame(3)).is_ok());
+
+    let changes = recorder.changes.lock().unwrap();
+    assert_eq!(changes.len(), 1);
+    assert_eq!(changes[0].display_mode, DecklinkDisplayModeId::HD1080i50);
+    let flags: Vec<_> = recorder
+        .seen
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|s| s.flags)
+        .collect();
+    assert_eq!(
+        flags,
+        [
+            ArrivalFlags::empty(),
+            ArrivalFlags::AFTER_FORMAT_CHANGE,
+            ArrivalFlags::empty(),
+        ]
+    );
+}
+
+#[test]
+fn a_retained_frame_outlives_its_callback() {
+    let backend = common::mini_recorder();
+    let recorder = Arc::new(Recorder::default());
+    let (_input, mock) = start(&backend, recorder.clone());
+
+    assert!(mock.deliver_frame(frame(5)).is_ok());
+    assert!(mock.deliver_frame(frame(6)).is_ok());
+
+    let kept = recorder.kept.lock().unwrap();
+    let first_bytes: Vec<_> = kept.iter().map(|f| f.bytes().unwrap().0[0]).collect();
+    assert_eq!(first_bytes, [5, 6]);
+}
+
+#[derive(PartialEq, Debug)]
+enum Called {
+    Audio(usize),
+    Timing(Option<i64>),
+    Frame(Option<u8>),
+    ConversionFailed,
+}
+
+/// A callback written against the older trait.
+#[derive(Default)]
+struct Legacy {
+    called: Mutex<Vec<Called>>,
+}
+
+impl DeckLinkInputCallback for Legacy {
+    fn video_input_format_changed(
+        &self,
+        _events: DecklinkVideoInputFormatChangedEvents,
+        _new_display_mode: DecklinkDisplayModeId,
+        _detected_signal_flags: DecklinkDetectedVideoInputFormatFlags,
+    ) {
+    }
+
+    fn video_input_frame_arrived(&self, video_frame: Option<DecklinkVideoFrame>) -> bool {
+        let first = video_frame.map(|f| f.bytes().unwrap().0[0]);
+        self.called.lock().unwrap().push(Called::Frame(first));
+        true
+    }
+
+    fn video_input_frame_timing(&self, timing: DecklinkFrameTiming) {
+        let stream_time = timing.stream_time_in(25000).map(|t| t.value);
+        self.called
+            .lock()
+            .unwrap()
+            .push(Called::Timing(stream_time));
+    }
+
+    fn video_input_frame_conversion_failed(&self, _failure: FrameConversionFailure) {
+        self.called.lock().unwrap().push(Called::ConversionFailed);
+    }
+
+    fn audio_input_packet_arrived(&self, audio_packet: DecklinkAudioInputPacket) {
+        let sample_frames = audio_packet.sample_frame_count();
+        self.called
+            .lock()
+            .unwrap()
+            .push(Called::Audio(sample_frames));
+    }
+}
+
+#[test]
+fn a_shared_legacy_callback_is_called_as_before() {
+    let backend = common::mini_recorder();
+    let legacy = Arc::new(Legacy::default());
+    let shared: Arc<dyn DeckLinkInputCallback> = legacy.clone();
+    let (_input, mock) = start(&backend, Arc::new(shared));
+
+    assert!(mock.deliver_frame_with_audio(frame(1), &SAMPLES).is_ok());
+    assert!(mock.deliver_frame(frame(2).conversion_fails()).is_ok());
+
+    assert_eq!(
+        *legacy.called.lock().unwrap(),
+        [
+            Called::Audio(2),
+            Called::Timing(Some(1000)),
+            Called::Frame(Some(1)),
+            Called::ConversionFailed,
+        ]
+    );
+}
+
+#[test]
+fn a_legacy_callback_is_set_as_it_was_before_handlers() {
+    let backend = common::mini_recorder();
+    let legacy = Arc::new(Legacy::default());
+    let shared: Arc<dyn DeckLinkInputCallback> = legacy.clone();
+    let mut input = common::open_input(MODE, FORMAT, DecklinkVideoInputFlags::empty());
+    input.set_callback(Some(shared)).unwrap();
+    input.start_streams().unwrap();
+    let mock = backend.input(0);
+
+    assert!(mock.deliver_frame(frame(1)).is_ok());
+    input.set_callback(None).unwrap();
+    assert!(mock.deliver_frame(frame(2)).is_ok());
+
+    assert_eq!(
+        *legacy.called.lock().unwrap(),
+        [Called::Timing(Some(1000)), Called::Frame(Some(1))]
+    );
+}
@@ -0,0 +1,207 @@
+//! Callbacks a mock driver makes after streams are stopped, paused or disabled.
+#![cfg(feature = "mock-backend")]
+
+mod common;
+
+use decklink::device::input::{
+    DeckLinkInputCallback, DecklinkDetectedVideoInputFormatFlags, DecklinkInputDevice,
+    DecklinkVideoInputFlags, DecklinkVideoInputFormatChangedEvents,
+};
+use decklink::display_mode::DecklinkDisplayModeId;
+use decklink::frame::{DecklinkPixelFormat, DecklinkVideoFrame};
+use decklink::mock::{MockBackend, MockFrame, MockInput};
+use decklink::SdkError;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+const MODE: DecklinkDisplayModeId = DecklinkDisplayModeId::HD1080p25;
+const FORMAT: DecklinkPixelFormat = DecklinkPixelFormat::Format8BitYUV;
+
+/// Counts the callbacks the handler receives.
+#[derive(Default)]
+struct Counter {
+    frames: AtomicUsize,
+    format_changes: AtomicUsize,
+}
+
+impl DeckLinkInputCallback for Counter {
+    fn video_input_format_changed(
+        &self,
+        _events: DecklinkVideoInputFormatChangedEvents,
+        _new_display_mode: DecklinkDisplayModeId,
+        _detected_signal_flags: DecklinkDetectedVideoInputFormatFlags,
+    ) {
+        self.format_changes.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn video_input_frame_arrived(&self, _video_frame: Option<DecklinkVideoFrame>) -> bool {
+        self.frames.fetch_add(1, Ordering::SeqCst);
+        true
+    }
+}
+
+fn start(backend: &MockBackend) -> (DecklinkInputDevice, MockInput, Arc<Counter>) {
+    let counter = Arc::new(Counter::default());
+    let (input, mock) = common::start_input(
+        backend,
+        MODE,
+        FORMAT,
+        DecklinkVideoInputFlags::ENABLE_FORMAT_DETECTION,
+        counter.clone(),
+    );
+    mock.set_late_callbacks(true);
+    (input, mock, counter)
+}
+
+/// Deliver `count` frames, small enough that a busy driver calls back every few milliseconds.
+fn deliver(mock: &MockInput, count: usize) {
+    for _ in 0..count {
+        assert!(mock.deliver_frame(MockFrame::new(48, 2, FORMAT)).is_ok());
+    }
+}
+
+#[test]
+fn frames_after_stop_are_suppressed() {
+    let backend = common::mini_recorder();
+    let (input, mock, counter) = start(&backend);
+
+    deliver(&mock, 2);
+    input.stop_streams().unwrap();
+    assert!(!mock.is_streaming());
+    deliver(&mock, 3);
+
+    assert_eq!(counter.frames.load(Ordering::SeqCst), 2);
+    assert_eq!(input.suppressed_callback_count(), 3);
+}
+
+#[test]
+fn restarting_reopens_the_gate() {
+    let backend = common::mini_recorder();
+    let (input, mock, counter) = start(&backend);
+
+    deliver(&mock, 1);
+    input.stop_streams().unwrap();
+    deliver(&mock, 2);
+    input.start_streams().unwrap();
+    deliver(&mock, 2);
+    input.stop_streams().unwrap();
+    deliver(&mock, 1);
+
+    assert_eq!(counter.frames.load(Ordering::SeqCst), 3);
+    assert_eq!(input.suppressed_callback_count(), 3);
+}
+
+#[test]
+fn format_change_after_stop_is_suppressed() {
+    let backend = common::mini_recorder();
+    let (input, mock, counter) = start(&backend);
+
+    input.stop_streams().unwrap();
+    assert!(mock
+        .deliver_format_change(
+            DecklinkVideoInputFormatChangedEvents::DISPLAY_MODE_CHANGED,
+            DecklinkDisplayModeId::HD1080p5994,
+            DecklinkDetectedVideoInputFormatFlags::YCBCR_422,
+        )
+        .is_ok());
+
+    assert_eq!(counter.format_changes.load(Ordering::SeqCst), 0);
+    assert_eq!(input.suppressed_callback_count(), 1);
+}
+
+#[test]
+fn pausing_and_disabling_close_the_gate() {
+    let backend = common::mini_recorder();
+    let (mut input, mock, counter) = start(&backend);
+
+    input.pause_streams().unwrap();
+    deliver(&mock, 1);
+    assert_eq!(input.suppressed_callback_count(), 1);
+
+    input.stop_streams().unwrap();
+    input.start_streams().unwrap();
+    deliver(&mock, 1);
+    assert_eq!(counter.frames.load(Ordering::SeqCst), 1);
+
+    input.disable_video_input().unwrap();
+    deliver(&mock, 1);
+    assert_eq!(counter.frames.load(Ordering::SeqCst), 1);
+    assert_eq!(input.suppressed_callback_count(), 2);
+}
+
+#[test]
+fn without_late_callbacks_nothing_is_suppressed() {
+    let backend = common::mini_recorder();
+    let (input, mock, counter) = start(&backend);
+    mock.set_late_callbacks(false);
+
+    deliver(&mock, 1);
+    input.stop_streams().unwrap();
+    assert!(!mock.deliver_frame(MockFrame::new(48, 2, FORMAT)).is_ok());
+
+    assert_eq!(counter.frames.load(Ordering::SeqCst), 1);
+    assert_eq!(input.suppressed_callback_count(), 0);
+}
+
+#[test]
+fn quiesced_stop_returns_when_quiet() {
+    let backend = common::mini_recorder();
+    let (input, mock, counter) = start(&backend);
+
+    deliver(&mock, 1);
+    input
+        .stop_streams_quiesced(Some(Duration::from_millis(20)))
+        .unwrap();
+    assert_eq!(counter.frames.load(Ordering::SeqCst), 1);
+    assert_eq!(input.suppressed_callback_count(), 0);
+}
+
+#[test]
+fn quiesced_stop_waits_for_late_frames() {
+    let backend = common::mini_recorder();
+    let (input, mock, counter) = start(&backend);
+
+    let driver = std::thread::spawn(move || {
+        for _ in 0..3 {
+            deliver(&mock, 1);
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    });
+    std::thread::sleep(Duration::from_millis(5));
+    input
+        .stop_streams_quiesced(Some(Duration::from_millis(100)))
+        .unwrap();
+    driver.join().unwrap();
+
+    // Every frame arrived before the wait returned
+    let suppressed = input.suppressed_callback_count();
+    assert_eq!(counter.frames.load(Ordering::SeqCst) as u64 + suppressed, 3);
+    assert!(suppressed >= 2);
+}
+
+#[test]
+fn quiesced_stop_gives_up_on_a_busy_driver() {
+    let backend = common::mini_recorder();
+    let (input, mock, counter) = start(&backend);
+
+    let done = Arc::new(AtomicBool::new(false));
+    let driver = {
+        let done = done.clone();
+        std::thread::spawn(move || {
+            while !done.load(Ordering::SeqCst) {
+                deliver(&mock, 1);
+                std::thread::sleep(Duration::from_millis(2));
+            }
+        })
+    };
+    while counter.frames.load(Ordering::SeqCst) == 0 {
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    let result = input.stop_streams_quiesced(Some(Duration::from_millis(20)));
+    done.store(true, Ordering::SeqCst);
+    driver.join().unwrap();
+
+    assert!(matches!(result, Err(SdkError::ABORT)));
+    assert!(input.suppressed_callback_count() > 0);
+}
@@ -0,0 +1,267 @@
+//! The settings of the latency profiles, and the percentiles and events of a
+//! `LatencyMeter`, fed directly and from a mock capture.
+
+#[cfg(feature = "mock-backend")]
+mod common;
+
+use decklink::batch::BatchConfig;
+use decklink::latency::{
+    FrameLatency, LatencyBound, LatencyConflict, LatencyEvent, LatencyMeter, LatencyProfile,
+    LatencyStage, StageStats,
+};
+use decklink::retention::RetentionLimit;
+use decklink::tap::TapSpec;
+use std::time::Duration;
+
+fn ms(ms: u64) -> Duration {
+    Duration::from_millis(ms)
+}
+
+fn handler(ms: u64) -> FrameLatency {
+    FrameLatency {
+        handler: Duration::from_millis(ms),
+        ..FrameLatency::default()
+    }
+}
+
+#[test]
+fn low_latency_chooses_the_settings_that_add_least() {
+    let profile = LatencyProfile::LowLatency;
+    let spec = profile.tap_spec();
+    assert_eq!(spec.retention, RetentionLimit::Frames(1));
+    assert_eq!(spec.decimation, TapSpec::default().decimation);
+    assert_eq!(profile.detection_debounce_frames(), 0);
+    assert!(profile.check_tap(&spec).is_ok());
+
+    assert_eq!(LatencyProfile::default(), LatencyProfile::Default);
+    assert_eq!(LatencyProfile::Default.tap_spec(), TapSpec::default());
+    assert_eq!(LatencyProfile::Default.detection_debounce_frames(), 2);
+}
+
+#[test]
+fn conflicting_settings_are_refused_with_the_conflict_named() {
+    let profile = LatencyProfile::LowLatency;
+
+    let batch = BatchConfig::default();
+    assert!(LatencyProfile::Default.check_batch(&batch).is_ok());
+    let error = profile.check_batch(&batch).unwrap_err();
+    assert_eq!(error, LatencyConflict::Batching { max_frames: 4 });
+    assert_eq!(
+        error.to_string(),
+        "batching 4 frames conflicts with low latency, which dispatches directly"
+    );
+
+    let decimated = TapSpec {
+        decimation: 3,
+        ..profile.tap_spec()
+    };
+    assert_eq!(
+        profile.check_tap(&decimated),
+        Err(LatencyConflict::Decimation { decimation: 3 })
+    );
+
+    for limit in [RetentionLimit::Frames(3), RetentionLimit::Bytes(1 << 20)] {
+        let retaining = TapSpec {
+            retention: limit,
+            ..TapSpec::default()
+        };
+        assert_eq!(
+            profile.check_tap(&retaining),
+            Err(LatencyConflict::Retention { limit })
+        );
+        assert!(LatencyProfile::Default.check_tap(&retaining).is_ok());
+    }
+    let two = TapSpec {
+        retention: RetentionLimit::Frames(2),
+        ..TapSpec::default()
+    };
+    assert!(profile.check_tap(&two).is_ok());
+}
+
+#[test]
+fn percentiles_are_taken_over_the_window() {
+    let meter = LatencyMeter::new(10);
+    assert_eq!(meter.stats().total, StageStats::default());
+    for n in 1..=20 {
+        meter.record(FrameLatency {
+            queue_wait: ms(1),
+            ..handler(n)
+        });
+    }
+
+    let stats = meter.stats();
+    assert_eq!((stats.frames, stats.window), (20, 10));
+    assert_eq!(
+        stats.handler,
+        StageStats {
+            p50: ms(15),
+            p99: ms(20),
+            max: ms(20),
+        }
+    );
+    assert_eq!(
+        stats.queue_wait,
+        StageStats {
+            p50: ms(1),
+            p99: ms(1),
+            max: ms(1),
+        }
+    );
+    assert_eq!(stats.conversion, StageStats::default());
+    assert_eq!(stats.total.p99, ms(21));
+}
+
+#[test]
+fn exceeding_the_bound_names_the_stage_that_grew() {
+    let meter = LatencyMeter::new(100);
+    meter.set_bound(Some(LatencyBound {
+        p99: ms(10),
+        sustained: Duration::ZERO,
+    }));
+
+    // Within the bound, then queue waits grow
+    for _ in 0..32 {
+        meter.record(handler(1));
+    }
+    for _ in 0..32 {
+        meter.record(FrameLatency {
+            queue_wait: ms(20),
+            ..handler(2)
+        });
+    }
+    assert_eq!(
+        meter.take_events(),
+        [LatencyEvent::BoundExceeded {
+            p99: ms(22),
+            stage: LatencyStage::QueueWait,
+            stage_p99: ms(20),
+        }]
+    );
+
+    // Reported once, then recovered once the slow frames leave the window
+    for _ in 0..128 {
+        meter.record(handler(1));
+    }
+    assert_eq!(
+        meter.take_events(),
+        [LatencyEvent::BoundRecovered { p99: ms(1) }]
+    );
+}
+
+#[test]
+fn brief_excursions_are_not_reported() {
+    let meter = LatencyMeter::new(32);
+    meter.set_bound(Some(LatencyBound {
+        p99: ms(10),
+        sustained: Duration::from_secs(3600),
+    }));
+    for _ in 0..64 {
+        meter.record(handler(50));
+    }
+    assert!(meter.take_events().is_empty());
+
+    // Without a bound, nothing is checked
+    let meter = LatencyMeter::new(32);
+    for _ in 0..64 {
+        meter.record(handler(50));
+    }
+    assert!(meter.take_events().is_empty());
+}
+
+#[cfg(feature = "mock-backend")]
+mod mock {
+    use super::common;
+    use super::*;
+    use decklink::device::input::{
+        DeckLinkInputCallback, DecklinkDetectedVideoInputFormatFlags, DecklinkInputDevice,
+        DecklinkVideoInputFlags, DecklinkVideoInputFormatChangedEvents, InputHandler,
+    };
+    use decklink::display_mode::DecklinkDisplayModeId;
+    use decklink::frame::{DecklinkPixelFormat, DecklinkVideoFrame};
+    use decklink::mock::{MockBackend, MockFrame, MockInput};
+    use decklink::tap::{DeckLinkTapCallback, TapSplitter, TappedFrame};
+    use std::sync::mpsc::{channel, Receiver};
+    use std::sync::Arc;
+
+    const FORMAT: DecklinkPixelFormat = DecklinkPixelFormat::Format8BitYUV;
+
+    /// A handler that takes 2 ms over each frame.
+    struct Slow;
+
+    impl DeckLinkInputCallback for Slow {
+        fn video_input_format_changed(
+            &self,
+            _events: DecklinkVideoInputFormatChangedEvents,
+            _new_display_mode: DecklinkDisplayModeId,
+            _detected_signal_flags: DecklinkDetectedVideoInputFormatFlags,
+        ) {
+        }
+
+        fn video_input_frame_arrived(&self, _video_frame: Option<DecklinkVideoFrame>) -> bool {
+            std::thread::sleep(ms(2));
+            true
+        }
+    }
+
+    fn start(
+        backend: &MockBackend,
+        callback: Arc<dyn InputHandler>,
+    ) -> (DecklinkInputDevice, MockInput) {
+        let mut input = common::open_input(
+            DecklinkDisplayModeId::HD1080p25,
+            FORMAT,
+            DecklinkVideoInputFlags::empty(),
+        );
+        common::start_streams(&mut input, callback);
+        (input, backend.input(0))
+    }
+
+    #[test]
+    fn handler_time_is_measured_in_the_callback() {
+        let backend = common::mini_recorder();
+        let meter = LatencyMeter::new(16);
+        let (_input, mock) = start(&backend, meter.measured(Arc::new(Slow)));
+
+        for _ in 0..5 {
+            assert!(mock.deliver_frame(MockFrame::new(48, 2, FORMAT)).is_ok());
+        }
+        let stats = meter.stats();
+        assert_eq!(stats.frames, 5);
+        assert!(stats.handler.p50 >= ms(2));
+        assert_eq!(stats.total, stats.handler);
+        assert_eq!(stats.queue_wait, StageStats::default());
+    }
+
+    /// A tap that waits for the test before taking each frame.
+    struct HeldTap(Receiver<()>);
+
+    impl DeckLinkTapCallback for HeldTap {
+        fn frame_tapped(&mut self, _frame: TappedFrame) {
+            let _ = self.0.recv();
+        }
+    }
+
+    #[test]
+    fn low_latency_taps_drop_rather_than_fall_behind() {
+        let backend = common::mini_recorder();
+        let meter = LatencyMeter::new(16);
+        let splitter = TapSplitter::new(meter.measured(Arc::new(Slow)), None);
+        let (_input, mock) = start(&backend, splitter.callback());
+
+        let spec = LatencyProfile::LowLatency.tap_spec();
+        assert!(LatencyProfile::LowLatency.check_tap(&spec).is_ok());
+        let (release, hold) = channel();
+        let tap = splitter.tap(HeldTap(hold), spec);
+        for _ in 0..4 {
+            assert!(mock.deliver_frame(MockFrame::new(48, 2, FORMAT)).is_ok());
+        }
+        drop(release);
+        tap.cancel();
+
+        // The tap holds one frame while it is busy, so the rest are dropped rather than
+        // queued behind it
+        let report = tap.join();
+        assert_eq!((report.delivered, report.dropped), (1, 3));
+        assert_eq!(meter.stats().frames, 4);
+    }
+}
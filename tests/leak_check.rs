@@ -0,0 +1,232 @@
+//! Repeated open/capture/close cycles against the mock backend, checking that every wrapper
+//! and every C object the crate was given is released.
+#![cfg(all(feature = "mock-backend", feature = "leak-check"))]
+
+mod common;
+
+use decklink::allocator::{
+    BufferSpec, VideoBuffer, VideoBufferAllocator, VideoBufferAllocatorProvider,
+};
+use decklink::debug;
+use decklink::device::get_devices;
+use decklink::device::input::{
+    DeckLinkInputCallback, DecklinkDetectedVideoInputFormatFlags, DecklinkVideoInputFlags,
+    DecklinkVideoInputFormatChangedEvents,
+};
+use decklink::display_mode::DecklinkDisplayModeId;
+use decklink::frame::{DecklinkPixelFormat, DecklinkVideoFrame};
+use decklink::mock::MockBackend;
+use decklink::{ApiVersion, SdkError};
+use std::cell::RefCell;
+use std::ffi::c_void;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+const MODE: DecklinkDisplayModeId = DecklinkDisplayModeId::NTSC;
+const FORMAT: DecklinkPixelFormat = DecklinkPixelFormat::Format8BitYUV;
+
+struct HeapBuffer(Mutex<Vec<u8>>);
+
+impl VideoBuffer for HeapBuffer {
+    fn get_bytes(&self) -> Result<*mut c_void, SdkError> {
+        Ok(self.0.lock().unwrap().as_mut_ptr() as *mut c_void)
+    }
+}
+
+struct HeapAllocator(usize);
+
+impl VideoBufferAllocator for HeapAllocator {
+    fn allocate(&self) -> Result<Box<dyn VideoBuffer>, SdkError> {
+        Ok(Box::new(HeapBuffer(Mutex::new(vec![0; self.0]))))
+    }
+}
+
+struct HeapProvider;
+
+impl VideoBufferAllocatorProvider for HeapProvider {
+    fn get_allocator(&self, spec: BufferSpec) -> Result<Arc<dyn VideoBufferAllocator>, SdkError> {
+        Ok(Arc::new(HeapAllocator(spec.buffer_size as usize)))
+    }
+}
+
+thread_local! {
+    // The mock delivers frames on the thread of the test, and frames are not Send
+    static RETAINED: RefCell<Option<DecklinkVideoFrame>> = const { RefCell::new(None) };
+}
+
+/// Counts the frames that arrive, optionally keeping the last one.
+#[derive(Default)]
+struct Capture {
+    frames: AtomicUsize,
+    retain: bool,
+}
+
+impl DeckLinkInputCallback for Capture {
+    fn video_input_format_changed(
+        &self,
+        _events: DecklinkVideoInputFormatChangedEvents,
+        _new_display_mode: DecklinkDisplayModeId,
+        _detected_signal_flags: DecklinkDetectedVideoInputFormatFlags,
+    ) {
+    }
+
+    fn video_input_frame_arrived(&self, video_frame: Option<DecklinkVideoFrame>) -> bool {
+        let frame = video_frame.unwrap();
+        assert!(frame.bytes_to_vec().unwrap().iter().all(|b| *b == 0x80));
+        self.frames.fetch_add(1, Ordering::SeqCst);
+        if self.retain {
+            RETAINED.with(|r| *r.borrow_mut() = Some(frame));
+        }
+        true
+    }
+}
+
+/// A single recorder, with drivers new enough to take an allocator provider.
+fn backend() -> MockBackend {
+    let backend = common::mini_recorder();
+    backend.set_api_version(Some(ApiVersion::new(14, 3, 0)));
+    backend
+}
+
+/// Open the first device, capture `frames` frames through an allocator provider, and close it.
+fn capture_cycle(backend: &MockBackend, capture: Arc<Capture>, frames: usize) {
+    let devices = get_devices().unwrap();
+    let mut input = devices[0].input().unwrap();
+    input
+        .enable_video_input_with_allocator(
+            MODE,
+            FORMAT,
+            DecklinkVideoInputFlags::empty(),
+            Arc::new(HeapProvider),
+        )
+        .unwrap();
+    common::start_streams(&mut input, capture);
+
+    let mock = backend.input(0);
+    for _ in 0..frames {
+        assert!(mock.deliver_frame(mock.frame().fill(0x80)).is_ok());
+    }
+
+    input.stop_streams().unwrap();
+}
+
+#[test]
+fn open_capture_close_loop_leaks_nothing() {
+    let backend = backend();
+    let capture = Arc::new(Capture::default());
+
+    for _ in 0..1000 {
+        capture_cycle(&backend, capture.clone(), 3);
+    }
+
+    assert_eq!(capture.frames.load(Ordering::SeqCst), 3000);
+    assert_eq!(debug::live_objects(), Vec::new());
+    assert!(debug::assert_no_leaks().is_ok());
+    assert_eq!(MockBackend::live_objects(), 0);
+}
+
+#[test]
+fn retained_frame_is_reported() {
+    let backend = backend();
+    let capture = Arc::new(Capture {
+        retain: true,
+        ..Default::default()
+    });
+
+    capture_cycle(&backend, capture, 1);
+
+    // The frame outlives the device it was captured from, holding the buffer it was captured into
+    let report = debug::assert_no_leaks().unwrap_err();
+    assert_eq!(
+        report.objects,
+        vec![("DecklinkVideoFrame", 1), ("VideoBufferContext", 1)]
+    );
+    assert!(report.to_string().contains("DecklinkVideoFrame: 1"));
+    assert!(MockBackend::live_objects() > 0);
+
+    RETAINED.with(|r| r.borrow_mut().take());
+    assert!(debug::assert_no_leaks().is_ok());
+    assert_eq!(MockBackend::live_objects(), 0);
+}
+
+#[test]
+fn a_buffer_size_change_leaks_neither_generation() {
+    let backend = backend();
+    let capture = Arc::new(Capture::default());
+
+    for _ in 0..100 {
+        let devices = get_devices().unwrap();
+        let mut input = devices[0].input().unwrap();
+        input
+            .enable_video_input_with_allocator(
+                MODE,
+                FORMAT,
+                DecklinkVideoInputFlags::empty(),
+                Arc::new(HeapProvider),
+            )
+            .unwrap();
+        common::start_streams(&mut input, capture.clone());
+
+        let mock = backend.input(0);
+        mock.set_buffer_padding(0);
+        assert!(mock.deliver_frame(mock.frame().fill(0x80)).is_ok());
+        mock.set_buffer_padding(256);
+        assert!(mock.deliver_frame(mock.frame().fill(0x80)).is_ok());
+        assert_eq!(mock.allocator_count(), 2);
+
+        input.stop_streams().unwrap();
+    }
+
+    assert_eq!(capture.frames.load(Ordering::SeqCst), 200);
+    assert!(debug::assert_no_leaks().is_ok());
+    assert_eq!(MockBackend::live_objects(), 0);
+}
+
+#[test]
+fn a_failed_enable_releases_its_provider_before_input_can_be_enabled_again() {
+    let backend = backend();
+    let mock = backend.input(0);
+    let devices = get_devices().unwrap();
+    let mut input = devices[0].input().unwrap();
+    let provider: Arc<dyn VideoBufferAllocatorProvider> = Arc::new(HeapProvider);
+    let enable = |input: &mut decklink::device::input::DecklinkInputDevice, mode| {
+        input.enable_video_input_with_allocator(
+            mode,
+            FORMAT,
+            DecklinkVideoInputFlags::empty(),
+            provider.clone(),
+        )
+    };
+
+    for _ in 0..100 {
+        // The driver refuses the mode after the provider was created
+        assert_eq!(
+            enable(&mut input, DecklinkDisplayModeId::UHD4K2160p60),
+            Err(SdkError::INVALIDARG)
+        );
+        assert!(!mock.has_allocator_provider());
+        assert_eq!(Arc::strong_count(&provider), 1);
+
+        // The provider is not created at all
+        backend.fail_allocator_providers(true);
+        assert_eq!(enable(&mut input, MODE), Err(SdkError::OUTOFMEMORY));
+        backend.fail_allocator_providers(false);
+        assert!(!mock.has_allocator_provider());
+        assert_eq!(Arc::strong_count(&provider), 1);
+
+        enable(&mut input, MODE).unwrap();
+        assert!(mock.has_allocator_provider());
+        assert_eq!(
+            input.enable_video_input(MODE, FORMAT, DecklinkVideoInputFlags::empty()),
+            Err(SdkError::ACCESSDENIED)
+        );
+        input.disable_video_input().unwrap();
+        assert!(!mock.has_allocator_provider());
+        assert_eq!(Arc::strong_count(&provider), 1);
+    }
+
+    drop(input);
+    drop(devices);
+    assert!(debug::assert_no_leaks().is_ok());
+    assert_eq!(MockBackend::live_objects(), 0);
+}
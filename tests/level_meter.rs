@@ -0,0 +1,307 @@
+//! Audio level metering of synthesized signals, and of packets delivered by a mock input.
+
+#[cfg(feature = "mock-backend")]
+mod common;
+
+use decklink::audio::{ChannelLevel, LevelMeter};
+use std::f64::consts::PI;
+use std::time::Duration;
+
+const RATE: u32 = 48000;
+const WINDOW: Duration = Duration::from_millis(300);
+
+/// One window of a 1 kHz sine of `amplitude` relative to full scale, on every channel.
+fn sine(amplitude: f64, full_scale: f64, channels: usize) -> Vec<f64> {
+    let frames = (RATE as f64 * WINDOW.as_secs_f64()) as usize;
+    (0..frames)
+        .flat_map(|i| {
+            let value = amplitude * full_scale * (2.0 * PI * 1000.0 * i as f64 / RATE as f64).sin();
+            std::iter::repeat_n(value.round(), channels)
+        })
+        .collect()
+}
+
+/// One window of a 1 kHz square wave of `amplitude` relative to full scale, on one channel.
+fn square(amplitude: f64, full_scale: f64) -> Vec<f64> {
+    let frames = (RATE as f64 * WINDOW.as_secs_f64()) as usize;
+    (0..frames)
+        .map(|i| {
+            let sign = if (i / 24) % 2 == 0 { 1.0 } else { -1.0 };
+            (sign * amplitude * full_scale).round()
+        })
+        .collect()
+}
+
+fn i16s(samples: &[f64]) -> Vec<i16> {
+    samples
+        .iter()
+        .map(|s| s.clamp(i16::MIN as f64, i16::MAX as f64) as i16)
+        .collect()
+}
+
+fn i32s(samples: &[f64]) -> Vec<i32> {
+    samples
+        .iter()
+        .map(|s| s.clamp(i32::MIN as f64, i32::MAX as f64) as i32)
+        .collect()
+}
+
+fn assert_db(actual: f64, expected: f64) {
+    assert!(
+        (actual - expected).abs() < 0.1,
+        "{} dBFS is not within 0.1 dB of {} dBFS",
+        actual,
+        expected
+    );
+}
+
+#[test]
+fn sine_levels_i16() {
+    let mut meter = LevelMeter::new(RATE, WINDOW, 0.0);
+    meter.feed_i16(&i16s(&sine(0.5, 32768.0, 2)), 2);
+
+    let levels = meter.levels();
+    assert_eq!(levels.len(), 2);
+    for level in levels {
+        assert_db(level.peak_dbfs, -6.02);
+        assert_db(level.rms_dbfs, -9.03);
+        assert_eq!(level.clipped_samples, 0);
+    }
+}
+
+#[test]
+fn sine_levels_i32() {
+    let mut meter = LevelMeter::new(RATE, WINDOW, 0.0);
+    meter.feed_i32(&i32s(&sine(0.1, 2147483648.0, 16)), 16);
+
+    let levels = meter.levels();
+    assert_eq!(levels.len(), 16);
+    for level in levels {
+        assert_db(level.peak_dbfs, -20.0);
+        assert_db(level.rms_dbfs, -23.01);
+    }
+}
+
+#[test]
+fn square_wave_peak_equals_rms() {
+    let mut meter = LevelMeter::new(RATE, WINDOW, 0.0);
+    meter.feed_i16(&i16s(&square(0.25, 32768.0)), 1);
+
+    let level = meter.levels()[0];
+    assert_db(level.peak_dbfs, -12.04);
+    assert_db(level.rms_dbfs, -12.04);
+}
+
+#[test]
+fn full_scale_samples_are_clipped() {
+    let mut meter = LevelMeter::new(RATE, WINDOW, 0.0);
+    // A full scale square wave is at full scale on every sample
+    let samples = i16s(&square(1.0, 32768.0));
+    meter.feed_i16(&samples, 1);
+
+    let level = meter.levels()[0];
+    assert_eq!(level.clipped_samples, samples.len() as u64);
+    assert_db(level.peak_dbfs, 0.0);
+
+    // A sine just below full scale does not clip
+    let mut meter = LevelMeter::new(RATE, WINDOW, 0.0);
+    meter.feed_i32(&i32s(&sine(0.99, 2147483648.0, 1)), 1);
+    assert_eq!(meter.levels()[0].clipped_samples, 0);
+}
+
+#[test]
+fn levels_update_once_per_window() {
+    let mut meter = LevelMeter::new(RATE, WINDOW, 0.0);
+    let samples = i16s(&sine(0.5, 32768.0, 1));
+
+    meter.feed_i16(&samples[..samples.len() / 2], 1);
+    assert_eq!(
+        meter.levels(),
+        vec![ChannelLevel {
+            peak_dbfs: f64::NEG_INFINITY,
+            rms_dbfs: f64::NEG_INFINITY,
+            clipped_samples: 0,
+        }]
+    );
+
+    meter.feed_i16(&samples[samples.len() / 2..], 1);
+    assert_db(meter.levels()[0].rms_dbfs, -9.03);
+}
+
+#[test]
+fn peak_falls_at_the_configured_rate() {
+    // 10 dB per second falls 3 dB per 300 ms window
+    let mut meter = LevelMeter::new(RATE, WINDOW, 10.0);
+    meter.feed_i16(&i16s(&sine(0.5, 32768.0, 1)), 1);
+    assert_db(meter.levels()[0].peak_dbfs, -6.02);
+
+    let quiet = i16s(&sine(0.01, 32768.0, 1));
+    meter.feed_i16(&quiet, 1);
+    assert_db(meter.levels()[0].peak_dbfs, -9.02);
+    meter.feed_i16(&quiet, 1);
+    assert_db(meter.levels()[0].peak_dbfs, -12.02);
+    // The RMS follows the signal without ballistics
+    assert_db(meter.levels()[0].rms_dbfs, -43.01);
+
+    // The peak never falls below the level of the signal
+    for _ in 0..20 {
+        meter.feed_i16(&quiet, 1);
+    }
+    assert_db(meter.levels()[0].peak_dbfs, -40.0);
+
+    // A louder window raises it at once
+    meter.feed_i16(&i16s(&sine(0.5, 32768.0, 1)), 1);
+    assert_db(meter.levels()[0].peak_dbfs, -6.02);
+}
+
+#[test]
+fn channel_count_change_resets() {
+    let mut meter = LevelMeter::new(RATE, WINDOW, 0.0);
+    meter.feed_i16(&i16s(&square(1.0, 32768.0)), 1);
+    assert!(meter.levels()[0].clipped_samples > 0);
+
+    meter.feed_i16(&i16s(&sine(0.5, 32768.0, 8)), 8);
+    let levels = meter.levels();
+    assert_eq!(levels.len(), 8);
+    assert!(levels.iter().all(|l| l.clipped_samples == 0));
+    assert_db(levels[0].peak_dbfs, -6.02);
+
+    meter.reset();
+    assert!(meter.levels().is_empty());
+}
+
+#[cfg(feature = "mock-backend")]
+mod mock {
+    use super::common;
+    use super::{assert_db, i16s, i32s, sine, RATE, WINDOW};
+    use decklink::audio::LevelMeter;
+    use decklink::device::get_devices;
+    use decklink::device::input::{
+        DeckLinkInputCallback, DecklinkAudioInputPacket, DecklinkAudioSampleRate,
+        DecklinkAudioSampleType, DecklinkDetectedVideoInputFormatFlags,
+        DecklinkVideoInputFormatChangedEvents,
+    };
+    use decklink::display_mode::DecklinkDisplayModeId;
+    use decklink::frame::DecklinkVideoFrame;
+    use std::sync::{Arc, Mutex};
+
+    /// Feeds every packet to a meter, recording the size and time of each.
+    struct Meter {
+        meter: Mutex<LevelMeter>,
+        packets: Mutex<Vec<(DecklinkAudioSampleType, u32, usize, i64)>>,
+    }
+
+    impl Meter {
+        fn new() -> Meter {
+            Meter {
+                meter: Mutex::new(LevelMeter::new(RATE, WINDOW, 0.0)),
+                packets: Mutex::new(Vec::new()),
+            }
+        }
+    }
+
+    impl DeckLinkInputCallback for Meter {
+        fn video_input_format_changed(
+            &self,
+            _events: DecklinkVideoInputFormatChangedEvents,
+            _new_display_mode: DecklinkDisplayModeId,
+            _detected_signal_flags: DecklinkDetectedVideoInputFormatFlags,
+        ) {
+        }
+
+        fn video_input_frame_arrived(&self, video_frame: Option<DecklinkVideoFrame>) -> bool {
+            assert!(video_frame.is_none());
+            true
+        }
+
+        fn audio_input_packet_arrived(&self, audio_packet: DecklinkAudioInputPacket) {
+            self.meter
+                .lock()
+                .unwrap()
+                .feed_packet(&audio_packet)
+                .unwrap();
+            self.packets.lock().unwrap().push((
+                audio_packet.sample_type(),
+                audio_packet.channel_count(),
+                audio_packet.sample_frame_count(),
+                audio_packet.packet_time(RATE as i64).unwrap(),
+            ));
+        }
+    }
+
+    fn bytes<T: Copy, const N: usize>(samples: &[T], to_bytes: fn(T) -> [u8; N]) -> Vec<u8> {
+        samples.iter().flat_map(|s| to_bytes(*s)).collect()
+    }
+
+    #[test]
+    fn packets_are_metered() {
+        let backend = common::mini_recorder();
+        let devices = get_devices().unwrap();
+        let mut input = devices[0].input().unwrap();
+        let meter = Arc::new(Meter::new());
+        common::enable_stereo_audio(&mut input);
+        common::start_streams(&mut input, meter.clone());
+
+        let mock = backend.input(0);
+        assert!(mock.is_audio_enabled());
+        let samples = bytes(&i16s(&sine(0.5, 32768.0, 2)), i16::to_ne_bytes);
+        // Delivered in two packets of half a window each
+        let (first, second) = samples.split_at(samples.len() / 2);
+        assert!(mock.deliver_audio(first).is_ok());
+        assert!(mock.deliver_audio(second).is_ok());
+
+        assert_eq!(
+            *meter.packets.lock().unwrap(),
+            vec![
+                (DecklinkAudioSampleType::Int16, 2, 7200, 0),
+                (DecklinkAudioSampleType::Int16, 2, 7200, 7200),
+            ]
+        );
+        let levels = meter.meter.lock().unwrap().levels();
+        assert_eq!(levels.len(), 2);
+        for level in levels {
+            assert_db(level.peak_dbfs, -6.02);
+            assert_db(level.rms_dbfs, -9.03);
+        }
+    }
+
+    #[test]
+    fn reenabling_with_other_channels_resets_the_meter() {
+        let backend = common::mini_recorder();
+        let devices = get_devices().unwrap();
+        let mut input = devices[0].input().unwrap();
+        let meter = Arc::new(Meter::new());
+        common::enable_stereo_audio(&mut input);
+        common::start_streams(&mut input, meter.clone());
+
+        let mock = backend.input(0);
+        let samples = bytes(&i16s(&sine(0.5, 32768.0, 2)), i16::to_ne_bytes);
+        assert!(mock.deliver_audio(&samples).is_ok());
+        assert_eq!(meter.meter.lock().unwrap().levels().len(), 2);
+
+        input.stop_streams().unwrap();
+        input.disable_audio_input().unwrap();
+        input
+            .enable_audio_input(
+                DecklinkAudioSampleRate::Rate48kHz,
+                DecklinkAudioSampleType::Int32,
+                16,
+            )
+            .unwrap();
+        input.start_streams().unwrap();
+
+        let samples = bytes(&i32s(&sine(0.1, 2147483648.0, 16)), i32::to_ne_bytes);
+        assert!(mock.deliver_audio(&samples).is_ok());
+
+        assert_eq!(
+            meter.packets.lock().unwrap().last(),
+            Some(&(DecklinkAudioSampleType::Int32, 16, 14400, 0))
+        );
+        let levels = meter.meter.lock().unwrap().levels();
+        assert_eq!(levels.len(), 16);
+        for level in levels {
+            assert_db(level.peak_dbfs, -20.0);
+            assert_db(level.rms_dbfs, -23.01);
+        }
+    }
+}
@@ -0,0 +1,329 @@
+//! Bandwidth of display modes against SDI links, and frame sizes at 8K.
+
+use decklink::frame::{DecklinkFrameBase, DecklinkPixelFormat};
+use decklink::link::{
+    check_bandwidth, required_bandwidth, DecklinkLinkConfiguration, LinkBandwidthWarning,
+    SDI_12G_BIT_RATE,
+};
+use decklink::testing::TestFrameBuilder;
+use decklink::time::DecklinkTime;
+
+const P60: DecklinkTime = DecklinkTime {
+    value: 1000,
+    scale: 60000,
+};
+
+#[test]
+fn bandwidth_is_estimated_from_the_frame_size() {
+    // 1080p60 8-bit YUV is 2 bytes a pixel
+    assert_eq!(
+        required_bandwidth(1920, 1080, P60, DecklinkPixelFormat::Format8BitYUV),
+        Some(1920 * 2 * 1080 * 8 * 60)
+    );
+    // v210 rows are padded to groups of 48 pixels in 128 bytes
+    assert_eq!(
+        required_bandwidth(7680, 4320, P60, DecklinkPixelFormat::Format10BitYUV),
+        Some(20480 * 4320 * 8 * 60)
+    );
+    let p5994 = DecklinkTime::new(1001, 60000);
+    assert_eq!(
+        required_bandwidth(1920, 1080, p5994, DecklinkPixelFormat::Format8BitYUV),
+        Some(1920 * 2 * 1080 * 8 * 60000 / 1001)
+    );
+}
+
+#[test]
+fn bandwidth_of_compressed_formats_and_invalid_durations_is_unknown() {
+    assert_eq!(
+        required_bandwidth(3840, 2160, P60, DecklinkPixelFormat::FormatH265),
+        None
+    );
+    for duration in [DecklinkTime::new(0, 60000), DecklinkTime::new(1000, 0)] {
+        assert_eq!(
+            required_bandwidth(1920, 1080, duration, DecklinkPixelFormat::Format8BitYUV),
+            None
+        );
+    }
+    // Too many bits per second for a u64
+    assert_eq!(
+        required_bandwidth(
+            usize::MAX,
+            usize::MAX,
+            P60,
+            DecklinkPixelFormat::Format8BitBGRA
+        ),
+        None
+    );
+}
+
+#[test]
+fn fewest_links_are_found() {
+    use DecklinkLinkConfiguration::*;
+    assert_eq!(QuadLink.bit_rate(), 4 * SDI_12G_BIT_RATE);
+    assert_eq!(DecklinkLinkConfiguration::minimum_for(1), Some(SingleLink));
+    assert_eq!(
+        DecklinkLinkConfiguration::minimum_for(SDI_12G_BIT_RATE),
+        Some(SingleLink)
+    );
+    assert_eq!(
+        DecklinkLinkConfiguration::minimum_for(SDI_12G_BIT_RATE + 1),
+        Some(DualLink)
+    );
+    assert_eq!(
+        DecklinkLinkConfiguration::minimum_for(3 * SDI_12G_BIT_RATE),
+        Some(QuadLink)
+    );
+    assert_eq!(
+        DecklinkLinkConfiguration::minimum_for(4 * SDI_12G_BIT_RATE + 1),
+        None
+    );
+}
+
+#[test]
+fn signal_that_does_not_fit_is_warned_about() {
+    let required = 3 * SDI_12G_BIT_RATE;
+    assert_eq!(
+        check_bandwidth(required, DecklinkLinkConfiguration::QuadLink),
+        None
+    );
+
+    let warning = check_bandwidth(required, DecklinkLinkConfiguration::DualLink).unwrap();
+    assert_eq!(
+        warning,
+        LinkBandwidthWarning {
+            required,
+            available: 2 * SDI_12G_BIT_RATE,
+            link: DecklinkLinkConfiguration::DualLink,
+            minimum_link: Some(DecklinkLinkConfiguration::QuadLink),
+        }
+    );
+    assert_eq!(
+        warning.to_string(),
+        "35.64 Gb/s needed but DualLink carries 23.76 Gb/s, use QuadLink"
+    );
+
+    let warning = check_bandwidth(5 * SDI_12G_BIT_RATE, DecklinkLinkConfiguration::QuadLink);
+    assert_eq!(
+        warning.unwrap().to_string(),
+        "59.40 Gb/s needed but QuadLink carries 47.52 Gb/s, and no link configuration is enough"
+    );
+}
+
+#[test]
+fn frames_at_8k_are_sized_without_overflow() {
+    // 8K DCI in v210
+    let frame = TestFrameBuilder::new(8192, 4320)
+        .pixel_format(DecklinkPixelFormat::Format10BitYUV)
+        .build()
+        .unwrap();
+    assert_eq!(frame.row_bytes(), 8192_usize.div_ceil(48) * 128);
+    assert_eq!(frame.bytes().unwrap().0.len(), 21888 * 4320);
+
+    // A byte count that overflows is refused rather than wrapping
+    assert!(matches!(
+        TestFrameBuilder::new(4, usize::MAX).row_bytes(16).build(),
+        Err(decklink::SdkError::INVALIDARG)
+    ));
+}
+
+#[cfg(feature = "mock-backend")]
+mod mock {
+    use decklink::device::input::DecklinkVideoInputFlags;
+    use decklink::device::output::DecklinkVideoOutputFlags;
+    use decklink::device::{get_devices, DecklinkDeviceDisplayModes};
+    use decklink::display_mode::DecklinkDisplayModeId;
+    use decklink::frame::{
+        DecklinkAlignedBytes, DecklinkFrameBase, DecklinkFrameFlags, DecklinkPixelFormat,
+    };
+    use decklink::link::{check_link_bandwidth, DecklinkLinkConfiguration};
+    use decklink::mock::{
+        MockBackend, MockDevice, DEFAULT_MODES, DEFAULT_OUTPUT_PIXEL_FORMATS, EIGHT_K_MODES,
+    };
+    use decklink::probe::{run_all_with, ProbeOptions, ProbeOutcome};
+    use decklink::SdkError;
+    use std::time::Duration;
+
+    #[test]
+    fn eight_k_device_lists_its_modes() {
+        let _backend = MockBackend::install(vec![MockDevice::eight_k("DeckLink 8K Pro")]);
+        let devices = get_devices().unwrap();
+        let input = devices[0].input().unwrap();
+
+        let modes = input.display_modes().unwrap();
+        assert_eq!(modes.len(), DEFAULT_MODES.len() + EIGHT_K_MODES.len());
+        let mode = modes
+            .iter()
+            .find(|m| m.mode() == DecklinkDisplayModeId::UHD8KDCI5994)
+            .unwrap();
+        assert_eq!((mode.width(), mode.height()), (8192, 4320));
+        assert_eq!(
+            mode.frame_duration().map(|d| (d.value, d.scale)),
+            Some((1001, 60000))
+        );
+
+        assert_eq!(
+            input
+                .supported_pixel_formats_for(
+                    DecklinkDisplayModeId::UHD8K4320p60,
+                    DecklinkVideoInputFlags::empty()
+                )
+                .unwrap(),
+            DEFAULT_OUTPUT_PIXEL_FORMATS
+        );
+        let output = devices[0].output().unwrap();
+        assert_eq!(
+            output.display_modes().unwrap().len(),
+            DEFAULT_MODES.len() + EIGHT_K_MODES.len()
+        );
+    }
+
+    #[test]
+    fn pixel_formats_are_probed_one_mode_at_a_time() {
+        let _backend = MockBackend::install(vec![MockDevice::new("DeckLink Mini Recorder")
+            .mode_pixel_formats(
+                DecklinkDisplayModeId::HD1080p25,
+                &[DecklinkPixelFormat::Format8BitBGRA],
+            )]);
+        let devices = get_devices().unwrap();
+        let input = devices[0].input().unwrap();
+
+        let formats = |mode| {
+            input
+                .supported_pixel_formats_for(mode, DecklinkVideoInputFlags::empty())
+                .unwrap()
+        };
+        assert_eq!(
+            formats(DecklinkDisplayModeId::HD1080p25),
+            [
+                DecklinkPixelFormat::Format8BitYUV,
+                DecklinkPixelFormat::Format10BitYUV,
+                DecklinkPixelFormat::Format8BitBGRA
+            ]
+        );
+        assert_eq!(
+            formats(DecklinkDisplayModeId::HD720p50),
+            [
+                DecklinkPixelFormat::Format8BitYUV,
+                DecklinkPixelFormat::Format10BitYUV
+            ]
+        );
+    }
+
+    #[test]
+    fn modes_of_the_device_are_checked_against_the_links() {
+        let _backend = MockBackend::install(vec![MockDevice::eight_k("DeckLink 8K Pro")]);
+        let devices = get_devices().unwrap();
+        let modes = devices[0].input().unwrap().display_modes().unwrap();
+        let mode = |id| modes.iter().find(|m| m.mode() == id).unwrap();
+
+        let hd = mode(DecklinkDisplayModeId::HD1080p50);
+        let uhd = mode(DecklinkDisplayModeId::UHD4K2160p60);
+        let eight_k = mode(DecklinkDisplayModeId::UHD8K4320p60);
+        let yuv = DecklinkPixelFormat::Format10BitYUV;
+        let single = DecklinkLinkConfiguration::SingleLink;
+
+        assert_eq!(check_link_bandwidth(hd, yuv, single), None);
+        assert_eq!(check_link_bandwidth(uhd, yuv, single), None);
+        // 4K60 RGB needs two links
+        let warning =
+            check_link_bandwidth(uhd, DecklinkPixelFormat::Format10BitRGB, single).unwrap();
+        assert_eq!(
+            warning.minimum_link,
+            Some(DecklinkLinkConfiguration::DualLink)
+        );
+        // 8K60 needs four
+        let warning = check_link_bandwidth(eight_k, yuv, single).unwrap();
+        assert_eq!(
+            warning.minimum_link,
+            Some(DecklinkLinkConfiguration::QuadLink)
+        );
+        assert_eq!(
+            check_link_bandwidth(eight_k, yuv, DecklinkLinkConfiguration::QuadLink),
+            None
+        );
+        assert_eq!(
+            check_link_bandwidth(eight_k, DecklinkPixelFormat::FormatH265, single),
+            None
+        );
+    }
+
+    #[test]
+    fn support_matrix_of_an_eight_k_device_is_probed_in_time() {
+        let _backend = MockBackend::install(vec![MockDevice::eight_k("DeckLink 8K Pro")]);
+        let devices = get_devices().unwrap();
+        let options = ProbeOptions {
+            capture_duration: Duration::from_millis(50),
+            probe_timeout: Duration::from_secs(10),
+        };
+
+        let report = run_all_with(&devices[0], options);
+        let result = report.result("support_matrix").unwrap();
+        assert_eq!(result.outcome, ProbeOutcome::Pass);
+        assert_eq!(
+            report.support_matrix.len(),
+            DEFAULT_MODES.len() + EIGHT_K_MODES.len()
+        );
+        assert!(report
+            .support_matrix
+            .iter()
+            .all(|support| support.pixel_formats == DEFAULT_OUTPUT_PIXEL_FORMATS));
+    }
+
+    #[test]
+    fn support_matrix_stops_at_the_probe_timeout() {
+        let _backend = MockBackend::install(vec![MockDevice::eight_k("DeckLink 8K Pro")]);
+        let devices = get_devices().unwrap();
+        let options = ProbeOptions {
+            capture_duration: Duration::ZERO,
+            probe_timeout: Duration::ZERO,
+        };
+
+        let report = run_all_with(&devices[0], options);
+        let result = report.result("support_matrix").unwrap();
+        assert_eq!(result.outcome, ProbeOutcome::Fail { hresult: None });
+        assert_eq!(result.detail, "timed out after checking 0 of 65 modes");
+    }
+
+    /// A frame wider than the SDK's i32 arguments can describe.
+    struct Oversized;
+
+    impl DecklinkFrameBase for Oversized {
+        fn width(&self) -> usize {
+            1 << 31
+        }
+        fn height(&self) -> usize {
+            1
+        }
+        fn row_bytes(&self) -> usize {
+            1 << 33
+        }
+        fn pixel_format(&self) -> DecklinkPixelFormat {
+            DecklinkPixelFormat::Format8BitBGRA
+        }
+        fn flags(&self) -> DecklinkFrameFlags {
+            DecklinkFrameFlags::empty()
+        }
+        fn bytes(&self) -> Result<DecklinkAlignedBytes<'_>, SdkError> {
+            Ok(DecklinkAlignedBytes(&[]))
+        }
+    }
+
+    #[test]
+    fn output_refuses_frames_too_large_for_the_sdk() {
+        let backend = MockBackend::install(vec![MockDevice::eight_k("DeckLink 8K Pro")]);
+        let devices = get_devices().unwrap();
+        let output = devices[0].output().unwrap();
+        let video = output
+            .enable_video_output_sync(
+                DecklinkDisplayModeId::UHD8K4320p25,
+                DecklinkVideoOutputFlags::empty(),
+            )
+            .unwrap();
+
+        assert!(matches!(
+            video.display_frame_copy(&Oversized),
+            Err(SdkError::INVALIDARG)
+        ));
+        assert!(backend.output(0).displayed().is_empty());
+    }
+}
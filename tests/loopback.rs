@@ -0,0 +1,294 @@
+//! The loopback test pattern and tone, checked without a cable.
+
+#[cfg(feature = "mock-backend")]
+mod common;
+
+use decklink::frame::{
+    DecklinkAlignedBytes, DecklinkFrameBase, DecklinkFrameFlags, DecklinkPixelFormat,
+};
+use decklink::loopback::{tone_level, LoopbackPattern, PatternError, ToneGenerator, STAMP_ROWS};
+use decklink::SdkError;
+
+const FORMATS: [DecklinkPixelFormat; 2] = [
+    DecklinkPixelFormat::Format8BitYUV,
+    DecklinkPixelFormat::Format10BitYUV,
+];
+
+/// A frame held in memory, as a captured frame would be.
+struct Captured {
+    width: usize,
+    height: usize,
+    row_bytes: usize,
+    pixel_format: DecklinkPixelFormat,
+    flags: DecklinkFrameFlags,
+    bytes: Vec<u8>,
+}
+
+impl Captured {
+    fn of(pattern: &LoopbackPattern, index: u32) -> Captured {
+        let frame = pattern.frame(index).unwrap();
+        Captured {
+            width: frame.width(),
+            height: frame.height(),
+            row_bytes: frame.row_bytes(),
+            pixel_format: frame.pixel_format(),
+            flags: frame.flags(),
+            bytes: frame.bytes().unwrap().0.to_vec(),
+        }
+    }
+}
+
+impl DecklinkFrameBase for Captured {
+    fn width(&self) -> usize {
+        self.width
+    }
+    fn height(&self) -> usize {
+        self.height
+    }
+    fn row_bytes(&self) -> usize {
+        self.row_bytes
+    }
+    fn pixel_format(&self) -> DecklinkPixelFormat {
+        self.pixel_format
+    }
+    fn flags(&self) -> DecklinkFrameFlags {
+        self.flags
+    }
+    fn bytes(&self) -> Result<DecklinkAlignedBytes<'_>, SdkError> {
+        Ok(DecklinkAlignedBytes(&self.bytes))
+    }
+}
+
+#[test]
+fn only_yuv_patterns_of_a_usable_size_are_made() {
+    assert!(matches!(
+        LoopbackPattern::new(1920, 1080, DecklinkPixelFormat::Format8BitBGRA),
+        Err(PatternError::UnsupportedPixelFormat(
+            DecklinkPixelFormat::Format8BitBGRA
+        ))
+    ));
+    for (width, height) in [(32, 1080), (1921, 1080), (1920, STAMP_ROWS)] {
+        assert!(
+            matches!(
+                LoopbackPattern::new(width, height, DecklinkPixelFormat::Format8BitYUV),
+                Err(PatternError::InvalidSize { .. })
+            ),
+            "{width}x{height}"
+        );
+    }
+    let pattern = LoopbackPattern::new(1920, 1080, DecklinkPixelFormat::Format10BitYUV).unwrap();
+    assert_eq!(pattern.row_bytes(), 5120);
+}
+
+#[test]
+fn each_frame_reads_back_its_index_and_matches() {
+    for format in FORMATS {
+        let pattern = LoopbackPattern::new(720, 486, format).unwrap();
+        for index in [0, 1, 1000, 0xA5A5_5A5A, u32::MAX] {
+            let frame = pattern.frame(index).unwrap();
+            assert_eq!(pattern.read_index(&frame).unwrap(), index, "{format:?}");
+            let check = pattern.check(&frame, 0).unwrap();
+            assert!(check.matches(), "{format:?} {check:?}");
+            assert_eq!((check.index, check.largest_error), (index, 0));
+        }
+    }
+}
+
+#[test]
+fn frames_of_different_indices_differ_below_the_stamp() {
+    let pattern = LoopbackPattern::new(720, 486, DecklinkPixelFormat::Format8BitYUV).unwrap();
+    let row = |frame: &Captured, y: usize| {
+        frame.bytes[y * frame.row_bytes..(y + 1) * frame.row_bytes].to_vec()
+    };
+    let (first, second) = (Captured::of(&pattern, 1), Captured::of(&pattern, 2));
+    assert_ne!(row(&first, STAMP_ROWS + 10), row(&second, STAMP_ROWS + 10));
+
+    // A stale frame under a later stamp does not match
+    let mut stale = Captured::of(&pattern, 1);
+    let stamp = STAMP_ROWS * stale.row_bytes;
+    stale.bytes[..stamp].copy_from_slice(&second.bytes[..stamp]);
+    let check = pattern.check(&stale, 0).unwrap();
+    assert_eq!(check.index, 2);
+    assert!(!check.matches());
+}
+
+#[test]
+fn mismatches_beyond_the_tolerance_are_counted_and_located() {
+    let pattern = LoopbackPattern::new(720, 486, DecklinkPixelFormat::Format8BitYUV).unwrap();
+    let mut frame = Captured::of(&pattern, 7);
+    // One 8-bit step is four 10-bit codes, at the luma of pixel 10 of row 100
+    let offset = 100 * frame.row_bytes + 10 * 2 + 1;
+    frame.bytes[offset] = frame.bytes[offset].wrapping_add(1);
+
+    let check = pattern.check(&frame, 4).unwrap();
+    assert!(check.matches(), "{check:?}");
+    assert_eq!(check.largest_error, 4);
+
+    let check = pattern.check(&frame, 3).unwrap();
+    assert_eq!(check.mismatched_codes, 1);
+    assert_eq!(check.first_mismatch, Some((10, 100)));
+
+    // A frame flagged without a signal never matches
+    frame.bytes[offset] = frame.bytes[offset].wrapping_sub(1);
+    frame.flags = DecklinkFrameFlags::HAS_NO_INPUT_SOURCE;
+    let check = pattern.check(&frame, 0).unwrap();
+    assert!(check.no_input_source && !check.matches());
+}
+
+#[test]
+fn frames_of_another_format_are_refused() {
+    let pattern = LoopbackPattern::new(720, 486, DecklinkPixelFormat::Format8BitYUV).unwrap();
+    let other = LoopbackPattern::new(720, 486, DecklinkPixelFormat::Format10BitYUV).unwrap();
+    let frame = other.frame(0).unwrap();
+    assert!(matches!(
+        pattern.check(&frame, 0),
+        Err(PatternError::WrongFormat {
+            width: 720,
+            height: 486,
+            pixel_format: DecklinkPixelFormat::Format10BitYUV,
+        })
+    ));
+}
+
+#[test]
+fn a_tone_measures_at_its_level_on_its_frequency() {
+    let mut tone = ToneGenerator::new(1000.0, 48000, -20.0);
+    assert_eq!(tone.frequency(), 1000.0);
+    let mut samples = vec![0i16; 48000 * 2];
+    tone.fill(&mut samples, 2);
+
+    for channel in 0..2 {
+        let level = tone_level(&samples, 2, channel, 48000, 1000.0);
+        assert!((level + 20.0).abs() < 0.1, "{level}");
+    }
+    assert!(tone_level(&samples, 2, 0, 48000, 2000.0) < -60.0);
+    assert_eq!(tone_level(&samples, 2, 2, 48000, 1000.0), f64::NEG_INFINITY);
+    assert_eq!(
+        tone_level::<i16>(&[], 2, 0, 48000, 1000.0),
+        f64::NEG_INFINITY
+    );
+
+    // A silent channel measures far below the tone
+    let mut one_channel = samples.clone();
+    one_channel
+        .iter_mut()
+        .skip(1)
+        .step_by(2)
+        .for_each(|s| *s = 0);
+    assert!(tone_level(&one_channel, 2, 1, 48000, 1000.0) < -100.0);
+}
+
+#[test]
+fn a_tone_continues_across_buffers() {
+    let mut whole = vec![0i32; 4800];
+    ToneGenerator::new(440.0, 48000, -6.0).fill(&mut whole, 1);
+
+    let mut tone = ToneGenerator::new(440.0, 48000, -6.0);
+    let mut parts = vec![0i32; 4800];
+    let (first, second) = parts.split_at_mut(1234);
+    tone.fill(first, 1);
+    tone.fill(second, 1);
+
+    let largest = whole
+        .iter()
+        .zip(&parts)
+        .map(|(a, b)| a.abs_diff(*b))
+        .max()
+        .unwrap();
+    // Only rounding of the phase differs
+    assert!(largest < 1 << 12, "{largest}");
+}
+
+#[cfg(feature = "mock-backend")]
+mod mock {
+    use super::common;
+    use super::*;
+    use decklink::device::get_devices;
+    use decklink::device::input::{
+        DeckLinkInputCallback, DecklinkDetectedVideoInputFormatFlags, DecklinkVideoInputFlags,
+        DecklinkVideoInputFormatChangedEvents,
+    };
+    use decklink::device::output::DecklinkVideoOutputFlags;
+    use decklink::display_mode::DecklinkDisplayModeId;
+    use decklink::frame::DecklinkVideoFrame;
+    use decklink::loopback::PatternCheck;
+    use decklink::mock::{MockBackend, MockDevice};
+    use std::sync::{Arc, Mutex};
+
+    const MODE: DecklinkDisplayModeId = DecklinkDisplayModeId::NTSC;
+
+    struct Checker {
+        pattern: LoopbackPattern,
+        checks: Mutex<Vec<PatternCheck>>,
+    }
+
+    impl DeckLinkInputCallback for Checker {
+        fn video_input_format_changed(
+            &self,
+            _events: DecklinkVideoInputFormatChangedEvents,
+            _new_display_mode: DecklinkDisplayModeId,
+            _detected_signal_flags: DecklinkDetectedVideoInputFormatFlags,
+        ) {
+        }
+
+        fn video_input_frame_arrived(&self, video_frame: Option<DecklinkVideoFrame>) -> bool {
+            let check = self.pattern.check(&video_frame.unwrap(), 0).unwrap();
+            self.checks.lock().unwrap().push(check);
+            true
+        }
+    }
+
+    #[test]
+    fn played_frames_are_matched_once_captured() {
+        let backend = MockBackend::install(vec![
+            MockDevice::new("DeckLink Duo (1)").with_output(),
+            MockDevice::new("DeckLink Duo (2)"),
+        ]);
+        let devices = get_devices().unwrap();
+
+        for format in FORMATS {
+            let pattern = LoopbackPattern::new(720, 486, format).unwrap();
+            let output = devices[0].output().unwrap();
+            let video = output
+                .enable_video_output_sync(MODE, DecklinkVideoOutputFlags::empty())
+                .unwrap();
+            for index in 0..5 {
+                video
+                    .display_frame_copy(&pattern.frame(index).unwrap())
+                    .unwrap();
+            }
+
+            let mut input = devices[1].input().unwrap();
+            let checker = Arc::new(Checker {
+                pattern,
+                checks: Mutex::new(Vec::new()),
+            });
+            input
+                .enable_video_input(MODE, format, DecklinkVideoInputFlags::empty())
+                .unwrap();
+            common::start_streams(&mut input, checker.clone());
+
+            // The cable, dropping the third frame
+            let played = backend.output(0).displayed();
+            let mock = backend.input(1);
+            for (n, frame) in played.into_iter().skip(played_start(format)).enumerate() {
+                if n != 2 {
+                    assert!(mock.deliver_frame(frame).is_ok());
+                }
+            }
+            let checks = checker.checks.lock().unwrap();
+            assert!(checks.iter().all(PatternCheck::matches), "{checks:?}");
+            let indices: Vec<u32> = checks.iter().map(|check| check.index).collect();
+            assert_eq!(indices, [0, 1, 3, 4], "{format:?}");
+            drop(checks);
+
+            input.stop_streams().unwrap();
+        }
+    }
+
+    /// Where the frames of `format` start in what the output displayed, after those of the
+    /// formats before it.
+    fn played_start(format: DecklinkPixelFormat) -> usize {
+        FORMATS.iter().position(|f| *f == format).unwrap() * 5
+    }
+}
@@ -0,0 +1,223 @@
+//! Parsing .cube files and applying 1D and 3D look up tables.
+
+use decklink::frame::{DecklinkFrameFlags, DecklinkPixelFormat, DecklinkVideoMutableFrame};
+use decklink::lut::{CubeError, Lut, Lut1d, Lut3d};
+
+fn assert_close(a: [f32; 3], b: [f32; 3]) {
+    assert!(
+        a.iter().zip(&b).all(|(x, y)| (x - y).abs() < 1e-5),
+        "{:?} != {:?}",
+        a,
+        b
+    );
+}
+
+fn parse_error(text: &str) -> (usize, String) {
+    match Lut::parse_cube(text) {
+        Err(CubeError::Parse { line, message }) => (line, message),
+        other => panic!("expected a parse error, got {:?}", other),
+    }
+}
+
+#[test]
+fn identity_tables_are_lossless() {
+    let all8: Vec<u8> = (0..=255).flat_map(|v| [v, 255 - v, v / 2, 7]).collect();
+    let all16: Vec<u16> = (0..=65535u16)
+        .step_by(3)
+        .flat_map(|v| [v, 65535 - v, v / 2])
+        .collect();
+
+    for lut in [
+        Lut::OneD(Lut1d::identity(1024)),
+        Lut::ThreeD(Lut3d::identity(33)),
+    ] {
+        let mut pixels = all8.clone();
+        lut.apply_rgb8(&mut pixels, 4);
+        assert_eq!(pixels, all8);
+
+        let mut pixels = all16.clone();
+        lut.apply_rgb16(&mut pixels, 3);
+        assert_eq!(pixels, all16);
+    }
+}
+
+#[test]
+fn one_dimensional_table_interpolates() {
+    let lut = Lut::parse_cube(
+        "TITLE \"half\"\n\
+         # Halves every channel\n\
+         LUT_1D_SIZE 3\n\
+         0.0 0.0 0.0\n\
+         0.25 0.25 0.5\n\
+         0.5 0.5 1.0\n",
+    )
+    .unwrap();
+    let Lut::OneD(table) = &lut else {
+        panic!("expected a 1D table")
+    };
+    assert_eq!(table.size(), 3);
+
+    assert_close(lut.apply([0.5, 0.25, 0.75]), [0.25, 0.125, 0.75]);
+    assert_close(lut.apply([1.0, 0.0, 0.25]), [0.5, 0.0, 0.25]);
+}
+
+#[test]
+fn three_dimensional_table_interpolates_trilinearly() {
+    // Red and green are swapped, and blue is inverted
+    let mut text = String::from("LUT_3D_SIZE 2\n");
+    for b in 0..2 {
+        for g in 0..2 {
+            for r in 0..2 {
+                text += &format!("{} {} {}\n", g, r, 1 - b);
+            }
+        }
+    }
+    let lut = Lut::parse_cube(&text).unwrap();
+    let Lut::ThreeD(table) = &lut else {
+        panic!("expected a 3D table")
+    };
+    assert_eq!(table.size(), 2);
+    assert_eq!(table.table()[1], [0.0, 1.0, 1.0]);
+
+    assert_close(lut.apply([0.0, 0.0, 0.0]), [0.0, 0.0, 1.0]);
+    assert_close(lut.apply([0.2, 0.6, 0.3]), [0.6, 0.2, 0.7]);
+    assert_close(lut.apply([0.5, 0.5, 0.5]), [0.5, 0.5, 0.5]);
+}
+
+#[test]
+fn inputs_outside_the_domain_are_clamped() {
+    let lut = Lut::OneD(Lut1d::identity(2));
+    assert_close(lut.apply([-0.5, 1.5, 0.5]), [0.0, 1.0, 0.5]);
+
+    let lut = Lut::ThreeD(Lut3d::identity(2));
+    assert_close(lut.apply([-1.0, 2.0, 0.25]), [0.0, 1.0, 0.25]);
+}
+
+#[test]
+fn domain_is_mapped_onto_the_table() {
+    let lut = Lut::parse_cube(
+        "DOMAIN_MIN 0 0 -1\n\
+         DOMAIN_MAX 2 1 1\n\
+         LUT_1D_SIZE 2\n\
+         0 0 0\n\
+         1 1 1\n",
+    )
+    .unwrap();
+    let Lut::OneD(table) = &lut else {
+        panic!("expected a 1D table")
+    };
+    assert_eq!(table.domain(), ([0.0, 0.0, -1.0], [2.0, 1.0, 1.0]));
+    assert_close(lut.apply([1.0, 0.5, 0.0]), [0.5, 0.5, 0.5]);
+    assert_close(lut.apply([3.0, 0.5, -2.0]), [1.0, 0.5, 0.0]);
+
+    // The older form shares one range between the channels
+    let lut = Lut::parse_cube("LUT_1D_INPUT_RANGE 0 4\nLUT_1D_SIZE 2\n0 0 0\n1 1 1\n").unwrap();
+    assert_close(lut.apply([1.0, 2.0, 4.0]), [0.25, 0.5, 1.0]);
+}
+
+#[test]
+fn malformed_files_report_the_line() {
+    assert_eq!(
+        parse_error("LUT_1D_SIZE 2\n0 0 0\n0 x 0\n"),
+        (3, "invalid number 'x'".to_string())
+    );
+    assert_eq!(
+        parse_error("LUT_1D_SIZE 2\n0 0\n1 1 1\n"),
+        (2, "expected 3 values, found 2".to_string())
+    );
+    assert_eq!(parse_error("# Comment\n0 0 0\n").0, 2);
+    assert_eq!(parse_error("LUT_3D_SIZE 1\n").0, 1);
+    assert_eq!(parse_error("LUT_3D_SIZE 257\n").0, 1);
+    assert_eq!(
+        parse_error("TITLE \"t\"\nLUT_3D_SIZE 2\nLUT_1D_SIZE 2\n"),
+        (3, "both LUT_1D_SIZE and LUT_3D_SIZE are given".to_string())
+    );
+    assert_eq!(
+        parse_error("LUT_1D_SIZE 2\n0 0 0\nLUT_3D_SIZE 2\n1 1 1\n"),
+        (3, "keyword 'LUT_3D_SIZE' after table data".to_string())
+    );
+    assert_eq!(
+        parse_error("LUT_1D_SIZE 2\nGAMMA 2.2\n"),
+        (2, "unknown keyword 'GAMMA'".to_string())
+    );
+}
+
+#[test]
+fn incomplete_files_report_the_last_line() {
+    assert_eq!(
+        parse_error("LUT_3D_SIZE 2\n0 0 0\n1 1 1\n"),
+        (3, "expected 8 table entries, found 2".to_string())
+    );
+    assert_eq!(
+        parse_error("TITLE \"empty\"\n"),
+        (1, "missing LUT_1D_SIZE or LUT_3D_SIZE".to_string())
+    );
+    assert_eq!(
+        parse_error("DOMAIN_MIN 0 1 0\nDOMAIN_MAX 1 1 1\nLUT_1D_SIZE 2\n0 0 0\n1 1 1\n"),
+        (5, "DOMAIN_MAX must be greater than DOMAIN_MIN".to_string())
+    );
+}
+
+#[test]
+fn missing_files_are_io_errors() {
+    assert!(matches!(
+        Lut::load_cube(std::path::Path::new("/nonexistent/preview.cube")),
+        Err(CubeError::Io(_))
+    ));
+}
+
+/// A 2×2 UYVY frame, with rows padded to 8 bytes beyond the pixels.
+fn uyvy(rows: [[u8; 4]; 2]) -> DecklinkVideoMutableFrame {
+    let mut frame = DecklinkVideoMutableFrame::create(
+        2,
+        2,
+        12,
+        DecklinkPixelFormat::Format8BitYUV,
+        DecklinkFrameFlags::empty(),
+    );
+    let mut bytes = vec![0; 24];
+    bytes[..4].copy_from_slice(&rows[0]);
+    bytes[12..16].copy_from_slice(&rows[1]);
+    frame.copy_bytes(&bytes).unwrap();
+    frame
+}
+
+#[test]
+fn uyvy_frames_convert_through_the_table() {
+    // Video range black and white, then the 8-bit code values of Rec. 709 red, which carry
+    // a little rounding
+    let frame = uyvy([[128, 16, 128, 235], [102, 63, 240, 63]]);
+
+    let rgba = Lut::OneD(Lut1d::identity(2))
+        .convert_uyvy_to_rgba8(&frame)
+        .unwrap();
+    assert_eq!(
+        rgba,
+        [
+            0, 0, 0, 255, 255, 255, 255, 255, //
+            255, 1, 0, 255, 255, 1, 0, 255,
+        ]
+    );
+
+    // Inverting after the conversion
+    let invert = Lut::parse_cube("LUT_1D_SIZE 2\n1 1 1\n0 0 0\n").unwrap();
+    assert_eq!(
+        &invert.convert_uyvy_to_rgba8(&frame).unwrap()[..8],
+        [255, 255, 255, 255, 0, 0, 0, 255]
+    );
+}
+
+#[test]
+fn conversion_needs_uyvy() {
+    let frame = DecklinkVideoMutableFrame::create(
+        2,
+        2,
+        8,
+        DecklinkPixelFormat::Format8BitBGRA,
+        DecklinkFrameFlags::empty(),
+    );
+    assert!(matches!(
+        Lut::OneD(Lut1d::identity(2)).convert_uyvy_to_rgba8(&frame),
+        Err(decklink::SdkError::INVALIDARG)
+    ));
+}
@@ -0,0 +1,179 @@
+//! Polling a `LatestFrameMailbox` slower than frames arrive, across format changes, under a
+//! retention budget, and after teardown.
+#![cfg(feature = "mock-backend")]
+
+mod common;
+
+use decklink::device::input::{
+    DecklinkDetectedVideoInputFormatFlags, DecklinkInputDevice, DecklinkVideoInputFlags,
+    DecklinkVideoInputFormatChangedEvents,
+};
+use decklink::display_mode::DecklinkDisplayModeId;
+use decklink::frame::DecklinkPixelFormat;
+use decklink::mailbox::{LatestFrameMailbox, MailboxConfig, MailboxPoll};
+use decklink::mock::{MockBackend, MockFrame, MockInput};
+use decklink::retention::{RetentionBudget, RetentionLimit, RetentionMode};
+use std::time::Duration;
+
+const FORMAT: DecklinkPixelFormat = DecklinkPixelFormat::Format8BitYUV;
+
+fn start(
+    mailbox: &LatestFrameMailbox,
+    audio: bool,
+) -> (MockBackend, MockInput, DecklinkInputDevice) {
+    let backend = common::mini_recorder();
+    let mock = backend.input(0);
+    let mut input = common::open_input(
+        DecklinkDisplayModeId::HD1080p5994,
+        FORMAT,
+        DecklinkVideoInputFlags::empty(),
+    );
+    if audio {
+        common::enable_stereo_audio(&mut input);
+    }
+    common::start_streams(&mut input, mailbox.callback());
+    (backend, mock, input)
+}
+
+/// A small frame filled with the low byte of its number.
+fn numbered(sequence: u64) -> MockFrame {
+    MockFrame::new(48, 4, FORMAT).fill(sequence as u8)
+}
+
+fn change_format(mock: &MockInput) {
+    assert!(mock
+        .deliver_format_change(
+            DecklinkVideoInputFormatChangedEvents::DISPLAY_MODE_CHANGED,
+            DecklinkDisplayModeId::HD1080p50,
+            DecklinkDetectedVideoInputFormatFlags::YCBCR_422,
+        )
+        .is_ok());
+}
+
+#[test]
+fn a_slow_poller_sees_whole_frames_in_order() {
+    const FRAMES: u64 = 90;
+    let mailbox = LatestFrameMailbox::new(MailboxConfig::default(), None);
+    let (_backend, mock, input) = start(&mailbox, false);
+    assert!(matches!(mailbox.poll(), MailboxPoll::Empty));
+
+    let seen = std::thread::scope(|scope| {
+        let poller = scope.spawn(|| {
+            let mut last = 0;
+            let mut seen = 0;
+            while last < FRAMES {
+                match mailbox.poll_if_newer(last) {
+                    MailboxPoll::Frame(frame) => {
+                        assert!(frame.sequence() > last);
+                        let bytes = frame.frame().bytes_to_vec().unwrap();
+                        assert!(bytes.iter().all(|&b| b == frame.sequence() as u8));
+                        assert!(frame.age() < Duration::from_secs(5));
+                        last = frame.sequence();
+                        seen += 1;
+                    }
+                    MailboxPoll::Empty | MailboxPoll::Unchanged => {}
+                    _ => panic!("unexpected poll"),
+                }
+                // About three frames of the input at 60 frames per second
+                std::thread::sleep(Duration::from_millis(50));
+            }
+            seen
+        });
+        for sequence in 1..=FRAMES {
+            assert!(mock.deliver_frame(numbered(sequence)).is_ok());
+            std::thread::sleep(Duration::from_micros(16_667));
+        }
+        poller.join().unwrap()
+    });
+
+    // The poller took the last frame, and every frame it did not take was overwritten
+    let stats = mailbox.stats();
+    assert_eq!(stats.published, FRAMES);
+    assert_eq!(stats.polled, seen);
+    assert!(seen < FRAMES);
+    assert_eq!(stats.overwritten, FRAMES - seen);
+    assert!(matches!(
+        mailbox.poll_if_newer(FRAMES),
+        MailboxPoll::Unchanged
+    ));
+    assert_eq!(mailbox.poll().frame().unwrap().sequence(), FRAMES);
+    input.stop_streams().unwrap();
+}
+
+#[test]
+fn a_format_change_clears_the_mailbox() {
+    let mailbox = LatestFrameMailbox::new(MailboxConfig::default(), None);
+    let (_backend, mock, input) = start(&mailbox, false);
+
+    assert!(mock.deliver_frame(numbered(1)).is_ok());
+    let old = mailbox.poll().frame().unwrap();
+    assert_eq!(old.format_generation(), 0);
+    change_format(&mock);
+    assert!(matches!(mailbox.poll(), MailboxPoll::FormatChanged));
+    assert!(matches!(
+        mailbox.poll_if_newer(0),
+        MailboxPoll::FormatChanged
+    ));
+
+    // A frame of the new format no poll took is cleared by the next change
+    assert!(mock.deliver_frame(numbered(2)).is_ok());
+    change_format(&mock);
+    assert!(matches!(mailbox.poll(), MailboxPoll::FormatChanged));
+
+    assert!(mock.deliver_frame(numbered(3)).is_ok());
+    let new = mailbox.poll().frame().unwrap();
+    assert_eq!((new.sequence(), new.format_generation()), (3, 2));
+    let stats = mailbox.stats();
+    assert_eq!((stats.cleared, stats.overwritten), (1, 0));
+    assert_eq!(stats.format_changes, 2);
+    input.stop_streams().unwrap();
+}
+
+#[test]
+fn the_mailbox_retains_one_frame() {
+    let budget = RetentionBudget::new(RetentionLimit::Frames(1), RetentionMode::Strict);
+    let mailbox = LatestFrameMailbox::new(MailboxConfig::default(), Some(budget.clone()));
+    let (_backend, mock, input) = start(&mailbox, false);
+
+    for sequence in 1..=5 {
+        assert!(mock.deliver_frame(numbered(sequence)).is_ok());
+        assert_eq!(budget.stats().retained_frames, 1);
+    }
+    assert_eq!(mailbox.stats().overwritten, 4);
+
+    // Holding the polled frame leaves no room for the next one
+    let held = mailbox.poll().frame().unwrap();
+    assert!(mock.deliver_frame(numbered(6)).is_ok());
+    assert_eq!(mailbox.stats().refused, 1);
+    assert!(matches!(mailbox.poll(), MailboxPoll::Empty));
+    drop(held);
+    assert_eq!(budget.stats().retained_frames, 0);
+
+    // The refused frame was not numbered
+    assert!(mock.deliver_frame(numbered(7)).is_ok());
+    assert_eq!(mailbox.poll().frame().unwrap().sequence(), 6);
+    mailbox.close();
+    assert_eq!(budget.stats().retained_frames, 0);
+    assert!(mailbox.poll().is_closed());
+    input.stop_streams().unwrap();
+}
+
+#[test]
+fn releasing_the_callback_closes_the_mailbox() {
+    let mailbox = LatestFrameMailbox::new(MailboxConfig { audio: true }, None);
+    let (_backend, mock, mut input) = start(&mailbox, true);
+
+    assert!(mock
+        .deliver_frame_with_audio(numbered(1), &[0u8; 16])
+        .is_ok());
+    let frame = mailbox.poll().frame().unwrap();
+    assert_eq!(frame.audio_packet().unwrap().sample_frame_count(), 4);
+
+    input.stop_streams().unwrap();
+    input.set_callback(None).unwrap();
+    assert!(mailbox.is_closed());
+    assert!(mailbox.poll().is_closed());
+    assert!(mailbox.poll_if_newer(0).is_closed());
+    // A frame taken before the close stays valid
+    assert_eq!(frame.frame().bytes_to_vec().unwrap()[0], 1);
+}
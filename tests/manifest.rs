@@ -0,0 +1,497 @@
+//! The JSON format of `CaptureManifest`, and a manifest of a capture from a mock input.
+
+#[cfg(feature = "mock-backend")]
+mod common;
+
+use decklink::display_mode::DecklinkDisplayModeId;
+use decklink::effective_config::EffectiveConfig;
+use decklink::frame::DecklinkPixelFormat;
+use decklink::manifest::{CaptureManifest, CaptureSetup, ManifestEvent, MANIFEST_VERSION};
+use decklink::segment::{SegmentBoundary, SegmentInfo, SegmentReason};
+use decklink::time::DecklinkTime;
+use decklink::timecode::{DecklinkTimecodeFormat, TimecodeSourceStats};
+use std::path::PathBuf;
+use std::time::Duration;
+
+fn setup() -> CaptureSetup {
+    CaptureSetup {
+        device_name: Some("DeckLink \"Duo\" 2".to_string()),
+        driver_version: Some("14.2.1".to_string()),
+        display_mode: DecklinkDisplayModeId::HD1080i5994,
+        pixel_format: DecklinkPixelFormat::Format10BitYUV,
+        width: 1920,
+        height: 1080,
+        frame_duration: Some(DecklinkTime::new(1001, 30000)),
+        effective_config: None,
+    }
+}
+
+/// Replace every elapsed time with zero, as they depend on how fast the test runs.
+fn without_elapsed(json: &str) -> String {
+    let mut out = String::new();
+    let mut rest = json;
+    while let Some(i) = rest.find("\"elapsed_ms\": ") {
+        let (head, tail) = rest.split_at(i + "\"elapsed_ms\": ".len());
+        out.push_str(head);
+        out.push('0');
+        rest = tail.trim_start_matches(|c: char| c.is_ascii_digit());
+    }
+    out.push_str(rest);
+    out
+}
+
+/// A path in the temporary directory unique to this test binary and `name`.
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!(
+        "decklink-manifest-{}-{}.json",
+        std::process::id(),
+        name
+    ))
+}
+
+#[test]
+fn empty_manifest() {
+    let mut setup = setup();
+    setup.device_name = None;
+    setup.driver_version = None;
+    setup.frame_duration = None;
+    let manifest = CaptureManifest::new(setup);
+
+    assert_eq!(
+        manifest.to_json(false),
+        r#"{
+  "version": 1,
+  "finished": false,
+  "setup": {
+    "device_name": null,
+    "driver_version": null,
+    "display_mode": "HD1080i5994",
+    "pixel_format": "Format10BitYUV",
+    "width": 1920,
+    "height": 1080,
+    "frame_duration": null,
+    "time_scale": null,
+    "effective_config": null
+  },
+  "totals": {
+    "frames_captured": 0,
+    "frames_dropped": 0,
+    "first_timecode": null,
+    "last_timecode": null,
+    "timecode_sources": []
+  },
+  "events": []
+}
+"#
+    );
+    assert_eq!(MANIFEST_VERSION, 1);
+}
+
+#[test]
+fn the_effective_config_is_written_in_the_setup() {
+    let mut setup = setup();
+    let config = EffectiveConfig::new();
+    setup.effective_config = Some(config.clone());
+    let json = CaptureManifest::new(setup).to_json(false);
+    let line = format!("    \"effective_config\": {}\n  }},", config.to_json());
+    assert!(json.contains(&line), "{}", json);
+}
+
+#[test]
+fn every_event_is_written() {
+    let mut manifest = CaptureManifest::new(setup());
+    manifest
+        .record_frame(Some("10:00:00;00".to_string()))
+        .unwrap();
+    manifest.record_frame(None).unwrap();
+    manifest
+        .record_event(ManifestEvent::Dropped {
+            first_frame: 2,
+            count: 3,
+        })
+        .unwrap();
+    manifest
+        .record_event(ManifestEvent::SignalLost { frame: 5 })
+        .unwrap();
+    manifest
+        .record_event(ManifestEvent::SignalRestored { frame: 9 })
+        .unwrap();
+    manifest
+        .record_event(ManifestEvent::FormatChanged {
+            frame: 9,
+            display_mode: DecklinkDisplayModeId::HD1080p25,
+        })
+        .unwrap();
+    manifest
+        .record_event(ManifestEvent::FormatTransition {
+            first_frame: 9,
+            frames: 2,
+        })
+        .unwrap();
+    manifest
+        .record_event(ManifestEvent::Mark {
+            frame: 10,
+            label: "take 2\n\t\\ \u{1}".to_string(),
+        })
+        .unwrap();
+    manifest
+        .record_event(ManifestEvent::Segment(SegmentInfo {
+            boundary: SegmentBoundary {
+                index: 1,
+                first_frame: 4,
+                reason: SegmentReason::FormatChanged,
+            },
+            path: PathBuf::from("takes/segment_00001.mov"),
+            frame_count: 6,
+            byte_count: 1152,
+        }))
+        .unwrap();
+    manifest
+        .record_event(ManifestEvent::AvOffset {
+            frame: 10,
+            offset: DecklinkTime::new(-480, 48000),
+        })
+        .unwrap();
+    manifest
+        .record_event(ManifestEvent::MemberAdded {
+            member: 0,
+            tick: None,
+        })
+        .unwrap();
+    manifest
+        .record_event(ManifestEvent::MemberRemoved {
+            member: 1,
+            tick: Some(12),
+        })
+        .unwrap();
+    manifest
+        .record_frame(Some("10:00:00;10".to_string()))
+        .unwrap();
+    let source = |format, frames_with_timecode, longest_gap| TimecodeSourceStats {
+        format,
+        frames_with_timecode,
+        longest_gap,
+        discontinuities: 0,
+        duplicates: 0,
+    };
+    manifest.set_timecode_sources(vec![
+        source(DecklinkTimecodeFormat::RP188VITC1, 3, 1),
+        source(DecklinkTimecodeFormat::RP188LTC, 0, 0),
+    ]);
+
+    assert_eq!(manifest.frames_captured(), 3);
+    assert_eq!(manifest.frames_dropped(), 3);
+    assert_eq!(manifest.entries().len(), 10);
+    assert_eq!(
+        without_elapsed(&manifest.to_json(true)),
+        r#"{
+  "version": 1,
+  "finished": true,
+  "setup": {
+    "device_name": "DeckLink \"Duo\" 2",
+    "driver_version": "14.2.1",
+    "display_mode": "HD1080i5994",
+    "pixel_format": "Format10BitYUV",
+    "width": 1920,
+    "height": 1080,
+    "frame_duration": 1001,
+    "time_scale": 30000,
+    "effective_config": null
+  },
+  "totals": {
+    "frames_captured": 3,
+    "frames_dropped": 3,
+    "first_timecode": "10:00:00;00",
+    "last_timecode": "10:00:00;10",
+    "timecode_sources": [
+      { "format": "RP188VITC1", "frames_with_timecode": 3, "longest_gap": 1, "discontinuities": 0, "duplicates": 0 },
+      { "format": "RP188LTC", "frames_with_timecode": 0, "longest_gap": 0, "discontinuities": 0, "duplicates": 0 }
+    ]
+  },
+  "events": [
+    { "elapsed_ms": 0, "type": "dropped", "first_frame": 2, "count": 3 },
+    { "elapsed_ms": 0, "type": "signal_lost", "frame": 5 },
+    { "elapsed_ms": 0, "type": "signal_restored", "frame": 9 },
+    { "elapsed_ms": 0, "type": "format_changed", "frame": 9, "display_mode": "HD1080p25" },
+    { "elapsed_ms": 0, "type": "format_transition", "first_frame": 9, "frames": 2 },
+    { "elapsed_ms": 0, "type": "mark", "frame": 10, "label": "take 2\n\t\\ \u0001" },
+    { "elapsed_ms": 0, "type": "segment", "index": 1, "first_frame": 4, "frames": 6, "reason": "FormatChanged", "path": "takes/segment_00001.mov" },
+    { "elapsed_ms": 0, "type": "av_offset", "frame": 10, "offset": -480, "time_scale": 48000 },
+    { "elapsed_ms": 0, "type": "member_added", "member": 0, "tick": null },
+    { "elapsed_ms": 0, "type": "member_removed", "member": 1, "tick": 12 }
+  ]
+}
+"#
+    );
+}
+
+#[test]
+fn finish_writes_the_finished_manifest() {
+    let path = temp_path("finish");
+    let mut manifest = CaptureManifest::new(setup());
+    manifest.record_frame(None).unwrap();
+    let expected = manifest.to_json(true);
+
+    manifest.finish(&path).unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), expected);
+
+    // The temporary file was renamed over the manifest
+    let mut tmp = path.clone().into_os_string();
+    tmp.push(".tmp");
+    assert!(!PathBuf::from(tmp).exists());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn autosave_writes_a_partial_manifest() {
+    let path = temp_path("autosave");
+    let mut manifest = CaptureManifest::new(setup());
+    manifest.set_autosave(&path, Duration::ZERO);
+
+    manifest.record_frame(None).unwrap();
+    let partial = std::fs::read_to_string(&path).unwrap();
+    assert!(partial.contains("\"finished\": false"));
+    assert!(partial.contains("\"frames_captured\": 1"));
+
+    manifest
+        .record_event(ManifestEvent::SignalLost { frame: 1 })
+        .unwrap();
+    let partial = std::fs::read_to_string(&path).unwrap();
+    assert_eq!(
+        without_elapsed(&partial),
+        without_elapsed(&manifest.to_json(false))
+    );
+
+    // A crash leaves the last partial manifest behind
+    drop(manifest);
+    assert!(std::fs::read_to_string(&path)
+        .unwrap()
+        .contains("\"type\": \"signal_lost\""));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn autosave_waits_for_the_interval() {
+    let path = temp_path("interval");
+    let mut manifest = CaptureManifest::new(setup());
+    manifest.set_autosave(&path, Duration::from_secs(3600));
+
+    manifest.record_frame(None).unwrap();
+    assert!(!path.exists());
+}
+
+#[cfg(feature = "mock-backend")]
+mod mock {
+    use super::common;
+    use super::{temp_path, without_elapsed};
+    use decklink::device::input::{
+        DeckLinkInputCallback, DecklinkDetectedVideoInputFormatFlags, DecklinkVideoInputFlags,
+        DecklinkVideoInputFormatChangedEvents,
+    };
+    use decklink::device::{get_devices, DecklinkDeviceDisplayModes};
+    use decklink::display_mode::DecklinkDisplayModeId;
+    use decklink::frame::{
+        DecklinkFrameBase, DecklinkFrameFlags, DecklinkPixelFormat, DecklinkVideoFrame,
+    };
+    use decklink::manifest::{CaptureManifest, CaptureSetup, ManifestEvent};
+    use decklink::mock::MockFrame;
+    use decklink::time::DecklinkFrameTiming;
+    use std::sync::{Arc, Mutex};
+
+    /// Keeps a manifest of a capture, numbering frames by their stream time to find drops.
+    struct Recorder {
+        state: Mutex<RecorderState>,
+    }
+
+    struct RecorderState {
+        manifest: CaptureManifest,
+        /// The number of the next frame, by its stream time.
+        next_frame: u64,
+        /// The stream time frames are numbered from, set by the first frame in each mode.
+        origin: Option<u64>,
+        timing: Option<DecklinkFrameTiming>,
+        signal: bool,
+    }
+
+    impl DeckLinkInputCallback for Recorder {
+        fn video_input_format_changed(
+            &self,
+            _events: DecklinkVideoInputFormatChangedEvents,
+            new_display_mode: DecklinkDisplayModeId,
+            _detected_signal_flags: DecklinkDetectedVideoInputFormatFlags,
+        ) {
+            let mut state = self.state.lock().unwrap();
+            let frame = state.next_frame;
+            state.origin = None;
+            state
+                .manifest
+                .record_event(ManifestEvent::FormatChanged {
+                    frame,
+                    display_mode: new_display_mode,
+                })
+                .unwrap();
+        }
+
+        fn video_input_frame_timing(&self, timing: DecklinkFrameTiming) {
+            self.state.lock().unwrap().timing = Some(timing);
+        }
+
+        fn video_input_frame_arrived(&self, video_frame: Option<DecklinkVideoFrame>) -> bool {
+            let frame = video_frame.unwrap();
+            let mut state = self.state.lock().unwrap();
+            let timing = state.timing.take().unwrap();
+
+            let index = (timing.stream_time.value / timing.mode_duration.value) as u64;
+            let next_frame = state.next_frame;
+            let origin = *state.origin.get_or_insert(index - next_frame.min(index));
+            let number = index - origin;
+            if number > next_frame {
+                state
+                    .manifest
+                    .record_event(ManifestEvent::Dropped {
+                        first_frame: next_frame,
+                        count: number - next_frame,
+                    })
+                    .unwrap();
+            }
+            state.next_frame = number + 1;
+
+            let signal = !frame
+                .flags()
+                .contains(DecklinkFrameFlags::HAS_NO_INPUT_SOURCE);
+            if signal != state.signal {
+                let event = if signal {
+                    ManifestEvent::SignalRestored { frame: number }
+                } else {
+                    ManifestEvent::SignalLost { frame: number }
+                };
+                state.manifest.record_event(event).unwrap();
+                state.signal = signal;
+            }
+
+            let timecode = format!("frame {}", number);
+            state.manifest.record_frame(Some(timecode)).unwrap();
+            true
+        }
+    }
+
+    #[test]
+    fn manifest_of_a_capture() {
+        let backend = common::mini_recorder();
+        let devices = get_devices().unwrap();
+        let mut input = devices[0].input().unwrap();
+
+        let mode = DecklinkDisplayModeId::HD1080p25;
+        let format = DecklinkPixelFormat::Format8BitYUV;
+        input
+            .enable_video_input(
+                mode,
+                format,
+                DecklinkVideoInputFlags::ENABLE_FORMAT_DETECTION,
+            )
+            .unwrap();
+        let display_mode = input
+            .display_modes()
+            .unwrap()
+            .into_iter()
+            .find(|m| m.mode() == mode)
+            .unwrap();
+        let setup = CaptureSetup {
+            device_name: devices[0].display_name(),
+            driver_version: decklink::api_version().ok(),
+            display_mode: mode,
+            pixel_format: format,
+            width: display_mode.width(),
+            height: display_mode.height(),
+            frame_duration: display_mode.frame_duration(),
+            effective_config: None,
+        };
+        let recorder = Arc::new(Recorder {
+            state: Mutex::new(RecorderState {
+                manifest: CaptureManifest::new(setup),
+                next_frame: 0,
+                origin: None,
+                timing: None,
+                signal: true,
+            }),
+        });
+        common::start_streams(&mut input, recorder.clone());
+
+        let mock = backend.input(0);
+        let at = |frame: MockFrame, index: i64| frame.stream_time(index * 1000, 1000, 25000);
+        // Frames 0 to 2, then 3 and 4 are dropped
+        for index in 0..3 {
+            assert!(mock.deliver_frame(at(mock.frame(), index)).is_ok());
+        }
+        for index in 5..7 {
+            assert!(mock.deliver_frame(at(mock.frame(), index)).is_ok());
+        }
+        // The signal is lost for two frames
+        for index in 7..9 {
+            assert!(mock
+                .deliver_frame(at(mock.frame().no_signal(), index))
+                .is_ok());
+        }
+        // And comes back at 50 frames per second
+        assert!(mock
+            .deliver_format_change(
+                DecklinkVideoInputFormatChangedEvents::DISPLAY_MODE_CHANGED,
+                DecklinkDisplayModeId::HD1080p50,
+                DecklinkDetectedVideoInputFormatFlags::YCBCR_422,
+            )
+            .is_ok());
+        let frame = MockFrame::for_mode(DecklinkDisplayModeId::HD1080p50, format);
+        for index in [100, 101, 103] {
+            let frame = frame.clone().stream_time(index * 1000, 1000, 50000);
+            assert!(mock.deliver_frame(frame).is_ok());
+        }
+        input.stop_streams().unwrap();
+        drop(input);
+
+        let path = temp_path("capture");
+        let manifest = Arc::try_unwrap(recorder)
+            .ok()
+            .unwrap()
+            .state
+            .into_inner()
+            .unwrap()
+            .manifest;
+        manifest.finish(&path).unwrap();
+        let json = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            without_elapsed(&json),
+            r#"{
+  "version": 1,
+  "finished": true,
+  "setup": {
+    "device_name": "DeckLink Mini Recorder",
+    "driver_version": "14.2.1",
+    "display_mode": "HD1080p25",
+    "pixel_format": "Format8BitYUV",
+    "width": 1920,
+    "height": 1080,
+    "frame_duration": 1000,
+    "time_scale": 25000,
+    "effective_config": null
+  },
+  "totals": {
+    "frames_captured": 10,
+    "frames_dropped": 3,
+    "first_timecode": "frame 0",
+    "last_timecode": "frame 12",
+    "timecode_sources": []
+  },
+  "events": [
+    { "elapsed_ms": 0, "type": "dropped", "first_frame": 3, "count": 2 },
+    { "elapsed_ms": 0, "type": "signal_lost", "frame": 7 },
+    { "elapsed_ms": 0, "type": "format_changed", "frame": 9, "display_mode": "HD1080p50" },
+    { "elapsed_ms": 0, "type": "signal_restored", "frame": 9 },
+    { "elapsed_ms": 0, "type": "dropped", "first_frame": 11, "count": 1 }
+  ]
+}
+"#
+        );
+    }
+}
@@ -0,0 +1,149 @@
+//! Every copy path against a plain copy, from and to every alignment, with every tail length.
+
+use decklink::memcopy::{
+    chosen_path, copy_frame, copy_frame_using, copy_paths, CopyHint, CopyLayout, CopyPath,
+    STREAMING_THRESHOLD,
+};
+use decklink::SdkError;
+
+const GUARD: u8 = 0xA5;
+
+/// Bytes that differ at every offset, so a byte copied from the wrong place is noticed.
+fn source(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i * 7 + i / 251) as u8).collect()
+}
+
+/// Check a copy of `layout` with `path` into a buffer of guard bytes, with the source and
+/// destination starting `src_offset` and `dst_offset` bytes into their buffers.
+fn check(path: CopyPath, layout: CopyLayout, src_offset: usize, dst_offset: usize) {
+    let src_len = (layout.rows.max(1) - 1) * layout.src_row_bytes + layout.row_len;
+    let dst_len = (layout.rows.max(1) - 1) * layout.dst_row_bytes + layout.row_len;
+    let src = source(src_offset + src_len);
+    let mut dst = vec![GUARD; dst_offset + dst_len + 64];
+    copy_frame_using(path, &mut dst[dst_offset..], &src[src_offset..], layout).unwrap();
+
+    let mut expected = vec![GUARD; dst.len()];
+    for row in 0..layout.rows {
+        let s = src_offset + row * layout.src_row_bytes;
+        let d = dst_offset + row * layout.dst_row_bytes;
+        expected[d..d + layout.row_len].copy_from_slice(&src[s..s + layout.row_len]);
+    }
+    assert!(
+        dst == expected,
+        "{:?} copy of {:?} from offset {} to offset {} differs",
+        path,
+        layout,
+        src_offset,
+        dst_offset
+    );
+}
+
+#[test]
+fn contiguous_copies_match_at_every_alignment_and_length() {
+    for path in copy_paths() {
+        for src_offset in 0..32 {
+            for dst_offset in 0..32 {
+                for len in 0..300 {
+                    check(path, CopyLayout::contiguous(len), src_offset, dst_offset);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn frame_sized_copies_match() {
+    for path in copy_paths() {
+        for offset in [0, 1, 15, 31] {
+            // A 1080p 8-bit YUV frame, and a size with a tail after every block size
+            check(path, CopyLayout::contiguous(3840 * 1080), offset, 0);
+            check(
+                path,
+                CopyLayout::contiguous(STREAMING_THRESHOLD + 127),
+                0,
+                offset,
+            );
+        }
+    }
+}
+
+#[test]
+fn strided_copies_match_and_leave_padding_alone() {
+    for path in copy_paths() {
+        for row_len in [0, 1, 15, 16, 17, 63, 64, 65, 200] {
+            for (src_pad, dst_pad) in [(0, 0), (0, 3), (5, 0), (13, 48)] {
+                for dst_offset in 0..33 {
+                    let layout = CopyLayout::rows(row_len, 5, row_len + src_pad, row_len + dst_pad);
+                    check(path, layout, 3, dst_offset);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn streaming_is_chosen_only_for_large_write_only_copies() {
+    let large = STREAMING_THRESHOLD * 4;
+    assert_eq!(
+        chosen_path(CopyHint::WillReadSoon, large),
+        CopyPath::Standard
+    );
+    assert_eq!(
+        chosen_path(CopyHint::StreamingWriteOnly, STREAMING_THRESHOLD - 1),
+        CopyPath::Standard
+    );
+
+    let path = chosen_path(CopyHint::StreamingWriteOnly, large);
+    assert!(path.is_available());
+    assert_eq!(
+        path.is_streaming(),
+        cfg!(any(target_arch = "x86_64", target_arch = "aarch64"))
+    );
+    assert_eq!(copy_paths()[0], CopyPath::Standard);
+
+    let src = source(large);
+    let mut dst = vec![0; large];
+    copy_frame(
+        &mut dst,
+        &src,
+        CopyLayout::contiguous(large),
+        CopyHint::StreamingWriteOnly,
+    )
+    .unwrap();
+    assert!(dst == src);
+}
+
+#[test]
+fn bad_layouts_and_unavailable_paths_are_errors() {
+    let src = source(100);
+    let mut dst = vec![0; 99];
+    let hint = CopyHint::WillReadSoon;
+
+    assert_eq!(
+        copy_frame(&mut dst, &src, CopyLayout::contiguous(100), hint),
+        Err(SdkError::INVALIDARG)
+    );
+    // Rows that overlap in the destination
+    assert_eq!(
+        copy_frame(&mut dst, &src, CopyLayout::rows(20, 4, 20, 10), hint),
+        Err(SdkError::INVALIDARG)
+    );
+    assert_eq!(
+        copy_frame(&mut dst, &src, CopyLayout::rows(20, 4, 25, 25), hint),
+        Ok(())
+    );
+
+    let unavailable = [
+        CopyPath::StreamingSse2,
+        CopyPath::StreamingAvx,
+        CopyPath::StreamingNeon,
+    ]
+    .into_iter()
+    .find(|p| !p.is_available());
+    if let Some(path) = unavailable {
+        assert_eq!(
+            copy_frame_using(path, &mut dst, &src, CopyLayout::contiguous(10)),
+            Err(SdkError::NOTIMPL)
+        );
+    }
+}
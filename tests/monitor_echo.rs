@@ -0,0 +1,177 @@
+//! Echoing captured frames to the output of a mock device with `MonitorEcho`.
+#![cfg(feature = "mock-backend")]
+
+mod common;
+
+use decklink::device::get_devices;
+use decklink::device::input::{
+    DeckLinkInputCallback, DecklinkDetectedVideoInputFormatFlags, DecklinkVideoInputFlags,
+    DecklinkVideoInputFormatChangedEvents,
+};
+use decklink::device::output::DecklinkVideoOutputFlags;
+use decklink::display_mode::DecklinkDisplayModeId;
+use decklink::frame::{DecklinkPixelFormat, DecklinkVideoFrame};
+use decklink::mock::{MockBackend, MockDevice};
+use decklink::monitor::{EchoSlot, MonitorEcho};
+use decklink::SdkError;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+const MODE: DecklinkDisplayModeId = DecklinkDisplayModeId::HD1080p25;
+
+/// Offers every captured frame to the slot, counting those it retained.
+struct Echo {
+    slot: Arc<EchoSlot>,
+    retained: AtomicUsize,
+}
+
+impl DeckLinkInputCallback for Echo {
+    fn video_input_format_changed(
+        &self,
+        _events: DecklinkVideoInputFormatChangedEvents,
+        _new_display_mode: DecklinkDisplayModeId,
+        _detected_signal_flags: DecklinkDetectedVideoInputFormatFlags,
+    ) {
+    }
+
+    fn video_input_frame_arrived(&self, video_frame: Option<DecklinkVideoFrame>) -> bool {
+        if self.slot.offer(&video_frame.unwrap()).unwrap() {
+            self.retained.fetch_add(1, Ordering::SeqCst);
+        }
+        true
+    }
+}
+
+fn backend() -> MockBackend {
+    MockBackend::install(vec![MockDevice::new("DeckLink Duo").with_output()])
+}
+
+#[test]
+fn captured_frames_are_displayed_on_the_output() {
+    let backend = backend();
+    let devices = get_devices().unwrap();
+    let output = devices[0].output().unwrap();
+    let mut input = devices[0].input().unwrap();
+
+    let echo = MonitorEcho::new(&output, MODE, 1000.0).unwrap();
+    assert_eq!(
+        backend.output(0).video(),
+        Some((MODE, DecklinkVideoOutputFlags::empty()))
+    );
+
+    let callback = Arc::new(Echo {
+        slot: echo.slot(),
+        retained: AtomicUsize::new(0),
+    });
+    input
+        .enable_video_input(
+            MODE,
+            DecklinkPixelFormat::Format8BitYUV,
+            DecklinkVideoInputFlags::empty(),
+        )
+        .unwrap();
+    common::start_streams(&mut input, callback.clone());
+
+    // Nothing has been captured yet
+    assert!(!echo.poll().unwrap());
+
+    let mock = backend.input(0);
+    assert!(mock.deliver_frame(mock.frame().fill(0x42)).is_ok());
+    assert_eq!(callback.retained.load(Ordering::SeqCst), 1);
+    assert!(echo.poll().unwrap());
+    // The retained frame is only displayed once
+    assert!(!echo.poll().unwrap());
+
+    let displayed = backend.output(0).displayed();
+    assert_eq!(displayed, vec![mock.frame().fill(0x42)]);
+}
+
+#[test]
+fn frames_are_decimated_to_the_echo_rate() {
+    let backend = backend();
+    let devices = get_devices().unwrap();
+    let output = devices[0].output().unwrap();
+    let mut input = devices[0].input().unwrap();
+
+    // One frame every ten minutes keeps only the first
+    let echo = MonitorEcho::new(&output, MODE, 1.0 / 600.0).unwrap();
+    let callback = Arc::new(Echo {
+        slot: echo.slot(),
+        retained: AtomicUsize::new(0),
+    });
+    input
+        .enable_video_input(
+            MODE,
+            DecklinkPixelFormat::Format8BitYUV,
+            DecklinkVideoInputFlags::empty(),
+        )
+        .unwrap();
+    common::start_streams(&mut input, callback.clone());
+
+    let mock = backend.input(0);
+    for value in 0..10 {
+        assert!(mock.deliver_frame(mock.frame().fill(value)).is_ok());
+    }
+    assert_eq!(callback.retained.load(Ordering::SeqCst), 1);
+    assert!(echo.poll().unwrap());
+    assert_eq!(backend.output(0).displayed(), vec![mock.frame().fill(0)]);
+}
+
+#[test]
+fn echo_gives_way_to_playout() {
+    let backend = backend();
+    let devices = get_devices().unwrap();
+    let output = devices[0].output().unwrap();
+
+    let mut echo = MonitorEcho::new(&output, MODE, 5.0).unwrap();
+    // The output is in use by the echo
+    let playout =
+        output.enable_video_output_scheduled(MODE, DecklinkVideoOutputFlags::empty(), 25000);
+    assert!(matches!(playout, Err(SdkError::ACCESSDENIED)));
+
+    echo.suspend();
+    assert!(echo.is_suspended());
+    assert_eq!(backend.output(0).video(), None);
+    assert!(!echo.poll().unwrap());
+
+    let playout = output
+        .enable_video_output_scheduled(MODE, DecklinkVideoOutputFlags::empty(), 25000)
+        .unwrap();
+    assert!(matches!(echo.resume(&output), Err(SdkError::ACCESSDENIED)));
+    assert!(echo.is_suspended());
+    assert!(matches!(
+        MonitorEcho::new(&output, MODE, 5.0),
+        Err(SdkError::ACCESSDENIED)
+    ));
+
+    drop(playout);
+    echo.resume(&output).unwrap();
+    assert!(!echo.is_suspended());
+    assert!(backend.output(0).video().is_some());
+}
+
+#[test]
+fn output_is_disabled_on_drop() {
+    let backend = backend();
+    let devices = get_devices().unwrap();
+    let output = devices[0].output().unwrap();
+
+    let echo = MonitorEcho::new(&output, MODE, 5.0).unwrap();
+    assert!(backend.output(0).video().is_some());
+    drop(echo);
+    assert_eq!(backend.output(0).video(), None);
+}
+
+#[test]
+fn echo_rate_must_be_positive() {
+    let _backend = backend();
+    let devices = get_devices().unwrap();
+    let output = devices[0].output().unwrap();
+
+    for fps in [0.0, -5.0, f64::NAN, f64::INFINITY] {
+        assert!(matches!(
+            MonitorEcho::new(&output, MODE, fps),
+            Err(SdkError::INVALIDARG)
+        ));
+    }
+}
@@ -0,0 +1,607 @@
+//! Movie files written from mock captures, read back with a box reader of their own.
+#![cfg(all(feature = "container", feature = "mock-backend"))]
+
+mod common;
+
+use decklink::device::input::{
+    DeckLinkInputCallback, DecklinkAudioInputPacket, DecklinkAudioSampleType,
+    DecklinkDetectedVideoInputFormatFlags, DecklinkInputDevice, DecklinkVideoInputFlags,
+    DecklinkVideoInputFormatChangedEvents, InputHandler,
+};
+use decklink::display_mode::{DecklinkDisplayModeId, DecklinkFieldDominance};
+use decklink::experimental::mov::{MovAudioConfig, MovConfig, MovWriter, SegmentedMovWriter};
+use decklink::frame::{DecklinkPixelFormat, DecklinkVideoFrame};
+use decklink::mock::{MockBackend, MockFrame, MockInput};
+use decklink::segment::{SegmentPolicy, SegmentReason};
+use decklink::time::{DecklinkFrameTiming, DecklinkTime};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Reads the boxes of a QuickTime file. It follows the file format documentation rather
+/// than the writer, so that the tests check what a player would find.
+mod qt {
+    use std::convert::TryInto;
+
+    fn u16_at(data: &[u8], at: usize) -> u16 {
+        u16::from_be_bytes(data[at..at + 2].try_into().unwrap())
+    }
+
+    pub fn u32_at(data: &[u8], at: usize) -> u32 {
+        u32::from_be_bytes(data[at..at + 4].try_into().unwrap())
+    }
+
+    fn u64_at(data: &[u8], at: usize) -> u64 {
+        u64::from_be_bytes(data[at..at + 8].try_into().unwrap())
+    }
+
+    /// A box, with the offset of its contents in the data it was read from.
+    pub struct Atom<'a> {
+        pub kind: [u8; 4],
+        pub offset: usize,
+        pub body: &'a [u8],
+    }
+
+    /// The boxes in `data`, in order. A size of 1 is followed by a 64-bit size, and a size
+    /// of 0 runs to the end.
+    pub fn atoms(data: &[u8], offset: usize) -> Vec<Atom<'_>> {
+        let mut atoms = Vec::new();
+        let mut at = 0;
+        while at + 8 <= data.len() {
+            let kind = data[at + 4..at + 8].try_into().unwrap();
+            let (header, size) = match u32_at(data, at) {
+                0 => (8, data.len() - at),
+                1 => (16, u64_at(data, at + 8) as usize),
+                size => (8, size as usize),
+            };
+            atoms.push(Atom {
+                kind,
+                offset: offset + at + header,
+                body: &data[at + header..at + size],
+            });
+            at += size;
+        }
+        atoms
+    }
+
+    /// The contents of the first box at `path` below `data`.
+    pub fn find<'a>(data: &'a [u8], path: &[&[u8; 4]]) -> Option<&'a [u8]> {
+        let (first, rest) = path.split_first()?;
+        let atom = atoms(data, 0).into_iter().find(|a| &a.kind == *first)?;
+        if rest.is_empty() {
+            Some(atom.body)
+        } else {
+            find(atom.body, rest)
+        }
+    }
+
+    /// A track, as read from its `trak` box.
+    #[derive(Debug)]
+    pub struct Track {
+        pub handler: [u8; 4],
+        pub width: u32,
+        pub height: u32,
+        pub timescale: u32,
+        pub duration: u64,
+        /// The type and contents of the sample description.
+        pub entry: ([u8; 4], Vec<u8>),
+        /// `(count, duration)` runs of sample durations.
+        pub durations: Vec<(u32, u32)>,
+        /// `(first chunk, samples per chunk)` from the sample to chunk table.
+        pub chunks: Vec<(u32, u32)>,
+        pub sample_size: u32,
+        pub sample_count: u32,
+        pub chunk_offsets: Vec<u64>,
+        /// `(duration in the movie timescale, media time)` edits.
+        pub edits: Vec<(u64, i64)>,
+    }
+
+    impl Track {
+        fn read(trak: &[u8]) -> Track {
+            let tkhd = find(trak, &[b"tkhd"]).unwrap();
+            // Past the times, id, duration, layers, volume and matrix
+            let dims = if tkhd[0] == 1 { 88 } else { 76 };
+            let mdhd = find(trak, &[b"mdia", b"mdhd"]).unwrap();
+            let (timescale, duration) = if mdhd[0] == 1 {
+                (u32_at(mdhd, 20), u64_at(mdhd, 24))
+            } else {
+                (u32_at(mdhd, 12), u32_at(mdhd, 16) as u64)
+            };
+            let hdlr = find(trak, &[b"mdia", b"hdlr"]).unwrap();
+            let stbl = find(trak, &[b"mdia", b"minf", b"stbl"]).unwrap();
+            let stsd = find(stbl, &[b"stsd"]).unwrap();
+            let entry = &atoms(&stsd[8..], 0)[0];
+            let table = |kind: &[u8; 4], width: usize| -> Vec<Vec<u64>> {
+                let body = find(stbl, &[kind]).unwrap();
+                (0..u32_at(body, 4) as usize)
+                    .map(|i| {
+                        let at = 8 + i * width * 4;
+                        (0..width)
+                            .map(|j| u32_at(body, at + j * 4) as u64)
+                            .collect()
+                    })
+                    .collect()
+            };
+            let stsz = find(stbl, &[b"stsz"]).unwrap();
+            let co64 = find(stbl, &[b"co64"]).unwrap();
+            let edits = match find(trak, &[b"edts", b"elst"]) {
+                Some(elst) => (0..u32_at(elst, 4) as usize)
+                    .map(|i| {
+                        if elst[0] == 1 {
+                            let at = 8 + i * 20;
+                            (u64_at(elst, at), u64_at(elst, at + 8) as i64)
+                        } else {
+                            let at = 8 + i * 12;
+                            (u32_at(elst, at) as u64, u32_at(elst, at + 4) as i32 as i64)
+                        }
+                    })
+                    .collect(),
+                None => Vec::new(),
+            };
+            Track {
+                handler: hdlr[8..12].try_into().unwrap(),
+                width: u32_at(tkhd, dims) >> 16,
+                height: u32_at(tkhd, dims + 4) >> 16,
+                timescale,
+                duration,
+                entry: (entry.kind, entry.body.to_vec()),
+                durations: table(b"stts", 2)
+                    .iter()
+                    .map(|r| (r[0] as u32, r[1] as u32))
+                    .collect(),
+                chunks: table(b"stsc", 3)
+                    .iter()
+                    .map(|r| (r[0] as u32, r[1] as u32))
+                    .collect(),
+                sample_size: u32_at(stsz, 4),
+                sample_count: u32_at(stsz, 8),
+                chunk_offsets: (0..u32_at(co64, 4) as usize)
+                    .map(|i| u64_at(co64, 8 + i * 8))
+                    .collect(),
+                edits,
+            }
+        }
+
+        /// The total of the sample durations.
+        pub fn sample_duration(&self) -> u64 {
+            self.durations
+                .iter()
+                .map(|(count, delta)| *count as u64 * *delta as u64)
+                .sum()
+        }
+
+        /// The offset and number of samples of each chunk.
+        pub fn chunk_samples(&self) -> Vec<(u64, u32)> {
+            self.chunk_offsets
+                .iter()
+                .enumerate()
+                .map(|(i, offset)| {
+                    let chunk = i as u32 + 1;
+                    let (_, samples) = self
+                        .chunks
+                        .iter()
+                        .rev()
+                        .find(|(first, _)| *first <= chunk)
+                        .unwrap();
+                    (*offset, *samples)
+                })
+                .collect()
+        }
+
+        /// The width, height and compressor name of a video sample description.
+        pub fn video_entry(&self) -> (u16, u16, String) {
+            let body = &self.entry.1;
+            let name_len = body[42] as usize;
+            let name = String::from_utf8(body[43..43 + name_len].to_vec()).unwrap();
+            (u16_at(body, 24), u16_at(body, 26), name)
+        }
+
+        /// The boxes after the fixed fields of a video sample description.
+        pub fn video_entry_atoms(&self) -> Vec<([u8; 4], Vec<u8>)> {
+            atoms(&self.entry.1[78..], 0)
+                .into_iter()
+                .map(|a| (a.kind, a.body.to_vec()))
+                .collect()
+        }
+
+        /// The sample rate, channels, bits and bytes a frame of a version 2 sound
+        /// description.
+        pub fn audio_entry(&self) -> (f64, u32, u32, u32) {
+            let body = &self.entry.1;
+            assert_eq!(u16_at(body, 8), 2, "a version 2 sound description");
+            (
+                f64::from_bits(u64_at(body, 32)),
+                u32_at(body, 40),
+                u32_at(body, 48),
+                u32_at(body, 56),
+            )
+        }
+    }
+
+    /// A movie, as read from its `moov` box.
+    pub struct Movie {
+        /// The types of the top level boxes, in order.
+        pub layout: Vec<[u8; 4]>,
+        pub timescale: u32,
+        pub duration: u64,
+        pub tracks: Vec<Track>,
+    }
+
+    impl Movie {
+        pub fn read(file: &[u8]) -> Movie {
+            let top = atoms(file, 0);
+            let layout = top.iter().map(|a| a.kind).collect();
+            let moov = top.iter().find(|a| &a.kind == b"moov").unwrap().body;
+            let mvhd = find(moov, &[b"mvhd"]).unwrap();
+            let (timescale, duration) = if mvhd[0] == 1 {
+                (u32_at(mvhd, 20), u64_at(mvhd, 24))
+            } else {
+                (u32_at(mvhd, 12), u32_at(mvhd, 16) as u64)
+            };
+            let tracks = atoms(moov, 0)
+                .iter()
+                .filter(|a| &a.kind == b"trak")
+                .map(|a| Track::read(a.body))
+                .collect();
+            Movie {
+                layout,
+                timescale,
+                duration,
+                tracks,
+            }
+        }
+
+        pub fn track(&self, handler: &[u8; 4]) -> &Track {
+            self.tracks.iter().find(|t| &t.handler == handler).unwrap()
+        }
+    }
+}
+
+use qt::Movie;
+
+const FORMAT: DecklinkPixelFormat = DecklinkPixelFormat::Format8BitYUV;
+/// 25 frames per second, in the timescale of the mock stream times.
+const FRAME: DecklinkTime = DecklinkTime {
+    value: 1000,
+    scale: 25000,
+};
+/// The audio captured with each frame, at 48kHz.
+const AUDIO_FRAMES: usize = 1920;
+const STEREO_16: MovAudioConfig = MovAudioConfig {
+    sample_rate: 48000,
+    channels: 2,
+    sample_type: DecklinkAudioSampleType::Int16,
+};
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("decklink-mov-{}-{}", name, std::process::id()))
+}
+
+type Captured = Vec<(DecklinkVideoFrame, Option<DecklinkFrameTiming>)>;
+
+/// Keeps the frames a mock input captures, with their timing.
+#[derive(Default)]
+struct Capture {
+    timing: Mutex<Option<DecklinkFrameTiming>>,
+    frames: Mutex<Captured>,
+    /// The number of frames captured before each format change.
+    changes: Mutex<Vec<usize>>,
+}
+
+impl DeckLinkInputCallback for Capture {
+    fn video_input_format_changed(
+        &self,
+        _events: DecklinkVideoInputFormatChangedEvents,
+        _new_display_mode: DecklinkDisplayModeId,
+        _detected_signal_flags: DecklinkDetectedVideoInputFormatFlags,
+    ) {
+        let captured = self.frames.lock().unwrap().len();
+        self.changes.lock().unwrap().push(captured);
+    }
+
+    fn video_input_frame_timing(&self, timing: DecklinkFrameTiming) {
+        *self.timing.lock().unwrap() = Some(timing);
+    }
+
+    fn video_input_frame_arrived(&self, video_frame: Option<DecklinkVideoFrame>) -> bool {
+        let timing = self.timing.lock().unwrap().take();
+        self.frames
+            .lock()
+            .unwrap()
+            .push((video_frame.unwrap(), timing));
+        true
+    }
+}
+
+fn start(
+    backend: &MockBackend,
+    callback: Arc<dyn InputHandler>,
+) -> (DecklinkInputDevice, MockInput) {
+    let mut input = common::open_input(
+        DecklinkDisplayModeId::HD1080p25,
+        FORMAT,
+        DecklinkVideoInputFlags::empty(),
+    );
+    common::enable_stereo_audio(&mut input);
+    common::start_streams(&mut input, callback);
+    (input, backend.input(0))
+}
+
+/// Frame `n` of `width` pixels by 2 rows, filled with `n`, at its time at 25 frames per
+/// second.
+fn frame(n: i64, width: usize) -> MockFrame {
+    MockFrame::new(width, 2, FORMAT)
+        .fill(n as u8)
+        .stream_time(n * 1000, 1000, 25000)
+}
+
+/// The frames `deliver` delivers to a mock input, and where the format changes fell.
+fn capture_with(deliver: impl FnOnce(&MockInput)) -> (Captured, Vec<usize>) {
+    let backend = common::mini_recorder();
+    let capture = Arc::new(Capture::default());
+    let (_input, mock) = start(&backend, capture.clone());
+    deliver(&mock);
+    let frames = std::mem::take(&mut *capture.frames.lock().unwrap());
+    let changes = std::mem::take(&mut *capture.changes.lock().unwrap());
+    (frames, changes)
+}
+
+/// Capture frames `numbers` from a mock input.
+fn capture(numbers: &[i64]) -> Captured {
+    capture_with(|mock| {
+        for n in numbers {
+            assert!(mock.deliver_frame(frame(*n, 48)).is_ok());
+        }
+    })
+    .0
+}
+
+/// The stereo audio of frame `n` at 48kHz, every sample set to `n`.
+fn audio(n: i64) -> DecklinkAudioInputPacket {
+    let bytes = (n as i16).to_le_bytes().repeat(AUDIO_FRAMES * 2);
+    let time = DecklinkTime::new(n * AUDIO_FRAMES as i64, 48000);
+    DecklinkAudioInputPacket::from_samples(DecklinkAudioSampleType::Int16, 2, bytes, time)
+}
+
+/// The first byte of each video sample.
+fn video_samples(file: &[u8], movie: &Movie) -> Vec<u8> {
+    let video = movie.track(b"vide");
+    video
+        .chunk_samples()
+        .iter()
+        .flat_map(|(offset, samples)| {
+            (0..*samples as u64)
+                .map(move |i| file[(offset + i * video.sample_size as u64) as usize])
+        })
+        .collect()
+}
+
+#[test]
+fn a_capture_with_a_drop_plays_frame_accurately() {
+    let numbers = [0, 1, 2, 3, 4, 6, 7, 8, 9];
+    let frames = capture(&numbers);
+    let path = temp_path("drop");
+    let mut config = MovConfig::new(FRAME).with_audio(STEREO_16);
+    config.field_dominance = DecklinkFieldDominance::ProgressiveFrame;
+    config.flush_interval = None;
+    let mut writer = MovWriter::create(&path, config).unwrap();
+    for ((frame, timing), n) in frames.iter().zip(numbers) {
+        writer.write_audio(&audio(n)).unwrap();
+        writer.write_frame(frame, timing.as_ref()).unwrap();
+    }
+    let info = writer.finish().unwrap();
+
+    assert_eq!(info.video_frames, 9);
+    assert_eq!(info.video_gaps, 1);
+    assert_eq!(info.video_duration, DecklinkTime::new(10_000, 25000));
+    assert_eq!(info.silence_frames, AUDIO_FRAMES as u64);
+    assert_eq!(info.audio_frames, 10 * AUDIO_FRAMES as u64);
+
+    let file = std::fs::read(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(info.byte_count, file.len() as u64);
+    let movie = Movie::read(&file);
+    assert_eq!(movie.layout, [*b"ftyp", *b"mdat", *b"moov"]);
+    assert_eq!(movie.tracks.len(), 2);
+    assert_eq!((movie.timescale, movie.duration), (25000, 10_000));
+
+    let video = movie.track(b"vide");
+    assert_eq!((video.width, video.height), (48, 2));
+    assert_eq!(video.entry.0, *b"2vuy");
+    assert_eq!(
+        video.video_entry(),
+        (48, 2, "Component Y'CbCr 8-bit 4:2:2".into())
+    );
+    let entry_atoms = video.video_entry_atoms();
+    assert_eq!(
+        entry_atoms[0],
+        (*b"colr", b"nclc\0\x06\0\x01\0\x06".to_vec())
+    );
+    assert_eq!(entry_atoms[1], (*b"fiel", vec![1, 0]));
+    assert_eq!((video.timescale, video.duration), (25000, 10_000));
+    // The frame before the drop lasts for two
+    assert_eq!(video.durations, [(4, 1000), (1, 2000), (4, 1000)]);
+    assert_eq!(video.sample_duration(), 10_000);
+    assert_eq!((video.sample_size, video.sample_count), (96 * 2, 9));
+    assert_eq!(video_samples(&file, &movie), [0, 1, 2, 3, 4, 6, 7, 8, 9]);
+
+    let sound = movie.track(b"soun");
+    assert_eq!(sound.entry.0, *b"lpcm");
+    assert_eq!(sound.audio_entry(), (48000.0, 2, 16, 4));
+    assert_eq!((sound.timescale, sound.duration), (48000, 10 * 1920));
+    assert_eq!(sound.sample_count, 10 * 1920);
+    assert_eq!(sound.sample_duration(), 10 * 1920);
+    assert_eq!(sound.edits, [(10_000, 0)]);
+    // The silence filling the drop is between the audio of frames 4 and 6
+    let chunks = sound.chunk_samples();
+    assert_eq!(chunks.len(), 10);
+    let first_sample = |(offset, _): &(u64, u32)| file[*offset as usize];
+    let firsts: Vec<_> = chunks.iter().map(first_sample).collect();
+    assert_eq!(firsts, [0, 1, 2, 3, 4, 0, 6, 7, 8, 9]);
+}
+
+#[test]
+fn audio_starting_after_the_video_is_placed_with_an_edit() {
+    let frames = capture(&[0, 1]);
+    let path = temp_path("edit");
+    let mut config = MovConfig::new(FRAME).with_audio(STEREO_16);
+    config.flush_interval = None;
+    let mut writer = MovWriter::create(&path, config).unwrap();
+    // The audio starts half a frame, 960 sample frames, after the first frame
+    let late = DecklinkAudioInputPacket::from_samples(
+        DecklinkAudioSampleType::Int16,
+        2,
+        vec![0; 960 * 4],
+        DecklinkTime::new(960, 48000),
+    );
+    writer
+        .write_frame(&frames[0].0, frames[0].1.as_ref())
+        .unwrap();
+    writer.write_audio(&late).unwrap();
+    writer
+        .write_frame(&frames[1].0, frames[1].1.as_ref())
+        .unwrap();
+    writer.finish().unwrap();
+
+    let file = std::fs::read(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let movie = Movie::read(&file);
+    // An empty edit of half a frame, then the audio
+    assert_eq!(movie.track(b"soun").edits, [(500, -1), (500, 0)]);
+    assert!(movie.track(b"vide").edits.is_empty());
+}
+
+#[test]
+fn a_file_cut_short_plays_up_to_its_last_flush() {
+    let numbers: Vec<_> = (0..12).collect();
+    let frames = capture(&numbers);
+    let path = temp_path("cut");
+    let mut config = MovConfig::new(FRAME);
+    // Every 5 frames
+    config.flush_interval = Some(Duration::from_millis(200));
+    let mut writer = MovWriter::create(&path, config).unwrap();
+    for (frame, timing) in &frames {
+        writer.write_frame(frame, timing.as_ref()).unwrap();
+    }
+    // As if the process died, without finishing the file
+    std::mem::forget(writer);
+
+    let file = std::fs::read(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let movie = Movie::read(&file);
+    // The first index was replaced by the second, and the samples after it are open
+    assert_eq!(
+        movie.layout,
+        [*b"ftyp", *b"mdat", *b"free", *b"mdat", *b"moov", *b"wide", *b"mdat"]
+    );
+    let video = movie.track(b"vide");
+    assert_eq!(video.sample_count, 10);
+    assert_eq!(video.sample_duration(), 10_000);
+    assert_eq!(video_samples(&file, &movie), (0..10).collect::<Vec<u8>>());
+}
+
+#[test]
+fn files_past_4gb_use_64_bit_offsets() {
+    // Every offset is 64-bit and every mdat has a 64-bit size, whatever the file length
+    let frames = capture(&[0, 1]);
+    let path = temp_path("offsets");
+    let mut writer = MovWriter::create(&path, MovConfig::new(FRAME)).unwrap();
+    for (frame, timing) in &frames {
+        writer.write_frame(frame, timing.as_ref()).unwrap();
+    }
+    writer.finish().unwrap();
+
+    let file = std::fs::read(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let mdat = qt::atoms(&file, 0)
+        .into_iter()
+        .find(|a| &a.kind == b"mdat")
+        .unwrap();
+    assert_eq!(qt::u32_at(&file, mdat.offset - 16), 1);
+    let stbl = qt::find(&file, &[b"moov", b"trak", b"mdia", b"minf", b"stbl"]).unwrap();
+    assert!(qt::find(stbl, &[b"co64"]).is_some());
+    assert!(qt::find(stbl, &[b"stco"]).is_none());
+}
+
+#[test]
+fn a_format_change_starts_a_new_file() {
+    // Frame 2 is dropped. The format change is reported before frame 5, and the frame size
+    // changes without one at frame 8.
+    let numbers = [0, 1, 3, 4, 5, 6, 7, 8, 9];
+    let (frames, changes) = capture_with(|mock| {
+        for n in numbers {
+            if n == 5 {
+                assert!(mock
+                    .deliver_format_change(
+                        DecklinkVideoInputFormatChangedEvents::DISPLAY_MODE_CHANGED,
+                        DecklinkDisplayModeId::HD1080p25,
+                        DecklinkDetectedVideoInputFormatFlags::YCBCR_422,
+                    )
+                    .is_ok());
+            }
+            let width = if n < 8 { 48 } else { 96 };
+            assert!(mock.deliver_frame(frame(n, width)).is_ok());
+        }
+    });
+    assert_eq!(changes, [4]);
+
+    let dir = temp_path("segments");
+    std::fs::create_dir_all(&dir).unwrap();
+    let pattern = dir.join("take-{index}.mov");
+    let mut writer = SegmentedMovWriter::new(
+        pattern.to_string_lossy(),
+        SegmentPolicy::EveryFrames(100),
+        MovConfig::new(FRAME).with_audio(STEREO_16),
+    );
+    for (i, ((frame, timing), n)) in frames.iter().zip(numbers).enumerate() {
+        if changes.contains(&i) {
+            writer.format_changed();
+        }
+        // As the driver does, the audio of a frame comes before it
+        writer.write_audio(audio(n));
+        writer.write_frame(frame, timing.as_ref()).unwrap();
+    }
+    let segments = writer.finish().unwrap();
+
+    let summary: Vec<_> = segments
+        .iter()
+        .map(|s| (s.boundary.first_frame, s.boundary.reason, s.frame_count))
+        .collect();
+    assert_eq!(
+        summary,
+        [
+            (0, SegmentReason::Start, 2),
+            (2, SegmentReason::Discontinuity, 2),
+            (4, SegmentReason::FormatChanged, 3),
+            (7, SegmentReason::FormatChanged, 2),
+        ]
+    );
+    let mut contents = Vec::new();
+    for segment in &segments {
+        let file = std::fs::read(&segment.path).unwrap();
+        assert_eq!(file.len() as u64, segment.byte_count);
+        let movie = Movie::read(&file);
+        let video = movie.track(b"vide");
+        assert_eq!(video.sample_count as u64, segment.frame_count);
+        assert_eq!(video.sample_duration(), segment.frame_count * 1000);
+        // Each file has the audio of its own frames, lined up with the first of them
+        let sound = movie.track(b"soun");
+        assert_eq!(
+            sound.sample_count as u64,
+            segment.frame_count * AUDIO_FRAMES as u64
+        );
+        assert_eq!(sound.edits[0].1, 0);
+        contents.push((video.width, video_samples(&file, &movie)));
+        std::fs::remove_file(&segment.path).unwrap();
+    }
+    std::fs::remove_dir(&dir).unwrap();
+    assert_eq!(
+        contents,
+        [
+            (48, vec![0, 1]),
+            (48, vec![3, 4]),
+            (48, vec![5, 6, 7]),
+            (96, vec![8, 9]),
+        ]
+    );
+}
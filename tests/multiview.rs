@@ -0,0 +1,378 @@
+//! Multiview layouts, and composites of mock captures fed through taps.
+#![cfg(feature = "image-interop")]
+
+use decklink::multiview::{MultiviewConfig, MultiviewLayout, TileRect};
+use std::time::Duration;
+
+#[test]
+fn grid_tiles_cover_the_frame() {
+    assert_eq!(
+        MultiviewLayout::quad().tiles(1920, 1080),
+        [
+            TileRect::new(0, 0, 960, 540),
+            TileRect::new(960, 0, 960, 540),
+            TileRect::new(0, 540, 960, 540),
+            TileRect::new(960, 540, 960, 540),
+        ]
+    );
+
+    // Tiles that do not divide the frame evenly still leave no gaps
+    let tiles = MultiviewLayout::nine().tiles(1280, 720);
+    assert_eq!(tiles.len(), 9);
+    assert_eq!(tiles[0], TileRect::new(0, 0, 426, 240));
+    assert_eq!(tiles[1], TileRect::new(426, 0, 427, 240));
+    assert_eq!(tiles[8], TileRect::new(853, 480, 427, 240));
+    let area: u32 = tiles.iter().map(|t| t.width * t.height).sum();
+    assert_eq!(area, 1280 * 720);
+}
+
+#[test]
+fn configs_with_nothing_to_show_are_refused() {
+    let field = |config: MultiviewConfig| config.validate().unwrap_err().field;
+    assert!(MultiviewConfig::default().validate().is_ok());
+    assert_eq!(
+        field(MultiviewConfig {
+            layout: MultiviewLayout::grid(0, 2),
+            ..MultiviewConfig::default()
+        }),
+        "layout"
+    );
+    assert_eq!(
+        field(MultiviewConfig {
+            layout: MultiviewLayout::Rects(vec![TileRect::new(1900, 0, 40, 40)]),
+            ..MultiviewConfig::default()
+        }),
+        "layout"
+    );
+    assert_eq!(
+        field(MultiviewConfig {
+            frame_interval: Duration::ZERO,
+            ..MultiviewConfig::default()
+        }),
+        "frame_interval"
+    );
+    assert!(MultiviewConfig::builder().size(0, 1080).build().is_err());
+}
+
+#[cfg(feature = "mock-backend")]
+mod mock {
+    use decklink::device::get_devices;
+    use decklink::device::input::{
+        CallbackResult, DecklinkInputDevice, DecklinkVideoInputFlags, FrameArrival, InputHandler,
+    };
+    use decklink::display_mode::DecklinkDisplayModeId;
+    use decklink::frame::DecklinkPixelFormat;
+    use decklink::mock::{MockBackend, MockDevice, MockFrame, MockInput};
+    use decklink::multiview::{
+        CompositeFrame, MultiviewConfig, MultiviewLayout, Multiviewer, RawOutput, TileRect,
+        TileSignal,
+    };
+    use decklink::tap::{TapHandle, TapSpec, TapSplitter};
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+
+    const FORMAT: DecklinkPixelFormat = DecklinkPixelFormat::Format8BitBGRA;
+
+    /// The grey level each source is filled with.
+    const FILLS: [u8; 4] = [0x20, 0x60, 0xA0, 0xE0];
+
+    const LABELS: [&str; 4] = ["CAM 1", "CAM 2", "CAM 3", "CAM 4"];
+
+    struct Discard;
+
+    impl InputHandler for Discard {
+        fn frame_arrived(&self, _arrival: &FrameArrival<'_>) -> CallbackResult {
+            CallbackResult::Ok
+        }
+    }
+
+    struct Source {
+        _input: DecklinkInputDevice,
+        mock: MockInput,
+        splitter: TapSplitter,
+        tap: TapHandle,
+    }
+
+    /// A quad split of 64x36 tiles, the size of the mock frames, so pictures are not
+    /// scaled.
+    fn config() -> MultiviewConfig {
+        MultiviewConfig::builder()
+            .size(128, 72)
+            .layout(MultiviewLayout::quad())
+            .build()
+            .unwrap()
+    }
+
+    /// Four inputs, each tapped by a feed for the tile of the same index.
+    fn start(viewer: &Multiviewer) -> (MockBackend, Vec<Source>) {
+        let backend = MockBackend::install(
+            LABELS
+                .iter()
+                .map(|name| MockDevice::new(name).pixel_formats(&[FORMAT]))
+                .collect(),
+        );
+        let devices = get_devices().unwrap();
+        let sources = devices
+            .iter()
+            .enumerate()
+            .map(|(i, device)| {
+                let mut input = device.input().unwrap();
+                input
+                    .enable_video_input(
+                        DecklinkDisplayModeId::HD1080p25,
+                        FORMAT,
+                        DecklinkVideoInputFlags::empty(),
+                    )
+                    .unwrap();
+                let splitter = TapSplitter::new(Arc::new(Discard), None);
+                input.set_handler(Some(splitter.callback())).unwrap();
+                let tap = splitter.tap(viewer.feed(i, LABELS[i]), TapSpec::default());
+                input.start_streams().unwrap();
+                Source {
+                    _input: input,
+                    mock: backend.input(i),
+                    splitter,
+                    tap,
+                }
+            })
+            .collect();
+        (backend, sources)
+    }
+
+    fn frame(source: usize) -> MockFrame {
+        MockFrame::new(64, 36, FORMAT).fill(FILLS[source])
+    }
+
+    fn wait_for(condition: impl Fn() -> bool) {
+        let start = Instant::now();
+        while !condition() {
+            assert!(start.elapsed() < Duration::from_secs(5), "timed out");
+            std::thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    fn wait_for_signal(viewer: &Multiviewer, tile: usize, signal: TileSignal) {
+        wait_for(|| viewer.stats().tiles[tile].signal == signal);
+    }
+
+    fn grey(level: u8) -> [u8; 3] {
+        [level; 3]
+    }
+
+    /// Whether `rect` shows the slate, by its border and the slate around its text.
+    fn shows_slate(frame: &CompositeFrame, rect: TileRect) -> bool {
+        let border = [255, 0, 0];
+        frame.rgb(rect.x, rect.y + rect.height / 2) == border
+            && frame.rgb(rect.x + rect.width - 1, rect.y + rect.height - 1) == border
+            && frame.rgb(rect.x + 3, rect.y + rect.height - 4) == grey(48)
+    }
+
+    #[test]
+    fn each_source_is_placed_in_its_tile_under_its_label() {
+        let viewer = Multiviewer::new(config()).unwrap();
+        let (_backend, sources) = start(&viewer);
+        for (i, source) in sources.iter().enumerate() {
+            assert!(source.mock.deliver_frame(frame(i)).is_ok());
+        }
+        wait_for(|| viewer.stats().tiles.iter().all(|t| t.scaled == 1));
+
+        let composite = viewer.composite();
+        assert_eq!(
+            viewer.tiles(),
+            [
+                TileRect::new(0, 0, 64, 36),
+                TileRect::new(64, 0, 64, 36),
+                TileRect::new(0, 36, 64, 36),
+                TileRect::new(64, 36, 64, 36),
+            ]
+        );
+        for (i, &rect) in viewer.tiles().iter().enumerate() {
+            // The label is five characters of 6 pixels and a pixel of padding, 4 pixels in
+            let label = viewer.label_rect(i).unwrap();
+            assert_eq!(label, TileRect::new(rect.x + 4, rect.y + 4, 31, 9));
+            let mut text = 0;
+            for y in rect.y..rect.y + rect.height {
+                for x in rect.x..rect.x + rect.width {
+                    let pixel = composite.rgb(x, y);
+                    if !label.contains(x, y) {
+                        assert_eq!(pixel, grey(FILLS[i]), "tile {} at {},{}", i, x, y);
+                    } else if pixel == grey(255) {
+                        text += 1;
+                    } else {
+                        assert_eq!(pixel, grey(0), "label {} at {},{}", i, x, y);
+                    }
+                }
+            }
+            assert!(text > 0, "label {} has no text", i);
+            assert_eq!(viewer.stats().tiles[i].label, LABELS[i]);
+        }
+    }
+
+    #[test]
+    fn lost_and_removed_sources_show_the_slate_until_they_come_back() {
+        let viewer = Multiviewer::new(config()).unwrap();
+        let (_backend, sources) = start(&viewer);
+        for (i, source) in sources.iter().enumerate() {
+            assert!(source.mock.deliver_frame(frame(i)).is_ok());
+        }
+        wait_for(|| {
+            viewer
+                .stats()
+                .tiles
+                .iter()
+                .all(|t| t.signal == TileSignal::Live)
+        });
+        let rects = viewer.tiles().to_vec();
+        let composite = viewer.composite();
+        assert!(rects.iter().all(|&rect| !shows_slate(&composite, rect)));
+
+        // A frame without an input source replaces the picture at once
+        assert!(sources[3].mock.deliver_frame(frame(3).no_signal()).is_ok());
+        wait_for_signal(&viewer, 3, TileSignal::NoSignal);
+        // A source whose tap finishes leaves its tile detached
+        sources[2].tap.cancel();
+        wait_for_signal(&viewer, 2, TileSignal::Detached);
+
+        let composite = viewer.composite();
+        assert!(shows_slate(&composite, rects[3]));
+        assert!(shows_slate(&composite, rects[2]));
+        let center = |rect: TileRect| composite.rgb(rect.x + 40, rect.y + 30);
+        assert_eq!(center(rects[0]), grey(FILLS[0]));
+        assert_eq!(center(rects[1]), grey(FILLS[1]));
+
+        // The signal coming back, and a new feed for the removed source, restore both
+        assert!(sources[3].mock.deliver_frame(frame(3)).is_ok());
+        let tap = sources[2]
+            .splitter
+            .tap(viewer.feed(2, "CAM 3 BACKUP"), TapSpec::default());
+        assert!(sources[2].mock.deliver_frame(frame(2)).is_ok());
+        wait_for_signal(&viewer, 3, TileSignal::Live);
+        wait_for_signal(&viewer, 2, TileSignal::Live);
+
+        let composite = viewer.composite();
+        assert!(rects.iter().all(|&rect| !shows_slate(&composite, rect)));
+        assert_eq!(
+            composite.rgb(rects[2].x + 40, rects[2].y + 30),
+            grey(FILLS[2])
+        );
+        assert_eq!(viewer.stats().tiles[2].label, "CAM 3 BACKUP");
+        // The longer label is clipped to the tile
+        assert_eq!(
+            viewer.label_rect(2),
+            Some(TileRect::new(rects[2].x + 4, rects[2].y + 4, 60, 9))
+        );
+        tap.cancel();
+    }
+
+    #[test]
+    fn a_stalled_source_does_not_hold_up_the_output() {
+        let config = MultiviewConfig {
+            frame_interval: Duration::from_millis(10),
+            signal_timeout: Duration::from_millis(100),
+            ..config()
+        };
+        let mut viewer = Multiviewer::new(config).unwrap();
+        let (_backend, sources) = start(&viewer);
+        let composites = Arc::new(Mutex::new(Vec::new()));
+        {
+            let composites = composites.clone();
+            viewer.start(move |frame: &CompositeFrame| {
+                composites
+                    .lock()
+                    .unwrap()
+                    .push((frame.sequence, frame.composited_at));
+            });
+        }
+
+        // The fourth source delivers one frame and then stalls, the others run at mixed rates
+        assert!(sources[3].mock.deliver_frame(frame(3)).is_ok());
+        let running = Instant::now();
+        let deliverers: Vec<_> = [10, 20, 40]
+            .iter()
+            .enumerate()
+            .map(|(i, &period)| {
+                let mock = sources[i].mock.clone();
+                std::thread::spawn(move || {
+                    while running.elapsed() < Duration::from_millis(500) {
+                        assert!(mock.deliver_frame(frame(i)).is_ok());
+                        std::thread::sleep(Duration::from_millis(period));
+                    }
+                })
+            })
+            .collect();
+
+        std::thread::sleep(Duration::from_millis(300));
+        let stats = viewer.stats();
+        let signals: Vec<_> = stats.tiles.iter().map(|t| t.signal).collect();
+        assert_eq!(
+            signals,
+            [
+                TileSignal::Live,
+                TileSignal::Live,
+                TileSignal::Live,
+                TileSignal::Stalled
+            ]
+        );
+        let latest = viewer.latest().unwrap();
+        assert!(shows_slate(&latest, viewer.tiles()[3]));
+
+        for deliverer in deliverers {
+            deliverer.join().unwrap();
+        }
+        viewer.stop();
+
+        // Composites kept coming at the output rate while a source delivered nothing
+        let composites = composites.lock().unwrap();
+        assert!(composites.len() >= 20, "{} composites", composites.len());
+        for (n, (sequence, _)) in composites.iter().enumerate() {
+            assert_eq!(*sequence, n as u64);
+        }
+        let longest_gap = composites
+            .windows(2)
+            .map(|w| w[1].1 - w[0].1)
+            .max()
+            .unwrap();
+        assert!(
+            longest_gap < Duration::from_millis(100),
+            "{:?}",
+            longest_gap
+        );
+        assert_eq!(viewer.stats().composited, composites.len() as u64);
+    }
+
+    /// A writer whose bytes the test can read while the compositor thread owns it.
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(bytes);
+            Ok(bytes.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn raw_output_writes_whole_frames() {
+        let mut viewer = Multiviewer::new(config()).unwrap();
+        let written = Shared::default();
+        viewer.start(RawOutput::new(written.clone()));
+        wait_for(|| viewer.stats().composited >= 2);
+        viewer.stop();
+
+        let frames = viewer.stats().composited as usize;
+        let bytes = written.0.lock().unwrap();
+        assert_eq!(bytes.len(), frames * 128 * 72 * 4);
+        // No feeds are attached, so every tile shows the slate
+        let latest = viewer.latest().unwrap();
+        assert_eq!(&bytes[bytes.len() - latest.data.len()..], &latest.data[..]);
+        assert!(viewer
+            .tiles()
+            .iter()
+            .all(|&rect| shows_slate(&latest, rect)));
+    }
+}
@@ -0,0 +1,82 @@
+//! Device and display mode names, read from a mock driver once and borrowed after that.
+#![cfg(feature = "mock-backend")]
+
+mod common;
+
+use decklink::device::get_devices;
+use decklink::device::DecklinkDeviceDisplayModes;
+use decklink::display_mode::DecklinkDisplayModeId;
+use decklink::invalid_utf8_string_count;
+use decklink::mock::{MockBackend, MockDevice};
+
+#[test]
+fn device_names_are_cached() {
+    let _backend = MockBackend::install(vec![MockDevice::new("DeckLink Duo 2 (1)")
+        .model_name("DeckLink Duo 2")
+        .sub_device(0x5a10, 42)]);
+    let devices = get_devices().unwrap();
+    let device = &devices[0];
+
+    let display_name = device.display_name_str().unwrap();
+    assert_eq!(display_name, "DeckLink Duo 2 (1)");
+    // Later calls borrow the same string
+    assert!(std::ptr::eq(
+        display_name,
+        device.display_name_str().unwrap()
+    ));
+    assert_eq!(device.display_name().as_deref(), Some(display_name));
+
+    let model_name = device.model_name_str().unwrap();
+    assert_eq!(model_name, "DeckLink Duo 2");
+    assert!(std::ptr::eq(model_name, device.model_name_str().unwrap()));
+    assert_eq!(device.model_name().as_deref(), Some(model_name));
+
+    assert_eq!(device.persistent_id(), Some(42));
+    assert_eq!(device.persistent_id(), Some(42));
+}
+
+#[test]
+fn device_without_a_persistent_id() {
+    let _backend = common::mini_recorder();
+    let devices = get_devices().unwrap();
+    assert_eq!(devices[0].persistent_id(), None);
+}
+
+#[test]
+fn display_mode_names_are_cached() {
+    let _backend = common::mini_recorder();
+    let devices = get_devices().unwrap();
+    let modes = devices[0].input().unwrap().display_modes().unwrap();
+    let mode = modes
+        .iter()
+        .find(|m| m.mode() == DecklinkDisplayModeId::HD1080p25)
+        .unwrap();
+
+    let name = mode.name_str().unwrap();
+    assert_eq!(name, "1080p25");
+    assert!(std::ptr::eq(name, mode.name_str().unwrap()));
+    assert_eq!(mode.name().as_deref(), Some(name));
+}
+
+#[test]
+fn labels_that_are_not_utf8_are_kept() {
+    // Latin-1, as a driver in another locale may report
+    let _backend = MockBackend::install(vec![
+        MockDevice::new("DeckLink Mini Recorder").display_name_bytes(b"Caf\xe9 Recorder")
+    ]);
+    let devices = get_devices().unwrap();
+    let before = invalid_utf8_string_count();
+
+    assert_eq!(
+        devices[0].display_name().as_deref(),
+        Some("Caf\u{fffd} Recorder")
+    );
+    assert_eq!(invalid_utf8_string_count(), before + 1);
+    // The converted name is cached, so it is only counted once
+    devices[0].display_name_str();
+    assert_eq!(invalid_utf8_string_count(), before + 1);
+    assert_eq!(
+        devices[0].model_name().as_deref(),
+        Some("DeckLink Mini Recorder")
+    );
+}
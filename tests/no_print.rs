@@ -0,0 +1,168 @@
+//! The library never prints. Everything it has to say is given to callers as an error, an
+//! event or a count, so services can act on it, and only the command line tool in `src/bin`
+//! and the examples print. The conditions most tempting to print are checked for their
+//! signals here too.
+
+use std::path::{Path, PathBuf};
+
+const PRINTING_MACROS: [&str; 5] = ["println!", "eprintln!", "print!", "eprint!", "dbg!"];
+
+/// The Rust files under `dir`, leaving out the command line tool.
+fn library_sources(dir: &Path, out: &mut Vec<PathBuf>) {
+    for entry in std::fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            if path.file_name().is_some_and(|name| name != "bin") {
+                library_sources(&path, out);
+            }
+        } else if path.extension().is_some_and(|ext| ext == "rs") {
+            out.push(path);
+        }
+    }
+}
+
+/// The printing macros used by `line` of code, ignoring comments, which hold doc examples.
+fn printing_macros(line: &str) -> Vec<&'static str> {
+    let code = line.split("//").next().unwrap();
+    PRINTING_MACROS
+        .into_iter()
+        .filter(|name| {
+            code.match_indices(name).any(|(at, _)| {
+                !code[..at]
+                    .chars()
+                    .next_back()
+                    .is_some_and(|c| c.is_alphanumeric() || c == '_')
+            })
+        })
+        .collect()
+}
+
+#[test]
+fn library_code_does_not_print() {
+    let mut sources = Vec::new();
+    library_sources(
+        &Path::new(env!("CARGO_MANIFEST_DIR")).join("src"),
+        &mut sources,
+    );
+    assert!(!sources.is_empty());
+
+    let mut found = Vec::new();
+    for path in sources {
+        let text = std::fs::read_to_string(&path).unwrap();
+        for (number, line) in text.lines().enumerate() {
+            // Test modules go at the end of a file, and may print
+            if line.trim() == "#[cfg(test)]" {
+                break;
+            }
+            for name in printing_macros(line) {
+                found.push(format!("{}:{}: {}", path.display(), number + 1, name));
+            }
+        }
+    }
+    assert!(
+        found.is_empty(),
+        "library code prints, rather than giving callers an error or event:\n{}",
+        found.join("\n")
+    );
+}
+
+#[test]
+fn printing_is_found() {
+    assert_eq!(printing_macros("    eprintln!(\"{}\", e);"), ["eprintln!"]);
+    assert_eq!(
+        printing_macros("print!(\"x\"); dbg!(x)"),
+        ["print!", "dbg!"]
+    );
+    assert!(printing_macros("/// println!(\"Version: {0}\", version);").is_empty());
+    assert!(printing_macros("writeln!(out, \"x\")?; // not println!").is_empty());
+}
+
+#[cfg(feature = "image-interop")]
+#[test]
+fn unsupported_image_formats_are_errors() {
+    use decklink::frame::DecklinkPixelFormat;
+    use decklink::image_interop::{conversion_path, ConversionPath, ImageConversionError};
+
+    assert_eq!(
+        conversion_path(DecklinkPixelFormat::Format8BitBGRA).unwrap(),
+        ConversionPath::Reordered
+    );
+    let yuv = conversion_path(DecklinkPixelFormat::Format8BitYUV).unwrap();
+    assert!(yuv.uses_colorimetry() && !yuv.is_dithered());
+    let v210 = conversion_path(DecklinkPixelFormat::Format10BitYUV).unwrap();
+    assert!(v210.uses_colorimetry() && v210.is_dithered());
+    let r210 = conversion_path(DecklinkPixelFormat::Format10BitRGB).unwrap();
+    assert!(!r210.uses_colorimetry() && r210.is_dithered());
+    assert!(matches!(
+        conversion_path(DecklinkPixelFormat::Format12BitRGB),
+        Err(ImageConversionError::UnsupportedPixelFormat(
+            DecklinkPixelFormat::Format12BitRGB
+        ))
+    ));
+}
+
+#[test]
+fn allocator_failures_are_counted() {
+    use decklink::allocator::{
+        BufferSpec, MeteredProvider, VideoBuffer, VideoBufferAllocator,
+        VideoBufferAllocatorProvider,
+    };
+    use decklink::frame::DecklinkPixelFormat;
+    use decklink::SdkError;
+    use std::sync::Arc;
+
+    struct Failing;
+
+    impl VideoBufferAllocator for Failing {
+        fn allocate(&self) -> Result<Box<dyn VideoBuffer>, SdkError> {
+            Err(SdkError::OUTOFMEMORY)
+        }
+    }
+
+    impl VideoBufferAllocatorProvider for Failing {
+        fn get_allocator(
+            &self,
+            spec: BufferSpec,
+        ) -> Result<Arc<dyn VideoBufferAllocator>, SdkError> {
+            if spec.width == 0 {
+                Err(SdkError::INVALIDARG)
+            } else {
+                Ok(Arc::new(Failing))
+            }
+        }
+    }
+
+    let spec = BufferSpec {
+        buffer_size: 1920 * 1080 * 2,
+        width: 1920,
+        height: 1080,
+        row_bytes: 1920 * 2,
+        pixel_format: DecklinkPixelFormat::Format8BitYUV as u32,
+    };
+    let provider = MeteredProvider::new(Arc::new(Failing));
+    let allocator = provider.get_allocator(spec).unwrap();
+    assert!(allocator.allocate().is_err());
+    assert!(allocator.allocate().is_err());
+    assert!(provider
+        .get_allocator(BufferSpec { width: 0, ..spec })
+        .is_err());
+
+    let mut timings = provider.timings();
+    timings.sort_by_key(|t| t.spec.width);
+    assert_eq!(
+        (
+            timings[0].get_allocator_calls,
+            timings[0].failed_get_allocator_calls
+        ),
+        (1, 1)
+    );
+    assert_eq!(
+        (
+            timings[1].get_allocator_calls,
+            timings[1].failed_get_allocator_calls,
+            timings[1].allocations,
+            timings[1].failed_allocations
+        ),
+        (1, 0, 2, 2)
+    );
+}
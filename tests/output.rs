@@ -0,0 +1,127 @@
+//! Showing frames the driver created, one at a time, on the output of a mock device.
+#![cfg(feature = "mock-backend")]
+
+use decklink::device::get_devices;
+use decklink::device::output::DecklinkVideoOutputFlags;
+use decklink::display_mode::DecklinkDisplayModeId;
+use decklink::frame::{DecklinkFrameBase, DecklinkFrameFlags, DecklinkPixelFormat};
+use decklink::mock::{MockBackend, MockDevice, MockFrame};
+use decklink::SdkError;
+
+const MODE: DecklinkDisplayModeId = DecklinkDisplayModeId::HD1080p25;
+const FORMAT: DecklinkPixelFormat = DecklinkPixelFormat::Format8BitBGRA;
+
+fn backend() -> MockBackend {
+    MockBackend::install(vec![MockDevice::new("DeckLink Duo").with_output()])
+}
+
+#[test]
+fn created_frames_are_filled_in_place_and_displayed() {
+    let backend = backend();
+    let devices = get_devices().unwrap();
+    let output = devices[0].output().unwrap();
+    output
+        .enable_video_output(MODE, DecklinkVideoOutputFlags::empty())
+        .unwrap();
+    assert_eq!(
+        backend.output(0).video(),
+        Some((MODE, DecklinkVideoOutputFlags::empty()))
+    );
+
+    let mut frame = output
+        .create_video_frame(1920, 1080, 1920 * 4, FORMAT, DecklinkFrameFlags::empty())
+        .unwrap();
+    assert_eq!((frame.width(), frame.height()), (1920, 1080));
+    assert_eq!(
+        (frame.row_bytes(), frame.pixel_format()),
+        (1920 * 4, FORMAT)
+    );
+    // A white bar on the left half
+    let bytes = frame.bytes_mut().unwrap();
+    assert_eq!(bytes.len(), 1920 * 4 * 1080);
+    for row in bytes.chunks_mut(1920 * 4) {
+        row[..1920 * 2].fill(0xFF);
+    }
+    let expected = frame.bytes().unwrap().0.to_vec();
+
+    output.display_video_frame(&frame).unwrap();
+    assert_eq!(
+        backend.output(0).displayed(),
+        [MockFrame::for_mode(MODE, FORMAT).bytes(&expected)]
+    );
+
+    output.disable_video_output().unwrap();
+    assert_eq!(backend.output(0).video(), None);
+    drop(frame);
+    drop(output);
+    drop(devices);
+    assert_eq!(MockBackend::live_objects(), 0);
+}
+
+#[test]
+fn frames_are_only_displayed_while_video_output_is_enabled() {
+    let backend = backend();
+    let devices = get_devices().unwrap();
+    let output = devices[0].output().unwrap();
+    let frame = output
+        .create_video_frame(1920, 1080, 1920 * 4, FORMAT, DecklinkFrameFlags::empty())
+        .unwrap();
+
+    assert_eq!(
+        output.display_video_frame(&frame),
+        Err(SdkError::UNEXPECTED)
+    );
+    output
+        .enable_video_output(MODE, DecklinkVideoOutputFlags::empty())
+        .unwrap();
+    output.disable_video_output().unwrap();
+    assert_eq!(
+        output.display_video_frame(&frame),
+        Err(SdkError::UNEXPECTED)
+    );
+    // Disabling again does nothing
+    assert_eq!(output.disable_video_output(), Ok(()));
+    assert!(backend.output(0).displayed().is_empty());
+}
+
+#[test]
+fn video_output_is_enabled_once() {
+    let backend = backend();
+    let devices = get_devices().unwrap();
+    let output = devices[0].output().unwrap();
+    output
+        .enable_video_output(MODE, DecklinkVideoOutputFlags::empty())
+        .unwrap();
+    assert_eq!(
+        output.enable_video_output(MODE, DecklinkVideoOutputFlags::empty()),
+        Err(SdkError::ACCESSDENIED)
+    );
+    assert!(matches!(
+        output.enable_video_output_sync(MODE, DecklinkVideoOutputFlags::empty()),
+        Err(SdkError::ACCESSDENIED)
+    ));
+    output.disable_video_output().unwrap();
+
+    // Through a handle, video output is disabled by dropping the handle
+    let handle = output
+        .enable_video_output_sync(MODE, DecklinkVideoOutputFlags::empty())
+        .unwrap();
+    assert_eq!(output.disable_video_output(), Err(SdkError::ACCESSDENIED));
+    drop(handle);
+    assert_eq!(backend.output(0).video(), None);
+}
+
+#[test]
+fn dropping_the_output_disables_video_output() {
+    let backend = backend();
+    let devices = get_devices().unwrap();
+    let output = devices[0].output().unwrap();
+    output
+        .enable_video_output(MODE, DecklinkVideoOutputFlags::empty())
+        .unwrap();
+
+    drop(output);
+    assert_eq!(backend.output(0).video(), None);
+    drop(devices);
+    assert_eq!(MockBackend::live_objects(), 0);
+}
@@ -0,0 +1,123 @@
+//! Enabling video input under each `PixelFormatPreference`, against mock devices that support
+//! only some formats.
+#![cfg(feature = "mock-backend")]
+
+use decklink::device::get_devices;
+use decklink::device::input::{ChosenFormat, DecklinkVideoInputFlags, PixelFormatPreference};
+use decklink::display_mode::DecklinkDisplayModeId;
+use decklink::frame::DecklinkPixelFormat;
+use decklink::mock::{MockBackend, MockDevice};
+use decklink::SdkError;
+
+const MODE: DecklinkDisplayModeId = DecklinkDisplayModeId::HD1080p25;
+
+const YUV_ONLY: [DecklinkPixelFormat; 2] = [
+    DecklinkPixelFormat::Format8BitYUV,
+    DecklinkPixelFormat::Format10BitYUV,
+];
+
+const WITH_RGB: [DecklinkPixelFormat; 4] = [
+    DecklinkPixelFormat::Format8BitYUV,
+    DecklinkPixelFormat::Format10BitYUV,
+    DecklinkPixelFormat::Format8BitBGRA,
+    DecklinkPixelFormat::Format10BitRGB,
+];
+
+/// Enable the input of a device supporting `formats` with `preference`, returning what was
+/// chosen and the format the driver was enabled with.
+fn enable(
+    formats: &[DecklinkPixelFormat],
+    preference: &PixelFormatPreference,
+) -> (Result<ChosenFormat, SdkError>, Option<DecklinkPixelFormat>) {
+    let backend = MockBackend::install(vec![MockDevice::new("DeckLink").pixel_formats(formats)]);
+    let devices = get_devices().unwrap();
+    let mut input = devices[0].input().unwrap();
+
+    let chosen = input.enable_video_input_with_preference(
+        MODE,
+        preference,
+        DecklinkVideoInputFlags::empty(),
+    );
+    let enabled = backend.input(0).video().map(|(mode, format, _)| {
+        assert_eq!(mode, MODE);
+        format
+    });
+    (chosen, enabled)
+}
+
+#[test]
+fn exact_uses_only_the_given_format() {
+    let preference = PixelFormatPreference::Exact(DecklinkPixelFormat::Format8BitBGRA);
+
+    let (chosen, enabled) = enable(&WITH_RGB, &preference);
+    let chosen = chosen.unwrap();
+    assert_eq!(chosen.pixel_format, DecklinkPixelFormat::Format8BitBGRA);
+    assert!(!chosen.is_fallback());
+    assert_eq!(enabled, Some(DecklinkPixelFormat::Format8BitBGRA));
+
+    let (chosen, enabled) = enable(&YUV_ONLY, &preference);
+    assert!(matches!(chosen, Err(SdkError::NOTIMPL)));
+    assert_eq!(enabled, None);
+}
+
+#[test]
+fn fallback_uses_the_first_supported_candidate() {
+    let preference = PixelFormatPreference::PreferredWithFallback(vec![
+        DecklinkPixelFormat::Format8BitBGRA,
+        DecklinkPixelFormat::Format10BitYUV,
+        DecklinkPixelFormat::Format8BitYUV,
+    ]);
+
+    let (chosen, enabled) = enable(&WITH_RGB, &preference);
+    assert_eq!(
+        chosen.unwrap(),
+        ChosenFormat {
+            pixel_format: DecklinkPixelFormat::Format8BitBGRA,
+            requested: Some(DecklinkPixelFormat::Format8BitBGRA),
+        }
+    );
+    assert_eq!(enabled, Some(DecklinkPixelFormat::Format8BitBGRA));
+
+    // A recorder that only captures YUV falls back to the first YUV candidate
+    let (chosen, enabled) = enable(&YUV_ONLY, &preference);
+    let chosen = chosen.unwrap();
+    assert_eq!(chosen.pixel_format, DecklinkPixelFormat::Format10BitYUV);
+    assert_eq!(chosen.requested, Some(DecklinkPixelFormat::Format8BitBGRA));
+    assert!(chosen.is_fallback());
+    assert_eq!(enabled, Some(DecklinkPixelFormat::Format10BitYUV));
+}
+
+#[test]
+fn best_quality_picks_the_highest_supported_format() {
+    let (chosen, enabled) = enable(&WITH_RGB, &PixelFormatPreference::BestQuality);
+    let chosen = chosen.unwrap();
+    assert_eq!(chosen.pixel_format, DecklinkPixelFormat::Format10BitRGB);
+    // No format in particular was asked for, so the highest supported is not a fallback
+    assert_eq!(chosen.requested, None);
+    assert!(!chosen.is_fallback());
+    assert_eq!(enabled, Some(DecklinkPixelFormat::Format10BitRGB));
+
+    let (chosen, enabled) = enable(&YUV_ONLY, &PixelFormatPreference::BestQuality);
+    let chosen = chosen.unwrap();
+    assert_eq!(chosen.pixel_format, DecklinkPixelFormat::Format10BitYUV);
+    assert!(!chosen.is_fallback());
+    assert_eq!(enabled, Some(DecklinkPixelFormat::Format10BitYUV));
+}
+
+#[test]
+fn no_supported_candidate_is_notimpl() {
+    let rgb_only = PixelFormatPreference::PreferredWithFallback(vec![
+        DecklinkPixelFormat::Format8BitBGRA,
+        DecklinkPixelFormat::Format8BitARGB,
+    ]);
+    let nothing = PixelFormatPreference::PreferredWithFallback(Vec::new());
+    for (formats, preference) in [
+        (&YUV_ONLY[..], &rgb_only),
+        (&YUV_ONLY[..], &nothing),
+        (&[][..], &PixelFormatPreference::BestQuality),
+    ] {
+        let (chosen, enabled) = enable(formats, preference);
+        assert!(matches!(chosen, Err(SdkError::NOTIMPL)), "{:?}", preference);
+        assert_eq!(enabled, None);
+    }
+}
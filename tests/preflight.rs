@@ -0,0 +1,187 @@
+//! Preflighting inputs, and capturing on them once the mock driver refuses new resources.
+#![cfg(feature = "mock-backend")]
+
+use decklink::allocator::{
+    BufferSpec, VideoBuffer, VideoBufferAllocator, VideoBufferAllocatorProvider,
+};
+use decklink::device::get_devices;
+use decklink::device::input::{
+    CallbackResult, DecklinkAudioSampleRate, DecklinkAudioSampleType, FrameArrival, InputHandler,
+};
+use decklink::device::selector::DeviceSelector;
+use decklink::display_mode::DecklinkDisplayModeId;
+use decklink::frame::DecklinkPixelFormat;
+use decklink::mock::{MockBackend, MockDevice, MockFrame};
+use decklink::preflight::{PreflightError, PreflightSpec, PreflightStep};
+use decklink::tap::{DeckLinkTapCallback, TapSpec, TapSplitter, TappedFrame};
+use decklink::{preflight, ApiVersion, SdkError};
+use std::ffi::c_void;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const MODE: DecklinkDisplayModeId = DecklinkDisplayModeId::HD1080p25;
+const FORMAT: DecklinkPixelFormat = DecklinkPixelFormat::Format8BitYUV;
+
+#[derive(Default)]
+struct Counter(AtomicUsize);
+
+impl InputHandler for Counter {
+    fn frame_arrived(&self, arrival: &FrameArrival<'_>) -> CallbackResult {
+        if arrival.video_frame.is_some() {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+        CallbackResult::Ok
+    }
+}
+
+struct HeapBuffer(Mutex<Vec<u8>>);
+
+impl VideoBuffer for HeapBuffer {
+    fn get_bytes(&self) -> Result<*mut c_void, SdkError> {
+        Ok(self.0.lock().unwrap().as_mut_ptr() as *mut c_void)
+    }
+}
+
+struct HeapAllocator(usize);
+
+impl VideoBufferAllocator for HeapAllocator {
+    fn allocate(&self) -> Result<Box<dyn VideoBuffer>, SdkError> {
+        Ok(Box::new(HeapBuffer(Mutex::new(vec![0; self.0]))))
+    }
+}
+
+struct HeapProvider;
+
+impl VideoBufferAllocatorProvider for HeapProvider {
+    fn get_allocator(&self, spec: BufferSpec) -> Result<Arc<dyn VideoBufferAllocator>, SdkError> {
+        Ok(Arc::new(HeapAllocator(spec.buffer_size as usize)))
+    }
+}
+
+struct Discard;
+
+impl DeckLinkTapCallback for Discard {
+    fn frame_tapped(&mut self, _frame: TappedFrame) {}
+}
+
+fn install() -> MockBackend {
+    let backend = MockBackend::install(vec![
+        MockDevice::new("Capture A").pixel_formats(&[FORMAT]),
+        MockDevice::new("Capture B").pixel_formats(&[FORMAT]),
+    ]);
+    // The allocator provider of input B needs the 14.3 API
+    backend.set_api_version(Some(ApiVersion::new(14, 3, 0)));
+    backend
+}
+
+fn specs(a: Arc<Counter>, b: Arc<Counter>) -> Vec<PreflightSpec> {
+    vec![
+        PreflightSpec::new(
+            DeviceSelector::NameExact("Capture A".to_string()),
+            MODE,
+            FORMAT,
+            a,
+        )
+        .audio(
+            DecklinkAudioSampleRate::Rate48kHz,
+            DecklinkAudioSampleType::Int16,
+            2,
+        ),
+        PreflightSpec::new(DeviceSelector::Index(1), MODE, FORMAT, b)
+            .allocator(Arc::new(HeapProvider)),
+    ]
+}
+
+#[test]
+fn report_lists_what_each_input_opened() {
+    let backend = install();
+    let preflight = preflight(specs(Arc::default(), Arc::default())).unwrap();
+    let report = preflight.report();
+
+    assert_eq!(report.inputs.len(), 2);
+    assert_eq!(report.inputs[0].device, "Capture A");
+    assert_eq!(
+        report.inputs[0].steps,
+        vec![
+            PreflightStep::Device,
+            PreflightStep::Input,
+            PreflightStep::DisplayModes,
+            PreflightStep::VideoInput,
+            PreflightStep::AudioInput,
+            PreflightStep::Callback,
+            PreflightStep::Status,
+        ]
+    );
+    assert_eq!(report.inputs[1].device, "Capture B");
+    assert_eq!(
+        report.inputs[1].steps,
+        vec![
+            PreflightStep::Device,
+            PreflightStep::Input,
+            PreflightStep::DisplayModes,
+            PreflightStep::VideoInput,
+            PreflightStep::AllocatorProvider,
+            PreflightStep::Callback,
+            PreflightStep::Status,
+        ]
+    );
+    assert!(backend.input(1).has_allocator_provider());
+    // Inputs are run by the driver's threads, not the crate's
+    assert!(report.threads_started.is_empty());
+    assert_eq!(report.fds_opened.is_some(), cfg!(target_os = "linux"));
+    assert!(report.to_string().contains("Capture A: device, input"));
+}
+
+#[test]
+fn capture_runs_on_preflighted_resources_alone() {
+    let backend = install();
+    let a = Arc::new(Counter::default());
+    let b = Arc::new(Counter::default());
+    let preflight = preflight(specs(a.clone(), b.clone())).unwrap();
+
+    backend.deny_new_resources();
+    preflight.start().unwrap();
+    for n in 0..3 {
+        let frame = MockFrame::for_mode(MODE, FORMAT).fill(n);
+        assert!(backend.input(0).deliver_frame(frame.clone()).is_ok());
+        assert!(backend.input(1).deliver_frame(frame).is_ok());
+    }
+    preflight.stop().unwrap();
+
+    assert_eq!(a.0.load(Ordering::SeqCst), 3);
+    assert_eq!(b.0.load(Ordering::SeqCst), 3);
+    assert_eq!(backend.denied_resources(), Vec::<String>::new());
+}
+
+#[test]
+fn resources_asked_for_after_lockdown_are_denied() {
+    let backend = install();
+    let primary = Arc::new(Counter::default());
+    let preflight = preflight(specs(primary.clone(), Arc::default())).unwrap();
+
+    backend.deny_new_resources();
+    assert_eq!(get_devices().err(), Some(SdkError::FAIL));
+    let splitter = TapSplitter::new(primary, None);
+    let tap = splitter.tap(Discard, TapSpec::default());
+
+    let denied = backend.denied_resources();
+    assert_eq!(denied[0], "device list");
+    assert!(denied[1].starts_with("thread decklink-tap-"));
+    splitter.finish(Duration::from_secs(1));
+    tap.join();
+    drop(preflight);
+}
+
+#[test]
+fn a_selector_matching_nothing_fails_its_spec() {
+    let _backend = install();
+    let mut specs = specs(Arc::default(), Arc::default());
+    specs[1].device = DeviceSelector::NameExact("Capture C".to_string());
+
+    match preflight(specs) {
+        Err(PreflightError::Select { spec: 1, .. }) => {}
+        Err(e) => panic!("unexpected error: {}", e),
+        Ok(_) => panic!("preflight found a device that is not there"),
+    }
+}
@@ -0,0 +1,403 @@
+//! Probe reports, and probing mock devices with and without a signal.
+
+use decklink::display_mode::DecklinkDisplayModeId;
+use decklink::frame::DecklinkPixelFormat;
+use decklink::probe::{
+    ModeSupport, ProbeOutcome, ProbeReport, ProbeResult, SkipReason, PROBE_REPORT_VERSION,
+};
+use decklink::vpid::Vpid;
+use std::time::Duration;
+
+fn report() -> ProbeReport {
+    let result = |name, outcome, detail: &str| ProbeResult {
+        name,
+        outcome,
+        detail: detail.to_string(),
+        duration: Duration::from_millis(12),
+    };
+    ProbeReport {
+        device_name: Some("DeckLink \"Mini\" Recorder".to_string()),
+        model_name: None,
+        driver_version: Some("14.2.1".to_string()),
+        vpid: Some(Vpid::decode([0x8A, 0xC9, 0x00, 0x01])),
+        effective_config: None,
+        results: vec![
+            result("attributes", ProbeOutcome::Pass, "12 of 12"),
+            result(
+                "status",
+                ProbeOutcome::Fail {
+                    hresult: Some(0x80004005u32 as i32),
+                },
+                "FAIL",
+            ),
+            result(
+                "live_signal",
+                ProbeOutcome::Skipped(SkipReason::NoSignal),
+                "no input signal is locked",
+            ),
+        ],
+        support_matrix: vec![ModeSupport {
+            mode: DecklinkDisplayModeId::HD1080p25,
+            name: Some("1080p25".to_string()),
+            pixel_formats: vec![
+                DecklinkPixelFormat::Format8BitYUV,
+                DecklinkPixelFormat::Format10BitYUV,
+            ],
+        }],
+    }
+}
+
+#[test]
+fn report_serializes_to_stable_json() {
+    assert_eq!(PROBE_REPORT_VERSION, 5);
+    assert_eq!(
+        report().to_json(),
+        r#"{
+  "version": 5,
+  "device_name": "DeckLink \"Mini\" Recorder",
+  "model_name": null,
+  "driver_version": "14.2.1",
+  "vpid": { "bytes": "8A C9 00 01", "description": "3G-B 1080p50 YCbCr 4:2:2 10-bit" },
+  "effective_config": null,
+  "probes": [
+    { "name": "attributes", "outcome": "pass", "hresult": null, "skip_reason": null, "duration_ms": 12, "detail": "12 of 12" },
+    { "name": "status", "outcome": "fail", "hresult": "0x80004005", "skip_reason": null, "duration_ms": 12, "detail": "FAIL" },
+    { "name": "live_signal", "outcome": "skipped", "hresult": null, "skip_reason": "no_signal", "duration_ms": 12, "detail": "no input signal is locked" }
+  ],
+  "support_matrix": [
+    { "mode": "HD1080p25", "name": "1080p25", "pixel_formats": ["Format8BitYUV", "Format10BitYUV"] }
+  ]
+}
+"#
+    );
+}
+
+#[test]
+fn report_is_printed_as_a_table() {
+    let text = report().to_string();
+    assert!(text.starts_with("Decklink probe report (version 5)\n"));
+    assert!(text.contains("Model            Unknown\n"));
+    assert!(text.contains("VPID             3G-B 1080p50 YCbCr 4:2:2 10-bit (8A C9 00 01)\n"));
+    assert!(text.contains("status                 fail 0x80004005"));
+    assert!(text.contains("live_signal            skipped (no signal)"));
+    assert!(text.contains("  1080p25                  Format8BitYUV, Format10BitYUV\n"));
+}
+
+#[test]
+fn failures_and_results_are_found_by_name() {
+    let report = report();
+    let failures: Vec<_> = report.failures().map(|r| r.name).collect();
+    assert_eq!(failures, ["status"]);
+    assert_eq!(
+        report.result("live_signal").unwrap().outcome,
+        ProbeOutcome::Skipped(SkipReason::NoSignal)
+    );
+    assert!(report.result("vanc").is_none());
+}
+
+#[cfg(feature = "mock-backend")]
+mod mock {
+    use decklink::device::get_devices;
+    use decklink::device::status::DecklinkStatusId;
+    use decklink::display_mode::DecklinkDisplayModeId;
+    use decklink::frame::DecklinkPixelFormat;
+    use decklink::mock::{MockBackend, MockDevice, MockFrame, MockInput};
+    use decklink::probe::{
+        run_all_with, ProbeOptions, ProbeOutcome, ProbeReport, SkipReason, PROBE_NAMES,
+    };
+    use decklink::timecode::{DecklinkTimecode, DecklinkTimecodeFormat};
+    use decklink::{ApiVersion, SdkError};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    const MODES: [DecklinkDisplayModeId; 2] = [
+        DecklinkDisplayModeId::HD1080p25,
+        DecklinkDisplayModeId::HD1080i50,
+    ];
+    const FORMATS: [DecklinkPixelFormat; 2] = [
+        DecklinkPixelFormat::Format8BitYUV,
+        DecklinkPixelFormat::Format10BitYUV,
+    ];
+
+    fn device() -> MockDevice {
+        MockDevice::new("DeckLink Mini Recorder")
+            .modes(&MODES)
+            .pixel_formats(&FORMATS)
+            .format_detection(true)
+    }
+
+    fn options() -> ProbeOptions {
+        ProbeOptions {
+            capture_duration: Duration::from_millis(200),
+            ..Default::default()
+        }
+    }
+
+    fn outcome(report: &ProbeReport, name: &str) -> ProbeOutcome {
+        report.result(name).unwrap().outcome
+    }
+
+    /// The input is left disabled, with no callback.
+    fn assert_left_as_found(mock: &MockInput) {
+        assert_eq!(mock.video(), None);
+        assert!(!mock.is_audio_enabled());
+        assert!(!mock.has_callback());
+        assert!(!mock.is_streaming());
+    }
+
+    #[test]
+    fn device_without_a_signal() {
+        let backend = MockBackend::install(vec![device()]);
+        backend.set_api_version(Some(ApiVersion::new(14, 3, 0)));
+        let devices = get_devices().unwrap();
+        let report = run_all_with(&devices[0], options());
+
+        let names: Vec<_> = report.results.iter().map(|r| r.name).collect();
+        assert_eq!(names, PROBE_NAMES);
+        assert_eq!(
+            report.device_name.as_deref(),
+            Some("DeckLink Mini Recorder")
+        );
+        assert_eq!(report.driver_version.as_deref(), Some("14.3.0"));
+        for name in [
+            "attributes",
+            "status",
+            "display_modes",
+            "support_matrix",
+            "format_detection",
+            "allocator_round_trip",
+        ] {
+            assert_eq!(outcome(&report, name), ProbeOutcome::Pass, "{}", name);
+        }
+        for name in ["health", "configuration", "row_bytes", "vanc"] {
+            assert_eq!(
+                outcome(&report, name),
+                ProbeOutcome::Skipped(SkipReason::Unsupported)
+            );
+        }
+        for name in ["live_signal", "audio_channels", "timecode"] {
+            assert_eq!(
+                outcome(&report, name),
+                ProbeOutcome::Skipped(SkipReason::NoSignal)
+            );
+        }
+        assert_eq!(report.failures().count(), 0);
+        assert_eq!(report.vpid, None);
+        assert_eq!(report.effective_config, None);
+
+        let matrix: Vec<_> = report
+            .support_matrix
+            .iter()
+            .map(|s| (s.mode, s.pixel_formats.clone()))
+            .collect();
+        assert_eq!(
+            matrix,
+            [(MODES[0], FORMATS.to_vec()), (MODES[1], FORMATS.to_vec())]
+        );
+        assert_eq!(report.support_matrix[1].name.as_deref(), Some("1080i50"));
+        assert_left_as_found(&backend.input(0));
+    }
+
+    #[test]
+    fn live_signal_is_captured_with_its_audio() {
+        let backend = MockBackend::install(vec![
+            device().status_flag(DecklinkStatusId::VideoInputSignalLocked, true)
+        ]);
+        let devices = get_devices().unwrap();
+
+        // Frames with audio on the second of two 32-bit channels, while the probe captures
+        let mock = backend.input(0);
+        let done = Arc::new(AtomicBool::new(false));
+        let source = {
+            let done = done.clone();
+            thread::spawn(move || {
+                let audio: Vec<u8> = (0..1920).flat_map(|_| [0, 0, 0, 0, 0, 0, 1, 0]).collect();
+                let mut n = 0;
+                while !done.load(Ordering::SeqCst) {
+                    let timecode = DecklinkTimecode::from_frame_number(n, 25, false);
+                    let frame = MockFrame::new(48, 2, DecklinkPixelFormat::Format8BitYUV)
+                        .timecode(DecklinkTimecodeFormat::RP188LTC, timecode)
+                        .vpid([0x85, 0xC5, 0x00, 0x01]);
+                    mock.deliver_frame_with_audio(frame, &audio);
+                    n += 1;
+                    thread::sleep(Duration::from_millis(5));
+                }
+            })
+        };
+        let report = run_all_with(&devices[0], options());
+        done.store(true, Ordering::SeqCst);
+        source.join().unwrap();
+
+        let live = report.result("live_signal").unwrap();
+        assert_eq!(live.outcome, ProbeOutcome::Pass, "{}", live.detail);
+        assert!(live.detail.contains("at HD1080p25"), "{}", live.detail);
+        assert_eq!(
+            report.vpid.map(|v| v.describe()).as_deref(),
+            Some("HD 1080p25 YCbCr 4:2:2 10-bit")
+        );
+        let config = report.effective_config.as_ref().unwrap();
+        assert_eq!(
+            config.display_mode.actual,
+            Some(DecklinkDisplayModeId::HD1080p25)
+        );
+        assert_eq!(config.audio.actual.map(|a| a.channel_count), Some(2));
+        assert_eq!(config.driver_version, report.driver_version);
+        assert_eq!(
+            config.device.as_ref().unwrap().display_name,
+            report.device_name
+        );
+        assert!(report
+            .to_json()
+            .contains("\"effective_config\": {\"schema_version\":1,"));
+        let audio = report.result("audio_channels").unwrap();
+        assert_eq!(audio.outcome, ProbeOutcome::Pass, "{}", audio.detail);
+        assert!(
+            audio
+                .detail
+                .ends_with("with 2 channels enabled, audio on channels [2]"),
+            "{}",
+            audio.detail
+        );
+        let timecode = report.result("timecode").unwrap();
+        assert_eq!(timecode.outcome, ProbeOutcome::Pass, "{}", timecode.detail);
+        assert!(
+            timecode.detail.starts_with("RP188LTC on "),
+            "{}",
+            timecode.detail
+        );
+        assert_left_as_found(&backend.input(0));
+    }
+
+    #[test]
+    fn locked_signal_without_frames_fails() {
+        let backend = MockBackend::install(vec![
+            device().status_flag(DecklinkStatusId::VideoInputSignalLocked, true)
+        ]);
+        let devices = get_devices().unwrap();
+        let report = run_all_with(&devices[0], options());
+
+        assert_eq!(
+            outcome(&report, "live_signal"),
+            ProbeOutcome::Fail { hresult: None }
+        );
+        assert!(report
+            .result("live_signal")
+            .unwrap()
+            .detail
+            .starts_with("0 frames"));
+        assert_eq!(
+            outcome(&report, "audio_channels"),
+            ProbeOutcome::Skipped(SkipReason::NoSignal)
+        );
+        assert_left_as_found(&backend.input(0));
+    }
+
+    #[test]
+    fn reported_temperature_passes_the_health_probe() {
+        let _backend = MockBackend::install(vec![
+            device().status_int(DecklinkStatusId::DeviceTemperature, 47)
+        ]);
+        let devices = get_devices().unwrap();
+        let report = run_all_with(&devices[0], options());
+
+        let health = report.result("health").unwrap();
+        assert_eq!(health.outcome, ProbeOutcome::Pass);
+        assert_eq!(health.detail, "temperature 47 C");
+    }
+
+    #[test]
+    fn row_sizes_are_audited_through_the_output() {
+        let _backend = MockBackend::install(vec![device().with_output()]);
+        let devices = get_devices().unwrap();
+        let report = run_all_with(&devices[0], options());
+
+        let row_bytes = report.result("row_bytes").unwrap();
+        assert_eq!(row_bytes.outcome, ProbeOutcome::Pass);
+        // Each of the two modes in each of the two pixel formats
+        assert_eq!(row_bytes.detail, "4 row sizes match the driver");
+    }
+
+    #[test]
+    fn drivers_without_allocators_skip_the_allocator_probe() {
+        let backend = MockBackend::install(vec![device()]);
+        backend.set_api_version(Some(ApiVersion::new(14, 2, 1)));
+        let devices = get_devices().unwrap();
+        let report = run_all_with(&devices[0], options());
+
+        let allocator = report.result("allocator_round_trip").unwrap();
+        assert_eq!(
+            allocator.outcome,
+            ProbeOutcome::Skipped(SkipReason::Unsupported)
+        );
+        assert_eq!(
+            allocator.detail,
+            "the drivers do not support custom allocators"
+        );
+        assert!(!backend.input(0).has_allocator_provider());
+    }
+
+    #[test]
+    fn device_without_an_input_skips_input_probes() {
+        let _backend = MockBackend::install(vec![device().without_input().with_output()]);
+        let devices = get_devices().unwrap();
+        let report = run_all_with(&devices[0], options());
+
+        for name in ["display_modes", "support_matrix", "allocator_round_trip"] {
+            assert_eq!(
+                outcome(&report, name),
+                ProbeOutcome::Skipped(SkipReason::Unsupported),
+                "{}",
+                name
+            );
+        }
+        assert!(report.support_matrix.is_empty());
+        assert_eq!(report.failures().count(), 0);
+    }
+
+    #[test]
+    fn failing_probes_record_their_hresult_and_the_rest_run() {
+        let backend = MockBackend::install(vec![device()]);
+        let devices = get_devices().unwrap();
+        // Attribute and status queries fail once the device is unplugged
+        backend.detach(0);
+        let report = run_all_with(&devices[0], options());
+
+        assert_eq!(
+            outcome(&report, "status"),
+            ProbeOutcome::Fail {
+                hresult: Some(SdkError::FAIL as i32)
+            }
+        );
+        let failures: Vec<_> = report.failures().map(|r| r.name).collect();
+        assert_eq!(
+            failures,
+            ["attributes", "status", "health", "format_detection"]
+        );
+        assert_eq!(report.results.len(), PROBE_NAMES.len());
+        assert_eq!(outcome(&report, "support_matrix"), ProbeOutcome::Pass);
+    }
+
+    #[test]
+    fn probes_overrunning_their_bound_fail() {
+        let _backend = MockBackend::install(vec![device()]);
+        let devices = get_devices().unwrap();
+        let report = run_all_with(
+            &devices[0],
+            ProbeOptions {
+                probe_timeout: Duration::ZERO,
+                ..options()
+            },
+        );
+
+        let attributes = report.result("attributes").unwrap();
+        assert_eq!(attributes.outcome, ProbeOutcome::Fail { hresult: None });
+        assert!(attributes.detail.starts_with("took longer than 0ns: "));
+        // Skipped probes are not failed
+        assert_eq!(
+            outcome(&report, "vanc"),
+            ProbeOutcome::Skipped(SkipReason::Unsupported)
+        );
+    }
+}
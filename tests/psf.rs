@@ -0,0 +1,176 @@
+//! Pairing interlaced and PsF modes, and telling PsF content from interlaced content.
+
+use decklink::device::status::DecklinkVideoStatusFlags;
+use decklink::display_mode::{DecklinkDisplayModeId, DecklinkFieldDominance};
+
+const PAIRS: [(DecklinkDisplayModeId, DecklinkDisplayModeId); 3] = [
+    (
+        DecklinkDisplayModeId::HD1080i50,
+        DecklinkDisplayModeId::HD1080p25,
+    ),
+    (
+        DecklinkDisplayModeId::HD1080i5994,
+        DecklinkDisplayModeId::HD1080p2997,
+    ),
+    (
+        DecklinkDisplayModeId::HD1080i6000,
+        DecklinkDisplayModeId::HD1080p30,
+    ),
+];
+
+#[test]
+fn paired_modes_map_both_ways() {
+    for (interlaced, progressive) in PAIRS {
+        assert_eq!(interlaced.progressive_equivalent(), Some(progressive));
+        assert_eq!(progressive.interlaced_equivalent(), Some(interlaced));
+        assert_eq!(interlaced.interlaced_equivalent(), None);
+        assert_eq!(progressive.progressive_equivalent(), None);
+    }
+}
+
+#[test]
+fn unpaired_modes_have_no_equivalent() {
+    for mode in [
+        DecklinkDisplayModeId::NTSC,
+        DecklinkDisplayModeId::PAL,
+        DecklinkDisplayModeId::HD1080p24,
+        DecklinkDisplayModeId::HD1080p50,
+        DecklinkDisplayModeId::HD720p50,
+        DecklinkDisplayModeId::UHD4K2160p25,
+        DecklinkDisplayModeId::Unknown,
+    ] {
+        assert_eq!(mode.progressive_equivalent(), None, "{:?}", mode);
+        assert_eq!(mode.interlaced_equivalent(), None, "{:?}", mode);
+    }
+}
+
+#[test]
+fn psf_detection_suggests_the_progressive_mode() {
+    let psf = DecklinkVideoStatusFlags::PSF;
+    let none = DecklinkVideoStatusFlags::empty();
+    for (interlaced, progressive) in PAIRS {
+        assert_eq!(interlaced.suggested_for(psf), progressive);
+        assert_eq!(interlaced.suggested_for(none), interlaced);
+        assert_eq!(progressive.suggested_for(psf), progressive);
+    }
+    // Without a progressive pair the detected mode stands
+    assert_eq!(
+        DecklinkDisplayModeId::PAL.suggested_for(psf),
+        DecklinkDisplayModeId::PAL
+    );
+}
+
+#[test]
+fn psf_is_progressive_content_in_a_segmented_transport() {
+    use DecklinkFieldDominance::*;
+    let cases = [
+        (ProgressiveFrame, Some(true), Some(false)),
+        (ProgressiveSegmentedFrame, Some(true), Some(true)),
+        (UpperFieldFirst, Some(false), Some(true)),
+        (LowerFieldFirst, Some(false), Some(true)),
+        (Unknown, None, None),
+    ];
+    for (dominance, progressive, segmented) in cases {
+        assert_eq!(
+            dominance.is_progressive_content(),
+            progressive,
+            "{:?}",
+            dominance
+        );
+        assert_eq!(
+            dominance.is_segmented_transport(),
+            segmented,
+            "{:?}",
+            dominance
+        );
+    }
+}
+
+#[cfg(feature = "mock-backend")]
+mod mock {
+    use decklink::device::get_devices;
+    use decklink::device::status::{DecklinkStatusId, DecklinkVideoStatusFlags};
+    use decklink::device::DecklinkDeviceDisplayModes;
+    use decklink::display_mode::{
+        filter_psf_modes, DecklinkDisplayModeId, DecklinkFieldDominance, PsfFilter,
+    };
+    use decklink::mock::{MockBackend, MockDevice};
+
+    const MODES: [DecklinkDisplayModeId; 4] = [
+        DecklinkDisplayModeId::HD1080i50,
+        DecklinkDisplayModeId::HD1080p25,
+        DecklinkDisplayModeId::HD1080p2398,
+        DecklinkDisplayModeId::HD1080p50,
+    ];
+
+    fn install() -> MockBackend {
+        MockBackend::install(vec![MockDevice::new("DeckLink SDI 4K")
+            .modes(&MODES)
+            .psf_modes(&[
+                DecklinkDisplayModeId::HD1080p25,
+                DecklinkDisplayModeId::HD1080p2398,
+            ])])
+    }
+
+    fn ids(modes: &[&decklink::display_mode::DecklinkDisplayMode]) -> Vec<DecklinkDisplayModeId> {
+        modes.iter().map(|m| m.mode()).collect()
+    }
+
+    #[test]
+    fn psf_modes_are_reported_and_filtered() {
+        let _backend = install();
+        let devices = get_devices().unwrap();
+        let modes = devices[0].input().unwrap().display_modes().unwrap();
+
+        let psf: Vec<bool> = modes.iter().map(|m| m.is_psf()).collect();
+        assert_eq!(psf, [false, true, true, false]);
+        assert_eq!(
+            modes[1].field_dominance(),
+            DecklinkFieldDominance::ProgressiveSegmentedFrame
+        );
+        assert_eq!(
+            modes[1].field_dominance().is_progressive_content(),
+            Some(true)
+        );
+        assert_eq!(
+            modes[0].field_dominance().is_progressive_content(),
+            Some(false)
+        );
+
+        let all: Vec<_> = modes.iter().collect();
+        assert_eq!(
+            ids(&filter_psf_modes(&modes, PsfFilter::Include)),
+            ids(&all)
+        );
+        assert_eq!(
+            ids(&filter_psf_modes(&modes, PsfFilter::Exclude)),
+            [MODES[0], MODES[3]]
+        );
+        assert_eq!(
+            ids(&filter_psf_modes(&modes, PsfFilter::Prefer)),
+            [MODES[1], MODES[2], MODES[0], MODES[3]]
+        );
+    }
+
+    #[test]
+    fn detected_psf_signal_suggests_the_progressive_mode() {
+        let _backend = MockBackend::install(vec![MockDevice::new("DeckLink SDI 4K")
+            .status_int(
+                DecklinkStatusId::DetectedVideoInputMode,
+                DecklinkDisplayModeId::HD1080i50 as i64,
+            )
+            .status_int(
+                DecklinkStatusId::DetectedVideoInputFlags,
+                DecklinkVideoStatusFlags::PSF.bits() as i64,
+            )]);
+        let devices = get_devices().unwrap();
+        let status = devices[0].get_status().unwrap();
+
+        let detected = status.detected_video_input_mode().unwrap();
+        assert_eq!(detected, DecklinkDisplayModeId::HD1080i50);
+        assert_eq!(
+            detected.suggested_for(status.detected_video_input_flags().unwrap()),
+            DecklinkDisplayModeId::HD1080p25
+        );
+    }
+}
@@ -0,0 +1,1070 @@
+//! The public api, checked against the snapshot in `tests/public_api.txt`.
+//!
+//! The api is read from the source, module by module from `src/lib.rs`, rather than from
+//! rustdoc's json output, which needs a nightly toolchain. Every public item is listed on a
+//! line of its own, with its tier: `stable` for the modules of `STABLE_MODULES` and the items
+//! of `STABLE_ROOT_ITEMS`, `experimental` for the rest, `raw` for what the `raw-api` feature
+//! adds, and `deprecated` for what is kept only so that existing code still compiles. Items behind a feature list
+//! their `cfg`. Items re-exported with `pub use` are listed at the path they are exported at.
+//!
+//! A change to the api fails the test until the snapshot is updated, with
+//! `UPDATE_PUBLIC_API=1 cargo test --test public_api`, so that it shows up in review.
+//! A stable signature naming an experimental type fails whatever the snapshot says, as it
+//! would tie a stable item to one that may change.
+
+#[cfg(feature = "mock-backend")]
+mod common;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const CRATE: &str = "decklink";
+
+/// The stable modules at the crate root: those released in 0.1.0, and the time and timecode
+/// types the frames and timings of those are given in. Other root modules are experimental.
+const STABLE_MODULES: &[&str] = &[
+    "allocator",
+    "connectors",
+    "cuda",
+    "device",
+    "display_mode",
+    "frame",
+    "time",
+    "timecode",
+];
+
+/// The stable items declared or re-exported at the crate root, all released in 0.1.0.
+const STABLE_ROOT_ITEMS: &[&str] = &["api_version", "SdkError"];
+
+/// Whether an item is of the stable tier, by the module or root item its path is under.
+fn is_stable(path: &str) -> bool {
+    match path.split("::").nth(1) {
+        None => true,
+        Some(name) => STABLE_MODULES.contains(&name) || STABLE_ROOT_ITEMS.contains(&name),
+    }
+}
+
+#[derive(Clone, Default)]
+struct Member {
+    kind: &'static str,
+    name: String,
+    signature: String,
+    cfg: Vec<String>,
+}
+
+#[derive(Clone, Default)]
+struct Item {
+    kind: &'static str,
+    name: String,
+    /// The path of the module the item is declared in.
+    module: String,
+    signature: String,
+    cfg: Vec<String>,
+    deprecated: bool,
+    members: Vec<Member>,
+}
+
+struct Use {
+    path: Vec<String>,
+    /// The name the item is exported as, or `*`.
+    alias: String,
+    cfg: Vec<String>,
+}
+
+struct Impl {
+    target: String,
+    /// The header of a trait impl, or `None` for an inherent impl.
+    header: Option<String>,
+    members: Vec<Member>,
+    cfg: Vec<String>,
+}
+
+#[derive(Default)]
+struct Module {
+    public: bool,
+    deprecated: bool,
+    cfg: Vec<String>,
+    items: Vec<Item>,
+    uses: Vec<Use>,
+    impls: Vec<Impl>,
+    macros: Vec<String>,
+}
+
+#[derive(Default)]
+struct Crate {
+    modules: BTreeMap<String, Module>,
+    /// The number of types, public or not, declared with each name.
+    types: BTreeMap<String, usize>,
+}
+
+/// Removes comments, and the contents of string and character literals, so that the brackets
+/// and separators left are all the source's own. Strings of plain words, such as feature
+/// names, are kept.
+fn strip(source: &str) -> String {
+    let chars: Vec<char> = source.chars().collect();
+    let ident = |i: usize| i > 0 && (chars[i - 1].is_alphanumeric() || chars[i - 1] == '_');
+    let mut out = String::with_capacity(source.len());
+    let mut i = 0;
+    while i < chars.len() {
+        let next = chars.get(i + 1).copied();
+        match chars[i] {
+            '/' if next == Some('/') => {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+            }
+            '/' if next == Some('*') => {
+                let mut depth = 0;
+                while i < chars.len() {
+                    if chars[i] == '/' && chars.get(i + 1) == Some(&'*') {
+                        depth += 1;
+                        i += 2;
+                    } else if chars[i] == '*' && chars.get(i + 1) == Some(&'/') {
+                        depth -= 1;
+                        i += 2;
+                        if depth == 0 {
+                            break;
+                        }
+                    } else {
+                        i += 1;
+                    }
+                }
+                out.push(' ');
+            }
+            'r' if matches!(next, Some('"' | '#'))
+                && (!ident(i) || (chars[i - 1] == 'b' && !ident(i - 1))) =>
+            {
+                let mut start = i + 1;
+                let mut hashes = 0;
+                while chars.get(start) == Some(&'#') {
+                    hashes += 1;
+                    start += 1;
+                }
+                if chars.get(start) != Some(&'"') {
+                    out.push('r');
+                    i += 1;
+                    continue;
+                }
+                let mut end = start + 1;
+                while !(chars[end] == '"' && (1..=hashes).all(|k| chars.get(end + k) == Some(&'#')))
+                {
+                    end += 1;
+                }
+                literal(&mut out, &chars[start + 1..end]);
+                i = end + 1 + hashes;
+            }
+            '"' => {
+                let mut end = i + 1;
+                while chars[end] != '"' {
+                    end += if chars[end] == '\\' { 2 } else { 1 };
+                }
+                literal(&mut out, &chars[i + 1..end]);
+                i = end + 1;
+            }
+            '\'' if next == Some('\\') => {
+                let mut end = i + 3;
+                while chars[end] != '\'' {
+                    end += 1;
+                }
+                out.push_str("'_'");
+                i = end + 1;
+            }
+            '\'' if chars.get(i + 2) == Some(&'\'') => {
+                out.push_str("'_'");
+                i += 3;
+            }
+            c => {
+                out.push(c);
+                i += 1;
+            }
+        }
+    }
+    out
+}
+
+fn literal(out: &mut String, content: &[char]) {
+    out.push('"');
+    if content
+        .iter()
+        .all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | '.' | ' '))
+    {
+        out.extend(content);
+    } else {
+        out.push_str("...");
+    }
+    out.push('"');
+}
+
+/// Splits the body of a module, impl or trait into its items.
+fn items(body: &str) -> Vec<&str> {
+    let mut items = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in body.char_indices() {
+        match c {
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' => depth -= 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    items.push(&body[start..=i]);
+                    start = i + 1;
+                }
+            }
+            ';' if depth == 0 => {
+                items.push(&body[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    items.push(&body[start..]);
+    items
+        .into_iter()
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+/// Splits fields, variants or generic arguments on the commas between them.
+fn split_commas(text: &str) -> Vec<&str> {
+    let bytes = text.as_bytes();
+    let mut parts = Vec::new();
+    let (mut depth, mut angle, mut start) = (0, 0, 0);
+    for (i, &c) in bytes.iter().enumerate() {
+        let previous = if i > 0 { bytes[i - 1] } else { b' ' };
+        match c {
+            b'(' | b'[' | b'{' => depth += 1,
+            b')' | b']' | b'}' => depth -= 1,
+            b'<' if previous.is_ascii_alphanumeric() || matches!(previous, b'_' | b':' | b'&') => {
+                angle += 1
+            }
+            b'>' if angle > 0 && !matches!(previous, b'-' | b'=') => angle -= 1,
+            b',' if depth == 0 && angle == 0 => {
+                parts.push(&text[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&text[start..]);
+    parts
+        .into_iter()
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+        .collect()
+}
+
+/// The text up to the body of an item, or all of it if it has none.
+fn head(text: &str) -> &str {
+    let mut depth = 0;
+    for (i, c) in text.char_indices() {
+        match c {
+            '(' | '[' => depth += 1,
+            ')' | ']' => depth -= 1,
+            '{' | ';' if depth == 0 => return text[..i].trim(),
+            _ => {}
+        }
+    }
+    text.trim()
+}
+
+/// The text between the first `{` and the last `}`.
+fn body(text: &str) -> &str {
+    match (text.find('{'), text.rfind('}')) {
+        (Some(start), Some(end)) if start < end => &text[start + 1..end],
+        _ => "",
+    }
+}
+
+fn normalize(text: &str) -> String {
+    let mut text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    for (from, to) in [
+        ("( ", "("),
+        (" )", ")"),
+        ("[ ", "["),
+        (" ]", "]"),
+        ("< ", "<"),
+        (" >", ">"),
+        (",)", ")"),
+        (",]", "]"),
+        (",>", ">"),
+    ] {
+        text = text.replace(from, to);
+    }
+    text.trim_end_matches(',').to_string()
+}
+
+/// Splits the attributes from the front of an item.
+fn split_attributes(mut text: &str) -> (Vec<String>, &str) {
+    let mut attributes = Vec::new();
+    while let Some(rest) = text.strip_prefix("#!").or(text.strip_prefix('#')) {
+        let rest = rest.trim_start();
+        let mut depth = 0;
+        let end = rest
+            .char_indices()
+            .find(|&(_, c)| {
+                match c {
+                    '[' | '(' => depth += 1,
+                    ']' | ')' => depth -= 1,
+                    _ => {}
+                }
+                depth == 0
+            })
+            .map_or(rest.len(), |(i, _)| i);
+        attributes.push(normalize(&rest[1..end]));
+        text = rest[(end + 1).min(rest.len())..].trim_start();
+    }
+    (attributes, text)
+}
+
+fn cfgs(attributes: &[String]) -> Vec<String> {
+    attributes
+        .iter()
+        .filter(|a| a.starts_with("cfg("))
+        .cloned()
+        .collect()
+}
+
+fn is_deprecated(attributes: &[String]) -> bool {
+    attributes.iter().any(|a| a.starts_with("deprecated"))
+}
+
+/// The traits derived by the attributes, each with the `cfg` of a `cfg_attr` it is under.
+fn derives(attributes: &[String]) -> Vec<Member> {
+    let mut derived = Vec::new();
+    for attribute in attributes {
+        let (cfg, inner) = match attribute.strip_prefix("cfg_attr(") {
+            Some(rest) => {
+                let parts = split_commas(&rest[..rest.len() - 1]);
+                (vec![format!("cfg({})", parts[0])], parts[1..].to_vec())
+            }
+            None => (Vec::new(), vec![attribute.as_str()]),
+        };
+        for traits in inner.into_iter().filter_map(|a| a.strip_prefix("derive(")) {
+            for name in split_commas(&traits[..traits.len() - 1]) {
+                derived.push(Member {
+                    kind: "impl",
+                    name: String::new(),
+                    signature: format!("derive {name}"),
+                    cfg: cfg.clone(),
+                });
+            }
+        }
+    }
+    derived
+}
+
+/// Splits a `pub` off the front of an item. `pub(crate)` and narrower are not public.
+fn visibility(text: &str) -> (bool, &str) {
+    match text.strip_prefix("pub ") {
+        Some(rest) => (true, rest.trim_start()),
+        None => (false, text),
+    }
+}
+
+/// The keyword of an item, after any qualifiers, and the text from it on.
+fn keyword(mut text: &str) -> (&str, &str) {
+    const QUALIFIERS: [&str; 5] = ["const ", "unsafe ", "async ", "extern \"C\" ", "default "];
+    'strip: loop {
+        for qualifier in QUALIFIERS {
+            if let Some(rest) = text.strip_prefix(qualifier) {
+                let rest = rest.trim_start();
+                let word = rest
+                    .split(|c: char| !c.is_alphanumeric() && c != '_')
+                    .next();
+                if matches!(
+                    word,
+                    Some("fn" | "impl" | "trait" | "const" | "unsafe" | "extern")
+                ) {
+                    text = rest;
+                    continue 'strip;
+                }
+            }
+        }
+        break;
+    }
+    let end = text
+        .find(|c: char| !c.is_alphanumeric() && c != '_')
+        .unwrap_or(text.len());
+    (&text[..end], text)
+}
+
+fn kind(word: &str) -> &'static str {
+    [
+        "struct", "enum", "union", "trait", "type", "const", "static",
+    ]
+    .into_iter()
+    .find(|&kind| kind == word)
+    .unwrap_or("item")
+}
+
+fn ident(text: &str) -> String {
+    text.trim_start()
+        .split(|c: char| !c.is_alphanumeric() && c != '_')
+        .next()
+        .unwrap_or_default()
+        .to_string()
+}
+
+/// Expands a use tree into paths, each with the name it is imported as.
+fn use_paths(prefix: &[String], tree: &str, out: &mut Vec<(Vec<String>, String)>) {
+    let tree = tree.trim();
+    if tree.ends_with('}') {
+        let start = tree.find('{').unwrap();
+        let mut path = prefix.to_vec();
+        path.extend(segments(tree[..start].trim_end_matches("::")));
+        for part in split_commas(&tree[start + 1..tree.len() - 1]) {
+            use_paths(&path, part, out);
+        }
+        return;
+    }
+    let (tree, alias) = match tree.split_once(" as ") {
+        Some((tree, alias)) => (tree, Some(alias.trim().to_string())),
+        None => (tree, None),
+    };
+    let mut path = prefix.to_vec();
+    path.extend(segments(tree));
+    if path.last().map(String::as_str) == Some("self") {
+        path.pop();
+    }
+    let alias = alias.unwrap_or_else(|| path.last().cloned().unwrap_or_default());
+    out.push((path, alias));
+}
+
+fn segments(path: &str) -> Vec<String> {
+    path.split("::")
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+impl Crate {
+    fn read(src: &Path) -> Crate {
+        let mut krate = Crate::default();
+        let lib = fs::read_to_string(src.join("lib.rs")).unwrap();
+        let context = Module {
+            public: true,
+            ..Module::default()
+        };
+        krate.parse_module(CRATE, &strip(&lib), src, &context);
+        krate
+    }
+
+    fn parse_module(&mut self, path: &str, source: &str, dir: &Path, module: &Module) {
+        let mut this = Module {
+            public: module.public,
+            deprecated: module.deprecated,
+            cfg: module.cfg.clone(),
+            ..Module::default()
+        };
+        for text in items(source) {
+            let (attributes, text) = split_attributes(text);
+            let cfg: Vec<String> = this.cfg.iter().cloned().chain(cfgs(&attributes)).collect();
+            let (public, declared) = visibility(text);
+            let (word, text) = keyword(declared);
+            let rest = text[word.len()..].trim_start();
+            let item = |kind: &'static str, name: String, signature: String| Item {
+                kind,
+                name,
+                module: path.to_string(),
+                signature,
+                cfg: cfgs(&attributes),
+                deprecated: is_deprecated(&attributes),
+                members: derives(&attributes),
+            };
+            match word {
+                "mod" => {
+                    let name = ident(rest);
+                    let child = Module {
+                        public: this.public && public,
+                        deprecated: this.deprecated || is_deprecated(&attributes),
+                        cfg,
+                        ..Module::default()
+                    };
+                    let child_path = format!("{path}::{name}");
+                    if rest.trim_end().ends_with('}') {
+                        self.parse_module(&child_path, body(rest), &dir.join(&name), &child);
+                    } else {
+                        let file = [
+                            dir.join(format!("{name}.rs")),
+                            dir.join(&name).join("mod.rs"),
+                        ]
+                        .into_iter()
+                        .find(|file| file.exists())
+                        .unwrap_or_else(|| panic!("no file for module {child_path}"));
+                        let source = strip(&fs::read_to_string(&file).unwrap());
+                        self.parse_module(&child_path, &source, &dir.join(&name), &child);
+                    }
+                }
+                "use" if public => {
+                    let mut paths = Vec::new();
+                    use_paths(&[], rest, &mut paths);
+                    for (path, alias) in paths {
+                        this.uses.push(Use {
+                            path,
+                            alias,
+                            cfg: cfgs(&attributes),
+                        });
+                    }
+                }
+                "fn" if public => {
+                    let name = ident(rest);
+                    this.items.push(item(
+                        "fn",
+                        name,
+                        format!("pub {}", normalize(head(declared))),
+                    ));
+                }
+                "struct" | "enum" | "union" | "trait" | "type" => {
+                    let name = ident(rest);
+                    *self.types.entry(name.clone()).or_default() += 1;
+                    if public {
+                        let mut item = item(kind(word), name, String::new());
+                        item.signature = format!("pub {}", normalize(head(declared)));
+                        self.members(&mut item, word, text);
+                        this.items.push(item);
+                    }
+                }
+                "const" | "static" if public => {
+                    let name = ident(rest.trim_start_matches("mut "));
+                    let declaration = normalize(declared);
+                    let declaration = declaration.split(" = ").next().unwrap();
+                    this.items
+                        .push(item(kind(word), name, format!("pub {declaration}")));
+                }
+                "impl" => this.impls.push(parse_impl(text, cfgs(&attributes))),
+                "macro_rules" if attributes.iter().any(|a| a == "macro_export") => {
+                    let name = ident(rest.trim_start_matches('!'));
+                    this.items
+                        .push(item("macro", name.clone(), format!("{name}!")));
+                }
+                "macro_rules" => {}
+                "bitflags" if rest.starts_with('!') => {
+                    for flags in items(body(rest)) {
+                        let (attributes, flags) = split_attributes(flags);
+                        let (public, flags) = visibility(flags);
+                        let name = ident(flags.trim_start_matches("struct "));
+                        *self.types.entry(name.clone()).or_default() += 1;
+                        if !public {
+                            continue;
+                        }
+                        let mut members = derives(&attributes);
+                        members.extend(items(body(flags)).into_iter().map(|flag| Member {
+                            kind: "const",
+                            name: ident(flag.trim_start_matches("const ")),
+                            // The values are the SDK's
+                            signature: normalize(flag).split(" = ").next().unwrap().to_string(),
+                            cfg: Vec::new(),
+                        }));
+                        this.items.push(Item {
+                            kind: "struct",
+                            name,
+                            module: path.to_string(),
+                            signature: format!("pub {} (bitflags)", normalize(head(flags))),
+                            cfg: cfgs(&attributes),
+                            deprecated: is_deprecated(&attributes),
+                            members,
+                        });
+                    }
+                }
+                _ if rest.starts_with('!') && !word.is_empty() => {
+                    let arguments = body(rest);
+                    let arguments = if arguments.is_empty() {
+                        rest.trim_start_matches('!')
+                    } else {
+                        arguments
+                    };
+                    this.macros.extend(
+                        items(arguments)
+                            .into_iter()
+                            .flat_map(split_commas)
+                            .map(|part| format!("{word}! {}", normalize(part))),
+                    );
+                }
+                _ => {}
+            }
+        }
+        self.modules.insert(path.to_string(), this);
+    }
+
+    /// Adds the public fields, the variants, or the trait items, of a type.
+    fn members(&self, item: &mut Item, kind: &str, text: &str) {
+        let after = &text[head(text).len()..];
+        if !after.trim_start().starts_with('{') {
+            return;
+        }
+        let body = body(after);
+        match kind {
+            "struct" | "union" => {
+                let mut private = false;
+                for field in split_commas(body) {
+                    let (attributes, field) = split_attributes(field);
+                    match visibility(field) {
+                        (true, field) => item.members.push(Member {
+                            kind: "field",
+                            name: ident(field),
+                            signature: format!("pub {}", normalize(field)),
+                            cfg: cfgs(&attributes),
+                        }),
+                        (false, _) => private = true,
+                    }
+                }
+                if private {
+                    item.signature.push_str(" { .. }");
+                }
+            }
+            "enum" => {
+                for variant in split_commas(body) {
+                    let (attributes, variant) = split_attributes(variant);
+                    // The discriminants are the SDK's values
+                    let variant = normalize(variant);
+                    let variant = variant.split(" = ").next().unwrap();
+                    item.members.push(Member {
+                        kind: "variant",
+                        name: ident(variant),
+                        signature: normalize(variant),
+                        cfg: cfgs(&attributes),
+                    });
+                }
+            }
+            "trait" => {
+                for member in items(body) {
+                    let (attributes, declared) = split_attributes(member);
+                    let (word, member) = keyword(declared);
+                    let name = ident(&member[word.len()..]);
+                    let member = declared;
+                    item.members.push(Member {
+                        kind: match word {
+                            "fn" => "fn",
+                            "type" => "type",
+                            _ => "const",
+                        },
+                        name,
+                        signature: normalize(head(member)),
+                        cfg: cfgs(&attributes),
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn lines(&self) -> String {
+        let mut lines = BTreeSet::new();
+        for (path, module) in &self.modules {
+            if !module.public {
+                continue;
+            }
+            let context = (module.deprecated, &module.cfg);
+            add(&mut lines, context, "mod", path, "", &[]);
+            // The raw bindings and the deprecated aliases are listed as the paths they export,
+            // rather than everything under them
+            let expand = !module.deprecated && !module.cfg.iter().any(|c| c.contains("raw-api"));
+            for item in &module.items {
+                self.add_item(&mut lines, context, path, &item.name, item);
+            }
+            for export in &module.uses {
+                let target = format!("= {}", export.path.join("::"));
+                let exported = format!("{path}::{}", export.alias);
+                if !expand {
+                    add(&mut lines, context, "use", &exported, &target, &export.cfg);
+                    continue;
+                }
+                let resolved = self.resolve(path, &export.path, &export.alias, 0);
+                if resolved.is_empty() {
+                    // A path outside the crate
+                    add(&mut lines, context, "use", &exported, &target, &export.cfg);
+                }
+                for (alias, item) in resolved {
+                    let mut item = item.clone();
+                    item.cfg.extend(export.cfg.iter().cloned());
+                    self.add_item(&mut lines, context, path, &alias, &item);
+                }
+            }
+            for invocation in &module.macros {
+                add(&mut lines, context, "macro", path, invocation, &[]);
+            }
+        }
+        let mut out = String::new();
+        for (_, _, line) in lines {
+            out.push_str(&line);
+            out.push('\n');
+        }
+        out
+    }
+
+    fn add_item(
+        &self,
+        lines: &mut BTreeSet<(u8, String, String)>,
+        (deprecated, cfg): (bool, &Vec<String>),
+        module: &str,
+        name: &str,
+        item: &Item,
+    ) {
+        let path = format!("{module}::{name}");
+        let cfg: Vec<String> = cfg.iter().chain(&item.cfg).cloned().collect();
+        let context = (deprecated || item.deprecated, &cfg);
+        add(lines, context, item.kind, &path, &item.signature, &[]);
+        for member in &item.members {
+            let member_path = match member.name.as_str() {
+                "" => path.clone(),
+                name => format!("{path}::{name}"),
+            };
+            let signature = &member.signature;
+            add(
+                lines,
+                context,
+                member.kind,
+                &member_path,
+                signature,
+                &member.cfg,
+            );
+        }
+        if !matches!(item.kind, "struct" | "enum" | "union" | "trait" | "type") {
+            return;
+        }
+        let unique = self.types.get(&item.name) == Some(&1);
+        for (impl_module, module) in &self.modules {
+            for block in &module.impls {
+                if block.target != item.name || (impl_module != &item.module && !unique) {
+                    continue;
+                }
+                let cfg: Vec<String> = block
+                    .cfg
+                    .iter()
+                    .cloned()
+                    .chain(module.cfg.clone())
+                    .collect();
+                match &block.header {
+                    Some(header) => add(lines, context, "impl", &path, header, &cfg),
+                    None => {
+                        for member in &block.members {
+                            let member_cfg: Vec<String> =
+                                cfg.iter().chain(&member.cfg).cloned().collect();
+                            let member_path = format!("{path}::{}", member.name);
+                            let signature = &member.signature;
+                            add(
+                                lines,
+                                context,
+                                member.kind,
+                                &member_path,
+                                signature,
+                                &member_cfg,
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// The items a `pub use` of `path` in `module` exports, with the names they are exported as.
+    fn resolve(
+        &self,
+        module: &str,
+        path: &[String],
+        alias: &str,
+        depth: usize,
+    ) -> Vec<(String, &Item)> {
+        assert!(
+            depth < 16,
+            "re-exports of {} go round in a loop",
+            path.join("::")
+        );
+        let mut current = match path.first().map(String::as_str) {
+            Some("crate") => CRATE.to_string(),
+            Some("self") => module.to_string(),
+            Some("super") => module.rsplit_once("::").unwrap().0.to_string(),
+            Some(first) if self.modules.contains_key(&format!("{module}::{first}")) => {
+                format!("{module}::{first}")
+            }
+            _ => return Vec::new(),
+        };
+        let (last, middle) = path[1..].split_last().unwrap_or((&path[0], &[]));
+        for segment in middle {
+            current = match segment.as_str() {
+                "super" => current.rsplit_once("::").unwrap().0.to_string(),
+                segment => format!("{current}::{segment}"),
+            };
+        }
+        let Some(target) = self.modules.get(&current) else {
+            return Vec::new();
+        };
+        let glob = last == "*";
+        let mut resolved: Vec<(String, &Item)> = target
+            .items
+            .iter()
+            .filter(|item| glob || &item.name == last)
+            .map(|item| {
+                let name = if glob {
+                    item.name.clone()
+                } else {
+                    alias.to_string()
+                };
+                (name, item)
+            })
+            .collect();
+        for export in &target.uses {
+            if glob || export.alias == *last || export.alias == "*" {
+                let exported = self.resolve(&current, &export.path, &export.alias, depth + 1);
+                resolved.extend(exported.into_iter().filter_map(|(name, item)| {
+                    if glob {
+                        Some((name, item))
+                    } else {
+                        (name == *last).then(|| (alias.to_string(), item))
+                    }
+                }));
+            }
+        }
+        resolved
+    }
+}
+
+fn parse_impl(text: &str, cfg: Vec<String>) -> Impl {
+    let header = normalize(head(text));
+    let mut rest = header
+        .trim_start_matches("unsafe ")
+        .trim_start_matches("impl");
+    if rest.starts_with('<') {
+        let mut angle = 0;
+        let end = rest
+            .char_indices()
+            .find(|&(i, c)| {
+                match c {
+                    '<' => angle += 1,
+                    '>' if rest.as_bytes()[i - 1] != b'-' => angle -= 1,
+                    _ => {}
+                }
+                angle == 0
+            })
+            .map_or(rest.len(), |(i, _)| i + 1);
+        rest = &rest[end..];
+    }
+    let rest = rest.split(" where ").next().unwrap().trim();
+    let (header, target) = match rest.split_once(" for ") {
+        Some((_, target)) => (Some(header.clone()), target),
+        None => (None, rest),
+    };
+    let target = target.trim_start_matches('&').trim_start_matches("dyn ");
+    let target = target.split('<').next().unwrap();
+    let target = target.rsplit("::").next().unwrap().trim().to_string();
+    let mut members = Vec::new();
+    if header.is_none() {
+        for member in items(body(&text[head(text).len()..])) {
+            let (attributes, member) = split_attributes(member);
+            let (public, declared) = visibility(member);
+            let (word, member) = keyword(declared);
+            if !public || !matches!(word, "fn" | "const" | "type") {
+                continue;
+            }
+            let name = ident(&member[word.len()..]);
+            let member = declared;
+            let signature = match word {
+                "const" => normalize(member).split(" = ").next().unwrap().to_string(),
+                _ => normalize(head(member)),
+            };
+            members.push(Member {
+                kind: if word == "fn" { "fn" } else { "const" },
+                name,
+                signature: format!("pub {signature}"),
+                cfg: cfgs(&attributes),
+            });
+        }
+    }
+    Impl {
+        target,
+        header,
+        members,
+        cfg,
+    }
+}
+
+fn add(
+    lines: &mut BTreeSet<(u8, String, String)>,
+    (deprecated, cfg): (bool, &Vec<String>),
+    kind: &str,
+    path: &str,
+    signature: &str,
+    own_cfg: &[String],
+) {
+    let mut cfg: Vec<&String> = cfg.iter().chain(own_cfg).collect();
+    cfg.sort();
+    cfg.dedup();
+    let (rank, tier) = if deprecated {
+        (3, "deprecated")
+    } else if cfg.iter().any(|c| c.contains("raw-api")) {
+        (2, "raw")
+    } else if is_stable(path) {
+        (0, "stable")
+    } else {
+        (1, "experimental")
+    };
+    let mut line = format!("{tier} {kind} {path}");
+    if !signature.is_empty() {
+        line.push(' ');
+        line.push_str(signature);
+    }
+    for cfg in cfg {
+        line.push_str(&format!(" #[{cfg}]"));
+    }
+    lines.insert((rank, format!("{path} {kind}"), line));
+}
+
+fn root() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+}
+
+fn public_api() -> String {
+    Crate::read(&root().join("src")).lines()
+}
+
+#[test]
+fn public_api_matches_the_snapshot() {
+    let api = public_api();
+    let file = root().join("tests/public_api.txt");
+    if std::env::var_os("UPDATE_PUBLIC_API").is_some() {
+        fs::write(&file, &api).unwrap();
+        return;
+    }
+    let snapshot = fs::read_to_string(&file).unwrap_or_default();
+    if snapshot != api {
+        let old: BTreeSet<&str> = snapshot.lines().collect();
+        let new: BTreeSet<&str> = api.lines().collect();
+        let mut diff = String::new();
+        for line in old.difference(&new) {
+            diff += &format!("- {line}\n");
+        }
+        for line in new.difference(&old) {
+            diff += &format!("+ {line}\n");
+        }
+        panic!(
+            "the public api has changed, rerun with UPDATE_PUBLIC_API=1 to accept the change:\n{diff}"
+        );
+    }
+}
+
+#[test]
+fn stable_api_does_not_name_the_bindings() {
+    for line in public_api().lines() {
+        if line.starts_with("stable") || line.starts_with("experimental") {
+            assert!(
+                !line.contains("sdk::") && !line.contains("cdecklink_"),
+                "{line}"
+            );
+        }
+    }
+}
+
+#[test]
+fn stable_api_does_not_name_experimental_types() {
+    let api = public_api();
+    // The types of each tier, by name, as signatures name them
+    let mut stable = BTreeSet::new();
+    let mut experimental = BTreeSet::new();
+    for line in api.lines() {
+        let mut words = line.split(' ');
+        let (tier, kind, path) = (words.next(), words.next(), words.next());
+        if let (Some(tier), Some("struct" | "enum" | "trait" | "type" | "use"), Some(path)) =
+            (tier, kind, path)
+        {
+            let name = path.rsplit("::").next().unwrap();
+            match tier {
+                "stable" => stable.insert(name),
+                "experimental" => experimental.insert(name),
+                _ => false,
+            };
+        }
+    }
+
+    let mut named = Vec::new();
+    for line in api.lines().filter(|line| line.starts_with("stable ")) {
+        let signature = line.splitn(4, ' ').nth(3).unwrap_or("");
+        // An impl of a trait is used through the trait, so is of the trait's tier
+        if signature.starts_with("derive ") || signature.starts_with("impl ") {
+            continue;
+        }
+        let signature = signature.split(" #[").next().unwrap();
+        for word in signature.split(|c: char| !c.is_alphanumeric() && c != '_') {
+            if experimental.contains(word) && !stable.contains(word) {
+                named.push(format!("{line} names {word}"));
+            }
+        }
+    }
+    assert!(named.is_empty(), "{}", named.join("\n"));
+}
+
+#[cfg(all(feature = "raw-api", feature = "mock-backend"))]
+mod raw {
+    use super::common;
+    use decklink::device::get_devices;
+    use decklink::device::input::{
+        DeckLinkInputCallback, DecklinkDetectedVideoInputFormatFlags, DecklinkVideoInputFlags,
+        DecklinkVideoInputFormatChangedEvents,
+    };
+    use decklink::display_mode::DecklinkDisplayModeId;
+    use decklink::frame::{DecklinkPixelFormat, DecklinkVideoFrame};
+    use decklink::mock::{MockBackend, MockDevice, MockFrame};
+    use std::sync::atomic::{AtomicI64, Ordering};
+    use std::sync::Arc;
+
+    /// Reads the width of each frame through its raw pointer.
+    #[derive(Default)]
+    struct RawWidth(AtomicI64);
+
+    impl DeckLinkInputCallback for RawWidth {
+        fn video_input_format_changed(
+            &self,
+            _events: DecklinkVideoInputFormatChangedEvents,
+            _new_display_mode: DecklinkDisplayModeId,
+            _detected_signal_flags: DecklinkDetectedVideoInputFormatFlags,
+        ) {
+        }
+
+        fn video_input_frame_arrived(&self, video_frame: Option<DecklinkVideoFrame>) -> bool {
+            let frame = video_frame.unwrap();
+            let width = unsafe { decklink::raw::cdecklink_video_frame_get_width(frame.as_raw()) };
+            self.0.store(width as i64, Ordering::SeqCst);
+            true
+        }
+    }
+
+    #[test]
+    fn wrappers_give_out_the_pointers_they_call() {
+        let backend =
+            MockBackend::install(vec![MockDevice::new("DeckLink Duo 2 (1)").with_output()]);
+        let devices = get_devices().unwrap();
+        assert!(!devices[0].as_raw().is_null());
+        assert!(!devices[0].output().unwrap().as_raw().is_null());
+
+        let mut input = devices[0].input().unwrap();
+        let widths = Arc::new(RawWidth::default());
+        input
+            .enable_video_input(
+                DecklinkDisplayModeId::HD1080p25,
+                DecklinkPixelFormat::Format8BitYUV,
+                DecklinkVideoInputFlags::empty(),
+            )
+            .unwrap();
+        common::start_streams(&mut input, widths.clone());
+
+        let mock = backend.input(0);
+        let frame = || MockFrame::new(48, 2, DecklinkPixelFormat::Format8BitYUV);
+        mock.buffer_frame(frame());
+        mock.buffer_frame(frame());
+        let mut count = 0;
+        let result = unsafe {
+            decklink::raw::cdecklink_input_get_available_video_frame_count(
+                input.as_raw(),
+                &mut count,
+            )
+        };
+        assert_eq!((result, count), (0, 2));
+
+        assert!(mock.deliver_frame(frame()).is_ok());
+        assert_eq!(widths.0.load(Ordering::SeqCst), 48);
+        input.stop_streams().unwrap();
+    }
+}
@@ -0,0 +1,158 @@
+//! Overflow policies, counters and blocking pops of `FrameQueue`, and a producer racing a
+//! consumer.
+
+use decklink::queue::{FrameQueue, OverflowPolicy, PopError, PushResult};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[test]
+fn full_queue_rejects_the_newest() {
+    let queue = FrameQueue::new(2, OverflowPolicy::RejectNewest);
+    assert_eq!(queue.push(1), PushResult::Accepted);
+    assert_eq!(queue.push(2), PushResult::Accepted);
+    assert_eq!(queue.push(3), PushResult::Rejected(3));
+    assert_eq!(queue.len(), 2);
+
+    assert_eq!(queue.try_pop(), Some(1));
+    assert_eq!(queue.push(4), PushResult::Accepted);
+    assert_eq!(queue.try_pop(), Some(2));
+    assert_eq!(queue.try_pop(), Some(4));
+    assert_eq!(queue.try_pop(), None);
+    assert_eq!((queue.accepted_count(), queue.dropped_count()), (3, 1));
+}
+
+#[test]
+fn full_queue_drops_the_oldest() {
+    let queue = FrameQueue::new(2, OverflowPolicy::DropOldest);
+    queue.push(1);
+    queue.push(2);
+    assert_eq!(queue.push(3), PushResult::DroppedOldest(1));
+    assert_eq!(queue.push(4), PushResult::DroppedOldest(2));
+
+    assert_eq!(queue.try_pop(), Some(3));
+    assert_eq!(queue.try_pop(), Some(4));
+    assert!(queue.is_empty());
+    assert_eq!((queue.accepted_count(), queue.dropped_count()), (4, 2));
+}
+
+#[test]
+fn capacity_is_at_least_two() {
+    let queue = FrameQueue::new(1, OverflowPolicy::DropOldest);
+    assert_eq!(queue.capacity(), 2);
+    assert_eq!(queue.policy(), OverflowPolicy::DropOldest);
+    queue.push(1);
+    queue.push(2);
+    assert_eq!(queue.push(3), PushResult::DroppedOldest(1));
+    assert_eq!(queue.len(), 2);
+}
+
+#[test]
+fn pop_timeout_gives_up() {
+    let queue = FrameQueue::<u32>::new(4, OverflowPolicy::RejectNewest);
+    let start = Instant::now();
+    assert_eq!(
+        queue.pop_timeout(Duration::from_millis(20)),
+        Err(PopError::Timeout)
+    );
+    assert!(start.elapsed() >= Duration::from_millis(20));
+
+    queue.push(7);
+    assert_eq!(queue.pop_timeout(Duration::from_millis(20)), Ok(7));
+}
+
+#[test]
+fn push_wakes_a_waiting_consumer() {
+    let queue = Arc::new(FrameQueue::new(4, OverflowPolicy::RejectNewest));
+    let consumer = {
+        let queue = queue.clone();
+        thread::spawn(move || queue.pop_timeout(Duration::from_secs(5)))
+    };
+    thread::sleep(Duration::from_millis(20));
+    queue.push(5);
+    assert_eq!(consumer.join().unwrap(), Ok(5));
+}
+
+#[test]
+fn close_drains_then_wakes_the_consumer() {
+    let queue = Arc::new(FrameQueue::new(4, OverflowPolicy::RejectNewest));
+    queue.push(1);
+    queue.push(2);
+    queue.close();
+    assert!(queue.is_closed());
+    assert_eq!(queue.push(3), PushResult::Rejected(3));
+    // A rejection of a closed queue is not a drop
+    assert_eq!(queue.dropped_count(), 0);
+
+    assert_eq!(queue.pop(), Some(1));
+    assert_eq!(queue.pop(), Some(2));
+    assert_eq!(queue.pop(), None);
+    assert_eq!(
+        queue.pop_timeout(Duration::from_secs(5)),
+        Err(PopError::Closed)
+    );
+
+    let queue = Arc::new(FrameQueue::<u32>::new(4, OverflowPolicy::RejectNewest));
+    let consumer = {
+        let queue = queue.clone();
+        thread::spawn(move || queue.pop())
+    };
+    thread::sleep(Duration::from_millis(20));
+    queue.close();
+    assert_eq!(consumer.join().unwrap(), None);
+}
+
+#[test]
+fn queued_items_are_dropped_with_the_queue() {
+    let item = Arc::new(());
+    let queue = FrameQueue::new(4, OverflowPolicy::RejectNewest);
+    queue.push(item.clone());
+    queue.push(item.clone());
+    assert_eq!(Arc::strong_count(&item), 3);
+    drop(queue);
+    assert_eq!(Arc::strong_count(&item), 1);
+}
+
+const ITEMS: u64 = 100_000;
+
+/// Push `ITEMS` numbers from one thread while another pops them, returning what was popped.
+fn race(policy: OverflowPolicy) -> (Arc<FrameQueue<u64>>, Vec<u64>) {
+    let queue = Arc::new(FrameQueue::new(8, policy));
+    let consumer = {
+        let queue = queue.clone();
+        thread::spawn(move || {
+            let mut popped = Vec::new();
+            while let Some(n) = queue.pop() {
+                popped.push(n);
+            }
+            popped
+        })
+    };
+
+    for n in 0..ITEMS {
+        while let PushResult::Rejected(_) = queue.push(n) {
+            if policy == OverflowPolicy::DropOldest {
+                break;
+            }
+            thread::yield_now();
+        }
+    }
+    queue.close();
+    (queue, consumer.join().unwrap())
+}
+
+#[test]
+fn racing_consumer_sees_every_item_in_order() {
+    let (queue, popped) = race(OverflowPolicy::RejectNewest);
+    assert_eq!(popped, (0..ITEMS).collect::<Vec<_>>());
+    assert_eq!(queue.accepted_count(), ITEMS);
+}
+
+#[test]
+fn racing_consumer_sees_the_survivors_in_order() {
+    let (queue, popped) = race(OverflowPolicy::DropOldest);
+    assert!(popped.windows(2).all(|w| w[0] < w[1]));
+    assert_eq!(popped.last(), Some(&(ITEMS - 1)));
+    assert_eq!(queue.accepted_count(), ITEMS);
+    assert_eq!(popped.len() as u64 + queue.dropped_count(), ITEMS);
+}
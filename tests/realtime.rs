@@ -0,0 +1,383 @@
+//! Real-time scheduling, memory locking and the readiness report, with or without the
+//! privileges for them.
+#![cfg(all(feature = "realtime", target_os = "linux"))]
+
+#[cfg(feature = "mock-backend")]
+mod common;
+
+use decklink::allocator::{
+    BufferSpec, VideoBuffer, VideoBufferAllocator, VideoBufferAllocatorProvider,
+};
+use decklink::realtime::{
+    apply, elevate_internal_threads, lock_allocator_memory, realtime_readiness,
+    reset_internal_threads, thread_elevations, FindingSeverity, ReadinessReport, RealtimeConfig,
+    RealtimeError, ResourceLimit, SchedPolicy, ThreadElevation, ThreadScheduling,
+};
+use decklink::verify::{AsyncHasher, RedundancyComparator};
+use decklink::SdkError;
+use std::ffi::c_void;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// The scheduling of the crate's threads is set for the whole process, so the tests that
+/// change it run one at a time.
+fn exclusive() -> MutexGuard<'static, ()> {
+    static LOCK: Mutex<()> = Mutex::new(());
+    let guard = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    reset_internal_threads();
+    guard
+}
+
+const EPERM: i32 = 1;
+
+fn fifo(priority: i32) -> ThreadScheduling {
+    ThreadScheduling {
+        policy: SchedPolicy::Fifo,
+        priority,
+    }
+}
+
+const NORMAL: ThreadScheduling = ThreadScheduling {
+    policy: SchedPolicy::Other,
+    priority: 0,
+};
+
+/// Start two hashing threads, and wait for them to finish.
+fn run_hasher_threads() {
+    let (hasher, _verdicts) = AsyncHasher::new(RedundancyComparator::new(0), 2);
+    drop(hasher);
+}
+
+fn hasher_elevation() -> ThreadElevation {
+    thread_elevations()
+        .into_iter()
+        .find(|e| e.role == "hasher")
+        .unwrap()
+}
+
+#[test]
+fn an_out_of_range_priority_changes_nothing() {
+    let _guard = exclusive();
+    let error = elevate_internal_threads(fifo(0)).unwrap_err();
+    assert_eq!(error, RealtimeError::InvalidPriority { min: 1, max: 99 });
+    assert_eq!(error.to_string(), "the priority must be from 1 to 99");
+    assert_eq!(realtime_readiness().requested, None);
+}
+
+#[test]
+fn failures_are_reported_without_failing_unless_required() {
+    let _guard = exclusive();
+    let mut config = RealtimeConfig::fifo(0);
+    config.lock_all_memory = false;
+    let outcome = apply(&config).unwrap();
+    assert!(!outcome.is_complete());
+    assert_eq!(
+        outcome.internal_threads,
+        Some(RealtimeError::InvalidPriority { min: 1, max: 99 })
+    );
+    assert_eq!(
+        (outcome.lock_all_memory, outcome.current_thread),
+        (None, None)
+    );
+
+    config.required = true;
+    let outcome = apply(&config).unwrap_err();
+    assert!(outcome.internal_threads.is_some());
+
+    // Normal scheduling needs no privilege
+    config.scheduling = NORMAL;
+    config.elevate_current_thread = true;
+    assert!(apply(&config).unwrap().is_complete());
+}
+
+#[test]
+fn threads_started_by_the_crate_take_the_requested_scheduling() {
+    let _guard = exclusive();
+    elevate_internal_threads(NORMAL).unwrap();
+    let before = thread_elevations()
+        .into_iter()
+        .find(|e| e.role == "hasher")
+        .map_or(0, |e| e.started);
+    run_hasher_threads();
+    let hasher = hasher_elevation();
+    assert_eq!(hasher.started - before, 2);
+    assert_eq!(hasher.last_scheduling, Some(NORMAL));
+    assert_eq!(realtime_readiness().requested, Some(NORMAL));
+}
+
+#[test]
+fn real_time_threads_are_elevated_only_with_the_privilege_for_it() {
+    let _guard = exclusive();
+    let readiness = realtime_readiness();
+    let privileged = readiness.can_set_any_priority
+        || readiness
+            .rtprio_limit
+            .is_some_and(|limit| limit.soft.is_none_or(|soft| soft >= 1));
+
+    elevate_internal_threads(fifo(1)).unwrap();
+    let failed_before = thread_elevations()
+        .into_iter()
+        .find(|e| e.role == "hasher")
+        .map_or(0, |e| e.failed);
+    run_hasher_threads();
+    let hasher = hasher_elevation();
+    let report = realtime_readiness();
+    reset_internal_threads();
+
+    let not_elevated = report
+        .findings
+        .iter()
+        .find(|f| f.message.contains("hasher threads could not be elevated"));
+    if privileged {
+        assert_eq!(hasher.failed, failed_before);
+        assert_eq!(hasher.last_scheduling, Some(fifo(1)));
+    } else {
+        // What CI without CAP_SYS_NICE sees: the capture carries on, and the report says why
+        assert_eq!(hasher.failed - failed_before, 2);
+        assert_eq!(hasher.last_error, Some(RealtimeError::Os(EPERM)));
+        assert_eq!(hasher.last_scheduling, Some(NORMAL));
+        assert_eq!(not_elevated.unwrap().severity, FindingSeverity::Problem);
+        assert!(!report.is_ready());
+    }
+}
+
+struct HeapBuffer(Mutex<Vec<u8>>);
+
+impl VideoBuffer for HeapBuffer {
+    fn get_bytes(&self) -> Result<*mut c_void, SdkError> {
+        Ok(self.0.lock().unwrap().as_mut_ptr() as *mut c_void)
+    }
+}
+
+struct HeapAllocator(usize);
+
+impl VideoBufferAllocator for HeapAllocator {
+    fn allocate(&self) -> Result<Box<dyn VideoBuffer>, SdkError> {
+        Ok(Box::new(HeapBuffer(Mutex::new(vec![0; self.0]))))
+    }
+}
+
+struct HeapProvider;
+
+impl VideoBufferAllocatorProvider for HeapProvider {
+    fn get_allocator(&self, spec: BufferSpec) -> Result<Arc<dyn VideoBufferAllocator>, SdkError> {
+        Ok(Arc::new(HeapAllocator(spec.buffer_size as usize)))
+    }
+}
+
+#[test]
+fn allocated_buffers_are_locked_until_freed_or_counted_as_failed() {
+    let provider = lock_allocator_memory(Arc::new(HeapProvider));
+    let spec = BufferSpec {
+        buffer_size: 4096,
+        width: 32,
+        height: 32,
+        row_bytes: 128,
+        pixel_format: decklink::frame::DecklinkPixelFormat::Format8BitARGB as u32,
+    };
+    let allocator = provider.get_allocator(spec).unwrap();
+    let buffers: Vec<_> = (0..3).map(|_| allocator.allocate().unwrap()).collect();
+    let stats = provider.stats();
+    assert_eq!(stats.locked_buffers + stats.failed_buffers, 3);
+    assert_eq!(stats.locked_bytes, stats.locked_buffers * 4096);
+    assert_eq!(provider.last_error().is_some(), stats.failed_buffers > 0);
+
+    drop(buffers);
+    let stats = provider.stats();
+    assert_eq!((stats.locked_buffers, stats.locked_bytes), (0, 0));
+}
+
+/// A report of a process with generous limits and nothing requested.
+fn ready_report() -> ReadinessReport {
+    ReadinessReport {
+        memlock_limit: Some(ResourceLimit {
+            soft: None,
+            hard: None,
+        }),
+        rtprio_limit: Some(ResourceLimit {
+            soft: Some(99),
+            hard: Some(99),
+        }),
+        locked_bytes: Some(1 << 30),
+        can_lock_unlimited: false,
+        can_set_any_priority: false,
+        requested: None,
+        threads: Vec::new(),
+        callback_thread: None,
+        findings: Vec::new(),
+    }
+}
+
+fn messages(report: &ReadinessReport) -> Vec<(FindingSeverity, String)> {
+    report
+        .evaluate()
+        .into_iter()
+        .map(|f| (f.severity, f.message))
+        .collect()
+}
+
+#[test]
+fn a_good_setup_has_no_findings() {
+    let mut report = ready_report();
+    assert!(report.evaluate().is_empty());
+    assert!(report.is_ready());
+    assert_eq!(report.to_string(), "ready for real-time capture\n");
+
+    report.requested = Some(fifo(40));
+    report.callback_thread = Some(fifo(50));
+    assert!(report.evaluate().is_empty());
+}
+
+#[test]
+fn missing_privileges_are_problems_with_remedies() {
+    let mut report = ready_report();
+    report.memlock_limit = Some(ResourceLimit {
+        soft: Some(64 << 10),
+        hard: Some(64 << 10),
+    });
+    report.rtprio_limit = Some(ResourceLimit {
+        soft: Some(0),
+        hard: Some(0),
+    });
+    report.locked_bytes = Some(0);
+    report.requested = Some(fifo(40));
+    report.callback_thread = Some(fifo(50));
+    report.threads = vec![ThreadElevation {
+        role: "tap",
+        started: 3,
+        elevated: 1,
+        failed: 2,
+        last_error: Some(RealtimeError::Os(EPERM)),
+        last_scheduling: Some(NORMAL),
+    }];
+
+    assert_eq!(
+        messages(&report),
+        [
+            (
+                FindingSeverity::Problem,
+                "the memlock limit is 65536 bytes, too low to lock frame buffers".to_string()
+            ),
+            (
+                FindingSeverity::Problem,
+                "no memory of the process is locked, so frame buffers can be paged out".to_string()
+            ),
+            (
+                FindingSeverity::Problem,
+                "the rtprio limit is 0, so threads cannot be given real-time priorities"
+                    .to_string()
+            ),
+            (
+                FindingSeverity::Problem,
+                format!(
+                    "2 of 3 tap threads could not be elevated: {}",
+                    std::io::Error::from_raw_os_error(EPERM)
+                )
+            ),
+        ]
+    );
+    report.findings = report.evaluate();
+    assert!(!report.is_ready());
+    let text = report.to_string();
+    assert!(text.starts_with(
+        "problem: the memlock limit is 65536 bytes, too low to lock frame buffers (raise memlock"
+    ));
+    assert_eq!(text.lines().count(), 4);
+
+    // The capabilities lift the limits
+    report.can_lock_unlimited = true;
+    report.can_set_any_priority = true;
+    report.locked_bytes = Some(1 << 20);
+    report.threads.clear();
+    assert!(report.evaluate().is_empty());
+}
+
+#[test]
+fn the_crate_threads_are_compared_with_the_callback_thread() {
+    let mut report = ready_report();
+    report.callback_thread = Some(fifo(50));
+    assert_eq!(
+        messages(&report),
+        [(
+            FindingSeverity::Problem,
+            "the driver's callback thread runs Fifo at priority 50, but the crate's threads are \
+             not elevated"
+                .to_string()
+        )]
+    );
+
+    report.requested = Some(fifo(60));
+    assert_eq!(
+        messages(&report),
+        [(
+            FindingSeverity::Notice,
+            "the crate's threads run above the driver's callback thread, at 60 against 50"
+                .to_string()
+        )]
+    );
+
+    report.callback_thread = None;
+    let findings = report.evaluate();
+    assert_eq!(findings[0].severity, FindingSeverity::Notice);
+    assert!(findings[0].remedy.contains("observe_callback_thread"));
+}
+
+#[cfg(feature = "mock-backend")]
+mod mock {
+    use super::common;
+    use super::*;
+    use decklink::device::get_devices;
+    use decklink::device::input::{
+        DeckLinkInputCallback, DecklinkDetectedVideoInputFormatFlags, DecklinkVideoInputFlags,
+        DecklinkVideoInputFormatChangedEvents,
+    };
+    use decklink::display_mode::DecklinkDisplayModeId;
+    use decklink::frame::{DecklinkPixelFormat, DecklinkVideoFrame};
+    use decklink::mock::MockFrame;
+    use decklink::realtime::observe_callback_thread;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[derive(Default)]
+    struct Counter(AtomicUsize);
+
+    impl DeckLinkInputCallback for Counter {
+        fn video_input_format_changed(
+            &self,
+            _events: DecklinkVideoInputFormatChangedEvents,
+            _new_display_mode: DecklinkDisplayModeId,
+            _detected_signal_flags: DecklinkDetectedVideoInputFormatFlags,
+        ) {
+        }
+
+        fn video_input_frame_arrived(&self, _video_frame: Option<DecklinkVideoFrame>) -> bool {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            true
+        }
+    }
+
+    #[test]
+    fn the_callback_thread_is_observed() {
+        let _guard = exclusive();
+        let backend = common::mini_recorder();
+        let devices = get_devices().unwrap();
+        let mut input = devices[0].input().unwrap();
+        let counter = Arc::new(Counter::default());
+        input
+            .enable_video_input(
+                DecklinkDisplayModeId::HD1080p25,
+                DecklinkPixelFormat::Format8BitYUV,
+                DecklinkVideoInputFlags::empty(),
+            )
+            .unwrap();
+        input
+            .set_handler(Some(observe_callback_thread(counter.clone())))
+            .unwrap();
+        input.start_streams().unwrap();
+
+        // The mock calls back on the test thread, which runs with normal scheduling
+        let frame = || MockFrame::new(48, 2, DecklinkPixelFormat::Format8BitYUV);
+        assert!(backend.input(0).deliver_frame(frame()).is_ok());
+        assert!(backend.input(0).deliver_frame(frame()).is_ok());
+        assert_eq!(counter.0.load(Ordering::SeqCst), 2);
+        assert_eq!(realtime_readiness().callback_thread, Some(NORMAL));
+    }
+}
@@ -0,0 +1,261 @@
+//! Every variant of the enums `decklink::reference` covers has an entry, and the tables in
+//! its documentation are the ones the code renders.
+
+use decklink::device::status::DecklinkStatusId;
+use decklink::display_mode::DecklinkDisplayModeId;
+use decklink::frame::DecklinkPixelFormat;
+use decklink::reference::{self, DecklinkReferenceExt, ReferenceTable};
+use strum::IntoEnumIterator;
+
+// These matches stop the test compiling when a variant is added, until it is given an SDK
+// constant here and an entry in the table the test checks it against.
+
+fn pixel_format_constant(format: DecklinkPixelFormat) -> &'static str {
+    use DecklinkPixelFormat as F;
+    match format {
+        F::Format8BitYUV => "bmdFormat8BitYUV",
+        F::Format10BitYUV => "bmdFormat10BitYUV",
+        F::Format8BitARGB => "bmdFormat8BitARGB",
+        F::Format8BitBGRA => "bmdFormat8BitBGRA",
+        F::Format10BitRGB => "bmdFormat10BitRGB",
+        F::Format12BitRGB => "bmdFormat12BitRGB",
+        F::Format12BitRGBLE => "bmdFormat12BitRGBLE",
+        F::Format10BitRGBXLE => "bmdFormat10BitRGBXLE",
+        F::Format10BitRGBX => "bmdFormat10BitRGBX",
+        F::FormatH265 => "bmdFormatH265",
+        F::FormatDNxHR => "bmdFormatDNxHR",
+    }
+}
+
+fn display_mode_constant(mode: DecklinkDisplayModeId) -> &'static str {
+    use DecklinkDisplayModeId as M;
+    match mode {
+        M::NTSC => "bmdModeNTSC",
+        M::NTSC2398 => "bmdModeNTSC2398",
+        M::PAL => "bmdModePAL",
+        M::NTSCp => "bmdModeNTSCp",
+        M::PALp => "bmdModePALp",
+        M::HD1080p2398 => "bmdModeHD1080p2398",
+        M::HD1080p24 => "bmdModeHD1080p24",
+        M::HD1080p25 => "bmdModeHD1080p25",
+        M::HD1080p2997 => "bmdModeHD1080p2997",
+        M::HD1080p30 => "bmdModeHD1080p30",
+        M::HD1080i50 => "bmdModeHD1080i50",
+        M::HD1080i5994 => "bmdModeHD1080i5994",
+        M::HD1080i6000 => "bmdModeHD1080i6000",
+        M::HD1080p50 => "bmdModeHD1080p50",
+        M::HD1080p5994 => "bmdModeHD1080p5994",
+        M::HD1080p6000 => "bmdModeHD1080p6000",
+        M::HD1080p4795 => "bmdModeHD1080p4795",
+        M::HD1080p48 => "bmdModeHD1080p48",
+        M::HD1080p9590 => "bmdModeHD1080p9590",
+        M::HD1080p96 => "bmdModeHD1080p96",
+        M::HD1080p100 => "bmdModeHD1080p100",
+        M::HD1080p11988 => "bmdModeHD1080p11988",
+        M::HD1080p120 => "bmdModeHD1080p120",
+        M::HD720p50 => "bmdModeHD720p50",
+        M::HD720p5994 => "bmdModeHD720p5994",
+        M::HD720p60 => "bmdModeHD720p60",
+        M::HD2k2398 => "bmdMode2k2398",
+        M::HD2k24 => "bmdMode2k24",
+        M::HD2k25 => "bmdMode2k25",
+        M::HD2kDCI2398 => "bmdMode2kDCI2398",
+        M::HD2kDCI24 => "bmdMode2kDCI24",
+        M::HD2kDCI25 => "bmdMode2kDCI25",
+        M::HD2kDCI2997 => "bmdMode2kDCI2997",
+        M::HD2kDCI30 => "bmdMode2kDCI30",
+        M::HD2kDCI4795 => "bmdMode2kDCI4795",
+        M::HD2kDCI48 => "bmdMode2kDCI48",
+        M::HD2kDCI50 => "bmdMode2kDCI50",
+        M::HD2kDCI5994 => "bmdMode2kDCI5994",
+        M::HD2kDCI60 => "bmdMode2kDCI60",
+        M::HD2kDCI9590 => "bmdMode2kDCI9590",
+        M::HD2kDCI96 => "bmdMode2kDCI96",
+        M::HD2kDCI100 => "bmdMode2kDCI100",
+        M::HD2kDCI11988 => "bmdMode2kDCI11988",
+        M::HD2kDCI120 => "bmdMode2kDCI120",
+        M::UHD4K2160p2398 => "bmdMode4K2160p2398",
+        M::UHD4K2160p24 => "bmdMode4K2160p24",
+        M::UHD4K2160p25 => "bmdMode4K2160p25",
+        M::UHD4K2160p2997 => "bmdMode4K2160p2997",
+        M::UHD4K2160p30 => "bmdMode4K2160p30",
+        M::UHD4K2160p4795 => "bmdMode4K2160p4795",
+        M::UHD4K2160p48 => "bmdMode4K2160p48",
+        M::UHD4K2160p50 => "bmdMode4K2160p50",
+        M::UHD4K2160p5994 => "bmdMode4K2160p5994",
+        M::UHD4K2160p60 => "bmdMode4K2160p60",
+        M::UHD4K2160p9590 => "bmdMode4K2160p9590",
+        M::UHD4K2160p96 => "bmdMode4K2160p96",
+        M::UHD4K2160p100 => "bmdMode4K2160p100",
+        M::UHD4K2160p11988 => "bmdMode4K2160p11988",
+        M::UHD4K2160p120 => "bmdMode4K2160p120",
+        M::UHD4KDCI2398 => "bmdMode4kDCI2398",
+        M::UHD4KDCI24 => "bmdMode4kDCI24",
+        M::UHD4KDCI25 => "bmdMode4kDCI25",
+        M::UHD4KDCI2997 => "bmdMode4kDCI2997",
+        M::UHD4KDCI30 => "bmdMode4kDCI30",
+        M::UHD4KDCI4795 => "bmdMode4kDCI4795",
+        M::UHD4KDCI48 => "bmdMode4kDCI48",
+        M::UHD4KDCI50 => "bmdMode4kDCI50",
+        M::UHD4KDCI5994 => "bmdMode4kDCI5994",
+        M::UHD4KDCI60 => "bmdMode4kDCI60",
+        M::UHD4KDCI9590 => "bmdMode4kDCI9590",
+        M::UHD4KDCI96 => "bmdMode4kDCI96",
+        M::UHD4KDCI100 => "bmdMode4kDCI100",
+        M::UHD4KDCI11988 => "bmdMode4kDCI11988",
+        M::UHD4KDCI120 => "bmdMode4kDCI120",
+        M::UHD8K4320p2398 => "bmdMode8K4320p2398",
+        M::UHD8K4320p24 => "bmdMode8K4320p24",
+        M::UHD8K4320p25 => "bmdMode8K4320p25",
+        M::UHD8K4320p2997 => "bmdMode8K4320p2997",
+        M::UHD8K4320p30 => "bmdMode8K4320p30",
+        M::UHD8K4320p4795 => "bmdMode8K4320p4795",
+        M::UHD8K4320p48 => "bmdMode8K4320p48",
+        M::UHD8K4320p50 => "bmdMode8K4320p50",
+        M::UHD8K4320p5994 => "bmdMode8K4320p5994",
+        M::UHD8K4320p60 => "bmdMode8K4320p60",
+        M::UHD8KDCI2398 => "bmdMode8kDCI2398",
+        M::UHD8KDCI24 => "bmdMode8kDCI24",
+        M::UHD8KDCI25 => "bmdMode8kDCI25",
+        M::UHD8KDCI2997 => "bmdMode8kDCI2997",
+        M::UHD8KDCI30 => "bmdMode8kDCI30",
+        M::UHD8KDCI4795 => "bmdMode8kDCI4795",
+        M::UHD8KDCI48 => "bmdMode8kDCI48",
+        M::UHD8KDCI50 => "bmdMode8kDCI50",
+        M::UHD8KDCI5994 => "bmdMode8kDCI5994",
+        M::UHD8KDCI60 => "bmdMode8kDCI60",
+        M::PC640x480p60 => "bmdMode640x480p60",
+        M::PC800x600p60 => "bmdMode800x600p60",
+        M::PC1440x900p50 => "bmdMode1440x900p50",
+        M::PC1440x900p60 => "bmdMode1440x900p60",
+        M::PC1440x1080p50 => "bmdMode1440x1080p50",
+        M::PC1440x1080p60 => "bmdMode1440x1080p60",
+        M::PC1600x1200p50 => "bmdMode1600x1200p50",
+        M::PC1600x1200p60 => "bmdMode1600x1200p60",
+        M::PC1920x1200p50 => "bmdMode1920x1200p50",
+        M::PC1920x1200p60 => "bmdMode1920x1200p60",
+        M::PC1920x1440p50 => "bmdMode1920x1440p50",
+        M::PC1920x1440p60 => "bmdMode1920x1440p60",
+        M::PC2560x1440p50 => "bmdMode2560x1440p50",
+        M::PC2560x1440p60 => "bmdMode2560x1440p60",
+        M::PC2560x1600p50 => "bmdMode2560x1600p50",
+        M::PC2560x1600p60 => "bmdMode2560x1600p60",
+        M::Unknown => "bmdModeUnknown",
+    }
+}
+
+fn status_id_constant(id: DecklinkStatusId) -> &'static str {
+    use DecklinkStatusId as S;
+    match id {
+        S::DetectedVideoInputMode => "bmdDeckLinkStatusDetectedVideoInputMode",
+        S::DetectedVideoInputFlags => "bmdDeckLinkStatusDetectedVideoInputFormatFlags",
+        S::CurrentVideoInputMode => "bmdDeckLinkStatusCurrentVideoInputMode",
+        S::CurrentVideoInputPixelFormat => "bmdDeckLinkStatusCurrentVideoInputPixelFormat",
+        S::CurrentVideoInputFlags => "bmdDeckLinkStatusCurrentVideoInputFlags",
+        S::CurrentVideoOutputMode => "bmdDeckLinkStatusCurrentVideoOutputMode",
+        S::CurrentVideoOutputFlags => "bmdDeckLinkStatusCurrentVideoOutputFlags",
+        S::PCIExpressLinkWidth => "bmdDeckLinkStatusPCIExpressLinkWidth",
+        S::PCIExpressLinkSpeed => "bmdDeckLinkStatusPCIExpressLinkSpeed",
+        S::LastVideoOutputPixelFormat => "bmdDeckLinkStatusLastVideoOutputPixelFormat",
+        S::ReferenceSignalMode => "bmdDeckLinkStatusReferenceSignalMode",
+        S::ReferenceSignalFlags => "bmdDeckLinkStatusReferenceSignalFlags",
+        S::Busy => "bmdDeckLinkStatusBusy",
+        S::InterchangeablePanelType => "bmdDeckLinkStatusInterchangeablePanelType",
+        S::VideoInputSignalLocked => "bmdDeckLinkStatusVideoInputSignalLocked",
+        S::ReferenceSignalLocked => "bmdDeckLinkStatusReferenceSignalLocked",
+        S::ReceivedEDID => "bmdDeckLinkStatusReceivedEDID",
+        S::DeviceTemperature => "bmdDeckLinkStatusDeviceTemperature",
+    }
+}
+
+#[test]
+fn every_variant_has_metadata() {
+    let all = reference::all();
+    assert_eq!(all.pixel_formats.len(), DecklinkPixelFormat::iter().count());
+    for (format, entry) in DecklinkPixelFormat::iter().zip(all.pixel_formats) {
+        assert_eq!(entry.format, format);
+        assert_eq!(
+            format.metadata().sdk_constant,
+            pixel_format_constant(format)
+        );
+    }
+    assert_eq!(
+        all.display_modes.len(),
+        DecklinkDisplayModeId::iter().count()
+    );
+    for (mode, entry) in DecklinkDisplayModeId::iter().zip(all.display_modes) {
+        assert_eq!(entry.mode, mode);
+        assert_eq!(mode.metadata().sdk_constant, display_mode_constant(mode));
+    }
+    assert_eq!(all.status_ids.len(), DecklinkStatusId::iter().count());
+    for (id, entry) in DecklinkStatusId::iter().zip(all.status_ids) {
+        assert_eq!(entry.id, id);
+        assert_eq!(id.metadata().sdk_constant, status_id_constant(id));
+    }
+}
+
+#[test]
+fn bytes_per_row_follows_the_table() {
+    for format in DecklinkPixelFormat::iter() {
+        let packing = format.metadata().packing;
+        assert_eq!(packing.is_none(), format.bytes_per_row(1920).is_none());
+        if let Some(packing) = packing {
+            for width in [1, packing.pixels, packing.pixels + 1, 1920] {
+                assert_eq!(format.bytes_per_row(width), Some(packing.row_bytes(width)));
+            }
+        }
+    }
+}
+
+#[test]
+fn modes_agree_with_their_aspect_and_rate() {
+    for entry in reference::all().display_modes {
+        let Some(aspect) = entry.aspect else {
+            assert_eq!(entry.mode, DecklinkDisplayModeId::Unknown);
+            continue;
+        };
+        assert_eq!(entry.mode.pixel_aspect_ratio(false), Some(aspect.pixel));
+        assert_eq!(
+            entry.mode.display_aspect_ratio(true),
+            Some(aspect.widescreen_display)
+        );
+        assert!(entry.frame_duration == 1000 || entry.frame_duration == 1001);
+        assert!(entry
+            .description
+            .starts_with(&format!("{}x{} ", entry.width, entry.height)));
+    }
+}
+
+/// The tables are rendered into the module's documentation from `src/reference`. Run with
+/// `UPDATE_REFERENCE=1` to update them after changing a table.
+#[test]
+fn the_documented_tables_are_current() {
+    let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/src/reference");
+    for table in ReferenceTable::ALL {
+        let path = format!("{}/{}", dir, table.file_name());
+        let markdown = table.markdown();
+        if std::env::var_os("UPDATE_REFERENCE").is_some() {
+            std::fs::write(&path, markdown).unwrap();
+        } else {
+            assert_eq!(
+                std::fs::read_to_string(&path).unwrap(),
+                markdown,
+                "run with UPDATE_REFERENCE=1 to update {}",
+                table.file_name()
+            );
+        }
+    }
+}
+
+#[test]
+fn the_json_is_what_serde_gives() {
+    let json: serde_json::Value = serde_json::from_str(&reference::all().to_json()).unwrap();
+    assert_eq!(json["pixel_formats"][1]["fourcc"], "v210");
+    assert_eq!(
+        json["display_modes"][0]["aspect"]["widescreen_pixel"]["num"],
+        40
+    );
+    assert_eq!(json["status_ids"][16]["kind"], "Bytes");
+    #[cfg(feature = "serde")]
+    assert_eq!(json, serde_json::to_value(reference::all()).unwrap());
+}
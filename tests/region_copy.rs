@@ -0,0 +1,237 @@
+//! Copying regions of synthetic frames with `copy_region_into` and `copy_region_to_vec`.
+
+use decklink::frame::{
+    AlignmentError, DecklinkFrameBase, DecklinkFrameFlags, DecklinkPixelFormat,
+    DecklinkVideoMutableFrame, Rect, RegionCopyError,
+};
+use decklink::SdkError;
+
+/// A frame whose every byte holds its row in the high nibble and its offset in the row in the
+/// low bits, so that a copied byte tells where it came from.
+fn frame(
+    width: usize,
+    height: usize,
+    row_bytes: usize,
+    pixel_format: DecklinkPixelFormat,
+) -> DecklinkVideoMutableFrame {
+    let mut frame = DecklinkVideoMutableFrame::create(
+        width,
+        height,
+        row_bytes,
+        pixel_format,
+        DecklinkFrameFlags::empty(),
+    );
+    let bytes: Vec<u8> = (0..height)
+        .flat_map(|row| (0..row_bytes).map(move |i| byte(row, i)))
+        .collect();
+    frame.copy_bytes(&bytes).unwrap();
+    frame
+}
+
+fn byte(row: usize, offset: usize) -> u8 {
+    ((row << 4) as u8) ^ (offset as u8)
+}
+
+/// The bytes `offset..offset + len` of the rows `rows` of a frame from `frame`.
+fn expected(rows: std::ops::Range<usize>, offset: usize, len: usize) -> Vec<u8> {
+    rows.flat_map(|row| (offset..offset + len).map(move |i| byte(row, i)))
+        .collect()
+}
+
+fn rect(x: usize, y: usize, width: usize, height: usize) -> Rect {
+    Rect {
+        x,
+        y,
+        width,
+        height,
+    }
+}
+
+#[test]
+fn interior_region_is_copied_exactly() {
+    let frame = frame(8, 6, 32, DecklinkPixelFormat::Format8BitBGRA);
+    let copy = frame.copy_region_to_vec(rect(2, 1, 3, 4)).unwrap();
+    assert_eq!(copy, expected(1..5, 8, 12));
+}
+
+#[test]
+fn regions_at_the_edges_are_copied_exactly() {
+    let frame = frame(8, 6, 32, DecklinkPixelFormat::Format8BitARGB);
+
+    assert_eq!(
+        frame.copy_region_to_vec(rect(0, 0, 2, 2)).unwrap(),
+        expected(0..2, 0, 8)
+    );
+    assert_eq!(
+        frame.copy_region_to_vec(rect(6, 4, 2, 2)).unwrap(),
+        expected(4..6, 24, 8)
+    );
+    assert_eq!(
+        frame.copy_region_to_vec(rect(0, 0, 8, 6)).unwrap(),
+        frame.bytes().unwrap().0
+    );
+    assert!(frame
+        .copy_region_to_vec(rect(8, 6, 0, 0))
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn source_stride_is_honoured() {
+    // Rows are padded to 48 bytes past the 32 bytes of pixels
+    let frame = frame(8, 4, 48, DecklinkPixelFormat::Format10BitRGB);
+    assert_eq!(
+        frame.copy_region_to_vec(rect(1, 1, 7, 3)).unwrap(),
+        expected(1..4, 4, 28)
+    );
+}
+
+#[test]
+fn destination_stride_is_honoured() {
+    let frame = frame(8, 4, 32, DecklinkPixelFormat::Format8BitBGRA);
+    let mut dst = vec![0xEE; 2 * 16 + 8];
+    frame
+        .copy_region_into(rect(4, 2, 2, 2), &mut dst, 16)
+        .unwrap();
+
+    assert_eq!(dst[..8], expected(2..3, 16, 8)[..]);
+    assert_eq!(dst[8..16], [0xEE; 8]);
+    assert_eq!(dst[16..24], expected(3..4, 16, 8)[..]);
+    assert_eq!(dst[24..], [0xEE; 16]);
+}
+
+#[test]
+fn uyvy_region_copies_pairs_of_pixels() {
+    // 16 UYVY pixels are 32 bytes, 4 bytes for each pair
+    let frame = frame(16, 4, 32, DecklinkPixelFormat::Format8BitYUV);
+    assert_eq!(
+        frame.copy_region_to_vec(rect(4, 1, 6, 2)).unwrap(),
+        expected(1..3, 8, 12)
+    );
+}
+
+#[test]
+fn uyvy_needs_even_x_and_width() {
+    let frame = frame(16, 4, 32, DecklinkPixelFormat::Format8BitYUV);
+    let error = AlignmentError {
+        pixel_format: DecklinkPixelFormat::Format8BitYUV,
+        required_pixels: 2,
+    };
+
+    for rect in [rect(1, 0, 2, 1), rect(2, 0, 3, 1)] {
+        match frame.copy_region_to_vec(rect) {
+            Err(RegionCopyError::Alignment(e)) => assert_eq!(e, error),
+            other => panic!("{:?} is not an alignment error", other),
+        }
+    }
+}
+
+#[test]
+fn v210_region_copies_groups_of_48_pixels() {
+    // 96 v210 pixels are two groups of 128 bytes
+    let frame = frame(96, 3, 256, DecklinkPixelFormat::Format10BitYUV);
+    assert_eq!(
+        frame.copy_region_to_vec(rect(48, 1, 48, 2)).unwrap(),
+        expected(1..3, 128, 128)
+    );
+}
+
+#[test]
+fn v210_needs_48_pixel_alignment() {
+    let frame = frame(96, 3, 256, DecklinkPixelFormat::Format10BitYUV);
+    let error = AlignmentError {
+        pixel_format: DecklinkPixelFormat::Format10BitYUV,
+        required_pixels: 48,
+    };
+
+    for rect in [rect(6, 0, 48, 1), rect(0, 0, 42, 1), rect(2, 0, 2, 1)] {
+        match frame.copy_region_to_vec(rect) {
+            Err(RegionCopyError::Alignment(e)) => assert_eq!(e, error),
+            other => panic!("{:?} is not an alignment error", other),
+        }
+    }
+}
+
+#[test]
+fn rgb_12_bit_needs_groups_of_8_pixels() {
+    let frame = frame(16, 2, 72, DecklinkPixelFormat::Format12BitRGB);
+    assert_eq!(
+        frame.copy_region_to_vec(rect(8, 0, 8, 2)).unwrap(),
+        expected(0..2, 36, 36)
+    );
+    assert!(matches!(
+        frame.copy_region_to_vec(rect(4, 0, 8, 1)),
+        Err(RegionCopyError::Alignment(AlignmentError {
+            pixel_format: DecklinkPixelFormat::Format12BitRGB,
+            required_pixels: 8,
+        }))
+    ));
+}
+
+#[test]
+fn region_past_the_edge_is_out_of_bounds() {
+    let frame = frame(8, 6, 32, DecklinkPixelFormat::Format8BitBGRA);
+    for rect in [rect(6, 0, 3, 1), rect(0, 5, 1, 2), rect(9, 0, 0, 0)] {
+        assert!(matches!(
+            frame.copy_region_to_vec(rect),
+            Err(RegionCopyError::OutOfBounds)
+        ));
+    }
+}
+
+#[test]
+fn small_destination_is_rejected() {
+    let frame = frame(8, 6, 32, DecklinkPixelFormat::Format8BitBGRA);
+
+    // A stride shorter than a row of the region
+    let mut dst = vec![0; 64];
+    assert!(matches!(
+        frame.copy_region_into(rect(0, 0, 4, 2), &mut dst, 8),
+        Err(RegionCopyError::DestinationTooSmall)
+    ));
+    // The last row does not fit, although it needs no padding after it
+    let mut dst = vec![0; 16 + 15];
+    assert!(matches!(
+        frame.copy_region_into(rect(0, 0, 4, 2), &mut dst, 16),
+        Err(RegionCopyError::DestinationTooSmall)
+    ));
+    let mut dst = vec![0; 16 + 16];
+    frame
+        .copy_region_into(rect(0, 0, 4, 2), &mut dst, 16)
+        .unwrap();
+}
+
+#[test]
+fn compressed_and_empty_frames_are_not_copied() {
+    let frame = frame(8, 2, 32, DecklinkPixelFormat::FormatH265);
+    assert!(matches!(
+        frame.copy_region_to_vec(rect(0, 0, 1, 1)),
+        Err(RegionCopyError::Sdk(SdkError::NOTIMPL))
+    ));
+
+    let empty = DecklinkVideoMutableFrame::create(
+        8,
+        2,
+        32,
+        DecklinkPixelFormat::Format8BitBGRA,
+        DecklinkFrameFlags::empty(),
+    );
+    assert!(matches!(
+        empty.copy_region_to_vec(rect(0, 0, 1, 1)),
+        Err(RegionCopyError::Sdk(SdkError::FALSE))
+    ));
+}
+
+#[test]
+fn regions_past_the_end_of_short_rows_are_out_of_bounds() {
+    // A frame that reports 8 pixels a row, but rows of only 4
+    let frame = frame(8, 4, 16, DecklinkPixelFormat::Format8BitBGRA);
+    assert!(matches!(
+        frame.copy_region_to_vec(rect(4, 0, 4, 1)),
+        Err(RegionCopyError::OutOfBounds)
+    ));
+    assert_eq!(
+        frame.copy_region_to_vec(rect(0, 0, 4, 4)).unwrap(),
+        expected(0..4, 0, 16)
+    );
+}
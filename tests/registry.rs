@@ -0,0 +1,240 @@
+//! Following devices as they arrive and are removed, with one handle per device.
+
+use decklink::device::registry::{DeviceInfo, DeviceKey, DeviceRegistry, DeviceRemoved};
+use decklink::device::DecklinkDevice;
+use decklink::testing::MockDeviceSource;
+use decklink::SdkError;
+use std::sync::Arc;
+
+fn device(persistent_id: Option<i64>, name: &str) -> DeviceInfo {
+    DeviceInfo {
+        persistent_id,
+        display_name: Some(name.to_string()),
+        model_name: Some("DeckLink Duo 2".to_string()),
+        device_handle: Some(format!("{}:{}", name, persistent_id.unwrap_or_default())),
+        ..DeviceInfo::default()
+    }
+}
+
+#[test]
+fn refresh_reports_added_and_removed() {
+    let source = MockDeviceSource::new();
+    source.add(device(Some(1), "DeckLink Duo (1)"));
+    source.add(device(Some(2), "DeckLink Duo (2)"));
+    let mut registry = DeviceRegistry::with_source(source.clone()).unwrap();
+    assert_eq!(registry.len(), 2);
+
+    source.remove(|info| info.persistent_id == Some(1));
+    source.add(device(Some(3), "DeckLink Duo (3)"));
+    let delta = registry.refresh().unwrap();
+    assert!(delta.enumerated);
+    let names = |devices: &[Arc<DecklinkDevice>]| {
+        devices
+            .iter()
+            .map(|d| d.display_name().unwrap())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(names(&delta.added), ["DeckLink Duo (3)"]);
+    assert_eq!(names(&delta.removed), ["DeckLink Duo (1)"]);
+    assert_eq!(names(&delta.unchanged), ["DeckLink Duo (2)"]);
+    assert_eq!(
+        registry
+            .entries()
+            .map(|(key, ..)| key.clone())
+            .collect::<Vec<_>>(),
+        [DeviceKey::PersistentId(2), DeviceKey::PersistentId(3)]
+    );
+
+    let delta = registry.refresh().unwrap();
+    assert!(delta.is_empty());
+    assert_eq!(delta.unchanged.len(), 2);
+}
+
+#[test]
+fn handles_are_kept_across_refreshes() {
+    let source = MockDeviceSource::new();
+    source.add(device(Some(1), "DeckLink Duo (1)"));
+    let mut registry = DeviceRegistry::with_source(source.clone()).unwrap();
+    let first = registry.get(&DeviceKey::PersistentId(1)).unwrap();
+
+    for _ in 0..3 {
+        source.add(device(Some(9), "DeckLink Mini Recorder"));
+        registry.refresh().unwrap();
+        source.remove(|info| info.persistent_id == Some(9));
+        registry.refresh().unwrap();
+    }
+    let again = registry.get(&DeviceKey::PersistentId(1)).unwrap();
+    assert!(Arc::ptr_eq(&first, &again));
+    assert!(Arc::ptr_eq(
+        &first,
+        &registry.find_by_handle("DeckLink Duo (1):1").unwrap()
+    ));
+    assert_eq!(registry.key_of(&first), Some(&DeviceKey::PersistentId(1)));
+    assert_eq!(
+        registry.info(&first).unwrap().model_name.as_deref(),
+        Some("DeckLink Duo 2")
+    );
+}
+
+#[test]
+fn removed_handles_are_dead() {
+    let source = MockDeviceSource::new();
+    source.add(device(Some(1), "DeckLink Duo (1)"));
+    let mut registry = DeviceRegistry::with_source(source.clone()).unwrap();
+    let held = registry.devices().remove(0);
+    assert_eq!(held.check_present(), Ok(()));
+
+    source.remove(|_| true);
+    let delta = registry.refresh().unwrap();
+    assert!(Arc::ptr_eq(&delta.removed[0], &held));
+    assert!(held.is_removed());
+    assert_eq!(
+        held.check_present(),
+        Err(DeviceRemoved {
+            persistent_id: Some(1),
+            display_name: Some("DeckLink Duo (1)".to_string()),
+        })
+    );
+    assert_eq!(
+        held.check_present().unwrap_err().to_string(),
+        "device \"DeckLink Duo (1)\" has been removed"
+    );
+    assert_eq!(held.get_attributes().err(), Some(SdkError::HANDLE));
+    assert!(held.input().is_none());
+    // The cached identity is still readable
+    assert_eq!(held.persistent_id(), Some(1));
+    assert!(registry.get(&DeviceKey::PersistentId(1)).is_none());
+
+    // A device that comes back is a new handle, and the old one stays removed
+    source.add(device(Some(1), "DeckLink Duo (1)"));
+    let delta = registry.refresh().unwrap();
+    assert!(!Arc::ptr_eq(&delta.added[0], &held));
+    assert!(!delta.added[0].is_removed());
+    assert!(held.is_removed());
+}
+
+#[test]
+fn event_driven_refresh_skips_enumeration() {
+    let source = MockDeviceSource::new().event_driven();
+    source.add(device(Some(1), "DeckLink Duo (1)"));
+    let mut registry = DeviceRegistry::with_source(source.clone()).unwrap();
+    assert!(registry.is_event_driven());
+
+    for _ in 0..10 {
+        let delta = registry.refresh().unwrap();
+        assert!(!delta.enumerated);
+        assert_eq!(delta.unchanged.len(), 1);
+    }
+    assert_eq!(source.enumerations(), 1);
+
+    source.add(device(Some(2), "DeckLink Duo (2)"));
+    assert_eq!(registry.refresh().unwrap().added.len(), 1);
+    assert_eq!(source.enumerations(), 2);
+    assert_eq!(registry.enumerations(), 2);
+
+    // A forced refresh enumerates without a notification
+    assert!(registry.force_refresh().unwrap().enumerated);
+    assert_eq!(source.enumerations(), 3);
+}
+
+#[test]
+fn keys_fall_back_to_topological_id_then_name() {
+    let source = MockDeviceSource::new();
+    source.add(DeviceInfo {
+        topological_id: Some(0x200),
+        ..device(None, "DeckLink Quad (1)")
+    });
+    source.add(device(None, "Intensity Pro"));
+    source.add(device(None, "Intensity Pro"));
+    let registry = DeviceRegistry::with_source(source).unwrap();
+
+    let keys: Vec<DeviceKey> = registry.entries().map(|(key, ..)| key.clone()).collect();
+    assert_eq!(
+        keys,
+        [
+            DeviceKey::TopologicalId(0x200),
+            DeviceKey::Name {
+                name: "Intensity Pro".to_string(),
+                index: 0
+            },
+            DeviceKey::Name {
+                name: "Intensity Pro".to_string(),
+                index: 1
+            },
+        ]
+    );
+    assert_eq!(keys[0].to_string(), "topological:0x200");
+    assert_eq!(keys[2].to_string(), "name:Intensity Pro #2");
+}
+
+#[cfg(feature = "mock-backend")]
+mod mock {
+    use super::*;
+    use decklink::mock::{MockBackend, MockDevice};
+
+    #[test]
+    fn devices_of_the_driver_are_followed_as_they_are_detached() {
+        let backend = MockBackend::install(vec![
+            MockDevice::new("DeckLink Duo (1)").sub_device(7, 1),
+            MockDevice::new("DeckLink Duo (2)").sub_device(7, 2),
+            MockDevice::new("DeckLink Mini Recorder"),
+        ]);
+        let mut registry = DeviceRegistry::new().unwrap();
+        assert!(!registry.is_event_driven());
+        assert_eq!(
+            registry
+                .entries()
+                .map(|(key, ..)| key.clone())
+                .collect::<Vec<_>>(),
+            [
+                DeviceKey::PersistentId(1),
+                DeviceKey::PersistentId(2),
+                DeviceKey::Name {
+                    name: "DeckLink Mini Recorder".to_string(),
+                    index: 0,
+                },
+            ]
+        );
+        let held = registry.devices();
+
+        // Without discovery notifications each refresh enumerates, and keeps the handles
+        let delta = registry.refresh().unwrap();
+        assert!(delta.enumerated && delta.is_empty());
+        for (unchanged, held) in delta.unchanged.iter().zip(&held) {
+            assert!(Arc::ptr_eq(unchanged, held));
+        }
+
+        backend.detach(1);
+        let delta = registry.refresh().unwrap();
+        assert!(delta.added.is_empty());
+        assert!(Arc::ptr_eq(&delta.removed[0], &held[1]));
+        assert_eq!(delta.unchanged.len(), 2);
+        assert!(Arc::ptr_eq(&delta.unchanged[0], &held[0]));
+        assert!(Arc::ptr_eq(&delta.unchanged[1], &held[2]));
+
+        // The removed handle no longer reaches the driver
+        let removed = &held[1];
+        assert_eq!(
+            removed.check_present(),
+            Err(DeviceRemoved {
+                persistent_id: Some(2),
+                display_name: Some("DeckLink Duo (2)".to_string()),
+            })
+        );
+        assert_eq!(removed.get_attributes().err(), Some(SdkError::HANDLE));
+        assert!(removed.input().is_none() && removed.output().is_none());
+        assert!(held[0].input().is_some());
+
+        // Attached again, it is a new handle
+        backend.attach(1);
+        let delta = registry.refresh().unwrap();
+        assert!(delta.removed.is_empty());
+        assert!(!Arc::ptr_eq(&delta.added[0], removed));
+        assert_eq!(delta.added[0].display_name().unwrap(), "DeckLink Duo (2)");
+        assert!(!delta.added[0].is_removed() && removed.is_removed());
+        assert!(Arc::ptr_eq(
+            &registry.get(&DeviceKey::PersistentId(1)).unwrap(),
+            &held[0]
+        ));
+    }
+}
@@ -0,0 +1,517 @@
+//! Recording input callbacks to the session file format, and replaying them.
+
+#[cfg(feature = "mock-backend")]
+mod common;
+
+use decklink::device::input::{
+    DecklinkAudioSampleType, DecklinkDetectedVideoInputFormatFlags,
+    DecklinkVideoInputFormatChangedEvents,
+};
+use decklink::display_mode::DecklinkDisplayModeId;
+use decklink::frame::{DecklinkFrameFlags, DecklinkPixelFormat};
+use decklink::replay::{
+    replay, RecordedEvent, RecordedFrame, ReplayPace, SessionEvent, SessionReader, SessionWriter,
+    SESSION_FORMAT_VERSION,
+};
+use decklink::time::{DecklinkFrameTiming, DecklinkTime};
+use std::io;
+use std::time::{Duration, Instant};
+
+const MODE: DecklinkDisplayModeId = DecklinkDisplayModeId::HD1080p25;
+const FORMAT: DecklinkPixelFormat = DecklinkPixelFormat::Format8BitYUV;
+
+fn timing(n: i64) -> DecklinkFrameTiming {
+    DecklinkFrameTiming {
+        stream_time: DecklinkTime::new(n * 1000, 25000),
+        duration: DecklinkTime::new(1000, 25000),
+        mode_duration: DecklinkTime::new(1000, 25000),
+    }
+}
+
+fn frame_event(n: i64, flags: DecklinkFrameFlags) -> SessionEvent {
+    SessionEvent::Frame {
+        frame: Some(RecordedFrame {
+            width: 48,
+            height: 2,
+            row_bytes: 96,
+            pixel_format: FORMAT,
+            flags,
+        }),
+        timing: Some(timing(n)),
+    }
+}
+
+fn format_event(mode: DecklinkDisplayModeId) -> SessionEvent {
+    SessionEvent::FormatChanged {
+        events: DecklinkVideoInputFormatChangedEvents::DISPLAY_MODE_CHANGED,
+        display_mode: mode,
+        detected_flags: DecklinkDetectedVideoInputFormatFlags::YCBCR_422
+            | DecklinkDetectedVideoInputFormatFlags::BIT_DEPTH_8,
+    }
+}
+
+fn audio_event(sample_frame_count: usize) -> SessionEvent {
+    SessionEvent::AudioPacket {
+        sample_type: DecklinkAudioSampleType::Int16,
+        channel_count: 2,
+        sample_frame_count,
+    }
+}
+
+/// Events `delta_ms` apart.
+fn recorded(events: Vec<SessionEvent>, delta_ms: u64) -> Vec<RecordedEvent> {
+    events
+        .into_iter()
+        .map(|event| RecordedEvent {
+            delta: Duration::from_millis(delta_ms),
+            event,
+        })
+        .collect()
+}
+
+fn write(events: &[RecordedEvent]) -> Vec<u8> {
+    let mut writer = SessionWriter::new(Vec::new()).unwrap();
+    for event in events {
+        writer.write_event(event).unwrap();
+    }
+    writer.into_inner()
+}
+
+fn read(bytes: &[u8]) -> Vec<RecordedEvent> {
+    SessionReader::new(bytes)
+        .unwrap()
+        .collect::<io::Result<_>>()
+        .unwrap()
+}
+
+/// Every kind of event, as a capture with a flapping format and a frame without a signal.
+fn session() -> Vec<RecordedEvent> {
+    recorded(
+        vec![
+            format_event(DecklinkDisplayModeId::HD1080p5994),
+            format_event(MODE),
+            frame_event(0, DecklinkFrameFlags::empty()),
+            audio_event(1920),
+            frame_event(1, DecklinkFrameFlags::HAS_NO_INPUT_SOURCE),
+            audio_event(1920),
+            SessionEvent::Frame {
+                frame: None,
+                timing: None,
+            },
+        ],
+        4,
+    )
+}
+
+#[test]
+fn events_are_read_back_as_written() {
+    let events = session();
+    let bytes = write(&events);
+    assert_eq!(&bytes[..4], b"DLSR");
+    assert_eq!(
+        u16::from_le_bytes([bytes[4], bytes[5]]),
+        SESSION_FORMAT_VERSION
+    );
+
+    let reader = SessionReader::new(&bytes[..]).unwrap();
+    assert_eq!(reader.version(), SESSION_FORMAT_VERSION);
+    let read_back = read(&bytes);
+    assert_eq!(read_back, events);
+
+    // Writing what was read gives the same file
+    assert_eq!(write(&read_back), bytes);
+}
+
+#[test]
+fn foreign_and_newer_files_are_rejected() {
+    let error = |bytes: &[u8]| SessionReader::new(bytes).err().unwrap().kind();
+
+    assert_eq!(error(b"RIFF\x01\x00"), io::ErrorKind::InvalidData);
+    let newer = (SESSION_FORMAT_VERSION + 1).to_le_bytes();
+    assert_eq!(
+        error(&[b'D', b'L', b'S', b'R', newer[0], newer[1]]),
+        io::ErrorKind::InvalidData
+    );
+    assert_eq!(error(b"DLS"), io::ErrorKind::UnexpectedEof);
+}
+
+#[test]
+fn damaged_events_are_errors() {
+    let mut bytes = write(&session()[..1]);
+    bytes[6] = 9;
+    let mut reader = SessionReader::new(&bytes[..]).unwrap();
+    assert_eq!(
+        reader.next_event().unwrap_err().kind(),
+        io::ErrorKind::InvalidData
+    );
+
+    // A file cut short in the middle of an event
+    let bytes = write(&session());
+    let events: Vec<io::Result<RecordedEvent>> = SessionReader::new(&bytes[..bytes.len() - 1])
+        .unwrap()
+        .collect();
+    assert_eq!(events.len(), session().len());
+    assert_eq!(
+        events.last().unwrap().as_ref().unwrap_err().kind(),
+        io::ErrorKind::UnexpectedEof
+    );
+}
+
+#[test]
+fn replay_keeps_the_original_pace() {
+    let bytes = write(&recorded(
+        vec![frame_event(0, DecklinkFrameFlags::empty()); 5],
+        20,
+    ));
+
+    let start = Instant::now();
+    let mut seen = Vec::new();
+    replay(
+        SessionReader::new(&bytes[..]).unwrap(),
+        ReplayPace::OriginalSpeed,
+        |event| seen.push(start.elapsed().saturating_sub(event.delta)),
+    )
+    .unwrap();
+    assert_eq!(seen.len(), 5);
+    assert!(start.elapsed() >= Duration::from_millis(100));
+    for (i, at) in seen.iter().enumerate() {
+        assert!(*at >= Duration::from_millis(20 * i as u64));
+    }
+
+    let start = Instant::now();
+    let mut count = 0;
+    replay(
+        SessionReader::new(&bytes[..]).unwrap(),
+        ReplayPace::AsFastAsPossible,
+        |_| count += 1,
+    )
+    .unwrap();
+    assert_eq!(count, 5);
+    assert!(start.elapsed() < Duration::from_millis(100));
+}
+
+#[cfg(feature = "mock-backend")]
+mod mock {
+    use super::common;
+    use super::{audio_event, format_event, frame_event, read, recorded, write, FORMAT, MODE};
+    use decklink::conformance::{ConformanceChecker, ConformanceWarning, EnabledConfig};
+    use decklink::device::get_devices;
+    use decklink::device::input::{
+        DeckLinkInputCallback, DecklinkDetectedVideoInputFormatFlags, DecklinkInputDevice,
+        DecklinkVideoInputFlags, DecklinkVideoInputFormatChangedEvents, InputHandler,
+    };
+    use decklink::device::DecklinkDeviceDisplayModes;
+    use decklink::display_mode::DecklinkDisplayModeId;
+    use decklink::frame::{DecklinkFrameFlags, DecklinkVideoFrame};
+    use decklink::mock::{MockBackend, MockDevice, MockFrame, MockInput};
+    use decklink::replay::{
+        replay_to_mock, RecordedEvent, ReplayPace, SessionEvent, SessionReader, SessionRecorder,
+    };
+    use std::io::{self, Write};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+
+    /// A file the test can read while a recorder writes to it.
+    #[derive(Clone, Default)]
+    struct File(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for File {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Counts the frame callbacks forwarded by a recorder.
+    #[derive(Default)]
+    struct Counter {
+        frames: AtomicUsize,
+    }
+
+    impl DeckLinkInputCallback for Counter {
+        fn video_input_format_changed(
+            &self,
+            _events: DecklinkVideoInputFormatChangedEvents,
+            _new_display_mode: DecklinkDisplayModeId,
+            _detected_signal_flags: DecklinkDetectedVideoInputFormatFlags,
+        ) {
+        }
+
+        fn video_input_frame_arrived(&self, _video_frame: Option<DecklinkVideoFrame>) -> bool {
+            self.frames.fetch_add(1, Ordering::SeqCst);
+            true
+        }
+    }
+
+    /// Capture from input `index` in `MODE` with 2 channels of 16-bit audio, into `callback`.
+    fn start(
+        backend: &MockBackend,
+        index: usize,
+        callback: Arc<dyn InputHandler>,
+    ) -> (DecklinkInputDevice, MockInput) {
+        let devices = get_devices().unwrap();
+        let mut input = devices[index].input().unwrap();
+        input
+            .enable_video_input(
+                MODE,
+                FORMAT,
+                DecklinkVideoInputFlags::ENABLE_FORMAT_DETECTION,
+            )
+            .unwrap();
+        common::enable_stereo_audio(&mut input);
+        common::start_streams(&mut input, callback);
+        (input, backend.input(index))
+    }
+
+    fn record(
+        backend: &MockBackend,
+        index: usize,
+    ) -> (DecklinkInputDevice, MockInput, File, Arc<Counter>) {
+        let file = File::default();
+        let counter = Arc::new(Counter::default());
+        let recorder = SessionRecorder::new(file.clone(), Some(counter.clone())).unwrap();
+        let (input, mock) = start(backend, index, Arc::new(recorder));
+        (input, mock, file, counter)
+    }
+
+    fn events(file: &File) -> Vec<SessionEvent> {
+        read(&file.0.lock().unwrap())
+            .into_iter()
+            .map(|e| e.event)
+            .collect()
+    }
+
+    fn two_inputs() -> MockBackend {
+        MockBackend::install(vec![
+            MockDevice::new("DeckLink Duo 2 (1)"),
+            MockDevice::new("DeckLink Duo 2 (2)"),
+        ])
+    }
+
+    #[test]
+    fn recorder_logs_and_forwards_every_callback() {
+        let backend = two_inputs();
+        let (_input, mock, file, counter) = record(&backend, 0);
+
+        // The format flaps, and frames are timed in the mode it settles in
+        for mode in [DecklinkDisplayModeId::HD1080p5994, MODE] {
+            mock.deliver_format_change(
+                DecklinkVideoInputFormatChangedEvents::DISPLAY_MODE_CHANGED,
+                mode,
+                DecklinkDetectedVideoInputFormatFlags::YCBCR_422
+                    | DecklinkDetectedVideoInputFormatFlags::BIT_DEPTH_8,
+            );
+        }
+        let frame = |n: i64| MockFrame::new(48, 2, FORMAT).stream_time(n * 1000, 1000, 25000);
+        mock.deliver_frame(frame(0));
+        mock.deliver_frame_with_audio(frame(1).no_signal(), &[0; 1920 * 4]);
+        mock.deliver_audio(&[0; 1920 * 4]);
+
+        assert_eq!(
+            events(&file),
+            vec![
+                format_event(DecklinkDisplayModeId::HD1080p5994),
+                format_event(MODE),
+                frame_event(0, DecklinkFrameFlags::empty()),
+                audio_event(1920),
+                frame_event(1, DecklinkFrameFlags::HAS_NO_INPUT_SOURCE),
+                audio_event(1920),
+                SessionEvent::Frame {
+                    frame: None,
+                    timing: None,
+                },
+            ]
+        );
+        assert_eq!(counter.frames.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn replaying_a_replay_gives_the_same_metadata() {
+        let backend = two_inputs();
+        let (_first, mock, first, _) = record(&backend, 0);
+        let trace = write(&recorded(
+            super::session().into_iter().map(|e| e.event).collect(),
+            1,
+        ));
+
+        let delivered = replay_to_mock(
+            SessionReader::new(&trace[..]).unwrap(),
+            &mock,
+            ReplayPace::AsFastAsPossible,
+        )
+        .unwrap();
+        // The audio packets are delivered with the frame callbacks after them
+        assert_eq!(delivered, 5);
+        let replayed = events(&first);
+        assert_eq!(
+            replayed,
+            read(&trace)
+                .into_iter()
+                .map(|e| e.event)
+                .collect::<Vec<_>>()
+        );
+
+        // And a recording of that replay replays to the same events again
+        let (_second, mock, second, _) = record(&backend, 1);
+        let bytes = first.0.lock().unwrap().clone();
+        replay_to_mock(
+            SessionReader::new(&bytes[..]).unwrap(),
+            &mock,
+            ReplayPace::AsFastAsPossible,
+        )
+        .unwrap();
+        assert_eq!(events(&second), replayed);
+    }
+
+    #[test]
+    fn replay_to_mock_keeps_the_original_pace() {
+        let backend = two_inputs();
+        let (_input, mock, file, counter) = record(&backend, 0);
+        let trace = write(&recorded(
+            vec![frame_event(0, DecklinkFrameFlags::empty()); 4],
+            25,
+        ));
+
+        let start = Instant::now();
+        replay_to_mock(
+            SessionReader::new(&trace[..]).unwrap(),
+            &mock,
+            ReplayPace::OriginalSpeed,
+        )
+        .unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(100));
+        assert_eq!(counter.frames.load(Ordering::SeqCst), 4);
+
+        // The recorded gaps match the pace of the replay
+        let gaps: Vec<Duration> = read(&file.0.lock().unwrap())
+            .iter()
+            .skip(1)
+            .map(|e| e.delta)
+            .collect();
+        assert!(gaps.iter().all(|gap| *gap >= Duration::from_millis(15)));
+    }
+
+    #[test]
+    fn frame_callback_with_nothing_is_not_replayable() {
+        let backend = two_inputs();
+        let (_input, mock, _, _) = record(&backend, 0);
+        let trace = write(&recorded(
+            vec![SessionEvent::Frame {
+                frame: None,
+                timing: None,
+            }],
+            0,
+        ));
+        let error = replay_to_mock(
+            SessionReader::new(&trace[..]).unwrap(),
+            &mock,
+            ReplayPace::AsFastAsPossible,
+        )
+        .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    /// A source flapping between two modes, with frames in between.
+    fn format_flapping() -> Vec<RecordedEvent> {
+        let mut events = Vec::new();
+        for n in 0..3 {
+            events.push(format_event(DecklinkDisplayModeId::HD1080p5994));
+            events.push(frame_event(2 * n, DecklinkFrameFlags::empty()));
+            events.push(format_event(MODE));
+            events.push(frame_event(2 * n + 1, DecklinkFrameFlags::empty()));
+        }
+        recorded(events, 1)
+    }
+
+    /// Feeds format changes to a conformance checker.
+    struct Checker {
+        checker: Mutex<ConformanceChecker>,
+        warnings: Mutex<Vec<ConformanceWarning>>,
+    }
+
+    impl DeckLinkInputCallback for Checker {
+        fn video_input_format_changed(
+            &self,
+            _events: DecklinkVideoInputFormatChangedEvents,
+            new_display_mode: DecklinkDisplayModeId,
+            detected_signal_flags: DecklinkDetectedVideoInputFormatFlags,
+        ) {
+            let warnings = self
+                .checker
+                .lock()
+                .unwrap()
+                .check_format(new_display_mode, detected_signal_flags);
+            self.warnings.lock().unwrap().extend(warnings);
+        }
+
+        fn video_input_frame_arrived(&self, _video_frame: Option<DecklinkVideoFrame>) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn format_flapping_warns_once_per_flap() {
+        let backend = two_inputs();
+        let devices = get_devices().unwrap();
+        let modes = devices[0].input().unwrap().display_modes().unwrap();
+        let checker = Arc::new(Checker {
+            checker: Mutex::new(ConformanceChecker::new(
+                EnabledConfig {
+                    display_mode: MODE,
+                    pixel_format: FORMAT,
+                },
+                &modes,
+                u64::MAX,
+            )),
+            warnings: Mutex::new(Vec::new()),
+        });
+        let (_input, mock) = start(&backend, 0, checker.clone());
+
+        let trace = write(&format_flapping());
+        replay_to_mock(
+            SessionReader::new(&trace[..]).unwrap(),
+            &mock,
+            ReplayPace::AsFastAsPossible,
+        )
+        .unwrap();
+        assert_eq!(
+            *checker.warnings.lock().unwrap(),
+            vec![
+                ConformanceWarning::ModeMismatch {
+                    enabled: MODE,
+                    detected: DecklinkDisplayModeId::HD1080p5994,
+                };
+                3
+            ]
+        );
+    }
+
+    #[test]
+    fn late_frames_after_stop_stay_suppressed() {
+        let backend = two_inputs();
+        let (input, mock, file, counter) = record(&backend, 0);
+        let trace = write(&recorded(
+            (0..5)
+                .map(|n| frame_event(n, DecklinkFrameFlags::empty()))
+                .collect(),
+            1,
+        ));
+
+        // A driver that keeps calling back after the streams stop
+        input.stop_streams().unwrap();
+        mock.set_late_callbacks(true);
+        let delivered = replay_to_mock(
+            SessionReader::new(&trace[..]).unwrap(),
+            &mock,
+            ReplayPace::AsFastAsPossible,
+        )
+        .unwrap();
+        assert_eq!(delivered, 5);
+        assert_eq!(counter.frames.load(Ordering::SeqCst), 0);
+        assert!(events(&file).is_empty());
+    }
+}
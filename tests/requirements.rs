@@ -0,0 +1,285 @@
+//! Driver versions, hardware details and checking requirements up front.
+
+#[cfg(feature = "mock-backend")]
+mod common;
+
+use decklink::{ApiVersion, CallbackReturnHandling, LibraryCapabilities};
+
+#[test]
+fn api_versions_decode_and_compare() {
+    let version = ApiVersion::from_packed(0x0e030100);
+    assert_eq!(version, ApiVersion::new(14, 3, 1));
+    assert_eq!(version.to_string(), "14.3.1");
+
+    assert!(ApiVersion::new(14, 2, 1) < ApiVersion::new(14, 3, 0));
+    assert!(ApiVersion::new(12, 9, 9) < ApiVersion::new(14, 0, 0));
+    assert!(ApiVersion::new(14, 3, 1) > ApiVersion::new(14, 3, 0));
+}
+
+#[test]
+fn capabilities_follow_the_driver_version() {
+    assert_eq!(
+        LibraryCapabilities::for_version(ApiVersion::new(10, 11, 4)),
+        LibraryCapabilities {
+            driver_version: Some(ApiVersion::new(10, 11, 4)),
+            allocator_provider: false,
+            profiles: false,
+            callback_returns: CallbackReturnHandling::Unknown,
+        }
+    );
+    let capabilities = LibraryCapabilities::for_version(ApiVersion::new(14, 2, 1));
+    assert!(capabilities.profiles && !capabilities.allocator_provider);
+    let capabilities = LibraryCapabilities::for_version(ApiVersion::new(14, 3, 0));
+    assert!(capabilities.profiles && capabilities.allocator_provider);
+    assert!(capabilities.drivers_installed());
+    assert!(!LibraryCapabilities::default().drivers_installed());
+}
+
+#[cfg(feature = "mock-backend")]
+mod mock {
+    use super::common;
+    use decklink::allocator::{BufferSpec, VideoBufferAllocator, VideoBufferAllocatorProvider};
+    use decklink::device::attributes::{DecklinkDeviceInterface, DecklinkVideoIOSupport};
+    use decklink::device::get_devices;
+    use decklink::device::input::DecklinkVideoInputFlags;
+    use decklink::device::status::DecklinkStatusId;
+    use decklink::device::{HardwareInfo, PcieLink};
+    use decklink::display_mode::DecklinkDisplayModeId;
+    use decklink::frame::DecklinkPixelFormat;
+    use decklink::mock::{MockBackend, MockDevice, DEFAULT_API_VERSION};
+    use decklink::{
+        api_version, api_version_number, capabilities, require, ApiVersion, LibraryCapabilities,
+        Requirements, SdkError, UnmetRequirement,
+    };
+    use std::sync::Arc;
+
+    /// A capture card on a x4 link, supporting format detection.
+    fn recorder() -> MockDevice {
+        MockDevice::new("DeckLink Mini Recorder 4K")
+            .format_detection(true)
+            .video_io_support(DecklinkVideoIOSupport::CAPTURE)
+            .device_interface(DecklinkDeviceInterface::PCI)
+            .device_handle("1f:00.0:/dev/blackmagic/io0")
+            .status_int(DecklinkStatusId::PCIExpressLinkWidth, 4)
+            .status_int(DecklinkStatusId::PCIExpressLinkSpeed, 2)
+    }
+
+    #[test]
+    fn driver_version_is_reported() {
+        let backend = MockBackend::install(vec![]);
+        assert_eq!(api_version_number().unwrap(), DEFAULT_API_VERSION);
+        assert_eq!(api_version().unwrap(), "14.2.1");
+
+        backend.set_api_version(Some(ApiVersion::new(15, 0, 0)));
+        assert_eq!(api_version_number().unwrap(), ApiVersion::new(15, 0, 0));
+        assert_eq!(api_version().unwrap(), "15.0.0");
+
+        backend.set_api_version(None);
+        assert!(api_version_number().is_err());
+        assert!(api_version().is_err());
+    }
+
+    #[test]
+    fn hardware_info_is_collected() {
+        let _backend = MockBackend::install(vec![recorder()]);
+        let devices = get_devices().unwrap();
+
+        assert_eq!(
+            devices[0].hardware_info(),
+            HardwareInfo {
+                model_name: Some("DeckLink Mini Recorder 4K".to_string()),
+                hardware_revision: Some("1f".to_string()),
+                device_interface: Some(DecklinkDeviceInterface::PCI),
+                pcie_link: Some(PcieLink { gen: 2, width: 4 }),
+            }
+        );
+    }
+
+    #[test]
+    fn hardware_info_fields_may_be_absent() {
+        let _backend = MockBackend::install(vec![MockDevice::new("DeckLink Mini Recorder")
+            .status_int(DecklinkStatusId::PCIExpressLinkWidth, 1)]);
+        let devices = get_devices().unwrap();
+
+        assert_eq!(
+            devices[0].hardware_info(),
+            HardwareInfo {
+                model_name: Some("DeckLink Mini Recorder".to_string()),
+                hardware_revision: None,
+                device_interface: None,
+                // A width without a speed is not a link
+                pcie_link: None,
+            }
+        );
+    }
+
+    #[test]
+    fn satisfied_requirements_pass() {
+        let _backend = MockBackend::install(vec![recorder()]);
+        let devices = get_devices().unwrap();
+
+        let requirements = Requirements {
+            min_driver: Some(ApiVersion::new(14, 0, 0)),
+            needs_format_detection: true,
+            needs_capture: true,
+            min_pcie_width: Some(4),
+            ..Default::default()
+        };
+        assert_eq!(require(Some(&devices[0]), &requirements), Ok(()));
+        assert_eq!(require(None, &Requirements::default()), Ok(()));
+    }
+
+    #[test]
+    fn every_unmet_requirement_is_reported() {
+        let _backend = MockBackend::install(vec![recorder()]);
+        let devices = get_devices().unwrap();
+
+        let requirements = Requirements {
+            min_driver: Some(ApiVersion::new(14, 4, 0)),
+            needs_allocator_api: true,
+            needs_profiles: true,
+            needs_format_detection: true,
+            needs_capture: true,
+            needs_playback: true,
+            min_pcie_width: Some(8),
+        };
+        let error = require(Some(&devices[0]), &requirements).unwrap_err();
+        assert_eq!(
+            error.unmet,
+            vec![
+                UnmetRequirement::DriverTooOld {
+                    installed: DEFAULT_API_VERSION,
+                    required: ApiVersion::new(14, 4, 0),
+                },
+                UnmetRequirement::AllocatorApiUnavailable {
+                    installed: DEFAULT_API_VERSION,
+                },
+                UnmetRequirement::PlaybackUnsupported,
+                UnmetRequirement::PcieLinkTooNarrow {
+                    width: Some(4),
+                    required: 8,
+                },
+            ]
+        );
+        assert_eq!(
+            error.to_string(),
+            "unmet requirements: drivers 14.2.1 are installed, but 14.4.0 or newer is required; \
+             drivers 14.2.1 do not support custom buffer allocators, 14.3.0 or newer is \
+             required; the device cannot play back; the device has a x4 PCIe link, but x8 is \
+             required"
+        );
+    }
+
+    #[test]
+    fn unreported_capabilities_are_unmet() {
+        let _backend = common::mini_recorder();
+        let devices = get_devices().unwrap();
+
+        let requirements = Requirements {
+            needs_format_detection: true,
+            needs_capture: true,
+            min_pcie_width: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(
+            require(Some(&devices[0]), &requirements).unwrap_err().unmet,
+            vec![
+                UnmetRequirement::FormatDetectionUnsupported,
+                UnmetRequirement::CaptureUnsupported,
+                UnmetRequirement::PcieLinkTooNarrow {
+                    width: None,
+                    required: 1,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_drivers_and_device_are_reported() {
+        let backend = MockBackend::install(vec![]);
+        backend.set_api_version(None);
+
+        let requirements = Requirements {
+            min_driver: Some(ApiVersion::new(14, 0, 0)),
+            needs_capture: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            require(None, &requirements).unwrap_err().unmet,
+            vec![UnmetRequirement::DriverMissing, UnmetRequirement::NoDevice]
+        );
+
+        backend.set_api_version(Some(ApiVersion::new(14, 3, 0)));
+        let requirements = Requirements {
+            needs_allocator_api: true,
+            ..Default::default()
+        };
+        assert_eq!(require(None, &requirements), Ok(()));
+    }
+
+    #[test]
+    fn capabilities_of_the_installed_drivers() {
+        let backend = MockBackend::install(vec![]);
+        assert_eq!(
+            capabilities(),
+            LibraryCapabilities::for_version(DEFAULT_API_VERSION)
+        );
+
+        backend.set_api_version(Some(ApiVersion::new(14, 3, 0)));
+        assert!(capabilities().allocator_provider);
+
+        backend.set_api_version(None);
+        assert_eq!(capabilities(), LibraryCapabilities::default());
+    }
+
+    #[test]
+    fn drivers_without_profiles_are_reported() {
+        let backend = MockBackend::install(vec![]);
+        backend.set_api_version(Some(ApiVersion::new(10, 11, 4)));
+
+        let requirements = Requirements {
+            needs_profiles: true,
+            ..Default::default()
+        };
+        let error = require(None, &requirements).unwrap_err();
+        assert_eq!(
+            error.unmet,
+            vec![UnmetRequirement::ProfilesUnavailable {
+                installed: ApiVersion::new(10, 11, 4),
+            }]
+        );
+        assert_eq!(
+            error.to_string(),
+            "unmet requirements: drivers 10.11.4 do not support device profiles, 11.0.0 or \
+             newer is required"
+        );
+    }
+
+    /// A provider that the drivers must never be given.
+    struct UnusedProvider;
+
+    impl VideoBufferAllocatorProvider for UnusedProvider {
+        fn get_allocator(
+            &self,
+            _spec: BufferSpec,
+        ) -> Result<Arc<dyn VideoBufferAllocator>, SdkError> {
+            unreachable!("the drivers cannot take an allocator provider")
+        }
+    }
+
+    #[test]
+    fn allocators_need_drivers_that_support_them() {
+        let backend = common::mini_recorder();
+        let mut input = get_devices().unwrap()[0].input().unwrap();
+
+        let result = input.enable_video_input_with_allocator(
+            DecklinkDisplayModeId::HD1080p25,
+            DecklinkPixelFormat::Format8BitYUV,
+            DecklinkVideoInputFlags::empty(),
+            Arc::new(UnusedProvider),
+        );
+        assert!(matches!(result, Err(SdkError::NOINTERFACE)));
+        assert_eq!(backend.input(0).video(), None);
+        assert!(!backend.input(0).has_allocator_provider());
+    }
+}
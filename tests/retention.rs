@@ -0,0 +1,224 @@
+//! A leaky consumer of a mock input running into its retention budget.
+#![cfg(feature = "mock-backend")]
+
+mod common;
+
+use decklink::device::input::{
+    DeckLinkInputCallback, DecklinkDetectedVideoInputFormatFlags, DecklinkInputDevice,
+    DecklinkVideoInputFlags, DecklinkVideoInputFormatChangedEvents,
+};
+use decklink::device::{get_devices, DecklinkDevice};
+use decklink::display_mode::DecklinkDisplayModeId;
+use decklink::frame::{DecklinkFrameBase, DecklinkPixelFormat, DecklinkVideoFrame};
+use decklink::mock::{Delivery, MockBackend, MockDevice, MockFrame, MockInput};
+use decklink::retention::{
+    RetainedFrame, RetentionBudget, RetentionBudgetExceeded, RetentionLimit, RetentionMode,
+};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const FORMAT: DecklinkPixelFormat = DecklinkPixelFormat::Format8BitYUV;
+/// The size of each delivered frame, 48 pixels by 2 rows.
+const FRAME_BYTES: usize = 96 * 2;
+
+/// Retains every frame it is given, releasing them only when asked to.
+struct Leaky {
+    budget: RetentionBudget,
+    retained: Mutex<Vec<RetainedFrame>>,
+    refused: Mutex<Vec<RetentionBudgetExceeded>>,
+}
+
+impl Leaky {
+    fn release(&self, count: usize) {
+        self.retained.lock().unwrap().drain(..count);
+    }
+
+    fn refused(&self) -> usize {
+        self.refused.lock().unwrap().len()
+    }
+}
+
+impl DeckLinkInputCallback for Leaky {
+    fn video_input_format_changed(
+        &self,
+        _events: DecklinkVideoInputFormatChangedEvents,
+        _new_display_mode: DecklinkDisplayModeId,
+        _detected_signal_flags: DecklinkDetectedVideoInputFormatFlags,
+    ) {
+    }
+
+    fn video_input_frame_arrived(&self, video_frame: Option<DecklinkVideoFrame>) -> bool {
+        match self.budget.retain(video_frame.unwrap()) {
+            Ok(frame) => self.retained.lock().unwrap().push(frame),
+            Err(error) => self.refused.lock().unwrap().push(error),
+        }
+        true
+    }
+}
+
+fn start(budget: &RetentionBudget) -> (DecklinkInputDevice, Arc<Leaky>) {
+    start_on(&get_devices().unwrap()[0], budget)
+}
+
+fn start_on(
+    device: &DecklinkDevice,
+    budget: &RetentionBudget,
+) -> (DecklinkInputDevice, Arc<Leaky>) {
+    let mut input = device.input().unwrap();
+    let leaky = Arc::new(Leaky {
+        budget: budget.clone(),
+        retained: Mutex::new(Vec::new()),
+        refused: Mutex::new(Vec::new()),
+    });
+    input
+        .enable_video_input(
+            DecklinkDisplayModeId::HD1080p25,
+            FORMAT,
+            DecklinkVideoInputFlags::empty(),
+        )
+        .unwrap();
+    input.set_callback(Some(leaky.clone())).unwrap();
+    input.start_streams().unwrap();
+    (input, leaky)
+}
+
+fn deliver(mock: &MockInput, count: usize) {
+    for _ in 0..count {
+        let delivery = mock.deliver_frame(MockFrame::new(48, 2, FORMAT));
+        assert!(matches!(delivery, Delivery::Returned(_)));
+    }
+}
+
+#[test]
+fn strict_budget_refuses_and_capture_continues() {
+    let backend = common::mini_recorder();
+    let budget = RetentionBudget::new(RetentionLimit::Frames(3), RetentionMode::Strict);
+    let (_input, leaky) = start(&budget);
+    let mock = backend.input(0);
+
+    deliver(&mock, 5);
+    assert_eq!(leaky.retained.lock().unwrap().len(), 3);
+    assert_eq!(leaky.refused(), 2);
+    assert_eq!(
+        leaky.refused.lock().unwrap()[0],
+        RetentionBudgetExceeded {
+            limit: RetentionLimit::Frames(3),
+            retained_frames: 3,
+            retained_bytes: 3 * FRAME_BYTES,
+        }
+    );
+    let stats = budget.stats();
+    assert_eq!(
+        (
+            stats.retained_frames,
+            stats.retained_bytes,
+            stats.peak_frames
+        ),
+        (3, 3 * FRAME_BYTES, 3)
+    );
+    assert_eq!(stats.exceeded_count, 2);
+
+    // Releasing frames makes room for new ones
+    leaky.release(2);
+    assert_eq!(budget.stats().retained_frames, 1);
+    deliver(&mock, 2);
+    assert_eq!(leaky.retained.lock().unwrap().len(), 3);
+    assert_eq!(leaky.refused(), 2);
+
+    leaky.release(3);
+    let stats = budget.stats();
+    assert_eq!((stats.retained_frames, stats.retained_bytes), (0, 0));
+    assert_eq!(stats.peak_frames, 3);
+}
+
+#[test]
+fn lenient_budget_counts_overruns() {
+    let backend = common::mini_recorder();
+    let budget = RetentionBudget::new(RetentionLimit::Frames(2), RetentionMode::Lenient);
+    let (_input, leaky) = start(&budget);
+
+    deliver(&backend.input(0), 5);
+    assert_eq!(leaky.retained.lock().unwrap().len(), 5);
+    assert_eq!(leaky.refused(), 0);
+    let stats = budget.stats();
+    assert_eq!((stats.retained_frames, stats.peak_frames), (5, 5));
+    assert_eq!(stats.exceeded_count, 3);
+}
+
+#[test]
+fn byte_budget_limits_retained_data() {
+    let backend = common::mini_recorder();
+    let budget = RetentionBudget::new(
+        RetentionLimit::Bytes(2 * FRAME_BYTES + 1),
+        RetentionMode::Strict,
+    );
+    let (_input, leaky) = start(&budget);
+
+    deliver(&backend.input(0), 3);
+    assert_eq!(leaky.retained.lock().unwrap().len(), 2);
+    assert_eq!(
+        leaky.refused.lock().unwrap()[0].retained_bytes,
+        2 * FRAME_BYTES
+    );
+    assert_eq!(leaky.retained.lock().unwrap()[0].row_bytes(), 96);
+}
+
+#[test]
+fn sub_budgets_charge_their_parent() {
+    let backend = MockBackend::install(vec![
+        MockDevice::new("DeckLink Duo 2 (1)"),
+        MockDevice::new("DeckLink Duo 2 (2)"),
+    ]);
+    let devices = get_devices().unwrap();
+    let parent = RetentionBudget::new(RetentionLimit::Frames(3), RetentionMode::Strict);
+    let queue = parent.sub_budget(RetentionLimit::Frames(2));
+    let fanout = parent.sub_budget(RetentionLimit::Frames(3));
+    let (_queue_input, queue_consumer) = start_on(&devices[0], &queue);
+    let (_fanout_input, fanout_consumer) = start_on(&devices[1], &fanout);
+
+    // The sub-budget's own limit is reached first
+    deliver(&backend.input(0), 3);
+    assert_eq!(queue_consumer.refused(), 1);
+    assert_eq!(parent.stats().retained_frames, 2);
+    assert_eq!(parent.stats().exceeded_count, 0);
+
+    // The other sub-budget has room of its own, but the parent runs out
+    deliver(&backend.input(1), 2);
+    assert_eq!(fanout_consumer.refused(), 1);
+    assert_eq!(
+        fanout_consumer.refused.lock().unwrap()[0].limit,
+        RetentionLimit::Frames(3)
+    );
+    assert_eq!(fanout.stats().retained_frames, 1);
+    assert_eq!(parent.stats().retained_frames, 3);
+    assert_eq!(parent.stats().exceeded_count, 1);
+
+    // Releasing from a sub-budget releases from the parent
+    queue_consumer.release(2);
+    assert_eq!(parent.stats().retained_frames, 1);
+    deliver(&backend.input(1), 1);
+    assert_eq!(fanout.stats().retained_frames, 2);
+    assert_eq!(queue.limit(), RetentionLimit::Frames(2));
+    assert_eq!(queue.mode(), RetentionMode::Strict);
+}
+
+#[test]
+fn time_at_budget_accumulates_while_full() {
+    let backend = common::mini_recorder();
+    let budget = RetentionBudget::new(RetentionLimit::Frames(2), RetentionMode::Strict);
+    let (_input, leaky) = start(&budget);
+
+    deliver(&backend.input(0), 1);
+    std::thread::sleep(Duration::from_millis(20));
+    assert_eq!(budget.stats().time_at_budget, Duration::ZERO);
+
+    deliver(&backend.input(0), 1);
+    std::thread::sleep(Duration::from_millis(20));
+    leaky.release(1);
+    let at_budget = budget.stats().time_at_budget;
+    assert!(at_budget >= Duration::from_millis(20), "{:?}", at_budget);
+
+    // Time stops accumulating once there is room again
+    std::thread::sleep(Duration::from_millis(20));
+    assert_eq!(budget.stats().time_at_budget, at_budget);
+}
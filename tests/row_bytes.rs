@@ -0,0 +1,313 @@
+//! The row sizes expected for each pixel format, and checking them against a mock driver's.
+
+#[cfg(feature = "mock-backend")]
+mod common;
+
+use decklink::frame::DecklinkPixelFormat;
+use decklink::row_bytes::{RowBytesMismatch, RowBytesPolicy, RowBytesSource, RowBytesValidator};
+use strum::IntoEnumIterator;
+
+/// The rows of `tests/fixtures/row_bytes.txt`: width, pixel format and row bytes.
+fn fixtures() -> Vec<(usize, DecklinkPixelFormat, usize)> {
+    include_str!("fixtures/row_bytes.txt")
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .map(|line| {
+            let fields: Vec<_> = line.split_whitespace().collect();
+            let pixel_format = DecklinkPixelFormat::iter()
+                .find(|f| format!("{:?}", f) == fields[1])
+                .unwrap_or_else(|| panic!("unknown pixel format in {:?}", line));
+            (
+                fields[0].parse().unwrap(),
+                pixel_format,
+                fields[2].parse().unwrap(),
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn bytes_per_row_matches_the_fixtures() {
+    let fixtures = fixtures();
+    for (width, pixel_format, row_bytes) in &fixtures {
+        assert_eq!(
+            pixel_format.bytes_per_row(*width),
+            Some(*row_bytes),
+            "{:?} at {} pixels wide",
+            pixel_format,
+            width
+        );
+    }
+    // Every uncompressed format is covered
+    for pixel_format in DecklinkPixelFormat::iter() {
+        let covered = fixtures.iter().any(|(_, f, _)| *f == pixel_format);
+        assert_eq!(
+            covered,
+            pixel_format.bytes_per_row(1920).is_some(),
+            "{:?}",
+            pixel_format
+        );
+    }
+}
+
+#[test]
+fn compressed_formats_have_no_row_size() {
+    assert_eq!(DecklinkPixelFormat::FormatH265.bytes_per_row(1920), None);
+    assert_eq!(DecklinkPixelFormat::FormatDNxHR.bytes_per_row(1920), None);
+}
+
+#[test]
+fn v210_rows_are_padded_to_whole_groups() {
+    let v210 = DecklinkPixelFormat::Format10BitYUV;
+    assert_eq!(v210.bytes_per_row(48), Some(128));
+    assert_eq!(v210.bytes_per_row(49), Some(256));
+    assert_eq!(v210.bytes_per_row(1280), Some(3456));
+}
+
+#[test]
+fn mismatches_are_recorded_with_their_cause() {
+    let validator = RowBytesValidator::new(RowBytesPolicy::Warn);
+    let yuv = DecklinkPixelFormat::Format8BitYUV;
+    assert!(validator
+        .check(yuv, 1920, 3840, RowBytesSource::Frame)
+        .is_ok());
+    // Compressed formats always pass
+    assert!(validator
+        .check(
+            DecklinkPixelFormat::FormatH265,
+            1920,
+            1,
+            RowBytesSource::Frame
+        )
+        .is_ok());
+
+    let padded = validator
+        .check(yuv, 1920, 4096, RowBytesSource::Allocator)
+        .unwrap_err();
+    assert_eq!(
+        padded,
+        RowBytesMismatch {
+            pixel_format: yuv,
+            width: 1920,
+            expected: 3840,
+            reported: 4096,
+            source: RowBytesSource::Allocator,
+        }
+    );
+    assert!(padded.to_string().starts_with(
+        "Format8BitYUV at 1920 pixels wide has 4096 bytes a row in the allocator request, \
+         but 3840 were expected: the driver pads rows more"
+    ));
+    let short = validator
+        .check(yuv, 1920, 3000, RowBytesSource::Frame)
+        .unwrap_err();
+    assert!(short.likely_cause().contains("pads rows less"));
+
+    assert_eq!(validator.take_mismatches(), [padded, short]);
+    assert!(validator.take_mismatches().is_empty());
+}
+
+#[cfg(feature = "mock-backend")]
+mod mock {
+    use super::common;
+    use super::*;
+    use decklink::allocator::{
+        BufferSpec, VideoBuffer, VideoBufferAllocator, VideoBufferAllocatorProvider,
+    };
+    use decklink::device::get_devices;
+    use decklink::device::input::{
+        DeckLinkInputCallback, DecklinkDetectedVideoInputFormatFlags, DecklinkInputDevice,
+        DecklinkVideoInputFlags, DecklinkVideoInputFormatChangedEvents,
+    };
+    use decklink::display_mode::DecklinkDisplayModeId;
+    use decklink::frame::DecklinkVideoFrame;
+    use decklink::mock::{Delivery, MockBackend, MockFrame, MockInput};
+    use decklink::{ApiVersion, SdkError};
+    use std::ffi::c_void;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    const MODE: DecklinkDisplayModeId = DecklinkDisplayModeId::HD1080p25;
+    const FORMAT: DecklinkPixelFormat = DecklinkPixelFormat::Format8BitYUV;
+
+    #[derive(Default)]
+    struct Counter {
+        frames: AtomicUsize,
+    }
+
+    impl DeckLinkInputCallback for Counter {
+        fn video_input_format_changed(
+            &self,
+            _events: DecklinkVideoInputFormatChangedEvents,
+            _new_display_mode: DecklinkDisplayModeId,
+            _detected_signal_flags: DecklinkDetectedVideoInputFormatFlags,
+        ) {
+        }
+
+        fn video_input_frame_arrived(&self, _video_frame: Option<DecklinkVideoFrame>) -> bool {
+            self.frames.fetch_add(1, Ordering::SeqCst);
+            true
+        }
+    }
+
+    struct HeapBuffer(Mutex<Vec<u8>>);
+
+    impl VideoBuffer for HeapBuffer {
+        fn get_bytes(&self) -> Result<*mut c_void, SdkError> {
+            Ok(self.0.lock().unwrap().as_mut_ptr() as *mut c_void)
+        }
+    }
+
+    struct HeapAllocator(usize);
+
+    impl VideoBufferAllocator for HeapAllocator {
+        fn allocate(&self) -> Result<Box<dyn VideoBuffer>, SdkError> {
+            Ok(Box::new(HeapBuffer(Mutex::new(vec![0; self.0]))))
+        }
+    }
+
+    struct HeapProvider;
+
+    impl VideoBufferAllocatorProvider for HeapProvider {
+        fn get_allocator(
+            &self,
+            spec: BufferSpec,
+        ) -> Result<Arc<dyn VideoBufferAllocator>, SdkError> {
+            Ok(Arc::new(HeapAllocator(spec.buffer_size as usize)))
+        }
+    }
+
+    /// A frame of 1920 pixels, padded to `row_bytes` instead of the 3840 expected.
+    fn frame(row_bytes: usize) -> MockFrame {
+        MockFrame::new(1920, 2, FORMAT).row_bytes(row_bytes)
+    }
+
+    fn padded(source: RowBytesSource) -> RowBytesMismatch {
+        RowBytesMismatch {
+            pixel_format: FORMAT,
+            width: 1920,
+            expected: 3840,
+            reported: 4096,
+            source,
+        }
+    }
+
+    /// A device with a driver new enough to take an allocator provider.
+    fn backend() -> MockBackend {
+        let backend = common::mini_recorder();
+        backend.set_api_version(Some(ApiVersion::new(14, 3, 0)));
+        backend
+    }
+
+    /// Start capturing through `validator`, with a custom allocator if `allocate` is set.
+    fn start(
+        backend: &MockBackend,
+        validator: &RowBytesValidator,
+        allocate: bool,
+    ) -> (DecklinkInputDevice, MockInput, Arc<Counter>) {
+        let devices = get_devices().unwrap();
+        let mut input = devices[0].input().unwrap();
+        let counter = Arc::new(Counter::default());
+        if allocate {
+            let provider = validator.provider(Arc::new(HeapProvider));
+            input
+                .enable_video_input_with_allocator(
+                    MODE,
+                    FORMAT,
+                    DecklinkVideoInputFlags::empty(),
+                    provider,
+                )
+                .unwrap();
+            input.set_callback(Some(counter.clone())).unwrap();
+        } else {
+            input
+                .enable_video_input(MODE, FORMAT, DecklinkVideoInputFlags::empty())
+                .unwrap();
+            input
+                .set_handler(Some(validator.callback(counter.clone())))
+                .unwrap();
+        }
+        input.start_streams().unwrap();
+        (input, backend.input(0), counter)
+    }
+
+    #[test]
+    fn allocator_requests_are_checked() {
+        let backend = backend();
+        let validator = RowBytesValidator::new(RowBytesPolicy::Warn);
+        let (_input, mock, counter) = start(&backend, &validator, true);
+
+        assert!(mock.deliver_frame(frame(3840)).is_ok());
+        assert!(validator.take_mismatches().is_empty());
+        // Warning only, the padded frame reaches the handler
+        assert!(mock.deliver_frame(frame(4096)).is_ok());
+        assert_eq!(
+            validator.take_mismatches(),
+            [padded(RowBytesSource::Allocator)]
+        );
+        assert_eq!(counter.frames.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn strict_allocator_requests_with_the_wrong_row_size_fail() {
+        let backend = backend();
+        let validator = RowBytesValidator::new(RowBytesPolicy::Strict);
+        let (_input, mock, counter) = start(&backend, &validator, true);
+
+        // The driver drops a frame it gets no buffer for
+        assert_eq!(mock.deliver_frame(frame(4096)), Delivery::NotDelivered);
+        assert_eq!(
+            validator.take_mismatches(),
+            [padded(RowBytesSource::Allocator)]
+        );
+        assert!(mock.deliver_frame(frame(3840)).is_ok());
+        assert_eq!(counter.frames.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn only_the_first_frame_of_a_format_is_checked() {
+        let backend = backend();
+        let validator = RowBytesValidator::new(RowBytesPolicy::Warn);
+        let (_input, mock, counter) = start(&backend, &validator, false);
+
+        assert!(mock.deliver_frame(frame(4096)).is_ok());
+        assert!(mock.deliver_frame(frame(4096)).is_ok());
+        assert_eq!(validator.take_mismatches(), [padded(RowBytesSource::Frame)]);
+        assert!(!validator.holding_frames());
+        assert_eq!(counter.frames.load(Ordering::SeqCst), 2);
+
+        assert!(mock
+            .deliver_format_change(
+                DecklinkVideoInputFormatChangedEvents::DISPLAY_MODE_CHANGED,
+                DecklinkDisplayModeId::HD1080p50,
+                DecklinkDetectedVideoInputFormatFlags::YCBCR_422,
+            )
+            .is_ok());
+        assert!(mock.deliver_frame(frame(4096)).is_ok());
+        assert_eq!(validator.take_mismatches(), [padded(RowBytesSource::Frame)]);
+    }
+
+    #[test]
+    fn strict_validation_holds_frames_until_a_format_change_fixes_them() {
+        let backend = backend();
+        let validator = RowBytesValidator::new(RowBytesPolicy::Strict);
+        let (_input, mock, counter) = start(&backend, &validator, false);
+
+        assert!(mock.deliver_frame(frame(4096)).is_ok());
+        assert!(mock.deliver_frame(frame(4096)).is_ok());
+        assert!(validator.holding_frames());
+        assert_eq!(counter.frames.load(Ordering::SeqCst), 0);
+
+        assert!(mock
+            .deliver_format_change(
+                DecklinkVideoInputFormatChangedEvents::DISPLAY_MODE_CHANGED,
+                DecklinkDisplayModeId::HD1080p50,
+                DecklinkDetectedVideoInputFormatFlags::YCBCR_422,
+            )
+            .is_ok());
+        assert!(mock.deliver_frame(frame(3840)).is_ok());
+        assert!(!validator.holding_frames());
+        assert_eq!(counter.frames.load(Ordering::SeqCst), 1);
+        assert_eq!(validator.take_mismatches(), [padded(RowBytesSource::Frame)]);
+    }
+}
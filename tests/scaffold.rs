@@ -0,0 +1,118 @@
+//! Generated projects, which are built and tested with cargo so the templates cannot fall
+//! behind the api.
+
+use decklink::scaffold::{
+    render, write_project, DecklinkDependency, ScaffoldError, ScaffoldFile, ScaffoldOptions,
+};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+fn options(name: &str, audio: bool, cuda: bool, tokio: bool) -> ScaffoldOptions {
+    ScaffoldOptions {
+        name: name.to_string(),
+        audio,
+        cuda,
+        tokio,
+        decklink: DecklinkDependency::Path(PathBuf::from(env!("CARGO_MANIFEST_DIR"))),
+    }
+}
+
+fn file<'a>(files: &'a [ScaffoldFile], path: &str) -> &'a str {
+    &files
+        .iter()
+        .find(|f| f.path == Path::new(path))
+        .unwrap()
+        .contents
+}
+
+/// A directory under the system temp dir, emptied for one test.
+fn temp_dir(name: &str) -> PathBuf {
+    let dir =
+        std::env::temp_dir().join(format!("decklink-scaffold-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+#[test]
+fn projects_only_refer_to_the_options_they_enable() {
+    let plain = render(&options("plain", false, false, false)).unwrap();
+    let all = render(&options("all-of-it", true, true, true)).unwrap();
+
+    for f in plain.iter().chain(&all) {
+        assert!(!f.contents.contains("{{"), "{:?} is not rendered", f.path);
+    }
+    for f in &plain {
+        for word in ["cuda", "Cuda", "tokio", "audio"] {
+            assert!(
+                !f.contents.contains(word),
+                "{:?} refers to {}",
+                f.path,
+                word
+            );
+        }
+    }
+
+    let manifest = file(&all, "Cargo.toml");
+    assert!(manifest.contains("name = \"all-of-it\""));
+    assert!(manifest.contains("features = [\"cuda\"]"));
+    assert!(manifest.contains("features = [\"cuda\", \"mock-backend\"]"));
+    assert!(manifest.contains("tokio = "));
+    assert!(file(&all, "src/main.rs").contains("use all_of_it::{run, Config, Summary};"));
+    assert!(file(&all, "src/lib.rs").contains("CudaAllocatorProvider::new"));
+    assert!(file(&all, "src/lib.rs").contains("enable_audio_input"));
+    assert!(file(&all, "README.md").contains("/cuda/struct.CudaAllocatorProvider.html"));
+}
+
+#[test]
+fn invalid_names_and_existing_files_are_refused() {
+    for name in ["", "1st", "has space", "decklink"] {
+        let err = render(&options(name, false, false, false)).unwrap_err();
+        assert_eq!(err.field, "name");
+    }
+
+    let dir = temp_dir("existing");
+    std::fs::create_dir_all(dir.join("src")).unwrap();
+    std::fs::write(dir.join("src/main.rs"), "fn main() {}\n").unwrap();
+    match write_project(&dir, &options("existing", false, false, false)) {
+        Err(ScaffoldError::Exists(path)) => assert_eq!(path, dir.join("src/main.rs")),
+        other => panic!("unexpected result: {:?}", other),
+    }
+    // Nothing is written alongside the existing file
+    assert!(!dir.join("Cargo.toml").exists());
+    assert_eq!(
+        std::fs::read_to_string(dir.join("src/main.rs")).unwrap(),
+        "fn main() {}\n"
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+/// Generate each combination of options that builds without the CUDA toolkit, and build it
+/// and run its mock test with cargo. The projects share a target directory, so this crate
+/// is built for them once.
+#[test]
+fn generated_projects_build_and_capture_from_the_mock_backend() {
+    let cargo = std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+    let target = Path::new(env!("CARGO_MANIFEST_DIR")).join("target/scaffold");
+
+    for (audio, tokio) in [(false, false), (true, false), (false, true), (true, true)] {
+        let name = format!(
+            "capture{}{}",
+            if audio { "-audio" } else { "" },
+            if tokio { "-tokio" } else { "" }
+        );
+        let dir = temp_dir(&name);
+        write_project(&dir, &options(&name, audio, false, tokio)).unwrap();
+
+        for step in ["check", "test"] {
+            let status = Command::new(&cargo)
+                .arg(step)
+                .arg("--all-targets")
+                .current_dir(&dir)
+                .env("CARGO_TARGET_DIR", &target)
+                .status()
+                .unwrap();
+            assert!(status.success(), "cargo {} failed for {}", step, name);
+        }
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,290 @@
+//! Scheduled playback on the output of a mock device, and its completion callbacks.
+#![cfg(feature = "mock-backend")]
+
+use decklink::device::get_devices;
+use decklink::device::output::{
+    DeckLinkVideoOutputCallback, DecklinkOutputFrameCompletionResult, DecklinkVideoOutputFlags,
+};
+use decklink::display_mode::DecklinkDisplayModeId;
+use decklink::frame::{
+    DecklinkFrameBase, DecklinkFrameFlags, DecklinkPixelFormat, DecklinkVideoFrame,
+};
+use decklink::mock::{Delivery, MockBackend, MockDevice};
+use decklink::SdkError;
+use std::sync::{Arc, Mutex};
+
+const MODE: DecklinkDisplayModeId = DecklinkDisplayModeId::HD1080p25;
+const FORMAT: DecklinkPixelFormat = DecklinkPixelFormat::Format8BitBGRA;
+const TIMESCALE: i64 = 25000;
+
+fn backend() -> MockBackend {
+    MockBackend::install(vec![MockDevice::new("DeckLink Duo").with_output()])
+}
+
+#[derive(PartialEq, Debug)]
+enum Event {
+    Completed(Option<usize>, DecklinkOutputFrameCompletionResult),
+    Stopped,
+}
+
+/// Records each callback, returning `ok` from them.
+struct Recorder {
+    events: Mutex<Vec<Event>>,
+    ok: bool,
+}
+
+impl Recorder {
+    fn new(ok: bool) -> Arc<Recorder> {
+        Arc::new(Recorder {
+            events: Mutex::new(Vec::new()),
+            ok,
+        })
+    }
+
+    fn events(&self) -> Vec<Event> {
+        std::mem::take(&mut self.events.lock().unwrap())
+    }
+}
+
+impl DeckLinkVideoOutputCallback for Recorder {
+    fn scheduled_frame_completed(
+        &self,
+        frame: Option<DecklinkVideoFrame>,
+        result: DecklinkOutputFrameCompletionResult,
+    ) -> bool {
+        let width = frame.map(|f| f.width());
+        self.events
+            .lock()
+            .unwrap()
+            .push(Event::Completed(width, result));
+        self.ok
+    }
+
+    fn scheduled_playback_has_stopped(&self) -> bool {
+        self.events.lock().unwrap().push(Event::Stopped);
+        self.ok
+    }
+}
+
+/// Implements only the methods the trait had before they were renamed.
+struct Legacy {
+    completed: Mutex<u32>,
+}
+
+impl DeckLinkVideoOutputCallback for Legacy {
+    fn schedule_frame_completed_callback(
+        &self,
+        _frame: Option<DecklinkVideoFrame>,
+        _result: DecklinkOutputFrameCompletionResult,
+    ) -> bool {
+        *self.completed.lock().unwrap() += 1;
+        false
+    }
+}
+
+#[test]
+fn scheduled_frames_complete_through_the_callback() {
+    let backend = backend();
+    let devices = get_devices().unwrap();
+    let output = devices[0].output().unwrap();
+    let recorder = Recorder::new(true);
+    output.set_callback(Some(recorder.clone())).unwrap();
+    output
+        .enable_video_output(MODE, DecklinkVideoOutputFlags::empty())
+        .unwrap();
+
+    for i in 0..3 {
+        let frame = output
+            .create_video_frame(1920, 1080, 1920 * 4, FORMAT, DecklinkFrameFlags::empty())
+            .unwrap();
+        output
+            .schedule_video_frame(frame, i * 1000, 1000, TIMESCALE)
+            .unwrap();
+    }
+    let mock = backend.output(0);
+    assert_eq!(
+        mock.scheduled_times(),
+        [(0, 1000), (1000, 1000), (2000, 1000)]
+    );
+    assert_eq!(output.buffered_video_frame_count(), Ok(3));
+
+    output.start_scheduled_playback(0, TIMESCALE, 1.0).unwrap();
+    assert!(mock.is_playing());
+    assert_eq!(output.is_scheduled_playback_running(), Ok(true));
+
+    use DecklinkOutputFrameCompletionResult::*;
+    assert_eq!(mock.complete_next(Completed), Delivery::Returned(0));
+    assert_eq!(mock.complete_next(DisplayedLate), Delivery::Returned(0));
+    assert_eq!(output.stop_scheduled_playback(1500, TIMESCALE), Ok(1500));
+    assert!(!mock.is_playing());
+    assert_eq!(mock.complete_next(Flushed), Delivery::Returned(0));
+    assert_eq!(mock.playback_stopped(), Delivery::Returned(0));
+    assert_eq!(
+        recorder.events(),
+        [
+            Event::Completed(Some(1920), Completed),
+            Event::Completed(Some(1920), DisplayedLate),
+            Event::Completed(Some(1920), Flushed),
+            Event::Stopped,
+        ]
+    );
+    assert_eq!(output.buffered_video_frame_count(), Ok(0));
+
+    drop(output);
+    drop(devices);
+    assert_eq!(MockBackend::live_objects(), 0);
+}
+
+#[test]
+fn handler_results_are_returned_to_the_driver() {
+    let backend = backend();
+    let devices = get_devices().unwrap();
+    let output = devices[0].output().unwrap();
+    output
+        .enable_video_output(MODE, DecklinkVideoOutputFlags::empty())
+        .unwrap();
+    let frame = output
+        .create_video_frame(1920, 1080, 1920 * 4, FORMAT, DecklinkFrameFlags::empty())
+        .unwrap();
+    output
+        .schedule_video_frame(frame, 0, 1000, TIMESCALE)
+        .unwrap();
+
+    output.set_callback(Some(Recorder::new(false))).unwrap();
+    let mock = backend.output(0);
+    assert_eq!(
+        mock.complete_next(DecklinkOutputFrameCompletionResult::Dropped),
+        Delivery::Returned(1)
+    );
+    assert_eq!(mock.playback_stopped(), Delivery::Returned(1));
+
+    // Without a handler, the callbacks return S_OK
+    output.set_callback(None).unwrap();
+    assert_eq!(mock.playback_stopped(), Delivery::Returned(0));
+}
+
+#[test]
+fn callbacks_written_against_the_old_names_are_still_called() {
+    let backend = backend();
+    let devices = get_devices().unwrap();
+    let output = devices[0].output().unwrap();
+    let legacy = Arc::new(Legacy {
+        completed: Mutex::new(0),
+    });
+    output.set_callback(Some(legacy.clone())).unwrap();
+    output
+        .enable_video_output(MODE, DecklinkVideoOutputFlags::empty())
+        .unwrap();
+    let frame = output
+        .create_video_frame(1920, 1080, 1920 * 4, FORMAT, DecklinkFrameFlags::empty())
+        .unwrap();
+    output
+        .schedule_video_frame(frame, 0, 1000, TIMESCALE)
+        .unwrap();
+
+    let mock = backend.output(0);
+    assert_eq!(
+        mock.complete_next(DecklinkOutputFrameCompletionResult::Completed),
+        Delivery::Returned(1)
+    );
+    assert_eq!(*legacy.completed.lock().unwrap(), 1);
+    // Not implemented, so the default returns S_OK
+    assert_eq!(mock.playback_stopped(), Delivery::Returned(0));
+}
+
+#[test]
+fn scheduling_needs_video_output_enabled() {
+    let _backend = backend();
+    let devices = get_devices().unwrap();
+    let output = devices[0].output().unwrap();
+    let frame = output
+        .create_video_frame(1920, 1080, 1920 * 4, FORMAT, DecklinkFrameFlags::empty())
+        .unwrap();
+
+    assert_eq!(
+        output.schedule_video_frame(frame, 0, 1000, TIMESCALE),
+        Err(SdkError::UNEXPECTED)
+    );
+    assert_eq!(
+        output.start_scheduled_playback(0, TIMESCALE, 1.0),
+        Err(SdkError::UNEXPECTED)
+    );
+}
+
+#[test]
+fn the_callback_outlives_disabling_video_output() {
+    let backend = backend();
+    let devices = get_devices().unwrap();
+    let output = devices[0].output().unwrap();
+    let recorder = Recorder::new(true);
+    output.set_callback(Some(recorder.clone())).unwrap();
+    output
+        .enable_video_output(MODE, DecklinkVideoOutputFlags::empty())
+        .unwrap();
+    output.start_scheduled_playback(0, TIMESCALE, 1.0).unwrap();
+    output.disable_video_output().unwrap();
+    assert!(!backend.output(0).is_playing());
+
+    output
+        .enable_video_output(MODE, DecklinkVideoOutputFlags::empty())
+        .unwrap();
+    assert_eq!(backend.output(0).playback_stopped(), Delivery::Returned(0));
+    assert_eq!(recorder.events(), [Event::Stopped]);
+}
+
+#[test]
+fn dropping_the_output_unregisters_the_callback() {
+    let backend = backend();
+    let devices = get_devices().unwrap();
+    let output = devices[0].output().unwrap();
+    let recorder = Recorder::new(true);
+    output.set_callback(Some(recorder.clone())).unwrap();
+    output
+        .enable_video_output(MODE, DecklinkVideoOutputFlags::empty())
+        .unwrap();
+    let frame = output
+        .create_video_frame(1920, 1080, 1920 * 4, FORMAT, DecklinkFrameFlags::empty())
+        .unwrap();
+    output
+        .schedule_video_frame(frame, 0, 1000, TIMESCALE)
+        .unwrap();
+    output.start_scheduled_playback(0, TIMESCALE, 1.0).unwrap();
+
+    // Dropped while playing, with a frame still scheduled
+    drop(output);
+    let mock = backend.output(0);
+    assert!(!mock.is_playing());
+    assert_eq!(mock.video(), None);
+    assert_eq!(mock.playback_stopped(), Delivery::NotDelivered);
+    assert!(recorder.events().is_empty());
+    // The wrapper was freed, letting go of the handler
+    assert_eq!(Arc::strong_count(&recorder), 1);
+
+    drop(devices);
+    assert_eq!(MockBackend::live_objects(), 0);
+}
+
+#[test]
+fn scheduled_handles_share_the_callback_of_the_output() {
+    let backend = backend();
+    let devices = get_devices().unwrap();
+    let output = devices[0].output().unwrap();
+    let recorder = Recorder::new(true);
+    let mut handle = output
+        .enable_video_output_scheduled(MODE, DecklinkVideoOutputFlags::empty(), TIMESCALE)
+        .unwrap();
+    handle.set_callback(Some(recorder.clone())).unwrap();
+    assert_eq!(backend.output(0).playback_stopped(), Delivery::Returned(0));
+    assert_eq!(recorder.events(), [Event::Stopped]);
+
+    // The handle lets go of its handler, but the callback stays registered until the
+    // output is dropped
+    drop(handle);
+    assert_eq!(Arc::strong_count(&recorder), 1);
+    assert_eq!(backend.output(0).playback_stopped(), Delivery::Returned(0));
+
+    drop(output);
+    assert_eq!(backend.output(0).playback_stopped(), Delivery::NotDelivered);
+    drop(devices);
+    assert_eq!(MockBackend::live_objects(), 0);
+}
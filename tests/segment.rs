@@ -0,0 +1,261 @@
+//! Placing frames into segments, and writing a mock capture with drops and a format change
+//! into segment files.
+
+#[cfg(feature = "mock-backend")]
+mod common;
+
+use decklink::segment::{SegmentBoundary, SegmentPolicy, SegmentReason, Segmenter};
+use decklink::time::{DecklinkFrameTiming, DecklinkTime};
+use std::time::Duration;
+
+/// The timing of frame `n` at 25 frames per second.
+fn timing(n: i64) -> DecklinkFrameTiming {
+    DecklinkFrameTiming {
+        stream_time: DecklinkTime::new(n * 1000, 25000),
+        duration: DecklinkTime::new(1000, 25000),
+        mode_duration: DecklinkTime::new(1000, 25000),
+    }
+}
+
+/// Place frames `numbers` in order, returning the boundaries as (index, first frame, reason).
+fn place(
+    segmenter: &mut Segmenter,
+    numbers: impl IntoIterator<Item = i64>,
+) -> Vec<(u64, u64, SegmentReason)> {
+    numbers
+        .into_iter()
+        .filter_map(|n| segmenter.frame(Some(&timing(n))))
+        .map(|b| (b.index, b.first_frame, b.reason))
+        .collect()
+}
+
+#[test]
+fn frame_count_policy() {
+    let mut segmenter = Segmenter::new(SegmentPolicy::EveryFrames(3));
+    assert_eq!(
+        place(&mut segmenter, 0..8),
+        [
+            (0, 0, SegmentReason::Start),
+            (1, 3, SegmentReason::Policy),
+            (2, 6, SegmentReason::Policy),
+        ]
+    );
+    assert_eq!(segmenter.frame_count(), 8);
+    assert_eq!(segmenter.policy(), SegmentPolicy::EveryFrames(3));
+}
+
+#[test]
+fn duration_policy_sums_frame_durations() {
+    // 200 ms is 5 frames at 25 frames per second
+    let mut segmenter = Segmenter::new(SegmentPolicy::EveryDuration(Duration::from_millis(200)));
+    assert_eq!(
+        place(&mut segmenter, 0..11),
+        [
+            (0, 0, SegmentReason::Start),
+            (1, 5, SegmentReason::Policy),
+            (2, 10, SegmentReason::Policy),
+        ]
+    );
+
+    // A tenth of a frame short of the duration does not roll over
+    let mut segmenter = Segmenter::new(SegmentPolicy::EveryDuration(Duration::from_millis(196)));
+    assert_eq!(place(&mut segmenter, 0..5).len(), 1);
+    assert!(segmenter.frame(Some(&timing(5))).is_some());
+}
+
+#[test]
+fn discontinuity_starts_a_segment() {
+    let mut segmenter = Segmenter::new(SegmentPolicy::EveryFrames(100));
+    // Frames 3 and 4 were dropped
+    assert_eq!(
+        place(&mut segmenter, [0, 1, 2, 5, 6]),
+        [
+            (0, 0, SegmentReason::Start),
+            (1, 3, SegmentReason::Discontinuity),
+        ]
+    );
+    // A restart that goes back in time is also a discontinuity
+    assert_eq!(
+        place(&mut segmenter, [0, 1]),
+        [(2, 5, SegmentReason::Discontinuity)]
+    );
+}
+
+#[test]
+fn format_change_starts_a_segment_and_resets_the_count() {
+    let mut segmenter = Segmenter::new(SegmentPolicy::EveryFrames(3));
+    place(&mut segmenter, 0..2);
+    segmenter.format_changed();
+    assert_eq!(
+        segmenter.frame(Some(&timing(2))),
+        Some(SegmentBoundary {
+            index: 1,
+            first_frame: 2,
+            reason: SegmentReason::FormatChanged,
+        })
+    );
+    // The policy counts from the boundary frame
+    assert_eq!(place(&mut segmenter, 3..6), [(2, 5, SegmentReason::Policy)]);
+}
+
+#[test]
+fn frames_without_timing_only_count() {
+    let mut segmenter = Segmenter::new(SegmentPolicy::EveryDuration(Duration::from_millis(40)));
+    assert!(segmenter.frame(None).is_some());
+    for _ in 0..10 {
+        assert!(segmenter.frame(None).is_none());
+    }
+
+    let mut segmenter = Segmenter::new(SegmentPolicy::EveryFrames(2));
+    let boundaries: Vec<_> = (0..5).filter_map(|_| segmenter.frame(None)).collect();
+    assert_eq!(boundaries.len(), 3);
+
+    // A gap after a frame without timing cannot be seen
+    let mut segmenter = Segmenter::new(SegmentPolicy::EveryFrames(100));
+    place(&mut segmenter, [0]);
+    assert!(segmenter.frame(None).is_none());
+    assert!(place(&mut segmenter, [10]).is_empty());
+}
+
+#[cfg(feature = "mock-backend")]
+mod mock {
+    use super::common;
+    use decklink::device::get_devices;
+    use decklink::device::input::{
+        DeckLinkInputCallback, DecklinkDetectedVideoInputFormatFlags, DecklinkVideoInputFlags,
+        DecklinkVideoInputFormatChangedEvents,
+    };
+    use decklink::display_mode::DecklinkDisplayModeId;
+    use decklink::frame::{DecklinkPixelFormat, DecklinkVideoFrame};
+    use decklink::mock::{MockFrame, MockInput};
+    use decklink::segment::{SegmentPolicy, SegmentReason, SegmentedWriter};
+    use decklink::time::DecklinkFrameTiming;
+    use std::path::PathBuf;
+    use std::sync::{Arc, Mutex};
+
+    const MODE: DecklinkDisplayModeId = DecklinkDisplayModeId::HD1080p25;
+    const FORMAT: DecklinkPixelFormat = DecklinkPixelFormat::Format8BitYUV;
+    /// Frames are 48 pixels by 2 rows.
+    const FRAME_BYTES: usize = 96 * 2;
+
+    struct Recorder {
+        writer: Mutex<Option<SegmentedWriter>>,
+        timing: Mutex<Option<DecklinkFrameTiming>>,
+    }
+
+    impl DeckLinkInputCallback for Recorder {
+        fn video_input_format_changed(
+            &self,
+            _events: DecklinkVideoInputFormatChangedEvents,
+            _new_display_mode: DecklinkDisplayModeId,
+            _detected_signal_flags: DecklinkDetectedVideoInputFormatFlags,
+        ) {
+            self.writer
+                .lock()
+                .unwrap()
+                .as_mut()
+                .unwrap()
+                .format_changed();
+        }
+
+        fn video_input_frame_timing(&self, timing: DecklinkFrameTiming) {
+            *self.timing.lock().unwrap() = Some(timing);
+        }
+
+        fn video_input_frame_arrived(&self, video_frame: Option<DecklinkVideoFrame>) -> bool {
+            let timing = self.timing.lock().unwrap().take();
+            let mut writer = self.writer.lock().unwrap();
+            writer
+                .as_mut()
+                .unwrap()
+                .write_frame(&video_frame.unwrap(), timing.as_ref())
+                .unwrap();
+            true
+        }
+    }
+
+    /// Frame `n`, filled with `n`.
+    fn deliver(mock: &MockInput, n: i64) {
+        let frame = MockFrame::new(48, 2, FORMAT)
+            .fill(n as u8)
+            .stream_time(n * 1000, 1000, 25000);
+        assert!(mock.deliver_frame(frame).is_ok());
+    }
+
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("decklink-segment-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn every_frame_lands_in_exactly_one_segment() {
+        let backend = common::mini_recorder();
+        let devices = get_devices().unwrap();
+        let mut input = devices[0].input().unwrap();
+        let dir = temp_dir();
+        let pattern = dir.join("seg-{index}-{frame}.raw");
+        let recorder = Arc::new(Recorder {
+            writer: Mutex::new(Some(SegmentedWriter::new(
+                pattern.to_str().unwrap(),
+                SegmentPolicy::EveryFrames(4),
+            ))),
+            timing: Mutex::new(None),
+        });
+        input
+            .enable_video_input(
+                MODE,
+                FORMAT,
+                DecklinkVideoInputFlags::ENABLE_FORMAT_DETECTION,
+            )
+            .unwrap();
+        common::start_streams(&mut input, recorder.clone());
+        let mock = backend.input(0);
+
+        // Frame 6 is dropped, and the format changes before frame 9
+        let delivered = [0, 1, 2, 3, 4, 5, 7, 8, 9, 10, 11, 12, 13];
+        for n in delivered {
+            if n == 9 {
+                assert!(mock
+                    .deliver_format_change(
+                        DecklinkVideoInputFormatChangedEvents::FIELD_DOMINANCE_CHANGED,
+                        MODE,
+                        DecklinkDetectedVideoInputFormatFlags::YCBCR_422,
+                    )
+                    .is_ok());
+            }
+            deliver(&mock, n);
+        }
+        input.stop_streams().unwrap();
+        let writer = recorder.writer.lock().unwrap().take().unwrap();
+        assert_eq!(writer.finished_segments().len(), 4);
+        let segments = writer.finish().unwrap();
+
+        let summary: Vec<_> = segments
+            .iter()
+            .map(|s| (s.boundary.first_frame, s.boundary.reason, s.frame_count))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (0, SegmentReason::Start, 4),
+                (4, SegmentReason::Policy, 2),
+                (6, SegmentReason::Discontinuity, 2),
+                (8, SegmentReason::FormatChanged, 4),
+                (12, SegmentReason::Policy, 1),
+            ]
+        );
+        assert_eq!(segments[2].path, dir.join("seg-00002-6.raw"),);
+
+        // Reading the files back gives every delivered frame once, in order
+        let mut frames = Vec::new();
+        for segment in &segments {
+            let data = std::fs::read(&segment.path).unwrap();
+            assert_eq!(data.len() as u64, segment.byte_count);
+            assert_eq!(data.len(), segment.frame_count as usize * FRAME_BYTES);
+            frames.extend(data.chunks(FRAME_BYTES).map(|frame| frame[0] as i64));
+            std::fs::remove_file(&segment.path).unwrap();
+        }
+        assert_eq!(frames, delivered);
+    }
+}
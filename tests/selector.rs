@@ -0,0 +1,246 @@
+//! Parsing device selectors, and resolving them against mock devices.
+
+use decklink::device::selector::DeviceSelector;
+
+#[test]
+fn selectors_parse_and_format_back() {
+    let cases = [
+        ("first", DeviceSelector::First),
+        ("2", DeviceSelector::Index(2)),
+        ("id:0x1234", DeviceSelector::PersistentId(0x1234)),
+        (
+            "sub:0x5a10/1",
+            DeviceSelector::SubDevice {
+                persistent_id: 0x5a10,
+                index: 1,
+            },
+        ),
+        (
+            "name:DeckLink Duo (1)",
+            DeviceSelector::NameExact("DeckLink Duo (1)".to_string()),
+        ),
+        (
+            "contains:Duo",
+            DeviceSelector::NameContains("Duo".to_string()),
+        ),
+        ("label:CAM 1", DeviceSelector::Label("CAM 1".to_string())),
+    ];
+    for (text, selector) in cases {
+        assert_eq!(
+            text.parse::<DeviceSelector>().unwrap(),
+            selector,
+            "{}",
+            text
+        );
+        assert_eq!(selector.to_string(), text);
+        assert_eq!(
+            selector.to_string().parse::<DeviceSelector>().unwrap(),
+            selector
+        );
+    }
+}
+
+#[test]
+fn other_spellings_parse_to_the_same_selector() {
+    let parse = |text: &str| text.parse::<DeviceSelector>().unwrap();
+    assert_eq!(parse("id:4660"), DeviceSelector::PersistentId(0x1234));
+    assert_eq!(parse("id:0X1234"), DeviceSelector::PersistentId(0x1234));
+    assert_eq!(
+        parse("sub:23056/0"),
+        DeviceSelector::SubDevice {
+            persistent_id: 0x5a10,
+            index: 0
+        }
+    );
+    // Unprefixed text is an exact name, formatted back with its prefix
+    let selector = parse("DeckLink Mini Recorder");
+    assert_eq!(
+        selector,
+        DeviceSelector::NameExact("DeckLink Mini Recorder".to_string())
+    );
+    assert_eq!(selector.to_string(), "name:DeckLink Mini Recorder");
+    // A name may contain the separators of other forms
+    assert_eq!(
+        parse("name:id:7"),
+        DeviceSelector::NameExact("id:7".to_string())
+    );
+}
+
+#[test]
+fn malformed_selectors_are_rejected() {
+    for (text, reason) in [
+        ("id:", "expected a persistent id, such as id:0x1234"),
+        ("id:0xzz", "expected a persistent id, such as id:0x1234"),
+        (
+            "sub:0x5a10",
+            "expected a device group id and index, such as sub:0x1234/1",
+        ),
+        (
+            "sub:0x5a10/x",
+            "expected a device group id and index, such as sub:0x1234/1",
+        ),
+        ("name:", "the name is empty"),
+        ("", "the name is empty"),
+    ] {
+        let error = text.parse::<DeviceSelector>().unwrap_err();
+        assert_eq!((error.text.as_str(), error.reason), (text, reason));
+    }
+    assert_eq!(
+        "label:".parse::<DeviceSelector>().unwrap_err().to_string(),
+        "invalid device selector \"label:\": the name is empty"
+    );
+}
+
+#[cfg(feature = "mock-backend")]
+mod mock {
+    use decklink::device::get_devices;
+    use decklink::device::selector::{DeviceSelector, SelectError};
+    use decklink::mock::{MockBackend, MockDevice};
+
+    /// A Duo 2 with two sub-devices, and two recorders with the same name, one of which has
+    /// no persistent id.
+    fn topology() -> Vec<MockDevice> {
+        vec![
+            MockDevice::new("DeckLink Duo 2 (1)")
+                .sub_device(0x5a10, 0x11)
+                .sub_device_index(0),
+            MockDevice::new("DeckLink Duo 2 (2)")
+                .sub_device(0x5a10, 0x12)
+                .sub_device_index(1),
+            MockDevice::new("DeckLink Mini Recorder").sub_device(0x6b20, 0x21),
+            MockDevice::new("DeckLink Mini Recorder"),
+        ]
+    }
+
+    fn resolve(text: &str) -> Result<Option<String>, SelectError> {
+        let selector: DeviceSelector = text.parse().unwrap();
+        selector.resolve().map(|d| d.display_name())
+    }
+
+    fn names(text: &str) -> Vec<String> {
+        let selector: DeviceSelector = text.parse().unwrap();
+        selector
+            .resolve_all()
+            .unwrap()
+            .iter()
+            .map(|d| d.display_name().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn each_form_picks_out_one_device() {
+        let _backend = MockBackend::install(topology());
+
+        assert_eq!(resolve("first").unwrap().unwrap(), "DeckLink Duo 2 (1)");
+        assert_eq!(resolve("1").unwrap().unwrap(), "DeckLink Duo 2 (2)");
+        assert_eq!(
+            resolve("id:0x21").unwrap().unwrap(),
+            "DeckLink Mini Recorder"
+        );
+        assert_eq!(resolve("id:18").unwrap().unwrap(), "DeckLink Duo 2 (2)");
+        assert_eq!(
+            resolve("name:DeckLink Duo 2 (1)").unwrap().unwrap(),
+            "DeckLink Duo 2 (1)"
+        );
+        assert_eq!(
+            resolve("contains:(2)").unwrap().unwrap(),
+            "DeckLink Duo 2 (2)"
+        );
+        // A resolved device is the listed one, not a copy
+        let selector: DeviceSelector = "2".parse().unwrap();
+        let devices = get_devices().unwrap();
+        let device = selector.resolve_from(devices).unwrap();
+        assert_eq!(device.persistent_id(), Some(0x21));
+    }
+
+    #[test]
+    fn duplicate_names_are_ambiguous() {
+        let _backend = MockBackend::install(topology());
+
+        let error = resolve("DeckLink Mini Recorder").unwrap_err();
+        assert!(matches!(
+            &error,
+            SelectError::Ambiguous { candidates, .. } if candidates == &[
+                "2: DeckLink Mini Recorder (id:0x21)",
+                "3: DeckLink Mini Recorder",
+            ]
+        ));
+        assert_eq!(
+            error.to_string(),
+            "name:DeckLink Mini Recorder matches more than one device: \
+             2: DeckLink Mini Recorder (id:0x21), 3: DeckLink Mini Recorder"
+        );
+        assert!(matches!(
+            resolve("contains:Duo"),
+            Err(SelectError::Ambiguous { .. })
+        ));
+
+        // resolve_all takes every match, in driver order
+        assert_eq!(
+            names("contains:DeckLink"),
+            [
+                "DeckLink Duo 2 (1)",
+                "DeckLink Duo 2 (2)",
+                "DeckLink Mini Recorder",
+                "DeckLink Mini Recorder"
+            ]
+        );
+        assert_eq!(names("contains:Duo").len(), 2);
+    }
+
+    #[test]
+    fn no_match_lists_the_devices_found() {
+        let _backend = MockBackend::install(topology());
+
+        let error = resolve("id:0x99").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "no device matches id:0x99. Devices found: \
+             0: DeckLink Duo 2 (1) (id:0x11), 1: DeckLink Duo 2 (2) (id:0x12), \
+             2: DeckLink Mini Recorder (id:0x21), 3: DeckLink Mini Recorder"
+        );
+        assert!(matches!(resolve("4"), Err(SelectError::NoMatch { .. })));
+        let selector: DeviceSelector = "contains:8K".parse().unwrap();
+        assert!(matches!(
+            selector.resolve_all(),
+            Err(SelectError::NoMatch { .. })
+        ));
+    }
+
+    #[test]
+    fn without_devices_nothing_matches() {
+        let _backend = MockBackend::install(Vec::new());
+        let error = resolve("first").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "no device matches first, and no devices were found"
+        );
+    }
+
+    #[test]
+    fn sub_devices_are_found_by_card_and_index() {
+        let _backend = MockBackend::install(topology());
+        assert_eq!(
+            resolve("sub:0x5a10/1").unwrap().unwrap(),
+            "DeckLink Duo 2 (2)"
+        );
+        assert_eq!(
+            resolve("sub:0x5a10/0").unwrap().unwrap(),
+            "DeckLink Duo 2 (1)"
+        );
+        // The recorder is in a card of its own, but has no sub-device index
+        assert!(matches!(
+            resolve("sub:0x6b20/0"),
+            Err(SelectError::NoMatch { .. })
+        ));
+    }
+
+    #[test]
+    fn labels_are_unsupported() {
+        let _backend = MockBackend::install(topology());
+        assert!(matches!(
+            resolve("label:CAM 1"),
+            Err(SelectError::Unsupported { .. })
+        ));
+    }
+}
@@ -0,0 +1,622 @@
+//! Following an input until its format has settled, with `DetectionSettle`.
+
+#[cfg(feature = "mock-backend")]
+mod common;
+
+use decklink::device::input::{
+    DecklinkDetectedVideoInputFormatFlags, DecklinkVideoInputFormatChangedEvents,
+};
+use decklink::display_mode::DecklinkDisplayModeId;
+use decklink::experimental::quirks::QuirkPolicy;
+use decklink::settle::{
+    AbandonPolicy, DetectionSettle, SettleAction, SettleConfig, SettleFailure, SettlePhase,
+    SettleState, SettleTimeouts,
+};
+use std::time::{Duration, Instant};
+
+use DecklinkDisplayModeId::{HD1080p25, HD1080p5994};
+
+const FLAGS: DecklinkDetectedVideoInputFormatFlags =
+    DecklinkDetectedVideoInputFormatFlags::YCBCR_422;
+const CHANGED: DecklinkVideoInputFormatChangedEvents =
+    DecklinkVideoInputFormatChangedEvents::DISPLAY_MODE_CHANGED;
+
+/// A settle of an input enabled in HD1080p25 at `start`, with a timeout of 100ms for each phase.
+fn settle(start: Instant, config: SettleConfig) -> DetectionSettle {
+    let timeout = Some(Duration::from_millis(100));
+    let config = SettleConfig {
+        timeouts: SettleTimeouts {
+            waiting_for_signal: timeout,
+            detecting_format: timeout,
+            reconfiguring: timeout,
+            stabilizing: timeout,
+        },
+        ..config
+    };
+    DetectionSettle::new(config, HD1080p25, FLAGS, start)
+}
+
+fn states(settle: &mut DetectionSettle) -> Vec<SettleState> {
+    settle
+        .take_progress()
+        .into_iter()
+        .map(|progress| progress.state)
+        .collect()
+}
+
+fn stabilizing(seen: u32, needed: u32) -> SettleState {
+    SettleState::Stabilizing { seen, needed }
+}
+
+fn settled(mode: DecklinkDisplayModeId) -> SettleState {
+    SettleState::Settled {
+        mode,
+        format: FLAGS,
+    }
+}
+
+#[test]
+fn an_immediate_signal_settles_in_the_enabled_mode() {
+    let start = Instant::now();
+    let ms = |ms| start + Duration::from_millis(ms);
+    let mut settle = settle(start, SettleConfig::default());
+    assert_eq!(settle.state().phase(), Some(SettlePhase::WaitingForSignal));
+
+    for n in 1..=3 {
+        assert_eq!(settle.frame(true, ms(n * 20)), None);
+    }
+    assert_eq!(settle.state(), settled(HD1080p25));
+    assert!(settle.state().is_finished());
+
+    let progress = settle.take_progress();
+    let elapsed: Vec<_> = progress.iter().map(|p| p.elapsed.as_millis()).collect();
+    assert_eq!(elapsed, [0, 20, 40, 60]);
+    let states: Vec<_> = progress.into_iter().map(|p| p.state).collect();
+    assert_eq!(
+        states,
+        [
+            SettleState::WaitingForSignal,
+            stabilizing(1, 3),
+            stabilizing(2, 3),
+            settled(HD1080p25),
+        ]
+    );
+
+    // Nothing more is acted on once settled
+    assert_eq!(
+        settle.format_changed(CHANGED, HD1080p5994, FLAGS, ms(80)),
+        None
+    );
+    assert_eq!(settle.frame(false, ms(100)), None);
+    assert_eq!(settle.cancel(ms(120)), None);
+    assert!(settle.take_progress().is_empty());
+}
+
+#[test]
+fn a_late_signal_waits_then_starts_the_count_over_when_lost() {
+    let start = Instant::now();
+    let ms = |ms| start + Duration::from_millis(ms);
+    let mut settle = settle(start, SettleConfig::default());
+
+    for n in 1..=4 {
+        assert_eq!(settle.frame(false, ms(n * 20)), None);
+    }
+    // Frames without a signal are waited through, within the timeout of the phase
+    assert_eq!(settle.state(), SettleState::WaitingForSignal);
+
+    settle.frame(true, ms(90));
+    settle.frame(true, ms(110));
+    settle.frame(false, ms(130));
+    assert_eq!(settle.state(), SettleState::WaitingForSignal);
+    for n in 0..3 {
+        settle.frame(true, ms(150 + n * 20));
+    }
+    assert_eq!(
+        states(&mut settle),
+        [
+            SettleState::WaitingForSignal,
+            stabilizing(1, 3),
+            stabilizing(2, 3),
+            SettleState::WaitingForSignal,
+            stabilizing(1, 3),
+            stabilizing(2, 3),
+            settled(HD1080p25),
+        ]
+    );
+}
+
+#[test]
+fn a_format_change_reenables_then_stabilizes_in_the_new_mode() {
+    let start = Instant::now();
+    let ms = |ms| start + Duration::from_millis(ms);
+    let config = SettleConfig {
+        stable_frames_after_change: 2,
+        quirks: QuirkPolicy::Off,
+        ..SettleConfig::default()
+    };
+    let mut settle = settle(start, config);
+
+    assert_eq!(
+        settle.format_changed(CHANGED, HD1080p5994, FLAGS, ms(10)),
+        Some(SettleAction::Reenable {
+            mode: HD1080p5994,
+            flags: FLAGS,
+        })
+    );
+    // The input is still in the old mode until it is re-enabled
+    assert_eq!(settle.enabled_mode(), HD1080p25);
+    assert_eq!(settle.frame(true, ms(20)), None);
+    settle.reconfigured(HD1080p5994, FLAGS, ms(30));
+    assert_eq!(settle.enabled_mode(), HD1080p5994);
+    settle.frame(true, ms(40));
+    settle.frame(true, ms(50));
+    assert_eq!(
+        states(&mut settle),
+        [
+            SettleState::WaitingForSignal,
+            SettleState::DetectingFormat,
+            SettleState::Reconfiguring {
+                mode: HD1080p5994,
+                flags: FLAGS,
+            },
+            stabilizing(0, 2),
+            stabilizing(1, 2),
+            settled(HD1080p5994),
+        ]
+    );
+}
+
+#[test]
+fn without_reconfiguring_a_change_goes_straight_to_stabilizing() {
+    let start = Instant::now();
+    let ms = |ms| start + Duration::from_millis(ms);
+    let config = SettleConfig {
+        stable_frames_after_change: 1,
+        quirks: QuirkPolicy::Off,
+        reconfigure: false,
+        ..SettleConfig::default()
+    };
+    let mut settle = settle(start, config);
+
+    assert_eq!(
+        settle.format_changed(CHANGED, HD1080p5994, FLAGS, ms(10)),
+        None
+    );
+    assert_eq!(settle.state(), stabilizing(0, 1));
+    settle.frame(true, ms(20));
+    assert_eq!(settle.state(), settled(HD1080p5994));
+}
+
+#[test]
+fn flapping_is_debounced_by_the_quirk_rules() {
+    let start = Instant::now();
+    let ms = |ms| start + Duration::from_millis(ms);
+    let config = SettleConfig {
+        stable_frames_after_change: 2,
+        ..SettleConfig::default()
+    };
+    let mut settle = settle(start, config);
+
+    // The first change is acted on at once
+    let action = settle.format_changed(CHANGED, HD1080p5994, FLAGS, ms(0));
+    assert!(matches!(
+        action,
+        Some(SettleAction::Reenable {
+            mode: HD1080p5994,
+            ..
+        })
+    ));
+    settle.reconfigured(HD1080p5994, FLAGS, ms(1));
+    settle.frame(true, ms(2));
+
+    // Changing back soon after is flapping at the start, which waits the debounce out
+    assert_eq!(
+        settle.format_changed(CHANGED, HD1080p25, FLAGS, ms(3)),
+        None
+    );
+    assert_eq!(settle.state(), SettleState::DetectingFormat);
+    settle.frame(true, ms(4));
+    assert_eq!(settle.state(), SettleState::DetectingFormat);
+    let applied: Vec<_> = settle
+        .detector_mut()
+        .take_applied()
+        .into_iter()
+        .map(|applied| applied.rule)
+        .collect();
+    assert_eq!(applied, ["flapping-at-start"]);
+
+    // And a change back to the enabled mode leaves it as it is
+    assert_eq!(
+        settle.format_changed(CHANGED, HD1080p5994, FLAGS, ms(5)),
+        None
+    );
+    assert_eq!(settle.state(), stabilizing(0, 2));
+    settle.frame(true, ms(6));
+    settle.frame(true, ms(7));
+    assert_eq!(settle.state(), settled(HD1080p5994));
+}
+
+#[test]
+fn each_phase_times_out_on_its_own_timeout() {
+    let start = Instant::now();
+    let ms = |ms| start + Duration::from_millis(ms);
+    let timed_out = |phase| SettleState::Failed {
+        reason: SettleFailure::Timeout(phase),
+    };
+    let config = SettleConfig {
+        debounce_frames: 5,
+        quirks: QuirkPolicy::Off,
+        on_abandon: AbandonPolicy::Disabled,
+        ..SettleConfig::default()
+    };
+
+    let mut settle = settle(start, config.clone());
+    assert_eq!(settle.poll(ms(100)), None);
+    assert_eq!(settle.poll(ms(101)), Some(SettleAction::Disable));
+    assert_eq!(settle.state(), timed_out(SettlePhase::WaitingForSignal));
+
+    let mut settle = self::settle(start, config.clone());
+    settle.format_changed(CHANGED, HD1080p5994, FLAGS, ms(50));
+    assert_eq!(settle.frame(true, ms(150)), None);
+    assert_eq!(settle.frame(true, ms(151)), Some(SettleAction::Disable));
+    assert_eq!(settle.state(), timed_out(SettlePhase::DetectingFormat));
+
+    let mut settle = self::settle(start, config.clone());
+    settle.format_changed(CHANGED, HD1080p5994, FLAGS, ms(0));
+    for n in 1..=5 {
+        settle.frame(true, ms(n));
+    }
+    assert!(matches!(settle.state(), SettleState::Reconfiguring { .. }));
+    assert_eq!(settle.poll(ms(105)), None);
+    assert_eq!(settle.poll(ms(106)), Some(SettleAction::Disable));
+    assert_eq!(settle.state(), timed_out(SettlePhase::Reconfiguring));
+
+    // The count of the stabilizing phase does not restart its timeout
+    let mut settle = self::settle(
+        start,
+        SettleConfig {
+            stable_frames: 10,
+            ..config
+        },
+    );
+    settle.frame(true, ms(10));
+    for n in 1..=4 {
+        settle.frame(true, ms(10 + n * 25));
+    }
+    assert_eq!(settle.state(), stabilizing(5, 10));
+    assert_eq!(settle.frame(true, ms(111)), Some(SettleAction::Disable));
+    assert_eq!(settle.state(), timed_out(SettlePhase::Stabilizing));
+}
+
+#[test]
+fn no_timeout_never_fails() {
+    let start = Instant::now();
+    let config = SettleConfig::default();
+    let mut settle = DetectionSettle::new(config, HD1080p25, FLAGS, start);
+    assert_eq!(settle.poll(start + Duration::from_secs(3600)), None);
+    assert_eq!(settle.state(), SettleState::WaitingForSignal);
+}
+
+/// Drive a settle of an input enabled in HD1080p25 into `phase`, and give the action on
+/// cancelling it.
+fn cancel_in(
+    phase: SettlePhase,
+    on_abandon: AbandonPolicy,
+) -> (Option<SettleAction>, DetectionSettle) {
+    let start = Instant::now();
+    let ms = |ms| start + Duration::from_millis(ms);
+    let config = SettleConfig {
+        stable_frames: 1,
+        stable_frames_after_change: 2,
+        debounce_frames: 2,
+        quirks: QuirkPolicy::Off,
+        on_abandon,
+        ..SettleConfig::default()
+    };
+    let mut settle = DetectionSettle::new(config, HD1080p25, FLAGS, start);
+    let other = HD1080p5994;
+    match phase {
+        SettlePhase::WaitingForSignal => {}
+        SettlePhase::DetectingFormat => {
+            settle.format_changed(CHANGED, other, FLAGS, ms(10));
+        }
+        SettlePhase::Reconfiguring => {
+            settle.format_changed(CHANGED, other, FLAGS, ms(10));
+            settle.frame(true, ms(11));
+            settle.frame(true, ms(12));
+        }
+        SettlePhase::Stabilizing => {
+            settle.format_changed(CHANGED, other, FLAGS, ms(10));
+            settle.frame(true, ms(11));
+            settle.frame(true, ms(12));
+            settle.reconfigured(other, FLAGS, ms(13));
+        }
+    }
+    assert_eq!(settle.state().phase(), Some(phase));
+    let action = settle.cancel(ms(20));
+    assert_eq!(
+        settle.state(),
+        SettleState::Failed {
+            reason: SettleFailure::Cancelled,
+        }
+    );
+    (action, settle)
+}
+
+const PHASES: [SettlePhase; 4] = [
+    SettlePhase::WaitingForSignal,
+    SettlePhase::DetectingFormat,
+    SettlePhase::Reconfiguring,
+    SettlePhase::Stabilizing,
+];
+
+#[test]
+fn cancelling_in_each_phase_disables_the_input_under_the_disabled_policy() {
+    for phase in PHASES {
+        let (action, _) = cancel_in(phase, AbandonPolicy::Disabled);
+        assert_eq!(action, Some(SettleAction::Disable), "{phase:?}");
+    }
+}
+
+#[test]
+fn cancelling_in_each_phase_keeps_the_starting_mode_when_none_settled() {
+    for phase in PHASES {
+        let (action, settle) = cancel_in(phase, AbandonPolicy::KeepLastGood);
+        let expected = match phase {
+            // Re-enabled in the new mode, so back to the one it started in
+            SettlePhase::Stabilizing => Some(SettleAction::Reenable {
+                mode: HD1080p25,
+                flags: FLAGS,
+            }),
+            // Still in the mode it started in
+            _ => None,
+        };
+        assert_eq!(action, expected, "{phase:?}");
+        assert_eq!(settle.enabled_mode(), HD1080p25, "{phase:?}");
+    }
+}
+
+#[test]
+fn an_unknown_starting_mode_is_not_reenabled() {
+    let start = Instant::now();
+    let config = SettleConfig {
+        quirks: QuirkPolicy::Off,
+        ..SettleConfig::default()
+    };
+    let mut settle = DetectionSettle::new(
+        config,
+        DecklinkDisplayModeId::Unknown,
+        DecklinkDetectedVideoInputFormatFlags::empty(),
+        start,
+    );
+    let action = settle.format_changed(CHANGED, HD1080p5994, FLAGS, start);
+    assert!(matches!(action, Some(SettleAction::Reenable { .. })));
+    settle.reconfigured(HD1080p5994, FLAGS, start);
+    assert_eq!(settle.cancel(start), None);
+}
+
+#[cfg(feature = "mock-backend")]
+mod mock {
+    use super::common;
+    use super::*;
+    use decklink::device::get_devices;
+    use decklink::device::input::{
+        DeckLinkInputCallback, DecklinkInputDevice, DecklinkVideoInputFlags,
+    };
+    use decklink::frame::{
+        DecklinkFrameBase, DecklinkFrameFlags, DecklinkPixelFormat, DecklinkVideoFrame,
+    };
+    use decklink::mock::{MockBackend, MockFrame, MockInput};
+    use std::sync::{Arc, Mutex};
+
+    const FORMAT: DecklinkPixelFormat = DecklinkPixelFormat::Format8BitYUV;
+    const DETECT: DecklinkVideoInputFlags = DecklinkVideoInputFlags::ENABLE_FORMAT_DETECTION;
+
+    /// Feeds the callbacks of an input to a settle, keeping the actions it returns for the
+    /// test to carry out.
+    struct Settling {
+        settle: Mutex<DetectionSettle>,
+        actions: Mutex<Vec<SettleAction>>,
+    }
+
+    impl Settling {
+        fn keep(&self, action: Option<SettleAction>) {
+            self.actions.lock().unwrap().extend(action);
+        }
+
+        /// Carry out the actions so far on `input`, as the caller of a settle does.
+        fn carry_out(&self, input: &mut DecklinkInputDevice) {
+            for action in std::mem::take(&mut *self.actions.lock().unwrap()) {
+                match action {
+                    SettleAction::Reenable { mode, flags } => {
+                        input.stop_streams().unwrap();
+                        input.disable_video_input().unwrap();
+                        input.enable_video_input(mode, FORMAT, DETECT).unwrap();
+                        input.start_streams().unwrap();
+                        self.settle
+                            .lock()
+                            .unwrap()
+                            .reconfigured(mode, flags, Instant::now());
+                    }
+                    SettleAction::Disable => {
+                        input.stop_streams().unwrap();
+                        input.disable_video_input().unwrap();
+                    }
+                }
+            }
+        }
+
+        fn state(&self) -> SettleState {
+            self.settle.lock().unwrap().state()
+        }
+    }
+
+    impl DeckLinkInputCallback for Settling {
+        fn video_input_format_changed(
+            &self,
+            events: DecklinkVideoInputFormatChangedEvents,
+            new_display_mode: DecklinkDisplayModeId,
+            detected_signal_flags: DecklinkDetectedVideoInputFormatFlags,
+        ) {
+            let action = self.settle.lock().unwrap().format_changed(
+                events,
+                new_display_mode,
+                detected_signal_flags,
+                Instant::now(),
+            );
+            self.keep(action);
+        }
+
+        fn video_input_frame_arrived(&self, video_frame: Option<DecklinkVideoFrame>) -> bool {
+            let has_signal = video_frame.is_some_and(|frame| {
+                !frame
+                    .flags()
+                    .contains(DecklinkFrameFlags::HAS_NO_INPUT_SOURCE)
+            });
+            let action = self
+                .settle
+                .lock()
+                .unwrap()
+                .frame(has_signal, Instant::now());
+            self.keep(action);
+            true
+        }
+    }
+
+    fn config(on_abandon: AbandonPolicy) -> SettleConfig {
+        SettleConfig {
+            stable_frames: 2,
+            stable_frames_after_change: 2,
+            quirks: QuirkPolicy::Off,
+            on_abandon,
+            ..SettleConfig::default()
+        }
+    }
+
+    fn start(
+        backend: &MockBackend,
+        config: SettleConfig,
+    ) -> (DecklinkInputDevice, MockInput, Arc<Settling>) {
+        let devices = get_devices().unwrap();
+        let mut input = devices[0].input().unwrap();
+        input.enable_video_input(HD1080p25, FORMAT, DETECT).unwrap();
+        let settling = Arc::new(Settling {
+            settle: Mutex::new(DetectionSettle::new(
+                config,
+                HD1080p25,
+                FLAGS,
+                Instant::now(),
+            )),
+            actions: Mutex::new(Vec::new()),
+        });
+        common::start_streams(&mut input, settling.clone());
+        (input, backend.input(0), settling)
+    }
+
+    fn change_to(mock: &MockInput, mode: DecklinkDisplayModeId) {
+        assert!(mock.deliver_format_change(CHANGED, mode, FLAGS).is_ok());
+    }
+
+    fn frames(mock: &MockInput, mode: DecklinkDisplayModeId, count: usize) {
+        for _ in 0..count {
+            assert!(mock
+                .deliver_frame(MockFrame::for_mode(mode, FORMAT))
+                .is_ok());
+        }
+    }
+
+    fn enabled_mode(mock: &MockInput) -> Option<DecklinkDisplayModeId> {
+        mock.video().map(|(mode, _, _)| mode)
+    }
+
+    #[test]
+    fn an_input_with_a_signal_settles_as_enabled() {
+        let backend = common::mini_recorder();
+        let (mut input, mock, settling) = start(&backend, config(AbandonPolicy::KeepLastGood));
+        frames(&mock, HD1080p25, 2);
+        settling.carry_out(&mut input);
+        assert_eq!(settling.state(), settled(HD1080p25));
+        assert_eq!(enabled_mode(&mock), Some(HD1080p25));
+        assert!(mock.is_streaming());
+    }
+
+    #[test]
+    fn a_late_signal_in_another_mode_settles_in_it() {
+        let backend = common::mini_recorder();
+        let (mut input, mock, settling) = start(&backend, config(AbandonPolicy::KeepLastGood));
+        for _ in 0..3 {
+            assert!(mock.deliver_frame(mock.frame().no_signal()).is_ok());
+        }
+        assert_eq!(settling.state(), SettleState::WaitingForSignal);
+        change_to(&mock, HD1080p5994);
+        settling.carry_out(&mut input);
+        assert_eq!(enabled_mode(&mock), Some(HD1080p5994));
+        frames(&mock, HD1080p5994, 2);
+        assert_eq!(settling.state(), settled(HD1080p5994));
+        assert!(mock.is_streaming());
+    }
+
+    /// Cancel a settle of an input re-enabled in HD1080p5994 while it stabilizes.
+    fn cancel_while_stabilizing(
+        input: &mut DecklinkInputDevice,
+        mock: &MockInput,
+        settling: &Settling,
+    ) {
+        change_to(mock, HD1080p5994);
+        settling.carry_out(input);
+        frames(mock, HD1080p5994, 1);
+        assert_eq!(settling.state(), stabilizing(1, 2));
+
+        let action = settling.settle.lock().unwrap().cancel(Instant::now());
+        settling.keep(action);
+        settling.carry_out(input);
+        assert_eq!(
+            settling.state(),
+            SettleState::Failed {
+                reason: SettleFailure::Cancelled,
+            }
+        );
+    }
+
+    #[test]
+    fn cancelling_keeps_the_last_good_mode() {
+        let backend = common::mini_recorder();
+        let (mut input, mock, settling) = start(&backend, config(AbandonPolicy::KeepLastGood));
+        cancel_while_stabilizing(&mut input, &mock, &settling);
+        assert_eq!(enabled_mode(&mock), Some(HD1080p25));
+        assert!(mock.is_streaming());
+    }
+
+    #[test]
+    fn cancelling_disables_the_input() {
+        let backend = common::mini_recorder();
+        let (mut input, mock, settling) = start(&backend, config(AbandonPolicy::Disabled));
+        cancel_while_stabilizing(&mut input, &mock, &settling);
+        assert_eq!(mock.video(), None);
+        assert!(!mock.is_streaming());
+    }
+
+    #[test]
+    fn a_timeout_leaves_the_input_as_the_policy_says() {
+        let backend = common::mini_recorder();
+        let config = SettleConfig {
+            timeouts: SettleTimeouts {
+                waiting_for_signal: Some(Duration::from_millis(50)),
+                ..SettleTimeouts::default()
+            },
+            ..config(AbandonPolicy::Disabled)
+        };
+        let (mut input, mock, settling) = start(&backend, config);
+        assert!(mock.deliver_frame(mock.frame().no_signal()).is_ok());
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(mock.deliver_frame(mock.frame().no_signal()).is_ok());
+        settling.carry_out(&mut input);
+        assert_eq!(
+            settling.state(),
+            SettleState::Failed {
+                reason: SettleFailure::Timeout(SettlePhase::WaitingForSignal),
+            }
+        );
+        assert_eq!(mock.video(), None);
+    }
+}
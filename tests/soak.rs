@@ -0,0 +1,302 @@
+//! The invariants of a soak run, broken one at a time by hand-made samples, and short soak
+//! runs on the mock backend.
+
+use decklink::soak::{InvariantChecker, SoakSample, SoakThresholds, SoakViolation};
+use std::time::Duration;
+
+const INTERVAL: Duration = Duration::from_secs(10);
+const MIB: u64 = 1024 * 1024;
+
+fn thresholds() -> SoakThresholds {
+    SoakThresholds {
+        max_frame_rate: Some(37.5),
+        trend_window: 40,
+        min_trend_samples: 8,
+        ..SoakThresholds::default()
+    }
+}
+
+fn checker() -> InvariantChecker {
+    InvariantChecker::new(thresholds(), INTERVAL * 3)
+}
+
+/// The sample of a healthy run at 25 frames a second.
+fn sample(index: u64) -> SoakSample {
+    SoakSample {
+        index,
+        elapsed: INTERVAL * index as u32,
+        frames: 250 * index,
+        live_objects: Some(40),
+        retained_frames: 3,
+        retention_limit: 4,
+        rss_bytes: Some(200 * MIB),
+        latency_p99: Duration::from_micros(200),
+        events_kept: 10,
+        event_capacity: 256,
+        ..SoakSample::default()
+    }
+}
+
+/// Check `count` healthy samples, then `broken` as the next, returning its violation.
+fn violation_after(count: u64, broken: impl Fn(&mut SoakSample)) -> SoakViolation {
+    let mut checker = checker();
+    for index in 0..count {
+        checker.check(&sample(index)).unwrap();
+    }
+    let mut last = sample(count);
+    broken(&mut last);
+    checker.check(&last).unwrap_err()
+}
+
+#[test]
+fn a_healthy_run_breaks_no_invariant() {
+    let mut checker = checker();
+    for index in 0..200 {
+        let mut sample = sample(index);
+        // Noise that is no trend
+        sample.rss_bytes = Some(200 * MIB + (index % 3) * MIB);
+        sample.live_objects = Some(40 + (index % 5) as usize);
+        checker.check(&sample).unwrap();
+    }
+    // The first sample after the warmup
+    assert_eq!(checker.live_baseline(), Some(43));
+}
+
+#[test]
+fn live_objects_are_compared_with_the_count_after_the_warmup() {
+    let mut checker = checker();
+    // Objects created during the warmup are not counted against the run
+    let mut warming = sample(0);
+    warming.live_objects = Some(500);
+    checker.check(&warming).unwrap();
+    for index in 1..10 {
+        checker.check(&sample(index)).unwrap();
+    }
+    let mut leaking = sample(10);
+    leaking.live_objects = Some(40 + 33);
+    assert_eq!(
+        checker.check(&leaking),
+        Err(SoakViolation::LiveObjectsGrew {
+            baseline: 40,
+            live: 73
+        })
+    );
+}
+
+#[test]
+fn retaining_past_the_budget_is_a_violation() {
+    let violation = violation_after(5, |s| s.retained_frames = 5);
+    assert_eq!(
+        violation,
+        SoakViolation::RetentionExceeded {
+            retained: 5,
+            limit: 4
+        }
+    );
+}
+
+#[test]
+fn a_refused_frame_is_a_violation() {
+    let violation = violation_after(5, |s| s.refused_frames = 1);
+    assert_eq!(violation, SoakViolation::FramesRefused { refused: 1 });
+}
+
+#[test]
+fn a_counter_going_back_is_a_violation() {
+    let violation = violation_after(5, |s| s.frames = 900);
+    assert_eq!(
+        violation,
+        SoakViolation::CounterWentBack {
+            counter: "frames".to_string(),
+            previous: 1000,
+            current: 900
+        }
+    );
+}
+
+#[test]
+fn frames_arriving_too_fast_are_a_violation() {
+    let violation = violation_after(5, |s| s.frames += 500);
+    match violation {
+        SoakViolation::CounterTooFast {
+            counter,
+            per_second,
+            limit,
+        } => {
+            assert_eq!(counter, "frames");
+            assert_eq!(per_second, 75.0);
+            assert_eq!(limit, 37.5);
+        }
+        v => panic!("unexpected violation {:?}", v),
+    }
+}
+
+#[test]
+fn memory_growing_faster_than_the_slope_is_a_violation() {
+    let mut checker = checker();
+    let mut result = Ok(());
+    for index in 0..40 {
+        let mut sample = sample(index);
+        // A megabyte every sample is 360 MiB an hour
+        sample.rss_bytes = Some(200 * MIB + index * MIB);
+        result = checker.check(&sample);
+        if result.is_err() {
+            break;
+        }
+    }
+    match result {
+        Err(SoakViolation::MemoryGrowth {
+            bytes_per_hour,
+            limit,
+        }) => {
+            assert!((bytes_per_hour - 360.0 * MIB as f64).abs() < MIB as f64);
+            assert_eq!(limit, 64 * MIB);
+        }
+        r => panic!("unexpected result {:?}", r),
+    }
+}
+
+#[test]
+fn latency_trending_upward_is_a_violation() {
+    let mut checker = checker();
+    let mut result = Ok(());
+    for index in 0..40 {
+        let mut sample = sample(index);
+        sample.latency_p99 = Duration::from_millis(1 + index);
+        result = checker.check(&sample);
+        if result.is_err() {
+            break;
+        }
+    }
+    assert!(
+        matches!(result, Err(SoakViolation::LatencyTrend { earlier_p99, recent_p99 })
+            if recent_p99 > earlier_p99 * 2),
+        "{:?}",
+        result
+    );
+}
+
+#[test]
+fn latency_under_the_floor_is_no_trend() {
+    let mut checker = checker();
+    for index in 0..40 {
+        let mut sample = sample(index);
+        sample.latency_p99 = Duration::from_micros(10 + 20 * index);
+        checker.check(&sample).unwrap();
+    }
+}
+
+#[test]
+fn an_event_ring_over_its_capacity_is_a_violation() {
+    let violation = violation_after(5, |s| s.events_kept = 257);
+    assert_eq!(
+        violation,
+        SoakViolation::EventRingGrew {
+            kept: 257,
+            capacity: 256
+        }
+    );
+}
+
+#[test]
+fn a_thread_panicking_during_the_run_is_a_violation() {
+    let mut checker = checker();
+    // Threads that panicked before the run are not its failure
+    let mut first = sample(0);
+    first.threads_panicked = 2;
+    checker.check(&first).unwrap();
+    let mut next = sample(1);
+    next.threads_panicked = 3;
+    assert_eq!(
+        checker.check(&next),
+        Err(SoakViolation::ThreadDied { panicked: 1 })
+    );
+}
+
+#[test]
+fn violations_name_their_invariant() {
+    let violation = violation_after(5, |s| s.refused_frames = 2);
+    assert_eq!(violation.kind_str(), "frames_refused");
+    assert_eq!(
+        violation.to_string(),
+        "the retention budget refused 2 frames while it had room for them"
+    );
+}
+
+#[cfg(feature = "mock-backend")]
+mod mock {
+    use super::*;
+    use decklink::display_mode::DecklinkDisplayModeId;
+    use decklink::mock::MockBackend;
+    use decklink::soak::{run_mock, SoakConfig, SoakProfile};
+
+    fn config(duration: Duration, profile: SoakProfile) -> SoakConfig {
+        let mut config = SoakConfig::new(duration);
+        config.profile = profile;
+        config.mode = DecklinkDisplayModeId::NTSC;
+        config.alternate_mode = DecklinkDisplayModeId::PAL;
+        config
+    }
+
+    #[test]
+    fn a_perturbed_mock_run_passes_and_checkpoints() {
+        let path = std::env::temp_dir().join(format!("decklink-soak-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut config = config(Duration::from_secs(120), SoakProfile::Default);
+        config.sample_interval = Duration::from_secs(5);
+        config.warmup = Duration::from_secs(10);
+        config.perturb_every = Some(Duration::from_secs(15));
+        config.checkpoint = Some(path.clone());
+
+        let summary = run_mock(&config).unwrap();
+        assert!(summary.passed(), "{}", summary.to_json());
+        assert!(summary.mock);
+        assert!(summary.elapsed >= config.duration);
+        assert!(summary.perturbations >= 6, "{}", summary.perturbations);
+        assert!(summary.frames > 2000, "{}", summary.frames);
+        assert!(summary.peak_retained_frames <= 3);
+        assert!(summary.to_json().contains("\"passed\":true"));
+        let effective = summary.effective_config.as_ref().unwrap();
+        assert_eq!(
+            effective.device.as_ref().unwrap().display_name.as_deref(),
+            Some("DeckLink Soak")
+        );
+        assert_eq!(effective.display_mode.actual, Some(config.mode));
+        assert!(summary.to_json().contains(&effective.to_json()));
+        assert_eq!(MockBackend::live_objects(), 0);
+
+        let checkpoint = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<_> = checkpoint.lines().collect();
+        assert!(lines[0].starts_with("{\"entry\":\"start\",\"profile\":\"default\""));
+        assert!(lines[lines.len() - 1].starts_with("{\"entry\":\"summary\""));
+        for kind in ["format_change", "signal_loss", "device_removal"] {
+            let line = format!("\"entry\":\"perturbation\",\"kind\":\"{}\"", kind);
+            assert!(checkpoint.contains(&line), "no {} in {}", kind, checkpoint);
+        }
+        assert_eq!(
+            lines
+                .iter()
+                .filter(|l| l.contains("\"entry\":\"sample\""))
+                .count() as u64,
+            summary.samples
+        );
+        let _ = std::fs::remove_file(&path);
+    }
+
+    /// The soak of the release checklist, for a maintainer to run in release mode.
+    #[test]
+    #[ignore = "runs 8 hours of stream time for each profile"]
+    fn eight_hour_perturbed_mock_soak() {
+        for profile in [
+            SoakProfile::Default,
+            SoakProfile::LowLatency,
+            SoakProfile::Allocator,
+        ] {
+            let mut config = config(Duration::from_secs(8 * 3600), profile);
+            config.perturb_every = Some(Duration::from_secs(60));
+            let summary = run_mock(&config).unwrap();
+            println!("{}", summary.to_json());
+            assert!(summary.passed(), "{}", summary.to_json());
+        }
+    }
+}
@@ -0,0 +1,259 @@
+//! Exporting synthetic ramps through each colour policy, checked at anchor points.
+
+#![cfg(feature = "image-interop")]
+
+use decklink::colorimetry::{Colorimetry, TransferFunction};
+use decklink::convert::rgb16_to_r210;
+use decklink::display_mode::Ratio;
+use decklink::frame::DecklinkPixelFormat;
+use decklink::image_interop::{ConversionPath, DecklinkFrameImageExt};
+use decklink::still::{
+    cicp, encode_png, encode_png_with_aspect, to_sdr_image, tone_map, ExportColorPolicy,
+    PixelAspectPolicy, SourceColor, StillExport, ToneMapOperator,
+};
+use decklink::testing::{FillPattern, TestFrame, TestFrameBuilder};
+
+/// The 10-bit codes of the grey ramp: black, 50%, the 75% of HLG reference white, and white.
+const RAMP: [u16; 4] = [64, 502, 721, 940];
+
+/// A one line `r210` frame of grey at each code of `RAMP`.
+fn ramp() -> TestFrame {
+    let components: Vec<u16> = RAMP.iter().flat_map(|&code| [code; 3]).collect();
+    let mut bytes = vec![0; RAMP.len() * 4];
+    rgb16_to_r210(&components, &mut bytes).unwrap();
+    TestFrameBuilder::new(RAMP.len(), 1)
+        .pixel_format(DecklinkPixelFormat::Format10BitRGB)
+        .fill(FillPattern::Bytes(bytes))
+        .build()
+        .unwrap()
+}
+
+/// The 8-bit code of grey `signal` tone mapped from `source`.
+fn sdr_code(signal: f32, source: SourceColor, operator: ToneMapOperator) -> u8 {
+    let rgb = tone_map([signal; 3], source, operator);
+    assert!(
+        rgb.iter().all(|c| (c - rgb[0]).abs() < 1e-3),
+        "grey stays grey"
+    );
+    (rgb[0] * 255.0).round() as u8
+}
+
+/// The data of the chunk of `kind` in `png`, and its position among the chunks.
+fn chunk<'a>(png: &'a [u8], kind: &[u8; 4]) -> Option<(usize, &'a [u8])> {
+    let mut at = 8;
+    let mut index = 0;
+    while at + 8 <= png.len() {
+        let length = u32::from_be_bytes(png[at..at + 4].try_into().unwrap()) as usize;
+        if &png[at + 4..at + 8] == kind {
+            return Some((index, &png[at..at + 12 + length]));
+        }
+        at += 12 + length;
+        index += 1;
+    }
+    None
+}
+
+#[test]
+fn sixteen_bit_intermediate_keeps_ten_bits() {
+    let image = ramp().to_rgb16_image_with(Colorimetry::Rec709).unwrap();
+    let values: Vec<u16> = image.pixels().map(|p| p.0[0]).collect();
+    // (code - 64) / 876 of 65535
+    assert_eq!(values, [0, 32768, 49151, 65535]);
+}
+
+#[test]
+fn hlg_reference_white_through_each_operator() {
+    let hlg = SourceColor::hlg();
+    assert_eq!(sdr_code(0.75, hlg, ToneMapOperator::Clip), 255);
+    assert_eq!(sdr_code(0.75, hlg, ToneMapOperator::Reinhard), 194);
+    assert_eq!(sdr_code(0.75, hlg, ToneMapOperator::Bt2408), 230);
+}
+
+#[test]
+fn pq_anchors_through_each_operator() {
+    let pq = SourceColor::pq();
+    // 92 cd/m², below the knee of the EETF
+    assert_eq!(sdr_code(0.5, pq, ToneMapOperator::Clip), 184);
+    assert_eq!(sdr_code(0.5, pq, ToneMapOperator::Reinhard), 158);
+    assert_eq!(sdr_code(0.5, pq, ToneMapOperator::Bt2408), 184);
+    // 1000 cd/m², the peak every operator maps to white
+    for operator in [
+        ToneMapOperator::Clip,
+        ToneMapOperator::Reinhard,
+        ToneMapOperator::Bt2408,
+    ] {
+        assert_eq!(sdr_code(0.7518, pq, operator), 255);
+        assert_eq!(sdr_code(0.0, pq, operator), 0);
+    }
+}
+
+#[test]
+fn hlg_ramp_is_tone_mapped() {
+    let frame = ramp();
+    let codes = |operator| -> Vec<u8> {
+        to_sdr_image(&frame, SourceColor::hlg(), operator)
+            .unwrap()
+            .pixels()
+            .map(|p| p.0[0])
+            .collect()
+    };
+    assert_eq!(codes(ToneMapOperator::Clip), [0, 143, 255, 255]);
+    assert_eq!(codes(ToneMapOperator::Reinhard), [0, 131, 194, 255]);
+    assert_eq!(codes(ToneMapOperator::Bt2408), [0, 143, 230, 255]);
+
+    let (png, export) = encode_png(
+        &frame,
+        SourceColor::hlg(),
+        ExportColorPolicy::ToneMap(ToneMapOperator::Reinhard),
+    )
+    .unwrap();
+    assert_eq!(
+        export,
+        StillExport {
+            path: ConversionPath::Rgb10,
+            tone_mapped: Some(ToneMapOperator::Reinhard),
+            primaries_converted: true,
+            cicp: None,
+            pixel_aspect: Ratio::SQUARE,
+        }
+    );
+    assert!(chunk(&png, b"cICP").is_none());
+    let decoded = image::load_from_memory(&png).unwrap().to_rgb8();
+    assert_eq!(decoded.get_pixel(2, 0).0, [194; 3]);
+}
+
+#[test]
+fn sdr_rec709_is_converted_as_before() {
+    let frame = ramp();
+    let sdr = SourceColor {
+        colorimetry: Colorimetry::Rec709,
+        transfer: TransferFunction::Sdr,
+    };
+    assert_eq!(
+        to_sdr_image(&frame, sdr, ToneMapOperator::default()).unwrap(),
+        frame.to_rgb_image_with(Colorimetry::Rec709).unwrap()
+    );
+}
+
+#[test]
+fn sdr_export_reports_no_tone_mapping() {
+    let frame = ramp();
+    let (_, export) = encode_png(
+        &frame,
+        SourceColor::sdr_for_height(1080),
+        ExportColorPolicy::default(),
+    )
+    .unwrap();
+    assert_eq!(
+        export,
+        StillExport {
+            path: ConversionPath::Rgb10,
+            tone_mapped: None,
+            primaries_converted: false,
+            cicp: None,
+            pixel_aspect: Ratio::SQUARE,
+        }
+    );
+}
+
+#[test]
+fn rec2020_primaries_are_converted() {
+    let sdr2020 = SourceColor {
+        colorimetry: Colorimetry::Rec2020,
+        transfer: TransferFunction::Sdr,
+    };
+    // Grey is unchanged, and Rec. 2020 green is outside Rec. 709, so is clipped
+    assert_eq!(
+        tone_map([0.5; 3], sdr2020, ToneMapOperator::Clip).map(|c| (c * 255.0).round() as u8),
+        [128; 3]
+    );
+    assert_eq!(
+        tone_map([0.0, 1.0, 0.0], sdr2020, ToneMapOperator::Clip),
+        [0.0, 1.0, 0.0]
+    );
+}
+
+#[test]
+fn h273_code_points() {
+    assert_eq!(Colorimetry::Rec601.h273(576), (5, 6));
+    assert_eq!(Colorimetry::Rec601.h273(486), (6, 6));
+    assert_eq!(Colorimetry::Rec709.h273(1080), (1, 1));
+    assert_eq!(Colorimetry::Rec2020.h273(2160), (9, 9));
+    assert_eq!(TransferFunction::Sdr.h273(), 1);
+    assert_eq!(TransferFunction::Pq.h273(), 16);
+    assert_eq!(TransferFunction::Hlg.h273(), 18);
+}
+
+#[test]
+fn cicp_chunk_bytes() {
+    assert_eq!(cicp(SourceColor::pq(), 2160), [9, 16, 0, 1]);
+    assert_eq!(cicp(SourceColor::hlg(), 1080), [9, 18, 0, 1]);
+    assert_eq!(cicp(SourceColor::sdr_for_height(1080), 1080), [1, 1, 0, 1]);
+
+    let frame = ramp();
+    let (png, export) = encode_png(&frame, SourceColor::pq(), ExportColorPolicy::Hdr16).unwrap();
+    assert_eq!(export.cicp, Some([9, 16, 0, 1]));
+    assert_eq!(export.tone_mapped, None);
+    let (index, bytes) = chunk(&png, b"cICP").unwrap();
+    // Just after the header, ahead of the image data
+    assert_eq!(index, 1);
+    assert_eq!(
+        bytes,
+        [0, 0, 0, 4, b'c', b'I', b'C', b'P', 9, 16, 0, 1, 0x4d, 0x23, 0x23, 0xfe]
+    );
+
+    let (png, _) = encode_png(&frame, SourceColor::hlg(), ExportColorPolicy::Hdr16).unwrap();
+    let (_, bytes) = chunk(&png, b"cICP").unwrap();
+    assert_eq!(&bytes[8..], [9, 18, 0, 1, 0x4e, 0xa7, 0xf7, 0x90]);
+
+    // The signal is kept at 16 bits
+    let decoded = image::load_from_memory(&png).unwrap().to_rgb16();
+    let values: Vec<u16> = decoded.pixels().map(|p| p.0[0]).collect();
+    assert_eq!(values, [0, 32768, 49151, 65535]);
+}
+
+#[test]
+fn non_square_pixels_are_tagged_or_resampled() {
+    let frame = TestFrameBuilder::new(33, 2)
+        .pixel_format(DecklinkPixelFormat::Format8BitBGRA)
+        .fill(FillPattern::Solid(0x80))
+        .build()
+        .unwrap();
+    let anamorphic = Ratio::new(40, 33);
+    let source = SourceColor::sdr_for_height(486);
+
+    let (png, export) = encode_png_with_aspect(
+        &frame,
+        source,
+        ExportColorPolicy::default(),
+        PixelAspectPolicy::Tag(anamorphic),
+    )
+    .unwrap();
+    assert_eq!(export.pixel_aspect, anamorphic);
+    let (_, bytes) = chunk(&png, b"pHYs").unwrap();
+    // 33 pixels per unit across, 40 down, and no unit
+    assert_eq!(&bytes[8..17], [0, 0, 0, 33, 0, 0, 0, 40, 0]);
+    let decoded = image::load_from_memory(&png).unwrap();
+    assert_eq!((decoded.width(), decoded.height()), (33, 2));
+
+    let (png, export) = encode_png_with_aspect(
+        &frame,
+        source,
+        ExportColorPolicy::Hdr16,
+        PixelAspectPolicy::Resample(anamorphic),
+    )
+    .unwrap();
+    assert_eq!(export.pixel_aspect, Ratio::SQUARE);
+    assert!(chunk(&png, b"pHYs").is_none());
+    let decoded = image::load_from_memory(&png).unwrap().to_rgb16();
+    assert_eq!(decoded.dimensions(), (40, 2));
+    let first = decoded.get_pixel(0, 0).0;
+    assert!(
+        decoded.pixels().all(|p| p.0 == first),
+        "a flat field stays flat"
+    );
+
+    // Square pixels are neither tagged nor resampled
+    let (png, _) = encode_png(&frame, source, ExportColorPolicy::default()).unwrap();
+    assert!(chunk(&png, b"pHYs").is_none());
+}
@@ -0,0 +1,102 @@
+//! Listing the pixel formats a mock input can capture.
+#![cfg(feature = "mock-backend")]
+
+mod common;
+
+use decklink::device::get_devices;
+use decklink::display_mode::DecklinkDisplayModeId;
+use decklink::frame::DecklinkPixelFormat;
+use decklink::mock::{MockBackend, MockDevice};
+
+#[test]
+fn yuv_only_device() {
+    let _backend = common::mini_recorder();
+    let devices = get_devices().unwrap();
+    let input = devices[0].input().unwrap();
+
+    assert_eq!(
+        input.supported_pixel_formats().unwrap(),
+        vec![
+            DecklinkPixelFormat::Format8BitYUV,
+            DecklinkPixelFormat::Format10BitYUV,
+        ]
+    );
+}
+
+#[test]
+fn device_with_rgb() {
+    let _backend = MockBackend::install(vec![MockDevice::new("DeckLink 4K Extreme")
+        .pixel_formats(&[
+            DecklinkPixelFormat::Format10BitRGB,
+            DecklinkPixelFormat::Format8BitBGRA,
+            DecklinkPixelFormat::Format8BitYUV,
+            DecklinkPixelFormat::Format8BitARGB,
+            DecklinkPixelFormat::Format10BitYUV,
+        ])]);
+    let devices = get_devices().unwrap();
+    let input = devices[0].input().unwrap();
+
+    // In the order the formats are declared
+    assert_eq!(
+        input.supported_pixel_formats().unwrap(),
+        vec![
+            DecklinkPixelFormat::Format8BitYUV,
+            DecklinkPixelFormat::Format10BitYUV,
+            DecklinkPixelFormat::Format8BitARGB,
+            DecklinkPixelFormat::Format8BitBGRA,
+            DecklinkPixelFormat::Format10BitRGB,
+        ]
+    );
+}
+
+#[test]
+fn formats_of_some_modes_are_included() {
+    let _backend = MockBackend::install(vec![MockDevice::new("DeckLink Mini Recorder")
+        .mode_pixel_formats(
+            DecklinkDisplayModeId::NTSC,
+            &[DecklinkPixelFormat::Format8BitARGB],
+        )
+        .mode_pixel_formats(
+            DecklinkDisplayModeId::HD1080p25,
+            &[DecklinkPixelFormat::Format10BitRGB],
+        )]);
+    let devices = get_devices().unwrap();
+    let input = devices[0].input().unwrap();
+
+    assert_eq!(
+        input.supported_pixel_formats().unwrap(),
+        vec![
+            DecklinkPixelFormat::Format8BitYUV,
+            DecklinkPixelFormat::Format10BitYUV,
+            DecklinkPixelFormat::Format8BitARGB,
+            DecklinkPixelFormat::Format10BitRGB,
+        ]
+    );
+}
+
+#[test]
+fn device_without_modes_supports_nothing() {
+    let _backend = MockBackend::install(vec![MockDevice::new("DeckLink Mini Recorder").modes(&[])]);
+    let devices = get_devices().unwrap();
+    let input = devices[0].input().unwrap();
+
+    assert_eq!(input.supported_pixel_formats().unwrap(), Vec::new());
+}
+
+#[test]
+fn probe_is_cached_until_refreshed() {
+    let backend = common::mini_recorder();
+    let devices = get_devices().unwrap();
+    let input = devices[0].input().unwrap();
+    let mock = backend.input(0);
+
+    let formats = input.supported_pixel_formats().unwrap();
+    let queries = mock.support_queries();
+    assert!(queries > 0);
+
+    assert_eq!(input.supported_pixel_formats().unwrap(), formats);
+    assert_eq!(mock.support_queries(), queries);
+
+    assert_eq!(input.refresh_supported_pixel_formats().unwrap(), formats);
+    assert_eq!(mock.support_queries(), 2 * queries);
+}
@@ -0,0 +1,650 @@
+//! Tapping copies of frames captured from a mock input, alongside the primary callback.
+#![cfg(feature = "mock-backend")]
+
+mod common;
+
+use decklink::device::input::{
+    DeckLinkInputCallback, DecklinkDetectedVideoInputFormatFlags, DecklinkInputDevice,
+    DecklinkVideoInputFlags, DecklinkVideoInputFormatChangedEvents,
+};
+use decklink::display_mode::DecklinkDisplayModeId;
+use decklink::frame::{DecklinkFrameBase, DecklinkPixelFormat, DecklinkVideoFrame};
+use decklink::mock::{MockBackend, MockFrame, MockInput};
+use decklink::retention::{RetentionBudget, RetentionLimit, RetentionMode};
+use decklink::tap::{
+    DeckLinkTapCallback, FormatChange, FormatChangePolicy, TapEnd, TapHandle, TapReport, TapSpec,
+    TapSplitter, TappedFrame,
+};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+const MODE: DecklinkDisplayModeId = DecklinkDisplayModeId::HD1080p25;
+const FORMAT: DecklinkPixelFormat = DecklinkPixelFormat::Format8BitYUV;
+
+/// The primary callback, noting the first byte of each frame.
+#[derive(Default)]
+struct Primary {
+    frames: Mutex<Vec<u8>>,
+}
+
+impl DeckLinkInputCallback for Primary {
+    fn video_input_format_changed(
+        &self,
+        _events: DecklinkVideoInputFormatChangedEvents,
+        _new_display_mode: DecklinkDisplayModeId,
+        _detected_signal_flags: DecklinkDetectedVideoInputFormatFlags,
+    ) {
+    }
+
+    fn video_input_frame_arrived(&self, video_frame: Option<DecklinkVideoFrame>) -> bool {
+        let first = video_frame.unwrap().bytes().unwrap().0[0];
+        self.frames.lock().unwrap().push(first);
+        true
+    }
+}
+
+#[derive(PartialEq, Debug)]
+enum Tapped {
+    /// The index and first byte of a tapped frame, and whether it had a timing.
+    Frame(u64, u8, bool),
+    Finished(TapReport),
+}
+
+/// A tap sending what it is given to the test, optionally waiting for the test to release
+/// it before taking its first frame.
+struct Tap {
+    sender: Sender<Tapped>,
+    hold: Option<Receiver<()>>,
+}
+
+impl DeckLinkTapCallback for Tap {
+    fn frame_tapped(&mut self, frame: TappedFrame) {
+        if let Some(hold) = self.hold.take() {
+            let _ = hold.recv();
+        }
+        let first = frame.bytes().unwrap().0[0];
+        let _ = self
+            .sender
+            .send(Tapped::Frame(frame.index, first, frame.timing.is_some()));
+    }
+
+    fn tap_finished(&mut self, report: TapReport) {
+        let _ = self.sender.send(Tapped::Finished(report));
+    }
+}
+
+/// A tap that panics when given the frame filled with `panic_at`, or in `tap_finished` if
+/// there is none.
+struct PanickingTap {
+    tap: Tap,
+    panic_at: Option<u8>,
+}
+
+impl DeckLinkTapCallback for PanickingTap {
+    fn frame_tapped(&mut self, frame: TappedFrame) {
+        if Some(frame.bytes().unwrap().0[0]) == self.panic_at {
+            panic!("tap failed");
+        }
+        self.tap.frame_tapped(frame);
+    }
+
+    fn tap_finished(&mut self, report: TapReport) {
+        if self.panic_at.is_none() {
+            panic!("tap failed to finish");
+        }
+        self.tap.tap_finished(report);
+    }
+}
+
+fn tap() -> (Tap, Receiver<Tapped>) {
+    let (sender, receiver) = channel();
+    (Tap { sender, hold: None }, receiver)
+}
+
+/// A tap that holds its first frame until the returned sender is used or dropped.
+fn held_tap() -> (Tap, Receiver<Tapped>, Sender<()>) {
+    let (sender, receiver) = channel();
+    let (release, hold) = channel();
+    let tap = Tap {
+        sender,
+        hold: Some(hold),
+    };
+    (tap, receiver, release)
+}
+
+fn start(backend: &MockBackend, splitter: &TapSplitter) -> (DecklinkInputDevice, MockInput) {
+    common::start_input(
+        backend,
+        MODE,
+        FORMAT,
+        DecklinkVideoInputFlags::empty(),
+        splitter.callback(),
+    )
+}
+
+/// Deliver frames numbered `frames`, each filled with its number.
+fn deliver(mock: &MockInput, frames: std::ops::RangeInclusive<u8>) {
+    for n in frames {
+        let frame = MockFrame::new(48, 2, FORMAT)
+            .fill(n)
+            .stream_time(n as i64 * 1000, 1000, 25000);
+        assert!(mock.deliver_frame(frame).is_ok());
+    }
+}
+
+/// The frames a tap was given, and its report once it finished.
+fn tapped(receiver: Receiver<Tapped>) -> (Vec<(u64, u8)>, Option<TapReport>) {
+    let mut frames = Vec::new();
+    let mut report = None;
+    for event in receiver.iter() {
+        match event {
+            Tapped::Frame(index, first, timed) => {
+                assert!(timed);
+                frames.push((index, first));
+            }
+            Tapped::Finished(r) => report = Some(r),
+        }
+    }
+    (frames, report)
+}
+
+#[test]
+fn tap_detaches_once_it_has_its_frames() {
+    let backend = common::mini_recorder();
+    let primary = Arc::new(Primary::default());
+    let splitter = TapSplitter::new(primary.clone(), None);
+    let (_input, mock) = start(&backend, &splitter);
+
+    deliver(&mock, 1..=2);
+    let (consumer, receiver) = tap();
+    let handle = splitter.tap(consumer, TapSpec::frames(3));
+    assert_eq!(splitter.active_taps(), 1);
+    deliver(&mock, 3..=8);
+
+    let report = handle.join();
+    assert_eq!(
+        report,
+        TapReport {
+            end: TapEnd::FramesReached,
+            delivered: 3,
+            dropped: 0,
+            last_sequence: Some(4),
+        }
+    );
+    assert_eq!(
+        tapped(receiver),
+        (vec![(0, 3), (1, 4), (2, 5)], Some(report))
+    );
+    assert_eq!(splitter.active_taps(), 0);
+    // The primary callback saw every frame, in order
+    assert_eq!(*primary.frames.lock().unwrap(), [1, 2, 3, 4, 5, 6, 7, 8]);
+}
+
+#[test]
+fn decimated_tap_takes_every_nth_frame() {
+    let backend = common::mini_recorder();
+    let splitter = TapSplitter::new(Arc::new(Primary::default()), None);
+    let (_input, mock) = start(&backend, &splitter);
+
+    let (consumer, receiver) = tap();
+    let spec = TapSpec {
+        frames: Some(3),
+        decimation: 3,
+        ..TapSpec::default()
+    };
+    let handle = splitter.tap(consumer, spec);
+    deliver(&mock, 1..=12);
+
+    assert_eq!(handle.join().delivered, 3);
+    // The indices show the frames skipped between them
+    assert_eq!(tapped(receiver).0, [(0, 1), (3, 4), (6, 7)]);
+}
+
+#[test]
+fn tap_detaches_after_its_duration() {
+    let backend = common::mini_recorder();
+    let primary = Arc::new(Primary::default());
+    let splitter = TapSplitter::new(primary.clone(), None);
+    let (_input, mock) = start(&backend, &splitter);
+
+    let (consumer, receiver) = tap();
+    let spec = TapSpec {
+        duration: Some(Duration::from_millis(200)),
+        ..TapSpec::default()
+    };
+    let handle = splitter.tap(consumer, spec);
+    deliver(&mock, 1..=2);
+    thread::sleep(Duration::from_millis(300));
+    // Frames after the duration are not tapped
+    deliver(&mock, 3..=4);
+
+    let report = handle.join();
+    assert_eq!(report.end, TapEnd::DurationReached);
+    assert_eq!(report.delivered, 2);
+    assert_eq!(tapped(receiver).0, [(0, 1), (1, 2)]);
+    assert_eq!(splitter.active_taps(), 0);
+    assert_eq!(*primary.frames.lock().unwrap(), [1, 2, 3, 4]);
+}
+
+#[test]
+fn cancelled_tap_delivers_what_it_already_copied() {
+    let backend = common::mini_recorder();
+    let splitter = TapSplitter::new(Arc::new(Primary::default()), None);
+    let (_input, mock) = start(&backend, &splitter);
+
+    // A slow tap that may hold two frames drops the rest
+    let (consumer, receiver, release) = held_tap();
+    let spec = TapSpec {
+        retention: RetentionLimit::Frames(2),
+        ..TapSpec::default()
+    };
+    let handle = splitter.tap(consumer, spec);
+    deliver(&mock, 1..=5);
+    handle.cancel();
+    assert!(handle.is_finished());
+    deliver(&mock, 6..=7);
+    drop(release);
+
+    let report = handle.join();
+    assert_eq!(
+        report,
+        TapReport {
+            end: TapEnd::Cancelled,
+            delivered: 2,
+            dropped: 3,
+            last_sequence: Some(1),
+        }
+    );
+    assert_eq!(tapped(receiver), (vec![(0, 1), (1, 2)], Some(report)));
+}
+
+#[test]
+fn concurrent_taps_share_the_splitter_budget() {
+    let backend = common::mini_recorder();
+    let primary = Arc::new(Primary::default());
+    let budget = RetentionBudget::new(RetentionLimit::Frames(3), RetentionMode::Strict);
+    let splitter = TapSplitter::new(primary.clone(), Some(budget.clone()));
+    let (_input, mock) = start(&backend, &splitter);
+
+    let spec = TapSpec {
+        retention: RetentionLimit::Frames(2),
+        ..TapSpec::default()
+    };
+    let (first, first_frames, release_first) = held_tap();
+    let (second, second_frames, release_second) = held_tap();
+    let first = splitter.tap(first, spec);
+    let second = splitter.tap(second, spec);
+    assert_eq!(splitter.active_taps(), 2);
+    deliver(&mock, 1..=4);
+    assert_eq!(budget.stats().retained_frames, 3);
+
+    drop((release_first, release_second));
+    first.cancel();
+    second.cancel();
+    let (first, second) = (first.join(), second.join());
+    assert_eq!((first.delivered, first.dropped), (2, 2));
+    assert_eq!((second.delivered, second.dropped), (1, 3));
+    assert_eq!(tapped(first_frames).0, [(0, 1), (1, 2)]);
+    assert_eq!(tapped(second_frames).0, [(0, 1)]);
+    assert_eq!(budget.stats().retained_frames, 0);
+    assert_eq!(*primary.frames.lock().unwrap(), [1, 2, 3, 4]);
+}
+
+#[test]
+fn dropping_the_splitter_closes_its_taps() {
+    let backend = common::mini_recorder();
+    let splitter = TapSplitter::new(Arc::new(Primary::default()), None);
+    let (input, mock) = start(&backend, &splitter);
+
+    let (consumer, receiver) = tap();
+    let handle = splitter.tap(consumer, TapSpec::default());
+    deliver(&mock, 1..=1);
+    input.stop_streams().unwrap();
+    drop(splitter);
+
+    assert_eq!(handle.join().end, TapEnd::Closed);
+    assert_eq!(tapped(receiver).0, [(0, 1)]);
+}
+
+#[test]
+fn finish_gives_every_tap_the_same_final_frame() {
+    let backend = common::mini_recorder();
+    let primary = Arc::new(Primary::default());
+    let splitter = TapSplitter::new(primary.clone(), None);
+    let (input, mock) = start(&backend, &splitter);
+
+    deliver(&mock, 1..=2);
+    let (first, first_frames) = tap();
+    let (second, second_frames, release) = held_tap();
+    let first = splitter.tap(first, TapSpec::default());
+    let second = splitter.tap(second, TapSpec::default());
+    deliver(&mock, 3..=6);
+    input.stop_streams().unwrap();
+    // The second tap is still draining when the splitter is finished
+    let releaser = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(100));
+        drop(release);
+    });
+
+    let report = splitter.finish(Duration::from_secs(5));
+    releaser.join().unwrap();
+    assert_eq!(report.final_sequence, Some(5));
+    assert_eq!(report.taps.len(), 2);
+    for tap in &report.taps {
+        assert!(tap.drained);
+        assert!(!tap.panicked);
+        assert_eq!(
+            tap.report,
+            TapReport {
+                end: TapEnd::Finished,
+                delivered: 4,
+                dropped: 0,
+                last_sequence: Some(5),
+            }
+        );
+    }
+    let frames = vec![(0, 3), (1, 4), (2, 5), (3, 6)];
+    assert_eq!(
+        tapped(first_frames),
+        (frames.clone(), Some(report.taps[0].report))
+    );
+    assert_eq!(tapped(second_frames), (frames, Some(report.taps[1].report)));
+    assert_eq!(first.join().end, TapEnd::Finished);
+    assert_eq!(second.join().end, TapEnd::Finished);
+}
+
+#[test]
+fn frames_after_finish_go_to_the_primary_callback_only() {
+    let backend = common::mini_recorder();
+    let primary = Arc::new(Primary::default());
+    let splitter = TapSplitter::new(primary.clone(), None);
+    let (_input, mock) = start(&backend, &splitter);
+
+    let (consumer, receiver) = tap();
+    let handle = splitter.tap(consumer, TapSpec::default());
+    deliver(&mock, 1..=2);
+    assert_eq!(
+        splitter.finish(Duration::from_secs(5)).final_sequence,
+        Some(1)
+    );
+    deliver(&mock, 3..=4);
+
+    // A tap attached after finish detaches straight away
+    let (late, late_frames) = tap();
+    let late = splitter.tap(late, TapSpec::default());
+    deliver(&mock, 5..=5);
+    assert_eq!(
+        late.join(),
+        TapReport {
+            end: TapEnd::Finished,
+            delivered: 0,
+            dropped: 0,
+            last_sequence: None,
+        }
+    );
+    assert!(tapped(late_frames).0.is_empty());
+    assert_eq!(handle.join().delivered, 2);
+    assert_eq!(tapped(receiver).0, [(0, 1), (1, 2)]);
+    assert_eq!(*primary.frames.lock().unwrap(), [1, 2, 3, 4, 5]);
+}
+
+#[test]
+fn slow_tap_runs_out_of_its_drain_time() {
+    let backend = common::mini_recorder();
+    let splitter = TapSplitter::new(Arc::new(Primary::default()), None);
+    let (input, mock) = start(&backend, &splitter);
+
+    let (slow, slow_frames, release) = held_tap();
+    let (healthy, healthy_frames) = tap();
+    let slow = splitter.tap(slow, TapSpec::default());
+    let healthy = splitter.tap(healthy, TapSpec::default());
+    deliver(&mock, 1..=3);
+    input.stop_streams().unwrap();
+
+    let report = splitter.finish(Duration::from_millis(200));
+    let (slow_report, healthy_report) = (report.taps[0], report.taps[1]);
+    assert!(!slow_report.drained);
+    assert!(slow_report.drain_time >= Duration::from_millis(200));
+    assert_eq!(slow_report.report.last_sequence, None);
+    // The healthy tap has its own timeout, so the slow one does not use it up
+    assert!(healthy_report.drained);
+    assert_eq!(healthy_report.report.last_sequence, Some(2));
+    assert_eq!(tapped(healthy_frames).0, [(0, 1), (1, 2), (2, 3)]);
+    assert_eq!(healthy.join().end, TapEnd::Finished);
+
+    // Once released, the slow tap still delivers what was queued for it
+    drop(release);
+    let slow = slow.join();
+    assert_eq!((slow.end, slow.last_sequence), (TapEnd::Finished, Some(2)));
+    assert_eq!(tapped(slow_frames).0, [(0, 1), (1, 2), (2, 3)]);
+}
+
+#[test]
+fn panicking_taps_are_reported_and_others_carry_on() {
+    let backend = common::mini_recorder();
+    let splitter = TapSplitter::new(Arc::new(Primary::default()), None);
+    let (input, mock) = start(&backend, &splitter);
+
+    // The tap that panics is held on its first frame until every frame has been offered,
+    // so that it is still attached when the splitter finishes
+    let (tap_panicking, panicking_frames, release) = held_tap();
+    let (finish_panicking, finish_panicking_frames) = tap();
+    let (healthy, healthy_frames) = tap();
+    let _panicking = splitter.tap(
+        PanickingTap {
+            tap: tap_panicking,
+            panic_at: Some(2),
+        },
+        TapSpec::default(),
+    );
+    let _finish_panicking = splitter.tap(
+        PanickingTap {
+            tap: finish_panicking,
+            panic_at: None,
+        },
+        TapSpec::default(),
+    );
+    let _healthy = splitter.tap(healthy, TapSpec::default());
+    deliver(&mock, 1..=4);
+    drop(release);
+    input.stop_streams().unwrap();
+
+    let report = splitter.finish(Duration::from_secs(5));
+    assert_eq!(report.final_sequence, Some(3));
+    let [panicking, finish_panicking, healthy] = report.taps[..] else {
+        panic!("three taps");
+    };
+
+    // A panic in frame_tapped discards the rest of the queue and skips tap_finished
+    assert!(panicking.drained && panicking.panicked);
+    assert_eq!(panicking.report.end, TapEnd::Panicked);
+    assert_eq!(panicking.report.last_sequence, Some(0));
+    assert_eq!(tapped(panicking_frames), (vec![(0, 1)], None));
+
+    // A panic in tap_finished comes after every frame was given
+    assert!(finish_panicking.drained && finish_panicking.panicked);
+    assert_eq!(finish_panicking.report.end, TapEnd::Finished);
+    assert_eq!(finish_panicking.report.last_sequence, Some(3));
+    assert_eq!(tapped(finish_panicking_frames).0.len(), 4);
+
+    assert!(healthy.drained && !healthy.panicked);
+    assert_eq!(
+        tapped(healthy_frames),
+        (vec![(0, 1), (1, 2), (2, 3), (3, 4)], Some(healthy.report))
+    );
+}
+
+/// What a tap was given around a format change, in order.
+#[derive(PartialEq, Debug)]
+enum Seen {
+    /// The first byte and width of a frame, and whether it was transitional.
+    Frame(u8, usize, bool),
+    /// A format change, with the sequence of the frame after it.
+    Change(u64),
+}
+
+struct Recorder(Sender<Seen>);
+
+impl DeckLinkTapCallback for Recorder {
+    fn frame_tapped(&mut self, frame: TappedFrame) {
+        let first = frame.bytes().unwrap().0[0];
+        let _ = self
+            .0
+            .send(Seen::Frame(first, frame.width(), frame.transitional));
+    }
+
+    fn format_changed(&mut self, change: FormatChange) {
+        let _ = self.0.send(Seen::Change(change.sequence));
+    }
+}
+
+/// A tap with `format_change`, recording what it is given.
+fn recording_tap(
+    splitter: &TapSplitter,
+    format_change: FormatChangePolicy,
+) -> (TapHandle, Receiver<Seen>) {
+    let (sender, receiver) = channel();
+    let spec = TapSpec {
+        retention: RetentionLimit::Frames(16),
+        format_change,
+        ..TapSpec::default()
+    };
+    (splitter.tap(Recorder(sender), spec), receiver)
+}
+
+/// Frames 1 and 2 at 48 pixels wide, a change to 1080p50, frames 3 and 4 during the
+/// transition, and frames 5 to 9 at 96 pixels wide once the new format is applied. Frame 6
+/// has no signal.
+fn change_format(splitter: &TapSplitter, mock: &MockInput) {
+    let frame = |n: u8, width| MockFrame::new(width, 2, FORMAT).fill(n);
+    for n in 1..=2 {
+        assert!(mock.deliver_frame(frame(n, 48)).is_ok());
+    }
+    assert!(mock
+        .deliver_format_change(
+            DecklinkVideoInputFormatChangedEvents::DISPLAY_MODE_CHANGED,
+            DecklinkDisplayModeId::HD1080p50,
+            DecklinkDetectedVideoInputFormatFlags::YCBCR_422,
+        )
+        .is_ok());
+    assert!(splitter.in_transition());
+    for n in 3..=4 {
+        assert!(mock.deliver_frame(frame(n, 48)).is_ok());
+    }
+    splitter.format_applied();
+    assert!(!splitter.in_transition());
+    for n in 5..=9 {
+        let frame = if n == 6 {
+            frame(n, 96).no_signal()
+        } else {
+            frame(n, 96)
+        };
+        assert!(mock.deliver_frame(frame).is_ok());
+    }
+}
+
+/// The frames and changes a tap was given, once it is cancelled and has finished.
+fn seen(handle: TapHandle, receiver: Receiver<Seen>) -> Vec<Seen> {
+    handle.cancel();
+    handle.join();
+    receiver.iter().collect()
+}
+
+#[test]
+fn each_tap_follows_its_format_change_policy() {
+    let backend = common::mini_recorder();
+    let primary = Arc::new(Primary::default());
+    let splitter = TapSplitter::new(primary.clone(), None);
+    let (_input, mock) = start(&backend, &splitter);
+
+    let (clean, clean_seen) = recording_tap(&splitter, FormatChangePolicy::CleanCut);
+    let (through, through_seen) = recording_tap(&splitter, FormatChangePolicy::DeliverThrough);
+    let (stable, stable_seen) = recording_tap(
+        &splitter,
+        FormatChangePolicy::PauseUntilStable { frames: 2 },
+    );
+    change_format(&splitter, &mock);
+    assert_eq!(primary.frames.lock().unwrap().len(), 9);
+
+    // A clean cut sees no frame of the old format after the change
+    assert_eq!(
+        seen(clean, clean_seen),
+        [
+            Seen::Frame(1, 48, false),
+            Seen::Frame(2, 48, false),
+            Seen::Change(2),
+            Seen::Frame(5, 96, false),
+            Seen::Frame(6, 96, false),
+            Seen::Frame(7, 96, false),
+            Seen::Frame(8, 96, false),
+            Seen::Frame(9, 96, false),
+        ]
+    );
+    assert_eq!(
+        seen(through, through_seen),
+        [
+            Seen::Frame(1, 48, false),
+            Seen::Frame(2, 48, false),
+            Seen::Change(2),
+            Seen::Frame(3, 48, true),
+            Seen::Frame(4, 48, true),
+            Seen::Frame(5, 96, false),
+            Seen::Frame(6, 96, false),
+            Seen::Frame(7, 96, false),
+            Seen::Frame(8, 96, false),
+            Seen::Frame(9, 96, false),
+        ]
+    );
+    // The frame without a signal starts the count again, so 7 and 8 are the two in a row
+    assert_eq!(
+        seen(stable, stable_seen),
+        [
+            Seen::Frame(1, 48, false),
+            Seen::Frame(2, 48, false),
+            Seen::Change(2),
+            Seen::Frame(8, 96, false),
+            Seen::Frame(9, 96, false),
+        ]
+    );
+
+    let transitions = splitter.take_transitions();
+    assert_eq!(transitions.len(), 1);
+    assert_eq!(
+        transitions[0].display_mode,
+        DecklinkDisplayModeId::HD1080p50
+    );
+    assert_eq!(
+        (transitions[0].first_sequence, transitions[0].frames),
+        (2, 2)
+    );
+    assert!(splitter.take_transitions().is_empty());
+}
+
+#[test]
+fn a_policy_can_change_between_captures() {
+    let backend = common::mini_recorder();
+    let splitter = TapSplitter::new(Arc::new(Primary::default()), None);
+    let (_input, mock) = start(&backend, &splitter);
+
+    let (handle, receiver) = recording_tap(&splitter, FormatChangePolicy::DeliverThrough);
+    handle.set_format_change_policy(FormatChangePolicy::CleanCut);
+    change_format(&splitter, &mock);
+    handle.set_format_change_policy(FormatChangePolicy::DeliverThrough);
+    change_format(&splitter, &mock);
+    let seen = seen(handle, receiver);
+
+    // The first change is a clean cut, and the second is delivered through
+    let transitional: Vec<_> = seen
+        .iter()
+        .filter(|seen| matches!(seen, Seen::Frame(_, _, true)))
+        .collect();
+    assert_eq!(
+        transitional,
+        [&Seen::Frame(3, 48, true), &Seen::Frame(4, 48, true)]
+    );
+    assert_eq!(seen.len(), 8 + 10);
+    assert!(seen.contains(&Seen::Change(2)) && seen.contains(&Seen::Change(11)));
+    assert_eq!(splitter.take_transitions().len(), 2);
+}
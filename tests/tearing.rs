@@ -0,0 +1,320 @@
+//! Suspecting torn frames in synthetic sequences, and not suspecting still or fading ones.
+
+use decklink::experimental::tearing::{TearDetector, TearSuspicion, TearingConfig, TearingEvent};
+use decklink::frame::DecklinkPixelFormat;
+use decklink::testing::{FillPattern, TestFrame, TestFrameBuilder};
+
+const WIDTH: usize = 64;
+const HEIGHT: usize = 120;
+
+/// A frame whose rows each have the value `row_value` gives them, with a little texture
+/// along the row.
+fn frame(row_value: impl Fn(usize) -> u8) -> TestFrame {
+    let row_bytes = WIDTH * 4;
+    let bytes = (0..HEIGHT)
+        .flat_map(|row| {
+            let value = row_value(row);
+            (0..row_bytes).map(move |x| value.wrapping_add((x % 7) as u8))
+        })
+        .collect();
+    TestFrameBuilder::new(WIDTH, HEIGHT)
+        .pixel_format(DecklinkPixelFormat::Format8BitBGRA)
+        .fill(FillPattern::Bytes(bytes))
+        .build()
+        .unwrap()
+}
+
+/// Row `row` of frame `index` of a picture that moves everywhere.
+fn moving(index: usize, row: usize) -> u8 {
+    (row * 3 + index * 40) as u8
+}
+
+/// Frame `index` of the moving picture, with the rows from `tear` down left from the frame
+/// before.
+fn torn(index: usize, tear: usize) -> TestFrame {
+    frame(|row| {
+        if row < tear {
+            moving(index, row)
+        } else {
+            moving(index - 1, row)
+        }
+    })
+}
+
+fn run(detector: &mut TearDetector, frames: &[TestFrame]) -> Vec<TearSuspicion> {
+    frames.iter().filter_map(|f| detector.check(f)).collect()
+}
+
+#[test]
+fn torn_frame_is_suspected() {
+    let mut detector = TearDetector::new(TearingConfig::default());
+    let mut frames: Vec<TestFrame> = (0..3).map(|i| frame(|row| moving(i, row))).collect();
+    frames.push(torn(3, 70));
+    frames.extend((4..8).map(|i| frame(|row| moving(i, row))));
+
+    let suspected = run(&mut detector, &frames);
+    assert_eq!(suspected.len(), 1);
+    let suspicion = suspected[0];
+    assert_eq!(suspicion.sequence, 3);
+    assert_eq!(suspicion.previous_sequence, 2);
+    assert!(suspicion.last_changed_row < 70 && 70 <= suspicion.tear_row);
+    // 32 rows sampled evenly are 3.75 rows apart
+    assert!(suspicion.tear_row - suspicion.last_changed_row <= 4);
+    assert!(suspicion.magnitude > 0.1);
+
+    let stats = detector.stats();
+    assert_eq!(stats.frames, 8);
+    assert_eq!(stats.compared, 7);
+    assert_eq!(stats.suspected, 1);
+    assert_eq!(stats.last_tear_row, Some(suspicion.tear_row));
+    assert_eq!(detector.take_events(), [TearingEvent::Suspected(suspicion)]);
+}
+
+#[test]
+fn tear_row_is_bounded_by_the_positions_sampled() {
+    let positions = (0..10).map(|i| i as f64 / 10.0).collect();
+    let config = TearingConfig::builder()
+        .positions(positions)
+        .build()
+        .unwrap();
+    let mut detector = TearDetector::new(config);
+    let frames = [
+        frame(|row| moving(0, row)),
+        frame(|row| moving(1, row)),
+        torn(2, 50),
+    ];
+
+    let suspected = run(&mut detector, &frames);
+    assert_eq!(suspected.len(), 1);
+    assert_eq!(suspected[0].last_changed_row, 48);
+    assert_eq!(suspected[0].tear_row, 60);
+    assert_eq!(suspected[0].changed_rows, 5);
+    assert_eq!(suspected[0].unchanged_rows, 5);
+}
+
+#[test]
+fn moving_pictures_are_not_suspected() {
+    let mut detector = TearDetector::new(TearingConfig::default());
+    let frames: Vec<TestFrame> = (0..30).map(|i| frame(|row| moving(i, row))).collect();
+    assert!(run(&mut detector, &frames).is_empty());
+    assert_eq!(detector.stats().compared, 29);
+}
+
+#[test]
+fn still_pictures_are_not_suspected() {
+    let mut detector = TearDetector::new(TearingConfig::default());
+    let frames: Vec<TestFrame> = (0..10).map(|_| frame(|row| moving(0, row))).collect();
+    assert!(run(&mut detector, &frames).is_empty());
+
+    // Nor a picture that stops moving
+    let frames: Vec<TestFrame> = (0..10)
+        .map(|i| frame(|row| moving(i.min(4), row)))
+        .collect();
+    assert!(run(&mut detector, &frames).is_empty());
+    assert_eq!(detector.stats().suspected, 0);
+}
+
+#[test]
+fn still_lower_region_is_not_a_tear() {
+    // A letterbox bar below a moving picture splits the frame as a tear does, but was still
+    // in the frames before too
+    let letterboxed = |index| frame(move |row| if row < 90 { moving(index, row) } else { 16 });
+    let frames: Vec<TestFrame> = (0..20).map(letterboxed).collect();
+
+    let mut detector = TearDetector::new(TearingConfig::default());
+    assert!(run(&mut detector, &frames).is_empty());
+
+    let config = TearingConfig::builder()
+        .require_prior_motion(false)
+        .build()
+        .unwrap();
+    let mut detector = TearDetector::new(config);
+    assert_eq!(run(&mut detector, &frames).len(), 19);
+}
+
+#[test]
+fn slow_fade_is_not_a_tear() {
+    // The light upper rows move by one code every frame, and the dark lower ones every
+    // other frame, so every other frame is split as a tear is, by a small change
+    let fade = |index: usize| {
+        frame(move |row| {
+            if row < 80 {
+                100 + index as u8
+            } else {
+                16 + index as u8 / 2
+            }
+        })
+    };
+    let frames: Vec<TestFrame> = (0..40).map(fade).collect();
+
+    let mut detector = TearDetector::new(TearingConfig::default());
+    assert!(run(&mut detector, &frames).is_empty());
+
+    let config = TearingConfig::builder()
+        .min_change_magnitude(0.0)
+        .build()
+        .unwrap();
+    let mut detector = TearDetector::new(config);
+    let suspected = run(&mut detector, &frames);
+    assert!(!suspected.is_empty());
+    assert!(suspected.iter().all(|s| s.magnitude < 0.01));
+}
+
+#[test]
+fn small_motion_over_a_still_picture_is_not_a_tear() {
+    // Only a ticker along the top moves, over fewer sampled rows than `min_changed_rows`
+    let ticker = |index| frame(move |row| if row < 8 { moving(index, row) } else { 64 });
+    let frames: Vec<TestFrame> = (0..20).map(ticker).collect();
+    let config = TearingConfig::builder()
+        .require_prior_motion(false)
+        .build()
+        .unwrap();
+    let mut detector = TearDetector::new(config);
+    assert!(run(&mut detector, &frames).is_empty());
+}
+
+#[test]
+fn comparison_starts_again_after_a_format_change() {
+    let mut detector = TearDetector::new(TearingConfig::default());
+    detector.check(&frame(|row| moving(0, row)));
+    detector.check(&frame(|row| moving(1, row)));
+    let smaller = TestFrameBuilder::new(WIDTH, HEIGHT / 2)
+        .pixel_format(DecklinkPixelFormat::Format8BitBGRA)
+        .build()
+        .unwrap();
+    assert_eq!(detector.check(&smaller), None);
+    // The torn frame is the first of its format again, so it cannot be compared
+    assert_eq!(detector.check(&torn(2, 70)), None);
+
+    let stats = detector.stats();
+    assert_eq!(stats.frames, 4);
+    assert_eq!(stats.compared, 1);
+    assert_eq!(stats.resets, 2);
+}
+
+#[test]
+fn config_is_validated() {
+    let error = TearingConfig::builder().rows(4).build().unwrap_err();
+    assert_eq!(error.field, "rows");
+    let error = TearingConfig::builder()
+        .positions(vec![0.0, 0.25, 0.5, 0.75, 1.0])
+        .build()
+        .unwrap_err();
+    assert_eq!(error.field, "positions");
+    let error = TearingConfig::builder()
+        .min_changed_fraction(0.0)
+        .build()
+        .unwrap_err();
+    assert_eq!(error.field, "min_changed_fraction");
+    assert!(TearingConfig::default().validate().is_ok());
+}
+
+#[cfg(feature = "mock-backend")]
+mod mock {
+    use super::*;
+    use decklink::device::input::{
+        DeckLinkInputCallback, DecklinkDetectedVideoInputFormatFlags,
+        DecklinkVideoInputFormatChangedEvents,
+    };
+    use decklink::display_mode::DecklinkDisplayModeId;
+    use decklink::experimental::tearing::{SuspectAction, TearingGuard};
+    use decklink::frame::DecklinkVideoFrame;
+    use decklink::testing::TestCallbackDriver;
+    use std::sync::{Arc, Mutex};
+
+    /// Notes, for each frame it is given, the sequence the guard suspects it of tearing at.
+    struct Primary {
+        guard: TearingGuard,
+        suspicions: Mutex<Vec<Option<u64>>>,
+    }
+
+    impl DeckLinkInputCallback for Primary {
+        fn video_input_format_changed(
+            &self,
+            _events: DecklinkVideoInputFormatChangedEvents,
+            _new_display_mode: DecklinkDisplayModeId,
+            _detected_signal_flags: DecklinkDetectedVideoInputFormatFlags,
+        ) {
+        }
+
+        fn video_input_frame_arrived(&self, _video_frame: Option<DecklinkVideoFrame>) -> bool {
+            let suspicion = self.guard.current_suspicion();
+            self.suspicions
+                .lock()
+                .unwrap()
+                .push(suspicion.map(|s| s.sequence));
+            true
+        }
+    }
+
+    /// Play `frames` through a guard acting on suspects as `on_suspect`, giving what the
+    /// primary saw and the guard.
+    fn play(
+        on_suspect: SuspectAction,
+        driver: impl FnOnce(TestCallbackDriver) -> TestCallbackDriver,
+    ) -> (Vec<Option<u64>>, TearingGuard) {
+        let config = TearingConfig::builder()
+            .on_suspect(on_suspect)
+            .build()
+            .unwrap();
+        let guard = TearingGuard::new(config);
+        let primary = Arc::new(Primary {
+            guard: guard.clone(),
+            suspicions: Mutex::new(Vec::new()),
+        });
+        driver(TestCallbackDriver::new(
+            DecklinkDisplayModeId::HD1080p25,
+            DecklinkPixelFormat::Format8BitBGRA,
+        ))
+        .run(guard.guarded(primary.clone()))
+        .unwrap();
+        let suspicions = primary.suspicions.lock().unwrap().clone();
+        (suspicions, guard)
+    }
+
+    /// Three moving frames, the fourth torn, and three more moving.
+    fn torn_sequence(driver: TestCallbackDriver) -> TestCallbackDriver {
+        let driver = (0..3).fold(driver, |d, i| d.frame(frame(|row| moving(i, row))));
+        let driver = driver.frame(torn(3, 70));
+        (4..7).fold(driver, |d, i| d.frame(frame(|row| moving(i, row))))
+    }
+
+    #[test]
+    fn a_guard_passes_the_verdict_to_its_primary() {
+        let (suspicions, guard) = play(SuspectAction::Deliver, torn_sequence);
+        assert_eq!(suspicions, [None, None, None, Some(3), None, None, None]);
+        // Outside the primary's call there is no frame to suspect
+        assert_eq!(guard.current_suspicion(), None);
+        let stats = guard.stats();
+        assert_eq!((stats.frames, stats.suspected, stats.dropped), (7, 1, 0));
+        assert!(matches!(
+            guard.take_events()[..],
+            [TearingEvent::Suspected(TearSuspicion { sequence: 3, .. })]
+        ));
+    }
+
+    #[test]
+    fn a_guard_drops_suspected_frames() {
+        let (suspicions, guard) = play(SuspectAction::Drop, torn_sequence);
+        assert_eq!(suspicions, [None; 6]);
+        let stats = guard.stats();
+        assert_eq!((stats.frames, stats.suspected, stats.dropped), (7, 1, 1));
+    }
+
+    #[test]
+    fn a_format_change_through_the_guard_starts_the_comparison_again() {
+        let (suspicions, guard) = play(SuspectAction::Drop, |driver| {
+            (0..3)
+                .fold(driver, |d, i| d.frame(frame(|row| moving(i, row))))
+                .format_change(
+                    DecklinkDisplayModeId::HD1080p25,
+                    DecklinkDetectedVideoInputFormatFlags::RGB_444,
+                )
+                .frame(torn(3, 70))
+        });
+        // The torn frame is the first after the change, so it is not compared
+        assert_eq!(suspicions, [None; 4]);
+        let stats = guard.stats();
+        assert_eq!((stats.suspected, stats.resets), (0, 1));
+    }
+}
@@ -0,0 +1,277 @@
+//! Synthetic frames from `TestFrameBuilder`, and scripts played to an input callback.
+
+use decklink::frame::{DecklinkFrameBase, DecklinkFrameFlags, DecklinkPixelFormat};
+use decklink::testing::{FillPattern, TestFrameBuilder};
+use decklink::time::DecklinkTime;
+use decklink::SdkError;
+
+#[test]
+fn row_bytes_default_to_the_pixel_format_minimum() {
+    let frame = TestFrameBuilder::new(100, 2).build().unwrap();
+    assert_eq!(frame.row_bytes(), 200);
+    assert_eq!(frame.pixel_format(), DecklinkPixelFormat::Format8BitYUV);
+    assert_eq!(frame.flags(), DecklinkFrameFlags::empty());
+    assert!(frame.bytes().unwrap().0.iter().all(|&b| b == 0));
+
+    let frame = TestFrameBuilder::new(100, 2)
+        .pixel_format(DecklinkPixelFormat::Format10BitYUV)
+        .build()
+        .unwrap();
+    assert_eq!(frame.row_bytes(), 384);
+    assert_eq!(frame.bytes().unwrap().0.len(), 768);
+}
+
+#[test]
+fn rows_can_be_padded() {
+    let frame = TestFrameBuilder::new(8, 3)
+        .pixel_format(DecklinkPixelFormat::Format8BitBGRA)
+        .row_bytes(48)
+        .fill(FillPattern::Counting)
+        .build()
+        .unwrap();
+    assert_eq!(
+        (frame.width(), frame.height(), frame.row_bytes()),
+        (8, 3, 48)
+    );
+    let bytes = frame.bytes().unwrap().0;
+    assert_eq!(bytes.len(), 144);
+    assert_eq!(bytes[47], 47);
+    assert_eq!(bytes[143], 143);
+}
+
+#[test]
+fn fill_patterns() {
+    let bytes = |fill| {
+        TestFrameBuilder::new(4, 2)
+            .pixel_format(DecklinkPixelFormat::Format8BitBGRA)
+            .row_bytes(300)
+            .fill(fill)
+            .build()
+            .unwrap()
+            .bytes()
+            .unwrap()
+            .0
+            .to_vec()
+    };
+
+    assert!(bytes(FillPattern::Solid(0x80)).iter().all(|&b| b == 0x80));
+
+    let gradient = bytes(FillPattern::Gradient);
+    assert_eq!((gradient[0], gradient[299]), (0, 255));
+    assert_eq!((gradient[300], gradient[599]), (0, 255));
+    assert!(gradient[..300].windows(2).all(|w| w[0] <= w[1]));
+
+    let counting = bytes(FillPattern::Counting);
+    assert_eq!((counting[255], counting[256], counting[599]), (255, 0, 87));
+
+    let given: Vec<u8> = (0..600).map(|i| (i % 7) as u8).collect();
+    assert_eq!(bytes(FillPattern::Bytes(given.clone())), given);
+}
+
+#[test]
+fn flags_are_kept() {
+    let frame = TestFrameBuilder::new(4, 2)
+        .flags(DecklinkFrameFlags::FLIP_VERTICAL)
+        .no_input_source()
+        .build()
+        .unwrap();
+    assert_eq!(
+        frame.flags(),
+        DecklinkFrameFlags::FLIP_VERTICAL | DecklinkFrameFlags::HAS_NO_INPUT_SOURCE
+    );
+    // The owned frame is the one the test frame wraps
+    assert_eq!(frame.frame().flags(), frame.flags());
+    assert_eq!(frame.into_frame().width(), 4);
+}
+
+#[test]
+fn frame_index_sets_the_timing() {
+    let frame = TestFrameBuilder::new(4, 2)
+        .frame_index(5, 1001, 30000)
+        .build()
+        .unwrap();
+    let timing = frame.timing().unwrap();
+    assert_eq!(timing.stream_time, DecklinkTime::new(5005, 30000));
+    assert_eq!(timing.duration, DecklinkTime::new(1001, 30000));
+    assert_eq!(timing.mode_duration, timing.duration);
+
+    assert!(TestFrameBuilder::new(4, 2)
+        .build()
+        .unwrap()
+        .timing()
+        .is_none());
+}
+
+#[test]
+fn invalid_frames_are_rejected() {
+    assert!(matches!(
+        TestFrameBuilder::new(100, 2).row_bytes(199).build(),
+        Err(SdkError::INVALIDARG)
+    ));
+    assert!(matches!(
+        TestFrameBuilder::new(4, 2)
+            .fill(FillPattern::Bytes(vec![0; 15]))
+            .build(),
+        Err(SdkError::INVALIDARG)
+    ));
+    assert!(matches!(
+        TestFrameBuilder::new(4, 2)
+            .pixel_format(DecklinkPixelFormat::FormatH265)
+            .build(),
+        Err(SdkError::NOTIMPL)
+    ));
+    assert!(TestFrameBuilder::new(4, 2)
+        .pixel_format(DecklinkPixelFormat::FormatH265)
+        .row_bytes(16)
+        .build()
+        .is_ok());
+}
+
+#[cfg(feature = "mock-backend")]
+mod mock {
+    use decklink::device::input::{
+        DeckLinkInputCallback, DecklinkDetectedVideoInputFormatFlags,
+        DecklinkVideoInputFormatChangedEvents,
+    };
+    use decklink::display_mode::DecklinkDisplayModeId;
+    use decklink::frame::{
+        DecklinkFrameBase, DecklinkFrameFlags, DecklinkPixelFormat, DecklinkVideoFrame,
+    };
+    use decklink::testing::{FillPattern, TestCallbackDriver, TestFrameBuilder};
+    use decklink::time::DecklinkFrameTiming;
+    use decklink::SdkError;
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+
+    const FORMAT: DecklinkPixelFormat = DecklinkPixelFormat::Format8BitYUV;
+
+    #[derive(PartialEq, Debug)]
+    enum Event {
+        Format(DecklinkDisplayModeId),
+        Timing(i64),
+        Frame {
+            width: usize,
+            no_signal: bool,
+            first_byte: u8,
+        },
+    }
+
+    /// Records what it is called with, failing frames while `fail` is set.
+    #[derive(Default)]
+    struct Recorder {
+        events: Mutex<Vec<Event>>,
+        fail: bool,
+    }
+
+    impl DeckLinkInputCallback for Recorder {
+        fn video_input_format_changed(
+            &self,
+            _events: DecklinkVideoInputFormatChangedEvents,
+            new_display_mode: DecklinkDisplayModeId,
+            _detected_signal_flags: DecklinkDetectedVideoInputFormatFlags,
+        ) {
+            self.events
+                .lock()
+                .unwrap()
+                .push(Event::Format(new_display_mode));
+        }
+
+        fn video_input_frame_timing(&self, timing: DecklinkFrameTiming) {
+            self.events
+                .lock()
+                .unwrap()
+                .push(Event::Timing(timing.stream_time.value));
+        }
+
+        fn video_input_frame_arrived(&self, video_frame: Option<DecklinkVideoFrame>) -> bool {
+            let frame = video_frame.unwrap();
+            self.events.lock().unwrap().push(Event::Frame {
+                width: frame.width(),
+                no_signal: frame
+                    .flags()
+                    .contains(DecklinkFrameFlags::HAS_NO_INPUT_SOURCE),
+                first_byte: frame.bytes().unwrap().0[0],
+            });
+            !self.fail
+        }
+    }
+
+    fn frame(value: u8) -> TestFrameBuilder {
+        TestFrameBuilder::new(48, 2).fill(FillPattern::Solid(value))
+    }
+
+    #[test]
+    fn script_is_played_in_order() {
+        let recorder = Arc::new(Recorder::default());
+        TestCallbackDriver::new(DecklinkDisplayModeId::HD1080p25, FORMAT)
+            .frame(frame(1).frame_index(0, 1000, 25000).build().unwrap())
+            .frame(frame(2).frame_index(1, 1000, 25000).build().unwrap())
+            .format_change(
+                DecklinkDisplayModeId::PAL,
+                DecklinkDetectedVideoInputFormatFlags::YCBCR_422,
+            )
+            .no_signal(2)
+            .frame(frame(3).build().unwrap())
+            .run(recorder.clone())
+            .unwrap();
+
+        let captured = |width, first_byte| Event::Frame {
+            width,
+            no_signal: false,
+            first_byte,
+        };
+        let no_signal = Event::Frame {
+            width: 720,
+            no_signal: true,
+            first_byte: 0,
+        };
+        assert_eq!(
+            *recorder.events.lock().unwrap(),
+            vec![
+                Event::Timing(0),
+                captured(48, 1),
+                Event::Timing(1000),
+                captured(48, 2),
+                Event::Format(DecklinkDisplayModeId::PAL),
+                no_signal,
+                Event::Frame {
+                    width: 720,
+                    no_signal: true,
+                    first_byte: 0,
+                },
+                captured(48, 3),
+            ]
+        );
+    }
+
+    #[test]
+    fn frames_are_paced() {
+        let recorder = Arc::new(Recorder::default());
+        let start = Instant::now();
+        TestCallbackDriver::new(DecklinkDisplayModeId::HD1080p25, FORMAT)
+            .pace(Duration::from_millis(20))
+            .frame(frame(1).build().unwrap())
+            .frame(frame(2).build().unwrap())
+            .wait(Duration::from_millis(30))
+            .frame(frame(3).build().unwrap())
+            .run(recorder.clone())
+            .unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(90));
+        assert_eq!(recorder.events.lock().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn failing_callback_fails_the_run() {
+        let recorder = Arc::new(Recorder {
+            fail: true,
+            ..Default::default()
+        });
+        let result = TestCallbackDriver::new(DecklinkDisplayModeId::HD1080p25, FORMAT)
+            .frame(frame(1).build().unwrap())
+            .frame(frame(2).build().unwrap())
+            .run(recorder.clone());
+        assert!(matches!(result, Err(SdkError::FAIL)));
+        // Every step is still played
+        assert_eq!(recorder.events.lock().unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,186 @@
+//! Which public types are `Send` and `Sync`, checked at compile time, and captured frames,
+//! display modes and status used from other threads.
+//!
+//! Most types wrap SDK interfaces through raw pointers, so their auto traits are chosen by
+//! hand. These checks fail the build if a change to a type adds or removes one by accident.
+
+#[cfg(feature = "mock-backend")]
+mod common;
+
+use decklink::dashboard::DeviceMonitor;
+use decklink::device::attributes::DecklinkDeviceAttributes;
+use decklink::device::input::{CancellationToken, DecklinkInputDevice, FirstFrame};
+use decklink::device::output::DecklinkOutputDevice;
+use decklink::device::registry::DeviceRegistry;
+use decklink::device::status::DecklinkDeviceStatus;
+use decklink::device::DecklinkDevice;
+use decklink::display_mode::DecklinkDisplayMode;
+use decklink::experimental::transform::TransformChain;
+use decklink::frame::{DecklinkVideoFrame, DecklinkVideoMutableFrame};
+use decklink::retention::{RetainedFrame, RetentionBudget};
+use decklink::row_bytes::RowBytesValidator;
+
+/// Fails to compile unless the type implements all of the traits.
+macro_rules! assert_impl_all {
+    ($type:ty: $($bound:path),+) => {
+        const _: fn() = || {
+            fn check<T: ?Sized $(+ $bound)+>() {}
+            check::<$type>();
+        };
+    };
+}
+
+/// Fails to compile if the type implements the trait. With the blanket impls below, the
+/// trait item is ambiguous only when `$bound` is implemented.
+macro_rules! assert_not_impl {
+    ($type:ty: $bound:path) => {
+        const _: fn() = || {
+            trait AmbiguousIfImpl<A> {
+                fn some_item() {}
+            }
+            impl<T: ?Sized> AmbiguousIfImpl<()> for T {}
+            struct Invalid;
+            impl<T: ?Sized + $bound> AmbiguousIfImpl<Invalid> for T {}
+            let _ = <$type as AmbiguousIfImpl<_>>::some_item;
+        };
+    };
+}
+
+assert_impl_all!(DecklinkDisplayMode: Send, Sync);
+assert_impl_all!(DecklinkVideoMutableFrame: Send, Sync);
+assert_impl_all!(DecklinkDeviceAttributes: Send, Sync);
+assert_impl_all!(DecklinkDeviceStatus: Send, Sync);
+assert_impl_all!(CancellationToken: Send, Sync);
+assert_impl_all!(RetentionBudget: Send, Sync);
+assert_impl_all!(DeviceMonitor: Send, Sync);
+assert_impl_all!(RowBytesValidator: Send, Sync);
+
+assert_impl_all!(DecklinkVideoFrame: Send);
+assert_not_impl!(DecklinkVideoFrame: Sync);
+assert_impl_all!(RetainedFrame: Send);
+assert_not_impl!(RetainedFrame: Sync);
+assert_impl_all!(FirstFrame: Send);
+assert_impl_all!(TransformChain: Send);
+
+assert_impl_all!(DecklinkInputDevice: Send);
+assert_not_impl!(DecklinkInputDevice: Sync);
+
+assert_not_impl!(DecklinkDevice: Send);
+assert_not_impl!(DecklinkDevice: Sync);
+assert_not_impl!(DeviceRegistry: Send);
+assert_not_impl!(DecklinkOutputDevice: Send);
+assert_not_impl!(DecklinkOutputDevice: Sync);
+
+#[cfg(feature = "mock-backend")]
+mod mock {
+    use super::common;
+    use decklink::device::get_devices;
+    use decklink::device::input::{
+        DeckLinkInputCallback, DecklinkDetectedVideoInputFormatFlags, DecklinkVideoInputFlags,
+        DecklinkVideoInputFormatChangedEvents,
+    };
+    use decklink::device::status::DecklinkStatusId;
+    use decklink::device::DecklinkDeviceDisplayModes;
+    use decklink::display_mode::DecklinkDisplayModeId;
+    use decklink::frame::{DecklinkFrameBase, DecklinkPixelFormat, DecklinkVideoFrame};
+    use decklink::mock::{MockBackend, MockDevice, MockFrame};
+    use std::sync::mpsc::{channel, Sender};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    const FORMAT: DecklinkPixelFormat = DecklinkPixelFormat::Format8BitYUV;
+
+    /// Sends every captured frame to a worker.
+    struct ToWorker(Mutex<Sender<DecklinkVideoFrame>>);
+
+    impl DeckLinkInputCallback for ToWorker {
+        fn video_input_format_changed(
+            &self,
+            _events: DecklinkVideoInputFormatChangedEvents,
+            _new_display_mode: DecklinkDisplayModeId,
+            _detected_signal_flags: DecklinkDetectedVideoInputFormatFlags,
+        ) {
+        }
+
+        fn video_input_frame_arrived(&self, video_frame: Option<DecklinkVideoFrame>) -> bool {
+            self.0.lock().unwrap().send(video_frame.unwrap()).unwrap();
+            true
+        }
+    }
+
+    #[test]
+    fn mode_chosen_on_one_thread_and_frames_processed_on_another() {
+        let backend = common::mini_recorder();
+        let devices = get_devices().unwrap();
+        let mut input = devices[0].input().unwrap();
+
+        // Pick a mode on another thread
+        let modes = input.display_modes().unwrap();
+        let mode = thread::spawn(move || {
+            modes
+                .into_iter()
+                .find(|m| m.mode() == DecklinkDisplayModeId::HD1080p25)
+                .unwrap()
+        })
+        .join()
+        .unwrap();
+        assert_eq!(mode.name().as_deref(), Some("1080p25"));
+
+        // A worker sums the first byte of every frame, and drops it
+        let (sender, receiver) = channel::<DecklinkVideoFrame>();
+        let worker = thread::spawn(move || {
+            receiver
+                .iter()
+                .map(|frame| frame.bytes().unwrap().0[0] as u32)
+                .sum::<u32>()
+        });
+        input
+            .enable_video_input(mode.mode(), FORMAT, DecklinkVideoInputFlags::empty())
+            .unwrap();
+        input
+            .set_callback(Some(Arc::new(ToWorker(Mutex::new(sender)))))
+            .unwrap();
+        input.start_streams().unwrap();
+        let mock = backend.input(0);
+        for n in 1..=10 {
+            assert!(mock
+                .deliver_frame(MockFrame::new(48, 2, FORMAT).fill(n))
+                .is_ok());
+        }
+
+        // The input is stopped from another thread, which releases the callback and so the
+        // sender
+        thread::spawn(move || {
+            input.stop_streams().unwrap();
+            input.set_callback(None).unwrap();
+        })
+        .join()
+        .unwrap();
+        assert_eq!(worker.join().unwrap(), 55);
+    }
+
+    #[test]
+    fn status_is_shared_between_threads() {
+        let _backend = MockBackend::install(vec![MockDevice::new("DeckLink Mini Recorder")
+            .status_int(DecklinkStatusId::DeviceTemperature, 51)]);
+        let devices = get_devices().unwrap();
+        let status = Arc::new(devices[0].get_status().unwrap());
+        let attributes = Arc::new(devices[0].get_attributes().unwrap());
+
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let status = status.clone();
+                let attributes = attributes.clone();
+                thread::spawn(move || {
+                    (
+                        status.device_temperature().unwrap(),
+                        attributes.supports_input_format_detection().is_ok(),
+                    )
+                })
+            })
+            .collect();
+        for reader in readers {
+            assert_eq!(reader.join().unwrap().0, 51);
+        }
+    }
+}
@@ -0,0 +1,261 @@
+//! Listing the crate's threads, reporting their panics, and joining them all on teardown.
+
+#[cfg(feature = "mock-backend")]
+mod common;
+
+use decklink::batch::{BatchConfig, BatchDispatcher, BatchedFrame, DeckLinkInputBatchCallback};
+use decklink::device::input::{
+    DecklinkDetectedVideoInputFormatFlags, DecklinkVideoInputFormatChangedEvents,
+    InputFormatChange, InputHandler,
+};
+use decklink::dispatch::{
+    DispatchConfig, DispatchHandler, DispatchPool, DispatchedFrame, SourceId,
+};
+use decklink::display_mode::DecklinkDisplayModeId;
+use decklink::threads::{self, ThreadEvent, ThreadSpawner};
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// The threads are listed process wide, so the tests run one at a time.
+static SERIAL: Mutex<()> = Mutex::new(());
+
+fn serial() -> MutexGuard<'static, ()> {
+    let guard = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+    threads::take_events();
+    guard
+}
+
+fn pool(workers: usize) -> DispatchPool {
+    let config = DispatchConfig::builder().workers(workers).build().unwrap();
+    DispatchPool::new(config)
+}
+
+/// A handler that panics when the format changes.
+struct PanickingHandler;
+
+impl DispatchHandler for PanickingHandler {
+    fn frame_arrived(&self, _source: SourceId, _frame: DispatchedFrame) {}
+
+    fn video_input_format_changed(
+        &self,
+        _source: SourceId,
+        _events: DecklinkVideoInputFormatChangedEvents,
+        _new_display_mode: DecklinkDisplayModeId,
+        _detected_signal_flags: DecklinkDetectedVideoInputFormatFlags,
+    ) {
+        panic!("mock consumer failed");
+    }
+}
+
+struct IdleBatches;
+
+impl DeckLinkInputBatchCallback for IdleBatches {
+    fn frames_arrived(&mut self, _frames: &[BatchedFrame]) {}
+}
+
+fn names(role: &str) -> Vec<String> {
+    threads::threads()
+        .into_iter()
+        .filter(|t| t.role == role)
+        .map(|t| t.name)
+        .collect()
+}
+
+#[test]
+fn threads_are_listed_by_name_and_role() {
+    let _serial = serial();
+    let pool = pool(3);
+    let listed: Vec<_> = threads::threads()
+        .into_iter()
+        .filter(|t| t.role == "dispatch")
+        .collect();
+    assert_eq!(listed.len(), 3);
+    assert!(listed.iter().all(|t| t.alive));
+    assert!(listed
+        .iter()
+        .all(|t| t.name.starts_with("decklink-dispatch-")));
+    let mut distinct: Vec<_> = listed.iter().map(|t| &t.name).collect();
+    distinct.sort();
+    distinct.dedup();
+    assert_eq!(distinct.len(), 3);
+
+    drop(pool);
+    assert!(names("dispatch").is_empty());
+    let events = threads::take_events();
+    for thread in &listed {
+        let of_thread: Vec<_> = events
+            .iter()
+            .filter(|e| match e {
+                ThreadEvent::Spawned { name, .. }
+                | ThreadEvent::Exited { name, .. }
+                | ThreadEvent::Joined { name, .. } => *name == thread.name,
+                ThreadEvent::Panicked(_) => false,
+            })
+            .collect();
+        assert!(matches!(
+            of_thread[..],
+            [
+                ThreadEvent::Spawned { .. },
+                ThreadEvent::Exited { .. },
+                ThreadEvent::Joined { .. }
+            ]
+        ));
+    }
+}
+
+#[test]
+fn panic_of_a_consumer_is_reported() {
+    let _serial = serial();
+    threads::install_panic_hook();
+    let before = threads::health().panicked;
+
+    let pool = pool(1);
+    let source = pool.add_source("mock", 1, Arc::new(PanickingHandler));
+    pool.callback(source)
+        .format_changed(&InputFormatChange::new(
+            DecklinkVideoInputFormatChangedEvents::empty(),
+            DecklinkDisplayModeId::HD1080p25,
+            DecklinkDetectedVideoInputFormatFlags::empty(),
+        ));
+    drop(pool);
+
+    let panics: Vec<_> = threads::take_events()
+        .into_iter()
+        .filter_map(|e| match e {
+            ThreadEvent::Panicked(panic) => Some(panic),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(panics.len(), 1);
+    let panic = &panics[0];
+    assert!(panic.name.starts_with("decklink-dispatch-"));
+    assert_eq!(panic.role, "dispatch");
+    assert_eq!(panic.payload, "mock consumer failed");
+    assert!(panic.location.as_ref().unwrap().contains("threads.rs:"));
+
+    let health = threads::health();
+    assert!(!health.is_healthy());
+    assert_eq!(health.panicked, before + 1);
+    assert_eq!(health.last_panic.as_ref(), Some(panic));
+}
+
+/// Starts threads with `std::thread::Builder`, counting them.
+#[derive(Default)]
+struct CountingSpawner {
+    spawned: AtomicUsize,
+    names: Mutex<Vec<String>>,
+}
+
+impl ThreadSpawner for CountingSpawner {
+    fn spawn(
+        &self,
+        name: &str,
+        _role: &'static str,
+        body: Box<dyn FnOnce() + Send>,
+    ) -> io::Result<()> {
+        self.spawned.fetch_add(1, Ordering::Relaxed);
+        self.names.lock().unwrap().push(name.to_string());
+        std::thread::Builder::new()
+            .name(format!("app-{}", name))
+            .spawn(body)
+            .map(drop)
+    }
+}
+
+#[test]
+fn threads_are_started_by_a_custom_spawner() {
+    let _serial = serial();
+    let spawner = Arc::new(CountingSpawner::default());
+    threads::set_thread_spawner(Some(spawner.clone()));
+    let pool = pool(2);
+    threads::set_thread_spawner(None);
+
+    assert_eq!(spawner.spawned.load(Ordering::Relaxed), 2);
+    let mut listed = names("dispatch");
+    listed.sort();
+    let mut spawned = spawner.names.lock().unwrap().clone();
+    spawned.sort();
+    assert_eq!(listed, spawned);
+
+    // The detached threads are joined by waiting for their work to return
+    drop(pool);
+    assert!(names("dispatch").is_empty());
+}
+
+#[test]
+fn teardown_joins_every_thread() {
+    let _serial = serial();
+    let pool = pool(2);
+    let batches = BatchDispatcher::new(BatchConfig::default(), IdleBatches);
+    assert_eq!(names("dispatch").len(), 2);
+    assert_eq!(names("batch").len(), 1);
+
+    drop(batches);
+    drop(pool);
+    assert!(threads::threads().is_empty());
+    let joined = threads::take_events()
+        .iter()
+        .filter(|e| matches!(e, ThreadEvent::Joined { .. }))
+        .count();
+    assert_eq!(joined, 3);
+
+    #[cfg(feature = "leak-check")]
+    assert!(decklink::debug::live_objects()
+        .iter()
+        .all(|(type_name, _)| *type_name != "InternalThread"));
+}
+
+#[cfg(feature = "mock-backend")]
+mod mock {
+    use super::common;
+    use super::*;
+    use decklink::device::input::DecklinkVideoInputFlags;
+    use decklink::frame::DecklinkPixelFormat;
+    use decklink::mock::MockFrame;
+
+    /// A handler that panics on the first frame it is given.
+    struct PanickingConsumer;
+
+    impl DispatchHandler for PanickingConsumer {
+        fn frame_arrived(&self, _source: SourceId, _frame: DispatchedFrame) {
+            panic!("mock consumer failed on a frame");
+        }
+    }
+
+    #[test]
+    fn panic_of_a_consumer_of_a_capture_is_reported() {
+        let _serial = serial();
+        threads::install_panic_hook();
+        let before = threads::health().panicked;
+        let backend = common::mini_recorder();
+
+        let pool = pool(1);
+        let source = pool.add_source("mock", 1, Arc::new(PanickingConsumer));
+        let mut input = common::open_input(
+            DecklinkDisplayModeId::HD1080p25,
+            DecklinkPixelFormat::Format8BitYUV,
+            DecklinkVideoInputFlags::empty(),
+        );
+        common::start_streams(&mut input, pool.callback(source));
+        let frame = MockFrame::new(48, 2, DecklinkPixelFormat::Format8BitYUV);
+        assert!(backend.input(0).deliver_frame(frame).is_ok());
+        input.stop_streams().unwrap();
+        drop(input);
+        drop(pool);
+
+        let panics: Vec<_> = threads::take_events()
+            .into_iter()
+            .filter_map(|e| match e {
+                ThreadEvent::Panicked(panic) => Some(panic),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(panics.len(), 1, "{panics:?}");
+        assert!(panics[0].name.starts_with("decklink-dispatch-"));
+        assert_eq!(panics[0].payload, "mock consumer failed on a frame");
+        assert_eq!(threads::health().panicked, before + 1);
+        // The worker that panicked is joined with the others
+        assert!(names("dispatch").is_empty());
+    }
+}
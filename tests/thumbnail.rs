@@ -0,0 +1,407 @@
+//! Thumbnail sizes, and thumbnails taken from a mock capture through a tap.
+#![cfg(feature = "image-interop")]
+
+#[cfg(feature = "mock-backend")]
+mod common;
+
+use decklink::thumbnail::{PixelAspect, ThumbnailInterval, ThumbnailSpec};
+use std::time::Duration;
+
+fn spec() -> ThumbnailSpec {
+    ThumbnailSpec::new(ThumbnailInterval::Every(Duration::from_secs(5)))
+}
+
+#[test]
+fn standard_definition_pixels_are_not_square() {
+    assert_eq!(
+        PixelAspect::for_height(486, false),
+        PixelAspect { num: 10, den: 11 }
+    );
+    assert_eq!(
+        PixelAspect::for_height(480, true),
+        PixelAspect { num: 40, den: 33 }
+    );
+    assert_eq!(
+        PixelAspect::for_height(576, false),
+        PixelAspect { num: 12, den: 11 }
+    );
+    assert_eq!(
+        PixelAspect::for_height(576, true),
+        PixelAspect { num: 16, den: 11 }
+    );
+    assert_eq!(PixelAspect::for_height(1080, true), PixelAspect::SQUARE);
+}
+
+#[test]
+fn thumbnails_fit_the_box_at_the_display_aspect() {
+    let with_aspect = |height, widescreen| ThumbnailSpec {
+        pixel_aspect: PixelAspect::for_height(height, widescreen),
+        ..spec()
+    };
+
+    // HD and UHD fill the 16:9 box
+    assert_eq!(spec().thumbnail_size(1920, 1080), (320, 180));
+    assert_eq!(spec().thumbnail_size(3840, 2160), (320, 180));
+    assert_eq!(spec().thumbnail_size(4096, 2160), (320, 169));
+    // NTSC and PAL at 4:3
+    assert_eq!(with_aspect(486, false).thumbnail_size(720, 486), (242, 180));
+    assert_eq!(with_aspect(576, false).thumbnail_size(720, 576), (245, 180));
+    // Anamorphic 16:9 PAL and NTSC
+    assert_eq!(with_aspect(576, true).thumbnail_size(720, 576), (320, 176));
+    assert_eq!(with_aspect(486, true).thumbnail_size(720, 486), (320, 178));
+    // Thumbnails are never scaled up
+    assert_eq!(spec().thumbnail_size(160, 90), (160, 90));
+    let tall = ThumbnailSpec {
+        max_width: 100,
+        max_height: 100,
+        ..spec()
+    };
+    assert_eq!(tall.thumbnail_size(1080, 1920), (56, 100));
+}
+
+#[cfg(feature = "mock-backend")]
+mod mock {
+    use super::common;
+    use decklink::deinterlace::DeinterlacePolicy;
+    use decklink::device::get_devices;
+    use decklink::device::input::{
+        DeckLinkInputCallback, DecklinkDetectedVideoInputFormatFlags, DecklinkInputDevice,
+        DecklinkVideoInputFlags, DecklinkVideoInputFormatChangedEvents,
+    };
+    use decklink::device::DecklinkDeviceDisplayModes;
+    use decklink::display_mode::DecklinkDisplayModeId;
+    use decklink::frame::{DecklinkPixelFormat, DecklinkVideoFrame};
+    use decklink::mock::{MockBackend, MockFrame, MockInput};
+    use decklink::retention::RetentionLimit;
+    use decklink::tap::{TapHandle, TapSpec, TapSplitter};
+    use decklink::thumbnail::{
+        ThumbnailInterval, ThumbnailMeta, ThumbnailPipeline, ThumbnailSink, ThumbnailSpec,
+        ThumbnailStats,
+    };
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::mpsc::{channel, Receiver};
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+
+    const FORMAT: DecklinkPixelFormat = DecklinkPixelFormat::Format8BitYUV;
+
+    #[derive(Default)]
+    struct Primary {
+        frames: AtomicUsize,
+    }
+
+    impl DeckLinkInputCallback for Primary {
+        fn video_input_format_changed(
+            &self,
+            _events: DecklinkVideoInputFormatChangedEvents,
+            _new_display_mode: DecklinkDisplayModeId,
+            _detected_signal_flags: DecklinkDetectedVideoInputFormatFlags,
+        ) {
+        }
+
+        fn video_input_frame_arrived(&self, _video_frame: Option<DecklinkVideoFrame>) -> bool {
+            self.frames.fetch_add(1, Ordering::SeqCst);
+            true
+        }
+    }
+
+    struct Capture {
+        _input: DecklinkInputDevice,
+        mock: MockInput,
+        primary: Arc<Primary>,
+        stats: ThumbnailStats,
+        tap: TapHandle,
+        _splitter: TapSplitter,
+    }
+
+    /// Capture in `mode`, taking thumbnails with `configure` applied to a spec for the mode.
+    fn start(
+        backend: &MockBackend,
+        mode: DecklinkDisplayModeId,
+        interval: ThumbnailInterval,
+        configure: impl FnOnce(ThumbnailSpec) -> ThumbnailSpec,
+        sink: ThumbnailSink,
+    ) -> Capture {
+        let devices = get_devices().unwrap();
+        let mut input = devices[0].input().unwrap();
+        let modes = input.display_modes().unwrap();
+        let display_mode = modes.iter().find(|m| m.mode() == mode).unwrap();
+        let spec = configure(ThumbnailSpec::new(interval).for_mode(display_mode, false));
+        input
+            .enable_video_input(mode, FORMAT, DecklinkVideoInputFlags::empty())
+            .unwrap();
+
+        let primary = Arc::new(Primary::default());
+        let splitter = TapSplitter::new(primary.clone(), None);
+        input.set_handler(Some(splitter.callback())).unwrap();
+        let pipeline = ThumbnailPipeline::new(spec, sink);
+        let stats = pipeline.stats();
+        // Enough retention for the tap to be given every frame while a thumbnail is encoded
+        let tap = splitter.tap(
+            pipeline,
+            TapSpec {
+                retention: RetentionLimit::Frames(16),
+                ..TapSpec::default()
+            },
+        );
+        input.start_streams().unwrap();
+        Capture {
+            _input: input,
+            mock: backend.input(0),
+            primary,
+            stats,
+            tap,
+            _splitter: splitter,
+        }
+    }
+
+    /// A sink sending each thumbnail to the test.
+    fn channel_sink() -> (ThumbnailSink, Receiver<(Vec<u8>, ThumbnailMeta)>) {
+        let (sender, receiver) = channel();
+        let sink = ThumbnailSink::Callback(Box::new(move |bytes: &[u8], meta: &ThumbnailMeta| {
+            let _ = sender.send((bytes.to_vec(), *meta));
+        }));
+        (sink, receiver)
+    }
+
+    fn small_frame() -> MockFrame {
+        MockFrame::new(48, 2, FORMAT).fill(0x80)
+    }
+
+    fn wait_for(condition: impl Fn() -> bool) {
+        let start = Instant::now();
+        while !condition() {
+            assert!(start.elapsed() < Duration::from_secs(5), "timed out");
+            std::thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn every_nth_frame_is_taken() {
+        let backend = common::mini_recorder();
+        let (sink, thumbnails) = channel_sink();
+        let capture = start(
+            &backend,
+            DecklinkDisplayModeId::HD1080p25,
+            ThumbnailInterval::EveryFrames(3),
+            |spec| spec,
+            sink,
+        );
+
+        // Each frame is tapped and any thumbnail finished before the next arrives
+        for _ in 0..10 {
+            assert!(capture.mock.deliver_frame(small_frame()).is_ok());
+            std::thread::sleep(Duration::from_millis(30));
+        }
+        capture.tap.cancel();
+        capture.tap.join();
+
+        let metas: Vec<_> = thumbnails
+            .iter()
+            .map(|(_, meta)| (meta.sequence, meta.frame_index))
+            .collect();
+        assert_eq!(metas, [(0, 0), (1, 3), (2, 6), (3, 9)]);
+        assert_eq!((capture.stats.written(), capture.stats.skipped()), (4, 0));
+    }
+
+    #[test]
+    fn thumbnails_are_taken_at_a_time_interval() {
+        let backend = common::mini_recorder();
+        let (sink, thumbnails) = channel_sink();
+        let interval = Duration::from_millis(100);
+        let capture = start(
+            &backend,
+            DecklinkDisplayModeId::HD1080p25,
+            ThumbnailInterval::Every(interval),
+            |spec| spec,
+            sink,
+        );
+
+        for _ in 0..12 {
+            assert!(capture.mock.deliver_frame(small_frame()).is_ok());
+            std::thread::sleep(Duration::from_millis(25));
+        }
+        capture.tap.cancel();
+        capture.tap.join();
+
+        let metas: Vec<ThumbnailMeta> = thumbnails.iter().map(|(_, meta)| meta).collect();
+        assert!((2..12).contains(&metas.len()), "{}", metas.len());
+        for pair in metas.windows(2) {
+            assert!(pair[1].arrived - pair[0].arrived >= interval);
+        }
+    }
+
+    #[test]
+    fn a_slow_sink_skips_frames_without_holding_up_capture() {
+        let backend = common::mini_recorder();
+        let (release, hold) = channel::<()>();
+        let hold = Mutex::new(hold);
+        let written = Arc::new(Mutex::new(Vec::new()));
+        let sink = {
+            let written = written.clone();
+            ThumbnailSink::Callback(Box::new(move |_: &[u8], meta: &ThumbnailMeta| {
+                let _ = hold.lock().unwrap().recv_timeout(Duration::from_secs(5));
+                written.lock().unwrap().push(meta.frame_index);
+            }))
+        };
+        let capture = start(
+            &backend,
+            DecklinkDisplayModeId::HD1080p25,
+            ThumbnailInterval::EveryFrames(1),
+            |spec| spec,
+            sink,
+        );
+
+        // The sink blocks on the first thumbnail, while capture carries on
+        let start = Instant::now();
+        for _ in 0..10 {
+            assert!(capture.mock.deliver_frame(small_frame()).is_ok());
+        }
+        assert!(start.elapsed() < Duration::from_millis(500));
+        assert_eq!(capture.primary.frames.load(Ordering::SeqCst), 10);
+        wait_for(|| capture.stats.skipped() == 9);
+        assert_eq!(capture.stats.written(), 0);
+
+        // Once the sink is free, the next frame is taken straight away
+        release.send(()).unwrap();
+        wait_for(|| capture.stats.written() == 1);
+        std::thread::sleep(Duration::from_millis(30));
+        drop(release);
+        assert!(capture.mock.deliver_frame(small_frame()).is_ok());
+        wait_for(|| capture.stats.written() == 2);
+        assert_eq!(*written.lock().unwrap(), [0, 10]);
+        assert_eq!(capture.stats.skipped(), 9);
+    }
+
+    #[test]
+    fn anamorphic_thumbnails_are_encoded_at_their_display_size() {
+        let backend = common::mini_recorder();
+        let (sink, thumbnails) = channel_sink();
+        let mode = DecklinkDisplayModeId::PAL;
+        let capture = start(
+            &backend,
+            mode,
+            ThumbnailInterval::EveryFrames(1),
+            |spec| spec,
+            sink,
+        );
+
+        assert!(capture
+            .mock
+            .deliver_frame(MockFrame::for_mode(mode, FORMAT))
+            .is_ok());
+        let (bytes, meta) = thumbnails.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(
+            (
+                meta.source_width,
+                meta.source_height,
+                meta.width,
+                meta.height
+            ),
+            (720, 576, 245, 180)
+        );
+        let image = image::load_from_memory_with_format(&bytes, image::ImageFormat::Png).unwrap();
+        assert_eq!((image.width(), image.height()), (245, 180));
+    }
+
+    #[test]
+    fn anamorphic_standard_definition_is_thumbnailed_at_16_by_9() {
+        let backend = common::mini_recorder();
+        let (sink, thumbnails) = channel_sink();
+        let mode = DecklinkDisplayModeId::NTSC;
+        let capture = start(
+            &backend,
+            mode,
+            ThumbnailInterval::EveryFrames(1),
+            |spec| ThumbnailSpec {
+                pixel_aspect: mode.pixel_aspect_ratio(true).unwrap(),
+                ..spec
+            },
+            sink,
+        );
+
+        assert!(capture
+            .mock
+            .deliver_frame(MockFrame::for_mode(mode, FORMAT))
+            .is_ok());
+        let (bytes, meta) = thumbnails.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!((meta.source_width, meta.source_height), (720, 486));
+        let image = image::load_from_memory_with_format(&bytes, image::ImageFormat::Png).unwrap();
+        assert_eq!((image.width(), image.height()), (320, 178));
+        // The picture is 16:9 over the middle 704 samples of 480 of the lines
+        let picture =
+            image.width() as f64 * 704.0 / 720.0 / (image.height() as f64 * 480.0 / 486.0);
+        assert!((picture - 16.0 / 9.0).abs() < 0.01, "{}", picture);
+    }
+
+    #[test]
+    fn ten_bit_rgb_frames_are_thumbnailed() {
+        let backend = common::mini_recorder();
+        let (sink, thumbnails) = channel_sink();
+        let capture = start(
+            &backend,
+            DecklinkDisplayModeId::HD1080p25,
+            ThumbnailInterval::EveryFrames(1),
+            |spec| ThumbnailSpec {
+                max_width: 4,
+                max_height: 4,
+                ..spec
+            },
+            sink,
+        );
+
+        // Video range red in the little endian layout, which read big endian is not red
+        let red = (940u32 << 22 | 64 << 12 | 64 << 2).to_le_bytes();
+        let bytes: Vec<u8> = (0..8 * 64).flat_map(|_| red).collect();
+        let frame = MockFrame::new(8, 8, DecklinkPixelFormat::Format10BitRGBXLE).bytes(&bytes);
+        assert!(capture.mock.deliver_frame(frame).is_ok());
+        let (bytes, _) = thumbnails.recv_timeout(Duration::from_secs(5)).unwrap();
+        let image = image::load_from_memory(&bytes).unwrap().to_rgb8();
+        assert!(image.pixels().all(|p| p.0 == [0xff, 0, 0]));
+    }
+
+    /// The 4x4 thumbnail of an 8x8 BGRA frame in an interlaced mode, with white upper field
+    /// rows and black lower field rows.
+    fn field_thumbnail(policy: DeinterlacePolicy) -> image::RgbImage {
+        let backend = common::mini_recorder();
+        let (sink, thumbnails) = channel_sink();
+        let capture = start(
+            &backend,
+            DecklinkDisplayModeId::HD1080i50,
+            ThumbnailInterval::EveryFrames(1),
+            |spec| ThumbnailSpec {
+                max_width: 4,
+                max_height: 4,
+                deinterlace: policy,
+                ..spec
+            },
+            sink,
+        );
+
+        let mut bytes = vec![0u8; 8 * 32];
+        for row in (0..8).step_by(2) {
+            bytes[row * 32..(row + 1) * 32].fill(0xff);
+        }
+        let frame = MockFrame::new(8, 8, DecklinkPixelFormat::Format8BitBGRA).bytes(&bytes);
+        assert!(capture.mock.deliver_frame(frame).is_ok());
+        let (bytes, _) = thumbnails.recv_timeout(Duration::from_secs(5)).unwrap();
+        image::load_from_memory(&bytes).unwrap().to_rgb8()
+    }
+
+    #[test]
+    fn interlaced_frames_are_scaled_from_one_field() {
+        let field = |policy| {
+            let image = field_thumbnail(policy);
+            assert_eq!(image.dimensions(), (4, 4));
+            let first = image.get_pixel(0, 0).0;
+            assert!(image.pixels().all(|p| p.0 == first));
+            first
+        };
+
+        // The mode is upper field first
+        assert_eq!(field(DeinterlacePolicy::Auto), [0xff; 3]);
+        assert_eq!(field(DeinterlacePolicy::BobBottomFirst), [0; 3]);
+        // Left woven, the fields are averaged together
+        assert_eq!(field(DeinterlacePolicy::None), [0x80; 3]);
+    }
+}
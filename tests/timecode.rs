@@ -0,0 +1,533 @@
+//! Drop frame timecode arithmetic, and the source choice and continuity checks of a
+//! `TimecodeTracker` fed scripted timecodes and frames of a mock input.
+
+#[cfg(feature = "mock-backend")]
+mod common;
+
+use decklink::time::DecklinkTime;
+use decklink::timecode::{
+    frame_rate_of, DecklinkTimecode, DecklinkTimecodeFlags, DecklinkTimecodeFormat, TimecodeEvent,
+    TimecodeSourceStats, TimecodeTracker, TimecodeTrackerConfig,
+};
+
+use DecklinkTimecodeFormat::{RP188HighFrameRate, RP188LTC, RP188VITC1};
+
+fn tc(hours: u8, minutes: u8, seconds: u8, frames: u8) -> DecklinkTimecode {
+    DecklinkTimecode {
+        hours,
+        minutes,
+        seconds,
+        frames,
+        flags: DecklinkTimecodeFlags::empty(),
+        user_bits: 0,
+    }
+}
+
+fn drop_frame(hours: u8, minutes: u8, seconds: u8, frames: u8) -> DecklinkTimecode {
+    DecklinkTimecode {
+        flags: DecklinkTimecodeFlags::IS_DROP_FRAME,
+        ..tc(hours, minutes, seconds, frames)
+    }
+}
+
+fn field_mark(timecode: DecklinkTimecode) -> DecklinkTimecode {
+    DecklinkTimecode {
+        flags: timecode.flags | DecklinkTimecodeFlags::FIELD_MARK,
+        ..timecode
+    }
+}
+
+/// A tracker of `formats` at `frame_rate`, returning to a preferred source after 3 frames.
+fn tracker(formats: &[DecklinkTimecodeFormat], frame_rate: u32) -> TimecodeTracker {
+    TimecodeTracker::new(TimecodeTrackerConfig {
+        formats: formats.to_vec(),
+        frame_rate,
+        return_after: 3,
+    })
+}
+
+/// Observe a frame with the timecodes of `present`, and none in the other formats.
+fn observe(tracker: &mut TimecodeTracker, present: &[(DecklinkTimecodeFormat, DecklinkTimecode)]) {
+    tracker.observe(|format| {
+        present
+            .iter()
+            .find(|(f, _)| *f == format)
+            .map(|(_, timecode)| *timecode)
+    });
+}
+
+/// The source `best` uses.
+fn best_format(tracker: &TimecodeTracker) -> Option<DecklinkTimecodeFormat> {
+    tracker.best().map(|(format, _)| format)
+}
+
+#[test]
+fn drop_frames_are_skipped_at_each_minute_but_the_tenth() {
+    // 29.97 frames per second skips ;00 and ;01
+    assert_eq!(drop_frame(0, 0, 59, 29).frame_number(30), 1799);
+    assert_eq!(drop_frame(0, 1, 0, 2).frame_number(30), 1800);
+    assert_eq!(
+        DecklinkTimecode::from_frame_number(1800, 30, true),
+        drop_frame(0, 1, 0, 2)
+    );
+    assert_eq!(drop_frame(0, 9, 59, 29).frame_number(30), 17981);
+    assert_eq!(drop_frame(0, 10, 0, 0).frame_number(30), 17982);
+    assert_eq!(
+        DecklinkTimecode::from_frame_number(17982, 30, true),
+        drop_frame(0, 10, 0, 0)
+    );
+    assert_eq!(
+        DecklinkTimecode::from_frame_number(17983, 30, true),
+        drop_frame(0, 10, 0, 1)
+    );
+
+    // 59.94 frames per second skips four
+    assert_eq!(
+        DecklinkTimecode::from_frame_number(
+            drop_frame(0, 0, 59, 59).frame_number(60) + 1,
+            60,
+            true
+        ),
+        drop_frame(0, 1, 0, 4)
+    );
+
+    // Without the flag nothing is skipped
+    assert_eq!(tc(0, 1, 0, 0).frame_number(30), 1800);
+    assert_eq!(
+        DecklinkTimecode::from_frame_number(1800, 30, false),
+        tc(0, 1, 0, 0)
+    );
+}
+
+#[test]
+fn frame_numbers_round_trip_and_wrap_at_midnight() {
+    for (rate, drop) in [(25, false), (30, true), (60, true), (24, false)] {
+        for number in (0..20 * 60 * rate as u64).step_by(7) {
+            let timecode = DecklinkTimecode::from_frame_number(number, rate, drop);
+            assert_eq!(
+                timecode.frame_number(rate),
+                number,
+                "{} at {}",
+                timecode,
+                rate
+            );
+        }
+    }
+
+    // A day of 29.97 drop frame timecode is 2589408 frames
+    assert_eq!(
+        DecklinkTimecode::from_frame_number(2589407, 30, true),
+        drop_frame(23, 59, 59, 29)
+    );
+    assert_eq!(
+        DecklinkTimecode::from_frame_number(2589408, 30, true),
+        drop_frame(0, 0, 0, 0)
+    );
+    assert_eq!(
+        DecklinkTimecode::from_frame_number(25 * 86_400 + 3, 25, false),
+        tc(0, 0, 0, 3)
+    );
+}
+
+#[test]
+fn timecodes_are_displayed_with_their_separator() {
+    assert_eq!(tc(1, 2, 3, 4).to_string(), "01:02:03:04");
+    assert_eq!(drop_frame(10, 0, 0, 2).to_string(), "10:00:00;02");
+}
+
+#[test]
+fn frame_rates_are_rounded_to_whole_frames() {
+    assert_eq!(frame_rate_of(DecklinkTime::new(1001, 30000)), 30);
+    assert_eq!(frame_rate_of(DecklinkTime::new(1000, 25000)), 25);
+    assert_eq!(frame_rate_of(DecklinkTime::new(1001, 60000)), 60);
+    assert_eq!(frame_rate_of(DecklinkTime::new(1001, 24000)), 24);
+    assert_eq!(frame_rate_of(DecklinkTime::new(0, 25000)), 0);
+}
+
+#[test]
+fn the_default_config_prefers_vitc1_then_ltc() {
+    let config = TimecodeTrackerConfig::new(25);
+    assert_eq!(
+        config.formats,
+        [
+            RP188VITC1,
+            RP188LTC,
+            DecklinkTimecodeFormat::RP188VITC2,
+            DecklinkTimecodeFormat::VITC,
+        ]
+    );
+    assert_eq!(config.return_after, 25);
+}
+
+#[test]
+fn best_prefers_the_first_source_present() {
+    let mut tracker = tracker(&[RP188VITC1, RP188LTC], 25);
+    assert_eq!(tracker.best(), None);
+
+    observe(
+        &mut tracker,
+        &[(RP188LTC, tc(1, 0, 0, 0)), (RP188VITC1, tc(10, 0, 0, 0))],
+    );
+    assert_eq!(tracker.best(), Some((RP188VITC1, tc(10, 0, 0, 0))));
+    assert_eq!(
+        tracker.take_events(),
+        [
+            TimecodeEvent::Appeared {
+                format: RP188VITC1,
+                frame: 0
+            },
+            TimecodeEvent::Appeared {
+                format: RP188LTC,
+                frame: 0
+            },
+            TimecodeEvent::BestChanged {
+                from: None,
+                to: Some(RP188VITC1),
+                frame: 0
+            },
+        ]
+    );
+
+    // With no source, there is no best
+    observe(&mut tracker, &[]);
+    assert_eq!(tracker.best(), None);
+    assert_eq!(tracker.frame_count(), 2);
+}
+
+#[test]
+fn best_sticks_until_the_preferred_source_has_returned_for_long_enough() {
+    let mut tracker = tracker(&[RP188VITC1, RP188LTC], 25);
+    observe(&mut tracker, &[(RP188LTC, tc(1, 0, 0, 0))]);
+    assert_eq!(best_format(&tracker), Some(RP188LTC));
+    tracker.take_events();
+
+    // VITC1 comes and goes, so LTC is kept
+    for frame in 1..3 {
+        observe(
+            &mut tracker,
+            &[
+                (RP188VITC1, tc(10, 0, 0, frame)),
+                (RP188LTC, tc(1, 0, 0, frame)),
+            ],
+        );
+        assert_eq!(best_format(&tracker), Some(RP188LTC));
+    }
+    observe(&mut tracker, &[(RP188LTC, tc(1, 0, 0, 3))]);
+    for frame in 4..6 {
+        observe(
+            &mut tracker,
+            &[
+                (RP188VITC1, tc(10, 0, 0, frame)),
+                (RP188LTC, tc(1, 0, 0, frame)),
+            ],
+        );
+        assert_eq!(best_format(&tracker), Some(RP188LTC));
+    }
+
+    // On its third frame in a row, VITC1 is chosen
+    observe(
+        &mut tracker,
+        &[(RP188VITC1, tc(10, 0, 0, 6)), (RP188LTC, tc(1, 0, 0, 6))],
+    );
+    assert_eq!(tracker.best(), Some((RP188VITC1, tc(10, 0, 0, 6))));
+    let changes: Vec<_> = tracker
+        .take_events()
+        .into_iter()
+        .filter(|e| matches!(e, TimecodeEvent::BestChanged { .. }))
+        .collect();
+    assert_eq!(
+        changes,
+        [TimecodeEvent::BestChanged {
+            from: Some(RP188LTC),
+            to: Some(RP188VITC1),
+            frame: 6
+        }]
+    );
+
+    // Once the chosen source goes, the next one present is used at once
+    observe(&mut tracker, &[(RP188LTC, tc(1, 0, 0, 7))]);
+    assert_eq!(best_format(&tracker), Some(RP188LTC));
+}
+
+#[test]
+fn jumps_and_duplicates_are_discontinuities() {
+    let mut tracker = tracker(&[RP188VITC1], 25);
+    for timecode in [
+        tc(10, 0, 0, 0),
+        tc(10, 0, 0, 1),
+        tc(10, 0, 0, 5),
+        tc(10, 0, 0, 5),
+    ] {
+        observe(&mut tracker, &[(RP188VITC1, timecode)]);
+    }
+    // After a jump, the count follows on from the new timecode
+    observe(&mut tracker, &[(RP188VITC1, tc(10, 0, 0, 6))]);
+
+    let events = tracker.take_events();
+    assert_eq!(
+        events[2..],
+        [
+            TimecodeEvent::Jump {
+                format: RP188VITC1,
+                frame: 2,
+                expected: tc(10, 0, 0, 2),
+                actual: tc(10, 0, 0, 5),
+            },
+            TimecodeEvent::Duplicate {
+                format: RP188VITC1,
+                frame: 3,
+                timecode: tc(10, 0, 0, 5),
+            },
+        ]
+    );
+    assert_eq!(
+        tracker.stats(),
+        [TimecodeSourceStats {
+            format: RP188VITC1,
+            frames_with_timecode: 5,
+            longest_gap: 0,
+            discontinuities: 2,
+            duplicates: 1,
+        }]
+    );
+}
+
+#[test]
+fn drop_frame_minutes_follow_on() {
+    let mut tracker = tracker(&[RP188LTC], 30);
+    for timecode in [
+        drop_frame(0, 0, 59, 28),
+        drop_frame(0, 0, 59, 29),
+        drop_frame(0, 1, 0, 2),
+        drop_frame(0, 1, 0, 3),
+    ] {
+        observe(&mut tracker, &[(RP188LTC, timecode)]);
+    }
+    assert_eq!(tracker.stats()[0].discontinuities, 0);
+
+    // The tenth minute drops nothing, so ;02 there is a jump
+    let mut tracker = tracker_at(drop_frame(0, 9, 59, 29));
+    observe(&mut tracker, &[(RP188LTC, drop_frame(0, 10, 0, 2))]);
+    assert_eq!(
+        tracker.take_events().last(),
+        Some(&TimecodeEvent::Jump {
+            format: RP188LTC,
+            frame: 1,
+            expected: drop_frame(0, 10, 0, 0),
+            actual: drop_frame(0, 10, 0, 2),
+        })
+    );
+
+    // And counting through the dropped numbers is a jump at every other minute
+    let mut tracker = tracker_at(drop_frame(0, 0, 59, 29));
+    observe(&mut tracker, &[(RP188LTC, drop_frame(0, 1, 0, 0))]);
+    assert!(matches!(
+        tracker.take_events().last(),
+        Some(TimecodeEvent::Jump { expected, .. }) if *expected == drop_frame(0, 1, 0, 2)
+    ));
+}
+
+/// A 29.97 frames per second LTC tracker that has seen `timecode`, with its events taken.
+fn tracker_at(timecode: DecklinkTimecode) -> TimecodeTracker {
+    let mut tracker = tracker(&[RP188LTC], 30);
+    observe(&mut tracker, &[(RP188LTC, timecode)]);
+    tracker.take_events();
+    tracker
+}
+
+#[test]
+fn timecode_wraps_at_midnight() {
+    let mut tracker = tracker(&[RP188LTC], 25);
+    observe(&mut tracker, &[(RP188LTC, tc(23, 59, 59, 24))]);
+    observe(&mut tracker, &[(RP188LTC, tc(0, 0, 0, 0))]);
+    assert_eq!(tracker.stats()[0].discontinuities, 0);
+}
+
+#[test]
+fn high_frame_rates_count_pairs_of_frames() {
+    // At 50 frames per second, RP188 counts 25 and marks the second frame of each pair, but
+    // the high frame rate timecode counts every frame
+    let mut tracker = tracker(&[RP188VITC1, RP188HighFrameRate], 50);
+    for (vitc, hfr) in [
+        (tc(0, 0, 0, 24), tc(0, 0, 0, 48)),
+        (field_mark(tc(0, 0, 0, 24)), tc(0, 0, 0, 49)),
+        (tc(0, 0, 1, 0), tc(0, 0, 1, 0)),
+        (field_mark(tc(0, 0, 1, 0)), tc(0, 0, 1, 1)),
+    ] {
+        observe(
+            &mut tracker,
+            &[(RP188VITC1, vitc), (RP188HighFrameRate, hfr)],
+        );
+    }
+    assert!(tracker.stats().iter().all(|s| s.discontinuities == 0));
+
+    // A missing field mark repeats the count
+    observe(
+        &mut tracker,
+        &[
+            (RP188VITC1, tc(0, 0, 1, 1)),
+            (RP188HighFrameRate, tc(0, 0, 1, 2)),
+        ],
+    );
+    observe(
+        &mut tracker,
+        &[
+            (RP188VITC1, tc(0, 0, 1, 1)),
+            (RP188HighFrameRate, tc(0, 0, 1, 3)),
+        ],
+    );
+    assert_eq!(tracker.stats()[0].duplicates, 1);
+    assert_eq!(tracker.stats()[1].discontinuities, 0);
+}
+
+#[test]
+fn gaps_are_measured_and_reported() {
+    let mut tracker = tracker(&[RP188VITC1], 25);
+    observe(&mut tracker, &[]);
+    observe(&mut tracker, &[(RP188VITC1, tc(10, 0, 0, 0))]);
+    for _ in 0..3 {
+        observe(&mut tracker, &[]);
+    }
+    // The timecode kept counting while it was missing, so it follows on
+    observe(&mut tracker, &[(RP188VITC1, tc(10, 0, 0, 4))]);
+    observe(&mut tracker, &[]);
+
+    let appearances: Vec<_> = tracker
+        .take_events()
+        .into_iter()
+        .filter(|e| !matches!(e, TimecodeEvent::BestChanged { .. }))
+        .collect();
+    assert_eq!(
+        appearances,
+        [
+            TimecodeEvent::Appeared {
+                format: RP188VITC1,
+                frame: 1
+            },
+            TimecodeEvent::Disappeared {
+                format: RP188VITC1,
+                frame: 2
+            },
+            TimecodeEvent::Appeared {
+                format: RP188VITC1,
+                frame: 5
+            },
+            TimecodeEvent::Disappeared {
+                format: RP188VITC1,
+                frame: 6
+            },
+        ]
+    );
+    let stats = tracker.stats()[0];
+    // The frame before the source first appeared is not a gap
+    assert_eq!(stats.longest_gap, 3);
+    assert_eq!(stats.frames_with_timecode, 2);
+    assert_eq!(stats.discontinuities, 0);
+}
+
+#[cfg(feature = "mock-backend")]
+mod mock {
+    use super::common;
+    use super::*;
+    use decklink::device::get_devices;
+    use decklink::device::input::{
+        DeckLinkInputCallback, DecklinkDetectedVideoInputFormatFlags, DecklinkVideoInputFlags,
+        DecklinkVideoInputFormatChangedEvents,
+    };
+    use decklink::display_mode::DecklinkDisplayModeId;
+    use decklink::frame::{DecklinkPixelFormat, DecklinkVideoFrame};
+    use decklink::mock::MockFrame;
+    use std::sync::{Arc, Mutex};
+
+    const FORMAT: DecklinkPixelFormat = DecklinkPixelFormat::Format8BitYUV;
+
+    /// Tracks the timecode of every frame, and reads each frame's RP188 timecode of any kind.
+    struct Tracking {
+        tracker: Mutex<TimecodeTracker>,
+        any: Mutex<Vec<Option<DecklinkTimecode>>>,
+    }
+
+    impl DeckLinkInputCallback for Tracking {
+        fn video_input_format_changed(
+            &self,
+            _events: DecklinkVideoInputFormatChangedEvents,
+            _new_display_mode: DecklinkDisplayModeId,
+            _detected_signal_flags: DecklinkDetectedVideoInputFormatFlags,
+        ) {
+        }
+
+        fn video_input_frame_arrived(&self, video_frame: Option<DecklinkVideoFrame>) -> bool {
+            let frame = video_frame.unwrap();
+            self.tracker.lock().unwrap().observe_frame(&frame);
+            let any = frame.timecode(DecklinkTimecodeFormat::RP188Any).unwrap();
+            self.any.lock().unwrap().push(any);
+            true
+        }
+    }
+
+    #[test]
+    fn timecode_is_read_from_captured_frames() {
+        let backend = common::mini_recorder();
+        let devices = get_devices().unwrap();
+        let mut input = devices[0].input().unwrap();
+        let tracking = Arc::new(Tracking {
+            tracker: Mutex::new(TimecodeTracker::new(TimecodeTrackerConfig::new(25))),
+            any: Mutex::new(Vec::new()),
+        });
+        input
+            .enable_video_input(
+                DecklinkDisplayModeId::HD1080p25,
+                FORMAT,
+                DecklinkVideoInputFlags::empty(),
+            )
+            .unwrap();
+        common::start_streams(&mut input, tracking.clone());
+
+        let mock = backend.input(0);
+        let user_bits = DecklinkTimecode {
+            user_bits: 0x1234_5678,
+            ..tc(10, 0, 0, 0)
+        };
+        let frames = [
+            MockFrame::new(48, 2, FORMAT)
+                .timecode(RP188VITC1, user_bits)
+                .timecode(RP188LTC, tc(1, 0, 0, 0)),
+            MockFrame::new(48, 2, FORMAT).timecode(RP188LTC, tc(1, 0, 0, 1)),
+            MockFrame::new(48, 2, FORMAT),
+        ];
+        for frame in frames {
+            assert!(mock.deliver_frame(frame).is_ok());
+        }
+        input.stop_streams().unwrap();
+
+        assert_eq!(
+            *tracking.any.lock().unwrap(),
+            [Some(user_bits), Some(tc(1, 0, 0, 1)), None]
+        );
+        let mut tracker = tracking.tracker.lock().unwrap();
+        assert_eq!(tracker.frame_count(), 3);
+        assert_eq!(tracker.best(), None);
+        let stats = tracker.stats();
+        assert_eq!(
+            (stats[0].format, stats[0].frames_with_timecode),
+            (RP188VITC1, 1)
+        );
+        assert_eq!(
+            (stats[1].format, stats[1].frames_with_timecode),
+            (RP188LTC, 2)
+        );
+        assert!(stats.iter().all(|s| s.discontinuities == 0));
+        let changes: Vec<_> = tracker
+            .take_events()
+            .into_iter()
+            .filter_map(|e| match e {
+                TimecodeEvent::BestChanged { to, frame, .. } => Some((frame, to)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            changes,
+            [(0, Some(RP188VITC1)), (1, Some(RP188LTC)), (2, None)]
+        );
+    }
+}
@@ -0,0 +1,217 @@
+//! Finding frames by time in synthetic timelines, with drops, format changes and fractional
+//! rates.
+
+use decklink::time::{DecklinkFrameTiming, DecklinkTime};
+use decklink::timeline::{FrameLocator, FrameTimeline};
+
+/// The timing of a frame at `value` ticks lasting `duration`, of `scale` ticks a second.
+fn timing(value: i64, duration: i64, scale: i64) -> DecklinkFrameTiming {
+    DecklinkFrameTiming {
+        stream_time: DecklinkTime::new(value, scale),
+        duration: DecklinkTime::new(duration, scale),
+        mode_duration: DecklinkTime::new(duration, scale),
+    }
+}
+
+/// Record frames `frames` of a `duration` tick rate, numbering them by frame.
+fn record(timeline: &mut FrameTimeline, frames: std::ops::Range<i64>, duration: i64, scale: i64) {
+    for n in frames {
+        timeline.record(n as u64, &timing(n * duration, duration, scale), None);
+    }
+}
+
+fn at(value: i64, scale: i64) -> DecklinkTime {
+    DecklinkTime::new(value, scale)
+}
+
+fn sequence(frame: Option<FrameLocator>) -> Option<u64> {
+    frame.map(|f| f.sequence)
+}
+
+fn sequences(frames: &[FrameLocator]) -> Vec<u64> {
+    frames.iter().map(|f| f.sequence).collect()
+}
+
+#[test]
+fn frames_cover_half_open_intervals() {
+    let mut timeline = FrameTimeline::new(50);
+    record(&mut timeline, 0..10, 1000, 25000);
+
+    assert_eq!(sequence(timeline.frame_at(at(0, 25000))), Some(0));
+    assert_eq!(sequence(timeline.frame_at(at(999, 25000))), Some(0));
+    assert_eq!(sequence(timeline.frame_at(at(1000, 25000))), Some(1));
+    assert_eq!(sequence(timeline.frame_at(at(9999, 25000))), Some(9));
+    // The end of the last frame is not covered
+    assert_eq!(timeline.frame_at(at(10000, 25000)), None);
+    assert_eq!(timeline.frame_at(at(-1, 25000)), None);
+
+    let frame = timeline.latest().unwrap();
+    assert_eq!(frame.end(), at(10000, 25000));
+    assert!(frame.contains(at(9000, 25000)) && !frame.contains(at(10000, 25000)));
+}
+
+#[test]
+fn every_time_maps_to_its_frame_at_fractional_rates() {
+    // 29.97 and 59.94 Hz, queried in microseconds
+    for duration in [1001, 2002] {
+        let mut timeline = FrameTimeline::new(100);
+        record(&mut timeline, 0..60, duration, 30000);
+
+        let end = 60 * duration * 1_000_000 / 30000;
+        for micros in (0..end).step_by(37) {
+            // The query is rounded to the nearest tick of the frames' timescale
+            let tick = (micros * 30000 + 500_000) / 1_000_000;
+            let expected = (tick / duration) as u64;
+            assert_eq!(
+                sequence(timeline.frame_at(at(micros, 1_000_000))),
+                Some(expected).filter(|n| *n < 60),
+                "{} us at {} ticks a frame",
+                micros,
+                duration
+            );
+        }
+    }
+}
+
+#[test]
+fn ranges_are_half_open() {
+    let mut timeline = FrameTimeline::new(50);
+    record(&mut timeline, 0..10, 1001, 30000);
+
+    assert_eq!(
+        sequences(&timeline.range(at(1001, 30000), at(4004, 30000))),
+        [1, 2, 3]
+    );
+    assert_eq!(
+        sequences(&timeline.range(at(1000, 30000), at(4005, 30000))),
+        [0, 1, 2, 3, 4]
+    );
+    // In milliseconds: 100 ms to 200 ms is frames 2 to 5 at 29.97 Hz
+    assert_eq!(
+        sequences(&timeline.range(at(100, 1000), at(200, 1000))),
+        [2, 3, 4, 5]
+    );
+    assert!(timeline.range(at(2000, 30000), at(2000, 30000)).is_empty());
+    assert!(timeline
+        .range(at(20000, 30000), at(30000, 30000))
+        .is_empty());
+}
+
+#[test]
+fn dropped_frames_split_the_timeline() {
+    let mut timeline = FrameTimeline::new(50);
+    record(&mut timeline, 0..5, 1000, 25000);
+    // Frames 5 and 6 were dropped
+    record(&mut timeline, 7..10, 1000, 25000);
+
+    let runs: Vec<u64> = timeline.frames().map(|f| f.run).collect();
+    assert_eq!(runs, [0, 0, 0, 0, 0, 1, 1, 1]);
+    assert_eq!(timeline.frame_at(at(5500, 25000)), None);
+
+    // Queries into the gap find the frame on the nearer side, or the later of two
+    let tolerance = at(2000, 25000);
+    assert_eq!(
+        sequence(timeline.nearest(at(5200, 25000), tolerance)),
+        Some(4)
+    );
+    assert_eq!(
+        sequence(timeline.nearest(at(6800, 25000), tolerance)),
+        Some(7)
+    );
+    assert_eq!(
+        sequence(timeline.nearest(at(6000, 25000), tolerance)),
+        Some(7)
+    );
+    assert_eq!(timeline.nearest(at(6000, 25000), at(999, 25000)), None);
+
+    // A range across the gap returns the later run only
+    assert_eq!(
+        sequences(&timeline.range(at(3000, 25000), at(9000, 25000))),
+        [7, 8]
+    );
+    assert_eq!(
+        sequences(&timeline.range(at(3000, 25000), at(5500, 25000))),
+        [3, 4]
+    );
+}
+
+#[test]
+fn format_changes_and_noted_discontinuities_start_runs() {
+    let mut timeline = FrameTimeline::new(50);
+    record(&mut timeline, 0..3, 1000, 25000);
+    // Frames dropped in the callback leave no gap in the stream time
+    timeline.discontinuity();
+    record(&mut timeline, 3..5, 1000, 25000);
+    // The signal changes to 59.94 Hz, continuing from 200 ms
+    timeline.format_changed();
+    for n in 0..4 {
+        let value = 12000 + n * 1001;
+        timeline.record(10 + n as u64, &timing(value, 1001, 60000), None);
+    }
+
+    let runs: Vec<u64> = timeline.frames().map(|f| f.run).collect();
+    assert_eq!(runs, [0, 0, 0, 1, 1, 2, 2, 2, 2]);
+    assert_eq!(
+        sequences(&timeline.range(at(0, 25000), at(10000, 25000))),
+        [10, 11, 12, 13]
+    );
+    assert_eq!(
+        sequences(&timeline.range(at(0, 25000), at(4000, 25000))),
+        [3]
+    );
+    // The new rate is found in both timescales
+    assert_eq!(sequence(timeline.frame_at(at(13001, 60000))), Some(11));
+    assert_eq!(
+        sequence(timeline.frame_at(at(220, 1000))),
+        Some(11),
+        "220 ms is 13200 ticks at 60 kHz"
+    );
+}
+
+#[test]
+fn restarted_stream_times_prefer_the_latest_run() {
+    let mut timeline = FrameTimeline::new(50);
+    record(&mut timeline, 0..5, 1000, 25000);
+    // The stream restarts from zero
+    for n in 0..3 {
+        timeline.record(100 + n as u64, &timing(n * 1000, 1000, 25000), None);
+    }
+
+    assert_eq!(timeline.latest().unwrap().run, 1);
+    assert_eq!(sequence(timeline.frame_at(at(1500, 25000))), Some(101));
+    assert_eq!(sequence(timeline.frame_at(at(3500, 25000))), Some(3));
+    assert_eq!(
+        sequences(&timeline.range(at(0, 25000), at(5000, 25000))),
+        [100, 101, 102]
+    );
+}
+
+#[test]
+fn oldest_frames_are_forgotten() {
+    let mut timeline = FrameTimeline::new(4);
+    record(&mut timeline, 0..10, 1000, 25000);
+
+    assert_eq!((timeline.len(), timeline.capacity()), (4, 4));
+    assert_eq!(timeline.frames().next().unwrap().sequence, 6);
+    assert_eq!(timeline.frame_at(at(5500, 25000)), None);
+    assert_eq!(sequence(timeline.frame_at(at(6500, 25000))), Some(6));
+
+    let timeline = FrameTimeline::new(0);
+    assert_eq!(timeline.capacity(), 1);
+    assert!(timeline.is_empty() && timeline.latest().is_none());
+}
+
+#[test]
+fn locators_match_the_frame_they_locate() {
+    let mut timeline = FrameTimeline::new(4);
+    let hardware = at(123_456, 1_000_000);
+    let frame = timeline.record(7, &timing(7007, 1001, 30000), Some(hardware));
+    assert_eq!(frame.hardware_time, Some(hardware));
+    assert_eq!(timeline.latest(), Some(frame));
+
+    assert!(frame.matches(&timing(7007, 1001, 30000)));
+    assert!(frame.matches(&timing(7008, 1001, 30000)));
+    assert!(!frame.matches(&timing(8008, 1001, 30000)));
+    // 7007 ticks at 30 kHz is 14014 at 60 kHz
+    assert!(frame.matches(&timing(14014, 2002, 60000)));
+}
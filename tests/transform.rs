@@ -0,0 +1,417 @@
+//! Negotiating and running chains of frame transforms, the crop and mask stages, and a chain
+//! run on a tap of a mock capture.
+
+#[cfg(feature = "mock-backend")]
+mod common;
+
+use decklink::deinterlace::FrameLayout;
+use decklink::experimental::transform::{
+    ChainError, Crop, FrameBufferMut, FrameTransform, FrameView, MaskRegion, NegotiationError,
+    TransformChain, TransformError, TransformEvent,
+};
+use decklink::frame::{DecklinkPixelFormat, Rect};
+use decklink::testing::{FillPattern, TestFrame, TestFrameBuilder};
+
+fn frame(width: usize, height: usize, fill: FillPattern) -> TestFrame {
+    TestFrameBuilder::new(width, height)
+        .fill(fill)
+        .build()
+        .unwrap()
+}
+
+fn layout(width: usize, height: usize) -> FrameLayout {
+    FrameLayout {
+        width,
+        height,
+        row_bytes: width * 2,
+        pixel_format: DecklinkPixelFormat::Format8BitYUV,
+    }
+}
+
+fn rect(x: usize, y: usize, width: usize, height: usize) -> Rect {
+    Rect {
+        x,
+        y,
+        width,
+        height,
+    }
+}
+
+/// Fails on frames whose first byte is odd, and copies the rest.
+struct FailOdd;
+
+impl FrameTransform for FailOdd {
+    fn name(&self) -> &str {
+        "fail odd"
+    }
+
+    fn negotiate(&self, input: FrameLayout) -> Result<FrameLayout, NegotiationError> {
+        Ok(input)
+    }
+
+    fn process(
+        &self,
+        input: FrameView<'_>,
+        output: &mut FrameBufferMut<'_>,
+    ) -> Result<(), TransformError> {
+        if input.data()[0] % 2 == 1 {
+            return Err(TransformError::Failed("odd".to_string()));
+        }
+        output.data_mut().copy_from_slice(input.data());
+        Ok(())
+    }
+}
+
+/// Panics on frames whose first byte is `panic_at`, or when negotiating if there is none,
+/// and copies the rest.
+struct Panicking {
+    panic_at: Option<u8>,
+}
+
+impl FrameTransform for Panicking {
+    fn name(&self) -> &str {
+        "panicking"
+    }
+
+    fn negotiate(&self, input: FrameLayout) -> Result<FrameLayout, NegotiationError> {
+        if self.panic_at.is_none() {
+            panic!("the stage failed to negotiate");
+        }
+        Ok(input)
+    }
+
+    fn process(
+        &self,
+        input: FrameView<'_>,
+        output: &mut FrameBufferMut<'_>,
+    ) -> Result<(), TransformError> {
+        if Some(input.data()[0]) == self.panic_at {
+            panic!("the stage failed");
+        }
+        output.data_mut().copy_from_slice(input.data());
+        Ok(())
+    }
+}
+
+#[test]
+fn an_empty_chain_passes_frames_through() {
+    let mut chain = TransformChain::new();
+    assert!(chain.is_empty());
+    let frame = frame(8, 2, FillPattern::Counting);
+
+    let view = chain.run(&frame).unwrap();
+    assert_eq!(view.layout(), layout(8, 2));
+    assert_eq!(view.data(), (0..32).collect::<Vec<u8>>());
+    assert_eq!(chain.output_layout(), Some(layout(8, 2)));
+}
+
+#[test]
+fn crop_cuts_out_its_rectangle() {
+    let mut chain = TransformChain::new().with(Crop {
+        rect: rect(2, 1, 4, 1),
+    });
+    let frame = frame(8, 2, FillPattern::Counting);
+
+    let view = chain.run(&frame).unwrap();
+    assert_eq!(view.layout(), layout(4, 1));
+    assert_eq!(view.data(), (20..28).collect::<Vec<u8>>());
+    assert_eq!(
+        chain.take_events(),
+        [TransformEvent::Configured {
+            input: layout(8, 2),
+            output: layout(4, 1),
+        }]
+    );
+}
+
+#[test]
+fn mask_blacks_out_its_rectangle_in_place() {
+    let mut chain = TransformChain::new()
+        .with(Crop {
+            rect: rect(2, 0, 4, 2),
+        })
+        .with(MaskRegion {
+            rect: rect(2, 0, 2, 2),
+        });
+    let frame = frame(8, 2, FillPattern::Solid(0xff));
+
+    let view = chain.run(&frame).unwrap();
+    let masked = [0xff, 0xff, 0xff, 0xff, 0x80, 0x10, 0x80, 0x10];
+    assert_eq!(view.data(), [masked, masked].concat());
+
+    // The crop changes the layout and so cannot be in place, but the mask can
+    let stats = chain.stats();
+    assert_eq!(
+        stats
+            .iter()
+            .map(|s| (s.name.as_str(), s.in_place))
+            .collect::<Vec<_>>(),
+        [("crop", false), ("mask", true)]
+    );
+    assert!(stats.iter().all(|s| s.frames == 1 && s.failures == 0));
+    assert!(stats[0].mean_time() <= stats[0].max_time);
+}
+
+#[test]
+fn refused_layouts_drop_frames_until_the_layout_changes() {
+    // A crop wider than the frame, then one off the pixel groups
+    for crop in [rect(0, 0, 10, 2), rect(1, 0, 2, 2)] {
+        let mut chain = TransformChain::new().with(Crop { rect: crop });
+        let error = NegotiationError::UnsupportedLayout(layout(8, 2));
+        assert!(matches!(
+            chain.configure(layout(8, 2)),
+            Err(ChainError::Negotiation { stage: 0, error: e }) if e == error
+        ));
+        assert_eq!(chain.output_layout(), None);
+    }
+
+    // The mask only knows black in the 8-bit formats, so refuses the crop of a v210 frame
+    let mut chain = TransformChain::new()
+        .with(Crop {
+            rect: rect(0, 0, 48, 2),
+        })
+        .with(MaskRegion {
+            rect: rect(0, 0, 48, 1),
+        });
+    let ten_bit = TestFrameBuilder::new(96, 2)
+        .pixel_format(DecklinkPixelFormat::Format10BitYUV)
+        .build()
+        .unwrap();
+    let error = NegotiationError::UnsupportedPixelFormat(DecklinkPixelFormat::Format10BitYUV);
+    for _ in 0..2 {
+        assert!(matches!(
+            chain.run(&ten_bit),
+            Err(ChainError::Negotiation { stage: 1, error: e }) if e == error
+        ));
+    }
+    // Negotiated once, not for every frame
+    let events = chain.take_events();
+    assert_eq!(events.len(), 1);
+    assert!(matches!(
+        events[0],
+        TransformEvent::NegotiationFailed { stage: 1, error: e, .. } if e == error
+    ));
+    assert_eq!(
+        error.to_string(),
+        "pixel format Format10BitYUV is not supported"
+    );
+
+    // An 8-bit frame is negotiated again, and accepted
+    assert!(chain.run(&frame(96, 2, FillPattern::Solid(1))).is_ok());
+}
+
+#[test]
+fn a_new_layout_renegotiates_the_chain() {
+    let mut chain = TransformChain::new().with(Crop {
+        rect: rect(0, 0, 4, 2),
+    });
+    for (width, height) in [(8, 2), (8, 2), (16, 4), (8, 2)] {
+        let frame = frame(width, height, FillPattern::Counting);
+        let view = chain.run(&frame).unwrap();
+        assert_eq!(view.layout(), layout(4, 2));
+    }
+    let inputs: Vec<_> = chain
+        .take_events()
+        .into_iter()
+        .map(|e| match e {
+            TransformEvent::Configured { input, .. } => input,
+            e => panic!("unexpected event {:?}", e),
+        })
+        .collect();
+    assert_eq!(inputs, [layout(8, 2), layout(16, 4), layout(8, 2)]);
+    assert_eq!(chain.stats()[0].frames, 4);
+}
+
+#[test]
+fn a_failing_stage_drops_only_that_frame() {
+    let mut chain = TransformChain::new().with(FailOdd);
+    for value in 0..4 {
+        let frame = frame(8, 2, FillPattern::Solid(value));
+        let result = chain.run(&frame);
+        if value % 2 == 0 {
+            assert_eq!(result.unwrap().data(), [value; 32]);
+        } else {
+            assert!(matches!(
+                result,
+                Err(ChainError::Transform {
+                    stage: 0,
+                    error: TransformError::Failed(_)
+                })
+            ));
+        }
+    }
+    let stats = chain.stats();
+    assert_eq!((stats[0].frames, stats[0].failures), (4, 2));
+    assert_eq!(chain.detached(), None);
+}
+
+#[test]
+fn a_panicking_stage_detaches_the_chain() {
+    let mut chain = TransformChain::new()
+        .with(FailOdd)
+        .with(Panicking { panic_at: Some(2) });
+    assert!(chain.run(&frame(8, 2, FillPattern::Solid(0))).is_ok());
+    assert!(matches!(
+        chain.run(&frame(8, 2, FillPattern::Solid(2))),
+        Err(ChainError::Panicked { stage: 1 })
+    ));
+    assert_eq!(chain.detached(), Some(1));
+    assert_eq!(
+        chain.take_events().last(),
+        Some(&TransformEvent::Detached { stage: 1 })
+    );
+
+    // Nothing runs once detached, not even a new layout
+    assert!(matches!(
+        chain.run(&frame(16, 2, FillPattern::Solid(0))),
+        Err(ChainError::Detached)
+    ));
+    assert!(matches!(
+        chain.configure(layout(8, 2)),
+        Err(ChainError::Detached)
+    ));
+    assert_eq!(chain.stats()[1].failures, 1);
+
+    // A panic while negotiating detaches it too
+    let mut chain = TransformChain::new().with(Panicking { panic_at: None });
+    assert!(matches!(
+        chain.configure(layout(8, 2)),
+        Err(ChainError::Panicked { stage: 0 })
+    ));
+    assert_eq!(chain.detached(), Some(0));
+}
+
+#[cfg(feature = "mock-backend")]
+mod mock {
+    use super::common;
+    use super::*;
+    use decklink::device::input::{
+        DeckLinkInputCallback, DecklinkDetectedVideoInputFormatFlags, DecklinkVideoInputFlags,
+        DecklinkVideoInputFormatChangedEvents,
+    };
+    use decklink::display_mode::DecklinkDisplayModeId;
+    use decklink::experimental::transform::{
+        DeckLinkTransformCallback, TransformStageStats, TransformTap,
+    };
+    use decklink::frame::DecklinkVideoFrame;
+    use decklink::mock::MockFrame;
+    use decklink::retention::RetentionLimit;
+    use decklink::tap::{DeckLinkTapCallback, TapReport, TapSpec, TapSplitter, TappedFrame};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    const FORMAT: DecklinkPixelFormat = DecklinkPixelFormat::Format8BitYUV;
+
+    #[derive(Default)]
+    struct Primary {
+        frames: AtomicUsize,
+    }
+
+    impl DeckLinkInputCallback for Primary {
+        fn video_input_format_changed(
+            &self,
+            _events: DecklinkVideoInputFormatChangedEvents,
+            _new_display_mode: DecklinkDisplayModeId,
+            _detected_signal_flags: DecklinkDetectedVideoInputFormatFlags,
+        ) {
+        }
+
+        fn video_input_frame_arrived(&self, _video_frame: Option<DecklinkVideoFrame>) -> bool {
+            self.frames.fetch_add(1, Ordering::SeqCst);
+            true
+        }
+    }
+
+    #[derive(Default)]
+    struct Seen {
+        /// The width and first byte of each transformed frame.
+        frames: Vec<(usize, u8)>,
+        events: Vec<TransformEvent>,
+        stats: Vec<TransformStageStats>,
+    }
+
+    struct Consumer(Arc<Mutex<Seen>>);
+
+    impl DeckLinkTransformCallback for Consumer {
+        fn frame_transformed(&mut self, frame: FrameView<'_>, _tapped: &TappedFrame) {
+            let seen = (frame.layout().width, frame.data()[0]);
+            self.0.lock().unwrap().frames.push(seen);
+        }
+
+        fn chain_events(&mut self, events: Vec<TransformEvent>) {
+            self.0.lock().unwrap().events.extend(events);
+        }
+
+        fn tap_finished(&mut self, _report: TapReport, stats: Vec<TransformStageStats>) {
+            self.0.lock().unwrap().stats = stats;
+        }
+    }
+
+    /// Counts the frames of a plain tap.
+    struct Counter(Arc<AtomicUsize>);
+
+    impl DeckLinkTapCallback for Counter {
+        fn frame_tapped(&mut self, _frame: TappedFrame) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn chains_renegotiate_and_detach_without_stopping_the_capture() {
+        let backend = common::mini_recorder();
+        let primary = Arc::new(Primary::default());
+        let splitter = TapSplitter::new(primary.clone(), None);
+        let mut input = common::open_input(
+            DecklinkDisplayModeId::HD1080p25,
+            FORMAT,
+            DecklinkVideoInputFlags::empty(),
+        );
+        common::start_streams(&mut input, splitter.callback());
+
+        let spec = TapSpec {
+            retention: RetentionLimit::Frames(16),
+            ..TapSpec::frames(5)
+        };
+        let seen = Arc::new(Mutex::new(Seen::default()));
+        let chain = TransformChain::new()
+            .with(Crop {
+                rect: rect(0, 0, 16, 2),
+            })
+            .with(Panicking { panic_at: Some(3) });
+        let transformed = splitter.tap(TransformTap::new(chain, Consumer(seen.clone())), spec);
+        let count = Arc::new(AtomicUsize::new(0));
+        let plain = splitter.tap(Counter(count.clone()), spec);
+
+        // The format changes after two frames, and the stage panics on the fourth
+        let mock = backend.input(0);
+        for (width, value) in [(48, 1), (48, 2), (96, 0), (96, 3), (96, 4)] {
+            let frame = MockFrame::new(width, 2, FORMAT).fill(value);
+            assert!(mock.deliver_frame(frame).is_ok());
+        }
+        transformed.join();
+        plain.join();
+        input.stop_streams().unwrap();
+
+        let seen = seen.lock().unwrap();
+        assert_eq!(seen.frames, [(16, 1), (16, 2), (16, 0)]);
+        let configured = |width| TransformEvent::Configured {
+            input: layout(width, 2),
+            output: layout(16, 2),
+        };
+        assert_eq!(
+            seen.events,
+            [
+                configured(48),
+                configured(96),
+                TransformEvent::Detached { stage: 1 }
+            ]
+        );
+        assert_eq!(seen.stats.len(), 2);
+        assert_eq!((seen.stats[0].frames, seen.stats[1].frames), (4, 4));
+        assert_eq!(seen.stats[1].failures, 1);
+
+        // The capture and the other tap carried on
+        assert_eq!(primary.frames.load(Ordering::SeqCst), 5);
+        assert_eq!(count.load(Ordering::SeqCst), 5);
+    }
+}
@@ -0,0 +1,360 @@
+//! Comparing fingerprints of frames from redundant sources.
+
+use decklink::frame::{DecklinkFrameFlags, DecklinkPixelFormat, DecklinkVideoMutableFrame};
+use decklink::verify::{
+    AsyncHasher, FrameFingerprint, PairStats, RedundancyComparator, SourceId, Verdict,
+};
+use std::sync::Arc;
+
+const A: SourceId = 1;
+const B: SourceId = 2;
+
+/// A 4 row BGRA frame filled with `value`, with `row` filled with `row_value` instead.
+fn frame(value: u8, row: usize, row_value: u8) -> DecklinkVideoMutableFrame {
+    let mut frame = DecklinkVideoMutableFrame::create(
+        8,
+        4,
+        32,
+        DecklinkPixelFormat::Format8BitBGRA,
+        DecklinkFrameFlags::empty(),
+    );
+    let mut bytes = vec![value; 4 * 32];
+    bytes[row * 32..(row + 1) * 32].fill(row_value);
+    frame.copy_bytes(&bytes).unwrap();
+    frame
+}
+
+fn fingerprint(frame: &DecklinkVideoMutableFrame) -> Arc<FrameFingerprint> {
+    Arc::new(FrameFingerprint::compute(frame).unwrap())
+}
+
+fn plain(value: u8) -> Arc<FrameFingerprint> {
+    fingerprint(&frame(value, 0, value))
+}
+
+#[test]
+fn fingerprints_locate_the_first_different_row() {
+    let a = plain(7);
+    assert_eq!(a.row_hashes.len(), 4);
+    assert_eq!(*a, *plain(7));
+    assert_eq!(a.first_different_row(&plain(7)), None);
+    assert_eq!(
+        a.first_different_row(&fingerprint(&frame(7, 2, 8))),
+        Some(2)
+    );
+}
+
+#[test]
+fn identical_sources_agree() {
+    let mut comparator = RedundancyComparator::new(0);
+    for t in 0..5 {
+        assert!(comparator.insert(A, t, plain(t as u8)).is_empty());
+        assert_eq!(
+            comparator.insert(B, t, plain(t as u8)),
+            vec![Verdict::Identical {
+                sources: (A, B),
+                timestamp: t,
+            }]
+        );
+    }
+
+    let stats = comparator.stats(B, A);
+    assert_eq!(
+        stats,
+        PairStats {
+            identical: 5,
+            ..Default::default()
+        }
+    );
+    assert_eq!(stats.agreement(), 1.0);
+}
+
+#[test]
+fn timestamps_are_matched_within_the_window() {
+    let mut comparator = RedundancyComparator::new(1);
+    for t in 0..3 {
+        comparator.insert(A, t * 10, plain(1));
+        assert_eq!(
+            comparator.insert(B, t * 10 + 1, plain(1)),
+            vec![Verdict::Identical {
+                sources: (A, B),
+                timestamp: t * 10 + 1,
+            }]
+        );
+    }
+    assert_eq!(comparator.stats(A, B).identical, 3);
+}
+
+#[test]
+fn sources_may_start_at_different_times() {
+    let mut comparator = RedundancyComparator::new(0);
+    // Nothing is missing before the second source has delivered anything
+    for t in 0..5 {
+        assert!(comparator.insert(A, t, plain(1)).is_empty());
+    }
+    for t in 5..8 {
+        comparator.insert(A, t, plain(1));
+        comparator.insert(B, t, plain(1));
+    }
+
+    assert_eq!(
+        comparator.stats(A, B),
+        PairStats {
+            identical: 3,
+            ..Default::default()
+        }
+    );
+}
+
+#[test]
+fn dropped_frames_are_missing_counterparts() {
+    let mut comparator = RedundancyComparator::new(0);
+    comparator.insert(A, 0, plain(1));
+    comparator.insert(B, 0, plain(1));
+    comparator.insert(A, 1, plain(1));
+    comparator.insert(A, 2, plain(1));
+
+    // B dropped frame 1, which is known once it delivers a later one
+    assert_eq!(
+        comparator.insert(B, 2, plain(1)),
+        vec![
+            Verdict::Identical {
+                sources: (A, B),
+                timestamp: 2,
+            },
+            Verdict::MissingCounterpart {
+                present: A,
+                missing: B,
+                timestamp: 1,
+            },
+        ]
+    );
+    let stats = comparator.stats(A, B);
+    assert_eq!((stats.identical, stats.missing), (2, 1));
+    // The match at 2 came before the miss was found
+    assert_eq!(stats.missing_streak, 1);
+
+    comparator.insert(A, 3, plain(1));
+    comparator.insert(B, 3, plain(1));
+    assert_eq!(comparator.stats(A, B).missing_streak, 0);
+}
+
+#[test]
+fn differing_frames_report_the_row() {
+    let mut comparator = RedundancyComparator::new(0);
+    comparator.insert(A, 0, plain(1));
+    comparator.insert(B, 0, plain(1));
+    comparator.insert(A, 1, fingerprint(&frame(1, 3, 0)));
+
+    assert_eq!(
+        comparator.insert(B, 1, plain(1)),
+        vec![Verdict::Different {
+            sources: (A, B),
+            timestamp: 1,
+            first_different_row: 3,
+        }]
+    );
+    let stats = comparator.stats(A, B);
+    assert_eq!((stats.identical, stats.different), (1, 1));
+    assert_eq!(stats.agreement(), 0.5);
+}
+
+#[test]
+fn format_mismatch_is_not_a_difference() {
+    let mut comparator = RedundancyComparator::new(0);
+    comparator.insert(A, 0, plain(1));
+    let mut other = (*plain(1)).clone();
+    other.pixel_format = DecklinkPixelFormat::Format8BitARGB;
+
+    assert_eq!(
+        comparator.insert(B, 0, Arc::new(other)),
+        vec![Verdict::FormatMismatch {
+            sources: (A, B),
+            timestamp: 0,
+        }]
+    );
+    let stats = comparator.stats(A, B);
+    assert_eq!((stats.format_mismatches, stats.different), (1, 0));
+    assert_eq!(stats.agreement(), 0.0);
+}
+
+#[test]
+fn hasher_pool_compares_every_pair() {
+    // The workers may insert frames out of order, so the window spans every timestamp and
+    // each frame is matched whichever counterpart is pending
+    let (hasher, verdicts) = AsyncHasher::new(RedundancyComparator::new(100), 4);
+    for t in 0..20 {
+        hasher.submit(A, t, frame(1, 0, 1)).unwrap();
+        hasher.submit(B, t, frame(1, 0, 1)).unwrap();
+    }
+    // Dropping the hasher waits for the queued frames, and closes the verdicts
+    drop(hasher);
+
+    let verdicts: Vec<Verdict> = verdicts.iter().collect();
+    assert_eq!(verdicts.len(), 20);
+    assert!(verdicts
+        .iter()
+        .all(|v| matches!(v, Verdict::Identical { .. })));
+}
+
+#[cfg(feature = "mock-backend")]
+mod mock {
+    use super::{A, B};
+    use decklink::device::get_devices;
+    use decklink::device::input::{
+        DeckLinkInputCallback, DecklinkDetectedVideoInputFormatFlags, DecklinkInputDevice,
+        DecklinkVideoInputFlags, DecklinkVideoInputFormatChangedEvents,
+    };
+    use decklink::display_mode::DecklinkDisplayModeId;
+    use decklink::frame::{DecklinkPixelFormat, DecklinkVideoFrame, DecklinkVideoMutableFrame};
+    use decklink::mock::{MockBackend, MockDevice, MockFrame, MockInput};
+    use decklink::time::DecklinkFrameTiming;
+    use decklink::verify::{AsyncHasher, RedundancyComparator, SourceId, Verdict};
+    use std::sync::{Arc, Mutex};
+
+    const FORMAT: DecklinkPixelFormat = DecklinkPixelFormat::Format8BitYUV;
+
+    /// Submits every captured frame of one source, at its stream time.
+    struct Source {
+        id: SourceId,
+        hasher: Arc<Mutex<Option<AsyncHasher>>>,
+        stream_time: Mutex<Option<i64>>,
+    }
+
+    impl DeckLinkInputCallback for Source {
+        fn video_input_format_changed(
+            &self,
+            _events: DecklinkVideoInputFormatChangedEvents,
+            _new_display_mode: DecklinkDisplayModeId,
+            _detected_signal_flags: DecklinkDetectedVideoInputFormatFlags,
+        ) {
+        }
+
+        fn video_input_frame_timing(&self, timing: DecklinkFrameTiming) {
+            *self.stream_time.lock().unwrap() = Some(timing.stream_time.value);
+        }
+
+        fn video_input_frame_arrived(&self, video_frame: Option<DecklinkVideoFrame>) -> bool {
+            let frame = DecklinkVideoMutableFrame::copy_from(&video_frame.unwrap()).unwrap();
+            let timestamp = self.stream_time.lock().unwrap().take().unwrap();
+            if let Some(hasher) = &*self.hasher.lock().unwrap() {
+                hasher.submit(self.id, timestamp, frame).unwrap();
+            }
+            true
+        }
+    }
+
+    fn start(
+        device: &decklink::device::DecklinkDevice,
+        id: SourceId,
+        hasher: &Arc<Mutex<Option<AsyncHasher>>>,
+    ) -> DecklinkInputDevice {
+        let mut input = device.input().unwrap();
+        input
+            .enable_video_input(
+                DecklinkDisplayModeId::HD1080p25,
+                FORMAT,
+                DecklinkVideoInputFlags::empty(),
+            )
+            .unwrap();
+        input
+            .set_callback(Some(Arc::new(Source {
+                id,
+                hasher: hasher.clone(),
+                stream_time: Mutex::new(None),
+            })))
+            .unwrap();
+        input.start_streams().unwrap();
+        input
+    }
+
+    /// A small frame at frame number `n`, with `row` set to `value`.
+    fn deliver(mock: &MockInput, n: i64, value: u8) {
+        let mut bytes = vec![0; 96 * 3];
+        bytes[96..192].fill(value);
+        let frame = MockFrame::new(48, 3, FORMAT)
+            .bytes(&bytes)
+            .stream_time(n * 1000, 1000, 25000);
+        assert!(mock.deliver_frame(frame).is_ok());
+    }
+
+    #[test]
+    fn identical_then_diverging_then_stalling_sources() {
+        let backend = MockBackend::install(vec![
+            MockDevice::new("DeckLink Duo 2 (1)"),
+            MockDevice::new("DeckLink Duo 2 (2)"),
+        ]);
+        let devices = get_devices().unwrap();
+        // One worker, so that frames are compared in the order they were captured
+        let (hasher, receiver) = AsyncHasher::new(RedundancyComparator::new(0), 1);
+        let hasher = Arc::new(Mutex::new(Some(hasher)));
+        let _a = start(&devices[0], A, &hasher);
+        let _b = start(&devices[1], B, &hasher);
+        let (main, backup) = (backend.input(0), backend.input(1));
+
+        // B starts later than A
+        deliver(&main, 0, 0);
+        for n in 1..4 {
+            deliver(&main, n, 0);
+            deliver(&backup, n, 0);
+        }
+        // The backup diverges
+        for n in 4..6 {
+            deliver(&main, n, 0);
+            deliver(&backup, n, 9);
+        }
+        // The backup stalls, then recovers
+        for n in 6..9 {
+            deliver(&main, n, 0);
+        }
+        deliver(&backup, 9, 0);
+        deliver(&main, 9, 0);
+
+        // The statistics are read once the expected verdicts are in, and nothing follows them
+        let mut verdicts: Vec<Verdict> = receiver.iter().take(9).collect();
+        let hasher = hasher.lock().unwrap().take().unwrap();
+        let stats = hasher.stats(A, B);
+        drop(hasher);
+        verdicts.extend(receiver.iter());
+
+        let identical = |timestamp| Verdict::Identical {
+            sources: (A, B),
+            timestamp,
+        };
+        let different = |timestamp| Verdict::Different {
+            sources: (A, B),
+            timestamp,
+            first_different_row: 1,
+        };
+        let missing = |timestamp| Verdict::MissingCounterpart {
+            present: A,
+            missing: B,
+            timestamp,
+        };
+        assert_eq!(
+            verdicts,
+            vec![
+                identical(1000),
+                identical(2000),
+                identical(3000),
+                different(4000),
+                different(5000),
+                missing(6000),
+                missing(7000),
+                missing(8000),
+                identical(9000),
+            ]
+        );
+        assert_eq!(
+            (
+                stats.identical,
+                stats.different,
+                stats.missing,
+                stats.format_mismatches
+            ),
+            (4, 2, 3, 0)
+        );
+        assert_eq!(stats.missing_streak, 0);
+        assert!((stats.agreement() - 4.0 / 6.0).abs() < 1e-9);
+    }
+}
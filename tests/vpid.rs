@@ -0,0 +1,205 @@
+//! Decoding SMPTE ST 352 payload identifiers, and reading them from captured frames.
+
+use decklink::colorimetry::{Colorimetry, TransferFunction};
+use decklink::vpid::{Vpid, VpidBitDepth, VpidPictureRate, VpidSampling, VpidScan, VpidStandard};
+
+/// The rows of `tests/fixtures/vpid.txt`: the payload and its description.
+fn fixtures() -> Vec<([u8; 4], String)> {
+    include_str!("fixtures/vpid.txt")
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .map(|line| {
+            let (bytes, description) = line
+                .split_once(" | ")
+                .unwrap_or_else(|| panic!("no description in {:?}", line));
+            let bytes: Vec<u8> = bytes
+                .split_whitespace()
+                .map(|b| u8::from_str_radix(b, 16).unwrap())
+                .collect();
+            (bytes.try_into().unwrap(), description.to_string())
+        })
+        .collect()
+}
+
+#[test]
+fn payloads_are_described_as_the_fixtures() {
+    let fixtures = fixtures();
+    assert!(fixtures.len() > 30);
+    for (bytes, description) in fixtures {
+        let vpid = Vpid::decode(bytes);
+        assert_eq!(vpid.describe(), description, "{:02X?}", bytes);
+        assert_eq!(vpid.bytes, bytes);
+    }
+}
+
+#[test]
+fn the_fields_of_a_payload_are_decoded() {
+    let vpid = Vpid::decode([0x8A, 0xC9, 0x00, 0x01]);
+    assert_eq!(vpid.standard, VpidStandard::Hd1080Level3B);
+    assert_eq!(vpid.standard.transport(), "3G-B");
+    assert_eq!(vpid.standard.lines(), Some(1080));
+    assert_eq!(vpid.scan, VpidScan::Progressive);
+    assert_eq!(vpid.picture_rate, VpidPictureRate::Rate50);
+    assert_eq!(vpid.picture_rate.frame_rate(), Some((50, 1)));
+    assert_eq!(vpid.sampling, VpidSampling::YCbCr422);
+    assert_eq!(vpid.colorimetry, Some(Colorimetry::Rec709));
+    assert_eq!(vpid.transfer, Some(TransferFunction::Sdr));
+    assert_eq!(vpid.bit_depth, VpidBitDepth::Bits10);
+    assert_eq!(vpid.link, 1);
+    assert_eq!(vpid.picture(), "1080p50");
+    assert_eq!(
+        vpid.to_string(),
+        "3G-B 1080p50 YCbCr 4:2:2 10-bit (8A C9 00 01)"
+    );
+}
+
+#[test]
+fn quad_link_and_12g_are_told_apart() {
+    let quad = Vpid::decode([0x98, 0xCA, 0x00, 0x81]);
+    let single = Vpid::decode([0xCE, 0xCA, 0x00, 0x01]);
+    assert_eq!(quad.picture(), single.picture());
+    assert_eq!(quad.standard.links(), 4);
+    assert_eq!(quad.link, 3);
+    assert_eq!(single.standard.links(), 1);
+    assert_eq!(single.link, 1);
+}
+
+#[test]
+fn unknown_codes_keep_their_raw_values() {
+    let vpid = Vpid::decode([0x7F, 0xB1, 0x3B, 0x03]);
+    assert_eq!(vpid.standard, VpidStandard::Unknown(0x7F));
+    assert_eq!(vpid.scan, VpidScan::Unknown(0b10));
+    assert_eq!(vpid.picture_rate, VpidPictureRate::Unknown(0x1));
+    assert_eq!(vpid.picture_rate.frame_rate(), None);
+    assert_eq!(vpid.sampling, VpidSampling::Unknown(0xB));
+    assert_eq!(vpid.colorimetry, None);
+    assert_eq!(vpid.transfer, None);
+    assert_eq!(vpid.bit_depth, VpidBitDepth::Unknown(0b11));
+    assert_eq!(vpid.bit_depth.bits(), None);
+    assert_eq!(vpid.bytes, [0x7F, 0xB1, 0x3B, 0x03]);
+}
+
+#[test]
+fn packets_shorter_than_a_payload_are_not_decoded() {
+    assert_eq!(Vpid::from_packet(&[0x89, 0xC9, 0x00]), None);
+    assert_eq!(
+        Vpid::from_packet(&[0x89, 0xC9, 0x00, 0x01, 0xFF]),
+        Some(Vpid::decode([0x89, 0xC9, 0x00, 0x01]))
+    );
+}
+
+#[cfg(feature = "mock-backend")]
+mod mock {
+    use super::*;
+    use decklink::device::get_devices;
+    use decklink::device::input::{
+        CallbackResult, DecklinkDetectedVideoInputFormatFlags, DecklinkInputDevice,
+        DecklinkVideoInputFlags, DecklinkVideoInputFormatChangedEvents, FrameArrival, InputHandler,
+    };
+    use decklink::display_mode::DecklinkDisplayModeId;
+    use decklink::frame::DecklinkPixelFormat;
+    use decklink::mock::{MockBackend, MockDevice, MockFrame};
+    use decklink::vpid::DecklinkVpidExt;
+    use std::sync::Arc;
+
+    const MODE: DecklinkDisplayModeId = DecklinkDisplayModeId::HD1080p25;
+    const FORMAT: DecklinkPixelFormat = DecklinkPixelFormat::Format8BitYUV;
+
+    struct Ignore;
+
+    impl InputHandler for Ignore {
+        fn frame_arrived(&self, _arrival: &FrameArrival<'_>) -> CallbackResult {
+            CallbackResult::Ok
+        }
+    }
+
+    fn capture(backend: &MockBackend) -> DecklinkInputDevice {
+        let devices = get_devices().unwrap();
+        let mut input = devices[0].input().unwrap();
+        input.set_handler(Some(Arc::new(Ignore))).unwrap();
+        input
+            .enable_video_input(MODE, FORMAT, DecklinkVideoInputFlags::empty())
+            .unwrap();
+        input.start_streams().unwrap();
+        assert!(backend.input(0).is_streaming());
+        input
+    }
+
+    fn frame() -> MockFrame {
+        MockFrame::for_mode(MODE, FORMAT)
+    }
+
+    #[test]
+    fn the_input_keeps_the_payload_of_its_signal() {
+        let backend = MockBackend::install(vec![MockDevice::new("DeckLink SDI 4K")]);
+        let mut input = capture(&backend);
+        assert_eq!(input.vpid(), Ok(None));
+
+        let mock = backend.input(0);
+        assert!(mock
+            .deliver_frame(frame().vpid([0x85, 0xC5, 0x00, 0x01]))
+            .is_ok());
+        let vpid = input.vpid().unwrap().unwrap();
+        assert_eq!(vpid.describe(), "HD 1080p25 YCbCr 4:2:2 10-bit");
+
+        // The payload is not read again while the signal stays the same
+        assert!(mock
+            .deliver_frame(frame().vpid([0x89, 0xC5, 0x00, 0x01]))
+            .is_ok());
+        assert_eq!(input.vpid().unwrap(), Some(vpid));
+
+        // but is from the first frame after a format change
+        assert!(mock
+            .deliver_format_change(
+                DecklinkVideoInputFormatChangedEvents::FIELD_DOMINANCE_CHANGED,
+                MODE,
+                DecklinkDetectedVideoInputFormatFlags::YCBCR_422,
+            )
+            .is_ok());
+        assert!(mock
+            .deliver_frame(frame().vpid([0x89, 0xC5, 0x00, 0x01]))
+            .is_ok());
+        assert_eq!(
+            input.vpid().unwrap().map(|v| v.standard),
+            Some(VpidStandard::Hd1080Level3A)
+        );
+
+        // and as the signal is lost and comes back
+        assert!(mock.deliver_frame(frame().no_signal()).is_ok());
+        assert_eq!(input.vpid(), Ok(None));
+        assert!(mock
+            .deliver_frame(frame().vpid([0x85, 0xC5, 0x00, 0x01]))
+            .is_ok());
+        assert_eq!(input.vpid().unwrap(), Some(vpid));
+
+        input.stop_streams().unwrap();
+        input.disable_video_input().unwrap();
+        assert_eq!(input.vpid(), Ok(None));
+        drop(input);
+        assert_eq!(MockBackend::live_objects(), 0);
+    }
+
+    #[test]
+    fn the_payload_is_read_again_after_video_input_is_enabled() {
+        let backend = MockBackend::install(vec![MockDevice::new("DeckLink SDI 4K")]);
+        let mut input = capture(&backend);
+        let mock = backend.input(0);
+        assert!(mock
+            .deliver_frame(frame().vpid([0x85, 0xC5, 0x00, 0x01]))
+            .is_ok());
+        input.stop_streams().unwrap();
+        input.disable_video_input().unwrap();
+
+        input
+            .enable_video_input(MODE, FORMAT, DecklinkVideoInputFlags::empty())
+            .unwrap();
+        input.start_streams().unwrap();
+        assert!(mock
+            .deliver_frame(frame().vpid([0x89, 0xC5, 0x00, 0x01]))
+            .is_ok());
+        assert_eq!(
+            input.vpid().unwrap().map(|v| v.standard),
+            Some(VpidStandard::Hd1080Level3A)
+        );
+    }
+}